@@ -0,0 +1,7 @@
+-- 问诊状态流转时间戳
+
+ALTER TABLE consultations ADD COLUMN accepted_at DATETIME;
+ALTER TABLE consultations ADD COLUMN completed_at DATETIME;
+ALTER TABLE consultations ADD COLUMN cancel_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_consultations_doctor_status ON consultations (doctor_id, status);
@@ -0,0 +1,5 @@
+-- 病历附件引用的缓存文件不参与清理
+
+ALTER TABLE file_cache ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_file_cache_pinned_accessed ON file_cache (pinned, last_accessed);
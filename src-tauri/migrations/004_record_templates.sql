@@ -0,0 +1,13 @@
+-- 病历模板表
+
+CREATE TABLE IF NOT EXISTS record_templates (
+    id TEXT PRIMARY KEY,
+    doctor_id TEXT NOT NULL,
+    record_type TEXT NOT NULL CHECK (record_type IN ('diagnosis', 'prescription', 'examination', 'treatment')),
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    variables TEXT, -- JSON数组格式存储模板变量定义
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_record_templates_doctor_type ON record_templates (doctor_id, record_type);
@@ -0,0 +1,45 @@
+-- 常用回复模板
+
+CREATE TABLE IF NOT EXISTS message_templates (
+    id TEXT PRIMARY KEY,
+    doctor_id TEXT NOT NULL,
+    category TEXT NOT NULL DEFAULT 'general',
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    usage_count INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_templates_doctor_usage ON message_templates (doctor_id, usage_count DESC);
+
+-- 消息表允许模板类型并记录来源模板（SQLite 无法修改 CHECK 约束，需要重建表）
+CREATE TABLE messages_new (
+    id TEXT PRIMARY KEY,
+    consultation_id TEXT NOT NULL,
+    sender_type TEXT NOT NULL CHECK (sender_type IN ('doctor', 'patient')),
+    message_type TEXT NOT NULL CHECK (message_type IN ('text', 'image', 'voice', 'file', 'template')),
+    content TEXT,
+    file_path TEXT,
+    file_size INTEGER,
+    mime_type TEXT,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    sync_status TEXT DEFAULT 'pending' CHECK (sync_status IN ('pending', 'synced', 'failed')),
+    read_status TEXT DEFAULT 'unread' CHECK (read_status IN ('unread', 'read')),
+    template_id TEXT,
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE,
+    FOREIGN KEY (template_id) REFERENCES message_templates (id) ON DELETE SET NULL
+);
+
+INSERT INTO messages_new (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status)
+SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE INDEX IF NOT EXISTS idx_messages_consultation ON messages (consultation_id);
+CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages (timestamp);
+CREATE INDEX IF NOT EXISTS idx_messages_sender ON messages (sender_type);
+CREATE INDEX IF NOT EXISTS idx_messages_sync_status ON messages (sync_status);
+CREATE INDEX IF NOT EXISTS idx_messages_template ON messages (template_id);
@@ -0,0 +1,7 @@
+-- 增量同步水位线（每种实体一行，记录已成功同步到的服务器时间）
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    entity_type TEXT PRIMARY KEY,
+    last_sync DATETIME NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
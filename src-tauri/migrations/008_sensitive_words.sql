@@ -0,0 +1,12 @@
+-- 敏感词表（forbidden 禁止发送，warn 仅提示）
+
+CREATE TABLE IF NOT EXISTS sensitive_words (
+    id TEXT PRIMARY KEY,
+    word TEXT NOT NULL UNIQUE,
+    category TEXT NOT NULL DEFAULT 'forbidden' CHECK (category IN ('forbidden', 'warn')),
+    created_by TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 保留原先硬编码的示例敏感词
+INSERT OR IGNORE INTO sensitive_words (id, word, category) VALUES ('default-sensitive-word', '测试敏感词', 'forbidden');
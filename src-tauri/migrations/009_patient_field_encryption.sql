@@ -0,0 +1,7 @@
+-- 患者手机号、身份证号加密存储，另存 HMAC 索引用于等值查询
+
+ALTER TABLE patients ADD COLUMN phone_hash TEXT;
+ALTER TABLE patients ADD COLUMN id_card_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_patients_phone_hash ON patients (phone_hash);
+CREATE INDEX IF NOT EXISTS idx_patients_id_card_hash ON patients (id_card_hash);
@@ -0,0 +1,7 @@
+-- 异常访问检测规则配置，每条规则一行，value 为 JSON
+
+CREATE TABLE IF NOT EXISTS security_config (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
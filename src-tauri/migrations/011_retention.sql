@@ -0,0 +1,26 @@
+-- 数据保留策略，每项一行（各表保留天数及自动整理阈值）
+
+CREATE TABLE IF NOT EXISTS retention_policy (
+    key TEXT PRIMARY KEY,
+    value INTEGER NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 定期维护执行记录
+CREATE TABLE IF NOT EXISTS maintenance_runs (
+    id TEXT PRIMARY KEY,
+    triggered_by TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    finished_at DATETIME NOT NULL,
+    deleted_messages INTEGER NOT NULL DEFAULT 0,
+    deleted_audit_logs INTEGER NOT NULL DEFAULT 0,
+    deleted_anomaly_records INTEGER NOT NULL DEFAULT 0,
+    deleted_cache_files INTEGER NOT NULL DEFAULT 0,
+    deleted_backups INTEGER NOT NULL DEFAULT 0,
+    freed_bytes INTEGER NOT NULL DEFAULT 0,
+    vacuumed INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    error_message TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_maintenance_runs_started_at ON maintenance_runs (started_at);
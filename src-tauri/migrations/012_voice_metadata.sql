@@ -0,0 +1,4 @@
+-- 语音消息时长（毫秒）和波形峰值（JSON 数组，0–1）
+
+ALTER TABLE messages ADD COLUMN duration_ms INTEGER;
+ALTER TABLE messages ADD COLUMN waveform TEXT;
@@ -0,0 +1,3 @@
+-- 图片文件的缩略图路径
+
+ALTER TABLE file_cache ADD COLUMN thumbnail_path TEXT;
@@ -0,0 +1,3 @@
+-- 断点续传：记录已下载的字节数
+
+ALTER TABLE file_cache ADD COLUMN bytes_downloaded INTEGER NOT NULL DEFAULT 0;
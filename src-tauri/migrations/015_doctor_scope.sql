@@ -0,0 +1,32 @@
+-- 多位医生共用工作站：按医生隔离本地数据
+-- 历史问诊缺少医生 ID 时，从同一问诊的病历中回填
+
+UPDATE consultations
+SET doctor_id = (
+    SELECT mr.doctor_id FROM medical_records mr
+    WHERE mr.consultation_id = consultations.id AND mr.doctor_id <> ''
+    ORDER BY mr.created_at
+    LIMIT 1
+)
+WHERE doctor_id = ''
+  AND EXISTS (
+    SELECT 1 FROM medical_records mr
+    WHERE mr.consultation_id = consultations.id AND mr.doctor_id <> ''
+  );
+
+-- 本机只登录过一位医生时，剩余的问诊都归属该医生
+UPDATE consultations
+SET doctor_id = (SELECT id FROM users)
+WHERE doctor_id = ''
+  AND (SELECT COUNT(*) FROM users) = 1;
+
+-- 病历缺少医生 ID 时取所属问诊的医生
+UPDATE medical_records
+SET doctor_id = (SELECT c.doctor_id FROM consultations c WHERE c.id = medical_records.consultation_id)
+WHERE doctor_id = ''
+  AND EXISTS (
+    SELECT 1 FROM consultations c
+    WHERE c.id = medical_records.consultation_id AND c.doctor_id <> ''
+  );
+
+CREATE INDEX IF NOT EXISTS idx_consultations_patient_doctor ON consultations (patient_id, doctor_id);
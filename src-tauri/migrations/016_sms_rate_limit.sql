@@ -0,0 +1,10 @@
+-- 短信验证码请求记录，用于客户端限流，重启后仍然生效
+-- 手机号只保存 HMAC 索引
+
+CREATE TABLE IF NOT EXISTS sms_code_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    phone_hash TEXT NOT NULL,
+    requested_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sms_code_requests_phone ON sms_code_requests (phone_hash, requested_at);
@@ -0,0 +1,47 @@
+-- 问诊转接记录
+
+CREATE TABLE IF NOT EXISTS consultation_transfers (
+    id TEXT PRIMARY KEY,
+    consultation_id TEXT NOT NULL,
+    from_doctor_id TEXT NOT NULL,
+    to_doctor_id TEXT NOT NULL,
+    note TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_consultation_transfers_consultation ON consultation_transfers (consultation_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_consultation_transfers_to_doctor ON consultation_transfers (to_doctor_id);
+
+-- 消息表允许系统消息（转接提示等），SQLite 无法修改 CHECK 约束，需要重建表
+CREATE TABLE messages_new (
+    id TEXT PRIMARY KEY,
+    consultation_id TEXT NOT NULL,
+    sender_type TEXT NOT NULL CHECK (sender_type IN ('doctor', 'patient', 'system')),
+    message_type TEXT NOT NULL CHECK (message_type IN ('text', 'image', 'voice', 'file', 'template')),
+    content TEXT,
+    file_path TEXT,
+    file_size INTEGER,
+    mime_type TEXT,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    sync_status TEXT DEFAULT 'pending' CHECK (sync_status IN ('pending', 'synced', 'failed')),
+    read_status TEXT DEFAULT 'unread' CHECK (read_status IN ('unread', 'read')),
+    template_id TEXT,
+    duration_ms INTEGER,
+    waveform TEXT,
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE,
+    FOREIGN KEY (template_id) REFERENCES message_templates (id) ON DELETE SET NULL
+);
+
+INSERT INTO messages_new (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform)
+SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE INDEX IF NOT EXISTS idx_messages_consultation ON messages (consultation_id);
+CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages (timestamp);
+CREATE INDEX IF NOT EXISTS idx_messages_sender ON messages (sender_type);
+CREATE INDEX IF NOT EXISTS idx_messages_sync_status ON messages (sync_status);
+CREATE INDEX IF NOT EXISTS idx_messages_template ON messages (template_id);
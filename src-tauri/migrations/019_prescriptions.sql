@@ -0,0 +1,17 @@
+-- 结构化处方，items 为药品明细 JSON 数组
+
+CREATE TABLE IF NOT EXISTS prescriptions (
+    id TEXT PRIMARY KEY,
+    consultation_id TEXT NOT NULL,
+    doctor_id TEXT NOT NULL,
+    items TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'draft' CHECK (status IN ('draft', 'issued', 'voided')),
+    void_reason TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    issued_at DATETIME,
+    voided_at DATETIME,
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_prescriptions_consultation ON prescriptions (consultation_id, created_at);
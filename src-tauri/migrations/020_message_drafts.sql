@@ -0,0 +1,12 @@
+-- 消息草稿，每位医生在每个问诊下保留一份
+
+CREATE TABLE IF NOT EXISTS message_drafts (
+    consultation_id TEXT NOT NULL,
+    doctor_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (consultation_id, doctor_id),
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_drafts_updated_at ON message_drafts (updated_at);
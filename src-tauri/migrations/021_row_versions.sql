@@ -0,0 +1,4 @@
+-- 患者和问诊的行版本号，用于乐观并发控制
+
+ALTER TABLE patients ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE consultations ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
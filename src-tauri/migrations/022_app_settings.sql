@@ -0,0 +1,9 @@
+-- 应用全局配置，value 为序列化后的 AppConfig，schema_version 用于补齐新增字段的默认值
+
+CREATE TABLE IF NOT EXISTS app_settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    schema_version INTEGER NOT NULL DEFAULT 1,
+    updated_by TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
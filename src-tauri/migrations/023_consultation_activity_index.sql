@@ -0,0 +1,3 @@
+-- 按问诊查最新消息时间，问诊超时扫描使用相关子查询 MAX(timestamp)
+
+CREATE INDEX IF NOT EXISTS idx_messages_consultation_timestamp ON messages (consultation_id, timestamp);
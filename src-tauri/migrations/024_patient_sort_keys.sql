@@ -0,0 +1,7 @@
+-- 患者列表排序：最近就诊时间由问诊完成时维护，姓名拼音排序键由应用写入
+
+ALTER TABLE patients ADD COLUMN last_visit DATETIME;
+ALTER TABLE patients ADD COLUMN name_pinyin TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_patients_last_visit ON patients (last_visit);
+CREATE INDEX IF NOT EXISTS idx_patients_name_pinyin ON patients (name_pinyin);
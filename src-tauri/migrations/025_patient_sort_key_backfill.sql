@@ -0,0 +1,11 @@
+-- 回填历史患者的排序字段，可重复执行
+-- pinyin_key() 由 MigrationManager 在执行迁移前注册
+
+-- 最近就诊时间取已完成问诊的完成时间，缺少完成时间的旧数据以最后更新时间代替
+UPDATE patients SET last_visit = (
+    SELECT MAX(COALESCE(c.completed_at, c.updated_at))
+    FROM consultations c
+    WHERE c.patient_id = patients.id AND c.status = 'completed'
+);
+
+UPDATE patients SET name_pinyin = pinyin_key(name);
@@ -0,0 +1,4 @@
+-- PDF、Word 附件的预览图路径和开头文字
+
+ALTER TABLE file_cache ADD COLUMN preview_path TEXT;
+ALTER TABLE file_cache ADD COLUMN preview_text TEXT;
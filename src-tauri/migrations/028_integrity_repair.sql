@@ -0,0 +1,19 @@
+-- 启动完整性修复：维护记录区分类型并保存修复摘要，无法自动修复的孤立记录移入隔离表供排查
+
+ALTER TABLE maintenance_runs ADD COLUMN kind TEXT NOT NULL DEFAULT 'retention';
+ALTER TABLE maintenance_runs ADD COLUMN summary TEXT;
+
+-- 隔离表保存原始行的 JSON，原表结构后续变更不影响隔离数据
+CREATE TABLE IF NOT EXISTS messages_orphaned (
+    id TEXT PRIMARY KEY,
+    row_data TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    quarantined_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS consultations_orphaned (
+    id TEXT PRIMARY KEY,
+    row_data TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    quarantined_at DATETIME NOT NULL
+);
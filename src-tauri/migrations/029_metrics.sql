@@ -0,0 +1,9 @@
+-- 运行指标：按小时累计的直方图计数，bucket 为桶下标，重启后继续累计
+
+CREATE TABLE IF NOT EXISTS metrics (
+    name TEXT NOT NULL,
+    hour DATETIME NOT NULL,
+    bucket INTEGER NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (name, hour, bucket)
+);
@@ -0,0 +1,25 @@
+-- 附件配额：缓存文件记录所属问诊，按问诊累计附件字节数，患者用量由其问诊汇总
+
+ALTER TABLE file_cache ADD COLUMN consultation_id TEXT;
+CREATE INDEX IF NOT EXISTS idx_file_cache_consultation ON file_cache (consultation_id);
+CREATE INDEX IF NOT EXISTS idx_messages_file_path ON messages (file_path);
+
+-- 已有的附件通过引用它的文件消息找到所属问诊，消息中保存的是本地路径或远程地址
+UPDATE file_cache SET consultation_id = (
+    SELECT m.consultation_id FROM messages m
+    WHERE m.file_path IN (file_cache.local_path, file_cache.file_url)
+    LIMIT 1
+);
+
+-- 与 file_cache 在同一事务中增减，查询用量时不再扫描 file_cache
+CREATE TABLE IF NOT EXISTS attachment_usage (
+    consultation_id TEXT PRIMARY KEY,
+    total_bytes INTEGER NOT NULL DEFAULT 0,
+    file_count INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO attachment_usage (consultation_id, total_bytes, file_count)
+SELECT consultation_id, SUM(COALESCE(file_size, bytes_downloaded)), COUNT(*)
+FROM file_cache
+WHERE consultation_id IS NOT NULL
+GROUP BY consultation_id;
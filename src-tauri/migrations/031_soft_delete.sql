@@ -0,0 +1,8 @@
+-- 软删除：消息和病历删除后先进入回收站，可恢复；超过回收站保留天数后由每日维护彻底清除
+
+ALTER TABLE messages ADD COLUMN deleted_at DATETIME;
+ALTER TABLE medical_records ADD COLUMN deleted_at DATETIME;
+CREATE INDEX IF NOT EXISTS idx_messages_deleted_at ON messages (deleted_at);
+CREATE INDEX IF NOT EXISTS idx_medical_records_deleted_at ON medical_records (deleted_at);
+
+ALTER TABLE maintenance_runs ADD COLUMN purged_trash INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- 问诊备注：医生私人记录，不发送给患者，不参与导出和同步；content 为字段级密文
+
+CREATE TABLE IF NOT EXISTS consultation_notes (
+    id TEXT PRIMARY KEY,
+    consultation_id TEXT NOT NULL,
+    doctor_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (consultation_id, doctor_id),
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE
+);
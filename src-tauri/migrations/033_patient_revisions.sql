@@ -0,0 +1,12 @@
+-- 患者资料修改记录：changes 为变更字段的新旧值（JSON），手机号、身份证号的新旧值为字段级密文
+
+CREATE TABLE IF NOT EXISTS patient_revisions (
+    id TEXT PRIMARY KEY,
+    patient_id TEXT NOT NULL,
+    editor_id TEXT,
+    changes TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (patient_id) REFERENCES patients (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_patient_revisions_patient ON patient_revisions (patient_id, created_at);
@@ -0,0 +1,11 @@
+-- 问诊前患者填写的问卷：data 保存患者端上报的原始 JSON；
+-- format_error 非空表示未通过对应版本的格式校验，原始数据仍保留供人工查看
+
+CREATE TABLE IF NOT EXISTS intake_forms (
+    consultation_id TEXT PRIMARY KEY,
+    schema_version INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    format_error TEXT,
+    submitted_at DATETIME NOT NULL,
+    FOREIGN KEY (consultation_id) REFERENCES consultations (id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- 慢速网络下压缩后上传的图片记录压缩前的字节数，未压缩的文件为空
+
+ALTER TABLE file_cache ADD COLUMN original_size INTEGER;
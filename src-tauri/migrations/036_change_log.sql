@@ -0,0 +1,13 @@
+-- 患者和问诊的变更记录：与数据修改在同一事务内写入，seq 单调递增（AUTOINCREMENT 保证清理后也不复用），
+-- 窗口被隐藏期间错过的变更通知可按 seq 补齐；由每日数据保留清理
+
+CREATE TABLE IF NOT EXISTS change_log (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    entity TEXT NOT NULL CHECK (entity IN ('patient', 'consultation')),
+    entity_id TEXT NOT NULL,
+    op TEXT NOT NULL CHECK (op IN ('insert', 'update', 'delete')),
+    changed_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_change_log_entity_seq ON change_log (entity, seq);
+CREATE INDEX IF NOT EXISTS idx_change_log_changed_at ON change_log (changed_at);
@@ -0,0 +1,10 @@
+-- 候诊优先级：分诊可将儿童、重症等患者标记为 urgent/critical 优先接诊
+-- queued_at 为进入候诊队列的时间，等待时长从此计算；已有问诊以创建时间回填
+
+ALTER TABLE consultations ADD COLUMN priority TEXT NOT NULL DEFAULT 'normal'
+    CHECK (priority IN ('normal', 'urgent', 'critical'));
+ALTER TABLE consultations ADD COLUMN queued_at DATETIME;
+
+UPDATE consultations SET queued_at = created_at WHERE queued_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_consultations_queue ON consultations(doctor_id, status, priority, queued_at);
@@ -0,0 +1,13 @@
+-- 时间列统一存储为定长的 RFC3339 UTC 字符串（如 2024-03-01T08:00:00.000000Z），按保留天数清理时才能直接按字符串比较
+-- 旧版本写入的格式有 CURRENT_TIMESTAMP 默认值、chrono 的 "YYYY-MM-DD HH:MM:SS.f+00:00" 和整数时间戳，
+-- 由迁移程序注册的 normalize_timestamp 函数改写，无法识别的取值保持不变
+
+UPDATE messages SET timestamp = normalize_timestamp(timestamp) WHERE timestamp IS NOT NULL;
+UPDATE messages SET deleted_at = normalize_timestamp(deleted_at) WHERE deleted_at IS NOT NULL;
+
+UPDATE audit_logs SET created_at = normalize_timestamp(created_at) WHERE created_at IS NOT NULL;
+
+UPDATE file_cache SET
+    expires_at = normalize_timestamp(expires_at),
+    downloaded_at = normalize_timestamp(downloaded_at),
+    last_accessed = normalize_timestamp(last_accessed);
@@ -0,0 +1,13 @@
+-- 按引用固定的缓存文件：未发送消息等引用的文件在引用解除前不参与过期、LRU、容量清理和清空缓存，
+-- 同一文件可被多个引用固定，全部解除后才允许淘汰；缓存记录删除时一并删除
+
+CREATE TABLE IF NOT EXISTS pinned_by_ref (
+    cache_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    ref_id TEXT NOT NULL,
+    pinned_at DATETIME NOT NULL,
+    PRIMARY KEY (cache_id, reason, ref_id),
+    FOREIGN KEY (cache_id) REFERENCES file_cache (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_pinned_by_ref_ref ON pinned_by_ref (reason, ref_id);
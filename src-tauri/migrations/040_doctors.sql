@@ -0,0 +1,16 @@
+-- 院内医生名录：每次同步拉取完整列表，synced_at 为最近一次在同步中出现的时间，
+-- 本次同步未出现的医生写入 removed_at 软删除，不再作为转接候选，再次出现时恢复；
+-- online_status 由同步和 WebSocket 在线状态推送更新
+
+CREATE TABLE IF NOT EXISTS doctors (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    department TEXT,
+    title TEXT,
+    online_status TEXT NOT NULL DEFAULT 'offline' CHECK (online_status IN ('online', 'busy', 'offline')),
+    updated_at DATETIME NOT NULL,
+    synced_at DATETIME NOT NULL,
+    removed_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_doctors_department ON doctors (department, name) WHERE removed_at IS NULL;
@@ -0,0 +1,3 @@
+-- 清洗前的消息原文，仅在清洗改写了正文时保存，供管理员取证查看
+
+ALTER TABLE messages ADD COLUMN raw_content TEXT;
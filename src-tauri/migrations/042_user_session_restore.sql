@@ -0,0 +1,5 @@
+-- 重启后恢复登录会话：登录返回的用户信息原样保存，恢复时返回与登录相同的结果；
+-- 最近一次由服务器确认会话有效的时间用于离线恢复的宽限期判断
+
+ALTER TABLE users ADD COLUMN profile TEXT;
+ALTER TABLE users ADD COLUMN session_verified_at DATETIME;
@@ -0,0 +1,7 @@
+-- 置顶消息：医生把过敏史等重要信息固定在会话顶部，每个问诊最多置顶 5 条，按置顶时间排列
+
+ALTER TABLE messages ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN pinned_at DATETIME;
+ALTER TABLE messages ADD COLUMN pinned_by TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_pinned ON messages (consultation_id, pinned_at) WHERE pinned = 1;
@@ -0,0 +1,13 @@
+-- 病历附件改为按引用固定：已固定的文件归到引用它的病历（包括回收站中的病历）名下，
+-- 之后移除 file_cache 上的固定标记
+
+INSERT OR IGNORE INTO pinned_by_ref (cache_id, reason, ref_id, pinned_at)
+SELECT f.id, 'medical_record', r.id, normalize_timestamp(CURRENT_TIMESTAMP)
+FROM file_cache f
+JOIN medical_records r ON r.attachments IS NOT NULL AND json_valid(r.attachments)
+JOIN json_each(r.attachments) a ON json_extract(a.value, '$.fileId') = f.id
+WHERE f.pinned = 1;
+
+DROP INDEX IF EXISTS idx_file_cache_pinned_accessed;
+
+ALTER TABLE file_cache DROP COLUMN pinned;
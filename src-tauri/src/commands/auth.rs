@@ -1,36 +1,227 @@
 // 认证相关命令
 
 use serde::{Deserialize, Serialize};
-use crate::services::AuthService;
-use crate::models::{User, LoginCredentials, AuthResult};
-use crate::utils::error::AppError;
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::PermissionServiceState;
+use crate::commands::security::SecurityServiceState;
+use crate::database::query_optimizer::clear_all_query_caches;
+use crate::database::try_get_database;
+use crate::services::{
+    effective_config, AuditAction, AuditOrigin, AuthService, OsKeychainKeyStore, SessionRestoreOutcome, SessionStatus, SessionStore,
+    TokenRefreshService,
+};
+use crate::models::{AppError, ErrorType, LoginCredentials, AuthResult, UserRole};
+use crate::utils::{mask_phone, MessageKey, ValidationResult, ValidationService};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::Mutex;
 
+pub type TokenRefreshServiceState = Arc<Mutex<TokenRefreshService>>;
+
+// 当前登录医生的用户 ID，未登录时返回认证错误
+pub(crate) async fn current_doctor_id(token_refresh: &State<'_, TokenRefreshServiceState>) -> Result<String, AppError> {
+    token_refresh
+        .lock()
+        .await
+        .current_user_id()
+        .await
+        .ok_or_else(|| AppError::new(ErrorType::AuthError, "请先登录").with_code("NOT_LOGGED_IN"))
+}
+
+#[tauri::command]
+pub async fn auth_login(
+    credentials: LoginCredentials,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    security_service: State<'_, SecurityServiceState>,
+) -> Result<AuthResult, AppError> {
+    let result = login(credentials).await?;
+    let user_id = result.user["id"].as_str().unwrap_or_default().to_string();
+    record_login(&security_service, &user_id, &result).await;
+
+    // 加密保存会话，下次启动时恢复；数据库未就绪时跳过
+    if try_get_database().is_some() {
+        match DateTime::parse_from_rfc3339(&result.expires_at) {
+            Ok(expires_at) => {
+                if let Err(e) = session_store().save(&result, expires_at.with_timezone(&Utc)) {
+                    tracing::warn!("Failed to persist session for user {}: {}", user_id, e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to parse token expiry, session not persisted: {}", e),
+        }
+    }
+
+    start_user_session(&result, user_id, &token_refresh, &permissions).await;
+    Ok(result)
+}
+
+/// 启动时恢复上次登录的会话：成功时返回与登录相同的结果，否则返回原因（过期、被吊销、离线无法确认等），前端据此显示登录页
 #[tauri::command]
-pub async fn auth_login(credentials: LoginCredentials) -> Result<AuthResult, String> {
-    println!("Login attempt with credentials: {:?}", credentials);
+pub async fn try_restore_session(
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    security_service: State<'_, SecurityServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<SessionRestoreOutcome, AppError> {
+    require_database(&readiness).await?;
+
+    let auth_service = AuthService::new(&effective_config());
+    let outcome = session_store().restore(&auth_service, Utc::now()).await?;
+    match &outcome {
+        SessionRestoreOutcome::Restored { auth, verified } => {
+            let user_id = auth.user["id"].as_str().unwrap_or_default().to_string();
+            tracing::info!("Restored session for user {} (verified: {})", user_id, verified);
+            record_login(&security_service, &user_id, auth).await;
+            start_user_session(auth, user_id, &token_refresh, &permissions).await;
+        }
+        SessionRestoreOutcome::Failed { reason } => tracing::info!("Session not restored: {:?}", reason),
+    }
+
+    Ok(outcome)
+}
+
+fn session_store() -> SessionStore {
+    SessionStore::new(Arc::new(OsKeychainKeyStore))
+}
+
+// 登录或恢复会话后按角色授权，并调度 token 自动刷新
+async fn start_user_session(
+    result: &AuthResult,
+    user_id: String,
+    token_refresh: &State<'_, TokenRefreshServiceState>,
+    permissions: &State<'_, PermissionServiceState>,
+) {
+    // 权限以 token 中的角色声明为准，没有时使用用户信息中的角色
+    let role = AuthService::role_from_token(&result.token)
+        .ok()
+        .or_else(|| result.user["role"].as_str().and_then(UserRole::parse));
+    if role.is_none() {
+        tracing::warn!("Unrecognized role for user {}, no permissions granted", user_id);
+    }
+    // 换人登录时丢弃上一位医生的查询缓存
+    clear_all_query_caches();
+    permissions.lock().await.start_session(user_id.clone(), role);
+
+    match DateTime::parse_from_rfc3339(&result.expires_at) {
+        Ok(expires_at) => {
+            token_refresh
+                .lock()
+                .await
+                .start_session(user_id, result.token.clone(), expires_at.with_timezone(&Utc))
+                .await;
+        }
+        Err(e) => tracing::warn!("Failed to parse token expiry, refresh not scheduled: {}", e),
+    }
+}
+
+// 登录审计优先记录认证服务返回的公网 IP，没有时由本机设备信息补全
+async fn record_login(security_service: &SecurityServiceState, user_id: &str, result: &AuthResult) {
+    let origin = AuditOrigin {
+        ip_address: result.client_ip.clone(),
+        user_agent: None,
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit_with_origin(
+            user_id.to_string(),
+            AuditAction::Login,
+            None,
+            None,
+            "success".to_string(),
+            None,
+            HashMap::new(),
+            origin,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for login: {}", e);
+    }
+}
+
+async fn login(credentials: LoginCredentials) -> Result<AuthResult, AppError> {
+    tracing::debug!("Login attempt ({:?}) for {}", credentials.login_type, credentials.masked_identifier());
 
-    let auth_service = AuthService::new();
+    let auth_service = AuthService::new(&effective_config());
 
     match auth_service.authenticate(credentials).await {
         Ok(result) => Ok(result),
         Err(e) => {
-            eprintln!("Authentication failed: {}", e);
-            Err(e.to_string())
+            tracing::error!("Authentication failed: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// 请求短信验证码，返回距下次可请求的秒数，前端据此显示倒计时
+#[tauri::command]
+pub async fn request_sms_code(
+    phone: String,
+    security_service: State<'_, SecurityServiceState>,
+) -> Result<u64, AppError> {
+    let auth_service = AuthService::new(&effective_config());
+    send_sms_code(&auth_service, &security_service, &phone).await
+}
+
+// 先校验手机号格式和本地限流，通过后才调用认证后端
+async fn send_sms_code(
+    auth_service: &AuthService,
+    security_service: &SecurityServiceState,
+    phone: &str,
+) -> Result<u64, AppError> {
+    tracing::info!("Requesting SMS code for: {}", mask_phone(phone));
+
+    if !ValidationService::validate_phone(phone) {
+        let mut validation = ValidationResult::new();
+        validation.add("phone", MessageKey::PhoneInvalid, &[], "INVALID_PHONE");
+        validation.into_app_result()?;
+    }
+
+    let retry_after = security_service.lock().await.acquire_sms_slot(phone).await?;
+
+    match auth_service.send_sms_code(phone).await {
+        Ok(_) => Ok(retry_after),
+        Err(e) => {
+            tracing::error!("Send SMS code failed: {}", e);
+            Err(e.into())
         }
     }
 }
 
 #[tauri::command]
-pub async fn auth_logout(token: Option<String>) -> Result<(), String> {
-    println!("User logout");
+pub async fn auth_logout(
+    token: Option<String>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    security_service: State<'_, SecurityServiceState>,
+) -> Result<(), AppError> {
+    // 解锁 PIN 只在本次登录内有效，保存的会话也一并清除，下次启动需重新登录
+    if let Some(user_id) = token_refresh.lock().await.current_user_id().await {
+        security_service.lock().await.clear_unlock_pin(&user_id).await;
+        if try_get_database().is_some() {
+            if let Err(e) = session_store().clear(&user_id) {
+                tracing::error!("Failed to clear stored session for user {}: {}", user_id, e);
+            }
+        }
+    }
+    token_refresh.lock().await.stop_session().await;
+    permissions.lock().await.clear_session();
+    clear_all_query_caches();
+    logout(token).await
+}
 
-    let auth_service = AuthService::new();
+async fn logout(token: Option<String>) -> Result<(), AppError> {
+    tracing::info!("User logout");
+
+    let auth_service = AuthService::new(&effective_config());
 
     if let Some(token) = token {
         match auth_service.logout(&token).await {
             Ok(_) => Ok(()),
             Err(e) => {
-                eprintln!("Logout failed: {}", e);
+                tracing::error!("Logout failed: {}", e);
                 // 即使登出失败也返回成功，因为前端需要清除状态
                 Ok(())
             }
@@ -41,38 +232,48 @@ pub async fn auth_logout(token: Option<String>) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn auth_refresh_token(current_token: String) -> Result<String, String> {
-    println!("Refreshing token: {}", current_token);
+pub async fn auth_refresh_token(current_token: String) -> Result<String, AppError> {
+    tracing::debug!("Refreshing token");
 
-    let auth_service = AuthService::new();
+    let auth_service = AuthService::new(&effective_config());
 
     match auth_service.refresh_token(&current_token).await {
-        Ok(new_token) => Ok(new_token),
+        Ok(refreshed) => Ok(refreshed.token),
         Err(e) => {
-            eprintln!("Token refresh failed: {}", e);
-            Err(e.to_string())
+            tracing::error!("Token refresh failed: {}", e);
+            Err(e)
         }
     }
 }
 
 #[tauri::command]
-pub async fn auth_validate_session(token: String) -> Result<bool, String> {
-    println!("Validating session token: {}", token);
+pub async fn auth_validate_session(token: String) -> Result<bool, AppError> {
+    tracing::debug!("Validating session token");
 
-    let auth_service = AuthService::new();
+    let auth_service = AuthService::new(&effective_config());
 
     match auth_service.validate_token(&token).await {
         Ok(is_valid) => Ok(is_valid),
         Err(e) => {
-            eprintln!("Session validation failed: {}", e);
+            tracing::error!("Session validation failed: {}", e);
             Ok(false) // 验证失败时返回 false 而不是错误
         }
     }
 }
+#[tauri::command]
+pub async fn get_session_status(
+    token_refresh: State<'_, TokenRefreshServiceState>,
+) -> Result<SessionStatus, AppError> {
+    Ok(token_refresh.lock().await.get_status().await)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::models::{LoginCredentials, LoginType};
+    use crate::services::{AuthProvider, AuthProviderResult, RefreshedToken, SecurityService};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[tokio::test]
     async fn test_password_login_success() {
@@ -85,7 +286,7 @@ mod tests {
             id_card: None,
         };
 
-        let result = auth_login(credentials).await;
+        let result = login(credentials).await;
         assert!(result.is_ok());
 
         let auth_result = result.unwrap();
@@ -105,16 +306,131 @@ mod tests {
             id_card: None,
         };
 
-        let result = auth_login(credentials).await;
+        let result = login(credentials).await;
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "用户名或密码错误");
+        assert_eq!(result.unwrap_err().message, "用户名或密码错误");
+    }
+
+    #[tokio::test]
+    async fn test_login_audit_uses_server_reported_ip() {
+        let device_info = crate::services::DeviceInfo {
+            hostname: "clinic-pc-01".to_string(),
+            os_version: "Windows 11".to_string(),
+            app_version: "0.1.0".to_string(),
+            local_ip: Some("192.168.1.20".to_string()),
+        };
+        let security: SecurityServiceState =
+            Arc::new(Mutex::new(SecurityService::new(300).with_device_info(device_info)));
+        let mut result = AuthResult {
+            token: "t".to_string(),
+            user: serde_json::json!({ "id": "42" }),
+            expires_at: "2030-01-01T00:00:00Z".to_string(),
+            client_ip: Some("203.0.113.7".to_string()),
+        };
+
+        record_login(&security, "42", &result).await;
+        result.client_ip = None;
+        record_login(&security, "42", &result).await;
+
+        let logs = security
+            .lock()
+            .await
+            .get_audit_logs(Some("42".to_string()), None, None, None, 10)
+            .await
+            .unwrap();
+        let mut ips: Vec<_> = logs.iter().filter_map(|log| log.ip_address.clone()).collect();
+        ips.sort();
+        assert_eq!(ips, vec!["192.168.1.20".to_string(), "203.0.113.7".to_string()]);
     }
 
     #[tokio::test]
     async fn test_logout_success() {
         let token = Some("some_token".to_string());
 
-        let logout_result = auth_logout(token).await;
+        let logout_result = logout(token).await;
         assert!(logout_result.is_ok());
     }
+
+    // 记录发送次数的认证后端
+    struct CountingSmsProvider {
+        sent: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AuthProvider for CountingSmsProvider {
+        async fn login_password(&self, _username: &str, _password: &str) -> AuthProviderResult<AuthResult> {
+            unsupported()
+        }
+
+        async fn login_sms(&self, _phone: &str, _sms_code: &str) -> AuthProviderResult<AuthResult> {
+            unsupported()
+        }
+
+        async fn login_realname(&self, _id_card: &str) -> AuthProviderResult<AuthResult> {
+            unsupported()
+        }
+
+        async fn send_sms_code(&self, _phone: &str) -> AuthProviderResult<()> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn verify_session(&self, _token: &str) -> AuthProviderResult<()> {
+            unsupported()
+        }
+
+        async fn refresh_token(&self, _token: &str) -> AuthProviderResult<RefreshedToken> {
+            unsupported()
+        }
+    }
+
+    // 测试后端未实现的接口返回错误，误调用时测试按错误失败而不是 panic
+    fn unsupported<T>() -> AuthProviderResult<T> {
+        Err(AppError::new(ErrorType::SystemError, "测试认证后端不支持该操作"))
+    }
+
+    fn sms_fixture() -> (Arc<CountingSmsProvider>, AuthService, SecurityServiceState) {
+        let provider = Arc::new(CountingSmsProvider { sent: AtomicUsize::new(0) });
+        let auth_service = AuthService::with_provider(provider.clone());
+        (provider, auth_service, Arc::new(Mutex::new(SecurityService::new(300))))
+    }
+
+    async fn request_invalid_phone() -> AppError {
+        let (provider, auth_service, security) = sms_fixture();
+        let error = send_sms_code(&auth_service, &security, "12345").await.unwrap_err();
+        // 格式错误时不调用后端，也不占用限流次数
+        assert_eq!(provider.sent.load(Ordering::SeqCst), 0);
+        error
+    }
+
+    #[tokio::test]
+    async fn test_send_sms_code_rejects_invalid_phone() {
+        assert_eq!(request_invalid_phone().await.message, "手机号格式不正确");
+    }
+
+    #[tokio::test]
+    async fn test_rapid_sms_requests_rate_limited() {
+        let (provider, auth_service, security) = sms_fixture();
+
+        assert_eq!(send_sms_code(&auth_service, &security, "13800138000").await.unwrap(), 60);
+        let error = send_sms_code(&auth_service, &security, "13800138000").await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("RATE_LIMITED"));
+        assert!(matches!(error.error_type, ErrorType::PermissionError));
+        assert!(error.details.unwrap()["retryAfterSeconds"].as_u64().unwrap() > 0);
+
+        // 被限流的请求不会到达后端
+        assert_eq!(provider.sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_validation_failure_payload_has_field_violations() {
+        let error = request_invalid_phone().await;
+        let payload = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(payload["type"], "VALIDATION_ERROR");
+        assert_eq!(payload["code"], "VALIDATION_FAILED");
+        assert_eq!(payload["retryable"], false);
+        assert_eq!(payload["details"]["violations"][0]["field"], "phone");
+        assert_eq!(payload["details"]["violations"][0]["code"], "INVALID_PHONE");
+    }
 }
\ No newline at end of file
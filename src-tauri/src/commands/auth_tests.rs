@@ -37,7 +37,7 @@ mod tests {
 
         let result = auth_login(credentials).await;
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "用户名或密码错误");
+        assert_eq!(result.unwrap_err().message, "用户名或密码错误");
     }
 
     #[tokio_test::test]
@@ -72,7 +72,7 @@ mod tests {
 
         let result = auth_login(credentials).await;
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "手机号或验证码错误");
+        assert_eq!(result.unwrap_err().message, "手机号或验证码错误");
     }
 
     #[tokio_test::test]
@@ -107,7 +107,7 @@ mod tests {
 
         let result = auth_login(credentials).await;
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "身份证号格式错误");
+        assert_eq!(result.unwrap_err().message, "身份证号格式错误");
     }
 
     #[tokio_test::test]
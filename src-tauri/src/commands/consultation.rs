@@ -0,0 +1,514 @@
+// 问诊流程相关命令
+
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::{current_data_scope, current_masking, require_permission, PermissionServiceState};
+use crate::commands::security::SecurityServiceState;
+use crate::commands::websocket::WebSocketManagerState;
+use crate::commands::window::{close_consultation_window, WindowManagerState};
+use crate::database::dao::{ConsultationNoteDao, DoctorDao, IntakeFormDao};
+use crate::models::{
+    AppError, Consultation, ConsultationMetrics, ConsultationNote, ConsultationPriority, ConsultationQueueItem,
+    ConsultationTransfer, ConsultationTransferResult, ConversationOverview, DataScope, Doctor, ErrorType, IntakeForm,
+    PaginatedResponse, Permission, TransferCandidate,
+};
+use crate::services::security::AuditAction;
+use crate::services::{
+    ConsultationExpiry, ConsultationExpiryService, ConsultationService, TranscriptExportResult, TranscriptFormat,
+    TranscriptService, WebSocketEvent, PERMISSION_DENIED,
+};
+use crate::utils::ValidationService;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+
+const DEFAULT_CONVERSATION_PAGE_SIZE: u32 = 30;
+
+pub type ConsultationExpiryServiceState = Arc<ConsultationExpiryService>;
+
+// 医生只能查看自己的问诊队列和统计
+async fn ensure_doctor_in_scope(permissions: &PermissionServiceState, doctor_id: &str) -> Result<(), AppError> {
+    match current_data_scope(permissions).await? {
+        DataScope::Doctor(current) if current != doctor_id => {
+            Err(AppError::new(ErrorType::PermissionError, "无权查看其他医生的问诊")
+                .with_code(PERMISSION_DENIED)
+                .with_retryable(false))
+        }
+        _ => Ok(()),
+    }
+}
+
+// 医生只能操作自己接诊的问诊
+pub(crate) async fn ensure_consultation_in_scope(
+    permissions: &PermissionServiceState,
+    consultation: &Consultation,
+) -> Result<(), AppError> {
+    match current_data_scope(permissions).await? {
+        DataScope::Doctor(current) if current != consultation.doctor_id => {
+            Err(AppError::new(ErrorType::PermissionError, "无权操作其他医生的问诊")
+                .with_code(PERMISSION_DENIED)
+                .with_retryable(false))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub async fn accept_consultation(
+    consultation_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Consultation, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Accepting consultation: {}", consultation_id);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let consultation_service = ConsultationService::new();
+
+    consultation_service
+        .accept_consultation(&consultation_id, user_id.as_deref())
+        .await
+}
+
+#[tauri::command]
+pub async fn complete_consultation(
+    consultation_id: String,
+    diagnosis: String,
+    prescription: Option<String>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Consultation, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Completing consultation: {}", consultation_id);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let consultation_service = ConsultationService::new();
+
+    consultation_service
+        .complete_consultation(&consultation_id, &diagnosis, prescription.as_deref(), user_id.as_deref())
+        .await
+}
+
+#[tauri::command]
+pub async fn cancel_consultation(
+    consultation_id: String,
+    reason: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Consultation, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Cancelling consultation: {}, reason: {}", consultation_id, reason);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let consultation_service = ConsultationService::new();
+
+    consultation_service
+        .cancel_consultation(&consultation_id, &reason, user_id.as_deref())
+        .await
+}
+
+// 收到 consultation-expiring 提醒后医生选择继续问诊，超时从此刻重新计算
+#[tauri::command]
+pub async fn keep_alive_consultation(
+    consultation_id: String,
+    expiry_service: State<'_, ConsultationExpiryServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<ConsultationExpiry, AppError> {
+    require_database(&readiness).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+    ensure_consultation_in_scope(&permissions, &consultation).await?;
+
+    let inactivity = expiry_service.inactivity()?;
+    Ok(expiry_service.keep_alive(&consultation_id, inactivity)?)
+}
+
+#[tauri::command]
+pub async fn get_consultation_queue(
+    doctor_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<ConsultationQueueItem>, AppError> {
+    require_database(&readiness).await?;
+    ensure_doctor_in_scope(&permissions, &doctor_id).await?;
+    let consultation_service = ConsultationService::new();
+
+    consultation_service.get_consultation_queue(&doctor_id).await
+}
+
+/// 会话侧边栏列表，按最近活动排序
+#[tauri::command]
+pub async fn get_conversation_list(
+    doctor_id: String,
+    page: Option<u32>,
+    page_size: Option<u32>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PaginatedResponse<ConversationOverview>, AppError> {
+    require_database(&readiness).await?;
+    ensure_doctor_in_scope(&permissions, &doctor_id).await?;
+    let masking = current_masking(&permissions).await;
+    let consultation_service = ConsultationService::new();
+
+    let mut conversations = consultation_service
+        .get_conversation_list(&doctor_id, page.unwrap_or(1), page_size.unwrap_or(DEFAULT_CONVERSATION_PAGE_SIZE))
+        .await?;
+    for conversation in conversations.items.iter_mut() {
+        conversation.patient_name = masking.patient_name(&conversation.patient_name);
+    }
+    Ok(conversations)
+}
+
+#[tauri::command]
+pub async fn get_consultation_metrics(
+    doctor_id: String,
+    days: Option<u32>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<ConsultationMetrics, AppError> {
+    require_database(&readiness).await?;
+    ensure_doctor_in_scope(&permissions, &doctor_id).await?;
+    let consultation_service = ConsultationService::new();
+
+    consultation_service
+        .get_consultation_metrics(&doctor_id, days.unwrap_or(30))
+        .await
+}
+
+// 把问诊转给其他医生：通知患者端，并关闭本机上原医生的问诊窗口
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn transfer_consultation(
+    app: AppHandle,
+    consultation_id: String,
+    target_doctor_id: String,
+    target_doctor_name: Option<String>,
+    note: Option<String>,
+    permissions: State<'_, PermissionServiceState>,
+    ws_manager: State<'_, WebSocketManagerState>,
+    window_state: State<'_, WindowManagerState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<ConsultationTransferResult, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Transferring consultation {} to doctor {}", consultation_id, target_doctor_id);
+
+    let consultation_service = ConsultationService::new();
+    let consultation = consultation_service.get_consultation(&consultation_id).await?;
+    ensure_consultation_in_scope(&permissions, &consultation).await?;
+    let user_id = permissions.lock().await.current_user_id().map(str::to_string);
+
+    let result = consultation_service
+        .transfer_consultation(
+            &consultation_id,
+            &target_doctor_id,
+            target_doctor_name.as_deref(),
+            note.as_deref(),
+            user_id.as_deref(),
+        )
+        .await?;
+
+    let event = WebSocketEvent::ConsultationTransferred {
+        consultation_id: consultation_id.clone(),
+        from_doctor_id: result.transfer.from_doctor_id.clone(),
+        to_doctor_id: result.transfer.to_doctor_id.clone(),
+        note: result.transfer.note.clone(),
+    };
+    // 转接已落库，推送失败时由系统消息随下次同步送达患者端
+    if ws_manager.lock().await.broadcast_event(&event).await == 0 {
+        tracing::warn!("No WebSocket connection, transfer of {} will reach patients on next sync", consultation_id);
+    }
+
+    if let Some(window_id) = close_consultation_window(&app, &window_state, &consultation_id) {
+        tracing::info!("Closed consultation window {} after transfer", window_id);
+    }
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_consultation_transfers(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<ConsultationTransfer>, AppError> {
+    require_database(&readiness).await?;
+    let scope = current_data_scope(&permissions).await?;
+    let transfers = ConsultationService::new().get_consultation_transfers(&consultation_id).await?;
+
+    // 医生只能看到自己参与的转接
+    Ok(match scope {
+        DataScope::All => transfers,
+        DataScope::Doctor(current) => transfers
+            .into_iter()
+            .filter(|t| t.from_doctor_id == current || t.to_doctor_id == current)
+            .collect(),
+    })
+}
+
+// 转接问诊时可选的医生：排除问诊当前医生，与其同科室的优先，其次按在线状态排列
+#[tauri::command]
+pub async fn get_transfer_candidates(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<TransferCandidate>, AppError> {
+    require_database(&readiness).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+    ensure_consultation_in_scope(&permissions, &consultation).await?;
+
+    let doctor_dao = DoctorDao::new();
+    let department = doctor_dao
+        .find_by_id(&consultation.doctor_id)
+        .map_err(|e| AppError::from(e).context("获取医生信息失败"))?
+        .and_then(|doctor| doctor.department);
+
+    doctor_dao
+        .find_transfer_candidates(&consultation.doctor_id, department.as_deref())
+        .map_err(|e| AppError::from(e).context("获取转接候选医生失败"))
+}
+
+// 按姓名、科室或职称搜索院内医生
+#[tauri::command]
+pub async fn search_doctors(
+    keyword: Option<String>,
+    department: Option<String>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<Doctor>, AppError> {
+    require_database(&readiness).await?;
+
+    DoctorDao::new()
+        .search_doctors(keyword.as_deref(), department.as_deref())
+        .map_err(|e| AppError::from(e).context("搜索医生失败"))
+}
+
+// 调整候诊优先级，修改前后的优先级和原因记录在审计日志中
+#[tauri::command]
+pub async fn set_consultation_priority(
+    consultation_id: String,
+    priority: ConsultationPriority,
+    reason: Option<String>,
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Consultation, AppError> {
+    require_database(&readiness).await?;
+    let consultation_service = ConsultationService::new();
+    let consultation = consultation_service.get_consultation(&consultation_id).await?;
+    ensure_consultation_in_scope(&permissions, &consultation).await?;
+    let user_id = permissions.lock().await.current_user_id().map(str::to_string);
+    tracing::info!("Setting priority of consultation {} to {}", consultation_id, priority.as_str());
+
+    let result = consultation_service.set_priority(&consultation_id, priority).await;
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "set_consultation_priority".to_string());
+    metadata.insert("priority".to_string(), priority.as_str().to_string());
+    metadata.insert("previousPriority".to_string(), consultation.priority.as_str().to_string());
+    if let Some(reason) = reason.as_deref().map(str::trim).filter(|r| !r.is_empty()) {
+        metadata.insert("reason".to_string(), reason.to_string());
+    }
+    let (status, error_message) = match &result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.message.clone())),
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::UpdateConsultation,
+            Some("consultation".to_string()),
+            Some(consultation_id.clone()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for consultation priority: {}", e);
+    }
+
+    result.map(|(_, updated)| updated)
+}
+
+// 患者未填写问卷时返回 None；格式异常的问卷返回原始数据和 formatError
+#[tauri::command]
+pub async fn get_intake_form(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<IntakeForm>, AppError> {
+    require_database(&readiness).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+    ensure_consultation_in_scope(&permissions, &consultation).await?;
+
+    IntakeFormDao::new()
+        .find(&consultation_id)
+        .map_err(|e| AppError::from(e).context("获取问诊问卷失败"))
+}
+
+// 导出问诊记录用于打印归档，导出文件含患者信息，无论成功与否都记录审计日志
+#[tauri::command]
+pub async fn export_consultation_transcript(
+    consultation_id: String,
+    format: TranscriptFormat,
+    output_path: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<TranscriptExportResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ExportPatientData).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+    ensure_consultation_in_scope(&permissions, &consultation).await?;
+    tracing::info!("Exporting transcript for consultation {}, format: {:?}", consultation_id, format);
+
+    let masking = current_masking(&permissions).await;
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let result =
+        TranscriptService::new().export(&consultation_id, format, std::path::Path::new(&output_path), &masking);
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "export_consultation_transcript".to_string());
+    metadata.insert("format".to_string(), format!("{:?}", format).to_lowercase());
+    metadata.insert("path".to_string(), output_path.clone());
+    let (status, error_message) = match &result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.to_string())),
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::DownloadFile,
+            Some("consultation".to_string()),
+            Some(consultation_id.clone()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for transcript export: {}", e);
+    }
+
+    result.map_err(|e| {
+        tracing::error!("Transcript export failed: {}", e);
+        AppError::from(e)
+    })
+}
+
+// 问诊备注是接诊医生的私人记录，不发送给患者，也不包含在问诊记录导出和消息同步中
+#[tauri::command]
+pub async fn save_consultation_note(
+    consultation_id: String,
+    content: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<ConsultationNote>, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+    ensure_note_owner(&consultation, &doctor_id)?;
+    let note_dao = ConsultationNoteDao::new();
+
+    // 内容为空视为删除
+    if content.trim().is_empty() {
+        note_dao
+            .delete(&consultation_id, &doctor_id)
+            .map_err(|e| AppError::from(e).context("删除问诊备注失败"))?;
+        return Ok(None);
+    }
+    ValidationService::validate_consultation_note(&content).map_err(|e| AppError::invalid_argument(e.to_string()))?;
+
+    note_dao
+        .upsert(&consultation_id, &doctor_id, &content)
+        .map(Some)
+        .map_err(|e| AppError::from(e).context("保存问诊备注失败"))
+}
+
+// 管理员可查看接诊医生的备注，此时记录敏感数据访问审计
+#[tauri::command]
+pub async fn get_consultation_note(
+    consultation_id: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<ConsultationNote>, AppError> {
+    require_database(&readiness).await?;
+    let user_id = current_doctor_id(&token_refresh).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+
+    if consultation.doctor_id == user_id {
+        return ConsultationNoteDao::new()
+            .find(&consultation_id, &user_id)
+            .map_err(|e| AppError::from(e).context("获取问诊备注失败"));
+    }
+    if current_data_scope(&permissions).await? != DataScope::All {
+        return Err(note_owner_required());
+    }
+
+    let result = ConsultationNoteDao::new()
+        .find(&consultation_id, &consultation.doctor_id)
+        .map_err(|e| AppError::from(e).context("获取问诊备注失败"));
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "read_consultation_note".to_string());
+    metadata.insert("ownerDoctorId".to_string(), consultation.doctor_id.clone());
+    let (status, error_message) = match &result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.message.clone())),
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id,
+            AuditAction::AccessSensitiveData,
+            Some("consultation_note".to_string()),
+            Some(consultation_id.clone()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for consultation note access: {}", e);
+    }
+
+    result
+}
+
+#[tauri::command]
+pub async fn delete_consultation_note(
+    consultation_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<bool, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let consultation = ConsultationService::new().get_consultation(&consultation_id).await?;
+    ensure_note_owner(&consultation, &doctor_id)?;
+
+    ConsultationNoteDao::new()
+        .delete(&consultation_id, &doctor_id)
+        .map_err(|e| AppError::from(e).context("删除问诊备注失败"))
+}
+
+// 备注只能由接诊医生本人编辑，管理员也不能代为修改
+fn ensure_note_owner(consultation: &Consultation, doctor_id: &str) -> Result<(), AppError> {
+    if consultation.doctor_id == doctor_id {
+        Ok(())
+    } else {
+        Err(note_owner_required())
+    }
+}
+
+fn note_owner_required() -> AppError {
+    AppError::new(ErrorType::PermissionError, "只有接诊医生可以访问问诊备注")
+        .with_code(PERMISSION_DENIED)
+        .with_retryable(false)
+}
@@ -1,33 +1,278 @@
 // 数据库相关命令
 
-use tauri::AppHandle;
+use crate::commands::permission::{require_permission, PermissionServiceState};
+use crate::commands::rate_limit::{require_rate_limit, CommandRateLimiterState};
+use crate::commands::security::SecurityServiceState;
+use crate::database::dao::{MessageDao, SyncStateDao};
+use crate::database::{
+    get_database, get_query_optimizer, load_database_config, save_database_config, try_get_database,
+    validate_enum_columns, DatabaseConfig, DatabaseEncryption, DatabaseReadiness, InitStatus, QueryStats, QueryStatsReport,
+    DATABASE_CONFIG_FILE, DATABASE_READY_TIMEOUT,
+};
+use crate::models::{
+    AppError, EnumColumnViolation, ErrorType, IntegrityRepairReport, MaintenanceRun, MaintenanceTrigger, Permission,
+    RetentionPolicy,
+};
+use crate::services::{
+    save_schedule_config, validate_retention_policy, BackgroundSyncStatus, IntegrityRepairService, OfflineState,
+    OfflineStateService, RetentionService, SyncReport, SyncScheduleConfig, SyncScheduler, BACKUP_DIR_NAME, SYNC_SCHEDULE_FILE,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State, WebviewWindow};
 
-#[tauri::command]
-pub async fn init_database(app: AppHandle) -> Result<(), String> {
-    println!("Initializing database...");
+pub type SyncSchedulerState = Arc<SyncScheduler>;
+pub type OfflineStateServiceState = Arc<OfflineStateService>;
+pub type DatabaseReadinessState = Arc<DatabaseReadiness>;
+
+// 访问数据库的命令先等待启动迁移完成，超时返回可重试的 DB_NOT_READY
+pub(crate) async fn require_database(readiness: &DatabaseReadinessState) -> Result<(), AppError> {
+    readiness.wait_ready(DATABASE_READY_TIMEOUT).await
+}
 
-    // TODO: 实现数据库初始化逻辑
-    crate::database::init_database(&app)
+// 数据库由启动流程初始化，这里只等待其完成
+#[tauri::command]
+pub async fn init_database(readiness: State<'_, DatabaseReadinessState>) -> Result<(), String> {
+    require_database(&readiness)
         .await
-        .map_err(|e| format!("Database initialization failed: {}", e))?;
+        .map_err(|e| format!("Database initialization failed: {}", e))
+}
 
-    println!("Database initialized successfully");
-    Ok(())
+/// 启动初始化进度，前端在收到 init-progress 事件前调用以获取当前阶段
+#[tauri::command]
+pub async fn get_init_status(readiness: State<'_, DatabaseReadinessState>) -> Result<InitStatus, AppError> {
+    Ok(readiness.status())
 }
 
+// 手动同步与后台同步共用调度器，避免并发执行
 #[tauri::command]
-pub async fn sync_data() -> Result<(), String> {
-    println!("Syncing data...");
+pub async fn sync_data(
+    scheduler: State<'_, SyncSchedulerState>,
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<SyncReport, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "sync_data")?;
+    require_permission(&permissions, Permission::SyncData).await?;
+    tracing::info!("Syncing data...");
 
-    // TODO: 实现数据同步逻辑
-    // 1. 检查网络连接
-    // 2. 同步患者数据
-    // 3. 同步消息数据
-    // 4. 同步其他必要数据
+    let report = scheduler.run_now().await.map_err(|e| {
+        tracing::error!("Data sync failed: {}", e);
+        AppError::new(ErrorType::NetworkError, e).with_retryable(true)
+    })?;
+
+    tracing::info!(
+        "Data sync completed: pulled {}, pushed {}, conflicts {}",
+        report.pulled, report.pushed, report.conflicts
+    );
+    Ok(report)
+}
 
-    // 模拟同步延迟
-    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+#[tauri::command]
+pub async fn pause_background_sync(
+    app: AppHandle,
+    scheduler: State<'_, SyncSchedulerState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<BackgroundSyncStatus, AppError> {
+    require_permission(&permissions, Permission::SyncData).await?;
+    tracing::info!("Pausing background sync");
+    persist_schedule(&app, &scheduler.pause())?;
+    Ok(scheduler.status())
+}
+
+#[tauri::command]
+pub async fn resume_background_sync(
+    app: AppHandle,
+    scheduler: State<'_, SyncSchedulerState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<BackgroundSyncStatus, AppError> {
+    require_permission(&permissions, Permission::SyncData).await?;
+    tracing::info!("Resuming background sync");
+    persist_schedule(&app, &scheduler.resume())?;
+    Ok(scheduler.status())
+}
+
+#[tauri::command]
+pub async fn get_sync_status(scheduler: State<'_, SyncSchedulerState>) -> Result<BackgroundSyncStatus, AppError> {
+    Ok(scheduler.status())
+}
 
-    println!("Data sync completed");
+// 离线提示所需的连通性、待发送消息数和待同步实体
+#[tauri::command]
+pub async fn get_offline_state(
+    offline_state: State<'_, OfflineStateServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<OfflineState, AppError> {
+    require_database(&readiness).await?;
+    offline_state
+        .offline_state(&MessageDao::new(), &SyncStateDao::new())
+        .map_err(|e| AppError::from(e).context("读取离线状态失败"))
+}
+
+pub fn sync_schedule_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(SYNC_SCHEDULE_FILE))
+}
+
+fn persist_schedule(app: &AppHandle, config: &SyncScheduleConfig) -> Result<(), AppError> {
+    let path = sync_schedule_path(app).ok_or_else(|| AppError::file_error("无法获取应用数据目录"))?;
+    save_schedule_config(&path, config).map_err(AppError::file_error)
+}
+
+#[tauri::command]
+pub async fn get_query_stats(permissions: State<'_, PermissionServiceState>) -> Result<QueryStatsReport, AppError> {
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    let optimizer = get_query_optimizer();
+    let mut queries = optimizer.get_all_stats();
+    queries.sort_by_key(|stats| std::cmp::Reverse(stats.total_duration));
+    Ok(QueryStatsReport {
+        queries,
+        lock_contention: optimizer.lock_contention(),
+    })
+}
+
+#[tauri::command]
+pub async fn get_slow_queries(permissions: State<'_, PermissionServiceState>) -> Result<Vec<QueryStats>, AppError> {
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    Ok(get_query_optimizer().get_slow_queries())
+}
+
+#[tauri::command]
+pub async fn clear_query_stats(permissions: State<'_, PermissionServiceState>) -> Result<(), AppError> {
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    tracing::info!("Clearing query stats");
+    get_query_optimizer().clear_stats();
     Ok(())
-}
\ No newline at end of file
+}
+
+// 扫描枚举列中无法解析的取值，供排查历史脏数据
+#[tauri::command]
+pub async fn check_enum_columns(
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<EnumColumnViolation>, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    let connection = get_database().get_connection();
+    let conn = connection.lock().unwrap();
+    validate_enum_columns(&conn).map_err(|e| AppError::database_error(e.to_string()))
+}
+
+pub fn database_config_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(DATABASE_CONFIG_FILE))
+}
+
+#[tauri::command]
+pub async fn get_database_config(
+    app: AppHandle,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<DatabaseConfig, AppError> {
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    let path = database_config_path(&app).ok_or_else(|| AppError::file_error("无法获取应用数据目录"))?;
+    Ok(load_database_config(&path))
+}
+
+// 切换整库加密模式，下次启动时生效；启用时现有明文数据库会先备份再迁移为加密库
+#[tauri::command]
+pub async fn set_database_encryption(
+    app: AppHandle,
+    encryption: DatabaseEncryption,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<DatabaseConfig, AppError> {
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+
+    if encryption == DatabaseEncryption::Sqlcipher && !cfg!(feature = "sqlcipher") {
+        return Err(AppError::invalid_argument("当前版本未包含 SQLCipher 支持，无法启用整库加密"));
+    }
+    // 加密库没有回退到明文的迁移路径，切回去会导致下次启动无法打开
+    if encryption == DatabaseEncryption::Plaintext && try_get_database().is_some_and(|db| db.is_encrypted()) {
+        return Err(AppError::invalid_argument("数据库已加密，不能切换回明文模式"));
+    }
+
+    let path = database_config_path(&app).ok_or_else(|| AppError::file_error("无法获取应用数据目录"))?;
+    let config = DatabaseConfig { encryption };
+    save_database_config(&path, &config).map_err(AppError::file_error)?;
+    tracing::info!("Database encryption set to {:?}, effective after restart", encryption);
+    Ok(config)
+}
+
+#[tauri::command]
+pub async fn get_retention_policy(
+    app: AppHandle,
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<RetentionPolicy, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    retention_service(&app, &security_service)
+        .policy()
+        .map_err(|e| AppError::database_error(e.to_string()))
+}
+
+#[tauri::command]
+pub async fn update_retention_policy(
+    policy: RetentionPolicy,
+    app: AppHandle,
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<RetentionPolicy, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    validate_retention_policy(&policy).map_err(|e| AppError::invalid_argument(e.to_string()))?;
+
+    retention_service(&app, &security_service)
+        .update_policy(policy)
+        .map_err(|e| AppError::database_error(e.to_string()))
+}
+
+#[tauri::command]
+pub async fn run_retention_now(
+    app: AppHandle,
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MaintenanceRun, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    tracing::info!("Running retention cleanup manually");
+
+    retention_service(&app, &security_service)
+        .run(MaintenanceTrigger::Manual)
+        .await
+        .map_err(|e| AppError::database_error(e.to_string()))
+}
+
+// 修复孤立记录；上次未正常退出时启动流程会自动执行一次
+#[tauri::command]
+pub async fn run_integrity_repair(
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<IntegrityRepairReport, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    tracing::info!("Running integrity repair manually");
+
+    IntegrityRepairService::new(security_service.inner().clone())
+        .run(MaintenanceTrigger::Manual)
+        .await
+        .map_err(|e| AppError::database_error(e.to_string()))
+}
+
+pub fn retention_backup_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(BACKUP_DIR_NAME))
+}
+
+fn retention_service(app: &AppHandle, security_service: &SecurityServiceState) -> RetentionService {
+    RetentionService::new(security_service.clone(), retention_backup_dir(app))
+}
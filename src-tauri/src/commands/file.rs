@@ -1,9 +1,28 @@
-use crate::models::file_cache::FileCache;
-use crate::services::file::FileService;
-use crate::utils::error::AppResult;
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::message::max_upload_size;
+use crate::commands::permission::{require_permission, PermissionServiceState};
+use crate::database::dao::file_cache_dao::quota_bytes;
+use crate::database::dao::{FileCacheDao, PatientDao};
+use crate::database::try_get_database;
+use crate::models::file_cache::{FileCache, PinnedFile};
+use crate::models::{AttachmentUsage, Permission};
+use crate::services::app_settings::effective_config;
+use crate::services::attachment_quota::AttachmentQuotaService;
+use crate::services::avatar::serve_avatar;
+use crate::services::chunked_upload::{check_inline_upload_size, ChunkedUploadManager};
+use crate::services::file::{
+    image_mime_type, DownloadManager, DownloadPriority, DownloadTask, FileService, UploadCandidateReport,
+};
+use crate::utils::error::{AppError, AppResult};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tauri::State;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
+
+pub type DownloadManagerState = Arc<DownloadManager>;
+
+// 远程文件的本地下载目录
+pub const DOWNLOAD_DIR_NAME: &str = "downloads";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileStorageConfig {
@@ -62,27 +81,69 @@ pub struct FileStatistics {
 }
 
 /// 保存文件到本地存储
+/// 不超过 5MB 的文件可直接传 file_data，更大的文件先分片上传后传 transfer_id
 #[tauri::command]
 pub async fn save_file_locally(
-    file_data: Vec<u8>,
+    file_data: Option<Vec<u8>>,
+    transfer_id: Option<String>,
     file_name: String,
     config: FileStorageConfig,
     file_service: State<'_, FileService>,
+    uploads: State<'_, ChunkedUploadManager>,
 ) -> AppResult<String> {
-    println!("Saving file locally: {} ({} bytes)", file_name, file_data.len());
-
-    let local_path = file_service.save_file(&file_data, &file_name).await?;
+    let file_data = match (file_data, transfer_id) {
+        (_, Some(transfer_id)) => uploads.take(&transfer_id)?.data,
+        (Some(file_data), None) => {
+            check_inline_upload_size(file_data.len() as u64, max_upload_size())?;
+            file_data
+        }
+        (None, None) => return Err(AppError::invalid_argument("缺少文件内容或分片上传 ID")),
+    };
+    tracing::info!("Saving file locally: {} ({} bytes)", file_name, file_data.len());
+
+    let check = file_service.check_upload_content(&file_name, &file_data);
+    if !check.is_valid {
+        return Err(AppError::validation_failed(check));
+    }
+
+    let dir = Path::new(&config.local_storage_path);
+    // 图片去除 EXIF 后保存，并在 thumbnails 目录生成缩略图
+    let local_path = if image_mime_type(&file_name).is_some() {
+        let image = file_service
+            .prepare_image(&file_data)
+            .map_err(|e| AppError::invalid_argument(e.to_string()))?;
+        file_service.save_image(dir, &image, &file_name).await?.0
+    } else {
+        file_service.save_file(dir, &file_data, &file_name).await?
+    };
 
     Ok(local_path.to_string_lossy().to_string())
 }
 
+/// 拖入或粘贴的文件在上传前校验，结果用于确认对话框
+#[tauri::command]
+pub async fn validate_upload_candidate(path: String) -> AppResult<UploadCandidateReport> {
+    tracing::debug!("Validating upload candidate: {}", path);
+
+    let path = PathBuf::from(path);
+    if !path.is_file() {
+        return Err(AppError::file_error(format!("文件不存在: {}", path.display())));
+    }
+
+    FileService::new()
+        .inspect_upload_candidate(&path, &effective_config())
+        .map_err(|e| AppError::file_error(format!("读取文件失败: {}", e)))
+}
+
 /// 从本地存储读取文件
 #[tauri::command]
 pub async fn read_file_from_local(
     local_path: String,
     file_service: State<'_, FileService>,
+    permissions: State<'_, PermissionServiceState>,
 ) -> AppResult<Vec<u8>> {
-    println!("Reading file from local: {}", local_path);
+    require_permission(&permissions, Permission::ReadFiles).await?;
+    tracing::debug!("Reading file from local: {}", local_path);
 
     let path = PathBuf::from(local_path);
     let file_data = tokio::fs::read(&path).await?;
@@ -102,8 +163,10 @@ pub async fn file_exists(file_path: String) -> AppResult<bool> {
 pub async fn delete_local_file(
     local_path: String,
     file_service: State<'_, FileService>,
+    permissions: State<'_, PermissionServiceState>,
 ) -> AppResult<()> {
-    println!("Deleting local file: {}", local_path);
+    require_permission(&permissions, Permission::DeleteFiles).await?;
+    tracing::info!("Deleting local file: {}", local_path);
 
     let path = PathBuf::from(local_path);
     file_service.delete_file(&path).await?;
@@ -118,7 +181,7 @@ pub async fn compress_file(
     quality: u8,
     file_service: State<'_, FileService>,
 ) -> AppResult<String> {
-    println!("Compressing file: {} with quality: {}", file_path, quality);
+    tracing::info!("Compressing file: {} with quality: {}", file_path, quality);
 
     // TODO: 实现文件压缩逻辑
     // 这里简化处理，实际项目中需要根据文件类型选择合适的压缩算法
@@ -134,7 +197,7 @@ pub async fn encrypt_file(
     file_path: String,
     file_service: State<'_, FileService>,
 ) -> AppResult<String> {
-    println!("Encrypting file: {}", file_path);
+    tracing::info!("Encrypting file: {}", file_path);
 
     // TODO: 实现文件加密逻辑
     // 使用 AES-256-GCM 加密
@@ -150,7 +213,7 @@ pub async fn decrypt_file(
     encrypted_path: String,
     file_service: State<'_, FileService>,
 ) -> AppResult<String> {
-    println!("Decrypting file: {}", encrypted_path);
+    tracing::info!("Decrypting file: {}", encrypted_path);
 
     // TODO: 实现文件解密逻辑
 
@@ -162,7 +225,7 @@ pub async fn decrypt_file(
 /// 添加文件到缓存
 #[tauri::command]
 pub async fn add_file_to_cache(cache_info: FileCache) -> AppResult<()> {
-    println!("Adding file to cache: {}", cache_info.id);
+    tracing::debug!("Adding file to cache: {}", cache_info.id);
 
     // TODO: 实现添加文件到缓存的逻辑
     // 1. 验证缓存信息
@@ -175,7 +238,7 @@ pub async fn add_file_to_cache(cache_info: FileCache) -> AppResult<()> {
 /// 从缓存获取文件信息
 #[tauri::command]
 pub async fn get_file_from_cache(file_url: String) -> AppResult<Option<FileCache>> {
-    println!("Getting file from cache: {}", file_url);
+    tracing::debug!("Getting file from cache: {}", file_url);
 
     // TODO: 实现从缓存获取文件信息的逻辑
     // 1. 查询数据库
@@ -188,7 +251,7 @@ pub async fn get_file_from_cache(file_url: String) -> AppResult<Option<FileCache
 /// 检查文件是否在缓存中
 #[tauri::command]
 pub async fn is_file_in_cache(file_url: String) -> AppResult<bool> {
-    println!("Checking if file is in cache: {}", file_url);
+    tracing::debug!("Checking if file is in cache: {}", file_url);
 
     // TODO: 实现检查文件是否在缓存中的逻辑
 
@@ -197,8 +260,12 @@ pub async fn is_file_in_cache(file_url: String) -> AppResult<bool> {
 
 /// 从缓存删除文件
 #[tauri::command]
-pub async fn remove_file_from_cache(file_url: String) -> AppResult<()> {
-    println!("Removing file from cache: {}", file_url);
+pub async fn remove_file_from_cache(
+    file_url: String,
+    permissions: State<'_, PermissionServiceState>,
+) -> AppResult<()> {
+    require_permission(&permissions, Permission::DeleteFiles).await?;
+    tracing::debug!("Removing file from cache: {}", file_url);
 
     // TODO: 实现从缓存删除文件的逻辑
     // 1. 删除本地文件
@@ -214,7 +281,7 @@ pub async fn update_cache_last_accessed(
     file_url: String,
     last_accessed: String,
 ) -> AppResult<()> {
-    println!("Updating cache last accessed: {} at {}", file_url, last_accessed);
+    tracing::debug!("Updating cache last accessed: {} at {}", file_url, last_accessed);
 
     // TODO: 实现更新最后访问时间的逻辑
 
@@ -224,7 +291,7 @@ pub async fn update_cache_last_accessed(
 /// 清理文件缓存
 #[tauri::command]
 pub async fn cleanup_file_cache(strategy: FileCacheCleanupStrategy) -> AppResult<CleanupResult> {
-    println!("Cleaning up file cache with strategy: {:?}", strategy);
+    tracing::info!("Cleaning up file cache with strategy: {:?}", strategy);
 
     // TODO: 实现缓存清理逻辑
     // 1. 根据策略查找需要清理的文件
@@ -241,38 +308,86 @@ pub async fn cleanup_file_cache(strategy: FileCacheCleanupStrategy) -> AppResult
 
 /// 清理过期缓存文件
 #[tauri::command]
-pub async fn cleanup_expired_cache_files() -> AppResult<u32> {
-    println!("Cleaning up expired cache files");
-
-    // TODO: 实现清理过期文件的逻辑
-
-    Ok(0)
+pub async fn cleanup_expired_cache_files(
+    force: Option<bool>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> AppResult<u32> {
+    require_database(&readiness).await?;
+    tracing::info!("Cleaning up expired cache files");
+
+    let expired = FileCacheDao::new()
+        .cleanup_expired(force.unwrap_or(false))
+        .map_err(|e| AppError::database_error(e.to_string()))?;
+    remove_cached_files(&expired);
+
+    Ok(expired.len() as u32)
 }
 
 /// 清理LRU缓存文件
 #[tauri::command]
-pub async fn cleanup_lru_cache_files(max_files: u32) -> AppResult<u32> {
-    println!("Cleaning up LRU cache files, max files: {}", max_files);
-
-    // TODO: 实现LRU清理逻辑
+pub async fn cleanup_lru_cache_files(
+    max_files: u32,
+    force: Option<bool>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> AppResult<u32> {
+    require_database(&readiness).await?;
+    tracing::info!("Cleaning up LRU cache files, max files: {}", max_files);
+
+    // 病历附件和未发送消息引用的文件已固定，除非强制清理否则不会被淘汰
+    let evicted = FileCacheDao::new()
+        .cleanup_lru(max_files, force.unwrap_or(false))
+        .map_err(|e| AppError::database_error(e.to_string()))?;
+    remove_cached_files(&evicted);
+
+    Ok(evicted.len() as u32)
+}
 
-    Ok(0)
+/// 清理超大缓存，返回释放的字节数
+#[tauri::command]
+pub async fn cleanup_oversized_cache(
+    max_size: u64,
+    force: Option<bool>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> AppResult<u64> {
+    require_database(&readiness).await?;
+    tracing::info!("Cleaning up oversized cache, max size: {}", max_size);
+
+    let evicted = FileCacheDao::new()
+        .cleanup_oversized(max_size, force.unwrap_or(false))
+        .map_err(|e| AppError::database_error(e.to_string()))?;
+    remove_cached_files(&evicted);
+
+    Ok(evicted.iter().map(|file| quota_bytes(file) as u64).sum())
 }
 
-/// 清理超大缓存
+/// 按引用固定的缓存文件，用于诊断缓存为何无法释放
 #[tauri::command]
-pub async fn cleanup_oversized_cache(max_size: u64) -> AppResult<u64> {
-    println!("Cleaning up oversized cache, max size: {}", max_size);
+pub async fn get_pinned_files(readiness: State<'_, DatabaseReadinessState>) -> AppResult<Vec<PinnedFile>> {
+    require_database(&readiness).await?;
 
-    // TODO: 实现超大缓存清理逻辑
+    FileCacheDao::new()
+        .get_pinned_files()
+        .map_err(|e| AppError::database_error(e.to_string()))
+}
 
-    Ok(0)
+// 删除被淘汰记录的本地文件及其缩略图和预览图
+fn remove_cached_files(files: &[FileCache]) {
+    for file in files {
+        if let Err(e) = std::fs::remove_file(&file.local_path) {
+            tracing::error!("Failed to remove cached file {}: {}", file.local_path, e);
+        }
+        for derived in file.thumbnail_path.iter().chain(&file.preview_path) {
+            if let Err(e) = std::fs::remove_file(derived) {
+                tracing::error!("Failed to remove derived file {}: {}", derived, e);
+            }
+        }
+    }
 }
 
 /// 获取文件缓存统计信息
 #[tauri::command]
 pub async fn get_file_cache_statistics() -> AppResult<FileStatistics> {
-    println!("Getting file cache statistics");
+    tracing::debug!("Getting file cache statistics");
 
     // TODO: 实现获取缓存统计信息的逻辑
 
@@ -290,43 +405,129 @@ pub async fn get_file_cache_statistics() -> AppResult<FileStatistics> {
 /// 获取缓存文件列表
 #[tauri::command]
 pub async fn get_cache_file_list(limit: u32, offset: u32) -> AppResult<Vec<FileCache>> {
-    println!("Getting cache file list, limit: {}, offset: {}", limit, offset);
+    tracing::debug!("Getting cache file list, limit: {}, offset: {}", limit, offset);
 
     // TODO: 实现获取缓存文件列表的逻辑
 
     Ok(vec![])
 }
 
-/// 清空所有缓存
+/// 清空所有缓存，固定的文件仅在强制清空时删除
 #[tauri::command]
-pub async fn clear_all_file_cache() -> AppResult<()> {
-    println!("Clearing all file cache");
+pub async fn clear_all_file_cache(
+    force: Option<bool>,
+    readiness: State<'_, DatabaseReadinessState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> AppResult<()> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::DeleteFiles).await?;
+    tracing::info!("Clearing all file cache");
 
-    // TODO: 实现清空所有缓存的逻辑
-    // 1. 删除所有缓存文件
-    // 2. 清空数据库记录
-    // 3. 重置统计信息
+    let cleared = FileCacheDao::new()
+        .clear_all(force.unwrap_or(false))
+        .map_err(|e| AppError::database_error(e.to_string()))?;
+    remove_cached_files(&cleared);
 
     Ok(())
 }
 
 /// 预热缓存
 #[tauri::command]
-pub async fn warmup_file_cache(file_urls: Vec<String>) -> AppResult<()> {
-    println!("Warming up cache for {} files", file_urls.len());
+pub async fn warmup_file_cache(file_urls: Vec<String>, downloads: State<'_, DownloadManagerState>) -> AppResult<()> {
+    tracing::info!("Warming up cache for {} files", file_urls.len());
 
-    // TODO: 实现缓存预热逻辑
-    // 1. 下载指定的文件
-    // 2. 添加到缓存
-    // 3. 更新统计信息
+    // 以低优先级排队，不影响用户主动发起的下载
+    for url in &file_urls {
+        if let Err(e) = downloads.enqueue(url, DownloadPriority::Low, None) {
+            tracing::warn!("Skipping cache warmup for {}: {}", url, e);
+        }
+    }
 
     Ok(())
 }
 
+/// 下载远程文件，进度通过 download-progress 事件推送；问诊附件传入 consultation_id 以计入附件配额
+#[tauri::command]
+pub async fn download_file(
+    url: String,
+    priority: Option<String>,
+    checksum: Option<String>,
+    consultation_id: Option<String>,
+    downloads: State<'_, DownloadManagerState>,
+) -> AppResult<DownloadTask> {
+    let priority = match priority.as_deref() {
+        Some(value) => DownloadPriority::parse(value)
+            .ok_or_else(|| AppError::invalid_argument(format!("无效的下载优先级: {}", value)))?,
+        None => DownloadPriority::Normal,
+    };
+    tracing::info!("Queueing download: {} ({})", url, priority.as_str());
+
+    downloads
+        .enqueue_for_consultation(&url, priority, checksum, consultation_id)
+        .map_err(|e| AppError::invalid_argument(e.to_string()))
+}
+
+/// 获取问诊或患者的附件用量和配额上限，二者必须且只能指定一个
+#[tauri::command]
+pub async fn get_attachment_usage(
+    consultation_id: Option<String>,
+    patient_id: Option<String>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> AppResult<AttachmentUsage> {
+    require_database(&readiness).await?;
+
+    let service = AttachmentQuotaService::new();
+    match (consultation_id, patient_id) {
+        (Some(consultation_id), None) => service.consultation_usage(&consultation_id),
+        (None, Some(patient_id)) => service.patient_usage(&patient_id),
+        _ => Err(AppError::invalid_argument("必须且只能指定问诊ID或患者ID之一")),
+    }
+}
+
+/// 取消下载，已下载的部分保留用于续传
+#[tauri::command]
+pub async fn cancel_download(id: String, downloads: State<'_, DownloadManagerState>) -> AppResult<bool> {
+    tracing::info!("Cancelling download: {}", id);
+    Ok(downloads.cancel(&id))
+}
+
+/// 获取下载队列
+#[tauri::command]
+pub async fn get_download_queue(downloads: State<'_, DownloadManagerState>) -> AppResult<Vec<DownloadTask>> {
+    Ok(downloads.queue())
+}
+
+pub fn download_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(DOWNLOAD_DIR_NAME))
+}
+
+// avatar:// 协议处理：数据库初始化前返回 503，前端稍后重试即可
+pub fn avatar_protocol_response(app: &AppHandle, request: &tauri::http::Request<Vec<u8>>) -> tauri::http::Response<Vec<u8>> {
+    let uri = request.uri();
+    let (status, content_type, cache_control, body) = match try_get_database() {
+        Some(db) => {
+            let cache_dir = download_dir(app).unwrap_or_else(|| std::env::temp_dir().join(DOWNLOAD_DIR_NAME));
+            let avatar = serve_avatar(&PatientDao::with_connection(db.get_connection()), &cache_dir, uri.host(), uri.path());
+            (avatar.status, avatar.content_type, avatar.cache_control, avatar.body)
+        }
+        None => (503, "text/plain", "no-store".to_string(), Vec::new()),
+    };
+
+    tauri::http::Response::builder()
+        .status(status)
+        .header(tauri::http::header::CONTENT_TYPE, content_type)
+        .header(tauri::http::header::CACHE_CONTROL, cache_control)
+        .body(body)
+        .unwrap_or_default()
+}
+
 /// 更新文件缓存记录
 #[tauri::command]
 pub async fn update_file_cache_record(cache_info: FileCache) -> AppResult<()> {
-    println!("Updating file cache record: {}", cache_info.id);
+    tracing::info!("Updating file cache record: {}", cache_info.id);
 
     // TODO: 实现更新缓存记录的逻辑
 
@@ -335,8 +536,12 @@ pub async fn update_file_cache_record(cache_info: FileCache) -> AppResult<()> {
 
 /// 删除文件缓存记录
 #[tauri::command]
-pub async fn delete_file_cache_record(local_path: String) -> AppResult<()> {
-    println!("Deleting file cache record for: {}", local_path);
+pub async fn delete_file_cache_record(
+    local_path: String,
+    permissions: State<'_, PermissionServiceState>,
+) -> AppResult<()> {
+    require_permission(&permissions, Permission::DeleteFiles).await?;
+    tracing::info!("Deleting file cache record for: {}", local_path);
 
     // TODO: 实现删除缓存记录的逻辑
 
@@ -346,7 +551,7 @@ pub async fn delete_file_cache_record(local_path: String) -> AppResult<()> {
 /// 获取文件缓存信息
 #[tauri::command]
 pub async fn get_file_cache_info(file_url: String) -> AppResult<Option<FileCache>> {
-    println!("Getting file cache info for: {}", file_url);
+    tracing::debug!("Getting file cache info for: {}", file_url);
 
     // TODO: 实现获取缓存信息的逻辑
 
@@ -359,9 +564,62 @@ pub async fn update_file_last_accessed(
     local_path: String,
     last_accessed: String,
 ) -> AppResult<()> {
-    println!("Updating file last accessed: {} at {}", local_path, last_accessed);
+    tracing::debug!("Updating file last accessed: {} at {}", local_path, last_accessed);
 
     // TODO: 实现更新最后访问时间的逻辑
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DatabaseReadiness;
+    use crate::models::UserRole;
+    use crate::services::{PermissionService, SecurityService, PERMISSION_DENIED};
+    use tauri::test::{mock_app, MockRuntime};
+    use tokio::sync::Mutex;
+
+    fn app_with_role(role: Option<UserRole>) -> tauri::App<MockRuntime> {
+        let app = mock_app();
+        let mut permissions = PermissionService::new(Arc::new(Mutex::new(SecurityService::new(300))));
+        permissions.start_session("user-1".to_string(), role);
+        let readiness = DatabaseReadiness::new();
+        readiness.mark_database_ready();
+
+        app.manage::<PermissionServiceState>(Arc::new(Mutex::new(permissions)));
+        app.manage::<DatabaseReadinessState>(Arc::new(readiness));
+        app.manage(FileService::new());
+        app
+    }
+
+    fn assert_denied(result: AppResult<()>) {
+        assert_eq!(result.unwrap_err().code.as_deref(), Some(PERMISSION_DENIED));
+    }
+
+    #[tokio::test]
+    async fn test_cache_deletion_requires_delete_files() {
+        let app = app_with_role(Some(UserRole::Nurse));
+        let url = "https://example.com/a.png".to_string();
+
+        assert_denied(remove_file_from_cache(url, app.state()).await);
+        assert_denied(delete_file_cache_record("/tmp/a.png".to_string(), app.state()).await);
+        assert_denied(clear_all_file_cache(Some(true), app.state(), app.state()).await);
+    }
+
+    #[tokio::test]
+    async fn test_read_file_requires_read_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.txt");
+        std::fs::write(&path, b"report").unwrap();
+        let local_path = path.to_string_lossy().to_string();
+
+        // 无法识别角色的会话没有任何权限
+        let app = app_with_role(None);
+        let result = read_file_from_local(local_path.clone(), app.state(), app.state()).await;
+        assert_eq!(result.unwrap_err().code.as_deref(), Some(PERMISSION_DENIED));
+
+        let app = app_with_role(Some(UserRole::Nurse));
+        let data = read_file_from_local(local_path, app.state(), app.state()).await.unwrap();
+        assert_eq!(data, b"report");
+    }
+}
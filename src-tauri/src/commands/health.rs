@@ -0,0 +1,113 @@
+// 应用健康检查命令
+
+use crate::commands::auth::TokenRefreshServiceState;
+use crate::commands::database::{require_database, DatabaseReadinessState, SyncSchedulerState};
+use crate::commands::permission::{require_permission, PermissionServiceState};
+use crate::commands::security::SecurityServiceState;
+use crate::commands::websocket::{EventDispatcherState, WebSocketManagerState};
+use crate::database::try_get_database;
+use crate::models::{DiagnosticTable, DiagnosticsBundleResult, Permission};
+use crate::services::security::AuditAction;
+use crate::services::{
+    collect_health, AppHealthReport, CacheProbe, DatabaseProbe, DeviceInfo, DiagnosticsBundleService, DiskProbe,
+    EventQueueProbe, HealthProbe, PendingMessagesProbe, SyncProbe, WebSocketProbe, FILE_CACHE_SIZE_LIMIT,
+    HEALTH_PROBE_TIMEOUT,
+};
+use crate::utils::AppError;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
+
+// 启动时采集一次，运行期间不变
+pub type DeviceInfoState = Arc<DeviceInfo>;
+
+#[tauri::command]
+pub async fn get_app_health(
+    app: AppHandle,
+    ws_manager: State<'_, WebSocketManagerState>,
+    scheduler: State<'_, SyncSchedulerState>,
+    dispatcher: State<'_, EventDispatcherState>,
+) -> Result<AppHealthReport, AppError> {
+    let database = try_get_database();
+    let connection = database.map(|db| db.get_connection());
+
+    let probes: Vec<Box<dyn HealthProbe>> = vec![
+        Box::new(DatabaseProbe { manager: database }),
+        Box::new(WebSocketProbe { manager: ws_manager.inner().clone() }),
+        Box::new(DiskProbe { data_dir: app.path().app_data_dir().ok() }),
+        Box::new(CacheProbe { connection: connection.clone(), limit_bytes: FILE_CACHE_SIZE_LIMIT }),
+        Box::new(SyncProbe { scheduler: scheduler.inner().clone() }),
+        Box::new(PendingMessagesProbe { connection }),
+        Box::new(EventQueueProbe { dispatcher: dispatcher.inner().clone() }),
+    ];
+
+    let version = app.package_info().version.to_string();
+    Ok(collect_health(&version, probes, HEALTH_PROBE_TIMEOUT).await)
+}
+
+/// 关于页面展示的设备信息
+#[tauri::command]
+pub async fn get_device_info(device_info: State<'_, DeviceInfoState>) -> Result<DeviceInfo, AppError> {
+    Ok(device_info.inner().as_ref().clone())
+}
+
+/// 导出加密的诊断包供技术支持排查问题；前端需先向用户说明导出内容并取得确认，未确认时返回 CONFIRMATION_REQUIRED
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_diagnostics_bundle(
+    path: String,
+    include_tables: Option<Vec<DiagnosticTable>>,
+    confirmed: bool,
+    device_info: State<'_, DeviceInfoState>,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<DiagnosticsBundleResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    if !confirmed {
+        return Err(AppError::confirmation_required("导出诊断包前需要用户确认"));
+    }
+
+    let tables = include_tables.unwrap_or_else(|| DiagnosticTable::DEFAULT.to_vec());
+    tracing::info!("Exporting diagnostics bundle to: {}", path);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let result = DiagnosticsBundleService::new().export_bundle(std::path::Path::new(&path), &tables, &device_info);
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "export_diagnostics_bundle".to_string());
+    metadata.insert(
+        "tables".to_string(),
+        tables.iter().map(|table| table.as_str()).collect::<Vec<_>>().join(","),
+    );
+    let (status, error_message) = match &result {
+        Ok(exported) => {
+            metadata.insert("path".to_string(), exported.path.clone());
+            ("success".to_string(), None)
+        }
+        Err(e) => ("failure".to_string(), Some(e.to_string())),
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::AccessSensitiveData,
+            Some("diagnostics_bundle".to_string()),
+            None,
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for diagnostics bundle export: {}", e);
+    }
+
+    result.map_err(|e| {
+        tracing::error!("Diagnostics bundle export failed: {}", e);
+        AppError::from(e)
+    })
+}
@@ -0,0 +1,23 @@
+// 日志查看与级别调整命令
+
+use crate::models::{LogEntry, LogLevel};
+use crate::utils::logging;
+
+const DEFAULT_LOG_LIMIT: usize = 200;
+const MAX_LOG_LIMIT: usize = 2000;
+
+#[tauri::command]
+pub async fn get_recent_logs(
+    level: Option<LogLevel>,
+    limit: Option<usize>,
+) -> Result<Vec<LogEntry>, String> {
+    let limit = limit.unwrap_or(DEFAULT_LOG_LIMIT).clamp(1, MAX_LOG_LIMIT);
+    logging::recent_log_entries(level.as_ref(), limit)
+}
+
+#[tauri::command]
+pub async fn set_log_level(level: LogLevel) -> Result<(), String> {
+    logging::set_max_log_level(&level)?;
+    tracing::info!("Log level set to {:?}", level);
+    Ok(())
+}
@@ -0,0 +1,135 @@
+// 病历相关命令
+
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::security::SecurityServiceState;
+use crate::commands::trash::audit_trash_change;
+use crate::models::{
+    AppError, CreateRecordTemplateRequest, MedicalRecord, RecordTemplate, RenderedTemplate, TrashEntityType,
+};
+use crate::services::{AuditAction, MedicalRecordService, RecordTemplateService};
+use std::collections::HashMap;
+use tauri::State;
+
+#[tauri::command]
+pub async fn create_medical_record(
+    record: MedicalRecord,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MedicalRecord, String> {
+    require_database(&readiness).await.map_err(|e| e.to_string())?;
+    tracing::info!("Creating medical record for patient: {}", record.patient_id);
+
+    let record_service = MedicalRecordService::new();
+
+    match record_service.create_medical_record(record).await {
+        Ok(record) => Ok(record),
+        Err(e) => {
+            tracing::error!("Failed to create medical record: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn update_medical_record(
+    record: MedicalRecord,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MedicalRecord, String> {
+    require_database(&readiness).await.map_err(|e| e.to_string())?;
+    tracing::info!("Updating medical record: {}", record.id);
+
+    let record_service = MedicalRecordService::new();
+
+    match record_service.update_medical_record(record).await {
+        Ok(record) => Ok(record),
+        Err(e) => {
+            tracing::error!("Failed to update medical record: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+// 删除病历：移入回收站，可通过 restore_medical_record 恢复
+#[tauri::command]
+pub async fn delete_medical_record(
+    record_id: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<(), String> {
+    require_database(&readiness).await.map_err(|e| e.to_string())?;
+    tracing::info!("Deleting medical record: {}", record_id);
+
+    let record_service = MedicalRecordService::new();
+    let result = record_service.delete_medical_record(&record_id).await;
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    audit_trash_change(
+        &security_service,
+        user_id,
+        AuditAction::DeleteData,
+        TrashEntityType::MedicalRecord,
+        &record_id,
+        &result,
+    )
+    .await;
+    result.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_record_template(
+    request: CreateRecordTemplateRequest,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<RecordTemplate, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let template_service = RecordTemplateService::new();
+
+    template_service.create_template(&doctor_id, request).await
+}
+
+#[tauri::command]
+pub async fn list_record_templates(
+    record_type: Option<String>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<RecordTemplate>, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let template_service = RecordTemplateService::new();
+
+    template_service
+        .list_templates(&doctor_id, record_type.as_deref())
+        .await
+}
+
+#[tauri::command]
+pub async fn render_record_template(
+    template_id: String,
+    variables: HashMap<String, String>,
+    consultation_id: Option<String>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<RenderedTemplate, AppError> {
+    require_database(&readiness).await?;
+    let template_service = RecordTemplateService::new();
+
+    template_service
+        .render_template(&template_id, variables, consultation_id.as_deref())
+        .await
+}
+
+#[tauri::command]
+pub async fn delete_record_template(
+    template_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<(), AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Deleting record template: {}", template_id);
+
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let template_service = RecordTemplateService::new();
+
+    template_service.delete_template(&doctor_id, &template_id).await
+}
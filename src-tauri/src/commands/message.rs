@@ -1,18 +1,52 @@
 // 消息相关命令
 
 use serde::{Deserialize, Serialize};
-use crate::database::dao::{MessageDao, BaseDao};
-use crate::models::{Message as MessageModel, MessageType, SenderType, SyncStatus, ReadStatus};
+use tauri::{AppHandle, Emitter, Manager, State, WebviewWindow};
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::consultation::ensure_consultation_in_scope;
+use crate::commands::database::{require_database, DatabaseReadinessState, OfflineStateServiceState};
+use crate::commands::permission::{current_data_scope, require_permission, PermissionServiceState};
+use crate::commands::rate_limit::{require_rate_limit, CommandRateLimiterState};
+use crate::commands::security::SecurityServiceState;
+use crate::commands::trash::audit_trash_change;
+use crate::commands::websocket::WebSocketManagerState;
+use crate::commands::window::WindowManagerState;
+use crate::database::dao::{ConsultationDao, FileCacheDao, MessageDao, MessageDraftDao, MessageSearchHit, OutboxDao, BaseDao};
+use crate::models::{
+    DataScope, FileCache, FilePreview, Message as MessageModel, MessageDraft, MessageTemplate, MessageType, ReadStatus, SenderType, SensitiveWord,
+    Permission, SensitiveWordCategory, SyncStatus, SystemEvent, TrashEntityType, UploadCompressionConfig,
+};
+use crate::services::{
+    check_inline_upload_size, effective_config, image_mime_type, previewable_mime_type, should_compress_upload, AppSettingsService,
+    AttachmentQuotaService, AudioMetadata, AuditAction, ChunkedUploadManager, FileService, MessageLatencyMetrics,
+    MessagePinChange, MessagePinService, MessageTemplateService, MessageWarmupService, MetricsService, OutboxDispatcher,
+    SensitiveWordService, UploadTransfer, MESSAGE_PIN_CHANGED_EVENT, SENSITIVE_WORD_BLOCKED,
+};
+use crate::utils::{sanitize_message_content, AppError, ErrorType, ValidationService, MAX_MESSAGE_CHARS};
 use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 pub struct SendMessageRequest {
     pub consultation_id: String,
-    pub message_type: String, // "text" | "image" | "voice" | "file"
+    pub message_type: String, // "text" | "image" | "voice" | "file" | "template"
+    #[serde(default)]
     pub content: String,
     pub sender: String, // "doctor" | "patient"
     pub file_path: Option<String>,
+    #[serde(default)]
+    pub template_id: Option<String>,
+    #[serde(default)]
+    pub template_variables: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessageTemplateRequest {
+    pub category: Option<String>,
+    pub title: String,
+    pub content: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -25,6 +59,33 @@ pub struct Message {
     pub timestamp: String,
     pub status: String, // "sending" | "sent" | "delivered" | "failed"
     pub file_path: Option<String>,
+    // 图片消息的缩略图路径
+    pub thumbnail: Option<String>,
+    // 文件消息的文档预览
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<FilePreview>,
+    pub template_id: Option<String>,
+    // 语音消息时长（毫秒）和波形峰值
+    pub duration_ms: Option<u64>,
+    pub waveform: Option<Vec<f32>>,
+    pub pinned: bool,
+    // 命中的提示类敏感词，前端据此标记消息
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flagged_words: Vec<String>,
+    // 系统事件消息的事件类型和附加信息，content 为提示文字
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<SystemEvent>,
+    // 图片在慢速网络下压缩后发送，前端显示"已压缩发送"
+    pub compressed: bool,
+}
+
+// 置顶栏中的消息，按置顶时间先后排列
+#[derive(Debug, Serialize)]
+pub struct PinnedMessageItem {
+    #[serde(flatten)]
+    pub message: Message,
+    pub pinned_at: String,
+    pub pinned_by: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -39,11 +100,38 @@ pub struct MessageList {
 pub struct FileUploadResult {
     pub url: String,
     pub path: String,
+    // 图片文件的缩略图路径
+    pub thumbnail: Option<String>,
+    // 语音文件的时长和波形，非音频或无法解析时为空
+    pub audio: Option<AudioMetadata>,
+    // PDF、Word 文档的预览图和开头文字
+    pub preview: Option<FilePreview>,
+    // 选择的文件大小和实际发送的大小，图片被压缩时两者不同
+    pub original_size: u64,
+    pub sent_size: u64,
+    pub compressed: bool,
 }
 
+pub type OutboxDispatcherState = Arc<OutboxDispatcher>;
+pub type MetricsServiceState = Arc<MetricsService>;
+
+// 上传文件的本地保存目录
+const UPLOAD_DIR_NAME: &str = "uploads";
+const PARTIAL_UPLOAD_DIR_NAME: &str = "partial";
+
 #[tauri::command]
-pub async fn send_message(request: SendMessageRequest) -> Result<Message, String> {
-    println!("Sending message: {:?}", request);
+pub async fn send_message(
+    request: SendMessageRequest,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    offline_state: State<'_, OfflineStateServiceState>,
+    outbox: State<'_, OutboxDispatcherState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Message, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Sending {} message in consultation {}", request.message_type, request.consultation_id);
+    ensure_consultation_id_in_scope(&permissions, &ConsultationDao::new(), &request.consultation_id).await?;
 
     let message_dao = MessageDao::new();
     let message_id = Uuid::new_v4().to_string();
@@ -53,7 +141,7 @@ pub async fn send_message(request: SendMessageRequest) -> Result<Message, String
     let sender_type = match request.sender.as_str() {
         "doctor" => SenderType::Doctor,
         "patient" => SenderType::Patient,
-        _ => return Err("Invalid sender type".to_string()),
+        _ => return Err(AppError::invalid_argument(format!("无效的发送方类型: {}", request.sender))),
     };
 
     let message_type = match request.message_type.as_str() {
@@ -62,7 +150,98 @@ pub async fn send_message(request: SendMessageRequest) -> Result<Message, String
         "voice" => MessageType::Voice,
         "file" => MessageType::File,
         "template" => MessageType::Template,
-        _ => return Err("Invalid message type".to_string()),
+        _ => return Err(AppError::invalid_argument(format!("无效的消息类型: {}", request.message_type))),
+    };
+
+    // 模板消息在本地展开后保存最终文本
+    if let MessageType::Template = message_type {
+        let template_id = request
+            .template_id
+            .clone()
+            .ok_or_else(|| AppError::invalid_argument("模板消息缺少模板ID"))?;
+        let service = MessageTemplateService::new();
+        let saved = service
+            .send_template_message(
+                &request.consultation_id,
+                sender_type,
+                &template_id,
+                request.template_variables.clone().unwrap_or_default(),
+            )
+            .await
+            .map_err(AppError::from);
+        let saved = match saved {
+            Ok(saved) => saved,
+            Err(error) => {
+                if error.code.as_deref() == Some(SENSITIVE_WORD_BLOCKED) {
+                    audit_blocked_message(&security_service, &token_refresh, &request.consultation_id, &error).await;
+                }
+                return Err(error.context("发送模板消息失败"));
+            }
+        };
+        if !offline_state.is_online() {
+            return Err(queued_error(&message_dao, &saved.id));
+        }
+        outbox.wake();
+
+        return Ok(Message {
+            id: saved.id,
+            consultation_id: saved.consultation_id,
+            message_type: request.message_type,
+            content: saved.content.unwrap_or_default(),
+            sender: request.sender,
+            timestamp: saved.timestamp.to_rfc3339(),
+            status: "sending".to_string(),
+            file_path: None,
+            thumbnail: None,
+            preview: None,
+            template_id: saved.template_id,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+            flagged_words: Vec::new(),
+            event: None,
+            compressed: false,
+        });
+    }
+
+    let mut flagged_words = Vec::new();
+    let mut content = request.content.clone();
+    if let MessageType::Text = message_type {
+        ValidationService::validate_message_content(&request.content, MAX_MESSAGE_CHARS)
+            .map_err(|e| AppError::invalid_argument(e.to_string()))?;
+        // 清洗后只剩标签或零宽字符的消息同样视为空消息
+        content = sanitize_message_content(&request.content);
+        ValidationService::validate_message_content(&content, MAX_MESSAGE_CHARS)
+            .map_err(|e| AppError::invalid_argument(e.to_string()))?;
+
+        // 禁用词拒绝发送并记录审计日志，提示类敏感词仅标记；按清洗后的文本匹配，零宽字符无法绕过词表
+        let scan = SensitiveWordService::new().scan(&content)?;
+        if scan.is_blocked() {
+            let error = scan.blocked_error();
+            audit_blocked_message(&security_service, &token_refresh, &request.consultation_id, &error).await;
+            return Err(error);
+        }
+        flagged_words = scan.warnings;
+    }
+
+    // 语音消息解析时长和波形，不支持的编码保持为空
+    let audio = match (&message_type, &request.file_path) {
+        (MessageType::Voice, Some(path)) => FileService::new().analyze_audio(std::path::Path::new(path)),
+        _ => None,
+    };
+    let duration_ms = audio.as_ref().map(|a| a.duration_ms);
+    let waveform = audio.map(|a| a.waveform);
+    let thumbnail = match (&message_type, &request.file_path) {
+        (MessageType::Image, Some(path)) => image_thumbnail(path),
+        _ => None,
+    };
+    let preview = match (&message_type, &request.file_path) {
+        (MessageType::File, Some(path)) => file_preview(&FileCacheDao::new(), path),
+        _ => None,
+    };
+    let compressed = match (&message_type, &request.file_path) {
+        (MessageType::Image, Some(path)) => image_compressed(&FileCacheDao::new(), path),
+        _ => false,
     };
 
     // 创建消息模型
@@ -71,98 +250,163 @@ pub async fn send_message(request: SendMessageRequest) -> Result<Message, String
         consultation_id: request.consultation_id.clone(),
         sender_type,
         message_type,
-        content: Some(request.content.clone()),
+        content: Some(content.clone()),
         file_path: request.file_path.clone(),
         file_size: None,
         mime_type: None,
         timestamp,
         sync_status: SyncStatus::Pending,
         read_status: ReadStatus::Unread,
+        template_id: None,
+        duration_ms,
+        waveform: waveform.clone(),
+        pinned: false,
     };
 
-    // 保存到本地数据库
-    let create_result = message_dao.create(&message_model).map_err(|e| e.to_string());
+    // 保存到本地数据库，医生发送时同时清除该问诊下的草稿
+    let draft_owner = match message_model.sender_type {
+        SenderType::Doctor => token_refresh.lock().await.current_user_id().await,
+        _ => None,
+    };
+    let raw_content = (content != request.content).then_some(request.content.as_str());
+    let create_result = message_dao.create_outgoing_with_raw(&message_model, raw_content, draft_owner.as_deref());
 
     match create_result {
         Ok(_) => {
-            println!("Message saved to local database: {}", message_id);
-
-            // TODO: 实际发送到服务器的逻辑
-            // 这里可以添加网络请求代码
-
-            // 模拟发送延迟
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            tracing::info!("Message saved to local database: {}", message_id);
 
-            // 更新同步状态为已发送
-            if let Err(e) = message_dao.update_sync_status(&message_id, "synced") {
-                println!("Failed to update sync status: {}", e);
+            // 离线时留在发件箱，恢复连接后由发件箱发送
+            if !offline_state.is_online() {
+                return Err(queued_error(&message_dao, &message_id));
             }
 
+            // 服务器确认前保持 sending，确认后通过 message-acknowledged 事件通知前端
+            outbox.wake();
+
             let response_message = Message {
                 id: message_id,
                 consultation_id: request.consultation_id,
                 message_type: request.message_type,
-                content: request.content,
+                content,
                 sender: request.sender,
                 timestamp: timestamp.to_rfc3339(),
-                status: "sent".to_string(),
+                status: "sending".to_string(),
                 file_path: request.file_path,
+                thumbnail,
+                preview,
+                template_id: None,
+                duration_ms,
+                waveform,
+                pinned: false,
+                flagged_words,
+                event: None,
+                compressed,
             };
 
             Ok(response_message)
         }
         Err(e) => {
-            println!("Failed to save message to database: {}", e);
-            Err(format!("保存消息失败: {}", e))
+            tracing::warn!("Failed to save message to database: {}", e);
+            Err(AppError::from(e).context("保存消息失败"))
         }
     }
 }
 
+// 消息已保存但未发送，错误中附带其在待发送队列中的位置
+fn queued_error(message_dao: &MessageDao, message_id: &str) -> AppError {
+    let position = match message_dao.queue_position(message_id) {
+        Ok(position) => position,
+        Err(e) => {
+            tracing::warn!("Failed to read queue position for {}: {}", message_id, e);
+            None
+        }
+    };
+    let position = position.or_else(|| message_dao.count_unsynced_messages().ok()).unwrap_or(0);
+    tracing::info!("Message {} queued for sending at position {}", message_id, position);
+    AppError::message_queued(message_id, position)
+}
+
 #[tauri::command]
 pub async fn get_message_history(
     consultation_id: String,
     page: Option<u32>,
     limit: Option<u32>,
-) -> Result<MessageList, String> {
-    println!("Getting message history for consultation: {}, page: {:?}", consultation_id, page);
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MessageList, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "get_message_history")?;
+    require_database(&readiness).await?;
+    tracing::debug!("Getting message history for consultation: {}, page: {:?}", consultation_id, page);
 
-    let message_dao = MessageDao::new();
-    let page = page.unwrap_or(1) as i32;
-    let limit = limit.unwrap_or(20) as i32;
+    let scope = current_data_scope(&permissions).await?;
+    load_message_history(
+        &MessageDao::new(),
+        &FileCacheDao::new(),
+        &consultation_id,
+        &scope,
+        page.unwrap_or(1) as i32,
+        limit.unwrap_or(20) as i32,
+    )
+}
 
-    match message_dao.find_by_consultation_id(&consultation_id, page, limit) {
+// 启动完成后在后台预热最近活跃问诊的消息，未登录或已关闭预热时跳过
+pub(crate) async fn warm_recent_messages(app: &AppHandle) {
+    let config = match AppSettingsService::new().load() {
+        Ok(config) => config.message_warmup,
+        Err(e) => {
+            tracing::warn!("Skipping message warmup, failed to load app config: {}", e);
+            return;
+        }
+    };
+    if !config.enabled {
+        return;
+    }
+
+    let Some(doctor_id) = app.state::<TokenRefreshServiceState>().lock().await.current_user_id().await else {
+        tracing::debug!("Skipping message warmup, no user logged in");
+        return;
+    };
+    let Ok(scope) = current_data_scope(app.state::<PermissionServiceState>().inner()).await else {
+        return;
+    };
+
+    let started = std::time::Instant::now();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        MessageWarmupService::new()
+            .warm(&doctor_id, &scope, &config)
+            .map_err(|e| e.to_string())
+    })
+    .await;
+    match result {
+        Ok(Ok(warmed)) => tracing::info!("Warmed message history of {} consultations in {:?}", warmed, started.elapsed()),
+        Ok(Err(e)) => tracing::warn!("Message warmup failed: {}", e),
+        Err(e) => tracing::warn!("Message warmup task failed: {}", e),
+    }
+}
+
+// 按数据范围读取问诊消息，其他医生的问诊返回空列表；第一页优先使用缓存
+fn load_message_history(
+    message_dao: &MessageDao,
+    file_cache_dao: &FileCacheDao,
+    consultation_id: &str,
+    scope: &DataScope,
+    page: i32,
+    limit: i32,
+) -> Result<MessageList, AppError> {
+    let page_result = if page == 1 {
+        message_dao.find_first_page_cached(consultation_id, scope, limit)
+    } else {
+        message_dao.find_by_consultation_id_in_scope(consultation_id, scope, page, limit)
+    };
+    match page_result {
         Ok(page_result) => {
-            let messages: Vec<Message> = page_result.items.into_iter().map(|msg| {
-                let sender = match msg.sender_type {
-                    SenderType::Doctor => "doctor",
-                    SenderType::Patient => "patient",
-                }.to_string();
-
-                let msg_type = match msg.message_type {
-                    MessageType::Text => "text",
-                    MessageType::Image => "image",
-                    MessageType::Voice => "voice",
-                    MessageType::File => "file",
-                    MessageType::Template => "template",
-                }.to_string();
-
-                let status = match msg.sync_status {
-                    SyncStatus::Synced => "delivered",
-                    SyncStatus::Pending => "pending",
-                    SyncStatus::Failed => "failed",
-                }.to_string();
-
-                Message {
-                    id: msg.id,
-                    consultation_id: msg.consultation_id,
-                    message_type: msg_type,
-                    content: msg.content.unwrap_or_default(),
-                    sender,
-                    timestamp: msg.timestamp.to_rfc3339(),
-                    status,
-                    file_path: msg.file_path,
-                }
-            }).collect();
+            let messages: Vec<Message> = page_result
+                .items
+                .into_iter()
+                .map(|msg| history_message(file_cache_dao, msg))
+                .collect();
 
             let has_more = (page_result.page as u32) < (page_result.total_pages as u32);
 
@@ -176,73 +420,597 @@ pub async fn get_message_history(
             Ok(result)
         }
         Err(e) => {
-            println!("Failed to get message history: {}", e);
-            Err(format!("获取消息历史失败: {}", e))
+            tracing::warn!("Failed to get message history: {}", e);
+            Err(AppError::database_error(format!("获取消息历史失败: {}", e)))
+        }
+    }
+}
+
+// 数据库中的消息转换为前端显示的格式，系统事件消息的内容替换为提示文字
+fn history_message(file_cache_dao: &FileCacheDao, msg: MessageModel) -> Message {
+    let sender = match msg.sender_type {
+        SenderType::Doctor => "doctor",
+        SenderType::Patient => "patient",
+        SenderType::System => "system",
+    }.to_string();
+
+    let msg_type = match msg.message_type {
+        MessageType::Text => "text",
+        MessageType::Image => "image",
+        MessageType::Voice => "voice",
+        MessageType::File => "file",
+        MessageType::Template => "template",
+        MessageType::Event => "event",
+    }.to_string();
+
+    let status = match msg.sync_status {
+        SyncStatus::Synced => "delivered",
+        SyncStatus::Pending => "pending",
+        SyncStatus::Failed => "failed",
+    }.to_string();
+
+    let thumbnail = match (&msg.message_type, &msg.file_path) {
+        (MessageType::Image, Some(path)) => image_thumbnail(path),
+        _ => None,
+    };
+    let preview = match (&msg.message_type, &msg.file_path) {
+        (MessageType::File, Some(path)) => file_preview(file_cache_dao, path),
+        _ => None,
+    };
+    let compressed = match (&msg.message_type, &msg.file_path) {
+        (MessageType::Image, Some(path)) => image_compressed(file_cache_dao, path),
+        _ => false,
+    };
+
+    let event = match msg.message_type {
+        MessageType::Event => msg.content.as_deref().and_then(SystemEvent::parse),
+        _ => None,
+    };
+    let content = match &event {
+        Some(event) => event.text.clone(),
+        None => msg.content.unwrap_or_default(),
+    };
+
+    Message {
+        id: msg.id,
+        consultation_id: msg.consultation_id,
+        message_type: msg_type,
+        content,
+        sender,
+        timestamp: msg.timestamp.to_rfc3339(),
+        status,
+        file_path: msg.file_path,
+        thumbnail,
+        preview,
+        template_id: msg.template_id,
+        duration_ms: msg.duration_ms,
+        waveform: msg.waveform,
+        pinned: msg.pinned,
+        flagged_words: Vec::new(),
+        event,
+        compressed,
+    }
+}
+
+// 会话内搜索的默认返回条数
+const CONSULTATION_SEARCH_LIMIT: u32 = 50;
+
+// 会话内搜索框：返回命中消息的高亮位置（按字符计）和在会话中的序号，最新的在前
+#[tauri::command]
+pub async fn search_in_consultation(
+    consultation_id: String,
+    keyword: String,
+    limit: Option<u32>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<MessageSearchHit>, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Searching messages in consultation: {}", consultation_id);
+
+    if let Some(consultation) = ConsultationDao::new().find_by_id(&consultation_id).map_err(AppError::from)? {
+        ensure_consultation_in_scope(&permissions, &consultation).await?;
+    }
+
+    MessageDao::new()
+        .search_in_consultation(&consultation_id, &keyword, limit.unwrap_or(CONSULTATION_SEARCH_LIMIT) as i32)
+        .map_err(|e| AppError::database_error(format!("搜索消息失败: {}", e)))
+}
+
+// 删除消息：移入回收站，可通过 restore_message 恢复
+#[tauri::command]
+pub async fn delete_message(
+    message_id: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<(), AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Deleting message: {}", message_id);
+
+    let message_dao = MessageDao::new();
+    let message = message_dao
+        .find_by_id(&message_id)
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::new(ErrorType::DataError, "消息不存在").with_code("MESSAGE_NOT_FOUND"))?;
+    if let Some(consultation) = ConsultationDao::new().find_by_id(&message.consultation_id).map_err(AppError::from)? {
+        ensure_consultation_in_scope(&permissions, &consultation).await?;
+    }
+
+    let result = message_dao.delete(&message_id).map_err(AppError::from);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    audit_trash_change(&security_service, user_id, AuditAction::DeleteData, TrashEntityType::Message, &message_id, &result)
+        .await;
+    result
+}
+
+/// 置顶消息，每个问诊最多置顶 5 条；已撤回和系统消息不能置顶，返回 MESSAGE_NOT_PINNABLE，超出上限返回 PIN_LIMIT_REACHED
+#[tauri::command]
+pub async fn pin_message(
+    app: AppHandle,
+    message_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MessagePinChange, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Pinning message: {}", message_id);
+
+    let service = MessagePinService::new();
+    ensure_message_in_scope(&permissions, &service, &message_id).await?;
+    let pinned_by = current_doctor_id(&token_refresh).await?;
+
+    let change = service.pin(&message_id, &pinned_by)?;
+    notify_pin_change(&app, &change).await;
+    Ok(change)
+}
+
+#[tauri::command]
+pub async fn unpin_message(
+    app: AppHandle,
+    message_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MessagePinChange, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Unpinning message: {}", message_id);
+
+    let service = MessagePinService::new();
+    ensure_message_in_scope(&permissions, &service, &message_id).await?;
+
+    let change = service.unpin(&message_id)?;
+    notify_pin_change(&app, &change).await;
+    Ok(change)
+}
+
+// 问诊的置顶消息，按置顶时间先后排列
+#[tauri::command]
+pub async fn get_pinned_messages(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<PinnedMessageItem>, AppError> {
+    require_database(&readiness).await?;
+
+    if let Some(consultation) = ConsultationDao::new().find_by_id(&consultation_id).map_err(AppError::from)? {
+        ensure_consultation_in_scope(&permissions, &consultation).await?;
+    }
+
+    let file_cache_dao = FileCacheDao::new();
+    let pinned = MessagePinService::new().pinned_messages(&consultation_id)?;
+    Ok(pinned
+        .into_iter()
+        .map(|pinned| PinnedMessageItem {
+            message: history_message(&file_cache_dao, pinned.message),
+            pinned_at: pinned.pinned_at.to_rfc3339(),
+            pinned_by: pinned.pinned_by,
+        })
+        .collect())
+}
+
+async fn ensure_message_in_scope(
+    permissions: &PermissionServiceState,
+    service: &MessagePinService,
+    message_id: &str,
+) -> Result<(), AppError> {
+    let consultation_id = service.consultation_of(message_id)?;
+    if let Some(consultation) = ConsultationDao::new().find_by_id(&consultation_id).map_err(AppError::from)? {
+        ensure_consultation_in_scope(permissions, &consultation).await?;
+    }
+    Ok(())
+}
+
+// 通知已打开的问诊窗口刷新置顶栏，并推送给患者端；推送失败不影响置顶结果，患者端下次同步时获取
+async fn notify_pin_change(app: &AppHandle, change: &MessagePinChange) {
+    if !change.changed {
+        return;
+    }
+
+    if let Some(window_id) = app.state::<WindowManagerState>().consultation_window_id(&change.consultation_id) {
+        if let Err(e) = app.emit_to(window_id.as_str(), MESSAGE_PIN_CHANGED_EVENT, change) {
+            tracing::warn!("Failed to emit {} event: {}", MESSAGE_PIN_CHANGED_EVENT, e);
         }
     }
+
+    let ws_manager = app.state::<WebSocketManagerState>();
+    if ws_manager.lock().await.broadcast_event(&change.to_event()).await == 0 {
+        tracing::debug!("No WebSocket connection, pin change of message {} not pushed", change.message_id);
+    }
+}
+
+// 管理员取证查看消息清洗前的原文，正文未被改写时为空；每次查看都记录审计日志
+#[tauri::command]
+pub async fn get_message_raw_content(
+    message_id: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<String>, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
+    tracing::info!("Reading raw content of message: {}", message_id);
+
+    let result = MessageDao::new()
+        .find_raw_content(&message_id)
+        .map_err(AppError::from)
+        .and_then(|raw| {
+            raw.ok_or_else(|| AppError::new(ErrorType::DataError, "消息不存在").with_code("MESSAGE_NOT_FOUND"))
+        });
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "get_message_raw_content".to_string());
+    let (status, error_message) = match &result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.message.clone())),
+    };
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::AccessSensitiveData,
+            Some("message".to_string()),
+            Some(message_id),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for raw message access: {}", e);
+    }
+
+    result
+}
+
+// 不超过 5MB 的文件可直接以字节数组传入，更大的文件使用 begin_file_upload 分片上传
+#[tauri::command]
+pub async fn upload_file(
+    app: AppHandle,
+    file_data: Vec<u8>,
+    file_name: String,
+    consultation_id: Option<String>,
+    // 发送原图：跳过慢速网络下的自动压缩
+    send_original: Option<bool>,
+    file_service: State<'_, FileService>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<FileUploadResult, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Uploading file: {}, size: {} bytes", file_name, file_data.len());
+
+    check_inline_upload_size(file_data.len() as u64, max_upload_size())?;
+    store_upload(&app, &file_service, file_data, file_name, consultation_id, send_original.unwrap_or(false)).await
+}
+
+// 开始分片上传，声明的大小超过 AppConfig.max_file_size 时返回 FILE_TOO_LARGE
+#[tauri::command]
+pub async fn begin_file_upload(
+    app: AppHandle,
+    file_name: String,
+    total_size: u64,
+    uploads: State<'_, ChunkedUploadManager>,
+) -> Result<UploadTransfer, AppError> {
+    uploads.begin(&partial_upload_dir(&app)?, &file_name, total_size, max_upload_size())
 }
 
+// 按顺序追加分片，index 从 0 开始；返回已收到的字节数
 #[tauri::command]
-pub async fn upload_file(file_data: Vec<u8>, file_name: String) -> Result<FileUploadResult, String> {
-    println!("Uploading file: {}, size: {} bytes", file_name, file_data.len());
+pub async fn append_file_upload_chunk(
+    transfer_id: String,
+    index: u32,
+    data: Vec<u8>,
+    uploads: State<'_, ChunkedUploadManager>,
+) -> Result<u64, AppError> {
+    uploads.append(&transfer_id, index, &data)
+}
 
-    // TODO: 实现实际的文件上传逻辑
-    // 1. 验证文件类型和大小
-    // 2. 保存到本地临时目录
-    // 3. 上传到服务器或云存储
+// 结束分片上传，之后的处理与 upload_file 相同
+#[tauri::command]
+pub async fn finish_file_upload(
+    app: AppHandle,
+    transfer_id: String,
+    consultation_id: Option<String>,
+    send_original: Option<bool>,
+    uploads: State<'_, ChunkedUploadManager>,
+    file_service: State<'_, FileService>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<FileUploadResult, AppError> {
+    require_database(&readiness).await?;
+    let upload = uploads.take(&transfer_id)?;
+    tracing::info!("Uploading file: {}, size: {} bytes (chunked)", upload.file_name, upload.data.len());
 
-    // 模拟上传延迟
-    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+    store_upload(&app, &file_service, upload.data, upload.file_name, consultation_id, send_original.unwrap_or(false)).await
+}
 
-    // 模拟文件保存路径
-    let timestamp = chrono::Utc::now().timestamp();
-    let local_path = format!("/temp/uploads/{}-{}", timestamp, file_name);
-    let url = format!("https://cdn.telemedicine.com/files/{}-{}", timestamp, file_name);
+#[tauri::command]
+pub async fn cancel_file_upload(transfer_id: String, uploads: State<'_, ChunkedUploadManager>) -> Result<bool, AppError> {
+    Ok(uploads.cancel(&transfer_id))
+}
+
+// 分片的临时文件目录
+pub(crate) fn partial_upload_dir(app: &AppHandle) -> Result<std::path::PathBuf, AppError> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AppError::file_error(format!("无法获取应用数据目录: {}", e)))?
+        .join(UPLOAD_DIR_NAME)
+        .join(PARTIAL_UPLOAD_DIR_NAME))
+}
+
+// 读取失败时按默认配置限制
+pub(crate) fn max_upload_size() -> u64 {
+    effective_config().max_file_size
+}
+
+async fn store_upload(
+    app: &AppHandle,
+    file_service: &FileService,
+    file_data: Vec<u8>,
+    file_name: String,
+    consultation_id: Option<String>,
+    send_original: bool,
+) -> Result<FileUploadResult, AppError> {
+    let check = file_service.check_upload_content(&file_name, &file_data);
+    if !check.is_valid {
+        return Err(AppError::validation_failed(check));
+    }
+
+    // 问诊附件超出问诊或患者的配额时不保存文件
+    if let Some(consultation_id) = &consultation_id {
+        AttachmentQuotaService::new().check(consultation_id, file_data.len() as u64)?;
+    }
+
+    let upload_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AppError::file_error(format!("无法获取应用数据目录: {}", e)))?
+        .join(UPLOAD_DIR_NAME);
+    let original_size = file_data.len() as u64;
+    let mut compressed = false;
+
+    // 图片去除 EXIF 后保存并生成缩略图，损坏的图片直接拒绝
+    // 最近上传速度较慢时先压缩再保存，压缩失败时发送原图
+    let mime_type = image_mime_type(&file_name);
+    let (local_path, thumbnail_path, file_size) = if mime_type.is_some() {
+        let mut image = file_service
+            .prepare_image(&file_data)
+            .map_err(|e| AppError::invalid_argument(e.to_string()))?;
+        let compression = upload_compression_config();
+        if should_compress_upload(&compression, mime_type, file_service.average_upload_kbps(), send_original) {
+            match file_service.compress_image(&image, &compression) {
+                Ok(Some(smaller)) => {
+                    tracing::info!("Compressed {} for slow upload: {} -> {} bytes", file_name, image.data.len(), smaller.data.len());
+                    image = smaller;
+                    compressed = true;
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to compress {}, sending original: {}", file_name, e),
+            }
+        }
+        let (path, thumbnail) = file_service.save_image(&upload_dir, &image, &file_name).await?;
+        (path, Some(thumbnail), image.data.len())
+    } else {
+        let path = file_service.save_file(&upload_dir, &file_data, &file_name).await?;
+        (path, None, file_data.len())
+    };
+
+    // 文档附件提取预览，失败时不影响上传
+    let preview = match previewable_mime_type(&local_path) {
+        Some(mime) => Some(file_service.extract_preview(&local_path, mime).await).filter(|p| !p.is_empty()),
+        None => None,
+    };
+
+    let url = file_service.upload_file(&local_path).await?;
+    let local_path = local_path.to_string_lossy().to_string();
+    let thumbnail = thumbnail_path.map(|path| path.to_string_lossy().to_string());
+
+    let now = Utc::now();
+    let cache = FileCache {
+        id: String::new(),
+        file_url: url.clone(),
+        local_path: local_path.clone(),
+        file_size: Some(file_size as u64),
+        mime_type: mime_type.map(str::to_string),
+        checksum: None,
+        expires_at: None,
+        downloaded_at: now,
+        last_accessed: now,
+        pinned: false,
+        thumbnail_path: thumbnail.clone(),
+        bytes_downloaded: file_size as u64,
+        preview_path: preview.as_ref().and_then(|p| p.preview_path.clone()),
+        preview_text: preview.as_ref().and_then(|p| p.preview_text.clone()),
+        consultation_id,
+        original_size: compressed.then_some(original_size),
+    };
+    if let Err(e) = FileCacheDao::new().create(&cache) {
+        tracing::warn!("Failed to record uploaded file in cache: {}", e);
+    }
+
+    // 语音文件在上传时一并解析时长和波形
+    let audio = if is_audio_file(&file_name) {
+        file_service.analyze_audio_bytes(&file_data)
+    } else {
+        None
+    };
 
     let result = FileUploadResult {
         url,
         path: local_path,
+        thumbnail,
+        audio,
+        preview,
+        original_size,
+        sent_size: file_size as u64,
+        compressed,
     };
 
     Ok(result)
 }
 
+// 读取失败时按默认配置压缩
+fn upload_compression_config() -> UploadCompressionConfig {
+    effective_config().upload_compression
+}
+
 #[tauri::command]
-pub async fn mark_messages_as_read(consultation_id: String) -> Result<u32, String> {
-    println!("Marking messages as read for consultation: {}", consultation_id);
+pub async fn mark_messages_as_read(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<u32, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Marking messages as read for consultation: {}", consultation_id);
 
-    let message_dao = MessageDao::new();
+    mark_consultation_read(&permissions, &ConsultationDao::new(), &MessageDao::new(), &consultation_id).await
+}
+
+#[tauri::command]
+pub async fn get_unread_message_count(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<u32, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Getting unread message count for consultation: {}", consultation_id);
 
-    match message_dao.mark_consultation_messages_as_read(&consultation_id, "doctor") {
+    unread_count(&permissions, &ConsultationDao::new(), &MessageDao::new(), &consultation_id).await
+}
+
+async fn mark_consultation_read(
+    permissions: &PermissionServiceState,
+    consultation_dao: &ConsultationDao,
+    message_dao: &MessageDao,
+    consultation_id: &str,
+) -> Result<u32, AppError> {
+    ensure_consultation_id_in_scope(permissions, consultation_dao, consultation_id).await?;
+
+    match message_dao.mark_consultation_messages_as_read(consultation_id, "doctor") {
         Ok(updated_count) => {
-            println!("Marked {} messages as read", updated_count);
+            tracing::info!("Marked {} messages as read", updated_count);
             Ok(updated_count as u32)
         }
         Err(e) => {
-            println!("Failed to mark messages as read: {}", e);
-            Err(format!("标记消息已读失败: {}", e))
+            tracing::warn!("Failed to mark messages as read: {}", e);
+            Err(AppError::database_error(format!("标记消息已读失败: {}", e)))
         }
     }
 }
 
-#[tauri::command]
-pub async fn get_unread_message_count(consultation_id: String) -> Result<u32, String> {
-    println!("Getting unread message count for consultation: {}", consultation_id);
+async fn unread_count(
+    permissions: &PermissionServiceState,
+    consultation_dao: &ConsultationDao,
+    message_dao: &MessageDao,
+    consultation_id: &str,
+) -> Result<u32, AppError> {
+    ensure_consultation_id_in_scope(permissions, consultation_dao, consultation_id).await?;
 
-    let message_dao = MessageDao::new();
-
-    match message_dao.get_unread_count(&consultation_id, "doctor") {
+    match message_dao.get_unread_count(consultation_id, "doctor") {
         Ok(count) => Ok(count as u32),
         Err(e) => {
-            println!("Failed to get unread count: {}", e);
-            Err(format!("获取未读消息数量失败: {}", e))
+            tracing::warn!("Failed to get unread count: {}", e);
+            Err(AppError::database_error(format!("获取未读消息数量失败: {}", e)))
         }
     }
 }
 
+// 按问诊 ID 校验数据范围，本地尚未同步的问诊放行
+async fn ensure_consultation_id_in_scope(
+    permissions: &PermissionServiceState,
+    consultation_dao: &ConsultationDao,
+    consultation_id: &str,
+) -> Result<(), AppError> {
+    if let Some(consultation) = consultation_dao.find_by_id(consultation_id).map_err(AppError::from)? {
+        ensure_consultation_in_scope(permissions, &consultation).await?;
+    }
+    Ok(())
+}
+
+// 保存当前医生在该问诊下的草稿，内容为空时视为清除
 #[tauri::command]
-pub async fn sync_pending_messages() -> Result<u32, String> {
-    println!("Syncing pending messages");
+pub async fn save_message_draft(
+    consultation_id: String,
+    content: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<MessageDraft>, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let draft_dao = MessageDraftDao::new();
+
+    if content.trim().is_empty() {
+        draft_dao
+            .delete(&consultation_id, &doctor_id)
+            .map_err(|e| AppError::from(e).context("清除消息草稿失败"))?;
+        return Ok(None);
+    }
+
+    draft_dao
+        .upsert(&consultation_id, &doctor_id, &content)
+        .map(Some)
+        .map_err(|e| AppError::from(e).context("保存消息草稿失败"))
+}
+
+#[tauri::command]
+pub async fn get_message_draft(
+    consultation_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<MessageDraft>, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+
+    MessageDraftDao::new()
+        .find(&consultation_id, &doctor_id)
+        .map_err(|e| AppError::from(e).context("获取消息草稿失败"))
+}
+
+#[tauri::command]
+pub async fn clear_message_draft(
+    consultation_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<bool, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+
+    MessageDraftDao::new()
+        .delete(&consultation_id, &doctor_id)
+        .map_err(|e| AppError::from(e).context("清除消息草稿失败"))
+}
+
+#[tauri::command]
+pub async fn sync_pending_messages(
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<u32, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "sync_pending_messages")?;
+    require_database(&readiness).await?;
+    tracing::info!("Syncing pending messages");
 
     let message_dao = MessageDao::new();
 
@@ -260,18 +1028,489 @@ pub async fn sync_pending_messages() -> Result<u32, String> {
                 tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
                 // 更新同步状态
-                if let Ok(_) = message_dao.update_sync_status(&message.id, "synced") {
+                if message_dao.update_sync_status(&message.id, SyncStatus::Synced).is_ok() {
                     synced_count += 1;
-                    println!("Synced message: {}", message.id);
+                    tracing::debug!("Synced message: {}", message.id);
                 }
             }
 
-            println!("Synced {} messages", synced_count);
+            tracing::info!("Synced {} messages", synced_count);
             Ok(synced_count)
         }
         Err(e) => {
-            println!("Failed to sync messages: {}", e);
-            Err(format!("同步消息失败: {}", e))
+            tracing::warn!("Failed to sync messages: {}", e);
+            Err(AppError::database_error(format!("同步消息失败: {}", e)))
         }
     }
-}
\ No newline at end of file
+}
+
+// 放弃发件箱中尚未发送的消息，指定问诊时只清空该问诊；消息标记为发送失败，附件解除固定后可被缓存清理
+#[tauri::command]
+pub async fn clear_message_outbox(
+    consultation_id: Option<String>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<u32, AppError> {
+    require_database(&readiness).await?;
+
+    let cleared = OutboxDao::new()
+        .clear(consultation_id.as_deref())
+        .map_err(|e| AppError::from(e).context("清空发件箱失败"))?;
+    tracing::info!("Cleared {} outbox messages", cleared);
+
+    Ok(cleared as u32)
+}
+#[tauri::command]
+pub async fn create_message_template(
+    request: MessageTemplateRequest,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MessageTemplate, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let service = MessageTemplateService::new();
+
+    service
+        .create_template(&doctor_id, request.category.as_deref(), &request.title, &request.content)
+        .await
+        .map_err(|e| AppError::from(e).context("创建消息模板失败"))
+}
+
+#[tauri::command]
+pub async fn update_message_template(
+    template_id: String,
+    request: MessageTemplateRequest,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MessageTemplate, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let service = MessageTemplateService::new();
+
+    service
+        .update_template(&doctor_id, &template_id, request.category.as_deref(), &request.title, &request.content)
+        .await
+        .map_err(|e| AppError::from(e).context("更新消息模板失败"))
+}
+
+#[tauri::command]
+pub async fn delete_message_template(
+    template_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<(), AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let service = MessageTemplateService::new();
+
+    service
+        .delete_template(&doctor_id, &template_id)
+        .await
+        .map_err(|e| AppError::from(e).context("删除消息模板失败"))
+}
+
+#[tauri::command]
+pub async fn list_message_templates(
+    category: Option<String>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<MessageTemplate>, AppError> {
+    require_database(&readiness).await?;
+    let doctor_id = current_doctor_id(&token_refresh).await?;
+    let service = MessageTemplateService::new();
+
+    service
+        .list_templates(&doctor_id, category.as_deref())
+        .await
+        .map_err(|e| AppError::from(e).context("获取消息模板失败"))
+}
+
+#[tauri::command]
+pub async fn list_sensitive_words(
+    category: Option<SensitiveWordCategory>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<SensitiveWord>, AppError> {
+    require_database(&readiness).await?;
+    SensitiveWordService::new()
+        .list_words(category)
+        .map_err(|e| AppError::from(e).context("获取敏感词失败"))
+}
+
+#[tauri::command]
+pub async fn add_sensitive_word(
+    word: String,
+    category: SensitiveWordCategory,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<SensitiveWord, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Adding sensitive word ({})", category.as_str());
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+
+    SensitiveWordService::new()
+        .add_word(&word, category, user_id.as_deref())
+        .map_err(|e| AppError::from(e).context("添加敏感词失败"))
+}
+
+#[tauri::command]
+pub async fn remove_sensitive_word(
+    word_id: String,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<bool, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Removing sensitive word: {}", word_id);
+
+    SensitiveWordService::new()
+        .remove_word(&word_id)
+        .map_err(|e| AppError::from(e).context("删除敏感词失败"))
+}
+
+// 最近 hours 小时内消息从点击发送到服务器确认的延迟分布，默认 24 小时
+#[tauri::command]
+pub async fn get_message_latency_metrics(
+    hours: Option<u32>,
+    metrics: State<'_, MetricsServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MessageLatencyMetrics, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+
+    metrics
+        .get_message_latency_metrics(hours.unwrap_or(24), Utc::now())
+        .map_err(|e| AppError::from(e).context("获取消息延迟指标失败"))
+}
+
+#[tauri::command]
+pub async fn reset_message_latency_metrics(
+    metrics: State<'_, MetricsServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<usize, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    tracing::info!("Resetting message latency metrics");
+
+    metrics
+        .reset_message_latency()
+        .map_err(|e| AppError::from(e).context("重置消息延迟指标失败"))
+}
+
+// 被拦截的发送都要留审计记录
+async fn audit_blocked_message(
+    security_service: &State<'_, SecurityServiceState>,
+    token_refresh: &State<'_, TokenRefreshServiceState>,
+    consultation_id: &str,
+    error: &AppError,
+) {
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let matches = error
+        .details
+        .as_ref()
+        .and_then(|d| d["matches"].as_array())
+        .map(|words| words.iter().filter_map(|w| w.as_str()).collect::<Vec<_>>().join(","))
+        .unwrap_or_default();
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "send_message".to_string());
+    metadata.insert("matched_words".to_string(), matches);
+
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::SendMessage,
+            Some("consultation".to_string()),
+            Some(consultation_id.to_string()),
+            "blocked".to_string(),
+            Some(error.message.clone()),
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for blocked message: {}", e);
+    }
+}
+
+// 按约定路径查找图片缩略图，不存在时为空
+fn image_thumbnail(file_path: &str) -> Option<String> {
+    let thumbnail = FileService::thumbnail_path_for(std::path::Path::new(file_path));
+    thumbnail.exists().then(|| thumbnail.to_string_lossy().to_string())
+}
+
+// 文件消息附件在缓存中记录的预览，没有时为空
+fn file_preview(file_cache_dao: &FileCacheDao, file_path: &str) -> Option<FilePreview> {
+    file_cache_dao.find_preview(file_path).unwrap_or_else(|e| {
+        tracing::warn!("Failed to load preview for {}: {}", file_path, e);
+        None
+    })
+}
+
+// 图片附件上传时是否被压缩过
+fn image_compressed(file_cache_dao: &FileCacheDao, file_path: &str) -> bool {
+    match file_cache_dao.find_original_size(file_path) {
+        Ok(original_size) => original_size.is_some(),
+        Err(e) => {
+            tracing::warn!("Failed to load upload size for {}: {}", file_path, e);
+            false
+        }
+    }
+}
+
+fn is_audio_file(file_name: &str) -> bool {
+    const AUDIO_EXTENSIONS: [&str; 6] = ["wav", "mp3", "m4a", "aac", "amr", "ogg"];
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::dao::{ConsultationDao, PatientDao};
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Consultation, ConsultationPriority, MessageWarmupConfig, Patient, SystemEventKind};
+    use crate::models::UserRole;
+    use crate::services::{PermissionService, SecurityService, MESSAGE_WARMUP_PAGE_SIZE, PERMISSION_DENIED};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    // 为医生创建一个问诊并写入一条消息，返回问诊 ID
+    fn seed_consultation(connection: &DbConnection, doctor_id: &str, content: &str) -> String {
+        let patient_id = format!("patient-{}", doctor_id);
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: patient_id.clone(),
+                name: "张三".to_string(),
+                age: None,
+                gender: None,
+                phone: None,
+                id_card: None,
+                tags: Vec::new(),
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                version: 1,
+            })
+            .unwrap();
+        let consultation_id = ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id,
+                doctor_id: doctor_id.to_string(),
+                status: "active".to_string(),
+                consultation_type: "text".to_string(),
+                title: None,
+                description: None,
+                diagnosis: None,
+                prescription: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap();
+        MessageDao::with_connection(connection.clone())
+            .create(&MessageModel {
+                id: String::new(),
+                consultation_id: consultation_id.clone(),
+                sender_type: SenderType::Patient,
+                message_type: MessageType::Text,
+                content: Some(content.to_string()),
+                file_path: None,
+                file_size: None,
+                mime_type: None,
+                timestamp: Utc::now(),
+                sync_status: SyncStatus::Synced,
+                read_status: ReadStatus::Unread,
+                template_id: None,
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
+            })
+            .unwrap();
+        consultation_id
+    }
+
+    #[test]
+    fn test_message_history_scoped_by_doctor() {
+        let connection = create_test_connection();
+        let consultation_a = seed_consultation(&connection, "doctor-a", "医生A的患者消息");
+        let consultation_b = seed_consultation(&connection, "doctor-b", "医生B的患者消息");
+        let files = FileCacheDao::with_connection(connection.clone());
+        let dao = MessageDao::with_connection(connection);
+        let doctor_a = DataScope::Doctor("doctor-a".to_string());
+
+        let own = load_message_history(&dao, &files, &consultation_a, &doctor_a, 1, 20).unwrap();
+        assert_eq!(own.total, 1);
+        assert_eq!(own.messages[0].content, "医生A的患者消息");
+
+        let other = load_message_history(&dao, &files, &consultation_b, &doctor_a, 1, 20).unwrap();
+        assert_eq!(other.total, 0);
+        assert!(other.messages.is_empty());
+
+        let all = load_message_history(&dao, &files, &consultation_b, &DataScope::All, 1, 20).unwrap();
+        assert_eq!(all.messages[0].content, "医生B的患者消息");
+    }
+
+    fn doctor_permissions(doctor_id: &str) -> PermissionServiceState {
+        let security = Arc::new(tokio::sync::Mutex::new(SecurityService::new(300)));
+        let mut permissions = PermissionService::new(security);
+        permissions.start_session(doctor_id.to_string(), Some(UserRole::Doctor));
+        Arc::new(tokio::sync::Mutex::new(permissions))
+    }
+
+    #[tokio::test]
+    async fn test_write_and_unread_paths_reject_other_doctor() {
+        let connection = create_test_connection();
+        let consultation_a = seed_consultation(&connection, "doctor-a", "医生A的患者消息");
+        let consultation_b = seed_consultation(&connection, "doctor-b", "医生B的患者消息");
+        let consultations = ConsultationDao::with_connection(connection.clone());
+        let dao = MessageDao::with_connection(connection);
+        let doctor_a = doctor_permissions("doctor-a");
+
+        // 发送、标记已读、未读数都不能触及其他医生的问诊
+        let error = ensure_consultation_id_in_scope(&doctor_a, &consultations, &consultation_b).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(PERMISSION_DENIED));
+        let error = unread_count(&doctor_a, &consultations, &dao, &consultation_b).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(PERMISSION_DENIED));
+        let error = mark_consultation_read(&doctor_a, &consultations, &dao, &consultation_b).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(PERMISSION_DENIED));
+        assert_eq!(dao.get_unread_count(&consultation_b, "doctor").unwrap(), 1);
+
+        assert!(ensure_consultation_id_in_scope(&doctor_a, &consultations, &consultation_a).await.is_ok());
+        assert_eq!(unread_count(&doctor_a, &consultations, &dao, &consultation_a).await.unwrap(), 1);
+        assert_eq!(mark_consultation_read(&doctor_a, &consultations, &dao, &consultation_a).await.unwrap(), 1);
+        assert_eq!(unread_count(&doctor_a, &consultations, &dao, &consultation_a).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_warmed_first_page_skips_database() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection, "doctor-a", "预热的消息");
+        let files = FileCacheDao::with_connection(connection.clone());
+        let scope = DataScope::Doctor("doctor-a".to_string());
+        let warmup = MessageWarmupService::with_connection(connection.clone()).with_pause(Duration::ZERO);
+
+        let disabled = MessageWarmupConfig { enabled: false, ..MessageWarmupConfig::default() };
+        assert_eq!(warmup.warm("doctor-a", &scope, &disabled).unwrap(), 0);
+        assert_eq!(warmup.warm("doctor-a", &scope, &MessageWarmupConfig::default()).unwrap(), 1);
+
+        // 预热后打开问诊窗口，第一页直接来自缓存，不查询数据库
+        let dao = MessageDao::with_connection(connection.clone());
+        let cached = load_message_history(&dao, &files, &consultation_id, &scope, 1, MESSAGE_WARMUP_PAGE_SIZE).unwrap();
+        assert_eq!(dao.history_query_count(), 0);
+        assert_eq!(cached.total, 1);
+        assert_eq!(cached.messages[0].content, "预热的消息");
+
+        // 其他页、其他分页大小和其他数据范围仍查询数据库
+        load_message_history(&dao, &files, &consultation_id, &scope, 2, MESSAGE_WARMUP_PAGE_SIZE).unwrap();
+        load_message_history(&dao, &files, &consultation_id, &scope, 1, 20).unwrap();
+        load_message_history(&dao, &files, &consultation_id, &DataScope::All, 1, MESSAGE_WARMUP_PAGE_SIZE).unwrap();
+        assert_eq!(dao.history_query_count(), 3);
+    }
+
+    #[test]
+    fn test_new_message_invalidates_only_its_consultation() {
+        let connection = create_test_connection();
+        let first = seed_consultation(&connection, "doctor-a", "第一个问诊");
+        let second = seed_consultation(&connection, "doctor-a", "第二个问诊");
+        let files = FileCacheDao::with_connection(connection.clone());
+        let scope = DataScope::Doctor("doctor-a".to_string());
+        let warmed = MessageWarmupService::with_connection(connection.clone())
+            .with_pause(Duration::ZERO)
+            .warm("doctor-a", &scope, &MessageWarmupConfig::default())
+            .unwrap();
+        assert_eq!(warmed, 2);
+
+        let dao = MessageDao::with_connection(connection.clone());
+        dao.insert_system_message(&first, SystemEventKind::Transferred, serde_json::json!({ "toDoctorName": "李医生" }))
+            .unwrap();
+
+        // 有新消息的问诊重新查询，另一个问诊仍命中缓存
+        let history = load_message_history(&dao, &files, &first, &scope, 1, MESSAGE_WARMUP_PAGE_SIZE).unwrap();
+        assert_eq!(history.total, 2);
+        assert_eq!(dao.history_query_count(), 1);
+        load_message_history(&dao, &files, &second, &scope, 1, MESSAGE_WARMUP_PAGE_SIZE).unwrap();
+        assert_eq!(dao.history_query_count(), 1);
+
+        // 重新查询的结果写回缓存
+        load_message_history(&dao, &files, &first, &scope, 1, MESSAGE_WARMUP_PAGE_SIZE).unwrap();
+        assert_eq!(dao.history_query_count(), 1);
+    }
+
+    #[test]
+    fn test_system_messages_in_history_and_unread() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection, "doctor-a", "患者消息");
+        let dao = MessageDao::with_connection(connection.clone());
+        dao.insert_system_message(
+            &consultation_id,
+            SystemEventKind::Transferred,
+            serde_json::json!({ "toDoctorName": "李医生" }),
+        )
+        .unwrap();
+
+        // 即使系统消息被标为未读，也不计入未读数，也不被标记已读
+        connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE messages SET read_status = 'unread' WHERE sender_type = 'system'", [])
+            .unwrap();
+        assert_eq!(dao.get_unread_count(&consultation_id, "doctor").unwrap(), 1);
+        assert_eq!(dao.mark_consultation_messages_as_read(&consultation_id, "doctor").unwrap(), 1);
+        assert_eq!(dao.get_unread_count(&consultation_id, "doctor").unwrap(), 0);
+
+        let files = FileCacheDao::with_connection(connection.clone());
+        let history = load_message_history(&dao, &files, &consultation_id, &DataScope::All, 1, 20).unwrap();
+        let system = history.messages.iter().find(|m| m.sender == "system").unwrap();
+        assert_eq!(system.message_type, "event");
+        assert_eq!(system.content, "已转接给李医生");
+        assert_eq!(system.event.as_ref().unwrap().kind, SystemEventKind::Transferred);
+    }
+
+    #[test]
+    fn test_queued_error_reports_queue_position() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection, "doctor-a", "患者消息");
+        {
+            let conn = connection.lock().unwrap();
+            conn.execute("UPDATE messages SET sync_status = 'synced'", []).unwrap();
+            conn.execute(
+                "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp, sync_status) VALUES
+                     ('queued-1', ?1, 'doctor', 'text', '第一条', '2024-03-01 09:00:00+00:00', 'pending'),
+                     ('queued-2', ?1, 'doctor', 'text', '第二条', '2024-03-01 09:01:00+00:00', 'pending')",
+                [&consultation_id],
+            )
+            .unwrap();
+        }
+        let dao = MessageDao::with_connection(connection);
+
+        let error = queued_error(&dao, "queued-2");
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_MESSAGE_QUEUED));
+        assert_eq!(error.retryable, Some(false));
+        let details = error.details.unwrap();
+        assert_eq!(details["messageId"], "queued-2");
+        assert_eq!(details["queuePosition"], 2);
+
+        assert_eq!(queued_error(&dao, "queued-1").details.unwrap()["queuePosition"], 1);
+    }
+}
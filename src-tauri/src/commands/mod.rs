@@ -2,19 +2,41 @@
 
 pub mod auth;
 pub mod patient;
+pub mod consultation;
+pub mod prescription;
+pub mod medical_record;
 pub mod message;
 pub mod window;
 pub mod database;
 pub mod file;
 pub mod websocket;
 pub mod security;
+pub mod permission;
+pub mod notification;
+pub mod logging;
+pub mod update;
+pub mod health;
+pub mod settings;
+pub mod trash;
+pub mod rate_limit;
 
 // 重新导出所有命令
 pub use auth::*;
 pub use patient::*;
+pub use consultation::*;
+pub use prescription::*;
+pub use medical_record::*;
 pub use message::*;
 pub use window::*;
 pub use database::*;
 pub use file::*;
 pub use websocket::*;
-pub use security::*;
\ No newline at end of file
+pub use security::*;
+pub use permission::*;
+pub use notification::*;
+pub use logging::*;
+pub use update::*;
+pub use health::*;
+pub use settings::*;
+pub use trash::*;
+pub use rate_limit::*;
\ No newline at end of file
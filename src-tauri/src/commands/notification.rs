@@ -0,0 +1,47 @@
+// 消息通知相关命令
+
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::database::dao::UserSettingsDao;
+use crate::services::{emit_unread_badge, NotificationRouterState, UnreadBadge, DO_NOT_DISTURB_KEY};
+use tauri::{AppHandle, State};
+
+#[tauri::command]
+pub async fn get_do_not_disturb(
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<bool, String> {
+    require_database(&readiness).await.map_err(|e| e.to_string())?;
+    let doctor_id = current_doctor_id(&token_refresh).await.map_err(|e| e.message)?;
+
+    UserSettingsDao::new()
+        .get_bool(&doctor_id, DO_NOT_DISTURB_KEY)
+        .map_err(|e| format!("获取免打扰设置失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn set_do_not_disturb(
+    enabled: bool,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<(), String> {
+    require_database(&readiness).await.map_err(|e| e.to_string())?;
+    tracing::info!("Setting do not disturb: {}", enabled);
+
+    let doctor_id = current_doctor_id(&token_refresh).await.map_err(|e| e.message)?;
+
+    UserSettingsDao::new()
+        .set(&doctor_id, DO_NOT_DISTURB_KEY, if enabled { "true" } else { "false" })
+        .map_err(|e| format!("保存免打扰设置失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn clear_unread_badge(
+    consultation_id: String,
+    app: AppHandle,
+    router: State<'_, NotificationRouterState>,
+) -> Result<UnreadBadge, String> {
+    let badge = router.lock().unwrap().clear_unread(&consultation_id);
+    emit_unread_badge(&app, &badge);
+    Ok(badge)
+}
@@ -1,155 +1,618 @@
 // 患者管理相关命令
 
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Deserialize)]
-pub struct PatientQuery {
-    pub page: Option<u32>,
-    pub limit: Option<u32>,
-    pub search: Option<String>,
-    pub tags: Option<Vec<String>>,
-}
-
-#[derive(Debug, Serialize)]
-pub struct Patient {
-    pub id: String,
-    pub name: String,
-    pub age: u32,
-    pub gender: String,
-    pub phone: String,
-    pub tags: Vec<String>,
-    pub last_visit: String,
-    pub medical_history: Vec<MedicalRecord>,
-}
-
-#[derive(Debug, Serialize)]
-pub struct MedicalRecord {
-    pub id: String,
-    pub patient_id: String,
-    pub doctor_id: String,
-    pub diagnosis: String,
-    pub treatment: String,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize)]
-pub struct PatientList {
-    pub patients: Vec<Patient>,
-    pub total: u32,
-    pub page: u32,
-    pub limit: u32,
-}
-
-#[tauri::command]
-pub async fn get_patient_list(query: PatientQuery) -> Result<PatientList, String> {
-    println!("Getting patient list with query: {:?}", query);
-
-    // TODO: 实现从数据库获取患者列表的逻辑
-
-    // 模拟数据库查询延迟
-    tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
-
-    // 模拟患者数据
-    let mock_patients = vec![
-        Patient {
-            id: "1".to_string(),
-            name: "李小明".to_string(),
-            age: 35,
-            gender: "male".to_string(),
-            phone: "138****1234".to_string(),
-            tags: vec!["高血压".to_string(), "糖尿病".to_string()],
-            last_visit: "2024-01-15T10:00:00Z".to_string(),
-            medical_history: vec![],
-        },
-        Patient {
-            id: "2".to_string(),
-            name: "王小红".to_string(),
-            age: 28,
-            gender: "female".to_string(),
-            phone: "139****5678".to_string(),
-            tags: vec!["孕期检查".to_string()],
-            last_visit: "2024-01-20T14:30:00Z".to_string(),
-            medical_history: vec![],
-        },
-    ];
-
-    let result = PatientList {
-        patients: mock_patients,
-        total: 2,
-        page: query.page.unwrap_or(1),
-        limit: query.limit.unwrap_or(20),
-    };
+use crate::commands::auth::TokenRefreshServiceState;
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::{current_data_scope, current_masking, require_permission, PermissionServiceState};
+use crate::commands::rate_limit::{require_rate_limit, CommandRateLimiterState};
+use crate::commands::security::SecurityServiceState;
+use crate::database::dao::ChangeLogDao;
+use crate::models::{
+    ChangeEntity, DataChangePage, DataScope, ErrorType, FieldEncryptionProgress, IdCardInfo, ImportReport, PaginatedResponse, Patient,
+    PatientDetail, PatientField, PatientQuery, PatientRevision, PatientRevisionField, Permission, TagUsage, TimelineEvent,
+    TimelineEventType,
+};
+use crate::services::{
+    effective_config, AuditAction, BundleExportResult, BundleFormat, BundleImportResult, PatientBundleService, PatientImportService,
+    PatientService, SecurityService,
+};
+use crate::utils::{AppError, MessageKey, ValidationResult, ValidationService};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, State, WebviewWindow};
+
+pub const PATIENT_ENCRYPTION_PROGRESS_EVENT: &str = "patient-encryption-progress";
+const ENCRYPTION_BATCH_SIZE: usize = 200;
+const DEFAULT_CHANGE_PAGE_SIZE: i64 = 500;
+const MAX_CHANGE_PAGE_SIZE: i64 = 2000;
+
+#[tauri::command]
+pub async fn get_patient_list(
+    query: PatientQuery,
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PaginatedResponse<Patient>, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "get_patient_list")?;
+    require_database(&readiness).await?;
+    tracing::debug!("Getting patient list with query: {:?}", query);
+
+    ValidationService::validate_patient_query(&query).into_app_result()?;
+
+    let scope = current_data_scope(&permissions).await?;
+    let masking = current_masking(&permissions).await;
+    let session_token = token_refresh.lock().await.current_token().await;
+    let patient_service = PatientService::with_session(&effective_config(), session_token);
+
+    match patient_service.get_patient_list(&query, &scope).await {
+        Ok(mut result) => {
+            result.items.iter_mut().for_each(|patient| masking.apply(patient));
+            Ok(result)
+        }
+        Err(e) => {
+            tracing::error!("Failed to get patient list: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_patient_detail(
+    patient_id: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PatientDetail, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Getting patient detail for ID: {}", patient_id);
+
+    let scope = current_data_scope(&permissions).await?;
+    let masking = current_masking(&permissions).await;
+    let session_token = token_refresh.lock().await.current_token().await;
+    let patient_service = PatientService::with_session(&effective_config(), session_token);
+
+    match patient_service.get_patient_detail(&patient_id, &scope).await {
+        Ok(mut detail) => {
+            masking.apply(&mut detail.patient);
+            Ok(detail)
+        }
+        Err(e) => {
+            tracing::error!("Failed to get patient detail: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 患者时间线：问诊、病历、处方和上传文件按时间倒序分页返回，filter_types 为空时返回全部类型
+#[tauri::command]
+pub async fn get_patient_timeline(
+    patient_id: String,
+    page: u32,
+    page_size: u32,
+    filter_types: Option<Vec<TimelineEventType>>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PaginatedResponse<TimelineEvent>, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Getting timeline for patient {}, page {}", patient_id, page);
+
+    let scope = current_data_scope(&permissions).await?;
+    let patient_service = PatientService::new(&effective_config());
+
+    patient_service
+        .get_patient_timeline(&patient_id, page, page_size, filter_types.as_deref(), &scope)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get patient timeline: {}", e);
+            AppError::from(e)
+        })
+}
+
+// version 为读取患者时的版本号；版本过期返回 STALE_WRITE，details.current 为最新的患者数据
+#[tauri::command]
+pub async fn update_patient_tags(
+    patient_id: String,
+    tags: Vec<String>,
+    version: i64,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Patient, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::EditPatients).await?;
+    tracing::info!("Updating patient tags for ID: {}, tags: {:?}", patient_id, tags);
+
+    let masking = current_masking(&permissions).await;
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let patient_service = PatientService::new(&effective_config());
+
+    match patient_service.update_patient_tags(&patient_id, tags, version, user_id.as_deref()).await {
+        Ok(mut patient) => {
+            masking.apply(&mut patient);
+            Ok(patient)
+        }
+        Err(e) => {
+            let error = AppError::from(e);
+            if !error.is_stale_write() {
+                return Err(error);
+            }
+            tracing::info!("Stale tag update for patient {} at version {}", patient_id, version);
+            match patient_service.find_patient(&patient_id) {
+                Ok(Some(mut current)) => {
+                    masking.apply(&mut current);
+                    Err(error.with_current(&current))
+                }
+                _ => Err(error),
+            }
+        }
+    }
+}
+
+// 患者资料修改记录，按修改时间倒序分页；敏感字段的新旧值按当前角色脱敏
+#[tauri::command]
+pub async fn get_patient_revisions(
+    patient_id: String,
+    page: u32,
+    page_size: u32,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PaginatedResponse<PatientRevision>, AppError> {
+    require_database(&readiness).await?;
+    tracing::debug!("Getting revisions for patient {}, page {}", patient_id, page);
 
+    let scope = current_data_scope(&permissions).await?;
+    let masking = current_masking(&permissions).await;
+    let patient_service = PatientService::new(&effective_config());
+
+    let mut result = patient_service
+        .get_patient_revisions(&patient_id, page, page_size, &scope)
+        .map_err(|e| {
+            tracing::error!("Failed to get patient revisions: {}", e);
+            AppError::from(e)
+        })?;
+    result.items.iter_mut().for_each(|revision| masking.apply_revision(revision));
     Ok(result)
 }
 
+// 窗口隐藏期间错过 data-changed 事件时，按 seq 补齐某类实体的变更；变更只含 id，
+// 窗口再按 id 通过带数据范围的命令取详情。resetRequired 时需重新加载完整列表
 #[tauri::command]
-pub async fn get_patient_detail(patient_id: String) -> Result<Patient, String> {
-    println!("Getting patient detail for ID: {}", patient_id);
-
-    // TODO: 实现从数据库获取患者详情的逻辑
-
-    // 模拟数据库查询延迟
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-    // 模拟患者详情数据
-    let patient = Patient {
-        id: patient_id.clone(),
-        name: "李小明".to_string(),
-        age: 35,
-        gender: "male".to_string(),
-        phone: "138****1234".to_string(),
-        tags: vec!["高血压".to_string(), "糖尿病".to_string()],
-        last_visit: "2024-01-15T10:00:00Z".to_string(),
-        medical_history: vec![
-            MedicalRecord {
-                id: "1".to_string(),
-                patient_id: patient_id.clone(),
-                doctor_id: "1".to_string(),
-                diagnosis: "高血压".to_string(),
-                treatment: "降压药物治疗".to_string(),
-                created_at: "2024-01-10T09:00:00Z".to_string(),
-            },
-        ],
+pub async fn get_changes_since(
+    entity: ChangeEntity,
+    watermark: i64,
+    limit: Option<i64>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<DataChangePage, AppError> {
+    require_database(&readiness).await?;
+    current_data_scope(&permissions).await?;
+
+    let limit = limit.unwrap_or(DEFAULT_CHANGE_PAGE_SIZE).clamp(1, MAX_CHANGE_PAGE_SIZE);
+    ChangeLogDao::new().find_since(entity, watermark, limit).map_err(|e| {
+        tracing::error!("Failed to get {} changes since {}: {}", entity.as_str(), watermark, e);
+        AppError::from(e)
+    })
+}
+
+// 把单个字段恢复为某次修改之前的值，恢复结果作为新的修改记录，成功和失败都记录审计日志
+#[tauri::command]
+pub async fn revert_patient_field(
+    patient_id: String,
+    revision_id: String,
+    field: PatientRevisionField,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Patient, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::EditPatients).await?;
+    tracing::info!("Reverting {} of patient {} to revision {}", field.as_str(), patient_id, revision_id);
+
+    let scope = current_data_scope(&permissions).await?;
+    let masking = current_masking(&permissions).await;
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let patient_service = PatientService::new(&effective_config());
+
+    let result = patient_service
+        .revert_patient_field(&patient_id, &revision_id, field, user_id.as_deref(), &scope)
+        .map_err(AppError::from);
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "revert_patient_field".to_string());
+    metadata.insert("revisionId".to_string(), revision_id.clone());
+    metadata.insert("field".to_string(), field.as_str().to_string());
+    let (status, error_message) = match &result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.message.clone())),
     };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::UpdatePatient,
+            Some("patient".to_string()),
+            Some(patient_id.clone()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for patient field revert: {}", e);
+    }
 
+    let mut patient = result?;
+    masking.apply(&mut patient);
     Ok(patient)
 }
 
 #[tauri::command]
-pub async fn update_patient_tags(patient_id: String, tags: Vec<String>) -> Result<(), String> {
-    println!("Updating patient tags for ID: {}, tags: {:?}", patient_id, tags);
+pub async fn search_patients(
+    keyword: String,
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<Patient>, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "search_patients")?;
+    require_database(&readiness).await?;
+    tracing::debug!("Searching patients with keyword: {}", keyword);
 
-    // TODO: 实现更新患者标签的逻辑
+    let scope = current_data_scope(&permissions).await?;
+    let masking = current_masking(&permissions).await;
+    let patient_service = PatientService::new(&effective_config());
 
-    // 模拟数据库更新延迟
-    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+    let mut patients = patient_service
+        .search_patients(&keyword, &scope)
+        .await
+        .map_err(AppError::from)?;
+    patients.iter_mut().for_each(|patient| masking.apply(patient));
+    Ok(patients)
+}
+
+// 查看单个脱敏字段的明文，需要解密敏感数据的权限，每次查看都记录审计日志
+#[tauri::command]
+pub async fn reveal_patient_field(
+    patient_id: String,
+    field: PatientField,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<String>, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::DecryptSensitiveData).await?;
+    tracing::info!("Revealing {} for patient {}", field.as_str(), patient_id);
 
-    Ok(())
+    let scope = current_data_scope(&permissions).await?;
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let patient_service = PatientService::new(&effective_config());
+    let security = security_service.lock().await;
+
+    reveal_field(&patient_service, &security, &scope, user_id, &patient_id, field).await
+}
+
+pub(crate) async fn reveal_field(
+    patient_service: &PatientService,
+    security: &SecurityService,
+    scope: &DataScope,
+    user_id: Option<String>,
+    patient_id: &str,
+    field: PatientField,
+) -> Result<Option<String>, AppError> {
+    let result = match patient_service.find_patient_in_scope(patient_id, scope) {
+        Ok(Some(patient)) => Ok(match field {
+            PatientField::Phone => patient.phone,
+            PatientField::IdCard => patient.id_card,
+            PatientField::Name => Some(patient.name),
+        }),
+        Ok(None) => Err(AppError::new(ErrorType::DataError, "患者不存在").with_code("PATIENT_NOT_FOUND")),
+        Err(e) => Err(AppError::from(e)),
+    };
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "reveal_patient_field".to_string());
+    metadata.insert("field".to_string(), field.as_str().to_string());
+    let (status, error_message) = match &result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.message.clone())),
+    };
+    if let Err(e) = security
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::AccessSensitiveData,
+            Some("patient".to_string()),
+            Some(patient_id.to_string()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for patient field reveal: {}", e);
+    }
+
+    result
 }
 
 #[tauri::command]
-pub async fn search_patients(keyword: String) -> Result<Vec<Patient>, String> {
-    println!("Searching patients with keyword: {}", keyword);
+pub async fn get_all_tags(readiness: State<'_, DatabaseReadinessState>) -> Result<Vec<TagUsage>, AppError> {
+    require_database(&readiness).await?;
+    let patient_service = PatientService::new(&effective_config());
 
-    // TODO: 实现患者搜索逻辑
+    patient_service.get_all_tags().await.map_err(AppError::from)
+}
 
-    // 模拟搜索延迟
-    tokio::time::sleep(tokio::time::Duration::from_millis(400)).await;
+#[tauri::command]
+pub async fn rename_patient_tag(
+    old_tag: String,
+    new_tag: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<usize, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManagePatientTags).await?;
+    tracing::info!("Renaming patient tag: {} -> {}", old_tag, new_tag);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let patient_service = PatientService::new(&effective_config());
+
+    patient_service
+        .rename_tag(&old_tag, &new_tag, user_id.as_deref())
+        .await
+        .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn merge_patient_tags(
+    source_tags: Vec<String>,
+    target_tag: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<usize, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManagePatientTags).await?;
+    tracing::info!("Merging patient tags: {:?} -> {}", source_tags, target_tag);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let patient_service = PatientService::new(&effective_config());
+
+    patient_service
+        .merge_tags(source_tags, &target_tag, user_id.as_deref())
+        .await
+        .map_err(AppError::from)
+}
+
+// 录入患者时根据身份证号自动填充出生日期和性别
+#[tauri::command]
+pub async fn parse_id_card(id_card: String) -> Result<IdCardInfo, AppError> {
+    ValidationService::parse_id_card(&id_card).ok_or_else(|| {
+        let mut validation = ValidationResult::new();
+        validation.add("idCard", MessageKey::IdCardInvalid, &[], "INVALID_FORMAT");
+        AppError::validation_failed(validation)
+    })
+}
+
+#[tauri::command]
+pub async fn import_patients(
+    file_path: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<ImportReport, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ImportPatients).await?;
+    tracing::info!("Importing patients from: {}", file_path);
+
+    let import_service = PatientImportService::new();
+
+    match import_service.import_file(std::path::Path::new(&file_path)) {
+        Ok(report) => {
+            tracing::info!(
+                "Patient import finished: imported={}, updated={}, skipped={}",
+                report.imported, report.updated, report.skipped
+            );
+            Ok(report)
+        }
+        Err(e) => {
+            tracing::error!("Patient import failed: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_patient_bundle(
+    patient_id: String,
+    format: BundleFormat,
+    output_dir: String,
+    mask_sensitive: bool,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<BundleExportResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ExportPatientData).await?;
+    tracing::info!("Exporting patient bundle for ID: {}, format: {:?}", patient_id, format);
+
+    // 无权查看完整标识的用户导出时同样脱敏
+    let mask_sensitive = mask_sensitive || current_masking(&permissions).await.masks_identifiers();
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let bundle_service = PatientBundleService::new();
+    let result = bundle_service.export_bundle(
+        &patient_id,
+        format,
+        std::path::Path::new(&output_dir),
+        mask_sensitive,
+    );
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "export_patient_bundle".to_string());
+    metadata.insert("masked".to_string(), mask_sensitive.to_string());
+    let (status, error_message) = match &result {
+        Ok(exported) => {
+            metadata.insert("path".to_string(), exported.path.clone());
+            ("success".to_string(), None)
+        }
+        Err(e) => ("failure".to_string(), Some(e.to_string())),
+    };
 
-    // 模拟搜索结果
-    let results = vec![
-        Patient {
-            id: "1".to_string(),
-            name: "李小明".to_string(),
-            age: 35,
-            gender: "male".to_string(),
-            phone: "138****1234".to_string(),
-            tags: vec!["高血压".to_string(), "糖尿病".to_string()],
-            last_visit: "2024-01-15T10:00:00Z".to_string(),
-            medical_history: vec![],
-        },
-    ];
+    // 导出患者数据属于敏感数据访问，无论成功与否都记录审计日志
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::AccessSensitiveData,
+            Some("patient".to_string()),
+            Some(patient_id.clone()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for patient export: {}", e);
+    }
 
-    Ok(results)
-}
\ No newline at end of file
+    result.map_err(|e| {
+        tracing::error!("Patient bundle export failed: {}", e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+pub async fn import_patient_bundle(
+    file_path: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<BundleImportResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ImportPatients).await?;
+    tracing::info!("Importing patient bundle from: {}", file_path);
+
+    let bundle_service = PatientBundleService::new();
+
+    bundle_service
+        .import_bundle_file(std::path::Path::new(&file_path))
+        .map_err(AppError::from)
+}
+
+// 一次性迁移：将历史明文的手机号和身份证号加密，按批次推送进度
+#[tauri::command]
+pub async fn migrate_encrypt_patient_fields(
+    app: AppHandle,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<usize, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    tracing::info!("Encrypting legacy patient fields");
+
+    let patient_service = PatientService::new(&effective_config());
+    let migrated = patient_service.encrypt_legacy_fields(ENCRYPTION_BATCH_SIZE, |processed, total| {
+        let progress = FieldEncryptionProgress { processed, total };
+        if let Err(e) = app.emit(PATIENT_ENCRYPTION_PROGRESS_EVENT, &progress) {
+            tracing::warn!("Failed to emit encryption progress: {}", e);
+        }
+    });
+
+    match migrated {
+        Ok(count) => {
+            tracing::info!("Encrypted sensitive fields for {} patients", count);
+            Ok(count)
+        }
+        Err(e) => {
+            tracing::error!("Patient field encryption failed: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::dao::PatientDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::services::AuditAction;
+    use chrono::{Duration, Utc};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn patient_service() -> PatientService {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "王小明".to_string(),
+                age: Some(40),
+                gender: Some("male".to_string()),
+                phone: Some("13812345678".to_string()),
+                id_card: Some("110101199001011234".to_string()),
+                tags: vec![],
+                avatar_url: None,
+                last_sync: Some(Utc::now()),
+                last_visit: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                version: 1,
+            })
+            .unwrap();
+        PatientService::with_connection(connection, None, Duration::minutes(30))
+    }
+
+    #[tokio::test]
+    async fn test_reveal_returns_plaintext_and_is_audited() {
+        let service = patient_service();
+        let security = SecurityService::new(300);
+
+        let admin = Some("admin-1".to_string());
+        let phone = reveal_field(&service, &security, &DataScope::All, admin, "p1", PatientField::Phone)
+            .await
+            .unwrap();
+        assert_eq!(phone.as_deref(), Some("13812345678"));
+
+        let logs = security
+            .get_audit_logs(Some("admin-1".to_string()), None, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(matches!(logs[0].action, AuditAction::AccessSensitiveData));
+        assert_eq!(logs[0].resource_id.as_deref(), Some("p1"));
+        assert_eq!(logs[0].status, "success");
+        assert_eq!(logs[0].metadata.get("field").map(String::as_str), Some("phone"));
+    }
+
+    #[tokio::test]
+    async fn test_reveal_outside_scope_is_audited_as_failure() {
+        let service = patient_service();
+        let security = SecurityService::new(300);
+        let scope = DataScope::Doctor("doctor-b".to_string());
+
+        let doctor = Some("doctor-b".to_string());
+        let error = reveal_field(&service, &security, &scope, doctor, "p1", PatientField::IdCard)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("PATIENT_NOT_FOUND"));
+
+        let logs = security
+            .get_audit_logs(Some("doctor-b".to_string()), None, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].status, "failure");
+        assert_eq!(logs[0].metadata.get("field").map(String::as_str), Some("idCard"));
+    }
+}
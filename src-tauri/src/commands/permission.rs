@@ -0,0 +1,120 @@
+// 权限相关命令
+
+use crate::models::{AppError, DataScope, ErrorType, Permission, UserPermissions};
+use crate::services::PermissionService;
+use crate::utils::MaskingPolicy;
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::Mutex;
+
+pub type PermissionServiceState = Arc<Mutex<PermissionService>>;
+
+// 受保护命令开头调用，没有权限时返回 PermissionError 并记录审计日志
+pub(crate) async fn require_permission(
+    permissions: &PermissionServiceState,
+    permission: Permission,
+) -> Result<(), AppError> {
+    permissions.lock().await.check(permission).await
+}
+
+// 当前登录用户可见的本地数据范围，未登录时返回认证错误
+pub(crate) async fn current_data_scope(permissions: &PermissionServiceState) -> Result<DataScope, AppError> {
+    permissions
+        .lock()
+        .await
+        .data_scope()
+        .ok_or_else(|| AppError::new(ErrorType::AuthError, "请先登录").with_code("NOT_LOGGED_IN"))
+}
+
+// 返回患者数据前按当前角色脱敏
+pub(crate) async fn current_masking(permissions: &PermissionServiceState) -> MaskingPolicy {
+    permissions.lock().await.masking_policy()
+}
+
+/// 获取当前用户的角色和权限
+#[tauri::command]
+pub async fn get_my_permissions(
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<UserPermissions, AppError> {
+    Ok(permissions.lock().await.current_permissions())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserRole;
+    use crate::services::SecurityService;
+
+    // 受保护命令 × 所需权限 × 允许的角色
+    const MATRIX: &[(&str, Permission, &[UserRole])] = &[
+        ("get_audit_logs", Permission::ViewAuditLogs, &[UserRole::Admin]),
+        ("export_audit_logs", Permission::ViewAuditLogs, &[UserRole::Admin]),
+        ("get_anomaly_records", Permission::ViewAuditLogs, &[UserRole::Admin]),
+        ("detect_anomalies", Permission::ViewAuditLogs, &[UserRole::Admin]),
+        ("get_message_raw_content", Permission::ViewAuditLogs, &[UserRole::Admin]),
+        ("get_security_config", Permission::ViewAuditLogs, &[UserRole::Admin]),
+        ("update_security_config", Permission::ManageSecurity, &[UserRole::Admin]),
+        ("resolve_anomaly", Permission::ManageSecurity, &[UserRole::Admin]),
+        ("cleanup_old_security_records", Permission::ManageSecurity, &[UserRole::Admin]),
+        ("decrypt_sensitive_data", Permission::DecryptSensitiveData, &[UserRole::Doctor, UserRole::Admin]),
+        ("reveal_patient_field", Permission::DecryptSensitiveData, &[UserRole::Doctor, UserRole::Admin]),
+        ("sync_data", Permission::SyncData, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+        ("pause_background_sync", Permission::SyncData, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+        ("resume_background_sync", Permission::SyncData, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+        ("get_query_stats", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("get_slow_queries", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("clear_query_stats", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("check_enum_columns", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("get_retention_policy", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("update_retention_policy", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("run_retention_now", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("run_integrity_repair", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("get_message_latency_metrics", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("reset_message_latency_metrics", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("migrate_encrypt_patient_fields", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("export_workstation_profile", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("import_workstation_profile", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("export_diagnostics_bundle", Permission::ManageDatabase, &[UserRole::Admin]),
+        ("update_patient_tags", Permission::EditPatients, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+        ("revert_patient_field", Permission::EditPatients, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+        ("rename_patient_tag", Permission::ManagePatientTags, &[UserRole::Doctor, UserRole::Admin]),
+        ("merge_patient_tags", Permission::ManagePatientTags, &[UserRole::Doctor, UserRole::Admin]),
+        ("import_patients", Permission::ImportPatients, &[UserRole::Doctor, UserRole::Admin]),
+        ("import_patient_bundle", Permission::ImportPatients, &[UserRole::Doctor, UserRole::Admin]),
+        ("export_patient_bundle", Permission::ExportPatientData, &[UserRole::Doctor, UserRole::Admin]),
+        ("export_consultation_transcript", Permission::ExportPatientData, &[UserRole::Doctor, UserRole::Admin]),
+        ("generate_prescription_pdf", Permission::ExportPatientData, &[UserRole::Doctor, UserRole::Admin]),
+        ("delete_local_file", Permission::DeleteFiles, &[UserRole::Doctor, UserRole::Admin]),
+        ("remove_file_from_cache", Permission::DeleteFiles, &[UserRole::Doctor, UserRole::Admin]),
+        ("delete_file_cache_record", Permission::DeleteFiles, &[UserRole::Doctor, UserRole::Admin]),
+        ("clear_all_file_cache", Permission::DeleteFiles, &[UserRole::Doctor, UserRole::Admin]),
+        ("read_file_from_local", Permission::ReadFiles, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+        ("log_audit", Permission::WriteAuditLogs, &[UserRole::Doctor, UserRole::Nurse, UserRole::Admin]),
+    ];
+
+    #[tokio::test]
+    async fn test_permission_matrix() {
+        let security = Arc::new(Mutex::new(SecurityService::new(300)));
+        let permissions: PermissionServiceState = Arc::new(Mutex::new(PermissionService::new(security.clone())));
+
+        let mut expected_denials = 0;
+        for role in UserRole::ALL {
+            permissions.lock().await.start_session(format!("{}-1", role.as_str()), Some(role));
+
+            for (command, permission, allowed) in MATRIX {
+                let result = require_permission(&permissions, *permission).await;
+                if allowed.contains(&role) {
+                    assert!(result.is_ok(), "{} should be allowed to call {}", role.as_str(), command);
+                } else {
+                    let error = result.expect_err(&format!("{} should not be allowed to call {}", role.as_str(), command));
+                    assert!(matches!(error.error_type, ErrorType::PermissionError));
+                    expected_denials += 1;
+                }
+            }
+        }
+
+        // 每次拒绝都记录一条审计日志
+        let logs = security.lock().await.get_audit_logs(None, None, None, None, 1000).await.unwrap();
+        assert_eq!(logs.len(), expected_denials);
+    }
+}
@@ -0,0 +1,163 @@
+// 处方相关命令
+
+use crate::commands::auth::TokenRefreshServiceState;
+use crate::commands::consultation::ensure_consultation_in_scope;
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::{current_masking, require_permission, PermissionServiceState};
+use crate::commands::security::SecurityServiceState;
+use crate::models::{AppError, ErrorType, Permission, Prescription, PrescriptionItem};
+use crate::services::security::AuditAction;
+use crate::services::{
+    load_prescription_font, AppSettingsService, ConsultationService, PrescriptionPdfResult, PrescriptionPdfService,
+    PrescriptionService,
+};
+use std::collections::HashMap;
+use tauri::{AppHandle, Manager, State};
+
+// 处方的读写权限跟随所属问诊
+async fn ensure_prescription_in_scope(
+    permissions: &PermissionServiceState,
+    consultation_id: &str,
+) -> Result<(), AppError> {
+    let consultation = ConsultationService::new().get_consultation(consultation_id).await?;
+    ensure_consultation_in_scope(permissions, &consultation).await
+}
+
+async fn current_user_id(permissions: &PermissionServiceState) -> Result<String, AppError> {
+    permissions
+        .lock()
+        .await
+        .current_user_id()
+        .map(str::to_string)
+        .ok_or_else(|| AppError::new(ErrorType::AuthError, "请先登录").with_code("NOT_LOGGED_IN"))
+}
+
+#[tauri::command]
+pub async fn save_prescription_draft(
+    consultation_id: String,
+    items: Vec<PrescriptionItem>,
+    prescription_id: Option<String>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Prescription, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Saving prescription draft for consultation: {}", consultation_id);
+
+    ensure_prescription_in_scope(&permissions, &consultation_id).await?;
+    let doctor_id = current_user_id(&permissions).await?;
+
+    PrescriptionService::new()
+        .save_draft(&consultation_id, prescription_id.as_deref(), items, &doctor_id)
+        .await
+}
+
+#[tauri::command]
+pub async fn issue_prescription(
+    prescription_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Prescription, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Issuing prescription: {}", prescription_id);
+
+    let prescription_service = PrescriptionService::new();
+    let prescription = prescription_service.get_prescription(&prescription_id).await?;
+    ensure_prescription_in_scope(&permissions, &prescription.consultation_id).await?;
+    let user_id = current_user_id(&permissions).await?;
+
+    prescription_service.issue(&prescription_id, Some(&user_id)).await
+}
+
+#[tauri::command]
+pub async fn void_prescription(
+    prescription_id: String,
+    reason: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Prescription, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Voiding prescription: {}, reason: {}", prescription_id, reason);
+
+    let prescription_service = PrescriptionService::new();
+    let prescription = prescription_service.get_prescription(&prescription_id).await?;
+    ensure_prescription_in_scope(&permissions, &prescription.consultation_id).await?;
+    let user_id = current_user_id(&permissions).await?;
+
+    prescription_service.void(&prescription_id, &reason, Some(&user_id)).await
+}
+
+#[tauri::command]
+pub async fn get_consultation_prescriptions(
+    consultation_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<Prescription>, AppError> {
+    require_database(&readiness).await?;
+    ensure_prescription_in_scope(&permissions, &consultation_id).await?;
+
+    PrescriptionService::new().list_by_consultation(&consultation_id).await
+}
+
+// 生成可打印的处方笺 PDF，患者信息按当前角色脱敏，返回文件路径和 SHA-256 供核对
+#[tauri::command]
+pub async fn generate_prescription_pdf(
+    prescription_id: String,
+    output_path: String,
+    app: AppHandle,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PrescriptionPdfResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ExportPatientData).await?;
+    let prescription = PrescriptionService::new().get_prescription(&prescription_id).await?;
+    ensure_prescription_in_scope(&permissions, &prescription.consultation_id).await?;
+    tracing::info!("Generating prescription PDF: {}", prescription_id);
+
+    let hospital = AppSettingsService::new().load()?.hospital;
+    let masking = current_masking(&permissions).await;
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let result = load_prescription_font(app.path().resource_dir().ok().as_deref()).and_then(|font| {
+        PrescriptionPdfService::new()?.generate(
+            &prescription_id,
+            std::path::Path::new(&output_path),
+            &hospital,
+            &masking,
+            &font,
+        )
+    });
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "generate_prescription_pdf".to_string());
+    metadata.insert("consultation_id".to_string(), prescription.consultation_id.clone());
+    metadata.insert("path".to_string(), output_path.clone());
+    let (status, error_message) = match &result {
+        Ok(generated) => {
+            metadata.insert("sha256".to_string(), generated.sha256.clone());
+            ("success".to_string(), None)
+        }
+        Err(e) => ("failure".to_string(), Some(e.to_string())),
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::DownloadFile,
+            Some("prescription".to_string()),
+            Some(prescription_id.clone()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for prescription PDF: {}", e);
+    }
+
+    result.map_err(|e| {
+        tracing::error!("Prescription PDF generation failed: {}", e);
+        AppError::from(e)
+    })
+}
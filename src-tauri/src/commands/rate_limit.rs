@@ -0,0 +1,24 @@
+// 命令调用限流相关命令
+
+use crate::services::{CommandCaller, CommandRateLimiter, RateLimitStats};
+use crate::utils::AppError;
+use std::sync::{Arc, Mutex};
+use tauri::{State, WebviewWindow};
+
+pub type CommandRateLimiterState = Arc<Mutex<CommandRateLimiter>>;
+
+// 在受限命令开头调用，按调用窗口的标签计数
+pub(crate) fn require_rate_limit(
+    limiter: &CommandRateLimiterState,
+    window: &WebviewWindow,
+    command: &str,
+) -> Result<(), AppError> {
+    let caller = CommandCaller::Window(window.label().to_string());
+    limiter.lock().unwrap().check(command, &caller)
+}
+
+/// 各命令、各窗口的放行和限流次数，用于排查前端循环调用
+#[tauri::command]
+pub async fn get_rate_limit_stats(limiter: State<'_, CommandRateLimiterState>) -> Result<Vec<RateLimitStats>, AppError> {
+    Ok(limiter.lock().unwrap().stats())
+}
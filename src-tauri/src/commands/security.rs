@@ -1,11 +1,23 @@
 // 安全相关命令
 
-use crate::services::security::{AuditAction, AuditLog, AnomalyRecord, SecurityService};
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::{require_permission, PermissionServiceState};
+use crate::commands::rate_limit::{require_rate_limit, CommandRateLimiterState};
+use crate::commands::websocket::WebSocketManagerState;
+use crate::commands::window::WindowManagerState;
+use crate::database::dao::{AuditLogDao, PageResult};
+use crate::models::{AuditLog as StoredAuditLog, AuditLogFilter, Permission, SecurityConfig};
+use crate::services::audit_export::{AuditExportFormat, AuditExportResult, AuditExportService};
+use crate::services::security::{AuditAction, AuditLog, AnomalyRecord, AutoLockStatus, SecurityService};
+use crate::services::session_purge::{self, PurgeReport};
+use crate::services::NotificationRouterState;
+use crate::utils::{active_timezone, parse_range_end, parse_range_start, AppError, MessageKey};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tauri::State;
+use tauri::{State, WebviewWindow};
 use tokio::sync::Mutex;
 
 pub type SecurityServiceState = Arc<Mutex<SecurityService>>;
@@ -30,16 +42,57 @@ pub struct GetAuditLogsRequest {
     pub limit: usize,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct QueryAuditLogsRequest {
+    pub user_id: Option<String>,
+    pub action: Option<String>,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub page: i32,
+    pub page_size: i32,
+}
+
+/// 持久化的审计日志及操作的展示名称
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditLogEntry {
+    #[serde(flatten)]
+    pub log: StoredAuditLog,
+    #[serde(rename = "actionLabel")]
+    pub action_label: String,
+}
+
+impl From<StoredAuditLog> for AuditLogEntry {
+    fn from(log: StoredAuditLog) -> Self {
+        // 旧版本写入的未知操作名原样展示
+        let action_label = AuditAction::parse(&log.action)
+            .map(|action| action.label().to_string())
+            .unwrap_or_else(|| log.action.clone());
+        Self { log, action_label }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportAuditLogsRequest {
+    pub user_id: Option<String>,
+    pub action: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub format: AuditExportFormat,
+    pub output_path: String,
+}
+
 /// 加密敏感数据
 #[tauri::command]
 pub async fn encrypt_sensitive_data(
     data: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     let service = security_service.lock().await;
     service
         .encrypt_sensitive_data(&data)
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 /// 解密敏感数据
@@ -47,11 +100,13 @@ pub async fn encrypt_sensitive_data(
 pub async fn decrypt_sensitive_data(
     encrypted_data: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<String, String> {
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<String, AppError> {
+    require_permission(&permissions, Permission::DecryptSensitiveData).await?;
     let service = security_service.lock().await;
     service
         .decrypt_sensitive_data(&encrypted_data)
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 /// 记录操作日志
@@ -59,7 +114,9 @@ pub async fn decrypt_sensitive_data(
 pub async fn log_audit(
     request: LogAuditRequest,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<String, String> {
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<String, AppError> {
+    require_permission(&permissions, Permission::WriteAuditLogs).await?;
     let service = security_service.lock().await;
 
     let action = parse_audit_action(&request.action)?;
@@ -75,7 +132,7 @@ pub async fn log_audit(
             request.metadata,
         )
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 /// 获取操作日志
@@ -83,7 +140,12 @@ pub async fn log_audit(
 pub async fn get_audit_logs(
     request: GetAuditLogsRequest,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<Vec<AuditLog>, String> {
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<Vec<AuditLog>, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "get_audit_logs")?;
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
     let service = security_service.lock().await;
 
     let action = if let Some(ref action_str) = request.action {
@@ -93,13 +155,13 @@ pub async fn get_audit_logs(
     };
 
     let start_time = if let Some(ref time_str) = request.start_time {
-        Some(parse_datetime(time_str)?)
+        Some(parse_start_time(time_str)?)
     } else {
         None
     };
 
     let end_time = if let Some(ref time_str) = request.end_time {
-        Some(parse_datetime(time_str)?)
+        Some(parse_end_time(time_str)?)
     } else {
         None
     };
@@ -107,7 +169,116 @@ pub async fn get_audit_logs(
     service
         .get_audit_logs(request.user_id, action, start_time, end_time, request.limit)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
+}
+
+/// 分页查询已持久化的操作日志，可按用户、操作、资源和时间范围筛选
+#[tauri::command]
+pub async fn query_audit_logs(
+    request: QueryAuditLogsRequest,
+    webview_window: WebviewWindow,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<PageResult<AuditLogEntry>, AppError> {
+    require_rate_limit(&rate_limiter, &webview_window, "query_audit_logs")?;
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
+
+    let filter = query_filter(&request)?;
+    tracing::debug!("Querying audit logs (page {}, filter: {})", request.page, filter.summary());
+
+    AuditLogDao::new()
+        .query(&filter, request.page, request.page_size)
+        .map(|page| page.map(AuditLogEntry::from))
+        .map_err(|e| AppError::database_error(format!("查询操作日志失败: {}", e)))
+}
+
+/// 某个资源（如患者详情页中的患者）的全部操作记录，按时间倒序
+#[tauri::command]
+pub async fn get_resource_audit_logs(
+    resource_type: String,
+    resource_id: String,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<AuditLogEntry>, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
+
+    AuditLogDao::new()
+        .find_by_resource(&resource_type, &resource_id)
+        .map(|logs| logs.into_iter().map(AuditLogEntry::from).collect())
+        .map_err(|e| AppError::database_error(format!("查询操作日志失败: {}", e)))
+}
+
+/// 按条件导出操作日志（CSV / JSON），供合规审查
+#[tauri::command]
+pub async fn export_audit_logs(
+    request: ExportAuditLogsRequest,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<AuditExportResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
+
+    let filter = AuditLogFilter {
+        user_id: request.user_id,
+        action: match request.action {
+            Some(ref action_str) => Some(parse_audit_action(action_str)?.as_str().to_string()),
+            None => None,
+        },
+        start_time: match request.start_time {
+            Some(ref time_str) => Some(parse_start_time(time_str)?),
+            None => None,
+        },
+        end_time: match request.end_time {
+            Some(ref time_str) => Some(parse_end_time(time_str)?),
+            None => None,
+        },
+        ..Default::default()
+    };
+    tracing::info!("Exporting audit logs to {} ({:?}, filter: {})", request.output_path, request.format, filter.summary());
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    let result = AuditExportService::new().export(&filter, request.format, std::path::Path::new(&request.output_path));
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "export_audit_logs".to_string());
+    metadata.insert("filter".to_string(), filter.summary());
+    metadata.insert("path".to_string(), request.output_path.clone());
+    let (status, error_message) = match &result {
+        Ok(exported) => {
+            metadata.insert("totalRows".to_string(), exported.metadata.total_rows.to_string());
+            metadata.insert("sha256".to_string(), exported.metadata.sha256.clone());
+            ("success".to_string(), None)
+        }
+        Err(e) => ("failure".to_string(), Some(e.to_string())),
+    };
+
+    // 导出行为本身也写入审计日志
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::AccessSensitiveData,
+            Some("audit_log".to_string()),
+            None,
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for audit export: {}", e);
+    }
+
+    result.map_err(|e| {
+        tracing::error!("Audit log export failed: {}", e);
+        AppError::from(e)
+    })
 }
 
 /// 检测异常访问
@@ -115,12 +286,14 @@ pub async fn get_audit_logs(
 pub async fn detect_anomalies(
     user_id: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<Vec<AnomalyRecord>, String> {
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<Vec<AnomalyRecord>, AppError> {
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
     let service = security_service.lock().await;
     service
         .detect_anomalies(&user_id)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 /// 记录登录失败
@@ -128,7 +301,7 @@ pub async fn detect_anomalies(
 pub async fn record_failed_login(
     user_id: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let service = security_service.lock().await;
     service.record_failed_login(&user_id).await;
     Ok(())
@@ -139,20 +312,84 @@ pub async fn record_failed_login(
 pub async fn reset_failed_login(
     user_id: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let service = security_service.lock().await;
     service.reset_failed_login(&user_id).await;
     Ok(())
 }
 
-/// 检查是否需要自动锁屏
+/// 记录一次真实的用户输入（键盘、鼠标、触摸），刷新自动锁屏计时
+#[tauri::command]
+pub async fn record_user_interaction(
+    user_id: String,
+    security_service: State<'_, SecurityServiceState>,
+) -> Result<(), AppError> {
+    let service = security_service.lock().await;
+    service.record_user_interaction(&user_id).await;
+    Ok(())
+}
+
+/// 退出登录时清空内存中的患者数据和会话密钥
+#[tauri::command]
+pub async fn purge_all_session_data(
+    security_service: State<'_, SecurityServiceState>,
+    websocket_manager: State<'_, WebSocketManagerState>,
+    notification_router: State<'_, NotificationRouterState>,
+    window_state: State<'_, WindowManagerState>,
+) -> Result<PurgeReport, AppError> {
+    let websocket = websocket_manager.lock().await;
+    let security = security_service.lock().await;
+    let mut report = session_purge::purge_all_session_data(&websocket, &security, &notification_router).await;
+
+    let mut saved_windows = window_state.saved_windows.lock().unwrap();
+    report.saved_windows = saved_windows.len();
+    saved_windows.clear();
+    Ok(report)
+}
+
+/// 检查是否需要自动锁屏，并返回锁屏后能否用 PIN 解锁
 #[tauri::command]
 pub async fn should_auto_lock(
     user_id: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<bool, String> {
+) -> Result<AutoLockStatus, AppError> {
+    let service = security_service.lock().await;
+    Ok(service.auto_lock_status(&user_id).await)
+}
+
+/// 为当前医生设置6位数字的本地解锁 PIN
+#[tauri::command]
+pub async fn set_unlock_pin(
+    pin: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+) -> Result<(), AppError> {
+    let user_id = current_doctor_id(&token_refresh).await?;
     let service = security_service.lock().await;
-    Ok(service.should_auto_lock(&user_id).await)
+    service.set_unlock_pin(&user_id, &pin).await.map_err(AppError::from)
+}
+
+/// 锁屏后用 PIN 解锁，连续输错达到上限时返回 REAUTH_REQUIRED，需要重新登录
+#[tauri::command]
+pub async fn unlock_with_pin(
+    pin: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+) -> Result<(), AppError> {
+    let user_id = current_doctor_id(&token_refresh).await?;
+    let service = security_service.lock().await;
+    service.unlock_with_pin(&user_id, &pin).await.map_err(AppError::from)
+}
+
+/// 清除当前医生的解锁 PIN，修改密码后调用
+#[tauri::command]
+pub async fn clear_unlock_pin(
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+) -> Result<bool, AppError> {
+    let user_id = current_doctor_id(&token_refresh).await?;
+    let service = security_service.lock().await;
+    Ok(service.clear_unlock_pin(&user_id).await)
 }
 
 /// 获取最后活动时间
@@ -160,7 +397,7 @@ pub async fn should_auto_lock(
 pub async fn get_last_activity(
     user_id: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<Option<String>, String> {
+) -> Result<Option<String>, AppError> {
     let service = security_service.lock().await;
     Ok(service
         .get_last_activity(&user_id)
@@ -174,12 +411,14 @@ pub async fn get_anomaly_records(
     user_id: Option<String>,
     resolved: Option<bool>,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<Vec<AnomalyRecord>, String> {
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<Vec<AnomalyRecord>, AppError> {
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
     let service = security_service.lock().await;
     service
         .get_anomaly_records(user_id, resolved)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 /// 标记异常已解决
@@ -187,12 +426,14 @@ pub async fn get_anomaly_records(
 pub async fn resolve_anomaly(
     anomaly_id: String,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<(), String> {
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<(), AppError> {
+    require_permission(&permissions, Permission::ManageSecurity).await?;
     let service = security_service.lock().await;
     service
         .resolve_anomaly(&anomaly_id)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 /// 清理旧的日志和记录
@@ -200,33 +441,195 @@ pub async fn resolve_anomaly(
 pub async fn cleanup_old_security_records(
     days: i64,
     security_service: State<'_, SecurityServiceState>,
-) -> Result<(), String> {
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<(), AppError> {
+    require_permission(&permissions, Permission::ManageSecurity).await?;
     let service = security_service.lock().await;
     service
         .cleanup_old_records(days)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
+}
+
+/// 获取异常访问检测规则
+#[tauri::command]
+pub async fn get_security_config(
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<SecurityConfig, AppError> {
+    require_permission(&permissions, Permission::ViewAuditLogs).await?;
+    Ok(security_service.lock().await.security_config().clone())
+}
+
+/// 更新异常访问检测规则
+#[tauri::command]
+pub async fn update_security_config(
+    config: SecurityConfig,
+    security_service: State<'_, SecurityServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+) -> Result<SecurityConfig, AppError> {
+    require_permission(&permissions, Permission::ManageSecurity).await?;
+    tracing::info!("Updating security config: {:?}", config);
+
+    let mut service = security_service.lock().await;
+    service.update_security_config(config).map_err(AppError::from)?;
+    Ok(service.security_config().clone())
 }
 
 // 辅助函数
-fn parse_audit_action(action_str: &str) -> Result<AuditAction, String> {
-    match action_str.to_lowercase().as_str() {
-        "login" => Ok(AuditAction::Login),
-        "logout" => Ok(AuditAction::Logout),
-        "view_patient" => Ok(AuditAction::ViewPatient),
-        "update_patient" => Ok(AuditAction::UpdatePatient),
-        "send_message" => Ok(AuditAction::SendMessage),
-        "upload_file" => Ok(AuditAction::UploadFile),
-        "download_file" => Ok(AuditAction::DownloadFile),
-        "access_sensitive_data" => Ok(AuditAction::AccessSensitiveData),
-        "change_settings" => Ok(AuditAction::ChangeSettings),
-        "delete_data" => Ok(AuditAction::DeleteData),
-        _ => Err(format!("Unknown audit action: {}", action_str)),
+fn parse_audit_action(action_str: &str) -> Result<AuditAction, AppError> {
+    AuditAction::parse(&action_str.to_lowercase())
+        .ok_or_else(|| AppError::invalid_argument(format!("未知的操作类型: {}", action_str)))
+}
+
+fn query_filter(request: &QueryAuditLogsRequest) -> Result<AuditLogFilter, AppError> {
+    if request.page < 1 {
+        return Err(AppError::invalid_argument(MessageKey::PageMustBePositive));
+    }
+    if request.page_size < 1 || request.page_size > 100 {
+        return Err(AppError::invalid_argument(MessageKey::PageSizeOutOfRange));
+    }
+    // 资源 ID 只在同一资源类型内唯一
+    if request.resource_id.is_some() && request.resource_type.is_none() {
+        return Err(AppError::invalid_argument("按资源 ID 查询时必须指定资源类型"));
     }
+
+    Ok(AuditLogFilter {
+        user_id: request.user_id.clone(),
+        action: match request.action {
+            Some(ref action_str) => Some(parse_audit_action(action_str)?.as_str().to_string()),
+            None => None,
+        },
+        resource_type: request.resource_type.clone(),
+        resource_id: request.resource_id.clone(),
+        start_time: match request.start_time {
+            Some(ref time_str) => Some(parse_start_time(time_str)?),
+            None => None,
+        },
+        end_time: match request.end_time {
+            Some(ref time_str) => Some(parse_end_time(time_str)?),
+            None => None,
+        },
+        ..Default::default()
+    })
+}
+
+// 不带时区的筛选时间按配置的时区理解；只有日期时开始取当天零点，结束取当天最后时刻
+fn parse_start_time(value: &str) -> Result<DateTime<Utc>, AppError> {
+    parse_range_start(value, active_timezone())
+        .ok_or_else(|| AppError::invalid_argument(format!("时间格式无效: {}", value)))
 }
 
-fn parse_datetime(datetime_str: &str) -> Result<DateTime<Utc>, String> {
-    DateTime::parse_from_rfc3339(datetime_str)
-        .map(|dt| dt.with_timezone(&Utc))
-        .map_err(|e| format!("Invalid datetime format: {}", e))
+fn parse_end_time(value: &str) -> Result<DateTime<Utc>, AppError> {
+    parse_range_end(value, active_timezone())
+        .ok_or_else(|| AppError::invalid_argument(format!("时间格式无效: {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{PermissionService, PERMISSION_DENIED};
+    use chrono::TimeZone;
+    use tauri::Manager;
+
+    fn stored_log(action: &str) -> StoredAuditLog {
+        StoredAuditLog {
+            id: "a1".to_string(),
+            user_id: Some("u1".to_string()),
+            action: action.to_string(),
+            resource_type: Some("patient".to_string()),
+            resource_id: Some("p1".to_string()),
+            details: serde_json::json!({}),
+            ip_address: None,
+            user_agent: None,
+            created_at: Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_query_filter_maps_request() {
+        let request = QueryAuditLogsRequest {
+            user_id: Some("u1".to_string()),
+            action: Some("VIEW_PATIENT".to_string()),
+            resource_type: Some("patient".to_string()),
+            resource_id: Some("p1".to_string()),
+            start_time: Some("2024-03-01T00:00:00Z".to_string()),
+            end_time: Some("2024-03-01T23:59:59+08:00".to_string()),
+            page: 1,
+            page_size: 20,
+        };
+        let filter = query_filter(&request).unwrap();
+        assert_eq!(filter.action.as_deref(), Some("view_patient"));
+        assert_eq!(filter.resource_id.as_deref(), Some("p1"));
+        assert_eq!(filter.start_time, Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()));
+        assert_eq!(filter.end_time, Some(Utc.with_ymd_and_hms(2024, 3, 1, 15, 59, 59).unwrap()));
+        assert_eq!(filter.summary().split("; ").nth(2), Some("resource=patient/p1"));
+
+        let filter = query_filter(&QueryAuditLogsRequest { page: 1, page_size: 20, ..Default::default() }).unwrap();
+        assert_eq!(filter, AuditLogFilter::default());
+    }
+
+    #[test]
+    fn test_query_filter_rejects_invalid_request() {
+        let valid = || QueryAuditLogsRequest { page: 1, page_size: 20, ..Default::default() };
+
+        assert!(query_filter(&QueryAuditLogsRequest { page: 0, ..valid() }).is_err());
+        assert!(query_filter(&QueryAuditLogsRequest { page_size: 0, ..valid() }).is_err());
+        assert!(query_filter(&QueryAuditLogsRequest { page_size: 101, ..valid() }).is_err());
+        assert!(query_filter(&QueryAuditLogsRequest { action: Some("unknown".to_string()), ..valid() }).is_err());
+        assert!(query_filter(&QueryAuditLogsRequest { start_time: Some("yesterday".to_string()), ..valid() }).is_err());
+        // 只给资源 ID 无法确定是哪类资源
+        assert!(query_filter(&QueryAuditLogsRequest { resource_id: Some("p1".to_string()), ..valid() }).is_err());
+    }
+
+    #[test]
+    fn test_audit_page_serializes_with_action_labels() {
+        let page = PageResult::new(vec![stored_log("view_patient"), stored_log("legacy_action")], 41, 2, 20)
+            .map(AuditLogEntry::from);
+        let json = serde_json::to_value(&page).unwrap();
+
+        assert_eq!(json["total"], 41);
+        assert_eq!(json["page"], 2);
+        assert_eq!(json["pageSize"], 20);
+        assert_eq!(json["totalPages"], 3);
+        assert_eq!(json["items"][0]["action"], "view_patient");
+        assert_eq!(json["items"][0]["actionLabel"], "查看患者");
+        assert_eq!(json["items"][0]["resourceId"], "p1");
+        assert_eq!(json["items"][1]["actionLabel"], "legacy_action");
+    }
+
+    #[tokio::test]
+    async fn test_log_audit_requires_session_role() {
+        let app = tauri::test::mock_app();
+        let security: SecurityServiceState = Arc::new(Mutex::new(SecurityService::new(300)));
+        let mut permissions = PermissionService::new(security.clone());
+        permissions.start_session("user-1".to_string(), None);
+        app.manage(security.clone());
+        app.manage::<PermissionServiceState>(Arc::new(Mutex::new(permissions)));
+
+        let request = LogAuditRequest {
+            user_id: "doctor-1".to_string(),
+            action: AuditAction::ViewPatient.as_str().to_string(),
+            resource_type: Some("patient".to_string()),
+            resource_id: Some("p1".to_string()),
+            status: "success".to_string(),
+            error_message: None,
+            metadata: HashMap::new(),
+        };
+        let error = log_audit(request, app.state(), app.state()).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(PERMISSION_DENIED));
+
+        // 只留下拒绝记录，伪造的操作日志没有写入
+        let logs = security.lock().await.get_audit_logs(None, None, None, None, 10).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(matches!(logs[0].action, AuditAction::PermissionDenied));
+    }
+
+    #[test]
+    fn test_every_audit_action_has_label() {
+        for action in AuditAction::ALL {
+            assert_eq!(parse_audit_action(action.as_str()).unwrap().as_str(), action.as_str());
+            assert!(!action.label().is_empty());
+        }
+    }
 }
@@ -0,0 +1,238 @@
+// 应用配置相关命令
+
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::{require_permission, PermissionServiceState};
+use crate::commands::rate_limit::CommandRateLimiterState;
+use crate::commands::security::SecurityServiceState;
+use crate::commands::window::WindowManagerState;
+use crate::models::{AppConfig, ErrorType, Permission, ProfileExportResult, ProfileImportReport, ProfileMergeStrategy};
+use crate::services::security::AuditAction;
+use crate::services::{
+    merge_config_patch, touches_security_settings, AppSettingsService, ConfigChangedEvent, WorkstationProfileService,
+    CONFIG_CHANGED_EVENT, SECURITY_SETTING_KEYS,
+};
+use crate::utils::{parse_timezone, set_active_locale, set_active_timezone, AppError, Locale, MessageKey};
+use std::collections::HashMap;
+use std::path::Path;
+use tauri::{AppHandle, Emitter, State};
+
+#[tauri::command]
+pub async fn get_app_config(readiness: State<'_, DatabaseReadinessState>) -> Result<AppConfig, AppError> {
+    require_database(&readiness).await?;
+    AppSettingsService::new().load()
+}
+
+/// 局部更新应用配置，只需提交要修改的字段
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_app_config(
+    patch: serde_json::Value,
+    app: AppHandle,
+    window_state: State<'_, WindowManagerState>,
+    security_service: State<'_, SecurityServiceState>,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<AppConfig, AppError> {
+    require_database(&readiness).await?;
+    let user_id = token_refresh
+        .lock()
+        .await
+        .current_user_id()
+        .await
+        .ok_or_else(|| AppError::new(ErrorType::AuthError, "请先登录").with_code("NOT_LOGGED_IN"))?;
+
+    let service = AppSettingsService::new();
+    let (_, pending_keys) = merge_config_patch(&service.load()?, &patch)?;
+    let security_relevant = touches_security_settings(&pending_keys);
+    if security_relevant {
+        require_permission(&permissions, Permission::ManageSecurity).await?;
+    }
+
+    let result = service.update(&patch, Some(&user_id));
+
+    if security_relevant {
+        let mut metadata = HashMap::new();
+        metadata.insert("operation".to_string(), "update_app_config".to_string());
+        metadata.insert("changedKeys".to_string(), pending_keys.join(","));
+        let (status, error_message) = match &result {
+            Ok(_) => ("success".to_string(), None),
+            Err(e) => ("failure".to_string(), Some(e.message.clone())),
+        };
+        if let Err(e) = security_service
+            .lock()
+            .await
+            .log_audit(
+                user_id.clone(),
+                AuditAction::ChangeSettings,
+                Some("app_config".to_string()),
+                None,
+                status,
+                error_message,
+                metadata,
+            )
+            .await
+        {
+            tracing::error!("Failed to record audit log for config update: {}", e);
+        }
+    }
+
+    let (config, changed_keys) = result?;
+    if changed_keys.is_empty() {
+        return Ok(config);
+    }
+
+    apply_hot_reload(&config, &changed_keys, &window_state, &security_service, &rate_limiter).await;
+
+    let event = ConfigChangedEvent {
+        changed_keys,
+        config: config.clone(),
+    };
+    if let Err(e) = app.emit(CONFIG_CHANGED_EVENT, &event) {
+        tracing::warn!("Failed to emit {} event: {}", CONFIG_CHANGED_EVENT, e);
+    }
+    Ok(config)
+}
+
+/// 切换后端校验和错误提示的语言，保存到应用配置中
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn set_locale(
+    locale: String,
+    app: AppHandle,
+    window_state: State<'_, WindowManagerState>,
+    security_service: State<'_, SecurityServiceState>,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<AppConfig, AppError> {
+    let parsed = Locale::parse(&locale)
+        .ok_or_else(|| AppError::invalid_argument(MessageKey::LocaleUnsupported.text(&[&locale])))?;
+    let patch = serde_json::json!({ "locale": parsed.as_str() });
+    update_app_config(patch, app, window_state, security_service, rate_limiter, token_refresh, permissions, readiness).await
+}
+
+// 可热更新的配置立即生效（含命令调用限流规则）；数据保留策略和问诊超时时长在下次执行时从数据库读取，无需通知
+pub async fn apply_hot_reload(
+    config: &AppConfig,
+    changed_keys: &[String],
+    window_state: &WindowManagerState,
+    security_service: &SecurityServiceState,
+    rate_limiter: &CommandRateLimiterState,
+) {
+    for key in changed_keys {
+        match key.as_str() {
+            "windowLimits" => window_state.apply_limits_config(&config.window_limits),
+            "autoLockTimeout" => security_service.lock().await.set_auto_lock_timeout(config.auto_lock_timeout),
+            "locale" => set_active_locale(config.locale),
+            "commandRateLimits" => rate_limiter.lock().unwrap().apply_config(config),
+            // 已通过 validate_config 校验，解析失败时保持原时区
+            "timezone" => {
+                if let Some(tz) = parse_timezone(&config.timezone) {
+                    set_active_timezone(tz);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 导出工作站配置包（应用配置、数据保留策略和模板），用管理员提供的口令签名
+#[tauri::command]
+pub async fn export_workstation_profile(
+    path: String,
+    passphrase: String,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<ProfileExportResult, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    tracing::info!("Exporting workstation profile to: {}", path);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    WorkstationProfileService::new().export_profile(Path::new(&path), &passphrase, user_id.as_deref())
+}
+
+/// 导入工作站配置包；服务器地址等安全相关配置只在具备安全管理权限时导入，每次导入都写入审计日志
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn import_workstation_profile(
+    path: String,
+    passphrase: String,
+    merge_strategy: ProfileMergeStrategy,
+    app: AppHandle,
+    window_state: State<'_, WindowManagerState>,
+    security_service: State<'_, SecurityServiceState>,
+    rate_limiter: State<'_, CommandRateLimiterState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<ProfileImportReport, AppError> {
+    require_database(&readiness).await?;
+    require_permission(&permissions, Permission::ManageDatabase).await?;
+    let user_id = current_doctor_id(&token_refresh).await?;
+    tracing::info!("Importing workstation profile from: {} ({})", path, merge_strategy.as_str());
+
+    let allow_sensitive = permissions.lock().await.has_permission(Permission::ManageSecurity);
+    let result = WorkstationProfileService::new().import_profile(
+        Path::new(&path),
+        &passphrase,
+        merge_strategy,
+        allow_sensitive,
+        Some(&user_id),
+    );
+
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), "import_workstation_profile".to_string());
+    metadata.insert("strategy".to_string(), merge_strategy.as_str().to_string());
+    let (status, error_message) = match &result {
+        Ok(report) => {
+            let sensitive_keys: Vec<&str> = report
+                .changed_settings
+                .iter()
+                .map(String::as_str)
+                .filter(|key| SECURITY_SETTING_KEYS.contains(key))
+                .collect();
+            metadata.insert("changedKeys".to_string(), report.changed_settings.join(","));
+            metadata.insert("sensitiveKeys".to_string(), sensitive_keys.join(","));
+            ("success".to_string(), None)
+        }
+        Err(e) => ("failure".to_string(), Some(e.message.clone())),
+    };
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id,
+            AuditAction::ChangeSettings,
+            Some("workstation_profile".to_string()),
+            None,
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for workstation profile import: {}", e);
+    }
+
+    let report = result?;
+    if report.changed_settings.is_empty() {
+        return Ok(report);
+    }
+
+    let config = AppSettingsService::new().load()?;
+    apply_hot_reload(&config, &report.changed_settings, &window_state, &security_service, &rate_limiter).await;
+    let event = ConfigChangedEvent {
+        changed_keys: report.changed_settings.clone(),
+        config,
+    };
+    if let Err(e) = app.emit(CONFIG_CHANGED_EVENT, &event) {
+        tracing::warn!("Failed to emit {} event: {}", CONFIG_CHANGED_EVENT, e);
+    }
+    Ok(report)
+}
@@ -0,0 +1,104 @@
+// 回收站相关命令：删除和恢复都记录审计日志
+
+use crate::commands::auth::TokenRefreshServiceState;
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::{current_data_scope, PermissionServiceState};
+use crate::commands::security::SecurityServiceState;
+use crate::models::{AppError, MedicalRecord, Message, TrashEntityType, TrashItem};
+use crate::services::{AuditAction, TrashService};
+use std::collections::HashMap;
+use std::fmt::Display;
+use tauri::State;
+
+#[tauri::command]
+pub async fn list_trash(
+    entity_type: TrashEntityType,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<TrashItem>, AppError> {
+    require_database(&readiness).await?;
+    let scope = current_data_scope(&permissions).await?;
+
+    TrashService::new().list(entity_type, &scope)
+}
+
+#[tauri::command]
+pub async fn restore_message(
+    message_id: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Message, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Restoring message: {}", message_id);
+
+    let scope = current_data_scope(&permissions).await?;
+    let result = TrashService::new().restore_message(&message_id, &scope);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    audit_trash_change(&security_service, user_id, AuditAction::RestoreData, TrashEntityType::Message, &message_id, &result)
+        .await;
+    result
+}
+
+#[tauri::command]
+pub async fn restore_medical_record(
+    record_id: String,
+    security_service: State<'_, SecurityServiceState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<MedicalRecord, AppError> {
+    require_database(&readiness).await?;
+    tracing::info!("Restoring medical record: {}", record_id);
+
+    let scope = current_data_scope(&permissions).await?;
+    let result = TrashService::new().restore_medical_record(&record_id, &scope);
+
+    let user_id = token_refresh.lock().await.current_user_id().await;
+    audit_trash_change(
+        &security_service,
+        user_id,
+        AuditAction::RestoreData,
+        TrashEntityType::MedicalRecord,
+        &record_id,
+        &result,
+    )
+    .await;
+    result
+}
+
+// 移入回收站或从回收站恢复的审计记录，失败的操作同样记录
+pub(crate) async fn audit_trash_change<T, E: Display>(
+    security_service: &SecurityServiceState,
+    user_id: Option<String>,
+    action: AuditAction,
+    entity_type: TrashEntityType,
+    id: &str,
+    result: &Result<T, E>,
+) {
+    let mut metadata = HashMap::new();
+    metadata.insert("operation".to_string(), format!("{}_{}", action.as_str(), entity_type.as_str()));
+    let (status, error_message) = match result {
+        Ok(_) => ("success".to_string(), None),
+        Err(e) => ("failure".to_string(), Some(e.to_string())),
+    };
+
+    if let Err(e) = security_service
+        .lock()
+        .await
+        .log_audit(
+            user_id.unwrap_or_else(|| "unknown".to_string()),
+            action,
+            Some(entity_type.as_str().to_string()),
+            Some(id.to_string()),
+            status,
+            error_message,
+            metadata,
+        )
+        .await
+    {
+        tracing::error!("Failed to record audit log for {} {}: {}", entity_type.as_str(), id, e);
+    }
+}
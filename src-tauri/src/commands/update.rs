@@ -0,0 +1,56 @@
+// 应用更新相关命令
+
+use crate::services::{effective_config, update_check_result, UpdateCheckResult, UpdateService, UPDATE_DOWNLOAD_PROGRESS_EVENT};
+use crate::utils::{AppError, AppResult};
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter, Manager};
+
+const UPDATE_DIR_NAME: &str = "updates";
+
+/// 检查内网更新服务器上是否有新版本
+#[tauri::command]
+pub async fn check_for_update(app: AppHandle) -> AppResult<UpdateCheckResult> {
+    let current = app.package_info().version.clone();
+    tracing::info!("Checking for update, current version {}", current);
+
+    let service = UpdateService::new(effective_config().update_manifest_url)?;
+    service.check(&current).await.map_err(|e| {
+        let error = AppError::from(e);
+        tracing::warn!("Update check failed: {}", error.message);
+        error
+    })
+}
+
+/// 用户确认后下载安装包，校验通过后返回本地路径
+#[tauri::command]
+pub async fn download_update(app: AppHandle) -> AppResult<String> {
+    // 重新获取并验签清单，不使用前端传入的下载地址
+    let service = UpdateService::new(effective_config().update_manifest_url)?;
+    let manifest = service.fetch_manifest().await?;
+    if !update_check_result(&app.package_info().version, &manifest).available {
+        return Err(AppError::invalid_argument(format!("当前已是最新版本 {}", app.package_info().version)));
+    }
+
+    let dir = update_dir(&app).ok_or_else(|| AppError::file_error("无法获取缓存目录"))?;
+    let path = service
+        .download(&manifest, &dir, |progress| {
+            if let Err(e) = app.emit(UPDATE_DOWNLOAD_PROGRESS_EVENT, &progress) {
+                tracing::warn!("Failed to emit update progress: {}", e);
+            }
+        })
+        .await
+        .map_err(|e| {
+            let error = AppError::from(e);
+            tracing::error!("Update download failed: {}", error.message);
+            error
+        })?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn update_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_cache_dir()
+        .ok()
+        .map(|dir| dir.join(UPDATE_DIR_NAME))
+}
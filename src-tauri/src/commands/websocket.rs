@@ -1,21 +1,44 @@
 // WebSocket 相关命令
 
-use crate::services::{WebSocketManager, QueuedMessage, ConnectionStatus};
+use crate::commands::consultation::ensure_consultation_in_scope;
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::permission::PermissionServiceState;
+use crate::database::dao::{BaseDao, ConsultationDao, MessageDao};
+use crate::services::{
+    load_tls_config, save_tls_config, BufferedEvent, ConnectionStatus, ConsultationEventDispatcher, QueuedMessage,
+    SharedConnectionInfo, TlsConfig, WebSocketEvent, WebSocketManager, WebSocketOptions, DEFAULT_COMPRESSION_THRESHOLD,
+    WEBSOCKET_TLS_FILE,
+};
 use crate::models::MessageType;
+use crate::utils::AppError;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tauri::{AppHandle, State, Emitter};
+use tauri::{AppHandle, Emitter, Manager, State};
 use tokio::sync::Mutex;
 
 // WebSocket 管理器状态
 pub type WebSocketManagerState = Arc<Mutex<WebSocketManager>>;
 
+// 推送事件按问诊有序处理的分发器
+pub type EventDispatcherState = Arc<ConsultationEventDispatcher>;
+
 // 连接请求
 #[derive(Debug, Deserialize)]
 pub struct ConnectRequest {
     pub url: String,
     pub auth_token: Option<String>,
+    // 为空时使用上次保存的配置
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    // 超过该字节数的消息压缩发送，默认 64KB
+    #[serde(default)]
+    pub compression_threshold: Option<usize>,
+    // 为 true 时与同一 URL + token 的其他窗口共用一条连接
+    #[serde(default)]
+    pub shared: bool,
 }
 
 // 发送消息请求
@@ -56,6 +79,23 @@ pub struct TypingStatusRequest {
 pub struct ConnectionStatusResponse {
     pub status: String,
     pub error_message: Option<String>,
+    pub shared: bool,
+    // 共享连接的使用者数，独占连接为 1
+    pub subscriber_count: usize,
+    pub subscribed_consultations: Vec<String>,
+}
+
+impl ConnectionStatusResponse {
+    fn with_shared_info(mut self, info: Option<SharedConnectionInfo>) -> Self {
+        if let Some(info) = info {
+            let mut consultations: Vec<String> = info.subscriptions.into_keys().collect();
+            consultations.sort();
+            self.shared = true;
+            self.subscriber_count = info.users;
+            self.subscribed_consultations = consultations;
+        }
+        self
+    }
 }
 
 impl From<ConnectionStatus> for ConnectionStatusResponse {
@@ -64,59 +104,106 @@ impl From<ConnectionStatus> for ConnectionStatusResponse {
             ConnectionStatus::Disconnected => Self {
                 status: "disconnected".to_string(),
                 error_message: None,
+                shared: false,
+                subscriber_count: 1,
+                subscribed_consultations: Vec::new(),
             },
             ConnectionStatus::Connecting => Self {
                 status: "connecting".to_string(),
                 error_message: None,
+                shared: false,
+                subscriber_count: 1,
+                subscribed_consultations: Vec::new(),
             },
             ConnectionStatus::Connected => Self {
                 status: "connected".to_string(),
                 error_message: None,
+                shared: false,
+                subscriber_count: 1,
+                subscribed_consultations: Vec::new(),
             },
             ConnectionStatus::Reconnecting => Self {
                 status: "reconnecting".to_string(),
                 error_message: None,
+                shared: false,
+                subscriber_count: 1,
+                subscribed_consultations: Vec::new(),
             },
             ConnectionStatus::Error(msg) => Self {
                 status: "error".to_string(),
                 error_message: Some(msg),
+                shared: false,
+                subscriber_count: 1,
+                subscribed_consultations: Vec::new(),
             },
         }
     }
 }
 
+pub fn websocket_tls_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(WEBSOCKET_TLS_FILE))
+}
+
 // 创建 WebSocket 连接
 #[tauri::command]
 pub async fn create_websocket_connection(
     request: ConnectRequest,
     ws_manager: State<'_, WebSocketManagerState>,
     app: AppHandle,
-) -> Result<String, String> {
-    println!("Creating WebSocket connection to: {}", request.url);
+) -> Result<String, AppError> {
+    tracing::info!("Creating WebSocket connection to: {}", request.url);
+
+    // 新的 TLS 配置保存下来，之后的连接和重连沿用
+    let tls_path = websocket_tls_path(&app);
+    let tls_config = match request.tls {
+        Some(tls_config) => {
+            if let Some(path) = &tls_path {
+                save_tls_config(path, &tls_config).map_err(AppError::file_error)?;
+            }
+            Some(tls_config)
+        }
+        None => tls_path.as_deref().and_then(load_tls_config),
+    };
 
     let manager = ws_manager.lock().await;
 
-    match manager.create_connection(request.url, request.auth_token).await {
+    let options = WebSocketOptions {
+        tls: tls_config,
+        compression_threshold: request.compression_threshold.unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
+    };
+
+    let result = if request.shared {
+        manager
+            .get_or_create_shared_connection(request.url, request.auth_token, options)
+            .await
+    } else {
+        manager.create_connection(request.url, request.auth_token, options).await
+    };
+
+    match result {
         Ok(connection_id) => {
-            println!("WebSocket connection created: {}", connection_id);
+            tracing::info!("WebSocket connection created: {}", connection_id);
 
             // 发送连接成功事件到前端
             if let Err(e) = app.emit("websocket-connected", &connection_id) {
-                println!("Failed to emit websocket-connected event: {}", e);
+                tracing::warn!("Failed to emit websocket-connected event: {}", e);
             }
 
             Ok(connection_id)
         }
         Err(e) => {
-            let error_msg = format!("Failed to create WebSocket connection: {}", e);
-            println!("{}", error_msg);
+            let error = AppError::from(e).context("创建 WebSocket 连接失败");
+            tracing::warn!("{}", error);
 
             // 发送连接失败事件到前端
-            if let Err(e) = app.emit("websocket-connection-failed", &error_msg) {
-                println!("Failed to emit websocket-connection-failed event: {}", e);
+            if let Err(e) = app.emit("websocket-connection-failed", &error.message) {
+                tracing::warn!("Failed to emit websocket-connection-failed event: {}", e);
             }
 
-            Err(error_msg)
+            Err(error)
         }
     }
 }
@@ -127,26 +214,30 @@ pub async fn close_websocket_connection(
     connection_id: String,
     ws_manager: State<'_, WebSocketManagerState>,
     app: AppHandle,
-) -> Result<(), String> {
-    println!("Closing WebSocket connection: {}", connection_id);
+) -> Result<(), AppError> {
+    tracing::info!("Closing WebSocket connection: {}", connection_id);
 
     let manager = ws_manager.lock().await;
 
     match manager.close_connection(&connection_id).await {
         Ok(_) => {
-            println!("WebSocket connection closed: {}", connection_id);
+            // 共享连接仍有其他使用者时不通知前端断开
+            if manager.shared_connection_info(&connection_id).await.is_some() {
+                return Ok(());
+            }
+            tracing::info!("WebSocket connection closed: {}", connection_id);
 
             // 发送连接关闭事件到前端
             if let Err(e) = app.emit("websocket-disconnected", &connection_id) {
-                println!("Failed to emit websocket-disconnected event: {}", e);
+                tracing::warn!("Failed to emit websocket-disconnected event: {}", e);
             }
 
             Ok(())
         }
         Err(e) => {
-            let error_msg = format!("Failed to close WebSocket connection: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+            let error = AppError::from(e).context("关闭 WebSocket 连接失败");
+            tracing::warn!("{}", error);
+            Err(error)
         }
     }
 }
@@ -156,17 +247,20 @@ pub async fn close_websocket_connection(
 pub async fn get_websocket_connection_status(
     connection_id: String,
     ws_manager: State<'_, WebSocketManagerState>,
-) -> Result<ConnectionStatusResponse, String> {
-    println!("Getting WebSocket connection status: {}", connection_id);
+) -> Result<ConnectionStatusResponse, AppError> {
+    tracing::debug!("Getting WebSocket connection status: {}", connection_id);
 
     let manager = ws_manager.lock().await;
 
     match manager.get_connection_status(&connection_id).await {
-        Ok(status) => Ok(status.into()),
+        Ok(status) => {
+            let shared = manager.shared_connection_info(&connection_id).await;
+            Ok(ConnectionStatusResponse::from(status).with_shared_info(shared))
+        }
         Err(e) => {
-            let error_msg = format!("Failed to get connection status: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+            let error = AppError::from(e).context("获取连接状态失败");
+            tracing::warn!("{}", error);
+            Err(error)
         }
     }
 }
@@ -175,16 +269,17 @@ pub async fn get_websocket_connection_status(
 #[tauri::command]
 pub async fn get_all_websocket_connections_status(
     ws_manager: State<'_, WebSocketManagerState>,
-) -> Result<HashMap<String, ConnectionStatusResponse>, String> {
-    println!("Getting all WebSocket connections status");
+) -> Result<HashMap<String, ConnectionStatusResponse>, AppError> {
+    tracing::debug!("Getting all WebSocket connections status");
 
     let manager = ws_manager.lock().await;
     let status_map = manager.get_all_connection_status().await;
 
-    let response_map: HashMap<String, ConnectionStatusResponse> = status_map
-        .into_iter()
-        .map(|(id, status)| (id, status.into()))
-        .collect();
+    let mut response_map = HashMap::with_capacity(status_map.len());
+    for (id, status) in status_map {
+        let shared = manager.shared_connection_info(&id).await;
+        response_map.insert(id, ConnectionStatusResponse::from(status).with_shared_info(shared));
+    }
 
     Ok(response_map)
 }
@@ -195,8 +290,8 @@ pub async fn send_websocket_message(
     request: SendWebSocketMessageRequest,
     ws_manager: State<'_, WebSocketManagerState>,
     app: AppHandle,
-) -> Result<(), String> {
-    println!("Sending WebSocket message: {:?}", request);
+) -> Result<(), AppError> {
+    tracing::debug!("Sending WebSocket message: {:?}", request);
 
     // 解析消息类型
     let message_type = match request.message_type.as_str() {
@@ -204,7 +299,7 @@ pub async fn send_websocket_message(
         "image" => MessageType::Image,
         "voice" => MessageType::Voice,
         "file" => MessageType::File,
-        _ => return Err("Invalid message type".to_string()),
+        _ => return Err(AppError::invalid_argument(format!("无效的消息类型: {}", request.message_type))),
     };
 
     // 创建队列消息
@@ -216,31 +311,32 @@ pub async fn send_websocket_message(
         file_path: request.file_path,
         retry_count: 0,
         created_at: chrono::Utc::now(),
+        idempotency_key: None,
     };
 
     let manager = ws_manager.lock().await;
 
     match manager.send_message(&request.connection_id, queued_message.clone()).await {
         Ok(_) => {
-            println!("WebSocket message sent successfully");
+            tracing::debug!("WebSocket message sent successfully");
 
             // 发送消息发送成功事件到前端
             if let Err(e) = app.emit("websocket-message-sent", &queued_message.id) {
-                println!("Failed to emit websocket-message-sent event: {}", e);
+                tracing::warn!("Failed to emit websocket-message-sent event: {}", e);
             }
 
             Ok(())
         }
         Err(e) => {
-            let error_msg = format!("Failed to send WebSocket message: {}", e);
-            println!("{}", error_msg);
+            let error = AppError::from(e).context("发送 WebSocket 消息失败");
+            tracing::warn!("{}", error);
 
             // 发送消息发送失败事件到前端
             if let Err(e) = app.emit("websocket-message-failed", &queued_message.id) {
-                println!("Failed to emit websocket-message-failed event: {}", e);
+                tracing::warn!("Failed to emit websocket-message-failed event: {}", e);
             }
 
-            Err(error_msg)
+            Err(error)
         }
     }
 }
@@ -250,20 +346,20 @@ pub async fn send_websocket_message(
 pub async fn subscribe_to_consultation(
     request: SubscriptionRequest,
     ws_manager: State<'_, WebSocketManagerState>,
-) -> Result<(), String> {
-    println!("Subscribing to consultation: {:?}", request);
+) -> Result<(), AppError> {
+    tracing::debug!("Subscribing to consultation: {:?}", request);
 
     let manager = ws_manager.lock().await;
 
     match manager.subscribe_to_consultation(&request.connection_id, request.consultation_id.clone()).await {
         Ok(_) => {
-            println!("Successfully subscribed to consultation: {}", request.consultation_id);
+            tracing::info!("Successfully subscribed to consultation: {}", request.consultation_id);
             Ok(())
         }
         Err(e) => {
-            let error_msg = format!("Failed to subscribe to consultation: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+            let error = AppError::from(e).context("订阅问诊消息失败");
+            tracing::warn!("{}", error);
+            Err(error)
         }
     }
 }
@@ -273,20 +369,20 @@ pub async fn subscribe_to_consultation(
 pub async fn unsubscribe_from_consultation(
     request: SubscriptionRequest,
     ws_manager: State<'_, WebSocketManagerState>,
-) -> Result<(), String> {
-    println!("Unsubscribing from consultation: {:?}", request);
+) -> Result<(), AppError> {
+    tracing::debug!("Unsubscribing from consultation: {:?}", request);
 
     let manager = ws_manager.lock().await;
 
     match manager.unsubscribe_from_consultation(&request.connection_id, request.consultation_id.clone()).await {
         Ok(_) => {
-            println!("Successfully unsubscribed from consultation: {}", request.consultation_id);
+            tracing::info!("Successfully unsubscribed from consultation: {}", request.consultation_id);
             Ok(())
         }
         Err(e) => {
-            let error_msg = format!("Failed to unsubscribe from consultation: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+            let error = AppError::from(e).context("取消订阅问诊消息失败");
+            tracing::warn!("{}", error);
+            Err(error)
         }
     }
 }
@@ -296,20 +392,20 @@ pub async fn unsubscribe_from_consultation(
 pub async fn send_read_receipt(
     request: ReadReceiptRequest,
     ws_manager: State<'_, WebSocketManagerState>,
-) -> Result<(), String> {
-    println!("Sending read receipt: {:?}", request);
+) -> Result<(), AppError> {
+    tracing::debug!("Sending read receipt: {:?}", request);
 
     let manager = ws_manager.lock().await;
 
     match manager.send_read_receipt(&request.connection_id, request.consultation_id, request.message_id).await {
         Ok(_) => {
-            println!("Read receipt sent successfully");
+            tracing::debug!("Read receipt sent successfully");
             Ok(())
         }
         Err(e) => {
-            let error_msg = format!("Failed to send read receipt: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+            let error = AppError::from(e).context("发送已读回执失败");
+            tracing::warn!("{}", error);
+            Err(error)
         }
     }
 }
@@ -319,20 +415,146 @@ pub async fn send_read_receipt(
 pub async fn send_typing_status(
     request: TypingStatusRequest,
     ws_manager: State<'_, WebSocketManagerState>,
-) -> Result<(), String> {
-    println!("Sending typing status: {:?}", request);
+) -> Result<(), AppError> {
+    tracing::debug!("Sending typing status: {:?}", request);
 
     let manager = ws_manager.lock().await;
 
     match manager.send_typing_status(&request.connection_id, request.consultation_id, request.is_typing).await {
         Ok(_) => {
-            println!("Typing status sent successfully");
+            tracing::debug!("Typing status sent successfully");
             Ok(())
         }
         Err(e) => {
-            let error_msg = format!("Failed to send typing status: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+            let error = AppError::from(e).context("发送输入状态失败");
+            tracing::warn!("{}", error);
+            Err(error)
         }
     }
-}
\ No newline at end of file
+}
+
+// 问诊窗口挂载时调用，补齐 since_timestamp 之后缓冲的事件，之后再依赖实时推送
+#[tauri::command]
+pub async fn get_recent_ws_events(
+    consultation_id: String,
+    since_timestamp: Option<DateTime<Utc>>,
+    ws_manager: State<'_, WebSocketManagerState>,
+    permissions: State<'_, PermissionServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Vec<BufferedEvent>, AppError> {
+    require_database(&readiness).await?;
+    if let Some(consultation) = ConsultationDao::new().find_by_id(&consultation_id).map_err(AppError::from)? {
+        ensure_consultation_in_scope(&permissions, &consultation).await?;
+    }
+
+    let events = ws_manager.lock().await.recent_events(&consultation_id, since_timestamp).await;
+    Ok(dedup_replayed_events(events, &MessageDao::new()))
+}
+
+// 窗口已经从数据库加载过历史消息，回放时去掉已落库的消息和重复推送的同一条消息
+pub(crate) fn dedup_replayed_events(events: Vec<BufferedEvent>, message_dao: &MessageDao) -> Vec<BufferedEvent> {
+    let mut seen = HashSet::new();
+
+    events
+        .into_iter()
+        .filter(|buffered| match &buffered.event {
+            WebSocketEvent::Message { message, .. } => {
+                seen.insert(message.id.clone()) && !matches!(message_dao.find_by_id(&message.id), Ok(Some(_)))
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Message, ReadStatus, SenderType, SyncStatus};
+    use crate::services::EventReplayBuffer;
+    use chrono::Duration;
+    use rusqlite::Connection;
+
+    fn message(id: &str) -> Message {
+        Message {
+            id: id.to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type: SenderType::Patient,
+            message_type: MessageType::Text,
+            content: Some("医生您好".to_string()),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now(),
+            sync_status: SyncStatus::Synced,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        }
+    }
+
+    fn message_event(id: &str) -> WebSocketEvent {
+        WebSocketEvent::Message {
+            consultation_id: "c1".to_string(),
+            message: message(id),
+            idempotency_key: None,
+            seq: None,
+        }
+    }
+
+    fn event_label(buffered: &BufferedEvent) -> String {
+        match &buffered.event {
+            WebSocketEvent::Message { message, .. } => message.id.clone(),
+            WebSocketEvent::ConsultationUpdate { status, .. } => status.clone(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_window_catch_up_orders_and_dedups() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');"
+        )
+        .unwrap();
+        MessageDao::upsert_in(&conn, &message("m-stored")).unwrap();
+        let message_dao = MessageDao::with_connection(std::sync::Arc::new(std::sync::Mutex::new(conn)));
+
+        let start = Utc::now();
+        let mut buffer = EventReplayBuffer::new();
+        let burst = [
+            message_event("m-old"),
+            message_event("m-stored"),
+            message_event("m-new"),
+            WebSocketEvent::ConsultationUpdate {
+                consultation_id: "c1".to_string(),
+                status: "active".to_string(),
+                priority: None,
+            },
+            message_event("m-new"),
+            WebSocketEvent::ConsultationUpdate {
+                consultation_id: "c2".to_string(),
+                status: "pending".to_string(),
+                priority: None,
+            },
+        ];
+        for (i, event) in burst.into_iter().enumerate() {
+            buffer.push(event, start + Duration::milliseconds(i as i64 * 10));
+        }
+
+        // 窗口在第一条事件之后才打开
+        let events = buffer.since("c1", Some(start), start + Duration::seconds(1));
+        let labels: Vec<String> = dedup_replayed_events(events, &message_dao).iter().map(event_label).collect();
+        assert_eq!(labels, vec!["m-new", "active"]);
+
+        // 不传起点时返回全部未落库的事件
+        let events = buffer.since("c1", None, start + Duration::seconds(1));
+        let labels: Vec<String> = dedup_replayed_events(events, &message_dao).iter().map(event_label).collect();
+        assert_eq!(labels, vec!["m-old", "m-new", "active"]);
+    }
+}
@@ -1,15 +1,282 @@
 // 窗口管理相关命令
 
+use crate::commands::auth::{current_doctor_id, TokenRefreshServiceState};
+use crate::commands::database::{require_database, DatabaseReadinessState};
+use crate::commands::rate_limit::CommandRateLimiterState;
+use crate::commands::security::SecurityServiceState;
+use crate::commands::websocket::WebSocketManagerState;
+use crate::database::dao::{BaseDao, ConsultationDao, UserSettingsDao};
+use crate::models::{AppError, ConsultationStatus, ErrorType, WindowLimitsConfig};
+use crate::services::{classify_pressure, purge_consultation_context, AuditAction, MemoryPressure, ResourceMonitor};
+use crate::utils::{MessageKey, CODE_WINDOW_TYPE_UNKNOWN};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Mutex;
-use tauri::{Manager, State, WebviewUrl, WebviewWindowBuilder};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+use tauri::{Emitter, Manager, State, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+use uuid::Uuid;
+
+const WINDOW_STATE_FILE: &str = "window_state.json";
+pub const CONSULTATION_READONLY_EVENT: &str = "consultation-readonly";
+// 窗口至少有这么宽的标题栏留在某个显示器上才视为可见
+const MIN_VISIBLE_WIDTH: f64 = 100.0;
+const TITLE_BAR_HEIGHT: f64 = 30.0;
+pub const WINDOW_LAYOUT_PRESET_KEY: &str = "window_layout_preset";
+// focus-left 布局中左侧主窗口所占宽度
+const FOCUS_LEFT_RATIO: f64 = 0.6;
+// 新建窗口时标签已被占用，换新 ID 重试的次数上限
+const MAX_WINDOW_ID_ATTEMPTS: usize = 3;
 
 // 全局窗口状态管理
 #[derive(Debug, Default)]
 pub struct WindowManagerState {
-    pub windows: Mutex<HashMap<String, WindowInfo>>,
-    pub limits: WindowLimits,
+    // 只能通过 with_windows / with_windows_mut 访问，闭包内不能 await，锁不会跨 await 持有
+    windows: RwLock<WindowRegistry>,
+    // 可通过应用配置热更新
+    pub limits: Mutex<WindowLimits>,
+    // 启动时读取的上次窗口布局，恢复后清空
+    pub saved_windows: Mutex<Vec<PersistedWindow>>,
+    // 应用退出过程中窗口逐个销毁，此时不再覆盖保存的布局
+    pub exiting: AtomicBool,
+    pub resource_monitor: Mutex<ResourceMonitor>,
+}
+
+thread_local! {
+    // 当前线程是否正在执行 with_windows / with_windows_mut 的闭包
+    static IN_WINDOW_REGISTRY: Cell<bool> = const { Cell::new(false) };
+}
+
+// 闭包返回或 panic 时清除标记
+struct RegistryAccess;
+
+impl RegistryAccess {
+    fn enter() -> Self {
+        // 闭包内再次访问（如调用会同步触发窗口事件的 Tauri 接口）会在同一线程上死锁，提前报错
+        assert!(
+            !IN_WINDOW_REGISTRY.with(|flag| flag.replace(true)),
+            "re-entrant access to window registry"
+        );
+        RegistryAccess
+    }
+}
+
+impl Drop for RegistryAccess {
+    fn drop(&mut self) {
+        IN_WINDOW_REGISTRY.with(|flag| flag.set(false));
+    }
+}
+
+impl WindowManagerState {
+    pub fn mark_exiting(&self) {
+        self.exiting.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_exiting(&self) -> bool {
+        self.exiting.load(Ordering::SeqCst)
+    }
+
+    pub fn current_limits(&self) -> WindowLimits {
+        self.limits.lock().unwrap().clone()
+    }
+
+    // 只影响之后新建的窗口，已打开的窗口不会被关闭
+    pub fn apply_limits_config(&self, config: &WindowLimitsConfig) {
+        let mut limits = self.limits.lock().unwrap();
+        limits.max_windows = config.max_windows as usize;
+        limits.max_consultation_windows = config.max_consultation_windows as usize;
+    }
+
+    // 闭包内只读写窗口记录，不要调用 Tauri 窗口接口或其他可能回到窗口状态的代码
+    pub fn with_windows<R>(&self, f: impl FnOnce(&WindowRegistry) -> R) -> R {
+        let _access = RegistryAccess::enter();
+        f(&self.windows.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn with_windows_mut<R>(&self, f: impl FnOnce(&mut WindowRegistry) -> R) -> R {
+        let _access = RegistryAccess::enter();
+        f(&mut self.windows.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn insert_window(&self, info: WindowInfo) {
+        self.with_windows_mut(|windows| windows.insert(info));
+    }
+
+    pub fn remove_window(&self, window_id: &str) -> Option<WindowInfo> {
+        self.with_windows_mut(|windows| windows.remove(window_id))
+    }
+
+    pub fn consultation_window_id(&self, consultation_id: &str) -> Option<String> {
+        self.with_windows(|windows| windows.consultation_window_id(consultation_id).map(str::to_string))
+    }
+
+    // 按限制预留一个窗口名额，预留期间同样计入限制，避免并发新建时超出上限
+    pub fn reserve_slot(&self, window_type: &str) -> Result<WindowSlot<'_>, String> {
+        let limits = self.current_limits();
+        self.with_windows_mut(|windows| {
+            check_limits(windows, &limits, window_type)?;
+            windows.reserved.push(window_type.to_string());
+            Ok::<(), String>(())
+        })?;
+        Ok(WindowSlot {
+            state: self,
+            window_type: window_type.to_string(),
+            filled: false,
+        })
+    }
+
+    // 查找问诊对应的窗口；记录存在但实际窗口已销毁时移除该记录
+    pub fn find_live_consultation_window(&self, consultation_id: &str, is_alive: impl Fn(&str) -> bool) -> Option<String> {
+        let window_id = self.consultation_window_id(consultation_id)?;
+
+        if is_alive(&window_id) {
+            Some(window_id)
+        } else {
+            self.remove_window(&window_id);
+            None
+        }
+    }
+
+    // 移除已不存在的窗口记录，返回被移除的窗口 ID
+    pub fn prune_orphan_windows(&self, live_labels: &HashSet<String>) -> Vec<String> {
+        self.with_windows_mut(|windows| {
+            let mut orphans: Vec<String> =
+                windows.ids().filter(|id| !live_labels.contains(*id)).map(str::to_string).collect();
+            orphans.sort();
+
+            for id in &orphans {
+                windows.remove(id);
+            }
+            orphans
+        })
+    }
+}
+
+// 窗口记录及问诊 ID → 窗口 ID 的索引，两者在同一把锁内修改，始终一致
+#[derive(Debug, Default)]
+pub struct WindowRegistry {
+    windows: HashMap<String, WindowInfo>,
+    // 打开问诊窗口时据此聚焦已有窗口
+    consultation_windows: HashMap<String, String>,
+    // 已通过限制检查、尚未创建完成的窗口类型
+    reserved: Vec<String>,
+}
+
+impl WindowRegistry {
+    pub fn get(&self, window_id: &str) -> Option<&WindowInfo> {
+        self.windows.get(window_id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &WindowInfo> {
+        self.windows.values()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.windows.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn consultation_window_id(&self, consultation_id: &str) -> Option<&str> {
+        self.consultation_windows.get(consultation_id).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, info: WindowInfo) {
+        if let Some(previous) = self.windows.remove(&info.id) {
+            unindex_consultation(&mut self.consultation_windows, &previous);
+        }
+        index_consultation(&mut self.consultation_windows, &info);
+        self.windows.insert(info.id.clone(), info);
+    }
+
+    pub fn remove(&mut self, window_id: &str) -> Option<WindowInfo> {
+        let removed = self.windows.remove(window_id)?;
+        unindex_consultation(&mut self.consultation_windows, &removed);
+        Some(removed)
+    }
+
+    // 修改窗口记录并同步问诊索引，窗口不存在时返回 None
+    pub fn update(&mut self, window_id: &str, f: impl FnOnce(&mut WindowInfo)) -> Option<&WindowInfo> {
+        let info = self.windows.get_mut(window_id)?;
+        unindex_consultation(&mut self.consultation_windows, info);
+        f(info);
+        index_consultation(&mut self.consultation_windows, info);
+        Some(info)
+    }
+
+    fn count_of_type(&self, window_type: &str) -> usize {
+        self.windows.values().filter(|w| w.window_type == window_type).count()
+            + self.reserved.iter().filter(|t| *t == window_type).count()
+    }
+
+    fn release(&mut self, window_type: &str) {
+        if let Some(position) = self.reserved.iter().position(|t| t == window_type) {
+            self.reserved.swap_remove(position);
+        }
+    }
+}
+
+// reserve_slot 预留的名额；未调用 fill 就被丢弃时（如窗口创建失败）释放名额
+pub struct WindowSlot<'a> {
+    state: &'a WindowManagerState,
+    window_type: String,
+    filled: bool,
+}
+
+impl WindowSlot<'_> {
+    // 登记创建完成的窗口，并在同一次加锁中释放名额
+    pub fn fill(mut self, info: WindowInfo) {
+        self.filled = true;
+        self.state.with_windows_mut(|windows| {
+            windows.release(&self.window_type);
+            windows.insert(info);
+        });
+    }
+}
+
+impl Drop for WindowSlot<'_> {
+    fn drop(&mut self) {
+        if !self.filled {
+            self.state.with_windows_mut(|windows| windows.release(&self.window_type));
+        }
+    }
+}
+
+// 可通过 create_new_window 新建的窗口类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowKind {
+    Main,
+    Consultation,
+    Patient,
+    Settings,
+}
+
+impl WindowKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindowKind::Main => "main",
+            WindowKind::Consultation => "consultation",
+            WindowKind::Patient => "patient",
+            WindowKind::Settings => "settings",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "main" => Some(WindowKind::Main),
+            "consultation" => Some(WindowKind::Consultation),
+            "patient" => Some(WindowKind::Patient),
+            "settings" => Some(WindowKind::Settings),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,17 +314,108 @@ pub struct WindowInfo {
     pub position: WindowPosition,
     pub size: WindowSize,
     pub state: String, // "normal" | "minimized" | "maximized"
+    // 用户固定的窗口不参与自动排列
+    #[serde(default)]
+    pub pinned: bool,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub last_focused: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// 持久化的窗口布局，不包含创建时间、聚焦时间等运行期字段
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistedWindow {
+    pub id: String,
+    pub window_type: String,
+    pub title: String,
+    pub url: String,
+    pub data: Option<serde_json::Value>,
+    pub position: WindowPosition,
+    pub size: WindowSize,
+    pub state: String, // "normal" | "maximized"
+}
+
+impl From<&WindowInfo> for PersistedWindow {
+    fn from(info: &WindowInfo) -> Self {
+        // 最小化状态不恢复
+        let state = if info.state == "maximized" { "maximized" } else { "normal" };
+
+        Self {
+            id: info.id.clone(),
+            window_type: info.window_type.clone(),
+            title: info.title.clone(),
+            url: info.url.clone(),
+            data: info.data.clone(),
+            position: info.position.clone(),
+            size: info.size.clone(),
+            state: state.to_string(),
+        }
+    }
+}
+
+// 显示器区域（逻辑坐标）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorBounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+// 自动排列后的窗口区域（逻辑坐标）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayoutPreset {
+    #[serde(rename = "grid")]
+    Grid,
+    #[serde(rename = "columns")]
+    Columns,
+    // 最近聚焦的窗口占左侧，其余窗口在右侧上下排列
+    #[serde(rename = "focus-left")]
+    FocusLeft,
+}
+
+impl LayoutPreset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LayoutPreset::Grid => "grid",
+            LayoutPreset::Columns => "columns",
+            LayoutPreset::FocusLeft => "focus-left",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "grid" => Some(LayoutPreset::Grid),
+            "columns" => Some(LayoutPreset::Columns),
+            "focus-left" => Some(LayoutPreset::FocusLeft),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowLayoutResult {
+    pub preset: LayoutPreset,
+    // 按排列顺序调整过的窗口
+    pub arranged: Vec<String>,
+    // 用户固定、保持原位的窗口
+    pub pinned: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WindowPosition {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WindowSize {
     pub width: f64,
     pub height: f64,
@@ -66,87 +424,212 @@ pub struct WindowSize {
 #[derive(Debug, Serialize)]
 pub struct ResourceUsage {
     pub memory_usage_mb: u64,
+    pub process_memory_mb: u64,
+    pub webview_memory_mb: u64,
+    pub cpu_usage: f32,
+    pub available_memory_mb: u64,
+    pub memory_threshold_mb: u64,
+    pub pressure: MemoryPressure,
     pub window_count: usize,
     pub consultation_window_count: usize,
     pub last_updated: chrono::DateTime<chrono::Utc>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenWindowResult {
+    pub created: bool,
+    pub window_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourcePressureEvent {
+    pub pressure: MemoryPressure,
+    pub memory_usage_mb: u64,
+    pub memory_threshold_mb: u64,
+}
+
 #[tauri::command]
 pub async fn create_new_window(
     app: tauri::AppHandle,
     state: State<'_, WindowManagerState>,
     request: CreateWindowRequest,
-) -> Result<String, String> {
-    println!("Creating new window: {:?}", request);
+) -> Result<String, AppError> {
+    tracing::info!("Creating new window: {:?}", request);
 
-    // 检查窗口数量限制
-    let windows = state.windows.lock().unwrap();
-    if windows.len() >= state.limits.max_windows {
-        return Err(format!("已达到最大窗口数量限制: {}", state.limits.max_windows));
-    }
+    let kind = WindowKind::parse(&request.window_type).ok_or_else(|| {
+        AppError::invalid_argument(MessageKey::WindowTypeUnknown.text(&[&request.window_type]))
+            .with_code(CODE_WINDOW_TYPE_UNKNOWN)
+    })?;
 
-    // 检查特定类型窗口限制
-    if request.window_type == "consultation" {
-        let consultation_count = windows
-            .values()
-            .filter(|w| w.window_type == "consultation")
-            .count();
-        if consultation_count >= state.limits.max_consultation_windows {
-            return Err(format!(
-                "已达到最大问诊窗口数量限制: {}",
-                state.limits.max_consultation_windows
-            ));
+    // 检查窗口数量限制，名额在窗口创建完成前保留
+    let slot = state
+        .reserve_slot(kind.as_str())
+        .map_err(|e| AppError::new(ErrorType::ValidationError, e).with_retryable(false))?;
+
+    let (window_id, (_, window_info)) = open_with_unique_id(kind, new_window_id, |window_id| {
+        open_window(
+            &app,
+            window_id,
+            kind.as_str(),
+            request.data.clone(),
+            request.position.clone(),
+            request.size.clone(),
+        )
+    })
+    .map_err(|e| AppError::new(ErrorType::SystemError, format!("Failed to create window: {}", e)))?;
+
+    slot.fill(window_info);
+    persist_window_state(&app, &state);
+
+    tracing::info!("Window created successfully: {}", window_id);
+    Ok(window_id)
+}
+
+// 同一问诊只保留一个窗口：已打开则聚焦，否则新建
+#[tauri::command]
+pub async fn open_or_focus_consultation_window(
+    app: tauri::AppHandle,
+    state: State<'_, WindowManagerState>,
+    consultation_id: String,
+    patient_name: Option<String>,
+) -> Result<OpenWindowResult, AppError> {
+    tracing::info!("Opening consultation window: {}", consultation_id);
+
+    let existing =
+        state.find_live_consultation_window(&consultation_id, |window_id| app.get_webview_window(window_id).is_some());
+
+    if let Some(window_id) = existing {
+        if let Some(window) = app.get_webview_window(&window_id) {
+            if window.is_minimized().unwrap_or(false) {
+                window
+                    .unminimize()
+                    .map_err(|e| AppError::new(ErrorType::SystemError, format!("Failed to unminimize window: {}", e)))?;
+            }
+            window
+                .set_focus()
+                .map_err(|e| AppError::new(ErrorType::SystemError, format!("Failed to focus window: {}", e)))?;
+
+            state.with_windows_mut(|windows| {
+                windows.update(&window_id, |window_info| {
+                    window_info.last_focused = chrono::Utc::now();
+                    if window_info.state == "minimized" {
+                        window_info.state = "normal".to_string();
+                    }
+                });
+            });
+
+            return Ok(OpenWindowResult { created: false, window_id });
         }
+
+        // 查找后窗口恰好被销毁，按新建处理
+        state.remove_window(&window_id);
     }
-    drop(windows);
 
-    let window_id = format!("{}-{}", request.window_type, chrono::Utc::now().timestamp_millis());
-    let title = get_window_title(&request.window_type, &request.data);
-    let url = get_window_url(&request.window_type, &request.data);
+    let mut data = serde_json::json!({ "consultationId": consultation_id });
+    if let Some(patient_name) = patient_name {
+        data["patientName"] = serde_json::Value::String(patient_name);
+    }
 
-    // 获取窗口配置
-    let (default_width, default_height, resizable, maximizable) = get_window_config(&request.window_type);
-    let width = request.size.as_ref().map(|s| s.width).unwrap_or(default_width);
-    let height = request.size.as_ref().map(|s| s.height).unwrap_or(default_height);
+    let request = CreateWindowRequest {
+        window_type: "consultation".to_string(),
+        data: Some(data),
+        position: None,
+        size: None,
+    };
+    let window_id = create_new_window(app, state, request).await?;
 
-    // 创建新窗口
-    let mut builder = WebviewWindowBuilder::new(&app, &window_id, WebviewUrl::App(url.clone().into()))
-        .title(&title)
-        .inner_size(width, height)
-        .min_inner_size(600.0, 400.0)
-        .resizable(resizable)
-        .maximizable(maximizable);
+    Ok(OpenWindowResult { created: true, window_id })
+}
 
-    // 设置窗口位置
-    if let Some(pos) = &request.position {
-        builder = builder.position(pos.x as f64, pos.y as f64);
-    } else {
-        builder = builder.center();
+// 关闭问诊对应的窗口（如问诊已转给其他医生），返回被关闭的窗口 ID
+pub fn close_consultation_window(
+    app: &tauri::AppHandle,
+    state: &WindowManagerState,
+    consultation_id: &str,
+) -> Option<String> {
+    let window_id = state.consultation_window_id(consultation_id)?;
+    state.remove_window(&window_id);
+
+    if let Some(window) = app.get_webview_window(&window_id) {
+        if let Err(e) = window.close() {
+            tracing::warn!("Failed to close consultation window {}: {}", window_id, e);
+        }
     }
+    persist_window_state(app, state);
 
-    let webview_window = builder
-        .build()
-        .map_err(|e| format!("Failed to create window: {}", e))?;
+    Some(window_id)
+}
 
-    // 保存窗口信息
-    let window_info = WindowInfo {
-        id: window_id.clone(),
-        window_type: request.window_type.clone(),
-        title,
-        url,
-        data: request.data,
-        position: request.position.unwrap_or(WindowPosition { x: 100, y: 100 }),
-        size: WindowSize { width, height },
-        state: "normal".to_string(),
-        created_at: chrono::Utc::now(),
-        last_focused: chrono::Utc::now(),
-    };
+// 恢复上次退出时的窗口布局；问诊窗口仅在问诊仍进行中时恢复
+#[tauri::command]
+pub async fn restore_previous_windows(
+    app: tauri::AppHandle,
+    state: State<'_, WindowManagerState>,
+) -> Result<Vec<String>, String> {
+    let saved = std::mem::take(&mut *state.saved_windows.lock().unwrap());
+    tracing::info!("Restoring {} saved windows", saved.len());
 
-    let mut windows = state.windows.lock().unwrap();
-    windows.insert(window_id.clone(), window_info);
+    let monitors = monitor_bounds(&app);
+    let mut restored = Vec::new();
 
-    println!("Window created successfully: {}", window_id);
-    Ok(window_id)
+    for window in saved {
+        if !should_restore(&window, is_consultation_active) {
+            continue;
+        }
+
+        let (position, size) = clamp_to_monitors(&window.position, &window.size, &monitors);
+
+        let webview_window = if let Some(existing) = app.get_webview_window(&window.id) {
+            // 已存在的窗口（如主窗口）只恢复位置和大小
+            existing
+                .set_position(tauri::LogicalPosition::new(position.x as f64, position.y as f64))
+                .map_err(|e| format!("Failed to restore window position: {}", e))?;
+            existing
+                .set_size(tauri::LogicalSize::new(size.width, size.height))
+                .map_err(|e| format!("Failed to restore window size: {}", e))?;
+
+            state.with_windows_mut(|windows| {
+                windows.update(&window.id, |window_info| {
+                    window_info.position = position;
+                    window_info.size = size;
+                });
+            });
+            existing
+        } else {
+            let slot = match state.reserve_slot(&window.window_type) {
+                Ok(slot) => slot,
+                Err(e) => {
+                    tracing::warn!("Skipping window {}: {}", window.id, e);
+                    continue;
+                }
+            };
+
+            let (webview_window, window_info) = open_window(
+                &app,
+                &window.id,
+                &window.window_type,
+                window.data.clone(),
+                Some(position),
+                Some(size),
+            )
+            .map_err(|e| format!("Failed to create window: {}", e))?;
+            slot.fill(window_info);
+            webview_window
+        };
+
+        if window.state == "maximized" && webview_window.maximize().is_ok() {
+            state.with_windows_mut(|windows| {
+                windows.update(&window.id, |window_info| window_info.state = "maximized".to_string());
+            });
+        }
+
+        restored.push(window.id);
+    }
+
+    persist_window_state(&app, &state);
+
+    tracing::info!("Restored windows: {:?}", restored);
+    Ok(restored)
 }
 
 #[tauri::command]
@@ -155,16 +638,16 @@ pub async fn close_window_by_id(
     state: State<'_, WindowManagerState>,
     window_id: String,
 ) -> Result<(), String> {
-    println!("Closing window: {}", window_id);
+    tracing::info!("Closing window: {}", window_id);
 
     if let Some(window) = app.get_webview_window(&window_id) {
         window.close().map_err(|e| format!("Failed to close window: {}", e))?;
 
         // 从状态中移除窗口信息
-        let mut windows = state.windows.lock().unwrap();
-        windows.remove(&window_id);
+        state.remove_window(&window_id);
+        persist_window_state(&app, &state);
 
-        println!("Window closed successfully: {}", window_id);
+        tracing::info!("Window closed successfully: {}", window_id);
     } else {
         return Err(format!("Window not found: {}", window_id));
     }
@@ -178,18 +661,17 @@ pub async fn focus_window_by_id(
     state: State<'_, WindowManagerState>,
     window_id: String,
 ) -> Result<(), String> {
-    println!("Focusing window: {}", window_id);
+    tracing::debug!("Focusing window: {}", window_id);
 
     if let Some(window) = app.get_webview_window(&window_id) {
         window.set_focus().map_err(|e| format!("Failed to focus window: {}", e))?;
 
         // 更新最后聚焦时间
-        let mut windows = state.windows.lock().unwrap();
-        if let Some(window_info) = windows.get_mut(&window_id) {
-            window_info.last_focused = chrono::Utc::now();
-        }
+        state.with_windows_mut(|windows| {
+            windows.update(&window_id, |window_info| window_info.last_focused = chrono::Utc::now());
+        });
 
-        println!("Window focused successfully: {}", window_id);
+        tracing::debug!("Window focused successfully: {}", window_id);
     } else {
         return Err(format!("Window not found: {}", window_id));
     }
@@ -197,12 +679,28 @@ pub async fn focus_window_by_id(
     Ok(())
 }
 
+// 以实际存在的窗口为准，清理状态中遗留的记录
+#[tauri::command]
+pub async fn reconcile_windows(
+    app: tauri::AppHandle,
+    state: State<'_, WindowManagerState>,
+) -> Result<Vec<String>, String> {
+    let live_labels: HashSet<String> = app.webview_windows().into_keys().collect();
+    let orphans = state.prune_orphan_windows(&live_labels);
+
+    if !orphans.is_empty() {
+        tracing::info!("Pruned orphan windows: {:?}", orphans);
+        persist_window_state(&app, &state);
+    }
+
+    Ok(orphans)
+}
+
 #[tauri::command]
 pub async fn get_all_windows(
     state: State<'_, WindowManagerState>,
 ) -> Result<Vec<WindowInfo>, String> {
-    let windows = state.windows.lock().unwrap();
-    Ok(windows.values().cloned().collect())
+    Ok(state.with_windows(|windows| windows.values().cloned().collect()))
 }
 
 #[tauri::command]
@@ -210,8 +708,7 @@ pub async fn get_window_info(
     state: State<'_, WindowManagerState>,
     window_id: String,
 ) -> Result<Option<WindowInfo>, String> {
-    let windows = state.windows.lock().unwrap();
-    Ok(windows.get(&window_id).cloned())
+    Ok(state.with_windows(|windows| windows.get(&window_id).cloned()))
 }
 
 #[tauri::command]
@@ -220,31 +717,127 @@ pub async fn update_window_data(
     window_id: String,
     data: serde_json::Value,
 ) -> Result<(), String> {
-    let mut windows = state.windows.lock().unwrap();
-    if let Some(window_info) = windows.get_mut(&window_id) {
-        window_info.data = Some(data);
-        Ok(())
-    } else {
-        Err(format!("Window not found: {}", window_id))
+    state
+        .with_windows_mut(|windows| windows.update(&window_id, |window_info| window_info.data = Some(data)).map(|_| ()))
+        .ok_or_else(|| format!("Window not found: {}", window_id))
+}
+
+/// 合并窗口上下文（如修正后的患者姓名）并刷新窗口标题
+#[tauri::command]
+pub async fn update_window_context(
+    app: tauri::AppHandle,
+    state: State<'_, WindowManagerState>,
+    window_id: String,
+    data: serde_json::Value,
+) -> Result<WindowInfo, String> {
+    apply_window_context(&app, &state, &window_id, data)
+}
+
+fn apply_window_context(
+    app: &tauri::AppHandle,
+    state: &WindowManagerState,
+    window_id: &str,
+    data: serde_json::Value,
+) -> Result<WindowInfo, String> {
+    // 窗口已被销毁时清理残留记录
+    let Some(window) = app.get_webview_window(window_id) else {
+        if state.remove_window(window_id).is_some() {
+            persist_window_state(app, state);
+        }
+        return Err(format!("Window not found: {}", window_id));
+    };
+
+    let info = state
+        .with_windows_mut(|windows| {
+            windows
+                .update(window_id, |window_info| {
+                    let merged = merge_window_data(window_info.data.take(), data);
+                    window_info.data = Some(merged);
+                    window_info.title = get_window_title(&window_info.window_type, &window_info.data);
+                })
+                .cloned()
+        })
+        .ok_or_else(|| format!("Window not found: {}", window_id))?;
+
+    if let Err(e) = window.set_title(&info.title) {
+        tracing::warn!("Failed to set title of window {}: {}", window_id, e);
+    }
+    persist_window_state(app, state);
+    Ok(info)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsultationReadonlyEvent {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    pub status: String,
+}
+
+// 问诊状态变化后更新对应窗口的标题；问诊结束时通知窗口禁止继续发送消息
+pub fn apply_consultation_status(app: &tauri::AppHandle, state: &WindowManagerState, consultation_id: &str, status: &str) {
+    let Some(window_id) = state.consultation_window_id(consultation_id) else {
+        return;
+    };
+
+    if let Err(e) = apply_window_context(app, state, &window_id, serde_json::json!({ "status": status })) {
+        tracing::debug!("Skipping status update for consultation window {}: {}", window_id, e);
+        return;
+    }
+
+    if ConsultationStatus::parse(status).is_some_and(is_readonly_status) {
+        let event = ConsultationReadonlyEvent {
+            consultation_id: consultation_id.to_string(),
+            status: status.to_string(),
+        };
+        if let Err(e) = app.emit_to(window_id.as_str(), CONSULTATION_READONLY_EVENT, &event) {
+            tracing::warn!("Failed to emit {} event: {}", CONSULTATION_READONLY_EVENT, e);
+        }
     }
 }
 
 #[tauri::command]
 pub async fn get_resource_usage(
+    app: tauri::AppHandle,
     state: State<'_, WindowManagerState>,
 ) -> Result<ResourceUsage, String> {
-    let windows = state.windows.lock().unwrap();
-    let consultation_count = windows
-        .values()
-        .filter(|w| w.window_type == "consultation")
-        .count();
+    let (window_count, consultation_count) = state.with_windows(|windows| {
+        let consultation_count = windows
+            .values()
+            .filter(|w| w.window_type == "consultation")
+            .count();
+        (windows.len(), consultation_count)
+    });
+
+    let threshold = state.current_limits().memory_threshold_mb;
+    let (sample, pressure, changed) = {
+        let mut monitor = state.resource_monitor.lock().unwrap();
+        let sample = monitor.sample();
+        let pressure = classify_pressure(&sample, threshold);
+        let changed = monitor.update_pressure(pressure);
+        (sample, pressure, changed)
+    };
 
-    // 简化的内存使用计算（实际应用中可以使用系统API获取真实数据）
-    let estimated_memory = windows.len() as u64 * 50; // 每个窗口估算50MB
+    // 内存压力状态变化时通知前端提示用户关闭部分窗口
+    if changed {
+        let event = ResourcePressureEvent {
+            pressure,
+            memory_usage_mb: sample.memory_usage_mb(),
+            memory_threshold_mb: threshold,
+        };
+        if let Err(e) = app.emit("resource-pressure", &event) {
+            tracing::warn!("Failed to emit resource-pressure event: {}", e);
+        }
+    }
 
     Ok(ResourceUsage {
-        memory_usage_mb: estimated_memory,
-        window_count: windows.len(),
+        memory_usage_mb: sample.memory_usage_mb(),
+        process_memory_mb: sample.process_memory_mb,
+        webview_memory_mb: sample.webview_memory_mb,
+        cpu_usage: sample.cpu_usage,
+        available_memory_mb: sample.available_memory_mb,
+        memory_threshold_mb: threshold,
+        pressure,
+        window_count,
         consultation_window_count: consultation_count,
         last_updated: chrono::Utc::now(),
     })
@@ -254,9 +847,8 @@ pub async fn get_resource_usage(
 pub async fn check_window_limits(
     state: State<'_, WindowManagerState>,
 ) -> Result<bool, String> {
-    let windows = state.windows.lock().unwrap();
-    let can_create = windows.len() < state.limits.max_windows;
-    Ok(can_create)
+    let max_windows = state.current_limits().max_windows;
+    Ok(state.with_windows(|windows| windows.len() + windows.reserved.len() < max_windows))
 }
 
 #[tauri::command]
@@ -269,10 +861,9 @@ pub async fn minimize_window(
         window.minimize().map_err(|e| format!("Failed to minimize window: {}", e))?;
 
         // 更新窗口状态
-        let mut windows = state.windows.lock().unwrap();
-        if let Some(window_info) = windows.get_mut(&window_id) {
-            window_info.state = "minimized".to_string();
-        }
+        state.with_windows_mut(|windows| {
+            windows.update(&window_id, |window_info| window_info.state = "minimized".to_string());
+        });
 
         Ok(())
     } else {
@@ -290,10 +881,10 @@ pub async fn maximize_window(
         window.maximize().map_err(|e| format!("Failed to maximize window: {}", e))?;
 
         // 更新窗口状态
-        let mut windows = state.windows.lock().unwrap();
-        if let Some(window_info) = windows.get_mut(&window_id) {
-            window_info.state = "maximized".to_string();
-        }
+        state.with_windows_mut(|windows| {
+            windows.update(&window_id, |window_info| window_info.state = "maximized".to_string());
+        });
+        persist_window_state(&app, &state);
 
         Ok(())
     } else {
@@ -301,36 +892,674 @@ pub async fn maximize_window(
     }
 }
 
-fn get_window_title(window_type: &str, data: &Option<serde_json::Value>) -> String {
-    match window_type {
-        "main" => "互联网医院 - 工作台".to_string(),
-        "consultation" => {
-            if let Some(data) = data {
-                if let Some(patient_name) = data.get("patientName").and_then(|v| v.as_str()) {
-                    format!("问诊 - {}", patient_name)
-                } else {
-                    "问诊窗口".to_string()
-                }
+/// 按预设平铺当前显示器上未最小化、未固定的问诊窗口，并记住所选预设
+#[tauri::command]
+pub async fn apply_window_layout(
+    app: tauri::AppHandle,
+    state: State<'_, WindowManagerState>,
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+    preset: String,
+) -> Result<WindowLayoutResult, String> {
+    let preset = LayoutPreset::parse(&preset).ok_or_else(|| format!("Unknown window layout preset: {}", preset))?;
+    save_layout_preset(&token_refresh, &readiness, preset).await;
+
+    let (mut candidates, pinned) = state.with_windows(|windows| {
+        let mut candidates = Vec::new();
+        let mut pinned = Vec::new();
+        for info in windows.values() {
+            if info.window_type != "consultation" || info.state == "minimized" {
+                continue;
+            }
+            if info.pinned {
+                pinned.push(info.id.clone());
             } else {
-                "问诊窗口".to_string()
+                candidates.push((info.id.clone(), info.last_focused));
             }
         }
-        "patient" => {
-            if let Some(data) = data {
-                if let Some(patient_name) = data.get("patientName").and_then(|v| v.as_str()) {
-                    format!("患者详情 - {}", patient_name)
-                } else {
-                    "患者管理".to_string()
-                }
-            } else {
-                "患者管理".to_string()
+        (candidates, pinned)
+    });
+    // 最近聚焦的窗口排在最前（focus-left 中占据左侧）
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let targets: Vec<WebviewWindow> = candidates
+        .iter()
+        .filter_map(|(id, _)| app.get_webview_window(id))
+        .collect();
+
+    let mut result = WindowLayoutResult {
+        preset,
+        arranged: Vec::new(),
+        pinned,
+    };
+    if targets.len() < 2 {
+        return Ok(result);
+    }
+
+    let monitor = targets[0]
+        .current_monitor()
+        .ok()
+        .flatten()
+        .or_else(|| app.primary_monitor().ok().flatten())
+        .ok_or_else(|| "No monitor available for window layout".to_string())?;
+    let scale = monitor.scale_factor();
+    let area = work_area_bounds(&monitor);
+
+    for (window, rect) in targets.iter().zip(compute_layout(preset, &area, targets.len())) {
+        if window.is_maximized().unwrap_or(false) {
+            window.unmaximize().map_err(|e| format!("Failed to unmaximize window: {}", e))?;
+        }
+        // 位置按目标显示器的缩放换算，移到该显示器后再按逻辑尺寸设置大小，混合 DPI 时尺寸不失真
+        let position = tauri::LogicalPosition::new(rect.x, rect.y).to_physical::<i32>(scale);
+        window
+            .set_position(tauri::Position::Physical(position))
+            .map_err(|e| format!("Failed to move window: {}", e))?;
+        window
+            .set_size(tauri::Size::Logical(tauri::LogicalSize::new(rect.width, rect.height)))
+            .map_err(|e| format!("Failed to resize window: {}", e))?;
+        result.arranged.push(window.label().to_string());
+    }
+
+    // 新的位置和尺寸由窗口的 Moved/Resized 事件写回并保存
+    tracing::info!("Applied {} layout to {} windows", preset.as_str(), result.arranged.len());
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_window_layout_preset(
+    token_refresh: State<'_, TokenRefreshServiceState>,
+    readiness: State<'_, DatabaseReadinessState>,
+) -> Result<Option<LayoutPreset>, String> {
+    require_database(&readiness).await.map_err(|e| e.to_string())?;
+    let doctor_id = current_doctor_id(&token_refresh).await.map_err(|e| e.message)?;
+
+    let saved = UserSettingsDao::new()
+        .get(&doctor_id, WINDOW_LAYOUT_PRESET_KEY)
+        .map_err(|e| format!("获取窗口布局失败: {}", e))?;
+    Ok(saved.as_deref().and_then(LayoutPreset::parse))
+}
+
+#[tauri::command]
+pub async fn set_window_pinned(
+    state: State<'_, WindowManagerState>,
+    window_id: String,
+    pinned: bool,
+) -> Result<(), String> {
+    state
+        .with_windows_mut(|windows| windows.update(&window_id, |window_info| window_info.pinned = pinned).map(|_| ()))
+        .ok_or_else(|| format!("Window not found: {}", window_id))
+}
+
+// 未登录或数据库未就绪时只排列窗口，不保存预设
+async fn save_layout_preset(
+    token_refresh: &State<'_, TokenRefreshServiceState>,
+    readiness: &DatabaseReadinessState,
+    preset: LayoutPreset,
+) {
+    if require_database(readiness).await.is_err() {
+        return;
+    }
+    let Ok(doctor_id) = current_doctor_id(token_refresh).await else {
+        return;
+    };
+    if let Err(e) = UserSettingsDao::new().set(&doctor_id, WINDOW_LAYOUT_PRESET_KEY, preset.as_str()) {
+        tracing::warn!("Failed to save window layout preset: {}", e);
+    }
+}
+
+// 按预设计算 count 个窗口在工作区内的位置，各窗口首尾相接铺满工作区
+pub fn compute_layout(preset: LayoutPreset, area: &MonitorBounds, count: usize) -> Vec<LayoutRect> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    match preset {
+        LayoutPreset::Columns => split_span(area.x, area.width, count)
+            .into_iter()
+            .map(|(x, width)| LayoutRect { x, y: area.y, width, height: area.height })
+            .collect(),
+        LayoutPreset::Grid => {
+            let columns = (count as f64).sqrt().ceil() as usize;
+            let rows = count.div_ceil(columns);
+            let mut rects = Vec::with_capacity(count);
+            for (row, (y, height)) in split_span(area.y, area.height, rows).into_iter().enumerate() {
+                // 最后一行窗口较少时平分整行宽度
+                let in_row = columns.min(count - row * columns);
+                rects.extend(
+                    split_span(area.x, area.width, in_row)
+                        .into_iter()
+                        .map(|(x, width)| LayoutRect { x, y, width, height }),
+                );
+            }
+            rects
+        }
+        LayoutPreset::FocusLeft => {
+            if count == 1 {
+                return vec![LayoutRect { x: area.x, y: area.y, width: area.width, height: area.height }];
+            }
+            let focus_width = (area.width * FOCUS_LEFT_RATIO).round();
+            let mut rects = vec![LayoutRect { x: area.x, y: area.y, width: focus_width, height: area.height }];
+            rects.extend(split_span(area.y, area.height, count - 1).into_iter().map(|(y, height)| LayoutRect {
+                x: area.x + focus_width,
+                y,
+                width: area.width - focus_width,
+                height,
+            }));
+            rects
+        }
+    }
+}
+
+// 把 [start, start + length) 均分为 parts 段，分界取整，返回各段起点和长度
+fn split_span(start: f64, length: f64, parts: usize) -> Vec<(f64, f64)> {
+    let edge = |i: usize| (start + length * i as f64 / parts as f64).round();
+    (0..parts).map(|i| (edge(i), edge(i + 1) - edge(i))).collect()
+}
+
+// 启动时读取上次保存的窗口布局，供 restore_previous_windows 使用
+pub fn load_saved_windows(app: &tauri::AppHandle) {
+    let Some(path) = window_state_path(app) else {
+        return;
+    };
+
+    let saved = match std::fs::read_to_string(&path) {
+        Ok(content) => parse_saved_windows(&content),
+        Err(_) => Vec::new(),
+    };
+
+    let state = app.state::<WindowManagerState>();
+    *state.saved_windows.lock().unwrap() = saved;
+}
+
+// 登记由配置文件创建的窗口（如主窗口），使其布局同样被保存
+pub fn register_existing_window(app: &tauri::AppHandle, window_id: &str, window_type: &str) {
+    let Some(window) = app.get_webview_window(window_id) else {
+        return;
+    };
+
+    let (default_width, default_height, _, _) = get_window_config(window_type);
+    let (position, size) = current_bounds(&window).unwrap_or((
+        WindowPosition { x: 100, y: 100 },
+        WindowSize { width: default_width, height: default_height },
+    ));
+
+    let window_info = WindowInfo {
+        id: window_id.to_string(),
+        window_type: window_type.to_string(),
+        title: get_window_title(window_type, &None),
+        url: get_window_url(window_type, &None),
+        data: None,
+        position,
+        size,
+        state: "normal".to_string(),
+        pinned: false,
+        created_at: chrono::Utc::now(),
+        last_focused: chrono::Utc::now(),
+    };
+
+    app.state::<WindowManagerState>().insert_window(window_info);
+    track_window_events(app, &window);
+}
+
+pub fn serialize_windows<'a>(windows: impl Iterator<Item = &'a WindowInfo>) -> Result<String, serde_json::Error> {
+    let mut saved: Vec<PersistedWindow> = windows.map(PersistedWindow::from).collect();
+    saved.sort_by(|a, b| a.id.cmp(&b.id));
+    serde_json::to_string_pretty(&saved)
+}
+
+pub fn parse_saved_windows(content: &str) -> Vec<PersistedWindow> {
+    serde_json::from_str(content).unwrap_or_else(|e| {
+        tracing::error!("Failed to parse saved window state: {}", e);
+        Vec::new()
+    })
+}
+
+// 保存的位置不在任何已连接显示器上时，移回主显示器并限制在其范围内
+pub fn clamp_to_monitors(
+    position: &WindowPosition,
+    size: &WindowSize,
+    monitors: &[MonitorBounds],
+) -> (WindowPosition, WindowSize) {
+    let Some(primary) = monitors.first() else {
+        return (position.clone(), size.clone());
+    };
+
+    let (x, y) = (position.x as f64, position.y as f64);
+    let visible = monitors.iter().any(|m| {
+        let overlap = (x + size.width).min(m.x + m.width) - x.max(m.x);
+        overlap >= MIN_VISIBLE_WIDTH.min(size.width) && y >= m.y && y <= m.y + m.height - TITLE_BAR_HEIGHT
+    });
+    if visible {
+        return (position.clone(), size.clone());
+    }
+
+    let width = size.width.min(primary.width);
+    let height = size.height.min(primary.height);
+    let x = x.clamp(primary.x, primary.x + primary.width - width);
+    let y = y.clamp(primary.y, primary.y + primary.height - height);
+
+    (
+        WindowPosition { x: x.round() as i32, y: y.round() as i32 },
+        WindowSize { width, height },
+    )
+}
+
+pub fn should_restore(window: &PersistedWindow, is_consultation_active: impl Fn(&str) -> bool) -> bool {
+    match window.window_type.as_str() {
+        "main" | "patient" | "settings" => true,
+        "consultation" => window
+            .data
+            .as_ref()
+            .and_then(|data| data.get("consultationId"))
+            .and_then(|v| v.as_str())
+            .map(&is_consultation_active)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn is_consultation_active(consultation_id: &str) -> bool {
+    match ConsultationDao::new().find_by_id(consultation_id) {
+        Ok(Some(consultation)) => consultation.status == ConsultationStatus::Active.as_str(),
+        Ok(None) => false,
+        Err(e) => {
+            tracing::error!("Failed to check consultation {}: {}", consultation_id, e);
+            false
+        }
+    }
+}
+
+// 预留中的名额同样计入
+fn check_limits(
+    windows: &WindowRegistry,
+    limits: &WindowLimits,
+    window_type: &str,
+) -> Result<(), String> {
+    if windows.len() + windows.reserved.len() >= limits.max_windows {
+        return Err(format!("已达到最大窗口数量限制: {}", limits.max_windows));
+    }
+
+    // 检查特定类型窗口限制
+    if window_type == "consultation" && windows.count_of_type("consultation") >= limits.max_consultation_windows {
+        return Err(format!(
+            "已达到最大问诊窗口数量限制: {}",
+            limits.max_consultation_windows
+        ));
+    }
+
+    Ok(())
+}
+
+fn open_window(
+    app: &tauri::AppHandle,
+    window_id: &str,
+    window_type: &str,
+    data: Option<serde_json::Value>,
+    position: Option<WindowPosition>,
+    size: Option<WindowSize>,
+) -> Result<(WebviewWindow, WindowInfo), tauri::Error> {
+    let title = get_window_title(window_type, &data);
+    let url = get_window_url(window_type, &data);
+
+    // 获取窗口配置
+    let (default_width, default_height, resizable, maximizable) = get_window_config(window_type);
+    let width = size.as_ref().map(|s| s.width).unwrap_or(default_width);
+    let height = size.as_ref().map(|s| s.height).unwrap_or(default_height);
+
+    // 创建新窗口
+    let mut builder = WebviewWindowBuilder::new(app, window_id, WebviewUrl::App(url.clone().into()))
+        .title(&title)
+        .inner_size(width, height)
+        .min_inner_size(600.0, 400.0)
+        .resizable(resizable)
+        .maximizable(maximizable);
+
+    // 设置窗口位置
+    if let Some(pos) = &position {
+        builder = builder.position(pos.x as f64, pos.y as f64);
+    } else {
+        builder = builder.center();
+    }
+
+    let webview_window = builder.build()?;
+    track_window_events(app, &webview_window);
+
+    let position = position
+        .or_else(|| current_bounds(&webview_window).map(|(p, _)| p))
+        .unwrap_or(WindowPosition { x: 100, y: 100 });
+
+    let window_info = WindowInfo {
+        id: window_id.to_string(),
+        window_type: window_type.to_string(),
+        title,
+        url,
+        data,
+        position,
+        size: WindowSize { width, height },
+        state: "normal".to_string(),
+        pinned: false,
+        created_at: chrono::Utc::now(),
+        last_focused: chrono::Utc::now(),
+    };
+
+    Ok((webview_window, window_info))
+}
+
+// 跟踪窗口生命周期：销毁时移除记录，移动、缩放、聚焦时同步状态
+fn track_window_events(app: &tauri::AppHandle, window: &WebviewWindow) {
+    let app_handle = app.clone();
+    let window_id = window.label().to_string();
+
+    window.on_window_event(move |event| {
+        let state = app_handle.state::<WindowManagerState>();
+
+        if let tauri::WindowEvent::Destroyed = event {
+            let removed = state.remove_window(&window_id);
+            app_handle.state::<CommandRateLimiterState>().lock().unwrap().remove_window(&window_id);
+            match removed {
+                // 主窗口关闭意味着会话结束，保留上次布局供下次启动恢复
+                Some(window) if window.window_type == "main" => state.mark_exiting(),
+                Some(window) if window.window_type == "consultation" => {
+                    tauri::async_runtime::spawn(purge_closed_consultation(app_handle.clone(), window));
+                }
+                _ => {}
+            }
+            if !state.is_exiting() {
+                persist_window_state(&app_handle, &state);
+            }
+            return;
+        }
+
+        if !matches!(
+            event,
+            tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) | tauri::WindowEvent::Focused(_)
+        ) {
+            return;
+        }
+
+        let Some(window) = app_handle.get_webview_window(&window_id) else {
+            return;
+        };
+        let minimized = window.is_minimized().unwrap_or(false);
+        let maximized = window.is_maximized().unwrap_or(false);
+        // 最小化或最大化时的尺寸不作为恢复尺寸
+        let bounds = if minimized || maximized { None } else { current_bounds(&window) };
+
+        let focused = matches!(event, tauri::WindowEvent::Focused(true));
+        let updated = state.with_windows_mut(|windows| {
+            windows
+                .update(&window_id, |window_info| {
+                    window_info.state = window_state_label(minimized, maximized).to_string();
+                    if let Some((position, size)) = bounds {
+                        window_info.position = position;
+                        window_info.size = size;
+                    }
+                    if focused {
+                        window_info.last_focused = chrono::Utc::now();
+                    }
+                })
+                .is_some()
+        });
+        if !updated {
+            return;
+        }
+
+        if !matches!(event, tauri::WindowEvent::Focused(_)) {
+            persist_window_state(&app_handle, &state);
+        }
+    });
+}
+
+// 问诊窗口关闭后清理该患者的内存数据，并记录患者上下文已关闭
+async fn purge_closed_consultation(app: tauri::AppHandle, window: WindowInfo) {
+    let data = window.data.as_ref();
+    let Some(consultation_id) = data.and_then(|data| data.get("consultationId")).and_then(|v| v.as_str()) else {
+        return;
+    };
+
+    let websocket = app.state::<WebSocketManagerState>().inner().clone();
+    let report = purge_consultation_context(&*websocket.lock().await, consultation_id).await;
+
+    let patient_id = data
+        .and_then(|data| data.get("patientId"))
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .or_else(|| {
+            ConsultationDao::new()
+                .find_by_id(consultation_id)
+                .ok()
+                .flatten()
+                .map(|consultation| consultation.patient_id)
+        });
+    let mut metadata = HashMap::from([
+        ("windowId".to_string(), window.id.clone()),
+        ("replayEvents".to_string(), report.replay_events.to_string()),
+        ("cacheEntries".to_string(), report.cache_entries.to_string()),
+    ]);
+    if let Some(patient_id) = patient_id {
+        metadata.insert("patientId".to_string(), patient_id);
+    }
+
+    let user_id = app
+        .state::<TokenRefreshServiceState>()
+        .lock()
+        .await
+        .current_user_id()
+        .await
+        .unwrap_or_else(|| "unknown".to_string());
+    let security = app.state::<SecurityServiceState>().inner().clone();
+    let logged = security
+        .lock()
+        .await
+        .log_system_audit(
+            user_id,
+            AuditAction::ClosePatientContext,
+            Some("consultation".to_string()),
+            Some(consultation_id.to_string()),
+            "success".to_string(),
+            None,
+            metadata,
+        )
+        .await;
+    if let Err(e) = logged {
+        tracing::warn!("Failed to audit closed consultation {}: {}", consultation_id, e);
+    }
+}
+
+fn window_state_label(minimized: bool, maximized: bool) -> &'static str {
+    if minimized {
+        "minimized"
+    } else if maximized {
+        "maximized"
+    } else {
+        "normal"
+    }
+}
+
+fn window_consultation_id(info: &WindowInfo) -> Option<&str> {
+    if info.window_type != "consultation" {
+        return None;
+    }
+    info.data.as_ref()?.get("consultationId")?.as_str()
+}
+
+fn index_consultation(index: &mut HashMap<String, String>, info: &WindowInfo) {
+    if let Some(consultation_id) = window_consultation_id(info) {
+        index.insert(consultation_id.to_string(), info.id.clone());
+    }
+}
+
+// 只移除仍指向该窗口的映射
+fn unindex_consultation(index: &mut HashMap<String, String>, info: &WindowInfo) {
+    if let Some(consultation_id) = window_consultation_id(info) {
+        if index.get(consultation_id) == Some(&info.id) {
+            index.remove(consultation_id);
+        }
+    }
+}
+
+// 形如 "consultation-1a2b3c4d"，同一毫秒内新建多个窗口也不会重复
+pub fn new_window_id(kind: WindowKind) -> String {
+    let uuid = Uuid::new_v4().simple().to_string();
+    format!("{}-{}", kind.as_str(), &uuid[..8])
+}
+
+// 窗口标签已被占用时换一个新 ID 重试，其他错误直接返回
+fn open_with_unique_id<T>(
+    kind: WindowKind,
+    mut next_id: impl FnMut(WindowKind) -> String,
+    mut open: impl FnMut(&str) -> Result<T, tauri::Error>,
+) -> Result<(String, T), tauri::Error> {
+    let mut attempt = 1;
+    loop {
+        let window_id = next_id(kind);
+        match open(&window_id) {
+            Ok(opened) => return Ok((window_id, opened)),
+            Err(tauri::Error::WindowLabelAlreadyExists(_) | tauri::Error::WebviewLabelAlreadyExists(_))
+                if attempt < MAX_WINDOW_ID_ATTEMPTS =>
+            {
+                tracing::warn!("Window label {} already exists, retrying with a new id", window_id);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn current_bounds(window: &WebviewWindow) -> Option<(WindowPosition, WindowSize)> {
+    let scale = window.scale_factor().ok()?;
+    let position = window.outer_position().ok()?.to_logical::<f64>(scale);
+    let size = window.inner_size().ok()?.to_logical::<f64>(scale);
+
+    Some((
+        WindowPosition { x: position.x.round() as i32, y: position.y.round() as i32 },
+        WindowSize { width: size.width, height: size.height },
+    ))
+}
+
+// 主显示器排在最前，作为窗口移回的目标
+fn monitor_bounds(app: &tauri::AppHandle) -> Vec<MonitorBounds> {
+    let mut monitors: Vec<MonitorBounds> = app
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(to_monitor_bounds)
+        .collect();
+
+    if let Ok(Some(primary)) = app.primary_monitor() {
+        let primary = to_monitor_bounds(&primary);
+        monitors.retain(|m| *m != primary);
+        monitors.insert(0, primary);
+    }
+
+    monitors
+}
+
+fn to_monitor_bounds(monitor: &tauri::Monitor) -> MonitorBounds {
+    let scale = monitor.scale_factor();
+    let position = monitor.position().to_logical::<f64>(scale);
+    let size = monitor.size().to_logical::<f64>(scale);
+
+    MonitorBounds {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    }
+}
+
+// 显示器去掉任务栏、Dock 后的可用区域
+fn work_area_bounds(monitor: &tauri::Monitor) -> MonitorBounds {
+    let scale = monitor.scale_factor();
+    let area = monitor.work_area();
+    let position = area.position.to_logical::<f64>(scale);
+    let size = area.size.to_logical::<f64>(scale);
+
+    MonitorBounds {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    }
+}
+
+fn window_state_path(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(WINDOW_STATE_FILE))
+}
+
+fn persist_window_state(app: &tauri::AppHandle, state: &WindowManagerState) {
+    let Some(path) = window_state_path(app) else {
+        return;
+    };
+
+    let content = match state.with_windows(|windows| serialize_windows(windows.values())) {
+        Ok(content) => content,
+        Err(e) => {
+            tracing::error!("Failed to serialize window state: {}", e);
+            return;
+        }
+    };
+
+    if let Some(parent) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            tracing::error!("Failed to create app data directory: {}", e);
+            return;
+        }
+    }
+
+    if let Err(e) = std::fs::write(&path, content) {
+        tracing::error!("Failed to save window state: {}", e);
+    }
+}
+
+fn get_window_title(window_type: &str, data: &Option<serde_json::Value>) -> String {
+    match window_type {
+        "main" => "互联网医院 - 工作台".to_string(),
+        "consultation" => {
+            let title = match patient_name(data) {
+                Some(patient_name) => format!("问诊 - {}", patient_name),
+                None => "问诊窗口".to_string(),
+            };
+            // 已结束的问诊在标题上标注状态
+            match consultation_status(data) {
+                Some(status) if is_readonly_status(status) => format!("{}（{}）", title, status.label()),
+                _ => title,
             }
         }
+        "patient" => match patient_name(data) {
+            Some(patient_name) => format!("患者详情 - {}", patient_name),
+            None => "患者管理".to_string(),
+        },
         "settings" => "设置".to_string(),
         _ => "互联网医院".to_string(),
     }
 }
 
+fn patient_name(data: &Option<serde_json::Value>) -> Option<&str> {
+    data.as_ref()?.get("patientName")?.as_str()
+}
+
+fn consultation_status(data: &Option<serde_json::Value>) -> Option<ConsultationStatus> {
+    ConsultationStatus::parse(data.as_ref()?.get("status")?.as_str()?)
+}
+
+fn is_readonly_status(status: ConsultationStatus) -> bool {
+    matches!(status, ConsultationStatus::Completed | ConsultationStatus::Cancelled)
+}
+
+// 窗口上下文按字段合并，非对象时整体替换
+pub fn merge_window_data(current: Option<serde_json::Value>, patch: serde_json::Value) -> serde_json::Value {
+    match (current, patch) {
+        (Some(serde_json::Value::Object(mut current)), serde_json::Value::Object(patch)) => {
+            current.extend(patch);
+            serde_json::Value::Object(current)
+        }
+        (_, patch) => patch,
+    }
+}
+
 fn get_window_url(window_type: &str, data: &Option<serde_json::Value>) -> String {
     match window_type {
         "main" => "/".to_string(),
@@ -369,4 +1598,540 @@ fn get_window_config(window_type: &str) -> (f64, f64, bool, bool) {
         "settings" => (600.0, 500.0, false, false),
         _ => (800.0, 600.0, true, true),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn window_info(id: &str, window_type: &str, state: &str) -> WindowInfo {
+        WindowInfo {
+            id: id.to_string(),
+            window_type: window_type.to_string(),
+            title: get_window_title(window_type, &None),
+            url: get_window_url(window_type, &None),
+            data: Some(serde_json::json!({ "consultationId": "c1" })),
+            position: WindowPosition { x: 120, y: 80 },
+            size: WindowSize { width: 800.0, height: 600.0 },
+            state: state.to_string(),
+            pinned: false,
+            created_at: chrono::Utc::now(),
+            last_focused: chrono::Utc::now(),
+        }
+    }
+
+    fn persisted(window_type: &str, data: Option<serde_json::Value>) -> PersistedWindow {
+        PersistedWindow {
+            id: format!("{}-1", window_type),
+            window_type: window_type.to_string(),
+            title: String::new(),
+            url: String::new(),
+            data,
+            position: WindowPosition { x: 0, y: 0 },
+            size: WindowSize { width: 800.0, height: 600.0 },
+            state: "normal".to_string(),
+        }
+    }
+
+    const PRIMARY: MonitorBounds = MonitorBounds { x: 0.0, y: 0.0, width: 1920.0, height: 1080.0 };
+    const SECONDARY: MonitorBounds = MonitorBounds { x: 1920.0, y: 0.0, width: 1280.0, height: 1024.0 };
+
+    #[test]
+    fn test_serialize_windows_round_trip() {
+        let mut windows = HashMap::new();
+        windows.insert("main".to_string(), window_info("main", "main", "maximized"));
+        windows.insert("consultation-1".to_string(), window_info("consultation-1", "consultation", "minimized"));
+
+        let json = serialize_windows(windows.values()).unwrap();
+        assert!(!json.contains("created_at"));
+        assert!(!json.contains("last_focused"));
+
+        let saved = parse_saved_windows(&json);
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0], PersistedWindow::from(&windows["consultation-1"]));
+        assert_eq!(saved[0].state, "normal");
+        assert_eq!(saved[1].state, "maximized");
+        assert_eq!(saved[1].position, WindowPosition { x: 120, y: 80 });
+    }
+
+    #[test]
+    fn test_parse_corrupted_state_returns_empty() {
+        assert!(parse_saved_windows("not json").is_empty());
+        assert!(parse_saved_windows("").is_empty());
+    }
+
+    #[test]
+    fn test_clamp_keeps_visible_window() {
+        let size = WindowSize { width: 800.0, height: 600.0 };
+
+        // 位于副屏上
+        let position = WindowPosition { x: 2200, y: 100 };
+        assert_eq!(clamp_to_monitors(&position, &size, &[PRIMARY, SECONDARY]).0, position);
+
+        // 部分超出屏幕但标题栏仍可拖动
+        let position = WindowPosition { x: -500, y: 200 };
+        assert_eq!(clamp_to_monitors(&position, &size, &[PRIMARY]).0, position);
+
+        // 没有显示器信息时原样返回
+        let position = WindowPosition { x: 5000, y: 5000 };
+        assert_eq!(clamp_to_monitors(&position, &size, &[]).0, position);
+    }
+
+    #[test]
+    fn test_clamp_moves_window_from_disconnected_monitor() {
+        let size = WindowSize { width: 800.0, height: 600.0 };
+        let position = WindowPosition { x: 2200, y: 100 };
+
+        let (clamped, clamped_size) = clamp_to_monitors(&position, &size, &[PRIMARY]);
+        assert_eq!(clamped, WindowPosition { x: 1120, y: 100 });
+        assert_eq!(clamped_size, size);
+
+        // 标题栏在屏幕下方之外
+        let position = WindowPosition { x: 100, y: 1070 };
+        let (clamped, _) = clamp_to_monitors(&position, &size, &[PRIMARY]);
+        assert_eq!(clamped, WindowPosition { x: 100, y: 480 });
+    }
+
+    #[test]
+    fn test_clamp_shrinks_oversized_window() {
+        let size = WindowSize { width: 2560.0, height: 1440.0 };
+        let position = WindowPosition { x: -3000, y: 0 };
+
+        let (clamped, clamped_size) = clamp_to_monitors(&position, &size, &[PRIMARY]);
+        assert_eq!(clamped, WindowPosition { x: 0, y: 0 });
+        assert_eq!(clamped_size, WindowSize { width: 1920.0, height: 1080.0 });
+    }
+
+    fn state_with(windows: &[WindowInfo]) -> WindowManagerState {
+        let state = WindowManagerState::default();
+        for info in windows {
+            state.insert_window(info.clone());
+        }
+        state
+    }
+
+    #[test]
+    fn test_prune_orphan_windows() {
+        let windows: Vec<WindowInfo> = ["main", "consultation-1", "consultation-2", "patient-1"]
+            .iter()
+            .map(|id| window_info(id, id.split('-').next().unwrap(), "normal"))
+            .collect();
+        let state = state_with(&windows);
+
+        // consultation-2 和 patient-1 已被系统关闭按钮关闭
+        let live: HashSet<String> = ["main", "consultation-1", "settings-9"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let pruned = state.prune_orphan_windows(&live);
+        assert_eq!(pruned, vec!["consultation-2".to_string(), "patient-1".to_string()]);
+        state.with_windows(|windows| {
+            assert_eq!(windows.len(), 2);
+            assert!(windows.get("main").is_some());
+            assert!(windows.get("consultation-1").is_some());
+        });
+
+        // 再次对账不再有变化
+        assert!(state.prune_orphan_windows(&live).is_empty());
+    }
+
+    #[test]
+    fn test_pruned_consultation_frees_limit() {
+        let limits = WindowLimits { max_windows: 8, max_consultation_windows: 2, memory_threshold_mb: 512 };
+        let state = state_with(&[
+            window_info("consultation-1", "consultation", "normal"),
+            window_info("consultation-2", "consultation", "normal"),
+        ]);
+        assert!(state.with_windows(|windows| check_limits(windows, &limits, "consultation")).is_err());
+
+        let live: HashSet<String> = ["consultation-1".to_string()].into_iter().collect();
+        state.prune_orphan_windows(&live);
+        assert!(state.with_windows(|windows| check_limits(windows, &limits, "consultation")).is_ok());
+    }
+
+    #[test]
+    fn test_find_live_consultation_window() {
+        let state = state_with(&[
+            window_info("main", "main", "normal"),
+            window_info("consultation-1", "consultation", "minimized"),
+        ]);
+
+        // 已打开的问诊窗口直接复用
+        let found = state.find_live_consultation_window("c1", |_| true);
+        assert_eq!(found.as_deref(), Some("consultation-1"));
+        assert_eq!(state.with_windows(WindowRegistry::len), 2);
+
+        // 其他问诊没有窗口
+        assert!(state.find_live_consultation_window("c2", |_| true).is_none());
+        assert_eq!(state.with_windows(WindowRegistry::len), 2);
+    }
+
+    #[test]
+    fn test_find_consultation_window_with_dead_webview() {
+        let state = state_with(&[window_info("consultation-1", "consultation", "normal")]);
+
+        // 记录还在但窗口已销毁，应移除记录并走新建流程
+        let found = state.find_live_consultation_window("c1", |_| false);
+        assert!(found.is_none());
+        assert!(state.with_windows(|windows| windows.is_empty() && windows.consultation_windows.is_empty()));
+    }
+
+    #[test]
+    fn test_consultation_index_follows_window_changes() {
+        let state = state_with(&[window_info("main", "main", "normal")]);
+        assert!(state.with_windows(|windows| windows.consultation_windows.is_empty()));
+
+        state.insert_window(window_info("consultation-a", "consultation", "normal"));
+        assert_eq!(state.consultation_window_id("c1").as_deref(), Some("consultation-a"));
+
+        // 同一问诊换了新窗口后指向新窗口，关闭旧窗口不影响新的映射
+        state.insert_window(window_info("consultation-b", "consultation", "normal"));
+        assert_eq!(state.consultation_window_id("c1").as_deref(), Some("consultation-b"));
+        assert!(state.remove_window("consultation-a").is_some());
+        assert_eq!(state.consultation_window_id("c1").as_deref(), Some("consultation-b"));
+
+        // 同一窗口改为其他问诊时旧映射被移除
+        let mut moved = window_info("consultation-b", "consultation", "normal");
+        moved.data = Some(serde_json::json!({ "consultationId": "c2" }));
+        state.insert_window(moved);
+        assert!(state.consultation_window_id("c1").is_none());
+        assert_eq!(state.consultation_window_id("c2").as_deref(), Some("consultation-b"));
+
+        assert!(state.remove_window("consultation-b").is_some());
+        assert!(state.remove_window("consultation-b").is_none());
+        assert!(state.with_windows(|windows| windows.consultation_windows.is_empty()));
+    }
+
+    #[test]
+    fn test_new_window_id_format() {
+        let id = new_window_id(WindowKind::Consultation);
+        let suffix = id.strip_prefix("consultation-").unwrap();
+        assert_eq!(suffix.len(), 8);
+        assert!(suffix.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, new_window_id(WindowKind::Consultation));
+    }
+
+    #[test]
+    fn test_open_retries_when_label_taken() {
+        let mut counter = 0;
+        let mut next_id = |kind: WindowKind| {
+            counter += 1;
+            format!("{}-{}", kind.as_str(), counter)
+        };
+        let mut attempts = Vec::new();
+        let (window_id, opened) = open_with_unique_id(WindowKind::Patient, &mut next_id, |id| {
+            attempts.push(id.to_string());
+            if id == "patient-1" {
+                Err(tauri::Error::WindowLabelAlreadyExists(id.to_string()))
+            } else {
+                Ok(id.len())
+            }
+        })
+        .unwrap();
+        assert_eq!(window_id, "patient-2");
+        assert_eq!(opened, "patient-2".len());
+        assert_eq!(attempts, vec!["patient-1", "patient-2"]);
+
+        // 持续冲突时重试有上限
+        let mut calls = 0;
+        let result = open_with_unique_id(WindowKind::Patient, &mut next_id, |id| -> Result<(), tauri::Error> {
+            calls += 1;
+            Err(tauri::Error::WebviewLabelAlreadyExists(id.to_string()))
+        });
+        assert!(matches!(result, Err(tauri::Error::WebviewLabelAlreadyExists(_))));
+        assert_eq!(calls, MAX_WINDOW_ID_ATTEMPTS);
+
+        // 其他错误不重试
+        let mut calls = 0;
+        let result = open_with_unique_id(WindowKind::Patient, &mut next_id, |_| -> Result<(), tauri::Error> {
+            calls += 1;
+            Err(tauri::Error::WindowNotFound)
+        });
+        assert!(matches!(result, Err(tauri::Error::WindowNotFound)));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_window_kind_names() {
+        for kind in [WindowKind::Main, WindowKind::Consultation, WindowKind::Patient, WindowKind::Settings] {
+            assert_eq!(WindowKind::parse(kind.as_str()), Some(kind));
+        }
+        assert_eq!(WindowKind::parse("notification"), None);
+        assert_eq!(WindowKind::parse(""), None);
+    }
+
+    #[test]
+    fn test_reserved_slot_counts_toward_limits() {
+        let state = state_with(&[window_info("consultation-1", "consultation", "normal")]);
+        *state.limits.lock().unwrap() =
+            WindowLimits { max_windows: 3, max_consultation_windows: 2, memory_threshold_mb: 512 };
+
+        // 创建中的问诊窗口占用名额
+        let slot = state.reserve_slot("consultation").unwrap();
+        assert!(state.reserve_slot("consultation").is_err());
+        let patient = state.reserve_slot("patient").unwrap();
+        assert!(state.reserve_slot("settings").is_err());
+
+        // 创建失败时名额释放
+        drop(slot);
+        slot_filled(state.reserve_slot("consultation").unwrap(), "consultation-2", "c2");
+        drop(patient);
+        assert_eq!(state.with_windows(WindowRegistry::len), 2);
+        assert!(state.with_windows(|windows| windows.reserved.is_empty()));
+        assert_eq!(state.consultation_window_id("c2").as_deref(), Some("consultation-2"));
+    }
+
+    #[test]
+    #[should_panic(expected = "re-entrant access to window registry")]
+    fn test_reentrant_registry_access_panics() {
+        let state = state_with(&[window_info("main", "main", "normal")]);
+        state.with_windows(|_| state.consultation_window_id("c1"));
+    }
+
+    fn slot_filled(slot: WindowSlot<'_>, window_id: &str, consultation_id: &str) {
+        let mut info = window_info(window_id, "consultation", "normal");
+        info.data = Some(serde_json::json!({ "consultationId": consultation_id }));
+        slot.fill(info);
+    }
+
+    // 索引中的每一项都指向对应问诊的窗口，每个问诊窗口的问诊都能查到窗口
+    fn assert_registry_consistent(state: &WindowManagerState) {
+        state.with_windows(|windows| {
+            assert!(windows.reserved.is_empty());
+            for (consultation_id, window_id) in &windows.consultation_windows {
+                let info = windows.get(window_id).expect("index points to missing window");
+                assert_eq!(window_consultation_id(info), Some(consultation_id.as_str()));
+            }
+            for info in windows.values() {
+                if let Some(consultation_id) = window_consultation_id(info) {
+                    assert!(windows.consultation_window_id(consultation_id).is_some());
+                }
+            }
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_window_operations_stay_consistent() {
+        const TASKS: usize = 16;
+        const WINDOWS_PER_TASK: usize = 20;
+        let state = Arc::new(WindowManagerState::default());
+        *state.limits.lock().unwrap() = WindowLimits {
+            max_windows: TASKS * WINDOWS_PER_TASK,
+            max_consultation_windows: TASKS * WINDOWS_PER_TASK,
+            memory_threshold_mb: 512,
+        };
+
+        let tasks = (0..TASKS).map(|task| {
+            let state = state.clone();
+            tokio::spawn(async move {
+                for j in 0..WINDOWS_PER_TASK {
+                    let window_id = format!("consultation-{}-{}", task, j);
+                    let consultation_id = format!("c{}-{}", task, j % 3);
+                    // 名额在创建窗口的 await 期间保留
+                    let slot = state.reserve_slot("consultation").unwrap();
+                    tokio::task::yield_now().await;
+                    slot_filled(slot, &window_id, &consultation_id);
+
+                    state.with_windows_mut(|windows| {
+                        windows.update(&window_id, |info| info.last_focused = chrono::Utc::now());
+                    });
+                    tokio::task::yield_now().await;
+                    state.find_live_consultation_window(&consultation_id, |_| true);
+                    if j % 2 == 0 {
+                        assert!(state.remove_window(&window_id).is_some());
+                    }
+                    let _ = state.with_windows(|windows| serialize_windows(windows.values()));
+                }
+            })
+        });
+        let finished = tokio::time::timeout(std::time::Duration::from_secs(10), futures_util::future::join_all(tasks))
+            .await
+            .expect("window operations deadlocked");
+        assert!(finished.into_iter().all(|result| result.is_ok()));
+
+        let mut ids: Vec<String> = state.with_windows(|windows| windows.ids().map(str::to_string).collect());
+        ids.sort();
+        let mut expected: Vec<String> = (0..TASKS)
+            .flat_map(|task| (1..WINDOWS_PER_TASK).step_by(2).map(move |j| format!("consultation-{}-{}", task, j)))
+            .collect();
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert_registry_consistent(&state);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_creates_respect_limits() {
+        let state = Arc::new(WindowManagerState::default());
+        *state.limits.lock().unwrap() =
+            WindowLimits { max_windows: 10, max_consultation_windows: 5, memory_threshold_mb: 512 };
+
+        let tasks = (0..32).map(|i| {
+            let state = state.clone();
+            tokio::spawn(async move {
+                let Ok(slot) = state.reserve_slot("consultation") else {
+                    return false;
+                };
+                tokio::task::yield_now().await;
+                slot_filled(slot, &format!("consultation-{}", i), &format!("c{}", i));
+                true
+            })
+        });
+        let created = tokio::time::timeout(std::time::Duration::from_secs(10), futures_util::future::join_all(tasks))
+            .await
+            .expect("window creation deadlocked")
+            .into_iter()
+            .filter(|result| *result.as_ref().unwrap())
+            .count();
+
+        assert_eq!(created, 5);
+        assert_eq!(state.with_windows(WindowRegistry::len), 5);
+        assert_registry_consistent(&state);
+    }
+
+    #[test]
+    fn test_window_state_label() {
+        assert_eq!(window_state_label(false, false), "normal");
+        assert_eq!(window_state_label(false, true), "maximized");
+        assert_eq!(window_state_label(true, true), "minimized");
+    }
+
+    #[test]
+    fn test_should_restore_only_active_consultations() {
+        let is_active = |id: &str| id == "active";
+
+        assert!(should_restore(&persisted("main", None), is_active));
+        assert!(should_restore(&persisted("settings", None), is_active));
+        assert!(should_restore(
+            &persisted("consultation", Some(serde_json::json!({ "consultationId": "active" }))),
+            is_active
+        ));
+        assert!(!should_restore(
+            &persisted("consultation", Some(serde_json::json!({ "consultationId": "done" }))),
+            is_active
+        ));
+        assert!(!should_restore(&persisted("consultation", None), is_active));
+        assert!(!should_restore(&persisted("unknown", None), is_active));
+    }
+
+    #[test]
+    fn test_title_recomputed_for_each_window_type() {
+        let data = |value: serde_json::Value| Some(value);
+
+        assert_eq!(get_window_title("main", &None), "互联网医院 - 工作台");
+        assert_eq!(get_window_title("settings", &data(serde_json::json!({ "patientName": "张三" }))), "设置");
+        assert_eq!(get_window_title("unknown", &None), "互联网医院");
+
+        assert_eq!(get_window_title("patient", &None), "患者管理");
+        assert_eq!(get_window_title("patient", &data(serde_json::json!({ "patientName": "张三" }))), "患者详情 - 张三");
+
+        assert_eq!(get_window_title("consultation", &None), "问诊窗口");
+        assert_eq!(
+            get_window_title("consultation", &data(serde_json::json!({ "patientName": "张三", "status": "active" }))),
+            "问诊 - 张三"
+        );
+        assert_eq!(
+            get_window_title("consultation", &data(serde_json::json!({ "patientName": "张三", "status": "completed" }))),
+            "问诊 - 张三（已完成）"
+        );
+        assert_eq!(
+            get_window_title("consultation", &data(serde_json::json!({ "status": "cancelled" }))),
+            "问诊窗口（已取消）"
+        );
+    }
+
+    #[test]
+    fn test_context_merge_updates_title() {
+        let current = Some(serde_json::json!({ "consultationId": "c1", "patientName": "张山" }));
+        let merged = merge_window_data(current, serde_json::json!({ "patientName": "张三" }));
+        assert_eq!(merged["consultationId"], "c1");
+        assert_eq!(get_window_title("consultation", &Some(merged.clone())), "问诊 - 张三");
+
+        let merged = merge_window_data(Some(merged), serde_json::json!({ "status": "completed" }));
+        assert_eq!(get_window_title("consultation", &Some(merged)), "问诊 - 张三（已完成）");
+
+        assert_eq!(merge_window_data(None, serde_json::json!({ "a": 1 })), serde_json::json!({ "a": 1 }));
+    }
+
+    // 各窗口不重叠，面积之和等于工作区面积，且都落在工作区内
+    fn assert_tiles(rects: &[LayoutRect], area: &MonitorBounds) {
+        let total: f64 = rects.iter().map(|r| r.width * r.height).sum();
+        assert_eq!(total, area.width * area.height);
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.width > 0.0 && a.height > 0.0);
+            assert!(a.x >= area.x && a.y >= area.y);
+            assert!(a.x + a.width <= area.x + area.width && a.y + a.height <= area.y + area.height);
+            for b in &rects[i + 1..] {
+                let overlap_x = a.x < b.x + b.width && b.x < a.x + a.width;
+                let overlap_y = a.y < b.y + b.height && b.y < a.y + a.height;
+                assert!(!(overlap_x && overlap_y), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_tiles_work_area_for_all_presets() {
+        // 带任务栏的工作区和无法整除的宽度
+        let taskbar = MonitorBounds { x: 0.0, y: 0.0, width: 1920.0, height: 1040.0 };
+        let odd = MonitorBounds { x: -1366.0, y: 25.0, width: 1366.0, height: 743.0 };
+        for area in [PRIMARY, SECONDARY, taskbar, odd] {
+            for preset in [LayoutPreset::Grid, LayoutPreset::Columns, LayoutPreset::FocusLeft] {
+                for count in 1..=7 {
+                    let rects = compute_layout(preset, &area, count);
+                    assert_eq!(rects.len(), count);
+                    assert_tiles(&rects, &area);
+                }
+            }
+        }
+        assert!(compute_layout(LayoutPreset::Grid, &PRIMARY, 0).is_empty());
+    }
+
+    #[test]
+    fn test_columns_layout() {
+        let rects = compute_layout(LayoutPreset::Columns, &SECONDARY, 3);
+        assert_eq!(rects[0], LayoutRect { x: 1920.0, y: 0.0, width: 427.0, height: 1024.0 });
+        assert_eq!(rects[1], LayoutRect { x: 2347.0, y: 0.0, width: 426.0, height: 1024.0 });
+        assert_eq!(rects[2], LayoutRect { x: 2773.0, y: 0.0, width: 427.0, height: 1024.0 });
+    }
+
+    #[test]
+    fn test_grid_layout_last_row_spans_width() {
+        let rects = compute_layout(LayoutPreset::Grid, &PRIMARY, 4);
+        assert_eq!(rects[3], LayoutRect { x: 960.0, y: 540.0, width: 960.0, height: 540.0 });
+
+        // 5 个窗口排成 3 + 2，第二行两个窗口平分整行
+        let rects = compute_layout(LayoutPreset::Grid, &PRIMARY, 5);
+        assert_eq!(rects[0], LayoutRect { x: 0.0, y: 0.0, width: 640.0, height: 540.0 });
+        assert_eq!(rects[3], LayoutRect { x: 0.0, y: 540.0, width: 960.0, height: 540.0 });
+        assert_eq!(rects[4], LayoutRect { x: 960.0, y: 540.0, width: 960.0, height: 540.0 });
+    }
+
+    #[test]
+    fn test_focus_left_layout() {
+        let rects = compute_layout(LayoutPreset::FocusLeft, &PRIMARY, 3);
+        assert_eq!(rects[0], LayoutRect { x: 0.0, y: 0.0, width: 1152.0, height: 1080.0 });
+        assert_eq!(rects[1], LayoutRect { x: 1152.0, y: 0.0, width: 768.0, height: 540.0 });
+        assert_eq!(rects[2], LayoutRect { x: 1152.0, y: 540.0, width: 768.0, height: 540.0 });
+
+        let single = compute_layout(LayoutPreset::FocusLeft, &SECONDARY, 1);
+        assert_eq!(single, vec![LayoutRect { x: 1920.0, y: 0.0, width: 1280.0, height: 1024.0 }]);
+    }
+
+    #[test]
+    fn test_layout_preset_names() {
+        for preset in [LayoutPreset::Grid, LayoutPreset::Columns, LayoutPreset::FocusLeft] {
+            assert_eq!(LayoutPreset::parse(preset.as_str()), Some(preset));
+            assert_eq!(serde_json::to_value(preset).unwrap(), serde_json::json!(preset.as_str()));
+        }
+        assert_eq!(LayoutPreset::parse("cascade"), None);
+    }
+
+    #[test]
+    fn test_pinned_defaults_to_false() {
+        let mut value = serde_json::to_value(window_info("consultation-1", "consultation", "normal")).unwrap();
+        value.as_object_mut().unwrap().remove("pinned");
+        let info: WindowInfo = serde_json::from_value(value).unwrap();
+        assert!(!info.pinned);
+    }
+}
@@ -4,17 +4,33 @@ use rusqlite::{Connection, OpenFlags, Result};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tauri::{AppHandle, Manager};
+use crate::database::encryption::{
+    apply_key, encrypt_plaintext_database, is_plaintext_database, load_database_config, resolve_key, verify_readable,
+    DatabaseKey, EncryptionProgress, EncryptionStep, DATABASE_CONFIG_FILE,
+};
+use crate::database::dao::patient_dao::init_field_crypto;
 use crate::database::migrations::MigrationManager;
+use crate::database::readiness::{DatabaseReadiness, InitPhase};
+use crate::database::retry::BUSY_TIMEOUT;
+use crate::services::BACKUP_DIR_NAME;
+use crate::utils::keychain::local_data_crypto;
 
 pub type DbConnection = Arc<Mutex<Connection>>;
 
 pub struct DatabaseManager {
     connection: DbConnection,
     db_path: PathBuf,
+    // SQLCipher 模式下的整库密钥，明文模式为 None
+    key: Option<DatabaseKey>,
 }
 
 impl DatabaseManager {
-    pub async fn new(app: &AppHandle) -> Result<Self, Box<dyn std::error::Error>> {
+    // 打开数据库并配置连接，迁移由调用方单独执行以便上报进度；
+    // 配置为加密模式而现有文件仍是明文时，先迁移为加密库
+    pub fn open(
+        app: &AppHandle,
+        on_encrypt_progress: impl FnMut(EncryptionProgress),
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let app_dir = app
             .path()
             .app_data_dir()
@@ -25,7 +41,22 @@ impl DatabaseManager {
             .map_err(|e| format!("Failed to create app data dir: {}", e))?;
 
         let db_path = app_dir.join("telemedicine.db");
+        // 患者字段加密和整库密钥都使用钥匙串中的密钥，钥匙串不可用时不打开数据库，避免用其他密钥写入
+        let crypto = local_data_crypto().map_err(|e| format!("Failed to load data key from keychain: {}", e))?;
+        let key = resolve_key(&load_database_config(&app_dir.join(DATABASE_CONFIG_FILE)), &crypto)?;
+        init_field_crypto(crypto);
+
+        if let Some(key) = &key {
+            if is_plaintext_database(&db_path) {
+                tracing::info!("Migrating plaintext database to SQLCipher: {:?}", db_path);
+                encrypt_plaintext_database(&db_path, key, &app_dir.join(BACKUP_DIR_NAME), on_encrypt_progress)?;
+            }
+        }
 
+        Self::open_path(db_path, key)
+    }
+
+    pub fn open_path(db_path: PathBuf, key: Option<DatabaseKey>) -> Result<Self, Box<dyn std::error::Error>> {
         // 创建数据库连接，启用外键约束和WAL模式
         let conn = Connection::open_with_flags(
             &db_path,
@@ -34,27 +65,36 @@ impl DatabaseManager {
                 | OpenFlags::SQLITE_OPEN_NO_MUTEX,
         )?;
 
+        // 密钥必须在其他语句之前设置，错误的密钥在这里就报出来
+        if let Some(key) = &key {
+            apply_key(&conn, key)?;
+        }
+        verify_readable(&conn, key.is_some())?;
+
         // 配置数据库
         Self::configure_connection(&conn)?;
 
-        let connection = Arc::new(Mutex::new(conn));
-
-        let manager = Self {
-            connection: connection.clone(),
+        Ok(Self {
+            connection: Arc::new(Mutex::new(conn)),
             db_path,
-        };
-
-        // 运行数据库迁移
-        manager.run_migrations().await?;
+            key,
+        })
+    }
 
-        println!("Database initialized at: {:?}", manager.db_path);
-        Ok(manager)
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
     }
 
     fn configure_connection(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
         // 启用外键约束
         conn.execute("PRAGMA foreign_keys = ON", [])?;
 
+        // 其他连接持有写锁时等待一段时间（PRAGMA busy_timeout），而不是立即返回 database is locked
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        // 新建的数据库启用增量整理，数据保留任务清理后可回收空间（需在建表前设置）
+        conn.execute("PRAGMA auto_vacuum = INCREMENTAL", [])?;
+
         // 启用WAL模式以提高并发性能
         conn.execute("PRAGMA journal_mode = WAL", [])?;
 
@@ -80,6 +120,15 @@ impl DatabaseManager {
         Ok(())
     }
 
+    // 启动后预热：读取常用表让页面进入 SQLite 缓存，首屏查询不必等磁盘
+    pub fn warm_up(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        for table in ["patients", "consultations", "messages"] {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0))?;
+        }
+        Ok(())
+    }
+
     pub fn get_connection(&self) -> DbConnection {
         self.connection.clone()
     }
@@ -88,9 +137,10 @@ impl DatabaseManager {
         &self.db_path
     }
 
-    // 数据库健康检查
+    // 数据库健康检查，加密模式下密钥错误返回 DB_KEY_INVALID 而不是通用的数据库错误
     pub fn health_check(&self) -> Result<bool, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        verify_readable(&conn, self.is_encrypted())?;
         let mut stmt = conn.prepare("SELECT 1")?;
         let result: i32 = stmt.query_row([], |row| row.get(0))?;
         Ok(result == 1)
@@ -126,7 +176,7 @@ impl DatabaseManager {
         })
     }
 
-    // 数据库备份
+    // 数据库备份，加密模式下备份文件使用同一密钥加密
     pub fn backup(&self, backup_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
 
@@ -137,24 +187,22 @@ impl DatabaseManager {
 
         // 执行备份
         let mut backup_conn = Connection::open(backup_path)?;
+        if let Some(key) = &self.key {
+            apply_key(&backup_conn, key)?;
+        }
         let backup = rusqlite::backup::Backup::new(&*conn, &mut backup_conn)?;
         backup.run_to_completion(5, std::time::Duration::from_millis(250), None)?;
 
-        println!("Database backup completed: {:?}", backup_path);
+        tracing::info!("Database backup completed: {:?}", backup_path);
         Ok(())
     }
 
-    // 清理过期缓存
+    // 清理过期缓存，固定的文件保留
     pub fn cleanup_expired_cache(&self) -> Result<usize, Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
-
-        let deleted = conn.execute(
-            "DELETE FROM file_cache WHERE expires_at IS NOT NULL AND expires_at < datetime('now')",
-            [],
-        )?;
+        let deleted = crate::database::dao::FileCacheDao::with_connection(self.connection.clone()).cleanup_expired(false)?.len();
 
         if deleted > 0 {
-            println!("Cleaned up {} expired cache entries", deleted);
+            tracing::info!("Cleaned up {} expired cache entries", deleted);
         }
 
         Ok(deleted)
@@ -171,8 +219,25 @@ pub struct DatabaseStats {
 static mut DATABASE_MANAGER: Option<DatabaseManager> = None;
 static INIT: std::sync::Once = std::sync::Once::new();
 
-pub async fn init_database(app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
-    let manager = DatabaseManager::new(app).await?;
+// 打开数据库并执行迁移，完成后通知等待中的命令；失败由调用方标记
+pub async fn init_database(app: &AppHandle, readiness: &DatabaseReadiness) -> Result<(), Box<dyn std::error::Error>> {
+    if try_get_database().is_some() {
+        readiness.mark_database_ready();
+        return Ok(());
+    }
+
+    readiness.set_phase(InitPhase::OpenDatabase);
+    let manager = DatabaseManager::open(app, |progress| {
+        if progress.step == EncryptionStep::BackupOriginal {
+            readiness.set_phase(InitPhase::EncryptDatabase);
+        }
+        tracing::info!("Database encryption {:?}: {}%", progress.step, progress.percent);
+        readiness.set_phase_progress(progress.percent);
+    })?;
+
+    readiness.set_phase(InitPhase::RunMigrations);
+    manager.run_migrations().await?;
+    tracing::info!("Database initialized at: {:?}", manager.db_path);
 
     unsafe {
         INIT.call_once(|| {
@@ -180,6 +245,7 @@ pub async fn init_database(app: &AppHandle) -> Result<(), Box<dyn std::error::Er
         });
     }
 
+    readiness.mark_database_ready();
     Ok(())
 }
 
@@ -189,6 +255,15 @@ pub fn get_database() -> &'static DatabaseManager {
     }
 }
 
+// 不 panic 的版本，供健康检查等可能在初始化前调用的场景使用；
+// 初始化完成前不读取 DATABASE_MANAGER，避免与 init_database 中的写入并发
+pub fn try_get_database() -> Option<&'static DatabaseManager> {
+    if !INIT.is_completed() {
+        return None;
+    }
+    unsafe { (*std::ptr::addr_of!(DATABASE_MANAGER)).as_ref() }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,8 +294,44 @@ mod tests {
         let manager = DatabaseManager {
             connection,
             db_path,
+            key: None,
         };
 
         assert!(manager.health_check().unwrap());
     }
+
+    #[test]
+    fn test_plaintext_backup_is_readable() {
+        let temp_dir = tempdir().unwrap();
+        let manager = DatabaseManager::open_path(temp_dir.path().join("test.db"), None).unwrap();
+        manager.connection.lock().unwrap().execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('a');").unwrap();
+
+        let backup_path = temp_dir.path().join("backups").join("backup.db");
+        manager.backup(&backup_path).unwrap();
+        assert!(is_plaintext_database(&backup_path));
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_mode_backup_and_wrong_key() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let key = DatabaseKey::from_bytes(&[1u8; 32]);
+        let manager = DatabaseManager::open_path(db_path.clone(), Some(key.clone())).unwrap();
+        manager.connection.lock().unwrap().execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('a');").unwrap();
+        assert!(manager.health_check().unwrap());
+
+        // 备份同样加密，用同一密钥可以打开
+        let backup_path = temp_dir.path().join("backup.db");
+        manager.backup(&backup_path).unwrap();
+        assert!(!is_plaintext_database(&backup_path));
+        let backup = DatabaseManager::open_path(backup_path, Some(key)).unwrap();
+        let value: String = backup.connection.lock().unwrap().query_row("SELECT v FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(value, "a");
+        drop(manager);
+
+        let error = DatabaseManager::open_path(db_path, Some(DatabaseKey::from_bytes(&[2u8; 32]))).err().unwrap();
+        let error = error.downcast::<crate::utils::AppError>().unwrap();
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_DB_KEY_INVALID));
+    }
 }
\ No newline at end of file
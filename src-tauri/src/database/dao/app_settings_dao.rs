@@ -0,0 +1,70 @@
+// 应用全局配置数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::AppConfig;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use chrono::Utc;
+
+const KEY_APP_CONFIG: &str = "app_config";
+// AppConfig 新增字段时递增，读取旧版本时由 serde 默认值补齐并回写
+pub const APP_CONFIG_SCHEMA_VERSION: i64 = 5;
+
+pub struct AppSettingsDao {
+    connection: DbConnection,
+}
+
+impl AppSettingsDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 返回配置及保存时的 schema 版本，从未保存过返回 None
+    pub fn load(&self) -> Result<Option<(AppConfig, i64)>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let row: Option<(String, i64)> = conn
+            .query_row(
+                "SELECT value, schema_version FROM app_settings WHERE key = ?1",
+                params![KEY_APP_CONFIG],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        match row {
+            Some((value, schema_version)) => Ok(Some((serde_json::from_str(&value)?, schema_version))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, config: &AppConfig, updated_by: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        Self::save_in(&conn, config, updated_by)
+    }
+
+    pub fn save_in(conn: &Connection, config: &AppConfig, updated_by: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO app_settings (key, value, schema_version, updated_by, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, schema_version = excluded.schema_version,
+                 updated_by = excluded.updated_by, updated_at = excluded.updated_at",
+            params![
+                KEY_APP_CONFIG,
+                serde_json::to_string(config)?,
+                APP_CONFIG_SCHEMA_VERSION,
+                updated_by,
+                Utc::now()
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl Default for AppSettingsDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
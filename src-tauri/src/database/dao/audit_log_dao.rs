@@ -1,9 +1,10 @@
 // 审计日志数据访问层
 
 use crate::database::connection::{get_database, DbConnection};
-use crate::database::dao::{BaseDao, PageResult};
-use crate::models::AuditLog;
-use rusqlite::{params, Result};
+use crate::database::dao::{BaseDao, PageResult, QueryBuilder};
+use crate::models::{AuditLog, AuditLogFilter};
+use crate::utils::SqlTimestamp;
+use rusqlite::{params, Connection, Result};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -18,6 +19,10 @@ impl AuditLogDao {
         }
     }
 
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
     pub fn find_by_user_id(&self, user_id: &str, page: i32, page_size: i32) -> Result<PageResult<AuditLog>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let offset = (page - 1) * page_size;
@@ -44,7 +49,7 @@ impl AuditLogDao {
                 ).unwrap_or_default(),
                 ip_address: row.get(6)?,
                 user_agent: row.get(7)?,
-                created_at: row.get(8)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
             })
         })?;
 
@@ -82,7 +87,7 @@ impl AuditLogDao {
                 ).unwrap_or_default(),
                 ip_address: row.get(6)?,
                 user_agent: row.get(7)?,
-                created_at: row.get(8)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
             })
         })?;
 
@@ -113,7 +118,7 @@ impl AuditLogDao {
                 ).unwrap_or_default(),
                 ip_address: row.get(6)?,
                 user_agent: row.get(7)?,
-                created_at: row.get(8)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
             })
         })?;
 
@@ -144,7 +149,80 @@ impl AuditLogDao {
                 ).unwrap_or_default(),
                 ip_address: row.get(6)?,
                 user_agent: row.get(7)?,
-                created_at: row.get(8)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
+            })
+        })?;
+
+        let mut logs = Vec::new();
+        for log in log_iter {
+            logs.push(log?);
+        }
+
+        Ok(logs)
+    }
+
+    // 某用户自 since 起的日志，按时间升序，供异常访问检测使用
+    pub fn find_by_user_since(&self, user_id: &str, since: DateTime<Utc>) -> Result<Vec<AuditLog>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at
+             FROM audit_logs WHERE user_id = ?1 AND created_at >= ?2 ORDER BY created_at ASC"
+        )?;
+
+        let log_iter = stmt.query_map(params![user_id, SqlTimestamp(since)], |row| {
+            Ok(AuditLog {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                action: row.get(2)?,
+                resource_type: row.get(3)?,
+                resource_id: row.get(4)?,
+                details: row.get::<_, Option<String>>(5)?.map(|s|
+                    serde_json::from_str(&s).unwrap_or_default()
+                ).unwrap_or_default(),
+                ip_address: row.get(6)?,
+                user_agent: row.get(7)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
+            })
+        })?;
+
+        let mut logs = Vec::new();
+        for log in log_iter {
+            logs.push(log?);
+        }
+
+        Ok(logs)
+    }
+
+    // 按筛选条件读取 after 之后的一页日志，按 (created_at, id) 升序做键集分页，导出时逐页读取
+    pub fn find_filtered_after(&self, filter: &AuditLogFilter, after: Option<&AuditLog>, limit: i64) -> Result<Vec<AuditLog>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut builder = filter_builder(filter);
+
+        if let Some(last) = after {
+            let cursor = SqlTimestamp(last.created_at);
+            builder = builder.add_condition(
+                "(created_at > ? OR (created_at = ? AND id > ?))",
+                vec![Box::new(cursor), Box::new(cursor), Box::new(last.id.clone())],
+            );
+        }
+
+        let builder = builder.order_by("created_at ASC, id ASC").limit(limit);
+        let (sql, values) = builder.build(AUDIT_LOG_SELECT);
+
+        let mut stmt = conn.prepare(&sql)?;
+        let log_iter = stmt.query_map(values.as_slice(), |row| {
+            Ok(AuditLog {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                action: row.get(2)?,
+                resource_type: row.get(3)?,
+                resource_id: row.get(4)?,
+                details: row.get::<_, Option<String>>(5)?.map(|s|
+                    serde_json::from_str(&s).unwrap_or_default()
+                ).unwrap_or_default(),
+                ip_address: row.get(6)?,
+                user_agent: row.get(7)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
             })
         })?;
 
@@ -156,16 +234,67 @@ impl AuditLogDao {
         Ok(logs)
     }
 
+    pub fn count_filtered(&self, filter: &AuditLogFilter) -> Result<i64, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let builder = filter_builder(filter);
+        let (sql, values) = builder.build_count("audit_logs");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let total: i64 = stmt.query_row(values.as_slice(), |row| row.get(0))?;
+        Ok(total)
+    }
+
+    // 按筛选条件分页查询，最新的在前
+    pub fn query(&self, filter: &AuditLogFilter, page: i32, page_size: i32) -> Result<PageResult<AuditLog>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let page = page.max(1);
+        let page_size = page_size.max(1);
+        let builder = filter_builder(filter);
+
+        let (count_sql, count_values) = builder.build_count("audit_logs");
+        let total: i64 = conn.query_row(&count_sql, count_values.as_slice(), |row| row.get(0))?;
+
+        let builder = builder
+            .order_by("created_at DESC, id DESC")
+            .limit(page_size as i64)
+            .offset(((page - 1) * page_size) as i64);
+        let (sql, values) = builder.build(AUDIT_LOG_SELECT);
+        let mut stmt = conn.prepare(&sql)?;
+        let logs = stmt
+            .query_map(values.as_slice(), |row| {
+                Ok(AuditLog {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    action: row.get(2)?,
+                    resource_type: row.get(3)?,
+                    resource_id: row.get(4)?,
+                    details: row.get::<_, Option<String>>(5)?.map(|s|
+                        serde_json::from_str(&s).unwrap_or_default()
+                    ).unwrap_or_default(),
+                    ip_address: row.get(6)?,
+                    user_agent: row.get(7)?,
+                    created_at: row.get::<_, SqlTimestamp>(8)?.0,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(PageResult::new(logs, total, page, page_size))
+    }
+
     pub fn cleanup_old_logs(&self, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        Self::cleanup_old_logs_in(&conn, days)
+    }
 
+    // 在调用方的事务内删除超过保留天数的日志
+    pub fn cleanup_old_logs_in(conn: &Connection, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
         let deleted = conn.execute(
-            "DELETE FROM audit_logs WHERE created_at < datetime('now', '-' || ?1 || ' days')",
-            params![days],
+            "DELETE FROM audit_logs WHERE created_at < ?1",
+            params![SqlTimestamp::days_ago(days)],
         )?;
 
         if deleted > 0 {
-            println!("Cleaned up {} old audit logs (older than {} days)", deleted, days);
+            tracing::info!("Cleaned up {} old audit logs (older than {} days)", deleted, days);
         }
 
         Ok(deleted)
@@ -176,12 +305,12 @@ impl AuditLogDao {
         let mut stmt = conn.prepare(
             "SELECT action, COUNT(*) as count
              FROM audit_logs
-             WHERE created_at >= datetime('now', '-' || ?1 || ' days')
+             WHERE created_at >= ?1
              GROUP BY action
              ORDER BY count DESC"
         )?;
 
-        let stat_iter = stmt.query_map(params![days], |row| {
+        let stat_iter = stmt.query_map(params![SqlTimestamp::days_ago(days)], |row| {
             Ok(ActionStat {
                 action: row.get(0)?,
                 count: row.get(1)?,
@@ -214,6 +343,38 @@ impl AuditLogDao {
     }
 }
 
+const AUDIT_LOG_SELECT: &str =
+    "SELECT id, user_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at FROM audit_logs";
+
+// 筛选条件对应的查询构建器
+fn filter_builder(filter: &AuditLogFilter) -> QueryBuilder {
+    let mut builder = QueryBuilder::new();
+
+    if let Some(user_id) = &filter.user_id {
+        builder = builder.where_eq("user_id", user_id.clone());
+    }
+    if let Some(action) = &filter.action {
+        builder = builder.where_eq("action", action.clone());
+    }
+    if let Some(actions) = &filter.actions {
+        builder = builder.where_in("action", actions.iter().cloned());
+    }
+    if let Some(resource_type) = &filter.resource_type {
+        builder = builder.where_eq("resource_type", resource_type.clone());
+    }
+    if let Some(resource_id) = &filter.resource_id {
+        builder = builder.where_eq("resource_id", resource_id.clone());
+    }
+    match (filter.start_time, filter.end_time) {
+        (Some(start), Some(end)) => builder = builder.where_between_dates("created_at", start, end),
+        (Some(start), None) => builder = builder.add_condition("created_at >= ?", vec![Box::new(SqlTimestamp(start))]),
+        (None, Some(end)) => builder = builder.add_condition("created_at <= ?", vec![Box::new(SqlTimestamp(end))]),
+        (None, None) => {}
+    }
+
+    builder
+}
+
 #[derive(Debug, Clone)]
 pub struct ActionStat {
     pub action: String,
@@ -238,7 +399,7 @@ impl BaseDao<AuditLog> for AuditLogDao {
                 details_json,
                 log.ip_address,
                 log.user_agent,
-                log.created_at
+                SqlTimestamp(log.created_at)
             ],
         )?;
 
@@ -264,7 +425,7 @@ impl BaseDao<AuditLog> for AuditLogDao {
                 ).unwrap_or_default(),
                 ip_address: row.get(6)?,
                 user_agent: row.get(7)?,
-                created_at: row.get(8)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
             })
         });
 
@@ -290,7 +451,7 @@ impl BaseDao<AuditLog> for AuditLogDao {
                 details_json,
                 log.ip_address,
                 log.user_agent,
-                log.created_at,
+                SqlTimestamp(log.created_at),
                 log.id
             ],
         )?;
@@ -323,7 +484,7 @@ impl BaseDao<AuditLog> for AuditLogDao {
                 ).unwrap_or_default(),
                 ip_address: row.get(6)?,
                 user_agent: row.get(7)?,
-                created_at: row.get(8)?,
+                created_at: row.get::<_, SqlTimestamp>(8)?.0,
             })
         })?;
 
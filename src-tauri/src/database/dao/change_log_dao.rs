@@ -0,0 +1,335 @@
+// 患者、问诊变更记录数据访问层：写入方在修改数据的同一事务内调用 record_in，提交后调用 publish_data_changes
+// 通知进程内的订阅者；窗口错过通知时按 seq 从 change_log 补齐
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::{ChangeEntity, ChangeOp, DataChangePage, DataChanged};
+use chrono::{Duration, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+pub const DATA_CHANGED_EVENT: &str = "data-changed";
+
+// 窗口隐藏期间积压的变更通常不多，超过后由前端重新加载列表
+const DATA_CHANGE_CAPACITY: usize = 256;
+
+static DATA_CHANGES: OnceLock<broadcast::Sender<DataChanged>> = OnceLock::new();
+
+fn data_change_sender() -> &'static broadcast::Sender<DataChanged> {
+    DATA_CHANGES.get_or_init(|| broadcast::channel(DATA_CHANGE_CAPACITY).0)
+}
+
+/// 订阅患者、问诊的变更，仅在写入提交后通知
+pub fn subscribe_data_changes() -> broadcast::Receiver<DataChanged> {
+    data_change_sender().subscribe()
+}
+
+// 事务提交后调用；没有订阅者时发送失败，忽略即可
+pub fn publish_data_changes(changes: &[DataChanged]) {
+    for change in changes {
+        let _ = data_change_sender().send(change.clone());
+    }
+}
+
+pub struct ChangeLogDao {
+    connection: DbConnection,
+}
+
+impl ChangeLogDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 在写入数据的同一事务内记录变更，事务回滚时变更记录一并撤销
+    pub fn record_in(conn: &Connection, entity: ChangeEntity, id: &str, op: ChangeOp) -> Result<DataChanged, Box<dyn std::error::Error>> {
+        let changed_at = Utc::now();
+        let seq = conn.query_row(
+            "INSERT INTO change_log (entity, entity_id, op, changed_at) VALUES (?1, ?2, ?3, ?4) RETURNING seq",
+            params![entity.as_str(), id, op.as_str(), changed_at],
+            |row| row.get(0),
+        )?;
+
+        Ok(DataChanged {
+            seq,
+            entity,
+            id: id.to_string(),
+            op,
+            changed_at,
+        })
+    }
+
+    // upsert 写入前调用，按记录是否已存在区分新增和修改
+    pub fn upsert_op_in(conn: &Connection, entity: ChangeEntity, id: &str) -> Result<ChangeOp, Box<dyn std::error::Error>> {
+        let sql = match entity {
+            ChangeEntity::Patient => "SELECT 1 FROM patients WHERE id = ?1",
+            ChangeEntity::Consultation => "SELECT 1 FROM consultations WHERE id = ?1",
+        };
+        let exists = conn.query_row(sql, params![id], |_| Ok(())).optional()?.is_some();
+        Ok(if exists { ChangeOp::Update } else { ChangeOp::Insert })
+    }
+
+    // 返回 seq 大于 watermark 的该实体变更，按 seq 升序，最多 limit 条
+    pub fn find_since(&self, entity: ChangeEntity, watermark: i64, limit: i64) -> Result<DataChangePage, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        // 已分配的最大 seq，change_log 被清空后仍保留
+        let latest: i64 = conn
+            .query_row("SELECT seq FROM sqlite_sequence WHERE name = 'change_log'", [], |row| row.get(0))
+            .optional()?
+            .unwrap_or(0);
+        let oldest: Option<i64> = conn.query_row("SELECT MIN(seq) FROM change_log", [], |row| row.get(0))?;
+
+        // watermark 之后的变更已有部分被清理，或 watermark 来自另一个数据库（例如恢复备份后）
+        let oldest_available = oldest.unwrap_or(latest + 1);
+        if watermark > latest || (watermark < latest && watermark + 1 < oldest_available) {
+            return Ok(DataChangePage {
+                entity,
+                changes: Vec::new(),
+                watermark: latest,
+                has_more: false,
+                reset_required: true,
+            });
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT seq, entity, entity_id, op, changed_at FROM change_log
+             WHERE entity = ?1 AND seq > ?2 ORDER BY seq LIMIT ?3",
+        )?;
+        let mut changes = stmt
+            .query_map(params![entity.as_str(), watermark, limit + 1], map_change)?
+            .collect::<Result<Vec<DataChanged>>>()?;
+
+        let has_more = changes.len() as i64 > limit;
+        changes.truncate(limit.max(0) as usize);
+        // 没有更多变更时直接推进到最新的 seq，跳过其他实体的变更
+        let watermark = match changes.last() {
+            Some(last) if has_more => last.seq,
+            _ => latest,
+        };
+
+        Ok(DataChangePage {
+            entity,
+            changes,
+            watermark,
+            has_more,
+            reset_required: false,
+        })
+    }
+
+    // 由每日数据保留在事务内调用
+    pub fn delete_older_than_in(conn: &Connection, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
+        let cutoff = Utc::now() - Duration::days(days as i64);
+        let deleted = conn.execute("DELETE FROM change_log WHERE changed_at < ?1", params![cutoff])?;
+
+        if deleted > 0 {
+            tracing::info!("Deleted {} change log entries (older than {} days)", deleted, days);
+        }
+
+        Ok(deleted)
+    }
+}
+
+impl Default for ChangeLogDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn map_change(row: &Row) -> Result<DataChanged> {
+    let entity: String = row.get(1)?;
+    let op: String = row.get(3)?;
+
+    Ok(DataChanged {
+        seq: row.get(0)?,
+        entity: ChangeEntity::parse(&entity)
+            .ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, format!("未知实体: {}", entity).into()))?,
+        id: row.get(2)?,
+        op: ChangeOp::parse(&op)
+            .ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, format!("未知操作: {}", op).into()))?,
+        changed_at: row.get(4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::{BaseDao, ConsultationDao, PatientDao};
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Consultation, ConsultationPriority, Patient};
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn patient(name: &str) -> Patient {
+        let now = Utc::now();
+        Patient {
+            id: String::new(),
+            name: name.to_string(),
+            age: Some(30),
+            gender: None,
+            phone: None,
+            id_card: None,
+            tags: Vec::new(),
+            avatar_url: None,
+            last_sync: None,
+            last_visit: None,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        }
+    }
+
+    fn consultation(patient_id: &str) -> Consultation {
+        let now = Utc::now();
+        Consultation {
+            id: String::new(),
+            patient_id: patient_id.to_string(),
+            doctor_id: "d1".to_string(),
+            status: "pending".to_string(),
+            consultation_type: "text".to_string(),
+            title: None,
+            description: None,
+            diagnosis: None,
+            prescription: None,
+            created_at: now,
+            updated_at: now,
+            accepted_at: None,
+            completed_at: None,
+            cancel_reason: None,
+            version: 1,
+            priority: ConsultationPriority::Normal,
+            queued_at: None,
+        }
+    }
+
+    #[test]
+    fn test_sequence_monotonic_across_entities_and_pruning() {
+        let connection = create_test_connection();
+        let patients = PatientDao::with_connection(connection.clone());
+        let consultations = ConsultationDao::with_connection(connection.clone());
+        let changes = ChangeLogDao::with_connection(connection.clone());
+
+        let patient_id = patients.create(&patient("张三")).unwrap();
+        let consultation_id = consultations.create(&consultation(&patient_id)).unwrap();
+        let mut edited = patients.find_by_id(&patient_id).unwrap().unwrap();
+        edited.name = "张三丰".to_string();
+        patients.update(&edited).unwrap();
+
+        let patient_page = changes.find_since(ChangeEntity::Patient, 0, 100).unwrap();
+        let ops: Vec<ChangeOp> = patient_page.changes.iter().map(|c| c.op).collect();
+        assert_eq!(ops, vec![ChangeOp::Insert, ChangeOp::Update]);
+        assert_eq!(patient_page.watermark, 3);
+
+        let consultation_page = changes.find_since(ChangeEntity::Consultation, 0, 100).unwrap();
+        assert_eq!(consultation_page.changes.len(), 1);
+        assert_eq!(consultation_page.changes[0].id, consultation_id);
+        assert_eq!(consultation_page.changes[0].seq, 2);
+
+        // 清理全部记录后新分配的 seq 仍然递增，不会与已下发的 seq 重复
+        connection.lock().unwrap().execute("DELETE FROM change_log", []).unwrap();
+        patients.delete(&patient_id).unwrap();
+        let after_prune = changes.find_since(ChangeEntity::Patient, 3, 100).unwrap();
+        assert!(!after_prune.reset_required);
+        let seqs: Vec<i64> = after_prune.changes.iter().map(|c| c.seq).collect();
+        assert!(seqs.iter().all(|seq| *seq > 3));
+        assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_change_rolled_back_with_data() {
+        let connection = create_test_connection();
+        let patients = PatientDao::with_connection(connection.clone());
+        let changes = ChangeLogDao::with_connection(connection.clone());
+        let patient_id = patients.create(&patient("李四")).unwrap();
+
+        // 变更记录写入失败时患者修改一并回滚
+        connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER fail_change_log BEFORE INSERT ON change_log
+                 BEGIN SELECT RAISE(ABORT, 'change log insert failed'); END;",
+            )
+            .unwrap();
+        let mut edited = patients.find_by_id(&patient_id).unwrap().unwrap();
+        edited.name = "李四光".to_string();
+        assert!(patients.update(&edited).is_err());
+        assert_eq!(patients.find_by_id(&patient_id).unwrap().unwrap().name, "李四");
+
+        // 数据修改失败时不留下变更记录
+        connection.lock().unwrap().execute_batch("DROP TRIGGER fail_change_log").unwrap();
+        let stale = edited.clone();
+        patients.update(&edited).unwrap();
+        assert!(patients.update(&stale).is_err());
+
+        let page = changes.find_since(ChangeEntity::Patient, 0, 100).unwrap();
+        let ops: Vec<ChangeOp> = page.changes.iter().map(|c| c.op).collect();
+        assert_eq!(ops, vec![ChangeOp::Insert, ChangeOp::Update]);
+    }
+
+    #[test]
+    fn test_catch_up_after_missed_events() {
+        let connection = create_test_connection();
+        let patients = PatientDao::with_connection(connection.clone());
+        let changes = ChangeLogDao::with_connection(connection.clone());
+        let mut receiver = subscribe_data_changes();
+
+        let first = patients.create(&patient("王五")).unwrap();
+        // 通知通道是全局的，跳过并行测试产生的变更
+        let seen = loop {
+            match receiver.try_recv() {
+                Ok(change) if change.id == first => break change,
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                Err(e) => panic!("未收到变更通知: {}", e),
+            }
+        };
+        assert_eq!(seen.op, ChangeOp::Insert);
+
+        // 窗口隐藏期间错过的通知：从最后收到的 seq 补齐，分页取完
+        let mut missed = Vec::new();
+        for i in 0..5 {
+            missed.push(patients.create(&patient(&format!("患者{}", i))).unwrap());
+        }
+        patients.delete(&first).unwrap();
+
+        let mut watermark = seen.seq;
+        let mut caught_up = Vec::new();
+        loop {
+            let page = changes.find_since(ChangeEntity::Patient, watermark, 2).unwrap();
+            assert!(!page.reset_required);
+            caught_up.extend(page.changes);
+            watermark = page.watermark;
+            if !page.has_more {
+                break;
+            }
+        }
+        let ids: Vec<&str> = caught_up.iter().map(|c| c.id.as_str()).collect();
+        let mut expected: Vec<&str> = missed.iter().map(String::as_str).collect();
+        expected.push(&first);
+        assert_eq!(ids, expected);
+        assert_eq!(caught_up.last().unwrap().op, ChangeOp::Delete);
+        assert!(changes.find_since(ChangeEntity::Patient, watermark, 2).unwrap().changes.is_empty());
+
+        // 错过的变更已被清理时要求重新加载
+        connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM change_log WHERE seq <= ?1", params![seen.seq + 2])
+            .unwrap();
+        let page = changes.find_since(ChangeEntity::Patient, seen.seq, 100).unwrap();
+        assert!(page.reset_required);
+        assert_eq!(page.watermark, watermark);
+    }
+}
@@ -1,12 +1,48 @@
 // 问诊数据访问层
 
 use crate::database::connection::{get_database, DbConnection};
-use crate::database::dao::{BaseDao, PageResult};
-use crate::models::Consultation;
-use rusqlite::{params, Result};
+use crate::database::dao::{publish_data_changes, BaseDao, ChangeLogDao, ConflictError, MessageDao, PageResult};
+use crate::database::query_optimizer::{get_query_optimizer, query_cache_for, CACHE_TAG_MESSAGES, CACHE_TAG_PATIENTS};
+use crate::database::retry::retry_transaction_on_busy;
+use crate::models::{
+    message_preview_text, ChangeEntity, ChangeOp, Consultation, ConsultationPriority, ConsultationTransfer, ConversationOverview, DailyCount,
+    DailyLatency, DataChanged, Message, MessageType, TypeCount,
+};
+use crate::utils::{format_local, SqlTimestamp};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+// 问诊状态变更通知，窗口管理据此刷新标题并切换只读
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsultationStatusChange {
+    pub consultation_id: String,
+    pub status: String,
+}
+
+const STATUS_CHANGE_CAPACITY: usize = 64;
+
+static STATUS_CHANGES: OnceLock<broadcast::Sender<ConsultationStatusChange>> = OnceLock::new();
+
+fn status_change_sender() -> &'static broadcast::Sender<ConsultationStatusChange> {
+    STATUS_CHANGES.get_or_init(|| broadcast::channel(STATUS_CHANGE_CAPACITY).0)
+}
+
+/// 订阅问诊状态变更，仅在写入提交后通知
+pub fn subscribe_status_changes() -> broadcast::Receiver<ConsultationStatusChange> {
+    status_change_sender().subscribe()
+}
+
+fn publish_status_change(consultation_id: &str, status: &str) {
+    // 没有订阅者时发送失败，忽略即可
+    let _ = status_change_sender().send(ConsultationStatusChange {
+        consultation_id: consultation_id.to_string(),
+        status: status.to_string(),
+    });
+}
+
 pub struct ConsultationDao {
     connection: DbConnection,
 }
@@ -18,10 +54,74 @@ impl ConsultationDao {
         }
     }
 
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 写入远端同步下来的问诊（保留远端 ID），在调用方的事务内执行；调用方提交后发布返回的变更
+    pub fn upsert_in(conn: &Connection, consultation: &Consultation) -> Result<Vec<DataChanged>, Box<dyn std::error::Error>> {
+        let op = ChangeLogDao::upsert_op_in(conn, ChangeEntity::Consultation, &consultation.id)?;
+        conn.execute(
+            "INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription,
+                                        created_at, updated_at, accepted_at, completed_at, cancel_reason, priority, queued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, COALESCE(?16, ?10))
+             ON CONFLICT(id) DO UPDATE SET
+                patient_id = excluded.patient_id,
+                doctor_id = excluded.doctor_id,
+                status = excluded.status,
+                consultation_type = excluded.consultation_type,
+                title = excluded.title,
+                description = excluded.description,
+                diagnosis = excluded.diagnosis,
+                prescription = excluded.prescription,
+                updated_at = excluded.updated_at,
+                accepted_at = excluded.accepted_at,
+                completed_at = excluded.completed_at,
+                cancel_reason = excluded.cancel_reason,
+                priority = excluded.priority,
+                queued_at = COALESCE(?16, consultations.queued_at, excluded.created_at),
+                version = consultations.version + 1",
+            params![
+                consultation.id,
+                consultation.patient_id,
+                consultation.doctor_id,
+                consultation.status,
+                consultation.consultation_type,
+                consultation.title,
+                consultation.description,
+                consultation.diagnosis,
+                consultation.prescription,
+                consultation.created_at,
+                consultation.updated_at,
+                consultation.accepted_at,
+                consultation.completed_at,
+                consultation.cancel_reason,
+                consultation.priority,
+                consultation.queued_at
+            ],
+        )?;
+
+        let mut changes = vec![ChangeLogDao::record_in(conn, ChangeEntity::Consultation, &consultation.id, op)?];
+
+        // 服务器上已完成的问诊同样刷新患者最近就诊时间，只前进不后退
+        if consultation.status == "completed" {
+            let updated = conn.execute(
+                "UPDATE patients SET last_visit = ?2 WHERE id = ?1 AND (last_visit IS NULL OR last_visit < ?2)",
+                params![consultation.patient_id, consultation.completed_at.unwrap_or(consultation.updated_at)],
+            )?;
+            if updated > 0 {
+                changes.push(ChangeLogDao::record_in(conn, ChangeEntity::Patient, &consultation.patient_id, ChangeOp::Update)?);
+            }
+        }
+
+        Ok(changes)
+    }
+
     pub fn find_by_patient_id(&self, patient_id: &str) -> Result<Vec<Consultation>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at
+            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
              FROM consultations WHERE patient_id = ?1 ORDER BY created_at DESC"
         )?;
 
@@ -38,6 +138,12 @@ impl ConsultationDao {
                 prescription: row.get(8)?,
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
+                accepted_at: row.get(11)?,
+                completed_at: row.get(12)?,
+                cancel_reason: row.get(13)?,
+                version: row.get(14)?,
+                priority: row.get(15)?,
+                queued_at: row.get(16)?,
             })
         })?;
 
@@ -49,14 +155,16 @@ impl ConsultationDao {
         Ok(consultations)
     }
 
-    pub fn find_by_doctor_id(&self, doctor_id: &str) -> Result<Vec<Consultation>, Box<dyn std::error::Error>> {
+    // 某位医生接诊该患者的问诊记录
+    pub fn find_by_patient_and_doctor(&self, patient_id: &str, doctor_id: &str) -> Result<Vec<Consultation>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at
-             FROM consultations WHERE doctor_id = ?1 ORDER BY created_at DESC"
+            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
+             FROM consultations WHERE patient_id = ?1 AND doctor_id = ?2 ORDER BY created_at DESC"
         )?;
 
-        let consultation_iter = stmt.query_map(params![doctor_id], |row| {
+        let consultation_iter = stmt.query_map(params![patient_id, doctor_id], |row| {
             Ok(Consultation {
                 id: row.get(0)?,
                 patient_id: row.get(1)?,
@@ -69,6 +177,12 @@ impl ConsultationDao {
                 prescription: row.get(8)?,
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
+                accepted_at: row.get(11)?,
+                completed_at: row.get(12)?,
+                cancel_reason: row.get(13)?,
+                version: row.get(14)?,
+                priority: row.get(15)?,
+                queued_at: row.get(16)?,
             })
         })?;
 
@@ -80,6 +194,52 @@ impl ConsultationDao {
         Ok(consultations)
     }
 
+    // 该医生是否接诊过此患者
+    pub fn has_patient_for_doctor(&self, patient_id: &str, doctor_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM consultations WHERE patient_id = ?1 AND doctor_id = ?2)",
+            params![patient_id, doctor_id],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    pub fn find_by_doctor_id(&self, doctor_id: &str) -> Result<Vec<Consultation>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let sql = "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
+             FROM consultations WHERE doctor_id = ?1 ORDER BY created_at DESC";
+
+        let consultations = get_query_optimizer().execute_sql(&conn, "consultation_list_by_doctor", sql, || {
+            let mut stmt = conn.prepare(sql)?;
+            let consultation_iter = stmt.query_map(params![doctor_id], |row| {
+                Ok(Consultation {
+                    id: row.get(0)?,
+                    patient_id: row.get(1)?,
+                    doctor_id: row.get(2)?,
+                    status: row.get(3)?,
+                    consultation_type: row.get(4)?,
+                    title: row.get(5)?,
+                    description: row.get(6)?,
+                    diagnosis: row.get(7)?,
+                    prescription: row.get(8)?,
+                    created_at: row.get(9)?,
+                    updated_at: row.get(10)?,
+                    accepted_at: row.get(11)?,
+                    completed_at: row.get(12)?,
+                    cancel_reason: row.get(13)?,
+                    version: row.get(14)?,
+                    priority: row.get(15)?,
+                    queued_at: row.get(16)?,
+                })
+            })?;
+            consultation_iter.collect::<Result<Vec<Consultation>>>()
+        })?;
+
+        Ok(consultations)
+    }
+
     pub fn find_by_status(&self, status: &str, page: i32, page_size: i32) -> Result<PageResult<Consultation>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let offset = (page - 1) * page_size;
@@ -89,64 +249,166 @@ impl ConsultationDao {
         let total: i64 = count_stmt.query_row(params![status], |row| row.get(0))?;
 
         // 获取分页数据
-        let mut stmt = conn.prepare(
-            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at
-             FROM consultations WHERE status = ?1 ORDER BY created_at DESC LIMIT ?2 OFFSET ?3"
-        )?;
-
-        let consultation_iter = stmt.query_map(params![status, page_size, offset], |row| {
-            Ok(Consultation {
-                id: row.get(0)?,
-                patient_id: row.get(1)?,
-                doctor_id: row.get(2)?,
-                status: row.get(3)?,
-                consultation_type: row.get(4)?,
-                title: row.get(5)?,
-                description: row.get(6)?,
-                diagnosis: row.get(7)?,
-                prescription: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
+        let sql = "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
+             FROM consultations WHERE status = ?1 ORDER BY created_at DESC LIMIT ?2 OFFSET ?3";
+
+        let consultations = get_query_optimizer().execute_sql(&conn, "consultation_list_by_status", sql, || {
+            let mut stmt = conn.prepare(sql)?;
+            let consultation_iter = stmt.query_map(params![status, page_size, offset], |row| {
+                Ok(Consultation {
+                    id: row.get(0)?,
+                    patient_id: row.get(1)?,
+                    doctor_id: row.get(2)?,
+                    status: row.get(3)?,
+                    consultation_type: row.get(4)?,
+                    title: row.get(5)?,
+                    description: row.get(6)?,
+                    diagnosis: row.get(7)?,
+                    prescription: row.get(8)?,
+                    created_at: row.get(9)?,
+                    updated_at: row.get(10)?,
+                    accepted_at: row.get(11)?,
+                    completed_at: row.get(12)?,
+                    cancel_reason: row.get(13)?,
+                    version: row.get(14)?,
+                    priority: row.get(15)?,
+                    queued_at: row.get(16)?,
+                })
+            })?;
+            consultation_iter.collect::<Result<Vec<Consultation>>>()
         })?;
 
-        let mut consultations = Vec::new();
-        for consultation in consultation_iter {
-            consultations.push(consultation?);
-        }
-
         Ok(PageResult::new(consultations, total, page, page_size))
     }
 
-    pub fn update_status(&self, consultation_id: &str, status: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // 会话侧边栏：一条语句取出每个问诊的患者、最新消息和未读数，按最近活动排序
+    pub fn get_conversation_overviews(&self, doctor_id: &str, page: i32, page_size: i32) -> Result<PageResult<ConversationOverview>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let offset = (page - 1) * page_size;
+
+        let mut count_stmt = conn.prepare(
+            "SELECT COUNT(*) FROM consultations c JOIN patients p ON p.id = c.patient_id WHERE c.doctor_id = ?1"
+        )?;
+        let total: i64 = count_stmt.query_row(params![doctor_id], |row| row.get(0))?;
+
+        // 未读数只统计患者发来的消息，医生和系统消息不计入；消息与问诊的时间列格式不同，按 julianday 换算后排序
+        let sql = "WITH latest AS (
+                 SELECT m.consultation_id, m.message_type, m.content, m.timestamp,
+                        ROW_NUMBER() OVER (PARTITION BY m.consultation_id ORDER BY julianday(m.timestamp) DESC, m.rowid DESC) AS rn
+                 FROM messages m JOIN consultations c ON c.id = m.consultation_id
+                 WHERE c.doctor_id = ?1 AND m.deleted_at IS NULL
+             ),
+             unread AS (
+                 SELECT m.consultation_id, COUNT(*) AS unread_count
+                 FROM messages m JOIN consultations c ON c.id = m.consultation_id
+                 WHERE c.doctor_id = ?1 AND m.sender_type = 'patient' AND m.read_status = 'unread' AND m.deleted_at IS NULL
+                 GROUP BY m.consultation_id
+             )
+             SELECT c.id, c.patient_id, p.name, p.avatar_url, c.status, c.title,
+                    l.message_type, l.content, l.timestamp, COALESCE(l.timestamp, c.updated_at) AS last_activity,
+                    COALESCE(u.unread_count, 0)
+             FROM consultations c
+             JOIN patients p ON p.id = c.patient_id
+             LEFT JOIN latest l ON l.consultation_id = c.id AND l.rn = 1
+             LEFT JOIN unread u ON u.consultation_id = c.id
+             WHERE c.doctor_id = ?1
+             ORDER BY julianday(last_activity) DESC, c.id
+             LIMIT ?2 OFFSET ?3";
+
+        let overviews = get_query_optimizer().execute_sql(&conn, "conversation_overviews", sql, || {
+            let mut stmt = conn.prepare(sql)?;
+            let overview_iter = stmt.query_map(params![doctor_id, page_size, offset], |row| {
+                let message_type: Option<MessageType> = row.get(6)?;
+                let content: Option<String> = row.get(7)?;
+                let last_activity_at: DateTime<Utc> = row.get(9)?;
+                Ok(ConversationOverview {
+                    consultation_id: row.get(0)?,
+                    patient_id: row.get(1)?,
+                    patient_name: row.get(2)?,
+                    patient_avatar_url: row.get(3)?,
+                    status: row.get(4)?,
+                    title: row.get(5)?,
+                    last_message_preview: message_type.map(|t| message_preview_text(&t, content.as_deref())),
+                    last_message_at: row.get(8)?,
+                    last_activity_at,
+                    last_activity_at_local: format_local(last_activity_at),
+                    unread_count: row.get(10)?,
+                })
+            })?;
+            overview_iter.collect::<Result<Vec<ConversationOverview>>>()
+        })?;
+
+        Ok(PageResult::new(overviews, total, page, page_size))
+    }
+
+    // 按读取时的版本号更新状态，返回新的版本号
+    pub fn update_status(&self, consultation_id: &str, status: &str, expected_version: i64) -> Result<i64, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
         let now = Utc::now();
 
-        conn.execute(
-            "UPDATE consultations SET status = ?1, updated_at = ?2 WHERE id = ?3",
-            params![status, now, consultation_id],
+        let updated = tx.execute(
+            "UPDATE consultations SET status = ?1, updated_at = ?2, version = version + 1 WHERE id = ?3 AND version = ?4",
+            params![status, now, consultation_id, expected_version],
         )?;
+        if updated == 0 {
+            return Err(Box::new(ConflictError::new("consultation", consultation_id, expected_version)));
+        }
+        let change = ChangeLogDao::record_in(&tx, ChangeEntity::Consultation, consultation_id, ChangeOp::Update)?;
+        tx.commit()?;
 
-        Ok(())
+        publish_status_change(consultation_id, status);
+        publish_data_changes(&[change]);
+        Ok(expected_version + 1)
     }
 
     pub fn update_diagnosis(&self, consultation_id: &str, diagnosis: &str, prescription: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
         let now = Utc::now();
 
-        conn.execute(
-            "UPDATE consultations SET diagnosis = ?1, prescription = ?2, updated_at = ?3 WHERE id = ?4",
+        let updated = tx.execute(
+            "UPDATE consultations SET diagnosis = ?1, prescription = ?2, updated_at = ?3, version = version + 1 WHERE id = ?4",
             params![diagnosis, prescription, now, consultation_id],
         )?;
-
+        let change = if updated > 0 {
+            Some(ChangeLogDao::record_in(&tx, ChangeEntity::Consultation, consultation_id, ChangeOp::Update)?)
+        } else {
+            None
+        };
+        tx.commit()?;
+
+        publish_data_changes(change.as_slice());
         Ok(())
     }
 
+    // 调整候诊优先级，优先级没有变化时返回 false 且不记录变更
+    pub fn set_priority(&self, consultation_id: &str, priority: ConsultationPriority) -> Result<bool, Box<dyn std::error::Error>> {
+        let change = retry_transaction_on_busy(&self.connection, "set consultation priority", |tx| {
+            let updated = tx.execute(
+                "UPDATE consultations SET priority = ?2, updated_at = ?3, version = version + 1 WHERE id = ?1 AND priority <> ?2",
+                params![consultation_id, priority, Utc::now()],
+            )?;
+            if updated == 0 {
+                return Ok(None);
+            }
+            Ok(Some(ChangeLogDao::record_in(tx, ChangeEntity::Consultation, consultation_id, ChangeOp::Update)?))
+        })?;
+
+        publish_data_changes(change.as_slice());
+        Ok(change.is_some())
+    }
+
+    // 进行中和待接诊的问诊：优先级高的在前，同一优先级按入队时间从早到晚
     pub fn get_active_consultations(&self, doctor_id: &str) -> Result<Vec<Consultation>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at
-             FROM consultations WHERE doctor_id = ?1 AND status IN ('pending', 'active') ORDER BY created_at ASC"
+            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
+             FROM consultations WHERE doctor_id = ?1 AND status IN ('pending', 'active')
+             ORDER BY CASE priority WHEN 'critical' THEN 2 WHEN 'urgent' THEN 1 ELSE 0 END DESC,
+                      COALESCE(queued_at, created_at) ASC"
         )?;
 
         let consultation_iter = stmt.query_map(params![doctor_id], |row| {
@@ -162,6 +424,12 @@ impl ConsultationDao {
                 prescription: row.get(8)?,
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
+                accepted_at: row.get(11)?,
+                completed_at: row.get(12)?,
+                cancel_reason: row.get(13)?,
+                version: row.get(14)?,
+                priority: row.get(15)?,
+                queued_at: row.get(16)?,
             })
         })?;
 
@@ -173,6 +441,321 @@ impl ConsultationDao {
         Ok(consultations)
     }
 
+    // 进行中且最后活动不晚于 before 的问诊，最早的在前；最后活动取最新一条消息（含系统消息）的时间，
+    // 还没有消息时取接诊时间。单条 SQL 用相关子查询取最新消息时间，不逐条查询；
+    // 消息时间与接诊时间格式不同，比较和排序都按 julianday 换算
+    pub fn find_inactive_consultations(&self, before: DateTime<Utc>) -> Result<Vec<InactiveConsultation>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, patient_id, last_activity_at FROM (
+                 SELECT c.id, c.doctor_id, c.patient_id,
+                        COALESCE(
+                            (SELECT m.timestamp FROM messages m WHERE m.consultation_id = c.id
+                             ORDER BY julianday(m.timestamp) DESC LIMIT 1),
+                            c.accepted_at,
+                            c.created_at
+                        ) AS last_activity_at
+                 FROM consultations c
+                 WHERE c.status = 'active'
+             )
+             WHERE julianday(last_activity_at) <= julianday(?1)
+             ORDER BY julianday(last_activity_at) ASC"
+        )?;
+
+        let rows = stmt.query_map(params![SqlTimestamp(before)], |row| {
+            Ok(InactiveConsultation {
+                consultation_id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                patient_id: row.get(2)?,
+                last_activity_at: row.get(3)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>>>()?)
+    }
+
+    // 仅当当前状态仍为 from 时才更新，并在同一事务内写入审计日志和系统事件消息；返回是否更新成功
+    pub fn transition_status(&self, transition: &StatusTransition<'_>) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = Utc::now();
+
+        let updated = tx.execute(
+            "UPDATE consultations SET
+                status = ?1,
+                updated_at = ?2,
+                accepted_at = CASE WHEN ?1 = 'active' THEN ?2 ELSE accepted_at END,
+                completed_at = CASE WHEN ?1 = 'completed' THEN ?2 ELSE completed_at END,
+                diagnosis = COALESCE(?3, diagnosis),
+                prescription = COALESCE(?4, prescription),
+                cancel_reason = COALESCE(?5, cancel_reason),
+                version = version + 1
+             WHERE id = ?6 AND status = ?7",
+            params![
+                transition.to,
+                now,
+                transition.diagnosis,
+                transition.prescription,
+                transition.cancel_reason,
+                transition.consultation_id,
+                transition.from
+            ],
+        )?;
+
+        if updated == 0 {
+            return Ok(false);
+        }
+        let mut changes = vec![ChangeLogDao::record_in(
+            &tx,
+            ChangeEntity::Consultation,
+            transition.consultation_id,
+            ChangeOp::Update,
+        )?];
+
+        let completed = transition.to == "completed";
+        if completed {
+            let patient_id: Option<String> = tx
+                .query_row(
+                    "UPDATE patients SET last_visit = ?1
+                     WHERE id = (SELECT patient_id FROM consultations WHERE id = ?2)
+                     RETURNING id",
+                    params![now, transition.consultation_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(patient_id) = patient_id {
+                changes.push(ChangeLogDao::record_in(&tx, ChangeEntity::Patient, &patient_id, ChangeOp::Update)?);
+            }
+        }
+
+        for notice in transition.notices {
+            MessageDao::upsert_in(&tx, notice)?;
+        }
+
+        let details = serde_json::json!({
+            "from": transition.from,
+            "to": transition.to,
+            "reason": transition.cancel_reason,
+        });
+        tx.execute(
+            "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, details, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Uuid::new_v4().to_string(),
+                transition.user_id,
+                transition.action,
+                "consultation",
+                transition.consultation_id,
+                details.to_string(),
+                SqlTimestamp(now)
+            ],
+        )?;
+
+        tx.commit()?;
+
+        if !transition.notices.is_empty() {
+            query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+        }
+        if completed {
+            query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_PATIENTS);
+        }
+        publish_status_change(transition.consultation_id, transition.to);
+        publish_data_changes(&changes);
+        Ok(true)
+    }
+
+    // 把待接诊或进行中的问诊转给另一位医生：更新接诊医生、写入系统消息、转接记录和审计日志，
+    // 任一步失败整体回滚；问诊已结束或已不属于原医生时返回 false
+    pub fn transfer(
+        &self,
+        transfer: &ConsultationTransfer,
+        notice: &Message,
+        user_id: Option<&str>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let updated = tx.execute(
+            "UPDATE consultations SET doctor_id = ?1, updated_at = ?2, version = version + 1
+             WHERE id = ?3 AND doctor_id = ?4 AND status IN ('pending', 'active')",
+            params![
+                transfer.to_doctor_id,
+                transfer.created_at,
+                transfer.consultation_id,
+                transfer.from_doctor_id
+            ],
+        )?;
+
+        if updated == 0 {
+            return Ok(false);
+        }
+        let change = ChangeLogDao::record_in(&tx, ChangeEntity::Consultation, &transfer.consultation_id, ChangeOp::Update)?;
+
+        MessageDao::upsert_in(&tx, notice)?;
+
+        tx.execute(
+            "INSERT INTO consultation_transfers (id, consultation_id, from_doctor_id, to_doctor_id, note, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                transfer.id,
+                transfer.consultation_id,
+                transfer.from_doctor_id,
+                transfer.to_doctor_id,
+                transfer.note,
+                transfer.created_at
+            ],
+        )?;
+
+        let details = serde_json::json!({
+            "fromDoctorId": transfer.from_doctor_id,
+            "toDoctorId": transfer.to_doctor_id,
+            "note": transfer.note,
+        });
+        tx.execute(
+            "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, details, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Uuid::new_v4().to_string(),
+                user_id,
+                "transfer_consultation",
+                "consultation",
+                transfer.consultation_id,
+                details.to_string(),
+                SqlTimestamp(transfer.created_at)
+            ],
+        )?;
+
+        tx.commit()?;
+
+        // 接诊医生变化会影响按医生划分的患者列表和消息查询
+        let cache = query_cache_for(&self.connection);
+        cache.invalidate_tag(CACHE_TAG_PATIENTS);
+        cache.invalidate_tag(CACHE_TAG_MESSAGES);
+        publish_data_changes(&[change]);
+        Ok(true)
+    }
+
+    // 问诊的转接记录，按时间先后排列
+    pub fn find_transfers(&self, consultation_id: &str) -> Result<Vec<ConsultationTransfer>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, consultation_id, from_doctor_id, to_doctor_id, note, created_at
+             FROM consultation_transfers WHERE consultation_id = ?1 ORDER BY created_at ASC"
+        )?;
+
+        let transfer_iter = stmt.query_map(params![consultation_id], |row| {
+            Ok(ConsultationTransfer {
+                id: row.get(0)?,
+                consultation_id: row.get(1)?,
+                from_doctor_id: row.get(2)?,
+                to_doctor_id: row.get(3)?,
+                note: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })?;
+
+        let mut transfers = Vec::new();
+        for transfer in transfer_iter {
+            transfers.push(transfer?);
+        }
+
+        Ok(transfers)
+    }
+
+    // 最近 days 天每天新建的问诊数（按 UTC 日期），没有问诊的日期不返回
+    pub fn get_daily_counts(&self, doctor_id: &str, days: u32) -> Result<Vec<DailyCount>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let since = window_start(days);
+
+        let mut stmt = conn.prepare(
+            "SELECT date(created_at) AS day, COUNT(*)
+             FROM consultations
+             WHERE doctor_id = ?1 AND julianday(created_at) >= julianday(?2)
+             GROUP BY day ORDER BY day ASC"
+        )?;
+
+        let count_iter = stmt.query_map(params![doctor_id, since], |row| {
+            Ok(DailyCount {
+                date: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+
+        let mut counts = Vec::new();
+        for count in count_iter {
+            counts.push(count?);
+        }
+
+        Ok(counts)
+    }
+
+    // 首次响应时长：患者第一条消息到其后医生第一条回复的间隔（秒），按问诊创建日期聚合
+    // 没有患者消息或医生尚未回复的问诊不参与统计
+    pub fn get_daily_first_response(&self, doctor_id: &str, days: u32) -> Result<Vec<DailyLatency>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let since = window_start(days);
+
+        let mut stmt = conn.prepare(
+            "SELECT day, AVG(latency), COUNT(*) FROM (
+                SELECT fp.day AS day,
+                       (MIN(julianday(d.timestamp)) - fp.first_patient) * 86400.0 AS latency
+                FROM (
+                    SELECT m.consultation_id, date(c.created_at) AS day, MIN(julianday(m.timestamp)) AS first_patient
+                    FROM messages m
+                    JOIN consultations c ON c.id = m.consultation_id
+                    WHERE c.doctor_id = ?1 AND julianday(c.created_at) >= julianday(?2) AND m.sender_type = 'patient'
+                    GROUP BY m.consultation_id
+                ) fp
+                JOIN messages d ON d.consultation_id = fp.consultation_id
+                    AND d.sender_type = 'doctor'
+                    AND julianday(d.timestamp) >= fp.first_patient
+                GROUP BY fp.consultation_id
+             )
+             GROUP BY day ORDER BY day ASC"
+        )?;
+
+        let latency_iter = stmt.query_map(params![doctor_id, since], |row| {
+            Ok(DailyLatency {
+                date: row.get(0)?,
+                average_seconds: row.get(1)?,
+                samples: row.get(2)?,
+            })
+        })?;
+
+        let mut latencies = Vec::new();
+        for latency in latency_iter {
+            latencies.push(latency?);
+        }
+
+        Ok(latencies)
+    }
+
+    pub fn get_completion_counts_by_type(&self, doctor_id: &str, days: u32) -> Result<Vec<TypeCount>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let since = window_start(days);
+
+        let mut stmt = conn.prepare(
+            "SELECT consultation_type, COUNT(*)
+             FROM consultations
+             WHERE doctor_id = ?1 AND status = 'completed' AND julianday(created_at) >= julianday(?2)
+             GROUP BY consultation_type ORDER BY consultation_type ASC"
+        )?;
+
+        let count_iter = stmt.query_map(params![doctor_id, since], |row| {
+            Ok(TypeCount {
+                consultation_type: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+
+        let mut counts = Vec::new();
+        for count in count_iter {
+            counts.push(count?);
+        }
+
+        Ok(counts)
+    }
+
     pub fn get_consultation_stats(&self, doctor_id: &str) -> Result<ConsultationStats, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
 
@@ -193,6 +776,29 @@ impl ConsultationDao {
     }
 }
 
+// 一次状态流转的参数
+#[derive(Debug, Clone)]
+pub struct StatusTransition<'a> {
+    pub consultation_id: &'a str,
+    pub from: &'a str,
+    pub to: &'a str,
+    pub action: &'a str,
+    pub user_id: Option<&'a str>,
+    pub diagnosis: Option<&'a str>,
+    pub prescription: Option<&'a str>,
+    pub cancel_reason: Option<&'a str>,
+    // 随状态变更写入会话的系统事件消息
+    pub notices: &'a [Message],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InactiveConsultation {
+    pub consultation_id: String,
+    pub doctor_id: String,
+    pub patient_id: String,
+    pub last_activity_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ConsultationStats {
     pub pending: i64,
@@ -200,37 +806,51 @@ pub struct ConsultationStats {
     pub completed: i64,
 }
 
+// 统计窗口起点：包含今天在内的最近 days 天的 UTC 零点
+pub fn window_start(days: u32) -> DateTime<Utc> {
+    let today = Utc::now().date_naive();
+    let start = today - chrono::Duration::days(days.max(1) as i64 - 1);
+    start.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
 impl BaseDao<Consultation> for ConsultationDao {
     fn create(&self, consultation: &Consultation) -> Result<String, Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
         let id = Uuid::new_v4().to_string();
         let now = Utc::now();
 
-        conn.execute(
-            "INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-            params![
-                id,
-                consultation.patient_id,
-                consultation.doctor_id,
-                consultation.status,
-                consultation.consultation_type,
-                consultation.title,
-                consultation.description,
-                consultation.diagnosis,
-                consultation.prescription,
-                now,
-                now
-            ],
-        )?;
+        let change = retry_transaction_on_busy(&self.connection, "create consultation", |tx| {
+            tx.execute(
+                "INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                                            priority, queued_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    id,
+                    consultation.patient_id,
+                    consultation.doctor_id,
+                    consultation.status,
+                    consultation.consultation_type,
+                    consultation.title,
+                    consultation.description,
+                    consultation.diagnosis,
+                    consultation.prescription,
+                    now,
+                    now,
+                    consultation.priority,
+                    consultation.queued_at.unwrap_or(now)
+                ],
+            )?;
+            ChangeLogDao::record_in(tx, ChangeEntity::Consultation, &id, ChangeOp::Insert)
+        })?;
 
+        publish_data_changes(&[change]);
         Ok(id)
     }
 
     fn find_by_id(&self, id: &str) -> Result<Option<Consultation>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at
+            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
              FROM consultations WHERE id = ?1"
         )?;
 
@@ -247,6 +867,12 @@ impl BaseDao<Consultation> for ConsultationDao {
                 prescription: row.get(8)?,
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
+                accepted_at: row.get(11)?,
+                completed_at: row.get(12)?,
+                cancel_reason: row.get(13)?,
+                version: row.get(14)?,
+                priority: row.get(15)?,
+                queued_at: row.get(16)?,
             })
         });
 
@@ -258,39 +884,57 @@ impl BaseDao<Consultation> for ConsultationDao {
     }
 
     fn update(&self, consultation: &Consultation) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
         let now = Utc::now();
 
-        conn.execute(
-            "UPDATE consultations SET patient_id = ?1, doctor_id = ?2, status = ?3, consultation_type = ?4,
-             title = ?5, description = ?6, diagnosis = ?7, prescription = ?8, updated_at = ?9 WHERE id = ?10",
-            params![
-                consultation.patient_id,
-                consultation.doctor_id,
-                consultation.status,
-                consultation.consultation_type,
-                consultation.title,
-                consultation.description,
-                consultation.diagnosis,
-                consultation.prescription,
-                now,
-                consultation.id
-            ],
-        )?;
+        // 版本号不一致说明读取之后已被其他窗口修改
+        let change = retry_transaction_on_busy(&self.connection, "update consultation", |tx| {
+            let updated = tx.execute(
+                "UPDATE consultations SET patient_id = ?1, doctor_id = ?2, status = ?3, consultation_type = ?4,
+                 title = ?5, description = ?6, diagnosis = ?7, prescription = ?8, updated_at = ?9, version = version + 1
+                 WHERE id = ?10 AND version = ?11",
+                params![
+                    consultation.patient_id,
+                    consultation.doctor_id,
+                    consultation.status,
+                    consultation.consultation_type,
+                    consultation.title,
+                    consultation.description,
+                    consultation.diagnosis,
+                    consultation.prescription,
+                    now,
+                    consultation.id,
+                    consultation.version
+                ],
+            )?;
+            if updated == 0 {
+                return Ok(None);
+            }
+            Ok(Some(ChangeLogDao::record_in(tx, ChangeEntity::Consultation, &consultation.id, ChangeOp::Update)?))
+        })?;
+        let Some(change) = change else {
+            return Err(Box::new(ConflictError::new("consultation", &consultation.id, consultation.version)));
+        };
 
+        publish_data_changes(&[change]);
         Ok(())
     }
 
     fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
-        conn.execute("DELETE FROM consultations WHERE id = ?1", params![id])?;
+        let change = retry_transaction_on_busy(&self.connection, "delete consultation", |tx| {
+            if tx.execute("DELETE FROM consultations WHERE id = ?1", params![id])? == 0 {
+                return Ok(None);
+            }
+            Ok(Some(ChangeLogDao::record_in(tx, ChangeEntity::Consultation, id, ChangeOp::Delete)?))
+        })?;
+        publish_data_changes(change.as_slice());
         Ok(())
     }
 
     fn find_all(&self) -> Result<Vec<Consultation>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at
+            "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at,
+                    accepted_at, completed_at, cancel_reason, version, priority, queued_at
              FROM consultations ORDER BY created_at DESC"
         )?;
 
@@ -307,6 +951,12 @@ impl BaseDao<Consultation> for ConsultationDao {
                 prescription: row.get(8)?,
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
+                accepted_at: row.get(11)?,
+                completed_at: row.get(12)?,
+                cancel_reason: row.get(13)?,
+                version: row.get(14)?,
+                priority: row.get(15)?,
+                queued_at: row.get(16)?,
             })
         })?;
 
@@ -0,0 +1,161 @@
+// 问诊备注数据访问层，备注内容以字段级密文落库
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::patient_dao::field_crypto;
+use crate::models::ConsultationNote;
+use rusqlite::types::Type;
+use rusqlite::{params, OptionalExtension, Result, Row};
+use chrono::Utc;
+use uuid::Uuid;
+
+pub struct ConsultationNoteDao {
+    connection: DbConnection,
+}
+
+impl ConsultationNoteDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 每个问诊每位医生一条备注，重复保存覆盖内容，保留原 ID
+    pub fn upsert(&self, consultation_id: &str, doctor_id: &str, content: &str) -> Result<ConsultationNote, Box<dyn std::error::Error>> {
+        let encrypted = field_crypto()?.encrypt_field(content)?;
+        let now = Utc::now();
+
+        let conn = self.connection.lock().unwrap();
+        let id: String = conn.query_row(
+            "INSERT INTO consultation_notes (id, consultation_id, doctor_id, content, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(consultation_id, doctor_id) DO UPDATE SET
+                content = excluded.content,
+                updated_at = excluded.updated_at
+             RETURNING id",
+            params![Uuid::new_v4().to_string(), consultation_id, doctor_id, encrypted, now],
+            |row| row.get(0),
+        )?;
+
+        Ok(ConsultationNote {
+            id,
+            consultation_id: consultation_id.to_string(),
+            doctor_id: doctor_id.to_string(),
+            content: content.to_string(),
+            updated_at: now,
+        })
+    }
+
+    pub fn find(&self, consultation_id: &str, doctor_id: &str) -> Result<Option<ConsultationNote>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let note = conn.query_row(
+            "SELECT id, consultation_id, doctor_id, content, updated_at FROM consultation_notes
+             WHERE consultation_id = ?1 AND doctor_id = ?2",
+            params![consultation_id, doctor_id],
+            map_note,
+        ).optional()?;
+
+        Ok(note)
+    }
+
+    pub fn delete(&self, consultation_id: &str, doctor_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let deleted = conn.execute(
+            "DELETE FROM consultation_notes WHERE consultation_id = ?1 AND doctor_id = ?2",
+            params![consultation_id, doctor_id],
+        )?;
+
+        Ok(deleted > 0)
+    }
+}
+
+impl Default for ConsultationNoteDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn map_note(row: &Row) -> Result<ConsultationNote> {
+    let stored: String = row.get(3)?;
+    let content = field_crypto()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?
+        .decrypt_field(&stored)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.into()))?;
+
+    Ok(ConsultationNote {
+        id: row.get(0)?,
+        consultation_id: row.get(1)?,
+        doctor_id: row.get(2)?,
+        content,
+        updated_at: row.get(4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::utils::CryptoService;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    fn seed(conn: &Connection) {
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_note_encrypted_at_rest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.db");
+        let conn = Connection::open(&path).unwrap();
+        seed(&conn);
+        let dao = ConsultationNoteDao::with_connection(Arc::new(Mutex::new(conn)));
+
+        let plaintext = "患者对青霉素过敏，下次复诊查肝功能";
+        let saved = dao.upsert("c1", "d1", plaintext).unwrap();
+        assert_eq!(dao.find("c1", "d1").unwrap().unwrap().content, plaintext);
+
+        let stored: String = dao
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT content FROM consultation_notes WHERE id = ?1", params![saved.id], |row| row.get(0))
+            .unwrap();
+        assert!(CryptoService::is_encrypted_field(&stored));
+
+        // 数据库文件中找不到明文
+        drop(dao);
+        let bytes = std::fs::read(&path).unwrap();
+        let needle = "青霉素".as_bytes();
+        assert!(!bytes.windows(needle.len()).any(|window| window == needle));
+    }
+
+    #[test]
+    fn test_note_upsert_per_doctor() {
+        let conn = Connection::open_in_memory().unwrap();
+        seed(&conn);
+        let dao = ConsultationNoteDao::with_connection(Arc::new(Mutex::new(conn)));
+
+        let first = dao.upsert("c1", "d1", "初稿").unwrap();
+        let second = dao.upsert("c1", "d1", "修改后的备注").unwrap();
+        assert_eq!(first.id, second.id);
+        dao.upsert("c1", "d2", "另一位医生的备注").unwrap();
+
+        assert_eq!(dao.find("c1", "d1").unwrap().unwrap().content, "修改后的备注");
+        assert_eq!(dao.find("c1", "d2").unwrap().unwrap().content, "另一位医生的备注");
+
+        assert!(dao.delete("c1", "d1").unwrap());
+        assert!(!dao.delete("c1", "d1").unwrap());
+        assert!(dao.find("c1", "d1").unwrap().is_none());
+    }
+}
@@ -0,0 +1,285 @@
+// 医生名录数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{escape_like, QueryBuilder};
+use crate::models::{Doctor, DoctorOnlineStatus, TransferCandidate};
+use crate::utils::SqlTimestamp;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+const DOCTOR_COLUMNS: &str = "id, name, department, title, online_status, updated_at";
+
+// 在线状态排序：online、busy、offline
+const STATUS_ORDER_SQL: &str = "CASE online_status WHEN 'online' THEN 0 WHEN 'busy' THEN 1 ELSE 2 END";
+
+pub struct DoctorDao {
+    connection: DbConnection,
+}
+
+impl DoctorDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 在调用方的事务内写入同步下来的医生，seen_at 为本次同步时间；已移除的医生再次出现时恢复
+    pub fn upsert_in(conn: &Connection, doctor: &Doctor, seen_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO doctors (id, name, department, title, online_status, updated_at, synced_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                department = excluded.department,
+                title = excluded.title,
+                online_status = excluded.online_status,
+                updated_at = excluded.updated_at,
+                synced_at = excluded.synced_at,
+                removed_at = NULL",
+            params![
+                doctor.id,
+                doctor.name,
+                doctor.department,
+                doctor.title,
+                doctor.online_status,
+                SqlTimestamp(doctor.updated_at),
+                SqlTimestamp(seen_at),
+            ],
+        )?;
+        Ok(())
+    }
+
+    // 软删除 seen_at 这次同步中未出现的医生，返回移除的人数
+    pub fn remove_unseen_in(conn: &Connection, seen_at: DateTime<Utc>) -> Result<usize, Box<dyn std::error::Error>> {
+        let removed = conn.execute(
+            "UPDATE doctors SET removed_at = ?1 WHERE synced_at < ?1 AND removed_at IS NULL",
+            params![SqlTimestamp(seen_at)],
+        )?;
+        Ok(removed)
+    }
+
+    // 包含已移除的医生，用于查找问诊当前医生的科室
+    pub fn find_by_id(&self, id: &str) -> Result<Option<Doctor>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let doctor = conn
+            .query_row(
+                &format!("SELECT {} FROM doctors WHERE id = ?1", DOCTOR_COLUMNS),
+                params![id],
+                map_doctor,
+            )
+            .optional()?;
+        Ok(doctor)
+    }
+
+    // 按姓名、科室或职称关键词和科室筛选名录中的医生，按科室、姓名排列
+    pub fn search_doctors(
+        &self,
+        keyword: Option<&str>,
+        department: Option<&str>,
+    ) -> Result<Vec<Doctor>, Box<dyn std::error::Error>> {
+        let mut builder = QueryBuilder::new().add_condition("removed_at IS NULL", Vec::new());
+
+        if let Some(keyword) = keyword.map(str::trim).filter(|k| !k.is_empty()) {
+            let pattern = format!("%{}%", escape_like(keyword));
+            builder = builder.add_condition(
+                "(name LIKE ? ESCAPE '\\' OR department LIKE ? ESCAPE '\\' OR title LIKE ? ESCAPE '\\')",
+                vec![Box::new(pattern.clone()), Box::new(pattern.clone()), Box::new(pattern)],
+            );
+        }
+        if let Some(department) = department.filter(|d| !d.is_empty()) {
+            builder = builder.where_eq("department", department.to_string());
+        }
+
+        let builder = builder.order_by("department IS NULL, department, name, id");
+        let (sql, params) = builder.build(&format!("SELECT {} FROM doctors", DOCTOR_COLUMNS));
+
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let doctors = stmt
+            .query_map(params.as_slice(), map_doctor)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(doctors)
+    }
+
+    // 转接候选：排除问诊当前医生和已移除的医生，同科室优先，其次按在线状态、姓名排列
+    pub fn find_transfer_candidates(
+        &self,
+        exclude_doctor_id: &str,
+        department: Option<&str>,
+    ) -> Result<Vec<TransferCandidate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, COALESCE(department = ?2, 0) AS same_department FROM doctors
+             WHERE removed_at IS NULL AND id <> ?1
+             ORDER BY same_department DESC, {}, name, id",
+            DOCTOR_COLUMNS, STATUS_ORDER_SQL
+        ))?;
+        let candidates = stmt
+            .query_map(params![exclude_doctor_id, department], |row| {
+                Ok(TransferCandidate {
+                    doctor: map_doctor(row)?,
+                    same_department: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(candidates)
+    }
+
+    // WebSocket 推送的在线状态，名录中没有该医生时忽略，返回是否有变化
+    pub fn set_online_status(&self, id: &str, status: DoctorOnlineStatus) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE doctors SET online_status = ?2 WHERE id = ?1 AND online_status <> ?2",
+            params![id, status],
+        )?;
+        Ok(updated > 0)
+    }
+}
+
+impl Default for DoctorDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn map_doctor(row: &Row) -> Result<Doctor> {
+    Ok(Doctor {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        department: row.get(2)?,
+        title: row.get(3)?,
+        online_status: row.get(4)?,
+        updated_at: row.get::<_, SqlTimestamp>(5)?.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use chrono::Duration;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn doctor(id: &str, name: &str, department: &str, status: DoctorOnlineStatus) -> Doctor {
+        Doctor {
+            id: id.to_string(),
+            name: name.to_string(),
+            department: Some(department.to_string()),
+            title: Some("主治医师".to_string()),
+            online_status: status,
+            updated_at: Utc::now() - Duration::days(1),
+        }
+    }
+
+    fn seed(connection: &DbConnection, doctors: &[Doctor], seen_at: DateTime<Utc>) {
+        let conn = connection.lock().unwrap();
+        for doctor in doctors {
+            DoctorDao::upsert_in(&conn, doctor, seen_at).unwrap();
+        }
+    }
+
+    fn ids<T>(items: &[T], id: impl Fn(&T) -> &str) -> Vec<&str> {
+        items.iter().map(id).collect()
+    }
+
+    #[test]
+    fn test_search_by_keyword_and_department() {
+        let connection = create_test_connection();
+        seed(
+            &connection,
+            &[
+                doctor("d1", "王医生", "内科", DoctorOnlineStatus::Online),
+                doctor("d2", "李医生", "外科", DoctorOnlineStatus::Offline),
+                doctor("d3", "王100%", "儿科", DoctorOnlineStatus::Busy),
+            ],
+            Utc::now(),
+        );
+        let dao = DoctorDao::with_connection(connection);
+
+        let all = dao.search_doctors(None, None).unwrap();
+        assert_eq!(ids(&all, |d| d.id.as_str()), vec!["d3", "d1", "d2"]);
+        let wang = dao.search_doctors(Some(" 王 "), None).unwrap();
+        assert_eq!(ids(&wang, |d| d.id.as_str()), vec!["d3", "d1"]);
+        assert_eq!(dao.search_doctors(Some("王"), Some("儿科")).unwrap()[0].id, "d3");
+        assert_eq!(dao.search_doctors(Some("外科"), None).unwrap()[0].id, "d2");
+        // 通配符按字面量匹配
+        assert_eq!(ids(&dao.search_doctors(Some("%"), None).unwrap(), |d| d.id.as_str()), vec!["d3"]);
+        assert_eq!(dao.search_doctors(None, Some("儿科")).unwrap()[0].online_status, DoctorOnlineStatus::Busy);
+    }
+
+    #[test]
+    fn test_transfer_candidates_order_and_presence() {
+        let connection = create_test_connection();
+        seed(
+            &connection,
+            &[
+                doctor("me", "张医生", "内科", DoctorOnlineStatus::Online),
+                doctor("d1", "外科在线", "外科", DoctorOnlineStatus::Online),
+                doctor("d2", "内科离线", "内科", DoctorOnlineStatus::Offline),
+                doctor("d3", "内科忙碌", "内科", DoctorOnlineStatus::Busy),
+                doctor("d4", "内科在线", "内科", DoctorOnlineStatus::Online),
+                doctor("d5", "外科离线", "外科", DoctorOnlineStatus::Offline),
+            ],
+            Utc::now(),
+        );
+        let dao = DoctorDao::with_connection(connection);
+
+        let candidates = dao.find_transfer_candidates("me", Some("内科")).unwrap();
+        assert_eq!(ids(&candidates, |c| c.doctor.id.as_str()), vec!["d4", "d3", "d2", "d1", "d5"]);
+        assert!(candidates[..3].iter().all(|c| c.same_department));
+        assert!(!candidates[3].same_department);
+
+        // 在线状态推送后重新排序，未知医生忽略
+        assert!(dao.set_online_status("d2", DoctorOnlineStatus::Online).unwrap());
+        assert!(!dao.set_online_status("d2", DoctorOnlineStatus::Online).unwrap());
+        assert!(!dao.set_online_status("unknown", DoctorOnlineStatus::Online).unwrap());
+        let candidates = dao.find_transfer_candidates("me", Some("内科")).unwrap();
+        assert_eq!(ids(&candidates, |c| c.doctor.id.as_str()), vec!["d4", "d2", "d3", "d1", "d5"]);
+
+        // 当前医生不在名录中时没有同科室优先
+        let candidates = dao.find_transfer_candidates("me", None).unwrap();
+        assert_eq!(ids(&candidates, |c| c.doctor.id.as_str()), vec!["d4", "d2", "d1", "d3", "d5"]);
+        assert!(candidates.iter().all(|c| !c.same_department));
+    }
+
+    #[test]
+    fn test_unseen_doctors_soft_removed_and_restored() {
+        let connection = create_test_connection();
+        let first_sync = Utc::now() - Duration::hours(1);
+        seed(
+            &connection,
+            &[
+                doctor("d1", "王医生", "内科", DoctorOnlineStatus::Online),
+                doctor("d2", "李医生", "内科", DoctorOnlineStatus::Online),
+            ],
+            first_sync,
+        );
+
+        let second_sync = Utc::now();
+        {
+            let conn = connection.lock().unwrap();
+            DoctorDao::upsert_in(&conn, &doctor("d1", "王医生", "内科", DoctorOnlineStatus::Online), second_sync).unwrap();
+            assert_eq!(DoctorDao::remove_unseen_in(&conn, second_sync).unwrap(), 1);
+            assert_eq!(DoctorDao::remove_unseen_in(&conn, second_sync).unwrap(), 0);
+        }
+        let dao = DoctorDao::with_connection(connection.clone());
+        assert_eq!(ids(&dao.search_doctors(None, None).unwrap(), |d| d.id.as_str()), vec!["d1"]);
+        assert!(dao.find_transfer_candidates("d1", Some("内科")).unwrap().is_empty());
+        // 已移除的医生仍可按 ID 查到
+        assert!(dao.find_by_id("d2").unwrap().is_some());
+
+        seed(&connection, &[doctor("d2", "李医生", "内科", DoctorOnlineStatus::Busy)], Utc::now());
+        assert_eq!(dao.search_doctors(None, None).unwrap().len(), 2);
+    }
+}
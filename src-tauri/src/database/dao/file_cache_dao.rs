@@ -2,10 +2,43 @@
 
 use crate::database::connection::{get_database, DbConnection};
 use crate::database::dao::BaseDao;
-use crate::models::FileCache;
-use rusqlite::{params, Result};
+use crate::models::{FileCache, FilePreview, PinnedFile};
+use crate::utils::SqlTimestamp;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
+
+// 计入附件配额的字节数：文件大小未知时按已下载的字节数计
+const QUOTA_BYTES_SQL: &str = "COALESCE(file_size, bytes_downloaded)";
+
+pub fn quota_bytes(cache: &FileCache) -> i64 {
+    cache.file_size.unwrap_or(cache.bytes_downloaded) as i64
+}
+
+// 被删除的缓存记录留在磁盘上的文件：(本地文件, 缩略图, 预览)
+pub type CachedFilePaths = (String, Option<String>, Option<String>);
+
+// 固定原因：被发件箱中尚未得到服务器确认的消息引用，引用方标识为消息 ID
+pub const PIN_REASON_OUTBOX: &str = "outbox";
+
+// 固定原因：被病历附件引用，引用方标识为病历 ID
+pub const PIN_REASON_MEDICAL_RECORD: &str = "medical_record";
+
+// 可被清理的记录：没有任何引用固定
+const EVICTABLE_SQL: &str = "NOT EXISTS (SELECT 1 FROM pinned_by_ref p WHERE p.cache_id = file_cache.id)";
+
+const CACHE_COLUMNS: &str = "id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed,
+             EXISTS (SELECT 1 FROM pinned_by_ref p WHERE p.cache_id = file_cache.id) AS pinned, thumbnail_path, bytes_downloaded,
+             preview_path, preview_text, consultation_id, original_size";
+
+// 各清理路径的固定检查，force 为 true 时连同固定的文件一并清理
+fn eviction_filter(force: bool) -> &'static str {
+    if force {
+        "1 = 1"
+    } else {
+        EVICTABLE_SQL
+    }
+}
 
 pub struct FileCacheDao {
     connection: DbConnection,
@@ -18,95 +51,39 @@ impl FileCacheDao {
         }
     }
 
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
     pub fn find_by_url(&self, file_url: &str) -> Result<Option<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed
-             FROM file_cache WHERE file_url = ?1"
-        )?;
-
-        let cache_result = stmt.query_row(params![file_url], |row| {
-            Ok(FileCache {
-                id: row.get(0)?,
-                file_url: row.get(1)?,
-                local_path: row.get(2)?,
-                file_size: row.get(3)?,
-                mime_type: row.get(4)?,
-                checksum: row.get(5)?,
-                expires_at: row.get(6)?,
-                downloaded_at: row.get(7)?,
-                last_accessed: row.get(8)?,
-            })
-        });
-
-        match cache_result {
-            Ok(cache) => Ok(Some(cache)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(Box::new(e)),
-        }
+        let files = Self::select_in(&conn, "WHERE file_url = ?1", params![file_url])?;
+        Ok(files.into_iter().next())
     }
 
     pub fn find_expired_files(&self) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed
-             FROM file_cache WHERE expires_at IS NOT NULL AND expires_at < datetime('now')"
+        let files = Self::select_in(
+            &conn,
+            &format!("WHERE expires_at IS NOT NULL AND expires_at < ?1 AND {}", EVICTABLE_SQL),
+            params![SqlTimestamp::now()],
         )?;
-
-        let cache_iter = stmt.query_map([], |row| {
-            Ok(FileCache {
-                id: row.get(0)?,
-                file_url: row.get(1)?,
-                local_path: row.get(2)?,
-                file_size: row.get(3)?,
-                mime_type: row.get(4)?,
-                checksum: row.get(5)?,
-                expires_at: row.get(6)?,
-                downloaded_at: row.get(7)?,
-                last_accessed: row.get(8)?,
-            })
-        })?;
-
-        let mut files = Vec::new();
-        for file in cache_iter {
-            files.push(file?);
-        }
-
         Ok(files)
     }
 
     pub fn find_old_files(&self, days: i32) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed
-             FROM file_cache WHERE last_accessed < datetime('now', '-' || ?1 || ' days')"
+        let files = Self::select_in(
+            &conn,
+            &format!("WHERE last_accessed < ?1 AND {}", EVICTABLE_SQL),
+            params![SqlTimestamp::days_ago(days)],
         )?;
-
-        let cache_iter = stmt.query_map(params![days], |row| {
-            Ok(FileCache {
-                id: row.get(0)?,
-                file_url: row.get(1)?,
-                local_path: row.get(2)?,
-                file_size: row.get(3)?,
-                mime_type: row.get(4)?,
-                checksum: row.get(5)?,
-                expires_at: row.get(6)?,
-                downloaded_at: row.get(7)?,
-                last_accessed: row.get(8)?,
-            })
-        })?;
-
-        let mut files = Vec::new();
-        for file in cache_iter {
-            files.push(file?);
-        }
-
         Ok(files)
     }
 
     pub fn update_last_accessed(&self, file_id: &str) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        let now = Utc::now();
+        let now = SqlTimestamp::now();
 
         conn.execute(
             "UPDATE file_cache SET last_accessed = ?1 WHERE id = ?2",
@@ -132,8 +109,8 @@ impl FileCacheDao {
         let mut size_stmt = conn.prepare("SELECT COALESCE(SUM(file_size), 0) FROM file_cache")?;
         let total_size: i64 = size_stmt.query_row([], |row| row.get(0))?;
 
-        let mut expired_stmt = conn.prepare("SELECT COUNT(*) FROM file_cache WHERE expires_at IS NOT NULL AND expires_at < datetime('now')")?;
-        let expired_files: i64 = expired_stmt.query_row([], |row| row.get(0))?;
+        let mut expired_stmt = conn.prepare("SELECT COUNT(*) FROM file_cache WHERE expires_at IS NOT NULL AND expires_at < ?1")?;
+        let expired_files: i64 = expired_stmt.query_row(params![SqlTimestamp::now()], |row| row.get(0))?;
 
         Ok(CacheStats {
             total_files,
@@ -142,29 +119,447 @@ impl FileCacheDao {
         })
     }
 
-    pub fn cleanup_expired(&self) -> Result<usize, Box<dyn std::error::Error>> {
+    // 删除已过期的缓存记录，固定的文件仅在 force 时删除，返回被删除的记录以便清理本地文件
+    pub fn cleanup_expired(&self, force: bool) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
 
-        let deleted = conn.execute(
-            "DELETE FROM file_cache WHERE expires_at IS NOT NULL AND expires_at < datetime('now')",
-            [],
+        let expired = Self::select_in(
+            &tx,
+            &format!("WHERE expires_at IS NOT NULL AND expires_at < ?1 AND {}", eviction_filter(force)),
+            params![SqlTimestamp::now()],
         )?;
+        Self::evict_in(&tx, &expired)?;
+
+        tx.commit()?;
+        Ok(expired)
+    }
+
+    pub fn cleanup_old_files(&self, days: i32, force: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let deleted = Self::cleanup_old_files_in(&tx, days, force)?.len();
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    // 在调用方的事务内删除超过保留天数的缓存记录，固定的文件仅在 force 时删除，
+    // 返回本地路径、缩略图和预览图路径以便删除文件
+    pub fn cleanup_old_files_in(
+        conn: &Connection,
+        days: i32,
+        force: bool,
+    ) -> Result<Vec<CachedFilePaths>, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare(&format!(
+            "DELETE FROM file_cache WHERE last_accessed < ?1 AND {}
+             RETURNING local_path, thumbnail_path, preview_path, consultation_id, {}",
+            eviction_filter(force),
+            QUOTA_BYTES_SQL
+        ))?;
+        let rows = stmt
+            .query_map(params![SqlTimestamp::days_ago(days)], |row| {
+                Ok((
+                    (
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ),
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files = Vec::with_capacity(rows.len());
+        for (file, consultation_id, bytes) in rows {
+            if let Some(consultation_id) = &consultation_id {
+                Self::adjust_usage_in(conn, consultation_id, -bytes, -1)?;
+            }
+            files.push(file);
+        }
+
+        Ok(files)
+    }
+
+    // 删除本地文件已不存在的缓存记录，返回被删除的记录以便清理残留的缩略图和预览
+    pub fn delete_missing_files_in(
+        conn: &Connection,
+    ) -> Result<Vec<CachedFilePaths>, Box<dyn std::error::Error>> {
+        let entries = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, local_path, thumbnail_path, preview_path, consultation_id, {} FROM file_cache",
+                QUOTA_BYTES_SQL
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+
+        let mut deleted = Vec::new();
+        for (id, local_path, thumbnail_path, preview_path, consultation_id, bytes) in entries {
+            if std::path::Path::new(&local_path).exists() {
+                continue;
+            }
+            conn.execute("DELETE FROM file_cache WHERE id = ?1", params![id])?;
+            if let Some(consultation_id) = &consultation_id {
+                Self::adjust_usage_in(conn, consultation_id, -bytes, -1)?;
+            }
+            deleted.push((local_path, thumbnail_path, preview_path));
+        }
 
         Ok(deleted)
     }
 
-    pub fn cleanup_old_files(&self, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
+    // 按最近访问时间淘汰文件，只保留 max_files 个，固定的文件不参与淘汰也不占名额（force 时一并淘汰），
+    // 返回被删除的记录以便清理本地文件
+    pub fn cleanup_lru(&self, max_files: u32, force: bool) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let evicted = Self::select_in(
+            &tx,
+            &format!(
+                "WHERE {} ORDER BY last_accessed DESC LIMIT -1 OFFSET ?1",
+                eviction_filter(force)
+            ),
+            params![max_files],
+        )?;
+        Self::evict_in(&tx, &evicted)?;
 
-        let deleted = conn.execute(
-            "DELETE FROM file_cache WHERE last_accessed < datetime('now', '-' || ?1 || ' days')",
-            params![days],
+        tx.commit()?;
+        Ok(evicted)
+    }
+
+    // 从最久未访问的文件开始淘汰，直到缓存总字节数不超过 max_bytes；固定的文件仍计入总量但不会被淘汰，
+    // 因此固定文件过多时清理后仍可能超出上限
+    pub fn cleanup_oversized(&self, max_bytes: u64, force: bool) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let total: i64 = tx.query_row(
+            &format!("SELECT COALESCE(SUM({}), 0) FROM file_cache", QUOTA_BYTES_SQL),
+            [],
+            |row| row.get(0),
         )?;
+        let mut excess = total - max_bytes as i64;
+
+        let mut evicted = Vec::new();
+        if excess > 0 {
+            let candidates = Self::select_in(
+                &tx,
+                &format!("WHERE {} ORDER BY last_accessed ASC", eviction_filter(force)),
+                [],
+            )?;
+            for file in candidates {
+                if excess <= 0 {
+                    break;
+                }
+                excess -= quota_bytes(&file);
+                evicted.push(file);
+            }
+        }
+        Self::evict_in(&tx, &evicted)?;
 
-        Ok(deleted)
+        tx.commit()?;
+        Ok(evicted)
+    }
+
+    // 清空缓存，固定的文件仅在 force 时删除，返回被删除的记录以便清理本地文件
+    pub fn clear_all(&self, force: bool) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let evicted = Self::select_in(&tx, &format!("WHERE {}", eviction_filter(force)), [])?;
+        Self::evict_in(&tx, &evicted)?;
+
+        tx.commit()?;
+        Ok(evicted)
+    }
+
+    fn select_in(conn: &Connection, clause: &str, params: impl rusqlite::Params) -> Result<Vec<FileCache>> {
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM file_cache {}", CACHE_COLUMNS, clause))?;
+        let files = stmt.query_map(params, map_cache)?.collect::<Result<Vec<_>>>()?;
+        Ok(files)
+    }
+
+    // 删除选出的记录并扣减所属问诊的附件用量，引用固定记录随缓存记录级联删除
+    fn evict_in(conn: &Connection, files: &[FileCache]) -> Result<(), Box<dyn std::error::Error>> {
+        for file in files {
+            conn.execute("DELETE FROM file_cache WHERE id = ?1", params![file.id])?;
+            if let Some(consultation_id) = &file.consultation_id {
+                Self::adjust_usage_in(conn, consultation_id, -quota_bytes(file), -1)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 记录下载进度，首次下载时创建缓存记录；问诊附件记下所属问诊，已有归属的不再改变
+    pub fn save_download_progress(
+        &self,
+        file_url: &str,
+        local_path: &str,
+        file_size: Option<u64>,
+        bytes_downloaded: u64,
+        consultation_id: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = SqlTimestamp::now();
+
+        Self::track_usage_in(&tx, "file_url", file_url, |conn| {
+            conn.execute(
+                "INSERT INTO file_cache (id, file_url, local_path, file_size, downloaded_at, last_accessed, bytes_downloaded, consultation_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?6, ?7)
+                 ON CONFLICT(file_url) DO UPDATE SET
+                    local_path = excluded.local_path,
+                    file_size = COALESCE(excluded.file_size, file_cache.file_size),
+                    bytes_downloaded = excluded.bytes_downloaded,
+                    last_accessed = excluded.last_accessed,
+                    consultation_id = COALESCE(file_cache.consultation_id, excluded.consultation_id)",
+                params![Uuid::new_v4().to_string(), file_url, local_path, file_size, now, bytes_downloaded, consultation_id],
+            )
+        })?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    // 下载完成并校验通过后记录文件大小和校验和
+    pub fn complete_download(&self, file_url: &str, file_size: u64, checksum: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = SqlTimestamp::now();
+
+        Self::track_usage_in(&tx, "file_url", file_url, |conn| {
+            conn.execute(
+                "UPDATE file_cache SET file_size = ?1, bytes_downloaded = ?1, checksum = ?2, downloaded_at = ?3, last_accessed = ?3
+                 WHERE file_url = ?4",
+                params![file_size, checksum, now, file_url],
+            )
+        })?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    // 单个问诊的附件用量（字节数, 文件数），读取计数表，不扫描 file_cache
+    pub fn find_consultation_usage(&self, consultation_id: &str) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let usage = conn
+            .query_row(
+                "SELECT total_bytes, file_count FROM attachment_usage WHERE consultation_id = ?1",
+                params![consultation_id],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()?
+            .unwrap_or((0, 0));
+
+        Ok((usage.0.max(0) as u64, usage.1.max(0) as u64))
+    }
+
+    // 患者所有问诊的附件用量之和，经 idx_consultations_patient 只读取该患者的问诊
+    pub fn find_patient_usage(&self, patient_id: &str) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let usage = conn.query_row(
+            "SELECT COALESCE(SUM(u.total_bytes), 0), COALESCE(SUM(u.file_count), 0)
+             FROM consultations c JOIN attachment_usage u ON u.consultation_id = c.id
+             WHERE c.patient_id = ?1",
+            params![patient_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )?;
+
+        Ok((usage.0.max(0) as u64, usage.1.max(0) as u64))
+    }
+
+    // 累加到问诊的附件用量上，需在修改 file_cache 的同一事务内调用
+    pub fn adjust_usage_in(
+        conn: &Connection,
+        consultation_id: &str,
+        bytes: i64,
+        files: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO attachment_usage (consultation_id, total_bytes, file_count) VALUES (?1, ?2, ?3)
+             ON CONFLICT(consultation_id) DO UPDATE SET
+                total_bytes = total_bytes + excluded.total_bytes,
+                file_count = file_count + excluded.file_count",
+            params![consultation_id, bytes, files],
+        )?;
+        Ok(())
+    }
+
+    // 修改前后各读一次该记录的归属问诊和字节数，差额计入附件用量
+    fn track_usage_in<T>(
+        conn: &Connection,
+        key_column: &str,
+        key: &str,
+        change: impl FnOnce(&Connection) -> Result<T>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let before = Self::usage_entry_in(conn, key_column, key)?;
+        let result = change(conn)?;
+        let after = Self::usage_entry_in(conn, key_column, key)?;
+
+        if before != after {
+            if let Some((consultation_id, bytes)) = before {
+                Self::adjust_usage_in(conn, &consultation_id, -bytes, -1)?;
+            }
+            if let Some((consultation_id, bytes)) = after {
+                Self::adjust_usage_in(conn, &consultation_id, bytes, 1)?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn usage_entry_in(conn: &Connection, key_column: &str, key: &str) -> Result<Option<(String, i64)>> {
+        let entry = conn
+            .query_row(
+                &format!(
+                    "SELECT consultation_id, {} FROM file_cache WHERE {} = ?1",
+                    QUOTA_BYTES_SQL, key_column
+                ),
+                params![key],
+                |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()?;
+
+        Ok(entry.and_then(|(consultation_id, bytes)| consultation_id.map(|id| (id, bytes))))
+    }
+
+    // 记录文档预览，按本地路径匹配上传和下载的缓存记录
+    pub fn set_preview(&self, local_path: &str, preview: &FilePreview) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE file_cache SET preview_path = ?1, preview_text = ?2 WHERE local_path = ?3",
+            params![preview.preview_path, preview.preview_text, local_path],
+        )?;
+
+        Ok(updated)
+    }
+
+    pub fn find_preview(&self, local_path: &str) -> Result<Option<FilePreview>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let preview = conn
+            .query_row(
+                "SELECT preview_path, preview_text FROM file_cache
+                 WHERE local_path = ?1 AND (preview_path IS NOT NULL OR preview_text IS NOT NULL)
+                 LIMIT 1",
+                params![local_path],
+                |row| {
+                    Ok(FilePreview {
+                        preview_path: row.get(0)?,
+                        preview_text: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(preview)
+    }
+
+    // 压缩后上传的图片返回压缩前的字节数，按本地路径匹配上传记录
+    pub fn find_original_size(&self, local_path: &str) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let size = conn
+            .query_row(
+                "SELECT original_size FROM file_cache WHERE local_path = ?1 AND original_size IS NOT NULL LIMIT 1",
+                params![local_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(size)
+    }
+
+    pub fn pin_ref(&self, file_ref: &str, reason: &str, ref_id: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        Self::pin_ref_in(&conn, file_ref, reason, ref_id)
+    }
+
+    // 在调用方的事务内为引用方固定文件，file_ref 可以是缓存记录 ID、远程地址或本地路径（上传记录两者都有）；
+    // 重复固定不产生新记录，返回新固定的缓存记录数，文件尚未缓存时为 0
+    pub fn pin_ref_in(
+        conn: &Connection,
+        file_ref: &str,
+        reason: &str,
+        ref_id: &str,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let pinned = conn.execute(
+            "INSERT OR IGNORE INTO pinned_by_ref (cache_id, reason, ref_id, pinned_at)
+             SELECT id, ?2, ?3, ?4 FROM file_cache WHERE id = ?1 OR file_url = ?1 OR local_path = ?1",
+            params![file_ref, reason, ref_id, SqlTimestamp::now()],
+        )?;
+        Ok(pinned)
+    }
+
+    pub fn unpin_ref(&self, reason: &str, ref_id: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        Self::unpin_ref_in(&conn, reason, ref_id)
+    }
+
+    // 解除引用方的全部固定，文件的其他引用不受影响
+    pub fn unpin_ref_in(conn: &Connection, reason: &str, ref_id: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let unpinned = conn.execute(
+            "DELETE FROM pinned_by_ref WHERE reason = ?1 AND ref_id = ?2",
+            params![reason, ref_id],
+        )?;
+        Ok(unpinned)
+    }
+
+    // 所有按引用固定的文件，按固定时间排序，供诊断缓存为何无法释放
+    pub fn get_pinned_files(&self) -> Result<Vec<PinnedFile>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT f.id, f.file_url, f.local_path, f.file_size, p.reason, p.ref_id, p.pinned_at
+             FROM pinned_by_ref p JOIN file_cache f ON f.id = p.cache_id
+             ORDER BY p.pinned_at ASC, p.reason ASC, p.ref_id ASC",
+        )?;
+        let files = stmt
+            .query_map([], |row| {
+                Ok(PinnedFile {
+                    cache_id: row.get(0)?,
+                    file_url: row.get(1)?,
+                    local_path: row.get(2)?,
+                    file_size: row.get(3)?,
+                    reason: row.get(4)?,
+                    ref_id: row.get(5)?,
+                    pinned_at: row.get::<_, SqlTimestamp>(6)?.0,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(files)
     }
 }
 
+fn map_cache(row: &Row) -> Result<FileCache> {
+    Ok(FileCache {
+        id: row.get(0)?,
+        file_url: row.get(1)?,
+        local_path: row.get(2)?,
+        file_size: row.get(3)?,
+        mime_type: row.get(4)?,
+        checksum: row.get(5)?,
+        expires_at: row.get::<_, Option<SqlTimestamp>>(6)?.map(|t| t.0),
+        downloaded_at: row.get::<_, SqlTimestamp>(7)?.0,
+        last_accessed: row.get::<_, SqlTimestamp>(8)?.0,
+        pinned: row.get(9)?,
+        thumbnail_path: row.get(10)?,
+        bytes_downloaded: row.get(11)?,
+        preview_path: row.get(12)?,
+        preview_text: row.get(13)?,
+        consultation_id: row.get(14)?,
+        original_size: row.get(15)?,
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct CacheStats {
     pub total_files: i64,
@@ -175,12 +570,14 @@ pub struct CacheStats {
 impl BaseDao<FileCache> for FileCacheDao {
     fn create(&self, cache: &FileCache) -> Result<String, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
         let id = Uuid::new_v4().to_string();
         let now = Utc::now();
 
-        conn.execute(
-            "INSERT INTO file_cache (id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        tx.execute(
+            "INSERT INTO file_cache (id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed, thumbnail_path, bytes_downloaded,
+                                     preview_path, preview_text, consultation_id, original_size)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
             params![
                 id,
                 cache.file_url,
@@ -188,97 +585,76 @@ impl BaseDao<FileCache> for FileCacheDao {
                 cache.file_size,
                 cache.mime_type,
                 cache.checksum,
-                cache.expires_at,
-                now,
-                now
+                cache.expires_at.map(SqlTimestamp),
+                SqlTimestamp(now),
+                SqlTimestamp(now),
+                cache.thumbnail_path,
+                cache.bytes_downloaded,
+                cache.preview_path,
+                cache.preview_text,
+                cache.consultation_id,
+                cache.original_size
             ],
         )?;
+        if let Some(consultation_id) = &cache.consultation_id {
+            Self::adjust_usage_in(&tx, consultation_id, quota_bytes(cache), 1)?;
+        }
 
+        tx.commit()?;
         Ok(id)
     }
 
     fn find_by_id(&self, id: &str) -> Result<Option<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed
-             FROM file_cache WHERE id = ?1"
-        )?;
-
-        let cache_result = stmt.query_row(params![id], |row| {
-            Ok(FileCache {
-                id: row.get(0)?,
-                file_url: row.get(1)?,
-                local_path: row.get(2)?,
-                file_size: row.get(3)?,
-                mime_type: row.get(4)?,
-                checksum: row.get(5)?,
-                expires_at: row.get(6)?,
-                downloaded_at: row.get(7)?,
-                last_accessed: row.get(8)?,
-            })
-        });
-
-        match cache_result {
-            Ok(cache) => Ok(Some(cache)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(Box::new(e)),
-        }
+        let files = Self::select_in(&conn, "WHERE id = ?1", params![id])?;
+        Ok(files.into_iter().next())
     }
 
     fn update(&self, cache: &FileCache) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        Self::track_usage_in(&tx, "id", &cache.id, |conn| {
+            conn.execute(
+                "UPDATE file_cache SET file_url = ?1, local_path = ?2, file_size = ?3, mime_type = ?4,
+                 checksum = ?5, expires_at = ?6, downloaded_at = ?7, last_accessed = ?8, thumbnail_path = ?9, bytes_downloaded = ?10,
+                 preview_path = ?11, preview_text = ?12, consultation_id = ?13 WHERE id = ?14",
+                params![
+                    cache.file_url,
+                    cache.local_path,
+                    cache.file_size,
+                    cache.mime_type,
+                    cache.checksum,
+                    cache.expires_at.map(SqlTimestamp),
+                    SqlTimestamp(cache.downloaded_at),
+                    SqlTimestamp(cache.last_accessed),
+                    cache.thumbnail_path,
+                    cache.bytes_downloaded,
+                    cache.preview_path,
+                    cache.preview_text,
+                    cache.consultation_id,
+                    cache.id
+                ],
+            )
+        })?;
 
-        conn.execute(
-            "UPDATE file_cache SET file_url = ?1, local_path = ?2, file_size = ?3, mime_type = ?4,
-             checksum = ?5, expires_at = ?6, downloaded_at = ?7, last_accessed = ?8 WHERE id = ?9",
-            params![
-                cache.file_url,
-                cache.local_path,
-                cache.file_size,
-                cache.mime_type,
-                cache.checksum,
-                cache.expires_at,
-                cache.downloaded_at,
-                cache.last_accessed,
-                cache.id
-            ],
-        )?;
-
+        tx.commit()?;
         Ok(())
     }
 
     fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        conn.execute("DELETE FROM file_cache WHERE id = ?1", params![id])?;
+        let tx = conn.unchecked_transaction()?;
+        Self::track_usage_in(&tx, "id", id, |conn| {
+            conn.execute("DELETE FROM file_cache WHERE id = ?1", params![id])
+        })?;
+        tx.commit()?;
         Ok(())
     }
 
     fn find_all(&self) -> Result<Vec<FileCache>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, file_url, local_path, file_size, mime_type, checksum, expires_at, downloaded_at, last_accessed
-             FROM file_cache ORDER BY downloaded_at DESC"
-        )?;
-
-        let cache_iter = stmt.query_map([], |row| {
-            Ok(FileCache {
-                id: row.get(0)?,
-                file_url: row.get(1)?,
-                local_path: row.get(2)?,
-                file_size: row.get(3)?,
-                mime_type: row.get(4)?,
-                checksum: row.get(5)?,
-                expires_at: row.get(6)?,
-                downloaded_at: row.get(7)?,
-                last_accessed: row.get(8)?,
-            })
-        })?;
-
-        let mut files = Vec::new();
-        for file in cache_iter {
-            files.push(file?);
-        }
-
+        let files = Self::select_in(&conn, "ORDER BY downloaded_at DESC", [])?;
         Ok(files)
     }
 }
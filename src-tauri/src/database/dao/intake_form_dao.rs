@@ -0,0 +1,195 @@
+// 问诊问卷数据访问层，未通过格式校验的问卷同样保存原始数据并记录异常原因
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::{IntakeForm, IntakeFormSubmission};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+pub struct IntakeFormDao {
+    connection: DbConnection,
+}
+
+impl IntakeFormDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 每个问诊一份问卷，患者重新提交时覆盖；晚到的旧版本不会覆盖较新的提交
+    pub fn upsert_in(conn: &Connection, form: &IntakeForm) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO intake_forms (consultation_id, schema_version, data, format_error, submitted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(consultation_id) DO UPDATE SET
+                schema_version = excluded.schema_version,
+                data = excluded.data,
+                format_error = excluded.format_error,
+                submitted_at = excluded.submitted_at
+             WHERE excluded.submitted_at >= intake_forms.submitted_at",
+            params![
+                form.consultation_id,
+                form.schema_version,
+                form.data.to_string(),
+                form.format_error,
+                form.submitted_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    // 校验后保存，返回解析结果
+    pub fn save(&self, submission: IntakeFormSubmission) -> Result<IntakeForm, Box<dyn std::error::Error>> {
+        let form = IntakeForm::from_submission(submission);
+        if let Some(error) = &form.format_error {
+            tracing::warn!("Intake form for consultation {} is malformed: {}", form.consultation_id, error);
+        }
+
+        let conn = self.connection.lock().unwrap();
+        Self::upsert_in(&conn, &form)?;
+        Ok(form)
+    }
+
+    pub fn find(&self, consultation_id: &str) -> Result<Option<IntakeForm>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let form = conn.query_row(
+            "SELECT consultation_id, schema_version, data, format_error, submitted_at FROM intake_forms
+             WHERE consultation_id = ?1",
+            params![consultation_id],
+            map_form,
+        ).optional()?;
+
+        Ok(form)
+    }
+}
+
+impl Default for IntakeFormDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 已标记异常的问卷不再解析，直接返回原始数据
+fn map_form(row: &Row) -> Result<IntakeForm> {
+    let stored: String = row.get(2)?;
+    let data = serde_json::from_str(&stored)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, e.into()))?;
+    let submission = IntakeFormSubmission {
+        consultation_id: row.get(0)?,
+        schema_version: row.get(1)?,
+        data,
+        submitted_at: row.get(4)?,
+    };
+
+    let format_error: Option<String> = row.get(3)?;
+    Ok(match format_error {
+        Some(error) => IntakeForm {
+            consultation_id: submission.consultation_id,
+            schema_version: submission.schema_version,
+            sections: None,
+            data: submission.data,
+            format_error: Some(error),
+            submitted_at: submission.submitted_at,
+        },
+        None => IntakeForm::from_submission(submission),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::IntakeFormSections;
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn create_dao() -> IntakeFormDao {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'pending');",
+        )
+        .unwrap();
+        IntakeFormDao::with_connection(Arc::new(Mutex::new(conn)))
+    }
+
+    fn submission(schema_version: u32, data: serde_json::Value) -> IntakeFormSubmission {
+        IntakeFormSubmission {
+            consultation_id: "c1".to_string(),
+            schema_version,
+            data,
+            submitted_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_v1_form_parsed_with_unknown_fields_preserved() {
+        let dao = create_dao();
+        let data = json!({
+            "chiefComplaint": "反复咳嗽",
+            "duration": "3天",
+            "allergies": ["青霉素"],
+            "smoking": false,
+        });
+        dao.save(submission(1, data.clone())).unwrap();
+
+        let form = dao.find("c1").unwrap().unwrap();
+        assert!(!form.is_malformed());
+        assert_eq!(form.data, data);
+        let Some(IntakeFormSections::V1(v1)) = &form.sections else {
+            panic!("expected v1 sections");
+        };
+        assert_eq!(v1.chief_complaint, "反复咳嗽");
+        assert_eq!(v1.duration.as_deref(), Some("3天"));
+        assert_eq!(v1.allergies, vec!["青霉素"]);
+        assert_eq!(v1.extra.get("smoking"), Some(&json!(false)));
+
+        // 返回前端时未知字段与已知字段位于同一层
+        let serialized = serde_json::to_value(&form).unwrap();
+        assert_eq!(serialized["sections"]["smoking"], json!(false));
+        assert_eq!(serialized["formatError"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_unknown_version_and_invalid_v1_stored_raw_and_flagged() {
+        let dao = create_dao();
+        let v2 = json!({
+            "sections": [{ "id": "complaint", "answer": "头痛" }],
+        });
+        let saved = dao.save(submission(2, v2.clone())).unwrap();
+        assert!(saved.is_malformed());
+
+        let form = dao.find("c1").unwrap().unwrap();
+        assert_eq!(form.schema_version, 2);
+        assert!(form.sections.is_none());
+        assert_eq!(form.data, v2);
+        assert!(form.format_error.unwrap().contains('2'));
+
+        // v1 缺少主诉
+        dao.save(submission(1, json!({ "allergies": ["花粉"] }))).unwrap();
+        let form = dao.find("c1").unwrap().unwrap();
+        assert!(form.is_malformed());
+        assert_eq!(form.data["allergies"], json!(["花粉"]));
+    }
+
+    #[test]
+    fn test_older_submission_does_not_overwrite() {
+        let dao = create_dao();
+        dao.save(submission(1, json!({ "chiefComplaint": "腹痛加重" }))).unwrap();
+
+        let mut stale = submission(1, json!({ "chiefComplaint": "腹痛" }));
+        stale.submitted_at = Utc::now() - Duration::hours(1);
+        dao.save(stale).unwrap();
+
+        let form = dao.find("c1").unwrap().unwrap();
+        assert_eq!(form.data["chiefComplaint"], "腹痛加重");
+        assert!(dao.find("missing").unwrap().is_none());
+    }
+}
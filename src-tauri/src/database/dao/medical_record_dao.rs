@@ -1,9 +1,10 @@
 // 医疗记录数据访问层
 
 use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::file_cache_dao::{FileCacheDao, PIN_REASON_MEDICAL_RECORD};
 use crate::database::dao::BaseDao;
-use crate::models::MedicalRecord;
-use rusqlite::{params, Result};
+use crate::models::{DataScope, MedicalRecord, TrashEntityType, TrashItem};
+use rusqlite::{params, Connection, Result};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -18,11 +19,15 @@ impl MedicalRecordDao {
         }
     }
 
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
     pub fn find_by_patient_id(&self, patient_id: &str) -> Result<Vec<MedicalRecord>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records WHERE patient_id = ?1 ORDER BY created_at DESC"
+             FROM medical_records WHERE patient_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC"
         )?;
 
         let record_iter = stmt.query_map(params![patient_id], |row| {
@@ -54,7 +59,7 @@ impl MedicalRecordDao {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records WHERE consultation_id = ?1 ORDER BY created_at DESC"
+             FROM medical_records WHERE consultation_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC"
         )?;
 
         let record_iter = stmt.query_map(params![consultation_id], |row| {
@@ -86,7 +91,7 @@ impl MedicalRecordDao {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records WHERE patient_id = ?1 AND record_type = ?2 ORDER BY created_at DESC"
+             FROM medical_records WHERE patient_id = ?1 AND record_type = ?2 AND deleted_at IS NULL ORDER BY created_at DESC"
         )?;
 
         let record_iter = stmt.query_map(params![patient_id, record_type], |row| {
@@ -120,12 +125,12 @@ impl MedicalRecordDao {
         let sql = if let Some(limit) = limit {
             format!(
                 "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-                 FROM medical_records WHERE doctor_id = ?1 ORDER BY created_at DESC LIMIT {}",
+                 FROM medical_records WHERE doctor_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC LIMIT {}",
                 limit
             )
         } else {
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records WHERE doctor_id = ?1 ORDER BY created_at DESC".to_string()
+             FROM medical_records WHERE doctor_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC".to_string()
         };
 
         let mut stmt = conn.prepare(&sql)?;
@@ -160,7 +165,7 @@ impl MedicalRecordDao {
 
         let mut stmt = conn.prepare(
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records WHERE patient_id = ?1 AND (title LIKE ?2 OR content LIKE ?2) ORDER BY created_at DESC"
+             FROM medical_records WHERE patient_id = ?1 AND deleted_at IS NULL AND (title LIKE ?2 OR content LIKE ?2) ORDER BY created_at DESC"
         )?;
 
         let record_iter = stmt.query_map(params![patient_id, search_pattern], |row| {
@@ -187,6 +192,63 @@ impl MedicalRecordDao {
 
         Ok(records)
     }
+
+    // 从回收站恢复，限定医生时只能恢复自己的病历；病历不在回收站时返回 false
+    pub fn restore_in_scope(&self, id: &str, scope: &DataScope) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let restored = conn.execute(
+            "UPDATE medical_records SET deleted_at = NULL
+             WHERE id = ?1 AND deleted_at IS NOT NULL AND (?2 IS NULL OR doctor_id = ?2)",
+            params![id, scope.doctor_id()],
+        )?;
+
+        Ok(restored > 0)
+    }
+
+    // 回收站中的病历，最近删除的在前
+    pub fn find_deleted_in_scope(&self, scope: &DataScope) -> Result<Vec<TrashItem>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, consultation_id, patient_id, title, deleted_at FROM medical_records
+             WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR doctor_id = ?1)
+             ORDER BY deleted_at DESC"
+        )?;
+
+        let items = stmt.query_map(params![scope.doctor_id()], |row| {
+            Ok(TrashItem {
+                entity_type: TrashEntityType::MedicalRecord,
+                id: row.get(0)?,
+                consultation_id: row.get(1)?,
+                patient_id: row.get(2)?,
+                summary: row.get(3)?,
+                deleted_at: row.get(4)?,
+            })
+        })?;
+
+        Ok(items.collect::<Result<Vec<_>>>()?)
+    }
+
+    // 在调用方的事务内彻底清除在回收站中超过 days 天的病历，并解除这些病历对附件的固定
+    pub fn purge_deleted_in(conn: &Connection, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare(
+            "DELETE FROM medical_records WHERE deleted_at < datetime('now', '-' || ?1 || ' days')
+             RETURNING id"
+        )?;
+        let purged = stmt
+            .query_map(params![days], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        for record_id in &purged {
+            FileCacheDao::unpin_ref_in(conn, PIN_REASON_MEDICAL_RECORD, record_id)?;
+        }
+
+        if !purged.is_empty() {
+            tracing::info!("Purged {} deleted medical records (in trash for more than {} days)", purged.len(), days);
+        }
+
+        Ok(purged.len())
+    }
 }
 
 impl BaseDao<MedicalRecord> for MedicalRecordDao {
@@ -220,7 +282,7 @@ impl BaseDao<MedicalRecord> for MedicalRecordDao {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records WHERE id = ?1"
+             FROM medical_records WHERE id = ?1 AND deleted_at IS NULL"
         )?;
 
         let record_result = stmt.query_row(params![id], |row| {
@@ -271,9 +333,13 @@ impl BaseDao<MedicalRecord> for MedicalRecordDao {
         Ok(())
     }
 
+    // 软删除，病历移入回收站
     fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
-        conn.execute("DELETE FROM medical_records WHERE id = ?1", params![id])?;
+        conn.execute(
+            "UPDATE medical_records SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, Utc::now()],
+        )?;
         Ok(())
     }
 
@@ -281,7 +347,7 @@ impl BaseDao<MedicalRecord> for MedicalRecordDao {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at
-             FROM medical_records ORDER BY created_at DESC"
+             FROM medical_records WHERE deleted_at IS NULL ORDER BY created_at DESC"
         )?;
 
         let record_iter = stmt.query_map([], |row| {
@@ -1,40 +1,249 @@
 // 消息数据访问层
 
 use crate::database::connection::{get_database, DbConnection};
-use crate::database::dao::{BaseDao, PageResult};
-use crate::models::Message;
-use rusqlite::{params, Result};
+use crate::database::dao::{BaseDao, MessageDraftDao, OutboxDao, PageResult};
+use crate::database::dao::escape_like;
+use crate::database::dao::file_cache_dao::{FileCacheDao, PIN_REASON_OUTBOX};
+use crate::database::query_optimizer::{
+    consultation_messages_tag, get_query_optimizer, query_cache_for, BatchOperations, BULK_BATCH_SIZE, CACHE_TAG_MESSAGES,
+};
+use crate::database::retry::{retry_on_busy, retry_transaction_on_busy};
+use crate::utils::SqlTimestamp;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::models::{
+    DataScope, Message, MessageType, OutboxEntry, PinnedMessage, ReadStatus, SenderType, SyncStatus, SystemEvent, SystemEventKind,
+    TrashEntityType, TrashItem,
+};
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 
 pub struct MessageDao {
     connection: DbConnection,
+    // 本实例实际查询消息分页的次数，用于确认缓存命中时没有访问数据库
+    history_queries: AtomicUsize,
 }
 
 impl MessageDao {
     pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
         Self {
-            connection: get_database().get_connection(),
+            connection,
+            history_queries: AtomicUsize::new(0),
+        }
+    }
+
+    // 消息数据变更后清除相关查询缓存
+    fn invalidate_cache(&self) {
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+    }
+
+    // 新消息只影响所在问诊的缓存，其他问诊预热的消息保留
+    fn invalidate_consultation_cache(&self, consultation_id: &str) {
+        query_cache_for(&self.connection).invalidate_tag(&consultation_messages_tag(consultation_id));
+    }
+
+    pub fn history_query_count(&self) -> usize {
+        self.history_queries.load(Ordering::Relaxed)
+    }
+
+    // 写入远端同步下来的消息（保留远端 ID），在调用方的事务内执行
+    pub fn upsert_in(conn: &Connection, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                consultation_id = excluded.consultation_id,
+                sender_type = excluded.sender_type,
+                message_type = excluded.message_type,
+                content = excluded.content,
+                file_path = excluded.file_path,
+                file_size = excluded.file_size,
+                mime_type = excluded.mime_type,
+                timestamp = excluded.timestamp,
+                sync_status = excluded.sync_status,
+                read_status = excluded.read_status,
+                template_id = excluded.template_id,
+                duration_ms = excluded.duration_ms,
+                waveform = excluded.waveform",
+            params![
+                message.id,
+                message.consultation_id,
+                message.sender_type,
+                message.message_type,
+                message.content,
+                message.file_path,
+                message.file_size,
+                message.mime_type,
+                SqlTimestamp(message.timestamp),
+                message.sync_status,
+                message.read_status,
+                message.template_id,
+                message.duration_ms,
+                waveform_json(&message.waveform)
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    // 保存清洗前的消息原文，upsert_in 不覆盖该列
+    pub fn set_raw_content_in(conn: &Connection, message_id: &str, raw_content: &str) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "UPDATE messages SET raw_content = ?2 WHERE id = ?1",
+            params![message_id, raw_content],
+        )?;
+        Ok(())
+    }
+
+    // 清洗前的原文，外层 None 表示消息不存在，内层 None 表示正文未被清洗改写
+    pub fn find_raw_content(&self, message_id: &str) -> Result<Option<Option<String>>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let raw_content = conn
+            .query_row(
+                "SELECT raw_content FROM messages WHERE id = ?1",
+                params![message_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(raw_content)
+    }
+
+    // 首次同步时批量写入服务器下发的消息，按远端 ID 去重，已存在的消息保持不变；返回实际新增条数
+    pub fn bulk_insert(&self, messages: &[Message]) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let inserted = Cell::new(0usize);
+
+        BatchOperations::batch_insert(&conn, messages, BULK_BATCH_SIZE, |tx, chunk| {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO messages (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
+            )?;
+            for message in chunk {
+                let changed = stmt.execute(params![
+                    message.id,
+                    message.consultation_id,
+                    message.sender_type,
+                    message.message_type,
+                    message.content,
+                    message.file_path,
+                    message.file_size,
+                    message.mime_type,
+                    SqlTimestamp(message.timestamp),
+                    message.sync_status,
+                    message.read_status,
+                    message.template_id,
+                    message.duration_ms,
+                    waveform_json(&message.waveform)
+                ])?;
+                inserted.set(inserted.get() + changed);
+            }
+            Ok(())
+        })?;
+        drop(conn);
+
+        if inserted.get() > 0 {
+            self.invalidate_cache();
         }
+        Ok(inserted.get())
     }
 
     pub fn find_by_consultation_id(&self, consultation_id: &str, page: i32, page_size: i32) -> Result<PageResult<Message>, String> {
+        self.find_by_consultation_id_in_scope(consultation_id, &DataScope::All, page, page_size)
+    }
+
+    // 限定医生时通过问诊关联校验归属，其他医生的问诊返回空列表
+    pub fn find_by_consultation_id_in_scope(
+        &self,
+        consultation_id: &str,
+        scope: &DataScope,
+        page: i32,
+        page_size: i32,
+    ) -> Result<PageResult<Message>, String> {
+        self.history_queries.fetch_add(1, Ordering::Relaxed);
         let conn = self.connection.lock().unwrap();
         let offset = (page - 1) * page_size;
+        let doctor_id = scope.doctor_id();
 
         // 获取总数
-        let mut count_stmt = conn.prepare("SELECT COUNT(*) FROM messages WHERE consultation_id = ?1")
-            .map_err(|e| e.to_string())?;
-        let total: i64 = count_stmt.query_row(params![consultation_id], |row| row.get(0))
+        let mut count_stmt = conn.prepare(
+            "SELECT COUNT(*) FROM messages m LEFT JOIN consultations c ON c.id = m.consultation_id
+             WHERE m.consultation_id = ?1 AND m.deleted_at IS NULL AND (?2 IS NULL OR c.doctor_id = ?2)"
+        ).map_err(|e| e.to_string())?;
+        let total: i64 = count_stmt.query_row(params![consultation_id, doctor_id], |row| row.get(0))
             .map_err(|e| e.to_string())?;
 
         // 获取分页数据，按时间倒序排列（最新的在前面）
+        let sql = "SELECT m.id, m.consultation_id, m.sender_type, m.message_type, m.content, m.file_path, m.file_size, m.mime_type, m.timestamp,
+                    m.sync_status, m.read_status, m.template_id, m.duration_ms, m.waveform, m.pinned
+             FROM messages m LEFT JOIN consultations c ON c.id = m.consultation_id
+             WHERE m.consultation_id = ?1 AND m.deleted_at IS NULL AND (?2 IS NULL OR c.doctor_id = ?2)
+             ORDER BY m.timestamp DESC LIMIT ?3 OFFSET ?4";
+
+        let messages = get_query_optimizer().execute_sql(&conn, "message_history", sql, || {
+            let mut stmt = conn.prepare(sql)?;
+            let message_iter = stmt.query_map(params![consultation_id, doctor_id, page_size, offset], |row| {
+                Ok(Message {
+                    id: row.get(0)?,
+                    consultation_id: row.get(1)?,
+                    sender_type: row.get(2)?,
+                    message_type: row.get(3)?,
+                    content: row.get(4)?,
+                    file_path: row.get(5)?,
+                    file_size: row.get(6)?,
+                    mime_type: row.get(7)?,
+                    timestamp: row.get::<_, SqlTimestamp>(8)?.0,
+                    sync_status: row.get(9)?,
+                    read_status: row.get(10)?,
+                    template_id: row.get(11)?,
+                    duration_ms: row.get(12)?,
+                    waveform: parse_waveform(row.get(13)?),
+                    pinned: row.get(14)?,
+                })
+            })?;
+            message_iter.collect::<Result<Vec<Message>>>()
+        }).map_err(|e| e.to_string())?;
+
+        Ok(PageResult::new(messages, total, page, page_size))
+    }
+
+    // 问诊的第一页消息优先从查询缓存读取，未命中时查询后写入缓存；缓存按数据范围和分页大小区分
+    pub fn find_first_page_cached(
+        &self,
+        consultation_id: &str,
+        scope: &DataScope,
+        page_size: i32,
+    ) -> Result<PageResult<Message>, String> {
+        let cache = query_cache_for(&self.connection);
+        let key = format!(
+            "messages:history:{}:{}:{}",
+            consultation_id,
+            scope.doctor_id().unwrap_or("*"),
+            page_size
+        );
+        if let Some(page) = cache.get_as::<PageResult<Message>>(&key) {
+            return Ok(page);
+        }
+
+        let page = self.find_by_consultation_id_in_scope(consultation_id, scope, 1, page_size)?;
+        cache.set_with_tags(&key, &page, &[CACHE_TAG_MESSAGES, &consultation_messages_tag(consultation_id)]);
+        Ok(page)
+    }
+
+    // 获取问诊的全部消息，按时间正序排列
+    pub fn find_all_by_consultation_id(&self, consultation_id: &str) -> Result<Vec<Message>, String> {
+        let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status
-             FROM messages WHERE consultation_id = ?1 ORDER BY timestamp DESC LIMIT ?2 OFFSET ?3"
+            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform, pinned
+             FROM messages WHERE consultation_id = ?1 AND deleted_at IS NULL ORDER BY timestamp ASC"
         ).map_err(|e| e.to_string())?;
 
-        let message_iter = stmt.query_map(params![consultation_id, page_size, offset], |row| {
+        let message_iter = stmt.query_map(params![consultation_id], |row| {
             Ok(Message {
                 id: row.get(0)?,
                 consultation_id: row.get(1)?,
@@ -44,9 +253,13 @@ impl MessageDao {
                 file_path: row.get(5)?,
                 file_size: row.get(6)?,
                 mime_type: row.get(7)?,
-                timestamp: row.get(8)?,
+                timestamp: row.get::<_, SqlTimestamp>(8)?.0,
                 sync_status: row.get(9)?,
                 read_status: row.get(10)?,
+                template_id: row.get(11)?,
+                duration_ms: row.get(12)?,
+                waveform: parse_waveform(row.get(13)?),
+                pinned: row.get(14)?,
             })
         }).map_err(|e| e.to_string())?;
 
@@ -55,14 +268,37 @@ impl MessageDao {
             messages.push(message.map_err(|e| e.to_string())?);
         }
 
-        Ok(PageResult::new(messages, total, page, page_size))
+        Ok(messages)
+    }
+
+    pub fn count_unsynced_messages(&self) -> Result<i64, String> {
+        let conn = self.connection.lock().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM messages WHERE sync_status = 'pending' AND deleted_at IS NULL", [], |row| row.get(0))
+            .map_err(|e| e.to_string())
+    }
+
+    // 消息在待发送队列中的位置（从 1 开始），与推送顺序一致；已发送或不存在时返回 None
+    pub fn queue_position(&self, message_id: &str) -> Result<Option<i64>, String> {
+        let conn = self.connection.lock().unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM messages q, messages m
+             WHERE m.id = ?1 AND m.sync_status = 'pending' AND q.sync_status = 'pending'
+               AND m.deleted_at IS NULL AND q.deleted_at IS NULL
+               AND (q.timestamp < m.timestamp OR (q.timestamp = m.timestamp AND q.id <= m.id))",
+            params![message_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|position| (position > 0).then_some(position))
+        .map_err(|e| e.to_string())
     }
 
+    // 批量同步推送的待发送消息；发件箱中的消息由发件箱带幂等键发送，不在此重复推送
     pub fn find_unsynced_messages(&self) -> Result<Vec<Message>, String> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status
-             FROM messages WHERE sync_status = 'pending' ORDER BY timestamp ASC"
+            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform, pinned
+             FROM messages WHERE sync_status = 'pending' AND deleted_at IS NULL AND id NOT IN (SELECT message_id FROM outbox)
+             ORDER BY timestamp ASC, id ASC"
         ).map_err(|e| e.to_string())?;
 
         let message_iter = stmt.query_map([], |row| {
@@ -75,9 +311,13 @@ impl MessageDao {
                 file_path: row.get(5)?,
                 file_size: row.get(6)?,
                 mime_type: row.get(7)?,
-                timestamp: row.get(8)?,
+                timestamp: row.get::<_, SqlTimestamp>(8)?.0,
                 sync_status: row.get(9)?,
                 read_status: row.get(10)?,
+                template_id: row.get(11)?,
+                duration_ms: row.get(12)?,
+                waveform: parse_waveform(row.get(13)?),
+                pinned: row.get(14)?,
             })
         }).map_err(|e| e.to_string())?;
 
@@ -89,7 +329,7 @@ impl MessageDao {
         Ok(messages)
     }
 
-    pub fn update_sync_status(&self, message_id: &str, status: &str) -> Result<(), String> {
+    pub fn update_sync_status(&self, message_id: &str, status: SyncStatus) -> Result<(), String> {
         let conn = self.connection.lock().unwrap();
 
         conn.execute(
@@ -97,10 +337,11 @@ impl MessageDao {
             params![status, message_id],
         ).map_err(|e| e.to_string())?;
 
+        self.invalidate_cache();
         Ok(())
     }
 
-    pub fn update_read_status(&self, message_id: &str, status: &str) -> Result<(), String> {
+    pub fn update_read_status(&self, message_id: &str, status: ReadStatus) -> Result<(), String> {
         let conn = self.connection.lock().unwrap();
 
         conn.execute(
@@ -108,24 +349,139 @@ impl MessageDao {
             params![status, message_id],
         ).map_err(|e| e.to_string())?;
 
+        self.invalidate_cache();
         Ok(())
     }
 
+    // 对方已读回执：把该问诊中截至指定时间、医生发出且未读的消息标记为已读，返回受影响的消息 ID
+    pub fn mark_sent_messages_read_up_to(
+        &self,
+        consultation_id: &str,
+        up_to: DateTime<Utc>,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let mut stmt = tx.prepare(
+            "SELECT id FROM messages
+             WHERE consultation_id = ?1 AND sender_type = 'doctor' AND read_status != 'read' AND timestamp <= ?2
+             ORDER BY timestamp ASC"
+        )?;
+        let ids = stmt
+            .query_map(params![consultation_id, SqlTimestamp(up_to)], |row| row.get(0))?
+            .collect::<Result<Vec<String>>>()?;
+        drop(stmt);
+
+        tx.execute(
+            "UPDATE messages SET read_status = 'read'
+             WHERE consultation_id = ?1 AND sender_type = 'doctor' AND read_status != 'read' AND timestamp <= ?2",
+            params![consultation_id, SqlTimestamp(up_to)],
+        )?;
+        tx.commit()?;
+        drop(conn);
+
+        if !ids.is_empty() {
+            self.invalidate_cache();
+        }
+        Ok(ids)
+    }
+
+    // 构造系统事件消息，内容为事件 JSON；系统消息不参与已读/未读
+    pub fn system_message(consultation_id: &str, kind: SystemEventKind, payload: serde_json::Value) -> Message {
+        let event = SystemEvent::new(kind, payload);
+
+        Message {
+            id: Uuid::new_v4().to_string(),
+            consultation_id: consultation_id.to_string(),
+            sender_type: SenderType::System,
+            message_type: MessageType::Event,
+            content: serde_json::to_string(&event).ok(),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now(),
+            sync_status: SyncStatus::Pending,
+            read_status: ReadStatus::Read,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        }
+    }
+
+    // 在会话中写入一条系统事件消息
+    pub fn insert_system_message(
+        &self,
+        consultation_id: &str,
+        kind: SystemEventKind,
+        payload: serde_json::Value,
+    ) -> Result<Message, Box<dyn std::error::Error>> {
+        let message = Self::system_message(consultation_id, kind, payload);
+        retry_on_busy(&self.connection, "insert system message", |conn| Self::upsert_in(conn, &message))?;
+
+        self.invalidate_consultation_cache(consultation_id);
+        Ok(message)
+    }
+
+    // 保存 WebSocket 实时推送的消息（保留远端 ID），由事件分发按问诊顺序逐条调用；原文由后续同步补写
+    pub fn save_incoming(&self, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
+        retry_on_busy(&self.connection, "save incoming message", |conn| Self::upsert_in(conn, message))?;
+
+        self.invalidate_consultation_cache(&message.consultation_id);
+        Ok(())
+    }
+
+    // 写入待发送的消息和发件箱记录，医生发送时同时清除该问诊下的草稿，三者在同一事务内提交
+    pub fn create_outgoing(&self, message: &Message, doctor_id: Option<&str>) -> Result<OutboxEntry, Box<dyn std::error::Error>> {
+        self.create_outgoing_with_raw(message, None, doctor_id)
+    }
+
+    // 同 create_outgoing，正文经过清洗时一并保存清洗前的原文
+    pub fn create_outgoing_with_raw(
+        &self,
+        message: &Message,
+        raw_content: Option<&str>,
+        doctor_id: Option<&str>,
+    ) -> Result<OutboxEntry, Box<dyn std::error::Error>> {
+        let entry = retry_transaction_on_busy(&self.connection, "create message", |tx| {
+            Self::upsert_in(tx, message)?;
+            if let Some(raw_content) = raw_content {
+                Self::set_raw_content_in(tx, &message.id, raw_content)?;
+            }
+            let entry = OutboxDao::enqueue_in(tx, &message.id)?;
+            // 附件在服务器确认前不能被缓存清理删除，否则重发时找不到文件
+            if let Some(file_path) = &message.file_path {
+                FileCacheDao::pin_ref_in(tx, file_path, PIN_REASON_OUTBOX, &message.id)?;
+            }
+            if let Some(doctor_id) = doctor_id {
+                MessageDraftDao::delete_in(tx, &message.consultation_id, doctor_id)?;
+            }
+            Ok(entry)
+        })?;
+
+        self.invalidate_consultation_cache(&message.consultation_id);
+        Ok(entry)
+    }
+
     pub fn mark_consultation_messages_as_read(&self, consultation_id: &str, sender_type: &str) -> Result<usize, String> {
         let conn = self.connection.lock().unwrap();
 
         let updated = conn.execute(
-            "UPDATE messages SET read_status = 'read' WHERE consultation_id = ?1 AND sender_type != ?2 AND read_status = 'unread'",
+            "UPDATE messages SET read_status = 'read'
+             WHERE consultation_id = ?1 AND sender_type != ?2 AND sender_type != 'system' AND read_status = 'unread'",
             params![consultation_id, sender_type],
         ).map_err(|e| e.to_string())?;
 
+        self.invalidate_cache();
         Ok(updated)
     }
 
     pub fn get_unread_count(&self, consultation_id: &str, sender_type: &str) -> Result<i64, String> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT COUNT(*) FROM messages WHERE consultation_id = ?1 AND sender_type != ?2 AND read_status = 'unread'"
+            "SELECT COUNT(*) FROM messages
+             WHERE consultation_id = ?1 AND sender_type != ?2 AND sender_type != 'system' AND read_status = 'unread'
+               AND deleted_at IS NULL"
         ).map_err(|e| e.to_string())?;
 
         let count: i64 = stmt.query_row(params![consultation_id, sender_type], |row| row.get(0))
@@ -136,8 +492,8 @@ impl MessageDao {
     pub fn get_latest_message(&self, consultation_id: &str) -> Result<Option<Message>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status
-             FROM messages WHERE consultation_id = ?1 ORDER BY timestamp DESC LIMIT 1"
+            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform, pinned
+             FROM messages WHERE consultation_id = ?1 AND deleted_at IS NULL ORDER BY timestamp DESC LIMIT 1"
         )?;
 
         let message_result = stmt.query_row(params![consultation_id], |row| {
@@ -150,9 +506,13 @@ impl MessageDao {
                 file_path: row.get(5)?,
                 file_size: row.get(6)?,
                 mime_type: row.get(7)?,
-                timestamp: row.get(8)?,
+                timestamp: row.get::<_, SqlTimestamp>(8)?.0,
                 sync_status: row.get(9)?,
                 read_status: row.get(10)?,
+                template_id: row.get(11)?,
+                duration_ms: row.get(12)?,
+                waveform: parse_waveform(row.get(13)?),
+                pinned: row.get(14)?,
             })
         });
 
@@ -163,31 +523,197 @@ impl MessageDao {
         }
     }
 
+    // 置顶前检查的消息状态，已删除（撤回）的消息同样返回，由调用方拒绝
+    pub fn find_pin_target(&self, message_id: &str) -> Result<Option<PinTarget>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let target = conn
+            .query_row(
+                "SELECT consultation_id, sender_type, message_type, deleted_at IS NOT NULL, pinned, pinned_at, pinned_by
+                 FROM messages WHERE id = ?1",
+                params![message_id],
+                |row| {
+                    Ok(PinTarget {
+                        consultation_id: row.get(0)?,
+                        sender_type: row.get(1)?,
+                        message_type: row.get(2)?,
+                        deleted: row.get(3)?,
+                        pinned: row.get(4)?,
+                        pinned_at: row.get::<_, Option<SqlTimestamp>>(5)?.map(|t| t.0),
+                        pinned_by: row.get(6)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(target)
+    }
+
+    // 问诊中置顶数未达上限时置顶，计数和写入在同一条语句内完成；返回 false 表示已达上限
+    pub fn pin(
+        &self,
+        message_id: &str,
+        consultation_id: &str,
+        pinned_by: &str,
+        pinned_at: DateTime<Utc>,
+        max_pinned: usize,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let pinned = retry_on_busy(&self.connection, "pin message", |conn| {
+            Ok(conn.execute(
+                "UPDATE messages SET pinned = 1, pinned_at = ?3, pinned_by = ?4
+                 WHERE id = ?1 AND pinned = 0 AND deleted_at IS NULL
+                   AND (SELECT COUNT(*) FROM messages WHERE consultation_id = ?2 AND pinned = 1 AND deleted_at IS NULL) < ?5",
+                params![message_id, consultation_id, SqlTimestamp(pinned_at), pinned_by, max_pinned as i64],
+            )?)
+        })?;
+
+        if pinned > 0 {
+            self.invalidate_consultation_cache(consultation_id);
+        }
+        Ok(pinned > 0)
+    }
+
+    // 取消置顶，消息未置顶时返回 false
+    pub fn unpin(&self, message_id: &str, consultation_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let unpinned = retry_on_busy(&self.connection, "unpin message", |conn| {
+            Ok(conn.execute(
+                "UPDATE messages SET pinned = 0, pinned_at = NULL, pinned_by = NULL WHERE id = ?1 AND pinned = 1",
+                params![message_id],
+            )?)
+        })?;
+
+        if unpinned > 0 {
+            self.invalidate_consultation_cache(consultation_id);
+        }
+        Ok(unpinned > 0)
+    }
+
+    // 问诊中置顶的消息，按置顶时间先后排列；移入回收站的消息不返回
+    pub fn find_pinned(&self, consultation_id: &str) -> Result<Vec<PinnedMessage>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform, pinned,
+                    pinned_at, pinned_by
+             FROM messages WHERE consultation_id = ?1 AND pinned = 1 AND deleted_at IS NULL
+             ORDER BY pinned_at ASC, id ASC"
+        )?;
+
+        let pinned = stmt
+            .query_map(params![consultation_id], |row| {
+                Ok(PinnedMessage {
+                    message: Message {
+                        id: row.get(0)?,
+                        consultation_id: row.get(1)?,
+                        sender_type: row.get(2)?,
+                        message_type: row.get(3)?,
+                        content: row.get(4)?,
+                        file_path: row.get(5)?,
+                        file_size: row.get(6)?,
+                        mime_type: row.get(7)?,
+                        timestamp: row.get::<_, SqlTimestamp>(8)?.0,
+                        sync_status: row.get(9)?,
+                        read_status: row.get(10)?,
+                        template_id: row.get(11)?,
+                        duration_ms: row.get(12)?,
+                        waveform: parse_waveform(row.get(13)?),
+                        pinned: row.get(14)?,
+                    },
+                    pinned_at: row.get::<_, SqlTimestamp>(15)?.0,
+                    pinned_by: row.get(16)?,
+                })
+            })?
+            .collect::<Result<Vec<PinnedMessage>>>()?;
+        Ok(pinned)
+    }
+
     pub fn delete_old_messages(&self, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let deleted = Self::delete_old_messages_in(&conn, days)?;
+
+        self.invalidate_cache();
+        Ok(deleted)
+    }
 
+    // 在调用方的事务内删除超过保留天数的消息，调用方负责失效消息缓存；截止时间在 Rust 中按统一格式计算
+    pub fn delete_old_messages_in(conn: &Connection, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
         let deleted = conn.execute(
-            "DELETE FROM messages WHERE timestamp < datetime('now', '-' || ?1 || ' days')",
-            params![days],
+            "DELETE FROM messages WHERE timestamp < ?1",
+            params![SqlTimestamp::days_ago(days)],
         )?;
 
         if deleted > 0 {
-            println!("Deleted {} old messages (older than {} days)", deleted, days);
+            tracing::info!("Deleted {} old messages (older than {} days)", deleted, days);
         }
 
         Ok(deleted)
     }
 
+    // 从回收站恢复，限定医生时只能恢复自己问诊中的消息；消息不在回收站时返回 false
+    pub fn restore_in_scope(&self, id: &str, scope: &DataScope) -> Result<bool, Box<dyn std::error::Error>> {
+        let doctor_id = scope.doctor_id();
+        let restored = retry_on_busy(&self.connection, "restore message", |conn| {
+            Ok(conn.execute(
+                "UPDATE messages SET deleted_at = NULL
+                 WHERE id = ?1 AND deleted_at IS NOT NULL
+                   AND (?2 IS NULL OR consultation_id IN (SELECT id FROM consultations WHERE doctor_id = ?2))",
+                params![id, doctor_id],
+            )?)
+        })?;
+
+        if restored > 0 {
+            self.invalidate_cache();
+        }
+        Ok(restored > 0)
+    }
+
+    // 回收站中的消息，最近删除的在前
+    pub fn find_deleted_in_scope(&self, scope: &DataScope) -> Result<Vec<TrashItem>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.consultation_id, c.patient_id, m.content, m.deleted_at
+             FROM messages m LEFT JOIN consultations c ON c.id = m.consultation_id
+             WHERE m.deleted_at IS NOT NULL AND (?1 IS NULL OR c.doctor_id = ?1)
+             ORDER BY m.deleted_at DESC"
+        )?;
+
+        let items = stmt.query_map(params![scope.doctor_id()], |row| {
+            Ok(TrashItem {
+                entity_type: TrashEntityType::Message,
+                id: row.get(0)?,
+                consultation_id: row.get(1)?,
+                patient_id: row.get(2)?,
+                summary: row.get(3)?,
+                deleted_at: row.get::<_, SqlTimestamp>(4)?.0,
+            })
+        })?;
+
+        Ok(items.collect::<Result<Vec<_>>>()?)
+    }
+
+    // 在调用方的事务内彻底清除在回收站中超过 days 天的消息，调用方负责失效消息缓存
+    pub fn purge_deleted_in(conn: &Connection, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
+        let purged = conn.execute(
+            "DELETE FROM messages WHERE deleted_at < ?1",
+            params![SqlTimestamp::days_ago(days)],
+        )?;
+
+        if purged > 0 {
+            tracing::info!("Purged {} deleted messages (in trash for more than {} days)", purged, days);
+        }
+
+        Ok(purged)
+    }
+
     pub fn get_message_stats(&self, consultation_id: &str) -> Result<MessageStats, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
 
-        let mut total_stmt = conn.prepare("SELECT COUNT(*) FROM messages WHERE consultation_id = ?1")?;
+        let mut total_stmt = conn.prepare("SELECT COUNT(*) FROM messages WHERE consultation_id = ?1 AND deleted_at IS NULL")?;
         let total_count: i64 = total_stmt.query_row(params![consultation_id], |row| row.get(0))?;
 
-        let mut unread_stmt = conn.prepare("SELECT COUNT(*) FROM messages WHERE consultation_id = ?1 AND read_status = 'unread'")?;
+        let mut unread_stmt = conn.prepare(
+            "SELECT COUNT(*) FROM messages WHERE consultation_id = ?1 AND deleted_at IS NULL AND sender_type != 'system' AND read_status = 'unread'"
+        )?;
         let unread_count: i64 = unread_stmt.query_row(params![consultation_id], |row| row.get(0))?;
 
-        let mut pending_stmt = conn.prepare("SELECT COUNT(*) FROM messages WHERE consultation_id = ?1 AND sync_status = 'pending'")?;
+        let mut pending_stmt = conn.prepare("SELECT COUNT(*) FROM messages WHERE consultation_id = ?1 AND deleted_at IS NULL AND sync_status = 'pending'")?;
         let pending_sync_count: i64 = pending_stmt.query_row(params![consultation_id], |row| row.get(0))?;
 
         Ok(MessageStats {
@@ -196,6 +722,116 @@ impl MessageDao {
             pending_sync: pending_sync_count,
         })
     }
+
+    // 会话内搜索，只匹配文本和模板消息；LIKE 对 ASCII 忽略大小写、对中文精确匹配，与高亮位置的计算规则一致
+    pub fn search_in_consultation(
+        &self,
+        consultation_id: &str,
+        keyword: &str,
+        limit: i32,
+    ) -> Result<Vec<MessageSearchHit>, String> {
+        let keyword = keyword.trim();
+        if keyword.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.connection.lock().unwrap();
+        // 位置按会话内时间正序计算，前端据此滚动到对应消息
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, content, position FROM (
+                 SELECT id, timestamp, content, message_type,
+                        ROW_NUMBER() OVER (ORDER BY timestamp ASC, id ASC) - 1 AS position
+                 FROM messages WHERE consultation_id = ?1 AND deleted_at IS NULL
+             )
+             WHERE message_type IN ('text', 'template') AND content LIKE ?2 ESCAPE '\\'
+             ORDER BY position DESC LIMIT ?3"
+        ).map_err(|e| e.to_string())?;
+
+        let pattern = format!("%{}%", escape_like(keyword));
+        let rows = stmt.query_map(params![consultation_id, pattern, limit], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, SqlTimestamp>(1)?.0,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        }).map_err(|e| e.to_string())?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            let (message_id, timestamp, content, position) = row.map_err(|e| e.to_string())?;
+            let matches = find_match_ranges(&content, keyword);
+            if matches.is_empty() {
+                continue;
+            }
+            hits.push(MessageSearchHit {
+                message_id,
+                timestamp,
+                message_index: position as usize,
+                matches,
+            });
+        }
+
+        Ok(hits)
+    }
+}
+
+// 会话内搜索命中的消息
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageSearchHit {
+    pub message_id: String,
+    pub timestamp: DateTime<Utc>,
+    // 消息在会话中的序号（按时间正序，从 0 开始）
+    pub message_index: usize,
+    pub matches: Vec<MatchRange>,
+}
+
+// 命中位置，按字符计数的左闭右开区间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+// 查找关键词在内容中的全部不重叠位置；ASCII 字母忽略大小写，其余字符精确匹配
+pub(crate) fn find_match_ranges(content: &str, keyword: &str) -> Vec<MatchRange> {
+    if keyword.is_empty() {
+        return Vec::new();
+    }
+
+    // ASCII 小写转换不改变字节长度，字节位置可直接对应回原文
+    let haystack = content.to_ascii_lowercase();
+    let needle = keyword.to_ascii_lowercase();
+    let keyword_chars = keyword.chars().count();
+
+    let mut ranges = Vec::new();
+    let mut chars_before = 0;
+    let mut scanned = 0;
+    let mut from = 0;
+    while let Some(found) = haystack[from..].find(&needle) {
+        let byte_start = from + found;
+        chars_before += haystack[scanned..byte_start].chars().count();
+        scanned = byte_start;
+        ranges.push(MatchRange {
+            start: chars_before,
+            end: chars_before + keyword_chars,
+        });
+        from = byte_start + needle.len();
+    }
+    ranges
+}
+
+// 置顶前需要检查的消息状态
+#[derive(Debug, Clone)]
+pub struct PinTarget {
+    pub consultation_id: String,
+    pub sender_type: SenderType,
+    pub message_type: MessageType,
+    // 已移入回收站（撤回）
+    pub deleted: bool,
+    pub pinned: bool,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub pinned_by: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -205,37 +841,52 @@ pub struct MessageStats {
     pub pending_sync: i64,
 }
 
+// 语音波形以 JSON 数组存储
+pub(crate) fn waveform_json(waveform: &Option<Vec<f32>>) -> Option<String> {
+    waveform.as_ref().and_then(|peaks| serde_json::to_string(peaks).ok())
+}
+
+pub(crate) fn parse_waveform(value: Option<String>) -> Option<Vec<f32>> {
+    value.and_then(|json| serde_json::from_str(&json).ok())
+}
+
 impl BaseDao<Message> for MessageDao {
     fn create(&self, message: &Message) -> Result<String, Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
         let id = Uuid::new_v4().to_string();
 
-        conn.execute(
-            "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-            params![
-                id,
-                message.consultation_id,
-                message.sender_type,
-                message.message_type,
-                message.content,
-                message.file_path,
-                message.file_size,
-                message.mime_type,
-                message.timestamp,
-                message.sync_status,
-                message.read_status
-            ],
-        )?;
+        retry_on_busy(&self.connection, "create message", |conn| {
+            conn.execute(
+                "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    id,
+                    message.consultation_id,
+                    message.sender_type,
+                    message.message_type,
+                    message.content,
+                    message.file_path,
+                    message.file_size,
+                    message.mime_type,
+                    SqlTimestamp(message.timestamp),
+                    message.sync_status,
+                    message.read_status,
+                    message.template_id,
+                    message.duration_ms,
+                    waveform_json(&message.waveform)
+                ],
+            )?;
+            Ok(())
+        })?;
 
+        self.invalidate_consultation_cache(&message.consultation_id);
         Ok(id)
     }
 
     fn find_by_id(&self, id: &str) -> Result<Option<Message>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status
-             FROM messages WHERE id = ?1"
+            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform, pinned
+             FROM messages WHERE id = ?1 AND deleted_at IS NULL"
         )?;
 
         let message_result = stmt.query_row(params![id], |row| {
@@ -248,9 +899,13 @@ impl BaseDao<Message> for MessageDao {
                 file_path: row.get(5)?,
                 file_size: row.get(6)?,
                 mime_type: row.get(7)?,
-                timestamp: row.get(8)?,
+                timestamp: row.get::<_, SqlTimestamp>(8)?.0,
                 sync_status: row.get(9)?,
                 read_status: row.get(10)?,
+                template_id: row.get(11)?,
+                duration_ms: row.get(12)?,
+                waveform: parse_waveform(row.get(13)?),
+                pinned: row.get(14)?,
             })
         });
 
@@ -262,41 +917,55 @@ impl BaseDao<Message> for MessageDao {
     }
 
     fn update(&self, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
 
-        conn.execute(
-            "UPDATE messages SET consultation_id = ?1, sender_type = ?2, message_type = ?3, content = ?4,
-             file_path = ?5, file_size = ?6, mime_type = ?7, timestamp = ?8, sync_status = ?9, read_status = ?10
-             WHERE id = ?11",
-            params![
-                message.consultation_id,
-                message.sender_type,
-                message.message_type,
-                message.content,
-                message.file_path,
-                message.file_size,
-                message.mime_type,
-                message.timestamp,
-                message.sync_status,
-                message.read_status,
-                message.id
-            ],
-        )?;
+        retry_on_busy(&self.connection, "update message", |conn| {
+            conn.execute(
+                "UPDATE messages SET consultation_id = ?1, sender_type = ?2, message_type = ?3, content = ?4,
+                 file_path = ?5, file_size = ?6, mime_type = ?7, timestamp = ?8, sync_status = ?9, read_status = ?10,
+                 template_id = ?11, duration_ms = ?12, waveform = ?13 WHERE id = ?14",
+                params![
+                    message.consultation_id,
+                    message.sender_type,
+                    message.message_type,
+                    message.content,
+                    message.file_path,
+                    message.file_size,
+                    message.mime_type,
+                    SqlTimestamp(message.timestamp),
+                    message.sync_status,
+                    message.read_status,
+                    message.template_id,
+                    message.duration_ms,
+                    waveform_json(&message.waveform),
+                    message.id
+                ],
+            )?;
+            Ok(())
+        })?;
 
+        self.invalidate_cache();
         Ok(())
     }
 
+    // 软删除，消息移入回收站
     fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
-        conn.execute("DELETE FROM messages WHERE id = ?1", params![id])?;
+        let deleted_at = SqlTimestamp::now();
+        retry_on_busy(&self.connection, "delete message", |conn| {
+            conn.execute(
+                "UPDATE messages SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+                params![id, deleted_at],
+            )?;
+            Ok(())
+        })?;
+        self.invalidate_cache();
         Ok(())
     }
 
     fn find_all(&self) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status
-             FROM messages ORDER BY timestamp DESC"
+            "SELECT id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, template_id, duration_ms, waveform, pinned
+             FROM messages WHERE deleted_at IS NULL ORDER BY timestamp DESC"
         )?;
 
         let message_iter = stmt.query_map([], |row| {
@@ -309,9 +978,13 @@ impl BaseDao<Message> for MessageDao {
                 file_path: row.get(5)?,
                 file_size: row.get(6)?,
                 mime_type: row.get(7)?,
-                timestamp: row.get(8)?,
+                timestamp: row.get::<_, SqlTimestamp>(8)?.0,
                 sync_status: row.get(9)?,
                 read_status: row.get(10)?,
+                template_id: row.get(11)?,
+                duration_ms: row.get(12)?,
+                waveform: parse_waveform(row.get(13)?),
+                pinned: row.get(14)?,
             })
         })?;
 
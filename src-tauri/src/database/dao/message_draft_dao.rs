@@ -0,0 +1,98 @@
+// 消息草稿数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::MessageDraft;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use chrono::Utc;
+
+pub struct MessageDraftDao {
+    connection: DbConnection,
+}
+
+impl MessageDraftDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 每个问诊每位医生只保留一份草稿，重复保存直接覆盖
+    pub fn upsert(&self, consultation_id: &str, doctor_id: &str, content: &str) -> Result<MessageDraft, Box<dyn std::error::Error>> {
+        let draft = MessageDraft {
+            consultation_id: consultation_id.to_string(),
+            doctor_id: doctor_id.to_string(),
+            content: content.to_string(),
+            updated_at: Utc::now(),
+        };
+
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "INSERT INTO message_drafts (consultation_id, doctor_id, content, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(consultation_id, doctor_id) DO UPDATE SET
+                content = excluded.content,
+                updated_at = excluded.updated_at",
+            params![draft.consultation_id, draft.doctor_id, draft.content, draft.updated_at],
+        )?;
+
+        Ok(draft)
+    }
+
+    pub fn find(&self, consultation_id: &str, doctor_id: &str) -> Result<Option<MessageDraft>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let draft = conn.query_row(
+            "SELECT consultation_id, doctor_id, content, updated_at FROM message_drafts
+             WHERE consultation_id = ?1 AND doctor_id = ?2",
+            params![consultation_id, doctor_id],
+            |row| {
+                Ok(MessageDraft {
+                    consultation_id: row.get(0)?,
+                    doctor_id: row.get(1)?,
+                    content: row.get(2)?,
+                    updated_at: row.get(3)?,
+                })
+            },
+        ).optional()?;
+
+        Ok(draft)
+    }
+
+    pub fn delete(&self, consultation_id: &str, doctor_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        Self::delete_in(&conn, consultation_id, doctor_id)
+    }
+
+    // 在调用方的事务内删除草稿，发送消息时与消息写入一起提交
+    pub fn delete_in(conn: &Connection, consultation_id: &str, doctor_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let deleted = conn.execute(
+            "DELETE FROM message_drafts WHERE consultation_id = ?1 AND doctor_id = ?2",
+            params![consultation_id, doctor_id],
+        )?;
+
+        Ok(deleted > 0)
+    }
+
+    // 在调用方的事务内删除超过保留天数未更新的草稿
+    pub fn delete_older_than_in(conn: &Connection, days: i32) -> Result<usize, Box<dyn std::error::Error>> {
+        let deleted = conn.execute(
+            "DELETE FROM message_drafts WHERE updated_at < datetime('now', '-' || ?1 || ' days')",
+            params![days],
+        )?;
+
+        if deleted > 0 {
+            tracing::info!("Deleted {} stale message drafts (older than {} days)", deleted, days);
+        }
+
+        Ok(deleted)
+    }
+}
+
+impl Default for MessageDraftDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
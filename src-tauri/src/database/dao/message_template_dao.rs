@@ -0,0 +1,208 @@
+// 常用回复模板数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::BaseDao;
+use crate::models::MessageTemplate;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use uuid::Uuid;
+use chrono::Utc;
+
+pub struct MessageTemplateDao {
+    connection: DbConnection,
+}
+
+impl MessageTemplateDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 按使用次数从多到少排列
+    pub fn find_by_doctor(&self, doctor_id: &str, category: Option<&str>) -> Result<Vec<MessageTemplate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, category, title, content, usage_count, created_at, updated_at
+             FROM message_templates WHERE doctor_id = ?1 AND (?2 IS NULL OR category = ?2)
+             ORDER BY usage_count DESC, updated_at DESC"
+        )?;
+
+        let template_iter = stmt.query_map(params![doctor_id, category], |row| {
+            Ok(MessageTemplate {
+                id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                category: row.get(2)?,
+                title: row.get(3)?,
+                content: row.get(4)?,
+                usage_count: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+            })
+        })?;
+
+        let mut templates = Vec::new();
+        for template in template_iter {
+            templates.push(template?);
+        }
+
+        Ok(templates)
+    }
+
+    pub fn increment_usage(&self, template_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        conn.execute(
+            "UPDATE message_templates SET usage_count = usage_count + 1 WHERE id = ?1",
+            params![template_id],
+        )?;
+
+        Ok(())
+    }
+
+    // 按 id 写入模板，已存在时更新内容，本机的使用次数保持不变；返回是否新插入
+    pub fn upsert_in(conn: &Connection, template: &MessageTemplate) -> Result<bool, Box<dyn std::error::Error>> {
+        let exists = conn
+            .query_row("SELECT 1 FROM message_templates WHERE id = ?1", params![template.id], |_| Ok(()))
+            .optional()?
+            .is_some();
+        conn.execute(
+            "INSERT INTO message_templates (id, doctor_id, category, title, content, usage_count, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET doctor_id = excluded.doctor_id, category = excluded.category,
+                 title = excluded.title, content = excluded.content, updated_at = excluded.updated_at",
+            params![
+                template.id,
+                template.doctor_id,
+                template.category,
+                template.title,
+                template.content,
+                template.created_at,
+                template.updated_at
+            ],
+        )?;
+        Ok(!exists)
+    }
+
+    pub fn delete_in(conn: &Connection, ids: &[String]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare("DELETE FROM message_templates WHERE id = ?1")?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += stmt.execute(params![id])?;
+        }
+        Ok(deleted)
+    }
+}
+
+impl BaseDao<MessageTemplate> for MessageTemplateDao {
+    fn create(&self, template: &MessageTemplate) -> Result<String, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+
+        conn.execute(
+            "INSERT INTO message_templates (id, doctor_id, category, title, content, usage_count, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                id,
+                template.doctor_id,
+                template.category,
+                template.title,
+                template.content,
+                template.usage_count,
+                now,
+                now
+            ],
+        )?;
+
+        Ok(id)
+    }
+
+    fn find_by_id(&self, id: &str) -> Result<Option<MessageTemplate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, category, title, content, usage_count, created_at, updated_at
+             FROM message_templates WHERE id = ?1"
+        )?;
+
+        let template_result = stmt.query_row(params![id], |row| {
+            Ok(MessageTemplate {
+                id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                category: row.get(2)?,
+                title: row.get(3)?,
+                content: row.get(4)?,
+                usage_count: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+            })
+        });
+
+        match template_result {
+            Ok(template) => Ok(Some(template)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    fn update(&self, template: &MessageTemplate) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let now = Utc::now();
+
+        conn.execute(
+            "UPDATE message_templates SET category = ?1, title = ?2, content = ?3, updated_at = ?4 WHERE id = ?5",
+            params![
+                template.category,
+                template.title,
+                template.content,
+                now,
+                template.id
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute("DELETE FROM message_templates WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn find_all(&self) -> Result<Vec<MessageTemplate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, category, title, content, usage_count, created_at, updated_at
+             FROM message_templates ORDER BY usage_count DESC, updated_at DESC"
+        )?;
+
+        let template_iter = stmt.query_map([], |row| {
+            Ok(MessageTemplate {
+                id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                category: row.get(2)?,
+                title: row.get(3)?,
+                content: row.get(4)?,
+                usage_count: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+            })
+        })?;
+
+        let mut templates = Vec::new();
+        for template in template_iter {
+            templates.push(template?);
+        }
+
+        Ok(templates)
+    }
+}
+
+impl Default for MessageTemplateDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,73 @@
+// 运行指标数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::retry::{retry_on_busy, retry_transaction_on_busy};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Result};
+
+// 某个小时内落在某个直方图桶的样本数
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricCount {
+    pub hour: DateTime<Utc>,
+    pub bucket: usize,
+    pub count: u64,
+}
+
+pub struct MetricsDao {
+    connection: DbConnection,
+}
+
+impl MetricsDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 一批计数在同一事务内累加到已有记录上
+    pub fn add_counts(&self, name: &str, counts: &[MetricCount]) -> Result<(), Box<dyn std::error::Error>> {
+        retry_transaction_on_busy(&self.connection, "add metric counts", |tx| {
+            let mut stmt = tx.prepare(
+                "INSERT INTO metrics (name, hour, bucket, count) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(name, hour, bucket) DO UPDATE SET count = count + excluded.count",
+            )?;
+            for count in counts {
+                stmt.execute(params![name, count.hour, count.bucket as i64, count.count as i64])?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn find_since(&self, name: &str, since: DateTime<Utc>) -> Result<Vec<MetricCount>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT hour, bucket, count FROM metrics WHERE name = ?1 AND hour >= ?2 ORDER BY hour, bucket",
+        )?;
+        let counts = stmt
+            .query_map(params![name, since], |row| {
+                Ok(MetricCount {
+                    hour: row.get(0)?,
+                    bucket: row.get::<_, i64>(1)?.max(0) as usize,
+                    count: row.get::<_, i64>(2)?.max(0) as u64,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(counts)
+    }
+
+    pub fn delete_metric(&self, name: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        retry_on_busy(&self.connection, "delete metric", |conn| {
+            Ok(conn.execute("DELETE FROM metrics WHERE name = ?1", params![name])?)
+        })
+    }
+}
+
+impl Default for MetricsDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,22 +3,89 @@
 pub mod user_dao;
 pub mod patient_dao;
 pub mod consultation_dao;
+pub mod doctor_dao;
 pub mod message_dao;
+pub mod message_draft_dao;
 pub mod medical_record_dao;
 pub mod file_cache_dao;
 pub mod audit_log_dao;
+pub mod record_template_dao;
+pub mod message_template_dao;
+pub mod user_settings_dao;
+pub mod sync_state_dao;
+pub mod sensitive_word_dao;
+pub mod security_config_dao;
+pub mod retention_dao;
+pub mod sms_request_dao;
+pub mod prescription_dao;
+pub mod app_settings_dao;
+pub mod outbox_dao;
+pub mod timeline_dao;
+pub mod metrics_dao;
+pub mod consultation_note_dao;
+pub mod patient_revision_dao;
+pub mod intake_form_dao;
+pub mod change_log_dao;
 
-pub use user_dao::UserDao;
-pub use patient_dao::PatientDao;
+pub use user_dao::{StoredSession, UserDao};
+pub use patient_dao::{PatientDao, ProtectedFields};
 pub use consultation_dao::ConsultationDao;
-pub use message_dao::MessageDao;
+pub use doctor_dao::DoctorDao;
+pub use message_dao::{MatchRange, MessageDao, MessageSearchHit, PinTarget};
+pub use message_draft_dao::MessageDraftDao;
 pub use medical_record_dao::MedicalRecordDao;
 pub use file_cache_dao::FileCacheDao;
 pub use audit_log_dao::AuditLogDao;
+pub use record_template_dao::RecordTemplateDao;
+pub use message_template_dao::MessageTemplateDao;
+pub use user_settings_dao::UserSettingsDao;
+pub use sync_state_dao::SyncStateDao;
+pub use sensitive_word_dao::SensitiveWordDao;
+pub use security_config_dao::SecurityConfigDao;
+pub use retention_dao::RetentionDao;
+pub use sms_request_dao::SmsRequestDao;
+pub use prescription_dao::PrescriptionDao;
+pub use app_settings_dao::{AppSettingsDao, APP_CONFIG_SCHEMA_VERSION};
+pub use outbox_dao::OutboxDao;
+pub use timeline_dao::TimelineDao;
+pub use metrics_dao::{MetricCount, MetricsDao};
+pub use consultation_note_dao::ConsultationNoteDao;
+pub use patient_revision_dao::PatientRevisionDao;
+pub use intake_form_dao::IntakeFormDao;
+pub use change_log_dao::{publish_data_changes, ChangeLogDao};
 
-use rusqlite::Result;
+use chrono::{DateTime, Utc};
+use crate::utils::SqlTimestamp;
+use rusqlite::{Result, ToSql};
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
+// 乐观锁冲突：按版本号更新时记录已被其他窗口修改，调用方应重新读取后合并
+#[derive(Debug, Clone)]
+pub struct ConflictError {
+    pub entity: &'static str,
+    pub id: String,
+    pub expected_version: i64,
+}
+
+impl ConflictError {
+    pub fn new(entity: &'static str, id: &str, expected_version: i64) -> Self {
+        Self {
+            entity,
+            id: id.to_string(),
+            expected_version,
+        }
+    }
+}
+
+impl std::fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} 已被修改（版本 {} 已过期）", self.entity, self.id, self.expected_version)
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
 // 通用DAO特征
 pub trait BaseDao<T>
 where
@@ -27,17 +94,21 @@ where
     fn create(&self, entity: &T) -> Result<String, Box<dyn std::error::Error>>;
     fn find_by_id(&self, id: &str) -> Result<Option<T>, Box<dyn std::error::Error>>;
     fn update(&self, entity: &T) -> Result<(), Box<dyn std::error::Error>>;
+    // 消息和病历的实现为软删除：只写入 deleted_at 移入回收站，find_by_id、find_all 等常规查询不再返回，
+    // 可通过各自的 restore_in_scope 恢复，超过回收站保留天数后由每日维护彻底清除；其余实体直接删除
     fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>>;
     fn find_all(&self) -> Result<Vec<T>, Box<dyn std::error::Error>>;
 }
 
 // 分页查询结果
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PageResult<T> {
     pub items: Vec<T>,
     pub total: i64,
     pub page: i32,
+    #[serde(rename = "pageSize")]
     pub page_size: i32,
+    #[serde(rename = "totalPages")]
     pub total_pages: i32,
 }
 
@@ -52,16 +123,27 @@ impl<T> PageResult<T> {
             total_pages,
         }
     }
+
+    // 转换每一项，分页信息保持不变
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> PageResult<U> {
+        PageResult {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            page: self.page,
+            page_size: self.page_size,
+            total_pages: self.total_pages,
+        }
+    }
 }
 
-// 查询条件构建器
-#[derive(Debug, Clone)]
+// 参数化查询构建器：条件片段只含占位符，取值一律作为绑定参数传入
+// 列名和条件片段必须来自代码本身，不能拼接用户输入
 pub struct QueryBuilder {
-    pub conditions: Vec<String>,
-    pub params: Vec<String>,
-    pub order_by: Option<String>,
-    pub limit: Option<i32>,
-    pub offset: Option<i32>,
+    conditions: Vec<String>,
+    params: Vec<Box<dyn ToSql>>,
+    order_by: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
 }
 
 impl QueryBuilder {
@@ -75,23 +157,61 @@ impl QueryBuilder {
         }
     }
 
-    pub fn add_condition(mut self, condition: &str, param: &str) -> Self {
+    // 任意条件片段，占位符数量须与 values 一致，用于 OR 组合、子查询等复杂条件
+    pub fn add_condition(mut self, condition: &str, values: Vec<Box<dyn ToSql>>) -> Self {
+        debug_assert_eq!(condition.matches('?').count(), values.len());
         self.conditions.push(condition.to_string());
-        self.params.push(param.to_string());
+        self.params.extend(values);
         self
     }
 
+    pub fn where_eq<T: ToSql + 'static>(self, column: &str, value: T) -> Self {
+        self.add_condition(&format!("{} = ?", column), vec![Box::new(value)])
+    }
+
+    // 包含匹配，关键词中的 % 和 _ 按字面量处理
+    pub fn where_like(self, column: &str, keyword: &str) -> Self {
+        let pattern = format!("%{}%", escape_like(keyword));
+        self.add_condition(&format!("{} LIKE ? ESCAPE '\\'", column), vec![Box::new(pattern)])
+    }
+
+    // 空列表生成恒假条件，避免拼出非法的 IN ()
+    pub fn where_in<T, I>(self, column: &str, values: I) -> Self
+    where
+        T: ToSql + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        let values: Vec<Box<dyn ToSql>> = values
+            .into_iter()
+            .map(|value| Box::new(value) as Box<dyn ToSql>)
+            .collect();
+        if values.is_empty() {
+            return self.add_condition("0 = 1", Vec::new());
+        }
+
+        let placeholders = vec!["?"; values.len()].join(", ");
+        self.add_condition(&format!("{} IN ({})", column, placeholders), values)
+    }
+
+    // 闭区间
+    pub fn where_between_dates(self, column: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.add_condition(
+            &format!("{} BETWEEN ? AND ?", column),
+            vec![Box::new(SqlTimestamp(start)), Box::new(SqlTimestamp(end))],
+        )
+    }
+
     pub fn order_by(mut self, order: &str) -> Self {
         self.order_by = Some(order.to_string());
         self
     }
 
-    pub fn limit(mut self, limit: i32) -> Self {
+    pub fn limit(mut self, limit: i64) -> Self {
         self.limit = Some(limit);
         self
     }
 
-    pub fn offset(mut self, offset: i32) -> Self {
+    pub fn offset(mut self, offset: i64) -> Self {
         self.offset = Some(offset);
         self
     }
@@ -110,17 +230,76 @@ impl QueryBuilder {
             .unwrap_or_default()
     }
 
+    // 分页值同样走绑定参数，由 build 追加到参数末尾
     pub fn build_limit_clause(&self) -> String {
         match (self.limit, self.offset) {
-            (Some(limit), Some(offset)) => format!("LIMIT {} OFFSET {}", limit, offset),
-            (Some(limit), None) => format!("LIMIT {}", limit),
+            (Some(_), Some(_)) => "LIMIT ? OFFSET ?".to_string(),
+            (Some(_), None) => "LIMIT ?".to_string(),
             _ => String::new(),
         }
     }
+
+    // 完整语句及按占位符顺序排列的参数，可直接交给 prepare / query_map
+    pub fn build(&self, select: &str) -> (String, Vec<&dyn ToSql>) {
+        let sql = join_clauses(&[
+            select.to_string(),
+            self.build_where_clause(),
+            self.build_order_clause(),
+            self.build_limit_clause(),
+        ]);
+
+        let mut params = self.condition_params();
+        if let Some(limit) = &self.limit {
+            params.push(limit);
+            if let Some(offset) = &self.offset {
+                params.push(offset);
+            }
+        }
+        (sql, params)
+    }
+
+    // 相同条件下的计数语句，忽略排序和分页
+    pub fn build_count(&self, from: &str) -> (String, Vec<&dyn ToSql>) {
+        let sql = join_clauses(&[format!("SELECT COUNT(*) FROM {}", from), self.build_where_clause()]);
+        (sql, self.condition_params())
+    }
+
+    fn condition_params(&self) -> Vec<&dyn ToSql> {
+        self.params.iter().map(|param| param.as_ref()).collect()
+    }
+}
+
+fn join_clauses(clauses: &[String]) -> String {
+    clauses
+        .iter()
+        .filter(|clause| !clause.is_empty())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl std::fmt::Debug for QueryBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryBuilder")
+            .field("conditions", &self.conditions)
+            .field("params", &self.params.len())
+            .field("order_by", &self.order_by)
+            .field("limit", &self.limit)
+            .field("offset", &self.offset)
+            .finish()
+    }
 }
 
 impl Default for QueryBuilder {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+// 转义 LIKE 通配符，配合 ESCAPE '\' 使用
+pub(crate) fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
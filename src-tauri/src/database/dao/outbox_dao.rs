@@ -0,0 +1,160 @@
+// 消息发件箱数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::file_cache_dao::{FileCacheDao, PIN_REASON_OUTBOX};
+use crate::database::query_optimizer::{query_cache_for, CACHE_TAG_MESSAGES};
+use crate::database::retry::{retry_on_busy, retry_transaction_on_busy};
+use crate::models::OutboxEntry;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use uuid::Uuid;
+
+const ENTRY_COLUMNS: &str = "message_id, idempotency_key, attempts, last_error, last_attempt_at, created_at";
+
+pub struct OutboxDao {
+    connection: DbConnection,
+}
+
+impl OutboxDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 在调用方的事务内登记待发送消息；已登记的消息保留原幂等键
+    pub fn enqueue_in(conn: &Connection, message_id: &str) -> Result<OutboxEntry, Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO outbox (message_id, idempotency_key, created_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(message_id) DO NOTHING",
+            params![message_id, Uuid::new_v4().to_string(), Utc::now()],
+        )?;
+
+        let entry = conn.query_row(
+            &format!("SELECT {} FROM outbox WHERE message_id = ?1", ENTRY_COLUMNS),
+            params![message_id],
+            map_entry,
+        )?;
+        Ok(entry)
+    }
+
+    pub fn find_by_message_id(&self, message_id: &str) -> Result<Option<OutboxEntry>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let entry = conn
+            .query_row(
+                &format!("SELECT {} FROM outbox WHERE message_id = ?1", ENTRY_COLUMNS),
+                params![message_id],
+                map_entry,
+            )
+            .optional()?;
+        Ok(entry)
+    }
+
+    // 按登记顺序返回尚未确认的消息，发送顺序与用户发送顺序一致
+    pub fn find_pending(&self, limit: usize) -> Result<Vec<OutboxEntry>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM outbox ORDER BY created_at ASC, message_id ASC LIMIT ?1",
+            ENTRY_COLUMNS
+        ))?;
+        let entries = stmt
+            .query_map(params![limit as i64], map_entry)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    pub fn count(&self) -> Result<i64, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        Ok(conn.query_row("SELECT COUNT(*) FROM outbox", [], |row| row.get(0))?)
+    }
+
+    // 记录一次发送尝试，发送失败时保存错误信息
+    pub fn record_attempt(&self, message_id: &str, error: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        retry_on_busy(&self.connection, "record outbox attempt", |conn| {
+            conn.execute(
+                "UPDATE outbox SET attempts = attempts + 1, last_error = ?2, last_attempt_at = ?3 WHERE message_id = ?1",
+                params![message_id, error, Utc::now()],
+            )?;
+            Ok(())
+        })
+    }
+
+    // 服务器确认后删除发件箱记录、解除附件固定并将消息标记为已同步，返回被删除的记录；重复确认返回 None
+    pub fn acknowledge(&self, idempotency_key: &str) -> Result<Option<OutboxEntry>, Box<dyn std::error::Error>> {
+        let acknowledged = retry_transaction_on_busy(&self.connection, "acknowledge outbox message", |tx| {
+            let entry = tx
+                .query_row(
+                    &format!("SELECT {} FROM outbox WHERE idempotency_key = ?1", ENTRY_COLUMNS),
+                    params![idempotency_key],
+                    map_entry,
+                )
+                .optional()?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+
+            tx.execute("DELETE FROM outbox WHERE message_id = ?1", params![entry.message_id])?;
+            FileCacheDao::unpin_ref_in(tx, PIN_REASON_OUTBOX, &entry.message_id)?;
+            tx.execute(
+                "UPDATE messages SET sync_status = 'synced' WHERE id = ?1",
+                params![entry.message_id],
+            )?;
+            Ok(Some(entry))
+        })?;
+
+        if acknowledged.is_some() {
+            query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+        }
+        Ok(acknowledged)
+    }
+
+    // 清空发件箱，指定问诊时只清空该问诊的消息；放弃发送的消息标记为失败并解除附件固定，返回清除的条数
+    pub fn clear(&self, consultation_id: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        let cleared = retry_transaction_on_busy(&self.connection, "clear outbox", |tx| {
+            let message_ids = {
+                let mut stmt = tx.prepare(
+                    "SELECT o.message_id FROM outbox o JOIN messages m ON m.id = o.message_id
+                     WHERE ?1 IS NULL OR m.consultation_id = ?1",
+                )?;
+                let rows = stmt.query_map(params![consultation_id], |row| row.get::<_, String>(0))?;
+                rows.collect::<Result<Vec<_>>>()?
+            };
+
+            for message_id in &message_ids {
+                tx.execute("DELETE FROM outbox WHERE message_id = ?1", params![message_id])?;
+                FileCacheDao::unpin_ref_in(tx, PIN_REASON_OUTBOX, message_id)?;
+                tx.execute(
+                    "UPDATE messages SET sync_status = 'failed' WHERE id = ?1",
+                    params![message_id],
+                )?;
+            }
+            Ok(message_ids.len())
+        })?;
+
+        if cleared > 0 {
+            query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+        }
+        Ok(cleared)
+    }
+}
+
+impl Default for OutboxDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn map_entry(row: &Row) -> Result<OutboxEntry> {
+    Ok(OutboxEntry {
+        message_id: row.get(0)?,
+        idempotency_key: row.get(1)?,
+        attempts: row.get(2)?,
+        last_error: row.get(3)?,
+        last_attempt_at: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
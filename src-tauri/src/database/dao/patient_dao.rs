@@ -1,12 +1,91 @@
 // 患者数据访问层
 
 use crate::database::connection::{get_database, DbConnection};
-use crate::database::dao::{BaseDao, QueryBuilder, PageResult};
-use crate::models::Patient;
-use rusqlite::{params, Result};
+use crate::database::dao::{
+    escape_like, publish_data_changes, BaseDao, ChangeLogDao, ConflictError, PatientRevisionDao, QueryBuilder, PageResult,
+};
+use crate::database::query_optimizer::{get_query_optimizer, query_cache_for, BatchOperations, BULK_BATCH_SIZE, CACHE_TAG_PATIENTS};
+use crate::database::retry::retry_transaction_on_busy;
+use crate::models::{AppError, ChangeEntity, ChangeOp, DataChanged, DataScope, Patient, PatientAvatar, PatientQuery, PatientRevision, PatientSortField, SortOrder, SortParams, TagUsage};
+use crate::utils::crypto::{CryptoService, ENCRYPTED_FIELD_PREFIX};
+use crate::utils::{pinyin_sort_key, SqlTimestamp};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row, ToSql};
+use std::cell::RefCell;
+use std::sync::OnceLock;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+static FIELD_CRYPTO: OnceLock<CryptoService> = OnceLock::new();
+
+// 打开数据库时设置为钥匙串中的密钥，之后不再更换
+pub fn init_field_crypto(crypto: CryptoService) {
+    if FIELD_CRYPTO.set(crypto).is_err() {
+        tracing::debug!("Field crypto already initialized");
+    }
+}
+
+// 尚未初始化时从钥匙串读取，钥匙串不可用时返回 DATA_KEY_UNAVAILABLE
+pub(crate) fn field_crypto() -> Result<&'static CryptoService, AppError> {
+    if let Some(crypto) = FIELD_CRYPTO.get() {
+        return Ok(crypto);
+    }
+    let crypto = default_field_crypto()?;
+    Ok(FIELD_CRYPTO.get_or_init(|| crypto))
+}
+
+#[cfg(not(test))]
+fn default_field_crypto() -> Result<CryptoService, AppError> {
+    crate::utils::keychain::local_data_crypto()
+        .map_err(|e| AppError::data_key_unavailable(format!("本地数据密钥不可用: {}", e)))
+}
+
+#[cfg(test)]
+fn default_field_crypto() -> Result<CryptoService, AppError> {
+    Ok(CryptoService::new())
+}
+
+// 退出登录时清零字段加密派生的会话密钥
+pub fn purge_field_crypto_keys() -> usize {
+    FIELD_CRYPTO.get().map_or(0, CryptoService::purge_session_keys)
+}
+
+// 手机号、身份证号落库前的密文和 HMAC 索引
+#[derive(Debug, Clone, Default)]
+pub struct ProtectedFields {
+    pub phone: Option<String>,
+    pub phone_hash: Option<String>,
+    pub id_card: Option<String>,
+    pub id_card_hash: Option<String>,
+}
+
+impl ProtectedFields {
+    pub fn protect(phone: Option<&str>, id_card: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let crypto = field_crypto()?;
+        let phone = phone.map(str::trim).filter(|v| !v.is_empty());
+        let id_card = id_card.map(|v| v.trim().to_uppercase()).filter(|v| !v.is_empty());
+
+        Ok(Self {
+            phone: phone.map(|v| crypto.encrypt_field(v)).transpose()?,
+            phone_hash: phone.map(phone_index).transpose()?,
+            id_card: id_card.as_deref().map(|v| crypto.encrypt_field(v)).transpose()?,
+            id_card_hash: id_card.as_deref().map(id_card_index).transpose()?,
+        })
+    }
+
+    fn of(patient: &Patient) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::protect(patient.phone.as_deref(), patient.id_card.as_deref())
+    }
+}
+
+pub fn phone_index(phone: &str) -> Result<String, AppError> {
+    Ok(field_crypto()?.blind_index(phone.trim()))
+}
+
+pub fn id_card_index(id_card: &str) -> Result<String, AppError> {
+    Ok(field_crypto()?.blind_index(&id_card.trim().to_uppercase()))
+}
+
 pub struct PatientDao {
     connection: DbConnection,
 }
@@ -18,79 +97,196 @@ impl PatientDao {
         }
     }
 
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 患者数据变更后清除相关查询缓存
+    fn invalidate_cache(&self) {
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_PATIENTS);
+    }
+
     pub fn search_patients(&self, keyword: &str, page: i32, page_size: i32) -> Result<PageResult<Patient>, Box<dyn std::error::Error>> {
+        self.search_patients_in_scope(keyword, page, page_size, &DataScope::All)
+    }
+
+    pub fn search_patients_in_scope(
+        &self,
+        keyword: &str,
+        page: i32,
+        page_size: i32,
+        scope: &DataScope,
+    ) -> Result<PageResult<Patient>, Box<dyn std::error::Error>> {
+        let query = PatientQuery {
+            keyword: Some(keyword.to_string()),
+            tags: None,
+            gender: None,
+            age_range: None,
+            last_visit_range: None,
+            sort: None,
+            page: page.max(1) as u32,
+            page_size: page_size.max(1) as u32,
+        };
+
+        self.query_patients_in_scope(&query, scope)
+    }
+
+    // 按查询条件分页检索患者（参数化查询）
+    pub fn query_patients(&self, query: &PatientQuery) -> Result<PageResult<Patient>, Box<dyn std::error::Error>> {
+        self.query_patients_in_scope(query, &DataScope::All)
+    }
+
+    // 限定医生时只返回该医生接诊过的患者
+    pub fn query_patients_in_scope(
+        &self,
+        query: &PatientQuery,
+        scope: &DataScope,
+    ) -> Result<PageResult<Patient>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let page = query.page.max(1) as i32;
+        let page_size = query.page_size.max(1) as i32;
         let offset = (page - 1) * page_size;
 
-        // 构建搜索条件
-        let search_condition = format!(
-            "WHERE name LIKE '%{}%' OR phone LIKE '%{}%' OR id_card LIKE '%{}%'",
-            keyword, keyword, keyword
-        );
+        let mut builder = QueryBuilder::new();
+
+        if let Some(keyword) = query.keyword.as_deref().map(str::trim).filter(|k| !k.is_empty()) {
+            // 加密后的手机号、身份证号只能按 HMAC 精确匹配，未迁移的明文行仍支持模糊匹配
+            let pattern = format!("%{}%", escape_like(keyword));
+            builder = builder.add_condition(
+                "(name LIKE ? ESCAPE '\\' OR phone_hash = ? OR id_card_hash = ?
+                  OR (phone_hash IS NULL AND phone LIKE ? ESCAPE '\\')
+                  OR (id_card_hash IS NULL AND id_card LIKE ? ESCAPE '\\'))",
+                vec![
+                    Box::new(pattern.clone()),
+                    Box::new(phone_index(keyword)?),
+                    Box::new(id_card_index(keyword)?),
+                    Box::new(pattern.clone()),
+                    Box::new(pattern),
+                ],
+            );
+        }
+
+        if let Some(tags) = query.tags.as_ref().filter(|t| !t.is_empty()) {
+            // tags 以 JSON 数组存储，匹配带引号的完整标签
+            let tag_conditions: Vec<&str> = tags.iter().map(|_| "tags LIKE ? ESCAPE '\\'").collect();
+            let values: Vec<Box<dyn ToSql>> = tags
+                .iter()
+                .map(|tag| Box::new(format!("%\"{}\"%", escape_like(tag))) as Box<dyn ToSql>)
+                .collect();
+            builder = builder.add_condition(&format!("({})", tag_conditions.join(" OR ")), values);
+        }
+
+        if let Some(gender) = &query.gender {
+            builder = builder.where_eq("gender", gender.to_string());
+        }
+
+        if let Some(age_range) = &query.age_range {
+            builder = builder.add_condition(
+                "age BETWEEN ? AND ?",
+                vec![Box::new(age_range.min), Box::new(age_range.max)],
+            );
+        }
+
+        if let Some(range) = &query.last_visit_range {
+            builder = builder.add_condition(
+                "EXISTS (SELECT 1 FROM consultations c WHERE c.patient_id = patients.id AND c.created_at BETWEEN ? AND ?)",
+                vec![Box::new(range.start), Box::new(range.end)],
+            );
+        }
+
+        if let Some(doctor_id) = scope.doctor_id() {
+            builder = builder.add_condition(
+                "EXISTS (SELECT 1 FROM consultations c WHERE c.patient_id = patients.id AND c.doctor_id = ?)",
+                vec![Box::new(doctor_id.to_string())],
+            );
+        }
 
         // 获取总数
-        let count_sql = format!("SELECT COUNT(*) FROM patients {}", search_condition);
+        let (count_sql, count_params) = builder.build_count("patients");
         let mut count_stmt = conn.prepare(&count_sql)?;
-        let total: i64 = count_stmt.query_row([], |row| row.get(0))?;
+        let total: i64 = count_stmt.query_row(count_params.as_slice(), |row| row.get(0))?;
 
         // 获取分页数据
-        let query_sql = format!(
-            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at
-             FROM patients {} ORDER BY created_at DESC LIMIT {} OFFSET {}",
-            search_condition, page_size, offset
+        let builder = builder
+            .order_by(&order_clause(query.sort.as_ref()))
+            .limit(page_size as i64)
+            .offset(offset as i64);
+        let (query_sql, query_params) = builder.build(
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
+             FROM patients",
         );
 
-        let mut stmt = conn.prepare(&query_sql)?;
-        let patient_iter = stmt.query_map([], |row| {
-            Ok(Patient {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                age: row.get(2)?,
-                gender: row.get(3)?,
-                phone: row.get(4)?,
-                id_card: row.get(5)?,
-                tags: row.get::<_, Option<String>>(6)?.map(|s|
-                    serde_json::from_str(&s).unwrap_or_default()
-                ).unwrap_or_default(),
-                avatar_url: row.get(7)?,
-                last_sync: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
+        let patients = get_query_optimizer().execute_sql(&conn, "patient_search", &query_sql, || {
+            let mut stmt = conn.prepare(&query_sql)?;
+            let patient_iter = stmt.query_map(query_params.as_slice(), map_patient)?;
+            patient_iter.collect::<Result<Vec<Patient>>>()
         })?;
 
-        let mut patients = Vec::new();
-        for patient in patient_iter {
-            patients.push(patient?);
-        }
-
         Ok(PageResult::new(patients, total, page, page_size))
     }
 
+    // 写入远端同步下来的患者（保留远端 ID）
+    pub fn upsert(&self, patient: &Patient) -> Result<(), Box<dyn std::error::Error>> {
+        let change = retry_transaction_on_busy(&self.connection, "upsert patient", |tx| Self::upsert_in(tx, patient))?;
+
+        self.invalidate_cache();
+        publish_data_changes(&[change]);
+        Ok(())
+    }
+
+    // 在调用方的事务内写入并记录变更，调用方负责提交后清除缓存、发布返回的变更
+    pub fn upsert_in(conn: &Connection, patient: &Patient) -> Result<DataChanged, Box<dyn std::error::Error>> {
+        let tags_json = serde_json::to_string(&patient.tags)?;
+        let protected = ProtectedFields::of(patient)?;
+        let op = ChangeLogDao::upsert_op_in(conn, ChangeEntity::Patient, &patient.id)?;
+
+        conn.execute(
+            "INSERT INTO patients (id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at,
+                                   phone_hash, id_card_hash, name_pinyin)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                name_pinyin = excluded.name_pinyin,
+                age = excluded.age,
+                gender = excluded.gender,
+                phone = excluded.phone,
+                id_card = excluded.id_card,
+                phone_hash = excluded.phone_hash,
+                id_card_hash = excluded.id_card_hash,
+                tags = excluded.tags,
+                avatar_url = excluded.avatar_url,
+                last_sync = excluded.last_sync,
+                updated_at = excluded.updated_at,
+                version = patients.version + 1",
+            params![
+                patient.id,
+                patient.name,
+                patient.age,
+                patient.gender,
+                protected.phone,
+                protected.id_card,
+                tags_json,
+                patient.avatar_url,
+                patient.last_sync,
+                patient.created_at,
+                patient.updated_at,
+                protected.phone_hash,
+                protected.id_card_hash,
+                pinyin_sort_key(&patient.name)
+            ],
+        )?;
+
+        ChangeLogDao::record_in(conn, ChangeEntity::Patient, &patient.id, op)
+    }
+
     pub fn find_by_phone(&self, phone: &str) -> Result<Option<Patient>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at
-             FROM patients WHERE phone = ?1"
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
+             FROM patients WHERE phone_hash = ?1 OR (phone_hash IS NULL AND phone = ?2)"
         )?;
 
-        let patient_result = stmt.query_row(params![phone], |row| {
-            Ok(Patient {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                age: row.get(2)?,
-                gender: row.get(3)?,
-                phone: row.get(4)?,
-                id_card: row.get(5)?,
-                tags: row.get::<_, Option<String>>(6)?.map(|s|
-                    serde_json::from_str(&s).unwrap_or_default()
-                ).unwrap_or_default(),
-                avatar_url: row.get(7)?,
-                last_sync: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        });
+        let patient_result = stmt.query_row(params![phone_index(phone)?, phone.trim()], map_patient);
 
         match patient_result {
             Ok(patient) => Ok(Some(patient)),
@@ -99,6 +295,182 @@ impl PatientDao {
         }
     }
 
+    pub fn find_by_id_card(&self, id_card: &str) -> Result<Option<Patient>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
+             FROM patients WHERE id_card_hash = ?1 OR (id_card_hash IS NULL AND UPPER(id_card) = ?2)"
+        )?;
+
+        let patient_result = stmt.query_row(params![id_card_index(id_card)?, id_card.trim().to_uppercase()], map_patient);
+
+        match patient_result {
+            Ok(patient) => Ok(Some(patient)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    // 批量导入：新患者分批插入，已存在的患者分批更新
+    pub fn bulk_import(&self, inserts: &[Patient], updates: &[Patient], batch_size: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        // 只收集已提交批次的变更
+        let changes = RefCell::new(Vec::new());
+
+        BatchOperations::batch_insert(&conn, inserts, batch_size, |tx, chunk| {
+            let mut chunk_changes = Vec::with_capacity(chunk.len());
+            let mut stmt = tx.prepare(
+                "INSERT INTO patients (id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at,
+                                       phone_hash, id_card_hash, name_pinyin)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
+            )?;
+            for patient in chunk {
+                let tags_json = serde_json::to_string(&patient.tags).unwrap_or_else(|_| "[]".to_string());
+                let protected = ProtectedFields::of(patient).map_err(encryption_error)?;
+                stmt.execute(params![
+                    patient.id,
+                    patient.name,
+                    patient.age,
+                    patient.gender,
+                    protected.phone,
+                    protected.id_card,
+                    tags_json,
+                    patient.avatar_url,
+                    patient.last_sync,
+                    patient.created_at,
+                    patient.updated_at,
+                    protected.phone_hash,
+                    protected.id_card_hash,
+                    pinyin_sort_key(&patient.name)
+                ])?;
+                chunk_changes.push(
+                    ChangeLogDao::record_in(tx, ChangeEntity::Patient, &patient.id, ChangeOp::Insert).map_err(change_log_error)?,
+                );
+            }
+            changes.borrow_mut().extend(chunk_changes);
+            Ok(())
+        })?;
+
+        BatchOperations::batch_update(&conn, updates, batch_size, |tx, chunk| {
+            let mut chunk_changes = Vec::with_capacity(chunk.len());
+            let mut stmt = tx.prepare(
+                "UPDATE patients SET name = ?1, age = ?2, gender = ?3, phone = ?4, id_card = ?5, tags = ?6, updated_at = ?7,
+                 phone_hash = ?8, id_card_hash = ?9, name_pinyin = ?10, version = version + 1 WHERE id = ?11"
+            )?;
+            for patient in chunk {
+                let tags_json = serde_json::to_string(&patient.tags).unwrap_or_else(|_| "[]".to_string());
+                let protected = ProtectedFields::of(patient).map_err(encryption_error)?;
+                stmt.execute(params![
+                    patient.name,
+                    patient.age,
+                    patient.gender,
+                    protected.phone,
+                    protected.id_card,
+                    tags_json,
+                    patient.updated_at,
+                    protected.phone_hash,
+                    protected.id_card_hash,
+                    pinyin_sort_key(&patient.name),
+                    patient.id
+                ])?;
+                chunk_changes.push(
+                    ChangeLogDao::record_in(tx, ChangeEntity::Patient, &patient.id, ChangeOp::Update).map_err(change_log_error)?,
+                );
+            }
+            changes.borrow_mut().extend(chunk_changes);
+            Ok(())
+        })?;
+        drop(conn);
+
+        self.invalidate_cache();
+        publish_data_changes(&changes.into_inner());
+        Ok(())
+    }
+
+    // 首次同步时批量写入服务器下发的患者，已存在的按远端数据覆盖；返回写入条数
+    pub fn bulk_upsert(&self, patients: &[Patient]) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let changes = RefCell::new(Vec::new());
+
+        BatchOperations::batch_insert(&conn, patients, BULK_BATCH_SIZE, |tx, chunk| {
+            let mut chunk_changes = Vec::with_capacity(chunk.len());
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO patients (id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at,
+                                       phone_hash, id_card_hash, name_pinyin)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                 ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name,
+                    name_pinyin = excluded.name_pinyin,
+                    age = excluded.age,
+                    gender = excluded.gender,
+                    phone = excluded.phone,
+                    id_card = excluded.id_card,
+                    phone_hash = excluded.phone_hash,
+                    id_card_hash = excluded.id_card_hash,
+                    tags = excluded.tags,
+                    avatar_url = excluded.avatar_url,
+                    last_sync = excluded.last_sync,
+                    updated_at = excluded.updated_at,
+                    version = patients.version + 1"
+            )?;
+            for patient in chunk {
+                let tags_json = serde_json::to_string(&patient.tags).unwrap_or_else(|_| "[]".to_string());
+                let protected = ProtectedFields::of(patient).map_err(encryption_error)?;
+                let op = ChangeLogDao::upsert_op_in(tx, ChangeEntity::Patient, &patient.id).map_err(change_log_error)?;
+                stmt.execute(params![
+                    patient.id,
+                    patient.name,
+                    patient.age,
+                    patient.gender,
+                    protected.phone,
+                    protected.id_card,
+                    tags_json,
+                    patient.avatar_url,
+                    patient.last_sync,
+                    patient.created_at,
+                    patient.updated_at,
+                    protected.phone_hash,
+                    protected.id_card_hash,
+                    pinyin_sort_key(&patient.name)
+                ])?;
+                chunk_changes.push(ChangeLogDao::record_in(tx, ChangeEntity::Patient, &patient.id, op).map_err(change_log_error)?);
+            }
+            changes.borrow_mut().extend(chunk_changes);
+            Ok(())
+        })?;
+        drop(conn);
+
+        if !patients.is_empty() {
+            self.invalidate_cache();
+        }
+        publish_data_changes(&changes.into_inner());
+        Ok(patients.len())
+    }
+
+    // 患者 ID 到本地头像缓存的映射，未下载完成的缓存记录不返回路径
+    pub fn find_avatar(&self, patient_id: &str) -> Result<Option<PatientAvatar>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let avatar = conn
+            .query_row(
+                "SELECT p.name, p.avatar_url, fc.local_path
+                 FROM patients p
+                 LEFT JOIN file_cache fc ON fc.file_url = p.avatar_url AND fc.checksum IS NOT NULL
+                 WHERE p.id = ?1",
+                params![patient_id],
+                |row| {
+                    Ok(PatientAvatar {
+                        patient_id: patient_id.to_string(),
+                        name: row.get(0)?,
+                        avatar_url: row.get(1)?,
+                        cached_path: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(avatar)
+    }
+
     pub fn find_by_tags(&self, tags: &[String]) -> Result<Vec<Patient>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
 
@@ -109,29 +481,13 @@ impl PatientDao {
         let where_clause = format!("WHERE {}", tag_conditions.join(" OR "));
 
         let query_sql = format!(
-            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
              FROM patients {} ORDER BY created_at DESC",
             where_clause
         );
 
         let mut stmt = conn.prepare(&query_sql)?;
-        let patient_iter = stmt.query_map([], |row| {
-            Ok(Patient {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                age: row.get(2)?,
-                gender: row.get(3)?,
-                phone: row.get(4)?,
-                id_card: row.get(5)?,
-                tags: row.get::<_, Option<String>>(6)?.map(|s|
-                    serde_json::from_str(&s).unwrap_or_default()
-                ).unwrap_or_default(),
-                avatar_url: row.get(7)?,
-                last_sync: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        })?;
+        let patient_iter = stmt.query_map([], map_patient)?;
 
         let mut patients = Vec::new();
         for patient in patient_iter {
@@ -141,55 +497,232 @@ impl PatientDao {
         Ok(patients)
     }
 
-    pub fn update_tags(&self, patient_id: &str, tags: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
+    // 按读取时的版本号更新标签并在同一事务内记录修改，返回新的版本号
+    pub fn update_tags(
+        &self,
+        patient_id: &str,
+        tags: &[String],
+        expected_version: i64,
+        editor_id: Option<&str>,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
         let tags_json = serde_json::to_string(tags)?;
         let now = Utc::now();
 
-        conn.execute(
-            "UPDATE patients SET tags = ?1, updated_at = ?2 WHERE id = ?3",
-            params![tags_json, now, patient_id],
-        )?;
+        let change = retry_transaction_on_busy(&self.connection, "update patient tags", |tx| {
+            let Some(current) = Self::find_in(tx, patient_id)? else {
+                return Ok(None);
+            };
+            let updated = tx.execute(
+                "UPDATE patients SET tags = ?1, updated_at = ?2, version = version + 1 WHERE id = ?3 AND version = ?4",
+                params![tags_json, now, patient_id, expected_version],
+            )?;
+            if updated == 0 {
+                return Ok(None);
+            }
+
+            let mut edited = current.clone();
+            edited.tags = tags.to_vec();
+            PatientRevisionDao::insert_in(tx, patient_id, editor_id, &PatientRevision::diff(&current, &edited))?;
+            Ok(Some(ChangeLogDao::record_in(tx, ChangeEntity::Patient, patient_id, ChangeOp::Update)?))
+        })?;
+        let Some(change) = change else {
+            return Err(Box::new(ConflictError::new("patient", patient_id, expected_version)));
+        };
 
+        self.invalidate_cache();
+        publish_data_changes(&[change]);
+        Ok(expected_version + 1)
+    }
+
+    // 按读取时的版本号更新患者资料，同一事务内写入修改记录；后台同步等非人工修改的 editor_id 为 None
+    pub fn update_by(&self, patient: &Patient, editor_id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Utc::now();
+        let tags_json = serde_json::to_string(&patient.tags)?;
+        let protected = ProtectedFields::of(patient)?;
+
+        // 版本号不一致说明读取之后已被其他窗口修改
+        let change = retry_transaction_on_busy(&self.connection, "update patient", |tx| {
+            let Some(current) = Self::find_in(tx, &patient.id)? else {
+                return Ok(None);
+            };
+            let updated = tx.execute(
+                "UPDATE patients SET name = ?1, age = ?2, gender = ?3, phone = ?4, id_card = ?5, tags = ?6,
+                 avatar_url = ?7, last_sync = ?8, updated_at = ?9, phone_hash = ?10, id_card_hash = ?11, name_pinyin = ?12,
+                 version = version + 1
+                 WHERE id = ?13 AND version = ?14",
+                params![
+                    patient.name,
+                    patient.age,
+                    patient.gender,
+                    protected.phone,
+                    protected.id_card,
+                    tags_json,
+                    patient.avatar_url,
+                    patient.last_sync,
+                    now,
+                    protected.phone_hash,
+                    protected.id_card_hash,
+                    pinyin_sort_key(&patient.name),
+                    patient.id,
+                    patient.version
+                ],
+            )?;
+            if updated == 0 {
+                return Ok(None);
+            }
+
+            PatientRevisionDao::insert_in(tx, &patient.id, editor_id, &PatientRevision::diff(&current, patient))?;
+            Ok(Some(ChangeLogDao::record_in(tx, ChangeEntity::Patient, &patient.id, ChangeOp::Update)?))
+        })?;
+        let Some(change) = change else {
+            return Err(Box::new(ConflictError::new("patient", &patient.id, patient.version)));
+        };
+
+        self.invalidate_cache();
+        publish_data_changes(&[change]);
         Ok(())
     }
 
+    fn find_in(conn: &Connection, id: &str) -> Result<Option<Patient>, Box<dyn std::error::Error>> {
+        let patient = conn.query_row(
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
+             FROM patients WHERE id = ?1",
+            params![id],
+            map_patient,
+        ).optional()?;
+
+        Ok(patient)
+    }
+
+    // 统计所有在用标签及其患者数
+    pub fn get_all_tags(&self) -> Result<Vec<TagUsage>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT tags FROM patients WHERE tags IS NOT NULL")?;
+
+        let tags_iter = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut counts: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
+        for tags_json in tags_iter {
+            let tags: Vec<String> = serde_json::from_str(&tags_json?).unwrap_or_default();
+            // 同一患者重复的标签只计一次
+            let unique: std::collections::HashSet<String> = tags.into_iter().collect();
+            for tag in unique {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+        }
+
+        let mut usages: Vec<TagUsage> = counts
+            .into_iter()
+            .map(|(tag, count)| TagUsage { tag, count })
+            .collect();
+        usages.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+
+        Ok(usages)
+    }
+
+    // 将 sources 中的标签合并为 target，返回受影响的患者数
+    pub fn merge_tags(&self, sources: &[String], target: &str, user_id: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        self.rewrite_tags(sources, target, "merge_tags", user_id)
+    }
+
+    pub fn rename_tag(&self, old_tag: &str, new_tag: &str, user_id: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        self.rewrite_tags(&[old_tag.to_string()], new_tag, "rename_tag", user_id)
+    }
+
+    // 在同一事务内改写患者标签并记录审计日志
+    fn rewrite_tags(&self, sources: &[String], target: &str, action: &str, user_id: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = Utc::now();
+
+        let affected: Vec<(String, Vec<String>)> = {
+            let mut stmt = tx.prepare("SELECT id, tags FROM patients WHERE tags IS NOT NULL")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+            let mut affected = Vec::new();
+            for row in rows {
+                let (id, tags_json) = row?;
+                let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+                if !tags.iter().any(|tag| sources.contains(tag)) {
+                    continue;
+                }
+
+                // 替换为目标标签并去重，保持原有顺序
+                let mut merged: Vec<String> = Vec::with_capacity(tags.len());
+                for tag in tags {
+                    let tag = if sources.contains(&tag) { target.to_string() } else { tag };
+                    if !merged.contains(&tag) {
+                        merged.push(tag);
+                    }
+                }
+                affected.push((id, merged));
+            }
+            affected
+        };
+
+        let mut changes = Vec::with_capacity(affected.len());
+        for (id, tags) in &affected {
+            tx.execute(
+                "UPDATE patients SET tags = ?1, updated_at = ?2, version = version + 1 WHERE id = ?3",
+                params![serde_json::to_string(tags)?, now, id],
+            )?;
+            changes.push(ChangeLogDao::record_in(&tx, ChangeEntity::Patient, id, ChangeOp::Update)?);
+        }
+
+        let details = serde_json::json!({
+            "sources": sources,
+            "target": target,
+            "patientCount": affected.len(),
+        });
+        tx.execute(
+            "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, details, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Uuid::new_v4().to_string(),
+                user_id,
+                action,
+                "patient_tag",
+                target,
+                details.to_string(),
+                SqlTimestamp(now)
+            ],
+        )?;
+
+        tx.commit()?;
+        self.invalidate_cache();
+        publish_data_changes(&changes);
+        Ok(affected.len())
+    }
+
     pub fn update_last_sync(&self, patient_id: &str) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
         let now = Utc::now();
 
-        conn.execute(
+        let updated = tx.execute(
             "UPDATE patients SET last_sync = ?1, updated_at = ?2 WHERE id = ?3",
             params![now, now, patient_id],
         )?;
+        let change = if updated > 0 {
+            Some(ChangeLogDao::record_in(&tx, ChangeEntity::Patient, patient_id, ChangeOp::Update)?)
+        } else {
+            None
+        };
+        tx.commit()?;
 
+        self.invalidate_cache();
+        publish_data_changes(change.as_slice());
         Ok(())
     }
 
     pub fn get_recent_patients(&self, limit: i32) -> Result<Vec<Patient>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
              FROM patients ORDER BY updated_at DESC LIMIT ?1"
         )?;
 
-        let patient_iter = stmt.query_map(params![limit], |row| {
-            Ok(Patient {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                age: row.get(2)?,
-                gender: row.get(3)?,
-                phone: row.get(4)?,
-                id_card: row.get(5)?,
-                tags: row.get::<_, Option<String>>(6)?.map(|s|
-                    serde_json::from_str(&s).unwrap_or_default()
-                ).unwrap_or_default(),
-                avatar_url: row.get(7)?,
-                last_sync: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        })?;
+        let patient_iter = stmt.query_map(params![limit], map_patient)?;
 
         let mut patients = Vec::new();
         for patient in patient_iter {
@@ -198,60 +731,134 @@ impl PatientDao {
 
         Ok(patients)
     }
+
+    // 统计尚未加密或缺少 HMAC 索引的患者
+    pub fn count_unprotected(&self) -> Result<i64, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let sql = format!("SELECT COUNT(*) FROM patients WHERE {}", UNPROTECTED_CONDITION);
+        let count = conn.query_row(&sql, params![ENCRYPTED_FIELD_PREFIX], |row| row.get(0))?;
+        Ok(count)
+    }
+
+    // 一次性迁移：分批加密历史明文，每批一个事务，progress(已处理, 总数)
+    // 不修改 updated_at，避免触发同步冲突
+    pub fn encrypt_legacy_rows<F>(&self, batch_size: usize, mut progress: F) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        F: FnMut(usize, usize),
+    {
+        let conn = self.connection.lock().unwrap();
+
+        let pending: Vec<(String, Option<String>, Option<String>)> = {
+            let sql = format!("SELECT id, phone, id_card FROM patients WHERE {}", UNPROTECTED_CONDITION);
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params![ENCRYPTED_FIELD_PREFIX], |row| {
+                Ok((row.get(0)?, read_protected(row, 1)?, read_protected(row, 2)?))
+            })?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+
+        let total = pending.len();
+        let mut processed = 0;
+        for chunk in pending.chunks(batch_size.max(1)) {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "UPDATE patients SET phone = ?1, id_card = ?2, phone_hash = ?3, id_card_hash = ?4 WHERE id = ?5"
+                )?;
+                for (id, phone, id_card) in chunk {
+                    let protected = ProtectedFields::protect(phone.as_deref(), id_card.as_deref())?;
+                    stmt.execute(params![
+                        protected.phone,
+                        protected.id_card,
+                        protected.phone_hash,
+                        protected.id_card_hash,
+                        id
+                    ])?;
+                }
+            }
+            tx.commit()?;
+
+            processed += chunk.len();
+            progress(processed, total);
+        }
+
+        if total > 0 {
+            self.invalidate_cache();
+        }
+        Ok(total)
+    }
+}
+
+// 排序字段已在命令层按白名单校验，无法识别的字段退回默认排序
+fn order_clause(sort: Option<&SortParams>) -> String {
+    let Some(sort) = sort else {
+        return "created_at DESC".to_string();
+    };
+    let direction = match sort.order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+
+    match PatientSortField::parse(&sort.field) {
+        // 拼音相同的同音字再按原文排序，保证翻页顺序稳定
+        Some(PatientSortField::Name) => format!("name_pinyin {0}, name {0}, id", direction),
+        // 从未就诊的患者无论升降序都排在最后
+        Some(PatientSortField::LastVisit) => format!("last_visit IS NULL, last_visit {}, id", direction),
+        Some(PatientSortField::CreatedAt) => format!("created_at {}, id", direction),
+        None => "created_at DESC".to_string(),
+    }
 }
 
+// 未加密（没有格式前缀）或缺少索引的行，?1 为加密前缀
+const UNPROTECTED_CONDITION: &str =
+    "(phone IS NOT NULL AND phone <> '' AND (phone_hash IS NULL OR substr(phone, 1, length(?1)) <> ?1))
+     OR (id_card IS NOT NULL AND id_card <> '' AND (id_card_hash IS NULL OR substr(id_card, 1, length(?1)) <> ?1))";
+
 impl BaseDao<Patient> for PatientDao {
     fn create(&self, patient: &Patient) -> Result<String, Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
         let id = Uuid::new_v4().to_string();
         let now = Utc::now();
         let tags_json = serde_json::to_string(&patient.tags)?;
+        let protected = ProtectedFields::of(patient)?;
 
-        conn.execute(
-            "INSERT INTO patients (id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-            params![
-                id,
-                patient.name,
-                patient.age,
-                patient.gender,
-                patient.phone,
-                patient.id_card,
-                tags_json,
-                patient.avatar_url,
-                patient.last_sync,
-                now,
-                now
-            ],
-        )?;
+        let change = retry_transaction_on_busy(&self.connection, "create patient", |tx| {
+            tx.execute(
+                "INSERT INTO patients (id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at,
+                                       phone_hash, id_card_hash, name_pinyin)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    id,
+                    patient.name,
+                    patient.age,
+                    patient.gender,
+                    protected.phone,
+                    protected.id_card,
+                    tags_json,
+                    patient.avatar_url,
+                    patient.last_sync,
+                    now,
+                    now,
+                    protected.phone_hash,
+                    protected.id_card_hash,
+                    pinyin_sort_key(&patient.name)
+                ],
+            )?;
+            ChangeLogDao::record_in(tx, ChangeEntity::Patient, &id, ChangeOp::Insert)
+        })?;
 
+        self.invalidate_cache();
+        publish_data_changes(&[change]);
         Ok(id)
     }
 
     fn find_by_id(&self, id: &str) -> Result<Option<Patient>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
              FROM patients WHERE id = ?1"
         )?;
 
-        let patient_result = stmt.query_row(params![id], |row| {
-            Ok(Patient {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                age: row.get(2)?,
-                gender: row.get(3)?,
-                phone: row.get(4)?,
-                id_card: row.get(5)?,
-                tags: row.get::<_, Option<String>>(6)?.map(|s|
-                    serde_json::from_str(&s).unwrap_or_default()
-                ).unwrap_or_default(),
-                avatar_url: row.get(7)?,
-                last_sync: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        });
+        let patient_result = stmt.query_row(params![id], map_patient);
 
         match patient_result {
             Ok(patient) => Ok(Some(patient)),
@@ -261,60 +868,41 @@ impl BaseDao<Patient> for PatientDao {
     }
 
     fn update(&self, patient: &Patient) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
-        let now = Utc::now();
-        let tags_json = serde_json::to_string(&patient.tags)?;
-
-        conn.execute(
-            "UPDATE patients SET name = ?1, age = ?2, gender = ?3, phone = ?4, id_card = ?5, tags = ?6,
-             avatar_url = ?7, last_sync = ?8, updated_at = ?9 WHERE id = ?10",
-            params![
-                patient.name,
-                patient.age,
-                patient.gender,
-                patient.phone,
-                patient.id_card,
-                tags_json,
-                patient.avatar_url,
-                patient.last_sync,
-                now,
-                patient.id
-            ],
-        )?;
-
-        Ok(())
+        self.update_by(patient, None)
     }
 
     fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = self.connection.lock().unwrap();
-        conn.execute("DELETE FROM patients WHERE id = ?1", params![id])?;
+        // 患者的问诊随患者级联删除，同样记录变更
+        let changes = retry_transaction_on_busy(&self.connection, "delete patient", |tx| {
+            let consultation_ids = {
+                let mut stmt = tx.prepare("SELECT id FROM consultations WHERE patient_id = ?1")?;
+                let ids = stmt.query_map(params![id], |row| row.get::<_, String>(0))?;
+                ids.collect::<Result<Vec<String>>>()?
+            };
+            if tx.execute("DELETE FROM patients WHERE id = ?1", params![id])? == 0 {
+                return Ok(Vec::new());
+            }
+
+            let mut changes = Vec::with_capacity(consultation_ids.len() + 1);
+            for consultation_id in &consultation_ids {
+                changes.push(ChangeLogDao::record_in(tx, ChangeEntity::Consultation, consultation_id, ChangeOp::Delete)?);
+            }
+            changes.push(ChangeLogDao::record_in(tx, ChangeEntity::Patient, id, ChangeOp::Delete)?);
+            Ok(changes)
+        })?;
+        self.invalidate_cache();
+        publish_data_changes(&changes);
         Ok(())
     }
 
     fn find_all(&self) -> Result<Vec<Patient>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at
+            "SELECT id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at, version, last_visit
              FROM patients ORDER BY created_at DESC"
         )?;
 
-        let patient_iter = stmt.query_map([], |row| {
-            Ok(Patient {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                age: row.get(2)?,
-                gender: row.get(3)?,
-                phone: row.get(4)?,
-                id_card: row.get(5)?,
-                tags: row.get::<_, Option<String>>(6)?.map(|s|
-                    serde_json::from_str(&s).unwrap_or_default()
-                ).unwrap_or_default(),
-                avatar_url: row.get(7)?,
-                last_sync: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        })?;
+        let patient_iter = stmt.query_map([], map_patient)?;
 
         let mut patients = Vec::new();
         for patient in patient_iter {
@@ -329,4 +917,271 @@ impl Default for PatientDao {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+fn map_patient(row: &Row) -> Result<Patient> {
+    Ok(Patient {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        age: row.get(2)?,
+        gender: row.get(3)?,
+        phone: read_protected(row, 4)?,
+        id_card: read_protected(row, 5)?,
+        tags: row.get::<_, Option<String>>(6)?.map(|s|
+            serde_json::from_str(&s).unwrap_or_default()
+        ).unwrap_or_default(),
+        avatar_url: row.get(7)?,
+        last_sync: row.get(8)?,
+        last_visit: row.get(12)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+        version: row.get(11)?,
+    })
+}
+
+// 读取加密字段，历史明文原样返回
+fn read_protected(row: &Row, index: usize) -> Result<Option<String>> {
+    let stored: Option<String> = row.get(index)?;
+    stored
+        .map(|value| {
+            field_crypto()
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))?
+                .decrypt_field(&value)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, e.into()))
+        })
+        .transpose()
+}
+
+// 批量写入回调只能返回 rusqlite::Error
+fn encryption_error(err: Box<dyn std::error::Error>) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(err.to_string().into())
+}
+
+fn change_log_error(err: Box<dyn std::error::Error>) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(err.to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::PatientFieldChange;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_dao() -> PatientDao {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        PatientDao::with_connection(Arc::new(Mutex::new(conn)))
+    }
+
+    fn patient(phone: &str, id_card: &str) -> Patient {
+        let now = Utc::now();
+        Patient {
+            id: String::new(),
+            name: "张三".to_string(),
+            age: Some(35),
+            gender: Some("male".to_string()),
+            phone: Some(phone.to_string()),
+            id_card: Some(id_card.to_string()),
+            tags: vec![],
+            avatar_url: None,
+            last_sync: None,
+            last_visit: None,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        }
+    }
+
+    fn raw_fields(dao: &PatientDao, id: &str) -> (String, String) {
+        let conn = dao.connection.lock().unwrap();
+        conn.query_row("SELECT phone, id_card FROM patients WHERE id = ?1", params![id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sensitive_fields_round_trip() {
+        let dao = create_test_dao();
+        let id = dao.create(&patient("13800138000", "11010119900101123x")).unwrap();
+
+        let (phone, id_card) = raw_fields(&dao, &id);
+        assert!(phone.starts_with(ENCRYPTED_FIELD_PREFIX));
+        assert!(id_card.starts_with(ENCRYPTED_FIELD_PREFIX));
+        assert!(!phone.contains("13800138000"));
+
+        let found = dao.find_by_id(&id).unwrap().unwrap();
+        assert_eq!(found.phone.as_deref(), Some("13800138000"));
+        assert_eq!(found.id_card.as_deref(), Some("11010119900101123X"));
+        assert_eq!(dao.find_by_id_card("11010119900101123x").unwrap().unwrap().id, id);
+    }
+
+    #[test]
+    fn test_interleaved_updates_detect_stale_version() {
+        let dao = create_test_dao();
+        let id = dao.create(&patient("13800138000", "110101199001011237")).unwrap();
+
+        // 两个窗口读到同一版本
+        let mut window_a = dao.find_by_id(&id).unwrap().unwrap();
+        let mut window_b = dao.find_by_id(&id).unwrap().unwrap();
+        assert_eq!(window_a.version, 1);
+
+        window_a.tags = vec!["高血压".to_string()];
+        dao.update(&window_a).unwrap();
+
+        window_b.name = "张三丰".to_string();
+        let err = dao.update(&window_b).unwrap_err();
+        let conflict = err.downcast_ref::<ConflictError>().unwrap();
+        assert_eq!(conflict.expected_version, 1);
+
+        // 后写入的窗口没有覆盖前一个窗口的标签
+        let current = dao.find_by_id(&id).unwrap().unwrap();
+        assert_eq!(current.name, "张三");
+        assert_eq!(current.tags, vec!["高血压"]);
+        assert_eq!(current.version, 2);
+
+        // 标签更新同样校验版本
+        assert!(dao.update_tags(&id, &["糖尿病".to_string()], 1, None).is_err());
+        assert_eq!(dao.update_tags(&id, &["糖尿病".to_string()], current.version, None).unwrap(), 3);
+
+        // 重新读取后再提交即可成功
+        window_b = dao.find_by_id(&id).unwrap().unwrap();
+        window_b.name = "张三丰".to_string();
+        dao.update(&window_b).unwrap();
+        let merged = dao.find_by_id(&id).unwrap().unwrap();
+        assert_eq!(merged.name, "张三丰");
+        assert_eq!(merged.tags, vec!["糖尿病"]);
+        assert_eq!(merged.version, 4);
+    }
+
+    #[test]
+    fn test_update_records_revision_diff() {
+        let dao = create_test_dao();
+        let revisions = PatientRevisionDao::with_connection(dao.connection.clone());
+        let id = dao.create(&patient("13800138000", "110101199001011237")).unwrap();
+
+        let mut edited = dao.find_by_id(&id).unwrap().unwrap();
+        edited.phone = Some("13900139000".to_string());
+        edited.age = Some(36);
+        edited.updated_at = Utc::now() + chrono::Duration::days(1);
+        dao.update_by(&edited, Some("d1")).unwrap();
+
+        // 只记录值发生变化的字段，updated_at 不计入
+        let page = revisions.find_by_patient(&id, 1, 20).unwrap();
+        assert_eq!(page.total, 1);
+        let revision = &page.items[0];
+        assert_eq!(revision.editor_id.as_deref(), Some("d1"));
+        assert_eq!(revision.changes.keys().collect::<Vec<_>>(), vec!["age", "phone"]);
+        assert_eq!(revision.changes["age"], PatientFieldChange { old: json!(35), new: json!(36) });
+        assert_eq!(
+            revision.changes["phone"],
+            PatientFieldChange { old: json!("13800138000"), new: json!("13900139000") }
+        );
+
+        // 手机号的新旧值以密文落库
+        let stored: String = dao
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT changes FROM patient_revisions WHERE id = ?1", params![revision.id], |row| row.get(0))
+            .unwrap();
+        assert!(!stored.contains("13800138000"));
+        assert!(!stored.contains("13900139000"));
+
+        // 没有变化的提交不产生修改记录，标签修改同样记录
+        let unchanged = dao.find_by_id(&id).unwrap().unwrap();
+        dao.update_by(&unchanged, Some("d1")).unwrap();
+        dao.update_tags(&id, &["高血压".to_string()], unchanged.version + 1, Some("n1")).unwrap();
+
+        let page = revisions.find_by_patient(&id, 1, 20).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].editor_id.as_deref(), Some("n1"));
+        assert_eq!(page.items[0].changes["tags"], PatientFieldChange { old: json!([]), new: json!(["高血压"]) });
+    }
+
+    #[test]
+    fn test_failed_update_writes_no_revision() {
+        let dao = create_test_dao();
+        let id = dao.create(&patient("13800138000", "110101199001011237")).unwrap();
+        let count = |dao: &PatientDao| -> i64 {
+            dao.connection
+                .lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM patient_revisions", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        // 版本过期的修改不写入修改记录
+        let mut stale = dao.find_by_id(&id).unwrap().unwrap();
+        stale.name = "李四".to_string();
+        stale.version = 0;
+        assert!(dao.update_by(&stale, Some("d1")).is_err());
+        assert!(dao.update_tags(&id, &["高血压".to_string()], 0, Some("d1")).is_err());
+        assert_eq!(count(&dao), 0);
+
+        // 修改记录写入失败时患者资料的修改一并回滚
+        dao.connection.lock().unwrap().execute("DROP TABLE patient_revisions", []).unwrap();
+        let mut edited = dao.find_by_id(&id).unwrap().unwrap();
+        edited.name = "李四".to_string();
+        assert!(dao.update_by(&edited, Some("d1")).is_err());
+        assert!(dao.update_tags(&id, &["高血压".to_string()], edited.version, Some("d1")).is_err());
+
+        let current = dao.find_by_id(&id).unwrap().unwrap();
+        assert_eq!(current.name, "张三");
+        assert!(current.tags.is_empty());
+        assert_eq!(current.version, edited.version);
+    }
+
+    #[test]
+    fn test_phone_lookup_uses_hmac_index() {
+        let dao = create_test_dao();
+        let id = dao.create(&patient("13800138000", "110101199001011237")).unwrap();
+        dao.create(&patient("13900139000", "110101198805120020")).unwrap();
+
+        assert_eq!(dao.find_by_phone(" 13800138000 ").unwrap().unwrap().id, id);
+        assert!(dao.find_by_phone("13800138001").unwrap().is_none());
+
+        // 关键字检索对加密字段只做精确匹配
+        assert_eq!(dao.search_patients("13800138000", 1, 20).unwrap().items.len(), 1);
+        assert_eq!(dao.search_patients("1380013", 1, 20).unwrap().items.len(), 0);
+    }
+
+    #[test]
+    fn test_legacy_plaintext_rows_are_migrated() {
+        let dao = create_test_dao();
+        {
+            let conn = dao.connection.lock().unwrap();
+            for i in 0..5 {
+                conn.execute(
+                    "INSERT INTO patients (id, name, phone, id_card, tags, created_at, updated_at)
+                     VALUES (?1, '历史患者', ?2, ?3, '[]', ?4, ?4)",
+                    params![format!("legacy-{}", i), format!("1380013800{}", i), "110101199003071233", Utc::now()],
+                )
+                .unwrap();
+            }
+        }
+
+        // 迁移前明文可读，也能按手机号找到
+        let legacy = dao.find_by_phone("13800138002").unwrap().unwrap();
+        assert_eq!(legacy.id, "legacy-2");
+        assert_eq!(dao.count_unprotected().unwrap(), 5);
+
+        let mut reported = Vec::new();
+        let migrated = dao.encrypt_legacy_rows(2, |processed, total| reported.push((processed, total))).unwrap();
+        assert_eq!(migrated, 5);
+        assert_eq!(reported, vec![(2, 5), (4, 5), (5, 5)]);
+
+        let (phone, id_card) = raw_fields(&dao, "legacy-2");
+        assert!(CryptoService::is_encrypted_field(&phone));
+        assert!(CryptoService::is_encrypted_field(&id_card));
+        assert_eq!(dao.find_by_phone("13800138002").unwrap().unwrap().phone.as_deref(), Some("13800138002"));
+        assert_eq!(dao.find_by_id("legacy-4").unwrap().unwrap().id_card.as_deref(), Some("110101199003071233"));
+
+        // 再次执行时没有需要处理的行
+        assert_eq!(dao.count_unprotected().unwrap(), 0);
+        assert_eq!(dao.encrypt_legacy_rows(2, |_, _| {}).unwrap(), 0);
+    }
+}
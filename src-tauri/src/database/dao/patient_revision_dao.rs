@@ -0,0 +1,145 @@
+// 患者资料修改记录数据访问层，手机号、身份证号的新旧值以字段级密文落库
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::patient_dao::field_crypto;
+use crate::database::dao::PageResult;
+use crate::models::{PatientFieldChange, PatientRevision, PatientRevisionField};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use chrono::Utc;
+use uuid::Uuid;
+
+pub struct PatientRevisionDao {
+    connection: DbConnection,
+}
+
+impl PatientRevisionDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 由修改患者资料的事务调用，没有字段变化时不写入，返回修改记录 ID
+    pub fn insert_in(
+        conn: &Connection,
+        patient_id: &str,
+        editor_id: Option<&str>,
+        changes: &BTreeMap<String, PatientFieldChange>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let id = Uuid::new_v4().to_string();
+        conn.execute(
+            "INSERT INTO patient_revisions (id, patient_id, editor_id, changes, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, patient_id, editor_id, seal_changes(changes)?.to_string(), Utc::now()],
+        )?;
+        Ok(Some(id))
+    }
+
+    // 按修改时间倒序分页
+    pub fn find_by_patient(&self, patient_id: &str, page: i32, page_size: i32) -> Result<PageResult<PatientRevision>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let offset = (page - 1) * page_size;
+
+        let total: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM patient_revisions WHERE patient_id = ?1",
+            params![patient_id],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, patient_id, editor_id, changes, created_at FROM patient_revisions
+             WHERE patient_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT ?2 OFFSET ?3",
+        )?;
+        let revisions = stmt
+            .query_map(params![patient_id, page_size, offset], map_revision)?
+            .collect::<Result<Vec<PatientRevision>>>()?;
+
+        Ok(PageResult::new(revisions, total, page, page_size))
+    }
+
+    pub fn find_by_id(&self, id: &str) -> Result<Option<PatientRevision>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let revision = conn.query_row(
+            "SELECT id, patient_id, editor_id, changes, created_at FROM patient_revisions WHERE id = ?1",
+            params![id],
+            map_revision,
+        ).optional()?;
+
+        Ok(revision)
+    }
+}
+
+impl Default for PatientRevisionDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_sensitive(field: &str) -> bool {
+    PatientRevisionField::parse(field).is_some_and(|f| f.is_sensitive())
+}
+
+// 加密敏感字段的新旧值，未填写的 null 保持原样
+fn seal_changes(changes: &BTreeMap<String, PatientFieldChange>) -> Result<Value, Box<dyn std::error::Error>> {
+    let crypto = field_crypto()?;
+    let seal = |value: &Value| -> Result<Value, Box<dyn std::error::Error>> {
+        match value {
+            Value::String(plain) => Ok(Value::String(crypto.encrypt_field(plain)?)),
+            other => Ok(other.clone()),
+        }
+    };
+
+    let mut sealed = serde_json::Map::new();
+    for (field, change) in changes {
+        let change = if is_sensitive(field) {
+            PatientFieldChange { old: seal(&change.old)?, new: seal(&change.new)? }
+        } else {
+            change.clone()
+        };
+        sealed.insert(field.clone(), serde_json::to_value(change)?);
+    }
+    Ok(Value::Object(sealed))
+}
+
+fn open_changes(stored: &str) -> Result<BTreeMap<String, PatientFieldChange>, Box<dyn std::error::Error>> {
+    let crypto = field_crypto()?;
+    let open = |value: Value| -> Result<Value, Box<dyn std::error::Error>> {
+        match value {
+            Value::String(stored) => Ok(Value::String(crypto.decrypt_field(&stored)?)),
+            other => Ok(other),
+        }
+    };
+
+    let mut changes: BTreeMap<String, PatientFieldChange> = serde_json::from_str(stored)?;
+    for (field, change) in changes.iter_mut() {
+        if is_sensitive(field) {
+            change.old = open(std::mem::take(&mut change.old))?;
+            change.new = open(std::mem::take(&mut change.new))?;
+        }
+    }
+    Ok(changes)
+}
+
+fn map_revision(row: &Row) -> Result<PatientRevision> {
+    let stored: String = row.get(3)?;
+    let changes = open_changes(&stored)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.to_string().into()))?;
+
+    Ok(PatientRevision {
+        id: row.get(0)?,
+        patient_id: row.get(1)?,
+        editor_id: row.get(2)?,
+        changes,
+        created_at: row.get(4)?,
+    })
+}
@@ -0,0 +1,250 @@
+// 处方数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{publish_data_changes, ChangeLogDao, MessageDao};
+use crate::database::query_optimizer::{query_cache_for, CACHE_TAG_MESSAGES};
+use crate::models::{ChangeEntity, ChangeOp, Message, Prescription, PrescriptionItem};
+use crate::utils::SqlTimestamp;
+use rusqlite::{params, OptionalExtension, Result};
+use uuid::Uuid;
+use chrono::Utc;
+
+pub struct PrescriptionDao {
+    connection: DbConnection,
+}
+
+impl PrescriptionDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn create(&self, prescription: &Prescription) -> Result<String, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+
+        conn.execute(
+            "INSERT INTO prescriptions (id, consultation_id, doctor_id, items, status, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, 'draft', ?5, ?6)",
+            params![
+                id,
+                prescription.consultation_id,
+                prescription.doctor_id,
+                serde_json::to_string(&prescription.items)?,
+                now,
+                now
+            ],
+        )?;
+
+        Ok(id)
+    }
+
+    // 只允许修改草稿，返回是否更新成功
+    pub fn update_draft_items(&self, id: &str, items: &[PrescriptionItem]) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        let updated = conn.execute(
+            "UPDATE prescriptions SET items = ?1, updated_at = ?2 WHERE id = ?3 AND status = 'draft'",
+            params![serde_json::to_string(items)?, Utc::now(), id],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    pub fn find_by_id(&self, id: &str) -> Result<Option<Prescription>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, consultation_id, doctor_id, items, status, void_reason, created_at, updated_at, issued_at, voided_at
+             FROM prescriptions WHERE id = ?1"
+        )?;
+
+        let prescription_result = stmt.query_row(params![id], |row| {
+            Ok(Prescription {
+                id: row.get(0)?,
+                consultation_id: row.get(1)?,
+                doctor_id: row.get(2)?,
+                items: serde_json::from_str(&row.get::<_, String>(3)?).unwrap_or_default(),
+                status: row.get(4)?,
+                void_reason: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+                issued_at: row.get(8)?,
+                voided_at: row.get(9)?,
+            })
+        });
+
+        match prescription_result {
+            Ok(prescription) => Ok(Some(prescription)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    pub fn find_by_consultation(&self, consultation_id: &str) -> Result<Vec<Prescription>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, consultation_id, doctor_id, items, status, void_reason, created_at, updated_at, issued_at, voided_at
+             FROM prescriptions WHERE consultation_id = ?1 ORDER BY created_at DESC"
+        )?;
+
+        let prescription_iter = stmt.query_map(params![consultation_id], |row| {
+            Ok(Prescription {
+                id: row.get(0)?,
+                consultation_id: row.get(1)?,
+                doctor_id: row.get(2)?,
+                items: serde_json::from_str(&row.get::<_, String>(3)?).unwrap_or_default(),
+                status: row.get(4)?,
+                void_reason: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+                issued_at: row.get(8)?,
+                voided_at: row.get(9)?,
+            })
+        })?;
+
+        let mut prescriptions = Vec::new();
+        for prescription in prescription_iter {
+            prescriptions.push(prescription?);
+        }
+
+        Ok(prescriptions)
+    }
+
+    // 开具草稿处方：仅当处方仍为草稿且问诊进行中时生效，同一事务内把摘要写入问诊的 prescription 字段，
+    // 并写入会话事件消息和审计日志；返回是否开具成功
+    pub fn issue(
+        &self,
+        id: &str,
+        summary: &str,
+        notice: &Message,
+        user_id: Option<&str>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = Utc::now();
+
+        let updated = tx.execute(
+            "UPDATE prescriptions SET status = 'issued', issued_at = ?1, updated_at = ?1
+             WHERE id = ?2 AND status = 'draft'
+               AND EXISTS (SELECT 1 FROM consultations c WHERE c.id = prescriptions.consultation_id AND c.status = 'active')",
+            params![now, id],
+        )?;
+
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        tx.execute(
+            "UPDATE consultations SET prescription = ?1, updated_at = ?2, version = version + 1
+             WHERE id = (SELECT consultation_id FROM prescriptions WHERE id = ?3)",
+            params![summary, now, id],
+        )?;
+        let change = ChangeLogDao::record_in(&tx, ChangeEntity::Consultation, &notice.consultation_id, ChangeOp::Update)?;
+
+        MessageDao::upsert_in(&tx, notice)?;
+
+        let details = serde_json::json!({ "consultationId": notice.consultation_id });
+        tx.execute(
+            "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, details, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Uuid::new_v4().to_string(),
+                user_id,
+                "issue_prescription",
+                "prescription",
+                id,
+                details.to_string(),
+                SqlTimestamp(now)
+            ],
+        )?;
+
+        tx.commit()?;
+
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+        publish_data_changes(&[change]);
+        Ok(true)
+    }
+
+    // 作废草稿或已开具的处方；已开具的处方同时清除问诊上由它写入的摘要。返回是否作废成功
+    pub fn void(
+        &self,
+        id: &str,
+        reason: &str,
+        summary: &str,
+        user_id: Option<&str>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = Utc::now();
+
+        let previous_status: Option<String> = match tx.query_row(
+            "SELECT status FROM prescriptions WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        ) {
+            Ok(status) => Some(status),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let updated = tx.execute(
+            "UPDATE prescriptions SET status = 'voided', void_reason = ?1, voided_at = ?2, updated_at = ?2
+             WHERE id = ?3 AND status IN ('draft', 'issued')",
+            params![reason, now, id],
+        )?;
+
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        let mut change = None;
+        if previous_status.as_deref() == Some("issued") {
+            let consultation_id: Option<String> = tx
+                .query_row(
+                    "UPDATE consultations SET prescription = NULL, updated_at = ?1, version = version + 1
+                     WHERE id = (SELECT consultation_id FROM prescriptions WHERE id = ?2) AND prescription = ?3
+                     RETURNING id",
+                    params![now, id, summary],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(consultation_id) = consultation_id {
+                change = Some(ChangeLogDao::record_in(&tx, ChangeEntity::Consultation, &consultation_id, ChangeOp::Update)?);
+            }
+        }
+
+        let details = serde_json::json!({
+            "from": previous_status,
+            "reason": reason,
+        });
+        tx.execute(
+            "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, details, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Uuid::new_v4().to_string(),
+                user_id,
+                "void_prescription",
+                "prescription",
+                id,
+                details.to_string(),
+                SqlTimestamp(now)
+            ],
+        )?;
+
+        tx.commit()?;
+        publish_data_changes(change.as_slice());
+        Ok(true)
+    }
+}
+
+impl Default for PrescriptionDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
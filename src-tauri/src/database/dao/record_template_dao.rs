@@ -0,0 +1,199 @@
+// 病历模板数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::BaseDao;
+use crate::models::RecordTemplate;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use uuid::Uuid;
+use chrono::Utc;
+
+pub struct RecordTemplateDao {
+    connection: DbConnection,
+}
+
+impl RecordTemplateDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn find_by_doctor(&self, doctor_id: &str, record_type: Option<&str>) -> Result<Vec<RecordTemplate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, record_type, title, content, variables, created_at
+             FROM record_templates WHERE doctor_id = ?1 AND (?2 IS NULL OR record_type = ?2)
+             ORDER BY created_at DESC"
+        )?;
+
+        let template_iter = stmt.query_map(params![doctor_id, record_type], |row| {
+            Ok(RecordTemplate {
+                id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                record_type: row.get(2)?,
+                title: row.get(3)?,
+                content: row.get(4)?,
+                variables: row.get::<_, Option<String>>(5)?.map(|s|
+                    serde_json::from_str(&s).unwrap_or_default()
+                ).unwrap_or_default(),
+                created_at: row.get(6)?,
+            })
+        })?;
+
+        let mut templates = Vec::new();
+        for template in template_iter {
+            templates.push(template?);
+        }
+
+        Ok(templates)
+    }
+
+    // 按 id 写入模板，已存在时整行覆盖；返回是否新插入
+    pub fn upsert_in(conn: &Connection, template: &RecordTemplate) -> Result<bool, Box<dyn std::error::Error>> {
+        let exists = conn
+            .query_row("SELECT 1 FROM record_templates WHERE id = ?1", params![template.id], |_| Ok(()))
+            .optional()?
+            .is_some();
+        conn.execute(
+            "INSERT INTO record_templates (id, doctor_id, record_type, title, content, variables, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET doctor_id = excluded.doctor_id, record_type = excluded.record_type,
+                 title = excluded.title, content = excluded.content, variables = excluded.variables",
+            params![
+                template.id,
+                template.doctor_id,
+                template.record_type,
+                template.title,
+                template.content,
+                serde_json::to_string(&template.variables)?,
+                template.created_at
+            ],
+        )?;
+        Ok(!exists)
+    }
+
+    pub fn delete_in(conn: &Connection, ids: &[String]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare("DELETE FROM record_templates WHERE id = ?1")?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += stmt.execute(params![id])?;
+        }
+        Ok(deleted)
+    }
+}
+
+impl BaseDao<RecordTemplate> for RecordTemplateDao {
+    fn create(&self, template: &RecordTemplate) -> Result<String, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+        let variables_json = serde_json::to_string(&template.variables)?;
+
+        conn.execute(
+            "INSERT INTO record_templates (id, doctor_id, record_type, title, content, variables, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                id,
+                template.doctor_id,
+                template.record_type,
+                template.title,
+                template.content,
+                variables_json,
+                now
+            ],
+        )?;
+
+        Ok(id)
+    }
+
+    fn find_by_id(&self, id: &str) -> Result<Option<RecordTemplate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, record_type, title, content, variables, created_at
+             FROM record_templates WHERE id = ?1"
+        )?;
+
+        let template_result = stmt.query_row(params![id], |row| {
+            Ok(RecordTemplate {
+                id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                record_type: row.get(2)?,
+                title: row.get(3)?,
+                content: row.get(4)?,
+                variables: row.get::<_, Option<String>>(5)?.map(|s|
+                    serde_json::from_str(&s).unwrap_or_default()
+                ).unwrap_or_default(),
+                created_at: row.get(6)?,
+            })
+        });
+
+        match template_result {
+            Ok(template) => Ok(Some(template)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    fn update(&self, template: &RecordTemplate) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let variables_json = serde_json::to_string(&template.variables)?;
+
+        conn.execute(
+            "UPDATE record_templates SET record_type = ?1, title = ?2, content = ?3, variables = ?4 WHERE id = ?5",
+            params![
+                template.record_type,
+                template.title,
+                template.content,
+                variables_json,
+                template.id
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute("DELETE FROM record_templates WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn find_all(&self) -> Result<Vec<RecordTemplate>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, doctor_id, record_type, title, content, variables, created_at
+             FROM record_templates ORDER BY created_at DESC"
+        )?;
+
+        let template_iter = stmt.query_map([], |row| {
+            Ok(RecordTemplate {
+                id: row.get(0)?,
+                doctor_id: row.get(1)?,
+                record_type: row.get(2)?,
+                title: row.get(3)?,
+                content: row.get(4)?,
+                variables: row.get::<_, Option<String>>(5)?.map(|s|
+                    serde_json::from_str(&s).unwrap_or_default()
+                ).unwrap_or_default(),
+                created_at: row.get(6)?,
+            })
+        })?;
+
+        let mut templates = Vec::new();
+        for template in template_iter {
+            templates.push(template?);
+        }
+
+        Ok(templates)
+    }
+}
+
+impl Default for RecordTemplateDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
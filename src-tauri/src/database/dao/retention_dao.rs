@@ -0,0 +1,159 @@
+// 数据保留策略及维护记录数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::{MaintenanceKind, MaintenanceRun, MaintenanceTrigger, RetentionPolicy};
+use rusqlite::{params, Connection, Result};
+use chrono::Utc;
+
+const KEY_MESSAGES: &str = "messages";
+const KEY_AUDIT_LOGS: &str = "audit_logs";
+const KEY_ANOMALY_RECORDS: &str = "anomaly_records";
+const KEY_FILE_CACHE: &str = "file_cache";
+const KEY_BACKUPS: &str = "backups";
+const KEY_TRASH: &str = "trash";
+const KEY_VACUUM_THRESHOLD_MB: &str = "vacuum_threshold_mb";
+
+pub struct RetentionDao {
+    connection: DbConnection,
+}
+
+impl RetentionDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 未保存的项使用默认值
+    pub fn load_policy(&self) -> Result<RetentionPolicy, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT key, value FROM retention_policy")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?)))?;
+
+        let mut policy = RetentionPolicy::default();
+        for row in rows {
+            let (key, value) = row?;
+            match key.as_str() {
+                KEY_MESSAGES => policy.message_days = value,
+                KEY_AUDIT_LOGS => policy.audit_log_days = value,
+                KEY_ANOMALY_RECORDS => policy.anomaly_record_days = value,
+                KEY_FILE_CACHE => policy.file_cache_days = value,
+                KEY_BACKUPS => policy.backup_days = value,
+                KEY_TRASH => policy.trash_days = value,
+                KEY_VACUUM_THRESHOLD_MB => policy.vacuum_threshold_mb = value,
+                _ => tracing::warn!("Ignoring unknown retention policy key: {}", key),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    pub fn save_policy(&self, policy: &RetentionPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        Self::save_policy_in(&tx, policy)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn save_policy_in(conn: &Connection, policy: &RetentionPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Utc::now();
+
+        let entries = [
+            (KEY_MESSAGES, policy.message_days),
+            (KEY_AUDIT_LOGS, policy.audit_log_days),
+            (KEY_ANOMALY_RECORDS, policy.anomaly_record_days),
+            (KEY_FILE_CACHE, policy.file_cache_days),
+            (KEY_BACKUPS, policy.backup_days),
+            (KEY_TRASH, policy.trash_days),
+            (KEY_VACUUM_THRESHOLD_MB, policy.vacuum_threshold_mb),
+        ];
+        for (key, value) in entries {
+            conn.execute(
+                "INSERT INTO retention_policy (key, value, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+                params![key, value, now],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn record_run(&self, run: &MaintenanceRun) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "INSERT INTO maintenance_runs (id, triggered_by, started_at, finished_at, deleted_messages, deleted_audit_logs,
+             deleted_anomaly_records, deleted_cache_files, deleted_backups, freed_bytes, vacuumed, status, error_message,
+             kind, summary, purged_trash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![
+                run.id,
+                run.triggered_by.as_str(),
+                run.started_at,
+                run.finished_at,
+                run.deleted_messages as i64,
+                run.deleted_audit_logs as i64,
+                run.deleted_anomaly_records as i64,
+                run.deleted_cache_files as i64,
+                run.deleted_backups as i64,
+                run.freed_bytes as i64,
+                run.vacuumed,
+                run.status,
+                run.error_message,
+                run.kind.as_str(),
+                run.summary.as_ref().map(|summary| summary.to_string()),
+                run.purged_trash as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn find_recent_runs(&self, limit: i32) -> Result<Vec<MaintenanceRun>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, triggered_by, started_at, finished_at, deleted_messages, deleted_audit_logs,
+             deleted_anomaly_records, deleted_cache_files, deleted_backups, freed_bytes, vacuumed, status, error_message,
+             kind, summary, purged_trash
+             FROM maintenance_runs ORDER BY started_at DESC LIMIT ?1"
+        )?;
+
+        let run_iter = stmt.query_map(params![limit], |row| {
+            Ok(MaintenanceRun {
+                id: row.get(0)?,
+                kind: MaintenanceKind::parse(&row.get::<_, String>(13)?).unwrap_or(MaintenanceKind::Retention),
+                triggered_by: MaintenanceTrigger::parse(&row.get::<_, String>(1)?).unwrap_or(MaintenanceTrigger::Scheduled),
+                started_at: row.get(2)?,
+                finished_at: row.get(3)?,
+                deleted_messages: row.get::<_, i64>(4)? as usize,
+                deleted_audit_logs: row.get::<_, i64>(5)? as usize,
+                deleted_anomaly_records: row.get::<_, i64>(6)? as usize,
+                deleted_cache_files: row.get::<_, i64>(7)? as usize,
+                deleted_backups: row.get::<_, i64>(8)? as usize,
+                purged_trash: row.get::<_, i64>(15)? as usize,
+                freed_bytes: row.get::<_, i64>(9)? as u64,
+                vacuumed: row.get(10)?,
+                status: row.get(11)?,
+                error_message: row.get(12)?,
+                summary: row
+                    .get::<_, Option<String>>(14)?
+                    .and_then(|summary| serde_json::from_str(&summary).ok()),
+            })
+        })?;
+
+        let mut runs = Vec::new();
+        for run in run_iter {
+            runs.push(run?);
+        }
+
+        Ok(runs)
+    }
+}
+
+impl Default for RetentionDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,77 @@
+// 安全检测配置数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::SecurityConfig;
+use rusqlite::{params, Result};
+use chrono::Utc;
+
+const KEY_BULK_PATIENT_VIEWS: &str = "bulk_patient_views";
+const KEY_OFF_HOURS_ACCESS: &str = "off_hours_access";
+const KEY_FILE_DOWNLOADS: &str = "file_downloads";
+
+pub struct SecurityConfigDao {
+    connection: DbConnection,
+}
+
+impl SecurityConfigDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 未保存或无法解析的规则使用默认值
+    pub fn load(&self) -> Result<SecurityConfig, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT key, value FROM security_config")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut config = SecurityConfig::default();
+        for row in rows {
+            let (key, value) = row?;
+            let parsed = match key.as_str() {
+                KEY_BULK_PATIENT_VIEWS => serde_json::from_str(&value).map(|rule| config.bulk_patient_views = rule),
+                KEY_OFF_HOURS_ACCESS => serde_json::from_str(&value).map(|rule| config.off_hours_access = rule),
+                KEY_FILE_DOWNLOADS => serde_json::from_str(&value).map(|rule| config.file_downloads = rule),
+                _ => Ok(()),
+            };
+            if let Err(e) = parsed {
+                tracing::warn!("Ignoring invalid security config {}: {}", key, e);
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn save(&self, config: &SecurityConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let now = Utc::now();
+
+        let entries = [
+            (KEY_BULK_PATIENT_VIEWS, serde_json::to_string(&config.bulk_patient_views)?),
+            (KEY_OFF_HOURS_ACCESS, serde_json::to_string(&config.off_hours_access)?),
+            (KEY_FILE_DOWNLOADS, serde_json::to_string(&config.file_downloads)?),
+        ];
+        for (key, value) in entries {
+            tx.execute(
+                "INSERT INTO security_config (key, value, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+                params![key, value, now],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Default for SecurityConfigDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
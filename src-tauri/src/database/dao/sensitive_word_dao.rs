@@ -0,0 +1,84 @@
+// 敏感词数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::{SensitiveWord, SensitiveWordCategory};
+use chrono::Utc;
+use rusqlite::{params, Result, Row};
+use uuid::Uuid;
+
+pub struct SensitiveWordDao {
+    connection: DbConnection,
+}
+
+impl SensitiveWordDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn find_all(&self, category: Option<SensitiveWordCategory>) -> Result<Vec<SensitiveWord>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, word, category, created_by, created_at FROM sensitive_words
+             WHERE ?1 IS NULL OR category = ?1
+             ORDER BY category, word"
+        )?;
+
+        let word_iter = stmt.query_map(params![category.map(|c| c.as_str())], map_word)?;
+
+        let mut words = Vec::new();
+        for word in word_iter {
+            words.push(word?);
+        }
+
+        Ok(words)
+    }
+
+    // 同一个词重复添加时更新类别
+    pub fn upsert(&self, word: &str, category: SensitiveWordCategory, created_by: Option<&str>) -> Result<SensitiveWord, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO sensitive_words (id, word, category, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(word) DO UPDATE SET category = excluded.category",
+            params![Uuid::new_v4().to_string(), word, category.as_str(), created_by, Utc::now()],
+        )?;
+
+        let saved = conn.query_row(
+            "SELECT id, word, category, created_by, created_at FROM sensitive_words WHERE word = ?1",
+            params![word],
+            map_word,
+        )?;
+
+        Ok(saved)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM sensitive_words WHERE id = ?1", params![id])?;
+        Ok(deleted > 0)
+    }
+}
+
+fn map_word(row: &Row) -> Result<SensitiveWord> {
+    let category: String = row.get(2)?;
+
+    Ok(SensitiveWord {
+        id: row.get(0)?,
+        word: row.get(1)?,
+        category: SensitiveWordCategory::parse(&category).unwrap_or(SensitiveWordCategory::Forbidden),
+        created_by: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+impl Default for SensitiveWordDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,59 @@
+// 短信验证码请求记录数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use rusqlite::{params, Result};
+use chrono::{DateTime, Utc};
+
+pub struct SmsRequestDao {
+    connection: DbConnection,
+}
+
+impl SmsRequestDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn record(&self, phone_hash: &str, requested_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "INSERT INTO sms_code_requests (phone_hash, requested_at) VALUES (?1, ?2)",
+            params![phone_hash, requested_at],
+        )?;
+
+        Ok(())
+    }
+
+    // 指定时间之后的请求时间，按时间正序
+    pub fn find_since(&self, phone_hash: &str, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT requested_at FROM sms_code_requests
+             WHERE phone_hash = ?1 AND requested_at > ?2 ORDER BY requested_at ASC"
+        )?;
+
+        let times = stmt
+            .query_map(params![phone_hash, since], |row| row.get(0))?
+            .collect::<Result<Vec<DateTime<Utc>>>>()?;
+
+        Ok(times)
+    }
+
+    // 删除已超出限流窗口的记录
+    pub fn delete_before(&self, before: DateTime<Utc>) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM sms_code_requests WHERE requested_at <= ?1", params![before])?;
+        Ok(deleted)
+    }
+}
+
+impl Default for SmsRequestDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
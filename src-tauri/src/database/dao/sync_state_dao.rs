@@ -0,0 +1,57 @@
+// 同步水位线数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use chrono::{DateTime, Utc};
+
+pub struct SyncStateDao {
+    connection: DbConnection,
+}
+
+impl SyncStateDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn get_watermark(&self, entity_type: &str) -> Result<Option<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        let last_sync = conn
+            .query_row(
+                "SELECT last_sync FROM sync_state WHERE entity_type = ?1",
+                params![entity_type],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(last_sync)
+    }
+
+    pub fn set_watermark(&self, entity_type: &str, last_sync: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        Self::set_watermark_in(&conn, entity_type, last_sync)
+    }
+
+    // 在调用方的事务内写入，保证水位线与同步数据一起提交
+    pub fn set_watermark_in(conn: &Connection, entity_type: &str, last_sync: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        conn.execute(
+            "INSERT INTO sync_state (entity_type, last_sync, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(entity_type) DO UPDATE SET last_sync = excluded.last_sync, updated_at = excluded.updated_at",
+            params![entity_type, last_sync, Utc::now()],
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Default for SyncStateDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,254 @@
+// 患者时间线数据访问层：问诊、病历、处方和文件消息合并为一条按时间倒序的事件流
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::PageResult;
+use crate::database::query_optimizer::get_query_optimizer;
+use crate::models::{TimelineEvent, TimelineEventType};
+use crate::utils::SqlTimestamp;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Result};
+
+// 每类事件对应一个子查询，结果列依次为 event_type, timestamp, title, summary, ref_id，第一个参数为患者 ID
+struct TimelineSource {
+    event_type: TimelineEventType,
+    sql: &'static str,
+    // 数据范围限定到医生时追加的条件，参数为医生 ID；病历与患者详情一致，不按医生过滤
+    doctor_filter: Option<&'static str>,
+}
+
+const TIMELINE_SOURCES: [TimelineSource; 5] = [
+    TimelineSource {
+        event_type: TimelineEventType::ConsultationStarted,
+        sql: "SELECT 'consultation_started' AS event_type, c.created_at AS timestamp, COALESCE(c.title, '问诊') AS title,
+                     c.description AS summary, c.id AS ref_id
+              FROM consultations c WHERE c.patient_id = ?",
+        doctor_filter: Some(" AND c.doctor_id = ?"),
+    },
+    TimelineSource {
+        event_type: TimelineEventType::ConsultationCompleted,
+        sql: "SELECT 'consultation_completed' AS event_type, COALESCE(c.completed_at, c.updated_at) AS timestamp,
+                     COALESCE(c.title, '问诊') AS title, c.diagnosis AS summary, c.id AS ref_id
+              FROM consultations c WHERE c.patient_id = ? AND c.status = 'completed'",
+        doctor_filter: Some(" AND c.doctor_id = ?"),
+    },
+    TimelineSource {
+        event_type: TimelineEventType::MedicalRecordCreated,
+        sql: "SELECT 'medical_record_created' AS event_type, r.created_at AS timestamp, r.title AS title,
+                     substr(r.content, 1, 100) AS summary, r.id AS ref_id
+              FROM medical_records r WHERE r.patient_id = ? AND r.deleted_at IS NULL",
+        doctor_filter: None,
+    },
+    TimelineSource {
+        event_type: TimelineEventType::PrescriptionIssued,
+        sql: "SELECT 'prescription_issued' AS event_type, p.issued_at AS timestamp, '开具处方' AS title,
+                     CASE WHEN json_valid(p.items) THEN '共 ' || json_array_length(p.items) || ' 种药品' END AS summary,
+                     p.id AS ref_id
+              FROM prescriptions p JOIN consultations c ON c.id = p.consultation_id
+              WHERE c.patient_id = ? AND p.issued_at IS NOT NULL",
+        doctor_filter: Some(" AND c.doctor_id = ?"),
+    },
+    TimelineSource {
+        event_type: TimelineEventType::FileUploaded,
+        sql: "SELECT 'file_uploaded' AS event_type, m.timestamp AS timestamp,
+                     CASE m.message_type WHEN 'image' THEN '上传图片' ELSE '上传文件' END AS title,
+                     COALESCE(m.content, m.file_path) AS summary, m.id AS ref_id
+              FROM messages m JOIN consultations c ON c.id = m.consultation_id
+              WHERE c.patient_id = ? AND m.message_type IN ('image', 'file') AND m.deleted_at IS NULL",
+        doctor_filter: Some(" AND c.doctor_id = ?"),
+    },
+];
+
+pub struct TimelineDao {
+    connection: DbConnection,
+}
+
+impl TimelineDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 排序和分页都在 SQL 中完成，只取当前页的事件；同一时间的事件按类型和 ID 排序，翻页结果稳定
+    pub fn find_by_patient(
+        &self,
+        patient_id: &str,
+        doctor_id: Option<&str>,
+        event_types: &[TimelineEventType],
+        page: i32,
+        page_size: i32,
+    ) -> Result<PageResult<TimelineEvent>, Box<dyn std::error::Error>> {
+        let mut branches = Vec::new();
+        let mut params: Vec<Value> = Vec::new();
+        // 未选中的事件类型不参与查询
+        for source in TIMELINE_SOURCES.iter().filter(|s| event_types.contains(&s.event_type)) {
+            let mut branch = source.sql.to_string();
+            params.push(Value::Text(patient_id.to_string()));
+            if let (Some(filter), Some(doctor_id)) = (source.doctor_filter, doctor_id) {
+                branch.push_str(filter);
+                params.push(Value::Text(doctor_id.to_string()));
+            }
+            branches.push(branch);
+        }
+
+        if branches.is_empty() {
+            return Ok(PageResult::new(Vec::new(), 0, page, page_size));
+        }
+
+        let union = branches.join(" UNION ALL ");
+        let conn = self.connection.lock().unwrap();
+        let offset = (page - 1) * page_size;
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM ({})", union),
+            params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+
+        // 各表的时间列格式不完全一致（消息为 RFC3339 UTC），按 julianday 换算后排序
+        let sql = format!(
+            "SELECT * FROM ({}) ORDER BY julianday(timestamp) DESC, event_type, ref_id LIMIT ? OFFSET ?",
+            union
+        );
+        params.push(Value::Integer(page_size as i64));
+        params.push(Value::Integer(offset as i64));
+
+        let events = get_query_optimizer().execute_sql(&conn, "patient_timeline", &sql, || {
+            let mut stmt = conn.prepare(&sql)?;
+            let event_iter = stmt.query_map(params_from_iter(params.iter()), |row| {
+                Ok(TimelineEvent {
+                    event_type: row.get(0)?,
+                    timestamp: row.get::<_, SqlTimestamp>(1)?.0,
+                    title: row.get(2)?,
+                    summary: row.get(3)?,
+                    ref_id: row.get(4)?,
+                })
+            })?;
+            event_iter.collect::<Result<Vec<TimelineEvent>>>()
+        })?;
+
+        Ok(PageResult::new(events, total, page, page_size))
+    }
+}
+
+impl Default for TimelineDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    // 患者 p1 在两位医生处就诊，事件时间交错分布在各表中
+    fn create_test_dao() -> TimelineDao {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三'), ('p2', '李四');
+             INSERT INTO consultations (id, patient_id, doctor_id, status, title, diagnosis, created_at, updated_at, completed_at) VALUES
+                 ('c1', 'p1', 'd1', 'completed', '复诊', '高血压', '2024-03-01 09:00:00', '2024-03-01 10:00:00', '2024-03-01 10:00:00'),
+                 ('c2', 'p1', 'd2', 'active', NULL, NULL, '2024-03-05 09:00:00', '2024-03-05 09:00:00', NULL),
+                 ('c3', 'p2', 'd1', 'active', NULL, NULL, '2024-03-06 09:00:00', '2024-03-06 09:00:00', NULL);
+             INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title, content, created_at) VALUES
+                 ('r1', 'p1', 'd1', 'c1', 'diagnosis', '诊断记录', '血压偏高', '2024-03-01 09:40:00');
+             INSERT INTO prescriptions (id, consultation_id, doctor_id, items, status, issued_at) VALUES
+                 ('rx1', 'c1', 'd1', '[{\"name\":\"氨氯地平\"}]', 'issued', '2024-03-01 09:50:00'),
+                 ('rx2', 'c1', 'd1', '[]', 'draft', NULL);
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content, file_path, timestamp) VALUES
+                 ('m1', 'c1', 'patient', 'image', NULL, '/files/a.png', '2024-03-01 09:20:00'),
+                 ('m2', 'c1', 'patient', 'text', '您好', NULL, '2024-03-01 09:10:00'),
+                 ('m3', 'c2', 'patient', 'file', '化验单.pdf', '/files/b.pdf', '2024-03-05 09:30:00'),
+                 ('m4', 'c3', 'patient', 'file', '其他患者.pdf', '/files/c.pdf', '2024-03-06 09:30:00');",
+        )
+        .unwrap();
+        TimelineDao::with_connection(Arc::new(Mutex::new(conn)))
+    }
+
+    fn ref_ids(page: &PageResult<TimelineEvent>) -> Vec<&str> {
+        page.items.iter().map(|e| e.ref_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_events_from_all_tables_in_descending_order() {
+        let dao = create_test_dao();
+        let page = dao.find_by_patient("p1", None, &TimelineEventType::ALL, 1, 20).unwrap();
+
+        assert_eq!(page.total, 7);
+        assert_eq!(ref_ids(&page), vec!["m3", "c2", "c1", "rx1", "r1", "m1", "c1"]);
+        let types: Vec<TimelineEventType> = page.items.iter().map(|e| e.event_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TimelineEventType::FileUploaded,
+                TimelineEventType::ConsultationStarted,
+                TimelineEventType::ConsultationCompleted,
+                TimelineEventType::PrescriptionIssued,
+                TimelineEventType::MedicalRecordCreated,
+                TimelineEventType::FileUploaded,
+                TimelineEventType::ConsultationStarted,
+            ]
+        );
+        assert_eq!(page.items[0].summary.as_deref(), Some("化验单.pdf"));
+        assert_eq!(page.items[1].title, "问诊");
+        assert_eq!(page.items[2].summary.as_deref(), Some("高血压"));
+        assert_eq!(page.items[3].summary.as_deref(), Some("共 1 种药品"));
+        assert_eq!(page.items[5].title, "上传图片");
+    }
+
+    #[test]
+    fn test_pagination_boundaries() {
+        let dao = create_test_dao();
+
+        let first = dao.find_by_patient("p1", None, &TimelineEventType::ALL, 1, 3).unwrap();
+        let second = dao.find_by_patient("p1", None, &TimelineEventType::ALL, 2, 3).unwrap();
+        let last = dao.find_by_patient("p1", None, &TimelineEventType::ALL, 3, 3).unwrap();
+        let beyond = dao.find_by_patient("p1", None, &TimelineEventType::ALL, 4, 3).unwrap();
+
+        assert_eq!(ref_ids(&first), vec!["m3", "c2", "c1"]);
+        assert_eq!(ref_ids(&second), vec!["rx1", "r1", "m1"]);
+        assert_eq!(ref_ids(&last), vec!["c1"]);
+        assert!(beyond.items.is_empty());
+        assert_eq!(first.total_pages, 3);
+        assert_eq!(beyond.total, 7);
+    }
+
+    #[test]
+    fn test_filter_by_event_type() {
+        let dao = create_test_dao();
+
+        let page = dao
+            .find_by_patient(
+                "p1",
+                None,
+                &[TimelineEventType::FileUploaded, TimelineEventType::MedicalRecordCreated],
+                1,
+                20,
+            )
+            .unwrap();
+        assert_eq!(ref_ids(&page), vec!["m3", "r1", "m1"]);
+        assert_eq!(page.total, 3);
+
+        let none = dao.find_by_patient("p1", None, &[], 1, 20).unwrap();
+        assert!(none.items.is_empty());
+        assert_eq!(none.total, 0);
+    }
+
+    #[test]
+    fn test_doctor_scope_limits_consultation_events() {
+        let dao = create_test_dao();
+
+        let page = dao.find_by_patient("p1", Some("d1"), &TimelineEventType::ALL, 1, 20).unwrap();
+        // c2 由其他医生接诊，其问诊和文件事件不可见
+        assert_eq!(ref_ids(&page), vec!["c1", "rx1", "r1", "m1", "c1"]);
+    }
+}
@@ -3,7 +3,7 @@
 use crate::database::connection::{get_database, DbConnection};
 use crate::database::dao::{BaseDao, QueryBuilder};
 use crate::models::User;
-use rusqlite::{params, Result};
+use rusqlite::{params, OptionalExtension, Result};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -11,6 +11,19 @@ pub struct UserDao {
     connection: DbConnection,
 }
 
+/// 已保存的登录会话，token 仍为密文
+#[derive(Debug, Clone)]
+pub struct StoredSession {
+    pub user_id: String,
+    pub username: String,
+    pub encrypted_token: String,
+    pub session_expires: Option<DateTime<Utc>>,
+    // 登录时认证服务返回的用户信息（JSON）
+    pub profile: Option<String>,
+    // 最近一次由服务器确认会话有效的时间
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
 impl UserDao {
     pub fn new() -> Self {
         Self {
@@ -18,6 +31,10 @@ impl UserDao {
         }
     }
 
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
     pub fn find_by_username(&self, username: &str) -> Result<Option<User>, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
@@ -68,6 +85,53 @@ impl UserDao {
         Ok(())
     }
 
+    // 登录成功后写入或更新本地用户记录，之后由 update_token 保存会话
+    pub fn save_session_user(&self, user_id: &str, username: &str, profile: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let now = Utc::now();
+
+        conn.execute(
+            "INSERT INTO users (id, username, profile, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT(id) DO UPDATE SET username = excluded.username, profile = excluded.profile, updated_at = excluded.updated_at",
+            params![user_id, username, profile, now],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn mark_session_verified(&self, user_id: &str, verified_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "UPDATE users SET session_verified_at = ?1 WHERE id = ?2 AND encrypted_token IS NOT NULL",
+            params![verified_at, user_id],
+        )?;
+        Ok(())
+    }
+
+    // 最近一次登录且仍保存着 token 的用户，过期与否由调用方判断
+    pub fn find_latest_session(&self) -> Result<Option<StoredSession>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+        let session = conn
+            .query_row(
+                "SELECT id, username, encrypted_token, session_expires, profile, session_verified_at
+                 FROM users WHERE encrypted_token IS NOT NULL
+                 ORDER BY last_login DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok(StoredSession {
+                        user_id: row.get(0)?,
+                        username: row.get(1)?,
+                        encrypted_token: row.get(2)?,
+                        session_expires: row.get(3)?,
+                        profile: row.get(4)?,
+                        verified_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(session)
+    }
+
     pub fn is_session_valid(&self, user_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
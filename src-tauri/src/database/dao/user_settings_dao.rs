@@ -0,0 +1,57 @@
+// 用户个人设置数据访问层
+
+use crate::database::connection::{get_database, DbConnection};
+use rusqlite::{params, OptionalExtension, Result};
+use chrono::Utc;
+
+pub struct UserSettingsDao {
+    connection: DbConnection,
+}
+
+impl UserSettingsDao {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn get(&self, user_id: &str, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        let value = conn
+            .query_row(
+                "SELECT value FROM user_settings WHERE user_id = ?1 AND key = ?2",
+                params![user_id, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(value)
+    }
+
+    pub fn set(&self, user_id: &str, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.connection.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO user_settings (user_id, key, value, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(user_id, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+            params![user_id, key, value, Utc::now()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_bool(&self, user_id: &str, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.get(user_id, key)?.as_deref() == Some("true"))
+    }
+}
+
+impl Default for UserSettingsDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,318 @@
+// 整库加密（SQLCipher）：部分部署要求本地数据库整体加密，而不仅是敏感字段
+//
+// 是否启用由应用数据目录下的 database.json 决定（数据库本身需要先解密才能读取配置），
+// 密钥由 CryptoService 主密钥派生。需要以 `sqlcipher` feature 编译，默认构建不依赖 SQLCipher。
+
+use crate::utils::{AppError, CryptoService};
+use rusqlite::{params, Connection, ErrorCode, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const DATABASE_CONFIG_FILE: &str = "database.json";
+// 派生整库密钥时使用的用途标识，修改会导致已加密的数据库无法打开
+const SQLCIPHER_KEY_PURPOSE: &str = "telemedicine sqlcipher key v1";
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+const ENCRYPTING_SUFFIX: &str = "encrypting";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseEncryption {
+    #[default]
+    Plaintext,
+    Sqlcipher,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    #[serde(default)]
+    pub encryption: DatabaseEncryption,
+}
+
+pub fn load_database_config(path: &Path) -> DatabaseConfig {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_database_config(path: &Path, config: &DatabaseConfig) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("创建数据目录失败: {}", e))?;
+    }
+
+    let content = serde_json::to_string_pretty(config).map_err(|e| format!("序列化数据库配置失败: {}", e))?;
+    std::fs::write(path, content).map_err(|e| format!("保存数据库配置失败: {}", e))
+}
+
+/// SQLCipher 原始密钥（十六进制），Debug 输出时隐藏
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(String);
+
+impl DatabaseKey {
+    pub fn derive(crypto: &CryptoService) -> Self {
+        Self::from_bytes(&crypto.derive_key(SQLCIPHER_KEY_PURPOSE))
+    }
+
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        Self(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    // x'...' 形式表示原始密钥，SQLCipher 不再做 PBKDF2 派生
+    fn sql_literal(&self) -> String {
+        format!("\"x'{}'\"", self.0)
+    }
+}
+
+impl std::fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DatabaseKey(**)")
+    }
+}
+
+// 按配置解析出的密钥，由钥匙串中的主密钥派生；未编译 SQLCipher 时拒绝打开加密模式，避免把明文库当成已加密
+pub fn resolve_key(config: &DatabaseConfig, crypto: &CryptoService) -> Result<Option<DatabaseKey>, AppError> {
+    match config.encryption {
+        DatabaseEncryption::Plaintext => Ok(None),
+        DatabaseEncryption::Sqlcipher if cfg!(feature = "sqlcipher") => Ok(Some(DatabaseKey::derive(crypto))),
+        DatabaseEncryption::Sqlcipher => Err(AppError::database_error(
+            "配置要求加密数据库，但当前版本未包含 SQLCipher 支持",
+        )),
+    }
+}
+
+// 新连接的第一条语句必须是设置密钥
+pub fn apply_key(conn: &Connection, key: &DatabaseKey) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!("PRAGMA key = {};", key.sql_literal()))
+}
+
+/// 读取 sqlite_master 确认数据库可读；加密模式下“file is not a database”即密钥错误
+pub fn verify_readable(conn: &Connection, encrypted: bool) -> Result<(), AppError> {
+    match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(()),
+        Err(e) if encrypted && e.sqlite_error_code() == Some(ErrorCode::NotADatabase) => Err(
+            AppError::database_key_invalid("数据库密钥错误，无法解密本地数据库，请恢复原密钥或从备份还原"),
+        ),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// 文件头为明文 SQLite 标识；不存在或为空的文件不需要迁移
+pub fn is_plaintext_database(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| &header == SQLITE_HEADER)
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionStep {
+    BackupOriginal,
+    Export,
+    Verify,
+    Replace,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct EncryptionProgress {
+    pub step: EncryptionStep,
+    pub percent: u8,
+}
+
+impl EncryptionStep {
+    fn progress(self) -> EncryptionProgress {
+        let percent = match self {
+            EncryptionStep::BackupOriginal => 0,
+            EncryptionStep::Export => 30,
+            EncryptionStep::Verify => 70,
+            EncryptionStep::Replace => 90,
+            EncryptionStep::Completed => 100,
+        };
+        EncryptionProgress { step: self, percent }
+    }
+}
+
+/// 把现有明文数据库迁移为加密数据库：先备份原文件，sqlcipher_export 到临时文件，
+/// 核对各表行数后替换原文件。任一步失败时原文件保持不变。返回原文件备份路径
+pub fn encrypt_plaintext_database(
+    db_path: &Path,
+    key: &DatabaseKey,
+    backup_dir: &Path,
+    mut on_progress: impl FnMut(EncryptionProgress),
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    on_progress(EncryptionStep::BackupOriginal.progress());
+    let source = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    source.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+
+    std::fs::create_dir_all(backup_dir)?;
+    let backup_path = backup_dir.join(format!(
+        "telemedicine_plaintext_{}.db",
+        chrono::Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    let mut backup_conn = Connection::open(&backup_path)?;
+    rusqlite::backup::Backup::new(&source, &mut backup_conn)?
+        .run_to_completion(100, std::time::Duration::from_millis(10), None)?;
+    drop(backup_conn);
+    tracing::info!("Plaintext database backed up to {:?}", backup_path);
+
+    on_progress(EncryptionStep::Export.progress());
+    let encrypted_path = sibling_path(db_path, ENCRYPTING_SUFFIX);
+    remove_if_exists(&encrypted_path)?;
+    source.execute(
+        &format!("ATTACH DATABASE ?1 AS encrypted KEY {}", key.sql_literal()),
+        params![encrypted_path.to_string_lossy()],
+    )?;
+    let exported = source
+        .query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+        .and_then(|_| source.execute_batch("DETACH DATABASE encrypted;"));
+    if let Err(e) = exported {
+        let _ = remove_if_exists(&encrypted_path);
+        return Err(e.into());
+    }
+
+    on_progress(EncryptionStep::Verify.progress());
+    let expected = table_counts(&source)?;
+    drop(source);
+    let verified = open_encrypted(&encrypted_path, key).and_then(|conn| table_counts(&conn).map_err(Into::into));
+    match verified {
+        Ok(actual) if actual == expected => {}
+        Ok(_) => {
+            remove_if_exists(&encrypted_path)?;
+            return Err("加密后的数据库与原数据库行数不一致".into());
+        }
+        Err(e) => {
+            remove_if_exists(&encrypted_path)?;
+            return Err(e);
+        }
+    }
+
+    on_progress(EncryptionStep::Replace.progress());
+    for suffix in ["wal", "shm"] {
+        remove_if_exists(&sibling_path(db_path, suffix))?;
+    }
+    std::fs::rename(&encrypted_path, db_path)?;
+
+    on_progress(EncryptionStep::Completed.progress());
+    tracing::info!("Database encrypted in place: {:?}", db_path);
+    Ok(backup_path)
+}
+
+fn open_encrypted(path: &Path, key: &DatabaseKey) -> Result<Connection, Box<dyn std::error::Error>> {
+    let conn = Connection::open(path)?;
+    apply_key(&conn, key)?;
+    verify_readable(&conn, true)?;
+    Ok(conn)
+}
+
+fn table_counts(conn: &Connection) -> rusqlite::Result<HashMap<String, i64>> {
+    let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut counts = HashMap::new();
+    for table in tables {
+        let count = conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))?;
+        counts.insert(table, count);
+    }
+    Ok(counts)
+}
+
+// telemedicine.db -> telemedicine.db-wal 等同目录的附属文件
+fn sibling_path(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{CODE_DB_ERROR, CODE_DB_KEY_INVALID};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config_defaults_to_plaintext() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DATABASE_CONFIG_FILE);
+        assert_eq!(load_database_config(&path).encryption, DatabaseEncryption::Plaintext);
+
+        let config = DatabaseConfig { encryption: DatabaseEncryption::Sqlcipher };
+        save_database_config(&path, &config).unwrap();
+        assert_eq!(load_database_config(&path), config);
+        assert!(std::fs::read_to_string(&path).unwrap().contains("\"sqlcipher\""));
+
+        // 未编译 SQLCipher 时不能静默以明文打开
+        assert_eq!(resolve_key(&config, &CryptoService::new()).is_ok(), cfg!(feature = "sqlcipher"));
+        assert!(format!("{:?}", DatabaseKey::derive(&CryptoService::new())).contains("**"));
+    }
+
+    #[test]
+    fn test_unreadable_file_distinguishes_wrong_key() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("garbage.db");
+        std::fs::write(&path, vec![0x5Au8; 4096]).unwrap();
+        assert!(!is_plaintext_database(&path));
+        assert!(!is_plaintext_database(&dir.path().join("missing.db")));
+
+        let conn = Connection::open(&path).unwrap();
+        let error = verify_readable(&conn, true).unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(CODE_DB_KEY_INVALID));
+        assert!(error.message.contains("密钥"));
+
+        let error = verify_readable(&conn, false).unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(CODE_DB_ERROR));
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypt_existing_plaintext_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("telemedicine.db");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 CREATE TABLE patients (id TEXT PRIMARY KEY, name TEXT);
+                 INSERT INTO patients VALUES ('p1', '张三'), ('p2', '李四');",
+            )
+            .unwrap();
+        }
+        assert!(is_plaintext_database(&db_path));
+
+        let key = DatabaseKey::from_bytes(&[7u8; 32]);
+        let mut steps = Vec::new();
+        let backup = encrypt_plaintext_database(&db_path, &key, &dir.path().join("backups"), |p| steps.push(p)).unwrap();
+
+        assert_eq!(steps.first().unwrap().step, EncryptionStep::BackupOriginal);
+        assert_eq!(steps.last().unwrap().percent, 100);
+        assert!(steps.windows(2).all(|pair| pair[0].percent < pair[1].percent));
+        assert!(is_plaintext_database(&backup));
+        assert!(!is_plaintext_database(&db_path));
+
+        let conn = open_encrypted(&db_path, &key).unwrap();
+        let name: String = conn
+            .query_row("SELECT name FROM patients WHERE id = 'p2'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "李四");
+
+        // 错误的密钥给出独立的错误码，而不是通用的 “file is not a database”
+        let conn = Connection::open(&db_path).unwrap();
+        apply_key(&conn, &DatabaseKey::from_bytes(&[8u8; 32])).unwrap();
+        let error = verify_readable(&conn, true).unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(CODE_DB_KEY_INVALID));
+    }
+}
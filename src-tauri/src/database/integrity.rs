@@ -0,0 +1,252 @@
+// 数据完整性检查：扫描枚举列中无法被模型解析的取值，修复崩溃后残留的孤立记录
+
+use crate::models::{
+    ConsultationPriority, ConsultationStatus, EnumColumnViolation, Gender, MessageType, OrphanKind, PrescriptionStatus, ReadStatus, RepairAction,
+    SenderType, SyncStatus,
+};
+use chrono::Utc;
+use rusqlite::{params, params_from_iter, Connection, Result};
+
+// 仍有有效患者的问诊；患者缺失的问诊会被隔离，引用它们的记录同样视为孤立
+const VALID_CONSULTATIONS: &str = "SELECT c.id FROM consultations c JOIN patients p ON p.id = c.patient_id";
+
+struct OrphanQuery {
+    table: &'static str,
+    column: &'static str,
+    condition: String,
+    // 外键列允许为空，可以置空引用而不删除记录
+    nullable: bool,
+}
+
+fn orphan_query(kind: OrphanKind) -> Option<OrphanQuery> {
+    match kind {
+        OrphanKind::MessageMissingConsultation => Some(OrphanQuery {
+            table: "messages",
+            column: "consultation_id",
+            condition: format!("consultation_id NOT IN ({})", VALID_CONSULTATIONS),
+            nullable: false,
+        }),
+        OrphanKind::ConsultationMissingPatient => Some(OrphanQuery {
+            table: "consultations",
+            column: "patient_id",
+            condition: "patient_id NOT IN (SELECT id FROM patients)".to_string(),
+            nullable: false,
+        }),
+        OrphanKind::MedicalRecordMissingConsultation => Some(OrphanQuery {
+            table: "medical_records",
+            column: "consultation_id",
+            condition: format!("consultation_id IS NOT NULL AND consultation_id NOT IN ({})", VALID_CONSULTATIONS),
+            nullable: true,
+        }),
+        // 需要检查本地文件，由 FileCacheDao::delete_missing_files_in 处理
+        OrphanKind::FileCacheMissingFile => None,
+    }
+}
+
+// 按指定方式修复一类孤立记录，返回处理的行数；隔离时原始行以 JSON 写入 <表名>_orphaned
+pub fn repair_orphans_in(
+    conn: &Connection,
+    kind: OrphanKind,
+    action: RepairAction,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let Some(query) = orphan_query(kind) else {
+        return Err(format!("{} 不能在数据库内修复", kind.as_str()).into());
+    };
+
+    let repaired = match action {
+        RepairAction::Delete => conn.execute(&format!("DELETE FROM {} WHERE {}", query.table, query.condition), [])?,
+        RepairAction::Nullify => {
+            if !query.nullable {
+                return Err(format!("{}.{} 不允许为空", query.table, query.column).into());
+            }
+            conn.execute(
+                &format!("UPDATE {} SET {} = NULL WHERE {}", query.table, query.column, query.condition),
+                [],
+            )?
+        }
+        RepairAction::Quarantine => {
+            let row_json = row_json_expression(conn, query.table)?;
+            conn.execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {table}_orphaned (id, row_data, reason, quarantined_at)
+                     SELECT id, {row_json}, ?1, ?2 FROM {table} WHERE {condition}",
+                    table = query.table,
+                    condition = query.condition
+                ),
+                params![kind.as_str(), Utc::now()],
+            )?;
+            conn.execute(&format!("DELETE FROM {} WHERE {}", query.table, query.condition), [])?
+        }
+    };
+    Ok(repaired)
+}
+
+// json_object('id', id, ...)，列名取自当前表结构
+fn row_json_expression(conn: &Connection, table: &str) -> Result<String> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?;
+    let pairs: Vec<String> = columns
+        .iter()
+        .map(|column| format!("'{column}', \"{column}\""))
+        .collect();
+    Ok(format!("json_object({})", pairs.join(", ")))
+}
+
+// 各枚举列及其合法取值，取值列表直接来自模型定义
+fn enum_columns() -> Vec<(&'static str, &'static str, Vec<&'static str>)> {
+    vec![
+        ("messages", "sender_type", SenderType::ALL.iter().map(|v| v.as_str()).collect()),
+        ("messages", "message_type", MessageType::ALL.iter().map(|v| v.as_str()).collect()),
+        ("messages", "sync_status", SyncStatus::ALL.iter().map(|v| v.as_str()).collect()),
+        ("messages", "read_status", ReadStatus::ALL.iter().map(|v| v.as_str()).collect()),
+        ("patients", "gender", Gender::ALL.iter().map(|v| v.as_str()).collect()),
+        ("consultations", "status", ConsultationStatus::ALL.iter().map(|v| v.as_str()).collect()),
+        ("consultations", "priority", ConsultationPriority::ALL.iter().map(|v| v.as_str()).collect()),
+        ("prescriptions", "status", PrescriptionStatus::ALL.iter().map(|v| v.as_str()).collect()),
+    ]
+}
+
+// 返回每个越界取值及其出现次数；NULL 视为未填写，不计入
+pub fn validate_enum_columns(conn: &Connection) -> Result<Vec<EnumColumnViolation>> {
+    let mut violations = Vec::new();
+
+    for (table, column, allowed) in enum_columns() {
+        let placeholders = vec!["?"; allowed.len()].join(", ");
+        let sql = format!(
+            "SELECT CAST({column} AS TEXT), COUNT(*) FROM {table}
+             WHERE {column} IS NOT NULL AND {column} NOT IN ({placeholders})
+             GROUP BY {column} ORDER BY {column}"
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(allowed.iter()), |row| {
+            Ok(EnumColumnViolation {
+                table: table.to_string(),
+                column: column.to_string(),
+                value: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+        for row in rows {
+            violations.push(row?);
+        }
+    }
+
+    if !violations.is_empty() {
+        tracing::warn!("Found {} out-of-vocabulary enum values", violations.len());
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::InvalidEnumValue;
+    use rusqlite::types::{FromSql, FromSqlError, ToSql, ToSqlOutput, Value, ValueRef};
+
+    fn round_trip<T: FromSql + ToSql + PartialEq + std::fmt::Debug>(value: T, expected: &str) {
+        match value.to_sql().unwrap() {
+            ToSqlOutput::Borrowed(ValueRef::Text(text)) => assert_eq!(text, expected.as_bytes()),
+            ToSqlOutput::Owned(Value::Text(text)) => assert_eq!(text, expected),
+            other => panic!("unexpected sql output: {:?}", other),
+        }
+        assert_eq!(T::column_result(ValueRef::Text(expected.as_bytes())).unwrap(), value);
+    }
+
+    #[test]
+    fn test_enum_round_trips() {
+        for v in MessageType::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in SenderType::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in SyncStatus::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in ReadStatus::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in Gender::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in ConsultationStatus::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in ConsultationPriority::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+        for v in PrescriptionStatus::ALL {
+            let s = v.as_str();
+            round_trip(v, s);
+        }
+
+        // 与前端约定的序列化取值保持一致
+        assert_eq!(serde_json::to_value(MessageType::Template).unwrap(), "template");
+        assert_eq!(serde_json::to_value(Gender::Unknown).unwrap(), "unknown");
+    }
+
+    #[test]
+    fn test_unknown_values_rejected_with_description() {
+        let err = SyncStatus::column_result(ValueRef::Text(b"Synced")).unwrap_err();
+        match err {
+            FromSqlError::Other(inner) => {
+                let inner = inner.downcast_ref::<InvalidEnumValue>().unwrap();
+                assert_eq!(inner.value, "Synced");
+                assert!(inner.to_string().contains("同步状态"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(MessageType::column_result(ValueRef::Text(b"")).is_err());
+        assert!(Gender::column_result(ValueRef::Text(b"M")).is_err());
+        // 非文本类型仍按类型错误处理
+        assert!(matches!(
+            ReadStatus::column_result(ValueRef::Integer(1)),
+            Err(FromSqlError::InvalidType)
+        ));
+    }
+
+    #[test]
+    fn test_validate_enum_columns_reports_bad_values() {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        assert!(validate_enum_columns(&conn).unwrap().is_empty());
+
+        // 旧版本写入或手工修改的数据可能绕过 CHECK 约束
+        conn.execute_batch(
+            "PRAGMA ignore_check_constraints = ON;
+             INSERT INTO patients (id, name, gender) VALUES ('p1', '张三', 'M'), ('p2', '李四', NULL), ('p3', '王五', 'female');
+             INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type) VALUES ('c1', 'p1', 'd1', 'closed', 'text');
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content, read_status, sync_status) VALUES
+                 ('m1', 'c1', 'patient', 'text', 'a', 'seen', 'synced'),
+                 ('m2', 'c1', 'patient', 'text', 'b', 'seen', 'synced'),
+                 ('m3', 'c1', 'bot', 'text', 'c', 'read', 'synced');
+             PRAGMA ignore_check_constraints = OFF;",
+        )
+        .unwrap();
+
+        let violations = validate_enum_columns(&conn).unwrap();
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.table.as_str(), v.column.as_str(), v.value.as_str(), v.count))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("messages", "sender_type", "bot", 1),
+                ("messages", "read_status", "seen", 2),
+                ("patients", "gender", "M", 1),
+                ("consultations", "status", "closed", 1),
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 // 数据库迁移管理
 
+use crate::utils::{register_pinyin_key_function, register_timestamp_function};
 use rusqlite::{Connection, Result};
 use std::collections::HashMap;
 
@@ -26,6 +27,350 @@ impl MigrationManager {
             down_sql: "DROP TABLE IF EXISTS file_cache; DROP TABLE IF EXISTS medical_records; DROP TABLE IF EXISTS messages; DROP TABLE IF EXISTS consultations; DROP TABLE IF EXISTS patients; DROP TABLE IF EXISTS users; DROP TABLE IF EXISTS schema_migrations;".to_string(),
         });
 
+        // 问诊状态流转
+        migrations.insert(2, Migration {
+            version: 2,
+            description: "Consultation lifecycle timestamps".to_string(),
+            up_sql: include_str!("../../migrations/002_consultation_lifecycle.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_consultations_doctor_status; ALTER TABLE consultations DROP COLUMN cancel_reason; ALTER TABLE consultations DROP COLUMN completed_at; ALTER TABLE consultations DROP COLUMN accepted_at;".to_string(),
+        });
+
+        // 缓存文件固定
+        migrations.insert(3, Migration {
+            version: 3,
+            description: "Pin file cache entries referenced by medical records".to_string(),
+            up_sql: include_str!("../../migrations/003_file_cache_pinning.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_file_cache_pinned_accessed; ALTER TABLE file_cache DROP COLUMN pinned;".to_string(),
+        });
+
+        // 病历模板
+        migrations.insert(4, Migration {
+            version: 4,
+            description: "Medical record templates".to_string(),
+            up_sql: include_str!("../../migrations/004_record_templates.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS record_templates;".to_string(),
+        });
+
+        // 常用回复模板
+        migrations.insert(5, Migration {
+            version: 5,
+            description: "Message templates and template message references".to_string(),
+            up_sql: include_str!("../../migrations/005_message_templates.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_messages_template; ALTER TABLE messages DROP COLUMN template_id; DROP TABLE IF EXISTS message_templates;".to_string(),
+        });
+
+        // 用户个人设置
+        migrations.insert(6, Migration {
+            version: 6,
+            description: "Per-user settings".to_string(),
+            up_sql: include_str!("../../migrations/006_user_settings.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS user_settings;".to_string(),
+        });
+
+        // 增量同步水位线
+        migrations.insert(7, Migration {
+            version: 7,
+            description: "Sync watermarks".to_string(),
+            up_sql: include_str!("../../migrations/007_sync_state.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS sync_state;".to_string(),
+        });
+
+        // 可维护的敏感词表
+        migrations.insert(8, Migration {
+            version: 8,
+            description: "Sensitive word list".to_string(),
+            up_sql: include_str!("../../migrations/008_sensitive_words.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS sensitive_words;".to_string(),
+        });
+
+        // 患者敏感字段加密及 HMAC 查询索引
+        migrations.insert(9, Migration {
+            version: 9,
+            description: "Patient field encryption indexes".to_string(),
+            up_sql: include_str!("../../migrations/009_patient_field_encryption.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_patients_phone_hash; DROP INDEX IF EXISTS idx_patients_id_card_hash; ALTER TABLE patients DROP COLUMN id_card_hash; ALTER TABLE patients DROP COLUMN phone_hash;".to_string(),
+        });
+
+        // 异常访问检测规则配置
+        migrations.insert(10, Migration {
+            version: 10,
+            description: "Security anomaly detection config".to_string(),
+            up_sql: include_str!("../../migrations/010_security_config.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS security_config;".to_string(),
+        });
+
+        // 数据保留策略及维护记录
+        migrations.insert(11, Migration {
+            version: 11,
+            description: "Retention policy and maintenance runs".to_string(),
+            up_sql: include_str!("../../migrations/011_retention.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS maintenance_runs; DROP TABLE IF EXISTS retention_policy;".to_string(),
+        });
+
+        // 语音消息时长和波形
+        migrations.insert(12, Migration {
+            version: 12,
+            description: "Voice message duration and waveform".to_string(),
+            up_sql: include_str!("../../migrations/012_voice_metadata.sql").to_string(),
+            down_sql: "ALTER TABLE messages DROP COLUMN waveform; ALTER TABLE messages DROP COLUMN duration_ms;".to_string(),
+        });
+
+        // 图片缩略图
+        migrations.insert(13, Migration {
+            version: 13,
+            description: "File cache thumbnails".to_string(),
+            up_sql: include_str!("../../migrations/013_file_thumbnails.sql").to_string(),
+            down_sql: "ALTER TABLE file_cache DROP COLUMN thumbnail_path;".to_string(),
+        });
+
+        // 文件下载断点续传进度
+        migrations.insert(14, Migration {
+            version: 14,
+            description: "File cache download progress".to_string(),
+            up_sql: include_str!("../../migrations/014_file_download_progress.sql").to_string(),
+            down_sql: "ALTER TABLE file_cache DROP COLUMN bytes_downloaded;".to_string(),
+        });
+
+        // 按医生隔离本地数据，回填历史问诊的医生 ID
+        migrations.insert(15, Migration {
+            version: 15,
+            description: "Doctor data scope".to_string(),
+            up_sql: include_str!("../../migrations/015_doctor_scope.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_consultations_patient_doctor;".to_string(),
+        });
+
+        // 短信验证码请求限流记录
+        migrations.insert(16, Migration {
+            version: 16,
+            description: "SMS code rate limit".to_string(),
+            up_sql: include_str!("../../migrations/016_sms_rate_limit.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS sms_code_requests;".to_string(),
+        });
+
+        // 问诊转接记录，消息表允许系统消息
+        migrations.insert(17, Migration {
+            version: 17,
+            description: "Consultation transfers".to_string(),
+            up_sql: include_str!("../../migrations/017_consultation_transfers.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS consultation_transfers;".to_string(),
+        });
+
+        // 消息表允许系统事件消息
+        migrations.insert(18, Migration {
+            version: 18,
+            description: "System event messages".to_string(),
+            up_sql: include_str!("../../migrations/018_system_event_messages.sql").to_string(),
+            down_sql: "DELETE FROM messages WHERE message_type = 'event';".to_string(),
+        });
+
+        // 结构化处方
+        migrations.insert(19, Migration {
+            version: 19,
+            description: "Structured prescriptions".to_string(),
+            up_sql: include_str!("../../migrations/019_prescriptions.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS prescriptions;".to_string(),
+        });
+
+        // 消息草稿自动保存
+        migrations.insert(20, Migration {
+            version: 20,
+            description: "Message drafts".to_string(),
+            up_sql: include_str!("../../migrations/020_message_drafts.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS message_drafts;".to_string(),
+        });
+
+        // 患者和问诊的乐观锁版本号
+        migrations.insert(21, Migration {
+            version: 21,
+            description: "Row versions for patients and consultations".to_string(),
+            up_sql: include_str!("../../migrations/021_row_versions.sql").to_string(),
+            down_sql: "ALTER TABLE consultations DROP COLUMN version; ALTER TABLE patients DROP COLUMN version;".to_string(),
+        });
+
+        // 应用全局配置
+        migrations.insert(22, Migration {
+            version: 22,
+            description: "App settings".to_string(),
+            up_sql: include_str!("../../migrations/022_app_settings.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS app_settings;".to_string(),
+        });
+
+        // 问诊最后活动时间查询
+        migrations.insert(23, Migration {
+            version: 23,
+            description: "Consultation activity index".to_string(),
+            up_sql: include_str!("../../migrations/023_consultation_activity_index.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_messages_consultation_timestamp;".to_string(),
+        });
+
+        // 患者列表按最近就诊时间和姓名拼音排序
+        migrations.insert(24, Migration {
+            version: 24,
+            description: "Patient sort keys".to_string(),
+            up_sql: include_str!("../../migrations/024_patient_sort_keys.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_patients_name_pinyin; DROP INDEX IF EXISTS idx_patients_last_visit; ALTER TABLE patients DROP COLUMN name_pinyin; ALTER TABLE patients DROP COLUMN last_visit;".to_string(),
+        });
+
+        // 按已完成问诊和姓名回填排序字段
+        migrations.insert(25, Migration {
+            version: 25,
+            description: "Patient sort key backfill".to_string(),
+            up_sql: include_str!("../../migrations/025_patient_sort_key_backfill.sql").to_string(),
+            down_sql: "UPDATE patients SET last_visit = NULL, name_pinyin = NULL;".to_string(),
+        });
+
+        // 文档附件预览
+        migrations.insert(26, Migration {
+            version: 26,
+            description: "File previews".to_string(),
+            up_sql: include_str!("../../migrations/026_file_previews.sql").to_string(),
+            down_sql: "ALTER TABLE file_cache DROP COLUMN preview_text; ALTER TABLE file_cache DROP COLUMN preview_path;".to_string(),
+        });
+
+        // 消息发件箱
+        migrations.insert(27, Migration {
+            version: 27,
+            description: "Message outbox".to_string(),
+            up_sql: include_str!("../../migrations/027_message_outbox.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_outbox_created_at; DROP TABLE IF EXISTS outbox;".to_string(),
+        });
+
+        // 启动完整性修复
+        migrations.insert(28, Migration {
+            version: 28,
+            description: "Integrity repair".to_string(),
+            up_sql: include_str!("../../migrations/028_integrity_repair.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS consultations_orphaned; DROP TABLE IF EXISTS messages_orphaned; ALTER TABLE maintenance_runs DROP COLUMN summary; ALTER TABLE maintenance_runs DROP COLUMN kind;".to_string(),
+        });
+
+        // 消息往返延迟等运行指标
+        migrations.insert(29, Migration {
+            version: 29,
+            description: "Metrics".to_string(),
+            up_sql: include_str!("../../migrations/029_metrics.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS metrics;".to_string(),
+        });
+
+        // 按问诊和患者的附件配额
+        migrations.insert(30, Migration {
+            version: 30,
+            description: "Attachment quota".to_string(),
+            up_sql: include_str!("../../migrations/030_attachment_quota.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS attachment_usage; DROP INDEX IF EXISTS idx_messages_file_path; DROP INDEX IF EXISTS idx_file_cache_consultation; ALTER TABLE file_cache DROP COLUMN consultation_id;".to_string(),
+        });
+
+        // 消息和病历的软删除回收站
+        migrations.insert(31, Migration {
+            version: 31,
+            description: "Soft delete".to_string(),
+            up_sql: include_str!("../../migrations/031_soft_delete.sql").to_string(),
+            down_sql: "ALTER TABLE maintenance_runs DROP COLUMN purged_trash; DROP INDEX IF EXISTS idx_medical_records_deleted_at; DROP INDEX IF EXISTS idx_messages_deleted_at; ALTER TABLE medical_records DROP COLUMN deleted_at; ALTER TABLE messages DROP COLUMN deleted_at;".to_string(),
+        });
+
+        // 医生私人的问诊备注
+        migrations.insert(32, Migration {
+            version: 32,
+            description: "Consultation notes".to_string(),
+            up_sql: include_str!("../../migrations/032_consultation_notes.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS consultation_notes;".to_string(),
+        });
+
+        // 患者资料修改记录
+        migrations.insert(33, Migration {
+            version: 33,
+            description: "Patient revisions".to_string(),
+            up_sql: include_str!("../../migrations/033_patient_revisions.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS patient_revisions;".to_string(),
+        });
+
+        // 问诊问卷
+        migrations.insert(34, Migration {
+            version: 34,
+            description: "Intake forms".to_string(),
+            up_sql: include_str!("../../migrations/034_intake_forms.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS intake_forms;".to_string(),
+        });
+
+        // 图片压缩上传前的大小
+        migrations.insert(35, Migration {
+            version: 35,
+            description: "Upload compression".to_string(),
+            up_sql: include_str!("../../migrations/035_upload_compression.sql").to_string(),
+            down_sql: "ALTER TABLE file_cache DROP COLUMN original_size;".to_string(),
+        });
+
+        // 患者和问诊的变更记录，供窗口增量刷新列表
+        migrations.insert(36, Migration {
+            version: 36,
+            description: "Change log".to_string(),
+            up_sql: include_str!("../../migrations/036_change_log.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS change_log;".to_string(),
+        });
+
+        // 问诊候诊优先级和入队时间
+        migrations.insert(37, Migration {
+            version: 37,
+            description: "Consultation priority".to_string(),
+            up_sql: include_str!("../../migrations/037_consultation_priority.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_consultations_queue; ALTER TABLE consultations DROP COLUMN queued_at; ALTER TABLE consultations DROP COLUMN priority;".to_string(),
+        });
+
+        // 消息、审计日志和文件缓存的时间列统一为 RFC3339 UTC 格式，改写后不可回退
+        migrations.insert(38, Migration {
+            version: 38,
+            description: "Normalize timestamps".to_string(),
+            up_sql: include_str!("../../migrations/038_normalize_timestamps.sql").to_string(),
+            down_sql: "SELECT 1;".to_string(),
+        });
+
+        // 未发送消息等引用的缓存文件固定记录
+        migrations.insert(39, Migration {
+            version: 39,
+            description: "File cache pins".to_string(),
+            up_sql: include_str!("../../migrations/039_file_cache_pins.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS pinned_by_ref;".to_string(),
+        });
+
+        // 院内医生名录，供转接问诊选择接诊医生
+        migrations.insert(40, Migration {
+            version: 40,
+            description: "Doctor directory".to_string(),
+            up_sql: include_str!("../../migrations/040_doctors.sql").to_string(),
+            down_sql: "DROP TABLE IF EXISTS doctors;".to_string(),
+        });
+
+        // 消息清洗前的原文
+        migrations.insert(41, Migration {
+            version: 41,
+            description: "Message raw content".to_string(),
+            up_sql: include_str!("../../migrations/041_message_raw_content.sql").to_string(),
+            down_sql: "ALTER TABLE messages DROP COLUMN raw_content;".to_string(),
+        });
+
+        // 重启后恢复登录会话
+        migrations.insert(42, Migration {
+            version: 42,
+            description: "User session restore".to_string(),
+            up_sql: include_str!("../../migrations/042_user_session_restore.sql").to_string(),
+            down_sql: "ALTER TABLE users DROP COLUMN session_verified_at; ALTER TABLE users DROP COLUMN profile;".to_string(),
+        });
+
+        // 问诊消息置顶
+        migrations.insert(43, Migration {
+            version: 43,
+            description: "Message pins".to_string(),
+            up_sql: include_str!("../../migrations/043_message_pins.sql").to_string(),
+            down_sql: "DROP INDEX IF EXISTS idx_messages_pinned; ALTER TABLE messages DROP COLUMN pinned_by; ALTER TABLE messages DROP COLUMN pinned_at; ALTER TABLE messages DROP COLUMN pinned;".to_string(),
+        });
+
+        // 病历附件按引用固定
+        migrations.insert(44, Migration {
+            version: 44,
+            description: "Pin medical record attachments by reference".to_string(),
+            up_sql: include_str!("../../migrations/044_medical_record_pins.sql").to_string(),
+            down_sql: "ALTER TABLE file_cache ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0; CREATE INDEX IF NOT EXISTS idx_file_cache_pinned_accessed ON file_cache (pinned, last_accessed); DELETE FROM pinned_by_ref WHERE reason = 'medical_record';".to_string(),
+        });
+
         Self { migrations }
     }
 
@@ -33,6 +378,11 @@ impl MigrationManager {
         // 创建迁移表
         self.create_migration_table(conn)?;
 
+        // 回填姓名拼音排序键的迁移需要该函数
+        register_pinyin_key_function(conn)?;
+        // 改写历史时间格式的迁移需要该函数
+        register_timestamp_function(conn)?;
+
         // 获取当前版本
         let current_version = self.get_current_version(conn)?;
 
@@ -43,7 +393,7 @@ impl MigrationManager {
         for version in versions {
             if version > current_version {
                 if let Some(migration) = self.migrations.get(&version) {
-                    println!("Running migration {}: {}", version, migration.description);
+                    tracing::info!("Running migration {}: {}", version, migration.description);
                     self.run_migration(conn, migration)?;
                 }
             }
@@ -86,7 +436,7 @@ impl MigrationManager {
         // 提交事务
         tx.commit()?;
 
-        println!("Migration {} completed successfully", migration.version);
+        tracing::info!("Migration {} completed successfully", migration.version);
         Ok(())
     }
 }
\ No newline at end of file
@@ -4,11 +4,26 @@ pub mod connection;
 pub mod migrations;
 pub mod dao;
 pub mod query_optimizer;
+pub mod integrity;
+pub mod readiness;
+pub mod encryption;
+pub mod retry;
 
 #[cfg(test)]
 mod tests;
 
-pub use connection::{init_database, get_database, DatabaseManager, DatabaseStats};
+pub use connection::{init_database, get_database, try_get_database, DatabaseManager, DatabaseStats};
 pub use migrations::MigrationManager;
+pub use integrity::{repair_orphans_in, validate_enum_columns};
+pub use encryption::{
+    load_database_config, save_database_config, DatabaseConfig, DatabaseEncryption, DatabaseKey, EncryptionProgress,
+    EncryptionStep, DATABASE_CONFIG_FILE,
+};
+pub use readiness::{DatabaseReadiness, InitPhase, InitStatus, DATABASE_READY_TIMEOUT, INIT_PROGRESS_EVENT};
 pub use dao::*;
-pub use query_optimizer::{QueryOptimizer, QueryCache, BatchOperations, IndexAdvisor};
\ No newline at end of file
+pub use retry::{retry_on_busy, retry_transaction_on_busy, BUSY_TIMEOUT};
+pub use query_optimizer::{
+    get_query_optimizer, query_cache_for, QueryOptimizer, QueryStats, QueryStatsReport, LockContentionStats, QueryCache,
+    BatchOperations, IndexAdvisor,
+    CACHE_TAG_MESSAGES, CACHE_TAG_PATIENTS,
+};
\ No newline at end of file
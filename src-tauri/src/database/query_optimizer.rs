@@ -1,17 +1,61 @@
+use crate::database::connection::DbConnection;
 use rusqlite::{Connection, Result};
+use serde::de::DeserializeOwned;
+use serde::{Serialize, Serializer};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
 use std::time::{Duration, Instant};
 
+/// 默认慢查询阈值（毫秒）
+const SLOW_QUERY_THRESHOLD_MS: u64 = 100;
+
+static QUERY_OPTIMIZER: OnceLock<QueryOptimizer> = OnceLock::new();
+
+/// 获取 DAO 共享的查询优化器
+pub fn get_query_optimizer() -> &'static QueryOptimizer {
+    QUERY_OPTIMIZER.get_or_init(|| QueryOptimizer::new(SLOW_QUERY_THRESHOLD_MS))
+}
+
 /// 查询性能统计
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct QueryStats {
     pub query: String,
     pub execution_count: u64,
+    #[serde(rename = "total_duration_ms", serialize_with = "serialize_duration_ms")]
     pub total_duration: Duration,
+    #[serde(rename = "avg_duration_ms", serialize_with = "serialize_duration_ms")]
     pub avg_duration: Duration,
+    #[serde(rename = "min_duration_ms", serialize_with = "serialize_duration_ms")]
     pub min_duration: Duration,
+    #[serde(rename = "max_duration_ms", serialize_with = "serialize_duration_ms")]
     pub max_duration: Duration,
+    /// 超过慢查询阈值的次数
+    pub slow_count: u64,
+    /// 最近一次慢查询的执行计划
+    pub query_plan: Vec<String>,
+    /// 执行计划中是否出现全表扫描
+    pub full_table_scan: bool,
+}
+
+fn serialize_duration_ms<S: Serializer>(duration: &Duration, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// 写入遇到数据库忙的统计，持续增长说明连接之间锁争用严重
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LockContentionStats {
+    /// 因数据库忙而重做的写入次数
+    pub retries: u64,
+    /// 重试用尽后返回 DB_BUSY 的次数
+    pub exhausted: u64,
+}
+
+/// get_query_stats 命令的返回结果
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryStatsReport {
+    pub queries: Vec<QueryStats>,
+    pub lock_contention: LockContentionStats,
 }
 
 /// 查询优化器
@@ -19,6 +63,8 @@ pub struct QueryStats {
 pub struct QueryOptimizer {
     stats: Arc<Mutex<HashMap<String, QueryStats>>>,
     slow_query_threshold: Duration,
+    busy_retries: AtomicU64,
+    busy_exhausted: AtomicU64,
 }
 
 impl QueryOptimizer {
@@ -26,6 +72,8 @@ impl QueryOptimizer {
         Self {
             stats: Arc::new(Mutex::new(HashMap::new())),
             slow_query_threshold: Duration::from_millis(slow_query_threshold_ms),
+            busy_retries: AtomicU64::new(0),
+            busy_exhausted: AtomicU64::new(0),
         }
     }
 
@@ -38,21 +86,45 @@ impl QueryOptimizer {
         let result = query();
         let duration = start.elapsed();
 
-        self.record_query(query_name, duration);
+        if self.record_query(query_name, duration) {
+            tracing::warn!(
+                "慢查询检测: {} 耗时 {:?}",
+                query_name,
+                duration
+            );
+        }
 
-        if duration > self.slow_query_threshold {
-            log::warn!(
+        result
+    }
+
+    /// 执行 SQL 查询并记录性能，慢查询时分析执行计划
+    pub fn execute_sql<F, T>(&self, conn: &Connection, query_name: &str, sql: &str, query: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let start = Instant::now();
+        let result = query();
+        let duration = start.elapsed();
+
+        if self.record_query(query_name, duration) {
+            tracing::warn!(
                 "慢查询检测: {} 耗时 {:?}",
                 query_name,
                 duration
             );
+
+            match IndexAdvisor::explain_query_plan(conn, sql) {
+                Ok(plan) => self.record_plan(query_name, plan),
+                Err(e) => tracing::warn!("执行计划分析失败: {} {}", query_name, e),
+            }
         }
 
         result
     }
 
-    /// 记录查询统计
-    fn record_query(&self, query_name: &str, duration: Duration) {
+    /// 记录查询统计，返回是否为慢查询
+    fn record_query(&self, query_name: &str, duration: Duration) -> bool {
+        let is_slow = duration > self.slow_query_threshold;
         let mut stats = self.stats.lock().unwrap();
 
         stats
@@ -63,6 +135,7 @@ impl QueryOptimizer {
                 s.avg_duration = s.total_duration / s.execution_count as u32;
                 s.min_duration = s.min_duration.min(duration);
                 s.max_duration = s.max_duration.max(duration);
+                s.slow_count += is_slow as u64;
             })
             .or_insert_with(|| QueryStats {
                 query: query_name.to_string(),
@@ -71,7 +144,22 @@ impl QueryOptimizer {
                 avg_duration: duration,
                 min_duration: duration,
                 max_duration: duration,
+                slow_count: is_slow as u64,
+                query_plan: Vec::new(),
+                full_table_scan: false,
             });
+
+        is_slow
+    }
+
+    /// 记录慢查询的执行计划
+    fn record_plan(&self, query_name: &str, plan: Vec<String>) {
+        let mut stats = self.stats.lock().unwrap();
+
+        if let Some(s) = stats.get_mut(query_name) {
+            s.full_table_scan = IndexAdvisor::has_full_table_scan(&plan);
+            s.query_plan = plan;
+        }
     }
 
     /// 获取查询统计信息
@@ -86,20 +174,39 @@ impl QueryOptimizer {
         stats.values().cloned().collect()
     }
 
-    /// 获取慢查询列表
+    /// 获取慢查询列表，按最长耗时倒序
     pub fn get_slow_queries(&self) -> Vec<QueryStats> {
         let stats = self.stats.lock().unwrap();
-        stats
+        let mut slow: Vec<QueryStats> = stats
             .values()
-            .filter(|s| s.avg_duration > self.slow_query_threshold)
+            .filter(|s| s.slow_count > 0 || s.avg_duration > self.slow_query_threshold)
             .cloned()
-            .collect()
+            .collect();
+        slow.sort_by_key(|stats| std::cmp::Reverse(stats.max_duration));
+        slow
+    }
+
+    pub fn record_busy_retry(&self) {
+        self.busy_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_busy_exhausted(&self) {
+        self.busy_exhausted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn lock_contention(&self) -> LockContentionStats {
+        LockContentionStats {
+            retries: self.busy_retries.load(Ordering::Relaxed),
+            exhausted: self.busy_exhausted.load(Ordering::Relaxed),
+        }
     }
 
     /// 清除统计信息
     pub fn clear_stats(&self) {
         let mut stats = self.stats.lock().unwrap();
         stats.clear();
+        self.busy_retries.store(0, Ordering::Relaxed);
+        self.busy_exhausted.store(0, Ordering::Relaxed);
     }
 }
 
@@ -123,9 +230,73 @@ impl Default for PoolConfig {
     }
 }
 
+/// 缓存标签：患者相关查询
+pub const CACHE_TAG_PATIENTS: &str = "patients";
+/// 缓存标签：消息相关查询
+pub const CACHE_TAG_MESSAGES: &str = "messages";
+
+/// 缓存标签：单个问诊的消息，新消息写入时只清除所在问诊的缓存
+pub fn consultation_messages_tag(consultation_id: &str) -> String {
+    format!("{}:{}", CACHE_TAG_MESSAGES, consultation_id)
+}
+
+const DEFAULT_CACHE_TTL_SECS: u64 = 60;
+const DEFAULT_CACHE_MAX_SIZE: usize = 200;
+
+// 连接释放后对应的缓存随之清理
+type ConnectionCaches = Vec<(Weak<Mutex<Connection>>, Arc<QueryCache>)>;
+
+static QUERY_CACHES: OnceLock<Mutex<ConnectionCaches>> = OnceLock::new();
+
+/// 获取数据库连接对应的查询缓存
+/// 每个连接一份缓存，避免不同数据库（如测试中的内存库）之间串数据
+pub fn query_cache_for(connection: &DbConnection) -> Arc<QueryCache> {
+    let mut caches = QUERY_CACHES.get_or_init(|| Mutex::new(Vec::new())).lock().unwrap();
+    caches.retain(|(conn, _)| conn.strong_count() > 0);
+
+    if let Some((_, cache)) = caches.iter().find(|(conn, _)| conn.as_ptr() == Arc::as_ptr(connection)) {
+        return cache.clone();
+    }
+
+    let cache = Arc::new(QueryCache::new(DEFAULT_CACHE_TTL_SECS, DEFAULT_CACHE_MAX_SIZE));
+    caches.push((Arc::downgrade(connection), cache.clone()));
+    cache
+}
+
+// 清空所有连接的查询缓存，切换登录用户或退出登录时调用，返回清除的条数
+pub fn clear_all_query_caches() -> usize {
+    let Some(caches) = QUERY_CACHES.get() else {
+        return 0;
+    };
+    caches
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, cache)| {
+            let cleared = cache.len();
+            cache.clear();
+            cleared
+        })
+        .sum()
+}
+
+// 在所有连接的查询缓存中清除带指定标签的条目
+pub fn invalidate_tag_in_all_caches(tag: &str) -> usize {
+    let Some(caches) = QUERY_CACHES.get() else {
+        return 0;
+    };
+    caches.lock().unwrap().iter().map(|(_, cache)| cache.invalidate_tag(tag)).sum()
+}
+
+struct CacheEntry {
+    value: serde_json::Value,
+    tags: Vec<String>,
+    inserted_at: Instant,
+}
+
 /// 查询缓存
 pub struct QueryCache {
-    cache: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
     ttl: Duration,
     max_size: usize,
 }
@@ -140,12 +311,12 @@ impl QueryCache {
     }
 
     /// 获取缓存
-    pub fn get(&self, key: &str) -> Option<String> {
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
         let mut cache = self.cache.lock().unwrap();
 
-        if let Some((value, timestamp)) = cache.get(key) {
-            if timestamp.elapsed() < self.ttl {
-                return Some(value.clone());
+        if let Some(entry) = cache.get(key) {
+            if entry.inserted_at.elapsed() < self.ttl {
+                return Some(entry.value.clone());
             } else {
                 cache.remove(key);
             }
@@ -154,22 +325,58 @@ impl QueryCache {
         None
     }
 
+    /// 获取缓存并反序列化为指定类型
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| serde_json::from_value(value).ok())
+    }
+
     /// 设置缓存
-    pub fn set(&self, key: String, value: String) {
+    pub fn set(&self, key: String, value: serde_json::Value) {
+        self.insert(key, value, Vec::new());
+    }
+
+    /// 序列化后设置缓存
+    pub fn set_from<T: Serialize>(&self, key: &str, value: &T) {
+        self.set_with_tags(key, value, &[]);
+    }
+
+    /// 设置带标签的缓存，标签失效时一并清除
+    pub fn set_with_tags<T: Serialize>(&self, key: &str, value: &T, tags: &[&str]) {
+        match serde_json::to_value(value) {
+            Ok(value) => self.insert(key.to_string(), value, tags.iter().map(|t| t.to_string()).collect()),
+            Err(e) => tracing::warn!("缓存序列化失败: {} {}", key, e),
+        }
+    }
+
+    /// 清除带有指定标签的缓存，返回清除的数量
+    pub fn invalidate_tag(&self, tag: &str) -> usize {
+        let mut cache = self.cache.lock().unwrap();
+        let before = cache.len();
+        cache.retain(|_, entry| !entry.tags.iter().any(|t| t == tag));
+        before - cache.len()
+    }
+
+    /// 清除指定缓存
+    pub fn invalidate(&self, key: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.remove(key);
+    }
+
+    fn insert(&self, key: String, value: serde_json::Value, tags: Vec<String>) {
         let mut cache = self.cache.lock().unwrap();
 
         // 如果缓存已满，移除最旧的项
-        if cache.len() >= self.max_size {
+        if cache.len() >= self.max_size && !cache.contains_key(&key) {
             if let Some(oldest_key) = cache
                 .iter()
-                .min_by_key(|(_, (_, timestamp))| timestamp)
+                .min_by_key(|(_, entry)| entry.inserted_at)
                 .map(|(k, _)| k.clone())
             {
                 cache.remove(&oldest_key);
             }
         }
 
-        cache.insert(key, (value, Instant::now()));
+        cache.insert(key, CacheEntry { value, tags, inserted_at: Instant::now() });
     }
 
     /// 清除缓存
@@ -183,10 +390,21 @@ impl QueryCache {
         let mut cache = self.cache.lock().unwrap();
         let now = Instant::now();
 
-        cache.retain(|_, (_, timestamp)| now.duration_since(*timestamp) < self.ttl);
+        cache.retain(|_, entry| now.duration_since(entry.inserted_at) < self.ttl);
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
+/// 同步等大批量写入时每个事务处理的行数
+pub const BULK_BATCH_SIZE: usize = 500;
+
 /// 批量操作助手
 pub struct BatchOperations;
 
@@ -252,6 +470,27 @@ impl IndexAdvisor {
         suggestions
     }
 
+    /// 获取查询的执行计划（参数按 NULL 绑定，不影响计划生成）
+    pub fn explain_query_plan(conn: &Connection, sql: &str) -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+        let nulls = vec![rusqlite::types::Null; stmt.parameter_count()];
+
+        let plan = stmt
+            .query_map(rusqlite::params_from_iter(nulls.iter()), |row| row.get::<_, String>(3))?
+            .collect::<Result<Vec<String>>>()?;
+
+        Ok(plan)
+    }
+
+    /// 执行计划中是否存在未使用索引的表扫描
+    pub fn has_full_table_scan(plan: &[String]) -> bool {
+        plan.iter().any(|detail| {
+            detail.starts_with("SCAN")
+                && !detail.contains("INDEX")
+                && !detail.contains("CONSTANT ROW")
+        })
+    }
+
     /// 检查表是否有索引
     pub fn check_indexes(conn: &Connection, table_name: &str) -> Result<Vec<String>> {
         let mut stmt = conn.prepare(&format!(
@@ -290,13 +529,145 @@ mod tests {
         assert_eq!(stats.execution_count, 1);
     }
 
+    #[test]
+    fn test_slow_sql_records_query_plan() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (id TEXT PRIMARY KEY, body TEXT);
+             INSERT INTO notes VALUES ('n1', 'a'), ('n2', 'b');",
+        )
+        .unwrap();
+        conn.create_scalar_function("sleep_ms", 1, rusqlite::functions::FunctionFlags::SQLITE_UTF8, |ctx| {
+            let ms: i64 = ctx.get(0)?;
+            std::thread::sleep(Duration::from_millis(ms as u64));
+            Ok(ms)
+        })
+        .unwrap();
+
+        let optimizer = QueryOptimizer::new(20);
+
+        let sql = "SELECT COUNT(*) FROM notes WHERE body = ?1 OR sleep_ms(30) = 0";
+        let count: i64 = optimizer
+            .execute_sql(&conn, "slow_notes", sql, || {
+                conn.query_row(sql, ["x"], |row| row.get(0))
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+
+        let fast_sql = "SELECT body FROM notes WHERE id = ?1";
+        optimizer
+            .execute_sql(&conn, "fast_notes", fast_sql, || {
+                conn.query_row(fast_sql, ["n1"], |row| row.get::<_, String>(0))
+            })
+            .unwrap();
+
+        let slow = optimizer.get_slow_queries();
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].query, "slow_notes");
+        assert_eq!(slow[0].slow_count, 1);
+        assert!(slow[0].full_table_scan);
+        assert!(!slow[0].query_plan.is_empty());
+
+        let json = serde_json::to_value(&slow[0]).unwrap();
+        assert!(json["max_duration_ms"].as_f64().unwrap() >= 30.0);
+
+        optimizer.clear_stats();
+        assert!(optimizer.get_all_stats().is_empty());
+    }
+
+    #[test]
+    fn test_full_table_scan_detection() {
+        let plan = |details: &[&str]| details.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+
+        assert!(IndexAdvisor::has_full_table_scan(&plan(&["SCAN messages"])));
+        assert!(IndexAdvisor::has_full_table_scan(&plan(&["SCAN TABLE messages"])));
+        assert!(!IndexAdvisor::has_full_table_scan(&plan(&[
+            "SEARCH messages USING INDEX idx_messages_consultation (consultation_id=?)",
+            "USE TEMP B-TREE FOR ORDER BY",
+        ])));
+        assert!(!IndexAdvisor::has_full_table_scan(&plan(&["SCAN patients USING INDEX idx_patients_created"])));
+    }
+
     #[test]
     fn test_query_cache() {
         let cache = QueryCache::new(60, 100);
 
-        cache.set("key1".to_string(), "value1".to_string());
-        assert_eq!(cache.get("key1"), Some("value1".to_string()));
+        cache.set_from("key1", &"value1".to_string());
+        assert_eq!(cache.get_as::<String>("key1"), Some("value1".to_string()));
 
         assert_eq!(cache.get("key2"), None);
     }
+
+    #[test]
+    fn test_query_cache_typed_values() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Page {
+            items: Vec<String>,
+            total: u32,
+        }
+
+        let cache = QueryCache::new(60, 100);
+        let page = Page { items: vec!["张三".to_string()], total: 1 };
+        cache.set_from("page", &page);
+
+        assert_eq!(cache.get_as::<Page>("page"), Some(page));
+        // 类型不匹配时视为未命中
+        assert_eq!(cache.get_as::<u32>("page"), None);
+    }
+
+    #[test]
+    fn test_query_cache_ttl_expiry() {
+        let cache = QueryCache::new(0, 100);
+
+        cache.set_from("key", &1);
+        assert_eq!(cache.get_as::<i32>("key"), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_query_cache_tag_invalidation() {
+        let cache = QueryCache::new(60, 100);
+
+        cache.set_with_tags("patients:list", &vec![1, 2], &[CACHE_TAG_PATIENTS]);
+        cache.set_with_tags("patients:tags", &vec!["高血压"], &[CACHE_TAG_PATIENTS]);
+        cache.set_with_tags("messages:c1", &vec!["你好"], &[CACHE_TAG_MESSAGES]);
+        cache.set_from("untagged", &true);
+
+        assert_eq!(cache.invalidate_tag(CACHE_TAG_PATIENTS), 2);
+        assert!(cache.get("patients:list").is_none());
+        assert!(cache.get("patients:tags").is_none());
+        assert!(cache.get("messages:c1").is_some());
+        assert!(cache.get("untagged").is_some());
+        assert_eq!(cache.invalidate_tag(CACHE_TAG_PATIENTS), 0);
+    }
+
+    #[test]
+    fn test_query_cache_evicts_oldest() {
+        let cache = QueryCache::new(60, 2);
+
+        cache.set_from("first", &1);
+        std::thread::sleep(Duration::from_millis(2));
+        cache.set_from("second", &2);
+        std::thread::sleep(Duration::from_millis(2));
+
+        // 覆盖已有键不触发淘汰
+        cache.set_from("second", &22);
+        assert_eq!(cache.len(), 2);
+
+        cache.set_from("third", &3);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("first").is_none());
+        assert_eq!(cache.get_as::<i32>("second"), Some(22));
+        assert_eq!(cache.get_as::<i32>("third"), Some(3));
+    }
+
+    #[test]
+    fn test_query_cache_per_connection() {
+        let first: DbConnection = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        let second: DbConnection = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+
+        query_cache_for(&first).set_from("key", &1);
+        assert_eq!(query_cache_for(&first).get_as::<i32>("key"), Some(1));
+        assert!(query_cache_for(&second).get("key").is_none());
+    }
 }
@@ -0,0 +1,250 @@
+// 启动初始化进度：数据库打开、迁移完成后命令才能访问数据库，清理和缓存预热在此之后进行
+
+use crate::utils::AppError;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub const INIT_PROGRESS_EVENT: &str = "init-progress";
+// 命令等待数据库就绪的最长时间，机械硬盘上首次迁移可能需要十几秒
+pub const DATABASE_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitPhase {
+    Starting,
+    OpenDatabase,
+    // 仅在配置为加密模式且现有数据库为明文时出现
+    EncryptDatabase,
+    RunMigrations,
+    Cleanup,
+    WarmCaches,
+    Completed,
+    Failed,
+}
+
+impl InitPhase {
+    // 进入该阶段时展示的进度百分比
+    pub fn percent(&self) -> u8 {
+        match self {
+            InitPhase::Starting => 0,
+            InitPhase::OpenDatabase => 10,
+            InitPhase::EncryptDatabase => 15,
+            InitPhase::RunMigrations => 30,
+            InitPhase::Cleanup => 70,
+            InitPhase::WarmCaches => 85,
+            InitPhase::Completed => 100,
+            InitPhase::Failed => 100,
+        }
+    }
+
+    fn next(&self) -> Option<InitPhase> {
+        match self {
+            InitPhase::Starting => Some(InitPhase::OpenDatabase),
+            InitPhase::OpenDatabase => Some(InitPhase::EncryptDatabase),
+            InitPhase::EncryptDatabase => Some(InitPhase::RunMigrations),
+            InitPhase::RunMigrations => Some(InitPhase::Cleanup),
+            InitPhase::Cleanup => Some(InitPhase::WarmCaches),
+            InitPhase::WarmCaches => Some(InitPhase::Completed),
+            InitPhase::Completed | InitPhase::Failed => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InitStatus {
+    pub phase: InitPhase,
+    pub percent: u8,
+    // 迁移完成即为 true，后续清理和预热不影响命令访问数据库
+    #[serde(rename = "databaseReady")]
+    pub database_ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Default for InitStatus {
+    fn default() -> Self {
+        Self {
+            phase: InitPhase::Starting,
+            percent: 0,
+            database_ready: false,
+            error: None,
+        }
+    }
+}
+
+/// 初始化进度的发布端，命令通过 `wait_ready` 等待数据库可用
+pub struct DatabaseReadiness {
+    sender: watch::Sender<InitStatus>,
+}
+
+impl DatabaseReadiness {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(InitStatus::default());
+        Self { sender }
+    }
+
+    pub fn status(&self) -> InitStatus {
+        self.sender.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<InitStatus> {
+        self.sender.subscribe()
+    }
+
+    pub fn set_phase(&self, phase: InitPhase) {
+        tracing::info!("Init phase: {:?}", phase);
+        self.sender.send_modify(|status| {
+            status.phase = phase;
+            status.percent = phase.percent();
+        });
+    }
+
+    // 阶段内的细分进度（0-100），折算到当前阶段与下一阶段之间
+    pub fn set_phase_progress(&self, progress: u8) {
+        self.sender.send_modify(|status| {
+            let start = status.phase.percent();
+            let end = status.phase.next().map(|next| next.percent()).unwrap_or(start);
+            status.percent = start + ((end - start) as u32 * progress.min(100) as u32 / 100) as u8;
+        });
+    }
+
+    pub fn mark_database_ready(&self) {
+        self.sender.send_modify(|status| status.database_ready = true);
+    }
+
+    pub fn mark_failed(&self, error: impl Into<String>) {
+        let error = error.into();
+        tracing::error!("Initialization failed: {}", error);
+        self.sender.send_modify(|status| {
+            status.phase = InitPhase::Failed;
+            status.percent = InitPhase::Failed.percent();
+            status.error = Some(error);
+        });
+    }
+
+    /// 等待迁移完成；初始化失败立即返回错误，超时返回可重试的 DB_NOT_READY
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<(), AppError> {
+        let mut receiver = self.sender.subscribe();
+        let waited = tokio::time::timeout(
+            timeout,
+            receiver.wait_for(|status| status.database_ready || status.phase == InitPhase::Failed),
+        )
+        .await;
+
+        match waited {
+            Ok(Ok(status)) if status.database_ready => Ok(()),
+            Ok(Ok(status)) => Err(AppError::database_error(format!(
+                "数据库初始化失败: {}",
+                status.error.clone().unwrap_or_default()
+            ))),
+            // 发送端随状态一起存活，关闭只会发生在应用退出时
+            Ok(Err(_)) => Err(AppError::database_not_ready("应用正在退出")),
+            Err(_) => Err(AppError::database_not_ready(format!(
+                "数据库仍在初始化（已等待 {} 秒），请稍后重试",
+                timeout.as_secs()
+            ))),
+        }
+    }
+}
+
+impl Default for DatabaseReadiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::migrations::MigrationManager;
+    use crate::utils::CODE_DB_NOT_READY;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_wait_ready_blocks_until_slow_migration_finishes() {
+        let readiness = Arc::new(DatabaseReadiness::new());
+        let connection: DbConnection = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+
+        let init = {
+            let readiness = readiness.clone();
+            let connection = connection.clone();
+            tokio::spawn(async move {
+                readiness.set_phase(InitPhase::RunMigrations);
+                // 模拟机械硬盘上缓慢的迁移
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                MigrationManager::new()
+                    .run_migrations(&connection.lock().unwrap())
+                    .unwrap();
+                readiness.mark_database_ready();
+            })
+        };
+
+        // 命令在迁移期间调用，应等待而不是报错
+        readiness.wait_ready(Duration::from_secs(5)).await.unwrap();
+        let count: i64 = connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM patients", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(readiness.status().phase, InitPhase::RunMigrations);
+        init.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_ready_times_out_with_retryable_error() {
+        let readiness = DatabaseReadiness::new();
+        readiness.set_phase(InitPhase::RunMigrations);
+
+        let error = readiness.wait_ready(Duration::from_millis(50)).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(CODE_DB_NOT_READY));
+        assert_eq!(error.retryable, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_wait_ready_returns_immediately_after_failure() {
+        let readiness = Arc::new(DatabaseReadiness::new());
+        let waiter = {
+            let readiness = readiness.clone();
+            tokio::spawn(async move { readiness.wait_ready(Duration::from_secs(5)).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        readiness.mark_failed("disk full");
+
+        let error = waiter.await.unwrap().unwrap_err();
+        assert!(error.message.contains("disk full"));
+        assert_eq!(readiness.status().phase, InitPhase::Failed);
+    }
+
+    #[test]
+    fn test_phase_progress_is_monotonic() {
+        let phases = [
+            InitPhase::Starting,
+            InitPhase::OpenDatabase,
+            InitPhase::EncryptDatabase,
+            InitPhase::RunMigrations,
+            InitPhase::Cleanup,
+            InitPhase::WarmCaches,
+            InitPhase::Completed,
+        ];
+        assert!(phases.windows(2).all(|pair| pair[0].percent() < pair[1].percent()));
+
+        let readiness = DatabaseReadiness::new();
+        readiness.set_phase(InitPhase::Cleanup);
+        readiness.mark_database_ready();
+        let status = readiness.status();
+        assert_eq!(status.percent, 70);
+        assert!(status.database_ready);
+
+        // 加密迁移的细分进度落在该阶段与迁移阶段之间
+        readiness.set_phase(InitPhase::EncryptDatabase);
+        readiness.set_phase_progress(50);
+        assert_eq!(readiness.status().percent, 22);
+        readiness.set_phase_progress(100);
+        assert_eq!(readiness.status().percent, InitPhase::RunMigrations.percent());
+    }
+}
@@ -0,0 +1,295 @@
+// 数据库忙时的写入重试：busy_timeout 内等不到锁，或 WAL 快照过期（此时 SQLite 不会等待）时，
+// 释放连接退避一段时间后整体重做本次写入，仍然失败返回 DB_BUSY
+
+use crate::database::connection::DbConnection;
+use crate::database::query_optimizer::get_query_optimizer;
+use crate::utils::AppError;
+use rusqlite::{Connection, ErrorCode, Transaction};
+use std::error::Error;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// 连接上设置的 busy_timeout，SQLite 在这段时间内自行等待锁释放
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAX_ATTEMPTS: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(20);
+
+pub fn is_busy_error(err: &(dyn Error + 'static)) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>().and_then(|e| e.sqlite_error_code()),
+        Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked)
+    )
+}
+
+/// 单条语句的写入：语句失败时 SQLite 已撤销它的全部修改，可以直接重做。
+/// 多条语句的写入必须使用 `retry_transaction_on_busy`
+pub fn retry_on_busy<T>(
+    connection: &DbConnection,
+    operation: &str,
+    mut write: impl FnMut(&Connection) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    retry(operation, || {
+        let conn = connection.lock().unwrap();
+        let result = write(&conn);
+        // 调用方的事务仍未结束时，失败的语句之前可能已有修改，不能重做
+        let rolled_back = conn.is_autocommit();
+        (result, rolled_back)
+    })
+}
+
+/// 多条语句的写入放在同一事务中，数据库忙时事务完整回滚后才重做
+pub fn retry_transaction_on_busy<T>(
+    connection: &DbConnection,
+    operation: &str,
+    mut write: impl FnMut(&Transaction) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    retry(operation, || {
+        let conn = connection.lock().unwrap();
+        let tx = match conn.unchecked_transaction() {
+            Ok(tx) => tx,
+            Err(e) => return (Err(e.into()), conn.is_autocommit()),
+        };
+
+        let result = match write(&tx) {
+            Ok(value) => tx.commit().map(|_| value).map_err(|e| e.into()),
+            Err(e) => match tx.rollback() {
+                Ok(()) => Err(e),
+                // 回滚失败时事务状态不确定，交给调用方处理
+                Err(rollback_error) => {
+                    tracing::error!("Failed to roll back {}: {}", operation, rollback_error);
+                    return (Err(e), false);
+                }
+            },
+        };
+        // 提交失败时事务在 drop 中回滚，以连接是否回到自动提交判断是否回滚干净
+        let rolled_back = conn.is_autocommit();
+        (result, rolled_back)
+    })
+}
+
+fn retry<T>(
+    operation: &str,
+    mut attempt_write: impl FnMut() -> (Result<T, Box<dyn Error>>, bool),
+) -> Result<T, Box<dyn Error>> {
+    let mut attempt = 1;
+    loop {
+        let error = match attempt_write() {
+            (Ok(value), _) => return Ok(value),
+            (Err(e), rolled_back) if rolled_back && is_busy_error(e.as_ref()) => e,
+            (Err(e), _) => return Err(e),
+        };
+
+        if attempt >= MAX_ATTEMPTS {
+            get_query_optimizer().record_busy_exhausted();
+            tracing::warn!("{} still busy after {} attempts: {}", operation, attempt, error);
+            return Err(Box::new(AppError::database_busy(operation, attempt)));
+        }
+
+        get_query_optimizer().record_busy_retry();
+        let delay = backoff(attempt);
+        tracing::debug!("{} hit busy database (attempt {}), retrying in {:?}", operation, attempt, delay);
+        std::thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+// 指数退避加随机抖动，避免多个写入方同时醒来再次冲突
+fn backoff(attempt: u32) -> Duration {
+    let base = BASE_BACKOFF * 2u32.pow(attempt - 1);
+    let jitter_ms = (Uuid::new_v4().as_u128() % BASE_BACKOFF.as_millis()) as u64;
+    base + Duration::from_millis(jitter_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::CODE_DB_BUSY;
+    use rusqlite::params;
+    use std::path::Path;
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread;
+    use tempfile::tempdir;
+
+    fn open(path: &Path, busy_timeout: Duration) -> DbConnection {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch("PRAGMA journal_mode = WAL;").unwrap();
+        conn.busy_timeout(busy_timeout).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS counters (id TEXT PRIMARY KEY, value INTEGER NOT NULL);
+             CREATE TABLE IF NOT EXISTS events (id INTEGER PRIMARY KEY AUTOINCREMENT, writer TEXT NOT NULL);",
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn count(connection: &DbConnection, sql: &str) -> i64 {
+        connection.lock().unwrap().query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    // 另一个连接持有写锁 hold 时长后释放
+    fn hold_write_lock(path: &Path, hold: Duration) -> thread::JoinHandle<()> {
+        let holder = open(path, Duration::ZERO);
+        let locked = Arc::new(Barrier::new(2));
+        let signal = locked.clone();
+        let handle = thread::spawn(move || {
+            let conn = holder.lock().unwrap();
+            conn.execute_batch("BEGIN IMMEDIATE; INSERT INTO events (writer) VALUES ('holder');").unwrap();
+            signal.wait();
+            thread::sleep(hold);
+            conn.execute_batch("COMMIT").unwrap();
+        });
+        locked.wait();
+        handle
+    }
+
+    #[test]
+    fn test_busy_write_retried_until_lock_released() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("busy.db");
+        let writer = open(&path, Duration::ZERO);
+        let retries_before = get_query_optimizer().lock_contention().retries;
+
+        let holder = hold_write_lock(&path, Duration::from_millis(30));
+        let mut attempts = 0;
+        retry_on_busy(&writer, "insert event", |conn| {
+            attempts += 1;
+            conn.execute("INSERT INTO events (writer) VALUES ('writer')", [])?;
+            Ok(())
+        })
+        .unwrap();
+        holder.join().unwrap();
+
+        assert!(attempts > 1);
+        assert!(get_query_optimizer().lock_contention().retries > retries_before);
+        assert_eq!(count(&writer, "SELECT COUNT(*) FROM events"), 2);
+    }
+
+    #[test]
+    fn test_exhausted_retries_surface_db_busy() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("busy.db");
+        let writer = open(&path, Duration::ZERO);
+        let exhausted_before = get_query_optimizer().lock_contention().exhausted;
+
+        let holder = hold_write_lock(&path, Duration::from_millis(500));
+        let mut attempts = 0;
+        let error = retry_on_busy(&writer, "insert event", |conn| {
+            attempts += 1;
+            conn.execute("INSERT INTO events (writer) VALUES ('writer')", [])?;
+            Ok(())
+        })
+        .unwrap_err();
+        holder.join().unwrap();
+
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        let error = AppError::from(error);
+        assert_eq!(error.code.as_deref(), Some(CODE_DB_BUSY));
+        assert!(error.is_retryable());
+        assert!(get_query_optimizer().lock_contention().exhausted > exhausted_before);
+    }
+
+    #[test]
+    fn test_other_errors_are_not_retried() {
+        let dir = tempdir().unwrap();
+        let writer = open(&dir.path().join("busy.db"), Duration::ZERO);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_on_busy(&writer, "insert counter", |conn| {
+            attempts += 1;
+            conn.execute("INSERT INTO counters (id, value) VALUES ('c', NULL)", [])?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_open_transaction_is_not_retried_statement_by_statement() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("busy.db");
+        let writer = open(&path, Duration::ZERO);
+        writer.lock().unwrap().execute_batch("BEGIN").unwrap();
+
+        let holder = hold_write_lock(&path, Duration::from_millis(30));
+        let mut attempts = 0;
+        let result = retry_on_busy(&writer, "insert event", |conn| {
+            attempts += 1;
+            conn.execute("INSERT INTO events (writer) VALUES ('writer')", [])?;
+            Ok(())
+        });
+        holder.join().unwrap();
+
+        // 调用方自己开启的事务中失败，重做单条语句可能破坏事务内其他修改
+        assert!(is_busy_error(result.unwrap_err().as_ref()));
+        assert_eq!(attempts, 1);
+        writer.lock().unwrap().execute_batch("ROLLBACK").unwrap();
+    }
+
+    #[test]
+    fn test_transaction_retried_only_after_full_rollback() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("busy.db");
+        let writer = open(&path, Duration::ZERO);
+        writer
+            .lock()
+            .unwrap()
+            .execute("INSERT INTO counters (id, value) VALUES ('sent', 0)", [])
+            .unwrap();
+
+        let holder = hold_write_lock(&path, Duration::from_millis(30));
+        let mut attempts = 0;
+        retry_transaction_on_busy(&writer, "record event", |tx| {
+            attempts += 1;
+            tx.execute("UPDATE counters SET value = value + 1 WHERE id = 'sent'", [])?;
+            tx.execute("INSERT INTO events (writer) VALUES ('writer')", [])?;
+            Ok(())
+        })
+        .unwrap();
+        holder.join().unwrap();
+
+        // 每次失败都整体回滚，计数不会因重试多加
+        assert!(attempts > 1);
+        assert_eq!(count(&writer, "SELECT value FROM counters WHERE id = 'sent'"), 1);
+        assert_eq!(count(&writer, "SELECT COUNT(*) FROM events WHERE writer = 'writer'"), 1);
+    }
+
+    #[test]
+    fn test_concurrent_writers_all_succeed() {
+        const WRITES_PER_THREAD: i64 = 200;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("busy.db");
+        open(&path, Duration::ZERO)
+            .lock()
+            .unwrap()
+            .execute("INSERT INTO counters (id, value) VALUES ('total', 0)", [])
+            .unwrap();
+
+        let start = Arc::new(Barrier::new(2));
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let connection = open(&path, Duration::from_millis(50));
+                let start = start.clone();
+                thread::spawn(move || {
+                    start.wait();
+                    for _ in 0..WRITES_PER_THREAD {
+                        retry_transaction_on_busy(&connection, "hammer", |tx| {
+                            tx.execute("INSERT INTO events (writer) VALUES (?1)", params![name])?;
+                            tx.execute("UPDATE counters SET value = value + 1 WHERE id = 'total'", [])?;
+                            Ok(())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let check = open(&path, Duration::ZERO);
+        assert_eq!(count(&check, "SELECT COUNT(*) FROM events"), WRITES_PER_THREAD * 2);
+        assert_eq!(count(&check, "SELECT value FROM counters WHERE id = 'total'"), WRITES_PER_THREAD * 2);
+    }
+}
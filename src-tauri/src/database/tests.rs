@@ -27,6 +27,15 @@ mod tests {
         Arc::new(Mutex::new(conn))
     }
 
+    // 以 SQLCipher 加密方式打开的测试库，连接配置与正式库一致
+    #[cfg(feature = "sqlcipher")]
+    fn create_encrypted_test_connection(key: &crate::database::DatabaseKey) -> Arc<Mutex<Connection>> {
+        let temp_dir = tempdir().unwrap();
+        let manager = DatabaseManager::open_path(temp_dir.path().join("test.db"), Some(key.clone())).unwrap();
+        MigrationManager::new().run_migrations(&manager.get_connection().lock().unwrap()).unwrap();
+        manager.get_connection()
+    }
+
     // 迁移测试
     mod migration_tests {
         use super::*;
@@ -55,6 +64,98 @@ mod tests {
             assert!(table_names.contains(&"file_cache".to_string()));
             assert!(table_names.contains(&"audit_logs".to_string()));
         }
+
+        #[test]
+        fn test_doctor_scope_backfill() {
+            let conn = Connection::open_in_memory().unwrap();
+            MigrationManager::new().run_migrations(&conn).unwrap();
+            conn.execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id) VALUES ('c1', 'p1', ''), ('c2', 'p1', '');
+                 INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title)
+                 VALUES ('r1', 'p1', 'd1', 'c1', 'diagnosis', '诊断'), ('r2', 'p1', '', 'c1', 'prescription', '处方');"
+            ).unwrap();
+
+            let backfill = include_str!("../../migrations/015_doctor_scope.sql");
+            conn.execute_batch(backfill).unwrap();
+
+            let doctor_of = |sql: &str, id: &str| -> String { conn.query_row(sql, [id], |row| row.get(0)).unwrap() };
+            assert_eq!(doctor_of("SELECT doctor_id FROM consultations WHERE id = ?1", "c1"), "d1");
+            assert_eq!(doctor_of("SELECT doctor_id FROM medical_records WHERE id = ?1", "r2"), "d1");
+            // 无法推断归属时保持为空
+            assert_eq!(doctor_of("SELECT doctor_id FROM consultations WHERE id = ?1", "c2"), "");
+
+            // 本机只有一位医生登录过
+            conn.execute("INSERT INTO users (id, username) VALUES ('d2', 'doctor2')", []).unwrap();
+            conn.execute_batch(backfill).unwrap();
+            assert_eq!(doctor_of("SELECT doctor_id FROM consultations WHERE id = ?1", "c2"), "d2");
+            assert_eq!(doctor_of("SELECT doctor_id FROM consultations WHERE id = ?1", "c1"), "d1");
+        }
+
+        #[test]
+        fn test_patient_sort_key_backfill() {
+            let conn = Connection::open_in_memory().unwrap();
+            MigrationManager::new().run_migrations(&conn).unwrap();
+            conn.execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三'), ('p2', '李四'), ('p3', '王五');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, completed_at, updated_at) VALUES
+                     ('c1', 'p1', 'd1', 'completed', '2024-03-01 09:00:00+00:00', '2024-03-01 09:00:00+00:00'),
+                     ('c2', 'p1', 'd1', 'completed', '2024-03-05 10:00:00+00:00', '2024-03-05 10:00:00+00:00'),
+                     ('c3', 'p1', 'd1', 'active', NULL, '2024-03-09 08:00:00+00:00'),
+                     ('c4', 'p2', 'd1', 'completed', NULL, '2024-02-20 15:30:00+00:00'),
+                     ('c5', 'p3', 'd1', 'cancelled', NULL, '2024-03-02 11:00:00+00:00');"
+            ).unwrap();
+
+            conn.execute_batch(include_str!("../../migrations/025_patient_sort_key_backfill.sql")).unwrap();
+
+            let row = |id: &str| -> (Option<String>, Option<String>) {
+                conn.query_row(
+                    "SELECT last_visit, name_pinyin FROM patients WHERE id = ?1",
+                    [id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                ).unwrap()
+            };
+            // 取最近一次完成的问诊，进行中的不算
+            assert_eq!(row("p1"), (Some("2024-03-05 10:00:00+00:00".to_string()), Some("zhang san".to_string())));
+            // 没有完成时间的旧数据退回到最后更新时间
+            assert_eq!(row("p2").0.as_deref(), Some("2024-02-20 15:30:00+00:00"));
+            // 只有取消的问诊不算就诊
+            assert_eq!(row("p3"), (None, Some("wang wu".to_string())));
+        }
+
+        #[test]
+        fn test_medical_record_pins_backfill() {
+            let conn = Connection::open_in_memory().unwrap();
+            MigrationManager::new().run_migrations(&conn).unwrap();
+            // 回到迁移前仍用固定标记的结构
+            conn.execute_batch(
+                r#"ALTER TABLE file_cache ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+                 INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO file_cache (id, file_url, local_path, pinned) VALUES
+                     ('f1', 'https://example.com/a.png', '/tmp/a.png', 1),
+                     ('f2', 'https://example.com/b.png', '/tmp/b.png', 1),
+                     ('f3', 'https://example.com/c.png', '/tmp/c.png', 0);
+                 INSERT INTO medical_records (id, patient_id, doctor_id, record_type, title, attachments, deleted_at) VALUES
+                     ('r1', 'p1', 'd1', 'examination', '胸片', '[{"fileId":"f1"},{"fileId":"f2"}]', NULL),
+                     ('r2', 'p1', 'd1', 'examination', '复查', '[{"fileId":"f1"}]', '2024-03-01T08:00:00.000000Z'),
+                     ('r3', 'p1', 'd1', 'examination', '旧片', '[{"fileId":"f3"}]', NULL);"#
+            ).unwrap();
+
+            conn.execute_batch(include_str!("../../migrations/044_medical_record_pins.sql")).unwrap();
+
+            let pins = {
+                let mut stmt = conn
+                    .prepare("SELECT cache_id, ref_id FROM pinned_by_ref WHERE reason = 'medical_record' ORDER BY cache_id, ref_id")
+                    .unwrap();
+                stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+                    .unwrap()
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                    .unwrap()
+            };
+            // 回收站中的病历同样保持固定，未固定的文件不补固定
+            let expected = [("f1", "r1"), ("f1", "r2"), ("f2", "r1")];
+            assert_eq!(pins, expected.map(|(f, r)| (f.to_string(), r.to_string())));
+        }
     }
 
     // 基础数据库操作测试
@@ -99,9 +200,7 @@ mod tests {
     mod crud_tests {
         use super::*;
 
-        #[test]
-        fn test_user_table_operations() {
-            let connection = create_test_connection();
+        fn user_table_operations(connection: Arc<Mutex<Connection>>) {
             let conn = connection.lock().unwrap();
 
             // 插入用户
@@ -140,9 +239,7 @@ mod tests {
             assert_eq!(count, 0);
         }
 
-        #[test]
-        fn test_patient_table_operations() {
-            let connection = create_test_connection();
+        fn patient_table_operations(connection: Arc<Mutex<Connection>>) {
             let conn = connection.lock().unwrap();
 
             // 插入患者
@@ -175,9 +272,7 @@ mod tests {
             assert_eq!(count, 1);
         }
 
-        #[test]
-        fn test_consultation_table_operations() {
-            let connection = create_test_connection();
+        fn consultation_table_operations(connection: Arc<Mutex<Connection>>) {
             let conn = connection.lock().unwrap();
 
             // 先插入患者（外键依赖）
@@ -213,6 +308,885 @@ mod tests {
             let count: i32 = stmt.query_row([status], |row| row.get(0)).unwrap();
             assert_eq!(count, 1);
         }
+
+        fn message_enum_round_trip(connection: Arc<Mutex<Connection>>) {
+            use crate::database::dao::{BaseDao, MessageDao};
+
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id) VALUES ('c1', 'p1', 'd1');"
+            ).unwrap();
+            let dao = MessageDao::with_connection(connection.clone());
+
+            let system = dao.insert_system_message(
+                "c1",
+                SystemEventKind::ConsultationCancelled,
+                serde_json::json!({ "reason": "患者未到" }),
+            ).unwrap();
+            let loaded = dao.find_by_id(&system.id).unwrap().unwrap();
+            assert!(matches!(loaded.sender_type, SenderType::System));
+            assert!(matches!(loaded.message_type, MessageType::Event));
+            assert!(matches!(loaded.read_status, ReadStatus::Read));
+
+            let event = SystemEvent::parse(loaded.content.as_deref().unwrap()).unwrap();
+            assert_eq!(event.kind, SystemEventKind::ConsultationCancelled);
+            assert_eq!(event.text, "问诊已取消：患者未到");
+
+            // 仍然拒绝未知的发送方
+            let conn = connection.lock().unwrap();
+            let result = conn.execute(
+                "INSERT INTO messages (id, consultation_id, sender_type, message_type) VALUES ('m1', 'c1', 'robot', 'text')",
+                [],
+            );
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn test_user_table_operations() {
+            user_table_operations(create_test_connection());
+        }
+
+        #[test]
+        fn test_patient_table_operations() {
+            patient_table_operations(create_test_connection());
+        }
+
+        #[test]
+        fn test_consultation_table_operations() {
+            consultation_table_operations(create_test_connection());
+        }
+
+        #[test]
+        fn test_message_enum_round_trip() {
+            message_enum_round_trip(create_test_connection());
+        }
+
+        // 同一组用例在 SQLCipher 加密库上再跑一遍
+        #[cfg(feature = "sqlcipher")]
+        mod sqlcipher {
+            use super::*;
+            use crate::database::DatabaseKey;
+
+            fn encrypted_connection() -> Arc<Mutex<Connection>> {
+                create_encrypted_test_connection(&DatabaseKey::from_bytes(&[9u8; 32]))
+            }
+
+            #[test]
+            fn test_user_table_operations() {
+                user_table_operations(encrypted_connection());
+            }
+
+            #[test]
+            fn test_patient_table_operations() {
+                patient_table_operations(encrypted_connection());
+            }
+
+            #[test]
+            fn test_consultation_table_operations() {
+                consultation_table_operations(encrypted_connection());
+            }
+
+            #[test]
+            fn test_message_enum_round_trip() {
+                message_enum_round_trip(encrypted_connection());
+            }
+        }
+    }
+
+    // 消息草稿测试
+    mod draft_tests {
+        use super::*;
+        use crate::database::dao::{MessageDao, MessageDraftDao};
+
+        fn seed_consultation(connection: &Arc<Mutex<Connection>>) {
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');"
+            ).unwrap();
+        }
+
+        fn doctor_message(id: &str) -> Message {
+            Message {
+                id: id.to_string(),
+                consultation_id: "c1".to_string(),
+                sender_type: SenderType::Doctor,
+                message_type: MessageType::Text,
+                content: Some("按时服药".to_string()),
+                file_path: None,
+                file_size: None,
+                mime_type: None,
+                timestamp: Utc::now(),
+                sync_status: SyncStatus::Pending,
+                read_status: ReadStatus::Unread,
+                template_id: None,
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
+            }
+        }
+
+        #[test]
+        fn test_draft_upsert_overwrites_per_doctor() {
+            let connection = create_test_connection();
+            seed_consultation(&connection);
+            let dao = MessageDraftDao::with_connection(connection.clone());
+
+            dao.upsert("c1", "d1", "初稿").unwrap();
+            dao.upsert("c1", "d1", "修改后的草稿").unwrap();
+            dao.upsert("c1", "d2", "另一位医生的草稿").unwrap();
+
+            let draft = dao.find("c1", "d1").unwrap().unwrap();
+            assert_eq!(draft.content, "修改后的草稿");
+            assert_eq!(dao.find("c1", "d2").unwrap().unwrap().content, "另一位医生的草稿");
+
+            let rows: i64 = connection.lock().unwrap()
+                .query_row("SELECT COUNT(*) FROM message_drafts", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(rows, 2);
+
+            assert!(dao.delete("c1", "d1").unwrap());
+            assert!(!dao.delete("c1", "d1").unwrap());
+            assert!(dao.find("c1", "d1").unwrap().is_none());
+        }
+
+        #[test]
+        fn test_send_clears_draft_in_same_transaction() {
+            let connection = create_test_connection();
+            seed_consultation(&connection);
+            let drafts = MessageDraftDao::with_connection(connection.clone());
+            let messages = MessageDao::with_connection(connection.clone());
+
+            drafts.upsert("c1", "d1", "按时服药").unwrap();
+            drafts.upsert("c1", "d2", "不受影响").unwrap();
+            let entry = messages.create_outgoing(&doctor_message("m1"), Some("d1")).unwrap();
+            assert_eq!(entry.message_id, "m1");
+            assert!(drafts.find("c1", "d1").unwrap().is_none());
+            assert!(drafts.find("c1", "d2").unwrap().is_some());
+
+            // 消息写入失败时草稿保留
+            drafts.upsert("c1", "d1", "重新编辑").unwrap();
+            connection.lock().unwrap().execute_batch(
+                "CREATE TRIGGER fail_message_insert BEFORE INSERT ON messages
+                 BEGIN SELECT RAISE(ABORT, 'insert failed'); END;"
+            ).unwrap();
+            assert!(messages.create_outgoing(&doctor_message("m2"), Some("d1")).is_err());
+            assert_eq!(drafts.find("c1", "d1").unwrap().unwrap().content, "重新编辑");
+        }
+    }
+
+    // 会话列表测试
+    mod conversation_tests {
+        use super::*;
+        use crate::database::dao::ConsultationDao;
+
+        fn seed(connection: &Arc<Mutex<Connection>>) {
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name, avatar_url) VALUES ('p1', '张三', 'https://cdn/p1.png'), ('p2', '李四', NULL), ('p3', '王五', NULL);
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, updated_at) VALUES
+                     ('c1', 'p1', 'd1', 'active', 'text', '2024-03-01 08:00:00'),
+                     ('c2', 'p2', 'd1', 'active', 'text', '2024-03-01 08:00:00'),
+                     ('c3', 'p3', 'd1', 'pending', 'text', '2024-03-01 07:00:00'),
+                     ('c4', 'p1', 'd2', 'active', 'text', '2024-03-01 12:00:00');
+                 INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp, read_status) VALUES
+                     ('m1', 'c1', 'patient', 'text', '头痛', '2024-03-01 09:00:00', 'unread'),
+                     ('m2', 'c1', 'patient', 'text', '还有点发烧', '2024-03-01 09:30:00', 'read'),
+                     ('m3', 'c1', 'doctor', 'text', '请按时服药', '2024-03-01 10:05:00', 'unread'),
+                     ('m4', 'c2', 'patient', 'text', '医生您好', '2024-03-01 10:30:00', 'unread'),
+                     ('m5', 'c2', 'patient', 'image', NULL, '2024-03-01 11:00:00', 'unread'),
+                     ('m6', 'c4', 'patient', 'text', '其他医生的患者', '2024-03-01 12:00:00', 'unread');"
+            ).unwrap();
+        }
+
+        #[test]
+        fn test_overviews_ordered_by_latest_activity() {
+            let connection = create_test_connection();
+            seed(&connection);
+            let dao = ConsultationDao::with_connection(connection.clone());
+
+            let page = dao.get_conversation_overviews("d1", 1, 10).unwrap();
+            assert_eq!(page.total, 3);
+            let ids: Vec<_> = page.items.iter().map(|o| o.consultation_id.as_str()).collect();
+            assert_eq!(ids, vec!["c2", "c1", "c3"]);
+
+            let c2 = &page.items[0];
+            assert_eq!(c2.patient_name, "李四");
+            assert_eq!(c2.last_message_preview.as_deref(), Some("[图片]"));
+            assert_eq!(c2.last_activity_at, c2.last_message_at.unwrap());
+
+            let c1 = &page.items[1];
+            assert_eq!(c1.patient_avatar_url.as_deref(), Some("https://cdn/p1.png"));
+            assert_eq!(c1.last_message_preview.as_deref(), Some("请按时服药"));
+
+            // 没有消息的问诊按更新时间排在最后
+            let c3 = &page.items[2];
+            assert_eq!(c3.last_message_preview, None);
+            assert_eq!(c3.last_message_at, None);
+            assert_eq!(c3.unread_count, 0);
+
+            let second = dao.get_conversation_overviews("d1", 2, 2).unwrap();
+            assert_eq!(second.total_pages, 2);
+            assert_eq!(second.items.len(), 1);
+            assert_eq!(second.items[0].consultation_id, "c3");
+        }
+
+        #[test]
+        fn test_overviews_order_mixed_timestamp_formats() {
+            let connection = create_test_connection();
+            seed(&connection);
+            // 应用写入的消息时间为 RFC3339 UTC，与问诊更新时间同一天时按实际先后排序
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p4', '赵六');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, updated_at) VALUES
+                     ('c5', 'p4', 'd1', 'pending', 'text', '2024-03-01 11:30:00');
+                 INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp, read_status)
+                 VALUES ('m7', 'c1', 'patient', 'text', '好多了', '2024-03-01T11:15:00.000000Z', 'read');"
+            ).unwrap();
+            let dao = ConsultationDao::with_connection(connection.clone());
+
+            let page = dao.get_conversation_overviews("d1", 1, 10).unwrap();
+            let ids: Vec<_> = page.items.iter().map(|o| o.consultation_id.as_str()).collect();
+            assert_eq!(ids, vec!["c5", "c1", "c2", "c3"]);
+            assert_eq!(page.items[1].last_message_preview.as_deref(), Some("好多了"));
+        }
+
+        #[test]
+        fn test_unread_counts_only_patient_messages() {
+            let connection = create_test_connection();
+            seed(&connection);
+            connection.lock().unwrap().execute(
+                "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp, read_status)
+                 VALUES ('m7', 'c1', 'system', 'event', NULL, '2024-03-01 08:30:00', 'unread')",
+                [],
+            ).unwrap();
+            let dao = ConsultationDao::with_connection(connection.clone());
+
+            let page = dao.get_conversation_overviews("d1", 1, 10).unwrap();
+            let unread = |id: &str| page.items.iter().find(|o| o.consultation_id == id).unwrap().unread_count;
+            // c1 中医生和系统消息未读不计入，已读的患者消息也不计入
+            assert_eq!(unread("c1"), 1);
+            assert_eq!(unread("c2"), 2);
+            assert_eq!(unread("c3"), 0);
+
+            let other = dao.get_conversation_overviews("d2", 1, 10).unwrap();
+            assert_eq!(other.items.len(), 1);
+            assert_eq!(other.items[0].unread_count, 1);
+        }
+    }
+
+    // 会话内搜索测试
+    mod search_tests {
+        use super::*;
+        use crate::database::dao::message_dao::find_match_ranges;
+        use crate::database::dao::{MatchRange, MessageDao};
+
+        fn seed(connection: &Arc<Mutex<Connection>>) {
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active'), ('c2', 'p1', 'd1', 'active');
+                 INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp) VALUES
+                     ('m1', 'c1', 'patient', 'text', '医生您好，我头痛', '2024-03-01 09:00:00'),
+                     ('m2', 'c1', 'doctor', 'text', '先做CT检查，ct结果出来后再看', '2024-03-01 09:05:00'),
+                     ('m3', 'c1', 'patient', 'image', NULL, '2024-03-01 09:06:00'),
+                     ('m4', 'c1', 'system', 'event', '{\"kind\":\"ct\"}', '2024-03-01 09:07:00'),
+                     ('m5', 'c1', 'doctor', 'template', '复查 Ct 时请空腹', '2024-03-01 09:10:00'),
+                     ('m6', 'c2', 'patient', 'text', 'CT 报告', '2024-03-01 09:20:00'),
+                     ('m7', 'c1', 'patient', 'text', '100%_确定', '2024-03-01 09:30:00');"
+            ).unwrap();
+        }
+
+        fn range(start: usize, end: usize) -> MatchRange {
+            MatchRange { start, end }
+        }
+
+        #[test]
+        fn test_char_offsets_in_mixed_content() {
+            // 中文字符占 3 个字节，位置必须按字符计
+            assert_eq!(find_match_ranges("先做CT检查，ct结果出来后再看", "ct"), vec![range(2, 4), range(7, 9)]);
+            assert_eq!(find_match_ranges("医生您好，我头痛", "头痛"), vec![range(6, 8)]);
+            assert_eq!(find_match_ranges("头痛头痛头痛", "头痛头痛"), vec![range(0, 4)]);
+            assert_eq!(find_match_ranges("Blood 血压 BLOOD", "blood"), vec![range(0, 5), range(9, 14)]);
+            assert!(find_match_ranges("头痛", "头疼").is_empty());
+            assert!(find_match_ranges("头痛", "").is_empty());
+        }
+
+        #[test]
+        fn test_search_in_consultation_returns_positions() {
+            let connection = create_test_connection();
+            seed(&connection);
+            let dao = MessageDao::with_connection(connection);
+
+            // 图片、系统事件和其他问诊的消息不参与匹配，最新的在前
+            let hits = dao.search_in_consultation("c1", "CT", 20).unwrap();
+            let ids: Vec<_> = hits.iter().map(|hit| hit.message_id.as_str()).collect();
+            assert_eq!(ids, vec!["m5", "m2"]);
+            assert_eq!(hits[0].message_index, 4);
+            assert_eq!(hits[0].matches, vec![range(3, 5)]);
+            assert_eq!(hits[1].message_index, 1);
+            assert_eq!(hits[1].matches, vec![range(2, 4), range(7, 9)]);
+
+            let chinese = dao.search_in_consultation("c1", "头痛", 20).unwrap();
+            assert_eq!(chinese.len(), 1);
+            assert_eq!(chinese[0].message_index, 0);
+            assert_eq!(chinese[0].matches, vec![range(6, 8)]);
+
+            // LIKE 通配符按字面匹配
+            let literal = dao.search_in_consultation("c1", "%_", 20).unwrap();
+            assert_eq!(literal.len(), 1);
+            assert_eq!(literal[0].message_id, "m7");
+            assert_eq!(literal[0].matches, vec![range(3, 5)]);
+
+            assert_eq!(dao.search_in_consultation("c1", "ct", 1).unwrap().len(), 1);
+            assert!(dao.search_in_consultation("c1", "  ", 20).unwrap().is_empty());
+        }
+    }
+
+    // 参数化查询构建器测试
+    mod query_builder_tests {
+        use super::*;
+        use crate::database::dao::{consultation_dao, AuditLogDao, BaseDao, ConsultationDao, PatientDao, QueryBuilder};
+        use chrono::{Duration, TimeZone};
+
+        #[test]
+        fn test_build_generates_placeholders() {
+            let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+            let builder = QueryBuilder::new()
+                .where_eq("user_id", "u1".to_string())
+                .where_like("name", "张")
+                .where_in("action", vec!["login", "logout"])
+                .where_between_dates("created_at", start, start + Duration::days(1))
+                .order_by("created_at DESC")
+                .limit(20)
+                .offset(40);
+
+            let (sql, params) = builder.build("SELECT id FROM audit_logs");
+            assert_eq!(
+                sql,
+                "SELECT id FROM audit_logs WHERE user_id = ? AND name LIKE ? ESCAPE '\\' AND action IN (?, ?) \
+                 AND created_at BETWEEN ? AND ? ORDER BY created_at DESC LIMIT ? OFFSET ?"
+            );
+            assert_eq!(params.len(), 8);
+
+            let (count_sql, count_params) = builder.build_count("audit_logs");
+            assert_eq!(
+                count_sql,
+                "SELECT COUNT(*) FROM audit_logs WHERE user_id = ? AND name LIKE ? ESCAPE '\\' AND action IN (?, ?) \
+                 AND created_at BETWEEN ? AND ?"
+            );
+            assert_eq!(count_params.len(), 6);
+
+            let bare_builder = QueryBuilder::new();
+            let (bare, bare_params) = bare_builder.build("SELECT id FROM patients");
+            assert_eq!(bare, "SELECT id FROM patients");
+            assert!(bare_params.is_empty());
+        }
+
+        #[test]
+        fn test_empty_in_matches_nothing() {
+            let connection = create_test_connection();
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三'), ('p2', '李四');"
+            ).unwrap();
+
+            let builder = QueryBuilder::new().where_in("id", Vec::<String>::new());
+            let (sql, params) = builder.build_count("patients");
+            assert_eq!(sql, "SELECT COUNT(*) FROM patients WHERE 0 = 1");
+
+            let conn = connection.lock().unwrap();
+            let count: i64 = conn.query_row(&sql, params.as_slice(), |row| row.get(0)).unwrap();
+            assert_eq!(count, 0);
+
+            let builder = QueryBuilder::new().where_in("id", vec!["p2"]);
+            let (sql, params) = builder.build("SELECT name FROM patients");
+            let name: String = conn.query_row(&sql, params.as_slice(), |row| row.get(0)).unwrap();
+            assert_eq!(name, "李四");
+        }
+
+        #[test]
+        fn test_injection_attempts_stay_inert() {
+            let connection = create_test_connection();
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三'), ('p2', '100%_正常');"
+            ).unwrap();
+            let conn = connection.lock().unwrap();
+            let count = |builder: QueryBuilder| -> i64 {
+                let (sql, params) = builder.build_count("patients");
+                conn.query_row(&sql, params.as_slice(), |row| row.get(0)).unwrap()
+            };
+
+            assert_eq!(count(QueryBuilder::new().where_eq("name", "x' OR '1'='1".to_string())), 0);
+            assert_eq!(count(QueryBuilder::new().where_like("name", "'; DROP TABLE patients; --")), 0);
+            assert_eq!(count(QueryBuilder::new().where_in("id", vec!["p1') OR ('1'='1"])), 0);
+            // 通配符按字面匹配，不会退化成全表匹配
+            assert_eq!(count(QueryBuilder::new().where_like("name", "%")), 1);
+            assert_eq!(count(QueryBuilder::new().where_like("name", "_")), 1);
+
+            // 表仍然存在且数据完整
+            assert_eq!(count(QueryBuilder::new()), 2);
+        }
+
+        #[test]
+        fn test_audit_log_query_filters() {
+            let connection = create_test_connection();
+            // 与 SqlTimestamp 写入的文本格式一致，保证时间范围按字符串比较正确
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, created_at) VALUES
+                     ('a1', 'u1', 'login', NULL, NULL, '2024-03-01T08:00:00.000000Z'),
+                     ('a2', 'u1', 'view_patient', 'patient', 'p1', '2024-03-01T09:00:00.000000Z'),
+                     ('a3', 'u1', 'send_message', 'consultation', 'c1', '2024-03-02T09:00:00.000000Z'),
+                     ('a4', 'u2', 'view_patient', 'patient', 'p2', '2024-03-01T10:00:00.000000Z');"
+            ).unwrap();
+            let dao = AuditLogDao::with_connection(connection);
+
+            let filter = AuditLogFilter {
+                user_id: Some("u1".to_string()),
+                actions: Some(vec!["view_patient".to_string(), "send_message".to_string()]),
+                ..Default::default()
+            };
+            let page = dao.query(&filter, 1, 10).unwrap();
+            assert_eq!(page.total, 2);
+            let ids: Vec<_> = page.items.iter().map(|log| log.id.as_str()).collect();
+            assert_eq!(ids, vec!["a3", "a2"]);
+
+            let filter = AuditLogFilter {
+                resource_type: Some("patient".to_string()),
+                start_time: Some(Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap()),
+                end_time: Some(Utc.with_ymd_and_hms(2024, 3, 1, 23, 59, 59).unwrap()),
+                ..Default::default()
+            };
+            let page = dao.query(&filter, 1, 10).unwrap();
+            assert_eq!(page.items.len(), 1);
+            assert_eq!(page.items[0].id, "a4");
+
+            let filter = AuditLogFilter {
+                actions: Some(Vec::new()),
+                ..Default::default()
+            };
+            assert_eq!(dao.query(&filter, 1, 10).unwrap().total, 0);
+
+            let page = dao.query(&AuditLogFilter::default(), 2, 3).unwrap();
+            assert_eq!(page.total, 4);
+            assert_eq!(page.total_pages, 2);
+            assert_eq!(page.items.len(), 1);
+            assert_eq!(page.items[0].id, "a1");
+        }
+
+        #[test]
+        fn test_audit_log_query_filter_combinations() {
+            let connection = create_test_connection();
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, created_at) VALUES
+                     ('a1', 'u1', 'view_patient', 'patient', 'p1', '2024-03-01T08:00:00.000000Z'),
+                     ('a2', 'u2', 'view_patient', 'patient', 'p1', '2024-03-01T09:00:00.000000Z'),
+                     ('a3', 'u2', 'update_patient', 'patient', 'p1', '2024-03-02T09:00:00.000000Z'),
+                     ('a4', 'u1', 'view_patient', 'patient', 'p2', '2024-03-02T10:00:00.000000Z'),
+                     ('a5', 'u1', 'view_patient', 'consultation', 'p1', '2024-03-02T11:00:00.000000Z');"
+            ).unwrap();
+            let dao = AuditLogDao::with_connection(connection);
+            let ids = |filter: AuditLogFilter| -> Vec<String> {
+                dao.query(&filter, 1, 10).unwrap().items.into_iter().map(|log| log.id).collect()
+            };
+            let patient_p1 = || AuditLogFilter {
+                resource_type: Some("patient".to_string()),
+                resource_id: Some("p1".to_string()),
+                ..Default::default()
+            };
+
+            // 同一 ID 的其他类型资源不算在内
+            assert_eq!(ids(patient_p1()), vec!["a3", "a2", "a1"]);
+            assert_eq!(
+                ids(AuditLogFilter { user_id: Some("u2".to_string()), ..patient_p1() }),
+                vec!["a3", "a2"]
+            );
+            assert_eq!(
+                ids(AuditLogFilter { action: Some("view_patient".to_string()), ..patient_p1() }),
+                vec!["a2", "a1"]
+            );
+            assert_eq!(
+                ids(AuditLogFilter {
+                    start_time: Some(Utc.with_ymd_and_hms(2024, 3, 1, 8, 30, 0).unwrap()),
+                    end_time: Some(Utc.with_ymd_and_hms(2024, 3, 1, 23, 59, 59).unwrap()),
+                    ..patient_p1()
+                }),
+                vec!["a2"]
+            );
+            assert_eq!(
+                ids(AuditLogFilter {
+                    user_id: Some("u1".to_string()),
+                    action: Some("view_patient".to_string()),
+                    start_time: Some(Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap()),
+                    ..Default::default()
+                }),
+                vec!["a5", "a4"]
+            );
+            assert_eq!(
+                ids(AuditLogFilter {
+                    end_time: Some(Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap()),
+                    ..patient_p1()
+                }),
+                vec!["a1"]
+            );
+            assert!(ids(AuditLogFilter { user_id: Some("u3".to_string()), ..patient_p1() }).is_empty());
+
+            let page = dao.query(&patient_p1(), 2, 2).unwrap();
+            assert_eq!(page.total, 3);
+            assert_eq!(page.total_pages, 2);
+            assert_eq!(page.items.len(), 1);
+            assert_eq!(page.items[0].id, "a1");
+            assert_eq!(dao.count_filtered(&patient_p1()).unwrap(), 3);
+        }
+
+        #[test]
+        fn test_patient_search_through_builder() {
+            let connection = create_test_connection();
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name, gender, age) VALUES
+                     ('p1', '张三', 'male', 30), ('p2', '张三丰', 'male', 90), ('p3', '张丽', 'female', 30);"
+            ).unwrap();
+            let dao = PatientDao::with_connection(connection);
+
+            let query = PatientQuery {
+                keyword: Some("张三".to_string()),
+                tags: None,
+                gender: None,
+                age_range: Some(AgeRange { min: 20, max: 40 }),
+                last_visit_range: None,
+                sort: None,
+                page: 1,
+                page_size: 10,
+            };
+            let page = dao.query_patients(&query).unwrap();
+            assert_eq!(page.total, 1);
+            assert_eq!(page.items[0].id, "p1");
+
+            let query = PatientQuery {
+                keyword: Some("' OR 1=1 --".to_string()),
+                age_range: None,
+                ..query
+            };
+            assert_eq!(dao.query_patients(&query).unwrap().total, 0);
+        }
+
+        #[test]
+        fn test_patient_list_sort_options() {
+            let connection = create_test_connection();
+            let dao = PatientDao::with_connection(connection.clone());
+            let mut ids = std::collections::HashMap::new();
+            for name in ["张伟", "阿强", "王芳", "李娜", "赵敏"] {
+                let now = Utc::now();
+                let patient = Patient {
+                    id: String::new(),
+                    name: name.to_string(),
+                    age: None,
+                    gender: None,
+                    phone: None,
+                    id_card: None,
+                    tags: vec![],
+                    avatar_url: None,
+                    last_sync: None,
+                    last_visit: None,
+                    created_at: now,
+                    updated_at: now,
+                    version: 1,
+                };
+                ids.insert(name, dao.create(&patient).unwrap());
+            }
+
+            let sorted = |field: &str, order: SortOrder| -> Vec<String> {
+                let query = PatientQuery {
+                    keyword: None,
+                    tags: None,
+                    gender: None,
+                    age_range: None,
+                    last_visit_range: None,
+                    sort: Some(SortParams { field: field.to_string(), order }),
+                    page: 1,
+                    page_size: 10,
+                };
+                dao.query_patients(&query).unwrap().items.into_iter().map(|p| p.name).collect()
+            };
+
+            // 按拼音而不是 Unicode 码位排序
+            assert_eq!(sorted("name", SortOrder::Asc), vec!["阿强", "李娜", "王芳", "张伟", "赵敏"]);
+            assert_eq!(sorted("name", SortOrder::Desc), vec!["赵敏", "张伟", "王芳", "李娜", "阿强"]);
+
+            // 问诊完成时维护最近就诊时间，未就诊的患者排在最后；李娜的就诊来自迁移回填的历史数据
+            connection.lock().unwrap().execute_batch(&format!(
+                "INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', '{}', 'd1', 'active');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, completed_at) VALUES
+                     ('c2', '{}', 'd1', 'completed', '2024-01-01 09:00:00+00:00');
+                 UPDATE patients SET last_visit = '2024-01-01 09:00:00+00:00' WHERE id = '{}';",
+                ids["王芳"], ids["李娜"], ids["李娜"]
+            )).unwrap();
+            let completed = ConsultationDao::with_connection(connection.clone())
+                .transition_status(&consultation_dao::StatusTransition {
+                    consultation_id: "c1",
+                    from: "active",
+                    to: "completed",
+                    action: "complete_consultation",
+                    user_id: Some("d1"),
+                    diagnosis: None,
+                    prescription: None,
+                    cancel_reason: None,
+                    notices: &[],
+                })
+                .unwrap();
+            assert!(completed);
+            assert!(dao.find_by_id(&ids["王芳"]).unwrap().unwrap().last_visit.is_some());
+
+            let by_visit = sorted("lastVisit", SortOrder::Desc);
+            assert_eq!(&by_visit[..2], ["王芳", "李娜"]);
+            let by_visit = sorted("lastVisit", SortOrder::Asc);
+            assert_eq!(&by_visit[..2], ["李娜", "王芳"]);
+
+            // 改名后排序键同步更新
+            let mut patient = dao.find_by_id(&ids["赵敏"]).unwrap().unwrap();
+            patient.name = "艾米".to_string();
+            dao.update(&patient).unwrap();
+            assert_eq!(sorted("name", SortOrder::Asc)[..2], ["阿强", "艾米"]);
+
+            assert_eq!(sorted("createdAt", SortOrder::Asc).len(), 5);
+        }
+    }
+
+    // 候诊队列排序测试
+    mod consultation_queue_tests {
+        use super::*;
+        use crate::database::dao::change_log_dao::subscribe_data_changes;
+        use crate::database::dao::{BaseDao, ConsultationDao};
+
+        fn seed(connection: &Arc<Mutex<Connection>>) {
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三'), ('p2', '李四');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, priority, created_at, queued_at) VALUES
+                     ('q-normal-old', 'p1', 'd1', 'pending', 'text', 'normal', '2024-03-01 08:00:00', '2024-03-01 08:00:00'),
+                     ('q-normal-new', 'p2', 'd1', 'pending', 'text', 'normal', '2024-03-01 08:30:00', '2024-03-01 08:30:00'),
+                     ('q-urgent-new', 'p1', 'd1', 'pending', 'text', 'urgent', '2024-03-01 09:10:00', '2024-03-01 09:10:00'),
+                     ('q-urgent-old', 'p2', 'd1', 'active', 'text', 'urgent', '2024-03-01 09:00:00', '2024-03-01 09:00:00'),
+                     ('q-critical', 'p1', 'd1', 'pending', 'text', 'critical', '2024-03-01 09:50:00', '2024-03-01 09:50:00'),
+                     ('q-done', 'p2', 'd1', 'completed', 'text', 'critical', '2024-03-01 07:00:00', '2024-03-01 07:00:00'),
+                     ('q-other', 'p2', 'd2', 'pending', 'text', 'critical', '2024-03-01 07:00:00', '2024-03-01 07:00:00');"
+            ).unwrap();
+        }
+
+        fn queue_ids(dao: &ConsultationDao) -> Vec<String> {
+            dao.get_active_consultations("d1").unwrap().into_iter().map(|c| c.id).collect()
+        }
+
+        #[test]
+        fn test_active_consultations_ordered_by_priority_then_wait() {
+            let connection = create_test_connection();
+            seed(&connection);
+            let dao = ConsultationDao::with_connection(connection.clone());
+
+            assert_eq!(
+                queue_ids(&dao),
+                vec!["q-critical", "q-urgent-old", "q-urgent-new", "q-normal-old", "q-normal-new"]
+            );
+            let critical = dao.get_active_consultations("d1").unwrap().remove(0);
+            assert_eq!(critical.priority, ConsultationPriority::Critical);
+            assert_eq!(critical.queued_since().to_rfc3339(), "2024-03-01T09:50:00+00:00");
+        }
+
+        #[test]
+        fn test_reprioritization_reorders_queue_and_publishes_change() {
+            let connection = create_test_connection();
+            seed(&connection);
+            let dao = ConsultationDao::with_connection(connection.clone());
+            let mut changes = subscribe_data_changes();
+
+            assert!(dao.set_priority("q-normal-new", ConsultationPriority::Urgent).unwrap());
+            // 同一优先级内等待更久的排在前面
+            assert_eq!(
+                queue_ids(&dao),
+                vec!["q-critical", "q-normal-new", "q-urgent-old", "q-urgent-new", "q-normal-old"]
+            );
+            let updated = dao.find_by_id("q-normal-new").unwrap().unwrap();
+            assert_eq!(updated.priority, ConsultationPriority::Urgent);
+            assert_eq!(updated.version, 2);
+
+            // 其他测试也可能在发布变更，只看本问诊的
+            let change = loop {
+                let change = changes.try_recv().expect("priority change not published");
+                if change.id == "q-normal-new" {
+                    break change;
+                }
+            };
+            assert_eq!(change.entity, ChangeEntity::Consultation);
+            assert_eq!(change.op, ChangeOp::Update);
+
+            // 优先级没有变化时不更新也不发布
+            assert!(!dao.set_priority("q-normal-new", ConsultationPriority::Urgent).unwrap());
+            assert_eq!(dao.find_by_id("q-normal-new").unwrap().unwrap().version, 2);
+            while let Ok(change) = changes.try_recv() {
+                assert_ne!(change.id, "q-normal-new");
+            }
+            assert!(!dao.set_priority("missing", ConsultationPriority::Critical).unwrap());
+        }
+    }
+
+    // 时间格式统一测试：旧版本写入的各种格式经迁移改写后，查询、排序和按天数清理都按真实时间进行
+    mod timestamp_tests {
+        use super::*;
+        use crate::database::dao::{AuditLogDao, FileCacheDao, MessageDao};
+        use crate::utils::SqlTimestamp;
+        use chrono::{DateTime, Duration, FixedOffset, TimeZone};
+        use rusqlite::params;
+
+        const NORMALIZE_SQL: &str = include_str!("../../migrations/038_normalize_timestamps.sql");
+
+        fn at(value: &str) -> DateTime<Utc> {
+            DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+        }
+
+        fn seed_consultation(conn: &Connection) {
+            conn.execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');"
+            ).unwrap();
+        }
+
+        fn insert_message(conn: &Connection, id: &str, timestamp: &dyn rusqlite::ToSql) {
+            conn.execute(
+                "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp)
+                 VALUES (?1, 'c1', 'patient', 'text', 'hi', ?2)",
+                params![id, timestamp],
+            ).unwrap();
+        }
+
+        fn insert_audit_log(conn: &Connection, id: &str, created_at: &dyn rusqlite::ToSql) {
+            conn.execute(
+                "INSERT INTO audit_logs (id, user_id, action, created_at) VALUES (?1, 'u1', 'view_patient', ?2)",
+                params![id, created_at],
+            ).unwrap();
+        }
+
+        fn column_values(conn: &Connection, sql: &str) -> Vec<String> {
+            let mut stmt = conn.prepare(sql).unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+            rows.collect::<rusqlite::Result<Vec<String>>>().unwrap()
+        }
+
+        #[test]
+        fn test_migration_rewrites_legacy_formats() {
+            let connection = create_test_connection();
+            {
+                let conn = connection.lock().unwrap();
+                seed_consultation(&conn);
+                // 按字符串或类型排序时旧格式的先后与真实时间不一致
+                insert_message(&conn, "m-rfc3339", &"2024-03-01T08:00:00Z");
+                insert_message(&conn, "m-sqlite", &"2024-03-01 09:00:00");
+                insert_message(&conn, "m-offset", &"2024-03-01 18:30:00+08:00");
+                insert_message(&conn, "m-epoch", &1709290800i64);
+                insert_message(&conn, "m-chrono", &at("2024-03-01T11:30:00Z"));
+                insert_audit_log(&conn, "a-sqlite", &"2024-03-01 09:00:00");
+                insert_audit_log(&conn, "a-offset", &"2024-03-01 18:30:00+08:00");
+                insert_audit_log(&conn, "a-epoch-ms", &1709290800000i64);
+
+                conn.execute_batch(NORMALIZE_SQL).unwrap();
+
+                let stored = column_values(&conn, "SELECT timestamp FROM messages UNION ALL SELECT created_at FROM audit_logs");
+                for value in stored {
+                    assert_eq!(SqlTimestamp::parse(&value).unwrap().format(), value);
+                }
+            }
+
+            let messages = MessageDao::with_connection(connection.clone()).find_all_by_consultation_id("c1").unwrap();
+            let ids: Vec<_> = messages.iter().map(|m| m.id.as_str()).collect();
+            assert_eq!(ids, vec!["m-rfc3339", "m-sqlite", "m-offset", "m-epoch", "m-chrono"]);
+            assert_eq!(messages[2].timestamp, at("2024-03-01T10:30:00Z"));
+            assert_eq!(messages[3].timestamp, at("2024-03-01T11:00:00Z"));
+
+            // 时间范围过滤与 SqlTimestamp 参数按同一格式比较
+            let dao = AuditLogDao::with_connection(connection);
+            let filter = AuditLogFilter {
+                start_time: Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap()),
+                ..Default::default()
+            };
+            let ids: Vec<_> = dao.query(&filter, 1, 10).unwrap().items.into_iter().map(|log| log.id).collect();
+            assert_eq!(ids, vec!["a-epoch-ms", "a-offset"]);
+        }
+
+        #[test]
+        fn test_cleanup_compares_real_time_after_migration() {
+            let connection = create_test_connection();
+            let now = Utc::now();
+            let sqlite_format = |t: DateTime<Utc>| t.format("%Y-%m-%d %H:%M:%S").to_string();
+            let east8 = FixedOffset::east_opt(8 * 3600).unwrap();
+            {
+                let conn = connection.lock().unwrap();
+                seed_consultation(&conn);
+                // 刚好在保留期内的旧格式记录与截止时间同一天，按字符串比较会被误删
+                insert_message(&conn, "m-expired", &(now - Duration::days(120)));
+                insert_message(&conn, "m-boundary", &sqlite_format(now - Duration::days(90) + Duration::hours(1)));
+                insert_message(&conn, "m-recent", &(now - Duration::days(1)).with_timezone(&east8).to_rfc3339());
+                insert_audit_log(&conn, "a-expired", &sqlite_format(now - Duration::days(40)));
+                insert_audit_log(&conn, "a-boundary", &(now - Duration::days(30) + Duration::hours(1)).timestamp());
+                conn.execute(
+                    "INSERT INTO file_cache (id, file_url, local_path, expires_at, downloaded_at, last_accessed) VALUES
+                         ('f-expired', 'https://cdn.example.com/a.png', '/tmp/a.png', ?1, ?2, ?2),
+                         ('f-valid', 'https://cdn.example.com/b.png', '/tmp/b.png', ?3, ?4, ?4)",
+                    params![
+                        sqlite_format(now - Duration::hours(1)),
+                        sqlite_format(now - Duration::days(40)),
+                        sqlite_format(now + Duration::hours(1)),
+                        sqlite_format(now - Duration::days(30) + Duration::hours(1))
+                    ],
+                ).unwrap();
+
+                conn.execute_batch(NORMALIZE_SQL).unwrap();
+
+                assert_eq!(MessageDao::delete_old_messages_in(&conn, 90).unwrap(), 1);
+                assert_eq!(AuditLogDao::cleanup_old_logs_in(&conn, 30).unwrap(), 1);
+                assert_eq!(column_values(&conn, "SELECT id FROM messages ORDER BY id"), vec!["m-boundary", "m-recent"]);
+                assert_eq!(column_values(&conn, "SELECT id FROM audit_logs"), vec!["a-boundary"]);
+            }
+
+            let dao = FileCacheDao::with_connection(connection);
+            let expired: Vec<_> = dao.find_expired_files().unwrap().into_iter().map(|f| f.id).collect();
+            assert_eq!(expired, vec!["f-expired"]);
+            assert_eq!(dao.get_cache_stats().unwrap().expired_files, 1);
+            let old: Vec<_> = dao.find_old_files(30).unwrap().into_iter().map(|f| f.id).collect();
+            assert_eq!(old, vec!["f-expired"]);
+            assert_eq!(dao.cleanup_expired(false).unwrap().len(), 1);
+            assert_eq!(dao.cleanup_old_files(30, false).unwrap(), 0);
+        }
+    }
+
+    // 乐观锁测试
+    mod concurrency_tests {
+        use super::*;
+        use crate::database::dao::{BaseDao, ConflictError, ConsultationDao};
+
+        #[test]
+        fn test_interleaved_consultation_updates() {
+            let connection = create_test_connection();
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type) VALUES ('c1', 'p1', 'd1', 'pending', 'text');"
+            ).unwrap();
+            let dao = ConsultationDao::with_connection(connection.clone());
+
+            let mut window_a = dao.find_by_id("c1").unwrap().unwrap();
+            let mut window_b = dao.find_by_id("c1").unwrap().unwrap();
+            assert_eq!(window_a.version, 1);
+
+            window_a.description = Some("头痛三天".to_string());
+            dao.update(&window_a).unwrap();
+
+            window_b.title = Some("复诊".to_string());
+            let err = dao.update(&window_b).unwrap_err();
+            assert!(err.downcast_ref::<ConflictError>().is_some());
+
+            // 状态更新使用过期版本同样被拒绝
+            assert!(dao.update_status("c1", "active", window_b.version).is_err());
+            let current = dao.find_by_id("c1").unwrap().unwrap();
+            assert_eq!(current.description.as_deref(), Some("头痛三天"));
+            assert_eq!(current.title, None);
+            assert_eq!(current.status, "pending");
+
+            assert_eq!(dao.update_status("c1", "active", current.version).unwrap(), 3);
+            assert_eq!(dao.find_by_id("c1").unwrap().unwrap().version, 3);
+        }
     }
 
     // 性能测试
@@ -250,6 +1224,101 @@ mod tests {
             assert_eq!(count, 100);
         }
 
+        #[test]
+        fn test_bulk_insert_messages_idempotent() {
+            use crate::database::dao::MessageDao;
+
+            let connection = create_test_connection();
+            connection.lock().unwrap().execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type) VALUES ('c1', 'p1', 'd1', 'active', 'text');"
+            ).unwrap();
+            let dao = MessageDao::with_connection(connection.clone());
+
+            let base = Utc::now();
+            let messages: Vec<Message> = (0..5000)
+                .map(|i| Message {
+                    id: format!("srv-msg-{}", i),
+                    consultation_id: "c1".to_string(),
+                    sender_type: if i % 2 == 0 { SenderType::Patient } else { SenderType::Doctor },
+                    message_type: MessageType::Text,
+                    content: Some(format!("历史消息{}", i)),
+                    file_path: None,
+                    file_size: None,
+                    mime_type: None,
+                    timestamp: base + chrono::Duration::seconds(i),
+                    sync_status: SyncStatus::Synced,
+                    read_status: ReadStatus::Read,
+                    template_id: None,
+                    duration_ms: None,
+                    waveform: None,
+                    pinned: false,
+                })
+                .collect();
+
+            let start = std::time::Instant::now();
+            let inserted = dao.bulk_insert(&messages).unwrap();
+            let duration = start.elapsed();
+            println!("批量写入5000条消息耗时: {:?}", duration);
+            assert_eq!(inserted, 5000);
+            assert!(duration.as_secs() < 10);
+
+            // 重复同步不会产生重复消息，也不会覆盖本地已有记录
+            connection.lock().unwrap().execute(
+                "UPDATE messages SET read_status = 'unread' WHERE id = 'srv-msg-0'",
+                [],
+            ).unwrap();
+            assert_eq!(dao.bulk_insert(&messages).unwrap(), 0);
+
+            let conn = connection.lock().unwrap();
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0)).unwrap();
+            assert_eq!(count, 5000);
+            let read_status: String = conn
+                .query_row("SELECT read_status FROM messages WHERE id = 'srv-msg-0'", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(read_status, "unread");
+        }
+
+        #[test]
+        fn test_bulk_upsert_patients() {
+            use crate::database::dao::PatientDao;
+
+            let connection = create_test_connection();
+            let dao = PatientDao::with_connection(connection.clone());
+            let now = Utc::now();
+            let mut patients: Vec<Patient> = (0..1200)
+                .map(|i| Patient {
+                    id: format!("srv-patient-{}", i),
+                    name: format!("患者{}", i),
+                    age: Some(20 + (i % 60)),
+                    gender: Some("male".to_string()),
+                    phone: Some(format!("1380013{:04}", i)),
+                    id_card: None,
+                    tags: vec![],
+                    avatar_url: None,
+                    last_sync: Some(now),
+                    last_visit: None,
+                    created_at: now,
+                    updated_at: now,
+                    version: 1,
+                })
+                .collect();
+
+            assert_eq!(dao.bulk_upsert(&patients).unwrap(), 1200);
+
+            patients[0].name = "患者0（已更名）".to_string();
+            dao.bulk_upsert(&patients).unwrap();
+
+            let conn = connection.lock().unwrap();
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM patients", [], |row| row.get(0)).unwrap();
+            assert_eq!(count, 1200);
+            let (name, version): (String, i64) = conn
+                .query_row("SELECT name, version FROM patients WHERE id = 'srv-patient-0'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap();
+            assert_eq!(name, "患者0（已更名）");
+            assert_eq!(version, 2);
+        }
+
         #[test]
         fn test_search_performance() {
             let connection = create_test_connection();
@@ -9,54 +9,196 @@ mod utils;
 
 use commands::*;
 use commands::window::WindowManagerState;
-use commands::websocket::WebSocketManagerState;
+use commands::websocket::{EventDispatcherState, WebSocketManagerState};
 use commands::security::SecurityServiceState;
-use services::{WebSocketManager, SecurityService};
+use commands::permission::PermissionServiceState;
+use commands::auth::TokenRefreshServiceState;
+use commands::consultation::ConsultationExpiryServiceState;
+use commands::message::{MetricsServiceState, OutboxDispatcherState};
+use commands::database::{DatabaseReadinessState, OfflineStateServiceState, SyncSchedulerState};
+use commands::file::DownloadManagerState;
+use database::dao::change_log_dao::DATA_CHANGED_EVENT;
+use commands::rate_limit::CommandRateLimiterState;
+use commands::health::DeviceInfoState;
+use models::{AppConfig, MaintenanceTrigger};
+use services::{WebSocketManager, SecurityService, PermissionService, ConfiguredAuthRefresher, TokenRefreshService, TokenRefreshConfig, TokenRefreshEvent, NotificationRouter, NotificationRouterState};
+use services::{
+    OfflineStateService, SyncScheduler, SyncProgressEvent, SessionSyncRunner, NetworkProbe, CONNECTIVITY_CHANGED_EVENT,
+    SYNC_PROGRESS_EVENT, SYNC_REPORT_EVENT,
+};
+use services::{ChunkedUploadManager, DownloadManager, FileService, DEFAULT_MAX_CONCURRENT_DOWNLOADS, DOWNLOAD_PROGRESS_EVENT};
+use services::DeviceInfoService;
+use services::{ConsultationExpiryService, CONSULTATION_EXPIRING_EVENT};
+use services::{MetricsService, OutboxDispatcher, WebSocketTransport};
+use services::{AppResumeTarget, ResumeMonitor, APP_RESUMED_EVENT};
 use std::sync::Arc;
+use tauri::Emitter;
 use tokio::sync::Mutex;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // 设备信息用于补全审计日志的来源 IP 和设备描述
+    let device_info: DeviceInfoState = Arc::new(DeviceInfoService::collect(env!("CARGO_PKG_VERSION")));
+    // 数据库此时尚未初始化，已保存的自动锁屏时间在数据库就绪后热更新
+    let mut security = SecurityService::new(AppConfig::default().auto_lock_timeout).with_device_info(device_info.as_ref().clone());
+    // 钥匙串不可用时不使用临时密钥，初始化在日志就绪后标记为失败
+    let data_key_error = match utils::keychain::local_data_crypto() {
+        Ok(crypto) => {
+            security = security.with_crypto(crypto);
+            None
+        }
+        Err(e) => Some(format!("Failed to load data key from keychain: {}", e)),
+    };
+    let security_service: SecurityServiceState = Arc::new(Mutex::new(security));
+    let (token_refresh_service, mut token_refresh_events) = TokenRefreshService::new(
+        Arc::new(ConfiguredAuthRefresher),
+        TokenRefreshConfig::default(),
+    );
+
     tauri::Builder::default()
+        .register_uri_scheme_protocol(services::AVATAR_SCHEME, |ctx, request| {
+            commands::file::avatar_protocol_response(ctx.app_handle(), &request)
+        })
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .manage(WindowManagerState::default())
+        .manage(device_info)
+        .manage(Arc::new(database::DatabaseReadiness::new()) as DatabaseReadinessState)
         .manage(Arc::new(Mutex::new(WebSocketManager::new())) as WebSocketManagerState)
-        .manage(Arc::new(Mutex::new(SecurityService::new(300))) as SecurityServiceState) // 5分钟自动锁屏
+        .manage(security_service.clone())
+        .manage(Arc::new(Mutex::new(PermissionService::new(security_service))) as PermissionServiceState)
+        .manage(Arc::new(Mutex::new(token_refresh_service)) as TokenRefreshServiceState)
+        .manage(Arc::new(std::sync::Mutex::new(NotificationRouter::new())) as NotificationRouterState)
+        // 先使用内置限流规则，数据库就绪后叠加已保存配置中的 commandRateLimits
+        .manage(Arc::new(std::sync::Mutex::new(services::CommandRateLimiter::new())) as CommandRateLimiterState)
+        .manage(FileService::new())
+        .manage(ChunkedUploadManager::new())
         .invoke_handler(tauri::generate_handler![
             // 认证相关命令
             auth_login,
             auth_logout,
+            try_restore_session,
             auth_refresh_token,
             auth_validate_session,
+            request_sms_code,
+            get_session_status,
+            get_my_permissions,
 
             // 患者管理命令
             get_patient_list,
+            get_changes_since,
             get_patient_detail,
+            get_patient_timeline,
             update_patient_tags,
+            get_patient_revisions,
+            revert_patient_field,
             search_patients,
+            reveal_patient_field,
+            get_all_tags,
+            rename_patient_tag,
+            merge_patient_tags,
+            parse_id_card,
+            migrate_encrypt_patient_fields,
+            import_patients,
+            export_patient_bundle,
+            import_patient_bundle,
+
+            // 问诊流程命令
+            accept_consultation,
+            complete_consultation,
+            cancel_consultation,
+            get_consultation_queue,
+            get_conversation_list,
+            get_consultation_metrics,
+            transfer_consultation,
+            get_consultation_transfers,
+            get_transfer_candidates,
+            search_doctors,
+            set_consultation_priority,
+            get_intake_form,
+            keep_alive_consultation,
+            export_consultation_transcript,
+            save_consultation_note,
+            get_consultation_note,
+            delete_consultation_note,
+
+            // 处方相关命令
+            save_prescription_draft,
+            issue_prescription,
+            void_prescription,
+            get_consultation_prescriptions,
+            generate_prescription_pdf,
+
+            // 病历命令
+            create_medical_record,
+            update_medical_record,
+            delete_medical_record,
+            restore_medical_record,
+            create_record_template,
+            list_record_templates,
+            render_record_template,
+            delete_record_template,
 
             // 消息相关命令
             send_message,
             get_message_history,
+            search_in_consultation,
+            delete_message,
+            pin_message,
+            unpin_message,
+            get_pinned_messages,
+            restore_message,
+            get_message_raw_content,
+            list_trash,
             upload_file,
+            begin_file_upload,
+            append_file_upload_chunk,
+            finish_file_upload,
+            cancel_file_upload,
             mark_messages_as_read,
             get_unread_message_count,
+            save_message_draft,
+            get_message_draft,
+            clear_message_draft,
             sync_pending_messages,
+            create_message_template,
+            update_message_template,
+            delete_message_template,
+            list_message_templates,
+            list_sensitive_words,
+            add_sensitive_word,
+            remove_sensitive_word,
+            get_message_latency_metrics,
+            reset_message_latency_metrics,
+            clear_message_outbox,
 
             // 窗口管理命令
             create_new_window,
+            restore_previous_windows,
+            reconcile_windows,
+            open_or_focus_consultation_window,
+
+            // 消息通知命令
+            get_do_not_disturb,
+            set_do_not_disturb,
+            clear_unread_badge,
             close_window_by_id,
             focus_window_by_id,
             get_all_windows,
             get_window_info,
             update_window_data,
+            update_window_context,
             get_resource_usage,
             check_window_limits,
             minimize_window,
             maximize_window,
+            apply_window_layout,
+            get_window_layout_preset,
+            set_window_pinned,
 
             // 文件管理命令
             save_file_locally,
+            validate_upload_candidate,
             read_file_from_local,
             file_exists,
             delete_local_file,
@@ -75,7 +217,12 @@ pub fn run() {
             get_file_cache_statistics,
             get_cache_file_list,
             clear_all_file_cache,
+            get_pinned_files,
             warmup_file_cache,
+            download_file,
+            cancel_download,
+            get_download_queue,
+            get_attachment_usage,
             update_file_cache_record,
             delete_file_cache_record,
             get_file_cache_info,
@@ -83,7 +230,35 @@ pub fn run() {
 
             // 数据库相关命令
             init_database,
+            get_init_status,
+            get_database_config,
+            set_database_encryption,
             sync_data,
+            pause_background_sync,
+            resume_background_sync,
+            get_sync_status,
+            get_offline_state,
+            get_query_stats,
+            get_slow_queries,
+            clear_query_stats,
+            check_enum_columns,
+            get_retention_policy,
+            update_retention_policy,
+            run_retention_now,
+            run_integrity_repair,
+
+            // 健康检查命令
+            get_app_health,
+            get_device_info,
+            get_rate_limit_stats,
+            export_diagnostics_bundle,
+
+            // 应用配置命令
+            get_app_config,
+            update_app_config,
+            set_locale,
+            export_workstation_profile,
+            import_workstation_profile,
 
             // WebSocket 相关命令
             create_websocket_connection,
@@ -95,32 +270,377 @@ pub fn run() {
             unsubscribe_from_consultation,
             send_read_receipt,
             send_typing_status,
+            get_recent_ws_events,
 
             // 安全相关命令
             encrypt_sensitive_data,
             decrypt_sensitive_data,
             log_audit,
             get_audit_logs,
+            query_audit_logs,
+            get_resource_audit_logs,
+            export_audit_logs,
             detect_anomalies,
             record_failed_login,
             reset_failed_login,
+            record_user_interaction,
+            purge_all_session_data,
             should_auto_lock,
+            set_unlock_pin,
+            unlock_with_pin,
+            clear_unlock_pin,
             get_last_activity,
             get_anomaly_records,
+            get_security_config,
+            update_security_config,
             resolve_anomaly,
             cleanup_old_security_records,
+            get_recent_logs,
+            set_log_level,
+
+            // 应用更新相关命令
+            check_for_update,
+            download_update,
         ])
-        .setup(|app| {
-            // 初始化数据库
+        .setup(move |app| {
+            // 日志写入应用数据目录下的 logs 目录
+            let mut unclean_shutdown = false;
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    if let Err(e) = utils::logging::init_logging(&dir.join("logs")) {
+                        eprintln!("Failed to initialize logging: {}", e);
+                    }
+                    unclean_shutdown = services::mark_running(&dir);
+                }
+                Err(e) => eprintln!("Failed to resolve app data dir: {}", e),
+            }
+
+            // 读取上次的窗口布局并登记主窗口
+            commands::window::load_saved_windows(app.handle());
+            commands::window::register_existing_window(app.handle(), "main", "main");
+
+            // 启动进度转发到前端，窗口在初始化期间即可显示进度
+            let app_handle = app.handle().clone();
+            let mut init_progress = app.state::<DatabaseReadinessState>().subscribe();
+            tauri::async_runtime::spawn(async move {
+                while init_progress.changed().await.is_ok() {
+                    let status = init_progress.borrow_and_update().clone();
+                    if let Err(e) = app_handle.emit(database::INIT_PROGRESS_EVENT, &status) {
+                        tracing::warn!("Failed to emit {} event: {}", database::INIT_PROGRESS_EVENT, e);
+                    }
+                }
+            });
+
+            // 初始化数据库：只有打开和迁移阻塞命令，其余工作在就绪后进行
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let readiness = app_handle.state::<DatabaseReadinessState>().inner().clone();
+                if let Some(e) = data_key_error {
+                    readiness.mark_failed(e);
+                    return;
+                }
+                if let Err(e) = database::init_database(&app_handle, &readiness).await {
+                    readiness.mark_failed(e.to_string());
+                    return;
+                }
+
+                // 审计日志持久化及异常检测规则依赖数据库
+                let connection = database::get_database().get_connection();
+                if let Err(e) = app_handle
+                    .state::<SecurityServiceState>()
+                    .lock()
+                    .await
+                    .attach_database(connection)
+                {
+                    tracing::error!("Failed to load security config: {}", e);
+                }
+
+                // 应用已保存的配置中可热更新的部分
+                match services::AppSettingsService::new().load() {
+                    Ok(config) => {
+                        let keys = [
+                            "windowLimits".to_string(),
+                            "autoLockTimeout".to_string(),
+                            "locale".to_string(),
+                            "timezone".to_string(),
+                            "commandRateLimits".to_string(),
+                        ];
+                        commands::settings::apply_hot_reload(
+                            &config,
+                            &keys,
+                            &app_handle.state::<WindowManagerState>(),
+                            &app_handle.state::<SecurityServiceState>(),
+                            &app_handle.state::<CommandRateLimiterState>(),
+                        )
+                        .await;
+                    }
+                    Err(e) => tracing::error!("Failed to load app config: {}", e),
+                }
+
+                // 以下为非关键工作，命令此时已可访问数据库
+                readiness.set_phase(database::InitPhase::Cleanup);
+                if unclean_shutdown {
+                    tracing::warn!("Previous run did not exit cleanly, running integrity repair");
+                    let repair = services::IntegrityRepairService::new(
+                        app_handle.state::<SecurityServiceState>().inner().clone(),
+                    );
+                    if let Err(e) = repair.run(MaintenanceTrigger::UncleanShutdown).await {
+                        tracing::error!("Startup integrity repair failed: {}", e);
+                    }
+                }
+                if let Err(e) = database::get_database().cleanup_expired_cache() {
+                    tracing::warn!("Failed to clean up expired cache entries: {}", e);
+                }
+
+                readiness.set_phase(database::InitPhase::WarmCaches);
+                database::get_query_optimizer();
+                if let Err(e) = database::get_database().warm_up() {
+                    tracing::warn!("Failed to warm up database cache: {}", e);
+                }
+                readiness.set_phase(database::InitPhase::Completed);
+
+                // 预热最近活跃问诊的消息，在后台逐个进行，不阻塞后续启动工作
+                let warmup_handle = app_handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    commands::message::warm_recent_messages(&warmup_handle).await;
+                });
+
+                // 每日按保留策略清理旧数据
+                let retention = Arc::new(services::RetentionService::new(
+                    app_handle.state::<SecurityServiceState>().inner().clone(),
+                    commands::database::retention_backup_dir(&app_handle),
+                ));
+                retention.run_daily().await;
+            });
+
+            // 推送事件按问诊分队列依次落库，再按窗口状态路由到问诊窗口或系统通知
+            let dispatcher: EventDispatcherState = Arc::new(services::ConsultationEventDispatcher::new(Arc::new(
+                services::AppEventHandler::new(app.handle().clone()),
+            )));
+            app.manage(dispatcher.clone());
+            let closing_dispatcher = dispatcher.clone();
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+                app_handle
+                    .state::<WebSocketManagerState>()
+                    .lock()
+                    .await
+                    .add_event_handler(sender)
+                    .await;
+
+                while let Some(event) = receiver.recv().await {
+                    dispatcher.dispatch(event);
+                }
+            });
+
+            // 问诊状态变化时刷新对应窗口标题，结束后切换为只读并释放事件队列
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut status_changes = database::dao::consultation_dao::subscribe_status_changes();
+                loop {
+                    match status_changes.recv().await {
+                        Ok(change) => {
+                            commands::window::apply_consultation_status(
+                                &app_handle,
+                                &app_handle.state::<WindowManagerState>(),
+                                &change.consultation_id,
+                                &change.status,
+                            );
+                            // 问诊结束后不再有推送，释放其事件队列
+                            if matches!(
+                                models::ConsultationStatus::parse(&change.status),
+                                Some(models::ConsultationStatus::Completed | models::ConsultationStatus::Cancelled)
+                            ) {
+                                closing_dispatcher.close_consultation(&change.consultation_id);
+                            }
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                            tracing::warn!("Missed {} consultation status changes", skipped);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // 患者、问诊变更转发给所有窗口增量更新列表；窗口发现 seq 不连续时调用 get_changes_since 补齐
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut data_changes = database::dao::change_log_dao::subscribe_data_changes();
+                loop {
+                    match data_changes.recv().await {
+                        Ok(change) => {
+                            if let Err(e) = app_handle.emit(DATA_CHANGED_EVENT, &change) {
+                                tracing::warn!("Failed to emit {} event: {}", DATA_CHANGED_EVENT, e);
+                            }
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                            tracing::warn!("Missed {} data changes", skipped);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // 后台定时同步，WebSocket 重新连上时立即同步
+            let schedule_config = commands::database::sync_schedule_path(app.handle())
+                .map(|path| services::load_schedule_config(&path))
+                .unwrap_or_default();
+            let websocket = app.state::<WebSocketManagerState>().inner().clone();
+            let (sync_scheduler, mut sync_events) = SyncScheduler::new(
+                Arc::new(SessionSyncRunner::new(app.state::<TokenRefreshServiceState>().inner().clone())),
+                Arc::new(NetworkProbe::new(websocket.clone())),
+                schedule_config,
+            );
+            let offline_probe = Arc::new(NetworkProbe::new(websocket.clone()));
+            let websocket_for_outbox = websocket.clone();
+            let sync_scheduler: SyncSchedulerState = Arc::new(sync_scheduler);
+            app.manage(sync_scheduler.clone());
+
+            // 休眠唤醒后依次恢复锁屏状态、会话、WebSocket 连接和同步，结果合并为一个事件通知前端
+            let (resume_monitor, mut resume_events) = ResumeMonitor::new(Arc::new(AppResumeTarget::new(
+                app.state::<SecurityServiceState>().inner().clone(),
+                app.state::<TokenRefreshServiceState>().inner().clone(),
+                websocket.clone(),
+                sync_scheduler.clone(),
+            )));
+            let resume_monitor = Arc::new(resume_monitor);
+            tauri::async_runtime::spawn(async move {
+                resume_monitor.start();
+            });
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(event) = resume_events.recv().await {
+                    if let Err(e) = app_handle.emit(APP_RESUMED_EVENT, &event) {
+                        tracing::warn!("Failed to emit {} event: {}", APP_RESUMED_EVENT, e);
+                    }
+                }
+            });
+
+            tauri::async_runtime::spawn(async move {
+                sync_scheduler.start();
+                services::watch_websocket_status(sync_scheduler, websocket).await;
+            });
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(event) = sync_events.recv().await {
+                    if let SyncProgressEvent::Completed { report } = &event {
+                        if let Some(downloads) = app_handle.try_state::<DownloadManagerState>() {
+                            let queued = FileService::new().enqueue_avatar_downloads(&downloads, &report.avatar_urls);
+                            if queued > 0 {
+                                tracing::info!("Queued {} patient avatars for download", queued);
+                            }
+                        }
+                        if let Err(e) = app_handle.emit(SYNC_REPORT_EVENT, report) {
+                            tracing::warn!("Failed to emit {} event: {}", SYNC_REPORT_EVENT, e);
+                        }
+                    }
+                    if let Err(e) = app_handle.emit(SYNC_PROGRESS_EVENT, &event) {
+                        tracing::warn!("Failed to emit {} event: {}", SYNC_PROGRESS_EVENT, e);
+                    }
+                }
+            });
+
+            // 离线提示：综合 WebSocket 和 HTTP 探测判断连通性，防抖后通知前端
+            let (offline_state, mut connectivity_events) = OfflineStateService::new(offline_probe);
+            let offline_state: OfflineStateServiceState = Arc::new(offline_state);
+            app.manage(offline_state.clone());
+            tauri::async_runtime::spawn(async move {
+                offline_state.start();
+            });
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(change) = connectivity_events.recv().await {
+                    if let Err(e) = app_handle.emit(CONNECTIVITY_CHANGED_EVENT, &change) {
+                        tracing::warn!("Failed to emit {} event: {}", CONNECTIVITY_CHANGED_EVENT, e);
+                    }
+                }
+            });
+
+            // 消息往返延迟指标，样本在数据库就绪后写入
+            let metrics: MetricsServiceState = Arc::new(MetricsService::new());
+            app.manage(metrics.clone());
+
+            // 消息发件箱：数据库就绪后启动，先重发上次运行未确认的消息
+            let outbox: OutboxDispatcherState = Arc::new(
+                OutboxDispatcher::new(Arc::new(WebSocketTransport::new(websocket_for_outbox)))
+                    .with_latency_recorder(metrics.recorder()),
+            );
+            app.manage(outbox.clone());
+            let readiness = app.state::<DatabaseReadinessState>().inner().clone();
+            tauri::async_runtime::spawn(async move {
+                if readiness.wait_ready(database::DATABASE_READY_TIMEOUT).await.is_ok() {
+                    metrics.start();
+                    outbox.start();
+                }
+            });
+
+            // 长时间无消息的问诊自动结束，超时前提醒医生
+            let (consultation_expiry, mut expiring_events) = ConsultationExpiryService::new();
+            let consultation_expiry: ConsultationExpiryServiceState = Arc::new(consultation_expiry);
+            app.manage(consultation_expiry.clone());
+            let readiness = app.state::<DatabaseReadinessState>().inner().clone();
+            tauri::async_runtime::spawn(async move {
+                if readiness.wait_ready(database::DATABASE_READY_TIMEOUT).await.is_ok() {
+                    consultation_expiry.start();
+                }
+            });
+
             let app_handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
-                if let Err(e) = database::init_database(&app_handle).await {
-                    eprintln!("Failed to initialize database: {}", e);
+                while let Some(expiry) = expiring_events.recv().await {
+                    if let Err(e) = app_handle.emit(CONSULTATION_EXPIRING_EVENT, &expiry) {
+                        tracing::warn!("Failed to emit {} event: {}", CONSULTATION_EXPIRING_EVENT, e);
+                    }
+                }
+            });
+
+            // 远程文件下载队列，进度转发到前端
+            let download_dir = commands::file::download_dir(app.handle())
+                .unwrap_or_else(|| std::env::temp_dir().join(commands::file::DOWNLOAD_DIR_NAME));
+            let (download_manager, mut download_events) =
+                DownloadManager::new(download_dir, DEFAULT_MAX_CONCURRENT_DOWNLOADS);
+            app.manage(Arc::new(download_manager) as DownloadManagerState);
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(event) = download_events.recv().await {
+                    if let Err(e) = app_handle.emit(DOWNLOAD_PROGRESS_EVENT, &event) {
+                        tracing::warn!("Failed to emit {} event: {}", DOWNLOAD_PROGRESS_EVENT, e);
+                    }
+                }
+            });
+
+            // 转发 token 刷新事件到前端
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(event) = token_refresh_events.recv().await {
+                    let event_name = match &event {
+                        TokenRefreshEvent::TokenRefreshed { .. } => "token-refreshed",
+                        TokenRefreshEvent::SessionExpiring { .. } => "session-expiring",
+                    };
+                    if let Err(e) = app_handle.emit(event_name, &event) {
+                        tracing::warn!("Failed to emit {} event: {}", event_name, e);
+                    }
                 }
             });
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            // 退出时窗口会逐个销毁，不应覆盖已保存的布局
+            if let tauri::RunEvent::ExitRequested { .. } = event {
+                app_handle.state::<WindowManagerState>().mark_exiting();
+            }
+            if let tauri::RunEvent::Exit = event {
+                if let Ok(dir) = app_handle.path().app_data_dir() {
+                    services::mark_clean_exit(&dir);
+                }
+            }
+        });
 }
@@ -2,6 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::utils::{deserialize_range_end, deserialize_range_start};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditLog {
@@ -38,8 +39,154 @@ pub struct AuditLogQuery {
     pub page_size: u32,
 }
 
+// 前端传来的时间范围（闭区间），只有日期或不带时区时按配置的时区理解
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DateRange {
+    #[serde(deserialize_with = "deserialize_range_start")]
     pub start: DateTime<Utc>,
+    #[serde(deserialize_with = "deserialize_range_end")]
     pub end: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+// 审计日志查询和导出的筛选条件，时间范围为闭区间
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuditLogFilter {
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+    pub action: Option<String>,
+    // 多个动作任一匹配；空列表不匹配任何日志
+    pub actions: Option<Vec<String>>,
+    #[serde(rename = "resourceType")]
+    pub resource_type: Option<String>,
+    // 仅与 resource_type 一起使用，例如查看某位患者的全部访问记录
+    #[serde(rename = "resourceId")]
+    pub resource_id: Option<String>,
+    #[serde(rename = "startTime")]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(rename = "endTime")]
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl AuditLogFilter {
+    // 写入导出文件头的筛选条件摘要
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(user_id) = &self.user_id {
+            parts.push(format!("user={}", user_id));
+        }
+        if let Some(action) = &self.action {
+            parts.push(format!("action={}", action));
+        }
+        if let Some(actions) = &self.actions {
+            parts.push(format!("actions=[{}]", actions.join(",")));
+        }
+        if let Some(resource_type) = &self.resource_type {
+            match &self.resource_id {
+                Some(resource_id) => parts.push(format!("resource={}/{}", resource_type, resource_id)),
+                None => parts.push(format!("resource={}", resource_type)),
+            }
+        }
+        if let Some(start) = &self.start_time {
+            parts.push(format!("from={}", start.to_rfc3339()));
+        }
+        if let Some(end) = &self.end_time {
+            parts.push(format!("to={}", end.to_rfc3339()));
+        }
+
+        if parts.is_empty() {
+            "all".to_string()
+        } else {
+            parts.join("; ")
+        }
+    }
+}
+// 短时间内查看大量不同患者
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BulkPatientViewRule {
+    pub enabled: bool,
+    #[serde(rename = "maxDistinctPatients")]
+    pub max_distinct_patients: usize,
+    #[serde(rename = "windowMinutes")]
+    pub window_minutes: i64,
+    pub severity: String,
+    // 达到阈值两倍时使用的级别
+    #[serde(rename = "escalatedSeverity")]
+    pub escalated_severity: String,
+}
+
+impl Default for BulkPatientViewRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_distinct_patients: 30,
+            window_minutes: 10,
+            severity: "medium".to_string(),
+            escalated_severity: "high".to_string(),
+        }
+    }
+}
+
+// 非工作时间访问患者病历，start_hour 到 end_hour 之间（本地时间，可跨零点）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OffHoursAccessRule {
+    pub enabled: bool,
+    #[serde(rename = "startHour")]
+    pub start_hour: u32,
+    #[serde(rename = "endHour")]
+    pub end_hour: u32,
+    // 为空时使用系统时区
+    #[serde(rename = "utcOffsetMinutes")]
+    pub utc_offset_minutes: Option<i32>,
+    #[serde(rename = "lookbackHours")]
+    pub lookback_hours: i64,
+    pub severity: String,
+}
+
+impl Default for OffHoursAccessRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            start_hour: 1,
+            end_hour: 5,
+            utc_offset_minutes: None,
+            lookback_hours: 24,
+            severity: "medium".to_string(),
+        }
+    }
+}
+
+// 一段时间内下载的文件总量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDownloadVolumeRule {
+    pub enabled: bool,
+    #[serde(rename = "maxMegabytes")]
+    pub max_megabytes: u64,
+    #[serde(rename = "windowMinutes")]
+    pub window_minutes: i64,
+    pub severity: String,
+    #[serde(rename = "escalatedSeverity")]
+    pub escalated_severity: String,
+}
+
+impl Default for FileDownloadVolumeRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_megabytes: 500,
+            window_minutes: 60,
+            severity: "high".to_string(),
+            escalated_severity: "critical".to_string(),
+        }
+    }
+}
+
+// 异常访问检测配置，保存在 security_config 表
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    #[serde(rename = "bulkPatientViews", default)]
+    pub bulk_patient_views: BulkPatientViewRule,
+    #[serde(rename = "offHoursAccess", default)]
+    pub off_hours_access: OffHoursAccessRule,
+    #[serde(rename = "fileDownloads", default)]
+    pub file_downloads: FileDownloadVolumeRule,
+}
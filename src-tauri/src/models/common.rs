@@ -2,6 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use crate::models::RetentionPolicy;
+use crate::utils::{system_timezone_id, Locale};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
@@ -30,6 +33,35 @@ pub struct PaginatedResponse<T> {
     pub total_pages: u32,
 }
 
+// 新建记录的行版本号，远端数据没有版本号时也按此处理
+pub const INITIAL_ROW_VERSION: i64 = 1;
+
+pub fn initial_row_version() -> i64 {
+    INITIAL_ROW_VERSION
+}
+
+// 数据库中枚举列出现了未知取值，读取时返回带列类型和原值的错误，而不是静默回退
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidEnumValue {
+    pub type_name: &'static str,
+    pub value: String,
+}
+
+impl std::fmt::Display for InvalidEnumValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "无效的{}取值: '{}'", self.type_name, self.value)
+    }
+}
+
+impl std::error::Error for InvalidEnumValue {}
+
+pub fn invalid_enum_value(type_name: &'static str, value: &str) -> rusqlite::types::FromSqlError {
+    rusqlite::types::FromSqlError::Other(Box::new(InvalidEnumValue {
+        type_name,
+        value: value.to_string(),
+    }))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppError {
     #[serde(rename = "type")]
@@ -55,6 +87,43 @@ pub enum ErrorType {
     UnknownError,
 }
 
+impl AppError {
+    pub fn new(error_type: ErrorType, message: impl Into<String>) -> Self {
+        Self {
+            error_type,
+            message: message.into(),
+            code: None,
+            details: None,
+            retryable: None,
+            retry_count: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.retryable = Some(retryable);
+        self
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
 impl std::fmt::Display for ErrorType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -123,7 +192,9 @@ pub struct CacheItem<T> {
     pub version: u32,
 }
 
+// 旧版本保存的配置缺少的字段使用 Default 中的值
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     #[serde(rename = "apiBaseUrl")]
     pub api_base_url: String,
@@ -141,6 +212,122 @@ pub struct AppConfig {
     pub retry_delay: u64, // milliseconds
     #[serde(rename = "windowLimits")]
     pub window_limits: WindowLimitsConfig,
+    #[serde(rename = "authProvider", default)]
+    pub auth_provider: AuthProviderKind,
+    #[serde(rename = "patientStalenessMinutes", default = "default_patient_staleness_minutes")]
+    pub patient_staleness_minutes: u64,
+    #[serde(rename = "updateManifestUrl", default = "default_update_manifest_url")]
+    pub update_manifest_url: String,
+    #[serde(rename = "autoLockTimeout")]
+    pub auto_lock_timeout: u64, // 秒
+    pub retention: RetentionPolicy,
+    // 进行中的问诊超过该时长双方都没有消息时自动结束
+    #[serde(rename = "consultationInactivityHours", default = "default_consultation_inactivity_hours")]
+    pub consultation_inactivity_hours: u64,
+    // 单个问诊和单个患者的附件总字节数上限，超过后拒绝上传和下载缓存
+    #[serde(rename = "maxConsultationAttachmentBytes", default = "default_max_consultation_attachment_bytes")]
+    pub max_consultation_attachment_bytes: u64,
+    #[serde(rename = "maxPatientAttachmentBytes", default = "default_max_patient_attachment_bytes")]
+    pub max_patient_attachment_bytes: u64,
+    // 后端校验和错误提示使用的语言
+    pub locale: Locale,
+    // 启动后预先加载最近活跃问诊的第一页消息
+    #[serde(rename = "messageWarmup", default)]
+    pub message_warmup: MessageWarmupConfig,
+    // 慢速网络下上传图片前自动压缩
+    #[serde(rename = "uploadCompression", default)]
+    pub upload_compression: UploadCompressionConfig,
+    // 打印处方笺等文书时的医院抬头
+    #[serde(default)]
+    pub hospital: HospitalProfileConfig,
+    // 导出文件、提醒和会话列表显示时间使用的 IANA 时区，默认取系统时区
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    // 按命令覆盖前端调用限流规则，未列出的命令使用内置默认值
+    #[serde(rename = "commandRateLimits", default)]
+    pub command_rate_limits: HashMap<String, CommandRateLimitConfig>,
+}
+
+fn default_patient_staleness_minutes() -> u64 {
+    30
+}
+
+fn default_consultation_inactivity_hours() -> u64 {
+    24
+}
+
+fn default_max_consultation_attachment_bytes() -> u64 {
+    200 * 1024 * 1024
+}
+
+fn default_max_patient_attachment_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_timezone() -> String {
+    system_timezone_id()
+}
+
+// 内网更新服务器上的版本清单地址
+fn default_update_manifest_url() -> String {
+    std::env::var("TELEMEDICINE_UPDATE_URL")
+        .unwrap_or_else(|_| "http://localhost:8080/updates/latest.json".to_string())
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            api_base_url: std::env::var("TELEMEDICINE_API_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:8080/api".to_string()),
+            ws_url: std::env::var("TELEMEDICINE_WS_URL")
+                .unwrap_or_else(|_| "ws://localhost:8080/ws".to_string()),
+            max_file_size: 50 * 1024 * 1024,
+            allowed_file_types: vec![
+                "image/jpeg".to_string(),
+                "image/png".to_string(),
+                "application/pdf".to_string(),
+            ],
+            cache_expiration: 24 * 60 * 60 * 1000,
+            retry_attempts: 3,
+            retry_delay: 1000,
+            window_limits: WindowLimitsConfig {
+                max_windows: 8,
+                max_consultation_windows: 5,
+            },
+            auth_provider: AuthProviderKind::default(),
+            patient_staleness_minutes: default_patient_staleness_minutes(),
+            update_manifest_url: default_update_manifest_url(),
+            auto_lock_timeout: 300,
+            retention: RetentionPolicy::default(),
+            consultation_inactivity_hours: default_consultation_inactivity_hours(),
+            max_consultation_attachment_bytes: default_max_consultation_attachment_bytes(),
+            max_patient_attachment_bytes: default_max_patient_attachment_bytes(),
+            locale: Locale::default(),
+            message_warmup: MessageWarmupConfig::default(),
+            upload_compression: UploadCompressionConfig::default(),
+            hospital: HospitalProfileConfig::default(),
+            timezone: default_timezone(),
+            command_rate_limits: HashMap::new(),
+        }
+    }
+}
+
+// 认证后端类型：开发环境使用模拟认证，发布版本调用医院接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthProviderKind {
+    Mock,
+    Http,
+}
+
+impl Default for AuthProviderKind {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            AuthProviderKind::Mock
+        } else {
+            AuthProviderKind::Http
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -151,6 +338,72 @@ pub struct WindowLimitsConfig {
     pub max_consultation_windows: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageWarmupConfig {
+    pub enabled: bool,
+    // 按最近活动时间取前几个问诊
+    #[serde(rename = "consultationCount")]
+    pub consultation_count: u32,
+}
+
+impl Default for MessageWarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            consultation_count: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadCompressionConfig {
+    pub enabled: bool,
+    // 最近几次上传的平均速度低于该值（kbit/s）时压缩
+    #[serde(rename = "slowUploadKbps")]
+    pub slow_upload_kbps: u32,
+    // 压缩后图片长边的最大像素
+    #[serde(rename = "maxDimension")]
+    pub max_dimension: u32,
+    // JPEG 重新编码的质量
+    pub quality: u8,
+}
+
+impl Default for UploadCompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            slow_upload_kbps: 1000,
+            max_dimension: 1920,
+            quality: 75,
+        }
+    }
+}
+
+// 单个命令的限流：最多连续调用 capacity 次，之后每秒恢复 refill_per_second 次
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CommandRateLimitConfig {
+    pub capacity: f64,
+    #[serde(rename = "refillPerSecond")]
+    pub refill_per_second: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HospitalProfileConfig {
+    pub name: String,
+    // 本地 PNG/JPEG 图片路径，未设置时只打印医院名称
+    #[serde(rename = "logoPath", default)]
+    pub logo_path: Option<String>,
+}
+
+impl Default for HospitalProfileConfig {
+    fn default() -> Self {
+        Self {
+            name: "互联网医院".to_string(),
+            logo_path: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
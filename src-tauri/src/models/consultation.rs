@@ -1,5 +1,7 @@
 // 问诊模型
 
+use crate::models::{invalid_enum_value, Message};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
@@ -21,4 +23,260 @@ pub struct Consultation {
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
-}
\ No newline at end of file
+    #[serde(rename = "acceptedAt", default)]
+    pub accepted_at: Option<DateTime<Utc>>,
+    #[serde(rename = "completedAt", default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(rename = "cancelReason", default)]
+    pub cancel_reason: Option<String>,
+    // 乐观锁版本号，每次修改加一
+    #[serde(default = "crate::models::initial_row_version")]
+    pub version: i64,
+    #[serde(default)]
+    pub priority: ConsultationPriority,
+    // 进入候诊队列的时间，为空时按创建时间计算等待时长
+    #[serde(rename = "queuedAt", default)]
+    pub queued_at: Option<DateTime<Utc>>,
+}
+
+impl Consultation {
+    pub fn queued_since(&self) -> DateTime<Utc> {
+        self.queued_at.unwrap_or(self.created_at)
+    }
+}
+
+// 问诊状态：pending -> active -> completed/cancelled，pending 也可直接取消
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsultationStatus {
+    Pending,
+    Active,
+    Completed,
+    Cancelled,
+}
+
+impl ConsultationStatus {
+    pub const ALL: [ConsultationStatus; 4] = [
+        ConsultationStatus::Pending,
+        ConsultationStatus::Active,
+        ConsultationStatus::Completed,
+        ConsultationStatus::Cancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsultationStatus::Pending => "pending",
+            ConsultationStatus::Active => "active",
+            ConsultationStatus::Completed => "completed",
+            ConsultationStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(ConsultationStatus::Pending),
+            "active" => Some(ConsultationStatus::Active),
+            "completed" => Some(ConsultationStatus::Completed),
+            "cancelled" => Some(ConsultationStatus::Cancelled),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConsultationStatus::Pending => "待接诊",
+            ConsultationStatus::Active => "进行中",
+            ConsultationStatus::Completed => "已完成",
+            ConsultationStatus::Cancelled => "已取消",
+        }
+    }
+
+    pub fn can_transition_to(&self, next: ConsultationStatus) -> bool {
+        matches!(
+            (self, next),
+            (ConsultationStatus::Pending, ConsultationStatus::Active)
+                | (ConsultationStatus::Pending, ConsultationStatus::Cancelled)
+                | (ConsultationStatus::Active, ConsultationStatus::Completed)
+                | (ConsultationStatus::Active, ConsultationStatus::Cancelled)
+        )
+    }
+}
+
+impl FromSql for ConsultationStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ConsultationStatus::parse(value).ok_or_else(|| invalid_enum_value("问诊状态", value))
+    }
+}
+
+impl ToSql for ConsultationStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+// 候诊优先级，候诊队列中 critical 最先，其次 urgent，同一优先级按等待时长排列
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsultationPriority {
+    #[default]
+    Normal,
+    Urgent,
+    Critical,
+}
+
+impl ConsultationPriority {
+    pub const ALL: [ConsultationPriority; 3] = [
+        ConsultationPriority::Normal,
+        ConsultationPriority::Urgent,
+        ConsultationPriority::Critical,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsultationPriority::Normal => "normal",
+            ConsultationPriority::Urgent => "urgent",
+            ConsultationPriority::Critical => "critical",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "normal" => Some(ConsultationPriority::Normal),
+            "urgent" => Some(ConsultationPriority::Urgent),
+            "critical" => Some(ConsultationPriority::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConsultationPriority::Normal => "普通",
+            ConsultationPriority::Urgent => "加急",
+            ConsultationPriority::Critical => "危急",
+        }
+    }
+}
+
+impl FromSql for ConsultationPriority {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ConsultationPriority::parse(value).ok_or_else(|| invalid_enum_value("问诊优先级", value))
+    }
+}
+
+impl ToSql for ConsultationPriority {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsultationQueueItem {
+    #[serde(flatten)]
+    pub consultation: Consultation,
+    // 自进入候诊队列起的等待时长（秒）
+    #[serde(rename = "waitSeconds")]
+    pub wait_seconds: i64,
+    #[serde(rename = "waitMinutes")]
+    pub wait_minutes: i64,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyCount {
+    pub date: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyLatency {
+    pub date: String,
+    // 当天没有可统计的问诊时为空
+    #[serde(rename = "averageSeconds")]
+    pub average_seconds: Option<f64>,
+    pub samples: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypeCount {
+    #[serde(rename = "consultationType")]
+    pub consultation_type: String,
+    pub count: i64,
+}
+
+// 工作台图表数据，daily_* 按日期升序且覆盖整个统计窗口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsultationMetrics {
+    pub days: u32,
+    #[serde(rename = "dailyCounts")]
+    pub daily_counts: Vec<DailyCount>,
+    #[serde(rename = "dailyFirstResponse")]
+    pub daily_first_response: Vec<DailyLatency>,
+    #[serde(rename = "averageFirstResponseSeconds")]
+    pub average_first_response_seconds: Option<f64>,
+    #[serde(rename = "completedByType")]
+    pub completed_by_type: Vec<TypeCount>,
+}
+
+// 问诊转接记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsultationTransfer {
+    pub id: String,
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "fromDoctorId")]
+    pub from_doctor_id: String,
+    #[serde(rename = "toDoctorId")]
+    pub to_doctor_id: String,
+    pub note: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+// 医生在问诊下的私人备注，仅接诊医生本人可见，不发送给患者
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsultationNote {
+    pub id: String,
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "doctorId")]
+    pub doctor_id: String,
+    pub content: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// 转接完成后的问诊、转接记录及写入会话的系统消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsultationTransferResult {
+    pub consultation: Consultation,
+    pub transfer: ConsultationTransfer,
+    pub notice: Message,
+}
+
+// 会话列表的一行：问诊、患者信息、最新消息摘要及未读数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationOverview {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "patientId")]
+    pub patient_id: String,
+    #[serde(rename = "patientName")]
+    pub patient_name: String,
+    #[serde(rename = "patientAvatarUrl")]
+    pub patient_avatar_url: Option<String>,
+    pub status: String,
+    pub title: Option<String>,
+    // 尚无消息时为空
+    #[serde(rename = "lastMessagePreview")]
+    pub last_message_preview: Option<String>,
+    #[serde(rename = "lastMessageAt")]
+    pub last_message_at: Option<DateTime<Utc>>,
+    // 最新消息时间，没有消息时取问诊更新时间
+    #[serde(rename = "lastActivityAt")]
+    pub last_activity_at: DateTime<Utc>,
+    // 按配置时区格式化的最近活动时间，会话列表直接显示
+    #[serde(rename = "lastActivityAtLocal")]
+    pub last_activity_at_local: String,
+    #[serde(rename = "unreadCount")]
+    pub unread_count: i64,
+}
@@ -0,0 +1,79 @@
+// 患者、问诊的变更通知，窗口据此增量更新列表而不必定时重新拉取
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeEntity {
+    Patient,
+    Consultation,
+}
+
+impl ChangeEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeEntity::Patient => "patient",
+            ChangeEntity::Consultation => "consultation",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "patient" => Some(ChangeEntity::Patient),
+            "consultation" => Some(ChangeEntity::Consultation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl ChangeOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOp::Insert => "insert",
+            ChangeOp::Update => "update",
+            ChangeOp::Delete => "delete",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "insert" => Some(ChangeOp::Insert),
+            "update" => Some(ChangeOp::Update),
+            "delete" => Some(ChangeOp::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// 一条变更记录，seq 在所有实体间全局单调递增
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataChanged {
+    pub seq: i64,
+    pub entity: ChangeEntity,
+    pub id: String,
+    pub op: ChangeOp,
+    #[serde(rename = "changedAt")]
+    pub changed_at: DateTime<Utc>,
+}
+
+/// get_changes_since 的结果：watermark 为下次补齐时传入的值；
+/// resetRequired 表示所需的变更已被清理，窗口应重新加载完整列表
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataChangePage {
+    pub entity: ChangeEntity,
+    pub changes: Vec<DataChanged>,
+    pub watermark: i64,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "resetRequired")]
+    pub reset_required: bool,
+}
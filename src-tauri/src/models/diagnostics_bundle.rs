@@ -0,0 +1,78 @@
+// 诊断包：技术支持排查问题时导出的本地数据库快照，按表导出为 JSON，正文内容已脱敏
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// 诊断包结构变化时递增，支持团队的解析工具据此区分
+pub const DIAGNOSTICS_BUNDLE_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticTable {
+    Consultations,
+    // 只导出消息元数据，不含正文、文件路径和语音波形
+    Messages,
+    SyncState,
+    MaintenanceRuns,
+    AppSettings,
+    // 默认不导出，手机号、身份证号和姓名按脱敏规则处理
+    Patients,
+}
+
+impl DiagnosticTable {
+    pub const DEFAULT: [DiagnosticTable; 5] = [
+        DiagnosticTable::Consultations,
+        DiagnosticTable::Messages,
+        DiagnosticTable::SyncState,
+        DiagnosticTable::MaintenanceRuns,
+        DiagnosticTable::AppSettings,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticTable::Consultations => "consultations",
+            DiagnosticTable::Messages => "messages",
+            DiagnosticTable::SyncState => "sync_state",
+            DiagnosticTable::MaintenanceRuns => "maintenance_runs",
+            DiagnosticTable::AppSettings => "app_settings",
+            DiagnosticTable::Patients => "patients",
+        }
+    }
+
+    // 诊断包中该表对应的文件名
+    pub fn file_name(&self) -> String {
+        format!("{}.json", self.as_str())
+    }
+}
+
+/// 诊断包中的 info.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsBundleInfo {
+    #[serde(rename = "bundleVersion")]
+    pub bundle_version: u32,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "osVersion")]
+    pub os_version: String,
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: i64,
+    #[serde(rename = "exportedAt")]
+    pub exported_at: DateTime<Utc>,
+    pub tables: Vec<DiagnosticTable>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticTableSummary {
+    pub table: DiagnosticTable,
+    #[serde(rename = "rowCount")]
+    pub row_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsBundleResult {
+    pub path: String,
+    pub tables: Vec<DiagnosticTableSummary>,
+    // 加密后写入磁盘的字节数
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: u64,
+}
@@ -0,0 +1,71 @@
+// 医生名录模型
+
+use crate::models::invalid_enum_value;
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+// 院内医生，从服务器同步，供转接问诊时选择接诊医生
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Doctor {
+    pub id: String,
+    pub name: String,
+    pub department: Option<String>,
+    // 职称，如主任医师、主治医师
+    pub title: Option<String>,
+    #[serde(rename = "onlineStatus", default)]
+    pub online_status: DoctorOnlineStatus,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// 医生在线状态，转接候选按 online、busy、offline 排列
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DoctorOnlineStatus {
+    Online,
+    Busy,
+    #[default]
+    Offline,
+}
+
+impl DoctorOnlineStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DoctorOnlineStatus::Online => "online",
+            DoctorOnlineStatus::Busy => "busy",
+            DoctorOnlineStatus::Offline => "offline",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "online" => Some(DoctorOnlineStatus::Online),
+            "busy" => Some(DoctorOnlineStatus::Busy),
+            "offline" => Some(DoctorOnlineStatus::Offline),
+            _ => None,
+        }
+    }
+}
+
+impl FromSql for DoctorOnlineStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        DoctorOnlineStatus::parse(value).ok_or_else(|| invalid_enum_value("医生在线状态", value))
+    }
+}
+
+impl ToSql for DoctorOnlineStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+// 转接候选医生，与问诊当前医生同科室的排在前面
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferCandidate {
+    #[serde(flatten)]
+    pub doctor: Doctor,
+    #[serde(rename = "sameDepartment")]
+    pub same_department: bool,
+}
@@ -21,6 +21,62 @@ pub struct FileCache {
     pub downloaded_at: DateTime<Utc>,
     #[serde(rename = "lastAccessed")]
     pub last_accessed: DateTime<Utc>,
+    // 是否被任何引用固定（病历附件、发件箱等），固定的文件不会被缓存清理删除
+    #[serde(default)]
+    pub pinned: bool,
+    // 图片文件的 JPEG 缩略图
+    #[serde(rename = "thumbnailPath", default)]
+    pub thumbnail_path: Option<String>,
+    // 断点续传时已下载的字节数
+    #[serde(rename = "bytesDownloaded", default)]
+    pub bytes_downloaded: u64,
+    // PDF 首页 PNG 预览图
+    #[serde(rename = "previewPath", default)]
+    pub preview_path: Option<String>,
+    // PDF、Word 文档开头的文字，用于悬停预览
+    #[serde(rename = "previewText", default)]
+    pub preview_text: Option<String>,
+    // 问诊附件所属的问诊，计入该问诊和患者的附件配额
+    #[serde(rename = "consultationId", default)]
+    pub consultation_id: Option<String>,
+    // 慢速网络下压缩后上传的图片压缩前的字节数，未压缩时为空
+    #[serde(rename = "originalSize", default)]
+    pub original_size: Option<u64>,
+}
+
+// 文档附件预览，提取失败或不支持的类型两项都为空
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilePreview {
+    #[serde(rename = "previewPath")]
+    pub preview_path: Option<String>,
+    #[serde(rename = "previewText")]
+    pub preview_text: Option<String>,
+}
+
+impl FilePreview {
+    pub fn is_empty(&self) -> bool {
+        self.preview_path.is_none() && self.preview_text.is_none()
+    }
+}
+
+// 按引用固定的缓存文件，每个引用一条，用于诊断哪些文件因何无法被清理
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedFile {
+    #[serde(rename = "cacheId")]
+    pub cache_id: String,
+    #[serde(rename = "fileUrl")]
+    pub file_url: String,
+    #[serde(rename = "localPath")]
+    pub local_path: String,
+    #[serde(rename = "fileSize")]
+    pub file_size: Option<u64>,
+    // 固定原因，如 outbox 表示被未发送的消息引用
+    pub reason: String,
+    // 引用方的标识，outbox 为消息 ID
+    #[serde(rename = "refId")]
+    pub ref_id: String,
+    #[serde(rename = "pinnedAt")]
+    pub pinned_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,4 +93,31 @@ pub struct FileInfo {
     pub mime_type: String,
     #[serde(rename = "uploadedAt")]
     pub uploaded_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+// 附件配额的统计范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentScope {
+    Consultation,
+    Patient,
+}
+
+// 某个问诊或患者的附件用量及配置的上限
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentUsage {
+    pub scope: AttachmentScope,
+    #[serde(rename = "scopeId")]
+    pub scope_id: String,
+    #[serde(rename = "usedBytes")]
+    pub used_bytes: u64,
+    #[serde(rename = "fileCount")]
+    pub file_count: u64,
+    #[serde(rename = "limitBytes")]
+    pub limit_bytes: u64,
+}
+
+impl AttachmentUsage {
+    pub fn remaining_bytes(&self) -> u64 {
+        self.limit_bytes.saturating_sub(self.used_bytes)
+    }
+}
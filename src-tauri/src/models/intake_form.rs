@@ -0,0 +1,95 @@
+// 问诊问卷模型：患者端在发起问诊时填写主诉、病程、过敏史，按 schemaVersion 区分问卷结构
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// 本机能够解析的问卷版本
+pub const INTAKE_FORM_SCHEMA_V1: u32 = 1;
+
+/// 患者端提交的问卷原文，同步拉取和 WebSocket 推送共用
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntakeFormSubmission {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    pub data: Value,
+    #[serde(rename = "submittedAt")]
+    pub submitted_at: DateTime<Utc>,
+}
+
+/// v1 问卷，患者端新增而本机不认识的字段原样保留在 extra 中
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntakeFormV1 {
+    #[serde(rename = "chiefComplaint")]
+    pub chief_complaint: String,
+    // 病程，如"3天"、"半年"
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub allergies: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// 按版本解析后的问卷内容
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum IntakeFormSections {
+    V1(IntakeFormV1),
+}
+
+impl IntakeFormSections {
+    // 不认识的版本和不符合该版本结构的数据都视为格式异常，返回异常原因
+    pub fn parse(schema_version: u32, data: &Value) -> Result<Self, String> {
+        match schema_version {
+            INTAKE_FORM_SCHEMA_V1 => {
+                let form: IntakeFormV1 = serde_json::from_value(data.clone()).map_err(|e| e.to_string())?;
+                if form.chief_complaint.trim().is_empty() {
+                    return Err("主诉不能为空".to_string());
+                }
+                Ok(IntakeFormSections::V1(form))
+            }
+            other => Err(format!("不支持的问卷版本: {}", other)),
+        }
+    }
+}
+
+/// 返回给前端的问卷：formatError 非空时 sections 为空，前端提示"表单格式异常"并展示原始数据
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IntakeForm {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    pub sections: Option<IntakeFormSections>,
+    // 患者端提交的原始数据
+    pub data: Value,
+    #[serde(rename = "formatError")]
+    pub format_error: Option<String>,
+    #[serde(rename = "submittedAt")]
+    pub submitted_at: DateTime<Utc>,
+}
+
+impl IntakeForm {
+    pub fn from_submission(submission: IntakeFormSubmission) -> Self {
+        let (sections, format_error) = match IntakeFormSections::parse(submission.schema_version, &submission.data) {
+            Ok(sections) => (Some(sections), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        Self {
+            consultation_id: submission.consultation_id,
+            schema_version: submission.schema_version,
+            sections,
+            data: submission.data,
+            format_error,
+            submitted_at: submission.submitted_at,
+        }
+    }
+
+    pub fn is_malformed(&self) -> bool {
+        self.format_error.is_some()
+    }
+}
@@ -0,0 +1,197 @@
+// 数据保留与定期维护模型
+
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+// 病历相关的沟通记录至少保留 30 天
+pub const MIN_MESSAGE_RETENTION_DAYS: u32 = 30;
+
+// 各类数据的保留天数，超过的在每日维护时清理
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    #[serde(rename = "messageDays")]
+    pub message_days: u32,
+    #[serde(rename = "auditLogDays")]
+    pub audit_log_days: u32,
+    #[serde(rename = "anomalyRecordDays")]
+    pub anomaly_record_days: u32,
+    #[serde(rename = "fileCacheDays")]
+    pub file_cache_days: u32,
+    #[serde(rename = "backupDays")]
+    pub backup_days: u32,
+    // 软删除的消息和病历在回收站中保留的天数
+    #[serde(rename = "trashDays", default = "default_trash_days")]
+    pub trash_days: u32,
+    // 清理后可回收空间超过该值时整理数据库文件
+    #[serde(rename = "vacuumThresholdMb")]
+    pub vacuum_threshold_mb: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            message_days: 365,
+            audit_log_days: 365,
+            anomaly_record_days: 90,
+            file_cache_days: 30,
+            backup_days: 30,
+            trash_days: default_trash_days(),
+            vacuum_threshold_mb: 10,
+        }
+    }
+}
+
+fn default_trash_days() -> u32 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaintenanceTrigger {
+    Scheduled,
+    Manual,
+    // 上次运行未正常退出，启动后自动执行
+    #[serde(rename = "unclean_shutdown")]
+    UncleanShutdown,
+}
+
+impl MaintenanceTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceTrigger::Scheduled => "scheduled",
+            MaintenanceTrigger::Manual => "manual",
+            MaintenanceTrigger::UncleanShutdown => "unclean_shutdown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "scheduled" => Some(MaintenanceTrigger::Scheduled),
+            "manual" => Some(MaintenanceTrigger::Manual),
+            "unclean_shutdown" => Some(MaintenanceTrigger::UncleanShutdown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceKind {
+    Retention,
+    IntegrityRepair,
+}
+
+impl MaintenanceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceKind::Retention => "retention",
+            MaintenanceKind::IntegrityRepair => "integrity_repair",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "retention" => Some(MaintenanceKind::Retention),
+            "integrity_repair" => Some(MaintenanceKind::IntegrityRepair),
+            _ => None,
+        }
+    }
+}
+
+// 一次维护的执行结果，写入 maintenance_runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceRun {
+    pub id: String,
+    pub kind: MaintenanceKind,
+    #[serde(rename = "triggeredBy")]
+    pub triggered_by: MaintenanceTrigger,
+    #[serde(rename = "startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: DateTime<Utc>,
+    #[serde(rename = "deletedMessages")]
+    pub deleted_messages: usize,
+    #[serde(rename = "deletedAuditLogs")]
+    pub deleted_audit_logs: usize,
+    #[serde(rename = "deletedAnomalyRecords")]
+    pub deleted_anomaly_records: usize,
+    #[serde(rename = "deletedCacheFiles")]
+    pub deleted_cache_files: usize,
+    #[serde(rename = "deletedBackups")]
+    pub deleted_backups: usize,
+    // 从回收站彻底清除的消息和病历
+    #[serde(rename = "purgedTrash", default)]
+    pub purged_trash: usize,
+    #[serde(rename = "freedBytes")]
+    pub freed_bytes: u64,
+    pub vacuumed: bool,
+    pub status: String,
+    #[serde(rename = "errorMessage")]
+    pub error_message: Option<String>,
+    // 完整性修复的各项结果，保留清理没有摘要
+    pub summary: Option<serde_json::Value>,
+}
+
+// 崩溃后可能残留的孤立记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanKind {
+    // 问诊不存在，或问诊本身因患者缺失被隔离
+    MessageMissingConsultation,
+    ConsultationMissingPatient,
+    // 缓存记录指向的本地文件已不存在
+    FileCacheMissingFile,
+    MedicalRecordMissingConsultation,
+}
+
+impl OrphanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrphanKind::MessageMissingConsultation => "message_missing_consultation",
+            OrphanKind::ConsultationMissingPatient => "consultation_missing_patient",
+            OrphanKind::FileCacheMissingFile => "file_cache_missing_file",
+            OrphanKind::MedicalRecordMissingConsultation => "medical_record_missing_consultation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairAction {
+    Delete,
+    // 外键允许为空时置空引用，保留记录本身
+    Nullify,
+    // 移入 *_orphaned 表供排查
+    Quarantine,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrphanRepair {
+    pub kind: OrphanKind,
+    pub action: RepairAction,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityRepairReport {
+    #[serde(rename = "runId")]
+    pub run_id: String,
+    #[serde(rename = "triggeredBy")]
+    pub triggered_by: MaintenanceTrigger,
+    pub repairs: Vec<OrphanRepair>,
+}
+
+impl IntegrityRepairReport {
+    pub fn total(&self) -> usize {
+        self.repairs.iter().map(|repair| repair.count).sum()
+    }
+}
+
+// 枚举列中无法被模型解析的取值（validate_enum_columns）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumColumnViolation {
+    pub table: String,
+    pub column: String,
+    pub value: String,
+    pub count: i64,
+}
@@ -23,14 +23,15 @@ pub struct MedicalRecord {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// 病历附件，file_id 对应 file_cache 表中的缓存文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Attachment {
-    pub id: String,
+    #[serde(rename = "fileId", alias = "id")]
+    pub file_id: String,
     pub name: String,
-    pub url: String,
-    #[serde(rename = "fileType")]
-    pub file_type: String,
+    #[serde(rename = "mimeType", default)]
+    pub mime_type: String,
     pub size: u64,
-    #[serde(rename = "uploadedAt")]
-    pub uploaded_at: DateTime<Utc>,
+    #[serde(default)]
+    pub checksum: Option<String>,
 }
\ No newline at end of file
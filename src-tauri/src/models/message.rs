@@ -2,10 +2,11 @@
 
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::models::invalid_enum_value;
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 
 // 消息类型枚举
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
     #[serde(rename = "text")]
     Text,
@@ -17,65 +18,93 @@ pub enum MessageType {
     File,
     #[serde(rename = "template")]
     Template,
+    // 问诊流程中的事件标记，仅由系统发送
+    #[serde(rename = "event")]
+    Event,
+}
+
+impl MessageType {
+    pub const ALL: [MessageType; 6] = [
+        MessageType::Text,
+        MessageType::Image,
+        MessageType::Voice,
+        MessageType::File,
+        MessageType::Template,
+        MessageType::Event,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::Text => "text",
+            MessageType::Image => "image",
+            MessageType::Voice => "voice",
+            MessageType::File => "file",
+            MessageType::Template => "template",
+            MessageType::Event => "event",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == value)
+    }
 }
 
 impl FromSql for MessageType {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        match value.as_str()? {
-            "text" => Ok(MessageType::Text),
-            "image" => Ok(MessageType::Image),
-            "voice" => Ok(MessageType::Voice),
-            "file" => Ok(MessageType::File),
-            "template" => Ok(MessageType::Template),
-            _ => Err(FromSqlError::InvalidType),
-        }
+        let value = value.as_str()?;
+        MessageType::parse(value).ok_or_else(|| invalid_enum_value("消息类型", value))
     }
 }
 
 impl ToSql for MessageType {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
-        let s = match self {
-            MessageType::Text => "text",
-            MessageType::Image => "image",
-            MessageType::Voice => "voice",
-            MessageType::File => "file",
-            MessageType::Template => "template",
-        };
-        Ok(ToSqlOutput::from(s))
+        Ok(ToSqlOutput::from(self.as_str()))
     }
 }
 
 // 发送者类型枚举
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SenderType {
     #[serde(rename = "doctor")]
     Doctor,
     #[serde(rename = "patient")]
     Patient,
+    // 转接提示等由系统生成的消息
+    #[serde(rename = "system")]
+    System,
+}
+
+impl SenderType {
+    pub const ALL: [SenderType; 3] = [SenderType::Doctor, SenderType::Patient, SenderType::System];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SenderType::Doctor => "doctor",
+            SenderType::Patient => "patient",
+            SenderType::System => "system",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == value)
+    }
 }
 
 impl FromSql for SenderType {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        match value.as_str()? {
-            "doctor" => Ok(SenderType::Doctor),
-            "patient" => Ok(SenderType::Patient),
-            _ => Err(FromSqlError::InvalidType),
-        }
+        let value = value.as_str()?;
+        SenderType::parse(value).ok_or_else(|| invalid_enum_value("发送者类型", value))
     }
 }
 
 impl ToSql for SenderType {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
-        let s = match self {
-            SenderType::Doctor => "doctor",
-            SenderType::Patient => "patient",
-        };
-        Ok(ToSqlOutput::from(s))
+        Ok(ToSqlOutput::from(self.as_str()))
     }
 }
 
 // 同步状态枚举
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SyncStatus {
     #[serde(rename = "pending")]
     Pending,
@@ -85,30 +114,37 @@ pub enum SyncStatus {
     Failed,
 }
 
+impl SyncStatus {
+    pub const ALL: [SyncStatus; 3] = [SyncStatus::Pending, SyncStatus::Synced, SyncStatus::Failed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncStatus::Pending => "pending",
+            SyncStatus::Synced => "synced",
+            SyncStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == value)
+    }
+}
+
 impl FromSql for SyncStatus {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        match value.as_str()? {
-            "pending" => Ok(SyncStatus::Pending),
-            "synced" => Ok(SyncStatus::Synced),
-            "failed" => Ok(SyncStatus::Failed),
-            _ => Err(FromSqlError::InvalidType),
-        }
+        let value = value.as_str()?;
+        SyncStatus::parse(value).ok_or_else(|| invalid_enum_value("同步状态", value))
     }
 }
 
 impl ToSql for SyncStatus {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
-        let s = match self {
-            SyncStatus::Pending => "pending",
-            SyncStatus::Synced => "synced",
-            SyncStatus::Failed => "failed",
-        };
-        Ok(ToSqlOutput::from(s))
+        Ok(ToSqlOutput::from(self.as_str()))
     }
 }
 
 // 已读状态枚举
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReadStatus {
     #[serde(rename = "unread")]
     Unread,
@@ -116,23 +152,31 @@ pub enum ReadStatus {
     Read,
 }
 
+impl ReadStatus {
+    pub const ALL: [ReadStatus; 2] = [ReadStatus::Unread, ReadStatus::Read];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread",
+            ReadStatus::Read => "read",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == value)
+    }
+}
+
 impl FromSql for ReadStatus {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        match value.as_str()? {
-            "unread" => Ok(ReadStatus::Unread),
-            "read" => Ok(ReadStatus::Read),
-            _ => Err(FromSqlError::InvalidType),
-        }
+        let value = value.as_str()?;
+        ReadStatus::parse(value).ok_or_else(|| invalid_enum_value("已读状态", value))
     }
 }
 
 impl ToSql for ReadStatus {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
-        let s = match self {
-            ReadStatus::Unread => "unread",
-            ReadStatus::Read => "read",
-        };
-        Ok(ToSqlOutput::from(s))
+        Ok(ToSqlOutput::from(self.as_str()))
     }
 }
 
@@ -157,6 +201,139 @@ pub struct Message {
     pub sync_status: SyncStatus,
     #[serde(rename = "readStatus")]
     pub read_status: ReadStatus,
+    // 模板消息对应的模板，内容已在发送时展开
+    #[serde(rename = "templateId", default)]
+    pub template_id: Option<String>,
+    // 语音消息的时长和归一化波形峰值，无法解析的音频为空
+    #[serde(rename = "durationMs", default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub waveform: Option<Vec<f32>>,
+    // 医生置顶的消息显示在会话顶部，置顶时间和操作人只在置顶列表中返回
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+// 问诊中置顶的消息，列表按置顶时间先后排列
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedMessage {
+    pub message: Message,
+    #[serde(rename = "pinnedAt")]
+    pub pinned_at: DateTime<Utc>,
+    #[serde(rename = "pinnedBy")]
+    pub pinned_by: Option<String>,
+}
+
+// 会话中的系统事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemEventKind {
+    ConsultationStarted,
+    ConsultationCompleted,
+    ConsultationCancelled,
+    PrescriptionIssued,
+    Transferred,
+    // 长时间无消息，系统自动结束问诊
+    AutoCompleted,
+    // 医生在超时提醒后选择继续问诊
+    KeptAlive,
+}
+
+impl SystemEventKind {
+    // 会话中展示的提示文字，payload 提供转接医生姓名、取消原因等补充信息
+    pub fn text(&self, payload: &serde_json::Value) -> String {
+        match self {
+            SystemEventKind::ConsultationStarted => "问诊已开始".to_string(),
+            SystemEventKind::ConsultationCompleted => "问诊已结束".to_string(),
+            SystemEventKind::ConsultationCancelled => match payload.get("reason").and_then(|v| v.as_str()) {
+                Some(reason) => format!("问诊已取消：{}", reason),
+                None => "问诊已取消".to_string(),
+            },
+            SystemEventKind::PrescriptionIssued => "处方已开具".to_string(),
+            SystemEventKind::Transferred => format!(
+                "已转接给{}",
+                payload.get("toDoctorName").and_then(|v| v.as_str()).unwrap_or("其他医生")
+            ),
+            SystemEventKind::AutoCompleted => match payload.get("inactiveHours").and_then(|v| v.as_u64()) {
+                Some(hours) => format!("问诊已超过 {} 小时无消息，系统自动结束", hours),
+                None => "问诊长时间无消息，系统自动结束".to_string(),
+            },
+            SystemEventKind::KeptAlive => "医生已延长问诊".to_string(),
+        }
+    }
+}
+
+// 系统事件消息的内容，以 JSON 存入 messages.content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemEvent {
+    pub kind: SystemEventKind,
+    pub text: String,
+    #[serde(default)]
+    pub payload: serde_json::Value,
+}
+
+impl SystemEvent {
+    pub fn new(kind: SystemEventKind, payload: serde_json::Value) -> Self {
+        Self {
+            kind,
+            text: kind.text(&payload),
+            payload,
+        }
+    }
+
+    pub fn parse(content: &str) -> Option<Self> {
+        serde_json::from_str(content).ok()
+    }
+}
+
+// 通知和会话列表中显示的消息摘要长度
+pub const PREVIEW_MAX_CHARS: usize = 40;
+
+// 非文本消息显示为类型标记，文本截断到 PREVIEW_MAX_CHARS
+pub fn message_preview_text(message_type: &MessageType, content: Option<&str>) -> String {
+    match message_type {
+        MessageType::Image => "[图片]".to_string(),
+        MessageType::Voice => "[语音]".to_string(),
+        MessageType::File => "[文件]".to_string(),
+        MessageType::Event => content.and_then(SystemEvent::parse).map(|event| event.text).unwrap_or_default(),
+        MessageType::Text | MessageType::Template => {
+            let content = content.unwrap_or_default().trim();
+            if content.chars().count() > PREVIEW_MAX_CHARS {
+                format!("{}…", content.chars().take(PREVIEW_MAX_CHARS).collect::<String>())
+            } else {
+                content.to_string()
+            }
+        }
+    }
+}
+
+// 医生在某个问诊下尚未发送的回复
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageDraft {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "doctorId")]
+    pub doctor_id: String,
+    pub content: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// 发件箱中等待服务器确认的消息，重发时沿用同一幂等键
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    #[serde(rename = "idempotencyKey")]
+    pub idempotency_key: String,
+    pub attempts: u32,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "lastAttemptAt")]
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    // 医生点击发送的时间，收到确认时据此计算往返延迟
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,4 +345,56 @@ pub struct SendMessageRequest {
     pub content: String,
     #[serde(rename = "fileId")]
     pub file_id: Option<String>,
-}
\ No newline at end of file
+}
+// 常用回复模板
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageTemplate {
+    pub id: String,
+    #[serde(rename = "doctorId")]
+    pub doctor_id: String,
+    pub category: String,
+    pub title: String,
+    pub content: String,
+    #[serde(rename = "usageCount")]
+    pub usage_count: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// 敏感词：forbidden 拒绝发送，warn 允许发送但提示
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensitiveWordCategory {
+    Forbidden,
+    Warn,
+}
+
+impl SensitiveWordCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensitiveWordCategory::Forbidden => "forbidden",
+            SensitiveWordCategory::Warn => "warn",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "forbidden" => Some(SensitiveWordCategory::Forbidden),
+            "warn" => Some(SensitiveWordCategory::Warn),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensitiveWord {
+    pub id: String,
+    pub word: String,
+    pub category: SensitiveWordCategory,
+    #[serde(rename = "createdBy")]
+    pub created_by: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
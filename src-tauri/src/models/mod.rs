@@ -4,18 +4,36 @@ pub mod user;
 pub mod patient;
 pub mod message;
 pub mod consultation;
+pub mod doctor;
+pub mod intake_form;
+pub mod data_change;
+pub mod prescription;
 pub mod medical_record;
+pub mod record_template;
 pub mod file_cache;
 pub mod audit_log;
+pub mod maintenance;
+pub mod trash;
 pub mod window;
+pub mod workstation_profile;
+pub mod diagnostics_bundle;
 pub mod common;
 
 pub use user::*;
 pub use patient::*;
 pub use message::*;
 pub use consultation::*;
+pub use doctor::*;
+pub use intake_form::*;
+pub use data_change::*;
+pub use prescription::*;
 pub use medical_record::*;
+pub use record_template::*;
 pub use file_cache::*;
 pub use audit_log::*;
+pub use maintenance::*;
+pub use trash::*;
 pub use window::*;
+pub use workstation_profile::*;
+pub use diagnostics_bundle::*;
 pub use common::*;
\ No newline at end of file
@@ -1,7 +1,11 @@
 // 患者模型
 
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::utils::{deserialize_range_end, deserialize_range_start};
+use crate::models::{invalid_enum_value, MedicalRecord, SortParams};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Patient {
@@ -17,26 +21,69 @@ pub struct Patient {
     pub avatar_url: Option<String>,
     #[serde(rename = "lastSync")]
     pub last_sync: Option<DateTime<Utc>>,
+    // 最近一次完成问诊的时间，由本地问诊状态变更维护，写入患者时不覆盖
+    #[serde(rename = "lastVisit", default)]
+    pub last_visit: Option<DateTime<Utc>>,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    // 乐观锁版本号，每次修改加一
+    #[serde(default = "crate::models::initial_row_version")]
+    pub version: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// 头像协议需要的患者信息，cached_path 仅在头像已完整下载时有值
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatientAvatar {
+    pub patient_id: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+    pub cached_path: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Gender {
     Male,
     Female,
+    // 患者未填写或证件无法判断
+    Unknown,
 }
 
-impl std::fmt::Display for Gender {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Gender {
+    pub const ALL: [Gender; 3] = [Gender::Male, Gender::Female, Gender::Unknown];
+
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Gender::Male => write!(f, "male"),
-            Gender::Female => write!(f, "female"),
+            Gender::Male => "male",
+            Gender::Female => "female",
+            Gender::Unknown => "unknown",
         }
     }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| g.as_str() == value)
+    }
+}
+
+impl std::fmt::Display for Gender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql for Gender {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        Gender::parse(value).ok_or_else(|| invalid_enum_value("性别", value))
+    }
+}
+
+impl ToSql for Gender {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,23 +95,206 @@ pub struct PatientQuery {
     pub age_range: Option<AgeRange>,
     #[serde(rename = "lastVisitRange")]
     pub last_visit_range: Option<DateRange>,
+    // 未指定时按建档时间倒序
+    #[serde(default)]
+    pub sort: Option<SortParams>,
     pub page: u32,
     #[serde(rename = "pageSize")]
     pub page_size: u32,
 }
 
+// 患者列表允许的排序字段，取值与前端字段名一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatientSortField {
+    Name,
+    LastVisit,
+    CreatedAt,
+}
+
+impl PatientSortField {
+    pub const ALL: [PatientSortField; 3] = [
+        PatientSortField::Name,
+        PatientSortField::LastVisit,
+        PatientSortField::CreatedAt,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PatientSortField::Name => "name",
+            PatientSortField::LastVisit => "lastVisit",
+            PatientSortField::CreatedAt => "createdAt",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.as_str() == value)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgeRange {
     pub min: u32,
     pub max: u32,
 }
 
+// 前端传来的时间范围（闭区间），只有日期或不带时区时按配置的时区理解
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DateRange {
+    #[serde(deserialize_with = "deserialize_range_start")]
     pub start: DateTime<Utc>,
+    #[serde(deserialize_with = "deserialize_range_end")]
     pub end: DateTime<Utc>,
 }
 
+// 从身份证号解析出的信息，用于录入时自动填充
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdCardInfo {
+    #[serde(rename = "birthDate")]
+    pub birth_date: NaiveDate,
+    pub gender: Gender,
+    #[serde(rename = "regionCode")]
+    pub region_code: String,
+}
+
+// 可按需查看明文的患者标识字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PatientField {
+    Phone,
+    IdCard,
+    Name,
+}
+
+impl PatientField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PatientField::Phone => "phone",
+            PatientField::IdCard => "idCard",
+            PatientField::Name => "name",
+        }
+    }
+}
+
+// 修改记录跟踪的患者资料字段，取值与 Patient 序列化后的字段名一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PatientRevisionField {
+    Name,
+    Age,
+    Gender,
+    Phone,
+    IdCard,
+    Tags,
+    AvatarUrl,
+}
+
+impl PatientRevisionField {
+    pub const ALL: [PatientRevisionField; 7] = [
+        PatientRevisionField::Name,
+        PatientRevisionField::Age,
+        PatientRevisionField::Gender,
+        PatientRevisionField::Phone,
+        PatientRevisionField::IdCard,
+        PatientRevisionField::Tags,
+        PatientRevisionField::AvatarUrl,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PatientRevisionField::Name => "name",
+            PatientRevisionField::Age => "age",
+            PatientRevisionField::Gender => "gender",
+            PatientRevisionField::Phone => "phone",
+            PatientRevisionField::IdCard => "idCard",
+            PatientRevisionField::Tags => "tags",
+            PatientRevisionField::AvatarUrl => "avatarUrl",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.as_str() == value)
+    }
+
+    // 新旧值落库前需要字段级加密
+    pub fn is_sensitive(&self) -> bool {
+        matches!(self, PatientRevisionField::Phone | PatientRevisionField::IdCard)
+    }
+
+    // 按落库时的规范化形式取值：手机号去空白、身份证号转大写，空字符串视为未填写
+    pub fn value_of(&self, patient: &Patient) -> serde_json::Value {
+        match self {
+            PatientRevisionField::Name => serde_json::json!(patient.name),
+            PatientRevisionField::Age => serde_json::json!(patient.age),
+            PatientRevisionField::Gender => serde_json::json!(patient.gender),
+            PatientRevisionField::Phone => {
+                serde_json::json!(patient.phone.as_deref().map(str::trim).filter(|v| !v.is_empty()))
+            }
+            PatientRevisionField::IdCard => serde_json::json!(patient
+                .id_card
+                .as_deref()
+                .map(|v| v.trim().to_uppercase())
+                .filter(|v| !v.is_empty())),
+            PatientRevisionField::Tags => serde_json::json!(patient.tags),
+            PatientRevisionField::AvatarUrl => serde_json::json!(patient.avatar_url),
+        }
+    }
+
+    // 把修改记录中的取值写回患者，撤销修改时使用
+    pub fn set_value(&self, patient: &mut Patient, value: serde_json::Value) -> Result<(), serde_json::Error> {
+        match self {
+            PatientRevisionField::Name => patient.name = serde_json::from_value(value)?,
+            PatientRevisionField::Age => patient.age = serde_json::from_value(value)?,
+            PatientRevisionField::Gender => patient.gender = serde_json::from_value(value)?,
+            PatientRevisionField::Phone => patient.phone = serde_json::from_value(value)?,
+            PatientRevisionField::IdCard => patient.id_card = serde_json::from_value(value)?,
+            PatientRevisionField::Tags => patient.tags = serde_json::from_value(value)?,
+            PatientRevisionField::AvatarUrl => patient.avatar_url = serde_json::from_value(value)?,
+        }
+        Ok(())
+    }
+}
+
+// 单个字段修改前后的值，未填写为 null
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatientFieldChange {
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+// 一次患者资料修改，changes 只包含值发生变化的字段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientRevision {
+    pub id: String,
+    #[serde(rename = "patientId")]
+    pub patient_id: String,
+    #[serde(rename = "editorId")]
+    pub editor_id: Option<String>,
+    pub changes: BTreeMap<String, PatientFieldChange>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl PatientRevision {
+    // 比较修改前后的患者资料，忽略未变化的字段和 updated_at 等维护字段
+    pub fn diff(old: &Patient, new: &Patient) -> BTreeMap<String, PatientFieldChange> {
+        PatientRevisionField::ALL
+            .iter()
+            .filter_map(|field| {
+                let old = field.value_of(old);
+                let new = field.value_of(new);
+                (old != new).then(|| (field.as_str().to_string(), PatientFieldChange { old, new }))
+            })
+            .collect()
+    }
+}
+
+// 历史患者敏感字段加密迁移进度
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldEncryptionProgress {
+    pub processed: usize,
+    pub total: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatientList {
     pub patients: Vec<Patient>,
@@ -74,6 +304,12 @@ pub struct PatientList {
     pub page_size: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagUsage {
+    pub tag: String,
+    pub count: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatientDetail {
     #[serde(flatten)]
@@ -82,6 +318,8 @@ pub struct PatientDetail {
     pub consultation_history: Vec<ConsultationSummary>,
     #[serde(rename = "followUpReminders")]
     pub follow_up_reminders: Vec<FollowUpReminder>,
+    #[serde(rename = "recentMedicalRecords")]
+    pub recent_medical_records: Vec<MedicalRecord>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +335,60 @@ pub struct ConsultationSummary {
     pub completed_at: Option<DateTime<Utc>>,
 }
 
+// 患者时间线事件类型，取值与前端筛选项一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineEventType {
+    ConsultationStarted,
+    ConsultationCompleted,
+    MedicalRecordCreated,
+    PrescriptionIssued,
+    FileUploaded,
+}
+
+impl TimelineEventType {
+    pub const ALL: [TimelineEventType; 5] = [
+        TimelineEventType::ConsultationStarted,
+        TimelineEventType::ConsultationCompleted,
+        TimelineEventType::MedicalRecordCreated,
+        TimelineEventType::PrescriptionIssued,
+        TimelineEventType::FileUploaded,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimelineEventType::ConsultationStarted => "consultation_started",
+            TimelineEventType::ConsultationCompleted => "consultation_completed",
+            TimelineEventType::MedicalRecordCreated => "medical_record_created",
+            TimelineEventType::PrescriptionIssued => "prescription_issued",
+            TimelineEventType::FileUploaded => "file_uploaded",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == value)
+    }
+}
+
+impl FromSql for TimelineEventType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        TimelineEventType::parse(value).ok_or_else(|| invalid_enum_value("时间线事件类型", value))
+    }
+}
+
+// 时间线上的一条事件，ref_id 为对应问诊、病历、处方或消息的 ID
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    #[serde(rename = "eventType")]
+    pub event_type: TimelineEventType,
+    pub timestamp: DateTime<Utc>,
+    pub title: String,
+    pub summary: Option<String>,
+    #[serde(rename = "refId")]
+    pub ref_id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FollowUpReminder {
     pub id: String,
@@ -108,4 +400,18 @@ pub struct FollowUpReminder {
     #[serde(rename = "scheduledAt")]
     pub scheduled_at: DateTime<Utc>,
     pub completed: bool,
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowError {
+    pub line: u64,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub errors: Vec<ImportRowError>,
+}
@@ -0,0 +1,118 @@
+// 处方模型
+
+use crate::models::invalid_enum_value;
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+// 处方状态：draft -> issued -> voided，草稿也可直接作废
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrescriptionStatus {
+    Draft,
+    Issued,
+    Voided,
+}
+
+impl PrescriptionStatus {
+    pub const ALL: [PrescriptionStatus; 3] = [PrescriptionStatus::Draft, PrescriptionStatus::Issued, PrescriptionStatus::Voided];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrescriptionStatus::Draft => "draft",
+            PrescriptionStatus::Issued => "issued",
+            PrescriptionStatus::Voided => "voided",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "draft" => Some(PrescriptionStatus::Draft),
+            "issued" => Some(PrescriptionStatus::Issued),
+            "voided" => Some(PrescriptionStatus::Voided),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PrescriptionStatus::Draft => "草稿",
+            PrescriptionStatus::Issued => "已开具",
+            PrescriptionStatus::Voided => "已作废",
+        }
+    }
+}
+
+impl FromSql for PrescriptionStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        PrescriptionStatus::parse(value).ok_or_else(|| invalid_enum_value("处方状态", value))
+    }
+}
+
+impl ToSql for PrescriptionStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+// 处方中的一味药
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrescriptionItem {
+    pub drug: String,
+    // 规格，如 0.25g×24粒
+    pub spec: String,
+    // 每次用量
+    pub dosage: f64,
+    // 用药频次，如 一日三次
+    pub frequency: String,
+    pub days: u32,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl PrescriptionItem {
+    // 写入问诊 prescription 文本时使用的单行描述
+    pub fn summary(&self) -> String {
+        let mut line = format!(
+            "{} {} 每次{} {} {}天",
+            self.drug.trim(),
+            self.spec.trim(),
+            self.dosage,
+            self.frequency.trim(),
+            self.days
+        );
+        if let Some(note) = self.note.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+            line.push_str(&format!("（{}）", note));
+        }
+        line
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prescription {
+    pub id: String,
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "doctorId")]
+    pub doctor_id: String,
+    pub items: Vec<PrescriptionItem>,
+    pub status: PrescriptionStatus,
+    #[serde(rename = "voidReason", default)]
+    pub void_reason: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(rename = "issuedAt", default)]
+    pub issued_at: Option<DateTime<Utc>>,
+    #[serde(rename = "voidedAt", default)]
+    pub voided_at: Option<DateTime<Utc>>,
+}
+
+impl Prescription {
+    // 兼容旧版只读 prescription 文本的页面，每味药一行
+    pub fn summary(&self) -> String {
+        self.items.iter().map(PrescriptionItem::summary).collect::<Vec<_>>().join("\n")
+    }
+}
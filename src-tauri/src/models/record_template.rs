@@ -0,0 +1,47 @@
+// 病历模板模型
+
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordTemplate {
+    pub id: String,
+    #[serde(rename = "doctorId")]
+    pub doctor_id: String,
+    #[serde(rename = "recordType")]
+    pub record_type: String,
+    pub title: String,
+    // 正文中使用 {{变量名}} 作为占位符
+    pub content: String,
+    pub variables: Vec<TemplateVariable>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub label: Option<String>,
+    #[serde(rename = "defaultValue")]
+    pub default_value: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRecordTemplateRequest {
+    #[serde(rename = "recordType")]
+    pub record_type: String,
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub variables: Vec<TemplateVariable>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedTemplate {
+    #[serde(rename = "templateId")]
+    pub template_id: String,
+    #[serde(rename = "recordType")]
+    pub record_type: String,
+    pub title: String,
+    pub content: String,
+}
@@ -0,0 +1,37 @@
+// 回收站模型：软删除的消息和病历
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashEntityType {
+    Message,
+    MedicalRecord,
+}
+
+impl TrashEntityType {
+    // 同时用作审计日志的资源类型
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrashEntityType::Message => "message",
+            TrashEntityType::MedicalRecord => "medical_record",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    #[serde(rename = "entityType")]
+    pub entity_type: TrashEntityType,
+    pub id: String,
+    #[serde(rename = "consultationId")]
+    pub consultation_id: Option<String>,
+    // 问诊未同步到本地时消息无法确定患者
+    #[serde(rename = "patientId")]
+    pub patient_id: Option<String>,
+    // 消息内容或病历标题
+    pub summary: Option<String>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: DateTime<Utc>,
+}
@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::utils::{mask_id_card, mask_phone};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -38,7 +40,7 @@ impl std::fmt::Display for AuditStatus {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LoginCredentials {
     #[serde(rename = "type")]
     pub login_type: LoginType,
@@ -51,6 +53,32 @@ pub struct LoginCredentials {
     pub id_card: Option<String>,
 }
 
+// 调试输出不含密码和验证码，手机号和身份证号脱敏
+impl fmt::Debug for LoginCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "***");
+        f.debug_struct("LoginCredentials")
+            .field("login_type", &self.login_type)
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("phone", &self.phone.as_deref().map(mask_phone))
+            .field("sms_code", &redacted(&self.sms_code))
+            .field("id_card", &self.id_card.as_deref().map(mask_id_card))
+            .finish()
+    }
+}
+
+impl LoginCredentials {
+    // 日志中标识本次登录的账号：用户名、脱敏的手机号或身份证号
+    pub fn masked_identifier(&self) -> String {
+        match self.login_type {
+            LoginType::Password => self.username.clone().unwrap_or_default(),
+            LoginType::Sms => self.phone.as_deref().map(mask_phone).unwrap_or_default(),
+            LoginType::Realname => self.id_card.as_deref().map(mask_id_card).unwrap_or_default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LoginType {
@@ -64,6 +92,9 @@ pub struct AuthResult {
     pub token: String,
     pub user: serde_json::Value,
     pub expires_at: String,
+    // 认证服务看到的客户端公网 IP，模拟认证和旧版接口不返回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,4 +114,119 @@ pub struct AuthSession {
     pub token: String,
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+// 用户角色，来自 JWT 中的 role 声明
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    Doctor,
+    Nurse,
+    Admin,
+}
+
+impl UserRole {
+    pub const ALL: [UserRole; 3] = [UserRole::Doctor, UserRole::Nurse, UserRole::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Doctor => "doctor",
+            UserRole::Nurse => "nurse",
+            UserRole::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "doctor" => Some(UserRole::Doctor),
+            "nurse" => Some(UserRole::Nurse),
+            "admin" => Some(UserRole::Admin),
+            _ => None,
+        }
+    }
+}
+
+// 受保护命令需要的权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ViewAuditLogs,
+    ManageSecurity,
+    WriteAuditLogs,
+    DecryptSensitiveData,
+    EditPatients,
+    ManagePatientTags,
+    ImportPatients,
+    ExportPatientData,
+    SyncData,
+    ManageDatabase,
+    ReadFiles,
+    DeleteFiles,
+    ViewAllPatients,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 13] = [
+        Permission::ViewAuditLogs,
+        Permission::ManageSecurity,
+        Permission::WriteAuditLogs,
+        Permission::DecryptSensitiveData,
+        Permission::EditPatients,
+        Permission::ManagePatientTags,
+        Permission::ImportPatients,
+        Permission::ExportPatientData,
+        Permission::SyncData,
+        Permission::ManageDatabase,
+        Permission::ReadFiles,
+        Permission::DeleteFiles,
+        Permission::ViewAllPatients,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::ViewAuditLogs => "view_audit_logs",
+            Permission::ManageSecurity => "manage_security",
+            Permission::WriteAuditLogs => "write_audit_logs",
+            Permission::DecryptSensitiveData => "decrypt_sensitive_data",
+            Permission::EditPatients => "edit_patients",
+            Permission::ManagePatientTags => "manage_patient_tags",
+            Permission::ImportPatients => "import_patients",
+            Permission::ExportPatientData => "export_patient_data",
+            Permission::SyncData => "sync_data",
+            Permission::ManageDatabase => "manage_database",
+            Permission::ReadFiles => "read_files",
+            Permission::DeleteFiles => "delete_files",
+            Permission::ViewAllPatients => "view_all_patients",
+        }
+    }
+}
+
+// 本地数据的可见范围：多位医生共用工作站时，医生只能看到自己接诊的患者、问诊和消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataScope {
+    All,
+    Doctor(String),
+}
+
+impl DataScope {
+    pub fn doctor_id(&self) -> Option<&str> {
+        match self {
+            DataScope::All => None,
+            DataScope::Doctor(doctor_id) => Some(doctor_id),
+        }
+    }
+
+    // 用于区分不同范围的查询缓存
+    pub fn cache_key(&self) -> String {
+        match self {
+            DataScope::All => "all".to_string(),
+            DataScope::Doctor(doctor_id) => format!("doctor:{}", doctor_id),
+        }
+    }
+}
+
+// 当前用户的角色和权限，前端据此隐藏无权使用的功能
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserPermissions {
+    pub role: Option<UserRole>,
+    pub permissions: Vec<Permission>,
+}
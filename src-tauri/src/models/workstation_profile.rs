@@ -0,0 +1,119 @@
+// 工作站配置包：在一台工作站配置好后复制到其他工作站的应用配置、保留策略和模板
+
+use crate::models::{MessageTemplate, RecordTemplate, RetentionPolicy};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+pub const WORKSTATION_PROFILE_FORMAT: &str = "telemedicine-workstation-profile";
+// 配置包结构变化时递增，导入时只接受相同版本
+pub const WORKSTATION_PROFILE_SCHEMA_VERSION: u32 = 1;
+
+/// 配置包内容，签名覆盖其全部字段；不包含用户会话、令牌和个人设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkstationProfile {
+    pub format: String,
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    // 导出时 AppConfig 的版本，比本机新的配置包无法导入
+    #[serde(rename = "appConfigSchemaVersion")]
+    pub app_config_schema_version: i64,
+    #[serde(rename = "exportedAt")]
+    pub exported_at: DateTime<Utc>,
+    #[serde(rename = "exportedBy")]
+    pub exported_by: Option<String>,
+    // 派生签名密钥用的随机盐（base64）
+    pub salt: String,
+    // AppConfig 中除数据保留策略以外的字段
+    #[serde(rename = "appSettings")]
+    pub app_settings: Map<String, Value>,
+    #[serde(rename = "retentionPolicy")]
+    pub retention_policy: RetentionPolicy,
+    #[serde(rename = "messageTemplates")]
+    pub message_templates: Vec<MessageTemplate>,
+    #[serde(rename = "recordTemplates")]
+    pub record_templates: Vec<RecordTemplate>,
+}
+
+/// 写入文件的格式：配置包及其 HMAC 签名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedWorkstationProfile {
+    pub profile: Value,
+    pub signature: String,
+}
+
+/// replace：以配置包为准，删除本机多出的模板；merge：保留本机改过的配置和内容不同的模板，记为冲突
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileMergeStrategy {
+    Replace,
+    Merge,
+}
+
+impl ProfileMergeStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfileMergeStrategy::Replace => "replace",
+            ProfileMergeStrategy::Merge => "merge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProfileSection {
+    AppSettings,
+    RetentionPolicy,
+    MessageTemplates,
+    RecordTemplates,
+}
+
+/// 未导入的配置项或模板：key 为配置项名称或模板 id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileConflict {
+    pub key: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileSectionReport {
+    pub section: ProfileSection,
+    pub imported: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub conflicts: Vec<ProfileConflict>,
+}
+
+impl ProfileSectionReport {
+    pub fn new(section: ProfileSection) -> Self {
+        Self {
+            section,
+            imported: 0,
+            updated: 0,
+            unchanged: 0,
+            removed: 0,
+            conflicts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileImportReport {
+    pub strategy: ProfileMergeStrategy,
+    pub sections: Vec<ProfileSectionReport>,
+    // 实际修改的顶层配置项，用于热更新和审计
+    #[serde(rename = "changedSettings")]
+    pub changed_settings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileExportResult {
+    pub path: String,
+    #[serde(rename = "settingCount")]
+    pub setting_count: usize,
+    #[serde(rename = "messageTemplateCount")]
+    pub message_template_count: usize,
+    #[serde(rename = "recordTemplateCount")]
+    pub record_template_count: usize,
+}
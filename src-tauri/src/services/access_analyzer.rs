@@ -0,0 +1,341 @@
+// 异常访问分析
+// 以持久化的审计日志为输入，按 SecurityConfig 中的规则检测批量查看患者、非工作时间访问和大量下载
+
+use crate::models::{AuditLog, SecurityConfig};
+use crate::services::security::{AnomalyRecord, AnomalyType};
+use chrono::{DateTime, Duration, FixedOffset, Local, Offset, Timelike, Utc};
+use std::collections::{HashMap, VecDeque};
+
+// 视为访问患者病历的操作
+const PATIENT_ACCESS_ACTIONS: &[&str] = &["view_patient", "update_patient", "access_sensitive_data"];
+const FILE_DOWNLOAD_ACTION: &str = "download_file";
+const BYTES_PER_MEGABYTE: u64 = 1024 * 1024;
+
+pub struct AccessAnalyzer {
+    config: SecurityConfig,
+}
+
+impl AccessAnalyzer {
+    pub fn new(config: SecurityConfig) -> Self {
+        Self { config }
+    }
+
+    // 需要读取的日志时间范围，取各规则窗口的最大值
+    pub fn lookback(&self) -> Duration {
+        let minutes = [
+            self.config.bulk_patient_views.window_minutes,
+            self.config.off_hours_access.lookback_hours * 60,
+            self.config.file_downloads.window_minutes,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+        Duration::minutes(minutes.max(1))
+    }
+
+    // logs 为同一用户按时间升序的日志
+    pub fn analyze(&self, user_id: &str, logs: &[AuditLog], now: DateTime<Utc>) -> Vec<AnomalyRecord> {
+        let mut anomalies = Vec::new();
+        anomalies.extend(self.detect_bulk_patient_views(user_id, logs, now));
+        anomalies.extend(self.detect_off_hours_access(user_id, logs, now));
+        anomalies.extend(self.detect_large_downloads(user_id, logs, now));
+        anomalies
+    }
+
+    // 日志范围内任意 window_minutes 内查看的不同患者数超过阈值
+    fn detect_bulk_patient_views(&self, user_id: &str, logs: &[AuditLog], now: DateTime<Utc>) -> Option<AnomalyRecord> {
+        let rule = &self.config.bulk_patient_views;
+        if !rule.enabled {
+            return None;
+        }
+
+        let window = Duration::minutes(rule.window_minutes);
+        let views = logs
+            .iter()
+            .filter(|log| is_patient_access(log))
+            .filter_map(|log| log.resource_id.as_deref().map(|id| (log.created_at, id)));
+
+        // 滑动窗口统计不同患者数的最大值
+        let mut in_window: VecDeque<(DateTime<Utc>, &str)> = VecDeque::new();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut peak = 0;
+        for (at, patient_id) in views {
+            in_window.push_back((at, patient_id));
+            *counts.entry(patient_id).or_insert(0) += 1;
+
+            while let Some(&(first_at, first_id)) = in_window.front() {
+                if at - first_at < window {
+                    break;
+                }
+                in_window.pop_front();
+                if let Some(count) = counts.get_mut(first_id) {
+                    *count -= 1;
+                    if *count == 0 {
+                        counts.remove(first_id);
+                    }
+                }
+            }
+            peak = peak.max(counts.len());
+        }
+
+        if peak <= rule.max_distinct_patients {
+            return None;
+        }
+
+        let severity = if peak >= rule.max_distinct_patients * 2 {
+            &rule.escalated_severity
+        } else {
+            &rule.severity
+        };
+        Some(anomaly(
+            user_id,
+            AnomalyType::UnusualAccessPattern,
+            severity,
+            format!("{} 分钟内查看了 {} 位不同患者的资料", rule.window_minutes, peak),
+            now,
+        ))
+    }
+
+    // 非工作时间访问患者病历
+    fn detect_off_hours_access(&self, user_id: &str, logs: &[AuditLog], now: DateTime<Utc>) -> Option<AnomalyRecord> {
+        let rule = &self.config.off_hours_access;
+        if !rule.enabled {
+            return None;
+        }
+
+        let offset = match rule.utc_offset_minutes {
+            Some(minutes) => FixedOffset::east_opt(minutes * 60)?,
+            None => Local::now().offset().fix(),
+        };
+        let since = now - Duration::hours(rule.lookback_hours);
+
+        let off_hours: Vec<&AuditLog> = logs
+            .iter()
+            .filter(|log| is_patient_access(log) && log.created_at >= since)
+            .filter(|log| in_hour_range(log.created_at.with_timezone(&offset).hour(), rule.start_hour, rule.end_hour))
+            .collect();
+        let first = off_hours.first()?;
+
+        Some(anomaly(
+            user_id,
+            AnomalyType::UnusualAccessPattern,
+            &rule.severity,
+            format!(
+                "在非工作时间（{:02}:00–{:02}:00）访问患者资料 {} 次，首次于 {}",
+                rule.start_hour,
+                rule.end_hour,
+                off_hours.len(),
+                first.created_at.with_timezone(&offset).format("%Y-%m-%d %H:%M")
+            ),
+            now,
+        ))
+    }
+
+    // window_minutes 内下载的文件总量超过阈值
+    fn detect_large_downloads(&self, user_id: &str, logs: &[AuditLog], now: DateTime<Utc>) -> Option<AnomalyRecord> {
+        let rule = &self.config.file_downloads;
+        if !rule.enabled {
+            return None;
+        }
+
+        let since = now - Duration::minutes(rule.window_minutes);
+        let total_bytes: u64 = logs
+            .iter()
+            .filter(|log| log.action == FILE_DOWNLOAD_ACTION && log.created_at >= since)
+            .filter_map(download_size)
+            .sum();
+
+        let limit_bytes = rule.max_megabytes * BYTES_PER_MEGABYTE;
+        if total_bytes <= limit_bytes {
+            return None;
+        }
+
+        let severity = if total_bytes >= limit_bytes * 2 {
+            &rule.escalated_severity
+        } else {
+            &rule.severity
+        };
+        Some(anomaly(
+            user_id,
+            AnomalyType::SuspiciousFileAccess,
+            severity,
+            format!(
+                "{} 分钟内下载文件 {:.1} MB，超过 {} MB 的限制",
+                rule.window_minutes,
+                total_bytes as f64 / BYTES_PER_MEGABYTE as f64,
+                rule.max_megabytes
+            ),
+            now,
+        ))
+    }
+}
+
+fn is_patient_access(log: &AuditLog) -> bool {
+    PATIENT_ACCESS_ACTIONS.contains(&log.action.as_str())
+}
+
+// 起止小时相同表示不启用，start > end 表示跨零点
+fn in_hour_range(hour: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        hour >= start && hour < end
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+// 下载大小记录在 details.size 中（字节），前端上报时可能是字符串
+fn download_size(log: &AuditLog) -> Option<u64> {
+    let size = log.details.get("size")?;
+    size.as_u64().or_else(|| size.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn anomaly(user_id: &str, anomaly_type: AnomalyType, severity: &str, description: String, now: DateTime<Utc>) -> AnomalyRecord {
+    AnomalyRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        anomaly_type,
+        severity: severity.to_string(),
+        description,
+        detected_at: now,
+        resolved: false,
+        metadata: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn log(action: &str, resource_id: &str, at: DateTime<Utc>, details: serde_json::Value) -> AuditLog {
+        AuditLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: Some("d1".to_string()),
+            action: action.to_string(),
+            resource_type: Some("patient".to_string()),
+            resource_id: Some(resource_id.to_string()),
+            details,
+            ip_address: None,
+            user_agent: None,
+            created_at: at,
+        }
+    }
+
+    // 固定在 UTC+8，非工作时间规则不受运行环境时区影响
+    fn analyzer() -> AccessAnalyzer {
+        let mut config = SecurityConfig::default();
+        config.off_hours_access.utc_offset_minutes = Some(8 * 60);
+        AccessAnalyzer::new(config)
+    }
+
+    // 北京时间 2024-03-01 的某个时刻
+    fn beijing(hour: u32, minute: u32) -> DateTime<Utc> {
+        FixedOffset::east_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 3, 1, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_bulk_patient_views() {
+        let analyzer = analyzer();
+        let start = beijing(10, 0);
+
+        // 10 分钟内 31 位不同患者，触发
+        let logs: Vec<AuditLog> = (0..31)
+            .map(|i| log("view_patient", &format!("p{}", i), start + Duration::seconds(i * 15), serde_json::Value::Null))
+            .collect();
+        let now = start + Duration::minutes(9);
+        let anomalies = analyzer.analyze("d1", &logs, now);
+        assert_eq!(anomalies.len(), 1);
+        assert!(matches!(anomalies[0].anomaly_type, AnomalyType::UnusualAccessPattern));
+        assert_eq!(anomalies[0].severity, "medium");
+
+        // 反复查看同一批患者不触发
+        let logs: Vec<AuditLog> = (0..200)
+            .map(|i| log("view_patient", &format!("p{}", i % 10), start + Duration::seconds(i), serde_json::Value::Null))
+            .collect();
+        assert!(analyzer.analyze("d1", &logs, now).is_empty());
+
+        // 同样 40 位患者分散在 40 分钟内，任一 10 分钟窗口都未超过阈值
+        let logs: Vec<AuditLog> = (0..40)
+            .map(|i| log("view_patient", &format!("p{}", i), start + Duration::minutes(i), serde_json::Value::Null))
+            .collect();
+        assert!(analyzer.analyze("d1", &logs, start + Duration::minutes(40)).is_empty());
+
+        // 超过阈值两倍时升级
+        let logs: Vec<AuditLog> = (0..60)
+            .map(|i| log("view_patient", &format!("p{}", i), start + Duration::seconds(i * 5), serde_json::Value::Null))
+            .collect();
+        assert_eq!(analyzer.analyze("d1", &logs, now)[0].severity, "high");
+    }
+
+    #[test]
+    fn test_off_hours_access() {
+        let analyzer = analyzer();
+
+        let logs = vec![
+            log("view_patient", "p1", beijing(0, 50), serde_json::Value::Null),
+            log("view_patient", "p1", beijing(2, 30), serde_json::Value::Null),
+        ];
+        let anomalies = analyzer.analyze("d1", &logs, beijing(9, 0));
+        assert_eq!(anomalies.len(), 1);
+        assert!(anomalies[0].description.contains("2024-03-01 02:30"));
+
+        // 边界：05:00 已不属于非工作时间，非病历操作也不计入
+        let logs = vec![
+            log("view_patient", "p1", beijing(5, 0), serde_json::Value::Null),
+            log("send_message", "c1", beijing(3, 0), serde_json::Value::Null),
+        ];
+        assert!(analyzer.analyze("d1", &logs, beijing(9, 0)).is_empty());
+
+        // 跨零点的时间段
+        let mut config = SecurityConfig::default();
+        config.off_hours_access.utc_offset_minutes = Some(8 * 60);
+        config.off_hours_access.start_hour = 22;
+        config.off_hours_access.end_hour = 6;
+        let logs = vec![log("update_patient", "p1", beijing(23, 15), serde_json::Value::Null)];
+        assert_eq!(AccessAnalyzer::new(config).analyze("d1", &logs, beijing(23, 30)).len(), 1);
+    }
+
+    #[test]
+    fn test_large_downloads() {
+        let analyzer = analyzer();
+        let start = beijing(14, 0);
+        let mb = BYTES_PER_MEGABYTE;
+
+        // 一小时内 3 × 200MB，超过 500MB
+        let logs: Vec<AuditLog> = (0..3)
+            .map(|i| log("download_file", "f", start + Duration::minutes(i * 10), serde_json::json!({ "size": 200 * mb })))
+            .collect();
+        let anomalies = analyzer.analyze("d1", &logs, start + Duration::minutes(30));
+        assert_eq!(anomalies.len(), 1);
+        assert!(matches!(anomalies[0].anomaly_type, AnomalyType::SuspiciousFileAccess));
+        assert_eq!(anomalies[0].severity, "high");
+
+        // 超出窗口的下载不计入；字符串格式的大小也能识别
+        let logs = vec![
+            log("download_file", "f", start, serde_json::json!({ "size": 400 * mb })),
+            log("download_file", "f", start + Duration::minutes(90), serde_json::json!({ "size": (300 * mb).to_string() })),
+        ];
+        assert!(analyzer.analyze("d1", &logs, start + Duration::minutes(100)).is_empty());
+
+        let logs = vec![log("download_file", "f", start, serde_json::json!({ "size": 1200 * mb }))];
+        assert_eq!(analyzer.analyze("d1", &logs, start + Duration::minutes(1))[0].severity, "critical");
+    }
+
+    #[test]
+    fn test_disabled_rules_do_not_trigger() {
+        let mut config = SecurityConfig::default();
+        config.off_hours_access.utc_offset_minutes = Some(8 * 60);
+        config.off_hours_access.enabled = false;
+        config.file_downloads.enabled = false;
+
+        let logs = vec![
+            log("view_patient", "p1", beijing(3, 0), serde_json::Value::Null),
+            log("download_file", "f", beijing(3, 5), serde_json::json!({ "size": 4096 * BYTES_PER_MEGABYTE })),
+        ];
+        assert!(AccessAnalyzer::new(config).analyze("d1", &logs, beijing(3, 10)).is_empty());
+    }
+}
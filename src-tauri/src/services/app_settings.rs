@@ -0,0 +1,282 @@
+// 应用全局配置：持久化到 app_settings 表，支持按字段局部更新
+
+use crate::database::connection::{get_database, try_get_database, DbConnection};
+use crate::database::dao::{AppSettingsDao, RetentionDao, APP_CONFIG_SCHEMA_VERSION};
+use crate::models::{AppConfig, AppError, ErrorType};
+use crate::services::validate_retention_policy;
+use crate::utils::ValidationService;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const CONFIG_CHANGED_EVENT: &str = "config-changed";
+
+// 修改这些配置需要管理员权限，并写入审计日志
+pub const SECURITY_SETTING_KEYS: &[&str] = &[
+    "apiBaseUrl",
+    "wsUrl",
+    "authProvider",
+    "updateManifestUrl",
+    "maxFileSize",
+    "allowedFileTypes",
+    "autoLockTimeout",
+    "retention",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChangedEvent {
+    #[serde(rename = "changedKeys")]
+    pub changed_keys: Vec<String>,
+    pub config: AppConfig,
+}
+
+pub fn touches_security_settings(changed_keys: &[String]) -> bool {
+    changed_keys.iter().any(|key| SECURITY_SETTING_KEYS.contains(&key.as_str()))
+}
+
+// 将局部更新合并到当前配置，返回新配置和发生变化的顶层字段
+pub fn merge_config_patch(current: &AppConfig, patch: &Value) -> Result<(AppConfig, Vec<String>), AppError> {
+    let patch = patch
+        .as_object()
+        .ok_or_else(|| AppError::invalid_argument("配置更新必须是对象"))?;
+    let current_value = serde_json::to_value(current).map_err(|e| AppError::new(ErrorType::SystemError, e.to_string()))?;
+
+    let mut merged = current_value.clone();
+    let fields = merged.as_object_mut().expect("AppConfig serializes to an object");
+    for (key, value) in patch {
+        let field = fields
+            .get_mut(key)
+            .ok_or_else(|| AppError::invalid_argument(format!("未知的配置项: {}", key)))?;
+        merge_value(field, value);
+    }
+
+    let next: AppConfig = serde_json::from_value(merged)
+        .map_err(|e| AppError::invalid_argument(format!("配置格式无效: {}", e)))?;
+    let next_value = serde_json::to_value(&next).map_err(|e| AppError::new(ErrorType::SystemError, e.to_string()))?;
+
+    let changed_keys = patch
+        .keys()
+        .filter(|key| current_value.get(key.as_str()) != next_value.get(key.as_str()))
+        .cloned()
+        .collect();
+    Ok((next, changed_keys))
+}
+
+// 嵌套对象逐字段合并，其余类型直接替换
+fn merge_value(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+pub fn validate_config(config: &AppConfig) -> Result<(), AppError> {
+    let mut result = ValidationService::validate_app_config(config);
+    if let Err(e) = validate_retention_policy(&config.retention) {
+        result.add_error("retention", &e.to_string(), "INVALID_VALUE");
+    }
+    result.into_app_result()
+}
+
+// 当前生效的配置：数据库尚未初始化或读取失败时使用默认配置
+pub fn effective_config() -> AppConfig {
+    match try_get_database() {
+        Some(database) => AppSettingsService::with_connection(database.get_connection()).load_or_default(),
+        None => AppConfig::default(),
+    }
+}
+
+pub struct AppSettingsService {
+    connection: DbConnection,
+}
+
+impl AppSettingsService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 数据保留策略仍以 retention_policy 表为准，其余字段来自 app_settings
+    pub fn load(&self) -> Result<AppConfig, AppError> {
+        let dao = AppSettingsDao::with_connection(self.connection.clone());
+        let mut config = match dao.load().map_err(dao_error)? {
+            Some((config, schema_version)) => {
+                if schema_version < APP_CONFIG_SCHEMA_VERSION {
+                    tracing::info!(
+                        "Upgrading stored app config from schema {} to {}",
+                        schema_version, APP_CONFIG_SCHEMA_VERSION
+                    );
+                    dao.save(&config, None).map_err(dao_error)?;
+                }
+                config
+            }
+            None => AppConfig::default(),
+        };
+
+        config.retention = RetentionDao::with_connection(self.connection.clone())
+            .load_policy()
+            .map_err(dao_error)?;
+        Ok(config)
+    }
+
+    pub fn load_or_default(&self) -> AppConfig {
+        self.load().unwrap_or_else(|e| {
+            tracing::warn!("Failed to load app config, using defaults: {}", e);
+            AppConfig::default()
+        })
+    }
+
+    // 校验不通过时不写入任何内容；没有实际变化时返回空的变更列表
+    pub fn update(&self, patch: &Value, updated_by: Option<&str>) -> Result<(AppConfig, Vec<String>), AppError> {
+        let current = self.load()?;
+        let (next, changed_keys) = merge_config_patch(&current, patch)?;
+        if changed_keys.is_empty() {
+            return Ok((current, changed_keys));
+        }
+        validate_config(&next)?;
+
+        if changed_keys.iter().any(|key| key == "retention") {
+            RetentionDao::with_connection(self.connection.clone())
+                .save_policy(&next.retention)
+                .map_err(dao_error)?;
+        }
+        AppSettingsDao::with_connection(self.connection.clone())
+            .save(&next, updated_by)
+            .map_err(dao_error)?;
+
+        tracing::info!("App config updated by {:?}: {:?}", updated_by, changed_keys);
+        Ok((next, changed_keys))
+    }
+}
+
+impl Default for AppSettingsService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MigrationManager;
+    use crate::services::FileService;
+    use crate::utils::{Locale, CODE_VALIDATION_FAILED};
+    use rusqlite::Connection;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn service() -> AppSettingsService {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        AppSettingsService::with_connection(Arc::new(Mutex::new(conn)))
+    }
+
+    #[test]
+    fn test_partial_update_only_touches_given_keys() {
+        let service = service();
+        let defaults = service.load().unwrap();
+
+        let (config, changed) = service
+            .update(&json!({ "windowLimits": { "maxWindows": 12 }, "retryAttempts": defaults.retry_attempts }), Some("admin"))
+            .unwrap();
+        assert_eq!(changed, vec!["windowLimits".to_string()]);
+        assert_eq!(config.window_limits.max_windows, 12);
+        // 嵌套对象中未提交的字段保持原值
+        assert_eq!(config.window_limits.max_consultation_windows, defaults.window_limits.max_consultation_windows);
+        assert_eq!(config.ws_url, defaults.ws_url);
+        assert!(!touches_security_settings(&changed));
+
+        let reloaded = service.load().unwrap();
+        assert_eq!(reloaded.window_limits.max_windows, 12);
+
+        let (_, changed) = service.update(&json!({ "retention": { "backupDays": 60 } }), Some("admin")).unwrap();
+        assert!(touches_security_settings(&changed));
+        assert_eq!(service.load().unwrap().retention.backup_days, 60);
+    }
+
+    #[test]
+    fn test_invalid_updates_rejected_without_saving() {
+        let service = service();
+        let defaults = service.load().unwrap();
+
+        let err = service.update(&json!({ "maxFileSize": 0, "wsUrl": "http://example.com/ws" }), None).unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_VALIDATION_FAILED));
+        let details = serde_json::to_string(&err.details).unwrap();
+        assert!(details.contains("maxFileSize"));
+        assert!(details.contains("wsUrl"));
+
+        assert!(service.update(&json!({ "apiBaseUrl": "not a url" }), None).is_err());
+        assert!(service.update(&json!({ "noSuchSetting": true }), None).is_err());
+        assert!(service.update(&json!({ "maxFileSize": "large" }), None).is_err());
+        assert!(service.update(&json!({ "retention": { "messageDays": 1 } }), None).is_err());
+
+        let reloaded = service.load().unwrap();
+        assert_eq!(reloaded.max_file_size, defaults.max_file_size);
+        assert_eq!(reloaded.ws_url, defaults.ws_url);
+        assert_eq!(reloaded.retention, defaults.retention);
+    }
+
+    #[test]
+    fn test_locale_saved_and_validated() {
+        let service = service();
+        assert_eq!(service.load().unwrap().locale, Locale::ZhCn);
+
+        let (config, changed) = service.update(&json!({ "locale": "en-US" }), Some("admin")).unwrap();
+        assert_eq!(changed, vec!["locale".to_string()]);
+        assert_eq!(config.locale, Locale::EnUs);
+        assert_eq!(service.load().unwrap().locale, Locale::EnUs);
+
+        assert!(service.update(&json!({ "locale": "ja-JP" }), None).is_err());
+        assert_eq!(service.load().unwrap().locale, Locale::EnUs);
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(ValidationService::validate_url("https://hospital.example.com/api", &["http", "https"]));
+        assert!(ValidationService::validate_url("wss://10.0.0.5:8443/ws", &["ws", "wss"]));
+        assert!(!ValidationService::validate_url("ftp://hospital.example.com", &["http", "https"]));
+        assert!(!ValidationService::validate_url("localhost:8080", &["http", "https"]));
+        assert!(!ValidationService::validate_url("", &["http", "https"]));
+    }
+
+    #[test]
+    fn test_saved_upload_allowlist_and_manifest_take_effect() {
+        let service = service();
+        let dir = tempfile::tempdir().unwrap();
+        let pdf = dir.path().join("检查报告.pdf");
+        std::fs::write(&pdf, b"%PDF-1.4\n1 0 obj << /Type /Pages /Count 0 >> endobj\n%%EOF").unwrap();
+        let files = FileService::new();
+        assert!(files.inspect_upload_candidate(&pdf, &service.load_or_default()).unwrap().accepted);
+
+        service
+            .update(
+                &json!({
+                    "allowedFileTypes": ["image/jpeg", "image/png"],
+                    "updateManifestUrl": "https://updates.hospital.example/manifest.json"
+                }),
+                Some("admin"),
+            )
+            .unwrap();
+
+        // 命令读取的生效配置包含已保存的修改
+        let config = service.load_or_default();
+        assert_eq!(config.update_manifest_url, "https://updates.hospital.example/manifest.json");
+        assert!(!files.inspect_upload_candidate(&pdf, &config).unwrap().accepted);
+    }
+}
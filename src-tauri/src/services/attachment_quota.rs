@@ -0,0 +1,276 @@
+// 附件配额：单个问诊和单个患者的附件总字节数不超过配置的上限，上传和下载缓存前检查
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, ConsultationDao, FileCacheDao};
+use crate::models::{AppConfig, AppError, AttachmentScope, AttachmentUsage, ErrorType};
+use crate::services::AppSettingsService;
+
+pub struct AttachmentQuotaService {
+    connection: DbConnection,
+}
+
+impl AttachmentQuotaService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn consultation_usage(&self, consultation_id: &str) -> Result<AttachmentUsage, AppError> {
+        let config = self.config()?;
+        self.consultation_usage_with(consultation_id, &config)
+    }
+
+    pub fn patient_usage(&self, patient_id: &str) -> Result<AttachmentUsage, AppError> {
+        let config = self.config()?;
+        self.patient_usage_with(patient_id, &config)
+    }
+
+    // 加入 incoming_bytes 后问诊或患者的用量超过上限时返回 QUOTA_EXCEEDED，先检查问诊再检查患者
+    pub fn check(&self, consultation_id: &str, incoming_bytes: u64) -> Result<(), AppError> {
+        let config = self.config()?;
+
+        let usage = self.consultation_usage_with(consultation_id, &config)?;
+        if incoming_bytes > usage.remaining_bytes() {
+            return Err(AppError::quota_exceeded(&usage, incoming_bytes));
+        }
+
+        // 本地还没有同步到的问诊无法确定患者，只按问诊配额限制
+        let consultation = ConsultationDao::with_connection(self.connection.clone())
+            .find_by_id(consultation_id)
+            .map_err(dao_error)?;
+        if let Some(consultation) = consultation {
+            let usage = self.patient_usage_with(&consultation.patient_id, &config)?;
+            if incoming_bytes > usage.remaining_bytes() {
+                return Err(AppError::quota_exceeded(&usage, incoming_bytes));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn consultation_usage_with(&self, consultation_id: &str, config: &AppConfig) -> Result<AttachmentUsage, AppError> {
+        let (used_bytes, file_count) = self.file_cache_dao().find_consultation_usage(consultation_id).map_err(dao_error)?;
+        Ok(AttachmentUsage {
+            scope: AttachmentScope::Consultation,
+            scope_id: consultation_id.to_string(),
+            used_bytes,
+            file_count,
+            limit_bytes: config.max_consultation_attachment_bytes,
+        })
+    }
+
+    fn patient_usage_with(&self, patient_id: &str, config: &AppConfig) -> Result<AttachmentUsage, AppError> {
+        let (used_bytes, file_count) = self.file_cache_dao().find_patient_usage(patient_id).map_err(dao_error)?;
+        Ok(AttachmentUsage {
+            scope: AttachmentScope::Patient,
+            scope_id: patient_id.to_string(),
+            used_bytes,
+            file_count,
+            limit_bytes: config.max_patient_attachment_bytes,
+        })
+    }
+
+    fn config(&self) -> Result<AppConfig, AppError> {
+        AppSettingsService::with_connection(self.connection.clone()).load()
+    }
+
+    fn file_cache_dao(&self) -> FileCacheDao {
+        FileCacheDao::with_connection(self.connection.clone())
+    }
+}
+
+impl Default for AttachmentQuotaService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MigrationManager;
+    use crate::models::FileCache;
+    use crate::utils::CODE_QUOTA_EXCEEDED;
+    use chrono::Utc;
+    use rusqlite::Connection;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    const MB: u64 = 1024 * 1024;
+
+    // 问诊上限 3MB，患者上限 5MB；患者 p1 有 c1、c2 两次问诊
+    fn setup() -> (DbConnection, AttachmentQuotaService) {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES
+                 ('c1', 'p1', 'd1', 'active'),
+                 ('c2', 'p1', 'd1', 'active');",
+        )
+        .unwrap();
+        let connection = Arc::new(Mutex::new(conn));
+        AppSettingsService::with_connection(connection.clone())
+            .update(
+                &json!({
+                    "maxFileSize": MB,
+                    "maxConsultationAttachmentBytes": 3 * MB,
+                    "maxPatientAttachmentBytes": 5 * MB,
+                }),
+                None,
+            )
+            .unwrap();
+        let service = AttachmentQuotaService::with_connection(connection.clone());
+        (connection, service)
+    }
+
+    fn attachment(connection: &DbConnection, name: &str, consultation_id: &str, size: u64) -> String {
+        let now = Utc::now();
+        FileCacheDao::with_connection(connection.clone())
+            .create(&FileCache {
+                id: String::new(),
+                file_url: format!("https://cdn.example.com/{}", name),
+                local_path: format!("/tmp/quota-test/{}", name),
+                file_size: Some(size),
+                mime_type: None,
+                checksum: None,
+                expires_at: None,
+                downloaded_at: now,
+                last_accessed: now,
+                pinned: false,
+                thumbnail_path: None,
+                bytes_downloaded: size,
+                preview_path: None,
+                preview_text: None,
+                consultation_id: Some(consultation_id.to_string()),
+                original_size: None,
+            })
+            .unwrap()
+    }
+
+    // 计数表与按 file_cache 实时汇总的结果一致
+    fn assert_counters_match(connection: &DbConnection) {
+        let conn = connection.lock().unwrap();
+        let mismatched: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM attachment_usage u
+                 LEFT JOIN (SELECT consultation_id, SUM(COALESCE(file_size, bytes_downloaded)) AS bytes, COUNT(*) AS files
+                            FROM file_cache WHERE consultation_id IS NOT NULL GROUP BY consultation_id) f
+                   ON f.consultation_id = u.consultation_id
+                 WHERE u.total_bytes != COALESCE(f.bytes, 0) OR u.file_count != COALESCE(f.files, 0)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(mismatched, 0);
+    }
+
+    #[test]
+    fn test_usage_aggregates_consultations_per_patient() {
+        let (connection, service) = setup();
+        attachment(&connection, "a.jpg", "c1", MB);
+        attachment(&connection, "b.jpg", "c1", 2 * MB);
+        attachment(&connection, "c.pdf", "c2", MB);
+
+        let consultation = service.consultation_usage("c1").unwrap();
+        assert_eq!(consultation.used_bytes, 3 * MB);
+        assert_eq!(consultation.file_count, 2);
+        assert_eq!(consultation.limit_bytes, 3 * MB);
+
+        let patient = service.patient_usage("p1").unwrap();
+        assert_eq!(patient.scope, AttachmentScope::Patient);
+        assert_eq!(patient.used_bytes, 4 * MB);
+        assert_eq!(patient.file_count, 3);
+        assert_eq!(service.patient_usage("p2").unwrap().used_bytes, 0);
+        assert_counters_match(&connection);
+    }
+
+    #[test]
+    fn test_counters_stay_consistent_after_deletes() {
+        let (connection, service) = setup();
+        let dao = FileCacheDao::with_connection(connection.clone());
+        let a = attachment(&connection, "a.jpg", "c1", MB);
+        attachment(&connection, "b.jpg", "c1", 2 * MB);
+        attachment(&connection, "c.pdf", "c2", MB);
+
+        dao.delete(&a).unwrap();
+        assert_eq!(service.consultation_usage("c1").unwrap().used_bytes, 2 * MB);
+        assert_counters_match(&connection);
+
+        // LRU 淘汰全部未固定文件
+        let evicted = dao.cleanup_lru(0, false).unwrap();
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(service.patient_usage("p1").unwrap().used_bytes, 0);
+        assert_eq!(service.consultation_usage("c2").unwrap().file_count, 0);
+        assert_counters_match(&connection);
+
+        // 按保留天数清理和本地文件缺失修复同样扣减用量
+        attachment(&connection, "d.jpg", "c1", MB);
+        attachment(&connection, "e.jpg", "c2", MB);
+        connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE file_cache SET last_accessed = datetime('now', '-40 days') WHERE local_path LIKE '%d.jpg'", [])
+            .unwrap();
+        assert_eq!(dao.cleanup_old_files(30, false).unwrap(), 1);
+        assert_eq!(service.consultation_usage("c1").unwrap().used_bytes, 0);
+        {
+            let conn = connection.lock().unwrap();
+            assert_eq!(FileCacheDao::delete_missing_files_in(&conn).unwrap().len(), 1);
+        }
+        assert_eq!(service.consultation_usage("c2").unwrap().used_bytes, 0);
+        assert_counters_match(&connection);
+    }
+
+    #[test]
+    fn test_download_progress_counts_toward_consultation() {
+        let (connection, service) = setup();
+        let dao = FileCacheDao::with_connection(connection.clone());
+        let url = "https://cdn.example.com/scan.pdf";
+
+        // 大小未知时按已下载字节计，响应头给出总大小后按总大小计
+        dao.save_download_progress(url, "/tmp/quota-test/scan.pdf", None, 1000, Some("c1")).unwrap();
+        assert_eq!(service.consultation_usage("c1").unwrap().used_bytes, 1000);
+        dao.save_download_progress(url, "/tmp/quota-test/scan.pdf", Some(MB), 2000, None).unwrap();
+        dao.complete_download(url, MB, "abc").unwrap();
+
+        let usage = service.consultation_usage("c1").unwrap();
+        assert_eq!(usage.used_bytes, MB);
+        assert_eq!(usage.file_count, 1);
+        assert_counters_match(&connection);
+    }
+
+    #[test]
+    fn test_rejects_when_quota_exceeded() {
+        let (connection, service) = setup();
+        attachment(&connection, "a.jpg", "c1", 2 * MB);
+
+        assert!(service.check("c1", MB).is_ok());
+
+        let err = service.check("c1", MB + 1).unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_QUOTA_EXCEEDED));
+        let details = err.details.unwrap();
+        assert_eq!(details["scope"], "consultation");
+        assert_eq!(details["scopeId"], "c1");
+        assert_eq!(details["usedBytes"], 2 * MB);
+        assert_eq!(details["limitBytes"], 3 * MB);
+        assert_eq!(details["requestedBytes"], MB + 1);
+
+        // c2 仍有空间，但患者总量会超过 5MB
+        attachment(&connection, "b.jpg", "c1", MB);
+        attachment(&connection, "c.pdf", "c2", MB);
+        let err = service.check("c2", MB + 1).unwrap_err();
+        let details = err.details.unwrap();
+        assert_eq!(details["scope"], "patient");
+        assert_eq!(details["scopeId"], "p1");
+        assert_eq!(details["usedBytes"], 4 * MB);
+    }
+}
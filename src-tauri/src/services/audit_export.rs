@@ -0,0 +1,406 @@
+// 审计日志导出（合规审查用）
+// 按页从 audit_logs 读取并写出，文件头记录导出元数据和正文的 SHA-256 用于防篡改校验
+// CSV：以 "# " 开头的元数据行，其后为表头和数据行
+// JSON：首行为元数据对象，其后每行一条日志（JSON Lines）
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::AuditLogDao;
+use crate::models::{AuditLog, AuditLogFilter};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const EXPORT_PAGE_SIZE: i64 = 500;
+const CSV_COLUMNS: [&str; 9] = [
+    "id",
+    "created_at",
+    "user_id",
+    "action",
+    "resource_type",
+    "resource_id",
+    "ip_address",
+    "user_agent",
+    "details",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditExportMetadata {
+    #[serde(rename = "generatedAt")]
+    pub generated_at: DateTime<Utc>,
+    pub filter: String,
+    #[serde(rename = "totalRows")]
+    pub total_rows: usize,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditExportResult {
+    pub path: String,
+    pub format: AuditExportFormat,
+    #[serde(flatten)]
+    pub metadata: AuditExportMetadata,
+}
+
+pub struct AuditExportService {
+    connection: DbConnection,
+}
+
+impl AuditExportService {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 正文先写入临时文件并计算摘要，完成后再与文件头合并为最终文件
+    pub fn export(&self, filter: &AuditLogFilter, format: AuditExportFormat, output_path: &Path) -> Result<AuditExportResult> {
+        if let Some(parent) = output_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let generated_at = Utc::now();
+        let body_path = body_path_for(output_path);
+        let (total_rows, sha256) = match self.write_body(filter, format, &body_path) {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = std::fs::remove_file(&body_path);
+                return Err(e);
+            }
+        };
+
+        let metadata = AuditExportMetadata {
+            generated_at,
+            filter: filter.summary(),
+            total_rows,
+            sha256,
+        };
+        let written = write_with_header(output_path, &body_path, format, &metadata);
+        let _ = std::fs::remove_file(&body_path);
+        written?;
+
+        tracing::info!(
+            "Exported {} audit logs to {} (filter: {})",
+            metadata.total_rows,
+            output_path.display(),
+            metadata.filter
+        );
+
+        Ok(AuditExportResult {
+            path: output_path.to_string_lossy().to_string(),
+            format,
+            metadata,
+        })
+    }
+
+    // 重新计算正文的 SHA-256 并与文件头记录的值比对
+    pub fn verify_export(path: &Path) -> Result<bool> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut hasher = Sha256::new();
+        let mut expected = None;
+
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if line.starts_with(b"{") && expected.is_none() {
+                // JSON 首行元数据
+                let header: serde_json::Value = serde_json::from_slice(&line)?;
+                expected = header["metadata"]["sha256"].as_str().map(str::to_string);
+                line.clear();
+                break;
+            }
+            if let Some(value) = line.strip_prefix(b"# sha256: ") {
+                expected = Some(String::from_utf8_lossy(value).trim().to_string());
+            } else if !line.starts_with(b"# ") {
+                // CSV 元数据之后的第一行属于正文
+                hasher.update(&line);
+                line.clear();
+                break;
+            }
+            line.clear();
+        }
+
+        let expected = expected.ok_or_else(|| anyhow!("导出文件缺少 sha256 元数据"))?;
+        std::io::copy(&mut reader, &mut hasher)?;
+        Ok(to_hex(&hasher.finalize()) == expected)
+    }
+
+    fn write_body(&self, filter: &AuditLogFilter, format: AuditExportFormat, path: &Path) -> Result<(usize, String)> {
+        let dao = AuditLogDao::with_connection(self.connection.clone());
+        let mut writer = BodyWriter::new(format, HashingWriter::new(BufWriter::new(File::create(path)?)))?;
+
+        let mut total_rows = 0;
+        let mut last: Option<AuditLog> = None;
+        loop {
+            let page = dao
+                .find_filtered_after(filter, last.as_ref(), EXPORT_PAGE_SIZE)
+                .map_err(|e| anyhow!(e.to_string()))?;
+
+            for log in &page {
+                writer.write_log(log)?;
+            }
+            total_rows += page.len();
+
+            if (page.len() as i64) < EXPORT_PAGE_SIZE {
+                break;
+            }
+            last = page.into_iter().last();
+        }
+
+        let mut hashing = writer.finish()?;
+        hashing.flush()?;
+        Ok((total_rows, to_hex(&hashing.hasher.finalize())))
+    }
+}
+
+impl Default for AuditExportService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 写入时同步计算 SHA-256
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// csv::Writer 自带较大的缓冲区，装箱后两种格式的大小相近
+enum BodyWriter<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json(W),
+}
+
+impl<W: Write> BodyWriter<W> {
+    fn new(format: AuditExportFormat, inner: W) -> Result<Self> {
+        Ok(match format {
+            AuditExportFormat::Csv => {
+                // details 中的逗号、引号和换行由 csv 按需加引号转义
+                let mut writer = csv::WriterBuilder::new()
+                    .quote_style(csv::QuoteStyle::Necessary)
+                    .from_writer(inner);
+                writer.write_record(CSV_COLUMNS)?;
+                BodyWriter::Csv(Box::new(writer))
+            }
+            AuditExportFormat::Json => BodyWriter::Json(inner),
+        })
+    }
+
+    fn write_log(&mut self, log: &AuditLog) -> Result<()> {
+        match self {
+            BodyWriter::Csv(writer) => {
+                writer.write_record([
+                    log.id.clone(),
+                    log.created_at.to_rfc3339(),
+                    log.user_id.clone().unwrap_or_default(),
+                    log.action.clone(),
+                    log.resource_type.clone().unwrap_or_default(),
+                    log.resource_id.clone().unwrap_or_default(),
+                    log.ip_address.clone().unwrap_or_default(),
+                    log.user_agent.clone().unwrap_or_default(),
+                    serde_json::to_string(&log.details)?,
+                ])?;
+            }
+            BodyWriter::Json(writer) => {
+                serde_json::to_writer(&mut *writer, log)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<W> {
+        match self {
+            BodyWriter::Csv(writer) => writer.into_inner().map_err(|e| anyhow!(e.to_string())),
+            BodyWriter::Json(writer) => Ok(writer),
+        }
+    }
+}
+
+fn body_path_for(output_path: &Path) -> PathBuf {
+    let mut name = output_path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+fn write_with_header(output_path: &Path, body_path: &Path, format: AuditExportFormat, metadata: &AuditExportMetadata) -> Result<()> {
+    let mut out = BufWriter::new(File::create(output_path)?);
+    match format {
+        AuditExportFormat::Csv => {
+            writeln!(out, "# generatedAt: {}", metadata.generated_at.to_rfc3339())?;
+            writeln!(out, "# filter: {}", metadata.filter.replace(['\r', '\n'], " "))?;
+            writeln!(out, "# totalRows: {}", metadata.total_rows)?;
+            writeln!(out, "# sha256: {}", metadata.sha256)?;
+        }
+        AuditExportFormat::Json => {
+            serde_json::to_writer(&mut out, &serde_json::json!({ "metadata": metadata }))?;
+            out.write_all(b"\n")?;
+        }
+    }
+
+    std::io::copy(&mut File::open(body_path)?, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::BaseDao;
+    use crate::database::migrations::MigrationManager;
+    use chrono::Duration;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    const SEED_ROWS: usize = 3000;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    // 三个用户轮流写入，每三条共用一个时间戳以覆盖分页边界上的同时刻记录
+    fn seed_logs(connection: &DbConnection) -> DateTime<Utc> {
+        let dao = AuditLogDao::with_connection(connection.clone());
+        let base = Utc::now() - Duration::days(1);
+        for i in 0..SEED_ROWS {
+            dao.create(&AuditLog {
+                id: String::new(),
+                user_id: Some(format!("doctor-{}", i % 3)),
+                action: if i % 2 == 0 { "view_patient" } else { "download_file" }.to_string(),
+                resource_type: Some("patient".to_string()),
+                resource_id: Some(format!("p{}", i)),
+                details: serde_json::json!({
+                    "status": "success",
+                    "note": format!("第 {} 条, 含 \"引号\"\n和换行", i),
+                }),
+                ip_address: Some("10.0.0.1".to_string()),
+                user_agent: None,
+                created_at: base + Duration::seconds((i / 3) as i64),
+            })
+            .unwrap();
+        }
+        base
+    }
+
+    #[test]
+    fn test_csv_export_counts_and_hash() {
+        let connection = create_test_connection();
+        seed_logs(&connection);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("audit.csv");
+
+        let filter = AuditLogFilter {
+            user_id: Some("doctor-1".to_string()),
+            ..Default::default()
+        };
+        let result = AuditExportService::with_connection(connection.clone())
+            .export(&filter, AuditExportFormat::Csv, &path)
+            .unwrap();
+        assert_eq!(result.metadata.total_rows, SEED_ROWS / 3);
+        assert_eq!(result.metadata.filter, "user=doctor-1");
+        assert!(!dir.path().join("audit.csv.part").exists());
+
+        // 元数据行以 # 开头，其余部分能按 CSV 正确读回
+        let mut reader = csv::ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .from_path(&path)
+            .unwrap();
+        assert_eq!(reader.headers().unwrap().iter().collect::<Vec<_>>(), CSV_COLUMNS);
+        let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), SEED_ROWS / 3);
+        assert!(records.iter().all(|r| &r[2] == "doctor-1"));
+        let details: serde_json::Value = serde_json::from_str(&records[0][8]).unwrap();
+        assert!(details["note"].as_str().unwrap().contains("\"引号\"\n"));
+
+        // 每条记录只导出一次
+        let mut ids: Vec<&str> = records.iter().map(|r| r.get(0).unwrap()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), SEED_ROWS / 3);
+
+        assert!(AuditExportService::verify_export(&path).unwrap());
+    }
+
+    #[test]
+    fn test_json_export_with_filters_and_tamper_detection() {
+        let connection = create_test_connection();
+        let base = seed_logs(&connection);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("exports").join("audit.json");
+
+        let filter = AuditLogFilter {
+            action: Some("download_file".to_string()),
+            start_time: Some(base),
+            end_time: Some(base + Duration::seconds(499)),
+            ..Default::default()
+        };
+        let expected = AuditLogDao::with_connection(connection.clone())
+            .count_filtered(&filter)
+            .unwrap() as usize;
+        assert_eq!(expected, 750);
+
+        let result = AuditExportService::with_connection(connection)
+            .export(&filter, AuditExportFormat::Json, &path)
+            .unwrap();
+        assert_eq!(result.metadata.total_rows, expected);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let (header, body) = content.split_once('\n').unwrap();
+        let header: serde_json::Value = serde_json::from_str(header).unwrap();
+        assert_eq!(header["metadata"]["totalRows"], expected);
+        assert_eq!(header["metadata"]["sha256"], result.metadata.sha256);
+        assert_eq!(to_hex(&Sha256::digest(body.as_bytes())), result.metadata.sha256);
+
+        let logs: Vec<AuditLog> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(logs.len(), expected);
+        assert!(logs.iter().all(|l| l.action == "download_file"));
+        assert!(logs.windows(2).all(|w| w[0].created_at <= w[1].created_at));
+        assert!(AuditExportService::verify_export(&path).unwrap());
+
+        // 修改正文后校验失败
+        std::fs::write(&path, content.replace("download_file", "view_patient")).unwrap();
+        assert!(!AuditExportService::verify_export(&path).unwrap());
+    }
+}
@@ -1,11 +1,14 @@
 // 认证服务
 
-use crate::models::{User, AuthSession, LoginCredentials, AuthResult, LoginType};
-use crate::utils::{crypto::CryptoService, error::AppError};
+use crate::models::{AppConfig, AppError, AuthProviderKind, AuthSession, LoginCredentials, AuthResult, LoginType, UserRole};
+use crate::services::auth_provider::{AuthProvider, HttpAuthProvider, MockAuthProvider};
+use crate::services::token_refresh::RefreshedToken;
 use anyhow::Result;
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine as _};
 use chrono::{DateTime, Utc, Duration};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct JwtClaims {
@@ -16,140 +19,72 @@ struct JwtClaims {
     role: String,    // 用户角色
 }
 
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenClaims {
+    pub subject: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
 pub struct AuthService {
-    crypto_service: CryptoService,
+    provider: Arc<dyn AuthProvider>,
     // 在实际应用中，这些应该存储在数据库中
     sessions: HashMap<String, AuthSession>,
 }
 
 impl AuthService {
-    pub fn new() -> Self {
+    pub fn new(config: &AppConfig) -> Self {
+        let provider: Arc<dyn AuthProvider> = match config.auth_provider {
+            AuthProviderKind::Mock => Arc::new(MockAuthProvider::new()),
+            AuthProviderKind::Http => Arc::new(HttpAuthProvider::new(
+                config.api_base_url.clone(),
+                HttpAuthProvider::DEFAULT_TIMEOUT,
+            )),
+        };
+
+        Self::with_provider(provider)
+    }
+
+    pub fn with_provider(provider: Arc<dyn AuthProvider>) -> Self {
         Self {
-            crypto_service: CryptoService::new(),
+            provider,
             sessions: HashMap::new(),
         }
     }
 
     pub async fn authenticate(&self, credentials: LoginCredentials) -> Result<AuthResult> {
-        match credentials.login_type {
+        let result = match credentials.login_type {
             LoginType::Password => {
-                self.authenticate_password(
+                self.provider.login_password(
                     credentials.username.as_deref().unwrap_or(""),
                     credentials.password.as_deref().unwrap_or(""),
                 ).await
             }
             LoginType::Sms => {
-                self.authenticate_sms(
+                self.provider.login_sms(
                     credentials.phone.as_deref().unwrap_or(""),
                     credentials.sms_code.as_deref().unwrap_or(""),
                 ).await
             }
             LoginType::Realname => {
-                self.authenticate_realname(
+                self.provider.login_realname(
                     credentials.id_card.as_deref().unwrap_or(""),
                 ).await
             }
-        }
-    }
-
-    async fn authenticate_password(&self, username: &str, password: &str) -> Result<AuthResult> {
-        // 模拟认证延迟
-        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-
-        // 简单的用户名密码验证（仅用于开发测试）
-        if username == "doctor" && password == "123456" {
-            let user_id = "1".to_string();
-            let token = self.generate_jwt_token(&user_id, username, "doctor")?;
-            let expires_at = Utc::now() + Duration::hours(8);
-
-            let user = User {
-                id: user_id.clone(),
-                username: username.to_string(),
-                encrypted_token: Some(token.clone()),
-                last_login: Some(Utc::now()),
-                session_expires: Some(expires_at),
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            };
-
-            Ok(AuthResult {
-                token,
-                user: serde_json::json!({
-                    "id": user.id,
-                    "username": user.username,
-                    "name": "张医生",
-                    "role": "doctor",
-                    "department": "内科",
-                    "title": "主治医师"
-                }),
-                expires_at: expires_at.to_rfc3339(),
-            })
-        } else {
-            Err(anyhow::anyhow!("用户名或密码错误"))
-        }
-    }
+        };
 
-    async fn authenticate_sms(&self, phone: &str, sms_code: &str) -> Result<AuthResult> {
-        // 模拟短信验证
-        tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
-
-        // 简单的验证码验证（仅用于开发测试）
-        if phone == "13800138000" && sms_code == "123456" {
-            let user_id = "2".to_string();
-            let username = format!("user_{}", &phone[7..]);
-            let token = self.generate_jwt_token(&user_id, &username, "doctor")?;
-            let expires_at = Utc::now() + Duration::hours(8);
-
-            Ok(AuthResult {
-                token,
-                user: serde_json::json!({
-                    "id": user_id,
-                    "username": username,
-                    "name": "李医生",
-                    "role": "doctor",
-                    "department": "外科",
-                    "title": "副主任医师"
-                }),
-                expires_at: expires_at.to_rfc3339(),
-            })
-        } else {
-            Err(anyhow::anyhow!("手机号或验证码错误"))
-        }
+        Ok(result?)
     }
 
-    async fn authenticate_realname(&self, id_card: &str) -> Result<AuthResult> {
-        // 模拟实名认证
-        tokio::time::sleep(tokio::time::Duration::from_millis(1500)).await;
-
-        // 简单的身份证验证（仅用于开发测试）
-        if id_card.len() == 18 {
-            let user_id = "3".to_string();
-            let username = format!("realname_{}", &id_card[14..18]);
-            let token = self.generate_jwt_token(&user_id, &username, "doctor")?;
-            let expires_at = Utc::now() + Duration::hours(8);
-
-            Ok(AuthResult {
-                token,
-                user: serde_json::json!({
-                    "id": user_id,
-                    "username": username,
-                    "name": "王医生",
-                    "role": "doctor",
-                    "department": "儿科",
-                    "title": "主任医师"
-                }),
-                expires_at: expires_at.to_rfc3339(),
-            })
-        } else {
-            Err(anyhow::anyhow!("身份证号格式错误"))
-        }
+    pub async fn send_sms_code(&self, phone: &str) -> Result<()> {
+        self.provider.send_sms_code(phone).await?;
+        Ok(())
     }
 
     pub async fn validate_token(&self, token: &str) -> Result<bool> {
         // 模拟验证延迟
         tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
 
-        match self.decode_jwt_token(token) {
+        match Self::decode_jwt_token(token) {
             Ok(claims) => {
                 let now = Utc::now().timestamp();
                 Ok(claims.exp > now)
@@ -158,56 +93,109 @@ impl AuthService {
         }
     }
 
-    pub async fn refresh_token(&self, current_token: &str) -> Result<String> {
-        // 模拟刷新延迟
-        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-        let claims = self.decode_jwt_token(current_token)?;
-
-        // 检查 token 是否即将过期（1小时内）
-        let now = Utc::now().timestamp();
-        if claims.exp - now > 3600 {
-            return Err(anyhow::anyhow!("Token 尚未到刷新时间"));
-        }
+    // 由认证后端签发新 token，HTTP 后端调用服务端刷新接口
+    pub async fn refresh_token(&self, current_token: &str) -> Result<RefreshedToken, AppError> {
+        self.provider.refresh_token(current_token).await
+    }
 
-        // 生成新的 token
-        let new_token = self.generate_jwt_token(&claims.sub, &claims.username, &claims.role)?;
-        Ok(new_token)
+    // 向认证服务确认会话仍然有效（签名、过期和是否被吊销），网络不可用时返回 NetworkError
+    pub async fn verify_session(&self, token: &str) -> Result<(), AppError> {
+        self.provider.verify_session(token).await
     }
 
     pub async fn logout(&self, token: &str) -> Result<()> {
         // TODO: 在实际应用中，应该将 token 加入黑名单
-        println!("User logged out with token: {}", token);
+        tracing::debug!("User logged out ({} byte token)", token.len());
         Ok(())
     }
 
-    fn generate_jwt_token(&self, user_id: &str, username: &str, role: &str) -> Result<String> {
-        let now = Utc::now();
-        let claims = JwtClaims {
-            sub: user_id.to_string(),
-            username: username.to_string(),
-            exp: (now + Duration::hours(8)).timestamp(),
-            iat: now.timestamp(),
-            role: role.to_string(),
+    // 读取 token 中的角色声明，同时支持本地模拟 token 和标准三段式 JWT
+    pub fn role_from_token(token: &str) -> Result<UserRole> {
+        let role = match Self::decode_jwt_token(token) {
+            Ok(claims) => claims.role,
+            Err(_) => decode_role_claim(token)?,
         };
 
-        // 在实际应用中，应该使用真正的 JWT 库
-        // 这里使用简单的 base64 编码作为模拟
-        let claims_json = serde_json::to_string(&claims)?;
-        let encoded = base64::encode(claims_json);
-        Ok(format!("jwt.{}", encoded))
+        UserRole::parse(&role).ok_or_else(|| anyhow::anyhow!("未知的用户角色: {}", role))
+    }
+
+    // token 中的用户和过期时间，本地模拟 token 和标准三段式 JWT 都只解析 payload，签名由服务端校验；
+    // 不透明的 token 两项都为 None
+    pub fn token_claims(token: &str) -> TokenClaims {
+        if let Ok(claims) = Self::decode_jwt_token(token) {
+            return TokenClaims {
+                subject: Some(claims.sub),
+                expires_at: DateTime::from_timestamp(claims.exp, 0),
+            };
+        }
+
+        #[derive(Deserialize)]
+        struct StandardClaims {
+            sub: Option<String>,
+            exp: Option<i64>,
+        }
+        let claims = token
+            .split('.')
+            .nth(1)
+            .and_then(|payload| URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok())
+            .and_then(|payload| serde_json::from_slice::<StandardClaims>(&payload).ok());
+        match claims {
+            Some(claims) => TokenClaims {
+                subject: claims.sub,
+                expires_at: claims.exp.and_then(|exp| DateTime::from_timestamp(exp, 0)),
+            },
+            None => TokenClaims::default(),
+        }
     }
 
-    fn decode_jwt_token(&self, token: &str) -> Result<JwtClaims> {
+    fn decode_jwt_token(token: &str) -> Result<JwtClaims> {
         if !token.starts_with("jwt.") {
             return Err(anyhow::anyhow!("Invalid token format"));
         }
 
         let encoded = &token[4..];
-        let decoded = base64::decode(encoded)?;
+        let decoded = STANDARD.decode(encoded)?;
         let claims_json = String::from_utf8(decoded)?;
         let claims: JwtClaims = serde_json::from_str(&claims_json)?;
 
         Ok(claims)
     }
-}
\ No newline at end of file
+}
+
+// 标准 JWT 只解析 payload 中的 role，签名由服务端校验
+fn decode_role_claim(token: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct RoleClaim {
+        role: String,
+    }
+
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("Invalid token format"))?;
+    let claims: RoleClaim = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload.trim_end_matches('='))?)?;
+    Ok(claims.role)
+}
+
+// 按本地模拟 token 中的用户和角色重新签发，供模拟认证后端刷新使用
+pub(crate) fn reissue_jwt_token(token: &str) -> Result<String> {
+    let claims = AuthService::decode_jwt_token(token)?;
+    encode_jwt_token(&claims.sub, &claims.username, &claims.role)
+}
+
+pub(crate) fn encode_jwt_token(user_id: &str, username: &str, role: &str) -> Result<String> {
+    let now = Utc::now();
+    let claims = JwtClaims {
+        sub: user_id.to_string(),
+        username: username.to_string(),
+        exp: (now + Duration::hours(8)).timestamp(),
+        iat: now.timestamp(),
+        role: role.to_string(),
+    };
+
+    // 在实际应用中，应该使用真正的 JWT 库
+    // 这里使用简单的 base64 编码作为模拟
+    let claims_json = serde_json::to_string(&claims)?;
+    let encoded = STANDARD.encode(claims_json);
+    Ok(format!("jwt.{}", encoded))
+}
@@ -0,0 +1,336 @@
+// 患者头像：通过 avatar:// 自定义协议向前端提供本地缓存的头像，未缓存时生成姓名首字头像
+
+use crate::database::dao::PatientDao;
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use anyhow::Result;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const AVATAR_SCHEME: &str = "avatar";
+// 生成头像的边长（像素）
+pub const AVATAR_SIZE: u32 = 128;
+// 已缓存的头像可长期复用；生成的头像在真实头像下载完成后应尽快替换
+const CACHED_AVATAR_MAX_AGE: u32 = 86400;
+const GENERATED_AVATAR_MAX_AGE: u32 = 300;
+const MAX_PATIENT_ID_LEN: usize = 128;
+
+// 首字头像背景色，按患者 ID 固定选取
+const AVATAR_COLORS: [[u8; 4]; 8] = [
+    [0x1f, 0x77, 0xb4, 0xff],
+    [0x2c, 0xa0, 0x2c, 0xff],
+    [0xd6, 0x27, 0x28, 0xff],
+    [0x94, 0x67, 0xbd, 0xff],
+    [0x8c, 0x56, 0x4b, 0xff],
+    [0xe3, 0x77, 0xc2, 0xff],
+    [0x17, 0xbe, 0xcf, 0xff],
+    [0xff, 0x7f, 0x0e, 0xff],
+];
+
+// 按顺序尝试的系统中文字体，均不可用时只绘制背景色
+const FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Medium.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvatarResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub cache_control: String,
+    pub body: Vec<u8>,
+}
+
+impl AvatarResponse {
+    fn image(body: Vec<u8>, content_type: &'static str, max_age: u32) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            cache_control: format!("private, max-age={}", max_age),
+            body,
+        }
+    }
+
+    fn error(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            cache_control: "no-store".to_string(),
+            body: Vec::new(),
+        }
+    }
+}
+
+// 从请求地址取出患者 ID：avatar://localhost/{id}（Windows 下为 http://avatar.localhost/{id}），也兼容 avatar://{id}
+// 只接受字母、数字、- 和 _，编码过的路径分隔符和 .. 一律拒绝
+pub fn avatar_patient_id(host: Option<&str>, path: &str) -> Option<String> {
+    let segment = path.trim_start_matches('/');
+    let candidate = if segment.is_empty() {
+        host.filter(|h| !matches!(*h, "localhost" | "avatar.localhost"))?
+    } else {
+        segment
+    };
+
+    let valid = !candidate.is_empty()
+        && candidate.len() <= MAX_PATIENT_ID_LEN
+        && candidate.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| candidate.to_string())
+}
+
+pub fn serve_avatar(dao: &PatientDao, cache_dir: &Path, host: Option<&str>, path: &str) -> AvatarResponse {
+    let Some(patient_id) = avatar_patient_id(host, path) else {
+        tracing::warn!("Rejected avatar request for path {:?}", path);
+        return AvatarResponse::error(400);
+    };
+
+    let avatar = match dao.find_avatar(&patient_id) {
+        Ok(Some(avatar)) => avatar,
+        Ok(None) => return AvatarResponse::error(404),
+        Err(e) => {
+            tracing::error!("Failed to look up avatar for patient {}: {}", patient_id, e);
+            return AvatarResponse::error(500);
+        }
+    };
+
+    if let Some((body, content_type)) = avatar
+        .cached_path
+        .as_deref()
+        .and_then(|cached| read_cached_avatar(cache_dir, Path::new(cached)))
+    {
+        return AvatarResponse::image(body, content_type, CACHED_AVATAR_MAX_AGE);
+    }
+
+    match render_initials_avatar(&avatar.patient_id, &avatar.name) {
+        Ok(png) => AvatarResponse::image(png, "image/png", GENERATED_AVATAR_MAX_AGE),
+        Err(e) => {
+            tracing::error!("Failed to render avatar for patient {}: {}", patient_id, e);
+            AvatarResponse::error(500)
+        }
+    }
+}
+
+// 缓存记录来自数据库，仍需确认文件位于下载目录内且确实是图片
+fn read_cached_avatar(cache_dir: &Path, path: &Path) -> Option<(Vec<u8>, &'static str)> {
+    let cache_dir = cache_dir.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    if !path.starts_with(&cache_dir) {
+        tracing::warn!("Cached avatar {:?} is outside the download directory", path);
+        return None;
+    }
+
+    let body = std::fs::read(&path).ok()?;
+    let content_type = infer::get(&body)
+        .map(|kind| kind.mime_type())
+        .filter(|mime| mime.starts_with("image/"))?;
+    Some((body, content_type))
+}
+
+// 中文姓名显示姓氏，其他姓名取前两个单词的首字母
+pub fn avatar_initials(name: &str) -> String {
+    let name = name.trim();
+    match name.chars().next() {
+        None => "?".to_string(),
+        Some(first) if !first.is_ascii() => first.to_string(),
+        Some(_) => name
+            .split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .take(2)
+            .flat_map(char::to_uppercase)
+            .collect(),
+    }
+}
+
+fn avatar_color(patient_id: &str) -> [u8; 4] {
+    let hash = patient_id
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    AVATAR_COLORS[hash as usize % AVATAR_COLORS.len()]
+}
+
+fn avatar_font() -> Option<&'static FontVec> {
+    static FONT: OnceLock<Option<FontVec>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        FONT_CANDIDATES.iter().find_map(|path| {
+            let data = std::fs::read(path).ok()?;
+            FontVec::try_from_vec_and_index(data, 0).ok()
+        })
+    })
+    .as_ref()
+}
+
+pub fn render_initials_avatar(patient_id: &str, name: &str) -> Result<Vec<u8>> {
+    let mut image = RgbaImage::from_pixel(AVATAR_SIZE, AVATAR_SIZE, Rgba(avatar_color(patient_id)));
+    match avatar_font() {
+        Some(font) => draw_centered_text(&mut image, font, &avatar_initials(name)),
+        None => tracing::debug!("No system font available, rendering avatar without initials"),
+    }
+
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+fn draw_centered_text(image: &mut RgbaImage, font: &FontVec, text: &str) {
+    let ratio = if text.chars().count() > 1 { 0.4 } else { 0.5 };
+    let scaled = font.as_scaled(PxScale::from(AVATAR_SIZE as f32 * ratio));
+
+    let mut caret = 0.0;
+    let mut glyphs = Vec::new();
+    for c in text.chars() {
+        let mut glyph = scaled.scaled_glyph(c);
+        glyph.position = point(caret, scaled.ascent());
+        caret += scaled.h_advance(glyph.id);
+        glyphs.push(glyph);
+    }
+
+    let offset_x = (AVATAR_SIZE as f32 - caret) / 2.0;
+    let offset_y = (AVATAR_SIZE as f32 - (scaled.ascent() - scaled.descent())) / 2.0;
+    for mut glyph in glyphs {
+        glyph.position.x += offset_x;
+        glyph.position.y += offset_y;
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|x, y, coverage| {
+            let px = bounds.min.x as i32 + x as i32;
+            let py = bounds.min.y as i32 + y as i32;
+            if px < 0 || py < 0 || px >= AVATAR_SIZE as i32 || py >= AVATAR_SIZE as i32 {
+                return;
+            }
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for channel in 0..3 {
+                let background = pixel.0[channel] as f32;
+                pixel.0[channel] = (background + (255.0 - background) * coverage.min(1.0)).round() as u8;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::migrations::MigrationManager;
+    use rusqlite::{params, Connection};
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name, avatar_url) VALUES
+                 ('p1', '张三', 'https://cdn.example.com/p1.png'),
+                 ('p2', '李四', 'https://cdn.example.com/p2.png');",
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn cache_avatar(connection: &DbConnection, url: &str, local_path: &Path) {
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO file_cache (id, file_url, local_path, checksum) VALUES (?1, ?2, ?3, 'abc')",
+                params![url, url, local_path.to_string_lossy()],
+            )
+            .unwrap();
+    }
+
+    fn png_bytes() -> Vec<u8> {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(2, 2))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_avatar_initials() {
+        assert_eq!(avatar_initials("张三"), "张");
+        assert_eq!(avatar_initials("  john smith "), "JS");
+        assert_eq!(avatar_initials("Alice"), "A");
+        assert_eq!(avatar_initials("Mary Ann Lee"), "MA");
+        assert_eq!(avatar_initials(""), "?");
+    }
+
+    #[test]
+    fn test_generated_avatar_is_deterministic_png() {
+        let png = render_initials_avatar("p1", "张三").unwrap();
+        assert_eq!(png, render_initials_avatar("p1", "张三").unwrap());
+
+        let image = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (AVATAR_SIZE, AVATAR_SIZE));
+        // 角落不会被文字覆盖，保持背景色
+        assert_eq!(image.get_pixel(0, 0).0, avatar_color("p1"));
+    }
+
+    #[test]
+    fn test_patient_id_rejects_path_traversal() {
+        assert_eq!(avatar_patient_id(Some("localhost"), "/p-1_a"), Some("p-1_a".to_string()));
+        assert_eq!(avatar_patient_id(Some("p1"), "/"), Some("p1".to_string()));
+        assert_eq!(avatar_patient_id(Some("avatar.localhost"), "/p1"), Some("p1".to_string()));
+
+        assert_eq!(avatar_patient_id(Some("localhost"), "/"), None);
+        assert_eq!(avatar_patient_id(Some("localhost"), "/../etc/passwd"), None);
+        assert_eq!(avatar_patient_id(Some("localhost"), "/p1/../../secret"), None);
+        assert_eq!(avatar_patient_id(Some("localhost"), "/..%2F..%2Fsecret"), None);
+        assert_eq!(avatar_patient_id(Some("localhost"), "/..\\windows"), None);
+        assert_eq!(avatar_patient_id(Some("localhost"), "/.."), None);
+        assert_eq!(avatar_patient_id(Some("localhost"), &format!("/{}", "a".repeat(200))), None);
+    }
+
+    #[test]
+    fn test_serve_cached_avatar() {
+        let connection = create_test_connection();
+        let cache_dir = tempdir().unwrap();
+        let cached = cache_dir.path().join("p1.png");
+        std::fs::write(&cached, png_bytes()).unwrap();
+        cache_avatar(&connection, "https://cdn.example.com/p1.png", &cached);
+
+        let dao = PatientDao::with_connection(connection);
+        let response = serve_avatar(&dao, cache_dir.path(), Some("localhost"), "/p1");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type, "image/png");
+        assert_eq!(response.body, png_bytes());
+        assert_eq!(response.cache_control, format!("private, max-age={}", CACHED_AVATAR_MAX_AGE));
+
+        // 未缓存的患者返回生成的首字头像
+        let generated = serve_avatar(&dao, cache_dir.path(), Some("localhost"), "/p2");
+        assert_eq!(generated.status, 200);
+        assert_eq!(generated.body, render_initials_avatar("p2", "李四").unwrap());
+        assert_eq!(generated.cache_control, format!("private, max-age={}", GENERATED_AVATAR_MAX_AGE));
+
+        assert_eq!(serve_avatar(&dao, cache_dir.path(), Some("localhost"), "/missing").status, 404);
+        assert_eq!(serve_avatar(&dao, cache_dir.path(), Some("localhost"), "/../p1").status, 400);
+    }
+
+    #[test]
+    fn test_cached_path_outside_download_dir_not_served() {
+        let connection = create_test_connection();
+        let cache_dir = tempdir().unwrap();
+        let outside_dir = tempdir().unwrap();
+        let outside = outside_dir.path().join("secret.png");
+        std::fs::write(&outside, png_bytes()).unwrap();
+
+        // 缓存记录借助 .. 指向下载目录之外的文件
+        let escaped = cache_dir.path().join("..").join(outside_dir.path().file_name().unwrap()).join("secret.png");
+        cache_avatar(&connection, "https://cdn.example.com/p1.png", &escaped);
+
+        let dao = PatientDao::with_connection(connection);
+        let response = serve_avatar(&dao, cache_dir.path(), Some("localhost"), "/p1");
+        assert_eq!(response.status, 200);
+        assert_ne!(response.body, png_bytes());
+        assert_eq!(response.body, render_initials_avatar("p1", "张三").unwrap());
+    }
+}
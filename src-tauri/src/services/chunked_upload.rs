@@ -0,0 +1,275 @@
+// 分片上传：大文件由前端按顺序分片传入并追加到临时文件，避免整个文件作为一个字节数组经 IPC 序列化
+// begin 时按声明的大小检查上限，append 时检查顺序和累计大小，take 时确认完整后交给上传或保存流程
+
+use crate::utils::{AppError, MessageKey, ValidationService};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// 不超过该大小的文件仍可直接以字节数组传入 upload_file / save_file_locally
+pub const INLINE_UPLOAD_LIMIT: u64 = 5 * 1024 * 1024;
+// 单个分片的上限
+pub const UPLOAD_CHUNK_SIZE: u64 = 1024 * 1024;
+// 超过该时间没有收到分片的传输视为已放弃，在下次开始传输时删除临时文件
+const TRANSFER_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const PARTIAL_SUFFIX: &str = "part";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadTransfer {
+    #[serde(rename = "transferId")]
+    pub transfer_id: String,
+    #[serde(rename = "chunkSize")]
+    pub chunk_size: u64,
+}
+
+// 已传输完整的文件，内容已读入内存，临时文件已删除
+#[derive(Debug, Clone)]
+pub struct CompletedUpload {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct PendingTransfer {
+    file_name: String,
+    total_size: u64,
+    received: u64,
+    next_index: u32,
+    path: PathBuf,
+    last_activity: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct ChunkedUploadManager {
+    transfers: Mutex<HashMap<String, PendingTransfer>>,
+}
+
+impl ChunkedUploadManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 声明的大小超过 max_size 时直接拒绝，不创建临时文件
+    pub fn begin(&self, dir: &Path, file_name: &str, total_size: u64, max_size: u64) -> Result<UploadTransfer, AppError> {
+        if total_size > max_size {
+            return Err(AppError::file_too_large(total_size, max_size));
+        }
+        if ValidationService::sanitize_filename(file_name).trim().is_empty() {
+            return Err(AppError::invalid_argument(MessageKey::FileNameRequired.text(&[])));
+        }
+        self.expire_idle(Instant::now());
+
+        let transfer_id = Uuid::new_v4().to_string();
+        let path = dir.join(format!("{}.{}", transfer_id, PARTIAL_SUFFIX));
+        std::fs::create_dir_all(dir)?;
+        std::fs::File::create(&path)?;
+
+        tracing::info!("Begin chunked upload {}: {} ({} bytes)", transfer_id, file_name, total_size);
+        self.transfers.lock().unwrap().insert(
+            transfer_id.clone(),
+            PendingTransfer {
+                file_name: file_name.to_string(),
+                total_size,
+                received: 0,
+                next_index: 0,
+                path,
+                last_activity: Instant::now(),
+            },
+        );
+
+        Ok(UploadTransfer {
+            transfer_id,
+            chunk_size: UPLOAD_CHUNK_SIZE,
+        })
+    }
+
+    // 分片必须从 0 开始连续编号；顺序错误时不写入，传输保持原状以便前端重发。返回已收到的字节数
+    pub fn append(&self, transfer_id: &str, index: u32, data: &[u8]) -> Result<u64, AppError> {
+        let mut transfers = self.transfers.lock().unwrap();
+        let transfer = transfers.get_mut(transfer_id).ok_or_else(AppError::upload_transfer_not_found)?;
+
+        if index != transfer.next_index {
+            return Err(AppError::upload_chunk_out_of_order(transfer.next_index, index));
+        }
+        if data.len() as u64 > UPLOAD_CHUNK_SIZE {
+            return Err(AppError::file_too_large(data.len() as u64, UPLOAD_CHUNK_SIZE));
+        }
+        let received = transfer.received + data.len() as u64;
+        if received > transfer.total_size {
+            return Err(AppError::file_too_large(received, transfer.total_size));
+        }
+
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&transfer.path)?
+            .write_all(data)?;
+        transfer.received = received;
+        transfer.next_index += 1;
+        transfer.last_activity = Instant::now();
+        Ok(received)
+    }
+
+    // 结束传输并读出文件内容；未传输完整时同样结束传输，前端需要重新开始
+    pub fn take(&self, transfer_id: &str) -> Result<CompletedUpload, AppError> {
+        let transfer = self
+            .transfers
+            .lock()
+            .unwrap()
+            .remove(transfer_id)
+            .ok_or_else(AppError::upload_transfer_not_found)?;
+
+        let result = if transfer.received == transfer.total_size {
+            std::fs::read(&transfer.path).map_err(AppError::from)
+        } else {
+            Err(AppError::invalid_argument(MessageKey::UploadIncomplete.text(&[
+                &ValidationService::format_file_size(transfer.received),
+                &ValidationService::format_file_size(transfer.total_size),
+            ])))
+        };
+        remove_partial(&transfer.path);
+
+        Ok(CompletedUpload {
+            file_name: transfer.file_name,
+            data: result?,
+        })
+    }
+
+    pub fn cancel(&self, transfer_id: &str) -> bool {
+        match self.transfers.lock().unwrap().remove(transfer_id) {
+            Some(transfer) => {
+                remove_partial(&transfer.path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expire_idle(&self, now: Instant) {
+        self.transfers.lock().unwrap().retain(|transfer_id, transfer| {
+            let active = now.saturating_duration_since(transfer.last_activity) < TRANSFER_IDLE_TIMEOUT;
+            if !active {
+                tracing::info!("Discarding idle chunked upload {}", transfer_id);
+                remove_partial(&transfer.path);
+            }
+            active
+        });
+    }
+}
+
+// 字节数组方式传入的文件在处理前检查内联上限和配置的文件大小上限
+pub fn check_inline_upload_size(size: u64, max_size: u64) -> Result<(), AppError> {
+    if size > max_size {
+        return Err(AppError::file_too_large(size, max_size));
+    }
+    if size > INLINE_UPLOAD_LIMIT {
+        return Err(AppError::inline_upload_too_large(size, INLINE_UPLOAD_LIMIT));
+    }
+    Ok(())
+}
+
+fn remove_partial(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        tracing::warn!("Failed to remove partial upload {:?}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{CODE_FILE_TOO_LARGE, CODE_UPLOAD_CHUNK_OUT_OF_ORDER, CODE_UPLOAD_TRANSFER_NOT_FOUND};
+    use tempfile::tempdir;
+
+    fn code(error: &AppError) -> &str {
+        error.code.as_deref().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_chunked_upload_happy_path() {
+        let dir = tempdir().unwrap();
+        let manager = ChunkedUploadManager::new();
+        let data: Vec<u8> = (0..(UPLOAD_CHUNK_SIZE * 2 + 100)).map(|i| (i % 251) as u8).collect();
+
+        let transfer = manager.begin(dir.path(), "检查报告.pdf", data.len() as u64, 50 * 1024 * 1024).unwrap();
+        assert_eq!(transfer.chunk_size, UPLOAD_CHUNK_SIZE);
+
+        let mut received = 0;
+        for (index, chunk) in data.chunks(UPLOAD_CHUNK_SIZE as usize).enumerate() {
+            received = manager.append(&transfer.transfer_id, index as u32, chunk).unwrap();
+        }
+        assert_eq!(received, data.len() as u64);
+
+        let completed = manager.take(&transfer.transfer_id).unwrap();
+        assert_eq!(completed.file_name, "检查报告.pdf");
+        assert_eq!(completed.data, data);
+
+        // 临时文件已删除，传输不能再次使用
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        let err = manager.take(&transfer.transfer_id).unwrap_err();
+        assert_eq!(code(&err), CODE_UPLOAD_TRANSFER_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_out_of_order_chunk_rejected() {
+        let dir = tempdir().unwrap();
+        let manager = ChunkedUploadManager::new();
+        let transfer = manager.begin(dir.path(), "photo.jpg", 6, 1024).unwrap();
+
+        manager.append(&transfer.transfer_id, 0, b"ab").unwrap();
+        let err = manager.append(&transfer.transfer_id, 2, b"ef").unwrap_err();
+        assert_eq!(code(&err), CODE_UPLOAD_CHUNK_OUT_OF_ORDER);
+        assert_eq!(err.details.as_ref().unwrap()["expectedIndex"], 1);
+        // 重复发送已收到的分片同样拒绝
+        let err = manager.append(&transfer.transfer_id, 0, b"ab").unwrap_err();
+        assert_eq!(code(&err), CODE_UPLOAD_CHUNK_OUT_OF_ORDER);
+
+        // 拒绝的分片没有写入，按顺序重发后内容正确
+        manager.append(&transfer.transfer_id, 1, b"cd").unwrap();
+        manager.append(&transfer.transfer_id, 2, b"ef").unwrap();
+        assert_eq!(manager.take(&transfer.transfer_id).unwrap().data, b"abcdef");
+    }
+
+    #[test]
+    fn test_size_cap() {
+        let dir = tempdir().unwrap();
+        let manager = ChunkedUploadManager::new();
+
+        // 声明的大小超过上限时不创建传输
+        let err = manager.begin(dir.path(), "large.pdf", 2048, 1024).unwrap_err();
+        assert_eq!(code(&err), CODE_FILE_TOO_LARGE);
+        assert_eq!(err.details.as_ref().unwrap()["maxBytes"], 1024);
+        assert!(manager.transfers.lock().unwrap().is_empty());
+
+        // 实际发送的字节数超过声明的大小
+        let transfer = manager.begin(dir.path(), "small.txt", 4, 1024).unwrap();
+        manager.append(&transfer.transfer_id, 0, b"abc").unwrap();
+        let err = manager.append(&transfer.transfer_id, 1, b"de").unwrap_err();
+        assert_eq!(code(&err), CODE_FILE_TOO_LARGE);
+
+        // 未传输完整时结束传输
+        assert!(manager.take(&transfer.transfer_id).is_err());
+        assert!(!manager.cancel(&transfer.transfer_id));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        assert!(check_inline_upload_size(INLINE_UPLOAD_LIMIT, 50 * 1024 * 1024).is_ok());
+        let err = check_inline_upload_size(INLINE_UPLOAD_LIMIT + 1, 50 * 1024 * 1024).unwrap_err();
+        assert_eq!(code(&err), CODE_FILE_TOO_LARGE);
+        let err = check_inline_upload_size(2048, 1024).unwrap_err();
+        assert_eq!(err.details.as_ref().unwrap()["maxBytes"], 1024);
+    }
+
+    #[test]
+    fn test_idle_transfer_expired() {
+        let dir = tempdir().unwrap();
+        let manager = ChunkedUploadManager::new();
+        let transfer = manager.begin(dir.path(), "photo.jpg", 4, 1024).unwrap();
+
+        manager.expire_idle(Instant::now() + TRANSFER_IDLE_TIMEOUT);
+        let err = manager.append(&transfer.transfer_id, 0, b"ab").unwrap_err();
+        assert_eq!(code(&err), CODE_UPLOAD_TRANSFER_NOT_FOUND);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,306 @@
+// 前端命令调用限流：按命令和调用窗口分别维护令牌桶，防止某个窗口的失控循环占满数据库连接
+
+use crate::models::AppConfig;
+use crate::utils::{AppError, MessageKey};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Instant;
+
+// 令牌桶：最多积累 capacity 个令牌，每秒补充 refill_per_second 个
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitRule {
+    pub capacity: f64,
+    pub refill_per_second: f64,
+}
+
+impl RateLimitRule {
+    pub const fn new(capacity: f64, refill_per_second: f64) -> Self {
+        Self { capacity, refill_per_second }
+    }
+}
+
+// 默认受限的命令：查询数据库较重或触发网络同步的命令，其余命令不限流
+pub const DEFAULT_RATE_LIMITS: &[(&str, RateLimitRule)] = &[
+    ("get_patient_list", RateLimitRule::new(10.0, 5.0)),
+    ("search_patients", RateLimitRule::new(10.0, 5.0)),
+    ("get_message_history", RateLimitRule::new(20.0, 10.0)),
+    ("get_audit_logs", RateLimitRule::new(5.0, 2.0)),
+    ("query_audit_logs", RateLimitRule::new(5.0, 2.0)),
+    ("sync_data", RateLimitRule::new(2.0, 0.2)),
+    ("sync_pending_messages", RateLimitRule::new(2.0, 0.2)),
+];
+
+// 命令的调用方：前端窗口按窗口标签分别计数，后台服务内部调用不受限
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandCaller {
+    Window(String),
+    Internal,
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    allowed: u64,
+    throttled: u64,
+    last_throttled_at: Option<DateTime<Utc>>,
+    // 上一次调用是否被拒绝
+    throttling: bool,
+}
+
+impl TokenBucket {
+    fn new(rule: &RateLimitRule, now: Instant) -> Self {
+        Self {
+            tokens: rule.capacity,
+            last_refill: now,
+            allowed: 0,
+            throttled: 0,
+            last_throttled_at: None,
+            throttling: false,
+        }
+    }
+
+    fn refill(&mut self, rule: &RateLimitRule, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rule.refill_per_second).min(rule.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateLimitStats {
+    pub command: String,
+    #[serde(rename = "windowLabel")]
+    pub window_label: String,
+    pub capacity: f64,
+    #[serde(rename = "refillPerSecond")]
+    pub refill_per_second: f64,
+    pub allowed: u64,
+    pub throttled: u64,
+    #[serde(rename = "lastThrottledAt")]
+    pub last_throttled_at: Option<DateTime<Utc>>,
+}
+
+pub struct CommandRateLimiter {
+    rules: HashMap<String, RateLimitRule>,
+    // (命令, 窗口标签) → 令牌桶
+    buckets: HashMap<(String, String), TokenBucket>,
+}
+
+impl CommandRateLimiter {
+    pub fn new() -> Self {
+        Self {
+            rules: default_rules(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    // 内置默认规则叠加应用配置 commandRateLimits 中按命令的覆盖；配置中移除的覆盖恢复为默认值，
+    // 已有的令牌桶按新规则继续计数
+    pub fn apply_config(&mut self, config: &AppConfig) {
+        self.rules = default_rules();
+        for (command, rule) in &config.command_rate_limits {
+            self.rules
+                .insert(command.clone(), RateLimitRule::new(rule.capacity, rule.refill_per_second));
+        }
+    }
+
+    // 覆盖或新增某个命令的限流规则，已有的令牌桶按新规则继续计数
+    pub fn with_rule(mut self, command: &str, rule: RateLimitRule) -> Self {
+        self.rules.insert(command.to_string(), rule);
+        self
+    }
+
+    pub fn check(&mut self, command: &str, caller: &CommandCaller) -> Result<(), AppError> {
+        self.check_at(command, caller, Instant::now())
+    }
+
+    // 超出限额时返回 RATE_LIMITED，details.retryAfterSeconds 为补足一个令牌所需的秒数
+    pub fn check_at(&mut self, command: &str, caller: &CommandCaller, now: Instant) -> Result<(), AppError> {
+        let CommandCaller::Window(label) = caller else {
+            return Ok(());
+        };
+        let Some(rule) = self.rules.get(command).copied() else {
+            return Ok(());
+        };
+
+        let bucket = self
+            .buckets
+            .entry((command.to_string(), label.clone()))
+            .or_insert_with(|| TokenBucket::new(&rule, now));
+        bucket.refill(&rule, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.allowed += 1;
+            bucket.throttling = false;
+            return Ok(());
+        }
+
+        // 连续超限时只在第一次记录日志，避免失控循环刷屏
+        if !bucket.throttling {
+            tracing::warn!("Command {} from window {} rate limited", command, label);
+        }
+        bucket.throttling = true;
+        bucket.throttled += 1;
+        bucket.last_throttled_at = Some(Utc::now());
+
+        let retry_after = ((1.0 - bucket.tokens) / rule.refill_per_second).ceil().max(1.0) as u64;
+        Err(AppError::rate_limited(MessageKey::CommandRateLimited.text(&[&retry_after]), retry_after))
+    }
+
+    // 按命令、窗口排序，供诊断页面展示
+    pub fn stats(&self) -> Vec<RateLimitStats> {
+        let mut stats: Vec<RateLimitStats> = self
+            .buckets
+            .iter()
+            .filter_map(|((command, label), bucket)| {
+                let rule = self.rules.get(command)?;
+                Some(RateLimitStats {
+                    command: command.clone(),
+                    window_label: label.clone(),
+                    capacity: rule.capacity,
+                    refill_per_second: rule.refill_per_second,
+                    allowed: bucket.allowed,
+                    throttled: bucket.throttled,
+                    last_throttled_at: bucket.last_throttled_at,
+                })
+            })
+            .collect();
+        stats.sort_by(|a, b| a.command.cmp(&b.command).then_with(|| a.window_label.cmp(&b.window_label)));
+        stats
+    }
+
+    // 窗口关闭后丢弃其令牌桶
+    pub fn remove_window(&mut self, label: &str) {
+        self.buckets.retain(|(_, window_label), _| window_label != label);
+    }
+}
+
+fn default_rules() -> HashMap<String, RateLimitRule> {
+    DEFAULT_RATE_LIMITS
+        .iter()
+        .map(|(command, rule)| (command.to_string(), *rule))
+        .collect()
+}
+
+impl Default for CommandRateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CommandRateLimitConfig;
+    use crate::utils::CODE_RATE_LIMITED;
+    use std::time::Duration;
+
+    fn window(label: &str) -> CommandCaller {
+        CommandCaller::Window(label.to_string())
+    }
+
+    #[test]
+    fn test_hammering_is_throttled_and_recovers() {
+        let mut limiter = CommandRateLimiter::new().with_rule("get_patient_list", RateLimitRule::new(10.0, 5.0));
+        let start = Instant::now();
+        let main = window("main");
+
+        // 同一时刻连续调用 200 次，只有桶容量内的请求通过
+        let allowed = (0..200)
+            .filter(|_| limiter.check_at("get_patient_list", &main, start).is_ok())
+            .count();
+        assert_eq!(allowed, 10);
+
+        let err = limiter.check_at("get_patient_list", &main, start).unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_RATE_LIMITED));
+        assert_eq!(err.details.as_ref().unwrap()["retryAfterSeconds"], 1);
+
+        // 200ms 补充一个令牌
+        let later = start + Duration::from_millis(200);
+        assert!(limiter.check_at("get_patient_list", &main, later).is_ok());
+        assert!(limiter.check_at("get_patient_list", &main, later).is_err());
+
+        // 停止调用足够久后恢复到满桶，但不超过容量
+        let recovered = start + Duration::from_secs(60);
+        let allowed = (0..20)
+            .filter(|_| limiter.check_at("get_patient_list", &main, recovered).is_ok())
+            .count();
+        assert_eq!(allowed, 10);
+
+        let stats = limiter.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].allowed, 21);
+        assert_eq!(stats[0].throttled, 200 - 10 + 1 + 1 + 10);
+        assert!(stats[0].last_throttled_at.is_some());
+    }
+
+    #[test]
+    fn test_windows_limited_independently() {
+        let mut limiter = CommandRateLimiter::new().with_rule("search_patients", RateLimitRule::new(2.0, 1.0));
+        let now = Instant::now();
+
+        for _ in 0..50 {
+            let _ = limiter.check_at("search_patients", &window("consultation-1a2b3c4d"), now);
+        }
+        assert!(limiter.check_at("search_patients", &window("consultation-1a2b3c4d"), now).is_err());
+        assert!(limiter.check_at("search_patients", &window("main"), now).is_ok());
+
+        // 关闭窗口后其计数被丢弃
+        limiter.remove_window("consultation-1a2b3c4d");
+        assert!(limiter.check_at("search_patients", &window("consultation-1a2b3c4d"), now).is_ok());
+    }
+
+    #[test]
+    fn test_internal_and_unlisted_commands_bypass() {
+        let mut limiter = CommandRateLimiter::new().with_rule("sync_data", RateLimitRule::new(1.0, 0.1));
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert!(limiter.check_at("sync_data", &CommandCaller::Internal, now).is_ok());
+            assert!(limiter.check_at("get_app_health", &window("main"), now).is_ok());
+        }
+        assert!(limiter.stats().is_empty());
+
+        assert!(limiter.check_at("sync_data", &window("main"), now).is_ok());
+        let err = limiter.check_at("sync_data", &window("main"), now).unwrap_err();
+        assert_eq!(err.details.as_ref().unwrap()["retryAfterSeconds"], 10);
+    }
+
+    #[test]
+    fn test_rules_follow_app_config() {
+        let mut config = AppConfig::default();
+        config.command_rate_limits.insert(
+            "get_patient_list".to_string(),
+            CommandRateLimitConfig { capacity: 2.0, refill_per_second: 1.0 },
+        );
+        config.command_rate_limits.insert(
+            "export_patients".to_string(),
+            CommandRateLimitConfig { capacity: 1.0, refill_per_second: 0.5 },
+        );
+        let mut limiter = CommandRateLimiter::new();
+        limiter.apply_config(&config);
+        let now = Instant::now();
+        let main = window("main");
+
+        let allowed = (0..10)
+            .filter(|_| limiter.check_at("get_patient_list", &main, now).is_ok())
+            .count();
+        assert_eq!(allowed, 2);
+        assert!(limiter.check_at("export_patients", &main, now).is_ok());
+        assert!(limiter.check_at("export_patients", &main, now).is_err());
+
+        // 移除覆盖后恢复默认规则，未列入默认规则的命令不再限流
+        limiter.apply_config(&AppConfig::default());
+        let later = now + Duration::from_secs(60);
+        let allowed = (0..20)
+            .filter(|_| limiter.check_at("get_patient_list", &main, later).is_ok())
+            .count();
+        assert_eq!(allowed, 10);
+        for _ in 0..5 {
+            assert!(limiter.check_at("export_patients", &main, later).is_ok());
+        }
+    }
+}
@@ -0,0 +1,808 @@
+// 问诊服务：接诊、完成、取消及候诊队列
+
+use crate::database::connection::DbConnection;
+use crate::database::dao::consultation_dao::{window_start, StatusTransition};
+use crate::database::dao::{BaseDao, ConsultationDao, MessageDao};
+use crate::models::{
+    AppError, Consultation, ConsultationMetrics, ConsultationPriority, ConsultationQueueItem, ConsultationStatus,
+    ConsultationTransfer, ConsultationTransferResult, ConversationOverview, DailyCount, DailyLatency, ErrorType, Message,
+    PaginatedResponse, SystemEventKind,
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+// 工作台图表最多统计的天数
+const MAX_METRICS_DAYS: u32 = 365;
+// 会话列表单页最多条数
+const MAX_CONVERSATION_PAGE_SIZE: u32 = 100;
+
+pub type ConsultationResult<T> = Result<T, AppError>;
+
+pub struct ConsultationService {
+    consultation_dao: ConsultationDao,
+}
+
+impl ConsultationService {
+    pub fn new() -> Self {
+        Self {
+            consultation_dao: ConsultationDao::new(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            consultation_dao: ConsultationDao::with_connection(connection),
+        }
+    }
+
+    pub async fn get_consultation(&self, consultation_id: &str) -> ConsultationResult<Consultation> {
+        self.load(consultation_id)
+    }
+
+    pub async fn accept_consultation(&self, consultation_id: &str, user_id: Option<&str>) -> ConsultationResult<Consultation> {
+        self.transition(consultation_id, ConsultationStatus::Active, "accept_consultation", user_id, None, None, None)
+    }
+
+    pub async fn complete_consultation(
+        &self,
+        consultation_id: &str,
+        diagnosis: &str,
+        prescription: Option<&str>,
+        user_id: Option<&str>,
+    ) -> ConsultationResult<Consultation> {
+        if diagnosis.trim().is_empty() {
+            return Err(AppError::new(ErrorType::ValidationError, "完成问诊前必须填写诊断").with_code("DIAGNOSIS_REQUIRED"));
+        }
+
+        self.transition(
+            consultation_id,
+            ConsultationStatus::Completed,
+            "complete_consultation",
+            user_id,
+            Some(diagnosis.trim()),
+            prescription.map(str::trim).filter(|p| !p.is_empty()),
+            None,
+        )
+    }
+
+    pub async fn cancel_consultation(&self, consultation_id: &str, reason: &str, user_id: Option<&str>) -> ConsultationResult<Consultation> {
+        if reason.trim().is_empty() {
+            return Err(AppError::new(ErrorType::ValidationError, "取消问诊必须填写原因").with_code("CANCEL_REASON_REQUIRED"));
+        }
+
+        self.transition(
+            consultation_id,
+            ConsultationStatus::Cancelled,
+            "cancel_consultation",
+            user_id,
+            None,
+            None,
+            Some(reason.trim()),
+        )
+    }
+
+    // 把待接诊或进行中的问诊转给另一位医生，并在会话中留下系统提示
+    pub async fn transfer_consultation(
+        &self,
+        consultation_id: &str,
+        target_doctor_id: &str,
+        target_doctor_name: Option<&str>,
+        note: Option<&str>,
+        user_id: Option<&str>,
+    ) -> ConsultationResult<ConsultationTransferResult> {
+        let target_doctor_id = target_doctor_id.trim();
+        if target_doctor_id.is_empty() {
+            return Err(AppError::new(ErrorType::ValidationError, "请选择转接的医生").with_code("TRANSFER_TARGET_REQUIRED"));
+        }
+
+        let current = self.load(consultation_id)?;
+        ensure_transferable(parse_status(&current.status)?)?;
+
+        if current.doctor_id == target_doctor_id {
+            return Err(AppError::new(ErrorType::ValidationError, "问诊已由该医生接诊").with_code("TRANSFER_TARGET_UNCHANGED"));
+        }
+
+        let now = Utc::now();
+        let transfer = ConsultationTransfer {
+            id: Uuid::new_v4().to_string(),
+            consultation_id: consultation_id.to_string(),
+            from_doctor_id: current.doctor_id.clone(),
+            to_doctor_id: target_doctor_id.to_string(),
+            note: note.map(str::trim).filter(|n| !n.is_empty()).map(str::to_string),
+            created_at: now,
+        };
+        let notice = MessageDao::system_message(
+            consultation_id,
+            SystemEventKind::Transferred,
+            serde_json::json!({
+                "fromDoctorId": transfer.from_doctor_id,
+                "toDoctorId": transfer.to_doctor_id,
+                "toDoctorName": target_doctor_name.map(str::trim).filter(|n| !n.is_empty()),
+                "note": transfer.note,
+            }),
+        );
+
+        let applied = self
+            .consultation_dao
+            .transfer(&transfer, &notice, user_id)
+            .map_err(dao_error)?;
+
+        // 条件更新失败说明问诊已被并发结束或转走，按最新状态报告
+        if !applied {
+            let latest = self.load(consultation_id)?;
+            ensure_transferable(parse_status(&latest.status)?)?;
+            return Err(AppError::new(ErrorType::ValidationError, "问诊已被其他操作转接，请刷新后重试")
+                .with_code("CONSULTATION_NOT_TRANSFERABLE"));
+        }
+
+        Ok(ConsultationTransferResult {
+            consultation: self.load(consultation_id)?,
+            transfer,
+            notice,
+        })
+    }
+
+    pub async fn get_consultation_transfers(&self, consultation_id: &str) -> ConsultationResult<Vec<ConsultationTransfer>> {
+        self.consultation_dao.find_transfers(consultation_id).map_err(dao_error)
+    }
+
+    // 进行中和待接诊的问诊，先按优先级从高到低，同优先级按等待时长从长到短排列
+    pub async fn get_consultation_queue(&self, doctor_id: &str) -> ConsultationResult<Vec<ConsultationQueueItem>> {
+        let now = Utc::now();
+        let mut queue: Vec<ConsultationQueueItem> = self
+            .consultation_dao
+            .get_active_consultations(doctor_id)
+            .map_err(dao_error)?
+            .into_iter()
+            .map(|consultation| {
+                let wait_seconds = (now - consultation.queued_since()).num_seconds().max(0);
+                ConsultationQueueItem {
+                    wait_seconds,
+                    wait_minutes: wait_seconds / 60,
+                    consultation,
+                }
+            })
+            .collect();
+
+        queue.sort_by(|a, b| {
+            b.consultation
+                .priority
+                .cmp(&a.consultation.priority)
+                .then(b.wait_seconds.cmp(&a.wait_seconds))
+        });
+        Ok(queue)
+    }
+
+    // 返回修改前的优先级和修改后的问诊；优先级未变化时不写库
+    pub async fn set_priority(
+        &self,
+        consultation_id: &str,
+        priority: ConsultationPriority,
+    ) -> ConsultationResult<(ConsultationPriority, Consultation)> {
+        let previous = self.load(consultation_id)?.priority;
+        self.consultation_dao
+            .set_priority(consultation_id, priority)
+            .map_err(dao_error)?;
+        Ok((previous, self.load(consultation_id)?))
+    }
+
+    pub async fn get_conversation_list(
+        &self,
+        doctor_id: &str,
+        page: u32,
+        page_size: u32,
+    ) -> ConsultationResult<PaginatedResponse<ConversationOverview>> {
+        let page = page.max(1);
+        let page_size = page_size.clamp(1, MAX_CONVERSATION_PAGE_SIZE);
+        let result = self
+            .consultation_dao
+            .get_conversation_overviews(doctor_id, page as i32, page_size as i32)
+            .map_err(dao_error)?;
+
+        Ok(PaginatedResponse {
+            items: result.items,
+            total: result.total.max(0) as u32,
+            page,
+            page_size,
+            total_pages: result.total_pages.max(0) as u32,
+        })
+    }
+
+    pub async fn get_consultation_metrics(&self, doctor_id: &str, days: u32) -> ConsultationResult<ConsultationMetrics> {
+        let days = days.clamp(1, MAX_METRICS_DAYS);
+        let counts = self.consultation_dao.get_daily_counts(doctor_id, days).map_err(dao_error)?;
+        let latencies = self.consultation_dao.get_daily_first_response(doctor_id, days).map_err(dao_error)?;
+        let completed_by_type = self
+            .consultation_dao
+            .get_completion_counts_by_type(doctor_id, days)
+            .map_err(dao_error)?;
+
+        // 按样本数加权得到整体平均值
+        let samples: i64 = latencies.iter().map(|l| l.samples).sum();
+        let average_first_response_seconds = if samples > 0 {
+            let total: f64 = latencies
+                .iter()
+                .map(|l| l.average_seconds.unwrap_or(0.0) * l.samples as f64)
+                .sum();
+            Some(total / samples as f64)
+        } else {
+            None
+        };
+
+        // 补齐没有数据的日期，方便前端直接绘图
+        let dates = window_dates(days);
+        let daily_counts = dates
+            .iter()
+            .map(|date| DailyCount {
+                date: date.clone(),
+                count: counts.iter().find(|c| &c.date == date).map(|c| c.count).unwrap_or(0),
+            })
+            .collect();
+        let daily_first_response = dates
+            .iter()
+            .map(|date| {
+                latencies.iter().find(|l| &l.date == date).cloned().unwrap_or(DailyLatency {
+                    date: date.clone(),
+                    average_seconds: None,
+                    samples: 0,
+                })
+            })
+            .collect();
+
+        Ok(ConsultationMetrics {
+            days,
+            daily_counts,
+            daily_first_response,
+            average_first_response_seconds,
+            completed_by_type,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn transition(
+        &self,
+        consultation_id: &str,
+        next: ConsultationStatus,
+        action: &str,
+        user_id: Option<&str>,
+        diagnosis: Option<&str>,
+        prescription: Option<&str>,
+        cancel_reason: Option<&str>,
+    ) -> ConsultationResult<Consultation> {
+        let current = self.load(consultation_id)?;
+        let current_status = parse_status(&current.status)?;
+
+        if !current_status.can_transition_to(next) {
+            return Err(illegal_transition(current_status, next));
+        }
+
+        let notices = transition_notices(consultation_id, next, prescription, cancel_reason);
+        let applied = self
+            .consultation_dao
+            .transition_status(&StatusTransition {
+                consultation_id,
+                from: current_status.as_str(),
+                to: next.as_str(),
+                action,
+                user_id,
+                diagnosis,
+                prescription,
+                cancel_reason,
+                notices: &notices,
+            })
+            .map_err(dao_error)?;
+
+        // 条件更新失败说明状态已被并发修改，按最新状态报告
+        if !applied {
+            let latest = parse_status(&self.load(consultation_id)?.status)?;
+            return Err(illegal_transition(latest, next));
+        }
+
+        self.load(consultation_id)
+    }
+
+    fn load(&self, consultation_id: &str) -> ConsultationResult<Consultation> {
+        self.consultation_dao
+            .find_by_id(consultation_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| {
+                AppError::new(ErrorType::DataError, format!("问诊不存在: {}", consultation_id))
+                    .with_code("CONSULTATION_NOT_FOUND")
+            })
+    }
+}
+
+impl Default for ConsultationService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn window_dates(days: u32) -> Vec<String> {
+    let start = window_start(days).date_naive();
+    (0..days as i64)
+        .map(|offset| (start + chrono::Duration::days(offset)).format("%Y-%m-%d").to_string())
+        .collect()
+}
+
+fn parse_status(value: &str) -> ConsultationResult<ConsultationStatus> {
+    ConsultationStatus::parse(value).ok_or_else(|| {
+        AppError::new(ErrorType::DataError, format!("未知的问诊状态: {}", value)).with_code("UNKNOWN_CONSULTATION_STATUS")
+    })
+}
+
+fn illegal_transition(from: ConsultationStatus, to: ConsultationStatus) -> AppError {
+    AppError::new(
+        ErrorType::ValidationError,
+        format!("问诊状态不能从「{}」变更为「{}」", from.label(), to.label()),
+    )
+    .with_code("ILLEGAL_CONSULTATION_TRANSITION")
+    .with_details(serde_json::json!({ "from": from, "to": to }))
+}
+
+// 状态变更时写入会话的事件标记，开具处方先于问诊结束
+fn transition_notices(
+    consultation_id: &str,
+    next: ConsultationStatus,
+    prescription: Option<&str>,
+    cancel_reason: Option<&str>,
+) -> Vec<Message> {
+    let event = |kind, payload| MessageDao::system_message(consultation_id, kind, payload);
+
+    match next {
+        ConsultationStatus::Active => vec![event(SystemEventKind::ConsultationStarted, serde_json::Value::Null)],
+        ConsultationStatus::Completed => {
+            let mut notices = Vec::new();
+            if prescription.is_some() {
+                notices.push(event(SystemEventKind::PrescriptionIssued, serde_json::Value::Null));
+            }
+            notices.push(event(SystemEventKind::ConsultationCompleted, serde_json::Value::Null));
+            notices
+        }
+        ConsultationStatus::Cancelled => vec![event(
+            SystemEventKind::ConsultationCancelled,
+            serde_json::json!({ "reason": cancel_reason }),
+        )],
+        ConsultationStatus::Pending => Vec::new(),
+    }
+}
+
+// 只有尚未结束的问诊可以转接
+fn ensure_transferable(status: ConsultationStatus) -> ConsultationResult<()> {
+    match status {
+        ConsultationStatus::Pending | ConsultationStatus::Active => Ok(()),
+        _ => Err(AppError::new(ErrorType::ValidationError, format!("{}的问诊不能转接", status.label()))
+            .with_code("CONSULTATION_NOT_TRANSFERABLE")
+            .with_details(serde_json::json!({ "status": status }))),
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::PatientDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{MessageType, Patient, SenderType, SystemEvent, TypeCount};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn seed_consultation(connection: &DbConnection, status: &str) -> String {
+        let now = Utc::now();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "张三".to_string(),
+                age: Some(35),
+                gender: Some("male".to_string()),
+                phone: None,
+                id_card: None,
+                tags: vec![],
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+
+        ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                status: status.to_string(),
+                consultation_type: "text".to_string(),
+                title: None,
+                description: None,
+                diagnosis: None,
+                prescription: None,
+                created_at: now,
+                updated_at: now,
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap()
+    }
+
+    fn audit_count(connection: &DbConnection, consultation_id: &str) -> i64 {
+        connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM audit_logs WHERE resource_type = 'consultation' AND resource_id = ?1",
+                [consultation_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    fn assert_illegal(error: AppError) {
+        assert!(matches!(error.error_type, ErrorType::ValidationError));
+        assert_eq!(error.code.as_deref(), Some("ILLEGAL_CONSULTATION_TRANSITION"));
+    }
+
+    #[tokio::test]
+    async fn test_accept_then_complete() {
+        let connection = create_test_connection();
+        let id = seed_consultation(&connection, "pending");
+        let service = ConsultationService::with_connection(connection.clone());
+
+        let accepted = service.accept_consultation(&id, Some("d1")).await.unwrap();
+        assert_eq!(accepted.status, "active");
+        assert!(accepted.accepted_at.is_some());
+
+        let completed = service
+            .complete_consultation(&id, "高血压", Some("降压药"), Some("d1"))
+            .await
+            .unwrap();
+        assert_eq!(completed.status, "completed");
+        assert!(completed.completed_at.is_some());
+        assert_eq!(completed.diagnosis.as_deref(), Some("高血压"));
+        assert_eq!(completed.prescription.as_deref(), Some("降压药"));
+        assert_eq!(audit_count(&connection, &id), 2);
+
+        // 每次流转都在会话中留下事件标记
+        let texts: Vec<String> = {
+            let conn = connection.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT content FROM messages WHERE consultation_id = ?1 AND sender_type = 'system' ORDER BY timestamp ASC")
+                .unwrap();
+            let rows = stmt.query_map([&id], |row| row.get::<_, String>(0)).unwrap();
+            rows.map(|content| SystemEvent::parse(&content.unwrap()).unwrap().text).collect()
+        };
+        assert_eq!(texts, vec!["问诊已开始", "处方已开具", "问诊已结束"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_and_active() {
+        let connection = create_test_connection();
+        let service = ConsultationService::with_connection(connection.clone());
+
+        let pending = seed_consultation(&connection, "pending");
+        let cancelled = service.cancel_consultation(&pending, "患者未到", Some("d1")).await.unwrap();
+        assert_eq!(cancelled.status, "cancelled");
+        assert_eq!(cancelled.cancel_reason.as_deref(), Some("患者未到"));
+
+        let active = seed_consultation(&connection, "active");
+        let cancelled = service.cancel_consultation(&active, "转线下就诊", Some("d1")).await.unwrap();
+        assert_eq!(cancelled.status, "cancelled");
+        assert_eq!(audit_count(&connection, &active), 1);
+    }
+
+    #[tokio::test]
+    async fn test_illegal_transitions_rejected() {
+        let connection = create_test_connection();
+        let service = ConsultationService::with_connection(connection.clone());
+
+        // completed -> active
+        let completed = seed_consultation(&connection, "completed");
+        assert_illegal(service.accept_consultation(&completed, None).await.unwrap_err());
+
+        // pending -> completed
+        let pending = seed_consultation(&connection, "pending");
+        assert_illegal(service.complete_consultation(&pending, "诊断", None, None).await.unwrap_err());
+
+        // cancelled -> cancelled
+        let cancelled = seed_consultation(&connection, "cancelled");
+        assert_illegal(service.cancel_consultation(&cancelled, "重复取消", None).await.unwrap_err());
+
+        // completed -> cancelled
+        assert_illegal(service.cancel_consultation(&completed, "已完成", None).await.unwrap_err());
+
+        // 非法流转不落库、不记审计
+        assert_eq!(audit_count(&connection, &completed), 0);
+        let reloaded = ConsultationDao::with_connection(connection).find_by_id(&completed).unwrap().unwrap();
+        assert_eq!(reloaded.status, "completed");
+    }
+
+    #[tokio::test]
+    async fn test_missing_consultation_and_diagnosis() {
+        let connection = create_test_connection();
+        let service = ConsultationService::with_connection(connection.clone());
+
+        let error = service.accept_consultation("missing", None).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("CONSULTATION_NOT_FOUND"));
+
+        let active = seed_consultation(&connection, "active");
+        let error = service.complete_consultation(&active, "  ", None, None).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("DIAGNOSIS_REQUIRED"));
+    }
+
+    fn insert_message(connection: &DbConnection, consultation_id: &str, sender: &str, at: chrono::DateTime<Utc>) {
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp, sync_status, read_status)
+                 VALUES (?1, ?2, ?3, 'text', 'hi', ?4, 'synced', 'read')",
+                rusqlite::params![uuid::Uuid::new_v4().to_string(), consultation_id, sender, at],
+            )
+            .unwrap();
+    }
+
+    fn set_consultation(connection: &DbConnection, id: &str, consultation_type: &str, created_at: chrono::DateTime<Utc>) {
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE consultations SET consultation_type = ?1, created_at = ?2 WHERE id = ?3",
+                rusqlite::params![consultation_type, created_at, id],
+            )
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_first_response_average() {
+        let connection = create_test_connection();
+        let today = Utc::now() - chrono::Duration::minutes(1);
+        let yesterday = today - chrono::Duration::days(1);
+
+        // 今天：60 秒和 180 秒，平均 120 秒
+        let a = seed_consultation(&connection, "completed");
+        set_consultation(&connection, &a, "text", today);
+        insert_message(&connection, &a, "patient", today);
+        insert_message(&connection, &a, "patient", today + chrono::Duration::seconds(30));
+        insert_message(&connection, &a, "doctor", today + chrono::Duration::seconds(60));
+        insert_message(&connection, &a, "doctor", today + chrono::Duration::seconds(90));
+
+        let b = seed_consultation(&connection, "completed");
+        set_consultation(&connection, &b, "video", today);
+        // 医生在患者之前发的消息不算作响应
+        insert_message(&connection, &b, "doctor", today - chrono::Duration::seconds(10));
+        insert_message(&connection, &b, "patient", today);
+        insert_message(&connection, &b, "doctor", today + chrono::Duration::seconds(180));
+
+        // 昨天：300 秒
+        let c = seed_consultation(&connection, "active");
+        set_consultation(&connection, &c, "text", yesterday);
+        insert_message(&connection, &c, "patient", yesterday);
+        insert_message(&connection, &c, "doctor", yesterday + chrono::Duration::seconds(300));
+
+        // 没有消息和医生未回复的问诊不参与响应统计
+        let d = seed_consultation(&connection, "pending");
+        set_consultation(&connection, &d, "text", today);
+        let e = seed_consultation(&connection, "pending");
+        set_consultation(&connection, &e, "text", today);
+        insert_message(&connection, &e, "patient", today);
+
+        // 窗口之外的数据
+        let old = seed_consultation(&connection, "completed");
+        set_consultation(&connection, &old, "text", today - chrono::Duration::days(40));
+
+        let metrics = ConsultationService::with_connection(connection)
+            .get_consultation_metrics("d1", 30)
+            .await
+            .unwrap();
+
+        assert_eq!(metrics.daily_counts.len(), 30);
+        assert_eq!(metrics.daily_first_response.len(), 30);
+
+        let today_key = today.format("%Y-%m-%d").to_string();
+        let yesterday_key = yesterday.format("%Y-%m-%d").to_string();
+        let today_count = metrics.daily_counts.iter().find(|c| c.date == today_key).unwrap();
+        assert_eq!(today_count.count, 4);
+        assert_eq!(metrics.daily_counts.iter().map(|c| c.count).sum::<i64>(), 5);
+
+        let today_latency = metrics.daily_first_response.iter().find(|l| l.date == today_key).unwrap();
+        assert_eq!(today_latency.samples, 2);
+        assert!((today_latency.average_seconds.unwrap() - 120.0).abs() < 0.01);
+
+        let yesterday_latency = metrics.daily_first_response.iter().find(|l| l.date == yesterday_key).unwrap();
+        assert!((yesterday_latency.average_seconds.unwrap() - 300.0).abs() < 0.01);
+
+        // (60 + 180 + 300) / 3
+        assert!((metrics.average_first_response_seconds.unwrap() - 180.0).abs() < 0.01);
+
+        assert_eq!(
+            metrics.completed_by_type,
+            vec![
+                TypeCount { consultation_type: "text".to_string(), count: 1 },
+                TypeCount { consultation_type: "video".to_string(), count: 1 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics_without_data() {
+        let metrics = ConsultationService::with_connection(create_test_connection())
+            .get_consultation_metrics("d1", 7)
+            .await
+            .unwrap();
+
+        assert_eq!(metrics.daily_counts.len(), 7);
+        assert!(metrics.daily_counts.iter().all(|c| c.count == 0));
+        assert!(metrics.daily_first_response.iter().all(|l| l.average_seconds.is_none()));
+        assert!(metrics.average_first_response_seconds.is_none());
+        assert!(metrics.completed_by_type.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_sorted_by_wait_time() {
+        let connection = create_test_connection();
+        let older = seed_consultation(&connection, "active");
+        let newer = seed_consultation(&connection, "pending");
+        seed_consultation(&connection, "completed");
+
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE consultations SET created_at = ?1, queued_at = ?1 WHERE id = ?2",
+                rusqlite::params![Utc::now() - chrono::Duration::minutes(20), older],
+            )
+            .unwrap();
+
+        let queue = ConsultationService::with_connection(connection.clone())
+            .get_consultation_queue("d1")
+            .await
+            .unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0].consultation.id, older);
+        assert_eq!(queue[1].consultation.id, newer);
+        assert!(queue[0].wait_seconds >= 20 * 60);
+        assert_eq!(queue[0].wait_minutes, queue[0].wait_seconds / 60);
+
+        // 加急的问诊排在等待更久的普通问诊之前
+        let service = ConsultationService::with_connection(connection);
+        let (previous, updated) = service.set_priority(&newer, ConsultationPriority::Urgent).await.unwrap();
+        assert_eq!(previous, ConsultationPriority::Normal);
+        assert_eq!(updated.priority, ConsultationPriority::Urgent);
+        let queue = service.get_consultation_queue("d1").await.unwrap();
+        assert_eq!(queue[0].consultation.id, newer);
+        assert_eq!(queue[1].consultation.id, older);
+    }
+
+    fn transfer_count(connection: &DbConnection, consultation_id: &str) -> i64 {
+        connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM consultation_transfers WHERE consultation_id = ?1",
+                [consultation_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transfer_active_consultation() {
+        let connection = create_test_connection();
+        let id = seed_consultation(&connection, "active");
+        let service = ConsultationService::with_connection(connection.clone());
+
+        let result = service
+            .transfer_consultation(&id, "d2", Some("李医生"), Some(" 专科会诊 "), Some("d1"))
+            .await
+            .unwrap();
+        assert_eq!(result.consultation.doctor_id, "d2");
+        assert_eq!(result.consultation.status, "active");
+        assert_eq!(result.transfer.from_doctor_id, "d1");
+        assert_eq!(result.transfer.note.as_deref(), Some("专科会诊"));
+        let event = SystemEvent::parse(result.notice.content.as_deref().unwrap()).unwrap();
+        assert_eq!(event.kind, SystemEventKind::Transferred);
+        assert_eq!(event.text, "已转接给李医生");
+
+        let messages = crate::database::dao::MessageDao::with_connection(connection.clone())
+            .find_by_consultation_id(&id, 1, 10)
+            .unwrap();
+        assert_eq!(messages.items.len(), 1);
+        assert!(matches!(messages.items[0].sender_type, SenderType::System));
+        assert!(matches!(messages.items[0].message_type, MessageType::Event));
+
+        let transfers = service.get_consultation_transfers(&id).await.unwrap();
+        assert_eq!(transfers, vec![result.transfer.clone()]);
+
+        let details: String = connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT details FROM audit_logs WHERE action = 'transfer_consultation' AND resource_id = ?1",
+                [&id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let details: serde_json::Value = serde_json::from_str(&details).unwrap();
+        assert_eq!(details["fromDoctorId"], "d1");
+        assert_eq!(details["toDoctorId"], "d2");
+    }
+
+    #[tokio::test]
+    async fn test_transfer_rejected_for_finished_or_same_doctor() {
+        let connection = create_test_connection();
+        let service = ConsultationService::with_connection(connection.clone());
+
+        let completed = seed_consultation(&connection, "completed");
+        let error = service
+            .transfer_consultation(&completed, "d2", None, None, Some("d1"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("CONSULTATION_NOT_TRANSFERABLE"));
+
+        let cancelled = seed_consultation(&connection, "cancelled");
+        let error = service
+            .transfer_consultation(&cancelled, "d2", None, None, Some("d1"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("CONSULTATION_NOT_TRANSFERABLE"));
+
+        let pending = seed_consultation(&connection, "pending");
+        let error = service
+            .transfer_consultation(&pending, "d1", None, None, Some("d1"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("TRANSFER_TARGET_UNCHANGED"));
+
+        let error = service
+            .transfer_consultation(&pending, "  ", None, None, Some("d1"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("TRANSFER_TARGET_REQUIRED"));
+
+        let reloaded = ConsultationDao::with_connection(connection.clone()).find_by_id(&completed).unwrap().unwrap();
+        assert_eq!(reloaded.doctor_id, "d1");
+        assert_eq!(transfer_count(&connection, &completed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_rolls_back_when_message_insert_fails() {
+        let connection = create_test_connection();
+        let id = seed_consultation(&connection, "pending");
+        connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER fail_message_insert BEFORE INSERT ON messages
+                 BEGIN SELECT RAISE(ABORT, 'message insert failed'); END;",
+            )
+            .unwrap();
+
+        let service = ConsultationService::with_connection(connection.clone());
+        let error = service
+            .transfer_consultation(&id, "d2", Some("李医生"), None, Some("d1"))
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("message insert failed"));
+
+        // 接诊医生、转接记录和审计日志都不应落库
+        let reloaded = ConsultationDao::with_connection(connection.clone()).find_by_id(&id).unwrap().unwrap();
+        assert_eq!(reloaded.doctor_id, "d1");
+        assert_eq!(transfer_count(&connection, &id), 0);
+        assert_eq!(audit_count(&connection, &id), 0);
+    }
+}
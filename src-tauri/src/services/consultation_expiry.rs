@@ -0,0 +1,387 @@
+// 问诊超时自动结束：进行中的问诊长时间双方都没有消息时自动完成，结束前 1 小时提醒医生
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::consultation_dao::{InactiveConsultation, StatusTransition};
+use crate::database::dao::{BaseDao, ConsultationDao, MessageDao};
+use crate::models::{AppError, ConsultationStatus, ErrorType, SystemEventKind};
+use crate::services::AppSettingsService;
+use crate::utils::format_local;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+pub const CONSULTATION_EXPIRING_EVENT: &str = "consultation-expiring";
+pub const AUTO_COMPLETE_ACTION: &str = "auto_complete_consultation";
+
+const SCAN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+// 自动结束前多久提醒医生
+pub fn expiry_warning_lead() -> Duration {
+    Duration::hours(1)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConsultationExpiry {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "doctorId")]
+    pub doctor_id: String,
+    #[serde(rename = "patientId")]
+    pub patient_id: String,
+    #[serde(rename = "lastActivityAt")]
+    pub last_activity_at: DateTime<Utc>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+    // 按配置时区格式化的自动结束时间，提醒文案直接使用
+    #[serde(rename = "expiresAtLocal")]
+    pub expires_at_local: String,
+}
+
+impl ConsultationExpiry {
+    fn new(inactive: InactiveConsultation, inactivity: Duration) -> Self {
+        let expires_at = inactive.last_activity_at + inactivity;
+        Self {
+            expires_at,
+            expires_at_local: format_local(expires_at),
+            consultation_id: inactive.consultation_id,
+            doctor_id: inactive.doctor_id,
+            patient_id: inactive.patient_id,
+            last_activity_at: inactive.last_activity_at,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ExpiryScanResult {
+    // 本次新发出提醒的问诊
+    pub warned: Vec<ConsultationExpiry>,
+    pub completed: Vec<String>,
+}
+
+pub struct ConsultationExpiryService {
+    // 应用启动时数据库尚未打开，未指定时使用时再取全局连接
+    connection: Option<DbConnection>,
+    // 已提醒的问诊及提醒时的最后活动时间，有新消息后可再次提醒
+    warned: Mutex<HashMap<String, DateTime<Utc>>>,
+    event_sender: mpsc::UnboundedSender<ConsultationExpiry>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ConsultationExpiryService {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<ConsultationExpiry>) {
+        Self::build(None)
+    }
+
+    pub fn with_connection(connection: DbConnection) -> (Self, mpsc::UnboundedReceiver<ConsultationExpiry>) {
+        Self::build(Some(connection))
+    }
+
+    fn build(connection: Option<DbConnection>) -> (Self, mpsc::UnboundedReceiver<ConsultationExpiry>) {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+
+        let service = Self {
+            connection,
+            warned: Mutex::new(HashMap::new()),
+            event_sender,
+            task: Mutex::new(None),
+        };
+
+        (service, event_receiver)
+    }
+
+    // 需在 tokio 运行时内调用；超时时长每次扫描时从应用配置读取，修改后无需重启
+    pub fn start(self: &Arc<Self>) {
+        let mut task = self.task.lock().unwrap();
+        if task.is_none() {
+            let service = self.clone();
+            *task = Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(SCAN_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let scanned = service
+                        .inactivity()
+                        .and_then(|inactivity| service.scan(Utc::now(), inactivity));
+                    if let Err(e) = scanned {
+                        tracing::error!("Consultation expiry scan failed: {}", e);
+                    }
+                }
+            }));
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    fn connection(&self) -> DbConnection {
+        self.connection
+            .clone()
+            .unwrap_or_else(|| get_database().get_connection())
+    }
+
+    pub fn inactivity(&self) -> Result<Duration> {
+        let config = AppSettingsService::with_connection(self.connection()).load()?;
+        Ok(Duration::hours(config.consultation_inactivity_hours as i64))
+    }
+
+    // 超时的问诊自动完成，即将超时的问诊发出一次提醒
+    pub fn scan(&self, now: DateTime<Utc>, inactivity: Duration) -> Result<ExpiryScanResult> {
+        let consultation_dao = ConsultationDao::with_connection(self.connection());
+        let candidates = consultation_dao
+            .find_inactive_consultations(now - inactivity + expiry_warning_lead())
+            .map_err(dao_error)?;
+
+        let mut result = ExpiryScanResult::default();
+        let mut warned = self.warned.lock().unwrap();
+        // 已延长或已结束的问诊不再保留提醒记录
+        warned.retain(|id, _| candidates.iter().any(|c| &c.consultation_id == id));
+
+        for candidate in candidates {
+            let expiry = ConsultationExpiry::new(candidate, inactivity);
+
+            if expiry.expires_at <= now {
+                if self.auto_complete(&consultation_dao, &expiry, inactivity)? {
+                    warned.remove(&expiry.consultation_id);
+                    result.completed.push(expiry.consultation_id);
+                }
+                continue;
+            }
+
+            if warned.get(&expiry.consultation_id) == Some(&expiry.last_activity_at) {
+                continue;
+            }
+            warned.insert(expiry.consultation_id.clone(), expiry.last_activity_at);
+            let _ = self.event_sender.send(expiry.clone());
+            result.warned.push(expiry);
+        }
+
+        Ok(result)
+    }
+
+    // 医生选择继续问诊：写入一条系统消息，超时从此刻重新计算
+    pub fn keep_alive(&self, consultation_id: &str, inactivity: Duration) -> Result<ConsultationExpiry> {
+        let consultation = ConsultationDao::with_connection(self.connection())
+            .find_by_id(consultation_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| {
+                AppError::new(ErrorType::DataError, format!("问诊不存在: {}", consultation_id))
+                    .with_code("CONSULTATION_NOT_FOUND")
+            })?;
+        if consultation.status != ConsultationStatus::Active.as_str() {
+            return Err(AppError::new(ErrorType::ValidationError, "只有进行中的问诊可以延长")
+                .with_code("CONSULTATION_NOT_ACTIVE")
+                .into());
+        }
+
+        let notice = MessageDao::with_connection(self.connection())
+            .insert_system_message(consultation_id, SystemEventKind::KeptAlive, serde_json::Value::Null)
+            .map_err(dao_error)?;
+        self.warned.lock().unwrap().remove(consultation_id);
+
+        tracing::info!("Consultation {} kept alive", consultation_id);
+        let expires_at = notice.timestamp + inactivity;
+        Ok(ConsultationExpiry {
+            consultation_id: consultation.id,
+            doctor_id: consultation.doctor_id,
+            patient_id: consultation.patient_id,
+            last_activity_at: notice.timestamp,
+            expires_at,
+            expires_at_local: format_local(expires_at),
+        })
+    }
+
+    // 与手动完成共用状态流转，同一事务内写入系统消息和审计日志；并发变更时返回 false
+    fn auto_complete(&self, dao: &ConsultationDao, expiry: &ConsultationExpiry, inactivity: Duration) -> Result<bool> {
+        let notice = MessageDao::system_message(
+            &expiry.consultation_id,
+            SystemEventKind::AutoCompleted,
+            serde_json::json!({ "inactiveHours": inactivity.num_hours() }),
+        );
+        let applied = dao
+            .transition_status(&StatusTransition {
+                consultation_id: &expiry.consultation_id,
+                from: ConsultationStatus::Active.as_str(),
+                to: ConsultationStatus::Completed.as_str(),
+                action: AUTO_COMPLETE_ACTION,
+                user_id: None,
+                diagnosis: None,
+                prescription: None,
+                cancel_reason: None,
+                notices: &[notice],
+            })
+            .map_err(dao_error)?;
+
+        if applied {
+            tracing::info!(
+                "Consultation {} auto-completed after inactivity since {}",
+                expiry.consultation_id,
+                expiry.last_activity_at
+            );
+        }
+        Ok(applied)
+    }
+}
+
+impl Drop for ConsultationExpiryService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::Consultation;
+    use rusqlite::Connection;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status, accepted_at) VALUES
+                 ('c1', 'p1', 'd1', 'active', '2024-03-01 08:00:00+00:00'),
+                 ('c2', 'p1', 'd1', 'active', '2024-03-01 08:00:00+00:00'),
+                 ('c3', 'p1', 'd1', 'active', '2024-03-02 07:30:00+00:00'),
+                 ('c4', 'p1', 'd1', 'completed', '2024-03-01 08:00:00+00:00');
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp) VALUES
+                 ('m1', 'c1', 'patient', 'text', '医生您好', '2024-03-01 09:00:00+00:00'),
+                 ('m2', 'c1', 'doctor', 'text', '请描述症状', '2024-03-01 09:30:00+00:00'),
+                 ('m3', 'c2', 'patient', 'text', '我头疼', '2024-03-01 09:00:00+00:00'),
+                 ('m4', 'c2', 'doctor', 'text', '多休息', '2024-03-02 09:10:00+00:00'),
+                 ('m5', 'c4', 'patient', 'text', '谢谢', '2024-03-01 08:30:00+00:00');",
+        )
+        .unwrap();
+        Arc::new(std::sync::Mutex::new(conn))
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn load(connection: &DbConnection, id: &str) -> Consultation {
+        ConsultationDao::with_connection(connection.clone()).find_by_id(id).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_find_inactive_uses_latest_message_or_accept_time() {
+        let connection = create_test_connection();
+        let dao = ConsultationDao::with_connection(connection);
+
+        let inactive = dao.find_inactive_consultations(at("2024-03-02T08:00:00Z")).unwrap();
+        let found: Vec<(&str, DateTime<Utc>)> = inactive
+            .iter()
+            .map(|c| (c.consultation_id.as_str(), c.last_activity_at))
+            .collect();
+        // c2 有更晚的消息，c4 已结束；c3 没有消息，按接诊时间计算
+        assert_eq!(
+            found,
+            vec![("c1", at("2024-03-01T09:30:00Z")), ("c3", at("2024-03-02T07:30:00Z"))]
+        );
+
+        assert!(dao.find_inactive_consultations(at("2024-03-01T09:00:00Z")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_inactive_same_day_cutoff_across_formats() {
+        let connection = create_test_connection();
+        // 应用写入的消息时间为 RFC3339 UTC，接诊时间为空格分隔格式
+        connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO consultations (id, patient_id, doctor_id, status, accepted_at) VALUES
+                     ('c5', 'p1', 'd1', 'active', '2024-03-01 08:00:00+00:00');
+                 INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp) VALUES
+                     ('m6', 'c5', 'patient', 'text', '还在吗', '2024-03-02T07:00:00.000000Z');",
+            )
+            .unwrap();
+        let dao = ConsultationDao::with_connection(connection);
+
+        let inactive = dao.find_inactive_consultations(at("2024-03-02T08:00:00Z")).unwrap();
+        let found: Vec<&str> = inactive.iter().map(|c| c.consultation_id.as_str()).collect();
+        assert_eq!(found, vec!["c1", "c5", "c3"]);
+        assert_eq!(inactive[1].last_activity_at, at("2024-03-02T07:00:00Z"));
+
+        let before_message = dao.find_inactive_consultations(at("2024-03-02T06:59:00Z")).unwrap();
+        assert!(before_message.iter().all(|c| c.consultation_id != "c5"));
+    }
+
+    #[test]
+    fn test_scan_warns_once_then_completes() {
+        let connection = create_test_connection();
+        let (service, mut events) = ConsultationExpiryService::with_connection(connection.clone());
+        let inactivity = Duration::hours(24);
+
+        // c1 最后活动 3/1 09:30，将于 3/2 09:30 超时
+        let result = service.scan(at("2024-03-02T08:45:00Z"), inactivity).unwrap();
+        assert_eq!(result.warned.len(), 1);
+        assert_eq!(result.warned[0].consultation_id, "c1");
+        assert_eq!(result.warned[0].expires_at, at("2024-03-02T09:30:00Z"));
+        assert!(result.completed.is_empty());
+        assert_eq!(events.try_recv().unwrap().consultation_id, "c1");
+
+        // 再次扫描不重复提醒
+        let result = service.scan(at("2024-03-02T09:00:00Z"), inactivity).unwrap();
+        assert!(result.warned.is_empty());
+        assert!(events.try_recv().is_err());
+
+        let result = service.scan(at("2024-03-02T09:31:00Z"), inactivity).unwrap();
+        assert_eq!(result.completed, vec!["c1"]);
+        let completed = load(&connection, "c1");
+        assert_eq!(completed.status, "completed");
+        assert!(completed.completed_at.is_some());
+
+        let messages = MessageDao::with_connection(connection.clone()).find_all_by_consultation_id("c1").unwrap();
+        let notice = messages.last().unwrap();
+        assert!(notice.content.as_deref().unwrap().contains("auto_completed"));
+
+        let audit_action: String = connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT action FROM audit_logs WHERE resource_type = 'consultation' AND resource_id = 'c1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(audit_action, AUTO_COMPLETE_ACTION);
+
+        // 其他问诊未到期
+        assert_eq!(load(&connection, "c2").status, "active");
+        assert_eq!(load(&connection, "c3").status, "active");
+    }
+
+    #[test]
+    fn test_keep_alive_resets_timer() {
+        let connection = create_test_connection();
+        let (service, mut events) = ConsultationExpiryService::with_connection(connection.clone());
+        let inactivity = Duration::hours(24);
+
+        service.scan(at("2024-03-02T09:00:00Z"), inactivity).unwrap();
+        assert_eq!(events.try_recv().unwrap().consultation_id, "c1");
+
+        let kept = service.keep_alive("c1", inactivity).unwrap();
+        assert_eq!(kept.expires_at, kept.last_activity_at + inactivity);
+        assert!(kept.last_activity_at > at("2024-03-02T09:00:00Z"));
+
+        // 系统消息成为最新活动，原定超时时间过后不会被结束
+        let result = service.scan(at("2024-03-02T10:00:00Z"), inactivity).unwrap();
+        assert!(result.completed.is_empty());
+        assert_eq!(load(&connection, "c1").status, "active");
+
+        let error = AppError::from(service.keep_alive("c4", inactivity).unwrap_err());
+        assert_eq!(error.code.as_deref(), Some("CONSULTATION_NOT_ACTIVE"));
+    }
+}
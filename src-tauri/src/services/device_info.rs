@@ -0,0 +1,91 @@
+// 设备信息服务：启动时采集主机名、系统版本、应用版本和本机 IP，用于审计日志和关于页面
+
+use serde::Serialize;
+use std::net::{IpAddr, UdpSocket};
+use sysinfo::System;
+
+// 仅用于选路确定本机出口地址，UDP connect 不会实际发送数据
+const ROUTE_PROBE_ADDR: &str = "8.8.8.8:80";
+const USER_AGENT_PRODUCT: &str = "TelemedicineDesktop";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    pub hostname: String,
+    #[serde(rename = "osVersion")]
+    pub os_version: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "localIp")]
+    pub local_ip: Option<String>,
+}
+
+impl DeviceInfo {
+    // 写入审计日志 user_agent 字段的设备描述
+    pub fn user_agent(&self) -> String {
+        format!(
+            "{}/{} ({}; {})",
+            USER_AGENT_PRODUCT, self.app_version, self.os_version, self.hostname
+        )
+    }
+}
+
+pub struct DeviceInfoService;
+
+impl DeviceInfoService {
+    /// 采集当前设备信息，取不到的字段记为 unknown
+    pub fn collect(app_version: &str) -> DeviceInfo {
+        let info = DeviceInfo {
+            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+            os_version: System::long_os_version().unwrap_or_else(|| std::env::consts::OS.to_string()),
+            app_version: app_version.to_string(),
+            local_ip: primary_local_ip().map(|ip| ip.to_string()),
+        };
+        tracing::info!(
+            "Device info collected: host={}, os={}, ip={}",
+            info.hostname,
+            info.os_version,
+            info.local_ip.as_deref().unwrap_or("-")
+        );
+        info
+    }
+}
+
+// 默认路由对应的本机地址；离线或只有回环网卡时返回 None
+fn primary_local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect(ROUTE_PROBE_ADDR).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified() && !ip.is_loopback()).then_some(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent_format() {
+        let info = DeviceInfo {
+            hostname: "clinic-pc-01".to_string(),
+            os_version: "Windows 11 (22631)".to_string(),
+            app_version: "0.1.0".to_string(),
+            local_ip: Some("192.168.1.20".to_string()),
+        };
+
+        assert_eq!(
+            info.user_agent(),
+            "TelemedicineDesktop/0.1.0 (Windows 11 (22631); clinic-pc-01)"
+        );
+    }
+
+    #[test]
+    fn test_collect_fills_required_fields() {
+        let info = DeviceInfoService::collect("1.2.3");
+
+        assert_eq!(info.app_version, "1.2.3");
+        assert!(!info.hostname.is_empty());
+        assert!(!info.os_version.is_empty());
+        if let Some(ip) = &info.local_ip {
+            assert!(ip.parse::<IpAddr>().is_ok());
+        }
+    }
+}
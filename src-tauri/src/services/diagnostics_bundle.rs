@@ -0,0 +1,386 @@
+// 技术支持诊断包：按表把本地数据库导出为 JSON 并打成 zip，用内置的支持团队公钥（age X25519）加密，
+// 只有持有对应私钥的支持团队能打开；问诊正文、消息内容等字段不导出原文，患者标识按脱敏规则处理
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, PatientDao};
+use crate::models::{
+    DiagnosticTable, DiagnosticTableSummary, DiagnosticsBundleInfo, DiagnosticsBundleResult, Patient,
+    DIAGNOSTICS_BUNDLE_SCHEMA_VERSION,
+};
+use crate::services::DeviceInfo;
+use crate::utils::MaskingPolicy;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use serde_json::{Map, Value};
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+// 支持团队的 age 公钥，私钥只保存在支持团队
+pub const SUPPORT_RECIPIENT: &str = "age17cq0esvnxylxzkehxw5zqwxwrakma483svhw3rapx7jved86q9mq5xhy7u";
+
+const INFO_FILE_NAME: &str = "info.json";
+// 有内容的脱敏字段替换为该占位符，空值保持为 null，便于区分“有内容”和“没有填写”
+const REDACTED: &str = "[已脱敏]";
+
+// 单张表的导出方式：显式列出列名，新增列不会自动进入诊断包
+struct TableQuery {
+    sql: &'static str,
+    redacted: &'static [&'static str],
+    // 以 JSON 文本存储的列，导出时展开为对象
+    json_columns: &'static [&'static str],
+}
+
+fn table_query(table: DiagnosticTable) -> Option<TableQuery> {
+    let query = match table {
+        DiagnosticTable::Consultations => TableQuery {
+            sql: "SELECT id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription,
+                         cancel_reason, created_at, updated_at, accepted_at, completed_at, version
+                  FROM consultations ORDER BY created_at",
+            redacted: &["title", "description", "diagnosis", "prescription", "cancel_reason"],
+            json_columns: &[],
+        },
+        // 正文、文件路径和语音波形不导出，只保留正文长度用于排查截断等问题
+        DiagnosticTable::Messages => TableQuery {
+            sql: "SELECT id, consultation_id, sender_type, message_type, LENGTH(content) AS content_length, file_size,
+                         mime_type, timestamp, sync_status, read_status, template_id, duration_ms, deleted_at
+                  FROM messages ORDER BY timestamp",
+            redacted: &[],
+            json_columns: &[],
+        },
+        DiagnosticTable::SyncState => TableQuery {
+            sql: "SELECT entity_type, last_sync, updated_at FROM sync_state ORDER BY entity_type",
+            redacted: &[],
+            json_columns: &[],
+        },
+        DiagnosticTable::MaintenanceRuns => TableQuery {
+            sql: "SELECT id, kind, triggered_by, started_at, finished_at, deleted_messages, deleted_audit_logs,
+                         deleted_anomaly_records, deleted_cache_files, deleted_backups, purged_trash, freed_bytes,
+                         vacuumed, status, error_message, summary
+                  FROM maintenance_runs ORDER BY started_at",
+            redacted: &[],
+            json_columns: &["summary"],
+        },
+        DiagnosticTable::AppSettings => TableQuery {
+            sql: "SELECT key, value, schema_version, updated_by, updated_at FROM app_settings ORDER BY key",
+            redacted: &[],
+            json_columns: &["value"],
+        },
+        // 患者字段在数据库中是密文，需要经 DAO 解密后脱敏
+        DiagnosticTable::Patients => return None,
+    };
+    Some(query)
+}
+
+pub struct DiagnosticsBundleService {
+    connection: DbConnection,
+    recipient: String,
+}
+
+impl DiagnosticsBundleService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            connection,
+            recipient: SUPPORT_RECIPIENT.to_string(),
+        }
+    }
+
+    // 测试用：改用测试夹具中的公钥加密
+    pub fn with_recipient(mut self, recipient: &str) -> Self {
+        self.recipient = recipient.to_string();
+        self
+    }
+
+    // 读取各表的脱敏数据，返回 (表, 行) 列表；重复的表只导出一次
+    pub fn collect_tables(&self, tables: &[DiagnosticTable]) -> Result<Vec<(DiagnosticTable, Vec<Value>)>> {
+        let mut collected: Vec<(DiagnosticTable, Vec<Value>)> = Vec::new();
+        for table in tables {
+            if collected.iter().any(|(existing, _)| existing == table) {
+                continue;
+            }
+            let rows = match table_query(*table) {
+                Some(query) => {
+                    let conn = self.connection.lock().unwrap();
+                    query_rows(&conn, &query)?
+                }
+                None => self.masked_patients()?,
+            };
+            collected.push((*table, rows));
+        }
+        Ok(collected)
+    }
+
+    pub fn export_bundle(
+        &self,
+        path: &Path,
+        tables: &[DiagnosticTable],
+        device: &DeviceInfo,
+    ) -> Result<DiagnosticsBundleResult> {
+        if tables.is_empty() {
+            return Err(anyhow!("至少选择一张要导出的表"));
+        }
+        let recipient: age::x25519::Recipient = self
+            .recipient
+            .parse()
+            .map_err(|e| anyhow!("支持团队公钥无效: {}", e))?;
+
+        let collected = self.collect_tables(tables)?;
+        let schema_version: i64 = {
+            let conn = self.connection.lock().unwrap();
+            conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", [], |row| row.get(0))?
+        };
+        let info = DiagnosticsBundleInfo {
+            bundle_version: DIAGNOSTICS_BUNDLE_SCHEMA_VERSION,
+            app_version: device.app_version.clone(),
+            os_version: device.os_version.clone(),
+            schema_version,
+            exported_at: Utc::now(),
+            tables: collected.iter().map(|(table, _)| *table).collect(),
+        };
+
+        // 明文 zip 只在内存中出现，落盘的只有加密后的内容
+        let archive = write_zip(&info, &collected)?;
+        let encrypted = encrypt_for(recipient, &archive)?;
+        std::fs::write(path, &encrypted)?;
+
+        Ok(DiagnosticsBundleResult {
+            path: path.to_string_lossy().to_string(),
+            tables: collected
+                .iter()
+                .map(|(table, rows)| DiagnosticTableSummary {
+                    table: *table,
+                    row_count: rows.len(),
+                })
+                .collect(),
+            size_bytes: encrypted.len() as u64,
+        })
+    }
+
+    fn masked_patients(&self) -> Result<Vec<Value>> {
+        let patients: Vec<Patient> = PatientDao::with_connection(self.connection.clone())
+            .find_all()
+            .map_err(|e| anyhow!("读取患者数据失败: {}", e))?;
+        patients
+            .into_iter()
+            .map(|mut patient| {
+                MaskingPolicy::ALL.apply(&mut patient);
+                Ok(serde_json::to_value(patient)?)
+            })
+            .collect()
+    }
+}
+
+impl Default for DiagnosticsBundleService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn query_rows(conn: &Connection, query: &TableQuery) -> Result<Vec<Value>> {
+    let mut stmt = conn.prepare(query.sql)?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+    let mut rows = stmt.query([])?;
+
+    let mut exported = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+        for (index, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(index)? {
+                ValueRef::Null => Value::Null,
+                _ if query.redacted.contains(&column.as_str()) => Value::String(REDACTED.to_string()),
+                ValueRef::Integer(n) => Value::from(n),
+                ValueRef::Real(f) => Value::from(f),
+                ValueRef::Text(bytes) => {
+                    let text = String::from_utf8_lossy(bytes).to_string();
+                    if query.json_columns.contains(&column.as_str()) {
+                        serde_json::from_str(&text).unwrap_or(Value::String(text))
+                    } else {
+                        Value::String(text)
+                    }
+                }
+                ValueRef::Blob(bytes) => Value::String(format!("<{} bytes>", bytes.len())),
+            };
+            object.insert(column.clone(), value);
+        }
+        exported.push(Value::Object(object));
+    }
+    Ok(exported)
+}
+
+fn write_zip(info: &DiagnosticsBundleInfo, tables: &[(DiagnosticTable, Vec<Value>)]) -> Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    writer.start_file(INFO_FILE_NAME, options)?;
+    writer.write_all(&serde_json::to_vec_pretty(info)?)?;
+
+    for (table, rows) in tables {
+        writer.start_file(table.file_name(), options)?;
+        writer.write_all(&serde_json::to_vec_pretty(rows)?)?;
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+fn encrypt_for(recipient: age::x25519::Recipient, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_recipients(vec![Box::new(recipient)])
+        .ok_or_else(|| anyhow!("缺少加密公钥"))?;
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    const IDENTITY_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/support_test_identity.txt");
+    // 与测试夹具中私钥对应的公钥
+    const TEST_RECIPIENT: &str = "age1wpnugp6qxzw3ldt8z6c3mpvzphfqhkvhv30vf4e94wywv5k0dfnssswjsg";
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status, title, description, diagnosis)
+             VALUES ('c1', 'p1', 'd1', 'active', '头痛三天', '夜间加重，伴恶心', '偏头痛');
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content, file_path, waveform)
+             VALUES ('m1', 'c1', 'patient', 'text', '我对青霉素过敏', '/data/files/m1.txt', '[1,2,3]');
+             INSERT INTO sync_state (entity_type, last_sync) VALUES ('messages', '2026-10-01T08:00:00Z');",
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn test_device() -> DeviceInfo {
+        DeviceInfo {
+            hostname: "ws-01".to_string(),
+            os_version: "Windows 11".to_string(),
+            app_version: "1.2.3".to_string(),
+            local_ip: None,
+        }
+    }
+
+    fn decrypt_with_fixture(path: &Path) -> Vec<u8> {
+        let fixture = std::fs::read_to_string(IDENTITY_FIXTURE).unwrap();
+        let identity: age::x25519::Identity = fixture
+            .lines()
+            .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let encrypted = std::fs::read(path).unwrap();
+        let decryptor = match age::Decryptor::new(&encrypted[..]).unwrap() {
+            age::Decryptor::Recipients(decryptor) => decryptor,
+            _ => panic!("诊断包应使用公钥加密"),
+        };
+        let mut reader = decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap();
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).unwrap();
+        plaintext
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Value {
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn test_content_fields_redacted() {
+        let service = DiagnosticsBundleService::with_connection(create_test_connection());
+        let tables = service.collect_tables(&DiagnosticTable::DEFAULT).unwrap();
+
+        let consultations = &tables.iter().find(|(t, _)| *t == DiagnosticTable::Consultations).unwrap().1;
+        assert_eq!(consultations[0]["status"], "active");
+        assert_eq!(consultations[0]["title"], REDACTED);
+        assert_eq!(consultations[0]["diagnosis"], REDACTED);
+        assert!(consultations[0]["prescription"].is_null());
+
+        let messages = &tables.iter().find(|(t, _)| *t == DiagnosticTable::Messages).unwrap().1;
+        assert_eq!(messages[0]["message_type"], "text");
+        assert_eq!(messages[0]["content_length"], 7);
+        assert!(messages[0].get("content").is_none());
+        assert!(messages[0].get("file_path").is_none());
+        assert!(messages[0].get("waveform").is_none());
+
+        let exported = serde_json::to_string(&tables.iter().map(|(_, rows)| rows).collect::<Vec<_>>()).unwrap();
+        for plain in ["头痛", "恶心", "偏头痛", "青霉素", "/data/files"] {
+            assert!(!exported.contains(plain), "诊断包不应包含原文: {}", plain);
+        }
+    }
+
+    #[test]
+    fn test_patient_identifiers_masked() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        let mut patient = dao.find_by_id("p1").unwrap().unwrap();
+        patient.name = "王小明".to_string();
+        patient.phone = Some("13812345678".to_string());
+        patient.id_card = Some("110101199001011234".to_string());
+        dao.update(&patient).unwrap();
+
+        let service = DiagnosticsBundleService::with_connection(connection);
+        let tables = service.collect_tables(&[DiagnosticTable::Patients]).unwrap();
+        let row = &tables[0].1[0];
+        assert_eq!(row["name"], "王*明");
+        assert_eq!(row["phone"], "138****5678");
+        assert_eq!(row["idCard"], "110101********1234");
+    }
+
+    #[test]
+    fn test_bundle_decrypts_with_support_identity() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("diagnostics.age");
+        let service = DiagnosticsBundleService::with_connection(create_test_connection()).with_recipient(TEST_RECIPIENT);
+
+        let tables = [DiagnosticTable::SyncState, DiagnosticTable::AppSettings, DiagnosticTable::SyncState];
+        let result = service.export_bundle(&path, &tables, &test_device()).unwrap();
+        assert_eq!(result.tables.len(), 2);
+        assert_eq!(result.tables[0].row_count, 1);
+        assert_eq!(result.size_bytes, std::fs::metadata(&path).unwrap().len());
+
+        // 磁盘上不是明文 zip
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.starts_with(b"PK"));
+        assert!(zip::ZipArchive::new(Cursor::new(raw)).is_err());
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(decrypt_with_fixture(&path))).unwrap();
+        let info = read_entry(&mut archive, INFO_FILE_NAME);
+        assert_eq!(info["appVersion"], "1.2.3");
+        assert_eq!(info["osVersion"], "Windows 11");
+        assert!(info["schemaVersion"].as_i64().unwrap() > 0);
+        assert_eq!(info["tables"], serde_json::json!(["sync_state", "app_settings"]));
+
+        let sync_state = read_entry(&mut archive, "sync_state.json");
+        assert_eq!(sync_state[0]["entity_type"], "messages");
+        assert!(archive.by_name("consultations.json").is_err());
+    }
+
+    #[test]
+    fn test_export_requires_tables() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("diagnostics.age");
+        let service = DiagnosticsBundleService::with_connection(create_test_connection()).with_recipient(TEST_RECIPIENT);
+
+        assert!(service.export_bundle(&path, &[], &test_device()).is_err());
+        assert!(!path.exists());
+    }
+}
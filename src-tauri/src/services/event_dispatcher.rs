@@ -0,0 +1,597 @@
+// WebSocket 事件按问诊有序处理：同一问诊的事件进入各自的有界 FIFO 队列，由单个任务依次落库、推送；
+// 服务器信封带序号时按序号重排，发现缺口时请求补同步
+
+use crate::services::WebSocketEvent;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::Instant;
+
+// 每个问诊队列的容量，队列满时丢弃新事件并请求补同步，不阻塞其他问诊
+pub const CONSULTATION_QUEUE_CAPACITY: usize = 256;
+// 出现序号缺口后等待缺失事件的时间，超时后放弃等待，按序放行已收到的事件
+pub const SEQUENCE_GAP_WAIT: Duration = Duration::from_secs(3);
+// 等待缺口期间最多暂存的事件数
+const MAX_HELD_EVENTS: usize = 64;
+// 队列处理完后保持空闲的时间，超时后移除队列并结束处理任务，有新事件时再重建
+pub const CONSULTATION_QUEUE_IDLE: Duration = Duration::from_secs(120);
+// 已读确认、在线状态等连接级事件共用一个队列
+pub const CONNECTION_QUEUE_KEY: &str = "_connection";
+
+/// 有序事件的处理方，生产环境先落库再路由到窗口或系统通知
+#[async_trait]
+pub trait OrderedEventHandler: Send + Sync + 'static {
+    // 同一问诊的事件按顺序逐个调用，上一个完成后才处理下一个
+    async fn handle(&self, event: WebSocketEvent);
+    // 问诊的推送出现缺口或被丢弃，需要从服务器补齐
+    fn request_gap_fill(&self, consultation_id: &str);
+}
+
+#[derive(Debug, Default)]
+pub struct SequencedBatch {
+    // 可以按顺序处理的事件
+    pub ready: Vec<WebSocketEvent>,
+    // 新出现的缺口：(期望的序号, 实际收到的序号)
+    pub gap: Option<(u64, u64)>,
+}
+
+/// 单个问诊的序号重排：不带序号的事件直接放行，序号超前的事件暂存到缺失的事件到达，重复和过期的事件丢弃
+#[derive(Debug, Default)]
+pub struct ConsultationSequencer {
+    last_seq: Option<u64>,
+    held: BTreeMap<u64, WebSocketEvent>,
+}
+
+impl ConsultationSequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn accept(&mut self, event: WebSocketEvent) -> SequencedBatch {
+        let mut batch = SequencedBatch::default();
+        let Some(seq) = event.sequence() else {
+            batch.ready.push(event);
+            return batch;
+        };
+
+        // 本次运行收到的第一个序号作为起点
+        let Some(last) = self.last_seq else {
+            self.last_seq = Some(seq);
+            batch.ready.push(event);
+            return batch;
+        };
+
+        if seq <= last || self.held.contains_key(&seq) {
+            tracing::debug!("Dropping duplicate or stale event seq {} (last {})", seq, last);
+            return batch;
+        }
+
+        if seq == last + 1 {
+            self.last_seq = Some(seq);
+            batch.ready.push(event);
+            batch.ready.extend(self.release());
+            return batch;
+        }
+
+        // 同一段等待期间只报告第一次缺口，一次补同步即可补齐
+        if self.held.is_empty() {
+            batch.gap = Some((last + 1, seq));
+        }
+        self.held.insert(seq, event);
+        if self.held.len() > MAX_HELD_EVENTS {
+            batch.ready = self.flush();
+        }
+        batch
+    }
+
+    // 放弃等待缺失的事件（由补同步写入数据库），按序号放行暂存的事件；之后迟到的缺失事件按过期丢弃
+    pub fn flush(&mut self) -> Vec<WebSocketEvent> {
+        let held = std::mem::take(&mut self.held);
+        if let Some(&seq) = held.keys().next_back() {
+            self.last_seq = Some(seq);
+        }
+        held.into_values().collect()
+    }
+
+    pub fn held_len(&self) -> usize {
+        self.held.len()
+    }
+
+    fn release(&mut self) -> Vec<WebSocketEvent> {
+        let mut ready = Vec::new();
+        while let Some(entry) = self.held.first_entry() {
+            if Some(*entry.key()) != self.last_seq.map(|seq| seq + 1) {
+                break;
+            }
+            self.last_seq = Some(*entry.key());
+            ready.push(entry.remove());
+        }
+        ready
+    }
+}
+
+#[derive(Debug, Default)]
+struct QueueDepth {
+    // 已入队未取出的事件
+    queued: AtomicUsize,
+    // 等待缺口时暂存的事件
+    held: AtomicUsize,
+}
+
+impl QueueDepth {
+    fn total(&self) -> usize {
+        self.queued.load(Ordering::SeqCst) + self.held.load(Ordering::SeqCst)
+    }
+}
+
+struct ConsultationQueue {
+    sender: mpsc::Sender<WebSocketEvent>,
+    depth: Arc<QueueDepth>,
+}
+
+type QueueMap = Mutex<HashMap<String, ConsultationQueue>>;
+
+pub struct ConsultationEventDispatcher {
+    handler: Arc<dyn OrderedEventHandler>,
+    queues: Arc<QueueMap>,
+    capacity: usize,
+    gap_wait: Duration,
+    idle_timeout: Duration,
+    dropped: AtomicU64,
+}
+
+impl ConsultationEventDispatcher {
+    pub fn new(handler: Arc<dyn OrderedEventHandler>) -> Self {
+        Self::with_limits(handler, CONSULTATION_QUEUE_CAPACITY, SEQUENCE_GAP_WAIT)
+    }
+
+    pub fn with_limits(handler: Arc<dyn OrderedEventHandler>, capacity: usize, gap_wait: Duration) -> Self {
+        Self {
+            handler,
+            queues: Arc::new(Mutex::new(HashMap::new())),
+            capacity: capacity.max(1),
+            gap_wait,
+            idle_timeout: CONSULTATION_QUEUE_IDLE,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    // 按问诊分发到对应队列，不等待处理完成；需在 tokio 运行时内调用
+    pub fn dispatch(&self, event: WebSocketEvent) {
+        let key = event.consultation_id().unwrap_or(CONNECTION_QUEUE_KEY).to_string();
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.entry(key.clone()).or_insert_with(|| self.spawn_queue(&key));
+
+        queue.depth.queued.fetch_add(1, Ordering::SeqCst);
+        match queue.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                queue.depth.queued.fetch_sub(1, Ordering::SeqCst);
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+                tracing::warn!("Event queue for {} is full, dropping event", key);
+                if let Some(consultation_id) = event.consultation_id() {
+                    self.handler.request_gap_fill(consultation_id);
+                }
+            }
+            // 处理任务意外退出，重建队列后重新分发
+            Err(TrySendError::Closed(event)) => {
+                tracing::warn!("Event queue for {} was closed, recreating", key);
+                queues.remove(&key);
+                drop(queues);
+                self.dispatch(event);
+            }
+        }
+    }
+
+    // 问诊结束后移除其队列，处理任务处理完已入队的事件后退出
+    pub fn close_consultation(&self, consultation_id: &str) {
+        if self.queues.lock().unwrap().remove(consultation_id).is_some() {
+            tracing::debug!("Closed event queue for consultation {}", consultation_id);
+        }
+    }
+
+    // 当前存在的队列数（含空闲队列）
+    pub fn queue_count(&self) -> usize {
+        self.queues.lock().unwrap().len()
+    }
+
+    // 各队列待处理的事件数，只列出非空队列，按问诊排序
+    pub fn queue_depths(&self) -> Vec<(String, usize)> {
+        let mut depths: Vec<_> = self
+            .queues
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, queue)| (key.clone(), queue.depth.total()))
+            .filter(|(_, depth)| *depth > 0)
+            .collect();
+        depths.sort();
+        depths
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // 启动以来因队列已满丢弃的事件数
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::SeqCst)
+    }
+
+    fn spawn_queue(&self, key: &str) -> ConsultationQueue {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        let depth = Arc::new(QueueDepth::default());
+        tokio::spawn(run_queue(
+            key.to_string(),
+            receiver,
+            depth.clone(),
+            self.handler.clone(),
+            QueueTimeouts {
+                gap_wait: self.gap_wait,
+                idle: self.idle_timeout,
+            },
+            Arc::downgrade(&self.queues),
+        ));
+        ConsultationQueue { sender, depth }
+    }
+}
+
+struct QueueTimeouts {
+    gap_wait: Duration,
+    idle: Duration,
+}
+
+// 单个问诊的处理循环：按序号重排后逐个交给处理方；缺口等待超时后按序放行暂存的事件；
+// 空闲超时后从分发器中移除自己并退出，队列被关闭时处理完剩余事件后退出
+async fn run_queue(
+    key: String,
+    mut receiver: mpsc::Receiver<WebSocketEvent>,
+    depth: Arc<QueueDepth>,
+    handler: Arc<dyn OrderedEventHandler>,
+    timeouts: QueueTimeouts,
+    queues: Weak<QueueMap>,
+) {
+    let mut sequencer = ConsultationSequencer::new();
+    let mut gap_deadline: Option<Instant> = None;
+
+    loop {
+        let received = match gap_deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    gap_deadline = None;
+                    tracing::warn!("Missing events for {} did not arrive, releasing held events", key);
+                    let ready = sequencer.flush();
+                    depth.held.store(0, Ordering::SeqCst);
+                    handle_in_order(&handler, ready).await;
+                    continue;
+                }
+            },
+            None => match tokio::time::timeout(timeouts.idle, receiver.recv()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    if remove_idle_queue(&queues, &key, &depth) {
+                        tracing::debug!("Event queue for {} idle, removing", key);
+                        break;
+                    }
+                    continue;
+                }
+            },
+        };
+        let Some(event) = received else {
+            // 队列已关闭：不再等待缺失的事件，按序放行暂存的事件
+            handle_in_order(&handler, sequencer.flush()).await;
+            depth.held.store(0, Ordering::SeqCst);
+            break;
+        };
+        depth.queued.fetch_sub(1, Ordering::SeqCst);
+
+        let batch = sequencer.accept(event);
+        depth.held.store(sequencer.held_len(), Ordering::SeqCst);
+        if let Some((expected, received)) = batch.gap {
+            tracing::warn!("Sequence gap for {}: expected {}, received {}", key, expected, received);
+            handler.request_gap_fill(&key);
+            gap_deadline = Some(Instant::now() + timeouts.gap_wait);
+        }
+        if sequencer.held_len() == 0 {
+            gap_deadline = None;
+        }
+        handle_in_order(&handler, batch.ready).await;
+    }
+}
+
+// 分发在持有锁时计数入队事件，这里在同一把锁下确认没有新事件后再移除；
+// 队列已被关闭或替换时不动新的队列。返回 false 表示期间有新事件，继续处理
+fn remove_idle_queue(queues: &Weak<QueueMap>, key: &str, depth: &Arc<QueueDepth>) -> bool {
+    let Some(queues) = queues.upgrade() else {
+        return true;
+    };
+    let mut queues = queues.lock().unwrap();
+    if depth.queued.load(Ordering::SeqCst) > 0 {
+        return false;
+    }
+    if queues.get(key).is_some_and(|queue| Arc::ptr_eq(&queue.depth, depth)) {
+        queues.remove(key);
+    }
+    true
+}
+
+// 每个事件在独立任务中处理并等待完成，处理方 panic 不会终止队列
+async fn handle_in_order(handler: &Arc<dyn OrderedEventHandler>, events: Vec<WebSocketEvent>) {
+    for event in events {
+        let handler = handler.clone();
+        if let Err(e) = tokio::spawn(async move { handler.handle(event).await }).await {
+            tracing::error!("Ordered event handler failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::dao::MessageDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Message, MessageType, ReadStatus, SenderType, SyncStatus};
+    use chrono::Utc;
+    use rusqlite::Connection;
+    use tokio::sync::Notify;
+
+    // 按处理顺序写入内存数据库，并记录补同步请求
+    struct RecordingHandler {
+        message_dao: MessageDao,
+        connection: DbConnection,
+        gap_fills: Mutex<Vec<String>>,
+        // 设置后处理每个事件前等待放行，用于让事件堆积在队列中
+        gate: Option<Arc<Notify>>,
+    }
+
+    impl RecordingHandler {
+        fn new(gate: Option<Arc<Notify>>) -> Arc<Self> {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+            MigrationManager::new().run_migrations(&conn).unwrap();
+            conn.execute_batch(
+                "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+                 INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES
+                     ('c1', 'p1', 'd1', 'active'), ('c2', 'p1', 'd1', 'active');",
+            )
+            .unwrap();
+            let connection: DbConnection = Arc::new(Mutex::new(conn));
+            Arc::new(Self {
+                message_dao: MessageDao::with_connection(connection.clone()),
+                connection,
+                gap_fills: Mutex::new(Vec::new()),
+                gate,
+            })
+        }
+
+        // 消息按写入顺序排列
+        fn stored(&self, consultation_id: &str) -> Vec<String> {
+            let conn = self.connection.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT id FROM messages WHERE consultation_id = ?1 ORDER BY rowid")
+                .unwrap();
+            let ids = stmt
+                .query_map([consultation_id], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<String>>>()
+                .unwrap();
+            ids
+        }
+
+        fn gap_fills(&self) -> Vec<String> {
+            self.gap_fills.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl OrderedEventHandler for RecordingHandler {
+        async fn handle(&self, event: WebSocketEvent) {
+            if let Some(gate) = &self.gate {
+                gate.notified().await;
+            }
+            if let WebSocketEvent::Message { message, .. } = event {
+                self.message_dao.save_incoming(&message).unwrap();
+            }
+        }
+
+        fn request_gap_fill(&self, consultation_id: &str) {
+            self.gap_fills.lock().unwrap().push(consultation_id.to_string());
+        }
+    }
+
+    fn message_event(consultation_id: &str, id: &str, seq: Option<u64>) -> WebSocketEvent {
+        WebSocketEvent::Message {
+            consultation_id: consultation_id.to_string(),
+            message: Message {
+                id: id.to_string(),
+                consultation_id: consultation_id.to_string(),
+                sender_type: SenderType::Patient,
+                message_type: MessageType::Text,
+                content: Some(format!("消息 {}", id)),
+                file_path: None,
+                file_size: None,
+                mime_type: None,
+                timestamp: Utc::now(),
+                sync_status: SyncStatus::Synced,
+                read_status: ReadStatus::Unread,
+                template_id: None,
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
+            },
+            idempotency_key: None,
+            seq,
+        }
+    }
+
+    fn ids(events: &[WebSocketEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                WebSocketEvent::Message { message, .. } => message.id.clone(),
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    // 等待所有队列处理完毕
+    async fn drain(dispatcher: &ConsultationEventDispatcher) {
+        for _ in 0..200 {
+            if dispatcher.queue_depths().is_empty() {
+                // 最后一个事件取出后仍可能在处理中
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("queues did not drain: {:?}", dispatcher.queue_depths());
+    }
+
+    #[test]
+    fn test_sequencer_reorders_and_reports_gap() {
+        let mut sequencer = ConsultationSequencer::new();
+        assert_eq!(ids(&sequencer.accept(message_event("c1", "m1", Some(1))).ready), vec!["m1"]);
+
+        // 3 先到：暂存并报告缺口
+        let batch = sequencer.accept(message_event("c1", "m3", Some(3)));
+        assert!(batch.ready.is_empty());
+        assert_eq!(batch.gap, Some((2, 3)));
+        let batch = sequencer.accept(message_event("c1", "m4", Some(4)));
+        assert!(batch.ready.is_empty());
+        assert_eq!(batch.gap, None);
+
+        // 2 到达后按序放行 2、3、4
+        assert_eq!(ids(&sequencer.accept(message_event("c1", "m2", Some(2))).ready), vec!["m2", "m3", "m4"]);
+        assert_eq!(sequencer.held_len(), 0);
+
+        // 重复推送丢弃，不带序号的事件直接放行
+        assert!(sequencer.accept(message_event("c1", "m3", Some(3))).ready.is_empty());
+        assert_eq!(ids(&sequencer.accept(message_event("c1", "local", None)).ready), vec!["local"]);
+
+        // 缺失的事件一直不来时，放弃等待后迟到的事件按过期丢弃
+        sequencer.accept(message_event("c1", "m7", Some(7)));
+        assert_eq!(ids(&sequencer.flush()), vec!["m7"]);
+        assert!(sequencer.accept(message_event("c1", "m5", Some(5))).ready.is_empty());
+        assert_eq!(ids(&sequencer.accept(message_event("c1", "m8", Some(8))).ready), vec!["m8"]);
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_events_stored_in_sequence() {
+        let handler = RecordingHandler::new(None);
+        let dispatcher = ConsultationEventDispatcher::new(handler.clone());
+
+        for (id, seq) in [("a1", 1), ("a3", 3), ("a2", 2), ("a5", 5), ("a4", 4)] {
+            dispatcher.dispatch(message_event("c1", id, Some(seq)));
+        }
+        // 另一个问诊的事件互不影响
+        for (id, seq) in [("b1", 10), ("b2", 11)] {
+            dispatcher.dispatch(message_event("c2", id, Some(seq)));
+        }
+        drain(&dispatcher).await;
+
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2", "a3", "a4", "a5"]);
+        assert_eq!(handler.stored("c2"), vec!["b1", "b2"]);
+        // 缺口在等待期间补上了，仍然请求了补同步（请求时无法预知缺失的事件是否会到达）
+        assert_eq!(handler.gap_fills(), vec!["c1", "c1"]);
+    }
+
+    #[tokio::test]
+    async fn test_gap_triggers_fill_and_releases_after_wait() {
+        let handler = RecordingHandler::new(None);
+        let dispatcher =
+            ConsultationEventDispatcher::with_limits(handler.clone(), CONSULTATION_QUEUE_CAPACITY, Duration::from_millis(50));
+
+        for (id, seq) in [("a1", 1), ("a2", 2), ("a5", 5), ("a6", 6)] {
+            dispatcher.dispatch(message_event("c1", id, Some(seq)));
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(handler.gap_fills(), vec!["c1"]);
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2"]);
+        assert_eq!(dispatcher.queue_depths(), vec![("c1".to_string(), 2)]);
+
+        // 等待超时后按序写入暂存的事件，迟到的缺失事件不再写入
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2", "a5", "a6"]);
+        dispatcher.dispatch(message_event("c1", "a3", Some(3)));
+        dispatcher.dispatch(message_event("c1", "a7", Some(7)));
+        drain(&dispatcher).await;
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2", "a5", "a6", "a7"]);
+        assert_eq!(handler.gap_fills(), vec!["c1"]);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_and_requests_gap_fill() {
+        let gate = Arc::new(Notify::new());
+        let handler = RecordingHandler::new(Some(gate.clone()));
+        let dispatcher = ConsultationEventDispatcher::with_limits(handler.clone(), 2, SEQUENCE_GAP_WAIT);
+
+        // 第一个事件被取出后卡在处理中，队列里再放两个，第四个放不下
+        dispatcher.dispatch(message_event("c1", "a1", None));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        for id in ["a2", "a3", "a4"] {
+            dispatcher.dispatch(message_event("c1", id, None));
+        }
+        assert_eq!(dispatcher.queue_depths(), vec![("c1".to_string(), 2)]);
+        assert_eq!(dispatcher.dropped_events(), 1);
+        assert_eq!(handler.gap_fills(), vec!["c1"]);
+
+        for _ in 0..3 {
+            gate.notify_one();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        drain(&dispatcher).await;
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2", "a3"]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_and_closed_queues_are_removed() {
+        let handler = RecordingHandler::new(None);
+        let dispatcher = ConsultationEventDispatcher::new(handler.clone()).with_idle_timeout(Duration::from_millis(200));
+
+        dispatcher.dispatch(message_event("c1", "a1", Some(1)));
+        dispatcher.dispatch(message_event("c2", "b1", Some(1)));
+        drain(&dispatcher).await;
+        assert_eq!(dispatcher.queue_count(), 2);
+
+        // 问诊结束后队列立即移除
+        dispatcher.close_consultation("c2");
+        assert_eq!(dispatcher.queue_count(), 1);
+
+        // 空闲超时后队列移除，之后的事件重建队列并照常处理
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(dispatcher.queue_count(), 0);
+        dispatcher.dispatch(message_event("c1", "a2", Some(2)));
+        drain(&dispatcher).await;
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2"]);
+        assert_eq!(handler.stored("c2"), vec!["b1"]);
+    }
+
+    #[tokio::test]
+    async fn test_closed_queue_finishes_pending_events() {
+        let gate = Arc::new(Notify::new());
+        let handler = RecordingHandler::new(Some(gate.clone()));
+        let dispatcher = ConsultationEventDispatcher::new(handler.clone());
+
+        for id in ["a1", "a2", "a3"] {
+            dispatcher.dispatch(message_event("c1", id, None));
+        }
+        dispatcher.close_consultation("c1");
+        assert_eq!(dispatcher.queue_count(), 0);
+
+        for _ in 0..3 {
+            gate.notify_one();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(handler.stored("c1"), vec!["a1", "a2", "a3"]);
+    }
+}
@@ -0,0 +1,207 @@
+// WebSocket 事件回放缓冲：问诊窗口打开时补齐刚刚错过的实时事件
+
+use crate::services::WebSocketEvent;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+// 每个问诊保留的事件条数
+pub const REPLAY_EVENTS_PER_CONSULTATION: usize = 100;
+// 全部问诊合计的事件上限，超出时淘汰最早的事件
+pub const REPLAY_MAX_TOTAL_EVENTS: usize = 2000;
+// 事件保留时长（分钟）
+pub const REPLAY_RETENTION_MINUTES: i64 = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BufferedEvent {
+    #[serde(rename = "receivedAt")]
+    pub received_at: DateTime<Utc>,
+    pub event: WebSocketEvent,
+}
+
+#[derive(Debug, Default)]
+pub struct EventReplayBuffer {
+    events: HashMap<String, VecDeque<BufferedEvent>>,
+    total: usize,
+}
+
+impl EventReplayBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 只缓存和具体问诊相关的事件，输入状态是瞬时的不需要回放
+    pub fn push(&mut self, event: WebSocketEvent, received_at: DateTime<Utc>) {
+        if matches!(event, WebSocketEvent::Typing { .. }) {
+            return;
+        }
+        let Some(consultation_id) = event.consultation_id().map(str::to_string) else {
+            return;
+        };
+
+        self.prune(received_at);
+
+        let queue = self.events.entry(consultation_id).or_default();
+        queue.push_back(BufferedEvent { received_at, event });
+        self.total += 1;
+        if queue.len() > REPLAY_EVENTS_PER_CONSULTATION {
+            queue.pop_front();
+            self.total -= 1;
+        }
+
+        while self.total > REPLAY_MAX_TOTAL_EVENTS {
+            self.evict_oldest();
+        }
+    }
+
+    // 返回指定时间之后收到的事件，按接收顺序
+    pub fn since(&mut self, consultation_id: &str, since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Vec<BufferedEvent> {
+        self.prune(now);
+
+        self.events
+            .get(consultation_id)
+            .map(|queue| {
+                queue
+                    .iter()
+                    .filter(|buffered| since.is_none_or(|since| buffered.received_at > since))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    // 问诊窗口关闭时丢弃该问诊的全部事件，返回丢弃条数
+    pub fn remove_consultation(&mut self, consultation_id: &str) -> usize {
+        let removed = self.events.remove(consultation_id).map_or(0, |queue| queue.len());
+        self.total -= removed;
+        removed
+    }
+
+    pub fn clear(&mut self) -> usize {
+        let removed = self.total;
+        self.events.clear();
+        self.total = 0;
+        removed
+    }
+
+    // 丢弃超过保留时长的事件
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::minutes(REPLAY_RETENTION_MINUTES);
+        let mut removed = 0;
+
+        self.events.retain(|_, queue| {
+            while queue.front().is_some_and(|buffered| buffered.received_at < cutoff) {
+                queue.pop_front();
+                removed += 1;
+            }
+            !queue.is_empty()
+        });
+
+        self.total -= removed;
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .events
+            .iter()
+            .filter_map(|(id, queue)| queue.front().map(|buffered| (id.clone(), buffered.received_at)))
+            .min_by_key(|(_, received_at)| *received_at)
+            .map(|(id, _)| id);
+
+        let Some(id) = oldest else {
+            return;
+        };
+        if let Some(queue) = self.events.get_mut(&id) {
+            queue.pop_front();
+            self.total -= 1;
+            if queue.is_empty() {
+                self.events.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(consultation_id: &str, status: &str) -> WebSocketEvent {
+        WebSocketEvent::ConsultationUpdate {
+            consultation_id: consultation_id.to_string(),
+            status: status.to_string(),
+            priority: None,
+        }
+    }
+
+    fn status_of(buffered: &BufferedEvent) -> &str {
+        match &buffered.event {
+            WebSocketEvent::ConsultationUpdate { status, .. } => status,
+            _ => "",
+        }
+    }
+
+    #[test]
+    fn test_per_consultation_limit_and_retention() {
+        let mut buffer = EventReplayBuffer::new();
+        let start = Utc::now();
+
+        for i in 0..REPLAY_EVENTS_PER_CONSULTATION + 5 {
+            buffer.push(update("c1", &i.to_string()), start + Duration::milliseconds(i as i64));
+        }
+        buffer.push(
+            WebSocketEvent::Typing { consultation_id: "c1".to_string(), user_id: "p1".to_string(), is_typing: true },
+            start,
+        );
+
+        let events = buffer.since("c1", None, start);
+        assert_eq!(events.len(), REPLAY_EVENTS_PER_CONSULTATION);
+        assert_eq!(status_of(&events[0]), "5");
+
+        // 超过保留时长后全部清除
+        let later = start + Duration::minutes(REPLAY_RETENTION_MINUTES + 1);
+        assert!(buffer.since("c1", None, later).is_empty());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_global_cap_evicts_oldest() {
+        let mut buffer = EventReplayBuffer::new();
+        let start = Utc::now();
+
+        for i in 0..REPLAY_MAX_TOTAL_EVENTS + 1 {
+            let consultation_id = format!("c{}", i % 50);
+            buffer.push(update(&consultation_id, &i.to_string()), start + Duration::milliseconds(i as i64));
+        }
+
+        assert_eq!(buffer.len(), REPLAY_MAX_TOTAL_EVENTS);
+        // 最早的一条（c0 的第 0 条）被淘汰
+        assert_eq!(status_of(&buffer.since("c0", None, start)[0]), "50");
+    }
+
+    #[test]
+    fn test_remove_consultation_and_clear() {
+        let mut buffer = EventReplayBuffer::new();
+        let start = Utc::now();
+        for i in 0..3 {
+            buffer.push(update("c1", &i.to_string()), start);
+            buffer.push(update("c2", &i.to_string()), start);
+        }
+
+        assert_eq!(buffer.remove_consultation("c1"), 3);
+        assert_eq!(buffer.remove_consultation("c1"), 0);
+        assert!(buffer.since("c1", None, start).is_empty());
+        assert_eq!(buffer.len(), 3);
+
+        assert_eq!(buffer.clear(), 3);
+        assert!(buffer.is_empty());
+        assert!(buffer.since("c2", None, start).is_empty());
+    }
+}
@@ -1,37 +1,308 @@
 // 文件服务
 
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::database::connection::DbConnection;
+use crate::database::dao::file_cache_dao::{quota_bytes, FileCacheDao};
+use crate::services::attachment_quota::AttachmentQuotaService;
+use crate::services::audit_export::to_hex;
+use crate::models::{AppConfig, FilePreview, UploadCompressionConfig, ValidationViolation as ViolationPayload};
+use crate::utils::{MessageKey, ValidationResult, ValidationService, CODE_EXTENSION_MISMATCH};
 
-pub struct FileService;
+// 语音气泡波形的柱数
+pub const WAVEFORM_BARS: usize = 48;
+
+// 缩略图最长边（像素）
+pub const THUMBNAIL_MAX_SIZE: u32 = 320;
+const THUMBNAIL_QUALITY: u8 = 80;
+const THUMBNAIL_DIR: &str = "thumbnails";
+
+// 语音文件时长和波形，峰值按最大峰值归一化到 0–1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioMetadata {
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    pub waveform: Vec<f32>,
+}
+
+// 识别真实文件类型时读取的文件头长度
+const FILE_SNIFF_BYTES: usize = 8192;
+
+pub const PDF_MIME_TYPE: &str = "application/pdf";
+pub const DOCX_MIME_TYPE: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+// 悬停预览展示的文字长度（字符）
+pub const PREVIEW_TEXT_CHARS: usize = 500;
+// 单个文件的预览提取时长上限，超时视为没有预览
+const PREVIEW_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+// docx 正文 XML 最多读取的字节数，避免异常大的文档占用内存
+const DOCX_XML_LIMIT: u64 = 8 * 1024 * 1024;
+
+// 上传前的文件检查结果，供确认对话框展示
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadCandidateReport {
+    pub path: String,
+    // 清理非法字符后的文件名
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    pub size: u64,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    // 按文件头识别的类型，文本等无法识别时为空
+    #[serde(rename = "detectedMimeType")]
+    pub detected_mime_type: Option<String>,
+    pub accepted: bool,
+    pub violations: Vec<ViolationPayload>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(rename = "pageCount")]
+    pub page_count: Option<u32>,
+}
+
+// 处理后的图片：去除 EXIF 的原图和 JPEG 缩略图
+#[derive(Debug, Clone)]
+pub struct PreparedImage {
+    pub data: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+// 计算平均上传速度时保留的最近上传次数
+pub const UPLOAD_THROUGHPUT_SAMPLES: usize = 5;
+
+// 自适应压缩只处理这些格式，GIF 可能是动图，重新编码会丢帧
+const COMPRESSIBLE_IMAGE_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/webp"];
+
+// 最近几次上传的字节数和耗时，按总字节数除以总耗时计算，避免小文件的固定开销拉低平均速度
+#[derive(Debug, Default)]
+pub struct UploadThroughput {
+    samples: VecDeque<(u64, Duration)>,
+}
+
+impl UploadThroughput {
+    pub fn record(&mut self, bytes: u64, elapsed: Duration) {
+        if elapsed.is_zero() {
+            return;
+        }
+        if self.samples.len() == UPLOAD_THROUGHPUT_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((bytes, elapsed));
+    }
+
+    // 平均速度（kbit/s），还没有上传记录时为空
+    pub fn average_kbps(&self) -> Option<f64> {
+        let seconds: f64 = self.samples.iter().map(|(_, elapsed)| elapsed.as_secs_f64()).sum();
+        if seconds <= 0.0 {
+            return None;
+        }
+        let bytes: u64 = self.samples.iter().map(|(bytes, _)| bytes).sum();
+        Some(bytes as f64 * 8.0 / 1000.0 / seconds)
+    }
+}
+
+// 是否在上传前重新压缩：仅限 JPEG/PNG/WebP 图片，医生未选择发送原图，且最近的上传速度低于阈值
+// 还没有测速记录时不压缩；语音和其他文件不受影响
+pub fn should_compress_upload(
+    config: &UploadCompressionConfig,
+    mime_type: Option<&str>,
+    average_kbps: Option<f64>,
+    send_original: bool,
+) -> bool {
+    config.enabled
+        && !send_original
+        && mime_type.is_some_and(|mime| COMPRESSIBLE_IMAGE_TYPES.contains(&mime))
+        && average_kbps.is_some_and(|kbps| kbps < config.slow_upload_kbps as f64)
+}
+
+pub struct FileService {
+    upload_throughput: Mutex<UploadThroughput>,
+}
 
 impl FileService {
     pub fn new() -> Self {
-        Self
+        Self {
+            upload_throughput: Mutex::new(UploadThroughput::default()),
+        }
     }
 
-    pub async fn save_file(&self, file_data: &[u8], file_name: &str) -> Result<PathBuf> {
-        // TODO: 实现文件保存逻辑
-        // 1. 验证文件类型和大小
-        // 2. 生成唯一文件名
-        // 3. 保存到本地目录
-        // 4. 返回文件路径
+    pub fn record_upload(&self, bytes: u64, elapsed: Duration) {
+        self.upload_throughput.lock().unwrap().record(bytes, elapsed);
+    }
 
-        let timestamp = chrono::Utc::now().timestamp();
-        let safe_filename = format!("{}-{}", timestamp, file_name);
-        let file_path = PathBuf::from(format!("/temp/uploads/{}", safe_filename));
+    pub fn average_upload_kbps(&self) -> Option<f64> {
+        self.upload_throughput.lock().unwrap().average_kbps()
+    }
 
-        // 模拟文件保存
-        println!("Saving file: {} ({} bytes)", safe_filename, file_data.len());
+    // 保存到指定目录，文件名加时间戳前缀避免重名
+    pub async fn save_file(&self, dir: &Path, file_data: &[u8], file_name: &str) -> Result<PathBuf> {
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let safe_filename = format!("{}-{}", timestamp, ValidationService::sanitize_filename(file_name));
+        let file_path = dir.join(&safe_filename);
+
+        tracing::info!("Saving file: {} ({} bytes)", safe_filename, file_data.len());
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(&file_path, file_data).await?;
 
         Ok(file_path)
     }
 
+    // 保存图片原图和缩略图，返回 (原图路径, 缩略图路径)
+    pub async fn save_image(&self, dir: &Path, image: &PreparedImage, file_name: &str) -> Result<(PathBuf, PathBuf)> {
+        let file_path = self.save_file(dir, &image.data, file_name).await?;
+        let thumbnail_path = Self::thumbnail_path_for(&file_path);
+        if let Some(parent) = thumbnail_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&thumbnail_path, &image.thumbnail).await?;
+
+        Ok((file_path, thumbnail_path))
+    }
+
+    // 缩略图保存在原图同级的 thumbnails 目录下
+    pub fn thumbnail_path_for(path: &Path) -> PathBuf {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+        path.parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(THUMBNAIL_DIR)
+            .join(format!("{}_thumb.jpg", stem))
+    }
+
+    // 文档预览图与缩略图放在同一目录
+    pub fn preview_path_for(path: &Path) -> PathBuf {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("document");
+        path.parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(THUMBNAIL_DIR)
+            .join(format!("{}_preview.png", stem))
+    }
+
+    // PDF 渲染首页预览图并提取开头文字，docx 只提取文字；其他类型返回空预览
+    // 在阻塞线程池中执行并限制时长，任何失败都降级为没有预览
+    pub async fn extract_preview(&self, path: &Path, mime: &str) -> FilePreview {
+        if !is_previewable(mime) {
+            return FilePreview::default();
+        }
+
+        let task = {
+            let path = path.to_path_buf();
+            let mime = mime.to_string();
+            tokio::task::spawn_blocking(move || extract_preview_blocking(&path, &mime))
+        };
+        // 超时后阻塞任务无法中止，会在后台自行结束，结果被丢弃
+        match tokio::time::timeout(PREVIEW_TIMEOUT, task).await {
+            Ok(Ok(preview)) => preview,
+            Ok(Err(e)) => {
+                tracing::warn!("Preview extraction panicked for {:?}: {}", path, e);
+                FilePreview::default()
+            }
+            Err(_) => {
+                tracing::warn!("Preview extraction timed out for {:?}", path);
+                FilePreview::default()
+            }
+        }
+    }
+
+    // 解码图片生成缩略图，并去除原图中的 EXIF（拍摄位置、设备信息等）
+    // JPEG/PNG 直接删除元数据段，不重新编码；需要按 EXIF 方向旋转的图片会重新编码
+    pub fn prepare_image(&self, data: &[u8]) -> Result<PreparedImage> {
+        let format = image::guess_format(data).map_err(|e| anyhow!("无法识别的图片格式: {}", e))?;
+        let mut decoder = ImageReader::with_format(Cursor::new(data), format)
+            .into_decoder()
+            .map_err(|e| anyhow!("图片文件已损坏: {}", e))?;
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let has_exif = matches!(decoder.exif_metadata(), Ok(Some(_)));
+        let mut img = DynamicImage::from_decoder(decoder).map_err(|e| anyhow!("图片文件已损坏: {}", e))?;
+        img.apply_orientation(orientation);
+
+        let upright = orientation == Orientation::NoTransforms;
+        let stripped = match format {
+            ImageFormat::Jpeg if upright => strip_jpeg_metadata(data)?,
+            ImageFormat::Png if upright => strip_png_metadata(data)?,
+            _ if upright && !has_exif => data.to_vec(),
+            _ => encode_image(&img, format)?,
+        };
+
+        let thumbnail = if img.width() > THUMBNAIL_MAX_SIZE || img.height() > THUMBNAIL_MAX_SIZE {
+            img.thumbnail(THUMBNAIL_MAX_SIZE, THUMBNAIL_MAX_SIZE)
+        } else {
+            img.clone()
+        };
+        let mut thumbnail_data = Vec::new();
+        JpegEncoder::new_with_quality(&mut thumbnail_data, THUMBNAIL_QUALITY)
+            .encode_image(&DynamicImage::ImageRgb8(thumbnail.to_rgb8()))
+            .map_err(|e| anyhow!("生成缩略图失败: {}", e))?;
+
+        Ok(PreparedImage {
+            data: stripped,
+            thumbnail: thumbnail_data,
+            width: img.width(),
+            height: img.height(),
+        })
+    }
+
+    // 慢速网络下上传前重新压缩图片：长边缩小到 maxDimension 以内，JPEG 按配置的质量重新编码，
+    // PNG/WebP 保持原格式只缩小尺寸，扩展名与内容保持一致；压缩后没有变小时返回 None，沿用原图
+    pub fn compress_image(&self, image: &PreparedImage, config: &UploadCompressionConfig) -> Result<Option<PreparedImage>> {
+        let format = image::guess_format(&image.data).map_err(|e| anyhow!("无法识别的图片格式: {}", e))?;
+        let img = image::load_from_memory_with_format(&image.data, format)
+            .map_err(|e| anyhow!("图片文件已损坏: {}", e))?;
+
+        let max = config.max_dimension;
+        let oversized = img.width() > max || img.height() > max;
+        let img = if oversized {
+            img.resize(max, max, FilterType::Triangle)
+        } else {
+            img
+        };
+
+        let data = match format {
+            ImageFormat::Jpeg => {
+                let mut output = Vec::new();
+                JpegEncoder::new_with_quality(&mut output, config.quality)
+                    .encode_image(&DynamicImage::ImageRgb8(img.to_rgb8()))
+                    .map_err(|e| anyhow!("图片压缩失败: {}", e))?;
+                output
+            }
+            ImageFormat::Png | ImageFormat::WebP if oversized => encode_image(&img, format)?,
+            _ => return Ok(None),
+        };
+        if data.len() >= image.data.len() {
+            return Ok(None);
+        }
+
+        Ok(Some(PreparedImage {
+            data,
+            thumbnail: image.thumbnail.clone(),
+            width: img.width(),
+            height: img.height(),
+        }))
+    }
+
+    // 上传耗时计入最近的上传速度，供下一次上传判断是否需要压缩
     pub async fn upload_file(&self, file_path: &PathBuf) -> Result<String> {
         // TODO: 实现文件上传逻辑
         // 1. 读取本地文件
         // 2. 上传到服务器或云存储
         // 3. 返回文件 URL
+        let started = Instant::now();
+        let size = tokio::fs::metadata(file_path).await.map(|m| m.len()).unwrap_or(0);
 
         let file_name = file_path.file_name()
             .and_then(|name| name.to_str())
@@ -39,7 +310,8 @@ impl FileService {
 
         let url = format!("https://cdn.telemedicine.com/files/{}", file_name);
 
-        println!("Uploading file: {:?} -> {}", file_path, url);
+        tracing::info!("Uploading file: {:?} -> {}", file_path, url);
+        self.record_upload(size, started.elapsed());
 
         Ok(url)
     }
@@ -50,17 +322,37 @@ impl FileService {
         // 2. 下载文件内容
         // 3. 保存到本地路径
 
-        println!("Downloading file: {} -> {:?}", url, local_path);
+        tracing::info!("Downloading file: {} -> {:?}", url, local_path);
         Ok(())
     }
 
+    // 同步到的患者头像以低优先级加入下载队列，已完整缓存的跳过，返回新加入的数量
+    pub fn enqueue_avatar_downloads(&self, downloads: &DownloadManager, avatar_urls: &[String]) -> usize {
+        let mut queued = 0;
+        for url in avatar_urls {
+            match downloads.is_cached(url) {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to check avatar cache for {}: {}", url, e);
+                    continue;
+                }
+            }
+            match downloads.enqueue(url, DownloadPriority::Low, None) {
+                Ok(_) => queued += 1,
+                Err(e) => tracing::warn!("Failed to enqueue avatar {}: {}", url, e),
+            }
+        }
+        queued
+    }
+
     pub async fn delete_file(&self, file_path: &PathBuf) -> Result<()> {
         // TODO: 实现文件删除逻辑
         // 1. 检查文件是否存在
         // 2. 删除本地文件
         // 3. 清理相关记录
 
-        println!("Deleting file: {:?}", file_path);
+        tracing::info!("Deleting file: {:?}", file_path);
         Ok(())
     }
 
@@ -97,4 +389,1435 @@ impl FileService {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    // 拖入或粘贴的文件上传前检查：按文件头识别真实类型，校验大小、类型白名单和扩展名
+    // 图片和 PDF 附带尺寸或页数
+    pub fn inspect_upload_candidate(&self, path: &Path, config: &AppConfig) -> Result<UploadCandidateReport> {
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() {
+            bail!("不是文件: {}", path.display());
+        }
+        let size = metadata.len();
+        let original_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+        let mut head = Vec::with_capacity(FILE_SNIFF_BYTES);
+        std::fs::File::open(path)?
+            .take(FILE_SNIFF_BYTES as u64)
+            .read_to_end(&mut head)?;
+        let detected = infer::get(&head).map(|kind| kind.mime_type());
+        let claimed = ValidationService::file_extension(original_name)
+            .and_then(|ext| ValidationService::mime_type_for_extension(&ext));
+        let mime_type = detected.or(claimed).unwrap_or("application/octet-stream");
+
+        let mut result = ValidationService::validate_upload(
+            original_name,
+            size,
+            mime_type,
+            config.max_file_size,
+            &config.allowed_file_types,
+        );
+        if let Some(claimed) = claimed {
+            if !content_matches_extension(claimed, detected) {
+                add_extension_mismatch(&mut result, original_name, detected);
+            }
+        }
+
+        // 超出大小限制的文件不再读取全文
+        let (mut width, mut height, mut page_count) = (None, None, None);
+        if size <= config.max_file_size {
+            if mime_type.starts_with("image/") {
+                let dimensions = ImageReader::open(path)
+                    .and_then(|reader| reader.with_guessed_format())
+                    .map_err(image::ImageError::from)
+                    .and_then(|reader| reader.into_dimensions());
+                if let Ok((w, h)) = dimensions {
+                    width = Some(w);
+                    height = Some(h);
+                }
+            } else if mime_type == "application/pdf" {
+                page_count = pdf_page_count(&std::fs::read(path)?);
+            }
+        }
+
+        Ok(UploadCandidateReport {
+            path: path.to_string_lossy().to_string(),
+            file_name: ValidationService::sanitize_filename(original_name),
+            size,
+            mime_type: mime_type.to_string(),
+            detected_mime_type: detected.map(str::to_string),
+            accepted: result.is_valid,
+            violations: result
+                .errors
+                .into_iter()
+                .map(|v| ViolationPayload {
+                    field: v.field,
+                    message: v.message,
+                    code: v.code,
+                })
+                .collect(),
+            width,
+            height,
+            page_count,
+        })
+    }
+
+    // upload_file 和 save_file_locally 处理文件前的检查：清理非法字符后的文件名不能为空，文件头与扩展名一致
+    pub fn check_upload_content(&self, file_name: &str, data: &[u8]) -> ValidationResult {
+        let mut result = ValidationResult::new();
+        if ValidationService::sanitize_filename(file_name).trim().is_empty() {
+            result.add("name", MessageKey::FileNameRequired, &[], "REQUIRED");
+        }
+
+        let head = &data[..data.len().min(FILE_SNIFF_BYTES)];
+        let detected = infer::get(head).map(|kind| kind.mime_type());
+        let claimed = ValidationService::file_extension(file_name)
+            .and_then(|ext| ValidationService::mime_type_for_extension(&ext));
+        if let Some(claimed) = claimed {
+            if !content_matches_extension(claimed, detected) {
+                add_extension_mismatch(&mut result, file_name, detected);
+            }
+        }
+        result
+    }
+
+    // 解析语音文件，目前支持 WAV，其他格式或损坏的文件返回 None
+    pub fn analyze_audio(&self, path: &Path) -> Option<AudioMetadata> {
+        let reader = match hound::WavReader::open(path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                tracing::debug!("Audio analysis skipped for {:?}: {}", path, e);
+                return None;
+            }
+        };
+        analyze_wav(reader)
+    }
+
+    // 上传前的音频数据
+    pub fn analyze_audio_bytes(&self, data: &[u8]) -> Option<AudioMetadata> {
+        let reader = hound::WavReader::new(Cursor::new(data)).ok()?;
+        analyze_wav(reader)
+    }
+}
+
+// 按文件扩展名判断是否为图片，返回对应的 MIME 类型
+impl Default for FileService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn image_mime_type(file_name: &str) -> Option<&'static str> {
+    let ext = ValidationService::file_extension(file_name)?;
+    ValidationService::mime_type_for_extension(&ext).filter(|mime| mime.starts_with("image/"))
+}
+
+fn is_previewable(mime: &str) -> bool {
+    mime == PDF_MIME_TYPE || mime == DOCX_MIME_TYPE
+}
+
+// 按文件头识别可预览的文档类型，Office 文档被识别为 zip 时以扩展名为准
+pub fn previewable_mime_type(path: &Path) -> Option<&'static str> {
+    let mut head = Vec::with_capacity(FILE_SNIFF_BYTES);
+    std::fs::File::open(path)
+        .and_then(|file| file.take(FILE_SNIFF_BYTES as u64).read_to_end(&mut head))
+        .ok()?;
+    let claimed = path
+        .to_str()
+        .and_then(ValidationService::file_extension)
+        .and_then(|ext| ValidationService::mime_type_for_extension(&ext));
+
+    let mime = match infer::get(&head).map(|kind| kind.mime_type()) {
+        Some("application/zip") => claimed?,
+        Some(detected) => detected,
+        None => return None,
+    };
+    is_previewable(mime).then_some(mime)
+}
+
+fn extract_preview_blocking(path: &Path, mime: &str) -> FilePreview {
+    let mut preview = FilePreview::default();
+    match mime {
+        PDF_MIME_TYPE => {
+            let output = FileService::preview_path_for(path);
+            match render_pdf_first_page(path, &output) {
+                Ok(()) => preview.preview_path = Some(output.to_string_lossy().to_string()),
+                Err(e) => tracing::debug!("PDF page render skipped for {:?}: {}", path, e),
+            }
+            match pdf_extract::extract_text(path) {
+                Ok(text) => preview.preview_text = preview_text(&text),
+                Err(e) => tracing::debug!("PDF text extraction failed for {:?}: {}", path, e),
+            }
+        }
+        DOCX_MIME_TYPE => match docx_text(path) {
+            Ok(text) => preview.preview_text = preview_text(&text),
+            Err(e) => tracing::debug!("DOCX text extraction failed for {:?}: {}", path, e),
+        },
+        _ => {}
+    }
+    preview
+}
+
+// 用 PDFium 渲染首页为 PNG；优先加载程序目录下随安装包分发的动态库，其次是系统库
+fn render_pdf_first_page(path: &Path, output: &Path) -> Result<()> {
+    use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+
+    let bundled_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&bundled_dir))
+        .or_else(|_| Pdfium::bind_to_system_library())
+        .map_err(|e| anyhow!("PDFium 不可用: {}", e))?;
+    let pdfium = Pdfium::new(bindings);
+
+    let document = pdfium
+        .load_pdf_from_file(path, None)
+        .map_err(|e| anyhow!("无法打开 PDF: {}", e))?;
+    let page = document.pages().get(0).map_err(|e| anyhow!("无法读取首页: {}", e))?;
+    let config = PdfRenderConfig::new()
+        .set_target_width(THUMBNAIL_MAX_SIZE as i32)
+        .set_maximum_height(THUMBNAIL_MAX_SIZE as i32);
+    let bitmap = page
+        .render_with_config(&config)
+        .map_err(|e| anyhow!("首页渲染失败: {}", e))?;
+
+    let (width, height) = (bitmap.width() as u32, bitmap.height() as u32);
+    let image = image::RgbaImage::from_raw(width, height, bitmap.as_rgba_bytes())
+        .ok_or_else(|| anyhow!("首页渲染结果尺寸不符"))?;
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image.save_with_format(output, ImageFormat::Png)?;
+    Ok(())
+}
+
+// 读取 word/document.xml 中的文字段，段落之间换行
+fn docx_text(path: &Path) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")?
+        .take(DOCX_XML_LIMIT)
+        .read_to_string(&mut xml)?;
+
+    let pattern = regex::Regex::new(r"<w:t(?:\s[^>]*)?>([^<]*)</w:t>|</w:p>").unwrap();
+    let mut text = String::new();
+    for caps in pattern.captures_iter(&xml) {
+        match caps.get(1) {
+            Some(run) => text.push_str(&unescape_xml(run.as_str())),
+            None => text.push('\n'),
+        }
+        // 只需要开头部分
+        if text.chars().count() > PREVIEW_TEXT_CHARS {
+            break;
+        }
+    }
+    Ok(text)
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// 合并连续空白后截取开头部分，没有文字时为空
+fn preview_text(text: &str) -> Option<String> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let preview: String = collapsed.chars().take(PREVIEW_TEXT_CHARS).collect();
+    (!preview.is_empty()).then_some(preview)
+}
+
+// 扩展名声明的类型与文件头是否一致；文本文件没有文件头，无法识别时视为一致
+fn add_extension_mismatch(result: &mut ValidationResult, file_name: &str, detected: Option<&str>) {
+    result.add_error(
+        "extension",
+        &format!("文件内容（{}）与扩展名不符: {}", detected.unwrap_or("未知类型"), file_name),
+        CODE_EXTENSION_MISMATCH,
+    );
+}
+
+fn content_matches_extension(claimed: &str, detected: Option<&str>) -> bool {
+    match detected {
+        Some(detected) if detected == claimed => true,
+        // Office 文档本身是 zip 包
+        Some("application/zip") => claimed.starts_with("application/vnd.openxmlformats-officedocument."),
+        Some(_) => false,
+        None => claimed.starts_with("text/"),
+    }
+}
+
+// 统计 PDF 页对象数，页对象在压缩对象流中时取页树的 /Count
+fn pdf_page_count(data: &[u8]) -> Option<u32> {
+    let page = regex::bytes::Regex::new(r"/Type\s*/Page((?-u:[^s])|$)").unwrap();
+    let pages = page.find_iter(data).count() as u32;
+    if pages > 0 {
+        return Some(pages);
+    }
+
+    let count = regex::bytes::Regex::new(r"/Count\s+(\d+)").unwrap();
+    count
+        .captures_iter(data)
+        .filter_map(|caps| std::str::from_utf8(&caps[1]).ok()?.parse::<u32>().ok())
+        .max()
+}
+
+// 删除 JPEG 的 APP1 段（EXIF/XMP），其余段原样保留
+fn strip_jpeg_metadata(data: &[u8]) -> Result<Vec<u8>> {
+    let corrupt = || anyhow!("图片文件已损坏: JPEG 段结构不完整");
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return Err(corrupt());
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&data[..2]);
+    let mut pos = 2;
+    while pos < data.len() {
+        if data[pos] != 0xFF {
+            return Err(corrupt());
+        }
+        // 跳过填充字节
+        let mut marker_pos = pos + 1;
+        while marker_pos < data.len() && data[marker_pos] == 0xFF {
+            marker_pos += 1;
+        }
+        let marker = *data.get(marker_pos).ok_or_else(corrupt)?;
+        match marker {
+            // 扫描数据开始，之后是压缩数据，原样复制
+            0xDA => {
+                output.extend_from_slice(&data[pos..]);
+                return Ok(output);
+            }
+            0x01 | 0xD0..=0xD7 => {
+                output.extend_from_slice(&data[pos..=marker_pos]);
+                pos = marker_pos + 1;
+            }
+            _ => {
+                let length_bytes = data.get(marker_pos + 1..marker_pos + 3).ok_or_else(corrupt)?;
+                let length = u16::from_be_bytes([length_bytes[0], length_bytes[1]]) as usize;
+                let end = marker_pos + 1 + length;
+                if length < 2 || end > data.len() {
+                    return Err(corrupt());
+                }
+                if marker != 0xE1 {
+                    output.extend_from_slice(&data[pos..end]);
+                }
+                pos = end;
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+// 删除 PNG 的 eXIf 块和文本元数据块
+fn strip_png_metadata(data: &[u8]) -> Result<Vec<u8>> {
+    const SIGNATURE_LEN: usize = 8;
+    const METADATA_CHUNKS: [&[u8]; 4] = [b"eXIf", b"tEXt", b"iTXt", b"zTXt"];
+    let corrupt = || anyhow!("图片文件已损坏: PNG 块结构不完整");
+    if data.len() < SIGNATURE_LEN {
+        return Err(corrupt());
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&data[..SIGNATURE_LEN]);
+    let mut pos = SIGNATURE_LEN;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(corrupt)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        // 长度 + 类型 + 数据 + CRC
+        let end = pos
+            .checked_add(12)
+            .and_then(|n| n.checked_add(length))
+            .filter(|end| *end <= data.len())
+            .ok_or_else(corrupt)?;
+        if !METADATA_CHUNKS.contains(&&header[4..8]) {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+
+    Ok(output)
+}
+
+fn encode_image(img: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    match format {
+        // JPEG 不支持透明通道
+        ImageFormat::Jpeg => JpegEncoder::new_with_quality(&mut output, 90)
+            .encode_image(&DynamicImage::ImageRgb8(img.to_rgb8()))
+            .map_err(|e| anyhow!("图片重新编码失败: {}", e))?,
+        _ => img
+            .write_to(&mut Cursor::new(&mut output), format)
+            .map_err(|e| anyhow!("图片重新编码失败: {}", e))?,
+    }
+    Ok(output)
+}
+
+fn analyze_wav<R: Read>(mut reader: hound::WavReader<R>) -> Option<AudioMetadata> {
+    let spec = reader.spec();
+    let frames = reader.duration() as u64;
+    if spec.sample_rate == 0 || spec.channels == 0 {
+        return None;
+    }
+
+    let duration_ms = frames * 1000 / spec.sample_rate as u64;
+    let mut peaks = vec![0f32; WAVEFORM_BARS];
+    if frames > 0 {
+        let channels = spec.channels as u64;
+        let bar_of = |index: usize| ((index as u64 / channels) * WAVEFORM_BARS as u64 / frames) as usize;
+        let result = match spec.sample_format {
+            hound::SampleFormat::Float => reader
+                .samples::<f32>()
+                .enumerate()
+                .try_for_each(|(i, sample)| sample.map(|v| record_peak(&mut peaks, bar_of(i), v.abs()))),
+            hound::SampleFormat::Int => {
+                let full_scale = (1i64 << (spec.bits_per_sample.clamp(1, 32) - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .enumerate()
+                    .try_for_each(|(i, sample)| sample.map(|v| record_peak(&mut peaks, bar_of(i), (v as f32 / full_scale).abs())))
+            }
+        };
+        if let Err(e) = result {
+            tracing::debug!("Failed to read audio samples: {}", e);
+            return None;
+        }
+    }
+
+    let max = peaks.iter().cloned().fold(0f32, f32::max);
+    let waveform = peaks
+        .iter()
+        .map(|peak| if max > 0.0 { (peak / max * 100.0).round() / 100.0 } else { 0.0 })
+        .collect();
+
+    Some(AudioMetadata { duration_ms, waveform })
+}
+
+fn record_peak(peaks: &mut [f32], bar: usize, value: f32) {
+    let bar = bar.min(peaks.len() - 1);
+    if value > peaks[bar] {
+        peaks[bar] = value;
+    }
+}
+
+// 下载进度事件
+pub const DOWNLOAD_PROGRESS_EVENT: &str = "download-progress";
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 3;
+// 本地文件缓存的容量上限，超过后健康检查提示清理
+pub const FILE_CACHE_SIZE_LIMIT: i64 = 2 * 1024 * 1024 * 1024;
+// 每下载这么多字节持久化一次进度并通知前端
+const DOWNLOAD_PERSIST_BYTES: u64 = 512 * 1024;
+// 队列中保留的已结束任务数
+const FINISHED_DOWNLOAD_HISTORY: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadPriority {
+    Low,
+    Normal,
+    High,
+}
+
+impl DownloadPriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadPriority::Low => "low",
+            DownloadPriority::Normal => "normal",
+            DownloadPriority::High => "high",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "low" => Some(DownloadPriority::Low),
+            "normal" => Some(DownloadPriority::Normal),
+            "high" => Some(DownloadPriority::High),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    Queued,
+    Downloading,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl DownloadStatus {
+    fn is_finished(&self) -> bool {
+        matches!(self, DownloadStatus::Completed | DownloadStatus::Failed | DownloadStatus::Cancelled)
+    }
+}
+
+// 字段与 UploadProgress 一致，前端可复用上传进度组件
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    #[serde(rename = "fileId")]
+    pub file_id: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    pub loaded: u64,
+    pub total: u64,
+    pub percentage: f32,
+    pub status: DownloadStatus,
+    pub url: String,
+    #[serde(rename = "localPath")]
+    pub local_path: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadTask {
+    pub id: String,
+    pub url: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    #[serde(rename = "localPath")]
+    pub local_path: String,
+    pub priority: DownloadPriority,
+    pub status: DownloadStatus,
+    pub loaded: u64,
+    pub total: u64,
+    #[serde(rename = "expectedChecksum")]
+    pub expected_checksum: Option<String>,
+    // 问诊附件所属的问诊，下载前检查附件配额
+    #[serde(rename = "consultationId")]
+    pub consultation_id: Option<String>,
+    pub error: Option<String>,
+    #[serde(rename = "queuedAt")]
+    pub queued_at: DateTime<Utc>,
+}
+
+impl DownloadTask {
+    fn progress(&self) -> DownloadProgress {
+        let percentage = if self.total > 0 {
+            (self.loaded as f32 / self.total as f32 * 100.0).min(100.0)
+        } else if self.status == DownloadStatus::Completed {
+            100.0
+        } else {
+            0.0
+        };
+
+        DownloadProgress {
+            file_id: self.id.clone(),
+            file_name: self.file_name.clone(),
+            loaded: self.loaded,
+            total: self.total,
+            percentage,
+            status: self.status,
+            url: self.url.clone(),
+            local_path: self.local_path.clone(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// 远程文件下载队列：按优先级调度，限制并发数，支持 HTTP Range 断点续传
+///
+/// 下载中的数据写入 `<本地路径>.part`，已下载字节数定期写入 file_cache，
+/// 取消或中断后再次加入队列时从记录的位置继续；服务器不支持 Range 时从头下载。
+pub struct DownloadManager {
+    inner: Arc<DownloadInner>,
+}
+
+struct DownloadInner {
+    client: reqwest::Client,
+    // 为空时使用全局数据库连接，数据库在应用启动后才初始化
+    connection: Option<DbConnection>,
+    download_dir: PathBuf,
+    max_concurrent: usize,
+    state: std::sync::Mutex<DownloadState>,
+    event_sender: mpsc::UnboundedSender<DownloadProgress>,
+}
+
+#[derive(Default)]
+struct DownloadState {
+    tasks: Vec<DownloadTask>,
+    running: HashMap<String, JoinHandle<()>>,
+}
+
+impl DownloadManager {
+    pub fn new(download_dir: PathBuf, max_concurrent: usize) -> (Self, mpsc::UnboundedReceiver<DownloadProgress>) {
+        Self::build(None, download_dir, max_concurrent)
+    }
+
+    pub fn with_connection(
+        connection: DbConnection,
+        download_dir: PathBuf,
+        max_concurrent: usize,
+    ) -> (Self, mpsc::UnboundedReceiver<DownloadProgress>) {
+        Self::build(Some(connection), download_dir, max_concurrent)
+    }
+
+    fn build(
+        connection: Option<DbConnection>,
+        download_dir: PathBuf,
+        max_concurrent: usize,
+    ) -> (Self, mpsc::UnboundedReceiver<DownloadProgress>) {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+        let inner = DownloadInner {
+            client: reqwest::Client::new(),
+            connection,
+            download_dir,
+            max_concurrent: max_concurrent.max(1),
+            state: std::sync::Mutex::new(DownloadState::default()),
+            event_sender,
+        };
+
+        (Self { inner: Arc::new(inner) }, event_receiver)
+    }
+
+    // 加入下载队列，同一地址已在队列中时只提升优先级
+    pub fn enqueue(&self, url: &str, priority: DownloadPriority, expected_checksum: Option<String>) -> Result<DownloadTask> {
+        self.enqueue_for_consultation(url, priority, expected_checksum, None)
+    }
+
+    // 问诊附件的下载计入该问诊和患者的附件配额，超出时任务以 QUOTA_EXCEEDED 失败
+    pub fn enqueue_for_consultation(
+        &self,
+        url: &str,
+        priority: DownloadPriority,
+        expected_checksum: Option<String>,
+        consultation_id: Option<String>,
+    ) -> Result<DownloadTask> {
+        let parsed = url::Url::parse(url).map_err(|e| anyhow!("无效的下载地址 {}: {}", url, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            bail!("不支持的下载协议: {}", parsed.scheme());
+        }
+
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            if let Some(task) = state.tasks.iter_mut().find(|t| t.url == url && !t.status.is_finished()) {
+                task.priority = task.priority.max(priority);
+                return Ok(task.clone());
+            }
+        }
+
+        let file_name = parsed
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("download")
+            .to_string();
+        // 之前下载过（包括未完成）的文件沿用原路径，以便续传
+        let local_path = match self.inner.cache_dao().find_by_url(url).map_err(dao_error)? {
+            Some(cache) => cache.local_path,
+            None => {
+                let unique = Uuid::new_v4().simple().to_string();
+                let name = format!("{}-{}", &unique[..8], ValidationService::sanitize_filename(&file_name));
+                self.inner.download_dir.join(name).to_string_lossy().to_string()
+            }
+        };
+
+        let task = DownloadTask {
+            id: Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            file_name,
+            local_path,
+            priority,
+            status: DownloadStatus::Queued,
+            loaded: 0,
+            total: 0,
+            expected_checksum,
+            consultation_id,
+            error: None,
+            queued_at: Utc::now(),
+        };
+
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            state.tasks.push(task.clone());
+            self.inner.emit(&task);
+        }
+        self.inner.pump();
+
+        Ok(task)
+    }
+
+    // 取消排队或下载中的任务，已下载的部分保留用于续传
+    pub fn cancel(&self, id: &str) -> bool {
+        {
+            let mut guard = self.inner.state.lock().unwrap();
+            let state = &mut *guard;
+            let Some(task) = state.tasks.iter_mut().find(|t| t.id == id && !t.status.is_finished()) else {
+                return false;
+            };
+            if let Some(handle) = state.running.remove(id) {
+                handle.abort();
+            }
+            task.status = DownloadStatus::Cancelled;
+            self.inner.emit(task);
+        }
+        self.inner.pump();
+
+        true
+    }
+
+    // 已下载完成且本地文件仍存在
+    pub fn is_cached(&self, url: &str) -> Result<bool> {
+        let cached = self.inner.cache_dao().find_by_url(url).map_err(dao_error)?;
+        Ok(cached.is_some_and(|cache| cache.checksum.is_some() && Path::new(&cache.local_path).is_file()))
+    }
+
+    pub fn queue(&self) -> Vec<DownloadTask> {
+        self.inner.state.lock().unwrap().tasks.clone()
+    }
+}
+
+impl DownloadInner {
+    fn cache_dao(&self) -> FileCacheDao {
+        match &self.connection {
+            Some(connection) => FileCacheDao::with_connection(connection.clone()),
+            None => FileCacheDao::new(),
+        }
+    }
+
+    fn quota_service(&self) -> AttachmentQuotaService {
+        match &self.connection {
+            Some(connection) => AttachmentQuotaService::with_connection(connection.clone()),
+            None => AttachmentQuotaService::new(),
+        }
+    }
+
+    fn emit(&self, task: &DownloadTask) {
+        // 接收端关闭时忽略
+        let _ = self.event_sender.send(task.progress());
+    }
+
+    // 在并发上限内启动优先级最高的排队任务
+    fn pump(self: &Arc<Self>) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        while state.running.len() < self.max_concurrent {
+            let Some(index) = next_queued(&state.tasks) else {
+                break;
+            };
+            let task = &mut state.tasks[index];
+            task.status = DownloadStatus::Downloading;
+            self.emit(task);
+
+            let task = task.clone();
+            let id = task.id.clone();
+            let inner = Arc::clone(self);
+            // 持有锁期间插入句柄，任务结束时一定能找到自己
+            let handle = tokio::spawn(async move {
+                let result = inner.transfer(&task).await;
+                inner.finish(&task.id, result);
+            });
+            state.running.insert(id, handle);
+        }
+    }
+
+    fn finish(self: &Arc<Self>, id: &str, result: Result<u64>) {
+        {
+            let mut guard = self.state.lock().unwrap();
+            let state = &mut *guard;
+            // 已被取消
+            if state.running.remove(id).is_none() {
+                return;
+            }
+            if let Some(task) = state.tasks.iter_mut().find(|t| t.id == id) {
+                match result {
+                    Ok(size) => {
+                        task.status = DownloadStatus::Completed;
+                        task.loaded = size;
+                        task.total = size;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Download failed for {}: {}", task.url, e);
+                        task.status = DownloadStatus::Failed;
+                        task.error = Some(e.to_string());
+                    }
+                }
+                self.emit(task);
+            }
+
+            let finished = state.tasks.iter().filter(|t| t.status.is_finished()).count();
+            let mut excess = finished.saturating_sub(FINISHED_DOWNLOAD_HISTORY);
+            state.tasks.retain(|t| {
+                if excess > 0 && t.status.is_finished() {
+                    excess -= 1;
+                    return false;
+                }
+                true
+            });
+        }
+        self.pump();
+    }
+
+    fn update_progress(&self, id: &str, loaded: u64, total: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(task) = state.tasks.iter_mut().find(|t| t.id == id) {
+            task.loaded = loaded;
+            task.total = total;
+            self.emit(task);
+        }
+    }
+
+    async fn transfer(&self, task: &DownloadTask) -> Result<u64> {
+        let cache = self.cache_dao();
+        let final_path = PathBuf::from(&task.local_path);
+        let part_path = partial_path(&final_path);
+        let cached = cache.find_by_url(&task.url).map_err(dao_error)?;
+
+        // 已完整下载且校验和一致时不再重复下载
+        if let Some(entry) = &cached {
+            let complete = entry.file_size == Some(entry.bytes_downloaded) && final_path.exists();
+            let checksum_ok = match (&task.expected_checksum, &entry.checksum) {
+                (Some(expected), Some(actual)) => expected.eq_ignore_ascii_case(actual),
+                (None, Some(_)) => true,
+                (_, None) => false,
+            };
+            if complete && checksum_ok {
+                return Ok(entry.bytes_downloaded);
+            }
+        }
+
+        // 以数据库记录和磁盘上的较小值为准，中断时未持久化的数据会被丢弃
+        let recorded = cached.as_ref().map(|c| c.bytes_downloaded).unwrap_or(0);
+        let on_disk = tokio::fs::metadata(&part_path).await.map(|m| m.len()).unwrap_or(0);
+        let offset = recorded.min(on_disk);
+
+        let mut request = self.client.get(&task.url);
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send().await?;
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            response = self.client.get(&task.url).send().await?;
+        }
+        let mut response = response.error_for_status()?;
+
+        // 服务器忽略 Range 返回 200 时从头下载
+        let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut loaded = if resumed { offset } else { 0 };
+        let total = response.content_length().map(|length| length + loaded);
+
+        // 写入缓存前检查配额，续传的文件已计入用量的部分不重复计算
+        if let (Some(consultation_id), Some(total)) = (&task.consultation_id, total) {
+            let counted = cached
+                .as_ref()
+                .filter(|entry| entry.consultation_id.is_some())
+                .map(|entry| quota_bytes(entry) as u64)
+                .unwrap_or(0);
+            self.quota_service().check(consultation_id, total.saturating_sub(counted))?;
+        }
+
+        if let Some(parent) = part_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = if resumed {
+            let file = tokio::fs::OpenOptions::new().append(true).open(&part_path).await?;
+            file.set_len(offset).await?;
+            file
+        } else {
+            tokio::fs::File::create(&part_path).await?
+        };
+
+        cache
+            .save_download_progress(&task.url, &task.local_path, total, loaded, task.consultation_id.as_deref())
+            .map_err(dao_error)?;
+        self.update_progress(&task.id, loaded, total.unwrap_or(0));
+
+        let mut persisted = loaded;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            loaded += chunk.len() as u64;
+            if loaded - persisted >= DOWNLOAD_PERSIST_BYTES {
+                file.flush().await?;
+                cache
+                    .save_download_progress(&task.url, &task.local_path, total, loaded, task.consultation_id.as_deref())
+                    .map_err(dao_error)?;
+                persisted = loaded;
+                self.update_progress(&task.id, loaded, total.unwrap_or(loaded));
+            }
+        }
+        file.flush().await?;
+        drop(file);
+        cache
+            .save_download_progress(&task.url, &task.local_path, total, loaded, task.consultation_id.as_deref())
+            .map_err(dao_error)?;
+
+        if let Some(total) = total {
+            if loaded != total {
+                bail!("下载不完整: {}/{} 字节", loaded, total);
+            }
+        }
+
+        let checksum = sha256_file(&part_path).await?;
+        if let Some(expected) = &task.expected_checksum {
+            if !expected.eq_ignore_ascii_case(&checksum) {
+                // 内容有误的文件不能续传，清除进度
+                if let Err(e) = tokio::fs::remove_file(&part_path).await {
+                    tracing::warn!("Failed to remove corrupt download {:?}: {}", part_path, e);
+                }
+                cache
+                    .save_download_progress(&task.url, &task.local_path, total, 0, task.consultation_id.as_deref())
+                    .map_err(dao_error)?;
+                bail!("文件校验失败: 期望 {}，实际 {}", expected, checksum);
+            }
+        }
+
+        tokio::fs::rename(&part_path, &final_path).await?;
+        cache.complete_download(&task.url, loaded, &checksum).map_err(dao_error)?;
+        tracing::info!("Downloaded {} -> {:?} ({} bytes)", task.url, final_path, loaded);
+
+        // 预览失败不影响下载结果
+        if let Some(mime) = previewable_mime_type(&final_path) {
+            let preview = FileService::new().extract_preview(&final_path, mime).await;
+            if !preview.is_empty() {
+                if let Err(e) = cache.set_preview(&task.local_path, &preview) {
+                    tracing::warn!("Failed to record preview for {}: {}", task.url, e);
+                }
+            }
+        }
+
+        Ok(loaded)
+    }
+}
+
+// 优先级最高的排队任务，同优先级先到先下
+fn next_queued(tasks: &[DownloadTask]) -> Option<usize> {
+    tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, task)| task.status == DownloadStatus::Queued)
+        .max_by(|(a_index, a), (b_index, b)| a.priority.cmp(&b.priority).then(b_index.cmp(a_index)))
+        .map(|(index, _)| index)
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+async fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::utils::CODE_EXECUTABLE_BLOCKED;
+    use rusqlite::Connection;
+    use tempfile::tempdir;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/voice_sample.wav");
+
+    // 测试音频：8kHz 单声道 0.5 秒，前半段为 440Hz 正弦波，后半段静音
+    #[test]
+    fn test_analyze_wav_fixture() {
+        let metadata = FileService::new().analyze_audio(Path::new(FIXTURE)).unwrap();
+        assert_eq!(metadata.duration_ms, 500);
+        assert_eq!(metadata.waveform.len(), WAVEFORM_BARS);
+        assert!(metadata.waveform[..WAVEFORM_BARS / 2].iter().all(|peak| *peak > 0.9));
+        assert!(metadata.waveform[WAVEFORM_BARS / 2..].iter().all(|peak| *peak == 0.0));
+
+        let bytes = std::fs::read(FIXTURE).unwrap();
+        assert_eq!(FileService::new().analyze_audio_bytes(&bytes), Some(metadata));
+    }
+
+    #[test]
+    fn test_unsupported_audio_is_none() {
+        let service = FileService::new();
+        assert!(service.analyze_audio_bytes(b"#!AMR\n not a wav file").is_none());
+        assert!(service.analyze_audio(Path::new("/nonexistent/voice.wav")).is_none());
+
+        // 截断的 WAV 不报错
+        let bytes = std::fs::read(FIXTURE).unwrap();
+        assert!(service.analyze_audio_bytes(&bytes[..20]).is_none());
+    }
+
+    // 单页 PDF 化验单，正文为英文，使用标准 Helvetica 字体
+    const PDF_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lab_report.pdf");
+
+    #[tokio::test]
+    async fn test_pdf_preview_text_and_page_render() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("lab_report.pdf");
+        std::fs::copy(PDF_FIXTURE, &path).unwrap();
+        assert_eq!(previewable_mime_type(&path), Some(PDF_MIME_TYPE));
+
+        let preview = FileService::new().extract_preview(&path, PDF_MIME_TYPE).await;
+        let text = preview.preview_text.unwrap();
+        assert!(text.starts_with("Lab Report"));
+        assert!(text.contains("Hemoglobin 135 g/L"));
+        assert!(!text.contains('\n'));
+
+        // 没有 PDFium 动态库的环境只有文字预览
+        if let Some(preview_path) = preview.preview_path {
+            assert_eq!(Path::new(&preview_path), FileService::preview_path_for(&path));
+            let image = image::open(&preview_path).unwrap();
+            assert!(image.width() <= THUMBNAIL_MAX_SIZE && image.height() <= THUMBNAIL_MAX_SIZE);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_docx_preview_text() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("discharge.docx");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        writer
+            .start_file("word/document.xml", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        let long_paragraph = "随访".repeat(400);
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><w:document><w:body>
+               <w:p><w:r><w:t>出院小结</w:t></w:r></w:p>
+               <w:p><w:r><w:t xml:space="preserve">血压 120/80 &amp; 心率 </w:t></w:r><w:r><w:t>72</w:t></w:r></w:p>
+               <w:p><w:r><w:t>{}</w:t></w:r></w:p>
+               </w:body></w:document>"#,
+            long_paragraph
+        );
+        std::io::Write::write_all(&mut writer, xml.as_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(previewable_mime_type(&path), Some(DOCX_MIME_TYPE));
+        let preview = FileService::new().extract_preview(&path, DOCX_MIME_TYPE).await;
+        assert!(preview.preview_path.is_none());
+        let text = preview.preview_text.unwrap();
+        assert!(text.starts_with("出院小结 血压 120/80 & 心率 72 随访"));
+        assert_eq!(text.chars().count(), PREVIEW_TEXT_CHARS);
+    }
+
+    #[tokio::test]
+    async fn test_broken_document_has_no_preview() {
+        let dir = tempdir().unwrap();
+        let service = FileService::new();
+
+        let truncated = dir.path().join("truncated.pdf");
+        std::fs::write(&truncated, &std::fs::read(PDF_FIXTURE).unwrap()[..64]).unwrap();
+        assert!(service.extract_preview(&truncated, PDF_MIME_TYPE).await.is_empty());
+
+        let not_zip = dir.path().join("fake.docx");
+        std::fs::write(&not_zip, b"plain text renamed to docx").unwrap();
+        assert!(service.extract_preview(&not_zip, DOCX_MIME_TYPE).await.is_empty());
+        assert_eq!(previewable_mime_type(&not_zip), None);
+
+        // 不支持的类型不读取文件
+        assert!(service.extract_preview(Path::new(FIXTURE), "audio/x-wav").await.is_empty());
+    }
+
+    // 生成带 APP1 Exif 段的 JPEG
+    fn sample_jpeg_with_exif(width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]));
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 90)
+            .encode_image(&DynamicImage::ImageRgb8(img))
+            .unwrap();
+
+        // 大端 TIFF 头 + 空 IFD
+        let payload = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0\0\0\0\0";
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(payload);
+        jpeg.splice(2..2, segment);
+        jpeg
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn test_prepare_image_thumbnail_dimensions() {
+        let service = FileService::new();
+        let prepared = service.prepare_image(&sample_jpeg_with_exif(1200, 800)).unwrap();
+        assert_eq!((prepared.width, prepared.height), (1200, 800));
+
+        let thumbnail = image::load_from_memory(&prepared.thumbnail).unwrap();
+        assert_eq!(image::guess_format(&prepared.thumbnail).unwrap(), ImageFormat::Jpeg);
+        assert_eq!(thumbnail.width(), THUMBNAIL_MAX_SIZE);
+        assert!((213..=214).contains(&thumbnail.height()));
+
+        // 小图不放大
+        let small = service.prepare_image(&sample_jpeg_with_exif(100, 50)).unwrap();
+        let thumbnail = image::load_from_memory(&small.thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (100, 50));
+
+        assert_eq!(
+            FileService::thumbnail_path_for(Path::new("/data/uploads/1-photo.png")),
+            PathBuf::from("/data/uploads/thumbnails/1-photo_thumb.jpg")
+        );
+    }
+
+    #[test]
+    fn test_prepare_image_strips_exif() {
+        let original = sample_jpeg_with_exif(640, 480);
+        assert!(contains(&original, b"Exif\0\0"));
+
+        let prepared = FileService::new().prepare_image(&original).unwrap();
+        assert!(!contains(&prepared.data, b"Exif\0\0"));
+
+        // 去除元数据后原图仍可正常解码
+        let stripped = image::load_from_memory(&prepared.data).unwrap();
+        assert_eq!((stripped.width(), stripped.height()), (640, 480));
+    }
+
+    #[test]
+    fn test_prepare_corrupt_image_fails() {
+        let service = FileService::new();
+        assert!(service.prepare_image(b"definitely not an image").is_err());
+        assert!(service.prepare_image(&[]).is_err());
+
+        let jpeg = sample_jpeg_with_exif(64, 64);
+        assert!(service.prepare_image(&jpeg[..20]).is_err());
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(b"\0\0\0\x0dIHDRgarbage");
+        assert!(service.prepare_image(&png).is_err());
+
+        assert_eq!(image_mime_type("scan.JPG"), Some("image/jpeg"));
+        assert_eq!(image_mime_type("report.pdf"), None);
+    }
+
+    fn upload_config(max_file_size: u64) -> AppConfig {
+        AppConfig {
+            max_file_size,
+            ..AppConfig::default()
+        }
+    }
+
+    fn violation_codes(report: &UploadCandidateReport) -> Vec<&str> {
+        report.violations.iter().map(|v| v.code.as_str()).collect()
+    }
+
+    #[test]
+    fn test_should_compress_upload_decision() {
+        let config = UploadCompressionConfig::default();
+        let slow = Some(config.slow_upload_kbps as f64 / 2.0);
+        let fast = Some(config.slow_upload_kbps as f64 * 2.0);
+
+        assert!(should_compress_upload(&config, Some("image/jpeg"), slow, false));
+        assert!(should_compress_upload(&config, Some("image/png"), slow, false));
+        assert!(!should_compress_upload(&config, Some("image/jpeg"), fast, false));
+        // 还没有测速记录
+        assert!(!should_compress_upload(&config, Some("image/jpeg"), None, false));
+        // 医生选择发送原图
+        assert!(!should_compress_upload(&config, Some("image/jpeg"), slow, true));
+        // 动图、语音和其他文件不压缩
+        assert!(!should_compress_upload(&config, Some("image/gif"), slow, false));
+        assert!(!should_compress_upload(&config, None, slow, false));
+
+        let disabled = UploadCompressionConfig { enabled: false, ..UploadCompressionConfig::default() };
+        assert!(!should_compress_upload(&disabled, Some("image/jpeg"), slow, false));
+    }
+
+    #[test]
+    fn test_upload_throughput_rolling_average() {
+        let service = FileService::new();
+        assert!(service.average_upload_kbps().is_none());
+
+        // 1 MB 用时 1 秒约 8000 kbit/s
+        service.record_upload(1_000_000, Duration::from_secs(1));
+        assert_eq!(service.average_upload_kbps().map(|kbps| kbps.round()), Some(8000.0));
+
+        // 之后的慢速上传把较早的记录挤出窗口
+        for _ in 0..UPLOAD_THROUGHPUT_SAMPLES {
+            service.record_upload(50_000, Duration::from_secs(1));
+        }
+        assert_eq!(service.average_upload_kbps().map(|kbps| kbps.round()), Some(400.0));
+    }
+
+    #[test]
+    fn test_compressed_image_passes_validation() {
+        let service = FileService::new();
+        let prepared = service.prepare_image(&sample_jpeg_with_exif(3000, 2000)).unwrap();
+        let config = UploadCompressionConfig {
+            max_dimension: 1280,
+            quality: 60,
+            ..UploadCompressionConfig::default()
+        };
+
+        let compressed = service.compress_image(&prepared, &config).unwrap().unwrap();
+        assert_eq!((compressed.width, compressed.height), (1280, 853));
+        assert!(compressed.data.len() < prepared.data.len());
+        assert_eq!(compressed.thumbnail, prepared.thumbnail);
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("舌苔照片.jpg");
+        std::fs::write(&path, &compressed.data).unwrap();
+        service.validate_file(&compressed.data, "舌苔照片.jpg").unwrap();
+        let report = service.inspect_upload_candidate(&path, &AppConfig::default()).unwrap();
+        assert!(report.accepted, "{:?}", report.violations);
+        assert_eq!((report.width, report.height), (Some(1280), Some(853)));
+
+        // 尺寸未超限的 PNG 不重新编码
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::new(64, 64))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let small = service.prepare_image(&png).unwrap();
+        assert!(service.compress_image(&small, &config).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_upload_candidate_normal_jpeg() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("舌苔照片 1.jpg");
+        std::fs::write(&path, sample_jpeg_with_exif(800, 600)).unwrap();
+
+        let report = FileService::new().inspect_upload_candidate(&path, &AppConfig::default()).unwrap();
+        assert!(report.accepted, "{:?}", report.violations);
+        assert_eq!(report.mime_type, "image/jpeg");
+        assert_eq!(report.detected_mime_type.as_deref(), Some("image/jpeg"));
+        assert_eq!((report.width, report.height), (Some(800), Some(600)));
+        assert_eq!(report.file_name, "舌苔照片 1.jpg");
+        assert!(report.page_count.is_none());
+
+        let pdf = dir.path().join("检查报告.pdf");
+        std::fs::write(
+            &pdf,
+            b"%PDF-1.4\n1 0 obj << /Type /Pages /Kids [2 0 R 3 0 R] /Count 2 >> endobj\n\
+              2 0 obj << /Type /Page /Parent 1 0 R >> endobj\n3 0 obj << /Type/Page /Parent 1 0 R >> endobj\n%%EOF",
+        )
+        .unwrap();
+        let report = FileService::new().inspect_upload_candidate(&pdf, &AppConfig::default()).unwrap();
+        assert!(report.accepted, "{:?}", report.violations);
+        assert_eq!(report.page_count, Some(2));
+    }
+
+    #[test]
+    fn test_upload_candidate_renamed_executable() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("化验单.pdf");
+        let mut exe = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff".to_vec();
+        exe.resize(512, 0);
+        std::fs::write(&path, exe).unwrap();
+
+        let report = FileService::new().inspect_upload_candidate(&path, &AppConfig::default()).unwrap();
+        assert!(!report.accepted);
+        assert_ne!(report.mime_type, "application/pdf");
+        assert_eq!(violation_codes(&report), vec![CODE_EXECUTABLE_BLOCKED, CODE_EXTENSION_MISMATCH]);
+
+        // 纯文本改成图片扩展名同样视为不符
+        let fake = dir.path().join("photo.png");
+        std::fs::write(&fake, "not really a png").unwrap();
+        let report = FileService::new().inspect_upload_candidate(&fake, &AppConfig::default()).unwrap();
+        assert!(violation_codes(&report).contains(&CODE_EXTENSION_MISMATCH));
+    }
+
+    #[test]
+    fn test_check_upload_content() {
+        let service = FileService::new();
+        assert!(service.check_upload_content("化验单.jpg", &sample_jpeg_with_exif(64, 64)).is_valid);
+        assert!(service.check_upload_content("备注.txt", "复诊提醒".as_bytes()).is_valid);
+
+        let mut exe = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff".to_vec();
+        exe.resize(512, 0);
+        let result = service.check_upload_content("化验单.pdf", &exe);
+        assert_eq!(result.errors.iter().map(|v| v.code.as_str()).collect::<Vec<_>>(), vec![CODE_EXTENSION_MISMATCH]);
+
+        let result = service.check_upload_content("  ", b"");
+        assert_eq!(result.errors.iter().map(|v| v.code.as_str()).collect::<Vec<_>>(), vec!["REQUIRED"]);
+    }
+
+    #[test]
+    fn test_upload_candidate_oversized() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("large.jpg");
+        std::fs::write(&path, sample_jpeg_with_exif(256, 256)).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        let report = FileService::new().inspect_upload_candidate(&path, &upload_config(size - 1)).unwrap();
+        assert!(!report.accepted);
+        assert_eq!(violation_codes(&report), vec!["FILE_TOO_LARGE"]);
+        assert!(report.width.is_none());
+
+        assert!(FileService::new()
+            .inspect_upload_candidate(&dir.path().join("missing.jpg"), &AppConfig::default())
+            .is_err());
+    }
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(std::sync::Mutex::new(conn))
+    }
+
+    fn download_body() -> Vec<u8> {
+        (0..4096u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    // 模拟上次中断的下载：磁盘和数据库中各有前 1000 字节
+    fn seed_partial(connection: &DbConnection, dir: &Path, url: &str, body: &[u8]) -> PathBuf {
+        let local = dir.join("report.bin");
+        std::fs::write(partial_path(&local), &body[..1000]).unwrap();
+        FileCacheDao::with_connection(connection.clone())
+            .save_download_progress(url, local.to_str().unwrap(), Some(body.len() as u64), 1000, None)
+            .unwrap();
+        local
+    }
+
+    async fn wait_finished(events: &mut mpsc::UnboundedReceiver<DownloadProgress>, id: &str) -> DownloadProgress {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.file_id == id && event.status.is_finished() {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("download did not finish")
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_with_range() {
+        let body = download_body();
+        let mut server = mockito::Server::new_async().await;
+        let ranged = server
+            .mock("GET", "/files/report.bin")
+            .match_header("range", "bytes=1000-")
+            .with_status(206)
+            .with_header("content-range", &format!("bytes 1000-{}/{}", body.len() - 1, body.len()))
+            .with_body(&body[1000..])
+            .create_async()
+            .await;
+
+        let url = format!("{}/files/report.bin", server.url());
+        let dir = tempdir().unwrap();
+        let connection = create_test_connection();
+        let local = seed_partial(&connection, dir.path(), &url, &body);
+        let checksum = to_hex(&Sha256::digest(&body));
+
+        let (manager, mut events) = DownloadManager::with_connection(connection.clone(), dir.path().to_path_buf(), 2);
+        let task = manager
+            .enqueue(&url, DownloadPriority::Normal, Some(checksum.to_uppercase()))
+            .unwrap();
+        assert_eq!(task.local_path, local.to_string_lossy());
+
+        let done = wait_finished(&mut events, &task.id).await;
+        assert_eq!(done.status, DownloadStatus::Completed, "{:?}", done.error);
+        assert_eq!(done.percentage, 100.0);
+        ranged.assert_async().await;
+        assert_eq!(std::fs::read(&local).unwrap(), body);
+        assert!(!partial_path(&local).exists());
+
+        let cache = FileCacheDao::with_connection(connection).find_by_url(&url).unwrap().unwrap();
+        assert_eq!(cache.bytes_downloaded, body.len() as u64);
+        assert_eq!(cache.checksum, Some(checksum));
+    }
+
+    #[tokio::test]
+    async fn test_download_restarts_without_range_support() {
+        let body = download_body();
+        let mut server = mockito::Server::new_async().await;
+        let full = server
+            .mock("GET", "/files/report.bin")
+            .with_status(200)
+            .with_body(&body)
+            .create_async()
+            .await;
+
+        let url = format!("{}/files/report.bin", server.url());
+        let dir = tempdir().unwrap();
+        let connection = create_test_connection();
+        let local = seed_partial(&connection, dir.path(), &url, &body);
+
+        let (manager, mut events) = DownloadManager::with_connection(connection, dir.path().to_path_buf(), 2);
+        let task = manager.enqueue(&url, DownloadPriority::High, None).unwrap();
+        let done = wait_finished(&mut events, &task.id).await;
+        assert_eq!(done.status, DownloadStatus::Completed, "{:?}", done.error);
+        full.assert_async().await;
+
+        // 服务器返回完整内容时不能追加到已有的部分后面
+        assert_eq!(std::fs::read(&local).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_download_checksum_mismatch_fails() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/files/scan.pdf")
+            .with_status(200)
+            .with_body(download_body())
+            .create_async()
+            .await;
+
+        let url = format!("{}/files/scan.pdf", server.url());
+        let dir = tempdir().unwrap();
+        let connection = create_test_connection();
+        let (manager, mut events) = DownloadManager::with_connection(connection.clone(), dir.path().to_path_buf(), 2);
+        let task = manager.enqueue(&url, DownloadPriority::Normal, Some("0".repeat(64))).unwrap();
+
+        let done = wait_finished(&mut events, &task.id).await;
+        assert_eq!(done.status, DownloadStatus::Failed);
+        assert!(done.error.unwrap().contains("校验失败"));
+        assert!(!Path::new(&task.local_path).exists());
+        assert!(!partial_path(Path::new(&task.local_path)).exists());
+
+        let cache = FileCacheDao::with_connection(connection).find_by_url(&url).unwrap().unwrap();
+        assert_eq!(cache.bytes_downloaded, 0);
+        assert!(cache.checksum.is_none());
+
+        assert!(manager.enqueue("ftp://example.com/a.pdf", DownloadPriority::Low, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_queue_priority_and_concurrency() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", mockito::Matcher::Regex(r"^/files/".to_string()))
+            .with_status(200)
+            .with_body(download_body())
+            .expect_at_least(3)
+            .create_async()
+            .await;
+
+        let dir = tempdir().unwrap();
+        let (manager, mut events) = DownloadManager::with_connection(create_test_connection(), dir.path().to_path_buf(), 1);
+        let url = |name: &str| format!("{}/files/{}", server.url(), name);
+        let a = manager.enqueue(&url("a.bin"), DownloadPriority::Low, None).unwrap();
+        let b = manager.enqueue(&url("b.bin"), DownloadPriority::Low, None).unwrap();
+        let c = manager.enqueue(&url("c.bin"), DownloadPriority::High, None).unwrap();
+        let d = manager.enqueue(&url("d.bin"), DownloadPriority::Normal, None).unwrap();
+
+        // 同一地址重复加入只提升优先级
+        let again = manager.enqueue(&url("b.bin"), DownloadPriority::Normal, None).unwrap();
+        assert_eq!(again.id, b.id);
+        assert_eq!(again.priority, DownloadPriority::Normal);
+
+        let queue = manager.queue();
+        assert_eq!(queue.iter().filter(|t| t.status == DownloadStatus::Downloading).count(), 1);
+        assert_eq!(queue.iter().find(|t| t.id == a.id).unwrap().status, DownloadStatus::Downloading);
+
+        assert!(manager.cancel(&d.id));
+        assert!(!manager.cancel(&d.id));
+
+        // 并发上限为 1 时按优先级依次完成
+        let mut completed = Vec::new();
+        while completed.len() < 3 {
+            let event = tokio::time::timeout(Duration::from_secs(10), events.recv()).await.unwrap().unwrap();
+            if event.status == DownloadStatus::Completed {
+                completed.push(event.file_id);
+            }
+        }
+        assert_eq!(completed, vec![a.id, c.id, b.id]);
+        assert_eq!(manager.queue().iter().find(|t| t.id == d.id).unwrap().status, DownloadStatus::Cancelled);
+    }
+}
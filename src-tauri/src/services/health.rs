@@ -0,0 +1,509 @@
+// 应用健康检查：汇总数据库、WebSocket、磁盘、缓存、同步等子系统状态
+
+use crate::database::dao::{FileCacheDao, MessageDao};
+use crate::database::connection::DbConnection;
+use crate::database::DatabaseManager;
+use crate::services::{
+    ConnectionStatus, ConsultationEventDispatcher, SyncScheduler, WebSocketManager, CONNECTION_QUEUE_KEY,
+};
+use crate::utils::{AppError, CODE_DB_KEY_INVALID};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+// 单个探针的最长执行时间，超时视为失败，不拖住整份报告
+pub const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+// WAL 文件过大说明检查点长期未执行
+const WAL_DEGRADED_BYTES: u64 = 64 * BYTES_PER_MB;
+const DISK_DEGRADED_BYTES: u64 = 1024 * BYTES_PER_MB;
+const DISK_FAILING_BYTES: u64 = 200 * BYTES_PER_MB;
+// 超过该时长没有成功同步提示降级
+const SYNC_STALE_HOURS: i64 = 24;
+const PENDING_MESSAGES_DEGRADED: i64 = 100;
+// 单个事件队列达到容量的该比例时提示降级
+const EVENT_QUEUE_DEGRADED_PERCENT: usize = 75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Degraded,
+    Failing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl SubsystemHealth {
+    pub fn new(name: &str, status: HealthStatus, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppHealthReport {
+    pub status: HealthStatus,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "checkedAt")]
+    pub checked_at: DateTime<Utc>,
+    pub subsystems: Vec<SubsystemHealth>,
+}
+
+/// 单个子系统的健康探针，返回 Err 时由汇总逻辑记为 failing
+#[async_trait]
+pub trait HealthProbe: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn check(&self) -> Result<SubsystemHealth>;
+}
+
+// 各探针并发执行，出错、超时或 panic 都只影响自身条目
+pub async fn collect_health(
+    app_version: &str,
+    probes: Vec<Box<dyn HealthProbe>>,
+    probe_timeout: Duration,
+) -> AppHealthReport {
+    let handles: Vec<_> = probes
+        .into_iter()
+        .map(|probe| {
+            let name = probe.name();
+            let handle = tokio::spawn(async move { tokio::time::timeout(probe_timeout, probe.check()).await });
+            (name, handle)
+        })
+        .collect();
+
+    let mut subsystems = Vec::with_capacity(handles.len());
+    for (name, handle) in handles {
+        let health = match handle.await {
+            Ok(Ok(Ok(health))) => health,
+            Ok(Ok(Err(e))) => SubsystemHealth::new(name, HealthStatus::Failing, e.to_string()),
+            Ok(Err(_)) => SubsystemHealth::new(
+                name,
+                HealthStatus::Failing,
+                format!("检查超时（{} 秒）", probe_timeout.as_secs_f32()),
+            ),
+            Err(e) => SubsystemHealth::new(name, HealthStatus::Failing, format!("检查异常终止: {}", e)),
+        };
+        if health.status != HealthStatus::Ok {
+            tracing::warn!("Health probe {} reported {:?}: {}", name, health.status, health.message);
+        }
+        subsystems.push(health);
+    }
+
+    AppHealthReport {
+        status: subsystems.iter().map(|s| s.status).max().unwrap_or(HealthStatus::Ok),
+        app_version: app_version.to_string(),
+        checked_at: Utc::now(),
+        subsystems,
+    }
+}
+
+fn file_len(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn require_connection(connection: &Option<DbConnection>) -> Result<DbConnection> {
+    connection.clone().ok_or_else(|| anyhow!("数据库未初始化"))
+}
+
+pub struct DatabaseProbe {
+    pub manager: Option<&'static DatabaseManager>,
+}
+
+#[async_trait]
+impl HealthProbe for DatabaseProbe {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let manager = self.manager.ok_or_else(|| anyhow!("数据库未初始化"))?;
+        // 密钥错误单独提示，不归为普通的查询失败
+        let healthy = manager.health_check().map_err(|e| match e.downcast_ref::<AppError>() {
+            Some(error) if error.code.as_deref() == Some(CODE_DB_KEY_INVALID) => anyhow!("{}", error.message),
+            _ => anyhow!("数据库查询失败: {}", e),
+        })?;
+        if !healthy {
+            return Err(anyhow!("数据库健康检查未通过"));
+        }
+
+        let db_path = manager.get_db_path();
+        let mut wal_path = db_path.clone().into_os_string();
+        wal_path.push("-wal");
+        let file_size = file_len(db_path);
+        let wal_size = file_len(Path::new(&wal_path));
+
+        let (status, message) = if wal_size > WAL_DEGRADED_BYTES {
+            (HealthStatus::Degraded, format!("WAL 文件过大（{} MB）", wal_size / BYTES_PER_MB))
+        } else {
+            (HealthStatus::Ok, "数据库正常".to_string())
+        };
+        Ok(SubsystemHealth::new(self.name(), status, message)
+            .with_details(json!({
+                "fileSizeBytes": file_size,
+                "walSizeBytes": wal_size,
+                "encrypted": manager.is_encrypted(),
+            })))
+    }
+}
+
+pub struct WebSocketProbe {
+    pub manager: Arc<Mutex<WebSocketManager>>,
+}
+
+pub fn websocket_health(statuses: &[(String, ConnectionStatus)]) -> (HealthStatus, String) {
+    if statuses.is_empty() {
+        return (HealthStatus::Ok, "无活动连接".to_string());
+    }
+    let unhealthy = statuses
+        .iter()
+        .filter(|(_, status)| !matches!(status, ConnectionStatus::Connected | ConnectionStatus::Connecting))
+        .count();
+    if unhealthy == 0 {
+        (HealthStatus::Ok, format!("{} 个连接正常", statuses.len()))
+    } else if unhealthy == statuses.len() {
+        (HealthStatus::Failing, "所有连接均已断开".to_string())
+    } else {
+        (HealthStatus::Degraded, format!("{}/{} 个连接异常", unhealthy, statuses.len()))
+    }
+}
+
+fn connection_label(status: &ConnectionStatus) -> String {
+    match status {
+        ConnectionStatus::Disconnected => "disconnected".to_string(),
+        ConnectionStatus::Connecting => "connecting".to_string(),
+        ConnectionStatus::Connected => "connected".to_string(),
+        ConnectionStatus::Reconnecting => "reconnecting".to_string(),
+        ConnectionStatus::Error(e) => format!("error: {}", e),
+    }
+}
+
+#[async_trait]
+impl HealthProbe for WebSocketProbe {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let mut statuses: Vec<_> = self.manager.lock().await.get_all_connection_status().await.into_iter().collect();
+        statuses.sort_by(|a, b| a.0.cmp(&b.0));
+        let (status, message) = websocket_health(&statuses);
+        let connections: serde_json::Map<_, _> = statuses
+            .into_iter()
+            .map(|(id, status)| (id, json!(connection_label(&status))))
+            .collect();
+        Ok(SubsystemHealth::new(self.name(), status, message).with_details(json!({ "connections": connections })))
+    }
+}
+
+pub struct DiskProbe {
+    pub data_dir: Option<PathBuf>,
+}
+
+pub fn disk_health(available_bytes: u64) -> HealthStatus {
+    if available_bytes < DISK_FAILING_BYTES {
+        HealthStatus::Failing
+    } else if available_bytes < DISK_DEGRADED_BYTES {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Ok
+    }
+}
+
+#[async_trait]
+impl HealthProbe for DiskProbe {
+    fn name(&self) -> &'static str {
+        "disk"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let data_dir = self.data_dir.as_ref().ok_or_else(|| anyhow!("无法获取应用数据目录"))?;
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+        // 取挂载点最长的匹配项，即数据目录实际所在的磁盘
+        let disk = disks
+            .list()
+            .iter()
+            .filter(|disk| data_dir.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .ok_or_else(|| anyhow!("未找到数据目录所在磁盘"))?;
+
+        let available = disk.available_space();
+        let status = disk_health(available);
+        Ok(SubsystemHealth::new(self.name(), status, format!("剩余 {} MB", available / BYTES_PER_MB))
+            .with_details(json!({ "availableBytes": available, "totalBytes": disk.total_space() })))
+    }
+}
+
+pub struct CacheProbe {
+    pub connection: Option<DbConnection>,
+    pub limit_bytes: i64,
+}
+
+#[async_trait]
+impl HealthProbe for CacheProbe {
+    fn name(&self) -> &'static str {
+        "cache"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let dao = FileCacheDao::with_connection(require_connection(&self.connection)?);
+        let size = dao.get_cache_size().map_err(|e| anyhow!(e.to_string()))?;
+        let (status, message) = if size > self.limit_bytes {
+            (HealthStatus::Degraded, "缓存已超出上限，建议清理".to_string())
+        } else {
+            (HealthStatus::Ok, format!("已使用 {} MB", size as u64 / BYTES_PER_MB))
+        };
+        Ok(SubsystemHealth::new(self.name(), status, message)
+            .with_details(json!({ "sizeBytes": size, "limitBytes": self.limit_bytes })))
+    }
+}
+
+pub struct SyncProbe {
+    pub scheduler: Arc<SyncScheduler>,
+}
+
+pub fn sync_health(
+    last_sync_at: Option<DateTime<Utc>>,
+    last_error: Option<&str>,
+    now: DateTime<Utc>,
+) -> (HealthStatus, String) {
+    if let Some(error) = last_error {
+        return (HealthStatus::Degraded, format!("最近一次同步失败: {}", error));
+    }
+    match last_sync_at {
+        None => (HealthStatus::Degraded, "尚未成功同步".to_string()),
+        Some(at) if now - at > ChronoDuration::hours(SYNC_STALE_HOURS) => {
+            (HealthStatus::Degraded, format!("超过 {} 小时未同步", SYNC_STALE_HOURS))
+        }
+        Some(_) => (HealthStatus::Ok, "同步正常".to_string()),
+    }
+}
+
+#[async_trait]
+impl HealthProbe for SyncProbe {
+    fn name(&self) -> &'static str {
+        "sync"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let status = self.scheduler.status();
+        let (health, message) = sync_health(status.last_sync_at, status.last_error.as_deref(), Utc::now());
+        Ok(SubsystemHealth::new(self.name(), health, message)
+            .with_details(json!({ "lastSyncAt": status.last_sync_at, "online": status.online })))
+    }
+}
+
+pub struct PendingMessagesProbe {
+    pub connection: Option<DbConnection>,
+}
+
+#[async_trait]
+impl HealthProbe for PendingMessagesProbe {
+    fn name(&self) -> &'static str {
+        "pendingMessages"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let dao = MessageDao::with_connection(require_connection(&self.connection)?);
+        let pending = dao.count_unsynced_messages().map_err(|e| anyhow!(e))?;
+        let status = if pending > PENDING_MESSAGES_DEGRADED {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Ok
+        };
+        Ok(SubsystemHealth::new(self.name(), status, format!("{} 条消息待同步", pending))
+            .with_details(json!({ "count": pending })))
+    }
+}
+
+pub struct EventQueueProbe {
+    pub dispatcher: Arc<ConsultationEventDispatcher>,
+}
+
+pub fn event_queue_health(depths: &[(String, usize)], capacity: usize) -> (HealthStatus, String) {
+    let total: usize = depths.iter().map(|(_, depth)| depth).sum();
+    if total == 0 {
+        return (HealthStatus::Ok, "无积压事件".to_string());
+    }
+    let max = depths.iter().map(|(_, depth)| *depth).max().unwrap_or(0);
+    let status = if max * 100 >= capacity * EVENT_QUEUE_DEGRADED_PERCENT {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Ok
+    };
+    (status, format!("{} 个队列共 {} 条事件待处理", depths.len(), total))
+}
+
+#[async_trait]
+impl HealthProbe for EventQueueProbe {
+    fn name(&self) -> &'static str {
+        "eventQueues"
+    }
+
+    async fn check(&self) -> Result<SubsystemHealth> {
+        let depths = self.dispatcher.queue_depths();
+        let (status, message) = event_queue_health(&depths, self.dispatcher.capacity());
+        let queues: serde_json::Map<_, _> = depths
+            .iter()
+            .map(|(key, depth)| {
+                let label = if key == CONNECTION_QUEUE_KEY { "connection" } else { key.as_str() };
+                (label.to_string(), json!(depth))
+            })
+            .collect();
+        Ok(SubsystemHealth::new(self.name(), status, message).with_details(json!({
+            "queues": queues,
+            "capacity": self.dispatcher.capacity(),
+            "droppedEvents": self.dispatcher.dropped_events(),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedProbe(&'static str, HealthStatus);
+
+    #[async_trait]
+    impl HealthProbe for FixedProbe {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn check(&self) -> Result<SubsystemHealth> {
+            Ok(SubsystemHealth::new(self.0, self.1, "fixed"))
+        }
+    }
+
+    struct FailingProbe;
+
+    #[async_trait]
+    impl HealthProbe for FailingProbe {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn check(&self) -> Result<SubsystemHealth> {
+            Err(anyhow!("probe exploded"))
+        }
+    }
+
+    struct SlowProbe;
+
+    #[async_trait]
+    impl HealthProbe for SlowProbe {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        async fn check(&self) -> Result<SubsystemHealth> {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            Ok(SubsystemHealth::new("slow", HealthStatus::Ok, "late"))
+        }
+    }
+
+    struct PanickingProbe;
+
+    #[async_trait]
+    impl HealthProbe for PanickingProbe {
+        fn name(&self) -> &'static str {
+            "panicking"
+        }
+
+        async fn check(&self) -> Result<SubsystemHealth> {
+            panic!("probe panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_probes_do_not_abort_report() {
+        let probes: Vec<Box<dyn HealthProbe>> = vec![
+            Box::new(FixedProbe("database", HealthStatus::Ok)),
+            Box::new(FailingProbe),
+            Box::new(SlowProbe),
+            Box::new(PanickingProbe),
+            Box::new(FixedProbe("cache", HealthStatus::Degraded)),
+        ];
+
+        let report = collect_health("1.2.3", probes, Duration::from_millis(50)).await;
+
+        assert_eq!(report.app_version, "1.2.3");
+        assert_eq!(report.status, HealthStatus::Failing);
+        let names: Vec<_> = report.subsystems.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["database", "failing", "slow", "panicking", "cache"]);
+        assert_eq!(report.subsystems[0].status, HealthStatus::Ok);
+        assert_eq!(report.subsystems[1].status, HealthStatus::Failing);
+        assert!(report.subsystems[1].message.contains("probe exploded"));
+        assert_eq!(report.subsystems[2].status, HealthStatus::Failing);
+        assert_eq!(report.subsystems[3].status, HealthStatus::Failing);
+        assert_eq!(report.subsystems[4].status, HealthStatus::Degraded);
+    }
+
+    #[tokio::test]
+    async fn test_overall_status_is_worst_subsystem() {
+        let probes: Vec<Box<dyn HealthProbe>> = vec![
+            Box::new(FixedProbe("a", HealthStatus::Ok)),
+            Box::new(FixedProbe("b", HealthStatus::Degraded)),
+        ];
+        let report = collect_health("1.0.0", probes, HEALTH_PROBE_TIMEOUT).await;
+        assert_eq!(report.status, HealthStatus::Degraded);
+
+        let report = collect_health("1.0.0", Vec::new(), HEALTH_PROBE_TIMEOUT).await;
+        assert_eq!(report.status, HealthStatus::Ok);
+
+        // 未初始化数据库时相关探针失败而不是 panic
+        let probes: Vec<Box<dyn HealthProbe>> = vec![
+            Box::new(DatabaseProbe { manager: None }),
+            Box::new(PendingMessagesProbe { connection: None }),
+        ];
+        let report = collect_health("1.0.0", probes, HEALTH_PROBE_TIMEOUT).await;
+        assert!(report.subsystems.iter().all(|s| s.status == HealthStatus::Failing));
+    }
+
+    #[test]
+    fn test_subsystem_thresholds() {
+        let now = Utc::now();
+        assert_eq!(sync_health(Some(now), None, now).0, HealthStatus::Ok);
+        assert_eq!(sync_health(None, None, now).0, HealthStatus::Degraded);
+        assert_eq!(sync_health(Some(now - ChronoDuration::hours(25)), None, now).0, HealthStatus::Degraded);
+        assert_eq!(sync_health(Some(now), Some("timeout"), now).0, HealthStatus::Degraded);
+
+        assert_eq!(disk_health(10 * DISK_DEGRADED_BYTES), HealthStatus::Ok);
+        assert_eq!(disk_health(DISK_FAILING_BYTES + 1), HealthStatus::Degraded);
+        assert_eq!(disk_health(0), HealthStatus::Failing);
+
+        let connected = ("a".to_string(), ConnectionStatus::Connected);
+        let broken = ("b".to_string(), ConnectionStatus::Error("refused".to_string()));
+        assert_eq!(websocket_health(&[]).0, HealthStatus::Ok);
+        assert_eq!(websocket_health(&[connected.clone(), broken.clone()]).0, HealthStatus::Degraded);
+        assert_eq!(websocket_health(&[broken]).0, HealthStatus::Failing);
+
+        assert_eq!(event_queue_health(&[], 256).0, HealthStatus::Ok);
+        assert_eq!(event_queue_health(&[("c1".to_string(), 10), ("c2".to_string(), 3)], 256).0, HealthStatus::Ok);
+        assert_eq!(event_queue_health(&[("c1".to_string(), 192)], 256).0, HealthStatus::Degraded);
+    }
+}
@@ -0,0 +1,376 @@
+// 启动完整性修复：崩溃后可能残留引用已不存在记录的数据，列表查询的关联假设会因此出错
+// 按策略表删除、置空引用或隔离孤立记录，结果写入 maintenance_runs 并记录审计日志
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{FileCacheDao, RetentionDao};
+use crate::database::integrity::repair_orphans_in;
+use crate::database::query_optimizer::{query_cache_for, CACHE_TAG_MESSAGES};
+use crate::models::{
+    IntegrityRepairReport, MaintenanceKind, MaintenanceRun, MaintenanceTrigger, OrphanKind, OrphanRepair, RepairAction,
+};
+use crate::services::security::{AuditAction, SecurityService};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+// 进程运行期间存在的标记文件，启动时仍存在说明上次没有正常退出
+pub const RUNNING_SENTINEL_FILE: &str = "running.lock";
+
+// 各类孤立记录的处理方式，按顺序执行：引用孤立问诊的病历和消息要在问诊被移走前处理，
+// 否则外键的 SET NULL / CASCADE 会在不留记录的情况下生效
+pub const ORPHAN_REPAIR_POLICY: [(OrphanKind, RepairAction); 4] = [
+    (OrphanKind::MedicalRecordMissingConsultation, RepairAction::Nullify),
+    (OrphanKind::MessageMissingConsultation, RepairAction::Quarantine),
+    (OrphanKind::ConsultationMissingPatient, RepairAction::Quarantine),
+    // 缓存文件可以重新下载
+    (OrphanKind::FileCacheMissingFile, RepairAction::Delete),
+];
+
+pub struct IntegrityRepairService {
+    connection: DbConnection,
+    security: Arc<Mutex<SecurityService>>,
+}
+
+impl IntegrityRepairService {
+    pub fn new(security: Arc<Mutex<SecurityService>>) -> Self {
+        Self::with_connection(get_database().get_connection(), security)
+    }
+
+    pub fn with_connection(connection: DbConnection, security: Arc<Mutex<SecurityService>>) -> Self {
+        Self { connection, security }
+    }
+
+    // 修复在同一事务中完成，失败时整体回滚，同样写入维护记录和审计日志
+    pub async fn run(&self, trigger: MaintenanceTrigger) -> Result<IntegrityRepairReport> {
+        let started_at = Utc::now();
+        let mut report = IntegrityRepairReport {
+            run_id: Uuid::new_v4().to_string(),
+            triggered_by: trigger,
+            repairs: Vec::new(),
+        };
+
+        let outcome = self.repair();
+        match &outcome {
+            Ok(repairs) => report.repairs = repairs.clone(),
+            Err(e) => tracing::error!("Integrity repair failed: {}", e),
+        }
+
+        let run = MaintenanceRun {
+            id: report.run_id.clone(),
+            kind: MaintenanceKind::IntegrityRepair,
+            triggered_by: trigger,
+            started_at,
+            finished_at: Utc::now(),
+            deleted_messages: 0,
+            deleted_audit_logs: 0,
+            deleted_anomaly_records: 0,
+            deleted_cache_files: 0,
+            deleted_backups: 0,
+            purged_trash: 0,
+            freed_bytes: 0,
+            vacuumed: false,
+            status: if outcome.is_ok() { "success" } else { "failed" }.to_string(),
+            error_message: outcome.as_ref().err().map(|e| e.to_string()),
+            summary: Some(serde_json::to_value(&report.repairs)?),
+        };
+        RetentionDao::with_connection(self.connection.clone())
+            .record_run(&run)
+            .map_err(|e| anyhow!(e.to_string()))?;
+        self.audit(&run, &report).await;
+        outcome?;
+
+        tracing::info!("Integrity repair finished ({}): {:?}", trigger.as_str(), report.repairs);
+        Ok(report)
+    }
+
+    fn repair(&self) -> Result<Vec<OrphanRepair>> {
+        let (repairs, evicted) = {
+            let conn = self.connection.lock().unwrap();
+            let tx = conn.unchecked_transaction()?;
+            let mut repairs = Vec::with_capacity(ORPHAN_REPAIR_POLICY.len());
+            let mut evicted = Vec::new();
+            for (kind, action) in ORPHAN_REPAIR_POLICY {
+                let count = match kind {
+                    OrphanKind::FileCacheMissingFile => {
+                        evicted = FileCacheDao::delete_missing_files_in(&tx).map_err(|e| anyhow!(e.to_string()))?;
+                        evicted.len()
+                    }
+                    _ => repair_orphans_in(&tx, kind, action).map_err(|e| anyhow!(e.to_string()))?,
+                };
+                repairs.push(OrphanRepair { kind, action, count });
+            }
+            tx.commit()?;
+            (repairs, evicted)
+        };
+
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+        // 原文件已不存在，只清理残留的缩略图和预览
+        let leftovers = evicted
+            .into_iter()
+            .flat_map(|(_, thumbnail_path, preview_path)| thumbnail_path.into_iter().chain(preview_path));
+        for path in leftovers {
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::debug!("Failed to remove leftover cache file {}: {}", path, e);
+            }
+        }
+        Ok(repairs)
+    }
+
+    async fn audit(&self, run: &MaintenanceRun, report: &IntegrityRepairReport) {
+        let mut metadata = HashMap::new();
+        metadata.insert("operation".to_string(), "integrity_repair".to_string());
+        metadata.insert("trigger".to_string(), run.triggered_by.as_str().to_string());
+        for repair in &report.repairs {
+            metadata.insert(repair.kind.as_str().to_string(), repair.count.to_string());
+        }
+
+        if let Err(e) = self
+            .security
+            .lock()
+            .await
+            .log_audit(
+                "system".to_string(),
+                AuditAction::DeleteData,
+                Some("database".to_string()),
+                Some(run.id.clone()),
+                run.status.clone(),
+                run.error_message.clone(),
+                metadata,
+            )
+            .await
+        {
+            tracing::error!("Failed to record audit log for integrity repair: {}", e);
+        }
+    }
+}
+
+// 启动时写入运行标记，返回上次是否未正常退出
+pub fn mark_running(app_dir: &Path) -> bool {
+    let path = app_dir.join(RUNNING_SENTINEL_FILE);
+    let unclean = path.exists();
+    let written = std::fs::create_dir_all(app_dir).and_then(|_| std::fs::write(&path, std::process::id().to_string()));
+    if let Err(e) = written {
+        tracing::warn!("Failed to write running sentinel {:?}: {}", path, e);
+    }
+    unclean
+}
+
+// 正常退出时删除运行标记
+pub fn mark_clean_exit(app_dir: &Path) {
+    let path = app_dir.join(RUNNING_SENTINEL_FILE);
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove running sentinel {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use rusqlite::{params, Connection};
+    use tempfile::tempdir;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content) VALUES
+                 ('m1', 'c1', 'doctor', 'text', '您好');
+             INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title) VALUES
+                 ('r1', 'p1', 'd1', 'c1', 'diagnosis', '上呼吸道感染');",
+        )
+        .unwrap();
+        Arc::new(std::sync::Mutex::new(conn))
+    }
+
+    // 崩溃或旧版本在外键关闭时写入的数据
+    fn without_foreign_keys(connection: &DbConnection, sql: &str) {
+        let conn = connection.lock().unwrap();
+        conn.execute_batch(&format!("PRAGMA foreign_keys = OFF; {} PRAGMA foreign_keys = ON;", sql))
+            .unwrap();
+    }
+
+    fn service(connection: &DbConnection) -> (IntegrityRepairService, Arc<Mutex<SecurityService>>) {
+        let security = Arc::new(Mutex::new(SecurityService::new(300)));
+        (IntegrityRepairService::with_connection(connection.clone(), security.clone()), security)
+    }
+
+    fn count(connection: &DbConnection, sql: &str) -> i64 {
+        connection.lock().unwrap().query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn repaired(report: &IntegrityRepairReport, kind: OrphanKind) -> usize {
+        report.repairs.iter().find(|repair| repair.kind == kind).unwrap().count
+    }
+
+    #[tokio::test]
+    async fn test_clean_database_is_untouched() {
+        let connection = create_test_connection();
+        let (service, _) = service(&connection);
+
+        let report = service.run(MaintenanceTrigger::Manual).await.unwrap();
+        assert_eq!(report.total(), 0);
+        assert_eq!(report.repairs.len(), ORPHAN_REPAIR_POLICY.len());
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM messages"), 1);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM medical_records WHERE consultation_id = 'c1'"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_message_without_consultation_quarantined() {
+        let connection = create_test_connection();
+        without_foreign_keys(
+            &connection,
+            "INSERT INTO messages (id, consultation_id, sender_type, message_type, content)
+                 VALUES ('m-orphan', 'c-missing', 'patient', 'text', '还在吗');",
+        );
+        let (service, _) = service(&connection);
+
+        let report = service.run(MaintenanceTrigger::Manual).await.unwrap();
+        assert_eq!(repaired(&report, OrphanKind::MessageMissingConsultation), 1);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM messages WHERE id = 'm-orphan'"), 0);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM messages"), 1);
+
+        let (reason, content): (String, String) = connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT reason, json_extract(row_data, '$.content') FROM messages_orphaned WHERE id = 'm-orphan'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(reason, "message_missing_consultation");
+        assert_eq!(content, "还在吗");
+    }
+
+    #[tokio::test]
+    async fn test_consultation_without_patient_quarantined_with_its_messages() {
+        let connection = create_test_connection();
+        without_foreign_keys(
+            &connection,
+            "INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c2', 'p-missing', 'd1', 'completed');
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content)
+                 VALUES ('m2', 'c2', 'doctor', 'text', '复诊提醒');
+             INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title)
+                 VALUES ('r2', 'p1', 'd1', 'c2', 'treatment', '随访');",
+        );
+        let (service, _) = service(&connection);
+
+        let report = service.run(MaintenanceTrigger::Manual).await.unwrap();
+        assert_eq!(repaired(&report, OrphanKind::ConsultationMissingPatient), 1);
+        // 问诊移走前先隔离其消息，不会被级联删除
+        assert_eq!(repaired(&report, OrphanKind::MessageMissingConsultation), 1);
+        assert_eq!(repaired(&report, OrphanKind::MedicalRecordMissingConsultation), 1);
+
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM consultations_orphaned WHERE id = 'c2'"), 1);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM messages_orphaned WHERE id = 'm2'"), 1);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM consultations"), 1);
+        assert_eq!(
+            count(&connection, "SELECT COUNT(*) FROM medical_records WHERE id = 'r2' AND consultation_id IS NULL"),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_medical_record_reference_nulled() {
+        let connection = create_test_connection();
+        without_foreign_keys(
+            &connection,
+            "INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title)
+                 VALUES ('r3', 'p1', 'd1', 'c-missing', 'examination', '血常规');",
+        );
+        let (service, _) = service(&connection);
+
+        let report = service.run(MaintenanceTrigger::UncleanShutdown).await.unwrap();
+        assert_eq!(repaired(&report, OrphanKind::MedicalRecordMissingConsultation), 1);
+        // 病历本身保留
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM medical_records"), 2);
+        assert_eq!(
+            count(&connection, "SELECT COUNT(*) FROM medical_records WHERE id = 'r3' AND consultation_id IS NULL"),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_rows_for_missing_files_deleted() {
+        let connection = create_test_connection();
+        let dir = tempdir().unwrap();
+        let present = dir.path().join("present.png");
+        let thumbnail = dir.path().join("gone_thumb.png");
+        std::fs::write(&present, b"png").unwrap();
+        std::fs::write(&thumbnail, b"thumb").unwrap();
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO file_cache (id, file_url, local_path, thumbnail_path) VALUES
+                     ('f1', 'https://files.example.com/present', ?1, NULL),
+                     ('f2', 'https://files.example.com/gone', ?2, ?3)",
+                params![
+                    present.to_string_lossy(),
+                    dir.path().join("gone.png").to_string_lossy(),
+                    thumbnail.to_string_lossy()
+                ],
+            )
+            .unwrap();
+        let (service, _) = service(&connection);
+
+        let report = service.run(MaintenanceTrigger::Manual).await.unwrap();
+        assert_eq!(repaired(&report, OrphanKind::FileCacheMissingFile), 1);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM file_cache"), 1);
+        assert!(present.exists());
+        assert!(!thumbnail.exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_recorded_in_history_and_audited() {
+        let connection = create_test_connection();
+        without_foreign_keys(
+            &connection,
+            "INSERT INTO messages (id, consultation_id, sender_type, message_type, content)
+                 VALUES ('m-orphan', 'c-missing', 'patient', 'text', '还在吗');",
+        );
+        let (service, security) = service(&connection);
+
+        let report = service.run(MaintenanceTrigger::UncleanShutdown).await.unwrap();
+
+        let runs = RetentionDao::with_connection(connection.clone()).find_recent_runs(10).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, report.run_id);
+        assert_eq!(runs[0].kind, MaintenanceKind::IntegrityRepair);
+        assert_eq!(runs[0].triggered_by, MaintenanceTrigger::UncleanShutdown);
+        let summary: Vec<OrphanRepair> = serde_json::from_value(runs[0].summary.clone().unwrap()).unwrap();
+        assert_eq!(summary, report.repairs);
+
+        let logs = security
+            .lock()
+            .await
+            .get_audit_logs(Some("system".to_string()), None, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(matches!(logs[0].action, AuditAction::DeleteData));
+        assert_eq!(logs[0].resource_id.as_deref(), Some(report.run_id.as_str()));
+        assert_eq!(logs[0].metadata.get("message_missing_consultation").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn test_sentinel_detects_unclean_shutdown() {
+        let dir = tempdir().unwrap();
+        assert!(!mark_running(dir.path()));
+        // 未调用 mark_clean_exit 即再次启动
+        assert!(mark_running(dir.path()));
+
+        mark_clean_exit(dir.path());
+        assert!(!mark_running(dir.path()));
+    }
+}
@@ -0,0 +1,282 @@
+// 病历服务：附件校验及缓存文件固定
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::file_cache_dao::{FileCacheDao, PIN_REASON_MEDICAL_RECORD};
+use crate::database::dao::{BaseDao, MedicalRecordDao};
+use crate::models::MedicalRecord;
+use crate::utils::ValidationService;
+use anyhow::{anyhow, Result};
+
+pub struct MedicalRecordService {
+    record_dao: MedicalRecordDao,
+    file_cache_dao: FileCacheDao,
+}
+
+impl MedicalRecordService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            record_dao: MedicalRecordDao::with_connection(connection.clone()),
+            file_cache_dao: FileCacheDao::with_connection(connection),
+        }
+    }
+
+    pub async fn create_medical_record(&self, record: MedicalRecord) -> Result<MedicalRecord> {
+        self.validate(&record)?;
+
+        let id = self.record_dao.create(&record).map_err(dao_error)?;
+        self.pin_attachments(&id, &record)?;
+
+        self.load(&id)
+    }
+
+    pub async fn update_medical_record(&self, record: MedicalRecord) -> Result<MedicalRecord> {
+        self.validate(&record)?;
+
+        self.load(&record.id)?;
+        self.record_dao.update(&record).map_err(dao_error)?;
+
+        // 移除的旧附件解除本病历的固定，其他病历的固定不受影响
+        self.file_cache_dao
+            .unpin_ref(PIN_REASON_MEDICAL_RECORD, &record.id)
+            .map_err(dao_error)?;
+        self.pin_attachments(&record.id, &record)?;
+
+        self.load(&record.id)
+    }
+
+    // 病历移入回收站，附件保持固定以便恢复，彻底清除时再解除
+    pub async fn delete_medical_record(&self, record_id: &str) -> Result<()> {
+        self.load(record_id)?;
+        self.record_dao.delete(record_id).map_err(dao_error)
+    }
+
+    fn validate(&self, record: &MedicalRecord) -> Result<()> {
+        let validation = ValidationService::validate_medical_record(record);
+        if !validation.is_valid {
+            let messages: Vec<String> = validation.errors.iter().map(|e| e.message.clone()).collect();
+            return Err(anyhow!(messages.join("; ")));
+        }
+
+        // 附件必须对应已缓存的文件，否则缓存清理后会无法打开
+        let mut missing = Vec::new();
+        for attachment in &record.attachments {
+            if self
+                .file_cache_dao
+                .find_by_id(&attachment.file_id)
+                .map_err(dao_error)?
+                .is_none()
+            {
+                missing.push(attachment.file_id.clone());
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow!("附件文件不存在: {}", missing.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    fn pin_attachments(&self, record_id: &str, record: &MedicalRecord) -> Result<()> {
+        for attachment in &record.attachments {
+            self.file_cache_dao
+                .pin_ref(&attachment.file_id, PIN_REASON_MEDICAL_RECORD, record_id)
+                .map_err(dao_error)?;
+        }
+        Ok(())
+    }
+
+    fn load(&self, record_id: &str) -> Result<MedicalRecord> {
+        self.record_dao
+            .find_by_id(record_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| anyhow!("病历不存在: {}", record_id))
+    }
+}
+
+impl Default for MedicalRecordService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::PatientDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Attachment, FileCache, Patient};
+    use chrono::{Duration, Utc};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+
+        let connection = Arc::new(Mutex::new(conn));
+        let now = Utc::now();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "张三".to_string(),
+                age: Some(35),
+                gender: None,
+                phone: None,
+                id_card: None,
+                tags: vec![],
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+        connection
+    }
+
+    fn cache_file(connection: &DbConnection, url: &str) -> String {
+        let now = Utc::now();
+        FileCacheDao::with_connection(connection.clone())
+            .create(&FileCache {
+                id: String::new(),
+                file_url: url.to_string(),
+                local_path: format!("/tmp/{}", url.rsplit('/').next().unwrap()),
+                file_size: Some(1024),
+                mime_type: Some("image/png".to_string()),
+                checksum: None,
+                expires_at: None,
+                downloaded_at: now,
+                last_accessed: now,
+                pinned: false,
+                thumbnail_path: None,
+                bytes_downloaded: 0,
+                preview_path: None,
+                preview_text: None,
+                consultation_id: None,
+                original_size: None,
+            })
+            .unwrap()
+    }
+
+    fn record_with(file_ids: &[&str]) -> MedicalRecord {
+        MedicalRecord {
+            id: String::new(),
+            patient_id: "p1".to_string(),
+            doctor_id: "d1".to_string(),
+            consultation_id: None,
+            record_type: "examination".to_string(),
+            title: "胸片".to_string(),
+            content: Some("未见异常".to_string()),
+            attachments: file_ids
+                .iter()
+                .map(|id| Attachment {
+                    file_id: id.to_string(),
+                    name: "chest.png".to_string(),
+                    mime_type: "image/png".to_string(),
+                    size: 1024,
+                    checksum: None,
+                })
+                .collect(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn is_pinned(connection: &DbConnection, file_id: &str) -> bool {
+        FileCacheDao::with_connection(connection.clone())
+            .find_by_id(file_id)
+            .unwrap()
+            .unwrap()
+            .pinned
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_rejected() {
+        let connection = create_test_connection();
+        let service = MedicalRecordService::with_connection(connection.clone());
+
+        let error = service.create_medical_record(record_with(&["missing"])).await.unwrap_err();
+        assert!(error.to_string().contains("missing"));
+        assert!(MedicalRecordDao::with_connection(connection).find_all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_file_survives_lru_cleanup() {
+        let connection = create_test_connection();
+        let attached = cache_file(&connection, "https://example.com/chest.png");
+        let loose = cache_file(&connection, "https://example.com/other.png");
+
+        // 被引用的文件最久未访问，按 LRU 应最先淘汰
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE file_cache SET last_accessed = ?1 WHERE id = ?2",
+                rusqlite::params![Utc::now() - Duration::days(30), attached],
+            )
+            .unwrap();
+
+        let service = MedicalRecordService::with_connection(connection.clone());
+        let record = service.create_medical_record(record_with(&[&attached])).await.unwrap();
+        assert!(is_pinned(&connection, &attached));
+
+        let dao = FileCacheDao::with_connection(connection.clone());
+        let pins = dao.get_pinned_files().unwrap();
+        assert_eq!(pins.len(), 1);
+        assert_eq!((pins[0].cache_id.as_str(), pins[0].reason.as_str()), (attached.as_str(), PIN_REASON_MEDICAL_RECORD));
+        assert_eq!(pins[0].ref_id, record.id);
+
+        let evicted = dao.cleanup_lru(0, false).unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].id, loose);
+        assert!(dao.find_by_id(&attached).unwrap().is_some());
+        assert_eq!(dao.cleanup_old_files(7, false).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_update_and_purge_unpin_files() {
+        let connection = create_test_connection();
+        let first = cache_file(&connection, "https://example.com/a.png");
+        let second = cache_file(&connection, "https://example.com/b.png");
+        let service = MedicalRecordService::with_connection(connection.clone());
+
+        let shared = service.create_medical_record(record_with(&[&first])).await.unwrap();
+        let record = service.create_medical_record(record_with(&[&first, &second])).await.unwrap();
+
+        // 更新时移除的附件解除固定
+        let mut updated = record.clone();
+        updated.attachments.retain(|a| a.file_id == first);
+        let record = service.update_medical_record(updated).await.unwrap();
+        assert!(!is_pinned(&connection, &second));
+
+        // 回收站中的病历可能被恢复，删除后附件保持固定
+        service.delete_medical_record(&record.id).await.unwrap();
+        service.delete_medical_record(&shared.id).await.unwrap();
+        assert!(is_pinned(&connection, &first));
+        assert!(service.delete_medical_record(&shared.id).await.is_err());
+
+        // 彻底清除后不再有病历引用，解除固定
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE medical_records SET deleted_at = ?1",
+                rusqlite::params![Utc::now() - Duration::days(31)],
+            )
+            .unwrap();
+        let purged = MedicalRecordDao::purge_deleted_in(&connection.lock().unwrap(), 30).unwrap();
+        assert_eq!(purged, 2);
+        assert!(!is_pinned(&connection, &first));
+    }
+}
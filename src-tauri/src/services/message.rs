@@ -16,7 +16,7 @@ impl MessageService {
         // 2. 发送到服务器
         // 3. 更新同步状态
 
-        println!("Sending message: {:?}", message);
+        tracing::debug!("Sending {} message in consultation {}", message.message_type.as_str(), message.consultation_id);
         Ok(())
     }
 
@@ -40,6 +40,10 @@ impl MessageService {
                 timestamp: chrono::Utc::now() - chrono::Duration::hours(2),
                 sync_status: SyncStatus::Synced,
                 read_status: ReadStatus::Read,
+                template_id: None,
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
             },
             Message {
                 id: "msg-2".to_string(),
@@ -53,6 +57,10 @@ impl MessageService {
                 timestamp: chrono::Utc::now() - chrono::Duration::hours(2) + chrono::Duration::minutes(2),
                 sync_status: SyncStatus::Synced,
                 read_status: ReadStatus::Read,
+                template_id: None,
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
             },
         ];
 
@@ -64,7 +72,7 @@ impl MessageService {
         // 1. 更新消息状态
         // 2. 同步到服务器
 
-        println!("Marking message {} as read in consultation {}", message_id, consultation_id);
+        tracing::debug!("Marking message {} as read in consultation {}", message_id, consultation_id);
         Ok(())
     }
 
@@ -74,7 +82,7 @@ impl MessageService {
         // 2. 与本地消息对比
         // 3. 更新本地数据库
 
-        println!("Syncing messages for consultation: {}", consultation_id);
+        tracing::info!("Syncing messages for consultation: {}", consultation_id);
         Ok(vec![])
     }
 }
\ No newline at end of file
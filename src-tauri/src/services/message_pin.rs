@@ -0,0 +1,272 @@
+// 消息置顶：医生把过敏史等重要信息固定在会话顶部，患者端支持时同步显示
+
+use crate::database::connection::DbConnection;
+use crate::database::dao::{MessageDao, PinTarget};
+use crate::models::{AppError, ErrorType, MessageType, PinnedMessage, SenderType};
+use crate::services::WebSocketEvent;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// 每个问诊最多置顶的消息数
+pub const MAX_PINNED_MESSAGES: usize = 5;
+// 推送到问诊窗口的事件名，前端据此刷新置顶栏
+pub const MESSAGE_PIN_CHANGED_EVENT: &str = "consultation-message-pin-changed";
+pub const PIN_LIMIT_REACHED: &str = "PIN_LIMIT_REACHED";
+pub const MESSAGE_NOT_PINNABLE: &str = "MESSAGE_NOT_PINNABLE";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessagePinChange {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    pub pinned: bool,
+    #[serde(rename = "pinnedAt")]
+    pub pinned_at: Option<DateTime<Utc>>,
+    #[serde(rename = "pinnedBy")]
+    pub pinned_by: Option<String>,
+    // 消息原本就处于该状态时为 false，调用方不再推送通知
+    #[serde(skip)]
+    pub changed: bool,
+}
+
+impl MessagePinChange {
+    // 同步给患者端的 WebSocket 事件
+    pub fn to_event(&self) -> WebSocketEvent {
+        WebSocketEvent::MessagePinned {
+            consultation_id: self.consultation_id.clone(),
+            message_id: self.message_id.clone(),
+            pinned: self.pinned,
+        }
+    }
+}
+
+pub struct MessagePinService {
+    message_dao: MessageDao,
+}
+
+impl MessagePinService {
+    pub fn new() -> Self {
+        Self {
+            message_dao: MessageDao::new(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            message_dao: MessageDao::with_connection(connection),
+        }
+    }
+
+    // 已撤回（移入回收站）和系统消息不能置顶；已置顶的消息原样返回
+    pub fn pin(&self, message_id: &str, pinned_by: &str) -> Result<MessagePinChange, AppError> {
+        let target = self.find_target(message_id)?;
+        if target.deleted {
+            return Err(not_pinnable("消息已撤回，不能置顶"));
+        }
+        if target.sender_type == SenderType::System || target.message_type == MessageType::Event {
+            return Err(not_pinnable("系统消息不能置顶"));
+        }
+        if target.pinned {
+            return Ok(pin_change(message_id, target, false));
+        }
+
+        let pinned_at = Utc::now();
+        let pinned = self
+            .message_dao
+            .pin(message_id, &target.consultation_id, pinned_by, pinned_at, MAX_PINNED_MESSAGES)
+            .map_err(AppError::from)?;
+        if !pinned {
+            return Err(AppError::new(
+                ErrorType::ValidationError,
+                format!("每个问诊最多置顶 {} 条消息，请先取消其他置顶", MAX_PINNED_MESSAGES),
+            )
+            .with_code(PIN_LIMIT_REACHED)
+            .with_details(serde_json::json!({ "maxPinned": MAX_PINNED_MESSAGES }))
+            .with_retryable(false));
+        }
+
+        Ok(MessagePinChange {
+            consultation_id: target.consultation_id,
+            message_id: message_id.to_string(),
+            pinned: true,
+            pinned_at: Some(pinned_at),
+            pinned_by: Some(pinned_by.to_string()),
+            changed: true,
+        })
+    }
+
+    // 取消置顶，未置顶的消息原样返回；撤回的消息同样可以取消
+    pub fn unpin(&self, message_id: &str) -> Result<MessagePinChange, AppError> {
+        let target = self.find_target(message_id)?;
+        let unpinned = self
+            .message_dao
+            .unpin(message_id, &target.consultation_id)
+            .map_err(AppError::from)?;
+
+        Ok(MessagePinChange {
+            consultation_id: target.consultation_id,
+            message_id: message_id.to_string(),
+            pinned: false,
+            pinned_at: None,
+            pinned_by: None,
+            changed: unpinned,
+        })
+    }
+
+    pub fn pinned_messages(&self, consultation_id: &str) -> Result<Vec<PinnedMessage>, AppError> {
+        self.message_dao.find_pinned(consultation_id).map_err(AppError::from)
+    }
+
+    // 消息所在的问诊，命令层据此校验数据范围
+    pub fn consultation_of(&self, message_id: &str) -> Result<String, AppError> {
+        Ok(self.find_target(message_id)?.consultation_id)
+    }
+
+    fn find_target(&self, message_id: &str) -> Result<PinTarget, AppError> {
+        self.message_dao
+            .find_pin_target(message_id)
+            .map_err(AppError::from)?
+            .ok_or_else(|| AppError::new(ErrorType::DataError, "消息不存在").with_code("MESSAGE_NOT_FOUND"))
+    }
+}
+
+impl Default for MessagePinService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn pin_change(message_id: &str, target: PinTarget, changed: bool) -> MessagePinChange {
+    MessagePinChange {
+        consultation_id: target.consultation_id,
+        message_id: message_id.to_string(),
+        pinned: target.pinned,
+        pinned_at: target.pinned_at,
+        pinned_by: target.pinned_by,
+        changed,
+    }
+}
+
+fn not_pinnable(message: &str) -> AppError {
+    AppError::new(ErrorType::ValidationError, message)
+        .with_code(MESSAGE_NOT_PINNABLE)
+        .with_retryable(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::BaseDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Message, ReadStatus, SyncStatus, SystemEventKind};
+    use chrono::Duration;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');"
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn seed(connection: &DbConnection, id: &str, content: &str, minutes_ago: i64) {
+        let message = Message {
+            id: id.to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type: SenderType::Patient,
+            message_type: MessageType::Text,
+            content: Some(content.to_string()),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now() - Duration::minutes(minutes_ago),
+            sync_status: SyncStatus::Synced,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        };
+        MessageDao::upsert_in(&connection.lock().unwrap(), &message).unwrap();
+    }
+
+    fn pinned_ids(service: &MessagePinService) -> Vec<String> {
+        service.pinned_messages("c1").unwrap().into_iter().map(|pinned| pinned.message.id).collect()
+    }
+
+    #[test]
+    fn test_pin_limit_per_consultation() {
+        let connection = create_test_connection();
+        for i in 0..=MAX_PINNED_MESSAGES {
+            seed(&connection, &format!("m{}", i), "对青霉素过敏", 10);
+        }
+        let service = MessagePinService::with_connection(connection.clone());
+
+        for i in 0..MAX_PINNED_MESSAGES {
+            assert!(service.pin(&format!("m{}", i), "d1").unwrap().changed);
+        }
+        let error = service.pin(&format!("m{}", MAX_PINNED_MESSAGES), "d1").unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(PIN_LIMIT_REACHED));
+
+        // 重复置顶不占名额，取消一条后可以再置顶
+        let repeated = service.pin("m0", "d1").unwrap();
+        assert!(repeated.pinned && !repeated.changed);
+        assert_eq!(repeated.pinned_by.as_deref(), Some("d1"));
+        assert!(service.unpin("m0").unwrap().changed);
+        assert!(!service.unpin("m0").unwrap().changed);
+        assert!(service.pin(&format!("m{}", MAX_PINNED_MESSAGES), "d1").is_ok());
+        assert_eq!(pinned_ids(&service).len(), MAX_PINNED_MESSAGES);
+    }
+
+    #[test]
+    fn test_recalled_and_system_messages_cannot_be_pinned() {
+        let connection = create_test_connection();
+        seed(&connection, "m1", "对青霉素过敏", 10);
+        let dao = MessageDao::with_connection(connection.clone());
+        let system = dao.insert_system_message("c1", SystemEventKind::ConsultationStarted, serde_json::json!({})).unwrap();
+        let service = MessagePinService::with_connection(connection.clone());
+
+        let error = service.pin(&system.id, "d1").unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(MESSAGE_NOT_PINNABLE));
+
+        dao.delete("m1").unwrap();
+        let error = service.pin("m1", "d1").unwrap_err();
+        assert_eq!(error.code.as_deref(), Some(MESSAGE_NOT_PINNABLE));
+
+        let error = service.pin("missing", "d1").unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("MESSAGE_NOT_FOUND"));
+        assert!(pinned_ids(&service).is_empty());
+    }
+
+    #[test]
+    fn test_pinned_messages_in_pin_order() {
+        let connection = create_test_connection();
+        // 置顶顺序与消息发送顺序无关
+        seed(&connection, "m1", "对青霉素过敏", 30);
+        seed(&connection, "m2", "有高血压病史", 20);
+        seed(&connection, "m3", "正在服用阿司匹林", 10);
+        let service = MessagePinService::with_connection(connection.clone());
+
+        for id in ["m3", "m1", "m2"] {
+            service.pin(id, "d1").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(pinned_ids(&service), vec!["m3", "m1", "m2"]);
+
+        // 历史消息带置顶标记，撤回的消息不再出现在置顶列表
+        let history = MessageDao::with_connection(connection.clone()).find_by_consultation_id("c1", 1, 20).unwrap();
+        assert!(history.items.iter().all(|message| message.pinned));
+        MessageDao::with_connection(connection.clone()).delete("m1").unwrap();
+        assert_eq!(pinned_ids(&service), vec!["m3", "m2"]);
+
+        let change = service.unpin("m3").unwrap();
+        assert_eq!(change.to_event().consultation_id(), Some("c1"));
+        assert_eq!(pinned_ids(&service), vec!["m2"]);
+    }
+}
@@ -0,0 +1,348 @@
+// 常用回复模板服务
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, ConsultationDao, MessageDao, MessageTemplateDao, PatientDao};
+use crate::models::{Message, MessageTemplate, MessageType, ReadStatus, SenderType, SyncStatus};
+use crate::services::record_template::{placeholder_values, substitute};
+use crate::services::sensitive_words::SensitiveWordService;
+use crate::utils::{sanitize_message_content, ValidationService, MAX_MESSAGE_CHARS};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const DEFAULT_CATEGORY: &str = "general";
+
+pub struct MessageTemplateService {
+    template_dao: MessageTemplateDao,
+    message_dao: MessageDao,
+    consultation_dao: ConsultationDao,
+    patient_dao: PatientDao,
+    sensitive_words: SensitiveWordService,
+}
+
+impl MessageTemplateService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            template_dao: MessageTemplateDao::with_connection(connection.clone()),
+            message_dao: MessageDao::with_connection(connection.clone()),
+            consultation_dao: ConsultationDao::with_connection(connection.clone()),
+            patient_dao: PatientDao::with_connection(connection.clone()),
+            sensitive_words: SensitiveWordService::with_connection(connection),
+        }
+    }
+
+    pub async fn create_template(&self, doctor_id: &str, category: Option<&str>, title: &str, content: &str) -> Result<MessageTemplate> {
+        validate_template(title, content)?;
+        self.reject_forbidden_words(content)?;
+
+        let now = Utc::now();
+        let template = MessageTemplate {
+            id: String::new(),
+            doctor_id: doctor_id.to_string(),
+            category: normalize_category(category),
+            title: title.trim().to_string(),
+            content: content.to_string(),
+            usage_count: 0,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let id = self.template_dao.create(&template).map_err(dao_error)?;
+        self.load(&id)
+    }
+
+    pub async fn update_template(
+        &self,
+        doctor_id: &str,
+        template_id: &str,
+        category: Option<&str>,
+        title: &str,
+        content: &str,
+    ) -> Result<MessageTemplate> {
+        validate_template(title, content)?;
+        self.reject_forbidden_words(content)?;
+
+        let mut template = self.load_owned(doctor_id, template_id)?;
+        template.category = normalize_category(category);
+        template.title = title.trim().to_string();
+        template.content = content.to_string();
+        self.template_dao.update(&template).map_err(dao_error)?;
+
+        self.load(template_id)
+    }
+
+    pub async fn delete_template(&self, doctor_id: &str, template_id: &str) -> Result<()> {
+        self.load_owned(doctor_id, template_id)?;
+        self.template_dao.delete(template_id).map_err(dao_error)
+    }
+
+    pub async fn list_templates(&self, doctor_id: &str, category: Option<&str>) -> Result<Vec<MessageTemplate>> {
+        self.template_dao.find_by_doctor(doctor_id, category).map_err(dao_error)
+    }
+
+    // 发送模板消息：展开占位符后保存最终文本并记录模板来源，同时累计使用次数
+    pub async fn send_template_message(
+        &self,
+        consultation_id: &str,
+        sender_type: SenderType,
+        template_id: &str,
+        variables: HashMap<String, String>,
+    ) -> Result<Message> {
+        let template = self.load(template_id)?;
+        let content = self.expand(&template, consultation_id, variables)?;
+        ValidationService::validate_message_content(&content, MAX_MESSAGE_CHARS)?;
+        let sanitized = sanitize_message_content(&content);
+        ValidationService::validate_message_content(&sanitized, MAX_MESSAGE_CHARS)?;
+        self.reject_forbidden_words(&sanitized)?;
+        let raw_content = (sanitized != content).then_some(content.as_str());
+
+        let message = Message {
+            id: Uuid::new_v4().to_string(),
+            consultation_id: consultation_id.to_string(),
+            sender_type,
+            message_type: MessageType::Template,
+            content: Some(sanitized),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now(),
+            sync_status: SyncStatus::Pending,
+            read_status: ReadStatus::Unread,
+            template_id: Some(template.id.clone()),
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        };
+
+        self.message_dao
+            .create_outgoing_with_raw(&message, raw_content, None)
+            .map_err(dao_error)?;
+        self.template_dao.increment_usage(&template.id).map_err(dao_error)?;
+
+        self.message_dao
+            .find_by_id(&message.id)
+            .map_err(dao_error)?
+            .ok_or_else(|| anyhow!("消息保存失败"))
+    }
+
+    // 模板内容及展开后的消息都不能包含禁用词
+    fn reject_forbidden_words(&self, content: &str) -> Result<()> {
+        let scan = self.sensitive_words.scan(content)?;
+        if scan.is_blocked() {
+            return Err(scan.blocked_error().into());
+        }
+        Ok(())
+    }
+
+    fn expand(&self, template: &MessageTemplate, consultation_id: &str, variables: HashMap<String, String>) -> Result<String> {
+        let consultation = self
+            .consultation_dao
+            .find_by_id(consultation_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| anyhow!("问诊不存在: {}", consultation_id))?;
+
+        let mut values = match self.patient_dao.find_by_id(&consultation.patient_id).map_err(dao_error)? {
+            Some(patient) => placeholder_values(&patient, &consultation),
+            None => HashMap::new(),
+        };
+        values.extend(variables);
+
+        let mut missing = Vec::new();
+        let content = substitute(&template.content, &values, &mut missing);
+        if !missing.is_empty() {
+            return Err(anyhow!("模板缺少变量: {}", missing.join(", ")));
+        }
+
+        Ok(content)
+    }
+
+    fn load(&self, template_id: &str) -> Result<MessageTemplate> {
+        self.template_dao
+            .find_by_id(template_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| anyhow!("模板不存在: {}", template_id))
+    }
+
+    fn load_owned(&self, doctor_id: &str, template_id: &str) -> Result<MessageTemplate> {
+        let template = self.load(template_id)?;
+        if template.doctor_id != doctor_id {
+            return Err(anyhow!("只能修改自己创建的模板"));
+        }
+        Ok(template)
+    }
+}
+
+impl Default for MessageTemplateService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn validate_template(title: &str, content: &str) -> Result<()> {
+    if title.trim().is_empty() {
+        return Err(anyhow!("模板标题不能为空"));
+    }
+    ValidationService::validate_message_content(content, MAX_MESSAGE_CHARS)
+}
+
+fn normalize_category(category: Option<&str>) -> String {
+    category
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .unwrap_or(DEFAULT_CATEGORY)
+        .to_string()
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Consultation, ConsultationPriority, Patient};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn seed_consultation(connection: &DbConnection) -> String {
+        let now = Utc::now();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "张三".to_string(),
+                age: Some(35),
+                gender: Some("male".to_string()),
+                phone: None,
+                id_card: None,
+                tags: vec![],
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+
+        ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                status: "active".to_string(),
+                consultation_type: "text".to_string(),
+                title: None,
+                description: None,
+                diagnosis: None,
+                prescription: None,
+                created_at: now,
+                updated_at: now,
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send_template_expands_and_counts_usage() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection);
+        let service = MessageTemplateService::with_connection(connection.clone());
+
+        let greeting = service
+            .create_template("d1", Some("问候"), "问候", "{{patient_name}}您好，我是{{doctor}}医生。")
+            .await
+            .unwrap();
+        let followup = service
+            .create_template("d1", None, "复诊提醒", "请一周后复诊。")
+            .await
+            .unwrap();
+
+        let variables: HashMap<String, String> = [("doctor".to_string(), "李".to_string())].into_iter().collect();
+        for _ in 0..2 {
+            service
+                .send_template_message(&consultation_id, SenderType::Doctor, &greeting.id, variables.clone())
+                .await
+                .unwrap();
+        }
+        service
+            .send_template_message(&consultation_id, SenderType::Doctor, &followup.id, HashMap::new())
+            .await
+            .unwrap();
+
+        let templates = service.list_templates("d1", None).await.unwrap();
+        assert_eq!(templates[0].id, greeting.id);
+        assert_eq!(templates[0].usage_count, 2);
+        assert_eq!(templates[1].usage_count, 1);
+        assert_eq!(templates[1].category, "general");
+
+        // 历史消息中保存的是展开后的文本
+        let history = MessageDao::with_connection(connection)
+            .find_all_by_consultation_id(&consultation_id)
+            .unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].content.as_deref(), Some("张三您好，我是李医生。"));
+        assert_eq!(history[0].template_id.as_deref(), Some(greeting.id.as_str()));
+        assert!(matches!(history[0].message_type, MessageType::Template));
+    }
+
+    #[tokio::test]
+    async fn test_send_template_with_missing_variable_fails() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection);
+        let service = MessageTemplateService::with_connection(connection.clone());
+
+        let template = service
+            .create_template("d1", None, "用药", "请按时服用{{drug}}。")
+            .await
+            .unwrap();
+        let error = service
+            .send_template_message(&consultation_id, SenderType::Doctor, &template.id, HashMap::new())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("drug"));
+
+        assert_eq!(service.list_templates("d1", None).await.unwrap()[0].usage_count, 0);
+        assert!(MessageDao::with_connection(connection)
+            .find_all_by_consultation_id(&consultation_id)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_template_content_validation_and_ownership() {
+        let service = MessageTemplateService::with_connection(create_test_connection());
+
+        assert!(service.create_template("d1", None, "空", "   ").await.is_err());
+        assert!(service.create_template("d1", None, "敏感", "测试敏感词").await.is_err());
+
+        let template = service.create_template("d1", None, "问候", "您好").await.unwrap();
+        assert!(service.delete_template("d2", &template.id).await.is_err());
+
+        let updated = service
+            .update_template("d1", &template.id, Some("问候"), "问候", "您好，请描述症状")
+            .await
+            .unwrap();
+        assert_eq!(updated.content, "您好，请描述症状");
+
+        service.delete_template("d1", &template.id).await.unwrap();
+        assert!(service.list_templates("d1", None).await.unwrap().is_empty());
+    }
+}
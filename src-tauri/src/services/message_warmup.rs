@@ -0,0 +1,66 @@
+// 启动预热：把最近活跃问诊的第一页消息读入查询缓存，启动后首次打开问诊窗口不必等待冷启动的磁盘读取
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{ConsultationDao, MessageDao};
+use crate::models::{DataScope, MessageWarmupConfig};
+use std::time::Duration;
+
+// 与问诊窗口首次加载的消息条数一致，否则预热的缓存不会被命中
+pub const MESSAGE_WARMUP_PAGE_SIZE: i32 = 50;
+
+// 每个问诊之间释放数据库连接并稍作停顿，让前台命令优先
+const WARMUP_PAUSE: Duration = Duration::from_millis(20);
+
+pub struct MessageWarmupService {
+    connection: DbConnection,
+    pause: Duration,
+}
+
+impl MessageWarmupService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            connection,
+            pause: WARMUP_PAUSE,
+        }
+    }
+
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    // 按会话列表的排序预热医生最近活跃的问诊，单个问诊失败不影响其余问诊，返回预热成功的问诊数
+    pub fn warm(&self, doctor_id: &str, scope: &DataScope, config: &MessageWarmupConfig) -> Result<usize, Box<dyn std::error::Error>> {
+        if !config.enabled {
+            return Ok(0);
+        }
+
+        let overviews = ConsultationDao::with_connection(self.connection.clone()).get_conversation_overviews(
+            doctor_id,
+            1,
+            config.consultation_count as i32,
+        )?;
+        let message_dao = MessageDao::with_connection(self.connection.clone());
+
+        let mut warmed = 0;
+        for overview in &overviews.items {
+            match message_dao.find_first_page_cached(&overview.consultation_id, scope, MESSAGE_WARMUP_PAGE_SIZE) {
+                Ok(_) => warmed += 1,
+                Err(e) => tracing::warn!("Failed to warm messages for consultation {}: {}", overview.consultation_id, e),
+            }
+            std::thread::sleep(self.pause);
+        }
+
+        Ok(warmed)
+    }
+}
+
+impl Default for MessageWarmupService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,347 @@
+// 运行指标：消息往返延迟（医生点击发送到收到服务器确认）按小时累计到固定的直方图桶，
+// 发送路径只向通道投递样本，由后台任务合并写入数据库，重启后继续累计
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{MetricCount, MetricsDao};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+pub const MESSAGE_LATENCY_METRIC: &str = "message_latency";
+
+// 直方图桶上界（毫秒），超过最后一个上界的样本计入溢出桶
+pub const LATENCY_BUCKET_BOUNDS_MS: [u64; 10] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000];
+
+const MAX_METRICS_HOURS: u32 = 30 * 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    pub recorded_at: DateTime<Utc>,
+    pub latency_ms: u64,
+}
+
+// 发送路径持有的记录端，记录一次只是一次通道发送，不加锁也不访问数据库
+#[derive(Debug, Clone)]
+pub struct LatencyRecorder {
+    sender: mpsc::UnboundedSender<LatencySample>,
+}
+
+impl LatencyRecorder {
+    pub fn record(&self, sample: LatencySample) {
+        // 指标任务已停止时丢弃样本
+        let _ = self.sender.send(sample);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencyBucket {
+    // 溢出桶没有上界
+    #[serde(rename = "upperBoundMs")]
+    pub upper_bound_ms: Option<u64>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageLatencyMetrics {
+    pub hours: u32,
+    #[serde(rename = "sampleCount")]
+    pub sample_count: u64,
+    pub buckets: Vec<LatencyBucket>,
+    #[serde(rename = "p50Ms")]
+    pub p50_ms: Option<f64>,
+    #[serde(rename = "p95Ms")]
+    pub p95_ms: Option<f64>,
+}
+
+pub struct MetricsService {
+    // 应用启动时数据库尚未打开，未指定时使用时再取全局连接
+    connection: Option<DbConnection>,
+    sender: mpsc::UnboundedSender<LatencySample>,
+    // 启动后台任务时取走；启动前记录的样本留在通道中，启动后一并写入
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<LatencySample>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MetricsService {
+    pub fn new() -> Self {
+        Self::build(None)
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self::build(Some(connection))
+    }
+
+    fn build(connection: Option<DbConnection>) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            connection,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn recorder(&self) -> LatencyRecorder {
+        LatencyRecorder {
+            sender: self.sender.clone(),
+        }
+    }
+
+    // 需在 tokio 运行时内调用，只能启动一次
+    pub fn start(self: &Arc<Self>) {
+        let mut task = self.task.lock().unwrap();
+        if task.is_some() {
+            return;
+        }
+        let Some(mut receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+
+        let service = self.clone();
+        *task = Some(tokio::spawn(async move {
+            while let Some(sample) = receiver.recv().await {
+                // 一次取出已到达的全部样本，合并为一个事务写入
+                let mut samples = vec![sample];
+                while let Ok(sample) = receiver.try_recv() {
+                    samples.push(sample);
+                }
+                if let Err(e) = service.persist(&samples) {
+                    tracing::warn!("Failed to persist {} latency samples: {}", samples.len(), e);
+                }
+            }
+        }));
+    }
+
+    pub fn stop(&self) {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    fn connection(&self) -> DbConnection {
+        self.connection
+            .clone()
+            .unwrap_or_else(|| get_database().get_connection())
+    }
+
+    pub fn persist(&self, samples: &[LatencySample]) -> Result<()> {
+        let mut counts: BTreeMap<(DateTime<Utc>, usize), u64> = BTreeMap::new();
+        for sample in samples {
+            *counts
+                .entry((hour_start(sample.recorded_at), bucket_index(sample.latency_ms)))
+                .or_default() += 1;
+        }
+        let counts: Vec<MetricCount> = counts
+            .into_iter()
+            .map(|((hour, bucket), count)| MetricCount { hour, bucket, count })
+            .collect();
+
+        MetricsDao::with_connection(self.connection())
+            .add_counts(MESSAGE_LATENCY_METRIC, &counts)
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    // 最近 hours 小时（含当前小时）的直方图和分位数
+    pub fn get_message_latency_metrics(&self, hours: u32, now: DateTime<Utc>) -> Result<MessageLatencyMetrics> {
+        let hours = hours.clamp(1, MAX_METRICS_HOURS);
+        let since = hour_start(now) - Duration::hours(hours as i64 - 1);
+        let rows = MetricsDao::with_connection(self.connection())
+            .find_since(MESSAGE_LATENCY_METRIC, since)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut counts = [0u64; LATENCY_BUCKET_BOUNDS_MS.len() + 1];
+        for row in rows {
+            // 桶划分调整后遗留的旧下标计入溢出桶
+            let bucket = row.bucket.min(LATENCY_BUCKET_BOUNDS_MS.len());
+            counts[bucket] += row.count;
+        }
+
+        Ok(MessageLatencyMetrics {
+            hours,
+            sample_count: counts.iter().sum(),
+            buckets: counts
+                .iter()
+                .enumerate()
+                .map(|(i, count)| LatencyBucket {
+                    upper_bound_ms: LATENCY_BUCKET_BOUNDS_MS.get(i).copied(),
+                    count: *count,
+                })
+                .collect(),
+            p50_ms: histogram_percentile(&counts, 0.5),
+            p95_ms: histogram_percentile(&counts, 0.95),
+        })
+    }
+
+    pub fn reset_message_latency(&self) -> Result<usize> {
+        MetricsDao::with_connection(self.connection())
+            .delete_metric(MESSAGE_LATENCY_METRIC)
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+}
+
+impl Default for MetricsService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MetricsService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn hour_start(at: DateTime<Utc>) -> DateTime<Utc> {
+    at.duration_trunc(Duration::hours(1)).unwrap_or(at)
+}
+
+// 延迟恰好等于上界时计入该桶
+pub fn bucket_index(latency_ms: u64) -> usize {
+    LATENCY_BUCKET_BOUNDS_MS
+        .iter()
+        .position(|bound| latency_ms <= *bound)
+        .unwrap_or(LATENCY_BUCKET_BOUNDS_MS.len())
+}
+
+// 按直方图估算分位数：找到累计数达到目标名次的桶，在桶的上下界之间线性插值；
+// 落在溢出桶时只能返回最后一个上界
+pub fn histogram_percentile(counts: &[u64], percentile: f64) -> Option<f64> {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+
+    let rank = percentile.clamp(0.0, 1.0) * total as f64;
+    let mut cumulative = 0u64;
+    for (i, &count) in counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let lower = i.checked_sub(1).map_or(0.0, |prev| LATENCY_BUCKET_BOUNDS_MS[prev] as f64);
+        let Some(&upper) = LATENCY_BUCKET_BOUNDS_MS.get(i) else {
+            return Some(lower);
+        };
+        if (cumulative + count) as f64 >= rank {
+            let fraction = (rank - cumulative as f64) / count as f64;
+            return Some(lower + fraction * (upper as f64 - lower));
+        }
+        cumulative += count;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use chrono::TimeZone;
+    use rusqlite::Connection;
+
+    fn create_test_service() -> Arc<MetricsService> {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(MetricsService::with_connection(Arc::new(Mutex::new(conn))))
+    }
+
+    fn samples(at: DateTime<Utc>, latencies: impl IntoIterator<Item = u64>) -> Vec<LatencySample> {
+        latencies
+            .into_iter()
+            .map(|latency_ms| LatencySample { recorded_at: at, latency_ms })
+            .collect()
+    }
+
+    fn counts_for(latencies: impl IntoIterator<Item = u64>) -> Vec<u64> {
+        let mut counts = vec![0u64; LATENCY_BUCKET_BOUNDS_MS.len() + 1];
+        for latency in latencies {
+            counts[bucket_index(latency)] += 1;
+        }
+        counts
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("percentile should be computed");
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn test_bucket_boundaries() {
+        assert_eq!(bucket_index(0), 0);
+        assert_eq!(bucket_index(50), 0);
+        assert_eq!(bucket_index(51), 1);
+        assert_eq!(bucket_index(60_000), LATENCY_BUCKET_BOUNDS_MS.len() - 1);
+        assert_eq!(bucket_index(60_001), LATENCY_BUCKET_BOUNDS_MS.len());
+    }
+
+    #[test]
+    fn test_percentiles_interpolate_within_bucket() {
+        // 1..=100 毫秒均匀分布：前 50 个在 0-50 桶，后 50 个在 50-100 桶
+        let counts = counts_for(1..=100);
+        assert_close(histogram_percentile(&counts, 0.5), 50.0);
+        assert_close(histogram_percentile(&counts, 0.95), 95.0);
+
+        // 90 个快速确认、10 个落在 1-2.5 秒
+        let mut latencies = vec![20; 90];
+        latencies.extend(vec![2_000; 10]);
+        let counts = counts_for(latencies);
+        assert_close(histogram_percentile(&counts, 0.5), 50.0 / 90.0 * 50.0);
+        assert_close(histogram_percentile(&counts, 0.95), 1_750.0);
+
+        assert_eq!(histogram_percentile(&counts_for([]), 0.5), None);
+        // 全部超时只能给出最后一个上界
+        assert_close(histogram_percentile(&counts_for([120_000, 90_000]), 0.95), 60_000.0);
+    }
+
+    #[test]
+    fn test_metrics_window_and_reset() {
+        let service = create_test_service();
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 10, 30, 0).unwrap();
+
+        service.persist(&samples(now, 1..=100)).unwrap();
+        // 同一小时分批写入会累加
+        service.persist(&samples(now - Duration::minutes(20), [70_000])).unwrap();
+        service.persist(&samples(now - Duration::hours(3), [5; 40])).unwrap();
+
+        let current = service.get_message_latency_metrics(1, now).unwrap();
+        assert_eq!(current.sample_count, 101);
+        assert_eq!(current.buckets[0].count, 50);
+        assert_eq!(current.buckets[1], LatencyBucket { upper_bound_ms: Some(100), count: 50 });
+        assert_eq!(current.buckets.last().unwrap(), &LatencyBucket { upper_bound_ms: None, count: 1 });
+
+        let day = service.get_message_latency_metrics(24, now).unwrap();
+        assert_eq!(day.sample_count, 141);
+        assert_eq!(day.buckets[0].count, 90);
+
+        assert_eq!(service.reset_message_latency().unwrap(), 4);
+        let cleared = service.get_message_latency_metrics(24, now).unwrap();
+        assert_eq!(cleared.sample_count, 0);
+        assert_eq!(cleared.p50_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_samples_persisted_by_background_task() {
+        let service = create_test_service();
+        let recorder = service.recorder();
+        let now = Utc::now();
+        // 启动前记录的样本也会写入
+        recorder.record(LatencySample { recorded_at: now, latency_ms: 80 });
+        service.start();
+        recorder.record(LatencySample { recorded_at: now, latency_ms: 300 });
+
+        let mut metrics = service.get_message_latency_metrics(1, now).unwrap();
+        for _ in 0..50 {
+            if metrics.sample_count == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            metrics = service.get_message_latency_metrics(1, now).unwrap();
+        }
+        assert_eq!(metrics.sample_count, 2);
+        assert_eq!(metrics.buckets[1].count, 1);
+        assert_eq!(metrics.buckets[3].count, 1);
+    }
+}
@@ -1,15 +1,103 @@
 // 服务模块
 
 pub mod auth;
+pub mod auth_provider;
 pub mod patient;
+pub mod patient_import;
+pub mod patient_export;
+pub mod transcript_export;
+pub mod prescription_pdf;
+pub mod consultation;
+pub mod consultation_expiry;
+pub mod prescription;
+pub mod medical_record;
+pub mod record_template;
 pub mod message;
+pub mod message_template;
+pub mod message_pin;
+pub mod sensitive_words;
 pub mod file;
+pub mod chunked_upload;
+pub mod attachment_quota;
+pub mod avatar;
 pub mod websocket;
+pub mod event_replay;
+pub mod event_dispatcher;
 pub mod security;
+pub mod audit_export;
+pub mod access_analyzer;
+pub mod device_info;
+pub mod permission;
+pub mod token_refresh;
+pub mod session_restore;
+pub mod resource_monitor;
+pub mod health;
+pub mod notification_router;
+pub mod read_receipt;
+pub mod sync;
+pub mod sync_scheduler;
+pub mod offline_state;
+pub mod resume_recovery;
+pub mod outbox;
+pub mod metrics;
+pub mod session_purge;
+pub mod retention;
+pub mod integrity_repair;
+pub mod trash;
+pub mod app_settings;
+pub mod workstation_profile;
+pub mod updater;
+pub mod command_rate_limit;
+pub mod message_warmup;
+pub mod diagnostics_bundle;
 
 pub use auth::*;
+pub use auth_provider::*;
 pub use patient::*;
+pub use patient_import::*;
+pub use patient_export::*;
+pub use transcript_export::*;
+pub use prescription_pdf::*;
+pub use consultation::*;
+pub use consultation_expiry::*;
+pub use prescription::*;
+pub use medical_record::*;
+pub use record_template::*;
 pub use message::*;
+pub use message_template::*;
+pub use message_pin::*;
+pub use sensitive_words::*;
 pub use file::*;
+pub use chunked_upload::*;
+pub use attachment_quota::*;
+pub use avatar::*;
 pub use websocket::*;
-pub use security::*;
\ No newline at end of file
+pub use event_replay::*;
+pub use event_dispatcher::*;
+pub use security::*;
+pub use audit_export::*;
+pub use access_analyzer::*;
+pub use device_info::*;
+pub use permission::*;
+pub use token_refresh::*;
+pub use session_restore::*;
+pub use resource_monitor::*;
+pub use health::*;
+pub use notification_router::*;
+pub use read_receipt::*;
+pub use sync::*;
+pub use sync_scheduler::*;
+pub use offline_state::*;
+pub use resume_recovery::*;
+pub use outbox::*;
+pub use metrics::*;
+pub use session_purge::*;
+pub use retention::*;
+pub use integrity_repair::*;
+pub use trash::*;
+pub use app_settings::*;
+pub use workstation_profile::*;
+pub use updater::*;
+pub use command_rate_limit::*;
+pub use message_warmup::*;
+pub use diagnostics_bundle::*;
\ No newline at end of file
@@ -0,0 +1,406 @@
+// 新消息通知路由：根据问诊窗口状态决定推送到窗口还是弹出系统通知
+
+use crate::commands::auth::TokenRefreshServiceState;
+use crate::commands::database::SyncSchedulerState;
+use crate::commands::message::OutboxDispatcherState;
+use crate::commands::window::WindowManagerState;
+use crate::database::dao::{ConsultationDao, DoctorDao, IntakeFormDao, MessageDao, UserSettingsDao};
+use crate::models::{message_preview_text, ConsultationPriority, DoctorOnlineStatus, IntakeFormSubmission, Message, SenderType};
+use crate::services::{
+    apply_read_receipt, OrderedEventHandler, SyncTrigger, WebSocketEvent, MESSAGES_READ_EVENT, MESSAGE_ACKNOWLEDGED_EVENT,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+pub const DO_NOT_DISTURB_KEY: &str = "do_not_disturb";
+pub const INTAKE_FORM_RECEIVED_EVENT: &str = "intake-form-received";
+
+pub type NotificationRouterState = Arc<Mutex<NotificationRouter>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsultationWindowStatus {
+    pub window_id: String,
+    pub focused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageRoute {
+    // 自己发送的消息不提醒
+    Ignore,
+    // 推送到已打开的问诊窗口，窗口未聚焦时累计未读
+    Window { window_id: String, bump_badge: bool },
+    // 没有对应窗口，弹出系统通知
+    Notify,
+    // 免打扰时只累计未读
+    BadgeOnly,
+}
+
+pub fn decide_route(
+    sender_type: &SenderType,
+    window: Option<&ConsultationWindowStatus>,
+    do_not_disturb: bool,
+) -> MessageRoute {
+    // 自己发出的消息和系统事件都不提醒
+    if matches!(sender_type, SenderType::Doctor | SenderType::System) {
+        return MessageRoute::Ignore;
+    }
+
+    match window {
+        Some(window) => MessageRoute::Window {
+            window_id: window.window_id.clone(),
+            bump_badge: !window.focused,
+        },
+        None if do_not_disturb => MessageRoute::BadgeOnly,
+        None => MessageRoute::Notify,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnreadBadge {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    pub count: u32,
+    pub total: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct NotificationRouter {
+    unread: HashMap<String, u32>,
+}
+
+impl NotificationRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bump_unread(&mut self, consultation_id: &str) -> UnreadBadge {
+        *self.unread.entry(consultation_id.to_string()).or_insert(0) += 1;
+        self.badge(consultation_id)
+    }
+
+    pub fn clear_unread(&mut self, consultation_id: &str) -> UnreadBadge {
+        self.unread.remove(consultation_id);
+        self.badge(consultation_id)
+    }
+
+    // 退出登录时清空全部未读计数，返回清除的问诊数
+    pub fn clear_all(&mut self) -> usize {
+        let cleared = self.unread.len();
+        self.unread.clear();
+        cleared
+    }
+
+    pub fn total_unread(&self) -> u32 {
+        self.unread.values().sum()
+    }
+
+    fn badge(&self, consultation_id: &str) -> UnreadBadge {
+        UnreadBadge {
+            consultation_id: consultation_id.to_string(),
+            count: self.unread.get(consultation_id).copied().unwrap_or(0),
+            total: self.total_unread(),
+        }
+    }
+}
+
+pub fn message_preview(message: &Message) -> String {
+    message_preview_text(&message.message_type, message.content.as_deref())
+}
+
+// 处理 WebSocket 推送的事件：新消息路由提醒，已读回执、问卷、问诊优先级和医生在线状态更新本地状态
+pub async fn route_websocket_event(app: &AppHandle, event: WebSocketEvent) {
+    let (consultation_id, message) = match event {
+        WebSocketEvent::Message { consultation_id, message, .. } => (consultation_id, message),
+        WebSocketEvent::ReadReceipt { .. } | WebSocketEvent::ReadReceiptBatch { .. } => {
+            route_read_receipt(app, &event);
+            return;
+        }
+        WebSocketEvent::MessageAck { idempotency_key, .. } => {
+            acknowledge_outbox_message(app, &idempotency_key, Utc::now());
+            return;
+        }
+        WebSocketEvent::IntakeForm { consultation_id, schema_version, data, submitted_at } => {
+            store_intake_form(app, IntakeFormSubmission { consultation_id, schema_version, data, submitted_at });
+            return;
+        }
+        WebSocketEvent::ConsultationUpdate { consultation_id, priority: Some(priority), .. } => {
+            apply_consultation_priority(&consultation_id, priority);
+            return;
+        }
+        WebSocketEvent::Presence { doctor_id, status } => {
+            apply_doctor_presence(&doctor_id, status);
+            return;
+        }
+        _ => return,
+    };
+
+    let window_id = app.state::<WindowManagerState>().consultation_window_id(&consultation_id);
+    let window = window_id.and_then(|window_id| {
+        app.get_webview_window(&window_id).map(|w| ConsultationWindowStatus {
+            focused: w.is_focused().unwrap_or(false),
+            window_id,
+        })
+    });
+
+    let do_not_disturb = window.is_none() && current_do_not_disturb(app).await;
+
+    match decide_route(&message.sender_type, window.as_ref(), do_not_disturb) {
+        MessageRoute::Ignore => {}
+        MessageRoute::Window { window_id, bump_badge } => {
+            if let Err(e) = app.emit_to(window_id.as_str(), "consultation-message", &message) {
+                tracing::warn!("Failed to emit consultation-message event: {}", e);
+            }
+            if bump_badge {
+                bump_badge_for(app, &consultation_id);
+            }
+        }
+        MessageRoute::Notify => {
+            bump_badge_for(app, &consultation_id);
+            // 点击通知由前端的 onAction 监听调用 open_or_focus_consultation_window
+            let result = app
+                .notification()
+                .builder()
+                .title("新的问诊消息")
+                .body(message_preview(&message))
+                .extra("consultationId", consultation_id.clone())
+                .show();
+            if let Err(e) = result {
+                tracing::warn!("Failed to show notification: {}", e);
+            }
+        }
+        MessageRoute::BadgeOnly => bump_badge_for(app, &consultation_id),
+    }
+}
+
+// 按问诊有序分发后的处理：新消息先落库再路由，窗口重新加载历史时顺序与推送一致
+pub struct AppEventHandler {
+    app: AppHandle,
+}
+
+impl AppEventHandler {
+    pub fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+#[async_trait]
+impl OrderedEventHandler for AppEventHandler {
+    async fn handle(&self, event: WebSocketEvent) {
+        if let WebSocketEvent::Message { message, .. } = &event {
+            // 本地还没有该问诊时写入失败，等待同步补齐，仍然照常提醒
+            if let Err(e) = MessageDao::new().save_incoming(message) {
+                tracing::warn!("Failed to store incoming message {}: {}", message.id, e);
+            }
+        }
+        route_websocket_event(&self.app, event).await;
+    }
+
+    fn request_gap_fill(&self, consultation_id: &str) {
+        let Some(scheduler) = self.app.try_state::<SyncSchedulerState>() else {
+            return;
+        };
+        tracing::info!("Requesting gap-fill sync for consultation {}", consultation_id);
+        scheduler.trigger(SyncTrigger::GapFill);
+    }
+}
+
+// 回执落库后只通知已打开的问诊窗口，未打开时下次加载历史即可看到
+fn route_read_receipt(app: &AppHandle, event: &WebSocketEvent) {
+    let Some(update) = apply_read_receipt(&MessageDao::new(), event) else {
+        return;
+    };
+
+    let window_id = app.state::<WindowManagerState>().consultation_window_id(&update.consultation_id);
+    if let Some(window_id) = window_id {
+        if let Err(e) = app.emit_to(window_id.as_str(), MESSAGES_READ_EVENT, &update) {
+            tracing::warn!("Failed to emit {} event: {}", MESSAGES_READ_EVENT, e);
+        }
+    }
+}
+
+// 问卷落库后通知已打开的问诊窗口刷新，格式异常的问卷同样通知，由前端提示
+fn store_intake_form(app: &AppHandle, submission: IntakeFormSubmission) {
+    let consultation_id = submission.consultation_id.clone();
+    let form = match IntakeFormDao::new().save(submission) {
+        Ok(form) => form,
+        Err(e) => {
+            tracing::error!("Failed to store intake form for consultation {}: {}", consultation_id, e);
+            return;
+        }
+    };
+
+    let window_id = app.state::<WindowManagerState>().consultation_window_id(&consultation_id);
+    if let Some(window_id) = window_id {
+        if let Err(e) = app.emit_to(window_id.as_str(), INTAKE_FORM_RECEIVED_EVENT, &form) {
+            tracing::warn!("Failed to emit {} event: {}", INTAKE_FORM_RECEIVED_EVENT, e);
+        }
+    }
+}
+
+// 优先级写入后由 data-changed 事件通知各窗口刷新候诊队列；本地没有该问诊时等待下次同步
+fn apply_consultation_priority(consultation_id: &str, priority: ConsultationPriority) {
+    match ConsultationDao::new().set_priority(consultation_id, priority) {
+        Ok(true) => tracing::info!("Consultation {} priority changed to {}", consultation_id, priority.as_str()),
+        Ok(false) => {}
+        Err(e) => tracing::error!("Failed to update priority of consultation {}: {}", consultation_id, e),
+    }
+}
+
+// 名录中还没有的医生等待下次同步，转接候选列表下次打开时按新状态排序
+fn apply_doctor_presence(doctor_id: &str, status: DoctorOnlineStatus) {
+    match DoctorDao::new().set_online_status(doctor_id, status) {
+        Ok(true) => tracing::debug!("Doctor {} is now {}", doctor_id, status.as_str()),
+        Ok(false) => {}
+        Err(e) => tracing::error!("Failed to update presence of doctor {}: {}", doctor_id, e),
+    }
+}
+
+// 服务器确认收到后从发件箱移除，并通知前端将消息标记为已发送；received_at 用于计算往返延迟
+fn acknowledge_outbox_message(app: &AppHandle, idempotency_key: &str, received_at: DateTime<Utc>) {
+    let Some(outbox) = app.try_state::<OutboxDispatcherState>() else {
+        return;
+    };
+    match outbox.acknowledge_at(idempotency_key, received_at) {
+        Ok(Some(message_id)) => {
+            if let Err(e) = app.emit(MESSAGE_ACKNOWLEDGED_EVENT, &message_id) {
+                tracing::warn!("Failed to emit {} event: {}", MESSAGE_ACKNOWLEDGED_EVENT, e);
+            }
+        }
+        Ok(None) => tracing::debug!("Ignoring duplicate ack for {}", idempotency_key),
+        Err(e) => tracing::error!("Failed to acknowledge outbox message {}: {}", idempotency_key, e),
+    }
+}
+
+pub fn emit_unread_badge(app: &AppHandle, badge: &UnreadBadge) {
+    if let Err(e) = app.emit("unread-badge", badge) {
+        tracing::warn!("Failed to emit unread-badge event: {}", e);
+    }
+
+    if let Some(main) = app.get_webview_window("main") {
+        let count = if badge.total > 0 { Some(badge.total as i64) } else { None };
+        if let Err(e) = main.set_badge_count(count) {
+            tracing::warn!("Failed to set badge count: {}", e);
+        }
+    }
+}
+
+fn bump_badge_for(app: &AppHandle, consultation_id: &str) {
+    let badge = app
+        .state::<NotificationRouterState>()
+        .lock()
+        .unwrap()
+        .bump_unread(consultation_id);
+    emit_unread_badge(app, &badge);
+}
+
+async fn current_do_not_disturb(app: &AppHandle) -> bool {
+    let token_refresh = app.state::<TokenRefreshServiceState>();
+    let Some(user_id) = token_refresh.lock().await.current_user_id().await else {
+        return false;
+    };
+
+    UserSettingsDao::new()
+        .get_bool(&user_id, DO_NOT_DISTURB_KEY)
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to read do-not-disturb setting: {}", e);
+            false
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MessageType, ReadStatus, SyncStatus, PREVIEW_MAX_CHARS};
+    use chrono::Utc;
+
+    fn window(focused: bool) -> ConsultationWindowStatus {
+        ConsultationWindowStatus {
+            window_id: "consultation-1".to_string(),
+            focused,
+        }
+    }
+
+    fn message(message_type: MessageType, content: Option<&str>) -> Message {
+        Message {
+            id: "m1".to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type: SenderType::Patient,
+            message_type,
+            content: content.map(str::to_string),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now(),
+            sync_status: SyncStatus::Synced,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn test_route_decision_table() {
+        let cases = [
+            // (发送方, 窗口, 免打扰, 期望路由)
+            (SenderType::Doctor, Some(window(false)), false, MessageRoute::Ignore),
+            (SenderType::Doctor, None, false, MessageRoute::Ignore),
+            (SenderType::System, None, false, MessageRoute::Ignore),
+            (
+                SenderType::Patient,
+                Some(window(true)),
+                false,
+                MessageRoute::Window { window_id: "consultation-1".to_string(), bump_badge: false },
+            ),
+            (
+                SenderType::Patient,
+                Some(window(false)),
+                false,
+                MessageRoute::Window { window_id: "consultation-1".to_string(), bump_badge: true },
+            ),
+            (
+                SenderType::Patient,
+                Some(window(false)),
+                true,
+                MessageRoute::Window { window_id: "consultation-1".to_string(), bump_badge: true },
+            ),
+            (SenderType::Patient, None, false, MessageRoute::Notify),
+            (SenderType::Patient, None, true, MessageRoute::BadgeOnly),
+        ];
+
+        for (sender, window, do_not_disturb, expected) in cases {
+            assert_eq!(decide_route(&sender, window.as_ref(), do_not_disturb), expected);
+        }
+    }
+
+    #[test]
+    fn test_unread_badge_counts() {
+        let mut router = NotificationRouter::new();
+
+        router.bump_unread("c1");
+        router.bump_unread("c1");
+        let badge = router.bump_unread("c2");
+        assert_eq!(badge, UnreadBadge { consultation_id: "c2".to_string(), count: 1, total: 3 });
+
+        let badge = router.clear_unread("c1");
+        assert_eq!(badge.count, 0);
+        assert_eq!(badge.total, 1);
+    }
+
+    #[test]
+    fn test_message_preview() {
+        assert_eq!(message_preview(&message(MessageType::Image, None)), "[图片]");
+        assert_eq!(message_preview(&message(MessageType::Text, Some(" 医生您好 "))), "医生您好");
+
+        let long = "头".repeat(50);
+        let preview = message_preview(&message(MessageType::Text, Some(&long)));
+        assert_eq!(preview.chars().count(), PREVIEW_MAX_CHARS + 1);
+        assert!(preview.ends_with('…'));
+    }
+}
@@ -0,0 +1,315 @@
+// 离线状态服务：综合 WebSocket 状态和定期 HTTP 探测判断整体连通性，状态稳定后才通知前端
+
+use crate::database::dao::{MessageDao, SyncStateDao};
+use crate::services::{ConnectivityProbe, SyncEntity};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+pub const CONNECTIVITY_CHANGED_EVENT: &str = "connectivity-changed";
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+// 新状态持续这么久才对外报告，网络抖动时不会反复切换提示
+const CONNECTIVITY_DEBOUNCE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConnectivityChange {
+    pub online: bool,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OfflineState {
+    pub online: bool,
+    // 进入当前在线/离线状态的时间
+    pub since: DateTime<Utc>,
+    // 本地已保存、尚未发送到服务器的消息
+    #[serde(rename = "queuedMessages")]
+    pub queued_messages: i64,
+    // 进入当前状态后还没有完成同步的实体
+    #[serde(rename = "pendingSyncEntities")]
+    pub pending_sync_entities: Vec<String>,
+}
+
+#[derive(Debug)]
+struct ConnectivityTracker {
+    online: bool,
+    since: DateTime<Utc>,
+    // 与已报告状态不同的观测结果及其首次出现时间
+    candidate: Option<(bool, Instant)>,
+}
+
+pub struct OfflineStateService {
+    probe: Arc<dyn ConnectivityProbe>,
+    probe_interval: Duration,
+    debounce: Duration,
+    tracker: std::sync::Mutex<ConnectivityTracker>,
+    event_sender: mpsc::UnboundedSender<ConnectivityChange>,
+    task: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl OfflineStateService {
+    pub fn new(probe: Arc<dyn ConnectivityProbe>) -> (Self, mpsc::UnboundedReceiver<ConnectivityChange>) {
+        Self::with_timing(probe, PROBE_INTERVAL, CONNECTIVITY_DEBOUNCE)
+    }
+
+    pub fn with_timing(
+        probe: Arc<dyn ConnectivityProbe>,
+        probe_interval: Duration,
+        debounce: Duration,
+    ) -> (Self, mpsc::UnboundedReceiver<ConnectivityChange>) {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+
+        let service = Self {
+            probe,
+            probe_interval,
+            debounce,
+            // 启动时先假定在线，避免首次探测完成前误报离线
+            tracker: std::sync::Mutex::new(ConnectivityTracker {
+                online: true,
+                since: Utc::now(),
+                candidate: None,
+            }),
+            event_sender,
+            task: std::sync::Mutex::new(None),
+        };
+
+        (service, event_receiver)
+    }
+
+    // 需在 tokio 运行时内调用
+    pub fn start(self: &Arc<Self>) {
+        let mut task = self.task.lock().unwrap();
+        if task.is_none() {
+            let service = self.clone();
+            *task = Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(service.probe_interval);
+                loop {
+                    interval.tick().await;
+                    let online = service.probe.is_online().await;
+                    service.observe(online);
+                }
+            }));
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.tracker.lock().unwrap().online
+    }
+
+    pub fn current(&self) -> ConnectivityChange {
+        let tracker = self.tracker.lock().unwrap();
+        ConnectivityChange {
+            online: tracker.online,
+            since: tracker.since,
+        }
+    }
+
+    pub fn observe(&self, online: bool) -> Option<ConnectivityChange> {
+        self.observe_at(online, Instant::now())
+    }
+
+    // 记录一次探测结果；新状态持续超过防抖时间才切换并发送事件
+    pub fn observe_at(&self, online: bool, at: Instant) -> Option<ConnectivityChange> {
+        let change = {
+            let mut tracker = self.tracker.lock().unwrap();
+            if online == tracker.online {
+                tracker.candidate = None;
+                return None;
+            }
+
+            match tracker.candidate {
+                Some((candidate, first_seen)) if candidate == online => {
+                    if at.saturating_duration_since(first_seen) < self.debounce {
+                        return None;
+                    }
+                }
+                _ => {
+                    tracker.candidate = Some((online, at));
+                    if !self.debounce.is_zero() {
+                        return None;
+                    }
+                }
+            }
+
+            tracker.online = online;
+            tracker.since = Utc::now();
+            tracker.candidate = None;
+            ConnectivityChange {
+                online,
+                since: tracker.since,
+            }
+        };
+
+        tracing::info!("Connectivity changed: {}", if change.online { "online" } else { "offline" });
+        let _ = self.event_sender.send(change.clone());
+        Some(change)
+    }
+
+    pub fn offline_state(&self, message_dao: &MessageDao, sync_state_dao: &SyncStateDao) -> Result<OfflineState> {
+        let current = self.current();
+        let queued_messages = message_dao.count_unsynced_messages().map_err(|e| anyhow!(e))?;
+
+        let mut pending_sync_entities = Vec::new();
+        for entity in SyncEntity::ALL {
+            let watermark = sync_state_dao
+                .get_watermark(entity.as_str())
+                .map_err(|e| anyhow!(e.to_string()))?;
+            if !matches!(watermark, Some(last_sync) if last_sync >= current.since) {
+                pending_sync_entities.push(entity.as_str().to_string());
+            }
+        }
+
+        Ok(OfflineState {
+            online: current.online,
+            since: current.since,
+            queued_messages,
+            pending_sync_entities,
+        })
+    }
+}
+
+impl Drop for OfflineStateService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use async_trait::async_trait;
+    use rusqlite::Connection;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    struct FixedProbe {
+        online: AtomicBool,
+    }
+
+    #[async_trait]
+    impl ConnectivityProbe for FixedProbe {
+        async fn is_online(&self) -> bool {
+            self.online.load(Ordering::SeqCst)
+        }
+    }
+
+    fn service(debounce: Duration) -> (OfflineStateService, Arc<FixedProbe>, mpsc::UnboundedReceiver<ConnectivityChange>) {
+        let probe = Arc::new(FixedProbe {
+            online: AtomicBool::new(true),
+        });
+        let (service, events) = OfflineStateService::with_timing(probe.clone(), Duration::from_millis(10), debounce);
+        (service, probe, events)
+    }
+
+    #[test]
+    fn test_transition_reported_after_debounce() {
+        let (service, _, mut events) = service(Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert!(service.observe_at(false, start).is_none());
+        assert!(service.observe_at(false, start + Duration::from_secs(5)).is_none());
+        assert!(service.is_online());
+
+        let change = service.observe_at(false, start + Duration::from_secs(10)).unwrap();
+        assert!(!change.online);
+        assert!(!service.is_online());
+        assert_eq!(events.try_recv().unwrap(), change);
+        assert!(events.try_recv().is_err());
+
+        // 保持离线不会重复通知
+        assert!(service.observe_at(false, start + Duration::from_secs(30)).is_none());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_flapping_network_does_not_emit() {
+        let (service, _, mut events) = service(Duration::from_secs(10));
+        let start = Instant::now();
+
+        // 每次离线都在防抖时间内恢复，计时重新开始
+        for i in 0..10 {
+            let at = start + Duration::from_secs(i * 4);
+            service.observe_at(i % 2 == 1, at);
+        }
+        assert!(service.is_online());
+        assert!(events.try_recv().is_err());
+
+        assert!(service.observe_at(false, start + Duration::from_secs(100)).is_none());
+        assert!(service.observe_at(true, start + Duration::from_secs(105)).is_none());
+        assert!(service.observe_at(false, start + Duration::from_secs(108)).is_none());
+        assert!(service.observe_at(false, start + Duration::from_secs(117)).is_none());
+        assert!(service.observe_at(false, start + Duration::from_secs(118)).is_some());
+        assert!(!events.try_recv().unwrap().online);
+    }
+
+    #[tokio::test]
+    async fn test_probe_loop_reports_transitions() {
+        let (service, probe, mut events) = service(Duration::from_millis(50));
+        let service = Arc::new(service);
+        service.start();
+
+        probe.online.store(false, Ordering::SeqCst);
+        let change = tokio::time::timeout(Duration::from_secs(2), events.recv()).await.unwrap().unwrap();
+        assert!(!change.online);
+
+        probe.online.store(true, Ordering::SeqCst);
+        let change = tokio::time::timeout(Duration::from_secs(2), events.recv()).await.unwrap().unwrap();
+        assert!(change.online);
+        assert!(service.is_online());
+        service.stop();
+    }
+
+    #[test]
+    fn test_offline_state_counts_queue_and_pending_entities() {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');
+             INSERT INTO messages (id, consultation_id, sender_type, message_type, content, timestamp, sync_status) VALUES
+                 ('m1', 'c1', 'doctor', 'text', '您好', '2024-03-01 09:00:00+00:00', 'pending'),
+                 ('m2', 'c1', 'doctor', 'text', '请稍等', '2024-03-01 09:01:00+00:00', 'pending'),
+                 ('m3', 'c1', 'patient', 'text', '好的', '2024-03-01 08:59:00+00:00', 'synced');",
+        )
+        .unwrap();
+        let connection = Arc::new(Mutex::new(conn));
+        let message_dao = MessageDao::with_connection(connection.clone());
+        let sync_state_dao = SyncStateDao::with_connection(connection);
+
+        let (service, _, _events) = service(Duration::ZERO);
+        service.observe_at(false, Instant::now());
+        let went_offline = service.current().since;
+
+        // 离线前同步过的实体仍算待同步
+        sync_state_dao.set_watermark("patients", went_offline - chrono::Duration::minutes(1)).unwrap();
+        let state = service.offline_state(&message_dao, &sync_state_dao).unwrap();
+        assert!(!state.online);
+        assert_eq!(state.queued_messages, 2);
+        assert_eq!(state.pending_sync_entities, vec!["patients", "consultations", "messages", "intake_forms", "doctors"]);
+
+        // 恢复在线后完成同步的实体不再待同步
+        service.observe_at(true, Instant::now());
+        let online_since = service.current().since;
+        sync_state_dao.set_watermark("patients", online_since + chrono::Duration::seconds(1)).unwrap();
+        let state = service.offline_state(&message_dao, &sync_state_dao).unwrap();
+        assert!(state.online);
+        assert_eq!(state.pending_sync_entities, vec!["consultations", "messages", "intake_forms", "doctors"]);
+
+        assert_eq!(message_dao.queue_position("m1").unwrap(), Some(1));
+        assert_eq!(message_dao.queue_position("m2").unwrap(), Some(2));
+        assert_eq!(message_dao.queue_position("m3").unwrap(), None);
+    }
+}
@@ -0,0 +1,564 @@
+// 消息发件箱分发：按登记顺序发送发件箱中的消息，收到服务器确认后才删除；
+// 崩溃或重启后未确认的消息用原幂等键重发，由服务器去重
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, MessageDao, OutboxDao};
+use crate::models::OutboxEntry;
+use crate::services::{LatencyRecorder, LatencySample, QueuedMessage, WebSocketManager};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+pub const MESSAGE_ACKNOWLEDGED_EVENT: &str = "message-acknowledged";
+
+const DISPATCH_INTERVAL: Duration = Duration::from_secs(5);
+// 发出后这么久仍未确认，用同一幂等键重发
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+const DISPATCH_BATCH_SIZE: usize = 50;
+
+#[async_trait]
+pub trait OutboxTransport: Send + Sync {
+    async fn send(&self, message: QueuedMessage) -> Result<()>;
+}
+
+// 通过已建立的 WebSocket 连接发送
+pub struct WebSocketTransport {
+    websocket: Arc<tokio::sync::Mutex<WebSocketManager>>,
+}
+
+impl WebSocketTransport {
+    pub fn new(websocket: Arc<tokio::sync::Mutex<WebSocketManager>>) -> Self {
+        Self { websocket }
+    }
+}
+
+#[async_trait]
+impl OutboxTransport for WebSocketTransport {
+    async fn send(&self, message: QueuedMessage) -> Result<()> {
+        self.websocket.lock().await.dispatch_message(&message).await
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DispatchReport {
+    pub sent: usize,
+    // 已发出、尚未超过确认等待时间的消息
+    pub awaiting_ack: usize,
+    // 发送失败时停止本轮分发，保留后续消息的顺序
+    pub failed: Option<String>,
+}
+
+pub struct OutboxDispatcher {
+    // 应用启动时数据库尚未打开，未指定时使用时再取全局连接
+    connection: Option<DbConnection>,
+    transport: Arc<dyn OutboxTransport>,
+    ack_timeout: Duration,
+    // 本实例发出、等待确认的消息及发出时间；重启后为空，未确认的消息全部重发
+    in_flight: Mutex<HashMap<String, Instant>>,
+    // 收到确认时上报从写入发件箱到确认的往返延迟
+    latency_recorder: Option<LatencyRecorder>,
+    wake: Notify,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl OutboxDispatcher {
+    pub fn new(transport: Arc<dyn OutboxTransport>) -> Self {
+        Self::build(None, transport, ACK_TIMEOUT)
+    }
+
+    pub fn with_connection(connection: DbConnection, transport: Arc<dyn OutboxTransport>, ack_timeout: Duration) -> Self {
+        Self::build(Some(connection), transport, ack_timeout)
+    }
+
+    fn build(connection: Option<DbConnection>, transport: Arc<dyn OutboxTransport>, ack_timeout: Duration) -> Self {
+        Self {
+            connection,
+            transport,
+            ack_timeout,
+            in_flight: Mutex::new(HashMap::new()),
+            latency_recorder: None,
+            wake: Notify::new(),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn with_latency_recorder(mut self, recorder: LatencyRecorder) -> Self {
+        self.latency_recorder = Some(recorder);
+        self
+    }
+
+    // 需在 tokio 运行时内调用；启动时立即分发，重发上次运行未确认的消息
+    pub fn start(self: &Arc<Self>) {
+        let mut task = self.task.lock().unwrap();
+        if task.is_none() {
+            let dispatcher = self.clone();
+            *task = Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(DISPATCH_INTERVAL);
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = dispatcher.wake.notified() => {}
+                    }
+                    match dispatcher.dispatch_pending().await {
+                        Ok(report) => {
+                            if let Some(error) = report.failed {
+                                tracing::debug!("Outbox dispatch paused after {} sent: {}", report.sent, error);
+                            }
+                        }
+                        Err(e) => tracing::error!("Outbox dispatch failed: {}", e),
+                    }
+                }
+            }));
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    // 新消息写入发件箱后立即分发，不必等待下一轮
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    fn connection(&self) -> DbConnection {
+        self.connection
+            .clone()
+            .unwrap_or_else(|| get_database().get_connection())
+    }
+
+    pub async fn dispatch_pending(&self) -> Result<DispatchReport> {
+        self.dispatch_pending_at(Instant::now()).await
+    }
+
+    pub async fn dispatch_pending_at(&self, now: Instant) -> Result<DispatchReport> {
+        let connection = self.connection();
+        let outbox_dao = OutboxDao::with_connection(connection.clone());
+        let message_dao = MessageDao::with_connection(connection);
+        let entries = outbox_dao.find_pending(DISPATCH_BATCH_SIZE).map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut report = DispatchReport::default();
+        for entry in entries {
+            if self.awaiting_ack(&entry.message_id, now) {
+                report.awaiting_ack += 1;
+                continue;
+            }
+            let Some(message) = self.queued_message(&message_dao, &entry)? else {
+                continue;
+            };
+
+            match self.transport.send(message).await {
+                Ok(()) => {
+                    outbox_dao
+                        .record_attempt(&entry.message_id, None)
+                        .map_err(|e| anyhow!(e.to_string()))?;
+                    self.in_flight.lock().unwrap().insert(entry.message_id.clone(), now);
+                    report.sent += 1;
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    outbox_dao
+                        .record_attempt(&entry.message_id, Some(&error))
+                        .map_err(|e| anyhow!(e.to_string()))?;
+                    report.failed = Some(error);
+                    break;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    // 服务器确认收到后删除发件箱记录，返回对应的消息 ID；重复的确认返回 None
+    pub fn acknowledge(&self, idempotency_key: &str) -> Result<Option<String>> {
+        self.acknowledge_at(idempotency_key, Utc::now())
+    }
+
+    // received_at 为收到确认帧的时间，与发件箱记录的写入时间之差即为往返延迟
+    pub fn acknowledge_at(&self, idempotency_key: &str, received_at: DateTime<Utc>) -> Result<Option<String>> {
+        let entry = OutboxDao::with_connection(self.connection())
+            .acknowledge(idempotency_key)
+            .map_err(|e| anyhow!(e.to_string()))?;
+        let Some(entry) = entry else {
+            return Ok(None);
+        };
+
+        self.in_flight.lock().unwrap().remove(&entry.message_id);
+        if let Some(recorder) = &self.latency_recorder {
+            recorder.record(LatencySample {
+                recorded_at: received_at,
+                latency_ms: (received_at - entry.created_at).num_milliseconds().max(0) as u64,
+            });
+        }
+        tracing::debug!("Outbox message {} acknowledged", entry.message_id);
+        Ok(Some(entry.message_id))
+    }
+
+    fn awaiting_ack(&self, message_id: &str, now: Instant) -> bool {
+        matches!(
+            self.in_flight.lock().unwrap().get(message_id),
+            Some(sent_at) if now.saturating_duration_since(*sent_at) < self.ack_timeout
+        )
+    }
+
+    fn queued_message(&self, message_dao: &MessageDao, entry: &OutboxEntry) -> Result<Option<QueuedMessage>> {
+        let message = message_dao
+            .find_by_id(&entry.message_id)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(message.map(|message| QueuedMessage {
+            id: message.id,
+            consultation_id: message.consultation_id,
+            message_type: message.message_type,
+            content: message.content.unwrap_or_default(),
+            file_path: message.file_path,
+            retry_count: entry.attempts,
+            created_at: message.timestamp,
+            idempotency_key: Some(entry.idempotency_key.clone()),
+        }))
+    }
+}
+
+impl Drop for OutboxDispatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::file_cache_dao::PIN_REASON_OUTBOX;
+    use crate::database::dao::FileCacheDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{FileCache, Message, MessageType, ReadStatus, SenderType, SyncStatus};
+    use crate::services::MetricsService;
+    use chrono::Utc;
+    use rusqlite::Connection;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // 模拟服务器：记录收到的每一帧，并按幂等键去重后保存消息
+    #[derive(Default)]
+    struct FakeServer {
+        frames: Mutex<Vec<QueuedMessage>>,
+        delivered: Mutex<Vec<String>>,
+        seen_keys: Mutex<HashSet<String>>,
+        offline: AtomicBool,
+    }
+
+    impl FakeServer {
+        fn frames(&self) -> Vec<QueuedMessage> {
+            self.frames.lock().unwrap().clone()
+        }
+
+        fn delivered(&self) -> Vec<String> {
+            self.delivered.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl OutboxTransport for FakeServer {
+        async fn send(&self, message: QueuedMessage) -> Result<()> {
+            if self.offline.load(Ordering::SeqCst) {
+                return Err(anyhow!("WebSocket 未连接"));
+            }
+            let key = message.idempotency_key.clone().expect("outbox frames carry an idempotency key");
+            if self.seen_keys.lock().unwrap().insert(key) {
+                self.delivered.lock().unwrap().push(message.id.clone());
+            }
+            self.frames.lock().unwrap().push(message);
+            Ok(())
+        }
+    }
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');",
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn send(connection: &DbConnection, id: &str, content: &str) -> OutboxEntry {
+        create_outgoing(connection, text_message(id, content))
+    }
+
+    // 引用已上传文件的消息，file_path 为上传时保存的本地路径
+    fn send_file(connection: &DbConnection, id: &str, file_path: &str) -> OutboxEntry {
+        let mut message = text_message(id, "");
+        message.message_type = MessageType::File;
+        message.content = None;
+        message.file_path = Some(file_path.to_string());
+        create_outgoing(connection, message)
+    }
+
+    fn create_outgoing(connection: &DbConnection, message: Message) -> OutboxEntry {
+        MessageDao::with_connection(connection.clone())
+            .create_outgoing(&message, Some("d1"))
+            .unwrap()
+    }
+
+    fn text_message(id: &str, content: &str) -> Message {
+        Message {
+            id: id.to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type: SenderType::Doctor,
+            message_type: MessageType::Text,
+            content: Some(content.to_string()),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now(),
+            sync_status: SyncStatus::Pending,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        }
+    }
+
+    // 已过期的上传缓存记录，不固定时任何清理都会删除
+    fn cache_upload(connection: &DbConnection, name: &str) -> (String, String) {
+        let local_path = format!("/tmp/uploads/{}", name);
+        let id = FileCacheDao::with_connection(connection.clone())
+            .create(&FileCache {
+                id: String::new(),
+                file_url: format!("https://files.example.com/{}", name),
+                local_path: local_path.clone(),
+                file_size: Some(2048),
+                mime_type: Some("application/pdf".to_string()),
+                checksum: None,
+                expires_at: Some(Utc::now() - chrono::Duration::days(1)),
+                downloaded_at: Utc::now(),
+                last_accessed: Utc::now(),
+                pinned: false,
+                thumbnail_path: None,
+                bytes_downloaded: 2048,
+                preview_path: None,
+                preview_text: None,
+                consultation_id: Some("c1".to_string()),
+                original_size: None,
+            })
+            .unwrap();
+        (id, local_path)
+    }
+
+    // 依次执行所有不强制的清理路径，返回被删除的缓存记录数
+    fn aggressive_cleanup(dao: &FileCacheDao) -> usize {
+        dao.cleanup_expired(false).unwrap().len()
+            + dao.cleanup_old_files(-1, false).unwrap()
+            + dao.cleanup_lru(0, false).unwrap().len()
+            + dao.cleanup_oversized(0, false).unwrap().len()
+            + dao.clear_all(false).unwrap().len()
+    }
+
+    fn dispatcher(connection: &DbConnection, server: &Arc<FakeServer>) -> OutboxDispatcher {
+        OutboxDispatcher::with_connection(connection.clone(), server.clone(), Duration::from_secs(30))
+    }
+
+    #[tokio::test]
+    async fn test_crash_before_ack_redelivers_with_same_key() {
+        let connection = create_test_connection();
+        let server = Arc::new(FakeServer::default());
+        let entry = send(&connection, "m1", "请按时服药");
+
+        // 发出后、收到确认前崩溃
+        let first = dispatcher(&connection, &server);
+        assert_eq!(first.dispatch_pending().await.unwrap().sent, 1);
+        drop(first);
+
+        // 重启后的新实例用同一幂等键重发，服务器只保存一次
+        let restarted = dispatcher(&connection, &server);
+        assert_eq!(restarted.dispatch_pending().await.unwrap().sent, 1);
+        let frames = server.frames();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|f| f.idempotency_key.as_deref() == Some(entry.idempotency_key.as_str())));
+        assert_eq!(server.delivered(), vec!["m1"]);
+
+        assert_eq!(restarted.acknowledge(&entry.idempotency_key).unwrap().as_deref(), Some("m1"));
+        let outbox_dao = OutboxDao::with_connection(connection.clone());
+        assert_eq!(outbox_dao.count().unwrap(), 0);
+        let message = MessageDao::with_connection(connection.clone()).find_by_id("m1").unwrap().unwrap();
+        assert!(matches!(message.sync_status, SyncStatus::Synced));
+
+        // 确认之后再重启不会重发，重复的确认也被忽略
+        let after_ack = dispatcher(&connection, &server);
+        assert_eq!(after_ack.dispatch_pending().await.unwrap(), DispatchReport::default());
+        assert_eq!(after_ack.acknowledge(&entry.idempotency_key).unwrap(), None);
+        assert_eq!(server.frames().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ack_reports_round_trip_latency() {
+        let connection = create_test_connection();
+        let server = Arc::new(FakeServer::default());
+        let metrics = Arc::new(MetricsService::with_connection(connection.clone()));
+        metrics.start();
+        let dispatcher = dispatcher(&connection, &server).with_latency_recorder(metrics.recorder());
+        let entry = send(&connection, "m1", "您好");
+        dispatcher.dispatch_pending().await.unwrap();
+
+        let received_at = entry.created_at + chrono::Duration::milliseconds(1_800);
+        assert!(dispatcher.acknowledge_at(&entry.idempotency_key, received_at).unwrap().is_some());
+        // 重复确认不再计入
+        assert!(dispatcher.acknowledge_at(&entry.idempotency_key, received_at).unwrap().is_none());
+
+        let mut latency = metrics.get_message_latency_metrics(1, received_at).unwrap();
+        for _ in 0..50 {
+            if latency.sample_count > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            latency = metrics.get_message_latency_metrics(1, received_at).unwrap();
+        }
+        assert_eq!(latency.sample_count, 1);
+        let bucket = latency.buckets.iter().find(|b| b.count == 1).unwrap();
+        assert_eq!(bucket.upper_bound_ms, Some(2_500));
+    }
+
+    #[tokio::test]
+    async fn test_unacked_message_resent_only_after_timeout() {
+        let connection = create_test_connection();
+        let server = Arc::new(FakeServer::default());
+        send(&connection, "m1", "您好");
+        let dispatcher = dispatcher(&connection, &server);
+        let start = Instant::now();
+
+        assert_eq!(dispatcher.dispatch_pending_at(start).await.unwrap().sent, 1);
+        let report = dispatcher.dispatch_pending_at(start + Duration::from_secs(10)).await.unwrap();
+        assert_eq!((report.sent, report.awaiting_ack), (0, 1));
+
+        let report = dispatcher.dispatch_pending_at(start + Duration::from_secs(31)).await.unwrap();
+        assert_eq!(report.sent, 1);
+        assert_eq!(server.frames().len(), 2);
+        assert_eq!(server.delivered(), vec!["m1"]);
+        let entry = OutboxDao::with_connection(connection).find_by_message_id("m1").unwrap().unwrap();
+        assert_eq!(entry.attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_failure_keeps_order_and_records_error() {
+        let connection = create_test_connection();
+        let server = Arc::new(FakeServer::default());
+        send(&connection, "m1", "第一条");
+        send(&connection, "m2", "第二条");
+        let dispatcher = dispatcher(&connection, &server);
+
+        server.offline.store(true, Ordering::SeqCst);
+        let report = dispatcher.dispatch_pending().await.unwrap();
+        assert_eq!(report.sent, 0);
+        assert!(report.failed.is_some());
+        let outbox_dao = OutboxDao::with_connection(connection.clone());
+        let first = outbox_dao.find_by_message_id("m1").unwrap().unwrap();
+        assert_eq!(first.attempts, 1);
+        assert!(first.last_error.is_some());
+        // 第一条失败后不再尝试后面的消息
+        assert_eq!(outbox_dao.find_by_message_id("m2").unwrap().unwrap().attempts, 0);
+
+        server.offline.store(false, Ordering::SeqCst);
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap().sent, 2);
+        assert_eq!(server.delivered(), vec!["m1", "m2"]);
+    }
+
+    #[test]
+    fn test_message_and_outbox_written_atomically() {
+        let connection = create_test_connection();
+        let entry = send(&connection, "m1", "您好");
+        let outbox_dao = OutboxDao::with_connection(connection.clone());
+        assert_eq!(outbox_dao.find_by_message_id("m1").unwrap(), Some(entry.clone()));
+
+        // 同一消息再次写入沿用原幂等键
+        assert_eq!(send(&connection, "m1", "您好").idempotency_key, entry.idempotency_key);
+
+        connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER fail_outbox_insert BEFORE INSERT ON outbox
+                 BEGIN SELECT RAISE(ABORT, 'outbox insert failed'); END;",
+            )
+            .unwrap();
+        let message_dao = MessageDao::with_connection(connection.clone());
+        let mut message = message_dao.find_by_id("m1").unwrap().unwrap();
+        message.id = "m2".to_string();
+        assert!(message_dao.create_outgoing(&message, None).is_err());
+        assert!(message_dao.find_by_id("m2").unwrap().is_none());
+
+        // 发件箱中的消息不再由批量同步重复推送
+        assert!(message_dao.find_unsynced_messages().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queued_file_pinned_until_acknowledged() {
+        let connection = create_test_connection();
+        let server = Arc::new(FakeServer::default());
+        let (cache_id, local_path) = cache_upload(&connection, "report.pdf");
+        let entry = send_file(&connection, "m1", &local_path);
+        let cache_dao = FileCacheDao::with_connection(connection.clone());
+
+        let pinned = cache_dao.get_pinned_files().unwrap();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(
+            (pinned[0].cache_id.as_str(), pinned[0].reason.as_str(), pinned[0].ref_id.as_str()),
+            (cache_id.as_str(), PIN_REASON_OUTBOX, "m1")
+        );
+
+        // 离线期间的各种清理都不能删除待发送消息的附件
+        assert_eq!(aggressive_cleanup(&cache_dao), 0);
+        assert!(cache_dao.find_by_id(&cache_id).unwrap().is_some());
+
+        let dispatcher = dispatcher(&connection, &server);
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap().sent, 1);
+        assert_eq!(aggressive_cleanup(&cache_dao), 0);
+
+        assert!(dispatcher.acknowledge(&entry.idempotency_key).unwrap().is_some());
+        assert!(cache_dao.get_pinned_files().unwrap().is_empty());
+        let evicted = cache_dao.cleanup_lru(0, false).unwrap();
+        assert_eq!(evicted.iter().map(|f| f.id.as_str()).collect::<Vec<_>>(), vec![cache_id.as_str()]);
+        assert_eq!(cache_dao.find_consultation_usage("c1").unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_clearing_outbox_unpins_and_force_ignores_pins() {
+        let connection = create_test_connection();
+        let (first_id, first_path) = cache_upload(&connection, "first.pdf");
+        let (second_id, second_path) = cache_upload(&connection, "second.pdf");
+        send_file(&connection, "m1", &first_path);
+        send_file(&connection, "m2", &second_path);
+        let cache_dao = FileCacheDao::with_connection(connection.clone());
+        let outbox_dao = OutboxDao::with_connection(connection.clone());
+
+        // 只清空其他问诊时不影响本问诊的消息
+        assert_eq!(outbox_dao.clear(Some("c2")).unwrap(), 0);
+        assert_eq!(cache_dao.get_pinned_files().unwrap().len(), 2);
+
+        // 强制清理忽略固定，引用记录随缓存记录一并删除
+        let forced = cache_dao.cleanup_lru(1, true).unwrap();
+        assert_eq!(forced.len(), 1);
+        assert_eq!(cache_dao.get_pinned_files().unwrap().len(), 1);
+
+        assert_eq!(outbox_dao.clear(Some("c1")).unwrap(), 2);
+        assert_eq!(outbox_dao.count().unwrap(), 0);
+        assert!(cache_dao.get_pinned_files().unwrap().is_empty());
+        let message = MessageDao::with_connection(connection.clone()).find_by_id("m1").unwrap().unwrap();
+        assert!(matches!(message.sync_status, SyncStatus::Failed));
+
+        let remaining = cache_dao.clear_all(false).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert!([first_id, second_id].contains(&remaining[0].id));
+    }
+}
@@ -1,113 +1,940 @@
 // 患者服务
 
-use crate::models::Patient;
-use anyhow::Result;
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{
+    BaseDao, ConflictError, ConsultationDao, MedicalRecordDao, PageResult, PatientDao, PatientRevisionDao, TimelineDao,
+};
+use crate::database::query_optimizer::{query_cache_for, QueryCache, CACHE_TAG_PATIENTS};
+use crate::models::{
+    AppConfig, AuthProviderKind, ConsultationSummary, DataScope, PaginatedResponse, Patient, PatientDetail,
+    PatientQuery, PatientRevision, PatientRevisionField, TagUsage, TimelineEvent, TimelineEventType,
+};
+use crate::utils::ValidationService;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
 
-pub struct PatientService;
+// 详情页展示的最近病历条数
+const RECENT_MEDICAL_RECORD_LIMIT: usize = 10;
 
-impl PatientService {
-    pub fn new() -> Self {
-        Self
-    }
-
-    pub async fn get_patient_list(&self, page: u32, limit: u32, search: Option<&str>) -> Result<Vec<Patient>> {
-        // TODO: 实现从数据库获取患者列表
-        // 1. 构建查询条件
-        // 2. 执行分页查询
-        // 3. 返回结果
-
-        // 模拟数据
-        let patients = vec![
-            Patient {
-                id: "1".to_string(),
-                name: "李小明".to_string(),
-                age: Some(35),
-                gender: Some("male".to_string()),
-                phone: Some("138****1234".to_string()),
-                id_card: None,
-                tags: vec!["高血压".to_string(), "糖尿病".to_string()],
-                avatar_url: None,
-                last_sync: Some(chrono::Utc::now() - chrono::Duration::days(5)),
-                created_at: chrono::Utc::now() - chrono::Duration::days(30),
-                updated_at: chrono::Utc::now() - chrono::Duration::days(5),
-            },
-            Patient {
-                id: "2".to_string(),
-                name: "王小红".to_string(),
-                age: Some(28),
-                gender: Some("female".to_string()),
-                phone: Some("139****5678".to_string()),
-                id_card: None,
-                tags: vec!["孕期检查".to_string()],
-                avatar_url: None,
-                last_sync: Some(chrono::Utc::now() - chrono::Duration::days(1)),
-                created_at: chrono::Utc::now() - chrono::Duration::days(15),
-                updated_at: chrono::Utc::now() - chrono::Duration::days(1),
-            },
+const MAX_TIMELINE_PAGE_SIZE: u32 = 100;
+
+const MAX_REVISION_PAGE_SIZE: u32 = 100;
+
+const ALL_TAGS_CACHE_KEY: &str = "patients:tags";
+
+/// 远端患者数据源（医院 REST 接口）
+#[async_trait]
+pub trait PatientRemoteSource: Send + Sync {
+    async fn fetch_patients(&self, query: &PatientQuery) -> Result<Vec<Patient>>;
+
+    async fn fetch_patient(&self, patient_id: &str) -> Result<Option<Patient>>;
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteEnvelope<T> {
+    data: Option<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemotePatientPage {
+    items: Vec<Patient>,
+}
+
+pub struct HttpPatientSource {
+    client: reqwest::Client,
+    base_url: String,
+    // 当前登录会话的 token，按 Bearer 方式携带
+    token: String,
+}
+
+impl HttpPatientSource {
+    pub fn new(base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl PatientRemoteSource for HttpPatientSource {
+    async fn fetch_patients(&self, query: &PatientQuery) -> Result<Vec<Patient>> {
+        let mut params = vec![
+            ("page", query.page.to_string()),
+            ("pageSize", query.page_size.to_string()),
         ];
+        if let Some(keyword) = &query.keyword {
+            params.push(("keyword", keyword.clone()));
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/patients", self.base_url))
+            .bearer_auth(&self.token)
+            .query(&params)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let envelope: RemoteEnvelope<RemotePatientPage> = response.json().await?;
+        Ok(envelope.data.map(|page| page.items).unwrap_or_default())
+    }
+
+    async fn fetch_patient(&self, patient_id: &str) -> Result<Option<Patient>> {
+        let response = self
+            .client
+            .get(format!("{}/patients/{}", self.base_url, patient_id))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let envelope: RemoteEnvelope<Patient> = response.error_for_status()?.json().await?;
+        Ok(envelope.data)
+    }
+}
+
+pub struct PatientService {
+    patient_dao: PatientDao,
+    consultation_dao: ConsultationDao,
+    medical_record_dao: MedicalRecordDao,
+    timeline_dao: TimelineDao,
+    revision_dao: PatientRevisionDao,
+    remote: Option<Arc<dyn PatientRemoteSource>>,
+    staleness_threshold: Duration,
+    cache: Arc<QueryCache>,
+}
+
+impl PatientService {
+    // 只读写本地数据，不访问远端接口
+    pub fn new(config: &AppConfig) -> Self {
+        Self::with_session(config, None)
+    }
+
+    // 数据过期时用当前会话的 token 从远端刷新；模拟模式或未登录时不访问远端接口
+    pub fn with_session(config: &AppConfig, session_token: Option<String>) -> Self {
+        let remote: Option<Arc<dyn PatientRemoteSource>> = match (config.auth_provider, session_token) {
+            (AuthProviderKind::Http, Some(token)) => {
+                Some(Arc::new(HttpPatientSource::new(config.api_base_url.clone(), token)))
+            }
+            _ => None,
+        };
+
+        Self::with_connection(
+            get_database().get_connection(),
+            remote,
+            Duration::minutes(config.patient_staleness_minutes as i64),
+        )
+    }
+
+    pub fn with_connection(
+        connection: DbConnection,
+        remote: Option<Arc<dyn PatientRemoteSource>>,
+        staleness_threshold: Duration,
+    ) -> Self {
+        Self {
+            patient_dao: PatientDao::with_connection(connection.clone()),
+            consultation_dao: ConsultationDao::with_connection(connection.clone()),
+            medical_record_dao: MedicalRecordDao::with_connection(connection.clone()),
+            timeline_dao: TimelineDao::with_connection(connection.clone()),
+            revision_dao: PatientRevisionDao::with_connection(connection.clone()),
+            remote,
+            staleness_threshold,
+            cache: query_cache_for(&connection),
+        }
+    }
 
-        Ok(patients)
-    }
-
-    pub async fn get_patient_by_id(&self, patient_id: &str) -> Result<Option<Patient>> {
-        // TODO: 实现从数据库获取患者详情
-
-        // 模拟数据
-        if patient_id == "1" {
-            let patient = Patient {
-                id: patient_id.to_string(),
-                name: "李小明".to_string(),
-                age: Some(35),
-                gender: Some("male".to_string()),
-                phone: Some("138****1234".to_string()),
-                id_card: None,
-                tags: vec!["高血压".to_string(), "糖尿病".to_string()],
-                avatar_url: None,
-                last_sync: Some(chrono::Utc::now() - chrono::Duration::days(5)),
-                created_at: chrono::Utc::now() - chrono::Duration::days(30),
-                updated_at: chrono::Utc::now() - chrono::Duration::days(5),
-            };
-            Ok(Some(patient))
+    pub async fn get_patient_list(&self, query: &PatientQuery, scope: &DataScope) -> Result<PaginatedResponse<Patient>> {
+        let validation = ValidationService::validate_patient_query(query);
+        if !validation.is_valid {
+            let messages: Vec<String> = validation.errors.iter().map(|e| e.message.clone()).collect();
+            return Err(anyhow!(messages.join("; ")));
+        }
+
+        // 只缓存首页，翻页请求直接查库
+        let cache_key = if query.page == 1 {
+            serde_json::to_string(query)
+                .ok()
+                .map(|q| format!("patients:list:{}:{}", scope.cache_key(), q))
         } else {
-            Ok(None)
+            None
+        };
+        if let Some(cached) = cache_key.as_deref().and_then(|key| self.cache.get_as(key)) {
+            return Ok(cached);
+        }
+
+        let mut page = self.patient_dao.query_patients_in_scope(query, scope).map_err(dao_error)?;
+
+        if self.is_any_stale(&page.items) {
+            if let Some(remote) = &self.remote {
+                match remote.fetch_patients(query).await {
+                    Ok(patients) => {
+                        self.store_synced(&patients)?;
+                        page = self.patient_dao.query_patients_in_scope(query, scope).map_err(dao_error)?;
+                    }
+                    Err(e) => {
+                        // 远端不可用时返回本地数据
+                        tracing::warn!("Failed to refresh patient list from server, using local data: {}", e);
+                    }
+                }
+            }
+        }
+
+        let response = to_paginated_response(page);
+        if let Some(key) = cache_key {
+            self.cache.set_with_tags(&key, &response, &[CACHE_TAG_PATIENTS]);
+        }
+
+        Ok(response)
+    }
+
+    pub async fn get_patient_detail(&self, patient_id: &str, scope: &DataScope) -> Result<PatientDetail> {
+        // 其他医生的患者按不存在处理
+        if !self.patient_in_scope(patient_id, scope)? {
+            return Err(anyhow!("患者不存在"));
+        }
+
+        let mut patient = self.patient_dao.find_by_id(patient_id).map_err(dao_error)?;
+
+        let needs_refresh = match &patient {
+            Some(local) => self.is_stale(local),
+            None => true,
+        };
+
+        if needs_refresh {
+            if let Some(remote) = &self.remote {
+                match remote.fetch_patient(patient_id).await {
+                    Ok(Some(remote_patient)) => {
+                        self.store_synced(std::slice::from_ref(&remote_patient))?;
+                        patient = self.patient_dao.find_by_id(patient_id).map_err(dao_error)?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("Failed to refresh patient {} from server, using local data: {}", patient_id, e);
+                    }
+                }
+            }
+        }
+
+        let patient = patient.ok_or_else(|| anyhow!("患者不存在"))?;
+
+        let consultations = match scope.doctor_id() {
+            Some(doctor_id) => self.consultation_dao.find_by_patient_and_doctor(patient_id, doctor_id),
+            None => self.consultation_dao.find_by_patient_id(patient_id),
+        };
+        let consultation_history = consultations
+            .map_err(dao_error)?
+            .into_iter()
+            .map(|consultation| ConsultationSummary {
+                completed_at: if consultation.status == "completed" {
+                    Some(consultation.updated_at)
+                } else {
+                    None
+                },
+                id: consultation.id,
+                consultation_type: consultation.consultation_type,
+                status: consultation.status,
+                diagnosis: consultation.diagnosis,
+                created_at: consultation.created_at,
+            })
+            .collect();
+
+        let mut recent_medical_records = self
+            .medical_record_dao
+            .find_by_patient_id(patient_id)
+            .map_err(dao_error)?;
+        recent_medical_records.truncate(RECENT_MEDICAL_RECORD_LIMIT);
+
+        Ok(PatientDetail {
+            patient,
+            consultation_history,
+            follow_up_reminders: Vec::new(),
+            recent_medical_records,
+        })
+    }
+
+    // 未指定事件类型或为空时返回全部类型
+    pub async fn get_patient_timeline(
+        &self,
+        patient_id: &str,
+        page: u32,
+        page_size: u32,
+        event_types: Option<&[TimelineEventType]>,
+        scope: &DataScope,
+    ) -> Result<PaginatedResponse<TimelineEvent>> {
+        if !self.patient_in_scope(patient_id, scope)? {
+            return Err(anyhow!("患者不存在"));
+        }
+
+        let event_types = match event_types {
+            Some(types) if !types.is_empty() => types,
+            _ => &TimelineEventType::ALL[..],
+        };
+        let page = page.max(1);
+        let page_size = page_size.clamp(1, MAX_TIMELINE_PAGE_SIZE);
+
+        let result = self
+            .timeline_dao
+            .find_by_patient(patient_id, scope.doctor_id(), event_types, page as i32, page_size as i32)
+            .map_err(dao_error)?;
+        Ok(to_paginated_response(result))
+    }
+
+    // expected_version 为前端读取患者时的版本号，期间被其他窗口修改过则返回 ConflictError
+    pub async fn update_patient_tags(
+        &self,
+        patient_id: &str,
+        tags: Vec<String>,
+        expected_version: i64,
+        editor_id: Option<&str>,
+    ) -> Result<Patient> {
+        if self.patient_dao.find_by_id(patient_id).map_err(dao_error)?.is_none() {
+            return Err(anyhow!("患者不存在"));
+        }
+
+        for tag in &tags {
+            ValidationService::validate_tag(tag)?;
+        }
+
+        self.patient_dao
+            .update_tags(patient_id, &tags, expected_version, editor_id)
+            .map_err(write_error)?;
+        self.find_patient(patient_id)?.ok_or_else(|| anyhow!("患者不存在"))
+    }
+
+    // 患者资料修改记录，按修改时间倒序分页
+    pub fn get_patient_revisions(
+        &self,
+        patient_id: &str,
+        page: u32,
+        page_size: u32,
+        scope: &DataScope,
+    ) -> Result<PaginatedResponse<PatientRevision>> {
+        if !self.patient_in_scope(patient_id, scope)? {
+            return Err(anyhow!("患者不存在"));
+        }
+
+        let page = page.max(1);
+        let page_size = page_size.clamp(1, MAX_REVISION_PAGE_SIZE);
+        let result = self
+            .revision_dao
+            .find_by_patient(patient_id, page as i32, page_size as i32)
+            .map_err(dao_error)?;
+        Ok(to_paginated_response(result))
+    }
+
+    // 把某个字段恢复为指定修改之前的值，恢复本身作为一次新的修改记录
+    pub fn revert_patient_field(
+        &self,
+        patient_id: &str,
+        revision_id: &str,
+        field: PatientRevisionField,
+        editor_id: Option<&str>,
+        scope: &DataScope,
+    ) -> Result<Patient> {
+        let revision = self
+            .revision_dao
+            .find_by_id(revision_id)
+            .map_err(dao_error)?
+            .filter(|revision| revision.patient_id == patient_id)
+            .ok_or_else(|| anyhow!("修改记录不存在"))?;
+        let change = revision
+            .changes
+            .get(field.as_str())
+            .ok_or_else(|| anyhow!("该修改记录未修改字段 {}", field.as_str()))?;
+
+        let mut patient = self.find_patient_in_scope(patient_id, scope)?.ok_or_else(|| anyhow!("患者不存在"))?;
+        if field.value_of(&patient) == change.old {
+            return Ok(patient);
+        }
+
+        field.set_value(&mut patient, change.old.clone())?;
+        self.patient_dao.update_by(&patient, editor_id).map_err(write_error)?;
+        self.find_patient(patient_id)?.ok_or_else(|| anyhow!("患者不存在"))
+    }
+
+    // 直接读取本地患者，不触发远端刷新
+    pub fn find_patient(&self, patient_id: &str) -> Result<Option<Patient>> {
+        self.patient_dao.find_by_id(patient_id).map_err(dao_error)
+    }
+
+    // 只查本地数据，不在可见范围内的患者返回 None
+    pub fn find_patient_in_scope(&self, patient_id: &str, scope: &DataScope) -> Result<Option<Patient>> {
+        if !self.patient_in_scope(patient_id, scope)? {
+            return Ok(None);
+        }
+        self.find_patient(patient_id)
+    }
+
+    fn patient_in_scope(&self, patient_id: &str, scope: &DataScope) -> Result<bool> {
+        match scope.doctor_id() {
+            Some(doctor_id) => self
+                .consultation_dao
+                .has_patient_for_doctor(patient_id, doctor_id)
+                .map_err(dao_error),
+            None => Ok(true),
         }
     }
 
-    pub async fn update_patient_tags(&self, patient_id: &str, tags: Vec<String>) -> Result<()> {
-        // TODO: 实现更新患者标签
-        // 1. 验证患者存在
-        // 2. 更新标签
-        // 3. 记录更新时间
+    pub async fn get_all_tags(&self) -> Result<Vec<TagUsage>> {
+        if let Some(cached) = self.cache.get_as(ALL_TAGS_CACHE_KEY) {
+            return Ok(cached);
+        }
+
+        let tags = self.patient_dao.get_all_tags().map_err(dao_error)?;
+        self.cache.set_with_tags(ALL_TAGS_CACHE_KEY, &tags, &[CACHE_TAG_PATIENTS]);
+        Ok(tags)
+    }
+
+    pub async fn rename_tag(&self, old_tag: &str, new_tag: &str, user_id: Option<&str>) -> Result<usize> {
+        ValidationService::validate_tag(old_tag)?;
+        ValidationService::validate_tag(new_tag)?;
+        if old_tag == new_tag {
+            return Err(anyhow!("新标签不能与原标签相同"));
+        }
+
+        self.patient_dao.rename_tag(old_tag, new_tag, user_id).map_err(dao_error)
+    }
+
+    pub async fn merge_tags(&self, source_tags: Vec<String>, target_tag: &str, user_id: Option<&str>) -> Result<usize> {
+        ValidationService::validate_tag(target_tag)?;
+        for tag in &source_tags {
+            ValidationService::validate_tag(tag)?;
+        }
 
-        println!("Updating patient {} tags: {:?}", patient_id, tags);
+        let sources: Vec<String> = source_tags.into_iter().filter(|tag| tag != target_tag).collect();
+        if sources.is_empty() {
+            return Err(anyhow!("请至少选择一个需要合并的标签"));
+        }
+
+        self.patient_dao.merge_tags(&sources, target_tag, user_id).map_err(dao_error)
+    }
+
+    pub async fn search_patients(&self, keyword: &str, scope: &DataScope) -> Result<Vec<Patient>> {
+        let page = self
+            .patient_dao
+            .search_patients_in_scope(keyword, 1, 50, scope)
+            .map_err(dao_error)?;
+        Ok(page.items)
+    }
+
+    // 加密历史明文的手机号和身份证号，返回处理的患者数
+    pub fn encrypt_legacy_fields<F>(&self, batch_size: usize, progress: F) -> Result<usize>
+    where
+        F: FnMut(usize, usize),
+    {
+        self.patient_dao.encrypt_legacy_rows(batch_size, progress).map_err(dao_error)
+    }
+
+    fn is_stale(&self, patient: &Patient) -> bool {
+        match patient.last_sync {
+            Some(last_sync) => Utc::now() - last_sync > self.staleness_threshold,
+            None => true,
+        }
+    }
+
+    fn is_any_stale(&self, patients: &[Patient]) -> bool {
+        patients.is_empty() || patients.iter().any(|patient| self.is_stale(patient))
+    }
+
+    fn store_synced(&self, patients: &[Patient]) -> Result<()> {
+        let now = Utc::now();
+        for patient in patients {
+            let mut synced = patient.clone();
+            synced.last_sync = Some(now);
+            self.patient_dao.upsert(&synced).map_err(dao_error)?;
+        }
         Ok(())
     }
+}
 
-    pub async fn search_patients(&self, keyword: &str) -> Result<Vec<Patient>> {
-        // TODO: 实现患者搜索
-        // 1. 构建搜索条件（姓名、电话、标签等）
-        // 2. 执行模糊查询
-        // 3. 返回匹配结果
-
-        println!("Searching patients with keyword: {}", keyword);
-
-        // 模拟搜索结果
-        let results = vec![
-            Patient {
-                id: "1".to_string(),
-                name: "李小明".to_string(),
-                age: Some(35),
-                gender: Some("male".to_string()),
-                phone: Some("138****1234".to_string()),
-                id_card: None,
-                tags: vec!["高血压".to_string(), "糖尿病".to_string()],
-                avatar_url: None,
-                last_sync: Some(chrono::Utc::now() - chrono::Duration::days(5)),
-                created_at: chrono::Utc::now() - chrono::Duration::days(30),
-                updated_at: chrono::Utc::now() - chrono::Duration::days(5),
-            },
-        ];
+fn to_paginated_response<T>(page: PageResult<T>) -> PaginatedResponse<T> {
+    PaginatedResponse {
+        items: page.items,
+        total: page.total.max(0) as u32,
+        page: page.page.max(0) as u32,
+        page_size: page.page_size.max(0) as u32,
+        total_pages: page.total_pages.max(0) as u32,
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+// 保留乐观锁冲突的类型，命令层据此返回 STALE_WRITE
+fn write_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    match err.downcast::<ConflictError>() {
+        Ok(conflict) => anyhow::Error::new(*conflict),
+        Err(err) => dao_error(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Consultation, ConsultationPriority};
+    use rusqlite::Connection;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    struct FakeRemote {
+        patients: Vec<Patient>,
+        fail: bool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PatientRemoteSource for FakeRemote {
+        async fn fetch_patients(&self, _query: &PatientQuery) -> Result<Vec<Patient>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(anyhow!("server unavailable"));
+            }
+            Ok(self.patients.clone())
+        }
+
+        async fn fetch_patient(&self, patient_id: &str) -> Result<Option<Patient>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(anyhow!("server unavailable"));
+            }
+            Ok(self.patients.iter().find(|p| p.id == patient_id).cloned())
+        }
+    }
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn patient(id: &str, name: &str, last_sync_minutes_ago: Option<i64>) -> Patient {
+        Patient {
+            id: id.to_string(),
+            name: name.to_string(),
+            age: Some(40),
+            gender: Some("male".to_string()),
+            phone: Some("13800138000".to_string()),
+            id_card: None,
+            tags: vec!["高血压".to_string()],
+            avatar_url: None,
+            last_sync: last_sync_minutes_ago.map(|m| Utc::now() - Duration::minutes(m)),
+            last_visit: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
+
+    fn fake_remote(patients: Vec<Patient>, fail: bool) -> Arc<FakeRemote> {
+        Arc::new(FakeRemote {
+            patients,
+            fail,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    fn query() -> PatientQuery {
+        PatientQuery {
+            keyword: None,
+            tags: None,
+            gender: None,
+            age_range: None,
+            last_visit_range: None,
+            sort: None,
+            page: 1,
+            page_size: 20,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fresh_list_served_locally() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("p1", "张三", Some(5))).unwrap();
+
+        let remote = fake_remote(vec![patient("p1", "张三(远端)", None)], false);
+        let service = PatientService::with_connection(connection, Some(remote.clone()), Duration::minutes(30));
+
+        let result = service.get_patient_list(&query(), &DataScope::All).await.unwrap();
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].name, "张三");
+        assert_eq!(remote.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stale_list_refreshed_from_remote() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("p1", "张三", Some(120))).unwrap();
+
+        let remote = fake_remote(
+            vec![patient("p1", "张三(远端)", None), patient("p2", "李四", None)],
+            false,
+        );
+        let service = PatientService::with_connection(connection, Some(remote.clone()), Duration::minutes(30));
+
+        let result = service.get_patient_list(&query(), &DataScope::All).await.unwrap();
+        assert_eq!(remote.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(result.total, 2);
+        assert!(result.items.iter().any(|p| p.name == "张三(远端)"));
+        assert!(result.items.iter().all(|p| p.last_sync.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_stale_list_falls_back_when_remote_fails() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("p1", "张三", Some(120))).unwrap();
+
+        let remote = fake_remote(vec![], true);
+        let service = PatientService::with_connection(connection, Some(remote.clone()), Duration::minutes(30));
+
+        let result = service.get_patient_list(&query(), &DataScope::All).await.unwrap();
+        assert_eq!(remote.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].name, "张三");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_query_rejected() {
+        let service = PatientService::with_connection(create_test_connection(), None, Duration::minutes(30));
+        let mut invalid = query();
+        invalid.page_size = 0;
+
+        assert!(service.get_patient_list(&invalid, &DataScope::All).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_list_and_tags_invalidated_on_write() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        dao.upsert(&patient("p1", "张三", Some(5))).unwrap();
+        let service = PatientService::with_connection(connection.clone(), None, Duration::minutes(30));
+
+        assert_eq!(service.get_patient_list(&query(), &DataScope::All).await.unwrap().items[0].tags, vec!["高血压"]);
+        assert_eq!(service.get_all_tags().await.unwrap()[0].tag, "高血压");
+
+        // 绕过 DAO 的写入不会清缓存，仍返回缓存结果
+        connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE patients SET name = '张三丰' WHERE id = 'p1'", [])
+            .unwrap();
+        assert_eq!(service.get_patient_list(&query(), &DataScope::All).await.unwrap().items[0].name, "张三");
+
+        service.update_patient_tags("p1", vec!["糖尿病".to_string()], 1, None).await.unwrap();
+        let page = service.get_patient_list(&query(), &DataScope::All).await.unwrap();
+        assert_eq!(page.items[0].name, "张三丰");
+        assert_eq!(page.items[0].tags, vec!["糖尿病"]);
+        assert_eq!(service.get_all_tags().await.unwrap()[0].tag, "糖尿病");
+
+        // 另一个窗口仍持有旧版本
+        let stale = service.update_patient_tags("p1", vec!["高血压".to_string()], 1, None).await.unwrap_err();
+        assert!(crate::utils::AppError::from(stale).is_stale_write());
+    }
+
+    #[tokio::test]
+    async fn test_revert_patient_field() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        dao.upsert(&patient("p1", "张三", Some(5))).unwrap();
+        dao.upsert(&patient("p2", "李四", Some(5))).unwrap();
+        let service = PatientService::with_connection(connection, None, Duration::minutes(30));
+
+        let mut edited = service.find_patient("p1").unwrap().unwrap();
+        edited.name = "张三丰".to_string();
+        edited.phone = Some("13900139000".to_string());
+        dao.update_by(&edited, Some("d1")).unwrap();
+        let revision = service.get_patient_revisions("p1", 1, 20, &DataScope::All).unwrap().items.remove(0);
+
+        // 只恢复手机号，姓名保持修改后的值
+        let reverted = service
+            .revert_patient_field("p1", &revision.id, PatientRevisionField::Phone, Some("admin"), &DataScope::All)
+            .unwrap();
+        assert_eq!(reverted.phone.as_deref(), Some("13800138000"));
+        assert_eq!(reverted.name, "张三丰");
+
+        // 恢复本身记为一次新的修改
+        let revisions = service.get_patient_revisions("p1", 1, 20, &DataScope::All).unwrap();
+        assert_eq!(revisions.total, 2);
+        let latest = &revisions.items[0];
+        assert_eq!(latest.editor_id.as_deref(), Some("admin"));
+        assert_eq!(latest.changes.len(), 1);
+        assert_eq!(latest.changes["phone"].new, serde_json::json!("13800138000"));
+
+        // 该修改未涉及的字段、其他患者的修改记录不能恢复
+        assert!(service
+            .revert_patient_field("p1", &revision.id, PatientRevisionField::Age, None, &DataScope::All)
+            .is_err());
+        assert!(service
+            .revert_patient_field("p2", &revision.id, PatientRevisionField::Name, None, &DataScope::All)
+            .is_err());
+        assert_eq!(service.find_patient("p2").unwrap().unwrap().name, "李四");
+    }
+
+    #[tokio::test]
+    async fn test_detail_includes_consultations() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("p1", "张三", Some(5))).unwrap();
+        ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                status: "completed".to_string(),
+                consultation_type: "text".to_string(),
+                title: Some("复诊".to_string()),
+                description: None,
+                diagnosis: Some("高血压".to_string()),
+                prescription: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap();
+
+        let service = PatientService::with_connection(connection, None, Duration::minutes(30));
+        let detail = service.get_patient_detail("p1", &DataScope::All).await.unwrap();
+
+        assert_eq!(detail.patient.name, "张三");
+        assert_eq!(detail.consultation_history.len(), 1);
+        assert!(detail.consultation_history[0].completed_at.is_some());
+    }
+
+    fn consultation(patient_id: &str, doctor_id: &str) -> Consultation {
+        Consultation {
+            id: String::new(),
+            patient_id: patient_id.to_string(),
+            doctor_id: doctor_id.to_string(),
+            status: "active".to_string(),
+            consultation_type: "text".to_string(),
+            title: None,
+            description: None,
+            diagnosis: None,
+            prescription: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            accepted_at: None,
+            completed_at: None,
+            cancel_reason: None,
+            version: 1,
+            priority: ConsultationPriority::Normal,
+            queued_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_patients_scoped_by_doctor() {
+        let connection = create_test_connection();
+        let patients = PatientDao::with_connection(connection.clone());
+        patients.upsert(&patient("pa", "张三", Some(5))).unwrap();
+        patients.upsert(&patient("pb", "李四", Some(5))).unwrap();
+        patients.upsert(&patient("shared", "王五", Some(5))).unwrap();
+        let consultations = ConsultationDao::with_connection(connection.clone());
+        consultations.create(&consultation("pa", "doctor-a")).unwrap();
+        consultations.create(&consultation("pb", "doctor-b")).unwrap();
+        consultations.create(&consultation("shared", "doctor-a")).unwrap();
+        consultations.create(&consultation("shared", "doctor-b")).unwrap();
+
+        let service = PatientService::with_connection(connection, None, Duration::minutes(30));
+        let doctor_a = DataScope::Doctor("doctor-a".to_string());
+        let doctor_b = DataScope::Doctor("doctor-b".to_string());
+
+        let names = |page: PaginatedResponse<Patient>| {
+            let mut names: Vec<String> = page.items.into_iter().map(|p| p.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(service.get_patient_list(&query(), &doctor_a).await.unwrap()), vec!["张三", "王五"]);
+        // 首页缓存按范围区分，B 不能拿到 A 的缓存结果
+        assert_eq!(names(service.get_patient_list(&query(), &doctor_b).await.unwrap()), vec!["李四", "王五"]);
+        assert_eq!(service.get_patient_list(&query(), &DataScope::All).await.unwrap().total, 3);
+        assert!(service.search_patients("张三", &doctor_b).await.unwrap().is_empty());
+
+        assert!(service.get_patient_detail("pa", &doctor_b).await.is_err());
+        let detail = service.get_patient_detail("shared", &doctor_b).await.unwrap();
+        assert_eq!(detail.consultation_history.len(), 1);
+        assert_eq!(service.get_patient_detail("shared", &DataScope::All).await.unwrap().consultation_history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeline_scoped_and_unfiltered_by_default() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("pa", "张三", Some(5))).unwrap();
+        let consultations = ConsultationDao::with_connection(connection.clone());
+        consultations.create(&consultation("pa", "doctor-a")).unwrap();
+
+        let service = PatientService::with_connection(connection, None, Duration::minutes(30));
+        let doctor_a = DataScope::Doctor("doctor-a".to_string());
+        let doctor_b = DataScope::Doctor("doctor-b".to_string());
+
+        assert!(service.get_patient_timeline("pa", 1, 20, None, &doctor_b).await.is_err());
+
+        let timeline = service.get_patient_timeline("pa", 0, 0, Some(&[]), &doctor_a).await.unwrap();
+        assert_eq!(timeline.total, 1);
+        assert_eq!(timeline.page, 1);
+        assert_eq!(timeline.page_size, 1);
+        assert_eq!(timeline.items[0].event_type, TimelineEventType::ConsultationStarted);
+    }
+
+    #[tokio::test]
+    async fn test_detail_fetched_from_remote_when_missing_locally() {
+        let connection = create_test_connection();
+        let remote = fake_remote(vec![patient("p9", "王五", None)], false);
+        let service = PatientService::with_connection(connection.clone(), Some(remote), Duration::minutes(30));
+
+        let detail = service.get_patient_detail("p9", &DataScope::All).await.unwrap();
+        assert_eq!(detail.patient.name, "王五");
+
+        let stored = PatientDao::with_connection(connection).find_by_id("p9").unwrap();
+        assert!(stored.unwrap().last_sync.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_detail_missing_everywhere() {
+        let service = PatientService::with_connection(
+            create_test_connection(),
+            Some(fake_remote(vec![], false)),
+            Duration::minutes(30),
+        );
+
+        assert!(service.get_patient_detail("unknown", &DataScope::All).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_is_parameterized() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        dao.upsert(&patient("p1", "张三", Some(5))).unwrap();
+        dao.upsert(&patient("p2", "O'Brien", Some(5))).unwrap();
+
+        let service = PatientService::with_connection(connection, None, Duration::minutes(30));
+        assert_eq!(service.search_patients("O'Brien", &DataScope::All).await.unwrap().len(), 1);
+        assert_eq!(service.search_patients("' OR '1'='1", &DataScope::All).await.unwrap().len(), 0);
+        assert_eq!(service.search_patients("%", &DataScope::All).await.unwrap().len(), 0);
+    }
+
+    fn tagged_patient(id: &str, tags: &[&str]) -> Patient {
+        let mut p = patient(id, id, Some(5));
+        p.tags = tags.iter().map(|t| t.to_string()).collect();
+        p
+    }
+
+    fn audit_count(connection: &DbConnection, action: &str) -> i64 {
+        connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM audit_logs WHERE action = ?1", [action], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_all_tags_counts() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        dao.upsert(&tagged_patient("p1", &["高血压", "糖尿病"])).unwrap();
+        dao.upsert(&tagged_patient("p2", &["高血压"])).unwrap();
+
+        let service = PatientService::with_connection(connection, None, Duration::minutes(30));
+        let tags = service.get_all_tags().await.unwrap();
+
+        assert_eq!(tags[0].tag, "高血压");
+        assert_eq!(tags[0].count, 2);
+        assert_eq!(tags[1].tag, "糖尿病");
+        assert_eq!(tags[1].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rename_tag_without_duplicates() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        dao.upsert(&tagged_patient("p1", &["高血压", "高血压病", "糖尿病"])).unwrap();
+        dao.upsert(&tagged_patient("p2", &["高血压"])).unwrap();
+        dao.upsert(&tagged_patient("p3", &["感冒"])).unwrap();
+
+        let service = PatientService::with_connection(connection.clone(), None, Duration::minutes(30));
+        let touched = service.rename_tag("高血压", "高血压病", Some("1")).await.unwrap();
+        assert_eq!(touched, 2);
+
+        let p1 = dao.find_by_id("p1").unwrap().unwrap();
+        assert_eq!(p1.tags, vec!["高血压病".to_string(), "糖尿病".to_string()]);
+        let p2 = dao.find_by_id("p2").unwrap().unwrap();
+        assert_eq!(p2.tags, vec!["高血压病".to_string()]);
+        let p3 = dao.find_by_id("p3").unwrap().unwrap();
+        assert_eq!(p3.tags, vec!["感冒".to_string()]);
+
+        assert_eq!(audit_count(&connection, "rename_tag"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_merge_tags() {
+        let connection = create_test_connection();
+        let dao = PatientDao::with_connection(connection.clone());
+        dao.upsert(&tagged_patient("p1", &["糖尿病", "II型糖尿病", "2型糖尿病"])).unwrap();
+        dao.upsert(&tagged_patient("p2", &["2型糖尿病"])).unwrap();
+
+        let service = PatientService::with_connection(connection.clone(), None, Duration::minutes(30));
+        let touched = service
+            .merge_tags(vec!["II型糖尿病".to_string(), "2型糖尿病".to_string()], "糖尿病", None)
+            .await
+            .unwrap();
+        assert_eq!(touched, 2);
+
+        assert_eq!(dao.find_by_id("p1").unwrap().unwrap().tags, vec!["糖尿病".to_string()]);
+        assert_eq!(dao.find_by_id("p2").unwrap().unwrap().tags, vec!["糖尿病".to_string()]);
+        assert_eq!(audit_count(&connection, "merge_tags"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rename_tag_rejects_invalid_target() {
+        let service = PatientService::with_connection(create_test_connection(), None, Duration::minutes(30));
+        assert!(service.rename_tag("高血压", "", None).await.is_err());
+        assert!(service.rename_tag("高血压", "高血压", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_source_sends_session_token() {
+        let mut server = mockito::Server::new_async().await;
+        let patient = r#"{"id":"p1","name":"张三","age":null,"gender":null,"phone":null,"idCard":null,"tags":[],"avatarUrl":null,"lastSync":null,"createdAt":"2024-01-01T00:00:00Z","updatedAt":"2024-01-01T00:00:00Z"}"#;
+        let list = server
+            .mock("GET", "/patients")
+            .match_query(mockito::Matcher::Any)
+            .match_header("authorization", "Bearer session-token")
+            .with_status(200)
+            .with_body(format!(r#"{{"data":{{"items":[{}]}}}}"#, patient))
+            .create_async()
+            .await;
+        let detail = server
+            .mock("GET", "/patients/p1")
+            .match_header("authorization", "Bearer session-token")
+            .with_status(200)
+            .with_body(format!(r#"{{"data":{}}}"#, patient))
+            .create_async()
+            .await;
 
-        Ok(results)
+        let source = HttpPatientSource::new(server.url(), "session-token");
+        let patients = source.fetch_patients(&query()).await.unwrap();
+        assert_eq!(patients.len(), 1);
+        assert_eq!(source.fetch_patient("p1").await.unwrap().unwrap().name, "张三");
+        list.assert_async().await;
+        detail.assert_async().await;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,618 @@
+// 单个患者数据导出 / 导入（交接用数据包）
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::message_dao::waveform_json;
+use crate::database::dao::{
+    publish_data_changes, BaseDao, ChangeLogDao, ConsultationDao, FileCacheDao, MedicalRecordDao, MessageDao, PatientDao,
+    ProtectedFields,
+};
+use crate::database::query_optimizer::{query_cache_for, CACHE_TAG_MESSAGES, CACHE_TAG_PATIENTS};
+use crate::models::{ChangeEntity, Consultation, MedicalRecord, Message, Patient};
+use crate::utils::{mask_id_card, mask_phone, SqlTimestamp, ValidationService};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// 数据包格式版本
+const BUNDLE_VERSION: u32 = 1;
+const BUNDLE_JSON_NAME: &str = "patient.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleFormat {
+    Json,
+    Zip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsultationBundle {
+    #[serde(flatten)]
+    pub consultation: Consultation,
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientBundle {
+    pub version: u32,
+    #[serde(rename = "exportedAt")]
+    pub exported_at: DateTime<Utc>,
+    pub masked: bool,
+    pub patient: Patient,
+    pub consultations: Vec<ConsultationBundle>,
+    #[serde(rename = "medicalRecords")]
+    pub medical_records: Vec<MedicalRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleExportResult {
+    pub path: String,
+    #[serde(rename = "consultationCount")]
+    pub consultation_count: usize,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+    #[serde(rename = "recordCount")]
+    pub record_count: usize,
+    #[serde(rename = "attachmentCount")]
+    pub attachment_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleImportResult {
+    #[serde(rename = "patientId")]
+    pub patient_id: String,
+    #[serde(rename = "consultationCount")]
+    pub consultation_count: usize,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+    #[serde(rename = "recordCount")]
+    pub record_count: usize,
+}
+
+pub struct PatientBundleService {
+    connection: DbConnection,
+}
+
+impl PatientBundleService {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn collect_bundle(&self, patient_id: &str, mask_sensitive: bool) -> Result<PatientBundle> {
+        let mut patient = PatientDao::with_connection(self.connection.clone())
+            .find_by_id(patient_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .ok_or_else(|| anyhow!("患者不存在"))?;
+
+        if mask_sensitive {
+            patient.phone = patient.phone.as_deref().map(mask_phone);
+            patient.id_card = patient.id_card.as_deref().map(mask_id_card);
+        }
+
+        let message_dao = MessageDao::with_connection(self.connection.clone());
+        let mut consultations = Vec::new();
+        for consultation in ConsultationDao::with_connection(self.connection.clone())
+            .find_by_patient_id(patient_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+        {
+            let messages = message_dao
+                .find_all_by_consultation_id(&consultation.id)
+                .map_err(|e| anyhow!(e))?;
+            consultations.push(ConsultationBundle { consultation, messages });
+        }
+
+        let medical_records = MedicalRecordDao::with_connection(self.connection.clone())
+            .find_by_patient_id(patient_id)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(PatientBundle {
+            version: BUNDLE_VERSION,
+            exported_at: Utc::now(),
+            masked: mask_sensitive,
+            patient,
+            consultations,
+            medical_records,
+        })
+    }
+
+    pub fn export_bundle(
+        &self,
+        patient_id: &str,
+        format: BundleFormat,
+        output_dir: &Path,
+        mask_sensitive: bool,
+    ) -> Result<BundleExportResult> {
+        let bundle = self.collect_bundle(patient_id, mask_sensitive)?;
+        std::fs::create_dir_all(output_dir)?;
+
+        let file_stem = format!(
+            "patient_{}_{}",
+            ValidationService::sanitize_filename(patient_id),
+            bundle.exported_at.format("%Y%m%d%H%M%S")
+        );
+        let json = serde_json::to_vec_pretty(&bundle)?;
+
+        let (path, attachment_count) = match format {
+            BundleFormat::Json => {
+                let path = output_dir.join(format!("{}.json", file_stem));
+                std::fs::write(&path, &json)?;
+                (path, 0)
+            }
+            BundleFormat::Zip => {
+                let path = output_dir.join(format!("{}.zip", file_stem));
+                let attachments = self.collect_attachment_files(&bundle)?;
+                write_zip(&path, &json, &attachments)?;
+                (path, attachments.len())
+            }
+        };
+
+        Ok(BundleExportResult {
+            path: path.to_string_lossy().to_string(),
+            consultation_count: bundle.consultations.len(),
+            message_count: bundle.consultations.iter().map(|c| c.messages.len()).sum(),
+            record_count: bundle.medical_records.len(),
+            attachment_count,
+        })
+    }
+
+    pub fn read_bundle(path: &Path) -> Result<PatientBundle> {
+        let is_zip = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("zip"))
+            .unwrap_or(false);
+
+        let json = if is_zip {
+            let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+            let mut entry = archive.by_name(BUNDLE_JSON_NAME)?;
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            content
+        } else {
+            std::fs::read_to_string(path)?
+        };
+
+        let bundle: PatientBundle = serde_json::from_str(&json)?;
+        if bundle.version > BUNDLE_VERSION {
+            return Err(anyhow!("不支持的数据包版本: {}", bundle.version));
+        }
+
+        Ok(bundle)
+    }
+
+    pub fn import_bundle_file(&self, path: &Path) -> Result<BundleImportResult> {
+        let bundle = Self::read_bundle(path)?;
+        self.import_bundle(&bundle)
+    }
+
+    // 在同一事务内写入患者及其问诊、消息和病历，保留原始 ID
+    pub fn import_bundle(&self, bundle: &PatientBundle) -> Result<BundleImportResult> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let patient = &bundle.patient;
+        // 脱敏数据包不覆盖本地的手机号和身份证号
+        let protected = if bundle.masked {
+            ProtectedFields::default()
+        } else {
+            ProtectedFields::protect(patient.phone.as_deref(), patient.id_card.as_deref())
+                .map_err(|e| anyhow!(e.to_string()))?
+        };
+        let change_log_error = |e: Box<dyn std::error::Error>| anyhow!(e.to_string());
+
+        let mut changes = Vec::with_capacity(bundle.consultations.len() + 1);
+        let op = ChangeLogDao::upsert_op_in(&tx, ChangeEntity::Patient, &patient.id).map_err(change_log_error)?;
+        tx.execute(
+            "INSERT INTO patients (id, name, age, gender, phone, id_card, tags, avatar_url, last_sync, created_at, updated_at,
+                                   phone_hash, id_card_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                age = excluded.age,
+                gender = excluded.gender,
+                phone = COALESCE(excluded.phone, patients.phone),
+                id_card = COALESCE(excluded.id_card, patients.id_card),
+                phone_hash = COALESCE(excluded.phone_hash, patients.phone_hash),
+                id_card_hash = COALESCE(excluded.id_card_hash, patients.id_card_hash),
+                tags = excluded.tags,
+                avatar_url = excluded.avatar_url,
+                updated_at = excluded.updated_at",
+            params![
+                patient.id,
+                patient.name,
+                patient.age,
+                patient.gender,
+                protected.phone,
+                protected.id_card,
+                serde_json::to_string(&patient.tags)?,
+                patient.avatar_url,
+                patient.last_sync,
+                patient.created_at,
+                patient.updated_at,
+                protected.phone_hash,
+                protected.id_card_hash
+            ],
+        )?;
+        changes.push(ChangeLogDao::record_in(&tx, ChangeEntity::Patient, &patient.id, op).map_err(change_log_error)?);
+
+        let mut message_count = 0;
+        for entry in &bundle.consultations {
+            let c = &entry.consultation;
+            let op = ChangeLogDao::upsert_op_in(&tx, ChangeEntity::Consultation, &c.id).map_err(change_log_error)?;
+            tx.execute(
+                "INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, title, description, diagnosis, prescription, created_at, updated_at, accepted_at, completed_at, cancel_reason)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                 ON CONFLICT(id) DO UPDATE SET
+                    status = excluded.status,
+                    title = excluded.title,
+                    description = excluded.description,
+                    diagnosis = excluded.diagnosis,
+                    prescription = excluded.prescription,
+                    updated_at = excluded.updated_at,
+                    accepted_at = excluded.accepted_at,
+                    completed_at = excluded.completed_at,
+                    cancel_reason = excluded.cancel_reason",
+                params![
+                    c.id,
+                    patient.id,
+                    c.doctor_id,
+                    c.status,
+                    c.consultation_type,
+                    c.title,
+                    c.description,
+                    c.diagnosis,
+                    c.prescription,
+                    c.created_at,
+                    c.updated_at,
+                    c.accepted_at,
+                    c.completed_at,
+                    c.cancel_reason
+                ],
+            )?;
+            changes.push(ChangeLogDao::record_in(&tx, ChangeEntity::Consultation, &c.id, op).map_err(change_log_error)?);
+
+            for message in &entry.messages {
+                message_count += tx.execute(
+                    "INSERT OR IGNORE INTO messages (id, consultation_id, sender_type, message_type, content, file_path, file_size, mime_type, timestamp, sync_status, read_status, duration_ms, waveform)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    params![
+                        message.id,
+                        c.id,
+                        message.sender_type,
+                        message.message_type,
+                        message.content,
+                        message.file_path,
+                        message.file_size,
+                        message.mime_type,
+                        SqlTimestamp(message.timestamp),
+                        message.sync_status,
+                        message.read_status,
+                        message.duration_ms,
+                        waveform_json(&message.waveform)
+                    ],
+                )?;
+            }
+        }
+
+        for record in &bundle.medical_records {
+            tx.execute(
+                "INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title, content, attachments, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT(id) DO UPDATE SET
+                    title = excluded.title,
+                    content = excluded.content,
+                    attachments = excluded.attachments,
+                    updated_at = excluded.updated_at",
+                params![
+                    record.id,
+                    patient.id,
+                    record.doctor_id,
+                    record.consultation_id,
+                    record.record_type,
+                    record.title,
+                    record.content,
+                    serde_json::to_string(&record.attachments)?,
+                    record.created_at,
+                    record.updated_at
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+
+        // 绕过 DAO 直接写入，需手动清除缓存
+        let cache = query_cache_for(&self.connection);
+        cache.invalidate_tag(CACHE_TAG_PATIENTS);
+        cache.invalidate_tag(CACHE_TAG_MESSAGES);
+        publish_data_changes(&changes);
+
+        Ok(BundleImportResult {
+            patient_id: patient.id.clone(),
+            consultation_count: bundle.consultations.len(),
+            message_count,
+            record_count: bundle.medical_records.len(),
+        })
+    }
+
+    // 收集本地已缓存的附件：病历附件通过 file_cache 查找，消息文件直接使用本地路径
+    fn collect_attachment_files(&self, bundle: &PatientBundle) -> Result<Vec<(String, PathBuf)>> {
+        let file_cache_dao = FileCacheDao::with_connection(self.connection.clone());
+        let mut files = Vec::new();
+
+        for record in &bundle.medical_records {
+            for attachment in &record.attachments {
+                if let Some(cached) = file_cache_dao.find_by_id(&attachment.file_id).map_err(|e| anyhow!(e.to_string()))? {
+                    let path = PathBuf::from(&cached.local_path);
+                    if path.is_file() {
+                        let name = format!(
+                            "attachments/records/{}_{}",
+                            attachment.file_id,
+                            ValidationService::sanitize_filename(&attachment.name)
+                        );
+                        files.push((name, path));
+                    }
+                }
+            }
+        }
+
+        for entry in &bundle.consultations {
+            for message in &entry.messages {
+                if let Some(file_path) = &message.file_path {
+                    let path = PathBuf::from(file_path);
+                    if path.is_file() {
+                        let file_name = path
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        let name = format!(
+                            "attachments/messages/{}_{}",
+                            message.id,
+                            ValidationService::sanitize_filename(&file_name)
+                        );
+                        files.push((name, path));
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+impl Default for PatientBundleService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn write_zip(path: &Path, json: &[u8], attachments: &[(String, PathBuf)]) -> Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    writer.start_file(BUNDLE_JSON_NAME, options)?;
+    writer.write_all(json)?;
+
+    for (name, source) in attachments {
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(&std::fs::read(source)?)?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Attachment, ConsultationPriority, FileCache, MessageType, ReadStatus, SenderType, SyncStatus};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    // 写入一个带问诊、消息和病历的患者，返回患者 ID
+    fn seed_patient(connection: &DbConnection, attachment_path: &Path) -> String {
+        let now = Utc::now();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "张三".to_string(),
+                age: Some(35),
+                gender: Some("male".to_string()),
+                phone: Some("13800138000".to_string()),
+                id_card: Some("110101198901011234".to_string()),
+                tags: vec!["高血压".to_string()],
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+
+        let consultation_id = ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                status: "completed".to_string(),
+                consultation_type: "text".to_string(),
+                title: Some("复诊".to_string()),
+                description: None,
+                diagnosis: Some("高血压".to_string()),
+                prescription: Some("降压药".to_string()),
+                created_at: now,
+                updated_at: now,
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap();
+
+        let message_dao = MessageDao::with_connection(connection.clone());
+        for (offset, content) in ["您好", "血压控制得怎么样？"].iter().enumerate() {
+            message_dao
+                .create(&Message {
+                    id: String::new(),
+                    consultation_id: consultation_id.clone(),
+                    sender_type: SenderType::Doctor,
+                    message_type: MessageType::Text,
+                    content: Some(content.to_string()),
+                    file_path: None,
+                    file_size: None,
+                    mime_type: None,
+                    timestamp: now + chrono::Duration::seconds(offset as i64),
+                    sync_status: SyncStatus::Synced,
+                    read_status: ReadStatus::Read,
+                    template_id: None,
+                    duration_ms: None,
+                    waveform: None,
+                    pinned: false,
+                })
+                .unwrap();
+        }
+
+        let file_id = FileCacheDao::with_connection(connection.clone())
+            .create(&FileCache {
+                id: String::new(),
+                file_url: "https://example.com/report.pdf".to_string(),
+                local_path: attachment_path.to_string_lossy().to_string(),
+                file_size: Some(4),
+                mime_type: Some("application/pdf".to_string()),
+                checksum: None,
+                expires_at: None,
+                downloaded_at: now,
+                last_accessed: now,
+                pinned: true,
+                thumbnail_path: None,
+                bytes_downloaded: 0,
+                preview_path: None,
+                preview_text: None,
+                consultation_id: None,
+                original_size: None,
+            })
+            .unwrap();
+
+        MedicalRecordDao::with_connection(connection.clone())
+            .create(&MedicalRecord {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                consultation_id: Some(consultation_id),
+                record_type: "examination".to_string(),
+                title: "血常规".to_string(),
+                content: Some("正常".to_string()),
+                attachments: vec![Attachment {
+                    file_id,
+                    name: "report.pdf".to_string(),
+                    mime_type: "application/pdf".to_string(),
+                    size: 4,
+                    checksum: None,
+                }],
+                created_at: now,
+                updated_at: now,
+            })
+            .unwrap();
+
+        "p1".to_string()
+    }
+
+    #[test]
+    fn test_json_bundle_round_trip() {
+        let dir = tempdir().unwrap();
+        let attachment = dir.path().join("report.pdf");
+        std::fs::write(&attachment, b"%PDF").unwrap();
+
+        let source = create_test_connection();
+        let patient_id = seed_patient(&source, &attachment);
+
+        let exported = PatientBundleService::with_connection(source)
+            .export_bundle(&patient_id, BundleFormat::Json, dir.path(), false)
+            .unwrap();
+        assert_eq!(exported.consultation_count, 1);
+        assert_eq!(exported.message_count, 2);
+        assert_eq!(exported.record_count, 1);
+
+        let target = create_test_connection();
+        let imported = PatientBundleService::with_connection(target.clone())
+            .import_bundle_file(Path::new(&exported.path))
+            .unwrap();
+        assert_eq!(imported.message_count, 2);
+
+        let patient = PatientDao::with_connection(target.clone()).find_by_id("p1").unwrap().unwrap();
+        assert_eq!(patient.phone.as_deref(), Some("13800138000"));
+
+        // 重新导出的内容与原数据一致
+        let bundle = PatientBundleService::with_connection(target).collect_bundle("p1", false).unwrap();
+        assert_eq!(bundle.consultations.len(), 1);
+        assert_eq!(bundle.consultations[0].messages[0].content.as_deref(), Some("您好"));
+        assert_eq!(bundle.medical_records[0].attachments[0].name, "report.pdf");
+    }
+
+    #[test]
+    fn test_zip_bundle_includes_cached_attachments() {
+        let dir = tempdir().unwrap();
+        let attachment = dir.path().join("report.pdf");
+        std::fs::write(&attachment, b"%PDF").unwrap();
+
+        let source = create_test_connection();
+        let patient_id = seed_patient(&source, &attachment);
+
+        let exported = PatientBundleService::with_connection(source)
+            .export_bundle(&patient_id, BundleFormat::Zip, &dir.path().join("out"), false)
+            .unwrap();
+        assert_eq!(exported.attachment_count, 1);
+
+        let archive = zip::ZipArchive::new(std::fs::File::open(&exported.path).unwrap()).unwrap();
+        assert!(archive
+            .file_names()
+            .any(|name| name.starts_with("attachments/records/") && name.ends_with("_report.pdf")));
+
+        let target = create_test_connection();
+        let imported = PatientBundleService::with_connection(target)
+            .import_bundle_file(Path::new(&exported.path))
+            .unwrap();
+        assert_eq!(imported.record_count, 1);
+    }
+
+    #[test]
+    fn test_masked_bundle_hides_sensitive_fields() {
+        let dir = tempdir().unwrap();
+        let source = create_test_connection();
+        let patient_id = seed_patient(&source, &dir.path().join("missing.pdf"));
+
+        let bundle = PatientBundleService::with_connection(source)
+            .collect_bundle(&patient_id, true)
+            .unwrap();
+        assert!(bundle.masked);
+        assert_eq!(bundle.patient.phone.as_deref(), Some("138****8000"));
+        assert_eq!(bundle.patient.id_card.as_deref(), Some("110101********1234"));
+
+        // 脱敏数据包导入时不写入脱敏后的号码
+        let target = create_test_connection();
+        PatientBundleService::with_connection(target.clone()).import_bundle(&bundle).unwrap();
+        let patient = PatientDao::with_connection(target).find_by_id("p1").unwrap().unwrap();
+        assert!(patient.phone.is_none());
+        assert!(patient.id_card.is_none());
+    }
+}
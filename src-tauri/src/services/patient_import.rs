@@ -0,0 +1,444 @@
+// 患者批量导入服务（HIS 导出的 CSV）
+
+use crate::database::connection::DbConnection;
+use crate::database::dao::PatientDao;
+use crate::models::{ImportReport, ImportRowError, Patient, INITIAL_ROW_VERSION};
+use crate::utils::ValidationService;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+
+// 每个事务写入的行数
+const IMPORT_BATCH_SIZE: usize = 200;
+
+const HEADER_NAME: &str = "姓名";
+const HEADER_AGE: &str = "年龄";
+const HEADER_GENDER: &str = "性别";
+const HEADER_PHONE: &str = "手机号";
+const HEADER_ID_CARD: &str = "身份证号";
+const HEADER_TAGS: &str = "标签";
+
+pub struct PatientImportService {
+    patient_dao: PatientDao,
+}
+
+struct ColumnIndexes {
+    name: usize,
+    age: Option<usize>,
+    gender: Option<usize>,
+    phone: Option<usize>,
+    id_card: Option<usize>,
+    tags: Option<usize>,
+}
+
+impl PatientImportService {
+    pub fn new() -> Self {
+        Self {
+            patient_dao: PatientDao::new(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            patient_dao: PatientDao::with_connection(connection),
+        }
+    }
+
+    pub fn import_file(&self, path: &Path) -> Result<ImportReport> {
+        let bytes = std::fs::read(path)?;
+        let content = decode_csv_bytes(&bytes);
+        self.import_csv(&content)
+    }
+
+    pub fn import_csv(&self, content: &str) -> Result<ImportReport> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(content.as_bytes());
+
+        let columns = resolve_columns(reader.headers()?)?;
+
+        let mut report = ImportReport::default();
+        let mut inserts: Vec<Patient> = Vec::new();
+        let mut updates: Vec<Patient> = Vec::new();
+        let mut seen_keys: HashSet<String> = HashSet::new();
+        let mut seen_existing: HashSet<String> = HashSet::new();
+
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    let line = e.position().map(|p| p.line()).unwrap_or(0);
+                    report.skipped += 1;
+                    report.errors.push(ImportRowError {
+                        line,
+                        field: "row".to_string(),
+                        message: format!("无法解析该行: {}", e),
+                    });
+                    continue;
+                }
+            };
+            let line = record.position().map(|p| p.line()).unwrap_or(0);
+
+            let patient = match parse_row(&record, &columns) {
+                Ok(patient) => patient,
+                Err(error) => {
+                    report.skipped += 1;
+                    report.errors.push(ImportRowError { line, field: error.0, message: error.1 });
+                    continue;
+                }
+            };
+
+            let validation = ValidationService::validate_patient(&patient);
+            if !validation.is_valid {
+                report.skipped += 1;
+                for violation in validation.errors {
+                    report.errors.push(ImportRowError {
+                        line,
+                        field: violation.field,
+                        message: violation.message,
+                    });
+                }
+                continue;
+            }
+
+            let existing = match self.find_existing(&patient)? {
+                Ok(existing) => existing,
+                Err(error) => {
+                    report.skipped += 1;
+                    report.errors.push(ImportRowError { line, field: error.0, message: error.1 });
+                    continue;
+                }
+            };
+
+            // 同一文件内按手机号/身份证号去重，分别按手机号和身份证号匹配到同一已有患者的行同样算重复
+            let keys: Vec<String> = patient.phone.iter().map(|p| format!("phone:{}", p))
+                .chain(patient.id_card.iter().map(|c| format!("id_card:{}", c.to_uppercase())))
+                .collect();
+            let existing_seen = existing.as_ref().is_some_and(|e| seen_existing.contains(&e.id));
+            if existing_seen || keys.iter().any(|key| seen_keys.contains(key)) {
+                report.skipped += 1;
+                report.errors.push(ImportRowError {
+                    line,
+                    field: "row".to_string(),
+                    message: "文件中存在重复的患者".to_string(),
+                });
+                continue;
+            }
+            seen_keys.extend(keys);
+
+            match existing {
+                Some(existing) => {
+                    seen_existing.insert(existing.id.clone());
+                    updates.push(merge_into_existing(existing, patient));
+                }
+                None => inserts.push(patient),
+            }
+        }
+
+        self.patient_dao
+            .bulk_import(&inserts, &updates, IMPORT_BATCH_SIZE)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        report.imported = inserts.len();
+        report.updated = updates.len();
+        Ok(report)
+    }
+
+    // 按手机号、身份证号查找已有患者；两者分别属于不同患者时无法确定更新哪一位，作为行错误返回
+    fn find_existing(&self, patient: &Patient) -> Result<std::result::Result<Option<Patient>, (String, String)>> {
+        let by_phone = match &patient.phone {
+            Some(phone) => self.patient_dao.find_by_phone(phone).map_err(|e| anyhow!(e.to_string()))?,
+            None => None,
+        };
+        let by_id_card = match &patient.id_card {
+            Some(id_card) => self.patient_dao.find_by_id_card(id_card).map_err(|e| anyhow!(e.to_string()))?,
+            None => None,
+        };
+
+        match (by_phone, by_id_card) {
+            (Some(phone_match), Some(id_card_match)) if phone_match.id != id_card_match.id => Ok(Err((
+                "row".to_string(),
+                format!("手机号与身份证号分别属于不同的已有患者（{}、{}）", phone_match.name, id_card_match.name),
+            ))),
+            (Some(existing), _) | (None, Some(existing)) => Ok(Ok(Some(existing))),
+            (None, None) => Ok(Ok(None)),
+        }
+    }
+}
+
+impl Default for PatientImportService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 优先按 UTF-8 解析（去除 BOM），失败时按 GBK 解码
+pub fn decode_csv_bytes(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(content) => content.to_string(),
+        Err(_) => {
+            let (content, _, _) = encoding_rs::GBK.decode(bytes);
+            content.into_owned()
+        }
+    }
+}
+
+fn resolve_columns(headers: &csv::StringRecord) -> Result<ColumnIndexes> {
+    let find = |name: &str| headers.iter().position(|h| h == name);
+
+    Ok(ColumnIndexes {
+        name: find(HEADER_NAME).ok_or_else(|| anyhow!("缺少必需的列: {}", HEADER_NAME))?,
+        age: find(HEADER_AGE),
+        gender: find(HEADER_GENDER),
+        phone: find(HEADER_PHONE),
+        id_card: find(HEADER_ID_CARD),
+        tags: find(HEADER_TAGS),
+    })
+}
+
+fn parse_row(record: &csv::StringRecord, columns: &ColumnIndexes) -> std::result::Result<Patient, (String, String)> {
+    let cell = |index: Option<usize>| {
+        index
+            .and_then(|i| record.get(i))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+
+    let age = match cell(columns.age) {
+        Some(value) => Some(
+            value
+                .parse::<u32>()
+                .map_err(|_| ("age".to_string(), format!("年龄格式不正确: {}", value)))?,
+        ),
+        None => None,
+    };
+
+    let gender = match cell(columns.gender).as_deref() {
+        Some("男") | Some("male") | Some("M") => Some("male".to_string()),
+        Some("女") | Some("female") | Some("F") => Some("female".to_string()),
+        Some(_) => Some("unknown".to_string()),
+        None => None,
+    };
+
+    let tags = cell(columns.tags)
+        .map(|value| {
+            value
+                .split([';', '；', ',', '，', '、', '|'])
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    let now = Utc::now();
+    Ok(Patient {
+        id: Uuid::new_v4().to_string(),
+        name: cell(Some(columns.name)).unwrap_or_default(),
+        age,
+        gender,
+        phone: cell(columns.phone),
+        id_card: cell(columns.id_card),
+        tags,
+        avatar_url: None,
+        last_sync: None,
+        last_visit: None,
+        created_at: now,
+        updated_at: now,
+        version: INITIAL_ROW_VERSION,
+    })
+}
+
+fn merge_into_existing(existing: Patient, imported: Patient) -> Patient {
+    let mut tags = existing.tags.clone();
+    for tag in imported.tags {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    Patient {
+        id: existing.id,
+        name: imported.name,
+        age: imported.age.or(existing.age),
+        gender: imported.gender.or(existing.gender),
+        phone: imported.phone.or(existing.phone),
+        id_card: imported.id_card.or(existing.id_card),
+        tags,
+        avatar_url: existing.avatar_url,
+        last_sync: existing.last_sync,
+        last_visit: existing.last_visit,
+        created_at: existing.created_at,
+        updated_at: Utc::now(),
+        version: existing.version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::BaseDao;
+    use crate::database::migrations::MigrationManager;
+    use chrono::Datelike;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    // 出生于 35 年前的 1 月 1 日，与样例中的年龄一致
+    fn zhang_id_card() -> String {
+        let body = format!("110101{}0101123", chrono::Utc::now().year() - 35);
+        let check = ValidationService::id_card_check_digit(&body).unwrap();
+        format!("{}{}", body, check)
+    }
+
+    fn sample_csv() -> String {
+        format!(
+            "姓名,年龄,性别,手机号,身份证号,标签
+张三,35,男,13800138001,{},高血压;糖尿病
+李四,28,女,13900139002,,孕期检查
+王五,abc,男,13700137003,,
+赵六,40,男,12345,,
+张三,35,男,13800138001,,重复行
+",
+            zhang_id_card()
+        )
+    }
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn assert_sample_report(report: &ImportReport) {
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.updated, 0);
+        assert_eq!(report.skipped, 3);
+
+        // 行号从表头开始计算
+        assert!(report.errors.iter().any(|e| e.line == 4 && e.field == "age"));
+        assert!(report.errors.iter().any(|e| e.line == 5 && e.field == "phone"));
+        assert!(report.errors.iter().any(|e| e.line == 6 && e.field == "row"));
+    }
+
+    #[test]
+    fn test_import_utf8_file() {
+        let connection = create_test_connection();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("patients_utf8.csv");
+        let mut bytes = vec![0xEF, 0xBB, 0xBF];
+        bytes.extend_from_slice(sample_csv().as_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let service = PatientImportService::with_connection(connection.clone());
+        let report = service.import_file(&path).unwrap();
+        assert_sample_report(&report);
+
+        let dao = PatientDao::with_connection(connection);
+        let zhang = dao.find_by_phone("13800138001").unwrap().unwrap();
+        assert_eq!(zhang.name, "张三");
+        assert_eq!(zhang.gender.as_deref(), Some("male"));
+        assert_eq!(zhang.tags, vec!["高血压".to_string(), "糖尿病".to_string()]);
+    }
+
+    #[test]
+    fn test_import_gbk_file() {
+        let connection = create_test_connection();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("patients_gbk.csv");
+        let csv = sample_csv();
+        let (encoded, _, had_errors) = encoding_rs::GBK.encode(&csv);
+        assert!(!had_errors);
+        std::fs::write(&path, encoded.as_ref()).unwrap();
+
+        let service = PatientImportService::with_connection(connection.clone());
+        let report = service.import_file(&path).unwrap();
+        assert_sample_report(&report);
+
+        let dao = PatientDao::with_connection(connection);
+        let li = dao.find_by_phone("13900139002").unwrap().unwrap();
+        assert_eq!(li.name, "李四");
+        assert_eq!(li.tags, vec!["孕期检查".to_string()]);
+    }
+
+    #[test]
+    fn test_reimport_updates_existing_patients() {
+        let connection = create_test_connection();
+        let service = PatientImportService::with_connection(connection.clone());
+        service.import_csv(&sample_csv()).unwrap();
+
+        let update_csv = "姓名,年龄,性别,手机号,身份证号,标签
+张三,36,男,13800138001,,冠心病
+";
+        let report = service.import_csv(update_csv).unwrap();
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.updated, 1);
+
+        let dao = PatientDao::with_connection(connection);
+        assert_eq!(dao.find_all().unwrap().len(), 2);
+        let zhang = dao.find_by_phone("13800138001").unwrap().unwrap();
+        assert_eq!(zhang.age, Some(36));
+        assert_eq!(zhang.id_card, Some(zhang_id_card()));
+        assert_eq!(zhang.tags, vec!["高血压".to_string(), "糖尿病".to_string(), "冠心病".to_string()]);
+    }
+
+    #[test]
+    fn test_rows_matching_same_existing_patient_deduplicated() {
+        let connection = create_test_connection();
+        let service = PatientImportService::with_connection(connection.clone());
+        service.import_csv(&sample_csv()).unwrap();
+
+        // 第一行按手机号、第二行按身份证号匹配到同一位已有患者
+        let update_csv = format!(
+            "姓名,年龄,性别,手机号,身份证号,标签
+张三,36,男,13800138001,,冠心病
+张三,35,男,,{},哮喘
+",
+            zhang_id_card()
+        );
+        let report = service.import_csv(&update_csv).unwrap();
+        assert_eq!(report.updated, 1);
+        assert_eq!(report.skipped, 1);
+        assert!(report.errors.iter().any(|e| e.line == 3 && e.field == "row"));
+
+        let dao = PatientDao::with_connection(connection);
+        let zhang = dao.find_by_phone("13800138001").unwrap().unwrap();
+        assert_eq!(zhang.age, Some(36));
+        assert!(!zhang.tags.contains(&"哮喘".to_string()));
+    }
+
+    #[test]
+    fn test_phone_and_id_card_of_different_patients_rejected() {
+        let connection = create_test_connection();
+        let service = PatientImportService::with_connection(connection.clone());
+        service.import_csv(&sample_csv()).unwrap();
+
+        // 手机号属于李四，身份证号属于张三
+        let conflict_csv = format!(
+            "姓名,年龄,性别,手机号,身份证号,标签
+王五,35,男,13900139002,{},
+",
+            zhang_id_card()
+        );
+        let report = service.import_csv(&conflict_csv).unwrap();
+        assert_eq!((report.imported, report.updated, report.skipped), (0, 0, 1));
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!((report.errors[0].line, report.errors[0].field.as_str()), (2, "row"));
+
+        let dao = PatientDao::with_connection(connection);
+        assert_eq!(dao.find_by_phone("13900139002").unwrap().unwrap().name, "李四");
+        assert_eq!(dao.find_by_id_card(&zhang_id_card()).unwrap().unwrap().name, "张三");
+    }
+
+    #[test]
+    fn test_missing_name_column_rejected() {
+        let service = PatientImportService::with_connection(create_test_connection());
+        assert!(service.import_csv("年龄,手机号\n30,13800138000\n").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+// 基于角色的权限控制
+// 登录时根据 JWT 中的角色初始化，受保护的命令在执行前调用 check
+
+use crate::models::{AppError, DataScope, ErrorType, Permission, UserPermissions, UserRole};
+use crate::services::security::{AuditAction, SecurityService};
+use crate::utils::MaskingPolicy;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub const PERMISSION_DENIED: &str = "PERMISSION_DENIED";
+
+#[derive(Debug, Clone)]
+struct PermissionSession {
+    user_id: String,
+    role: Option<UserRole>,
+}
+
+pub struct PermissionService {
+    session: Option<PermissionSession>,
+    security: Arc<Mutex<SecurityService>>,
+}
+
+impl PermissionService {
+    pub fn new(security: Arc<Mutex<SecurityService>>) -> Self {
+        Self {
+            session: None,
+            security,
+        }
+    }
+
+    // 各角色拥有的权限
+    pub fn role_permissions(role: UserRole) -> &'static [Permission] {
+        match role {
+            UserRole::Admin => &Permission::ALL,
+            UserRole::Doctor => &[
+                Permission::WriteAuditLogs,
+                Permission::DecryptSensitiveData,
+                Permission::EditPatients,
+                Permission::ManagePatientTags,
+                Permission::ImportPatients,
+                Permission::ExportPatientData,
+                Permission::SyncData,
+                Permission::ReadFiles,
+                Permission::DeleteFiles,
+            ],
+            // 护士负责分诊，不区分医生查看患者
+            UserRole::Nurse => &[
+                Permission::WriteAuditLogs,
+                Permission::EditPatients,
+                Permission::SyncData,
+                Permission::ReadFiles,
+                Permission::ViewAllPatients,
+            ],
+        }
+    }
+
+    // 各角色看到的患者标识是否脱敏；护士分诊需要核对姓名，只隐藏手机号和身份证号
+    pub fn role_masking(role: UserRole) -> MaskingPolicy {
+        match role {
+            UserRole::Admin | UserRole::Doctor => MaskingPolicy::NONE,
+            UserRole::Nurse => MaskingPolicy {
+                name: false,
+                ..MaskingPolicy::ALL
+            },
+        }
+    }
+
+    // 登录后设置当前用户，无法识别的角色没有任何权限
+    pub fn start_session(&mut self, user_id: String, role: Option<UserRole>) {
+        self.session = Some(PermissionSession { user_id, role });
+    }
+
+    pub fn clear_session(&mut self) {
+        self.session = None;
+    }
+
+    pub fn current_user_id(&self) -> Option<&str> {
+        self.session.as_ref().map(|s| s.user_id.as_str())
+    }
+
+    // 当前用户可见的本地数据范围，未登录时为空
+    pub fn data_scope(&self) -> Option<DataScope> {
+        let user_id = self.current_user_id()?;
+        if self.has_permission(Permission::ViewAllPatients) {
+            Some(DataScope::All)
+        } else {
+            Some(DataScope::Doctor(user_id.to_string()))
+        }
+    }
+
+    pub fn current_role(&self) -> Option<UserRole> {
+        self.session.as_ref().and_then(|s| s.role)
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.current_role()
+            .map(|role| Self::role_permissions(role).contains(&permission))
+            .unwrap_or(false)
+    }
+
+    // 未登录或角色无法识别时全部脱敏
+    pub fn masking_policy(&self) -> MaskingPolicy {
+        self.current_role().map(Self::role_masking).unwrap_or(MaskingPolicy::ALL)
+    }
+
+    pub fn current_permissions(&self) -> UserPermissions {
+        UserPermissions {
+            role: self.current_role(),
+            permissions: self
+                .current_role()
+                .map(|role| Self::role_permissions(role).to_vec())
+                .unwrap_or_default(),
+        }
+    }
+
+    // 校验权限，拒绝时记录审计日志并返回 PermissionError
+    pub async fn check(&self, permission: Permission) -> Result<(), AppError> {
+        if self.has_permission(permission) {
+            return Ok(());
+        }
+
+        let user_id = self
+            .session
+            .as_ref()
+            .map(|s| s.user_id.clone())
+            .unwrap_or_else(|| "unknown".to_string());
+        let role = self.current_role().map(|r| r.as_str()).unwrap_or("none");
+        tracing::warn!("Permission denied: user={}, role={}, permission={}", user_id, role, permission.as_str());
+
+        let mut metadata = HashMap::new();
+        metadata.insert("role".to_string(), role.to_string());
+        metadata.insert("permission".to_string(), permission.as_str().to_string());
+        if let Err(e) = self
+            .security
+            .lock()
+            .await
+            .log_audit(
+                user_id,
+                AuditAction::PermissionDenied,
+                Some("permission".to_string()),
+                Some(permission.as_str().to_string()),
+                "denied".to_string(),
+                None,
+                metadata,
+            )
+            .await
+        {
+            tracing::error!("Failed to record audit log for permission denial: {}", e);
+        }
+
+        Err(AppError::new(ErrorType::PermissionError, "当前账号没有执行该操作的权限")
+            .with_code(PERMISSION_DENIED)
+            .with_details(serde_json::json!({ "permission": permission }))
+            .with_retryable(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> (PermissionService, Arc<Mutex<SecurityService>>) {
+        let security = Arc::new(Mutex::new(SecurityService::new(300)));
+        (PermissionService::new(security.clone()), security)
+    }
+
+    #[tokio::test]
+    async fn test_denial_is_audited() {
+        let (mut permissions, security) = service();
+        permissions.start_session("nurse-1".to_string(), Some(UserRole::Nurse));
+
+        assert!(permissions.check(Permission::EditPatients).await.is_ok());
+
+        let error = permissions.check(Permission::ViewAuditLogs).await.unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::PermissionError));
+        assert_eq!(error.code.as_deref(), Some(PERMISSION_DENIED));
+
+        let logs = security
+            .lock()
+            .await
+            .get_audit_logs(Some("nurse-1".to_string()), None, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(matches!(logs[0].action, AuditAction::PermissionDenied));
+        assert_eq!(logs[0].resource_id.as_deref(), Some("view_audit_logs"));
+        assert_eq!(logs[0].status, "denied");
+    }
+
+    #[test]
+    fn test_role_read_from_token() {
+        use crate::services::auth::{encode_jwt_token, AuthService};
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+        let token = encode_jwt_token("2", "nurse", "nurse").unwrap();
+        assert_eq!(AuthService::role_from_token(&token).unwrap(), UserRole::Nurse);
+
+        // 医院接口签发的标准 JWT
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"3","role":"admin"}"#);
+        let token = format!("eyJhbGciOiJIUzI1NiJ9.{}.signature", payload);
+        assert_eq!(AuthService::role_from_token(&token).unwrap(), UserRole::Admin);
+
+        assert!(AuthService::role_from_token(&encode_jwt_token("4", "x", "pharmacist").unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_no_session_has_no_permissions() {
+        let (mut permissions, _) = service();
+        assert!(permissions.current_permissions().permissions.is_empty());
+        assert!(permissions.check(Permission::SyncData).await.is_err());
+
+        // 无法识别的角色同样没有权限
+        permissions.start_session("u1".to_string(), UserRole::parse("pharmacist"));
+        assert!(permissions.check(Permission::SyncData).await.is_err());
+
+        permissions.start_session("u1".to_string(), Some(UserRole::Admin));
+        assert_eq!(permissions.current_permissions().permissions.len(), Permission::ALL.len());
+        permissions.clear_session();
+        assert!(permissions.current_role().is_none());
+    }
+
+    #[test]
+    fn test_data_scope_follows_session() {
+        let (mut permissions, _) = service();
+        assert_eq!(permissions.data_scope(), None);
+
+        permissions.start_session("doctor-a".to_string(), Some(UserRole::Doctor));
+        assert_eq!(permissions.data_scope(), Some(DataScope::Doctor("doctor-a".to_string())));
+
+        permissions.start_session("admin-1".to_string(), Some(UserRole::Admin));
+        assert_eq!(permissions.data_scope(), Some(DataScope::All));
+
+        permissions.clear_session();
+        assert_eq!(permissions.data_scope(), None);
+    }
+
+    #[test]
+    fn test_masking_follows_role() {
+        let (mut permissions, _) = service();
+        assert_eq!(permissions.masking_policy(), MaskingPolicy::ALL);
+
+        permissions.start_session("nurse-1".to_string(), Some(UserRole::Nurse));
+        let policy = permissions.masking_policy();
+        assert!(policy.phone && policy.id_card && !policy.name);
+
+        permissions.start_session("doctor-a".to_string(), Some(UserRole::Doctor));
+        assert_eq!(permissions.masking_policy(), MaskingPolicy::NONE);
+    }
+}
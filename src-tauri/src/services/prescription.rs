@@ -0,0 +1,374 @@
+// 处方服务：草稿保存、开具与作废
+
+use crate::database::connection::DbConnection;
+use crate::database::dao::{BaseDao, ConsultationDao, MessageDao, PrescriptionDao};
+use crate::models::{
+    AppError, Consultation, ConsultationStatus, ErrorType, Prescription, PrescriptionItem, PrescriptionStatus,
+    SystemEventKind,
+};
+use crate::utils::ValidationService;
+use chrono::Utc;
+
+pub type PrescriptionResult<T> = Result<T, AppError>;
+
+pub struct PrescriptionService {
+    prescription_dao: PrescriptionDao,
+    consultation_dao: ConsultationDao,
+}
+
+impl PrescriptionService {
+    pub fn new() -> Self {
+        Self {
+            prescription_dao: PrescriptionDao::new(),
+            consultation_dao: ConsultationDao::new(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            prescription_dao: PrescriptionDao::with_connection(connection.clone()),
+            consultation_dao: ConsultationDao::with_connection(connection),
+        }
+    }
+
+    // 新建草稿，或在 prescription_id 对应的草稿上覆盖药品明细
+    pub async fn save_draft(
+        &self,
+        consultation_id: &str,
+        prescription_id: Option<&str>,
+        items: Vec<PrescriptionItem>,
+        doctor_id: &str,
+    ) -> PrescriptionResult<Prescription> {
+        ValidationService::validate_prescription(&items).into_app_result()?;
+
+        let consultation = self.load_consultation(consultation_id)?;
+        if !matches!(
+            ConsultationStatus::parse(&consultation.status),
+            Some(ConsultationStatus::Pending | ConsultationStatus::Active)
+        ) {
+            return Err(AppError::new(ErrorType::ValidationError, "问诊已结束，不能再编辑处方")
+                .with_code("CONSULTATION_CLOSED"));
+        }
+
+        let id = match prescription_id {
+            Some(id) => {
+                let existing = self.load(id)?;
+                if existing.consultation_id != consultation_id {
+                    return Err(AppError::invalid_argument("处方不属于该问诊"));
+                }
+                if !self.prescription_dao.update_draft_items(id, &items).map_err(dao_error)? {
+                    let latest = self.load(id)?;
+                    return Err(illegal_transition(latest.status, PrescriptionStatus::Draft));
+                }
+                id.to_string()
+            }
+            None => {
+                let now = Utc::now();
+                self.prescription_dao
+                    .create(&Prescription {
+                        id: String::new(),
+                        consultation_id: consultation_id.to_string(),
+                        doctor_id: doctor_id.to_string(),
+                        items,
+                        status: PrescriptionStatus::Draft,
+                        void_reason: None,
+                        created_at: now,
+                        updated_at: now,
+                        issued_at: None,
+                        voided_at: None,
+                    })
+                    .map_err(dao_error)?
+            }
+        };
+
+        self.load(&id)
+    }
+
+    // 开具处方，问诊必须处于进行中
+    pub async fn issue(&self, prescription_id: &str, user_id: Option<&str>) -> PrescriptionResult<Prescription> {
+        let prescription = self.load(prescription_id)?;
+        if prescription.status != PrescriptionStatus::Draft {
+            return Err(illegal_transition(prescription.status, PrescriptionStatus::Issued));
+        }
+        ValidationService::validate_prescription(&prescription.items).into_app_result()?;
+        self.ensure_consultation_active(&prescription.consultation_id)?;
+
+        let notice = MessageDao::system_message(
+            &prescription.consultation_id,
+            SystemEventKind::PrescriptionIssued,
+            serde_json::json!({ "prescriptionId": prescription.id }),
+        );
+        let applied = self
+            .prescription_dao
+            .issue(prescription_id, &prescription.summary(), &notice, user_id)
+            .map_err(dao_error)?;
+
+        // 条件更新失败说明处方或问诊状态已被并发修改，按最新状态报告
+        if !applied {
+            let latest = self.load(prescription_id)?;
+            if latest.status != PrescriptionStatus::Draft {
+                return Err(illegal_transition(latest.status, PrescriptionStatus::Issued));
+            }
+            self.ensure_consultation_active(&latest.consultation_id)?;
+        }
+
+        self.load(prescription_id)
+    }
+
+    pub async fn void(&self, prescription_id: &str, reason: &str, user_id: Option<&str>) -> PrescriptionResult<Prescription> {
+        if reason.trim().is_empty() {
+            return Err(AppError::new(ErrorType::ValidationError, "作废处方必须填写原因").with_code("VOID_REASON_REQUIRED"));
+        }
+
+        let prescription = self.load(prescription_id)?;
+        if prescription.status == PrescriptionStatus::Voided {
+            return Err(illegal_transition(prescription.status, PrescriptionStatus::Voided));
+        }
+
+        let applied = self
+            .prescription_dao
+            .void(prescription_id, reason.trim(), &prescription.summary(), user_id)
+            .map_err(dao_error)?;
+        if !applied {
+            let latest = self.load(prescription_id)?;
+            return Err(illegal_transition(latest.status, PrescriptionStatus::Voided));
+        }
+
+        self.load(prescription_id)
+    }
+
+    pub async fn get_prescription(&self, prescription_id: &str) -> PrescriptionResult<Prescription> {
+        self.load(prescription_id)
+    }
+
+    pub async fn list_by_consultation(&self, consultation_id: &str) -> PrescriptionResult<Vec<Prescription>> {
+        self.prescription_dao.find_by_consultation(consultation_id).map_err(dao_error)
+    }
+
+    fn ensure_consultation_active(&self, consultation_id: &str) -> PrescriptionResult<()> {
+        let consultation = self.load_consultation(consultation_id)?;
+        if consultation.status != ConsultationStatus::Active.as_str() {
+            return Err(AppError::new(ErrorType::ValidationError, "只有进行中的问诊才能开具处方")
+                .with_code("CONSULTATION_NOT_ACTIVE")
+                .with_details(serde_json::json!({ "status": consultation.status })));
+        }
+        Ok(())
+    }
+
+    fn load(&self, prescription_id: &str) -> PrescriptionResult<Prescription> {
+        self.prescription_dao
+            .find_by_id(prescription_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| {
+                AppError::new(ErrorType::DataError, format!("处方不存在: {}", prescription_id))
+                    .with_code("PRESCRIPTION_NOT_FOUND")
+            })
+    }
+
+    fn load_consultation(&self, consultation_id: &str) -> PrescriptionResult<Consultation> {
+        self.consultation_dao
+            .find_by_id(consultation_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| {
+                AppError::new(ErrorType::DataError, format!("问诊不存在: {}", consultation_id))
+                    .with_code("CONSULTATION_NOT_FOUND")
+            })
+    }
+}
+
+impl Default for PrescriptionService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn illegal_transition(from: PrescriptionStatus, to: PrescriptionStatus) -> AppError {
+    AppError::new(
+        ErrorType::ValidationError,
+        format!("处方状态不能从「{}」变更为「{}」", from.label(), to.label()),
+    )
+    .with_code("ILLEGAL_PRESCRIPTION_TRANSITION")
+    .with_details(serde_json::json!({ "from": from, "to": to }))
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::PatientDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{ConsultationPriority, Patient};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn seed_consultation(connection: &DbConnection, status: &str) -> String {
+        let now = Utc::now();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "张三".to_string(),
+                age: Some(35),
+                gender: Some("male".to_string()),
+                phone: None,
+                id_card: None,
+                tags: vec![],
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+
+        ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                status: status.to_string(),
+                consultation_type: "text".to_string(),
+                title: None,
+                description: None,
+                diagnosis: None,
+                prescription: None,
+                created_at: now,
+                updated_at: now,
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap()
+    }
+
+    fn item(drug: &str) -> PrescriptionItem {
+        PrescriptionItem {
+            drug: drug.to_string(),
+            spec: "0.25g×24粒".to_string(),
+            dosage: 0.5,
+            frequency: "一日三次".to_string(),
+            days: 7,
+            note: Some("饭后".to_string()),
+        }
+    }
+
+    fn consultation_prescription(connection: &DbConnection, consultation_id: &str) -> Option<String> {
+        ConsultationDao::with_connection(connection.clone())
+            .find_by_id(consultation_id)
+            .unwrap()
+            .unwrap()
+            .prescription
+    }
+
+    fn audit_actions(connection: &DbConnection, prescription_id: &str) -> Vec<String> {
+        let conn = connection.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT action FROM audit_logs WHERE resource_type = 'prescription' AND resource_id = ?1 ORDER BY created_at")
+            .unwrap();
+        let rows = stmt.query_map([prescription_id], |row| row.get(0)).unwrap();
+        rows.map(|action| action.unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_draft_issue_and_void() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection, "active");
+        let service = PrescriptionService::with_connection(connection.clone());
+
+        let draft = service
+            .save_draft(&consultation_id, None, vec![item("阿莫西林胶囊")], "d1")
+            .await
+            .unwrap();
+        assert_eq!(draft.status, PrescriptionStatus::Draft);
+
+        let draft = service
+            .save_draft(&consultation_id, Some(&draft.id), vec![item("阿莫西林胶囊"), item("布洛芬缓释胶囊")], "d1")
+            .await
+            .unwrap();
+        assert_eq!(draft.items.len(), 2);
+        assert!(consultation_prescription(&connection, &consultation_id).is_none());
+
+        let issued = service.issue(&draft.id, Some("d1")).await.unwrap();
+        assert_eq!(issued.status, PrescriptionStatus::Issued);
+        assert!(issued.issued_at.is_some());
+        assert_eq!(
+            consultation_prescription(&connection, &consultation_id).as_deref(),
+            Some("阿莫西林胶囊 0.25g×24粒 每次0.5 一日三次 7天（饭后）\n布洛芬缓释胶囊 0.25g×24粒 每次0.5 一日三次 7天（饭后）")
+        );
+
+        // 开具后不能再修改或重复开具
+        let error = service
+            .save_draft(&consultation_id, Some(&issued.id), vec![item("头孢")], "d1")
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("ILLEGAL_PRESCRIPTION_TRANSITION"));
+        let error = service.issue(&issued.id, Some("d1")).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("ILLEGAL_PRESCRIPTION_TRANSITION"));
+
+        let voided = service.void(&issued.id, "剂量有误", Some("d1")).await.unwrap();
+        assert_eq!(voided.status, PrescriptionStatus::Voided);
+        assert_eq!(voided.void_reason.as_deref(), Some("剂量有误"));
+        assert!(consultation_prescription(&connection, &consultation_id).is_none());
+
+        let error = service.void(&issued.id, "重复作废", Some("d1")).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("ILLEGAL_PRESCRIPTION_TRANSITION"));
+        assert_eq!(audit_actions(&connection, &issued.id), vec!["issue_prescription", "void_prescription"]);
+    }
+
+    #[tokio::test]
+    async fn test_issue_requires_active_consultation() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection, "pending");
+        let service = PrescriptionService::with_connection(connection.clone());
+
+        let draft = service
+            .save_draft(&consultation_id, None, vec![item("阿莫西林胶囊")], "d1")
+            .await
+            .unwrap();
+        let error = service.issue(&draft.id, Some("d1")).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("CONSULTATION_NOT_ACTIVE"));
+
+        // 未开具时不写审计，不改动问诊
+        assert!(audit_actions(&connection, &draft.id).is_empty());
+        assert!(consultation_prescription(&connection, &consultation_id).is_none());
+        assert_eq!(service.get_prescription(&draft.id).await.unwrap().status, PrescriptionStatus::Draft);
+
+        let completed = seed_consultation(&connection, "completed");
+        let error = service
+            .save_draft(&completed, None, vec![item("阿莫西林胶囊")], "d1")
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("CONSULTATION_CLOSED"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_items_rejected() {
+        let connection = create_test_connection();
+        let consultation_id = seed_consultation(&connection, "active");
+        let service = PrescriptionService::with_connection(connection);
+
+        let error = service.save_draft(&consultation_id, None, Vec::new(), "d1").await.unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::ValidationError));
+
+        let mut invalid = item("阿莫西林胶囊");
+        invalid.days = 0;
+        assert!(service.save_draft(&consultation_id, None, vec![invalid], "d1").await.is_err());
+        assert!(service.list_by_consultation(&consultation_id).await.unwrap().is_empty());
+
+        let error = service.void("missing", "原因", None).await.unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("PRESCRIPTION_NOT_FOUND"));
+    }
+}
@@ -0,0 +1,564 @@
+// 处方笺 PDF：医院抬头、患者信息、药品明细、医师和带本机签名的核验二维码，用于打印交给患者
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::patient_dao::field_crypto;
+use crate::database::dao::{BaseDao, ConsultationDao, PatientDao, PrescriptionDao};
+use crate::models::{Gender, HospitalProfileConfig, Patient, Prescription, PrescriptionItem, PrescriptionStatus};
+use crate::services::audit_export::to_hex;
+use crate::utils::crypto::CryptoService;
+use crate::utils::{format_local, MaskingPolicy};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use printpdf::{
+    ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Line, Mm, PdfDocument,
+    PdfLayerReference, Point, Px, Rect,
+};
+use qrcode::{EcLevel, QrCode};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+const SIGNING_KEY_PURPOSE: &str = "telemedicine prescription signing v1";
+// 二维码内容的格式标记，核验端据此识别版本
+const VERIFICATION_PREFIX: &str = "TMRX1.";
+
+// 打包在安装目录中的中文字体子集（TrueType 轮廓），生成方式见 resources/fonts/README.md
+pub const PRESCRIPTION_FONT_FILE: &str = "resources/fonts/NotoSansSC-Subset.ttf";
+// 没有打包字体时（开发环境）按顺序尝试的系统字体；printpdf 只能嵌入单个 TrueType 字体，不支持 .ttc
+const SYSTEM_FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/arphic/uming.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+];
+
+// A5 纸，单位毫米，原点在左下角
+const PAGE_WIDTH: f32 = 148.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 12.0;
+const LOGO_SIZE: f32 = 16.0;
+const QR_SIZE: f32 = 26.0;
+const ROW_HEIGHT: f32 = 6.0;
+// 药品明细写到该高度以下时换页，留出医师签名和二维码的位置
+const FOOTER_TOP: f32 = 50.0;
+// 药品、规格、用量、频次、天数各列的起始横坐标
+const COLUMNS: [f32; 5] = [MARGIN, 62.0, 90.0, 106.0, 130.0];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrescriptionPdfResult {
+    pub path: String,
+    pub sha256: String,
+}
+
+// 二维码中签名的核验内容，药品明细只记录摘要
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrescriptionVerification {
+    pub v: u8,
+    #[serde(rename = "rx")]
+    pub prescription_id: String,
+    #[serde(rename = "cid")]
+    pub consultation_id: String,
+    #[serde(rename = "did")]
+    pub doctor_id: String,
+    #[serde(rename = "iat")]
+    pub issued_at: DateTime<Utc>,
+    #[serde(rename = "sha")]
+    pub items_sha256: String,
+}
+
+impl PrescriptionVerification {
+    pub fn for_prescription(prescription: &Prescription) -> Result<Self> {
+        let issued_at = prescription.issued_at.ok_or_else(|| anyhow!("处方尚未开具"))?;
+        Ok(Self {
+            v: 1,
+            prescription_id: prescription.id.clone(),
+            consultation_id: prescription.consultation_id.clone(),
+            doctor_id: prescription.doctor_id.clone(),
+            issued_at,
+            items_sha256: to_hex(&Sha256::digest(serde_json::to_vec(&prescription.items)?)),
+        })
+    }
+}
+
+// 处方签名密钥由本机主密钥派生，重装或换机后签名随之改变
+pub fn prescription_signing_key(crypto: &CryptoService) -> SigningKey {
+    SigningKey::from_bytes(&crypto.derive_key(SIGNING_KEY_PURPOSE))
+}
+
+// 二维码内容：TMRX1.<核验内容 JSON>.<Ed25519 签名>，两段均为 base64url
+pub fn sign_verification(verification: &PrescriptionVerification, key: &SigningKey) -> Result<String> {
+    let payload = serde_json::to_vec(verification)?;
+    let signature = key.sign(&payload);
+    Ok(format!(
+        "{}{}.{}",
+        VERIFICATION_PREFIX,
+        URL_SAFE_NO_PAD.encode(&payload),
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    ))
+}
+
+pub fn verify_verification_code(code: &str, key: &VerifyingKey) -> Result<PrescriptionVerification> {
+    let body = code.strip_prefix(VERIFICATION_PREFIX).ok_or_else(|| anyhow!("不是处方核验码"))?;
+    let (payload, signature) = body.split_once('.').ok_or_else(|| anyhow!("处方核验码格式错误"))?;
+    let payload = URL_SAFE_NO_PAD.decode(payload)?;
+    let signature: [u8; 64] = URL_SAFE_NO_PAD
+        .decode(signature)?
+        .try_into()
+        .map_err(|_| anyhow!("处方核验码签名长度错误"))?;
+    key.verify(&payload, &Signature::from_bytes(&signature))
+        .map_err(|_| anyhow!("处方核验码签名无效"))?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+// 优先使用安装目录中的字体，开发环境使用源码目录下的同一文件，均不存在时退回系统字体
+pub fn load_prescription_font(resource_dir: Option<&Path>) -> Result<Vec<u8>> {
+    let bundled = [resource_dir, Some(Path::new(env!("CARGO_MANIFEST_DIR")))]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(PRESCRIPTION_FONT_FILE));
+    let system = SYSTEM_FONT_CANDIDATES.iter().map(PathBuf::from);
+
+    bundled
+        .chain(system)
+        .find_map(|path| std::fs::read(path).ok())
+        .ok_or_else(|| anyhow!("未找到可用于处方打印的中文字体"))
+}
+
+// 渲染前整理好的处方笺内容，患者信息已按导出人的权限脱敏
+#[derive(Debug, Clone)]
+pub struct PrescriptionSheet {
+    pub hospital_name: String,
+    pub logo_path: Option<PathBuf>,
+    pub prescription: Prescription,
+    pub patient: Option<Patient>,
+    pub doctor_name: String,
+    pub verification_code: String,
+}
+
+pub struct PrescriptionPdfService {
+    connection: DbConnection,
+    signing_key: SigningKey,
+}
+
+impl PrescriptionPdfService {
+    pub fn new() -> Result<Self> {
+        // 签名密钥与患者字段加密共用钥匙串中的主密钥
+        Ok(Self::with_connection(get_database().get_connection(), prescription_signing_key(field_crypto()?)))
+    }
+
+    pub fn with_connection(connection: DbConnection, signing_key: SigningKey) -> Self {
+        Self { connection, signing_key }
+    }
+
+    // 只有已开具的处方可以打印，草稿和已作废的处方拒绝生成
+    pub fn collect(
+        &self,
+        prescription_id: &str,
+        hospital: &HospitalProfileConfig,
+        masking: &MaskingPolicy,
+    ) -> Result<PrescriptionSheet> {
+        let prescription = PrescriptionDao::with_connection(self.connection.clone())
+            .find_by_id(prescription_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .ok_or_else(|| anyhow!("处方不存在"))?;
+        if prescription.status != PrescriptionStatus::Issued {
+            return Err(anyhow!("只有已开具的处方可以打印，当前状态：{}", prescription.status.label()));
+        }
+
+        let consultation = ConsultationDao::with_connection(self.connection.clone())
+            .find_by_id(&prescription.consultation_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .ok_or_else(|| anyhow!("问诊不存在"))?;
+        let patient = PatientDao::with_connection(self.connection.clone())
+            .find_by_id(&consultation.patient_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .map(|mut patient| {
+                masking.apply(&mut patient);
+                patient
+            });
+
+        let verification = PrescriptionVerification::for_prescription(&prescription)?;
+        Ok(PrescriptionSheet {
+            hospital_name: hospital.name.clone(),
+            logo_path: hospital.logo_path.as_ref().map(PathBuf::from),
+            doctor_name: self.doctor_name(&prescription.doctor_id)?,
+            verification_code: sign_verification(&verification, &self.signing_key)?,
+            prescription,
+            patient,
+        })
+    }
+
+    pub fn generate(
+        &self,
+        prescription_id: &str,
+        output_path: &Path,
+        hospital: &HospitalProfileConfig,
+        masking: &MaskingPolicy,
+        font: &[u8],
+    ) -> Result<PrescriptionPdfResult> {
+        let sheet = self.collect(prescription_id, hospital, masking)?;
+        let pdf = render_prescription_pdf(&sheet, font)?;
+
+        if let Some(parent) = output_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(output_path, &pdf)?;
+
+        Ok(PrescriptionPdfResult {
+            path: output_path.to_string_lossy().to_string(),
+            sha256: to_hex(&Sha256::digest(&pdf)),
+        })
+    }
+
+    // 本机登录过的医生显示用户名，否则显示医生 ID
+    fn doctor_name(&self, doctor_id: &str) -> Result<String> {
+        let conn = self.connection.lock().unwrap();
+        let username: Option<String> = conn
+            .query_row("SELECT username FROM users WHERE id = ?1", params![doctor_id], |row| row.get(0))
+            .optional()?;
+        Ok(username.unwrap_or_else(|| doctor_id.to_string()))
+    }
+}
+
+fn gender_label(gender: Option<&str>) -> &'static str {
+    match gender.and_then(Gender::parse) {
+        Some(Gender::Male) => "男",
+        Some(Gender::Female) => "女",
+        _ => "未知",
+    }
+}
+
+// 按字形宽度估算文字宽度（毫米）：中文约为一个字号宽，ASCII 约为半个
+fn text_width(text: &str, font_size: f32) -> f32 {
+    let em = font_size * 25.4 / 72.0;
+    text.chars().map(|c| if c.is_ascii() { em * 0.55 } else { em }).sum()
+}
+
+// 超出列宽的文字按字符折行
+fn wrap_text(text: &str, font_size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for ch in text.chars() {
+        current.push(ch);
+        if text_width(&current, font_size) > max_width && current.chars().count() > 1 {
+            current.pop();
+            lines.push(std::mem::take(&mut current));
+            current.push(ch);
+        }
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn horizontal_rule(layer: &PdfLayerReference, y: f32) {
+    layer.add_line(Line {
+        points: vec![
+            (Point::new(Mm(MARGIN), Mm(y)), false),
+            (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(y)), false),
+        ],
+        is_closed: false,
+    });
+}
+
+// 医院标志缩放到 LOGO_SIZE 见方以内，读取失败时只打印医院名称
+fn draw_logo(layer: &PdfLayerReference, path: &Path) -> bool {
+    let logo = match image::open(path) {
+        Ok(logo) => logo.to_rgb8(),
+        Err(e) => {
+            tracing::warn!("Failed to load hospital logo {}: {}", path.display(), e);
+            return false;
+        }
+    };
+    let (width, height) = logo.dimensions();
+    let dpi = width.max(height) as f32 * 25.4 / LOGO_SIZE;
+    let image = Image::from(ImageXObject {
+        width: Px(width as usize),
+        height: Px(height as usize),
+        color_space: ColorSpace::Rgb,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data: logo.into_raw(),
+        image_filter: None,
+        smask: None,
+        clipping_bbox: None,
+    });
+    image.add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(PAGE_HEIGHT - MARGIN - LOGO_SIZE)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+    true
+}
+
+// 逐个模块绘制黑色方块，不依赖图片编码
+fn draw_qr_code(layer: &PdfLayerReference, content: &str, x: f32, y: f32) -> Result<()> {
+    let code = QrCode::with_error_correction_level(content.as_bytes(), EcLevel::M)?;
+    let width = code.width();
+    let module = QR_SIZE / width as f32;
+    for (index, color) in code.to_colors().into_iter().enumerate() {
+        if color != qrcode::Color::Dark {
+            continue;
+        }
+        let left = x + (index % width) as f32 * module;
+        let top = y + QR_SIZE - (index / width) as f32 * module;
+        layer.add_rect(Rect::new(Mm(left), Mm(top - module), Mm(left + module), Mm(top)));
+    }
+    Ok(())
+}
+
+fn draw_table_header(layer: &PdfLayerReference, font: &IndirectFontRef, y: f32) {
+    for (label, x) in ["药品名称", "规格", "单次用量", "频次", "天数"].iter().zip(COLUMNS) {
+        layer.use_text(*label, 9.0, Mm(x), Mm(y), font);
+    }
+}
+
+// 一味药占一行，药名过长时折行，备注另起一行
+fn draw_item(layer: &PdfLayerReference, font: &IndirectFontRef, item: &PrescriptionItem, y: f32) -> f32 {
+    let drug_lines = wrap_text(item.drug.trim(), 10.0, COLUMNS[1] - COLUMNS[0] - 2.0);
+    layer.use_text(item.spec.trim(), 10.0, Mm(COLUMNS[1]), Mm(y), font);
+    layer.use_text(item.dosage.to_string(), 10.0, Mm(COLUMNS[2]), Mm(y), font);
+    layer.use_text(item.frequency.trim(), 10.0, Mm(COLUMNS[3]), Mm(y), font);
+    layer.use_text(format!("{}天", item.days), 10.0, Mm(COLUMNS[4]), Mm(y), font);
+
+    let mut y = y;
+    for (index, line) in drug_lines.iter().enumerate() {
+        if index > 0 {
+            y -= ROW_HEIGHT;
+        }
+        layer.use_text(line.as_str(), 10.0, Mm(COLUMNS[0]), Mm(y), font);
+    }
+    if let Some(note) = item.note.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        for line in wrap_text(&format!("备注：{}", note), 8.0, PAGE_WIDTH - 2.0 * MARGIN - 4.0) {
+            y -= ROW_HEIGHT - 1.0;
+            layer.use_text(line, 8.0, Mm(MARGIN + 4.0), Mm(y), font);
+        }
+    }
+    y - ROW_HEIGHT
+}
+
+// 单个药品占用的高度，用于判断是否需要换页
+fn item_height(item: &PrescriptionItem) -> f32 {
+    let drug_lines = wrap_text(item.drug.trim(), 10.0, COLUMNS[1] - COLUMNS[0] - 2.0).len();
+    let note_lines = item
+        .note
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|note| wrap_text(&format!("备注：{}", note), 8.0, PAGE_WIDTH - 2.0 * MARGIN - 4.0).len())
+        .unwrap_or(0);
+    drug_lines as f32 * ROW_HEIGHT + note_lines as f32 * (ROW_HEIGHT - 1.0)
+}
+
+pub fn render_prescription_pdf(sheet: &PrescriptionSheet, font: &[u8]) -> Result<Vec<u8>> {
+    let prescription = &sheet.prescription;
+    let (doc, page, layer) =
+        PdfDocument::new(format!("处方笺 {}", prescription.id), Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "处方");
+    let font = doc.add_external_font(Cursor::new(font))?;
+    let mut layer = doc.get_page(page).get_layer(layer);
+    layer.set_outline_thickness(0.5);
+
+    // 抬头
+    let logo_drawn = sheet.logo_path.as_deref().map(|path| draw_logo(&layer, path)).unwrap_or(false);
+    let title_x = if logo_drawn { MARGIN + LOGO_SIZE + 4.0 } else { MARGIN };
+    layer.use_text(sheet.hospital_name.as_str(), 15.0, Mm(title_x), Mm(192.0), &font);
+    layer.use_text("处方笺", 12.0, Mm(title_x), Mm(184.0), &font);
+    horizontal_rule(&layer, 179.0);
+
+    // 患者信息
+    let (name, gender, age, id_card) = match &sheet.patient {
+        Some(patient) => (
+            patient.name.clone(),
+            gender_label(patient.gender.as_deref()),
+            patient.age.map(|age| format!("{}岁", age)).unwrap_or_else(|| "-".to_string()),
+            patient.id_card.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+        None => ("-".to_string(), gender_label(None), "-".to_string(), "-".to_string()),
+    };
+    layer.use_text(format!("姓名：{}", name), 10.0, Mm(MARGIN), Mm(172.0), &font);
+    layer.use_text(format!("性别：{}", gender), 10.0, Mm(70.0), Mm(172.0), &font);
+    layer.use_text(format!("年龄：{}", age), 10.0, Mm(104.0), Mm(172.0), &font);
+    layer.use_text(format!("身份证号：{}", id_card), 10.0, Mm(MARGIN), Mm(166.0), &font);
+    layer.use_text(format!("处方编号：{}", prescription.id), 9.0, Mm(MARGIN), Mm(160.0), &font);
+    horizontal_rule(&layer, 156.0);
+
+    // 药品明细，写不下时换页并重复表头
+    layer.use_text("Rp.", 13.0, Mm(MARGIN), Mm(148.0), &font);
+    draw_table_header(&layer, &font, 141.0);
+    let mut y = 134.0;
+    for item in &prescription.items {
+        if y - item_height(item) < FOOTER_TOP {
+            let (page, next) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "处方（续）");
+            layer = doc.get_page(page).get_layer(next);
+            layer.set_outline_thickness(0.5);
+            layer.use_text(format!("处方编号：{}（续）", prescription.id), 9.0, Mm(MARGIN), Mm(192.0), &font);
+            draw_table_header(&layer, &font, 184.0);
+            y = 177.0;
+        }
+        y = draw_item(&layer, &font, item, y);
+    }
+
+    // 医师、开具时间和核验二维码在最后一页底部
+    horizontal_rule(&layer, FOOTER_TOP - 2.0);
+    layer.use_text(format!("医师：{}", sheet.doctor_name), 10.0, Mm(MARGIN), Mm(40.0), &font);
+    if let Some(issued_at) = prescription.issued_at {
+        layer.use_text(format!("开具时间：{}", format_local(issued_at)), 8.0, Mm(MARGIN), Mm(32.0), &font);
+    }
+    layer.use_text("扫描右侧二维码核验处方真伪，涂改无效", 8.0, Mm(MARGIN), Mm(26.0), &font);
+    draw_qr_code(&layer, &sheet.verification_code, PAGE_WIDTH - MARGIN - QR_SIZE, MARGIN)?;
+
+    Ok(doc.save_to_bytes()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[5u8; 32])
+    }
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO users (id, username) VALUES ('d1', 'Dr. Li');
+             INSERT INTO patients (id, name, age, gender, id_card) VALUES ('p1', 'Zhang San', 42, 'male', '110101198001011234');
+             INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type) VALUES ('c1', 'p1', 'd1', 'active', 'text');
+             INSERT INTO prescriptions (id, consultation_id, doctor_id, items, status, issued_at) VALUES
+                 ('rx-fixture-001', 'c1', 'd1', '[{\"drug\":\"Amoxicillin Capsules\",\"spec\":\"0.25g x 24\",\"dosage\":0.5,\"frequency\":\"TID\",\"days\":7,\"note\":\"after meals\"}]',
+                  'issued', '2024-03-01T08:00:00Z'),
+                 ('rx-draft', 'c1', 'd1', '[]', 'draft', NULL);",
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn hospital() -> HospitalProfileConfig {
+        HospitalProfileConfig {
+            name: "Demo Hospital".to_string(),
+            logo_path: None,
+        }
+    }
+
+    fn font() -> Vec<u8> {
+        load_prescription_font(None).expect("prescription font not available")
+    }
+
+    #[test]
+    fn test_generated_pdf_contains_prescription_text() {
+        let dir = tempdir().unwrap();
+        let logo = dir.path().join("logo.png");
+        image::RgbImage::from_pixel(32, 16, image::Rgb([0x1f, 0x77, 0xb4])).save(&logo).unwrap();
+        let output = dir.path().join("out").join("rx.pdf");
+        let hospital = HospitalProfileConfig {
+            logo_path: Some(logo.to_string_lossy().to_string()),
+            ..hospital()
+        };
+
+        let service = PrescriptionPdfService::with_connection(create_test_connection(), signing_key());
+        let result = service
+            .generate("rx-fixture-001", &output, &hospital, &MaskingPolicy::ALL, &font())
+            .unwrap();
+
+        let bytes = std::fs::read(&output).unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+        assert_eq!(result.sha256, to_hex(&Sha256::digest(&bytes)));
+
+        let text = pdf_extract::extract_text_from_mem(&bytes).unwrap();
+        for expected in ["Demo Hospital", "rx-fixture-001", "Amoxicillin Capsules", "0.25g x 24", "TID", "Dr. Li"] {
+            assert!(text.contains(expected), "missing {:?} in {:?}", expected, text);
+        }
+        // 按导出人的权限脱敏身份证号
+        assert!(text.contains("110101********1234"));
+        assert!(!text.contains("110101198001011234"));
+    }
+
+    #[test]
+    fn test_verification_code_is_signed_by_install_key() {
+        let service = PrescriptionPdfService::with_connection(create_test_connection(), signing_key());
+        let sheet = service.collect("rx-fixture-001", &hospital(), &MaskingPolicy::NONE).unwrap();
+
+        let verification = verify_verification_code(&sheet.verification_code, &signing_key().verifying_key()).unwrap();
+        assert_eq!(verification.prescription_id, "rx-fixture-001");
+        assert_eq!(verification.doctor_id, "d1");
+        assert_eq!(verification.issued_at.to_rfc3339(), "2024-03-01T08:00:00+00:00");
+        assert_eq!(
+            verification.items_sha256,
+            to_hex(&Sha256::digest(serde_json::to_vec(&sheet.prescription.items).unwrap()))
+        );
+
+        let other = SigningKey::from_bytes(&[6u8; 32]).verifying_key();
+        assert!(verify_verification_code(&sheet.verification_code, &other).is_err());
+        let tampered = sheet.verification_code.replacen("TMRX1.e", "TMRX1.f", 1);
+        assert!(verify_verification_code(&tampered, &signing_key().verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_draft_prescription_cannot_be_printed() {
+        let service = PrescriptionPdfService::with_connection(create_test_connection(), signing_key());
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("draft.pdf");
+
+        let error = service
+            .generate("rx-draft", &output, &hospital(), &MaskingPolicy::NONE, &font())
+            .unwrap_err();
+        assert!(error.to_string().contains("草稿"));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_long_item_list_spans_pages() {
+        let items: Vec<_> = (0..30)
+            .map(|i| PrescriptionItem {
+                drug: format!("Drug {}", i),
+                spec: "10mg".to_string(),
+                dosage: 1.0,
+                frequency: "QD".to_string(),
+                days: 3,
+                note: None,
+            })
+            .collect();
+        let now = Utc::now();
+        let sheet = PrescriptionSheet {
+            hospital_name: "Demo Hospital".to_string(),
+            logo_path: None,
+            prescription: Prescription {
+                id: "rx-long".to_string(),
+                consultation_id: "c1".to_string(),
+                doctor_id: "d1".to_string(),
+                items,
+                status: PrescriptionStatus::Issued,
+                void_reason: None,
+                created_at: now,
+                updated_at: now,
+                issued_at: Some(now),
+                voided_at: None,
+            },
+            patient: None,
+            doctor_name: "Dr. Li".to_string(),
+            verification_code: "TMRX1.test".to_string(),
+        };
+
+        let pdf = render_prescription_pdf(&sheet, &font()).unwrap();
+        let text = pdf_extract::extract_text_from_mem(&pdf).unwrap();
+        // 续页顶部重复处方编号，医师签名只出现在最后一页的明细之后
+        assert_eq!(text.matches("rx-long").count(), 2);
+        let last_item = text.find("Drug 29").unwrap();
+        assert!(text.contains("Drug 0") && text.find("Dr. Li").unwrap() > last_item);
+    }
+}
@@ -0,0 +1,173 @@
+// 已读回执：把患者的已读状态同步到本地医生发出的消息
+
+use crate::database::dao::{BaseDao, MessageDao};
+use crate::models::{ReadStatus, SenderType};
+use crate::services::WebSocketEvent;
+use serde::Serialize;
+
+// 推送到问诊窗口的事件名，前端按 messageIds 只刷新对应的气泡
+pub const MESSAGES_READ_EVENT: &str = "consultation-messages-read";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReadReceiptUpdate {
+    #[serde(rename = "consultationId")]
+    pub consultation_id: String,
+    #[serde(rename = "messageIds")]
+    pub message_ids: Vec<String>,
+}
+
+// 应用单条或批量已读回执，没有消息状态变化时返回 None
+pub fn apply_read_receipt(dao: &MessageDao, event: &WebSocketEvent) -> Option<ReadReceiptUpdate> {
+    match event {
+        WebSocketEvent::ReadReceipt { consultation_id, message_id, .. } => {
+            apply_single(dao, consultation_id, message_id)
+        }
+        WebSocketEvent::ReadReceiptBatch { consultation_id, up_to_timestamp } => {
+            match dao.mark_sent_messages_read_up_to(consultation_id, *up_to_timestamp) {
+                Ok(message_ids) if message_ids.is_empty() => None,
+                Ok(message_ids) => Some(ReadReceiptUpdate {
+                    consultation_id: consultation_id.clone(),
+                    message_ids,
+                }),
+                Err(e) => {
+                    tracing::warn!("Failed to apply read receipts for consultation {}: {}", consultation_id, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+fn apply_single(dao: &MessageDao, consultation_id: &str, message_id: &str) -> Option<ReadReceiptUpdate> {
+    let message = match dao.find_by_id(message_id) {
+        Ok(Some(message)) => message,
+        Ok(None) => {
+            // 本地可能尚未同步到该消息，忽略即可
+            tracing::warn!("Read receipt for unknown message {} ignored", message_id);
+            return None;
+        }
+        Err(e) => {
+            tracing::warn!("Failed to load message {} for read receipt: {}", message_id, e);
+            return None;
+        }
+    };
+
+    if message.consultation_id != consultation_id {
+        tracing::warn!("Read receipt for message {} does not match consultation {}", message_id, consultation_id);
+        return None;
+    }
+    // 只有医生发出的消息才需要显示对方已读
+    if !matches!(message.sender_type, SenderType::Doctor) || matches!(message.read_status, ReadStatus::Read) {
+        return None;
+    }
+
+    if let Err(e) = dao.update_read_status(message_id, ReadStatus::Read) {
+        tracing::warn!("Failed to update read status for message {}: {}", message_id, e);
+        return None;
+    }
+
+    Some(ReadReceiptUpdate {
+        consultation_id: consultation_id.to_string(),
+        message_ids: vec![message_id.to_string()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{Message, MessageType, SyncStatus};
+    use chrono::{DateTime, Duration, Utc};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES ('c1', 'p1', 'd1', 'active');"
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn seed(connection: &DbConnection, id: &str, sender_type: SenderType, timestamp: DateTime<Utc>) {
+        let message = Message {
+            id: id.to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type,
+            message_type: MessageType::Text,
+            content: Some("请按时复诊".to_string()),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp,
+            sync_status: SyncStatus::Synced,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        };
+        MessageDao::upsert_in(&connection.lock().unwrap(), &message).unwrap();
+    }
+
+    fn is_read(dao: &MessageDao, id: &str) -> bool {
+        matches!(dao.find_by_id(id).unwrap().unwrap().read_status, ReadStatus::Read)
+    }
+
+    fn receipt(message_id: &str) -> WebSocketEvent {
+        WebSocketEvent::ReadReceipt {
+            consultation_id: "c1".to_string(),
+            message_id: message_id.to_string(),
+            read_by: "p1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_single_read_receipt() {
+        let connection = create_test_connection();
+        let dao = MessageDao::with_connection(connection.clone());
+        seed(&connection, "m-doctor", SenderType::Doctor, Utc::now());
+        seed(&connection, "m-patient", SenderType::Patient, Utc::now());
+
+        let update = apply_read_receipt(&dao, &receipt("m-doctor")).unwrap();
+        assert_eq!(update.consultation_id, "c1");
+        assert_eq!(update.message_ids, vec!["m-doctor".to_string()]);
+        assert!(is_read(&dao, "m-doctor"));
+
+        // 重复回执、患者自己的消息和未知消息都不触发刷新
+        assert!(apply_read_receipt(&dao, &receipt("m-doctor")).is_none());
+        assert!(apply_read_receipt(&dao, &receipt("m-patient")).is_none());
+        assert!(!is_read(&dao, "m-patient"));
+        assert!(apply_read_receipt(&dao, &receipt("m-unknown")).is_none());
+    }
+
+    #[test]
+    fn test_batch_read_receipt() {
+        let connection = create_test_connection();
+        let dao = MessageDao::with_connection(connection.clone());
+        let now = Utc::now();
+        seed(&connection, "m1", SenderType::Doctor, now - Duration::minutes(10));
+        seed(&connection, "m2", SenderType::Doctor, now - Duration::minutes(5));
+        seed(&connection, "m-patient", SenderType::Patient, now - Duration::minutes(4));
+        seed(&connection, "m3", SenderType::Doctor, now + Duration::minutes(1));
+
+        let batch = WebSocketEvent::ReadReceiptBatch {
+            consultation_id: "c1".to_string(),
+            up_to_timestamp: now,
+        };
+        let update = apply_read_receipt(&dao, &batch).unwrap();
+        assert_eq!(update.message_ids, vec!["m1".to_string(), "m2".to_string()]);
+        assert!(is_read(&dao, "m1") && is_read(&dao, "m2"));
+        assert!(!is_read(&dao, "m3"));
+        assert!(!is_read(&dao, "m-patient"));
+
+        // 已经全部已读时不再推送
+        assert!(apply_read_receipt(&dao, &batch).is_none());
+    }
+}
@@ -0,0 +1,376 @@
+// 病历模板服务：模板管理与占位符渲染
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, ConsultationDao, PatientDao, RecordTemplateDao};
+use crate::models::{
+    AppError, Consultation, CreateRecordTemplateRequest, ErrorType, Patient, RecordTemplate, RenderedTemplate,
+};
+use crate::utils::active_timezone;
+use chrono::Utc;
+use regex::{Captures, Regex};
+use std::collections::{HashMap, HashSet};
+
+pub type TemplateResult<T> = Result<T, AppError>;
+
+const RECORD_TYPES: [&str; 4] = ["diagnosis", "prescription", "examination", "treatment"];
+
+pub struct RecordTemplateService {
+    template_dao: RecordTemplateDao,
+    consultation_dao: ConsultationDao,
+    patient_dao: PatientDao,
+}
+
+impl RecordTemplateService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            template_dao: RecordTemplateDao::with_connection(connection.clone()),
+            consultation_dao: ConsultationDao::with_connection(connection.clone()),
+            patient_dao: PatientDao::with_connection(connection),
+        }
+    }
+
+    pub async fn create_template(&self, doctor_id: &str, request: CreateRecordTemplateRequest) -> TemplateResult<RecordTemplate> {
+        if !RECORD_TYPES.contains(&request.record_type.as_str()) {
+            return Err(validation_error("不支持的病历类型"));
+        }
+        if request.title.trim().is_empty() {
+            return Err(validation_error("模板标题不能为空"));
+        }
+        if request.content.trim().is_empty() {
+            return Err(validation_error("模板内容不能为空"));
+        }
+
+        let mut names = HashSet::new();
+        for variable in &request.variables {
+            if variable.name.trim().is_empty() || variable.name.contains(['{', '}']) || variable.name.contains(char::is_whitespace) {
+                return Err(validation_error(&format!("变量名不合法: {}", variable.name)));
+            }
+            if !names.insert(variable.name.as_str()) {
+                return Err(validation_error(&format!("变量名重复: {}", variable.name)));
+            }
+        }
+
+        let template = RecordTemplate {
+            id: String::new(),
+            doctor_id: doctor_id.to_string(),
+            record_type: request.record_type,
+            title: request.title.trim().to_string(),
+            content: request.content,
+            variables: request.variables,
+            created_at: Utc::now(),
+        };
+
+        let id = self.template_dao.create(&template).map_err(dao_error)?;
+        self.load(&id)
+    }
+
+    pub async fn list_templates(&self, doctor_id: &str, record_type: Option<&str>) -> TemplateResult<Vec<RecordTemplate>> {
+        self.template_dao
+            .find_by_doctor(doctor_id, record_type)
+            .map_err(dao_error)
+    }
+
+    pub async fn delete_template(&self, doctor_id: &str, template_id: &str) -> TemplateResult<()> {
+        let template = self.load(template_id)?;
+        if template.doctor_id != doctor_id {
+            return Err(AppError::new(ErrorType::PermissionError, "只能删除自己创建的模板").with_code("TEMPLATE_FORBIDDEN"));
+        }
+
+        self.template_dao.delete(template_id).map_err(dao_error)
+    }
+
+    // 变量取值优先级：调用方传入 > 问诊关联的患者信息 > 模板默认值
+    pub async fn render_template(
+        &self,
+        template_id: &str,
+        variables: HashMap<String, String>,
+        consultation_id: Option<&str>,
+    ) -> TemplateResult<RenderedTemplate> {
+        let template = self.load(template_id)?;
+
+        let mut values: HashMap<String, String> = template
+            .variables
+            .iter()
+            .filter_map(|v| v.default_value.clone().map(|d| (v.name.clone(), d)))
+            .collect();
+        values.insert("today".to_string(), today());
+        if let Some(consultation_id) = consultation_id {
+            values.extend(self.consultation_values(consultation_id)?);
+        }
+        values.extend(variables);
+
+        let mut missing = Vec::new();
+        let title = substitute(&template.title, &values, &mut missing);
+        let content = substitute(&template.content, &values, &mut missing);
+
+        if !missing.is_empty() {
+            return Err(AppError::new(
+                ErrorType::ValidationError,
+                format!("模板缺少变量: {}", missing.join(", ")),
+            )
+            .with_code("MISSING_TEMPLATE_VARIABLES")
+            .with_details(serde_json::json!({ "missing": missing })));
+        }
+
+        Ok(RenderedTemplate {
+            template_id: template.id,
+            record_type: template.record_type,
+            title,
+            content,
+        })
+    }
+
+    fn consultation_values(&self, consultation_id: &str) -> TemplateResult<HashMap<String, String>> {
+        let consultation = self
+            .consultation_dao
+            .find_by_id(consultation_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| not_found(&format!("问诊不存在: {}", consultation_id)))?;
+        let patient = self
+            .patient_dao
+            .find_by_id(&consultation.patient_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| not_found(&format!("患者不存在: {}", consultation.patient_id)))?;
+
+        Ok(placeholder_values(&patient, &consultation))
+    }
+
+    fn load(&self, template_id: &str) -> TemplateResult<RecordTemplate> {
+        self.template_dao
+            .find_by_id(template_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| not_found(&format!("模板不存在: {}", template_id)))
+    }
+}
+
+impl Default for RecordTemplateService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 问诊关联患者可自动填充的占位符
+pub(crate) fn placeholder_values(patient: &Patient, consultation: &Consultation) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    values.insert("patient_name".to_string(), patient.name.clone());
+    if let Some(age) = patient.age {
+        values.insert("patient_age".to_string(), age.to_string());
+    }
+    if let Some(gender) = &patient.gender {
+        let label = match gender.as_str() {
+            "male" => "男",
+            "female" => "女",
+            _ => "未知",
+        };
+        values.insert("patient_gender".to_string(), label.to_string());
+    }
+    if let Some(title) = &consultation.title {
+        values.insert("consultation_title".to_string(), title.clone());
+    }
+    values.insert("today".to_string(), today());
+
+    values
+}
+
+// 按配置的显示时区取当天日期
+fn today() -> String {
+    Utc::now().with_timezone(&active_timezone()).format("%Y-%m-%d").to_string()
+}
+
+// 替换 {{变量名}} 占位符；替换后的值不再展开，缺失的变量按出现顺序去重记录
+pub(crate) fn substitute(text: &str, values: &HashMap<String, String>, missing: &mut Vec<String>) -> String {
+    let placeholder = Regex::new(r"\{\{\s*([^{}\s]+)\s*\}\}").unwrap();
+
+    placeholder
+        .replace_all(text, |caps: &Captures| {
+            let name = &caps[1];
+            match values.get(name) {
+                Some(value) => value.clone(),
+                None => {
+                    if !missing.iter().any(|m| m == name) {
+                        missing.push(name.to_string());
+                    }
+                    caps[0].to_string()
+                }
+            }
+        })
+        .into_owned()
+}
+
+fn validation_error(message: &str) -> AppError {
+    AppError::new(ErrorType::ValidationError, message).with_code("INVALID_TEMPLATE")
+}
+
+fn not_found(message: &str) -> AppError {
+    AppError::new(ErrorType::DataError, message).with_code("NOT_FOUND")
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{ConsultationPriority, TemplateVariable};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    async fn create(service: &RecordTemplateService, title: &str, content: &str, variables: Vec<TemplateVariable>) -> RecordTemplate {
+        service
+            .create_template(
+                "d1",
+                CreateRecordTemplateRequest {
+                    record_type: "examination".to_string(),
+                    title: title.to_string(),
+                    content: content.to_string(),
+                    variables,
+                },
+            )
+            .await
+            .unwrap()
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_render_chinese_text_and_defaults() {
+        let service = RecordTemplateService::with_connection(create_test_connection());
+        let template = create(
+            &service,
+            "{{患者}}的体检报告",
+            "患者{{患者}}，血压{{ 血压 }}mmHg，结论：{{结论}}。",
+            vec![TemplateVariable {
+                name: "结论".to_string(),
+                label: Some("检查结论".to_string()),
+                default_value: Some("未见异常".to_string()),
+            }],
+        )
+        .await;
+
+        let rendered = service
+            .render_template(&template.id, vars(&[("患者", "张三"), ("血压", "120/80")]), None)
+            .await
+            .unwrap();
+        assert_eq!(rendered.title, "张三的体检报告");
+        assert_eq!(rendered.content, "患者张三，血压120/80mmHg，结论：未见异常。");
+    }
+
+    #[tokio::test]
+    async fn test_render_reports_all_missing_variables() {
+        let service = RecordTemplateService::with_connection(create_test_connection());
+        let template = create(&service, "复诊记录", "{{patient_name}} 主诉 {{complaint}}，{{unknown}}，{{complaint}}", vec![]).await;
+
+        let error = service
+            .render_template(&template.id, vars(&[("patient_name", "李四")]), None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("MISSING_TEMPLATE_VARIABLES"));
+        assert_eq!(
+            error.details.unwrap()["missing"],
+            serde_json::json!(["complaint", "unknown"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_nested_braces() {
+        let service = RecordTemplateService::with_connection(create_test_connection());
+        let template = create(&service, "处方", "{{{drug}}} 用法：{{ usage}}；{ 单括号 }；{{}}", vec![]).await;
+
+        // 值中包含的占位符不会被再次展开
+        let rendered = service
+            .render_template(&template.id, vars(&[("drug", "阿莫西林"), ("usage", "{{drug}} 每日三次")]), None)
+            .await
+            .unwrap();
+        assert_eq!(rendered.content, "{阿莫西林} 用法：{{drug}} 每日三次；{ 单括号 }；{{}}");
+    }
+
+    #[tokio::test]
+    async fn test_render_with_consultation_patient_fields() {
+        let connection = create_test_connection();
+        let now = Utc::now();
+        PatientDao::with_connection(connection.clone())
+            .upsert(&Patient {
+                id: "p1".to_string(),
+                name: "王五".to_string(),
+                age: Some(62),
+                gender: Some("female".to_string()),
+                phone: None,
+                id_card: None,
+                tags: vec![],
+                avatar_url: None,
+                last_sync: None,
+                last_visit: None,
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+        let consultation_id = ConsultationDao::with_connection(connection.clone())
+            .create(&Consultation {
+                id: String::new(),
+                patient_id: "p1".to_string(),
+                doctor_id: "d1".to_string(),
+                status: "active".to_string(),
+                consultation_type: "text".to_string(),
+                title: None,
+                description: None,
+                diagnosis: None,
+                prescription: None,
+                created_at: now,
+                updated_at: now,
+                accepted_at: None,
+                completed_at: None,
+                cancel_reason: None,
+                version: 1,
+                priority: ConsultationPriority::Normal,
+                queued_at: None,
+            })
+            .unwrap();
+
+        let service = RecordTemplateService::with_connection(connection);
+        let template = create(&service, "检查", "{{patient_name}}，{{patient_gender}}，{{patient_age}}岁", vec![]).await;
+
+        let rendered = service
+            .render_template(&template.id, HashMap::new(), Some(&consultation_id))
+            .await
+            .unwrap();
+        assert_eq!(rendered.content, "王五，女，62岁");
+
+        // 调用方传入的值优先
+        let rendered = service
+            .render_template(&template.id, vars(&[("patient_name", "王五（家属代述）")]), Some(&consultation_id))
+            .await
+            .unwrap();
+        assert!(rendered.content.starts_with("王五（家属代述）"));
+    }
+
+    #[tokio::test]
+    async fn test_list_and_delete_templates() {
+        let service = RecordTemplateService::with_connection(create_test_connection());
+        let template = create(&service, "血常规", "结果：{{result}}", vec![]).await;
+
+        assert_eq!(service.list_templates("d1", Some("examination")).await.unwrap().len(), 1);
+        assert!(service.list_templates("d1", Some("diagnosis")).await.unwrap().is_empty());
+
+        let error = service.delete_template("d2", &template.id).await.unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::PermissionError));
+
+        service.delete_template("d1", &template.id).await.unwrap();
+        assert!(service.list_templates("d1", None).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+// 资源监控服务：采样当前进程及 WebView 子进程的内存、CPU 占用
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, System};
+
+// 采样结果缓存时间，避免频繁调用时反复读取 /proc
+const SAMPLE_TTL: Duration = Duration::from_millis(1500);
+// 达到阈值的该比例时提示警告
+const WARNING_RATIO: f64 = 0.8;
+// 系统可用内存低于该值时至少提示警告
+const LOW_AVAILABLE_MEMORY_MB: u64 = 512;
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryPressure {
+    Ok,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceSample {
+    pub process_memory_mb: u64,
+    pub webview_memory_mb: u64,
+    pub cpu_usage: f32,
+    pub available_memory_mb: u64,
+    pub total_memory_mb: u64,
+}
+
+impl ResourceSample {
+    // 应用总内存占用（主进程 + WebView 子进程）
+    pub fn memory_usage_mb(&self) -> u64 {
+        self.process_memory_mb + self.webview_memory_mb
+    }
+}
+
+#[derive(Debug)]
+pub struct ResourceMonitor {
+    system: System,
+    pid: Option<Pid>,
+    last_sample: Option<(Instant, ResourceSample)>,
+    last_pressure: MemoryPressure,
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        Self {
+            system: System::new(),
+            pid: sysinfo::get_current_pid().ok(),
+            last_sample: None,
+            last_pressure: MemoryPressure::Ok,
+        }
+    }
+
+    pub fn sample(&mut self) -> ResourceSample {
+        if let Some((sampled_at, sample)) = &self.last_sample {
+            if sampled_at.elapsed() < SAMPLE_TTL {
+                return sample.clone();
+            }
+        }
+
+        let sample = self.collect();
+        self.last_sample = Some((Instant::now(), sample.clone()));
+        sample
+    }
+
+    // 记录最新的内存压力，状态变化时返回 true
+    pub fn update_pressure(&mut self, pressure: MemoryPressure) -> bool {
+        let changed = self.last_pressure != pressure;
+        self.last_pressure = pressure;
+        changed
+    }
+
+    fn collect(&mut self) -> ResourceSample {
+        self.system.refresh_memory();
+        self.system.refresh_processes();
+
+        let (process_memory, webview_memory, cpu_usage) = match self.pid {
+            Some(pid) => {
+                let process_memory = self.system.process(pid).map(|p| p.memory()).unwrap_or(0);
+                let descendants = self.descendants(pid);
+                let webview_memory: u64 = descendants
+                    .iter()
+                    .filter_map(|child| self.system.process(*child))
+                    .map(|p| p.memory())
+                    .sum();
+                let cpu_usage: f32 = std::iter::once(pid)
+                    .chain(descendants.iter().copied())
+                    .filter_map(|p| self.system.process(p))
+                    .map(|p| p.cpu_usage())
+                    .sum();
+                (process_memory, webview_memory, cpu_usage)
+            }
+            None => (0, 0, 0.0),
+        };
+
+        // sysinfo 的进程 CPU 占用按单核计算，换算为整机百分比
+        let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as f32;
+
+        ResourceSample {
+            process_memory_mb: process_memory / BYTES_PER_MB,
+            webview_memory_mb: webview_memory / BYTES_PER_MB,
+            cpu_usage: cpu_usage / cores,
+            available_memory_mb: self.system.available_memory() / BYTES_PER_MB,
+            total_memory_mb: self.system.total_memory() / BYTES_PER_MB,
+        }
+    }
+
+    // WebView 渲染进程在部分平台上是子进程（如 Linux 的 WebKitWebProcess）
+    fn descendants(&self, root: Pid) -> Vec<Pid> {
+        let mut found: HashSet<Pid> = HashSet::new();
+        let mut frontier = vec![root];
+
+        while let Some(parent) = frontier.pop() {
+            for (pid, process) in self.system.processes() {
+                if process.parent() == Some(parent) && found.insert(*pid) {
+                    frontier.push(*pid);
+                }
+            }
+        }
+
+        found.into_iter().collect()
+    }
+}
+
+impl Default for ResourceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn classify_pressure(sample: &ResourceSample, threshold_mb: u64) -> MemoryPressure {
+    let used = sample.memory_usage_mb();
+
+    if used >= threshold_mb {
+        return MemoryPressure::Critical;
+    }
+
+    if used as f64 >= threshold_mb as f64 * WARNING_RATIO
+        || sample.available_memory_mb < LOW_AVAILABLE_MEMORY_MB
+    {
+        return MemoryPressure::Warning;
+    }
+
+    MemoryPressure::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(process_mb: u64, webview_mb: u64, available_mb: u64) -> ResourceSample {
+        ResourceSample {
+            process_memory_mb: process_mb,
+            webview_memory_mb: webview_mb,
+            cpu_usage: 0.0,
+            available_memory_mb: available_mb,
+            total_memory_mb: 16384,
+        }
+    }
+
+    #[test]
+    fn test_classify_pressure() {
+        assert_eq!(classify_pressure(&sample(100, 100, 8000), 512), MemoryPressure::Ok);
+        assert_eq!(classify_pressure(&sample(200, 250, 8000), 512), MemoryPressure::Warning);
+        assert_eq!(classify_pressure(&sample(300, 212, 8000), 512), MemoryPressure::Critical);
+
+        // 系统可用内存不足时即使自身占用不高也提示
+        assert_eq!(classify_pressure(&sample(100, 100, 256), 512), MemoryPressure::Warning);
+    }
+
+    #[test]
+    fn test_update_pressure_reports_changes_only() {
+        let mut monitor = ResourceMonitor::new();
+
+        assert!(!monitor.update_pressure(MemoryPressure::Ok));
+        assert!(monitor.update_pressure(MemoryPressure::Warning));
+        assert!(!monitor.update_pressure(MemoryPressure::Warning));
+        assert!(monitor.update_pressure(MemoryPressure::Ok));
+    }
+
+    #[test]
+    fn test_sample_is_cached() {
+        let mut monitor = ResourceMonitor::new();
+
+        let first = monitor.sample();
+        let sampled_at = monitor.last_sample.as_ref().map(|(at, _)| *at);
+        let second = monitor.sample();
+
+        assert_eq!(first, second);
+        assert_eq!(monitor.last_sample.as_ref().map(|(at, _)| *at), sampled_at);
+        assert!(first.process_memory_mb > 0);
+    }
+}
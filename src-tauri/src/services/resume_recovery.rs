@@ -0,0 +1,444 @@
+// 休眠唤醒恢复：定期比较单调时钟与系统时间，发现跳变后依次检查自动锁屏、刷新 token、重建 WebSocket 连接并触发同步，
+// 各步骤互不影响，结果合并为一个 app-resumed 事件
+
+use crate::services::{SecurityService, SyncScheduler, SyncTrigger, TokenRefreshService, WebSocketManager};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+pub const APP_RESUMED_EVENT: &str = "app-resumed";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// 系统时间比单调时钟多走这么久视为经历了休眠，需大于检查间隔内可能的调度延迟
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+// 单个恢复步骤的超时，网络刚恢复时请求可能长时间无响应
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+const WEBSOCKET_RECONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResumeStep {
+    AutoLock,
+    Session,
+    WebSocket,
+    Sync,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResumeStepResult {
+    pub step: ResumeStep,
+    pub success: bool,
+    pub detail: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppResumedEvent {
+    // 估算的休眠时长
+    #[serde(rename = "sleptSeconds")]
+    pub slept_seconds: i64,
+    #[serde(rename = "resumedAt")]
+    pub resumed_at: DateTime<Utc>,
+    // 自动锁屏检查的结果，检查失败时按需要锁屏处理
+    #[serde(rename = "shouldLock")]
+    pub should_lock: bool,
+    pub steps: Vec<ResumeStepResult>,
+}
+
+impl AppResumedEvent {
+    pub fn all_succeeded(&self) -> bool {
+        self.steps.iter().all(|step| step.success)
+    }
+}
+
+/// 唤醒后需要恢复的各子系统，生产环境由 `AppResumeTarget` 实现
+#[async_trait]
+pub trait ResumeTarget: Send + Sync {
+    // 返回是否应锁屏
+    async fn evaluate_auto_lock(&self) -> Result<bool>;
+    // 返回会话的过期时间，未登录时返回 None
+    async fn refresh_session(&self) -> Result<Option<DateTime<Utc>>>;
+    // 返回重建成功的连接数，有连接重建失败时返回错误
+    async fn reconnect_websockets(&self) -> Result<usize>;
+    // 返回未能立即同步的原因（如已暂停），None 表示已触发
+    async fn trigger_sync(&self) -> Result<Option<String>>;
+}
+
+/// 比较两次检查之间单调时钟与系统时间各自走过的时长：
+/// 休眠期间单调时钟停止而系统时间照常前进，差值超过阈值即判定为唤醒
+#[derive(Debug)]
+pub struct ClockJumpDetector {
+    threshold: Duration,
+    last: Option<(Instant, DateTime<Utc>)>,
+}
+
+impl ClockJumpDetector {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold, last: None }
+    }
+
+    // 记录一次检查，发生跳变时返回估算的休眠时长；系统时间被调慢不视为休眠
+    pub fn observe(&mut self, now: Instant, wall: DateTime<Utc>) -> Option<chrono::Duration> {
+        let (last_instant, last_wall) = self.last.replace((now, wall))?;
+        let monotonic = chrono::Duration::from_std(now.saturating_duration_since(last_instant)).ok()?;
+        let gap = (wall - last_wall) - monotonic;
+        let threshold = chrono::Duration::from_std(self.threshold).ok()?;
+        (gap > threshold).then_some(gap)
+    }
+}
+
+pub struct ResumeMonitor {
+    target: Arc<dyn ResumeTarget>,
+    check_interval: Duration,
+    step_timeout: Duration,
+    detector: std::sync::Mutex<ClockJumpDetector>,
+    // 恢复过程中再次检测到跳变时不重复执行
+    recovering: AtomicBool,
+    event_sender: mpsc::UnboundedSender<AppResumedEvent>,
+    task: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ResumeMonitor {
+    pub fn new(target: Arc<dyn ResumeTarget>) -> (Self, mpsc::UnboundedReceiver<AppResumedEvent>) {
+        Self::with_timing(target, CHECK_INTERVAL, SLEEP_THRESHOLD, STEP_TIMEOUT)
+    }
+
+    pub fn with_timing(
+        target: Arc<dyn ResumeTarget>,
+        check_interval: Duration,
+        threshold: Duration,
+        step_timeout: Duration,
+    ) -> (Self, mpsc::UnboundedReceiver<AppResumedEvent>) {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+
+        let monitor = Self {
+            target,
+            check_interval,
+            step_timeout,
+            detector: std::sync::Mutex::new(ClockJumpDetector::new(threshold)),
+            recovering: AtomicBool::new(false),
+            event_sender,
+            task: std::sync::Mutex::new(None),
+        };
+
+        (monitor, event_receiver)
+    }
+
+    // 需在 tokio 运行时内调用
+    pub fn start(self: &Arc<Self>) {
+        let mut task = self.task.lock().unwrap();
+        if task.is_none() {
+            let monitor = self.clone();
+            *task = Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(monitor.check_interval);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    monitor.check_at(Instant::now(), Utc::now()).await;
+                }
+            }));
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    // 检查一次时钟，发现休眠时执行恢复并返回发送的事件
+    pub async fn check_at(&self, now: Instant, wall: DateTime<Utc>) -> Option<AppResumedEvent> {
+        let slept = self.detector.lock().unwrap().observe(now, wall)?;
+        if self.recovering.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+
+        tracing::info!("Detected system resume after ~{}s, running recovery", slept.num_seconds());
+        let event = self.recover(slept).await;
+        self.recovering.store(false, Ordering::SeqCst);
+
+        if !event.all_succeeded() {
+            tracing::warn!("Resume recovery finished with failures: {:?}", event.steps);
+        }
+        let _ = self.event_sender.send(event.clone());
+        Some(event)
+    }
+
+    // 按顺序执行恢复步骤，单个步骤失败或超时只记录在结果中，不影响后续步骤
+    pub async fn recover(&self, slept: chrono::Duration) -> AppResumedEvent {
+        let mut steps = Vec::with_capacity(4);
+
+        let auto_lock = self.run_step(self.target.evaluate_auto_lock()).await;
+        // 无法确认时宁可锁屏
+        let should_lock = !matches!(auto_lock, Ok(false));
+        steps.push(step_result(ResumeStep::AutoLock, auto_lock, |locked| {
+            locked.then(|| "已超过自动锁屏时间".to_string())
+        }));
+
+        let session = self.run_step(self.target.refresh_session()).await;
+        steps.push(step_result(ResumeStep::Session, session, |expires_at| match expires_at {
+            Some(expires_at) => Some(format!("会话有效期至 {}", expires_at.to_rfc3339())),
+            None => Some("未登录".to_string()),
+        }));
+
+        let websocket = self.run_step(self.target.reconnect_websockets()).await;
+        steps.push(step_result(ResumeStep::WebSocket, websocket, |count| {
+            Some(format!("已重建 {} 个连接", count))
+        }));
+
+        let sync = self.run_step(self.target.trigger_sync()).await;
+        steps.push(step_result(ResumeStep::Sync, sync, |reason| reason));
+
+        AppResumedEvent {
+            slept_seconds: slept.num_seconds(),
+            resumed_at: Utc::now(),
+            should_lock,
+            steps,
+        }
+    }
+
+    async fn run_step<T>(&self, step: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::time::timeout(self.step_timeout, step)
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("超时（{:?}）", self.step_timeout)))
+    }
+}
+
+fn step_result<T>(step: ResumeStep, result: Result<T>, detail: impl FnOnce(T) -> Option<String>) -> ResumeStepResult {
+    match result {
+        Ok(value) => ResumeStepResult {
+            step,
+            success: true,
+            detail: detail(value),
+            error: None,
+        },
+        Err(e) => ResumeStepResult {
+            step,
+            success: false,
+            detail: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 使用应用中共享的安全服务、会话、WebSocket 管理器和同步调度器
+pub struct AppResumeTarget {
+    security: Arc<Mutex<SecurityService>>,
+    token_refresh: Arc<Mutex<TokenRefreshService>>,
+    websocket: Arc<Mutex<WebSocketManager>>,
+    sync_scheduler: Arc<SyncScheduler>,
+}
+
+impl AppResumeTarget {
+    pub fn new(
+        security: Arc<Mutex<SecurityService>>,
+        token_refresh: Arc<Mutex<TokenRefreshService>>,
+        websocket: Arc<Mutex<WebSocketManager>>,
+        sync_scheduler: Arc<SyncScheduler>,
+    ) -> Self {
+        Self {
+            security,
+            token_refresh,
+            websocket,
+            sync_scheduler,
+        }
+    }
+}
+
+#[async_trait]
+impl ResumeTarget for AppResumeTarget {
+    async fn evaluate_auto_lock(&self) -> Result<bool> {
+        let Some(user_id) = self.token_refresh.lock().await.current_user_id().await else {
+            return Ok(false);
+        };
+        Ok(self.security.lock().await.should_auto_lock(&user_id).await)
+    }
+
+    async fn refresh_session(&self) -> Result<Option<DateTime<Utc>>> {
+        self.token_refresh.lock().await.resume().await.map_err(|e| anyhow!(e))
+    }
+
+    async fn reconnect_websockets(&self) -> Result<usize> {
+        // 不持有管理器锁，重建期间其他命令仍可使用管理器
+        let connections = self.websocket.lock().await.connections().await;
+        let mut reconnected = 0;
+        let mut failures = Vec::new();
+        for (connection_id, client) in connections {
+            match client.reconnect(WEBSOCKET_RECONNECT_TIMEOUT).await {
+                Ok(()) => reconnected += 1,
+                Err(e) => {
+                    tracing::warn!("Failed to reconnect WebSocket {} after resume: {}", connection_id, e);
+                    failures.push(format!("{}: {}", connection_id, e));
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(reconnected)
+        } else {
+            Err(anyhow!("{} 个连接重建失败（{}）", failures.len(), failures.join("; ")))
+        }
+    }
+
+    async fn trigger_sync(&self) -> Result<Option<String>> {
+        if self.sync_scheduler.config().paused {
+            return Ok(Some("后台同步已暂停".to_string()));
+        }
+        self.sync_scheduler.trigger(SyncTrigger::Resumed);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 记录调用顺序，按配置让指定步骤失败或挂起
+    #[derive(Default)]
+    struct FakeTarget {
+        calls: std::sync::Mutex<Vec<ResumeStep>>,
+        failing: Vec<ResumeStep>,
+        hanging: Vec<ResumeStep>,
+        should_lock: bool,
+    }
+
+    impl FakeTarget {
+        async fn record(&self, step: ResumeStep) -> Result<()> {
+            self.calls.lock().unwrap().push(step);
+            if self.hanging.contains(&step) {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            if self.failing.contains(&step) {
+                return Err(anyhow!("{:?} unavailable", step));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl ResumeTarget for FakeTarget {
+        async fn evaluate_auto_lock(&self) -> Result<bool> {
+            self.record(ResumeStep::AutoLock).await?;
+            Ok(self.should_lock)
+        }
+
+        async fn refresh_session(&self) -> Result<Option<DateTime<Utc>>> {
+            self.record(ResumeStep::Session).await?;
+            Ok(Some(Utc::now() + chrono::Duration::hours(8)))
+        }
+
+        async fn reconnect_websockets(&self) -> Result<usize> {
+            self.record(ResumeStep::WebSocket).await?;
+            Ok(2)
+        }
+
+        async fn trigger_sync(&self) -> Result<Option<String>> {
+            self.record(ResumeStep::Sync).await?;
+            Ok(None)
+        }
+    }
+
+    const ALL_STEPS: [ResumeStep; 4] = [ResumeStep::AutoLock, ResumeStep::Session, ResumeStep::WebSocket, ResumeStep::Sync];
+
+    fn monitor(target: Arc<FakeTarget>) -> (ResumeMonitor, mpsc::UnboundedReceiver<AppResumedEvent>) {
+        ResumeMonitor::with_timing(target, CHECK_INTERVAL, SLEEP_THRESHOLD, Duration::from_millis(100))
+    }
+
+    fn step_names(event: &AppResumedEvent) -> Vec<ResumeStep> {
+        event.steps.iter().map(|step| step.step).collect()
+    }
+
+    #[test]
+    fn test_detects_wall_clock_jump() {
+        let mut detector = ClockJumpDetector::new(SLEEP_THRESHOLD);
+        let start = Instant::now();
+        let wall = Utc::now();
+
+        assert_eq!(detector.observe(start, wall), None);
+        // 正常运行：两个时钟同步前进
+        assert_eq!(detector.observe(start + Duration::from_secs(5), wall + chrono::Duration::seconds(5)), None);
+        // 休眠一小时：单调时钟只走了 5 秒
+        let slept = detector
+            .observe(start + Duration::from_secs(10), wall + chrono::Duration::seconds(3610))
+            .unwrap();
+        assert_eq!(slept.num_seconds(), 3600);
+        // 系统时间被往回调整不视为休眠
+        assert_eq!(detector.observe(start + Duration::from_secs(15), wall), None);
+    }
+
+    #[tokio::test]
+    async fn test_recovery_runs_steps_in_order() {
+        let target = Arc::new(FakeTarget {
+            should_lock: true,
+            ..Default::default()
+        });
+        let (monitor, mut events) = monitor(target.clone());
+        let start = Instant::now();
+        let wall = Utc::now();
+
+        assert!(monitor.check_at(start, wall).await.is_none());
+        assert!(monitor
+            .check_at(start + Duration::from_secs(5), wall + chrono::Duration::seconds(5))
+            .await
+            .is_none());
+        assert!(target.calls.lock().unwrap().is_empty());
+
+        let event = monitor
+            .check_at(start + Duration::from_secs(10), wall + chrono::Duration::seconds(1800))
+            .await
+            .unwrap();
+        assert_eq!(*target.calls.lock().unwrap(), ALL_STEPS);
+        assert_eq!(step_names(&event), ALL_STEPS);
+        assert!(event.all_succeeded());
+        assert!(event.should_lock);
+        assert_eq!(event.slept_seconds, 1790);
+        assert_eq!(event.steps[2].detail.as_deref(), Some("已重建 2 个连接"));
+
+        // 只发送一个合并后的事件
+        assert_eq!(events.try_recv().unwrap(), event);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_steps_do_not_stop_recovery() {
+        let target = Arc::new(FakeTarget {
+            failing: vec![ResumeStep::Session, ResumeStep::WebSocket],
+            ..Default::default()
+        });
+        let (monitor, _events) = monitor(target.clone());
+
+        let event = monitor.recover(chrono::Duration::minutes(10)).await;
+        assert_eq!(*target.calls.lock().unwrap(), ALL_STEPS);
+        assert!(!event.all_succeeded());
+        assert!(!event.should_lock);
+
+        let failed: Vec<_> = event.steps.iter().filter(|step| !step.success).map(|step| step.step).collect();
+        assert_eq!(failed, vec![ResumeStep::Session, ResumeStep::WebSocket]);
+        assert_eq!(event.steps[1].error.as_deref(), Some("Session unavailable"));
+        assert!(event.steps[3].success);
+    }
+
+    #[tokio::test]
+    async fn test_hung_step_times_out() {
+        let target = Arc::new(FakeTarget {
+            hanging: vec![ResumeStep::AutoLock, ResumeStep::WebSocket],
+            ..Default::default()
+        });
+        let (monitor, _events) = monitor(target.clone());
+
+        let event = monitor.recover(chrono::Duration::minutes(10)).await;
+        assert_eq!(*target.calls.lock().unwrap(), ALL_STEPS);
+        assert!(event.steps[0].error.as_deref().unwrap().contains("超时"));
+        assert!(event.steps[2].error.as_deref().unwrap().contains("超时"));
+        assert!(event.steps[1].success && event.steps[3].success);
+        // 自动锁屏检查失败时按需要锁屏处理
+        assert!(event.should_lock);
+    }
+}
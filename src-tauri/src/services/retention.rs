@@ -0,0 +1,536 @@
+// 数据保留：每日按策略清理旧消息、审计日志、异常记录、文件缓存和备份，并清空回收站中过期的消息和病历、过期的变更记录
+// 每项清理在独立事务中执行，结果写入 maintenance_runs
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{
+    AuditLogDao, ChangeLogDao, FileCacheDao, MedicalRecordDao, MessageDao, MessageDraftDao, RetentionDao,
+};
+use crate::database::query_optimizer::{query_cache_for, CACHE_TAG_MESSAGES};
+use crate::models::{MaintenanceKind, MaintenanceRun, MaintenanceTrigger, RetentionPolicy, MIN_MESSAGE_RETENTION_DAYS};
+use crate::services::SecurityService;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rusqlite::Connection;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+pub const BACKUP_DIR_NAME: &str = "backups";
+
+const RETENTION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// 启动后稍等再执行，避免和登录后的首次同步争用数据库
+const STARTUP_DELAY: Duration = Duration::from_secs(10 * 60);
+const BYTES_PER_MB: u64 = 1024 * 1024;
+// 超过该天数未更新的消息草稿视为废弃
+const MESSAGE_DRAFT_RETENTION_DAYS: i32 = 30;
+// 变更记录只用于窗口补齐错过的通知，更早的变更由窗口重新加载列表代替
+const CHANGE_LOG_RETENTION_DAYS: i32 = 7;
+// 计算首次执行时间时查看的最近维护记录数
+const RECENT_RUNS_SCANNED: i32 = 20;
+// PRAGMA auto_vacuum 的 INCREMENTAL 模式
+const AUTO_VACUUM_INCREMENTAL: i64 = 2;
+
+pub struct RetentionService {
+    connection: DbConnection,
+    security: Arc<Mutex<SecurityService>>,
+    backup_dir: Option<PathBuf>,
+}
+
+impl RetentionService {
+    pub fn new(security: Arc<Mutex<SecurityService>>, backup_dir: Option<PathBuf>) -> Self {
+        Self::with_connection(get_database().get_connection(), security, backup_dir)
+    }
+
+    pub fn with_connection(
+        connection: DbConnection,
+        security: Arc<Mutex<SecurityService>>,
+        backup_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            connection,
+            security,
+            backup_dir,
+        }
+    }
+
+    pub fn policy(&self) -> Result<RetentionPolicy> {
+        RetentionDao::with_connection(self.connection.clone())
+            .load_policy()
+            .map_err(dao_error)
+    }
+
+    pub fn update_policy(&self, policy: RetentionPolicy) -> Result<RetentionPolicy> {
+        validate_retention_policy(&policy)?;
+        RetentionDao::with_connection(self.connection.clone())
+            .save_policy(&policy)
+            .map_err(dao_error)?;
+        tracing::info!("Retention policy updated: {:?}", policy);
+        Ok(policy)
+    }
+
+    pub fn recent_runs(&self, limit: i32) -> Result<Vec<MaintenanceRun>> {
+        RetentionDao::with_connection(self.connection.clone())
+            .find_recent_runs(limit)
+            .map_err(dao_error)
+    }
+
+    // 执行一次清理，失败时同样写入维护记录
+    pub async fn run(&self, trigger: MaintenanceTrigger) -> Result<MaintenanceRun> {
+        let started_at = Utc::now();
+        let mut run = MaintenanceRun {
+            id: Uuid::new_v4().to_string(),
+            kind: MaintenanceKind::Retention,
+            triggered_by: trigger,
+            started_at,
+            finished_at: started_at,
+            deleted_messages: 0,
+            deleted_audit_logs: 0,
+            deleted_anomaly_records: 0,
+            deleted_cache_files: 0,
+            deleted_backups: 0,
+            purged_trash: 0,
+            freed_bytes: 0,
+            vacuumed: false,
+            status: "success".to_string(),
+            error_message: None,
+            summary: None,
+        };
+
+        let outcome = self.execute(&mut run).await;
+        if let Err(e) = &outcome {
+            tracing::error!("Retention run failed: {}", e);
+            run.status = "failed".to_string();
+            run.error_message = Some(e.to_string());
+        }
+        run.finished_at = Utc::now();
+
+        RetentionDao::with_connection(self.connection.clone())
+            .record_run(&run)
+            .map_err(dao_error)?;
+        outcome?;
+
+        tracing::info!(
+            "Retention run finished: messages={}, audit_logs={}, anomalies={}, cache_files={}, backups={}, trash={}, freed={} bytes, vacuumed={}",
+            run.deleted_messages,
+            run.deleted_audit_logs,
+            run.deleted_anomaly_records,
+            run.deleted_cache_files,
+            run.deleted_backups,
+            run.purged_trash,
+            run.freed_bytes,
+            run.vacuumed
+        );
+        Ok(run)
+    }
+
+    // 后台每日执行，距上次执行不足一天时等满一天
+    pub async fn run_daily(self: Arc<Self>) {
+        tokio::time::sleep(self.initial_delay()).await;
+        loop {
+            if let Err(e) = self.run(MaintenanceTrigger::Scheduled).await {
+                tracing::error!("Scheduled retention run failed: {}", e);
+            }
+            tokio::time::sleep(RETENTION_INTERVAL).await;
+        }
+    }
+
+    fn initial_delay(&self) -> Duration {
+        // 维护记录中还有完整性修复，只看上次保留清理
+        let last_run = self
+            .recent_runs(RECENT_RUNS_SCANNED)
+            .ok()
+            .and_then(|runs| runs.into_iter().find(|run| run.kind == MaintenanceKind::Retention));
+        match last_run {
+            Some(run) => {
+                let elapsed = (Utc::now() - run.started_at).to_std().unwrap_or_default();
+                RETENTION_INTERVAL.saturating_sub(elapsed).max(STARTUP_DELAY)
+            }
+            None => STARTUP_DELAY,
+        }
+    }
+
+    async fn execute(&self, run: &mut MaintenanceRun) -> Result<()> {
+        let policy = effective_policy(self.policy()?);
+
+        run.deleted_messages =
+            self.in_transaction(|conn| MessageDao::delete_old_messages_in(conn, policy.message_days as i32))?;
+        run.purged_trash = self.in_transaction(|conn| {
+            let days = policy.trash_days as i32;
+            Ok(MessageDao::purge_deleted_in(conn, days)? + MedicalRecordDao::purge_deleted_in(conn, days)?)
+        })?;
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+
+        self.in_transaction(|conn| MessageDraftDao::delete_older_than_in(conn, MESSAGE_DRAFT_RETENTION_DAYS))?;
+        self.in_transaction(|conn| ChangeLogDao::delete_older_than_in(conn, CHANGE_LOG_RETENTION_DAYS))?;
+
+        run.deleted_audit_logs =
+            self.in_transaction(|conn| AuditLogDao::cleanup_old_logs_in(conn, policy.audit_log_days as i32))?;
+
+        let evicted =
+            self.in_transaction(|conn| FileCacheDao::cleanup_old_files_in(conn, policy.file_cache_days as i32, false))?;
+        let paths = evicted.iter().flat_map(|(local_path, thumbnail_path, preview_path)| {
+            std::iter::once(local_path).chain(thumbnail_path).chain(preview_path)
+        });
+        for path in paths {
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::warn!("Failed to remove cached file {}: {}", path, e);
+            }
+        }
+        run.deleted_cache_files = evicted.len();
+
+        run.deleted_anomaly_records = self
+            .security
+            .lock()
+            .await
+            .cleanup_anomaly_records(policy.anomaly_record_days as i64)
+            .await;
+
+        run.deleted_backups = self.cleanup_backups(policy.backup_days)?;
+
+        let (freed_bytes, vacuumed) = self.vacuum_if_needed(policy.vacuum_threshold_mb)?;
+        run.freed_bytes = freed_bytes;
+        run.vacuumed = vacuumed;
+        Ok(())
+    }
+
+    fn in_transaction<T>(&self, cleanup: impl FnOnce(&Connection) -> Result<T, Box<dyn Error>>) -> Result<T> {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        let result = cleanup(&tx).map_err(dao_error)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    fn cleanup_backups(&self, days: u32) -> Result<usize> {
+        let Some(dir) = self.backup_dir.as_ref().filter(|dir| dir.exists()) else {
+            return Ok(0);
+        };
+
+        let cutoff = SystemTime::now() - Duration::from_secs(days as u64 * 24 * 60 * 60);
+        let mut deleted = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() || metadata.modified()? >= cutoff {
+                continue;
+            }
+            match std::fs::remove_file(entry.path()) {
+                Ok(()) => deleted += 1,
+                Err(e) => tracing::warn!("Failed to remove backup {:?}: {}", entry.path(), e),
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    // 可回收空间超过阈值时整理数据库文件，返回可回收字节数和是否已整理
+    fn vacuum_if_needed(&self, threshold_mb: u32) -> Result<(u64, bool)> {
+        let conn = self.connection.lock().unwrap();
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+        let freed_bytes = (page_size * free_pages) as u64;
+
+        if freed_bytes < threshold_mb as u64 * BYTES_PER_MB {
+            return Ok((freed_bytes, false));
+        }
+
+        let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+        if auto_vacuum == AUTO_VACUUM_INCREMENTAL {
+            conn.execute_batch("PRAGMA incremental_vacuum;")?;
+        } else {
+            // 早期创建的数据库未开启增量模式，切换需要完整 VACUUM 一次
+            tracing::info!("Switching database to incremental auto vacuum");
+            conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+        }
+
+        Ok((freed_bytes, true))
+    }
+}
+
+pub fn validate_retention_policy(policy: &RetentionPolicy) -> Result<()> {
+    if policy.message_days < MIN_MESSAGE_RETENTION_DAYS {
+        return Err(anyhow!("消息记录涉及病历留存，保留天数不能少于 {} 天", MIN_MESSAGE_RETENTION_DAYS));
+    }
+    if [
+        policy.audit_log_days,
+        policy.anomaly_record_days,
+        policy.file_cache_days,
+        policy.backup_days,
+        policy.trash_days,
+    ]
+    .contains(&0)
+    {
+        return Err(anyhow!("保留天数必须大于 0"));
+    }
+    Ok(())
+}
+
+// 数据库中的策略可能被直接修改，执行时仍保证下限
+fn effective_policy(policy: RetentionPolicy) -> RetentionPolicy {
+    RetentionPolicy {
+        message_days: policy.message_days.max(MIN_MESSAGE_RETENTION_DAYS),
+        audit_log_days: policy.audit_log_days.max(1),
+        anomaly_record_days: policy.anomaly_record_days.max(1),
+        file_cache_days: policy.file_cache_days.max(1),
+        backup_days: policy.backup_days.max(1),
+        trash_days: policy.trash_days.max(1),
+        vacuum_threshold_mb: policy.vacuum_threshold_mb,
+    }
+}
+
+fn dao_error(err: Box<dyn Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use chrono::Duration as ChronoDuration;
+    use rusqlite::params;
+    use tempfile::tempdir;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(std::sync::Mutex::new(conn))
+    }
+
+    fn service(connection: &DbConnection, backup_dir: Option<PathBuf>) -> RetentionService {
+        RetentionService::with_connection(
+            connection.clone(),
+            Arc::new(Mutex::new(SecurityService::new(300))),
+            backup_dir,
+        )
+    }
+
+    // 在 age_days 天前写入一条消息、审计日志和缓存记录
+    fn seed_rows(connection: &DbConnection, age_days: i64, suffix: &str) {
+        let at = Utc::now() - ChronoDuration::days(age_days);
+        let conn = connection.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO patients (id, name, created_at, updated_at) VALUES ('p1', '张三', ?1, ?1)",
+            params![at],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO consultations (id, patient_id, doctor_id, status, created_at, updated_at)
+             VALUES ('c1', 'p1', 'd1', 'completed', ?1, ?1)",
+            params![at],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO messages (id, consultation_id, sender_type, content, message_type, timestamp, read_status)
+             VALUES (?1, 'c1', 'doctor', '复诊提醒', 'text', ?2, 'read')",
+            params![format!("m-{}", suffix), at],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO audit_logs (id, user_id, action, details, created_at) VALUES (?1, 'd1', 'view_patient', '{}', ?2)",
+            params![format!("a-{}", suffix), at],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO file_cache (id, file_url, local_path, file_size, downloaded_at, last_accessed)
+             VALUES (?1, ?2, ?3, 10, ?4, ?4)",
+            params![
+                format!("f-{}", suffix),
+                format!("https://files.example.com/{}", suffix),
+                format!("/nonexistent/{}", suffix),
+                at
+            ],
+        )
+        .unwrap();
+    }
+
+    fn count(connection: &DbConnection, table: &str) -> i64 {
+        let conn = connection.lock().unwrap();
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_per_table_retention_windows() {
+        let connection = create_test_connection();
+        let backups = tempdir().unwrap();
+        for (age, suffix) in [(5, "recent"), (45, "month"), (120, "old")] {
+            seed_rows(&connection, age, suffix);
+        }
+
+        let old_backup = backups.path().join("telemedicine_old.db");
+        std::fs::write(&old_backup, b"backup").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&old_backup)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(20 * 24 * 60 * 60))
+            .unwrap();
+        std::fs::write(backups.path().join("telemedicine_new.db"), b"backup").unwrap();
+
+        let service = service(&connection, Some(backups.path().to_path_buf()));
+        service
+            .update_policy(RetentionPolicy {
+                message_days: 90,
+                audit_log_days: 30,
+                anomaly_record_days: 30,
+                file_cache_days: 10,
+                backup_days: 14,
+                trash_days: 30,
+                vacuum_threshold_mb: 10,
+            })
+            .unwrap();
+
+        let run = service.run(MaintenanceTrigger::Manual).await.unwrap();
+        assert_eq!(run.deleted_messages, 1);
+        assert_eq!(run.deleted_audit_logs, 2);
+        assert_eq!(run.deleted_cache_files, 2);
+        assert_eq!(run.deleted_backups, 1);
+        assert_eq!(run.deleted_anomaly_records, 0);
+        assert!(!run.vacuumed);
+
+        assert_eq!(count(&connection, "messages"), 2);
+        assert_eq!(count(&connection, "audit_logs"), 1);
+        assert_eq!(count(&connection, "file_cache"), 1);
+        assert!(!old_backup.exists());
+        assert!(backups.path().join("telemedicine_new.db").exists());
+
+        let runs = service.recent_runs(10).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].triggered_by, MaintenanceTrigger::Manual);
+        assert_eq!(runs[0].deleted_audit_logs, 2);
+        assert_eq!(runs[0].status, "success");
+    }
+
+    #[tokio::test]
+    async fn test_message_retention_floor() {
+        let connection = create_test_connection();
+        let service = service(&connection, None);
+
+        let too_short = RetentionPolicy {
+            message_days: MIN_MESSAGE_RETENTION_DAYS - 1,
+            ..Default::default()
+        };
+        assert!(service.update_policy(too_short).is_err());
+        assert_eq!(service.policy().unwrap(), RetentionPolicy::default());
+        assert!(service
+            .update_policy(RetentionPolicy {
+                backup_days: 0,
+                ..Default::default()
+            })
+            .is_err());
+
+        // 绕过校验直接写库的过短配置，执行时仍按下限保留
+        connection
+            .lock()
+            .unwrap()
+            .execute("INSERT INTO retention_policy (key, value) VALUES ('messages', 1)", [])
+            .unwrap();
+        assert_eq!(service.policy().unwrap().message_days, 1);
+
+        seed_rows(&connection, 10, "ten");
+        seed_rows(&connection, 40, "forty");
+        let run = service.run(MaintenanceTrigger::Scheduled).await.unwrap();
+        assert_eq!(run.deleted_messages, 1);
+        assert_eq!(count(&connection, "messages"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_trash_purged_after_window() {
+        let connection = create_test_connection();
+        seed_rows(&connection, 1, "kept");
+        seed_rows(&connection, 1, "recent");
+        seed_rows(&connection, 1, "expired");
+        {
+            let conn = connection.lock().unwrap();
+            conn.execute(
+                "INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title) VALUES
+                     ('r-recent', 'p1', 'd1', 'c1', 'diagnosis', '复诊'), ('r-expired', 'p1', 'd1', 'c1', 'diagnosis', '初诊')",
+                [],
+            )
+            .unwrap();
+            for (table, id, age) in [
+                ("messages", "m-recent", 5),
+                ("messages", "m-expired", 40),
+                ("medical_records", "r-recent", 5),
+                ("medical_records", "r-expired", 40),
+            ] {
+                conn.execute(
+                    &format!("UPDATE {} SET deleted_at = ?1 WHERE id = ?2", table),
+                    params![Utc::now() - ChronoDuration::days(age), id],
+                )
+                .unwrap();
+            }
+        }
+
+        let service = service(&connection, None);
+        let run = service.run(MaintenanceTrigger::Manual).await.unwrap();
+        assert_eq!(run.purged_trash, 2);
+        assert_eq!(run.deleted_messages, 0);
+        assert_eq!(count(&connection, "messages"), 2);
+        assert_eq!(count(&connection, "medical_records"), 1);
+
+        let remaining: String = connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT id FROM messages WHERE deleted_at IS NOT NULL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, "m-recent");
+        assert_eq!(service.recent_runs(1).unwrap()[0].purged_trash, 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_drafts_removed() {
+        let connection = create_test_connection();
+        seed_rows(&connection, 1, "seed");
+        {
+            let conn = connection.lock().unwrap();
+            for (doctor_id, age) in [("d1", 5), ("d2", MESSAGE_DRAFT_RETENTION_DAYS as i64 + 1)] {
+                conn.execute(
+                    "INSERT INTO message_drafts (consultation_id, doctor_id, content, updated_at) VALUES ('c1', ?1, '草稿', ?2)",
+                    params![doctor_id, Utc::now() - ChronoDuration::days(age)],
+                )
+                .unwrap();
+            }
+        }
+
+        service(&connection, None).run(MaintenanceTrigger::Manual).await.unwrap();
+
+        assert_eq!(count(&connection, "message_drafts"), 1);
+        let remaining: String = connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT doctor_id FROM message_drafts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, "d1");
+    }
+
+    #[tokio::test]
+    async fn test_old_change_log_entries_removed() {
+        let connection = create_test_connection();
+        seed_rows(&connection, 1, "seed");
+        {
+            let conn = connection.lock().unwrap();
+            for (entity_id, age) in [("p-recent", 1), ("p-old", CHANGE_LOG_RETENTION_DAYS as i64 + 1)] {
+                conn.execute(
+                    "INSERT INTO change_log (entity, entity_id, op, changed_at) VALUES ('patient', ?1, 'update', ?2)",
+                    params![entity_id, Utc::now() - ChronoDuration::days(age)],
+                )
+                .unwrap();
+            }
+        }
+
+        service(&connection, None).run(MaintenanceTrigger::Manual).await.unwrap();
+
+        let remaining: Vec<String> = {
+            let conn = connection.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT entity_id FROM change_log").unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+            rows.collect::<Result<_, _>>().unwrap()
+        };
+        assert_eq!(remaining, vec!["p-recent".to_string()]);
+    }
+}
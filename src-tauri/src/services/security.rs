@@ -1,13 +1,32 @@
 // 安全服务模块
 
-use crate::utils::CryptoService;
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use crate::database::connection::DbConnection;
+use crate::database::dao::patient_dao::phone_index;
+use crate::database::dao::{AuditLogDao, SecurityConfigDao, SmsRequestDao};
+use crate::models::{AppError, ErrorType, SecurityConfig};
+use crate::services::access_analyzer::AccessAnalyzer;
+use crate::services::device_info::DeviceInfo;
+use crate::utils::crypto::{hash_password, verify_password};
+use crate::utils::{
+    CryptoService, MessageKey, ValidationResult, ValidationService, CODE_REAUTH_REQUIRED, CODE_UNLOCK_PIN_INCORRECT,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+// 同一手机号两次验证码请求的最小间隔，以及每小时最多请求次数
+const SMS_MIN_INTERVAL_SECS: i64 = 60;
+const SMS_HOURLY_LIMIT: usize = 5;
+const SMS_WINDOW_SECS: i64 = 60 * 60;
+// 解锁 PIN 连续输错的次数上限，达到后清除 PIN 并要求重新登录
+pub const UNLOCK_PIN_MAX_ATTEMPTS: u32 = 5;
+// 异常记录 metadata 中标记失败来源的键和值，区分登录密码失败和解锁 PIN 失败
+pub const ANOMALY_SOURCE_KEY: &str = "source";
+pub const ANOMALY_SOURCE_UNLOCK_PIN: &str = "unlock_pin";
+
 /// 操作日志类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AuditAction {
@@ -21,6 +40,88 @@ pub enum AuditAction {
     AccessSensitiveData,
     ChangeSettings,
     DeleteData,
+    // 从回收站恢复已删除的消息或病历
+    RestoreData,
+    PermissionDenied,
+    RateLimited,
+    // 问诊窗口关闭，患者相关的内存数据已清理
+    ClosePatientContext,
+    // 修改问诊优先级等候诊属性
+    UpdateConsultation,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 15] = [
+        AuditAction::Login,
+        AuditAction::Logout,
+        AuditAction::ViewPatient,
+        AuditAction::UpdatePatient,
+        AuditAction::SendMessage,
+        AuditAction::UploadFile,
+        AuditAction::DownloadFile,
+        AuditAction::AccessSensitiveData,
+        AuditAction::ChangeSettings,
+        AuditAction::DeleteData,
+        AuditAction::RestoreData,
+        AuditAction::PermissionDenied,
+        AuditAction::RateLimited,
+        AuditAction::ClosePatientContext,
+        AuditAction::UpdateConsultation,
+    ];
+
+    // 写入 audit_logs 表时使用的操作名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Logout => "logout",
+            AuditAction::ViewPatient => "view_patient",
+            AuditAction::UpdatePatient => "update_patient",
+            AuditAction::SendMessage => "send_message",
+            AuditAction::UploadFile => "upload_file",
+            AuditAction::DownloadFile => "download_file",
+            AuditAction::AccessSensitiveData => "access_sensitive_data",
+            AuditAction::ChangeSettings => "change_settings",
+            AuditAction::DeleteData => "delete_data",
+            AuditAction::RestoreData => "restore_data",
+            AuditAction::PermissionDenied => "permission_denied",
+            AuditAction::RateLimited => "rate_limited",
+            AuditAction::ClosePatientContext => "close_patient_context",
+            AuditAction::UpdateConsultation => "update_consultation",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<AuditAction> {
+        Self::ALL.into_iter().find(|action| action.as_str() == value)
+    }
+
+    // 审计记录界面展示的操作名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "登录",
+            AuditAction::Logout => "退出登录",
+            AuditAction::ViewPatient => "查看患者",
+            AuditAction::UpdatePatient => "修改患者信息",
+            AuditAction::SendMessage => "发送消息",
+            AuditAction::UploadFile => "上传文件",
+            AuditAction::DownloadFile => "下载文件",
+            AuditAction::AccessSensitiveData => "访问敏感数据",
+            AuditAction::ChangeSettings => "修改设置",
+            AuditAction::DeleteData => "删除数据",
+            AuditAction::RestoreData => "恢复数据",
+            AuditAction::PermissionDenied => "权限不足",
+            AuditAction::RateLimited => "操作过于频繁",
+            AuditAction::ClosePatientContext => "关闭问诊窗口",
+            AuditAction::UpdateConsultation => "修改问诊",
+        }
+    }
+
+    // 用户主动操作才刷新最后活动时间，其余操作不推迟自动锁屏
+    pub fn is_user_interaction(&self) -> bool {
+        matches!(
+            self,
+            AuditAction::Login | AuditAction::ViewPatient | AuditAction::SendMessage | AuditAction::ChangeSettings
+        )
+    }
 }
 
 /// 操作日志记录
@@ -39,6 +140,13 @@ pub struct AuditLog {
     pub timestamp: DateTime<Utc>,
 }
 
+/// 调用方明确提供的请求来源，缺省字段由本机设备信息补全
+#[derive(Debug, Clone, Default)]
+pub struct AuditOrigin {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
 /// 异常访问类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AnomalyType {
@@ -59,6 +167,17 @@ pub struct AnomalyRecord {
     pub description: String,
     pub detected_at: DateTime<Utc>,
     pub resolved: bool,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+/// 自动锁屏检查结果，锁屏界面据此决定是否提供 PIN 解锁
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AutoLockStatus {
+    #[serde(rename = "shouldLock")]
+    pub should_lock: bool,
+    #[serde(rename = "pinUnlockAvailable")]
+    pub pin_unlock_available: bool,
 }
 
 /// 会话活动跟踪
@@ -66,38 +185,118 @@ pub struct AnomalyRecord {
 struct SessionActivity {
     last_activity: DateTime<Utc>,
     failed_login_attempts: u32,
+    failed_unlock_attempts: u32,
     access_count: u32,
     last_access_times: Vec<DateTime<Utc>>,
 }
 
+impl SessionActivity {
+    fn new() -> Self {
+        Self {
+            last_activity: Utc::now(),
+            failed_login_attempts: 0,
+            failed_unlock_attempts: 0,
+            access_count: 0,
+            last_access_times: Vec::new(),
+        }
+    }
+}
+
+/// 本地解锁 PIN，只保存 argon2 哈希，退出登录后失效
+#[derive(Debug, Clone)]
+struct UnlockPin {
+    hash: String,
+    failed_attempts: u32,
+}
+
 /// 安全服务
 pub struct SecurityService {
-    crypto: CryptoService,
+    // 钥匙串中的本地数据密钥，未设置时敏感数据加解密返回 DATA_KEY_UNAVAILABLE
+    crypto: Option<CryptoService>,
     audit_logs: Arc<Mutex<Vec<AuditLog>>>,
     anomaly_records: Arc<Mutex<Vec<AnomalyRecord>>>,
     session_activities: Arc<Mutex<HashMap<String, SessionActivity>>>,
+    // 未挂载数据库时的验证码请求记录，键为手机号 HMAC 索引
+    sms_requests: Arc<Mutex<HashMap<String, Vec<DateTime<Utc>>>>>,
+    unlock_pins: Arc<Mutex<HashMap<String, UnlockPin>>>,
     auto_lock_timeout: u64, // 秒
+    connection: Option<DbConnection>,
+    config: SecurityConfig,
+    device_info: Option<DeviceInfo>,
 }
 
 impl SecurityService {
     pub fn new(auto_lock_timeout: u64) -> Self {
         Self {
-            crypto: CryptoService::new(),
+            crypto: default_crypto(),
             audit_logs: Arc::new(Mutex::new(Vec::new())),
             anomaly_records: Arc::new(Mutex::new(Vec::new())),
             session_activities: Arc::new(Mutex::new(HashMap::new())),
+            sms_requests: Arc::new(Mutex::new(HashMap::new())),
+            unlock_pins: Arc::new(Mutex::new(HashMap::new())),
             auto_lock_timeout,
+            connection: None,
+            config: SecurityConfig::default(),
+            device_info: None,
+        }
+    }
+
+    // 启动时采集的设备信息，用于补全审计日志的 IP 和设备描述
+    pub fn with_device_info(mut self, device_info: DeviceInfo) -> Self {
+        self.device_info = Some(device_info);
+        self
+    }
+
+    pub fn with_crypto(mut self, crypto: CryptoService) -> Self {
+        self.crypto = Some(crypto);
+        self
+    }
+
+    fn crypto(&self) -> Result<&CryptoService> {
+        self.crypto
+            .as_ref()
+            .ok_or_else(|| AppError::data_key_unavailable("本地数据密钥不可用").into())
+    }
+
+    // 数据库就绪后挂载：审计日志同时写入 audit_logs 表，并加载检测规则
+    pub fn attach_database(&mut self, connection: DbConnection) -> Result<()> {
+        self.config = SecurityConfigDao::with_connection(connection.clone())
+            .load()
+            .map_err(dao_error)?;
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    pub fn security_config(&self) -> &SecurityConfig {
+        &self.config
+    }
+
+    // 更新检测规则，已挂载数据库时持久化
+    pub fn update_security_config(&mut self, config: SecurityConfig) -> Result<()> {
+        validate_security_config(&config)?;
+
+        if let Some(connection) = &self.connection {
+            SecurityConfigDao::with_connection(connection.clone())
+                .save(&config)
+                .map_err(dao_error)?;
         }
+        self.config = config;
+        Ok(())
     }
 
     /// 加密敏感数据
     pub fn encrypt_sensitive_data(&self, data: &str) -> Result<String> {
-        self.crypto.encrypt_string(data)
+        self.crypto()?.encrypt_string(data)
     }
 
     /// 解密敏感数据
     pub fn decrypt_sensitive_data(&self, encrypted_data: &str) -> Result<String> {
-        self.crypto.decrypt_string(encrypted_data)
+        self.crypto()?.decrypt_string(encrypted_data)
+    }
+
+    /// 清零本次会话派生的密钥
+    pub fn purge_session_keys(&self) -> usize {
+        self.crypto.as_ref().map_or(0, CryptoService::purge_session_keys)
     }
 
     /// 记录操作日志
@@ -111,28 +310,111 @@ impl SecurityService {
         error_message: Option<String>,
         metadata: HashMap<String, String>,
     ) -> Result<String> {
+        self.log_audit_with_origin(
+            user_id,
+            action,
+            resource_type,
+            resource_id,
+            status,
+            error_message,
+            metadata,
+            AuditOrigin::default(),
+        )
+        .await
+    }
+
+    /// 记录操作日志，origin 中已提供的 IP / 设备描述优先于本机信息
+    #[allow(clippy::too_many_arguments)]
+    pub async fn log_audit_with_origin(
+        &self,
+        user_id: String,
+        action: AuditAction,
+        resource_type: Option<String>,
+        resource_id: Option<String>,
+        status: String,
+        error_message: Option<String>,
+        metadata: HashMap<String, String>,
+        origin: AuditOrigin,
+    ) -> Result<String> {
+        let interactive = action.is_user_interaction();
+        let log_id = self
+            .append_audit(user_id.clone(), action, resource_type, resource_id, status, error_message, metadata, origin)
+            .await;
+
+        // 更新会话活动
+        self.update_session_activity(&user_id, interactive).await;
+
+        Ok(log_id)
+    }
+
+    /// 后台任务记录操作日志，不计入会话活动，不会推迟自动锁屏
+    #[allow(clippy::too_many_arguments)]
+    pub async fn log_system_audit(
+        &self,
+        user_id: String,
+        action: AuditAction,
+        resource_type: Option<String>,
+        resource_id: Option<String>,
+        status: String,
+        error_message: Option<String>,
+        metadata: HashMap<String, String>,
+    ) -> Result<String> {
+        Ok(self
+            .append_audit(
+                user_id,
+                action,
+                resource_type,
+                resource_id,
+                status,
+                error_message,
+                metadata,
+                AuditOrigin::default(),
+            )
+            .await)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn append_audit(
+        &self,
+        user_id: String,
+        action: AuditAction,
+        resource_type: Option<String>,
+        resource_id: Option<String>,
+        status: String,
+        error_message: Option<String>,
+        metadata: HashMap<String, String>,
+        origin: AuditOrigin,
+    ) -> String {
+        let ip_address = origin
+            .ip_address
+            .or_else(|| self.device_info.as_ref().and_then(|info| info.local_ip.clone()));
+        let user_agent = origin
+            .user_agent
+            .or_else(|| self.device_info.as_ref().map(DeviceInfo::user_agent));
+
         let log = AuditLog {
             id: uuid::Uuid::new_v4().to_string(),
-            user_id: user_id.clone(),
+            user_id,
             action,
             resource_type,
             resource_id,
-            ip_address: None,
-            user_agent: None,
+            ip_address,
+            user_agent,
             status,
             error_message,
             metadata,
             timestamp: Utc::now(),
         };
 
-        let log_id = log.id.clone();
-        let mut logs = self.audit_logs.lock().await;
-        logs.push(log);
-
-        // 更新会话活动
-        self.update_session_activity(&user_id).await;
+        if let Some(connection) = &self.connection {
+            if let Err(e) = persist_audit_log(connection, &log) {
+                tracing::error!("Failed to persist audit log: {}", e);
+            }
+        }
 
-        Ok(log_id)
+        let log_id = log.id.clone();
+        self.audit_logs.lock().await.push(log);
+        log_id
     }
 
     /// 获取操作日志
@@ -202,6 +484,25 @@ impl SecurityService {
                     ),
                     detected_at: Utc::now(),
                     resolved: false,
+                    metadata: HashMap::new(),
+                });
+            }
+
+            // 锁屏后连续输错解锁 PIN 同样按多次登录失败处理，metadata 标记来源
+            if activity.failed_unlock_attempts >= 3 {
+                anomalies.push(AnomalyRecord {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    user_id: user_id.to_string(),
+                    anomaly_type: AnomalyType::MultipleFailedLogins,
+                    severity: if activity.failed_unlock_attempts >= UNLOCK_PIN_MAX_ATTEMPTS {
+                        "high".to_string()
+                    } else {
+                        "medium".to_string()
+                    },
+                    description: format!("检测到 {} 次连续解锁 PIN 验证失败", activity.failed_unlock_attempts),
+                    detected_at: Utc::now(),
+                    resolved: false,
+                    metadata: HashMap::from([(ANOMALY_SOURCE_KEY.to_string(), ANOMALY_SOURCE_UNLOCK_PIN.to_string())]),
                 });
             }
 
@@ -222,14 +523,36 @@ impl SecurityService {
                     description: format!("检测到异常高频访问：1分钟内 {} 次访问", recent_accesses),
                     detected_at: Utc::now(),
                     resolved: false,
+                    metadata: HashMap::new(),
                 });
             }
         }
+        drop(activities);
+
+        // 基于持久化审计日志的访问模式检测
+        if let Some(connection) = &self.connection {
+            let analyzer = AccessAnalyzer::new(self.config.clone());
+            let now = Utc::now();
+            let logs = AuditLogDao::with_connection(connection.clone())
+                .find_by_user_since(user_id, now - analyzer.lookback())
+                .map_err(dao_error)?;
+            anomalies.extend(analyzer.analyze(user_id, &logs, now));
+        }
 
-        // 保存异常记录
+        // 保存异常记录，未解决的相同异常不重复记录
         if !anomalies.is_empty() {
             let mut records = self.anomaly_records.lock().await;
-            records.extend(anomalies.clone());
+            for anomaly in &anomalies {
+                let duplicate = records.iter().any(|r| {
+                    !r.resolved
+                        && r.user_id == anomaly.user_id
+                        && r.description == anomaly.description
+                        && std::mem::discriminant(&r.anomaly_type) == std::mem::discriminant(&anomaly.anomaly_type)
+                });
+                if !duplicate {
+                    records.push(anomaly.clone());
+                }
+            }
         }
 
         Ok(anomalies)
@@ -238,12 +561,7 @@ impl SecurityService {
     /// 记录登录失败
     pub async fn record_failed_login(&self, user_id: &str) {
         let mut activities = self.session_activities.lock().await;
-        let activity = activities.entry(user_id.to_string()).or_insert(SessionActivity {
-            last_activity: Utc::now(),
-            failed_login_attempts: 0,
-            access_count: 0,
-            last_access_times: Vec::new(),
-        });
+        let activity = activities.entry(user_id.to_string()).or_insert_with(SessionActivity::new);
 
         activity.failed_login_attempts += 1;
         activity.last_activity = Utc::now();
@@ -257,17 +575,205 @@ impl SecurityService {
         }
     }
 
-    /// 更新会话活动
-    async fn update_session_activity(&self, user_id: &str) {
+    /// 设置本地解锁 PIN，只保存 argon2 哈希，重新设置时清零失败计数
+    pub async fn set_unlock_pin(&self, user_id: &str, pin: &str) -> Result<()> {
+        if !ValidationService::validate_unlock_pin(pin) {
+            let mut validation = ValidationResult::new();
+            validation.add("pin", MessageKey::UnlockPinInvalid, &[], "INVALID_FORMAT");
+            validation.into_app_result()?;
+        }
+
+        let hash = hash_password(pin)?;
+        self.unlock_pins.lock().await.insert(
+            user_id.to_string(),
+            UnlockPin {
+                hash,
+                failed_attempts: 0,
+            },
+        );
+        self.reset_failed_unlock(user_id).await;
+        tracing::info!("Unlock PIN set for user {}", user_id);
+        Ok(())
+    }
+
+    /// 用 PIN 解锁：成功时刷新最后活动时间；连续输错达到上限后清除 PIN，
+    /// 记录审计日志并返回 REAUTH_REQUIRED，之后只能重新登录
+    pub async fn unlock_with_pin(&self, user_id: &str, pin: &str) -> Result<()> {
+        let mut pins = self.unlock_pins.lock().await;
+        let Some(unlock_pin) = pins.get_mut(user_id) else {
+            return Err(reauth_required("未设置解锁 PIN，请重新登录").into());
+        };
+
+        // 格式不对的输入同样计为一次失败，不提前返回格式错误
+        let matched =
+            ValidationService::validate_unlock_pin(pin) && verify_password(pin, &unlock_pin.hash)?;
+        if matched {
+            unlock_pin.failed_attempts = 0;
+            drop(pins);
+            self.reset_failed_unlock(user_id).await;
+            self.record_user_interaction(user_id).await;
+            return Ok(());
+        }
+
+        unlock_pin.failed_attempts += 1;
+        let failed_attempts = unlock_pin.failed_attempts;
+        if failed_attempts >= UNLOCK_PIN_MAX_ATTEMPTS {
+            pins.remove(user_id);
+        }
+        drop(pins);
+        self.record_failed_unlock(user_id).await;
+        tracing::warn!("Unlock PIN rejected for user {} ({} failed attempts)", user_id, failed_attempts);
+
+        if failed_attempts < UNLOCK_PIN_MAX_ATTEMPTS {
+            let remaining = UNLOCK_PIN_MAX_ATTEMPTS - failed_attempts;
+            return Err(AppError::new(ErrorType::AuthError, format!("解锁 PIN 错误，还可尝试 {} 次", remaining))
+                .with_code(CODE_UNLOCK_PIN_INCORRECT)
+                .with_details(serde_json::json!({ "remainingAttempts": remaining }))
+                .with_retryable(false)
+                .into());
+        }
+
+        let message = format!("解锁 PIN 连续 {} 次错误，请重新登录", failed_attempts);
+        let mut metadata = HashMap::new();
+        metadata.insert("operation".to_string(), "unlock_with_pin".to_string());
+        metadata.insert("failed_attempts".to_string(), failed_attempts.to_string());
+        metadata.insert(ANOMALY_SOURCE_KEY.to_string(), ANOMALY_SOURCE_UNLOCK_PIN.to_string());
+        if let Err(e) = self
+            .log_system_audit(
+                user_id.to_string(),
+                AuditAction::RateLimited,
+                Some("unlock_pin".to_string()),
+                None,
+                "denied".to_string(),
+                Some(message.clone()),
+                metadata,
+            )
+            .await
+        {
+            tracing::error!("Failed to record audit log for unlock PIN lockout: {}", e);
+        }
+
+        Err(reauth_required(message).into())
+    }
+
+    pub async fn has_unlock_pin(&self, user_id: &str) -> bool {
+        self.unlock_pins.lock().await.contains_key(user_id)
+    }
+
+    /// 清除用户的解锁 PIN，修改密码后调用
+    pub async fn clear_unlock_pin(&self, user_id: &str) -> bool {
+        self.unlock_pins.lock().await.remove(user_id).is_some()
+    }
+
+    /// 退出登录时清除所有解锁 PIN，返回清除个数
+    pub async fn clear_all_unlock_pins(&self) -> usize {
+        let mut pins = self.unlock_pins.lock().await;
+        let count = pins.len();
+        pins.clear();
+        count
+    }
+
+    async fn record_failed_unlock(&self, user_id: &str) {
         let mut activities = self.session_activities.lock().await;
-        let activity = activities.entry(user_id.to_string()).or_insert(SessionActivity {
-            last_activity: Utc::now(),
-            failed_login_attempts: 0,
-            access_count: 0,
-            last_access_times: Vec::new(),
-        });
+        let activity = activities.entry(user_id.to_string()).or_insert_with(SessionActivity::new);
+        activity.failed_unlock_attempts += 1;
+    }
+
+    async fn reset_failed_unlock(&self, user_id: &str) {
+        let mut activities = self.session_activities.lock().await;
+        if let Some(activity) = activities.get_mut(user_id) {
+            activity.failed_unlock_attempts = 0;
+        }
+    }
+
+    /// 申请发送短信验证码：未超限时记录本次请求，返回距下次可请求的秒数
+    pub async fn acquire_sms_slot(&self, phone: &str) -> Result<u64> {
+        self.acquire_sms_slot_at(phone, Utc::now()).await
+    }
+
+    pub async fn acquire_sms_slot_at(&self, phone: &str, now: DateTime<Utc>) -> Result<u64> {
+        let phone_hash = phone_index(phone)?;
+        let mut recent = self
+            .recent_sms_requests(&phone_hash, now - Duration::seconds(SMS_WINDOW_SECS))
+            .await?;
+
+        let retry_after = sms_retry_after(&recent, now);
+        if retry_after > 0 {
+            let message = format!("验证码请求过于频繁，请 {} 秒后重试", retry_after);
+            tracing::warn!("SMS code request rate limited, retry after {}s", retry_after);
+
+            let mut metadata = HashMap::new();
+            metadata.insert("operation".to_string(), "request_sms_code".to_string());
+            metadata.insert("retry_after_seconds".to_string(), retry_after.to_string());
+            if let Err(e) = self
+                .log_system_audit(
+                    "anonymous".to_string(),
+                    AuditAction::RateLimited,
+                    Some("sms_code".to_string()),
+                    Some(phone_hash),
+                    "denied".to_string(),
+                    Some(message.clone()),
+                    metadata,
+                )
+                .await
+            {
+                tracing::error!("Failed to record audit log for rate limited SMS request: {}", e);
+            }
+
+            return Err(AppError::rate_limited(message, retry_after).into());
+        }
+
+        self.store_sms_request(&phone_hash, now).await?;
+        recent.push(now);
+        Ok(sms_retry_after(&recent, now))
+    }
+
+    async fn recent_sms_requests(&self, phone_hash: &str, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>> {
+        if let Some(connection) = &self.connection {
+            return SmsRequestDao::with_connection(connection.clone())
+                .find_since(phone_hash, since)
+                .map_err(dao_error);
+        }
+
+        let requests = self.sms_requests.lock().await;
+        Ok(requests
+            .get(phone_hash)
+            .map(|times| times.iter().filter(|t| **t > since).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn store_sms_request(&self, phone_hash: &str, requested_at: DateTime<Utc>) -> Result<()> {
+        let expired_before = requested_at - Duration::seconds(SMS_WINDOW_SECS);
+
+        if let Some(connection) = &self.connection {
+            let dao = SmsRequestDao::with_connection(connection.clone());
+            dao.record(phone_hash, requested_at).map_err(dao_error)?;
+            dao.delete_before(expired_before).map_err(dao_error)?;
+            return Ok(());
+        }
+
+        let mut requests = self.sms_requests.lock().await;
+        let times = requests.entry(phone_hash.to_string()).or_default();
+        times.retain(|t| *t > expired_before);
+        times.push(requested_at);
+        Ok(())
+    }
 
+    /// 前端在真实的键盘、鼠标输入时调用，刷新最后活动时间
+    pub async fn record_user_interaction(&self, user_id: &str) {
+        let mut activities = self.session_activities.lock().await;
+        let activity = activities.entry(user_id.to_string()).or_insert_with(SessionActivity::new);
         activity.last_activity = Utc::now();
+    }
+
+    /// 更新会话活动：访问记录供异常检测使用，只有用户主动操作刷新最后活动时间
+    async fn update_session_activity(&self, user_id: &str, interactive: bool) {
+        let mut activities = self.session_activities.lock().await;
+        let activity = activities.entry(user_id.to_string()).or_insert_with(SessionActivity::new);
+
+        if interactive {
+            activity.last_activity = Utc::now();
+        }
         activity.access_count += 1;
         activity.last_access_times.push(Utc::now());
 
@@ -277,6 +783,11 @@ impl SecurityService {
         }
     }
 
+    /// 应用配置变更时更新自动锁屏时间
+    pub fn set_auto_lock_timeout(&mut self, seconds: u64) {
+        self.auto_lock_timeout = seconds;
+    }
+
     /// 检查是否需要自动锁屏
     pub async fn should_auto_lock(&self, user_id: &str) -> bool {
         let activities = self.session_activities.lock().await;
@@ -289,6 +800,14 @@ impl SecurityService {
         false
     }
 
+    /// 自动锁屏检查结果，同时说明当前用户能否用 PIN 解锁
+    pub async fn auto_lock_status(&self, user_id: &str) -> AutoLockStatus {
+        AutoLockStatus {
+            should_lock: self.should_auto_lock(user_id).await,
+            pin_unlock_available: self.has_unlock_pin(user_id).await,
+        }
+    }
+
     /// 获取最后活动时间
     pub async fn get_last_activity(&self, user_id: &str) -> Option<DateTime<Utc>> {
         let activities = self.session_activities.lock().await;
@@ -344,12 +863,114 @@ impl SecurityService {
 
         Ok(())
     }
+
+    /// 清理超过保留天数的异常记录，返回删除条数
+    pub async fn cleanup_anomaly_records(&self, days: i64) -> usize {
+        let cutoff = Utc::now() - chrono::Duration::days(days);
+        let mut records = self.anomaly_records.lock().await;
+        let before = records.len();
+        records.retain(|record| record.detected_at > cutoff);
+        before - records.len()
+    }
+}
+
+// 根据窗口内的请求时间（正序）计算还需等待的秒数，0 表示可以请求
+fn sms_retry_after(requests: &[DateTime<Utc>], now: DateTime<Utc>) -> u64 {
+    let mut wait = 0;
+    if let Some(last) = requests.last() {
+        wait = SMS_MIN_INTERVAL_SECS - (now - *last).num_seconds();
+    }
+    if requests.len() >= SMS_HOURLY_LIMIT {
+        let oldest = requests[requests.len() - SMS_HOURLY_LIMIT];
+        wait = wait.max(SMS_WINDOW_SECS - (now - oldest).num_seconds());
+    }
+    wait.max(0) as u64
+}
+
+// PIN 不可用或已因多次输错清除，只能重新登录
+#[cfg(not(test))]
+fn default_crypto() -> Option<CryptoService> {
+    None
+}
+
+#[cfg(test)]
+fn default_crypto() -> Option<CryptoService> {
+    Some(CryptoService::new())
+}
+
+fn reauth_required(message: impl Into<String>) -> AppError {
+    AppError::new(ErrorType::AuthError, message)
+        .with_code(CODE_REAUTH_REQUIRED)
+        .with_retryable(false)
 }
 
 fn matches_action(a: &AuditAction, b: &AuditAction) -> bool {
     std::mem::discriminant(a) == std::mem::discriminant(b)
 }
 
+// 状态和元数据一并存入 details
+fn persist_audit_log(connection: &DbConnection, log: &AuditLog) -> Result<String, Box<dyn std::error::Error>> {
+    let mut details = serde_json::Map::new();
+    details.insert("status".to_string(), log.status.clone().into());
+    if let Some(error_message) = &log.error_message {
+        details.insert("errorMessage".to_string(), error_message.clone().into());
+    }
+    for (key, value) in &log.metadata {
+        details.insert(key.clone(), value.clone().into());
+    }
+
+    AuditLogDao::with_connection(connection.clone()).log_action(
+        &log.user_id,
+        log.action.as_str(),
+        log.resource_type.as_deref(),
+        log.resource_id.as_deref(),
+        Some(serde_json::Value::Object(details)),
+        log.ip_address.as_deref(),
+        log.user_agent.as_deref(),
+    )
+}
+
+const SEVERITIES: &[&str] = &["low", "medium", "high", "critical"];
+
+fn validate_security_config(config: &SecurityConfig) -> Result<()> {
+    let bulk = &config.bulk_patient_views;
+    let off_hours = &config.off_hours_access;
+    let downloads = &config.file_downloads;
+
+    let invalid = if bulk.max_distinct_patients == 0 || bulk.window_minutes <= 0 {
+        Some("批量查看规则的阈值和时间窗口必须大于 0")
+    } else if off_hours.start_hour > 23 || off_hours.end_hour > 23 {
+        Some("非工作时间的起止小时必须在 0–23 之间")
+    } else if off_hours.lookback_hours <= 0 {
+        Some("非工作时间规则的回溯时长必须大于 0")
+    } else if downloads.max_megabytes == 0 || downloads.window_minutes <= 0 {
+        Some("下载量规则的阈值和时间窗口必须大于 0")
+    } else if ![
+        &bulk.severity,
+        &bulk.escalated_severity,
+        &off_hours.severity,
+        &downloads.severity,
+        &downloads.escalated_severity,
+    ]
+    .iter()
+    .all(|severity| SEVERITIES.contains(&severity.as_str()))
+    {
+        Some("异常级别只能是 low、medium、high 或 critical")
+    } else {
+        None
+    };
+
+    match invalid {
+        Some(message) => Err(AppError::invalid_argument(message).into()),
+        None => Ok(()),
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
 #[cfg(test)]
+#[path = "security_test.rs"]
 mod tests;
 
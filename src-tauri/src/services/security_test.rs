@@ -201,6 +201,77 @@ mod security_service_tests {
         assert!(service.should_auto_lock(user_id).await);
     }
 
+    #[tokio::test]
+    async fn test_background_audits_do_not_prevent_auto_lock() {
+        let service = SecurityService::new(1);
+        let user_id = "doctor_001";
+
+        service
+            .log_audit(
+                user_id.to_string(),
+                AuditAction::Login,
+                None,
+                None,
+                "success".to_string(),
+                None,
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+        let logged_in_at = service.get_last_activity(user_id).await.unwrap();
+
+        // 超时时间内持续有后台审计写入，以及非交互类操作
+        for i in 0..8 {
+            service
+                .log_system_audit(
+                    user_id.to_string(),
+                    AuditAction::DeleteData,
+                    Some("retention".to_string()),
+                    None,
+                    "success".to_string(),
+                    None,
+                    HashMap::new(),
+                )
+                .await
+                .unwrap();
+            if i % 2 == 0 {
+                service
+                    .log_audit(
+                        user_id.to_string(),
+                        AuditAction::DownloadFile,
+                        None,
+                        None,
+                        "success".to_string(),
+                        None,
+                        HashMap::new(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        }
+
+        assert_eq!(service.get_last_activity(user_id).await, Some(logged_in_at));
+        assert!(service.should_auto_lock(user_id).await);
+        let logs = service.get_audit_logs(Some(user_id.to_string()), None, None, None, 100).await.unwrap();
+        assert_eq!(logs.len(), 13);
+
+        // 真实输入刷新计时
+        service.record_user_interaction(user_id).await;
+        assert!(!service.should_auto_lock(user_id).await);
+    }
+
+    #[test]
+    fn test_interactive_actions() {
+        assert!(AuditAction::Login.is_user_interaction());
+        assert!(AuditAction::ViewPatient.is_user_interaction());
+        assert!(AuditAction::SendMessage.is_user_interaction());
+        assert!(AuditAction::ChangeSettings.is_user_interaction());
+        assert!(!AuditAction::DeleteData.is_user_interaction());
+        assert!(!AuditAction::RateLimited.is_user_interaction());
+        assert!(!AuditAction::PermissionDenied.is_user_interaction());
+    }
+
     #[tokio::test]
     async fn test_anomaly_resolution() {
         let service = SecurityService::new(300);
@@ -324,4 +395,291 @@ mod security_service_tests {
         let user2_anomalies = service.detect_anomalies(user2).await.unwrap();
         assert!(user2_anomalies.is_empty());
     }
+
+    fn device_info() -> crate::services::DeviceInfo {
+        crate::services::DeviceInfo {
+            hostname: "clinic-pc-01".to_string(),
+            os_version: "Windows 11".to_string(),
+            app_version: "0.1.0".to_string(),
+            local_ip: Some("192.168.1.20".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_enriched_with_device_info() {
+        let connection = create_test_connection();
+        let mut service = SecurityService::new(300).with_device_info(device_info());
+        service.attach_database(connection.clone()).unwrap();
+
+        service
+            .log_audit(
+                "doctor_001".to_string(),
+                AuditAction::ViewPatient,
+                Some("patient".to_string()),
+                Some("patient_123".to_string()),
+                "success".to_string(),
+                None,
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+
+        let logs = service
+            .get_audit_logs(Some("doctor_001".to_string()), None, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs[0].ip_address.as_deref(), Some("192.168.1.20"));
+        assert_eq!(
+            logs[0].user_agent.as_deref(),
+            Some("TelemedicineDesktop/0.1.0 (Windows 11; clinic-pc-01)")
+        );
+
+        // 持久化的日志同样带上设备信息
+        let persisted = crate::database::dao::AuditLogDao::with_connection(connection)
+            .find_by_resource("patient", "patient_123")
+            .unwrap();
+        assert_eq!(persisted.len(), 1);
+        assert_eq!(persisted[0].ip_address.as_deref(), Some("192.168.1.20"));
+        assert!(persisted[0].user_agent.as_deref().unwrap().contains("clinic-pc-01"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_origin_overrides_device_info() {
+        let service = SecurityService::new(300).with_device_info(device_info());
+
+        service
+            .log_audit_with_origin(
+                "doctor_001".to_string(),
+                AuditAction::Login,
+                None,
+                None,
+                "success".to_string(),
+                None,
+                HashMap::new(),
+                AuditOrigin {
+                    ip_address: Some("203.0.113.7".to_string()),
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let logs = service
+            .get_audit_logs(None, Some(AuditAction::Login), None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs[0].ip_address.as_deref(), Some("203.0.113.7"));
+        // 未提供的字段仍由设备信息补全
+        assert!(logs[0].user_agent.as_deref().unwrap().starts_with("TelemedicineDesktop/0.1.0"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_without_device_info_leaves_origin_empty() {
+        let service = SecurityService::new(300);
+
+        service
+            .log_audit(
+                "doctor_001".to_string(),
+                AuditAction::Logout,
+                None,
+                None,
+                "success".to_string(),
+                None,
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+
+        let logs = service.get_audit_logs(None, None, None, None, 10).await.unwrap();
+        assert!(logs[0].ip_address.is_none());
+        assert!(logs[0].user_agent.is_none());
+    }
+
+    fn create_test_connection() -> crate::database::connection::DbConnection {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        crate::database::migrations::MigrationManager::new().run_migrations(&conn).unwrap();
+        std::sync::Arc::new(std::sync::Mutex::new(conn))
+    }
+
+    #[tokio::test]
+    async fn test_bulk_patient_views_from_persisted_logs() {
+        let connection = create_test_connection();
+        let mut service = SecurityService::new(300);
+        service.attach_database(connection.clone()).unwrap();
+
+        // 非工作时间规则与运行时刻有关，这里关闭
+        let mut config = service.security_config().clone();
+        config.off_hours_access.enabled = false;
+        service.update_security_config(config.clone()).unwrap();
+
+        for i in 0..35 {
+            service
+                .log_audit(
+                    "doctor_001".to_string(),
+                    AuditAction::ViewPatient,
+                    Some("patient".to_string()),
+                    Some(format!("patient_{}", i)),
+                    "success".to_string(),
+                    None,
+                    HashMap::new(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let anomalies = service.detect_anomalies("doctor_001").await.unwrap();
+        assert_eq!(anomalies.len(), 1);
+        assert!(matches!(anomalies[0].anomaly_type, AnomalyType::UnusualAccessPattern));
+
+        // 未解决的相同异常不重复记录
+        service.detect_anomalies("doctor_001").await.unwrap();
+        let records = service.get_anomaly_records(Some("doctor_001".to_string()), None).await.unwrap();
+        assert_eq!(records.len(), 1);
+
+        // 调高阈值后不再触发，配置在重新挂载后仍然有效
+        config.bulk_patient_views.max_distinct_patients = 50;
+        service.update_security_config(config.clone()).unwrap();
+        let mut reloaded = SecurityService::new(300);
+        reloaded.attach_database(connection).unwrap();
+        assert_eq!(reloaded.security_config(), &config);
+        assert!(reloaded.detect_anomalies("doctor_001").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_security_config_rejected() {
+        let mut service = SecurityService::new(300);
+        let mut config = service.security_config().clone();
+        config.off_hours_access.start_hour = 24;
+        assert!(service.update_security_config(config).is_err());
+
+        let mut config = service.security_config().clone();
+        config.file_downloads.severity = "urgent".to_string();
+        assert!(service.update_security_config(config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sms_rate_limit_with_clock_advance() {
+        let service = SecurityService::new(300);
+        let phone = "13800138000";
+        let start = Utc::now();
+        let at = |seconds: i64| start + chrono::Duration::seconds(seconds);
+
+        assert_eq!(service.acquire_sms_slot_at(phone, at(0)).await.unwrap(), 60);
+
+        // 一分钟内连续请求被拒绝，并返回剩余等待时间
+        let error = AppError::from(service.acquire_sms_slot_at(phone, at(20)).await.unwrap_err());
+        assert!(matches!(error.error_type, crate::models::ErrorType::PermissionError));
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_RATE_LIMITED));
+        assert_eq!(error.details.unwrap()["retryAfterSeconds"], 40);
+
+        // 其他手机号不受影响
+        assert_eq!(service.acquire_sms_slot_at("13900139000", at(20)).await.unwrap(), 60);
+
+        for minute in 1..4 {
+            assert_eq!(service.acquire_sms_slot_at(phone, at(minute * 60)).await.unwrap(), 60);
+        }
+        // 第 5 次请求后需等到第一次请求满一小时
+        assert_eq!(service.acquire_sms_slot_at(phone, at(240)).await.unwrap(), 3360);
+        assert!(service.acquire_sms_slot_at(phone, at(600)).await.is_err());
+        assert!(service.acquire_sms_slot_at(phone, at(3599)).await.is_err());
+        assert_eq!(service.acquire_sms_slot_at(phone, at(3600)).await.unwrap(), 60);
+
+        let logs = service
+            .get_audit_logs(Some("anonymous".to_string()), Some(AuditAction::RateLimited), None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 3);
+        assert_eq!(logs[0].status, "denied");
+        assert_ne!(logs[0].resource_id.as_deref(), Some(phone));
+    }
+
+    #[tokio::test]
+    async fn test_sms_rate_limit_survives_restart() {
+        let connection = create_test_connection();
+        let now = Utc::now();
+
+        let mut service = SecurityService::new(300);
+        service.attach_database(connection.clone()).unwrap();
+        service.acquire_sms_slot_at("13800138000", now).await.unwrap();
+
+        let mut restarted = SecurityService::new(300);
+        restarted.attach_database(connection).unwrap();
+        let error = AppError::from(
+            restarted
+                .acquire_sms_slot_at("13800138000", now + chrono::Duration::seconds(30))
+                .await
+                .unwrap_err(),
+        );
+        assert_eq!(error.details.unwrap()["retryAfterSeconds"], 30);
+    }
+
+    #[tokio::test]
+    async fn test_unlock_pin_stored_as_argon2_hash() {
+        let service = SecurityService::new(300);
+        let user_id = "doctor_001";
+
+        for invalid in ["12345", "1234567", "12a456", "１２３４５６", ""] {
+            let error = AppError::from(service.set_unlock_pin(user_id, invalid).await.unwrap_err());
+            assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_VALIDATION_FAILED));
+        }
+        assert!(!service.has_unlock_pin(user_id).await);
+
+        service.set_unlock_pin(user_id, "246810").await.unwrap();
+        let hash = service.unlock_pins.lock().await[user_id].hash.clone();
+        assert!(hash.starts_with("$argon2"));
+        assert!(!hash.contains("246810"));
+
+        assert!(service.auto_lock_status(user_id).await.pin_unlock_available);
+        assert!(!service.auto_lock_status("doctor_002").await.pin_unlock_available);
+        service.unlock_with_pin(user_id, "246810").await.unwrap();
+
+        // 修改密码后清除
+        assert!(service.clear_unlock_pin(user_id).await);
+        let error = AppError::from(service.unlock_with_pin(user_id, "246810").await.unwrap_err());
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_REAUTH_REQUIRED));
+    }
+
+    #[tokio::test]
+    async fn test_unlock_pin_rate_limited_then_requires_login() {
+        let service = SecurityService::new(300);
+        let user_id = "doctor_001";
+        service.set_unlock_pin(user_id, "246810").await.unwrap();
+
+        for attempt in 1..UNLOCK_PIN_MAX_ATTEMPTS {
+            let error = AppError::from(service.unlock_with_pin(user_id, "000000").await.unwrap_err());
+            assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_UNLOCK_PIN_INCORRECT));
+            assert_eq!(error.details.unwrap()["remainingAttempts"], UNLOCK_PIN_MAX_ATTEMPTS - attempt);
+        }
+
+        // 输错计入异常检测，metadata 标明来源是解锁 PIN
+        let anomalies = service.detect_anomalies(user_id).await.unwrap();
+        let anomaly = anomalies
+            .iter()
+            .find(|a| matches!(a.anomaly_type, AnomalyType::MultipleFailedLogins))
+            .unwrap();
+        assert_eq!(anomaly.metadata.get(ANOMALY_SOURCE_KEY).map(String::as_str), Some(ANOMALY_SOURCE_UNLOCK_PIN));
+
+        // 第 5 次输错后 PIN 被清除，正确的 PIN 也不能再解锁
+        let error = AppError::from(service.unlock_with_pin(user_id, "000000").await.unwrap_err());
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_REAUTH_REQUIRED));
+        assert!(!service.has_unlock_pin(user_id).await);
+        let error = AppError::from(service.unlock_with_pin(user_id, "246810").await.unwrap_err());
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_REAUTH_REQUIRED));
+
+        let logs = service
+            .get_audit_logs(Some(user_id.to_string()), Some(AuditAction::RateLimited), None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].resource_type.as_deref(), Some("unlock_pin"));
+        assert!(logs.iter().all(|log| !log.metadata.values().any(|v| v.contains("000000"))));
+
+        // 重新登录后设置新 PIN，失败计数从零开始
+        service.set_unlock_pin(user_id, "135790").await.unwrap();
+        assert!(service.unlock_with_pin(user_id, "000000").await.is_err());
+        service.unlock_with_pin(user_id, "135790").await.unwrap();
+        let anomalies = service.detect_anomalies(user_id).await.unwrap();
+        assert!(anomalies.iter().all(|a| !a.metadata.contains_key(ANOMALY_SOURCE_KEY)));
+    }
 }
@@ -0,0 +1,289 @@
+// 敏感词过滤服务
+// 词表保存在数据库中，内存里按连接缓存一份 Aho-Corasick 自动机，词表变更后重建
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::SensitiveWordDao;
+use crate::models::{AppError, ErrorType, SensitiveWord, SensitiveWordCategory};
+use aho_corasick::AhoCorasick;
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+
+const MAX_WORD_LENGTH: usize = 50;
+pub const SENSITIVE_WORD_BLOCKED: &str = "SENSITIVE_WORD_BLOCKED";
+
+type MatcherSlot = Arc<RwLock<Option<Arc<SensitiveWordMatcher>>>>;
+
+type ConnectionMatchers = Vec<(Weak<Mutex<Connection>>, MatcherSlot)>;
+
+static MATCHERS: OnceLock<Mutex<ConnectionMatchers>> = OnceLock::new();
+
+// 每个数据库连接一份自动机缓存
+fn matcher_slot_for(connection: &DbConnection) -> MatcherSlot {
+    let mut slots = MATCHERS.get_or_init(|| Mutex::new(Vec::new())).lock().unwrap();
+    slots.retain(|(conn, _)| conn.strong_count() > 0);
+
+    if let Some((_, slot)) = slots.iter().find(|(conn, _)| conn.as_ptr() == Arc::as_ptr(connection)) {
+        return slot.clone();
+    }
+
+    let slot: MatcherSlot = Arc::new(RwLock::new(None));
+    slots.push((Arc::downgrade(connection), slot.clone()));
+    slot
+}
+
+/// 一次扫描的结果，同一个词只出现一次，按首次出现的位置排序
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SensitiveWordScan {
+    pub forbidden: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl SensitiveWordScan {
+    pub fn is_blocked(&self) -> bool {
+        !self.forbidden.is_empty()
+    }
+
+    // 命中的禁用词放在 details.matches 中，供前端高亮
+    pub fn blocked_error(&self) -> AppError {
+        AppError::new(
+            ErrorType::ValidationError,
+            format!("消息包含禁止发送的敏感词: {}", self.forbidden.join("、")),
+        )
+        .with_code(SENSITIVE_WORD_BLOCKED)
+        .with_details(serde_json::json!({ "matches": self.forbidden }))
+        .with_retryable(false)
+    }
+}
+
+pub struct SensitiveWordMatcher {
+    automaton: Option<AhoCorasick>,
+    categories: Vec<SensitiveWordCategory>,
+    words: Vec<String>,
+}
+
+impl SensitiveWordMatcher {
+    pub fn build(words: &[SensitiveWord]) -> Result<Self> {
+        let automaton = if words.is_empty() {
+            None
+        } else {
+            Some(
+                AhoCorasick::builder()
+                    .ascii_case_insensitive(true)
+                    .build(words.iter().map(|w| w.word.as_str()))?,
+            )
+        };
+
+        Ok(Self {
+            automaton,
+            categories: words.iter().map(|w| w.category).collect(),
+            words: words.iter().map(|w| w.word.clone()).collect(),
+        })
+    }
+
+    // 重叠匹配：“高血压”和“血压药”在“高血压药”中都会命中
+    pub fn scan(&self, text: &str) -> SensitiveWordScan {
+        let mut scan = SensitiveWordScan::default();
+        let Some(automaton) = &self.automaton else {
+            return scan;
+        };
+
+        let mut seen = vec![false; self.words.len()];
+        for found in automaton.find_overlapping_iter(text) {
+            let index = found.pattern().as_usize();
+            if std::mem::replace(&mut seen[index], true) {
+                continue;
+            }
+
+            let word = self.words[index].clone();
+            match self.categories[index] {
+                SensitiveWordCategory::Forbidden => scan.forbidden.push(word),
+                SensitiveWordCategory::Warn => scan.warnings.push(word),
+            }
+        }
+
+        scan
+    }
+}
+
+pub struct SensitiveWordService {
+    dao: SensitiveWordDao,
+    matcher: MatcherSlot,
+}
+
+impl SensitiveWordService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self {
+            matcher: matcher_slot_for(&connection),
+            dao: SensitiveWordDao::with_connection(connection),
+        }
+    }
+
+    pub fn list_words(&self, category: Option<SensitiveWordCategory>) -> Result<Vec<SensitiveWord>> {
+        self.dao.find_all(category).map_err(dao_error)
+    }
+
+    pub fn add_word(&self, word: &str, category: SensitiveWordCategory, created_by: Option<&str>) -> Result<SensitiveWord> {
+        let word = word.trim();
+        if word.is_empty() {
+            return Err(anyhow!("敏感词不能为空"));
+        }
+        if word.chars().count() > MAX_WORD_LENGTH {
+            return Err(anyhow!("敏感词不能超过 {} 个字符", MAX_WORD_LENGTH));
+        }
+
+        let saved = self.dao.upsert(word, category, created_by).map_err(dao_error)?;
+        self.invalidate();
+        Ok(saved)
+    }
+
+    pub fn remove_word(&self, id: &str) -> Result<bool> {
+        let removed = self.dao.delete(id).map_err(dao_error)?;
+        if removed {
+            self.invalidate();
+        }
+        Ok(removed)
+    }
+
+    pub fn scan(&self, text: &str) -> Result<SensitiveWordScan> {
+        Ok(self.matcher()?.scan(text))
+    }
+
+    fn matcher(&self) -> Result<Arc<SensitiveWordMatcher>> {
+        if let Some(matcher) = self.matcher.read().unwrap().as_ref() {
+            return Ok(matcher.clone());
+        }
+
+        let mut slot = self.matcher.write().unwrap();
+        if let Some(matcher) = slot.as_ref() {
+            return Ok(matcher.clone());
+        }
+
+        let words = self.dao.find_all(None).map_err(dao_error)?;
+        let matcher = Arc::new(SensitiveWordMatcher::build(&words)?);
+        *slot = Some(matcher.clone());
+        Ok(matcher)
+    }
+
+    fn invalidate(&self) {
+        *self.matcher.write().unwrap() = None;
+    }
+}
+
+impl Default for SensitiveWordService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use chrono::Utc;
+    use std::time::{Duration, Instant};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn word(word: &str, category: SensitiveWordCategory) -> SensitiveWord {
+        SensitiveWord {
+            id: word.to_string(),
+            word: word.to_string(),
+            category,
+            created_by: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_default_word_is_forbidden() {
+        let service = SensitiveWordService::with_connection(create_test_connection());
+
+        let scan = service.scan("这是一条测试敏感词消息").unwrap();
+        assert!(scan.is_blocked());
+        assert_eq!(scan.forbidden, vec!["测试敏感词".to_string()]);
+
+        let payload = serde_json::to_value(scan.blocked_error()).unwrap();
+        assert_eq!(payload["code"], SENSITIVE_WORD_BLOCKED);
+        assert_eq!(payload["details"]["matches"][0], "测试敏感词");
+    }
+
+    #[test]
+    fn test_overlapping_words_and_categories() {
+        let matcher = SensitiveWordMatcher::build(&[
+            word("高血压", SensitiveWordCategory::Warn),
+            word("血压药", SensitiveWordCategory::Forbidden),
+            word("代购", SensitiveWordCategory::Forbidden),
+            word("VIP", SensitiveWordCategory::Warn),
+        ])
+        .unwrap();
+
+        let scan = matcher.scan("高血压药可以代购吗，vip 通道，再问一次高血压药");
+        assert_eq!(scan.forbidden, vec!["血压药".to_string(), "代购".to_string()]);
+        assert_eq!(scan.warnings, vec!["高血压".to_string(), "VIP".to_string()]);
+
+        assert!(!matcher.scan("请按时复诊").is_blocked());
+    }
+
+    #[test]
+    fn test_matcher_rebuilt_after_change() {
+        let connection = create_test_connection();
+        let service = SensitiveWordService::with_connection(connection.clone());
+        assert!(service.scan("偏方").unwrap().warnings.is_empty());
+
+        let added = service.add_word("偏方", SensitiveWordCategory::Warn, Some("admin")).unwrap();
+        // 另一个实例共享同一连接的缓存
+        let other = SensitiveWordService::with_connection(connection);
+        assert_eq!(other.scan("祖传偏方").unwrap().warnings, vec!["偏方".to_string()]);
+
+        // 重复添加只更新类别
+        service.add_word("偏方", SensitiveWordCategory::Forbidden, None).unwrap();
+        assert!(other.scan("祖传偏方").unwrap().is_blocked());
+        assert_eq!(service.list_words(Some(SensitiveWordCategory::Forbidden)).unwrap().len(), 2);
+
+        assert!(service.remove_word(&added.id).unwrap());
+        assert!(!other.scan("祖传偏方").unwrap().is_blocked());
+        assert!(service.add_word("  ", SensitiveWordCategory::Warn, None).is_err());
+    }
+
+    #[test]
+    fn test_scan_long_message_benchmark() {
+        let words: Vec<SensitiveWord> = (0..2000)
+            .map(|i| word(&format!("违禁词{}号", i), SensitiveWordCategory::Forbidden))
+            .collect();
+        let matcher = SensitiveWordMatcher::build(&words).unwrap();
+
+        let mut message: String = "患者主诉头痛三天，伴有恶心。".chars().cycle().take(4990).collect();
+        message.push_str("违禁词1999号");
+        assert_eq!(message.chars().count(), 5000);
+
+        let iterations = 200;
+        let start = Instant::now();
+        for _ in 0..iterations {
+            assert!(matcher.scan(&message).is_blocked());
+        }
+        let elapsed = start.elapsed();
+
+        // 单条 5000 字消息的扫描应远低于 10ms
+        assert!(
+            elapsed < Duration::from_millis(10) * iterations,
+            "scanning took {:?} for {} iterations",
+            elapsed,
+            iterations
+        );
+    }
+}
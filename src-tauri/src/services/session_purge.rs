@@ -0,0 +1,177 @@
+// 会话数据清理：问诊窗口关闭或退出登录时丢弃内存中的患者数据，之后的访问重新从数据库读取
+
+use crate::database::dao::patient_dao::purge_field_crypto_keys;
+use crate::database::query_optimizer::{
+    clear_all_query_caches, invalidate_tag_in_all_caches, CACHE_TAG_MESSAGES, CACHE_TAG_PATIENTS,
+};
+use crate::services::{NotificationRouter, SecurityService, WebSocketManager};
+use serde::Serialize;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PurgeReport {
+    #[serde(rename = "replayEvents")]
+    pub replay_events: usize,
+    #[serde(rename = "cacheEntries")]
+    pub cache_entries: usize,
+    #[serde(rename = "sessionKeys")]
+    pub session_keys: usize,
+    #[serde(rename = "unreadBadges")]
+    pub unread_badges: usize,
+    #[serde(rename = "savedWindows")]
+    pub saved_windows: usize,
+    #[serde(rename = "unlockPins")]
+    pub unlock_pins: usize,
+}
+
+/// 问诊窗口关闭后清理该问诊的回放事件和解密后的患者、消息缓存
+pub async fn purge_consultation_context(websocket: &WebSocketManager, consultation_id: &str) -> PurgeReport {
+    let replay_events = websocket.purge_replay(Some(consultation_id)).await;
+    // 查询缓存按标签而不是按问诊组织，患者和消息缓存整体失效
+    let cache_entries =
+        invalidate_tag_in_all_caches(CACHE_TAG_PATIENTS) + invalidate_tag_in_all_caches(CACHE_TAG_MESSAGES);
+
+    tracing::info!(
+        "Purged consultation {} context: {} replay events, {} cache entries",
+        consultation_id,
+        replay_events,
+        cache_entries
+    );
+    PurgeReport {
+        replay_events,
+        cache_entries,
+        ..Default::default()
+    }
+}
+
+/// 退出登录时清空各服务的内存缓存，清零本次会话派生的密钥并清除解锁 PIN
+pub async fn purge_all_session_data(
+    websocket: &WebSocketManager,
+    security: &SecurityService,
+    router: &Mutex<NotificationRouter>,
+) -> PurgeReport {
+    let replay_events = websocket.purge_replay(None).await;
+    let unlock_pins = security.clear_all_unlock_pins().await;
+    let report = PurgeReport {
+        replay_events,
+        cache_entries: clear_all_query_caches(),
+        session_keys: security.purge_session_keys() + purge_field_crypto_keys(),
+        unread_badges: router.lock().unwrap().clear_all(),
+        saved_windows: 0,
+        unlock_pins,
+    };
+
+    tracing::info!("Purged session data: {:?}", report);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connection::DbConnection;
+    use crate::database::dao::PatientDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::database::query_optimizer::query_cache_for;
+    use crate::models::{DataScope, Patient, PatientQuery};
+    use crate::services::PatientService;
+    use chrono::{Duration, Utc};
+    use rusqlite::Connection;
+    use std::sync::Arc;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn patient(id: &str, name: &str) -> Patient {
+        Patient {
+            id: id.to_string(),
+            name: name.to_string(),
+            age: Some(40),
+            gender: Some("male".to_string()),
+            phone: Some("13800138000".to_string()),
+            id_card: None,
+            tags: vec![],
+            avatar_url: None,
+            last_sync: Some(Utc::now()),
+            last_visit: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
+
+    fn query() -> PatientQuery {
+        PatientQuery {
+            keyword: None,
+            tags: None,
+            gender: None,
+            age_range: None,
+            last_visit_range: None,
+            sort: None,
+            page: 1,
+            page_size: 20,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_purge_all_empties_caches_and_rereads_database() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("p1", "张三")).unwrap();
+        let service = PatientService::with_connection(connection.clone(), None, Duration::minutes(30));
+        assert_eq!(service.get_patient_list(&query(), &DataScope::All).await.unwrap().items[0].name, "张三");
+
+        let security = SecurityService::new(900);
+        let router = Mutex::new(NotificationRouter::new());
+        router.lock().unwrap().bump_unread("c1");
+        let websocket = WebSocketManager::new();
+
+        connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE patients SET name = '张三丰' WHERE id = 'p1'", [])
+            .unwrap();
+
+        let report = purge_all_session_data(&websocket, &security, &router).await;
+        assert!(report.cache_entries >= 1);
+        assert_eq!(report.unread_badges, 1);
+        assert!(query_cache_for(&connection).is_empty());
+        assert_eq!(router.lock().unwrap().total_unread(), 0);
+
+        // 缓存清空后重新查库拿到最新数据
+        let page = service.get_patient_list(&query(), &DataScope::All).await.unwrap();
+        assert_eq!(page.items[0].name, "张三丰");
+    }
+
+    #[tokio::test]
+    async fn test_purge_all_clears_unlock_pins() {
+        let security = SecurityService::new(900);
+        security.set_unlock_pin("doctor_001", "246810").await.unwrap();
+        security.set_unlock_pin("doctor_002", "135790").await.unwrap();
+
+        let report = purge_all_session_data(&WebSocketManager::new(), &security, &Mutex::new(NotificationRouter::new())).await;
+        assert_eq!(report.unlock_pins, 2);
+        assert!(!security.has_unlock_pin("doctor_001").await);
+        assert!(!security.auto_lock_status("doctor_002").await.pin_unlock_available);
+        assert!(security.unlock_with_pin("doctor_001", "246810").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_purge_consultation_drops_patient_cache() {
+        let connection = create_test_connection();
+        PatientDao::with_connection(connection.clone()).upsert(&patient("p1", "张三")).unwrap();
+        let service = PatientService::with_connection(connection.clone(), None, Duration::minutes(30));
+        service.get_patient_list(&query(), &DataScope::All).await.unwrap();
+        query_cache_for(&connection).set("unrelated".to_string(), serde_json::json!(1));
+
+        let report = purge_consultation_context(&WebSocketManager::new(), "c1").await;
+        assert!(report.cache_entries >= 1);
+        assert_eq!(report.replay_events, 0);
+
+        // 没有标签的缓存不受影响
+        let cache = query_cache_for(&connection);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get("unrelated").is_some());
+    }
+}
@@ -0,0 +1,388 @@
+// 重启后恢复登录会话：登录成功时 token 用系统钥匙串中保管的密钥加密后存入 users 表，
+// 启动时取最近一次登录的会话，校验过期时间后向认证服务确认；离线时在宽限期内允许恢复
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{StoredSession, UserDao};
+use crate::models::{AuthResult, ErrorType};
+use crate::services::AuthService;
+use crate::utils::crypto::CryptoService;
+use crate::utils::keychain::load_or_create_keychain_key;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+const KEYCHAIN_ACCOUNT: &str = "session-token-key";
+// 离线无法向服务器确认时，距上次确认不超过该时长的会话仍可恢复
+pub const OFFLINE_RESTORE_GRACE_HOURS: i64 = 24;
+
+/// 会话 token 加密密钥的保管处，生产环境为系统钥匙串
+pub trait SessionKeyStore: Send + Sync {
+    // 读取密钥，首次使用时生成并保存
+    fn load_or_create_key(&self) -> Result<Zeroizing<[u8; 32]>>;
+}
+
+/// macOS 钥匙串、Windows 凭据管理器或 Linux Secret Service
+pub struct OsKeychainKeyStore;
+
+impl SessionKeyStore for OsKeychainKeyStore {
+    fn load_or_create_key(&self) -> Result<Zeroizing<[u8; 32]>> {
+        load_or_create_keychain_key(KEYCHAIN_ACCOUNT)
+    }
+}
+
+// 用钥匙串中的密钥加解密会话 token
+pub fn session_crypto(key_store: &dyn SessionKeyStore) -> Result<CryptoService> {
+    Ok(CryptoService::with_master_key(&*key_store.load_or_create_key()?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreFailureReason {
+    // 没有保存的会话（从未登录或已退出）
+    NoSession,
+    Expired,
+    // 认证服务确认会话已失效
+    Revoked,
+    // 无法连接认证服务，且距上次确认已超过宽限期
+    OfflineUnverifiable,
+    // 无法解密或内容与用户不符
+    Corrupted,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SessionRestoreOutcome {
+    Restored {
+        // 与登录返回的结果相同
+        auth: AuthResult,
+        // false 表示离线时按宽限期恢复，尚未经服务器确认
+        verified: bool,
+    },
+    Failed {
+        reason: RestoreFailureReason,
+    },
+}
+
+pub struct SessionStore {
+    connection: Option<DbConnection>,
+    key_store: Arc<dyn SessionKeyStore>,
+    offline_grace: Duration,
+}
+
+impl SessionStore {
+    pub fn new(key_store: Arc<dyn SessionKeyStore>) -> Self {
+        Self {
+            connection: None,
+            key_store,
+            offline_grace: Duration::hours(OFFLINE_RESTORE_GRACE_HOURS),
+        }
+    }
+
+    // 指定数据库连接（默认使用全局数据库）
+    pub fn with_connection(mut self, connection: DbConnection) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    fn user_dao(&self) -> UserDao {
+        match &self.connection {
+            Some(connection) => UserDao::with_connection(connection.clone()),
+            None => UserDao::with_connection(get_database().get_connection()),
+        }
+    }
+
+    // 登录成功后保存会话，刚由认证服务签发的 token 视为已确认
+    pub fn save(&self, result: &AuthResult, expires_at: DateTime<Utc>) -> Result<()> {
+        let user_id = result.user["id"].as_str().filter(|id| !id.is_empty()).ok_or_else(|| anyhow!("登录结果缺少用户 ID"))?;
+        let username = result.user["username"].as_str().unwrap_or(user_id);
+        let encrypted_token = session_crypto(self.key_store.as_ref())?.encrypt_string(&result.token)?;
+
+        let dao = self.user_dao();
+        dao.save_session_user(user_id, username, &result.user.to_string()).map_err(|e| anyhow!(e.to_string()))?;
+        dao.update_token(user_id, &encrypted_token, expires_at).map_err(|e| anyhow!(e.to_string()))?;
+        dao.mark_session_verified(user_id, Utc::now()).map_err(|e| anyhow!(e.to_string()))?;
+        Ok(())
+    }
+
+    // 刷新后的 token 覆盖保存的会话
+    pub fn update_token(&self, user_id: &str, token: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        let encrypted_token = session_crypto(self.key_store.as_ref())?.encrypt_string(token)?;
+        self.user_dao()
+            .update_token(user_id, &encrypted_token, expires_at)
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    // 退出登录时清除保存的 token
+    pub fn clear(&self, user_id: &str) -> Result<()> {
+        self.user_dao().clear_token(user_id).map_err(|e| anyhow!(e.to_string()))
+    }
+
+    // 依次检查：有无保存的会话、能否解密、是否过期、认证服务是否确认；
+    // 过期、被吊销和损坏的会话随即清除，离线超出宽限期的保留，联网后可再次尝试
+    pub async fn restore(&self, auth_service: &AuthService, now: DateTime<Utc>) -> Result<SessionRestoreOutcome> {
+        let dao = self.user_dao();
+        let Some(stored) = dao.find_latest_session().map_err(|e| anyhow!(e.to_string()))? else {
+            return Ok(failed(RestoreFailureReason::NoSession));
+        };
+
+        let token = match session_crypto(self.key_store.as_ref())?.decrypt_string(&stored.encrypted_token) {
+            Ok(token) => token,
+            Err(e) => {
+                tracing::warn!("Failed to decrypt stored session for user {}: {}", stored.user_id, e);
+                return self.discard(&stored, RestoreFailureReason::Corrupted);
+            }
+        };
+
+        let claims = AuthService::token_claims(&token);
+        if claims.subject.as_deref().is_some_and(|subject| subject != stored.user_id) {
+            tracing::warn!("Stored token does not belong to user {}", stored.user_id);
+            return self.discard(&stored, RestoreFailureReason::Corrupted);
+        }
+        let expires_at = match (stored.session_expires, claims.expires_at) {
+            (Some(stored_at), Some(claimed_at)) => Some(stored_at.min(claimed_at)),
+            (stored_at, claimed_at) => stored_at.or(claimed_at),
+        };
+        let Some(expires_at) = expires_at.filter(|expires_at| *expires_at > now) else {
+            return self.discard(&stored, RestoreFailureReason::Expired);
+        };
+
+        let verified = match auth_service.verify_session(&token).await {
+            Ok(()) => {
+                dao.mark_session_verified(&stored.user_id, now).map_err(|e| anyhow!(e.to_string()))?;
+                true
+            }
+            // 被明确拒绝；限流等可重试的错误按无法确认处理
+            Err(e) if matches!(e.error_type, ErrorType::AuthError) && e.retryable != Some(true) => {
+                tracing::info!("Stored session for user {} was rejected: {}", stored.user_id, e);
+                return self.discard(&stored, RestoreFailureReason::Revoked);
+            }
+            Err(e) => {
+                let within_grace = stored.verified_at.is_some_and(|at| now - at <= self.offline_grace);
+                if !within_grace {
+                    tracing::info!("Cannot verify stored session for user {} offline: {}", stored.user_id, e);
+                    return Ok(failed(RestoreFailureReason::OfflineUnverifiable));
+                }
+                tracing::info!("Restoring session for user {} without server verification: {}", stored.user_id, e);
+                false
+            }
+        };
+
+        let user = stored
+            .profile
+            .as_deref()
+            .and_then(|profile| serde_json::from_str(profile).ok())
+            .unwrap_or_else(|| serde_json::json!({ "id": stored.user_id, "username": stored.username }));
+        Ok(SessionRestoreOutcome::Restored {
+            auth: AuthResult {
+                token,
+                user,
+                expires_at: expires_at.to_rfc3339(),
+                client_ip: None,
+            },
+            verified,
+        })
+    }
+
+    fn discard(&self, stored: &StoredSession, reason: RestoreFailureReason) -> Result<SessionRestoreOutcome> {
+        self.clear(&stored.user_id)?;
+        Ok(failed(reason))
+    }
+}
+
+fn failed(reason: RestoreFailureReason) -> SessionRestoreOutcome {
+    SessionRestoreOutcome::Failed { reason }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::AppError;
+    use crate::services::auth::encode_jwt_token;
+    use crate::services::{AuthProvider, AuthProviderResult, RefreshedToken};
+    use async_trait::async_trait;
+    use rusqlite::Connection;
+    use std::sync::Mutex;
+
+    /// 测试用的内存密钥，不访问系统钥匙串
+    pub(crate) struct MemoryKeyStore(pub [u8; 32]);
+
+    impl SessionKeyStore for MemoryKeyStore {
+        fn load_or_create_key(&self) -> Result<Zeroizing<[u8; 32]>> {
+            Ok(Zeroizing::new(self.0))
+        }
+    }
+
+    // 按预设结果回应会话确认
+    struct VerifyingProvider {
+        outcome: Mutex<AuthProviderResult<()>>,
+    }
+
+    #[async_trait]
+    impl AuthProvider for VerifyingProvider {
+        async fn login_password(&self, _username: &str, _password: &str) -> AuthProviderResult<AuthResult> {
+            unsupported()
+        }
+
+        async fn login_sms(&self, _phone: &str, _sms_code: &str) -> AuthProviderResult<AuthResult> {
+            unsupported()
+        }
+
+        async fn login_realname(&self, _id_card: &str) -> AuthProviderResult<AuthResult> {
+            unsupported()
+        }
+
+        async fn send_sms_code(&self, _phone: &str) -> AuthProviderResult<()> {
+            unsupported()
+        }
+
+        async fn verify_session(&self, _token: &str) -> AuthProviderResult<()> {
+            self.outcome.lock().unwrap().clone()
+        }
+
+        async fn refresh_token(&self, _token: &str) -> AuthProviderResult<RefreshedToken> {
+            unsupported()
+        }
+    }
+
+    // 测试后端未实现的接口返回错误，误调用时测试按错误失败而不是 panic
+    fn unsupported<T>() -> AuthProviderResult<T> {
+        Err(AppError::new(ErrorType::SystemError, "测试认证后端不支持该操作"))
+    }
+
+    fn auth_service(outcome: AuthProviderResult<()>) -> AuthService {
+        AuthService::with_provider(Arc::new(VerifyingProvider { outcome: Mutex::new(outcome) }))
+    }
+
+    fn offline() -> AuthProviderResult<()> {
+        Err(AppError::new(ErrorType::NetworkError, "无法连接认证服务").with_retryable(true))
+    }
+
+    fn create_store() -> (SessionStore, DbConnection) {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        let connection: DbConnection = Arc::new(Mutex::new(conn));
+        let store = SessionStore::new(Arc::new(MemoryKeyStore([7u8; 32]))).with_connection(connection.clone());
+        (store, connection)
+    }
+
+    fn login_result() -> AuthResult {
+        AuthResult {
+            token: encode_jwt_token("1", "doctor", "doctor").unwrap(),
+            user: serde_json::json!({ "id": "1", "username": "doctor", "name": "张医生", "role": "doctor" }),
+            expires_at: (Utc::now() + Duration::hours(8)).to_rfc3339(),
+            client_ip: Some("203.0.113.7".to_string()),
+        }
+    }
+
+    fn stored_token(connection: &DbConnection) -> Option<String> {
+        connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT encrypted_token FROM users WHERE id = '1'", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn set_verified_at(connection: &DbConnection, verified_at: DateTime<Utc>) {
+        connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE users SET session_verified_at = ?1 WHERE id = '1'", rusqlite::params![verified_at])
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_success_returns_login_result() {
+        let (store, connection) = create_store();
+        let login = login_result();
+        store.save(&login, Utc::now() + Duration::hours(8)).unwrap();
+
+        // 数据库中只有密文
+        let encrypted = stored_token(&connection).unwrap();
+        assert_ne!(encrypted, login.token);
+        assert!(CryptoService::new().decrypt_string(&encrypted).is_err());
+
+        match store.restore(&auth_service(Ok(())), Utc::now()).await.unwrap() {
+            SessionRestoreOutcome::Restored { auth, verified } => {
+                assert!(verified);
+                assert_eq!(auth.token, login.token);
+                assert_eq!(auth.user, login.user);
+                assert!(DateTime::parse_from_rfc3339(&auth.expires_at).is_ok());
+            }
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        // 被服务器拒绝时清除保存的 token
+        let outcome = store
+            .restore(&auth_service(Err(AppError::new(ErrorType::AuthError, "登录已失效"))), Utc::now())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, SessionRestoreOutcome::Failed { reason: RestoreFailureReason::Revoked }));
+        assert!(stored_token(&connection).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_session_is_discarded() {
+        let (store, connection) = create_store();
+        store.save(&login_result(), Utc::now() + Duration::hours(8)).unwrap();
+
+        // token 自身的过期时间同样生效
+        let outcome = store.restore(&auth_service(Ok(())), Utc::now() + Duration::hours(9)).await.unwrap();
+        assert!(matches!(outcome, SessionRestoreOutcome::Failed { reason: RestoreFailureReason::Expired }));
+        assert!(stored_token(&connection).is_none());
+
+        let outcome = store.restore(&auth_service(Ok(())), Utc::now()).await.unwrap();
+        assert!(matches!(outcome, SessionRestoreOutcome::Failed { reason: RestoreFailureReason::NoSession }));
+    }
+
+    #[tokio::test]
+    async fn test_offline_restore_within_grace_period() {
+        let (store, connection) = create_store();
+        store.save(&login_result(), Utc::now() + Duration::hours(8)).unwrap();
+
+        // 上次确认在宽限期内：离线恢复，标记为未确认
+        set_verified_at(&connection, Utc::now() - Duration::hours(OFFLINE_RESTORE_GRACE_HOURS - 1));
+        match store.restore(&auth_service(offline()), Utc::now()).await.unwrap() {
+            SessionRestoreOutcome::Restored { verified, .. } => assert!(!verified),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        // 超出宽限期：不恢复，但保留 token 供联网后重试
+        set_verified_at(&connection, Utc::now() - Duration::hours(OFFLINE_RESTORE_GRACE_HOURS + 1));
+        let outcome = store.restore(&auth_service(offline()), Utc::now()).await.unwrap();
+        assert!(matches!(
+            outcome,
+            SessionRestoreOutcome::Failed { reason: RestoreFailureReason::OfflineUnverifiable }
+        ));
+        assert!(stored_token(&connection).is_some());
+
+        // 联网后确认成功，重新开始计算宽限期
+        assert!(matches!(
+            store.restore(&auth_service(Ok(())), Utc::now()).await.unwrap(),
+            SessionRestoreOutcome::Restored { verified: true, .. }
+        ));
+        assert!(matches!(
+            store.restore(&auth_service(offline()), Utc::now()).await.unwrap(),
+            SessionRestoreOutcome::Restored { verified: false, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_logout_wipes_token_and_wrong_key_is_corrupted() {
+        let (store, connection) = create_store();
+        store.save(&login_result(), Utc::now() + Duration::hours(8)).unwrap();
+
+        // 钥匙串中的密钥变了（如换了系统账户），无法解密
+        let other_key = SessionStore::new(Arc::new(MemoryKeyStore([9u8; 32]))).with_connection(connection.clone());
+        let outcome = other_key.restore(&auth_service(Ok(())), Utc::now()).await.unwrap();
+        assert!(matches!(outcome, SessionRestoreOutcome::Failed { reason: RestoreFailureReason::Corrupted }));
+
+        store.save(&login_result(), Utc::now() + Duration::hours(8)).unwrap();
+        store.clear("1").unwrap();
+        assert!(stored_token(&connection).is_none());
+        let outcome = store.restore(&auth_service(Ok(())), Utc::now()).await.unwrap();
+        assert!(matches!(outcome, SessionRestoreOutcome::Failed { reason: RestoreFailureReason::NoSession }));
+    }
+}
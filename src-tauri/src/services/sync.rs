@@ -0,0 +1,822 @@
+// 数据同步服务：按实体增量拉取服务器变更、推送本地待同步消息
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{
+    publish_data_changes, BaseDao, ConsultationDao, DoctorDao, IntakeFormDao, MessageDao, PatientDao, SyncStateDao,
+};
+use crate::database::query_optimizer::{query_cache_for, CACHE_TAG_MESSAGES, CACHE_TAG_PATIENTS};
+use crate::models::{Consultation, Doctor, IntakeForm, IntakeFormSubmission, Message, Patient, SyncStatus};
+use crate::utils::sanitize_message;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Instant;
+
+pub const SYNC_REPORT_EVENT: &str = "sync-report";
+
+// 单次推送的消息条数
+const PUSH_BATCH_SIZE: usize = 100;
+
+/// 参与增量同步的实体，拉取顺序需满足外键依赖
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncEntity {
+    Patients,
+    Consultations,
+    Messages,
+    IntakeForms,
+    Doctors,
+}
+
+impl SyncEntity {
+    pub const ALL: [SyncEntity; 5] = [
+        SyncEntity::Patients,
+        SyncEntity::Consultations,
+        SyncEntity::Messages,
+        SyncEntity::IntakeForms,
+        SyncEntity::Doctors,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncEntity::Patients => "patients",
+            SyncEntity::Consultations => "consultations",
+            SyncEntity::Messages => "messages",
+            SyncEntity::IntakeForms => "intake_forms",
+            SyncEntity::Doctors => "doctors",
+        }
+    }
+
+    // 每次拉取完整列表而不是增量，据此判断哪些记录已在服务器删除
+    pub fn is_snapshot(&self) -> bool {
+        matches!(self, SyncEntity::Doctors)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub pulled: usize,
+    pub pushed: usize,
+    pub conflicts: usize,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    // 本次拉取到的患者头像地址，同步完成后加入下载队列
+    #[serde(skip)]
+    pub avatar_urls: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteEnvelope<T> {
+    data: Option<T>,
+}
+
+// 服务器返回的增量数据，serverTime 作为下一次拉取的水位线
+#[derive(Debug, Deserialize)]
+struct ChangeSet<T> {
+    #[serde(default = "Vec::new")]
+    items: Vec<T>,
+    #[serde(rename = "serverTime")]
+    server_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct PushMessagesBody<'a> {
+    messages: &'a [Message],
+}
+
+#[derive(Debug, Deserialize)]
+struct PushMessagesResult {
+    #[serde(default)]
+    accepted: Vec<String>,
+}
+
+pub struct SyncService {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+    connection: DbConnection,
+    patient_dao: PatientDao,
+    consultation_dao: ConsultationDao,
+    message_dao: MessageDao,
+    sync_state_dao: SyncStateDao,
+}
+
+impl SyncService {
+    pub fn new(base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        Self::with_connection(get_database().get_connection(), base_url, token)
+    }
+
+    pub fn with_connection(connection: DbConnection, base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+            patient_dao: PatientDao::with_connection(connection.clone()),
+            consultation_dao: ConsultationDao::with_connection(connection.clone()),
+            message_dao: MessageDao::with_connection(connection.clone()),
+            sync_state_dao: SyncStateDao::with_connection(connection.clone()),
+            connection,
+        }
+    }
+
+    // 先拉取再推送：与本地待同步消息冲突的服务器数据在拉取阶段裁决
+    pub async fn sync(&self) -> Result<SyncReport> {
+        let started = Instant::now();
+        let mut report = SyncReport::default();
+
+        self.pull_patients(&mut report).await?;
+        self.pull_consultations(&mut report).await?;
+        self.pull_messages(&mut report).await?;
+        self.pull_intake_forms(&mut report).await?;
+        self.pull_doctors().await?;
+        self.push_messages(&mut report).await?;
+
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    pub fn get_watermark(&self, entity: SyncEntity) -> Result<Option<DateTime<Utc>>> {
+        self.sync_state_dao.get_watermark(entity.as_str()).map_err(dao_error)
+    }
+
+    async fn pull_patients(&self, report: &mut SyncReport) -> Result<()> {
+        let changes: ChangeSet<Patient> = self.fetch_changes(SyncEntity::Patients).await?;
+        let watermark = changes
+            .server_time
+            .or_else(|| changes.items.iter().map(|p| p.updated_at).max());
+
+        let now = Utc::now();
+        let mut rows = Vec::with_capacity(changes.items.len());
+        for remote in changes.items {
+            let local = self.patient_dao.find_by_id(&remote.id).map_err(dao_error)?;
+            let (row, conflict) = resolve_patient(local.as_ref(), remote, now);
+            if conflict {
+                report.conflicts += 1;
+            }
+            rows.push(row);
+        }
+
+        let data_changes = self.apply(SyncEntity::Patients, watermark, |conn| {
+            let mut data_changes = Vec::with_capacity(rows.len());
+            for patient in &rows {
+                data_changes.push(PatientDao::upsert_in(conn, patient)?);
+            }
+            Ok(data_changes)
+        })?;
+
+        report.pulled += rows.len();
+        report
+            .avatar_urls
+            .extend(rows.iter().filter_map(|patient| patient.avatar_url.clone()));
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_PATIENTS);
+        publish_data_changes(&data_changes);
+        Ok(())
+    }
+
+    async fn pull_consultations(&self, report: &mut SyncReport) -> Result<()> {
+        let changes: ChangeSet<Consultation> = self.fetch_changes(SyncEntity::Consultations).await?;
+        let watermark = changes
+            .server_time
+            .or_else(|| changes.items.iter().map(|c| c.updated_at).max());
+
+        // 问诊状态由服务器流转，直接以服务器为准
+        let data_changes = self.apply(SyncEntity::Consultations, watermark, |conn| {
+            let mut data_changes = Vec::with_capacity(changes.items.len());
+            for consultation in &changes.items {
+                data_changes.extend(ConsultationDao::upsert_in(conn, consultation)?);
+            }
+            Ok(data_changes)
+        })?;
+
+        report.pulled += changes.items.len();
+        if changes.items.iter().any(|c| c.status == "completed") {
+            query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_PATIENTS);
+        }
+        publish_data_changes(&data_changes);
+        Ok(())
+    }
+
+    async fn pull_messages(&self, report: &mut SyncReport) -> Result<()> {
+        let changes: ChangeSet<Message> = self.fetch_changes(SyncEntity::Messages).await?;
+        let watermark = changes
+            .server_time
+            .or_else(|| changes.items.iter().map(|m| m.timestamp).max());
+
+        let mut rows = Vec::with_capacity(changes.items.len());
+        for remote in changes.items {
+            let local = self.message_dao.find_by_id(&remote.id).map_err(dao_error)?;
+            let (row, conflict) = resolve_message(local.as_ref(), remote);
+            if conflict {
+                report.conflicts += 1;
+            }
+            rows.extend(row);
+        }
+        // 远端消息入库前清洗正文，被改写的保留原文
+        let raw_contents: Vec<(String, String)> = rows
+            .iter_mut()
+            .filter_map(|message| sanitize_message(message).map(|raw| (message.id.clone(), raw)))
+            .collect();
+
+        self.apply(SyncEntity::Messages, watermark, |conn| {
+            for message in &rows {
+                MessageDao::upsert_in(conn, message)?;
+            }
+            for (message_id, raw_content) in &raw_contents {
+                MessageDao::set_raw_content_in(conn, message_id, raw_content)?;
+            }
+            Ok(())
+        })?;
+
+        report.pulled += rows.len();
+        query_cache_for(&self.connection).invalidate_tag(CACHE_TAG_MESSAGES);
+        Ok(())
+    }
+
+    // 问卷只从服务器拉取，格式异常的问卷同样入库并标记
+    async fn pull_intake_forms(&self, report: &mut SyncReport) -> Result<()> {
+        let changes: ChangeSet<IntakeFormSubmission> = self.fetch_changes(SyncEntity::IntakeForms).await?;
+        let watermark = changes
+            .server_time
+            .or_else(|| changes.items.iter().map(|f| f.submitted_at).max());
+
+        let forms: Vec<IntakeForm> = changes.items.into_iter().map(IntakeForm::from_submission).collect();
+        self.apply(SyncEntity::IntakeForms, watermark, |conn| {
+            for form in &forms {
+                IntakeFormDao::upsert_in(conn, form)?;
+            }
+            Ok(())
+        })?;
+
+        report.pulled += forms.len();
+        Ok(())
+    }
+
+    // 医生名录为完整列表：列表中的医生更新并恢复，本次未出现的软删除；
+    // 服务器返回空列表时视为名录暂不可用，不移除任何医生
+    async fn pull_doctors(&self) -> Result<()> {
+        let changes: ChangeSet<Doctor> = self.fetch_changes(SyncEntity::Doctors).await?;
+        let seen_at = Utc::now();
+
+        let removed = self.apply(SyncEntity::Doctors, changes.server_time, |conn| {
+            for doctor in &changes.items {
+                DoctorDao::upsert_in(conn, doctor, seen_at)?;
+            }
+            if changes.items.is_empty() {
+                return Ok(0);
+            }
+            DoctorDao::remove_unseen_in(conn, seen_at)
+        })?;
+
+        // 名录每次都是完整列表，不计入拉取条数
+        if removed > 0 {
+            tracing::info!("Removed {} doctors no longer in the directory", removed);
+        }
+        Ok(())
+    }
+
+    // 只推送消息；医生的问诊备注只保存在本机，不参与同步
+    async fn push_messages(&self, report: &mut SyncReport) -> Result<()> {
+        let pending = self.message_dao.find_unsynced_messages().map_err(|e| anyhow!(e))?;
+
+        for chunk in pending.chunks(PUSH_BATCH_SIZE) {
+            let response = self
+                .client
+                .post(format!("{}/sync/messages", self.base_url))
+                .bearer_auth(&self.token)
+                .json(&PushMessagesBody { messages: chunk })
+                .send()
+                .await?
+                .error_for_status()?;
+
+            let envelope: RemoteEnvelope<PushMessagesResult> = response.json().await?;
+            let accepted = envelope.data.map(|r| r.accepted).unwrap_or_default();
+
+            // 未被服务器接受的消息保持 pending，下次同步重试
+            for message in chunk.iter().filter(|m| accepted.contains(&m.id)) {
+                self.message_dao
+                    .update_sync_status(&message.id, SyncStatus::Synced)
+                    .map_err(|e| anyhow!(e))?;
+                report.pushed += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_changes<T: DeserializeOwned>(&self, entity: SyncEntity) -> Result<ChangeSet<T>> {
+        let since = if entity.is_snapshot() {
+            None
+        } else {
+            self.get_watermark(entity)?
+        };
+
+        let mut request = self
+            .client
+            .get(format!("{}/sync/{}", self.base_url, entity.as_str()))
+            .bearer_auth(&self.token);
+        if let Some(since) = since {
+            request = request.query(&[("since", since.to_rfc3339())]);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        let envelope: RemoteEnvelope<ChangeSet<T>> = response.json().await?;
+        Ok(envelope.data.unwrap_or(ChangeSet {
+            items: Vec::new(),
+            server_time: None,
+        }))
+    }
+
+    // 数据与水位线在同一事务内提交，任一写入失败则整体回滚，水位线保持不变
+    fn apply<T, F>(&self, entity: SyncEntity, watermark: Option<DateTime<Utc>>, write: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T, Box<dyn std::error::Error>>,
+    {
+        let conn = self.connection.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let written = write(&tx).map_err(|e| anyhow!("同步{}失败: {}", entity.as_str(), e))?;
+        if let Some(watermark) = watermark {
+            SyncStateDao::set_watermark_in(&tx, entity.as_str(), watermark).map_err(dao_error)?;
+        }
+
+        tx.commit()?;
+        Ok(written)
+    }
+}
+
+// 患者基本信息以服务器为准；本地自上次同步后修改过的标签保留
+// 返回 (写入的数据, 是否与本地修改冲突)
+pub fn resolve_patient(local: Option<&Patient>, remote: Patient, now: DateTime<Utc>) -> (Patient, bool) {
+    let mut merged = remote;
+    merged.last_sync = Some(now);
+
+    let local = match local {
+        Some(local) if local.last_sync.is_none_or(|last_sync| local.updated_at > last_sync) => local,
+        _ => return (merged, false),
+    };
+
+    let conflict = local.name != merged.name
+        || local.age != merged.age
+        || local.gender != merged.gender
+        || local.phone != merged.phone
+        || local.id_card != merged.id_card
+        || local.avatar_url != merged.avatar_url;
+    merged.tags = local.tags.clone();
+
+    (merged, conflict)
+}
+
+// 同一消息以时间戳较新的一方为准；本地较新的待同步消息保留，随后推送到服务器
+// 返回 (需要写入的数据, 是否与本地待同步消息冲突)
+pub fn resolve_message(local: Option<&Message>, remote: Message) -> (Option<Message>, bool) {
+    let mut synced = remote;
+    synced.sync_status = SyncStatus::Synced;
+
+    match local {
+        Some(local) if matches!(local.sync_status, SyncStatus::Pending) => {
+            if local.timestamp > synced.timestamp {
+                (None, true)
+            } else {
+                (Some(synced), true)
+            }
+        }
+        _ => (Some(synced), false),
+    }
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> anyhow::Error {
+    anyhow!(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{ConsultationPriority, DoctorOnlineStatus, MessageType, ReadStatus, SenderType};
+    use chrono::Duration;
+    use mockito::Matcher;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn patient(id: &str, name: &str) -> Patient {
+        let at = Utc::now() - Duration::hours(1);
+        Patient {
+            id: id.to_string(),
+            name: name.to_string(),
+            age: Some(40),
+            gender: Some("male".to_string()),
+            phone: Some("13800138000".to_string()),
+            id_card: None,
+            tags: vec![],
+            avatar_url: None,
+            last_sync: None,
+            last_visit: None,
+            created_at: at,
+            updated_at: at,
+            version: 1,
+        }
+    }
+
+    fn consultation(id: &str, patient_id: &str) -> Consultation {
+        let at = Utc::now() - Duration::hours(1);
+        Consultation {
+            id: id.to_string(),
+            patient_id: patient_id.to_string(),
+            doctor_id: "d1".to_string(),
+            status: "active".to_string(),
+            consultation_type: "text".to_string(),
+            title: None,
+            description: None,
+            diagnosis: None,
+            prescription: None,
+            created_at: at,
+            updated_at: at,
+            accepted_at: Some(at),
+            completed_at: None,
+            cancel_reason: None,
+            version: 1,
+            priority: ConsultationPriority::Normal,
+            queued_at: None,
+        }
+    }
+
+    fn doctor(id: &str, name: &str, department: &str) -> Doctor {
+        Doctor {
+            id: id.to_string(),
+            name: name.to_string(),
+            department: Some(department.to_string()),
+            title: Some("主治医师".to_string()),
+            online_status: DoctorOnlineStatus::Online,
+            updated_at: Utc::now() - Duration::days(1),
+        }
+    }
+
+    fn message(id: &str, consultation_id: &str, content: &str, timestamp: DateTime<Utc>, sync_status: SyncStatus) -> Message {
+        Message {
+            id: id.to_string(),
+            consultation_id: consultation_id.to_string(),
+            sender_type: SenderType::Patient,
+            message_type: MessageType::Text,
+            content: Some(content.to_string()),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp,
+            sync_status,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        }
+    }
+
+    fn change_set<T: Serialize>(items: &[T], server_time: DateTime<Utc>) -> String {
+        serde_json::json!({
+            "success": true,
+            "data": { "items": items, "serverTime": server_time },
+        })
+        .to_string()
+    }
+
+    async fn mock_changes<T: Serialize>(server: &mut mockito::Server, entity: &str, items: &[T], server_time: DateTime<Utc>) -> mockito::Mock {
+        server
+            .mock("GET", format!("/sync/{}", entity).as_str())
+            .match_query(Matcher::Any)
+            .match_header("authorization", "Bearer token")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(change_set(items, server_time))
+            .create_async()
+            .await
+    }
+
+    async fn mock_push(server: &mut mockito::Server, accepted: &[&str]) -> mockito::Mock {
+        server
+            .mock("POST", "/sync/messages")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::json!({ "success": true, "data": { "accepted": accepted } }).to_string())
+            .create_async()
+            .await
+    }
+
+    fn seed(connection: &DbConnection, patients: &[Patient], consultations: &[Consultation], messages: &[Message]) {
+        let conn = connection.lock().unwrap();
+        for p in patients {
+            PatientDao::upsert_in(&conn, p).unwrap();
+        }
+        for c in consultations {
+            ConsultationDao::upsert_in(&conn, c).unwrap();
+        }
+        for m in messages {
+            MessageDao::upsert_in(&conn, m).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_pulls_pushes_and_advances_watermarks() {
+        let connection = create_test_connection();
+        let now = Utc::now();
+        seed(
+            &connection,
+            &[patient("p1", "张三")],
+            &[consultation("c1", "p1")],
+            &[message("local-1", "c1", "医生回复", now, SyncStatus::Pending)],
+        );
+
+        let mut server = mockito::Server::new_async().await;
+        let server_time = now + Duration::seconds(1);
+        mock_changes(&mut server, "patients", &[patient("p2", "李四")], server_time).await;
+        mock_changes(&mut server, "consultations", &[consultation("c2", "p2")], server_time).await;
+        mock_changes(
+            &mut server,
+            "messages",
+            &[message("remote-1", "c2", "你好", now, SyncStatus::Pending)],
+            server_time,
+        )
+        .await;
+        mock_changes(
+            &mut server,
+            "intake_forms",
+            &[IntakeFormSubmission {
+                consultation_id: "c2".to_string(),
+                schema_version: 1,
+                data: serde_json::json!({ "chiefComplaint": "发热" }),
+                submitted_at: now,
+            }],
+            server_time,
+        )
+        .await;
+        mock_changes(&mut server, "doctors", &[doctor("d2", "李医生", "内科")], server_time).await;
+        let push = mock_push(&mut server, &["local-1"]).await;
+
+        let service = SyncService::with_connection(connection.clone(), server.url(), "token");
+        let report = service.sync().await.unwrap();
+        push.assert_async().await;
+
+        assert_eq!(report.pulled, 4);
+        assert_eq!(report.pushed, 1);
+        assert_eq!(report.conflicts, 0);
+
+        let message_dao = MessageDao::with_connection(connection.clone());
+        let pulled = message_dao.find_by_id("remote-1").unwrap().unwrap();
+        assert!(matches!(pulled.sync_status, SyncStatus::Synced));
+        assert!(message_dao.find_unsynced_messages().unwrap().is_empty());
+        assert!(PatientDao::with_connection(connection.clone()).find_by_id("p2").unwrap().unwrap().last_sync.is_some());
+        let form = IntakeFormDao::with_connection(connection.clone()).find("c2").unwrap().unwrap();
+        assert!(form.sections.is_some());
+        assert_eq!(DoctorDao::with_connection(connection).search_doctors(None, None).unwrap().len(), 1);
+
+        for entity in SyncEntity::ALL {
+            assert_eq!(
+                service.get_watermark(entity).unwrap().map(|t| t.timestamp_millis()),
+                Some(server_time.timestamp_millis())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_sends_watermark_as_since() {
+        let connection = create_test_connection();
+        let watermark = Utc::now() - Duration::days(1);
+        SyncStateDao::with_connection(connection.clone())
+            .set_watermark("patients", watermark)
+            .unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let patients = server
+            .mock("GET", "/sync/patients")
+            .match_query(Matcher::UrlEncoded("since".into(), watermark.to_rfc3339()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"data":{"items":[]}}"#)
+            .create_async()
+            .await;
+        mock_changes::<Consultation>(&mut server, "consultations", &[], Utc::now()).await;
+        mock_changes::<Message>(&mut server, "messages", &[], Utc::now()).await;
+        mock_changes::<IntakeFormSubmission>(&mut server, "intake_forms", &[], Utc::now()).await;
+        mock_changes::<Doctor>(&mut server, "doctors", &[], Utc::now()).await;
+
+        let service = SyncService::with_connection(connection, server.url(), "token");
+        let report = service.sync().await.unwrap();
+        patients.assert_async().await;
+
+        // 无数据且服务器未返回时间时水位线不变
+        assert_eq!(report.pulled, 0);
+        assert_eq!(
+            service.get_watermark(SyncEntity::Patients).unwrap().map(|t| t.timestamp_millis()),
+            Some(watermark.timestamp_millis())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_resolved_per_entity_rules() {
+        let connection = create_test_connection();
+        let now = Utc::now();
+
+        // 本地在上次同步后修改过患者的手机号和标签
+        let mut local_patient = patient("p1", "张三");
+        local_patient.last_sync = Some(now - Duration::minutes(30));
+        local_patient.updated_at = now - Duration::minutes(5);
+        local_patient.phone = Some("13900000000".to_string());
+        local_patient.tags = vec!["高血压".to_string()];
+        seed(
+            &connection,
+            &[local_patient],
+            &[consultation("c1", "p1")],
+            &[
+                message("m-local-newer", "c1", "本地较新", now, SyncStatus::Pending),
+                message("m-remote-newer", "c1", "本地较旧", now - Duration::minutes(10), SyncStatus::Pending),
+            ],
+        );
+
+        let mut server = mockito::Server::new_async().await;
+        let mut remote_patient = patient("p1", "张三");
+        remote_patient.tags = vec!["糖尿病".to_string()];
+        mock_changes(&mut server, "patients", &[remote_patient], now).await;
+        mock_changes::<Consultation>(&mut server, "consultations", &[], now).await;
+        mock_changes(
+            &mut server,
+            "messages",
+            &[
+                message("m-local-newer", "c1", "服务器较旧", now - Duration::minutes(1), SyncStatus::Synced),
+                message("m-remote-newer", "c1", "服务器较新", now - Duration::minutes(1), SyncStatus::Synced),
+            ],
+            now,
+        )
+        .await;
+        mock_changes::<IntakeFormSubmission>(&mut server, "intake_forms", &[], now).await;
+        mock_changes::<Doctor>(&mut server, "doctors", &[], now).await;
+        mock_push(&mut server, &["m-local-newer"]).await;
+
+        let report = SyncService::with_connection(connection.clone(), server.url(), "token")
+            .sync()
+            .await
+            .unwrap();
+        assert_eq!(report.conflicts, 3);
+        assert_eq!(report.pushed, 1);
+
+        let stored = PatientDao::with_connection(connection.clone()).find_by_id("p1").unwrap().unwrap();
+        assert_eq!(stored.phone.as_deref(), Some("13800138000"));
+        assert_eq!(stored.tags, vec!["高血压"]);
+
+        let message_dao = MessageDao::with_connection(connection);
+        let kept = message_dao.find_by_id("m-local-newer").unwrap().unwrap();
+        assert_eq!(kept.content.as_deref(), Some("本地较新"));
+        assert!(matches!(kept.sync_status, SyncStatus::Synced));
+        let replaced = message_dao.find_by_id("m-remote-newer").unwrap().unwrap();
+        assert_eq!(replaced.content.as_deref(), Some("服务器较新"));
+        assert!(matches!(replaced.sync_status, SyncStatus::Synced));
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_rolls_back_and_keeps_watermark() {
+        let connection = create_test_connection();
+        let now = Utc::now();
+        seed(&connection, &[patient("p1", "张三")], &[consultation("c1", "p1")], &[]);
+
+        let mut server = mockito::Server::new_async().await;
+        mock_changes::<Patient>(&mut server, "patients", &[], now).await;
+        mock_changes::<Consultation>(&mut server, "consultations", &[], now).await;
+        // 第二条消息引用不存在的问诊，写入失败
+        mock_changes(
+            &mut server,
+            "messages",
+            &[
+                message("m1", "c1", "正常消息", now, SyncStatus::Synced),
+                message("m2", "missing", "孤立消息", now, SyncStatus::Synced),
+            ],
+            now,
+        )
+        .await;
+        let push = server.mock("POST", "/sync/messages").expect(0).create_async().await;
+
+        let service = SyncService::with_connection(connection.clone(), server.url(), "token");
+        assert!(service.sync().await.is_err());
+        push.assert_async().await;
+
+        assert!(MessageDao::with_connection(connection).find_by_id("m1").unwrap().is_none());
+        assert!(service.get_watermark(SyncEntity::Messages).unwrap().is_none());
+        assert!(service.get_watermark(SyncEntity::Patients).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_server_error_keeps_watermark() {
+        let connection = create_test_connection();
+        let mut server = mockito::Server::new_async().await;
+        mock_changes(&mut server, "patients", &[patient("p1", "张三")], Utc::now()).await;
+        server
+            .mock("GET", "/sync/consultations")
+            .match_query(Matcher::Any)
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let service = SyncService::with_connection(connection.clone(), server.url(), "token");
+        assert!(service.sync().await.is_err());
+
+        // 已提交的患者批次保留，失败的问诊批次下次从原水位线重试
+        assert!(PatientDao::with_connection(connection).find_by_id("p1").unwrap().is_some());
+        assert!(service.get_watermark(SyncEntity::Patients).unwrap().is_some());
+        assert!(service.get_watermark(SyncEntity::Consultations).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pulled_messages_sanitized_and_raw_content_kept() {
+        let connection = create_test_connection();
+        let now = Utc::now();
+        seed(&connection, &[patient("p1", "张三")], &[consultation("c1", "p1")], &[]);
+
+        let injected = "<script>alert(1)</script>头\u{200B}痛<b>三天</b>";
+        let mut server = mockito::Server::new_async().await;
+        mock_changes::<Patient>(&mut server, "patients", &[], now).await;
+        mock_changes::<Consultation>(&mut server, "consultations", &[], now).await;
+        mock_changes(
+            &mut server,
+            "messages",
+            &[
+                message("remote-1", "c1", injected, now, SyncStatus::Synced),
+                message("remote-2", "c1", "👨\u{200D}👩\u{200D}👧 好的", now, SyncStatus::Synced),
+            ],
+            now,
+        )
+        .await;
+        mock_changes::<IntakeFormSubmission>(&mut server, "intake_forms", &[], now).await;
+        mock_changes::<Doctor>(&mut server, "doctors", &[], now).await;
+
+        SyncService::with_connection(connection.clone(), server.url(), "token")
+            .sync()
+            .await
+            .unwrap();
+
+        let dao = MessageDao::with_connection(connection);
+        assert_eq!(dao.find_by_id("remote-1").unwrap().unwrap().content.as_deref(), Some("头痛三天"));
+        assert_eq!(dao.find_raw_content("remote-1").unwrap(), Some(Some(injected.to_string())));
+        assert_eq!(
+            dao.find_by_id("remote-2").unwrap().unwrap().content.as_deref(),
+            Some("👨\u{200D}👩\u{200D}👧 好的")
+        );
+        assert_eq!(dao.find_raw_content("remote-2").unwrap(), Some(None));
+        assert_eq!(dao.find_raw_content("missing").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_doctor_directory_upserts_full_list_and_removes_unseen() {
+        let connection = create_test_connection();
+        let dao = DoctorDao::with_connection(connection.clone());
+        {
+            let conn = connection.lock().unwrap();
+            let earlier = Utc::now() - Duration::hours(1);
+            for stale in [doctor("d1", "王医生", "内科"), doctor("d-left", "离职医生", "外科")] {
+                DoctorDao::upsert_in(&conn, &stale, earlier).unwrap();
+            }
+        }
+        let watermark = Utc::now() - Duration::days(1);
+        SyncStateDao::with_connection(connection.clone())
+            .set_watermark("doctors", watermark)
+            .unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let now = Utc::now();
+        mock_changes::<Patient>(&mut server, "patients", &[], now).await;
+        mock_changes::<Consultation>(&mut server, "consultations", &[], now).await;
+        mock_changes::<Message>(&mut server, "messages", &[], now).await;
+        mock_changes::<IntakeFormSubmission>(&mut server, "intake_forms", &[], now).await;
+        let mut renamed = doctor("d1", "王主任", "内科");
+        renamed.title = Some("主任医师".to_string());
+        // 名录总是完整拉取，不带水位线
+        let doctors = server
+            .mock("GET", "/sync/doctors")
+            .match_query(Matcher::Missing)
+            .with_status(200)
+            .with_body(change_set(&[renamed, doctor("d2", "李医生", "儿科")], now))
+            .create_async()
+            .await;
+
+        let service = SyncService::with_connection(connection.clone(), server.url(), "token");
+        let report = service.sync().await.unwrap();
+        doctors.assert_async().await;
+        assert_eq!(report.pulled, 0);
+
+        let listed = dao.search_doctors(None, None).unwrap();
+        assert_eq!(listed.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["d2", "d1"]);
+        let updated = dao.find_by_id("d1").unwrap().unwrap();
+        assert_eq!((updated.name.as_str(), updated.title.as_deref()), ("王主任", Some("主任医师")));
+        assert!(dao.find_transfer_candidates("d2", None).unwrap().iter().all(|c| c.doctor.id != "d-left"));
+        assert_eq!(
+            service.get_watermark(SyncEntity::Doctors).unwrap().map(|t| t.timestamp_millis()),
+            Some(now.timestamp_millis())
+        );
+    }
+}
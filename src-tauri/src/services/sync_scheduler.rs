@@ -0,0 +1,594 @@
+// 后台定时同步：按间隔执行同步，离线时退避，WebSocket 重新连上后立即同步
+
+use crate::services::{effective_config, ConnectionStatus, SyncReport, SyncService, TokenRefreshService, WebSocketManager};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tokio::task::JoinHandle;
+
+pub const SYNC_PROGRESS_EVENT: &str = "sync-progress";
+pub const SYNC_SCHEDULE_FILE: &str = "sync_schedule.json";
+
+// WebSocket 状态没有变更通知，定期轮询
+const WEBSOCKET_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 执行一次同步，生产环境使用当前登录会话调用 `SyncService`
+#[async_trait]
+pub trait SyncRunner: Send + Sync {
+    // 未登录等无法同步的情况返回 Ok(None)
+    async fn run_sync(&self) -> Result<Option<SyncReport>>;
+}
+
+/// 判断服务器是否可达
+#[async_trait]
+pub trait ConnectivityProbe: Send + Sync {
+    async fn is_online(&self) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncScheduleConfig {
+    pub paused: bool,
+    #[serde(rename = "intervalMinutes")]
+    pub interval_minutes: u64,
+    // 离线退避的最长间隔
+    #[serde(rename = "maxBackoffMinutes")]
+    pub max_backoff_minutes: u64,
+}
+
+impl Default for SyncScheduleConfig {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            interval_minutes: 5,
+            max_backoff_minutes: 30,
+        }
+    }
+}
+
+impl SyncScheduleConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_minutes.max(1) * 60)
+    }
+
+    // 连续 n 次探测离线后按 interval * 2^n 退避，不超过 max_backoff
+    pub fn offline_delay(&self, failures: u32) -> Duration {
+        let max = Duration::from_secs(self.max_backoff_minutes.max(self.interval_minutes.max(1)) * 60);
+        let factor = 1u32.checked_shl(failures.min(16)).unwrap_or(u32::MAX);
+        self.interval().checked_mul(factor).unwrap_or(max).min(max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncTrigger {
+    Scheduled,
+    Reconnected,
+    Manual,
+    // 休眠唤醒后补同步
+    Resumed,
+    // 实时推送的序号出现缺口，补齐丢失的消息
+    GapFill,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "phase", rename_all = "lowercase")]
+pub enum SyncProgressEvent {
+    Started {
+        trigger: SyncTrigger,
+    },
+    Completed {
+        report: SyncReport,
+    },
+    Failed {
+        error: String,
+    },
+    Skipped {
+        reason: String,
+    },
+    Offline {
+        #[serde(rename = "retryInSeconds")]
+        retry_in_seconds: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackgroundSyncStatus {
+    pub running: bool,
+    pub paused: bool,
+    #[serde(rename = "inFlight")]
+    pub in_flight: bool,
+    pub online: bool,
+    #[serde(rename = "lastSyncAt")]
+    pub last_sync_at: Option<DateTime<Utc>>,
+    #[serde(rename = "lastReport")]
+    pub last_report: Option<SyncReport>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    pub config: SyncScheduleConfig,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    config: SyncScheduleConfig,
+    online: bool,
+    websocket_connected: bool,
+    pending_trigger: Option<SyncTrigger>,
+    last_sync_at: Option<DateTime<Utc>>,
+    last_report: Option<SyncReport>,
+    last_error: Option<String>,
+}
+
+struct SchedulerInner {
+    runner: Arc<dyn SyncRunner>,
+    probe: Arc<dyn ConnectivityProbe>,
+    state: std::sync::Mutex<SchedulerState>,
+    in_flight: AtomicBool,
+    wake: Notify,
+    event_sender: mpsc::UnboundedSender<SyncProgressEvent>,
+}
+
+pub struct SyncScheduler {
+    inner: Arc<SchedulerInner>,
+    task: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SyncScheduler {
+    pub fn new(
+        runner: Arc<dyn SyncRunner>,
+        probe: Arc<dyn ConnectivityProbe>,
+        config: SyncScheduleConfig,
+    ) -> (Self, mpsc::UnboundedReceiver<SyncProgressEvent>) {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+
+        let inner = SchedulerInner {
+            runner,
+            probe,
+            state: std::sync::Mutex::new(SchedulerState {
+                config,
+                online: true,
+                websocket_connected: false,
+                pending_trigger: None,
+                last_sync_at: None,
+                last_report: None,
+                last_error: None,
+            }),
+            in_flight: AtomicBool::new(false),
+            wake: Notify::new(),
+            event_sender,
+        };
+
+        let scheduler = Self {
+            inner: Arc::new(inner),
+            task: std::sync::Mutex::new(None),
+        };
+
+        (scheduler, event_receiver)
+    }
+
+    // 需在 tokio 运行时内调用
+    pub fn start(&self) {
+        let mut task = self.task.lock().unwrap();
+        if task.is_none() {
+            *task = Some(tokio::spawn(self.inner.clone().run_loop()));
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    // 立即同步一次（手动触发），正在同步时返回错误
+    pub async fn run_now(&self) -> Result<SyncReport, String> {
+        self.inner.run(SyncTrigger::Manual).await
+    }
+
+    // 唤醒调度循环执行一次同步（仍受暂停和离线检测约束）
+    pub fn trigger(&self, trigger: SyncTrigger) {
+        self.inner.state.lock().unwrap().pending_trigger = Some(trigger);
+        self.inner.wake.notify_one();
+    }
+
+    // WebSocket 从未连接变为已连接时立即同步
+    pub fn observe_websocket(&self, connected: bool) {
+        let reconnected = {
+            let mut state = self.inner.state.lock().unwrap();
+            let reconnected = connected && !state.websocket_connected;
+            state.websocket_connected = connected;
+            reconnected
+        };
+
+        if reconnected {
+            self.trigger(SyncTrigger::Reconnected);
+        }
+    }
+
+    pub fn pause(&self) -> SyncScheduleConfig {
+        self.set_paused(true)
+    }
+
+    pub fn resume(&self) -> SyncScheduleConfig {
+        self.set_paused(false)
+    }
+
+    pub fn config(&self) -> SyncScheduleConfig {
+        self.inner.state.lock().unwrap().config.clone()
+    }
+
+    pub fn status(&self) -> BackgroundSyncStatus {
+        let state = self.inner.state.lock().unwrap();
+        BackgroundSyncStatus {
+            running: self.task.lock().unwrap().is_some(),
+            paused: state.config.paused,
+            in_flight: self.inner.in_flight.load(Ordering::SeqCst),
+            online: state.online,
+            last_sync_at: state.last_sync_at,
+            last_report: state.last_report.clone(),
+            last_error: state.last_error.clone(),
+            config: state.config.clone(),
+        }
+    }
+
+    fn set_paused(&self, paused: bool) -> SyncScheduleConfig {
+        let config = {
+            let mut state = self.inner.state.lock().unwrap();
+            state.config.paused = paused;
+            state.config.clone()
+        };
+        // 唤醒循环按新的配置重新计时
+        self.inner.wake.notify_one();
+        config
+    }
+}
+
+impl Drop for SyncScheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl SchedulerInner {
+    async fn run_loop(self: Arc<Self>) {
+        let mut offline_failures = 0u32;
+
+        loop {
+            let (paused, delay) = {
+                let state = self.state.lock().unwrap();
+                let delay = if offline_failures > 0 {
+                    state.config.offline_delay(offline_failures)
+                } else {
+                    state.config.interval()
+                };
+                (state.config.paused, delay)
+            };
+
+            // 暂停期间只等待唤醒
+            let woken = tokio::select! {
+                _ = tokio::time::sleep(delay), if !paused => false,
+                _ = self.wake.notified() => true,
+            };
+            let trigger = if woken {
+                self.take_pending_trigger()
+            } else {
+                Some(SyncTrigger::Scheduled)
+            };
+
+            let Some(trigger) = trigger else {
+                continue;
+            };
+            if self.state.lock().unwrap().config.paused {
+                continue;
+            }
+
+            if !self.probe.is_online().await {
+                offline_failures = offline_failures.saturating_add(1);
+                let retry_in = {
+                    let mut state = self.state.lock().unwrap();
+                    state.online = false;
+                    state.config.offline_delay(offline_failures)
+                };
+                let _ = self.event_sender.send(SyncProgressEvent::Offline {
+                    retry_in_seconds: retry_in.as_secs(),
+                });
+                continue;
+            }
+
+            offline_failures = 0;
+            self.state.lock().unwrap().online = true;
+
+            if let Err(e) = self.run(trigger).await {
+                tracing::warn!("Background sync did not complete: {}", e);
+            }
+        }
+    }
+
+    // 仅调整暂停状态的唤醒没有待执行的触发
+    fn take_pending_trigger(&self) -> Option<SyncTrigger> {
+        self.state.lock().unwrap().pending_trigger.take()
+    }
+
+    async fn run(&self, trigger: SyncTrigger) -> Result<SyncReport, String> {
+        if self.in_flight.swap(true, Ordering::SeqCst) {
+            return Err("同步正在进行中".to_string());
+        }
+
+        let _ = self.event_sender.send(SyncProgressEvent::Started { trigger });
+        let result = self.runner.run_sync().await;
+        self.in_flight.store(false, Ordering::SeqCst);
+
+        let mut state = self.state.lock().unwrap();
+        match result {
+            Ok(Some(report)) => {
+                state.last_sync_at = Some(Utc::now());
+                state.last_report = Some(report.clone());
+                state.last_error = None;
+                let _ = self.event_sender.send(SyncProgressEvent::Completed { report: report.clone() });
+                Ok(report)
+            }
+            Ok(None) => {
+                let reason = "请先登录".to_string();
+                let _ = self.event_sender.send(SyncProgressEvent::Skipped { reason: reason.clone() });
+                Err(reason)
+            }
+            Err(e) => {
+                let error = format!("数据同步失败: {}", e);
+                state.last_error = Some(error.clone());
+                let _ = self.event_sender.send(SyncProgressEvent::Failed { error: error.clone() });
+                Err(error)
+            }
+        }
+    }
+}
+
+/// 使用当前登录会话的 token 同步，服务器地址每次按已保存的配置读取
+pub struct SessionSyncRunner {
+    token_refresh: Arc<Mutex<TokenRefreshService>>,
+}
+
+impl SessionSyncRunner {
+    pub fn new(token_refresh: Arc<Mutex<TokenRefreshService>>) -> Self {
+        Self { token_refresh }
+    }
+}
+
+#[async_trait]
+impl SyncRunner for SessionSyncRunner {
+    async fn run_sync(&self) -> Result<Option<SyncReport>> {
+        let token = match self.token_refresh.lock().await.current_token().await {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        let report = SyncService::new(effective_config().api_base_url, token).sync().await?;
+        Ok(Some(report))
+    }
+}
+
+/// WebSocket 已连接即视为在线，否则用 HEAD 请求探测接口是否可达
+pub struct NetworkProbe {
+    websocket: Arc<Mutex<WebSocketManager>>,
+    client: reqwest::Client,
+}
+
+impl NetworkProbe {
+    pub fn new(websocket: Arc<Mutex<WebSocketManager>>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self { websocket, client }
+    }
+}
+
+#[async_trait]
+impl ConnectivityProbe for NetworkProbe {
+    async fn is_online(&self) -> bool {
+        if is_websocket_connected(&self.websocket).await {
+            return true;
+        }
+
+        // 任何 HTTP 响应（包括 404）都说明网络可达
+        self.client.head(effective_config().api_base_url).send().await.is_ok()
+    }
+}
+
+async fn is_websocket_connected(websocket: &Arc<Mutex<WebSocketManager>>) -> bool {
+    websocket
+        .lock()
+        .await
+        .get_all_connection_status()
+        .await
+        .values()
+        .any(|status| *status == ConnectionStatus::Connected)
+}
+
+// 轮询 WebSocket 连接状态并通知调度器
+pub async fn watch_websocket_status(scheduler: Arc<SyncScheduler>, websocket: Arc<Mutex<WebSocketManager>>) {
+    let mut interval = tokio::time::interval(WEBSOCKET_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        scheduler.observe_websocket(is_websocket_connected(&websocket).await);
+    }
+}
+
+pub fn load_schedule_config(path: &Path) -> SyncScheduleConfig {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_schedule_config(path: &Path, config: &SyncScheduleConfig) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("创建数据目录失败: {}", e))?;
+    }
+
+    let content = serde_json::to_string_pretty(config).map_err(|e| format!("序列化同步配置失败: {}", e))?;
+    std::fs::write(path, content).map_err(|e| format!("保存同步配置失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    struct CountingRunner {
+        calls: AtomicUsize,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl SyncRunner for CountingRunner {
+        async fn run_sync(&self) -> Result<Option<SyncReport>> {
+            tokio::time::sleep(self.delay).await;
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(SyncReport::default()))
+        }
+    }
+
+    struct FixedProbe {
+        online: AtomicBool,
+    }
+
+    #[async_trait]
+    impl ConnectivityProbe for FixedProbe {
+        async fn is_online(&self) -> bool {
+            self.online.load(Ordering::SeqCst)
+        }
+    }
+
+    fn scheduler(
+        delay_ms: u64,
+        online: bool,
+        config: SyncScheduleConfig,
+    ) -> (SyncScheduler, Arc<CountingRunner>, mpsc::UnboundedReceiver<SyncProgressEvent>) {
+        let runner = Arc::new(CountingRunner {
+            calls: AtomicUsize::new(0),
+            delay: Duration::from_millis(delay_ms),
+        });
+        let probe = Arc::new(FixedProbe {
+            online: AtomicBool::new(online),
+        });
+        let (scheduler, events) = SyncScheduler::new(runner.clone(), probe, config);
+        (scheduler, runner, events)
+    }
+
+    // 间隔足够长，测试中只有显式触发才会同步
+    fn long_interval() -> SyncScheduleConfig {
+        SyncScheduleConfig {
+            interval_minutes: 60,
+            ..SyncScheduleConfig::default()
+        }
+    }
+
+    async fn wait_for_calls(runner: &CountingRunner, expected: usize) {
+        for _ in 0..200 {
+            if runner.calls.load(Ordering::SeqCst) >= expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(runner.calls.load(Ordering::SeqCst), expected);
+    }
+
+    #[test]
+    fn test_offline_delay_backs_off_and_caps() {
+        let config = SyncScheduleConfig::default();
+        assert_eq!(config.interval(), Duration::from_secs(5 * 60));
+        assert_eq!(config.offline_delay(1), Duration::from_secs(10 * 60));
+        assert_eq!(config.offline_delay(2), Duration::from_secs(20 * 60));
+        assert_eq!(config.offline_delay(3), Duration::from_secs(30 * 60));
+        assert_eq!(config.offline_delay(40), Duration::from_secs(30 * 60));
+    }
+
+    #[tokio::test]
+    async fn test_run_now_skips_while_in_flight() {
+        let (scheduler, runner, _events) = scheduler(200, true, long_interval());
+
+        let (first, second) = tokio::join!(scheduler.run_now(), scheduler.run_now());
+        assert!(first.is_ok() != second.is_ok());
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 1);
+
+        assert!(scheduler.run_now().await.is_ok());
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 2);
+        assert!(scheduler.status().last_sync_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_websocket_reconnect_triggers_sync() {
+        let (scheduler, runner, mut events) = scheduler(0, true, long_interval());
+        scheduler.start();
+
+        scheduler.observe_websocket(true);
+        wait_for_calls(&runner, 1).await;
+        assert!(matches!(
+            events.recv().await,
+            Some(SyncProgressEvent::Started { trigger: SyncTrigger::Reconnected })
+        ));
+        assert!(matches!(events.recv().await, Some(SyncProgressEvent::Completed { .. })));
+
+        // 保持连接不重复触发
+        scheduler.observe_websocket(true);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 1);
+
+        scheduler.observe_websocket(false);
+        scheduler.observe_websocket(true);
+        wait_for_calls(&runner, 2).await;
+    }
+
+    #[tokio::test]
+    async fn test_offline_skips_sync() {
+        let (scheduler, runner, mut events) = scheduler(0, false, long_interval());
+        scheduler.start();
+
+        scheduler.trigger(SyncTrigger::Scheduled);
+        match tokio::time::timeout(Duration::from_secs(2), events.recv()).await.unwrap() {
+            Some(SyncProgressEvent::Offline { retry_in_seconds }) => assert_eq!(retry_in_seconds, 60 * 60),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 0);
+        assert!(!scheduler.status().online);
+    }
+
+    #[tokio::test]
+    async fn test_paused_scheduler_ignores_triggers() {
+        let (scheduler, runner, _events) = scheduler(0, true, long_interval());
+        scheduler.pause();
+        scheduler.start();
+
+        scheduler.observe_websocket(true);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 0);
+        assert!(scheduler.status().paused);
+
+        let config = scheduler.resume();
+        assert!(!config.paused);
+        scheduler.trigger(SyncTrigger::Scheduled);
+        wait_for_calls(&runner, 1).await;
+    }
+
+    #[test]
+    fn test_schedule_config_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SYNC_SCHEDULE_FILE);
+        assert_eq!(load_schedule_config(&path), SyncScheduleConfig::default());
+
+        let config = SyncScheduleConfig {
+            paused: true,
+            interval_minutes: 15,
+            max_backoff_minutes: 60,
+        };
+        save_schedule_config(&path, &config).unwrap();
+        assert_eq!(load_schedule_config(&path), config);
+    }
+}
@@ -0,0 +1,547 @@
+// Token 自动刷新服务
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::models::{AppError, ErrorType};
+use crate::services::{effective_config, AuthService, OsKeychainKeyStore, SessionKeyStore, SessionStore};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone)]
+pub struct RefreshedToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// 刷新 token 的抽象，生产环境由 `AuthService` 实现
+#[async_trait]
+pub trait TokenRefresher: Send + Sync {
+    async fn refresh(&self, current_token: &str) -> Result<RefreshedToken>;
+}
+
+#[async_trait]
+impl TokenRefresher for AuthService {
+    async fn refresh(&self, current_token: &str) -> Result<RefreshedToken> {
+        Ok(self.refresh_token(current_token).await?)
+    }
+}
+
+// 每次刷新按已保存的配置创建认证服务，修改服务器地址或认证方式后无需重启
+pub struct ConfiguredAuthRefresher;
+
+#[async_trait]
+impl TokenRefresher for ConfiguredAuthRefresher {
+    async fn refresh(&self, current_token: &str) -> Result<RefreshedToken> {
+        AuthService::new(&effective_config()).refresh(current_token).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenRefreshConfig {
+    // 在过期前多久发起刷新
+    pub refresh_before_expiry: Duration,
+    // 网络失败后的首次重试间隔
+    pub retry_initial_delay: std::time::Duration,
+    // 重试间隔上限
+    pub retry_max_delay: std::time::Duration,
+}
+
+impl Default for TokenRefreshConfig {
+    fn default() -> Self {
+        Self {
+            refresh_before_expiry: Duration::minutes(30),
+            retry_initial_delay: std::time::Duration::from_secs(5),
+            retry_max_delay: std::time::Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TokenRefreshEvent {
+    TokenRefreshed {
+        #[serde(rename = "expiresAt")]
+        expires_at: DateTime<Utc>,
+    },
+    SessionExpiring {
+        #[serde(rename = "expiresAt")]
+        expires_at: DateTime<Utc>,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatus {
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "lastRefresh")]
+    pub last_refresh: Option<DateTime<Utc>>,
+    #[serde(rename = "refreshInFlight")]
+    pub refresh_in_flight: bool,
+}
+
+#[derive(Debug, Clone)]
+struct SessionState {
+    user_id: String,
+    token: String,
+    expires_at: DateTime<Utc>,
+    last_refresh: Option<DateTime<Utc>>,
+    refresh_in_flight: bool,
+}
+
+pub struct TokenRefreshService {
+    refresher: Arc<dyn TokenRefresher>,
+    config: TokenRefreshConfig,
+    session: Arc<Mutex<Option<SessionState>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+    event_sender: mpsc::UnboundedSender<TokenRefreshEvent>,
+    connection: Option<DbConnection>,
+    key_store: Arc<dyn SessionKeyStore>,
+}
+
+impl TokenRefreshService {
+    pub fn new(
+        refresher: Arc<dyn TokenRefresher>,
+        config: TokenRefreshConfig,
+    ) -> (Self, mpsc::UnboundedReceiver<TokenRefreshEvent>) {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+
+        let service = Self {
+            refresher,
+            config,
+            session: Arc::new(Mutex::new(None)),
+            task: Mutex::new(None),
+            event_sender,
+            connection: None,
+            key_store: Arc::new(OsKeychainKeyStore),
+        };
+
+        (service, event_receiver)
+    }
+
+    // 指定持久化 token 使用的数据库连接（默认使用全局数据库）
+    pub fn with_connection(mut self, connection: DbConnection) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    // 指定加密持久化 token 的密钥来源（默认系统钥匙串）
+    pub fn with_key_store(mut self, key_store: Arc<dyn SessionKeyStore>) -> Self {
+        self.key_store = key_store;
+        self
+    }
+
+    // 登录成功后开始调度刷新
+    pub async fn start_session(&self, user_id: String, token: String, expires_at: DateTime<Utc>) {
+        self.stop_session().await;
+
+        *self.session.lock().await = Some(SessionState {
+            user_id,
+            token,
+            expires_at,
+            last_refresh: None,
+            refresh_in_flight: false,
+        });
+
+        *self.task.lock().await = Some(tokio::spawn(self.worker().run()));
+    }
+
+    // 休眠唤醒后调用：刷新任务的定时器在休眠期间停止计时，按当前时间重新判断，
+    // 已到刷新时间的立即刷新，然后重新调度；返回会话的过期时间，未登录时返回 None
+    pub async fn resume(&self) -> Result<Option<DateTime<Utc>>, String> {
+        if let Some(handle) = self.task.lock().await.take() {
+            handle.abort();
+        }
+        let (token, expires_at) = match self.session.lock().await.as_ref() {
+            Some(session) => (session.token.clone(), session.expires_at),
+            None => return Ok(None),
+        };
+
+        let worker = self.worker();
+        if expires_at - self.config.refresh_before_expiry <= Utc::now() {
+            worker.set_in_flight(true).await;
+            match self.refresher.refresh(&token).await {
+                Ok(refreshed) => {
+                    worker.apply_refresh(&refreshed).await;
+                }
+                Err(e) => {
+                    worker.set_in_flight(false).await;
+                    // 网络尚未恢复且 token 未过期时交给刷新任务继续重试
+                    if is_network_error(&e) && expires_at > Utc::now() {
+                        *self.task.lock().await = Some(tokio::spawn(worker.run()));
+                        return Err(format!("刷新 token 失败，稍后重试: {}", e));
+                    }
+                    let _ = self.event_sender.send(TokenRefreshEvent::SessionExpiring {
+                        expires_at,
+                        reason: e.to_string(),
+                    });
+                    return Err(e.to_string());
+                }
+            }
+        }
+
+        let expires_at = self.session.lock().await.as_ref().map(|s| s.expires_at);
+        *self.task.lock().await = Some(tokio::spawn(worker.run()));
+        Ok(expires_at)
+    }
+
+    fn worker(&self) -> RefreshWorker {
+        RefreshWorker {
+            refresher: self.refresher.clone(),
+            config: self.config.clone(),
+            session: self.session.clone(),
+            event_sender: self.event_sender.clone(),
+            connection: self.connection.clone(),
+            key_store: self.key_store.clone(),
+        }
+    }
+
+    // 登出时停止调度
+    pub async fn stop_session(&self) {
+        if let Some(handle) = self.task.lock().await.take() {
+            handle.abort();
+        }
+        *self.session.lock().await = None;
+    }
+
+    pub async fn get_status(&self) -> SessionStatus {
+        match self.session.lock().await.as_ref() {
+            Some(session) => SessionStatus {
+                expires_at: Some(session.expires_at),
+                last_refresh: session.last_refresh,
+                refresh_in_flight: session.refresh_in_flight,
+            },
+            None => SessionStatus {
+                expires_at: None,
+                last_refresh: None,
+                refresh_in_flight: false,
+            },
+        }
+    }
+
+    pub async fn current_token(&self) -> Option<String> {
+        self.session.lock().await.as_ref().map(|s| s.token.clone())
+    }
+
+    pub async fn current_user_id(&self) -> Option<String> {
+        self.session.lock().await.as_ref().map(|s| s.user_id.clone())
+    }
+}
+
+struct RefreshWorker {
+    refresher: Arc<dyn TokenRefresher>,
+    config: TokenRefreshConfig,
+    session: Arc<Mutex<Option<SessionState>>>,
+    event_sender: mpsc::UnboundedSender<TokenRefreshEvent>,
+    connection: Option<DbConnection>,
+    key_store: Arc<dyn SessionKeyStore>,
+}
+
+impl RefreshWorker {
+    async fn run(self) {
+        loop {
+            let (token, expires_at) = match self.session.lock().await.as_ref() {
+                Some(session) => (session.token.clone(), session.expires_at),
+                None => return,
+            };
+
+            // 等待到 exp - refresh_before_expiry
+            let refresh_at = expires_at - self.config.refresh_before_expiry;
+            if let Ok(wait) = (refresh_at - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+
+            self.set_in_flight(true).await;
+
+            match self.refresh_with_retry(&token, expires_at).await {
+                Ok(refreshed) => {
+                    if !self.apply_refresh(&refreshed).await {
+                        return;
+                    }
+                }
+                Err(reason) => {
+                    self.set_in_flight(false).await;
+                    let _ = self.event_sender.send(TokenRefreshEvent::SessionExpiring {
+                        expires_at,
+                        reason,
+                    });
+                    return;
+                }
+            }
+        }
+    }
+
+    // 网络失败时指数退避重试，直到无法在过期前完成
+    async fn refresh_with_retry(&self, token: &str, expires_at: DateTime<Utc>) -> Result<RefreshedToken, String> {
+        let mut delay = self.config.retry_initial_delay;
+
+        loop {
+            match self.refresher.refresh(token).await {
+                Ok(refreshed) => return Ok(refreshed),
+                Err(e) => {
+                    tracing::error!("Token refresh failed: {}", e);
+
+                    if !is_network_error(&e) {
+                        return Err(e.to_string());
+                    }
+
+                    let next_attempt = Utc::now() + Duration::from_std(delay).unwrap_or_else(|_| Duration::zero());
+                    if next_attempt >= expires_at {
+                        return Err(format!("会话即将过期，刷新失败: {}", e));
+                    }
+
+                    tokio::time::sleep(delay).await;
+                    delay = std::cmp::min(delay * 2, self.config.retry_max_delay);
+                }
+            }
+        }
+    }
+
+    // 保存刷新后的 token 并通知前端，会话已结束时返回 false
+    async fn apply_refresh(&self, refreshed: &RefreshedToken) -> bool {
+        let user_id = {
+            let mut guard = self.session.lock().await;
+            match guard.as_mut() {
+                Some(session) => {
+                    session.token = refreshed.token.clone();
+                    session.expires_at = refreshed.expires_at;
+                    session.last_refresh = Some(Utc::now());
+                    session.refresh_in_flight = false;
+                    session.user_id.clone()
+                }
+                None => return false,
+            }
+        };
+
+        if let Err(e) = self.persist_token(&user_id, refreshed) {
+            tracing::error!("Failed to persist refreshed token: {}", e);
+        }
+
+        let _ = self.event_sender.send(TokenRefreshEvent::TokenRefreshed {
+            expires_at: refreshed.expires_at,
+        });
+        true
+    }
+
+    async fn set_in_flight(&self, in_flight: bool) {
+        if let Some(session) = self.session.lock().await.as_mut() {
+            session.refresh_in_flight = in_flight;
+        }
+    }
+
+    fn persist_token(&self, user_id: &str, refreshed: &RefreshedToken) -> Result<(), Box<dyn std::error::Error>> {
+        let connection = match &self.connection {
+            Some(connection) => connection.clone(),
+            None => get_database().get_connection(),
+        };
+
+        SessionStore::new(self.key_store.clone())
+            .with_connection(connection)
+            .update_token(user_id, &refreshed.token, refreshed.expires_at)?;
+        Ok(())
+    }
+}
+
+fn is_network_error(error: &anyhow::Error) -> bool {
+    if let Some(app_error) = error.downcast_ref::<AppError>() {
+        return matches!(app_error.error_type, ErrorType::NetworkError);
+    }
+
+    error.downcast_ref::<reqwest::Error>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::session_restore::session_crypto;
+    use crate::services::session_restore::tests::MemoryKeyStore;
+    use rusqlite::{params, Connection};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FakeRefresher {
+        calls: AtomicUsize,
+        failures_before_success: usize,
+        error_type: ErrorType,
+        lifetime: Duration,
+    }
+
+    #[async_trait]
+    impl TokenRefresher for FakeRefresher {
+        async fn refresh(&self, current_token: &str) -> Result<RefreshedToken> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures_before_success {
+                return Err(AppError::new(self.error_type.clone(), "refresh failed").into());
+            }
+
+            Ok(RefreshedToken {
+                token: format!("{}-refreshed", current_token),
+                expires_at: Utc::now() + self.lifetime,
+            })
+        }
+    }
+
+    fn fake_refresher(failures_before_success: usize, error_type: ErrorType) -> Arc<FakeRefresher> {
+        Arc::new(FakeRefresher {
+            calls: AtomicUsize::new(0),
+            failures_before_success,
+            error_type,
+            lifetime: Duration::hours(8),
+        })
+    }
+
+    fn test_config() -> TokenRefreshConfig {
+        TokenRefreshConfig {
+            refresh_before_expiry: Duration::milliseconds(1900),
+            retry_initial_delay: std::time::Duration::from_millis(50),
+            retry_max_delay: std::time::Duration::from_millis(200),
+        }
+    }
+
+    fn create_user_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE users (
+                id TEXT PRIMARY KEY,
+                username TEXT UNIQUE NOT NULL,
+                encrypted_token TEXT,
+                last_login DATETIME,
+                session_expires DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        ).unwrap();
+        conn.execute("INSERT INTO users (id, username) VALUES ('1', 'doctor')", []).unwrap();
+        Arc::new(std::sync::Mutex::new(conn))
+    }
+
+    async fn next_event(receiver: &mut mpsc::UnboundedReceiver<TokenRefreshEvent>) -> TokenRefreshEvent {
+        tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("timed out waiting for event")
+            .expect("event channel closed")
+    }
+
+    #[tokio::test]
+    async fn test_refresh_scheduled_before_expiry() {
+        let connection = create_user_connection();
+        let refresher = fake_refresher(0, ErrorType::NetworkError);
+        let (service, mut events) = TokenRefreshService::new(refresher.clone(), test_config());
+        let key_store = Arc::new(MemoryKeyStore([3u8; 32]));
+        let service = service.with_connection(connection.clone()).with_key_store(key_store.clone());
+
+        service.start_session("1".to_string(), "token".to_string(), Utc::now() + Duration::seconds(2)).await;
+
+        match next_event(&mut events).await {
+            TokenRefreshEvent::TokenRefreshed { expires_at } => assert!(expires_at > Utc::now() + Duration::hours(7)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        let status = service.get_status().await;
+        assert!(status.last_refresh.is_some());
+        assert!(!status.refresh_in_flight);
+        assert_eq!(service.current_token().await.as_deref(), Some("token-refreshed"));
+
+        // 新 token 已加密写入 users 表
+        let conn = connection.lock().unwrap();
+        let stored: Option<String> = conn
+            .query_row("SELECT encrypted_token FROM users WHERE id = ?1", params!["1"], |row| row.get(0))
+            .unwrap();
+        let decrypted = session_crypto(key_store.as_ref()).unwrap().decrypt_string(&stored.unwrap()).unwrap();
+        assert_eq!(decrypted, "token-refreshed");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_retries_network_failures() {
+        let refresher = fake_refresher(2, ErrorType::NetworkError);
+        let (service, mut events) = TokenRefreshService::new(refresher.clone(), test_config());
+        let service = service.with_connection(create_user_connection());
+
+        service.start_session("1".to_string(), "token".to_string(), Utc::now() + Duration::seconds(2)).await;
+
+        assert!(matches!(next_event(&mut events).await, TokenRefreshEvent::TokenRefreshed { .. }));
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_session_expiring_when_refresh_rejected() {
+        let refresher = fake_refresher(usize::MAX, ErrorType::AuthError);
+        let (service, mut events) = TokenRefreshService::new(refresher.clone(), test_config());
+
+        service.start_session("1".to_string(), "token".to_string(), Utc::now() + Duration::seconds(2)).await;
+
+        assert!(matches!(next_event(&mut events).await, TokenRefreshEvent::SessionExpiring { .. }));
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 1);
+        assert!(!service.get_status().await.refresh_in_flight);
+    }
+
+    #[tokio::test]
+    async fn test_session_expiring_when_retries_exhausted() {
+        let refresher = fake_refresher(usize::MAX, ErrorType::NetworkError);
+        let (service, mut events) = TokenRefreshService::new(refresher.clone(), test_config());
+
+        service.start_session("1".to_string(), "token".to_string(), Utc::now() + Duration::seconds(2)).await;
+
+        assert!(matches!(next_event(&mut events).await, TokenRefreshEvent::SessionExpiring { .. }));
+        assert!(refresher.calls.load(Ordering::SeqCst) > 1);
+    }
+
+    // 模拟休眠：刷新任务没有按时运行，唤醒时 token 已到刷新时间
+    async fn set_stale_session(service: &TokenRefreshService, expires_at: DateTime<Utc>) {
+        *service.session.lock().await = Some(SessionState {
+            user_id: "1".to_string(),
+            token: "token".to_string(),
+            expires_at,
+            last_refresh: None,
+            refresh_in_flight: false,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_resume_refreshes_overdue_token() {
+        let refresher = fake_refresher(0, ErrorType::NetworkError);
+        let (service, mut events) = TokenRefreshService::new(refresher.clone(), TokenRefreshConfig::default());
+        let service = service.with_connection(create_user_connection());
+        set_stale_session(&service, Utc::now() + Duration::minutes(5)).await;
+
+        let expires_at = service.resume().await.unwrap().unwrap();
+        assert!(expires_at > Utc::now() + Duration::hours(7));
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(service.current_token().await.as_deref(), Some("token-refreshed"));
+        assert!(matches!(next_event(&mut events).await, TokenRefreshEvent::TokenRefreshed { .. }));
+
+        // 未到刷新时间时只重新调度，不发起请求
+        assert!(service.resume().await.unwrap().is_some());
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 1);
+        service.stop_session().await;
+        assert_eq!(service.resume().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_resume_reports_expired_session() {
+        let refresher = fake_refresher(usize::MAX, ErrorType::NetworkError);
+        let (service, mut events) = TokenRefreshService::new(refresher.clone(), TokenRefreshConfig::default());
+        set_stale_session(&service, Utc::now() - Duration::minutes(5)).await;
+
+        assert!(service.resume().await.is_err());
+        assert!(matches!(next_event(&mut events).await, TokenRefreshEvent::SessionExpiring { .. }));
+        assert!(!service.get_status().await.refresh_in_flight);
+    }
+
+    #[tokio::test]
+    async fn test_stop_session_clears_status() {
+        let (service, _events) = TokenRefreshService::new(fake_refresher(0, ErrorType::NetworkError), TokenRefreshConfig::default());
+
+        service.start_session("1".to_string(), "token".to_string(), Utc::now() + Duration::hours(8)).await;
+        assert!(service.get_status().await.expires_at.is_some());
+
+        service.stop_session().await;
+        let status = service.get_status().await;
+        assert!(status.expires_at.is_none());
+        assert!(!status.refresh_in_flight);
+    }
+}
@@ -0,0 +1,418 @@
+// 问诊记录导出为可打印的 HTML / Markdown，用于纸质病历归档
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, ConsultationDao, FileCacheDao, MessageDao, PatientDao};
+use crate::models::{message_preview_text, Consultation, Message, MessageType, SenderType};
+use crate::utils::{format_local, MaskingPolicy};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    Html,
+    Markdown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptExportResult {
+    pub path: String,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+}
+
+// 渲染前的一行会话记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptLine {
+    pub sender: String,
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub consultation: Consultation,
+    pub patient_name: String,
+    pub doctor_name: String,
+    pub lines: Vec<TranscriptLine>,
+    pub exported_at: DateTime<Utc>,
+}
+
+pub struct TranscriptService {
+    connection: DbConnection,
+}
+
+impl TranscriptService {
+    pub fn new() -> Self {
+        Self {
+            connection: get_database().get_connection(),
+        }
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 按时间正序整理问诊的全部消息，文件消息解析为本地缓存路径；患者姓名按导出人的权限脱敏。
+    // 医生的问诊备注（consultation_notes）是私人记录，不读取也不导出
+    pub fn collect(&self, consultation: Consultation, masking: &MaskingPolicy) -> Result<Transcript> {
+        let patient_name = PatientDao::with_connection(self.connection.clone())
+            .find_by_id(&consultation.patient_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .map(|patient| masking.patient_name(&patient.name))
+            .unwrap_or_else(|| consultation.patient_id.clone());
+        let doctor_name = self.doctor_name(&consultation.doctor_id)?;
+
+        let messages = MessageDao::with_connection(self.connection.clone())
+            .find_all_by_consultation_id(&consultation.id)
+            .map_err(|e| anyhow!(e))?;
+        let file_cache_dao = FileCacheDao::with_connection(self.connection.clone());
+
+        let mut lines = Vec::with_capacity(messages.len());
+        for message in &messages {
+            let sender = match message.sender_type {
+                SenderType::Patient => patient_name.clone(),
+                SenderType::Doctor => doctor_name.clone(),
+                SenderType::System => "系统".to_string(),
+            };
+            lines.push(TranscriptLine {
+                sender,
+                timestamp: message.timestamp,
+                text: message_text(message, &file_cache_dao)?,
+            });
+        }
+
+        Ok(Transcript {
+            consultation,
+            patient_name,
+            doctor_name,
+            lines,
+            exported_at: Utc::now(),
+        })
+    }
+
+    pub fn export(
+        &self,
+        consultation_id: &str,
+        format: TranscriptFormat,
+        output_path: &Path,
+        masking: &MaskingPolicy,
+    ) -> Result<TranscriptExportResult> {
+        let consultation = ConsultationDao::with_connection(self.connection.clone())
+            .find_by_id(consultation_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .ok_or_else(|| anyhow!("问诊不存在"))?;
+        let transcript = self.collect(consultation, masking)?;
+
+        let content = match format {
+            TranscriptFormat::Html => render_html(&transcript),
+            TranscriptFormat::Markdown => render_markdown(&transcript),
+        };
+        if let Some(parent) = output_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(output_path, content)?;
+
+        Ok(TranscriptExportResult {
+            path: output_path.to_string_lossy().to_string(),
+            message_count: transcript.lines.len(),
+        })
+    }
+
+    // 本机登录过的医生显示用户名，否则显示医生 ID
+    fn doctor_name(&self, doctor_id: &str) -> Result<String> {
+        let conn = self.connection.lock().unwrap();
+        let username: Option<String> = conn
+            .query_row("SELECT username FROM users WHERE id = ?1", params![doctor_id], |row| row.get(0))
+            .optional()?;
+        Ok(username.unwrap_or_else(|| doctor_id.to_string()))
+    }
+}
+
+impl Default for TranscriptService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 文件类消息优先显示本地路径，未缓存时显示占位文字
+fn message_text(message: &Message, file_cache_dao: &FileCacheDao) -> Result<String> {
+    let label = match message.message_type {
+        MessageType::Image => "图片",
+        MessageType::Voice => "语音",
+        MessageType::File => "文件",
+        _ => return Ok(message_body(message)),
+    };
+
+    let local_path = match &message.file_path {
+        Some(file_path) if Path::new(file_path).is_file() => Some(PathBuf::from(file_path)),
+        Some(file_path) => file_cache_dao
+            .find_by_url(file_path)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .map(|cached| PathBuf::from(cached.local_path))
+            .filter(|path| path.is_file()),
+        None => None,
+    };
+
+    Ok(match local_path {
+        Some(path) => format!("[{}] {}", label, path.display()),
+        None => format!("[{}：未缓存到本地]", label),
+    })
+}
+
+// 导出时保留文本消息全文，不做摘要截断
+fn message_body(message: &Message) -> String {
+    match message.message_type {
+        MessageType::Text | MessageType::Template => message.content.clone().unwrap_or_default(),
+        _ => message_preview_text(&message.message_type, message.content.as_deref()),
+    }
+}
+
+fn header_fields(transcript: &Transcript) -> Vec<(&'static str, String)> {
+    let c = &transcript.consultation;
+    let mut fields = vec![
+        ("患者", transcript.patient_name.clone()),
+        ("医生", transcript.doctor_name.clone()),
+        ("问诊编号", c.id.clone()),
+        ("问诊时间", format_local(c.created_at)),
+        ("状态", c.status.clone()),
+    ];
+    if let Some(title) = &c.title {
+        fields.push(("主题", title.clone()));
+    }
+    fields.push(("诊断", c.diagnosis.clone().unwrap_or_else(|| "无".to_string())));
+    fields.push(("处方", c.prescription.clone().unwrap_or_else(|| "无".to_string())));
+    fields
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+pub fn render_html(transcript: &Transcript) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>问诊记录 - {}</title>\n", escape_html(&transcript.patient_name)));
+    html.push_str(
+        "<style>body{font-family:sans-serif;margin:2em;}table.header td{padding:2px 12px 2px 0;vertical-align:top;}\
+         .message{margin:8px 0;}.meta{color:#666;font-size:0.9em;}.text{white-space:pre-wrap;}</style>\n",
+    );
+    html.push_str("</head>\n<body>\n<h1>问诊记录</h1>\n<table class=\"header\">\n");
+    for (label, value) in header_fields(transcript) {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"text\">{}</td></tr>\n",
+            label,
+            escape_html(&value)
+        ));
+    }
+    html.push_str("</table>\n<hr>\n");
+
+    for line in &transcript.lines {
+        html.push_str(&format!(
+            "<div class=\"message\"><div class=\"meta\">{} {}</div><div class=\"text\">{}</div></div>\n",
+            escape_html(&line.sender),
+            format_local(line.timestamp),
+            escape_html(&line.text)
+        ));
+    }
+
+    html.push_str(&format!(
+        "<hr>\n<p class=\"meta\">导出时间：{}</p>\n</body>\n</html>\n",
+        format_local(transcript.exported_at)
+    ));
+    html
+}
+
+pub fn render_markdown(transcript: &Transcript) -> String {
+    let mut markdown = String::from("# 问诊记录\n\n");
+    for (label, value) in header_fields(transcript) {
+        markdown.push_str(&format!("- **{}**：{}\n", label, markdown_text(&value)));
+    }
+    markdown.push_str("\n---\n\n");
+
+    for line in &transcript.lines {
+        markdown.push_str(&format!(
+            "**{}** {}\n\n{}\n\n",
+            markdown_text(&line.sender),
+            format_local(line.timestamp),
+            markdown_text(&line.text)
+        ));
+    }
+
+    markdown.push_str(&format!("---\n\n导出时间：{}\n", format_local(transcript.exported_at)));
+    markdown
+}
+
+// Markdown 渲染器会直接输出内嵌 HTML，消息内容同样需要转义；保留换行
+fn markdown_text(text: &str) -> String {
+    escape_html(text)
+        .replace('*', "\\*")
+        .replace('_', "\\_")
+        .replace('`', "\\`")
+        .replace('\n', "  \n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dao::ConsultationNoteDao;
+    use crate::database::migrations::MigrationManager;
+    use crate::models::{ReadStatus, SyncStatus};
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    fn create_test_connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status, consultation_type, diagnosis, prescription)
+                 VALUES ('c1', 'p1', 'd1', 'completed', 'text', '上呼吸道感染', '布洛芬 <0.2g> 每日三次');",
+        )
+        .unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn seed_messages(connection: &DbConnection) {
+        let now = Utc::now();
+        let message = |id: &str, offset: i64, sender_type: SenderType, message_type: MessageType, content: Option<&str>, file_path: Option<&str>| Message {
+            id: id.to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type,
+            message_type,
+            content: content.map(str::to_string),
+            file_path: file_path.map(str::to_string),
+            file_size: None,
+            mime_type: None,
+            timestamp: now + chrono::Duration::seconds(offset),
+            sync_status: SyncStatus::Synced,
+            read_status: ReadStatus::Read,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        };
+        MessageDao::with_connection(connection.clone())
+            .bulk_insert(&[
+                message("m2", 2, SenderType::Doctor, MessageType::Text, Some("多喝水 & 注意休息"), None),
+                message("m1", 1, SenderType::Patient, MessageType::Text, Some("<script>alert('x')</script>"), None),
+                message("m3", 3, SenderType::Patient, MessageType::Image, None, Some("https://cdn.example.com/a.png")),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_html_transcript_escapes_message_content() {
+        let connection = create_test_connection();
+        seed_messages(&connection);
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("transcript.html");
+
+        let result = TranscriptService::with_connection(connection)
+            .export("c1", TranscriptFormat::Html, &output, &MaskingPolicy::NONE)
+            .unwrap();
+        assert_eq!(result.message_count, 3);
+
+        let html = std::fs::read_to_string(&output).unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"));
+        assert!(html.contains("布洛芬 &lt;0.2g&gt; 每日三次"));
+        assert!(html.contains("上呼吸道感染"));
+        assert!(html.contains("[图片：未缓存到本地]"));
+
+        // 消息按时间正序排列
+        let first = html.find("alert").unwrap();
+        let second = html.find("多喝水 &amp; 注意休息").unwrap();
+        assert!(first < second);
+    }
+
+    #[test]
+    fn test_markdown_transcript_resolves_cached_files() {
+        let connection = create_test_connection();
+        seed_messages(&connection);
+        let dir = tempdir().unwrap();
+        let cached = dir.path().join("a.png");
+        std::fs::write(&cached, b"png").unwrap();
+        connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO file_cache (id, file_url, local_path) VALUES ('f1', 'https://cdn.example.com/a.png', ?1)",
+                params![cached.to_string_lossy()],
+            )
+            .unwrap();
+
+        let output = dir.path().join("out").join("transcript.md");
+        TranscriptService::with_connection(connection)
+            .export("c1", TranscriptFormat::Markdown, &output, &MaskingPolicy::NONE)
+            .unwrap();
+
+        let markdown = std::fs::read_to_string(&output).unwrap();
+        assert!(markdown.starts_with("# 问诊记录"));
+        assert!(markdown.contains("- **患者**：张三"));
+        assert!(markdown.contains(&format!("[图片] {}", cached.display())));
+        assert!(!markdown.contains("<script>"));
+    }
+
+    #[test]
+    fn test_masked_transcript_hides_patient_name() {
+        let connection = create_test_connection();
+        seed_messages(&connection);
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("transcript.md");
+
+        TranscriptService::with_connection(connection)
+            .export("c1", TranscriptFormat::Markdown, &output, &MaskingPolicy::ALL)
+            .unwrap();
+
+        let markdown = std::fs::read_to_string(&output).unwrap();
+        assert!(markdown.contains("- **患者**：张*"));
+        assert!(!markdown.contains("张三"));
+    }
+
+    #[test]
+    fn test_consultation_note_not_exported() {
+        let connection = create_test_connection();
+        seed_messages(&connection);
+        ConsultationNoteDao::with_connection(connection.clone())
+            .upsert("c1", "d1", "患者对青霉素过敏，下次复诊查肝功能")
+            .unwrap();
+        let dir = tempdir().unwrap();
+
+        for (format, name) in [(TranscriptFormat::Html, "transcript.html"), (TranscriptFormat::Markdown, "transcript.md")] {
+            let output = dir.path().join(name);
+            let result = TranscriptService::with_connection(connection.clone())
+                .export("c1", format, &output, &MaskingPolicy::NONE)
+                .unwrap();
+            assert_eq!(result.message_count, 3);
+
+            let content = std::fs::read_to_string(&output).unwrap();
+            assert!(!content.contains("青霉素"));
+            assert!(!content.contains("复诊查肝功能"));
+        }
+    }
+
+    #[test]
+    fn test_missing_consultation_fails() {
+        let dir = tempdir().unwrap();
+        let result = TranscriptService::with_connection(create_test_connection())
+            .export("missing", TranscriptFormat::Html, &dir.path().join("x.html"), &MaskingPolicy::NONE);
+        assert!(result.is_err());
+    }
+}
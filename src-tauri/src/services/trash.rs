@@ -0,0 +1,197 @@
+// 回收站：查看和恢复软删除的消息和病历，超过保留天数的由数据保留的每日维护彻底清除
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{BaseDao, MedicalRecordDao, MessageDao};
+use crate::models::{AppError, DataScope, ErrorType, MedicalRecord, Message, TrashEntityType, TrashItem};
+use crate::utils::CODE_TRASH_ITEM_NOT_FOUND;
+
+pub struct TrashService {
+    connection: DbConnection,
+}
+
+impl TrashService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    // 最近删除的在前
+    pub fn list(&self, entity_type: TrashEntityType, scope: &DataScope) -> Result<Vec<TrashItem>, AppError> {
+        match entity_type {
+            TrashEntityType::Message => self.message_dao().find_deleted_in_scope(scope),
+            TrashEntityType::MedicalRecord => self.record_dao().find_deleted_in_scope(scope),
+        }
+        .map_err(dao_error)
+    }
+
+    pub fn restore_message(&self, message_id: &str, scope: &DataScope) -> Result<Message, AppError> {
+        let message_dao = self.message_dao();
+        if !message_dao.restore_in_scope(message_id, scope).map_err(dao_error)? {
+            return Err(not_in_trash(TrashEntityType::Message, message_id));
+        }
+
+        message_dao
+            .find_by_id(message_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| not_in_trash(TrashEntityType::Message, message_id))
+    }
+
+    // 回收站中的病历附件一直保持固定，恢复后无需重新固定
+    pub fn restore_medical_record(&self, record_id: &str, scope: &DataScope) -> Result<MedicalRecord, AppError> {
+        let record_dao = self.record_dao();
+        if !record_dao.restore_in_scope(record_id, scope).map_err(dao_error)? {
+            return Err(not_in_trash(TrashEntityType::MedicalRecord, record_id));
+        }
+
+        record_dao
+            .find_by_id(record_id)
+            .map_err(dao_error)?
+            .ok_or_else(|| not_in_trash(TrashEntityType::MedicalRecord, record_id))
+    }
+
+    fn message_dao(&self) -> MessageDao {
+        MessageDao::with_connection(self.connection.clone())
+    }
+
+    fn record_dao(&self) -> MedicalRecordDao {
+        MedicalRecordDao::with_connection(self.connection.clone())
+    }
+}
+
+impl Default for TrashService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 不存在、未删除或属于其他医生时都按不在回收站处理
+fn not_in_trash(entity_type: TrashEntityType, id: &str) -> AppError {
+    let label = match entity_type {
+        TrashEntityType::Message => "消息",
+        TrashEntityType::MedicalRecord => "病历",
+    };
+    AppError::new(ErrorType::DataError, format!("回收站中没有该{}: {}", label, id))
+        .with_code(CODE_TRASH_ITEM_NOT_FOUND)
+        .with_retryable(false)
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MigrationManager;
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+
+    // 医生 d1、d2 各有一次问诊和一份病历，d1 的问诊有两条消息
+    fn setup() -> (DbConnection, TrashService) {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO patients (id, name) VALUES ('p1', '张三');
+             INSERT INTO consultations (id, patient_id, doctor_id, status) VALUES
+                 ('c1', 'p1', 'd1', 'active'),
+                 ('c2', 'p1', 'd2', 'active');
+             INSERT INTO messages (id, consultation_id, sender_type, content, message_type, timestamp, read_status) VALUES
+                 ('m1', 'c1', 'patient', '头痛三天', 'text', '2026-01-01 10:00:00', 'unread'),
+                 ('m2', 'c1', 'doctor', '请测量体温', 'text', '2026-01-01 10:05:00', 'read'),
+                 ('m3', 'c2', 'patient', '咳嗽', 'text', '2026-01-02 09:00:00', 'unread');
+             INSERT INTO medical_records (id, patient_id, doctor_id, consultation_id, record_type, title) VALUES
+                 ('r1', 'p1', 'd1', 'c1', 'diagnosis', '偏头痛'),
+                 ('r2', 'p1', 'd2', 'c2', 'diagnosis', '上呼吸道感染');",
+        )
+        .unwrap();
+        let connection = Arc::new(Mutex::new(conn));
+        let service = TrashService::with_connection(connection.clone());
+        (connection, service)
+    }
+
+    #[test]
+    fn test_deleted_rows_excluded_from_queries() {
+        let (connection, service) = setup();
+        let message_dao = MessageDao::with_connection(connection.clone());
+        let record_dao = MedicalRecordDao::with_connection(connection.clone());
+
+        message_dao.delete("m1").unwrap();
+        record_dao.delete("r1").unwrap();
+
+        assert!(message_dao.find_by_id("m1").unwrap().is_none());
+        let history = message_dao.find_by_consultation_id("c1", 1, 20).unwrap();
+        assert_eq!(history.total, 1);
+        assert_eq!(history.items[0].id, "m2");
+        assert_eq!(message_dao.get_unread_count("c1", "doctor").unwrap(), 0);
+        assert_eq!(message_dao.get_latest_message("c1").unwrap().unwrap().id, "m2");
+        assert!(message_dao.search_in_consultation("c1", "头痛", 10).unwrap().is_empty());
+        assert_eq!(message_dao.find_all().unwrap().len(), 2);
+
+        assert!(record_dao.find_by_id("r1").unwrap().is_none());
+        let records = record_dao.find_by_patient_id("p1").unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "r2");
+
+        // 行仍在表中，只是进入回收站
+        let remaining: i64 = connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM messages WHERE id = 'm1' AND deleted_at IS NOT NULL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 1);
+
+        let trash = service.list(TrashEntityType::Message, &DataScope::All).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].id, "m1");
+        assert_eq!(trash[0].patient_id.as_deref(), Some("p1"));
+        assert_eq!(trash[0].summary.as_deref(), Some("头痛三天"));
+        let trash = service.list(TrashEntityType::MedicalRecord, &DataScope::All).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].summary.as_deref(), Some("偏头痛"));
+    }
+
+    #[test]
+    fn test_restore_returns_row_to_queries() {
+        let (connection, service) = setup();
+        let message_dao = MessageDao::with_connection(connection.clone());
+        let record_dao = MedicalRecordDao::with_connection(connection.clone());
+        message_dao.delete("m1").unwrap();
+        record_dao.delete("r1").unwrap();
+
+        let message = service.restore_message("m1", &DataScope::All).unwrap();
+        assert_eq!(message.content.as_deref(), Some("头痛三天"));
+        assert_eq!(message_dao.find_by_consultation_id("c1", 1, 20).unwrap().total, 2);
+
+        let record = service.restore_medical_record("r1", &DataScope::All).unwrap();
+        assert_eq!(record.title, "偏头痛");
+        assert_eq!(record_dao.find_by_patient_id("p1").unwrap().len(), 2);
+
+        assert!(service.list(TrashEntityType::Message, &DataScope::All).unwrap().is_empty());
+        assert!(service.list(TrashEntityType::MedicalRecord, &DataScope::All).unwrap().is_empty());
+
+        // 已恢复的记录不能再次恢复
+        let err = service.restore_message("m1", &DataScope::All).unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_TRASH_ITEM_NOT_FOUND));
+    }
+
+    #[test]
+    fn test_trash_limited_to_doctor_scope() {
+        let (connection, service) = setup();
+        MessageDao::with_connection(connection.clone()).delete("m3").unwrap();
+        MedicalRecordDao::with_connection(connection.clone()).delete("r2").unwrap();
+        let d1 = DataScope::Doctor("d1".to_string());
+
+        assert!(service.list(TrashEntityType::Message, &d1).unwrap().is_empty());
+        assert!(service.list(TrashEntityType::MedicalRecord, &d1).unwrap().is_empty());
+        assert!(service.restore_message("m3", &d1).is_err());
+        assert!(service.restore_medical_record("r2", &d1).is_err());
+
+        let d2 = DataScope::Doctor("d2".to_string());
+        assert_eq!(service.list(TrashEntityType::Message, &d2).unwrap().len(), 1);
+        assert!(service.restore_medical_record("r2", &d2).is_ok());
+    }
+}
@@ -0,0 +1,370 @@
+// 应用更新服务：从内网更新服务器获取签名的版本清单，下载并校验安装包
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::services::audit_export::to_hex;
+use crate::utils::{AppError, ValidationService};
+
+pub const UPDATE_DOWNLOAD_PROGRESS_EVENT: &str = "update-download-progress";
+
+// 发布签名公钥（ed25519，base64），发布构建时可通过环境变量替换
+pub const UPDATE_PUBLIC_KEY: &str = match option_env!("TELEMEDICINE_UPDATE_PUBLIC_KEY") {
+    Some(key) => key,
+    None => "8A5SBl0KRxO0dXLv2POZrAP1LtKD0R730NVkwkROEVA=",
+};
+
+const MANIFEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+// 服务器返回的签名清单：manifest 为原始 JSON 文本，签名覆盖其全部字节
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpdateManifest {
+    pub version: String,
+    pub url: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(rename = "pubDate", default)]
+    pub pub_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateCheckResult {
+    #[serde(rename = "currentVersion")]
+    pub current_version: String,
+    #[serde(rename = "latestVersion")]
+    pub latest_version: String,
+    pub available: bool,
+    pub notes: Option<String>,
+    #[serde(rename = "pubDate")]
+    pub pub_date: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateDownloadProgress {
+    pub version: String,
+    pub loaded: u64,
+    pub total: u64,
+    pub percentage: f32,
+}
+
+pub struct UpdateService {
+    client: reqwest::Client,
+    manifest_url: String,
+    public_key: VerifyingKey,
+}
+
+impl UpdateService {
+    // 使用编译进程序的发布公钥
+    pub fn new(manifest_url: impl Into<String>) -> Result<Self> {
+        Ok(Self::with_public_key(manifest_url, parse_public_key(UPDATE_PUBLIC_KEY)?))
+    }
+
+    pub fn with_public_key(manifest_url: impl Into<String>, public_key: VerifyingKey) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(MANIFEST_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self {
+            client,
+            manifest_url: manifest_url.into(),
+            public_key,
+        }
+    }
+
+    // 获取并验证版本清单，签名不通过时不解析清单内容
+    pub async fn fetch_manifest(&self) -> Result<UpdateManifest> {
+        let signed: SignedManifest = self
+            .client
+            .get(&self.manifest_url)
+            .timeout(MANIFEST_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        self.verify_manifest(&signed)
+    }
+
+    pub fn verify_manifest(&self, signed: &SignedManifest) -> Result<UpdateManifest> {
+        let signature = STANDARD
+            .decode(signed.signature.trim())
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| AppError::update_signature_invalid("更新清单签名格式无效"))?;
+
+        self.public_key
+            .verify(signed.manifest.as_bytes(), &signature)
+            .map_err(|_| AppError::update_signature_invalid("更新清单签名校验失败，已拒绝本次更新"))?;
+
+        let manifest: UpdateManifest = serde_json::from_str(&signed.manifest)?;
+        Version::parse(&manifest.version)
+            .map_err(|e| anyhow!("更新清单版本号无效 {}: {}", manifest.version, e))?;
+        Ok(manifest)
+    }
+
+    pub async fn check(&self, current: &Version) -> Result<UpdateCheckResult> {
+        let manifest = self.fetch_manifest().await?;
+        Ok(update_check_result(current, &manifest))
+    }
+
+    // 下载安装包到指定目录，校验 SHA-256 后返回路径；已下载且校验一致的直接复用
+    pub async fn download(
+        &self,
+        manifest: &UpdateManifest,
+        dir: &Path,
+        mut on_progress: impl FnMut(UpdateDownloadProgress),
+    ) -> Result<PathBuf> {
+        tokio::fs::create_dir_all(dir).await?;
+        let target = dir.join(installer_file_name(manifest));
+        let expected = manifest.sha256.trim().to_lowercase();
+
+        if target.exists() && sha256_file(&target).await? == expected {
+            tracing::info!("Reusing downloaded installer: {}", target.display());
+            let size = tokio::fs::metadata(&target).await?.len();
+            on_progress(UpdateDownloadProgress {
+                version: manifest.version.clone(),
+                loaded: size,
+                total: size,
+                percentage: 100.0,
+            });
+            return Ok(target);
+        }
+
+        let partial = target.with_extension("part");
+        let mut response = self.client.get(&manifest.url).send().await?.error_for_status()?;
+        let total = response.content_length().or(manifest.size).unwrap_or(0);
+
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut hasher = Sha256::new();
+        let mut loaded = 0u64;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            hasher.update(&chunk);
+            loaded += chunk.len() as u64;
+            on_progress(UpdateDownloadProgress {
+                version: manifest.version.clone(),
+                loaded,
+                total,
+                percentage: if total > 0 {
+                    (loaded as f32 / total as f32 * 100.0).min(100.0)
+                } else {
+                    0.0
+                },
+            });
+        }
+        file.flush().await?;
+        drop(file);
+
+        let actual = to_hex(&hasher.finalize());
+        if actual != expected {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(AppError::update_checksum_mismatch(format!(
+                "安装包校验失败（已下载 {}），请重新下载",
+                ValidationService::format_file_size(loaded)
+            ))
+            .into());
+        }
+
+        tokio::fs::rename(&partial, &target).await?;
+        tracing::info!("Downloaded installer {} to {}", manifest.version, target.display());
+        Ok(target)
+    }
+}
+
+pub fn parse_public_key(encoded: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| anyhow!("更新公钥编码无效: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow!("更新公钥长度无效"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("更新公钥无效: {}", e))
+}
+
+// 只有清单版本严格高于当前版本时才提示更新
+pub fn update_check_result(current: &Version, manifest: &UpdateManifest) -> UpdateCheckResult {
+    let available = Version::parse(&manifest.version)
+        .map(|latest| latest > *current)
+        .unwrap_or(false);
+
+    UpdateCheckResult {
+        current_version: current.to_string(),
+        latest_version: manifest.version.clone(),
+        available,
+        notes: manifest.notes.clone(),
+        pub_date: manifest.pub_date.clone(),
+        size: manifest.size,
+    }
+}
+
+fn installer_file_name(manifest: &UpdateManifest) -> String {
+    let name = url::Url::parse(&manifest.url)
+        .ok()
+        .and_then(|url| url.path_segments().and_then(|mut segments| segments.next_back().map(str::to_string)))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "installer".to_string());
+    ValidationService::sanitize_filename(&format!("{}_{}", manifest.version, name))
+}
+
+async fn sha256_file(path: &Path) -> Result<String> {
+    let data = tokio::fs::read(path).await?;
+    Ok(to_hex(&Sha256::digest(&data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{CODE_UPDATE_CHECKSUM_MISMATCH, CODE_UPDATE_SIGNATURE_INVALID};
+    use ed25519_dalek::{Signer, SigningKey};
+    use tempfile::tempdir;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn manifest_json(url: &str, installer: &[u8]) -> String {
+        serde_json::json!({
+            "version": "1.2.0",
+            "url": url,
+            "sha256": to_hex(&Sha256::digest(installer)),
+            "size": installer.len(),
+            "notes": "修复问诊窗口崩溃",
+        })
+        .to_string()
+    }
+
+    fn signed_body(manifest: &str, key: &SigningKey) -> String {
+        serde_json::to_string(&SignedManifest {
+            manifest: manifest.to_string(),
+            signature: STANDARD.encode(key.sign(manifest.as_bytes()).to_bytes()),
+        })
+        .unwrap()
+    }
+
+    fn app_error(err: anyhow::Error) -> AppError {
+        AppError::from(err)
+    }
+
+    #[test]
+    fn test_compiled_public_key_is_valid() {
+        assert!(parse_public_key(UPDATE_PUBLIC_KEY).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_and_download_update() {
+        let installer = vec![0x5Au8; 300 * 1024];
+        let mut server = mockito::Server::new_async().await;
+        let installer_url = format!("{}/updates/telemedicine_1.2.0_x64.msi", server.url());
+        let body = signed_body(&manifest_json(&installer_url, &installer), &signing_key());
+        server
+            .mock("GET", "/updates/latest.json")
+            .with_status(200)
+            .with_body(&body)
+            .expect(2)
+            .create_async()
+            .await;
+        let download = server
+            .mock("GET", "/updates/telemedicine_1.2.0_x64.msi")
+            .with_status(200)
+            .with_body(&installer)
+            .create_async()
+            .await;
+
+        let service = UpdateService::with_public_key(
+            format!("{}/updates/latest.json", server.url()),
+            signing_key().verifying_key(),
+        );
+
+        let result = service.check(&Version::new(1, 1, 3)).await.unwrap();
+        assert!(result.available);
+        assert_eq!(result.latest_version, "1.2.0");
+        assert!(!service.check(&Version::new(1, 2, 0)).await.unwrap().available);
+
+        let dir = tempdir().unwrap();
+        let manifest: UpdateManifest = serde_json::from_str(&manifest_json(&installer_url, &installer)).unwrap();
+        let mut progress = Vec::new();
+        let path = service.download(&manifest, dir.path(), |p| progress.push(p)).await.unwrap();
+        download.assert_async().await;
+
+        assert_eq!(std::fs::read(&path).unwrap(), installer);
+        assert!(!path.with_extension("part").exists());
+        let last = progress.last().unwrap();
+        assert_eq!(last.loaded, installer.len() as u64);
+        assert_eq!(last.percentage, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_signature_mismatch_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let manifest = manifest_json("https://updates.example.com/setup.exe", b"installer");
+        // 用其他私钥签名，或签名后篡改清单内容
+        let forged = signed_body(&manifest, &SigningKey::from_bytes(&[9u8; 32]));
+        let mut tampered: SignedManifest = serde_json::from_str(&signed_body(&manifest, &signing_key())).unwrap();
+        tampered.manifest = tampered.manifest.replace("1.2.0", "9.9.9");
+
+        server
+            .mock("GET", "/forged.json")
+            .with_status(200)
+            .with_body(&forged)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/tampered.json")
+            .with_status(200)
+            .with_body(serde_json::to_string(&tampered).unwrap())
+            .create_async()
+            .await;
+        server.mock("GET", "/missing.json").with_status(503).create_async().await;
+
+        for path in ["/forged.json", "/tampered.json"] {
+            let service =
+                UpdateService::with_public_key(format!("{}{}", server.url(), path), signing_key().verifying_key());
+            let error = app_error(service.check(&Version::new(1, 0, 0)).await.unwrap_err());
+            assert_eq!(error.code.as_deref(), Some(CODE_UPDATE_SIGNATURE_INVALID), "{}", path);
+        }
+
+        // 网络错误与签名错误区分
+        let service =
+            UpdateService::with_public_key(format!("{}/missing.json", server.url()), signing_key().verifying_key());
+        let error = app_error(service.check(&Version::new(1, 0, 0)).await.unwrap_err());
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_NETWORK_ERROR));
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_removes_download() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/setup.exe")
+            .with_status(200)
+            .with_body("corrupted installer")
+            .create_async()
+            .await;
+
+        let url = format!("{}/setup.exe", server.url());
+        let manifest: UpdateManifest = serde_json::from_str(&manifest_json(&url, b"genuine installer")).unwrap();
+        let service = UpdateService::with_public_key(format!("{}/latest.json", server.url()), signing_key().verifying_key());
+
+        let dir = tempdir().unwrap();
+        let error = app_error(service.download(&manifest, dir.path(), |_| {}).await.unwrap_err());
+        assert_eq!(error.code.as_deref(), Some(CODE_UPDATE_CHECKSUM_MISMATCH));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -1,13 +1,85 @@
 // WebSocket 实时通信服务
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
 use futures_util::{SinkExt, StreamExt};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, RootCertStore, SignatureScheme};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex, RwLock};
-use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+use tokio_tungstenite::{
+    connect_async, connect_async_tls_with_config, tungstenite::Message as WsMessage, Connector, MaybeTlsStream,
+    WebSocketStream,
+};
+
+use crate::models::{AppError, ConsultationPriority, DoctorOnlineStatus, Message, MessageType, SenderType, SyncStatus, ReadStatus};
+use crate::services::audit_export::to_hex;
+use crate::services::event_replay::{BufferedEvent, EventReplayBuffer};
+use crate::utils::{sanitize_message, ValidationService};
+
+// 服务器证书与固定的指纹不一致
+pub const CERTIFICATE_PIN_MISMATCH: &str = "certificate pin mismatch";
+pub const WEBSOCKET_TLS_FILE: &str = "websocket_tls.json";
+
+// 超过该大小的消息压缩后发送，压缩后仍超过则拒绝，与服务器的帧大小上限一致
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 64 * 1024;
+const COMPRESSION_ENCODING: &str = "deflate";
+// 解压后的大小上限，防止压缩炸弹
+const MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+// 重建连接时轮询连接状态的间隔
+const RECONNECT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// 连接选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSocketOptions {
+    pub tls: Option<TlsConfig>,
+    pub compression_threshold: usize,
+}
+
+impl Default for WebSocketOptions {
+    fn default() -> Self {
+        Self {
+            tls: None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
 
-use crate::models::{Message, MessageType, SenderType, SyncStatus, ReadStatus};
+// 压缩消息的外层结构，payload 为 deflate 后的 JSON 再 base64 编码
+#[derive(Debug, Serialize, Deserialize)]
+struct CompressedEnvelope {
+    compressed: bool,
+    encoding: String,
+    payload: String,
+}
+
+// 医院私有部署的 wss 连接配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    // 额外信任的 CA 证书（PEM，可包含多个）
+    #[serde(default)]
+    pub custom_ca_pem: Option<String>,
+    // 服务器证书 DER 的 SHA-256 指纹（十六进制，可带冒号），命中任意一个即可
+    #[serde(default)]
+    pub pinned_sha256: Option<Vec<String>>,
+    // 跳过主机名校验，用于以 IP 访问的内网服务器
+    #[serde(default)]
+    pub accept_invalid_hostname: bool,
+}
 
 // WebSocket 连接状态
 #[derive(Debug, Clone, PartialEq)]
@@ -19,6 +91,16 @@ pub enum ConnectionStatus {
     Error(String),
 }
 
+impl ConnectionStatus {
+    // 断开或出错的连接不再复用
+    pub fn is_usable(&self) -> bool {
+        matches!(
+            self,
+            ConnectionStatus::Connected | ConnectionStatus::Connecting | ConnectionStatus::Reconnecting
+        )
+    }
+}
+
 // WebSocket 事件类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -27,11 +109,35 @@ pub enum WebSocketEvent {
     Message {
         consultation_id: String,
         message: Message,
+        // 客户端发出的消息附带幂等键，重发时不变，服务器据此去重
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<String>,
+        // 服务器信封中按问诊单调递增的序号，用于发现乱序和丢失的推送
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
     },
     #[serde(rename = "consultation_update")]
     ConsultationUpdate {
         consultation_id: String,
         status: String,
+        // 候诊优先级变化时附带，本地据此更新问诊记录
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        priority: Option<ConsultationPriority>,
+    },
+    #[serde(rename = "consultation_transferred")]
+    ConsultationTransferred {
+        consultation_id: String,
+        from_doctor_id: String,
+        to_doctor_id: String,
+        note: Option<String>,
+    },
+    // 患者提交或重新提交问诊问卷，data 为问卷原文
+    #[serde(rename = "intake_form")]
+    IntakeForm {
+        consultation_id: String,
+        schema_version: u32,
+        data: serde_json::Value,
+        submitted_at: chrono::DateTime<chrono::Utc>,
     },
     #[serde(rename = "typing")]
     Typing {
@@ -45,6 +151,31 @@ pub enum WebSocketEvent {
         message_id: String,
         read_by: String,
     },
+    // 患者一次性读到某个时间点为止的所有消息
+    #[serde(rename = "read_receipt_batch")]
+    ReadReceiptBatch {
+        consultation_id: String,
+        up_to_timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    // 医生置顶或取消置顶消息，患者端支持时同步显示
+    #[serde(rename = "message_pinned")]
+    MessagePinned {
+        consultation_id: String,
+        message_id: String,
+        pinned: bool,
+    },
+    // 服务器已收到客户端发出的消息
+    #[serde(rename = "message_ack")]
+    MessageAck {
+        message_id: String,
+        idempotency_key: String,
+    },
+    // 院内医生的在线状态变化，用于刷新转接候选的排序
+    #[serde(rename = "presence")]
+    Presence {
+        doctor_id: String,
+        status: DoctorOnlineStatus,
+    },
     #[serde(rename = "connection_ack")]
     ConnectionAck {
         user_id: String,
@@ -57,6 +188,34 @@ pub enum WebSocketEvent {
     },
 }
 
+impl WebSocketEvent {
+    // 事件所属的问诊，连接级事件返回 None
+    pub fn consultation_id(&self) -> Option<&str> {
+        match self {
+            WebSocketEvent::Message { consultation_id, .. }
+            | WebSocketEvent::ConsultationUpdate { consultation_id, .. }
+            | WebSocketEvent::ConsultationTransferred { consultation_id, .. }
+            | WebSocketEvent::IntakeForm { consultation_id, .. }
+            | WebSocketEvent::Typing { consultation_id, .. }
+            | WebSocketEvent::ReadReceipt { consultation_id, .. }
+            | WebSocketEvent::ReadReceiptBatch { consultation_id, .. }
+            | WebSocketEvent::MessagePinned { consultation_id, .. } => Some(consultation_id),
+            WebSocketEvent::MessageAck { .. }
+            | WebSocketEvent::Presence { .. }
+            | WebSocketEvent::ConnectionAck { .. }
+            | WebSocketEvent::Error { .. } => None,
+        }
+    }
+
+    // 服务器下发的问诊内序号，客户端自己构造的事件没有
+    pub fn sequence(&self) -> Option<u64> {
+        match self {
+            WebSocketEvent::Message { seq, .. } => *seq,
+            _ => None,
+        }
+    }
+}
+
 // 消息队列项
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueuedMessage {
@@ -67,6 +226,36 @@ pub struct QueuedMessage {
     pub file_path: Option<String>,
     pub retry_count: u32,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    // 来自发件箱的消息带幂等键
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+impl QueuedMessage {
+    pub fn to_event(&self) -> WebSocketEvent {
+        WebSocketEvent::Message {
+            consultation_id: self.consultation_id.clone(),
+            message: Message {
+                id: self.id.clone(),
+                consultation_id: self.consultation_id.clone(),
+                sender_type: SenderType::Doctor, // 假设医生端发送
+                message_type: self.message_type.clone(),
+                content: Some(self.content.clone()),
+                file_path: self.file_path.clone(),
+                file_size: None,
+                mime_type: None,
+                timestamp: self.created_at,
+                sync_status: SyncStatus::Pending,
+                read_status: ReadStatus::Unread,
+                template_id: None,
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
+            },
+            idempotency_key: self.idempotency_key.clone(),
+            seq: None,
+        }
+    }
 }
 
 // WebSocket 客户端
@@ -79,6 +268,12 @@ pub struct WebSocketClient {
     reconnect_attempts: Arc<Mutex<u32>>,
     max_reconnect_attempts: u32,
     reconnect_delay: std::time::Duration,
+    // 重连时沿用同一份 TLS 配置
+    tls_config: Option<TlsConfig>,
+    compression_threshold: usize,
+    // 每次主动重建连接时递增，旧连接的消息循环据此退出而不再自动重连
+    generation: Arc<AtomicU64>,
+    receive_task: Mutex<Option<tokio::task::AbortHandle>>,
 }
 
 impl WebSocketClient {
@@ -94,6 +289,10 @@ impl WebSocketClient {
             reconnect_attempts: Arc::new(Mutex::new(0)),
             max_reconnect_attempts: 5,
             reconnect_delay: std::time::Duration::from_secs(2),
+            tls_config: None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            generation: Arc::new(AtomicU64::new(0)),
+            receive_task: Mutex::new(None),
         };
 
         (client, event_receiver)
@@ -104,6 +303,16 @@ impl WebSocketClient {
         self.auth_token = Some(token);
     }
 
+    // 设置 TLS 配置（自定义 CA、证书固定）
+    pub fn set_tls_config(&mut self, tls_config: TlsConfig) {
+        self.tls_config = Some(tls_config);
+    }
+
+    // 设置消息压缩阈值（字节）
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.compression_threshold = threshold;
+    }
+
     // 获取连接状态
     pub async fn get_connection_status(&self) -> ConnectionStatus {
         self.connection_status.read().await.clone()
@@ -117,15 +326,8 @@ impl WebSocketClient {
     async fn connect_internal(&self) -> Result<()> {
         self.set_connection_status(ConnectionStatus::Connecting).await;
 
-        // 添加认证参数到 URL
-        let mut url_string = self.url.clone();
-        if let Some(token) = &self.auth_token {
-            let separator = if url_string.contains('?') { "&" } else { "?" };
-            url_string = format!("{}{}token={}", url_string, separator, token);
-        }
-
-        match connect_async(&url_string).await {
-            Ok((ws_stream, _)) => {
+        match self.open_stream().await {
+            Ok(ws_stream) => {
                 self.set_connection_status(ConnectionStatus::Connected).await;
                 self.reset_reconnect_attempts().await;
 
@@ -135,18 +337,70 @@ impl WebSocketClient {
                 Ok(())
             }
             Err(e) => {
-                let error_msg = format!("WebSocket connection failed: {}", e);
-                self.set_connection_status(ConnectionStatus::Error(error_msg.clone())).await;
-                Err(anyhow!(error_msg))
+                self.set_connection_status(ConnectionStatus::Error(e.to_string())).await;
+                Err(e)
             }
         }
     }
 
+    // 建立 WebSocket 连接，配置了 TLS 时使用自定义的 rustls 连接器
+    async fn open_stream(&self) -> Result<WsStream> {
+        // 添加认证参数到 URL
+        let mut url_string = self.url.clone();
+        if let Some(token) = &self.auth_token {
+            let separator = if url_string.contains('?') { "&" } else { "?" };
+            url_string = format!("{}{}token={}", url_string, separator, token);
+        }
+
+        let Some(tls_config) = &self.tls_config else {
+            return connect_async(&url_string)
+                .await
+                .map(|(ws_stream, _)| ws_stream)
+                .map_err(|e| anyhow!("WebSocket connection failed: {}", e));
+        };
+
+        let (connector, pin_mismatch) = build_tls_connector(tls_config)?;
+        match connect_async_tls_with_config(&url_string, None, false, Some(connector)).await {
+            Ok((ws_stream, _)) => Ok(ws_stream),
+            Err(_) if pin_mismatch.load(Ordering::SeqCst) => Err(anyhow!(CERTIFICATE_PIN_MISMATCH)),
+            Err(e) => Err(anyhow!("WebSocket connection failed: {}", e)),
+        }
+    }
+
     // 断开连接
     pub async fn disconnect(&self) {
         self.set_connection_status(ConnectionStatus::Disconnected).await;
     }
 
+    // 丢弃当前连接并重新建立（休眠唤醒后底层连接已失效，状态却可能仍是已连接），
+    // 等到连上、失败或超时后返回；连接沿用创建时的地址、token 和 TLS 配置
+    pub async fn reconnect(self: &Arc<Self>, timeout: std::time::Duration) -> Result<()> {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if let Some(task) = self.receive_task.lock().await.take() {
+            task.abort();
+        }
+        self.reset_reconnect_attempts().await;
+        self.set_connection_status(ConnectionStatus::Disconnected).await;
+
+        // connect 在连接期间一直运行消息循环，放到后台执行，这里轮询状态
+        let client = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.connect().await {
+                tracing::warn!("WebSocket reconnect failed: {}", e);
+            }
+        });
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match self.get_connection_status().await {
+                ConnectionStatus::Connected => return Ok(()),
+                ConnectionStatus::Error(e) => return Err(anyhow!(e)),
+                _ if tokio::time::Instant::now() >= deadline => return Err(anyhow!("WebSocket 重连超时")),
+                _ => tokio::time::sleep(RECONNECT_POLL_INTERVAL).await,
+            }
+        }
+    }
+
     // 发送消息
     pub async fn send_message(&self, message: QueuedMessage) -> Result<()> {
         let status = self.get_connection_status().await;
@@ -154,31 +408,30 @@ impl WebSocketClient {
         if status != ConnectionStatus::Connected {
             // 如果未连接，添加到队列
             self.add_to_queue(message).await;
-            return Err(anyhow!("WebSocket not connected, message queued"));
+            return Err(AppError::ws_not_connected("WebSocket 未连接，消息已加入队列").into());
         }
 
         // 构建 WebSocket 消息
-        let ws_event = WebSocketEvent::Message {
-            consultation_id: message.consultation_id.clone(),
-            message: Message {
-                id: message.id.clone(),
-                consultation_id: message.consultation_id.clone(),
-                sender_type: SenderType::Doctor, // 假设医生端发送
-                message_type: message.message_type.clone(),
-                content: Some(message.content.clone()),
-                file_path: message.file_path.clone(),
-                file_size: None,
-                mime_type: None,
-                timestamp: message.created_at,
-                sync_status: SyncStatus::Pending,
-                read_status: ReadStatus::Unread,
-            },
-        };
+        let ws_event = message.to_event();
 
         let json_message = serde_json::to_string(&ws_event)?;
+        let frame = encode_outgoing_frame(&json_message, self.compression_threshold)?;
 
         // 这里需要实际的发送逻辑，暂时模拟
-        println!("Sending WebSocket message: {}", json_message);
+        tracing::debug!("Sending WebSocket message: {} bytes (raw {} bytes)", frame.len(), json_message.len());
+
+        Ok(())
+    }
+
+    // 发送问诊状态类事件（转接等），未连接时直接报错，不进入消息队列
+    pub async fn send_event(&self, event: &WebSocketEvent) -> Result<()> {
+        if self.get_connection_status().await != ConnectionStatus::Connected {
+            return Err(AppError::ws_not_connected("WebSocket 未连接").into());
+        }
+
+        let json_message = serde_json::to_string(event)?;
+        let frame = encode_outgoing_frame(&json_message, self.compression_threshold)?;
+        tracing::debug!("Sending WebSocket event: {} bytes", frame.len());
 
         Ok(())
     }
@@ -191,7 +444,7 @@ impl WebSocketClient {
         });
 
         let json_message = serde_json::to_string(&subscribe_event)?;
-        println!("Subscribing to consultation: {}", json_message);
+        tracing::debug!("Subscribing to consultation: {}", json_message);
 
         Ok(())
     }
@@ -204,7 +457,7 @@ impl WebSocketClient {
         });
 
         let json_message = serde_json::to_string(&unsubscribe_event)?;
-        println!("Unsubscribing from consultation: {}", json_message);
+        tracing::debug!("Unsubscribing from consultation: {}", json_message);
 
         Ok(())
     }
@@ -218,7 +471,7 @@ impl WebSocketClient {
         };
 
         let json_message = serde_json::to_string(&receipt_event)?;
-        println!("Sending read receipt: {}", json_message);
+        tracing::debug!("Sending read receipt: {}", json_message);
 
         Ok(())
     }
@@ -232,7 +485,7 @@ impl WebSocketClient {
         };
 
         let json_message = serde_json::to_string(&typing_event)?;
-        println!("Sending typing status: {}", json_message);
+        tracing::debug!("Sending typing status: {}", json_message);
 
         Ok(())
     }
@@ -246,10 +499,10 @@ impl WebSocketClient {
             match self.send_message(message.clone()).await {
                 Ok(_) => {
                     processed_messages.push(message.id.clone());
-                    println!("Queued message sent: {}", message.id);
+                    tracing::debug!("Queued message sent: {}", message.id);
                 }
                 Err(e) => {
-                    println!("Failed to send queued message {}: {}", message.id, e);
+                    tracing::warn!("Failed to send queued message {}: {}", message.id, e);
                     break; // 停止处理剩余消息
                 }
             }
@@ -280,7 +533,7 @@ impl WebSocketClient {
     async fn add_to_queue(&self, message: QueuedMessage) {
         let mut queue = self.message_queue.lock().await;
         queue.push(message);
-        println!("Message added to queue, total queued: {}", queue.len());
+        tracing::debug!("Message added to queue, total queued: {}", queue.len());
     }
 
     // 私有方法：重置重连尝试次数
@@ -296,68 +549,83 @@ impl WebSocketClient {
     }
 
     // 私有方法：启动消息处理循环
-    async fn start_message_loop(&self, ws_stream: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>) {
+    async fn start_message_loop(&self, ws_stream: WsStream) {
         let (ws_sender, mut ws_receiver) = ws_stream.split();
         let event_sender = self.event_sender.clone();
         let connection_status = self.connection_status.clone();
+        let generation = self.generation.load(Ordering::SeqCst);
+        let current_generation = self.generation.clone();
 
         // 启动接收消息的任务
         let receive_task = tokio::spawn(async move {
             while let Some(message) = ws_receiver.next().await {
                 match message {
                     Ok(WsMessage::Text(text)) => {
-                        if let Ok(event) = serde_json::from_str::<WebSocketEvent>(&text) {
+                        let parsed = decode_incoming_frame(&text)
+                            .and_then(|json| Ok(serde_json::from_str::<WebSocketEvent>(&json)?));
+                        if let Ok(mut event) = parsed {
+                            sanitize_incoming_event(&mut event);
                             if let Err(e) = event_sender.send(event) {
-                                println!("Failed to send event to handler: {}", e);
+                                tracing::warn!("Failed to send event to handler: {}", e);
                                 break;
                             }
                         } else {
-                            println!("Failed to parse WebSocket message: {}", text);
+                            tracing::warn!("Failed to parse WebSocket message: {}", text);
                         }
                     }
                     Ok(WsMessage::Close(_)) => {
-                        println!("WebSocket connection closed by server");
+                        tracing::info!("WebSocket connection closed by server");
                         break;
                     }
                     Err(e) => {
-                        println!("WebSocket error: {}", e);
+                        tracing::warn!("WebSocket error: {}", e);
                         break;
                     }
                     _ => {}
                 }
             }
 
-            // 连接断开，更新状态
-            *connection_status.write().await = ConnectionStatus::Disconnected;
+            // 连接断开，更新状态；已被重建的连接不覆盖新连接的状态
+            if current_generation.load(Ordering::SeqCst) == generation {
+                *connection_status.write().await = ConnectionStatus::Disconnected;
+            }
         });
+        *self.receive_task.lock().await = Some(receive_task.abort_handle());
 
         // 处理队列中的消息
         if let Err(e) = self.process_message_queue().await {
-            println!("Failed to process message queue: {}", e);
+            tracing::warn!("Failed to process message queue: {}", e);
         }
 
         // 等待接收任务完成
         if let Err(e) = receive_task.await {
-            println!("Receive task error: {}", e);
+            tracing::warn!("Receive task error: {}", e);
         }
 
         // 尝试重连
-        self.attempt_reconnect().await;
+        self.attempt_reconnect(generation).await;
     }
 
-    // 私有方法：尝试重连
-    async fn attempt_reconnect(&self) {
+    // 私有方法：尝试重连，连接已被主动重建时不再重连
+    async fn attempt_reconnect(&self, generation: u64) {
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+
         let attempts = self.increment_reconnect_attempts().await;
 
         if attempts <= self.max_reconnect_attempts {
             self.set_connection_status(ConnectionStatus::Reconnecting).await;
 
-            println!("Attempting to reconnect ({}/{})", attempts, self.max_reconnect_attempts);
+            tracing::info!("Attempting to reconnect ({}/{})", attempts, self.max_reconnect_attempts);
 
             tokio::time::sleep(self.reconnect_delay * attempts).await;
+            if self.generation.load(Ordering::SeqCst) != generation {
+                return;
+            }
 
             if let Err(e) = self.connect().await {
-                println!("Reconnection attempt {} failed: {}", attempts, e);
+                tracing::warn!("Reconnection attempt {} failed: {}", attempts, e);
             }
         } else {
             let error_msg = format!("Max reconnection attempts ({}) exceeded", self.max_reconnect_attempts);
@@ -366,28 +634,192 @@ impl WebSocketClient {
     }
 }
 
+// 同一 URL + token 的共享连接：使用者引用计数，以及其上复用的问诊订阅（问诊 ID -> 订阅者数）
+#[derive(Debug)]
+struct SharedConnection {
+    connection_id: String,
+    users: usize,
+    subscriptions: HashMap<String, usize>,
+}
+
+// 共享连接的使用情况，状态查询时附带返回
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SharedConnectionInfo {
+    pub users: usize,
+    pub subscriptions: HashMap<String, usize>,
+}
+
+/// 共享连接登记表，只做计数，不持有连接本身
+#[derive(Debug, Default)]
+pub struct SharedConnectionRegistry {
+    connections: HashMap<(String, Option<String>), SharedConnection>,
+}
+
+impl SharedConnectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connection_id_for(&self, url: &str, auth_token: Option<&str>) -> Option<String> {
+        self.connections
+            .get(&(url.to_string(), auth_token.map(str::to_string)))
+            .map(|shared| shared.connection_id.clone())
+    }
+
+    // 已登记时增加一个使用者，返回连接 ID
+    pub fn acquire(&mut self, url: &str, auth_token: Option<&str>) -> Option<String> {
+        let shared = self
+            .connections
+            .get_mut(&(url.to_string(), auth_token.map(str::to_string)))?;
+        shared.users += 1;
+        Some(shared.connection_id.clone())
+    }
+
+    // 登记新建的共享连接，创建者即第一个使用者；同一键上的旧连接被替换
+    pub fn register(&mut self, url: &str, auth_token: Option<&str>, connection_id: &str) {
+        self.connections.insert(
+            (url.to_string(), auth_token.map(str::to_string)),
+            SharedConnection {
+                connection_id: connection_id.to_string(),
+                users: 1,
+                subscriptions: HashMap::new(),
+            },
+        );
+    }
+
+    // 减少一个使用者；None 表示不是共享连接，Some(true) 表示最后一个使用者已离开
+    pub fn release(&mut self, connection_id: &str) -> Option<bool> {
+        let key = self.key_of(connection_id)?;
+        let shared = self.connections.get_mut(&key)?;
+        shared.users = shared.users.saturating_sub(1);
+        if shared.users > 0 {
+            return Some(false);
+        }
+        self.connections.remove(&key);
+        Some(true)
+    }
+
+    pub fn remove(&mut self, connection_id: &str) {
+        if let Some(key) = self.key_of(connection_id) {
+            self.connections.remove(&key);
+        }
+    }
+
+    // 记录一个问诊订阅者；Some(true) 表示该问诊首次订阅，需要通知服务器
+    pub fn add_subscription(&mut self, connection_id: &str, consultation_id: &str) -> Option<bool> {
+        let shared = self.get_mut(connection_id)?;
+        let count = shared.subscriptions.entry(consultation_id.to_string()).or_insert(0);
+        *count += 1;
+        Some(*count == 1)
+    }
+
+    // 移除一个问诊订阅者；Some(true) 表示已无订阅者，需要通知服务器取消订阅
+    pub fn remove_subscription(&mut self, connection_id: &str, consultation_id: &str) -> Option<bool> {
+        let shared = self.get_mut(connection_id)?;
+        let Some(count) = shared.subscriptions.get_mut(consultation_id) else {
+            return Some(false);
+        };
+        *count -= 1;
+        if *count > 0 {
+            return Some(false);
+        }
+        shared.subscriptions.remove(consultation_id);
+        Some(true)
+    }
+
+    pub fn info(&self, connection_id: &str) -> Option<SharedConnectionInfo> {
+        self.connections
+            .values()
+            .find(|shared| shared.connection_id == connection_id)
+            .map(|shared| SharedConnectionInfo {
+                users: shared.users,
+                subscriptions: shared.subscriptions.clone(),
+            })
+    }
+
+    fn key_of(&self, connection_id: &str) -> Option<(String, Option<String>)> {
+        self.connections
+            .iter()
+            .find(|(_, shared)| shared.connection_id == connection_id)
+            .map(|(key, _)| key.clone())
+    }
+
+    fn get_mut(&mut self, connection_id: &str) -> Option<&mut SharedConnection> {
+        self.connections
+            .values_mut()
+            .find(|shared| shared.connection_id == connection_id)
+    }
+}
+
 // WebSocket 管理器
 pub struct WebSocketManager {
     clients: Arc<Mutex<HashMap<String, Arc<WebSocketClient>>>>,
+    shared: Arc<Mutex<SharedConnectionRegistry>>,
     event_handlers: Arc<Mutex<Vec<mpsc::UnboundedSender<WebSocketEvent>>>>,
+    replay: Arc<Mutex<EventReplayBuffer>>,
 }
 
 impl WebSocketManager {
     pub fn new() -> Self {
         Self {
             clients: Arc::new(Mutex::new(HashMap::new())),
+            shared: Arc::new(Mutex::new(SharedConnectionRegistry::new())),
             event_handlers: Arc::new(Mutex::new(Vec::new())),
+            replay: Arc::new(Mutex::new(EventReplayBuffer::new())),
         }
     }
 
+    // 多个问诊窗口共用一条连接：同一 URL + token 且连接可用时复用，否则新建并登记
+    pub async fn get_or_create_shared_connection(
+        &self,
+        url: String,
+        auth_token: Option<String>,
+        options: WebSocketOptions,
+    ) -> Result<String> {
+        let existing = self.shared.lock().await.connection_id_for(&url, auth_token.as_deref());
+        if let Some(connection_id) = existing {
+            let client = self.clients.lock().await.get(&connection_id).cloned();
+            let usable = match client {
+                Some(client) => client.get_connection_status().await.is_usable(),
+                None => false,
+            };
+            if usable {
+                self.shared.lock().await.acquire(&url, auth_token.as_deref());
+                tracing::debug!("Reusing shared WebSocket connection {}", connection_id);
+                return Ok(connection_id);
+            }
+
+            // 旧连接已断开，丢弃后重建
+            tracing::info!("Shared WebSocket connection {} is no longer usable, recreating", connection_id);
+            self.shared.lock().await.remove(&connection_id);
+            self.clients.lock().await.remove(&connection_id);
+        }
+
+        let connection_id = self.create_connection(url.clone(), auth_token.clone(), options).await?;
+        self.shared
+            .lock()
+            .await
+            .register(&url, auth_token.as_deref(), &connection_id);
+        Ok(connection_id)
+    }
+
     // 创建新的 WebSocket 连接
-    pub async fn create_connection(&self, url: String, auth_token: Option<String>) -> Result<String> {
+    pub async fn create_connection(
+        &self,
+        url: String,
+        auth_token: Option<String>,
+        options: WebSocketOptions,
+    ) -> Result<String> {
         let connection_id = uuid::Uuid::new_v4().to_string();
         let (mut client, event_receiver) = WebSocketClient::new(url);
 
         if let Some(token) = auth_token {
             client.set_auth_token(token);
         }
+        if let Some(tls_config) = options.tls {
+            client.set_tls_config(tls_config);
+        }
+        client.set_compression_threshold(options.compression_threshold);
 
         let client_arc = Arc::new(client);
 
@@ -406,13 +838,18 @@ impl WebSocketManager {
         Ok(connection_id)
     }
 
-    // 关闭连接
+    // 关闭连接，共享连接只有最后一个使用者关闭时才真正断开
     pub async fn close_connection(&self, connection_id: &str) -> Result<()> {
+        if self.shared.lock().await.release(connection_id) == Some(false) {
+            tracing::debug!("Released shared WebSocket connection {}, still in use", connection_id);
+            return Ok(());
+        }
+
         if let Some(client) = self.clients.lock().await.remove(connection_id) {
             client.disconnect().await;
             Ok(())
         } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+            Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into())
         }
     }
 
@@ -421,7 +858,7 @@ impl WebSocketManager {
         if let Some(client) = self.clients.lock().await.get(connection_id) {
             Ok(client.get_connection_status().await)
         } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+            Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into())
         }
     }
 
@@ -430,26 +867,44 @@ impl WebSocketManager {
         if let Some(client) = self.clients.lock().await.get(connection_id) {
             client.send_message(message).await
         } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+            Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into())
         }
     }
 
-    // 订阅问诊
+    // 共享连接使用者数和问诊订阅，非共享连接返回 None
+    pub async fn shared_connection_info(&self, connection_id: &str) -> Option<SharedConnectionInfo> {
+        self.shared.lock().await.info(connection_id)
+    }
+
+    // 订阅问诊，共享连接上同一问诊只向服务器订阅一次
     pub async fn subscribe_to_consultation(&self, connection_id: &str, consultation_id: String) -> Result<()> {
-        if let Some(client) = self.clients.lock().await.get(connection_id) {
-            client.subscribe_to_consultation(consultation_id).await
-        } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+        let Some(client) = self.clients.lock().await.get(connection_id).cloned() else {
+            return Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into());
+        };
+
+        let first = self.shared.lock().await.add_subscription(connection_id, &consultation_id);
+        if first == Some(false) {
+            return Ok(());
+        }
+
+        let result = client.subscribe_to_consultation(consultation_id.clone()).await;
+        if result.is_err() && first.is_some() {
+            self.shared.lock().await.remove_subscription(connection_id, &consultation_id);
         }
+        result
     }
 
-    // 取消订阅问诊
+    // 取消订阅问诊，共享连接上最后一个订阅者离开时才通知服务器
     pub async fn unsubscribe_from_consultation(&self, connection_id: &str, consultation_id: String) -> Result<()> {
-        if let Some(client) = self.clients.lock().await.get(connection_id) {
-            client.unsubscribe_from_consultation(consultation_id).await
-        } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+        let Some(client) = self.clients.lock().await.get(connection_id).cloned() else {
+            return Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into());
+        };
+
+        let last = self.shared.lock().await.remove_subscription(connection_id, &consultation_id);
+        if last == Some(false) {
+            return Ok(());
         }
+        client.unsubscribe_from_consultation(consultation_id).await
     }
 
     // 发送已读回执
@@ -457,7 +912,7 @@ impl WebSocketManager {
         if let Some(client) = self.clients.lock().await.get(connection_id) {
             client.send_read_receipt(consultation_id, message_id).await
         } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+            Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into())
         }
     }
 
@@ -466,10 +921,46 @@ impl WebSocketManager {
         if let Some(client) = self.clients.lock().await.get(connection_id) {
             client.send_typing_status(consultation_id, is_typing).await
         } else {
-            Err(anyhow!("Connection not found: {}", connection_id))
+            Err(AppError::ws_not_connected(format!("WebSocket 连接不存在: {}", connection_id)).into())
         }
     }
 
+    // 通过任一已连接的连接发送发件箱中的消息，未连接时直接报错，由发件箱稍后重发
+    pub async fn dispatch_message(&self, message: &QueuedMessage) -> Result<()> {
+        let clients: Vec<Arc<WebSocketClient>> = self.clients.lock().await.values().cloned().collect();
+        for client in clients {
+            if client.get_connection_status().await == ConnectionStatus::Connected {
+                return client.send_event(&message.to_event()).await;
+            }
+        }
+        Err(AppError::ws_not_connected("WebSocket 未连接").into())
+    }
+
+    // 通过所有已建立的连接发送事件，返回成功发送的连接数
+    pub async fn broadcast_event(&self, event: &WebSocketEvent) -> usize {
+        let clients = self.clients.lock().await;
+        let mut sent = 0;
+
+        for (id, client) in clients.iter() {
+            match client.send_event(event).await {
+                Ok(()) => sent += 1,
+                Err(e) => tracing::warn!("Failed to send event on connection {}: {}", id, e),
+            }
+        }
+
+        sent
+    }
+
+    // 当前所有连接，调用方可在不持有管理器锁的情况下逐个重建
+    pub async fn connections(&self) -> Vec<(String, Arc<WebSocketClient>)> {
+        self.clients
+            .lock()
+            .await
+            .iter()
+            .map(|(id, client)| (id.clone(), client.clone()))
+            .collect()
+    }
+
     // 获取所有连接的状态
     pub async fn get_all_connection_status(&self) -> HashMap<String, ConnectionStatus> {
         let mut status_map = HashMap::new();
@@ -482,6 +973,20 @@ impl WebSocketManager {
         status_map
     }
 
+    // 问诊窗口打开时补齐 since 之后收到的事件
+    pub async fn recent_events(&self, consultation_id: &str, since: Option<chrono::DateTime<chrono::Utc>>) -> Vec<BufferedEvent> {
+        self.replay.lock().await.since(consultation_id, since, chrono::Utc::now())
+    }
+
+    // 丢弃回放缓冲中的事件：指定问诊时只清该问诊，否则全部清空
+    pub async fn purge_replay(&self, consultation_id: Option<&str>) -> usize {
+        let mut replay = self.replay.lock().await;
+        match consultation_id {
+            Some(consultation_id) => replay.remove_consultation(consultation_id),
+            None => replay.clear(),
+        }
+    }
+
     // 添加事件处理器
     pub async fn add_event_handler(&self, sender: mpsc::UnboundedSender<WebSocketEvent>) {
         self.event_handlers.lock().await.push(sender);
@@ -490,15 +995,19 @@ impl WebSocketManager {
     // 私有方法：启动事件处理
     async fn start_event_handler(&self, mut event_receiver: mpsc::UnboundedReceiver<WebSocketEvent>) {
         let handlers = self.event_handlers.clone();
+        let replay = self.replay.clone();
 
         tokio::spawn(async move {
             while let Some(event) = event_receiver.recv().await {
+                // 先写入回放缓冲，稍后打开的问诊窗口可以补齐
+                replay.lock().await.push(event.clone(), chrono::Utc::now());
+
                 let handlers_guard = handlers.lock().await;
 
                 // 广播事件到所有处理器
                 for handler in handlers_guard.iter() {
                     if let Err(e) = handler.send(event.clone()) {
-                        println!("Failed to send event to handler: {}", e);
+                        tracing::warn!("Failed to send event to handler: {}", e);
                     }
                 }
             }
@@ -510,4 +1019,560 @@ impl Default for WebSocketManager {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+// 序列化后的消息超过阈值时压缩，压缩后仍超过则拒绝，提示改为上传文件
+pub fn encode_outgoing_frame(json: &str, threshold: usize) -> Result<String> {
+    if json.len() <= threshold {
+        return Ok(json.to_string());
+    }
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(json.as_bytes())?;
+    let envelope = CompressedEnvelope {
+        compressed: true,
+        encoding: COMPRESSION_ENCODING.to_string(),
+        payload: STANDARD.encode(encoder.finish()?),
+    };
+    let frame = serde_json::to_string(&envelope)?;
+
+    if frame.len() > threshold {
+        return Err(AppError::ws_message_too_large(format!(
+            "消息过大（压缩后 {}，上限 {}），请将图片等内容改为文件上传",
+            ValidationService::format_file_size(frame.len() as u64),
+            ValidationService::format_file_size(threshold as u64)
+        ))
+        .into());
+    }
+
+    tracing::debug!("Compressed WebSocket message from {} to {} bytes", json.len(), frame.len());
+    Ok(frame)
+}
+
+// 收到的帧带 compressed 标记时先解压，其余原样返回
+pub fn decode_incoming_frame(text: &str) -> Result<String> {
+    let envelope = match serde_json::from_str::<CompressedEnvelope>(text) {
+        Ok(envelope) if envelope.compressed => envelope,
+        _ => return Ok(text.to_string()),
+    };
+    if envelope.encoding != COMPRESSION_ENCODING {
+        bail!("不支持的消息压缩格式: {}", envelope.encoding);
+    }
+
+    let compressed = STANDARD
+        .decode(envelope.payload.as_bytes())
+        .map_err(|e| anyhow!("压缩消息编码无效: {}", e))?;
+    let mut json = String::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_string(&mut json)
+        .map_err(|e| anyhow!("解压消息失败: {}", e))?;
+    if json.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        bail!("解压后的消息超过 {} 字节上限", MAX_DECOMPRESSED_SIZE);
+    }
+
+    Ok(json)
+}
+
+// 推送的消息正文先清洗再分发给窗口和通知，原文在同步入库时保存
+pub fn sanitize_incoming_event(event: &mut WebSocketEvent) {
+    if let WebSocketEvent::Message { message, .. } = event {
+        if sanitize_message(message).is_some() {
+            tracing::debug!("Sanitized content of incoming message {}", message.id);
+        }
+    }
+}
+
+// 按配置构建 rustls 连接器，返回的标志在证书指纹不匹配时置位
+fn build_tls_connector(config: &TlsConfig) -> Result<(Connector, Arc<AtomicBool>)> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(pem) = &config.custom_ca_pem {
+        let mut added = 0;
+        for cert in rustls_pemfile::certs(&mut pem.as_bytes()) {
+            let cert = cert.map_err(|e| anyhow!("无法解析 CA 证书: {}", e))?;
+            roots.add(cert).map_err(|e| anyhow!("无效的 CA 证书: {}", e))?;
+            added += 1;
+        }
+        if added == 0 {
+            bail!("CA 证书 PEM 中没有证书");
+        }
+    }
+    let roots = Arc::new(roots);
+
+    let pins = config
+        .pinned_sha256
+        .iter()
+        .flatten()
+        .map(|pin| normalize_pin(pin))
+        .collect::<Result<Vec<_>>>()?;
+
+    let pin_mismatch = Arc::new(AtomicBool::new(false));
+    let verifier = PinningVerifier {
+        inner: WebPkiServerVerifier::builder_with_provider(roots.clone(), provider.clone())
+            .build()
+            .map_err(|e| anyhow!("创建证书校验器失败: {}", e))?,
+        roots,
+        provider: provider.clone(),
+        pins,
+        accept_invalid_hostname: config.accept_invalid_hostname,
+        pin_mismatch: pin_mismatch.clone(),
+    };
+
+    let client_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| anyhow!("TLS 配置无效: {}", e))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    Ok((Connector::Rustls(Arc::new(client_config)), pin_mismatch))
+}
+
+// 指纹统一为小写十六进制，允许 AA:BB 形式
+fn normalize_pin(pin: &str) -> Result<String> {
+    let normalized: String = pin
+        .chars()
+        .filter(|c| *c != ':' && !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    if normalized.len() != 64 || !normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("无效的证书指纹: {}", pin);
+    }
+    Ok(normalized)
+}
+
+pub fn load_tls_config(path: &Path) -> Option<TlsConfig> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+pub fn save_tls_config(path: &Path, config: &TlsConfig) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("创建数据目录失败: {}", e))?;
+    }
+
+    let content = serde_json::to_string_pretty(config).map_err(|e| format!("序列化 TLS 配置失败: {}", e))?;
+    std::fs::write(path, content).map_err(|e| format!("保存 TLS 配置失败: {}", e))
+}
+
+// 先检查证书指纹，再按系统根证书和自定义 CA 校验证书链
+// 固定了指纹的自签名证书不要求由受信任的 CA 签发
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    roots: Arc<RootCertStore>,
+    provider: Arc<CryptoProvider>,
+    pins: Vec<String>,
+    accept_invalid_hostname: bool,
+    pin_mismatch: Arc<AtomicBool>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if !self.pins.is_empty() {
+            let fingerprint = to_hex(&Sha256::digest(end_entity.as_ref()));
+            if !self.pins.contains(&fingerprint) {
+                self.pin_mismatch.store(true, Ordering::SeqCst);
+                tracing::warn!("Server certificate {} does not match any pinned fingerprint", fingerprint);
+                return Err(rustls::Error::General(CERTIFICATE_PIN_MISMATCH.to_string()));
+            }
+        }
+
+        let result = if self.accept_invalid_hostname {
+            let cert = rustls::server::ParsedCertificate::try_from(end_entity)?;
+            rustls::client::verify_server_cert_signed_by_trust_anchor(
+                &cert,
+                &self.roots,
+                intermediates,
+                now,
+                self.provider.signature_verification_algorithms.all,
+            )
+            .map(|_| ServerCertVerified::assertion())
+        } else {
+            self.inner
+                .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        };
+
+        match result {
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer)) if !self.pins.is_empty() => {
+                Ok(ServerCertVerified::assertion())
+            }
+            other => other,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, IsCa, KeyPair};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use tokio::net::TcpListener;
+
+    // 本地 wss 服务器，使用给定证书完成握手后保持连接
+    async fn start_tls_server(cert: CertificateDer<'static>, key: &KeyPair) -> u16 {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())))
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(tls_stream) = acceptor.accept(stream).await {
+                        if let Ok(mut ws) = tokio_tungstenite::accept_async(tls_stream).await {
+                            while let Some(Ok(_)) = ws.next().await {}
+                        }
+                    }
+                });
+            }
+        });
+
+        port
+    }
+
+    fn self_signed() -> CertifiedKey {
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+    }
+
+    fn client(port: u16, tls_config: TlsConfig) -> WebSocketClient {
+        let (mut client, _events) = WebSocketClient::new(format!("wss://localhost:{}/ws", port));
+        client.set_tls_config(tls_config);
+        client
+    }
+
+    fn message_event(content: String) -> WebSocketEvent {
+        WebSocketEvent::Message {
+            consultation_id: "c1".to_string(),
+            message: Message {
+                id: "m1".to_string(),
+                consultation_id: "c1".to_string(),
+                sender_type: SenderType::Doctor,
+                message_type: MessageType::Template,
+                content: Some(content),
+                file_path: None,
+                file_size: None,
+                mime_type: None,
+                timestamp: chrono::Utc::now(),
+                sync_status: SyncStatus::Pending,
+                read_status: ReadStatus::Unread,
+                template_id: Some("t1".to_string()),
+                duration_ms: None,
+                waveform: None,
+                pinned: false,
+            },
+            idempotency_key: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn test_large_message_compression_round_trip() {
+        // 模板中内嵌的 base64 图片，重复内容压缩率高
+        let image = STANDARD.encode(vec![0x42u8; 150 * 1024]);
+        let json = serde_json::to_string(&message_event(format!("<img src=\"data:image/png;base64,{}\">", image))).unwrap();
+        assert!(json.len() > DEFAULT_COMPRESSION_THRESHOLD);
+
+        let frame = encode_outgoing_frame(&json, DEFAULT_COMPRESSION_THRESHOLD).unwrap();
+        assert!(frame.len() <= DEFAULT_COMPRESSION_THRESHOLD);
+        let envelope: serde_json::Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(envelope["compressed"], true);
+
+        let decoded = decode_incoming_frame(&frame).unwrap();
+        assert_eq!(decoded, json);
+        match serde_json::from_str::<WebSocketEvent>(&decoded).unwrap() {
+            WebSocketEvent::Message { message, .. } => assert!(message.content.unwrap().contains(&image)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        // 小消息和普通事件不做处理
+        let small = serde_json::to_string(&message_event("你好".to_string())).unwrap();
+        assert_eq!(encode_outgoing_frame(&small, DEFAULT_COMPRESSION_THRESHOLD).unwrap(), small);
+        assert_eq!(decode_incoming_frame(&small).unwrap(), small);
+    }
+
+    #[test]
+    fn test_incoming_message_content_sanitized() {
+        let mut event = message_event("<script>fetch('//evil')</script>请\u{200B}按时服药💊\u{0000}".to_string());
+        sanitize_incoming_event(&mut event);
+        match event {
+            WebSocketEvent::Message { message, .. } => assert_eq!(message.content.as_deref(), Some("请按时服药💊")),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        let family = "👩\u{200D}👩\u{200D}👦".to_string();
+        let mut event = message_event(family.clone());
+        sanitize_incoming_event(&mut event);
+        match event {
+            WebSocketEvent::Message { message, .. } => assert_eq!(message.content, Some(family)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_incompressible_message_rejected() {
+        // 伪随机数据几乎无法压缩
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let noise: Vec<u8> = (0..120 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let json = serde_json::to_string(&message_event(STANDARD.encode(noise))).unwrap();
+
+        let error = encode_outgoing_frame(&json, DEFAULT_COMPRESSION_THRESHOLD).unwrap_err();
+        let error = error.downcast::<AppError>().unwrap();
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_WS_MESSAGE_TOO_LARGE));
+        assert!(error.message.contains("文件上传"));
+
+        // 阈值可按连接调整
+        assert!(encode_outgoing_frame(&json, json.len()).is_ok());
+
+        let bad = r#"{"compressed":true,"encoding":"zstd","payload":""}"#;
+        assert!(decode_incoming_frame(bad).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_event_requires_connection() {
+        let event = WebSocketEvent::ConsultationTransferred {
+            consultation_id: "c1".to_string(),
+            from_doctor_id: "d1".to_string(),
+            to_doctor_id: "d2".to_string(),
+            note: None,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "consultation_transferred");
+        assert_eq!(json["to_doctor_id"], "d2");
+
+        let (client, _events) = WebSocketClient::new("ws://127.0.0.1:1".to_string());
+        let error = client.send_event(&event).await.unwrap_err();
+        let error = error.downcast::<AppError>().unwrap();
+        assert_eq!(error.code.as_deref(), Some(crate::utils::CODE_WS_NOT_CONNECTED));
+    }
+
+    #[test]
+    fn test_intake_form_event_keeps_raw_data() {
+        let json = r#"{"type":"intake_form","consultation_id":"c1","schema_version":2,"data":{"sections":[]},"submitted_at":"2024-03-01T09:00:00Z"}"#;
+        let event: WebSocketEvent = serde_json::from_str(json).unwrap();
+        assert_eq!(event.consultation_id(), Some("c1"));
+        match event {
+            WebSocketEvent::IntakeForm { schema_version, data, .. } => {
+                assert_eq!(schema_version, 2);
+                assert_eq!(data, serde_json::json!({ "sections": [] }));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_presence_event_is_connection_level() {
+        let json = r#"{"type":"presence","doctor_id":"d2","status":"busy"}"#;
+        let event: WebSocketEvent = serde_json::from_str(json).unwrap();
+        assert_eq!(event.consultation_id(), None);
+        assert!(matches!(
+            event,
+            WebSocketEvent::Presence { ref doctor_id, status: DoctorOnlineStatus::Busy } if doctor_id == "d2"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_self_signed_certificate_connects() {
+        let server = self_signed();
+        let port = start_tls_server(server.cert.der().clone(), &server.key_pair).await;
+
+        // 指纹大写并带冒号同样有效
+        let fingerprint = to_hex(&Sha256::digest(server.cert.der().as_ref()));
+        let formatted = fingerprint
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap().to_uppercase())
+            .collect::<Vec<_>>()
+            .join(":");
+        let tls_config = TlsConfig {
+            pinned_sha256: Some(vec!["0".repeat(64), formatted]),
+            ..TlsConfig::default()
+        };
+
+        let stream = client(port, tls_config).open_stream().await;
+        assert!(stream.is_ok(), "{:?}", stream.err());
+
+        // 未固定指纹时自签名证书不受信任
+        let result = client(port, TlsConfig::default()).open_stream().await;
+        let error = result.err().unwrap().to_string();
+        assert!(!error.contains(CERTIFICATE_PIN_MISMATCH), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_certificate_pin_mismatch_sets_error_status() {
+        let server = self_signed();
+        let port = start_tls_server(server.cert.der().clone(), &server.key_pair).await;
+
+        let tls_config = TlsConfig {
+            pinned_sha256: Some(vec!["ab".repeat(32)]),
+            ..TlsConfig::default()
+        };
+        let client = client(port, tls_config);
+        let error = client.connect().await.unwrap_err();
+
+        assert_eq!(error.to_string(), CERTIFICATE_PIN_MISMATCH);
+        assert_eq!(
+            client.get_connection_status().await,
+            ConnectionStatus::Error(CERTIFICATE_PIN_MISMATCH.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_ca_and_invalid_pin_format() {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let leaf_key = KeyPair::generate().unwrap();
+        let leaf = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &ca, &ca_key)
+            .unwrap();
+        let port = start_tls_server(leaf.der().clone(), &leaf_key).await;
+
+        let tls_config = TlsConfig {
+            custom_ca_pem: Some(ca.pem()),
+            ..TlsConfig::default()
+        };
+        let stream = client(port, tls_config).open_stream().await;
+        assert!(stream.is_ok(), "{:?}", stream.err());
+
+        let tls_config = TlsConfig {
+            pinned_sha256: Some(vec!["not-a-fingerprint".to_string()]),
+            ..TlsConfig::default()
+        };
+        assert!(build_tls_connector(&tls_config).is_err());
+
+        // 配置持久化后重连沿用
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(WEBSOCKET_TLS_FILE);
+        let saved = TlsConfig {
+            custom_ca_pem: Some(ca.pem()),
+            pinned_sha256: Some(vec!["ab".repeat(32)]),
+            accept_invalid_hostname: true,
+        };
+        save_tls_config(&path, &saved).unwrap();
+        assert_eq!(load_tls_config(&path), Some(saved));
+        assert_eq!(load_tls_config(&dir.path().join("missing.json")), None);
+    }
+
+    #[test]
+    fn test_shared_connection_reused_for_same_url_and_token() {
+        let mut registry = SharedConnectionRegistry::new();
+        assert_eq!(registry.acquire("wss://a/ws", Some("t1")), None);
+
+        registry.register("wss://a/ws", Some("t1"), "conn-1");
+        assert_eq!(registry.acquire("wss://a/ws", Some("t1")), Some("conn-1".to_string()));
+        assert_eq!(registry.acquire("wss://a/ws", Some("t1")), Some("conn-1".to_string()));
+        assert_eq!(registry.info("conn-1").unwrap().users, 3);
+    }
+
+    #[test]
+    fn test_shared_connection_closes_on_last_release() {
+        let mut registry = SharedConnectionRegistry::new();
+        registry.register("wss://a/ws", None, "conn-1");
+        registry.acquire("wss://a/ws", None);
+
+        assert_eq!(registry.release("conn-1"), Some(false));
+        assert_eq!(registry.info("conn-1").unwrap().users, 1);
+        assert_eq!(registry.release("conn-1"), Some(true));
+        assert!(registry.info("conn-1").is_none());
+        assert_eq!(registry.connection_id_for("wss://a/ws", None), None);
+
+        // 非共享连接按原逻辑直接关闭
+        assert_eq!(registry.release("private"), None);
+    }
+
+    #[test]
+    fn test_shared_connections_isolated_by_url_and_token() {
+        let mut registry = SharedConnectionRegistry::new();
+        registry.register("wss://a/ws", Some("t1"), "conn-a");
+        registry.register("wss://b/ws", Some("t1"), "conn-b");
+        registry.register("wss://a/ws", Some("t2"), "conn-a2");
+
+        assert_eq!(registry.acquire("wss://b/ws", Some("t1")), Some("conn-b".to_string()));
+        assert_eq!(registry.connection_id_for("wss://a/ws", Some("t2")), Some("conn-a2".to_string()));
+        assert_eq!(registry.connection_id_for("wss://a/ws", None), None);
+
+        assert_eq!(registry.release("conn-a"), Some(true));
+        assert_eq!(registry.info("conn-b").unwrap().users, 2);
+        assert_eq!(registry.info("conn-a2").unwrap().users, 1);
+    }
+
+    #[test]
+    fn test_subscriptions_multiplexed_over_shared_connection() {
+        let mut registry = SharedConnectionRegistry::new();
+        registry.register("wss://a/ws", None, "conn-1");
+
+        // 同一问诊只在首个订阅者和最后一个取消者时通知服务器
+        assert_eq!(registry.add_subscription("conn-1", "c1"), Some(true));
+        assert_eq!(registry.add_subscription("conn-1", "c1"), Some(false));
+        assert_eq!(registry.add_subscription("conn-1", "c2"), Some(true));
+        assert_eq!(registry.info("conn-1").unwrap().subscriptions.get("c1"), Some(&2));
+
+        assert_eq!(registry.remove_subscription("conn-1", "c1"), Some(false));
+        assert_eq!(registry.remove_subscription("conn-1", "c1"), Some(true));
+        assert_eq!(registry.remove_subscription("conn-1", "c1"), Some(false));
+        assert_eq!(registry.add_subscription("private", "c1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_shared_connect_is_not_registered() {
+        let manager = WebSocketManager::new();
+        let result = manager
+            .get_or_create_shared_connection("ws://127.0.0.1:1/ws".to_string(), None, WebSocketOptions::default())
+            .await;
+
+        assert!(result.is_err());
+        assert!(manager.shared.lock().await.connection_id_for("ws://127.0.0.1:1/ws", None).is_none());
+        assert!(manager.get_all_connection_status().await.is_empty());
+    }
+}
@@ -0,0 +1,558 @@
+// 工作站配置包：导出应用配置、数据保留策略和常用回复、病历模板，用管理员提供的口令签名；
+// 导入时先校验签名和版本，再按 replace 或 merge 在一个事务中写入
+
+use crate::database::connection::{get_database, DbConnection};
+use crate::database::dao::{
+    AppSettingsDao, BaseDao, MessageTemplateDao, RecordTemplateDao, RetentionDao, APP_CONFIG_SCHEMA_VERSION,
+};
+use crate::models::{
+    AppConfig, AppError, ErrorType, ProfileConflict, ProfileExportResult, ProfileImportReport,
+    ProfileMergeStrategy, ProfileSection, ProfileSectionReport, SignedWorkstationProfile,
+    WorkstationProfile, WORKSTATION_PROFILE_FORMAT, WORKSTATION_PROFILE_SCHEMA_VERSION,
+};
+use crate::services::{merge_config_patch, validate_config, AppSettingsService, SECURITY_SETTING_KEYS};
+use crate::utils::{
+    random_salt, sign_with_passphrase, verify_passphrase_signature, MessageKey, CODE_PROFILE_SIGNATURE_INVALID,
+    CODE_PROFILE_VERSION_UNSUPPORTED,
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const MIN_PASSPHRASE_LENGTH: usize = 8;
+// AppConfig 中的数据保留策略，在配置包中单独成节
+const RETENTION_KEY: &str = "retention";
+
+pub struct WorkstationProfileService {
+    connection: DbConnection,
+}
+
+// 模板节的写入计划：replace 时 removals 为本机多出的模板
+struct TemplatePlan<T> {
+    upserts: Vec<T>,
+    removals: Vec<String>,
+}
+
+impl WorkstationProfileService {
+    pub fn new() -> Self {
+        Self::with_connection(get_database().get_connection())
+    }
+
+    pub fn with_connection(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn export_profile(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        exported_by: Option<&str>,
+    ) -> Result<ProfileExportResult, AppError> {
+        validate_passphrase(passphrase)?;
+
+        let config = AppSettingsService::with_connection(self.connection.clone()).load()?;
+        let mut app_settings = serde_json::to_value(&config)
+            .map_err(system_error)?
+            .as_object()
+            .cloned()
+            .expect("AppConfig serializes to an object");
+        app_settings.remove(RETENTION_KEY);
+
+        let salt = random_salt();
+        let profile = WorkstationProfile {
+            format: WORKSTATION_PROFILE_FORMAT.to_string(),
+            schema_version: WORKSTATION_PROFILE_SCHEMA_VERSION,
+            app_config_schema_version: APP_CONFIG_SCHEMA_VERSION,
+            exported_at: Utc::now(),
+            exported_by: exported_by.map(str::to_string),
+            salt: general_purpose::STANDARD.encode(salt),
+            app_settings,
+            retention_policy: config.retention.clone(),
+            message_templates: MessageTemplateDao::with_connection(self.connection.clone())
+                .find_all()
+                .map_err(dao_error)?,
+            record_templates: RecordTemplateDao::with_connection(self.connection.clone())
+                .find_all()
+                .map_err(dao_error)?,
+        };
+
+        // 签名覆盖序列化后的 profile，导入时对读到的 profile 重新序列化校验
+        let profile_value = serde_json::to_value(&profile).map_err(system_error)?;
+        let payload = serde_json::to_vec(&profile_value).map_err(system_error)?;
+        let signature = sign_with_passphrase(passphrase, &salt, &payload)?;
+        let signed = SignedWorkstationProfile {
+            profile: profile_value,
+            signature,
+        };
+        std::fs::write(path, serde_json::to_vec_pretty(&signed).map_err(system_error)?)?;
+
+        Ok(ProfileExportResult {
+            path: path.to_string_lossy().to_string(),
+            setting_count: profile.app_settings.len(),
+            message_template_count: profile.message_templates.len(),
+            record_template_count: profile.record_templates.len(),
+        })
+    }
+
+    // 没有安全管理权限时（allow_sensitive 为 false）服务器地址等安全相关配置不导入，记为冲突
+    pub fn import_profile(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        strategy: ProfileMergeStrategy,
+        allow_sensitive: bool,
+        imported_by: Option<&str>,
+    ) -> Result<ProfileImportReport, AppError> {
+        let profile = read_profile(path, passphrase)?;
+
+        let current = AppSettingsService::with_connection(self.connection.clone()).load()?;
+        let (patch, settings_report, retention_report) =
+            plan_settings(&current, &profile, strategy, allow_sensitive)?;
+        let (next, changed_settings) = merge_config_patch(&current, &Value::Object(patch))?;
+        if !changed_settings.is_empty() {
+            validate_config(&next)?;
+        }
+
+        let local_messages = MessageTemplateDao::with_connection(self.connection.clone())
+            .find_all()
+            .map_err(dao_error)?;
+        let (message_plan, message_report) = plan_templates(
+            ProfileSection::MessageTemplates,
+            &local_messages,
+            &profile.message_templates,
+            strategy,
+            |t| &t.id,
+            |a, b| a.doctor_id == b.doctor_id && a.category == b.category && a.title == b.title && a.content == b.content,
+        );
+        let local_records = RecordTemplateDao::with_connection(self.connection.clone())
+            .find_all()
+            .map_err(dao_error)?;
+        let (record_plan, record_report) = plan_templates(
+            ProfileSection::RecordTemplates,
+            &local_records,
+            &profile.record_templates,
+            strategy,
+            |t| &t.id,
+            |a, b| {
+                a.doctor_id == b.doctor_id
+                    && a.record_type == b.record_type
+                    && a.title == b.title
+                    && a.content == b.content
+                    && a.variables == b.variables
+            },
+        );
+
+        {
+            let conn = self.connection.lock().unwrap();
+            let tx = conn.unchecked_transaction()?;
+            if !changed_settings.is_empty() {
+                AppSettingsDao::save_in(&tx, &next, imported_by).map_err(dao_error)?;
+            }
+            if changed_settings.iter().any(|key| key == RETENTION_KEY) {
+                RetentionDao::save_policy_in(&tx, &next.retention).map_err(dao_error)?;
+            }
+            for template in &message_plan.upserts {
+                MessageTemplateDao::upsert_in(&tx, template).map_err(dao_error)?;
+            }
+            MessageTemplateDao::delete_in(&tx, &message_plan.removals).map_err(dao_error)?;
+            for template in &record_plan.upserts {
+                RecordTemplateDao::upsert_in(&tx, template).map_err(dao_error)?;
+            }
+            RecordTemplateDao::delete_in(&tx, &record_plan.removals).map_err(dao_error)?;
+            tx.commit()?;
+        }
+
+        tracing::info!(
+            "Workstation profile imported ({}) by {:?}: settings {:?}, {} message templates, {} record templates",
+            strategy.as_str(),
+            imported_by,
+            changed_settings,
+            message_plan.upserts.len(),
+            record_plan.upserts.len()
+        );
+        Ok(ProfileImportReport {
+            strategy,
+            sections: vec![settings_report, retention_report, message_report, record_report],
+            changed_settings,
+        })
+    }
+}
+
+impl Default for WorkstationProfileService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 先校验签名再解析内容，口令错误和文件被修改同样拒绝
+pub fn read_profile(path: &Path, passphrase: &str) -> Result<WorkstationProfile, AppError> {
+    let content = std::fs::read(path)?;
+    let signed: SignedWorkstationProfile =
+        serde_json::from_slice(&content).map_err(|e| AppError::invalid_argument(format!("不是有效的配置包: {}", e)))?;
+
+    let salt = signed
+        .profile
+        .get("salt")
+        .and_then(Value::as_str)
+        .and_then(|salt| general_purpose::STANDARD.decode(salt).ok())
+        .ok_or_else(|| AppError::invalid_argument("配置包缺少签名盐值"))?;
+    let payload = serde_json::to_vec(&signed.profile).map_err(system_error)?;
+    if !verify_passphrase_signature(passphrase, &salt, &payload, &signed.signature)? {
+        tracing::warn!("Workstation profile signature rejected: {}", path.display());
+        return Err(AppError::new(ErrorType::ValidationError, "配置包签名校验失败：口令错误或文件已被修改")
+            .with_code(CODE_PROFILE_SIGNATURE_INVALID)
+            .with_retryable(false));
+    }
+
+    let format = signed.profile.get("format").and_then(Value::as_str);
+    let schema_version = signed.profile.get("schemaVersion").and_then(Value::as_u64);
+    let config_version = signed.profile.get("appConfigSchemaVersion").and_then(Value::as_i64);
+    if format != Some(WORKSTATION_PROFILE_FORMAT)
+        || schema_version != Some(WORKSTATION_PROFILE_SCHEMA_VERSION as u64)
+        || config_version.is_none_or(|version| version > APP_CONFIG_SCHEMA_VERSION)
+    {
+        return Err(AppError::new(
+            ErrorType::ValidationError,
+            format!(
+                "配置包版本不受支持: {:?} v{:?}（应用配置 v{:?}）",
+                format, schema_version, config_version
+            ),
+        )
+        .with_code(CODE_PROFILE_VERSION_UNSUPPORTED)
+        .with_details(serde_json::json!({
+            "schemaVersion": WORKSTATION_PROFILE_SCHEMA_VERSION,
+            "appConfigSchemaVersion": APP_CONFIG_SCHEMA_VERSION,
+        }))
+        .with_retryable(false));
+    }
+
+    serde_json::from_value(signed.profile).map_err(|e| AppError::invalid_argument(format!("配置包内容无效: {}", e)))
+}
+
+// 逐个顶层配置项比较：相同的跳过；merge 时本机已改过（不同于默认值）的保留本机设置
+fn plan_settings(
+    current: &AppConfig,
+    profile: &WorkstationProfile,
+    strategy: ProfileMergeStrategy,
+    allow_sensitive: bool,
+) -> Result<(Map<String, Value>, ProfileSectionReport, ProfileSectionReport), AppError> {
+    let current_value = serde_json::to_value(current).map_err(system_error)?;
+    let defaults = serde_json::to_value(AppConfig::default()).map_err(system_error)?;
+
+    let mut incoming = profile.app_settings.clone();
+    incoming.insert(
+        RETENTION_KEY.to_string(),
+        serde_json::to_value(&profile.retention_policy).map_err(system_error)?,
+    );
+
+    let mut settings_report = ProfileSectionReport::new(ProfileSection::AppSettings);
+    let mut retention_report = ProfileSectionReport::new(ProfileSection::RetentionPolicy);
+    let mut patch = Map::new();
+    for (key, value) in incoming {
+        let report = if key == RETENTION_KEY {
+            &mut retention_report
+        } else {
+            &mut settings_report
+        };
+        let local = current_value.get(&key);
+        if local == Some(&value) {
+            report.unchanged += 1;
+            continue;
+        }
+        if !allow_sensitive && SECURITY_SETTING_KEYS.contains(&key.as_str()) {
+            report.conflicts.push(ProfileConflict {
+                key,
+                reason: "需要安全管理权限，未导入".to_string(),
+            });
+            continue;
+        }
+        if strategy == ProfileMergeStrategy::Merge && local.is_some() && local != defaults.get(&key) {
+            report.conflicts.push(ProfileConflict {
+                key,
+                reason: "本机已修改该配置，保留本机设置".to_string(),
+            });
+            continue;
+        }
+        patch.insert(key, value);
+        report.updated += 1;
+    }
+
+    Ok((patch, settings_report, retention_report))
+}
+
+// 模板按 id 对应；merge 时内容不同的保留本机版本，replace 时以配置包为准并删除本机多出的模板
+fn plan_templates<T: Clone>(
+    section: ProfileSection,
+    local: &[T],
+    incoming: &[T],
+    strategy: ProfileMergeStrategy,
+    id: impl Fn(&T) -> &String,
+    same: impl Fn(&T, &T) -> bool,
+) -> (TemplatePlan<T>, ProfileSectionReport) {
+    let local_by_id: HashMap<&String, &T> = local.iter().map(|t| (id(t), t)).collect();
+    let mut report = ProfileSectionReport::new(section);
+    let mut upserts = Vec::new();
+
+    for template in incoming {
+        match local_by_id.get(id(template)) {
+            None => {
+                upserts.push(template.clone());
+                report.imported += 1;
+            }
+            Some(existing) if same(existing, template) => report.unchanged += 1,
+            Some(_) if strategy == ProfileMergeStrategy::Replace => {
+                upserts.push(template.clone());
+                report.updated += 1;
+            }
+            Some(_) => report.conflicts.push(ProfileConflict {
+                key: id(template).clone(),
+                reason: "本机模板内容不同，保留本机版本".to_string(),
+            }),
+        }
+    }
+
+    let mut removals = Vec::new();
+    if strategy == ProfileMergeStrategy::Replace {
+        let incoming_ids: HashSet<&String> = incoming.iter().map(&id).collect();
+        removals = local
+            .iter()
+            .map(&id)
+            .filter(|local_id| !incoming_ids.contains(local_id))
+            .cloned()
+            .collect();
+        report.removed = removals.len();
+    }
+
+    (TemplatePlan { upserts, removals }, report)
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), AppError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+        return Err(AppError::invalid_argument(
+            MessageKey::ProfilePassphraseTooShort.text(&[&MIN_PASSPHRASE_LENGTH.to_string()]),
+        ));
+    }
+    Ok(())
+}
+
+fn system_error(err: serde_json::Error) -> AppError {
+    AppError::new(ErrorType::SystemError, err.to_string())
+}
+
+fn dao_error(err: Box<dyn std::error::Error>) -> AppError {
+    AppError::new(ErrorType::SystemError, format!("数据库操作失败: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MigrationManager;
+    use crate::models::{MessageTemplate, RecordTemplate, TemplateVariable};
+    use crate::utils::CODE_INVALID_ARGUMENT;
+    use rusqlite::Connection;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    const PASSPHRASE: &str = "rollout-2026";
+
+    fn connection() -> DbConnection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        MigrationManager::new().run_migrations(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn message_template(doctor_id: &str, title: &str, content: &str) -> MessageTemplate {
+        MessageTemplate {
+            id: String::new(),
+            doctor_id: doctor_id.to_string(),
+            category: "general".to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            usage_count: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn record_template(doctor_id: &str, title: &str) -> RecordTemplate {
+        RecordTemplate {
+            id: String::new(),
+            doctor_id: doctor_id.to_string(),
+            record_type: "diagnosis".to_string(),
+            title: title.to_string(),
+            content: "主诉：{{complaint}}".to_string(),
+            variables: vec![TemplateVariable {
+                name: "complaint".to_string(),
+                label: Some("主诉".to_string()),
+                default_value: None,
+            }],
+            created_at: Utc::now(),
+        }
+    }
+
+    // 源工作站：改过服务器地址、窗口上限和保留天数，各有两个模板
+    fn configured_source() -> DbConnection {
+        let connection = connection();
+        AppSettingsService::with_connection(connection.clone())
+            .update(
+                &json!({
+                    "apiBaseUrl": "https://his.hospital.example/api",
+                    "windowLimits": { "maxWindows": 12 },
+                    "retention": { "messageDays": 365 },
+                }),
+                Some("admin"),
+            )
+            .unwrap();
+        let messages = MessageTemplateDao::with_connection(connection.clone());
+        messages.create(&message_template("d1", "问候", "您好，请描述症状")).unwrap();
+        messages.create(&message_template("d2", "复诊", "请按时复诊")).unwrap();
+        let records = RecordTemplateDao::with_connection(connection.clone());
+        records.create(&record_template("d1", "上呼吸道感染")).unwrap();
+        records.create(&record_template("d2", "高血压随访")).unwrap();
+        connection
+    }
+
+    fn section(report: &ProfileImportReport, section: ProfileSection) -> &ProfileSectionReport {
+        report.sections.iter().find(|s| s.section == section).unwrap()
+    }
+
+    #[test]
+    fn test_export_wipe_import_round_trip() {
+        let source = configured_source();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json");
+
+        let exported = WorkstationProfileService::with_connection(source.clone())
+            .export_profile(&path, PASSPHRASE, Some("admin"))
+            .unwrap();
+        assert_eq!(exported.message_template_count, 2);
+        assert_eq!(exported.record_template_count, 2);
+
+        // 源库清空后从配置包恢复
+        source
+            .lock()
+            .unwrap()
+            .execute_batch("DELETE FROM app_settings; DELETE FROM retention_policy; DELETE FROM message_templates; DELETE FROM record_templates;")
+            .unwrap();
+        let service = WorkstationProfileService::with_connection(source.clone());
+        let report = service
+            .import_profile(&path, PASSPHRASE, ProfileMergeStrategy::Replace, true, Some("admin"))
+            .unwrap();
+
+        assert_eq!(section(&report, ProfileSection::MessageTemplates).imported, 2);
+        assert_eq!(section(&report, ProfileSection::RecordTemplates).imported, 2);
+        assert_eq!(section(&report, ProfileSection::AppSettings).updated, 2);
+        assert_eq!(section(&report, ProfileSection::RetentionPolicy).updated, 1);
+        assert!(report.sections.iter().all(|s| s.conflicts.is_empty()));
+
+        let config = AppSettingsService::with_connection(source.clone()).load().unwrap();
+        assert_eq!(config.api_base_url, "https://his.hospital.example/api");
+        assert_eq!(config.window_limits.max_windows, 12);
+        assert_eq!(config.retention.message_days, 365);
+        let records = RecordTemplateDao::with_connection(source.clone()).find_by_doctor("d1", None).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].variables[0].name, "complaint");
+        assert_eq!(MessageTemplateDao::with_connection(source.clone()).find_all().unwrap().len(), 2);
+
+        // 再次导入没有任何变化
+        let report = service
+            .import_profile(&path, PASSPHRASE, ProfileMergeStrategy::Replace, true, Some("admin"))
+            .unwrap();
+        assert!(report.changed_settings.is_empty());
+        assert!(report.sections.iter().all(|s| s.imported == 0 && s.updated == 0 && s.removed == 0));
+    }
+
+    #[test]
+    fn test_tampered_or_wrong_passphrase_rejected() {
+        let source = configured_source();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json");
+        let service = WorkstationProfileService::with_connection(source);
+        service.export_profile(&path, PASSPHRASE, None).unwrap();
+
+        let target = connection();
+        let target_service = WorkstationProfileService::with_connection(target.clone());
+        let err = target_service
+            .import_profile(&path, "wrong-passphrase", ProfileMergeStrategy::Replace, true, None)
+            .unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_PROFILE_SIGNATURE_INVALID));
+
+        // 修改服务器地址后签名不再匹配，目标库保持不变
+        let content = std::fs::read_to_string(&path).unwrap();
+        let tampered = content.replace("https://his.hospital.example/api", "https://evil.example/api");
+        assert_ne!(tampered, content);
+        std::fs::write(&path, tampered).unwrap();
+        let err = target_service
+            .import_profile(&path, PASSPHRASE, ProfileMergeStrategy::Replace, true, None)
+            .unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_PROFILE_SIGNATURE_INVALID));
+        assert_eq!(
+            AppSettingsService::with_connection(target.clone()).load().unwrap().api_base_url,
+            AppConfig::default().api_base_url
+        );
+        assert!(MessageTemplateDao::with_connection(target).find_all().unwrap().is_empty());
+
+        let err = service.export_profile(&path, "short", None).unwrap_err();
+        assert_eq!(err.code.as_deref(), Some(CODE_INVALID_ARGUMENT));
+    }
+
+    #[test]
+    fn test_merge_keeps_local_changes_and_sensitive_settings() {
+        let source = configured_source();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json");
+        WorkstationProfileService::with_connection(source.clone())
+            .export_profile(&path, PASSPHRASE, None)
+            .unwrap();
+        let exported = MessageTemplateDao::with_connection(source).find_all().unwrap();
+
+        // 目标工作站自己改过窗口上限，并且有一个同 id 但内容不同的模板
+        let target = connection();
+        AppSettingsService::with_connection(target.clone())
+            .update(&json!({ "windowLimits": { "maxWindows": 6 } }), None)
+            .unwrap();
+        let mut local = exported[0].clone();
+        local.content = "本机修改过的内容".to_string();
+        MessageTemplateDao::upsert_in(&target.lock().unwrap(), &local).unwrap();
+        MessageTemplateDao::with_connection(target.clone())
+            .create(&message_template("d3", "本机模板", "仅本机使用"))
+            .unwrap();
+
+        let report = WorkstationProfileService::with_connection(target.clone())
+            .import_profile(&path, PASSPHRASE, ProfileMergeStrategy::Merge, false, None)
+            .unwrap();
+
+        let settings = section(&report, ProfileSection::AppSettings);
+        let conflict_keys: Vec<&str> = settings.conflicts.iter().map(|c| c.key.as_str()).collect();
+        assert!(conflict_keys.contains(&"apiBaseUrl"));
+        assert!(conflict_keys.contains(&"windowLimits"));
+        assert_eq!(section(&report, ProfileSection::RetentionPolicy).conflicts[0].key, "retention");
+
+        let messages = section(&report, ProfileSection::MessageTemplates);
+        assert_eq!(messages.imported, 1);
+        assert_eq!(messages.removed, 0);
+        assert_eq!(messages.conflicts[0].key, local.id);
+
+        let config = AppSettingsService::with_connection(target.clone()).load().unwrap();
+        assert_eq!(config.api_base_url, AppConfig::default().api_base_url);
+        assert_eq!(config.window_limits.max_windows, 6);
+        assert_eq!(config.retention, AppConfig::default().retention);
+        let templates = MessageTemplateDao::with_connection(target.clone()).find_all().unwrap();
+        assert_eq!(templates.len(), 3);
+        assert!(templates.iter().any(|t| t.content == "本机修改过的内容"));
+
+        // replace 以配置包为准，删除本机多出的模板
+        let report = WorkstationProfileService::with_connection(target.clone())
+            .import_profile(&path, PASSPHRASE, ProfileMergeStrategy::Replace, true, None)
+            .unwrap();
+        let messages = section(&report, ProfileSection::MessageTemplates);
+        assert_eq!((messages.updated, messages.removed), (1, 1));
+        assert!(report.changed_settings.contains(&"apiBaseUrl".to_string()));
+        let config = AppSettingsService::with_connection(target.clone()).load().unwrap();
+        assert_eq!(config.window_limits.max_windows, 12);
+        assert_eq!(config.retention.message_days, 365);
+    }
+}
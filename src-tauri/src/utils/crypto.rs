@@ -5,19 +5,56 @@ use aes_gcm::aead::{Aead, OsRng, AeadCore};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::{rand_core::RngCore, SaltString}};
 use anyhow::Result;
 use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+// 字段级加密的格式标记，没有该前缀的值视为历史明文
+pub const ENCRYPTED_FIELD_PREFIX: &str = "enc:v1:";
 
 pub struct CryptoService {
     cipher: Aes256Gcm,
+    index_key: Zeroizing<Vec<u8>>,
+    master_key: Zeroizing<Vec<u8>>,
+    // 本次会话已派生的子密钥，退出登录时清零
+    session_keys: Mutex<HashMap<String, Zeroizing<[u8; 32]>>>,
 }
 
 impl CryptoService {
+    // 固定密钥仅用于测试，运行时的密钥来自系统钥匙串（见 keychain::local_data_crypto）
+    #[cfg(test)]
     pub fn new() -> Self {
-        // 在实际应用中，密钥应该从环境变量或配置文件中读取
-        let key_bytes = b"an example very very secret key."; // 32 bytes for AES-256
-        let key = Key::<Aes256Gcm>::from_slice(key_bytes);
+        Self::with_master_key(b"an example very very secret key.") // 32 bytes for AES-256
+    }
+
+    // 使用外部保管的主密钥（如系统钥匙串中的会话密钥），查询索引密钥由主密钥派生
+    pub fn with_master_key(key_bytes: &[u8; 32]) -> Self {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key_bytes).expect("HMAC accepts any key length");
+        mac.update(b"blind-index");
+        let index_key: Zeroizing<[u8; 32]> = Zeroizing::new(mac.finalize().into_bytes().into());
+        Self::with_keys(key_bytes, &index_key)
+    }
+
+    // 主密钥和查询索引使用的 HMAC 密钥分别保管
+    pub fn with_keys(master_key: &[u8; 32], index_key: &[u8; 32]) -> Self {
+        let key = Key::<Aes256Gcm>::from_slice(master_key);
         let cipher = Aes256Gcm::new(key);
 
-        Self { cipher }
+        Self {
+            cipher,
+            index_key: Zeroizing::new(index_key.to_vec()),
+            master_key: Zeroizing::new(master_key.to_vec()),
+            session_keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 生成随机的 32 字节密钥
+    pub fn generate_key() -> Zeroizing<[u8; 32]> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(key.as_mut());
+        key
     }
 
     pub fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>> {
@@ -56,6 +93,61 @@ impl CryptoService {
         Ok(String::from_utf8(decrypted)?)
     }
 
+    // 加密单个字段，结果带格式标记
+    pub fn encrypt_field(&self, value: &str) -> Result<String> {
+        Ok(format!("{}{}", ENCRYPTED_FIELD_PREFIX, self.encrypt_string(value)?))
+    }
+
+    // 解密单个字段，历史明文原样返回
+    pub fn decrypt_field(&self, stored: &str) -> Result<String> {
+        match stored.strip_prefix(ENCRYPTED_FIELD_PREFIX) {
+            Some(encrypted) => self.decrypt_string(encrypted),
+            None => Ok(stored.to_string()),
+        }
+    }
+
+    pub fn is_encrypted_field(stored: &str) -> bool {
+        stored.starts_with(ENCRYPTED_FIELD_PREFIX)
+    }
+
+    // 带密钥的 HMAC-SHA256，用于加密字段的等值查询
+    pub fn blind_index(&self, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key).expect("HMAC accepts any key length");
+        mac.update(value.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    // 由主密钥按用途派生独立的 32 字节子密钥（如整库加密密钥），主密钥本身不离开本服务
+    pub fn derive_key(&self, purpose: &str) -> [u8; 32] {
+        let mut session_keys = self.session_keys.lock().unwrap();
+        if let Some(key) = session_keys.get(purpose) {
+            return **key;
+        }
+
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.master_key).expect("HMAC accepts any key length");
+        mac.update(purpose.as_bytes());
+        let key: [u8; 32] = mac.finalize().into_bytes().into();
+        session_keys.insert(purpose.to_string(), Zeroizing::new(key));
+        key
+    }
+
+    // 清零并丢弃本次会话派生的子密钥，返回丢弃的数量；之后再用到时重新派生
+    pub fn purge_session_keys(&self) -> usize {
+        let mut session_keys = self.session_keys.lock().unwrap();
+        let purged = session_keys.len();
+        // Zeroizing 在 drop 时清零内存
+        session_keys.clear();
+        purged
+    }
+
+    pub fn session_key_count(&self) -> usize {
+        self.session_keys.lock().unwrap().len()
+    }
+
     pub fn hash_password(&self, password: &str) -> Result<String> {
         let salt = SaltString::generate(&mut OsRng);
         let argon2 = Argon2::default();
@@ -84,17 +176,48 @@ impl CryptoService {
     }
 }
 
-// 保持向后兼容的函数
+// 保持向后兼容的函数；口令哈希不使用加密密钥，这里用临时密钥即可
 pub fn hash_password(password: &str) -> Result<String> {
-    let crypto = CryptoService::new();
+    let crypto = CryptoService::with_master_key(&CryptoService::generate_key());
     crypto.hash_password(password)
 }
 
 pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
-    let crypto = CryptoService::new();
+    let crypto = CryptoService::with_master_key(&CryptoService::generate_key());
     crypto.verify_password(password, hash)
 }
 
+// 配置包等离线文件的签名：由口令和随机盐经 argon2 派生密钥，再计算 HMAC-SHA256，结果为 base64
+pub fn sign_with_passphrase(passphrase: &str, salt: &[u8], data: &[u8]) -> Result<String> {
+    let mac = passphrase_mac(passphrase, salt, data)?;
+    Ok(general_purpose::STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+// 口令错误和内容被修改都返回 false，比较为常量时间
+pub fn verify_passphrase_signature(passphrase: &str, salt: &[u8], data: &[u8], signature: &str) -> Result<bool> {
+    let expected = match general_purpose::STANDARD.decode(signature) {
+        Ok(expected) => expected,
+        Err(_) => return Ok(false),
+    };
+    Ok(passphrase_mac(passphrase, salt, data)?.verify_slice(&expected).is_ok())
+}
+
+fn passphrase_mac(passphrase: &str, salt: &[u8], data: &[u8]) -> Result<Hmac<Sha256>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key[..]).expect("HMAC accepts any key length");
+    mac.update(data);
+    Ok(mac)
+}
+
+pub fn random_salt() -> [u8; 16] {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +233,53 @@ mod tests {
         assert_eq!(original, decrypted);
     }
 
+    #[test]
+    fn test_field_encryption_keeps_legacy_plaintext() {
+        let crypto = CryptoService::new();
+
+        let encrypted = crypto.encrypt_field("13800138000").unwrap();
+        assert!(CryptoService::is_encrypted_field(&encrypted));
+        assert_ne!(encrypted, crypto.encrypt_field("13800138000").unwrap());
+        assert_eq!(crypto.decrypt_field(&encrypted).unwrap(), "13800138000");
+
+        assert_eq!(crypto.decrypt_field("13800138000").unwrap(), "13800138000");
+        assert_eq!(crypto.blind_index("13800138000"), crypto.blind_index("13800138000"));
+        assert_ne!(crypto.blind_index("13800138000"), crypto.blind_index("13800138001"));
+    }
+
+    #[test]
+    fn test_blind_index_depends_on_index_key() {
+        let master = CryptoService::generate_key();
+        let crypto = CryptoService::with_keys(&master, &CryptoService::generate_key());
+        let other_index = CryptoService::with_keys(&master, &CryptoService::generate_key());
+
+        assert_ne!(crypto.blind_index("13800138000"), other_index.blind_index("13800138000"));
+        assert_ne!(crypto.blind_index("13800138000"), CryptoService::new().blind_index("13800138000"));
+        // 只换索引密钥不影响加解密
+        let encrypted = crypto.encrypt_field("13800138000").unwrap();
+        assert_eq!(other_index.decrypt_field(&encrypted).unwrap(), "13800138000");
+    }
+
+    #[test]
+    fn test_derived_keys_stable_per_purpose() {
+        let crypto = CryptoService::new();
+
+        assert_eq!(crypto.derive_key("sqlcipher"), CryptoService::new().derive_key("sqlcipher"));
+        assert_ne!(crypto.derive_key("sqlcipher"), crypto.derive_key("backup"));
+    }
+
+    #[test]
+    fn test_purge_session_keys_rederives_same_key() {
+        let crypto = CryptoService::new();
+        let key = crypto.derive_key("sqlcipher");
+        crypto.derive_key("backup");
+        assert_eq!(crypto.session_key_count(), 2);
+
+        assert_eq!(crypto.purge_session_keys(), 2);
+        assert_eq!(crypto.session_key_count(), 0);
+        assert_eq!(crypto.derive_key("sqlcipher"), key);
+    }
+
     #[test]
     fn test_password_hash_verify() {
         let crypto = CryptoService::new();
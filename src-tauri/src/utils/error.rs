@@ -1,132 +1,414 @@
 // 错误处理工具
+//
+// 命令统一返回 models::AppError，前端按 type / code / retryable 决定提示方式，不再匹配错误文本
 
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use crate::database::dao::ConflictError;
+use crate::models::ValidationViolation as ViolationPayload;
+use crate::models::{AttachmentScope, AttachmentUsage};
+use crate::utils::i18n::{active_locale, Locale, MessageKey};
+use crate::utils::{ValidationResult, ValidationService};
+use rusqlite::ErrorCode;
 
-#[derive(Error, Debug, Serialize, Deserialize)]
-pub enum AppError {
-    #[error("网络请求失败: {message}")]
-    NetworkError { message: String },
+pub use crate::models::{AppError, ErrorType};
 
-    #[error("数据库操作失败: {message}")]
-    DatabaseError { message: String },
+pub const CODE_DB_LOCKED: &str = "DB_LOCKED";
+pub const CODE_DB_BUSY: &str = "DB_BUSY";
+pub const CODE_DB_ERROR: &str = "DB_ERROR";
+pub const CODE_DB_NOT_READY: &str = "DB_NOT_READY";
+pub const CODE_DB_KEY_INVALID: &str = "DB_KEY_INVALID";
+pub const CODE_DATA_KEY_UNAVAILABLE: &str = "DATA_KEY_UNAVAILABLE";
+pub const CODE_IO_ERROR: &str = "IO_ERROR";
+pub const CODE_NETWORK_ERROR: &str = "NETWORK_ERROR";
+pub const CODE_WS_NOT_CONNECTED: &str = "WS_NOT_CONNECTED";
+pub const CODE_WS_MESSAGE_TOO_LARGE: &str = "WS_MESSAGE_TOO_LARGE";
+pub const CODE_MESSAGE_QUEUED: &str = "MESSAGE_QUEUED";
+pub const CODE_RATE_LIMITED: &str = "RATE_LIMITED";
+pub const CODE_UPDATE_SIGNATURE_INVALID: &str = "UPDATE_SIGNATURE_INVALID";
+pub const CODE_UPDATE_CHECKSUM_MISMATCH: &str = "UPDATE_CHECKSUM_MISMATCH";
+pub const CODE_VALIDATION_FAILED: &str = "VALIDATION_FAILED";
+pub const CODE_INVALID_ARGUMENT: &str = "INVALID_ARGUMENT";
+pub const CODE_STALE_WRITE: &str = "STALE_WRITE";
+pub const CODE_QUOTA_EXCEEDED: &str = "QUOTA_EXCEEDED";
+pub const CODE_TRASH_ITEM_NOT_FOUND: &str = "TRASH_ITEM_NOT_FOUND";
+pub const CODE_UNLOCK_PIN_INCORRECT: &str = "UNLOCK_PIN_INCORRECT";
+pub const CODE_REAUTH_REQUIRED: &str = "REAUTH_REQUIRED";
+pub const CODE_PROFILE_SIGNATURE_INVALID: &str = "PROFILE_SIGNATURE_INVALID";
+pub const CODE_PROFILE_VERSION_UNSUPPORTED: &str = "PROFILE_VERSION_UNSUPPORTED";
+pub const CODE_WINDOW_TYPE_UNKNOWN: &str = "WINDOW_TYPE_UNKNOWN";
+pub const CODE_CONFIRMATION_REQUIRED: &str = "CONFIRMATION_REQUIRED";
+pub const CODE_FILE_TOO_LARGE: &str = "FILE_TOO_LARGE";
+pub const CODE_UPLOAD_TRANSFER_NOT_FOUND: &str = "UPLOAD_TRANSFER_NOT_FOUND";
+pub const CODE_UPLOAD_CHUNK_OUT_OF_ORDER: &str = "UPLOAD_CHUNK_OUT_OF_ORDER";
 
-    #[error("认证失败: {message}")]
-    AuthError { message: String },
+// 结果类型别名
+pub type AppResult<T> = Result<T, AppError>;
 
-    #[error("验证失败: {message}")]
-    ValidationError { message: String },
+impl AppError {
+    pub fn database_error(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::SystemError, message)
+            .with_code(CODE_DB_ERROR)
+            .with_retryable(false)
+    }
 
-    #[error("文件操作失败: {message}")]
-    FileError { message: String },
+    // 写入多次重试后数据库仍被其他连接占用，稍后重试即可
+    pub fn database_busy(operation: &str, attempts: u32) -> Self {
+        AppError::new(ErrorType::SystemError, MessageKey::DatabaseBusy)
+            .with_code(CODE_DB_BUSY)
+            .with_details(serde_json::json!({ "operation": operation, "attempts": attempts }))
+            .with_retryable(true)
+    }
 
-    #[error("权限不足: {message}")]
-    PermissionError { message: String },
+    // 启动时数据库迁移尚未完成，稍后重试即可
+    pub fn database_not_ready(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::SystemError, message)
+            .with_code(CODE_DB_NOT_READY)
+            .with_retryable(true)
+    }
 
-    #[error("未知错误: {message}")]
-    UnknownError { message: String },
-}
+    // 加密数据库无法用当前密钥解开，重试无效，需要恢复正确的密钥或备份
+    pub fn database_key_invalid(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::SystemError, message)
+            .with_code(CODE_DB_KEY_INVALID)
+            .with_retryable(false)
+    }
 
-impl AppError {
-    pub fn network_error(message: impl Into<String>) -> Self {
-        Self::NetworkError {
-            message: message.into(),
-        }
+    // 系统钥匙串中的本地数据密钥无法读取，不能加解密患者敏感字段
+    pub fn data_key_unavailable(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::SystemError, message)
+            .with_code(CODE_DATA_KEY_UNAVAILABLE)
+            .with_retryable(false)
     }
 
-    pub fn database_error(message: impl Into<String>) -> Self {
-        Self::DatabaseError {
-            message: message.into(),
-        }
+    pub fn file_error(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::SystemError, message)
+            .with_code(CODE_IO_ERROR)
+            .with_retryable(false)
     }
 
-    pub fn auth_error(message: impl Into<String>) -> Self {
-        Self::AuthError {
-            message: message.into(),
-        }
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::ValidationError, message)
+            .with_code(CODE_INVALID_ARGUMENT)
+            .with_retryable(false)
     }
 
-    pub fn validation_error(message: impl Into<String>) -> Self {
-        Self::ValidationError {
-            message: message.into(),
-        }
+    // 导出诊断包等操作需要用户在前端确认后带上 confirmed 重新调用
+    pub fn confirmation_required(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::PermissionError, message)
+            .with_code(CODE_CONFIRMATION_REQUIRED)
+            .with_retryable(false)
     }
 
-    pub fn file_error(message: impl Into<String>) -> Self {
-        Self::FileError {
-            message: message.into(),
-        }
+    // 文件超过上限，在读取和处理文件内容之前返回；details 带文件大小和上限
+    pub fn file_too_large(size: u64, max_size: u64) -> Self {
+        let message = MessageKey::FileTooLarge.text(&[
+            &ValidationService::format_file_size(size),
+            &ValidationService::format_file_size(max_size),
+        ]);
+        Self::file_size_exceeded(message, size, max_size)
     }
 
-    pub fn permission_error(message: impl Into<String>) -> Self {
-        Self::PermissionError {
-            message: message.into(),
-        }
+    // 通过字节数组传入的文件超过内联上限，前端应改用分片上传
+    pub fn inline_upload_too_large(size: u64, max_size: u64) -> Self {
+        let message = MessageKey::InlineUploadTooLarge.text(&[&ValidationService::format_file_size(max_size)]);
+        Self::file_size_exceeded(message, size, max_size)
     }
 
-    pub fn unknown_error(message: impl Into<String>) -> Self {
-        Self::UnknownError {
-            message: message.into(),
-        }
+    fn file_size_exceeded(message: String, size: u64, max_size: u64) -> Self {
+        AppError::new(ErrorType::ValidationError, message)
+            .with_code(CODE_FILE_TOO_LARGE)
+            .with_details(serde_json::json!({ "sizeBytes": size, "maxBytes": max_size }))
+            .with_retryable(false)
+    }
+
+    pub fn upload_transfer_not_found() -> Self {
+        AppError::new(ErrorType::ValidationError, MessageKey::UploadTransferNotFound.text(&[]))
+            .with_code(CODE_UPLOAD_TRANSFER_NOT_FOUND)
+            .with_retryable(false)
+    }
+
+    // 分片顺序错误，传输保持不变，前端从 details.expectedIndex 开始重新发送
+    pub fn upload_chunk_out_of_order(expected_index: u32, index: u32) -> Self {
+        AppError::new(ErrorType::ValidationError, MessageKey::UploadChunkOutOfOrder.text(&[&expected_index, &index]))
+            .with_code(CODE_UPLOAD_CHUNK_OUT_OF_ORDER)
+            .with_details(serde_json::json!({ "expectedIndex": expected_index }))
+            .with_retryable(true)
+    }
+
+    pub fn ws_not_connected(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::NetworkError, message)
+            .with_code(CODE_WS_NOT_CONNECTED)
+            .with_retryable(true)
+    }
+
+    // 压缩后仍超过帧大小上限，前端应改为文件上传
+    pub fn ws_message_too_large(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::ValidationError, message)
+            .with_code(CODE_WS_MESSAGE_TOO_LARGE)
+            .with_retryable(false)
+    }
+
+    // 离线时消息已保存到本地待发送队列，恢复连接后自动发送；details 带消息 ID 和队列位置
+    pub fn message_queued(message_id: &str, queue_position: i64) -> Self {
+        AppError::new(ErrorType::NetworkError, MessageKey::MessageQueued.text(&[&queue_position]))
+            .with_code(CODE_MESSAGE_QUEUED)
+            .with_details(serde_json::json!({ "messageId": message_id, "queuePosition": queue_position }))
+            .with_retryable(false)
+    }
+
+    // 请求过于频繁，details.retryAfterSeconds 为距下次允许请求的秒数
+    pub fn rate_limited(message: impl Into<String>, retry_after_seconds: u64) -> Self {
+        AppError::new(ErrorType::PermissionError, message)
+            .with_code(CODE_RATE_LIMITED)
+            .with_details(serde_json::json!({ "retryAfterSeconds": retry_after_seconds }))
+            .with_retryable(true)
+    }
+
+    // 附件超出问诊或患者的配额，details 带当前用量、上限和本次需要的字节数供前端说明
+    pub fn quota_exceeded(usage: &AttachmentUsage, requested_bytes: u64) -> Self {
+        let key = match usage.scope {
+            AttachmentScope::Consultation => MessageKey::ConsultationAttachmentQuotaExceeded,
+            AttachmentScope::Patient => MessageKey::PatientAttachmentQuotaExceeded,
+        };
+        let message = key.text(&[
+            &ValidationService::format_file_size(usage.used_bytes),
+            &ValidationService::format_file_size(usage.limit_bytes),
+            &ValidationService::format_file_size(requested_bytes),
+        ]);
+        let mut details = serde_json::to_value(usage).unwrap_or_default();
+        details["requestedBytes"] = serde_json::json!(requested_bytes);
+
+        AppError::new(ErrorType::ValidationError, message)
+            .with_code(CODE_QUOTA_EXCEEDED)
+            .with_details(details)
+            .with_retryable(false)
+    }
+
+    // 更新清单签名校验失败，可能被篡改，不能重试
+    pub fn update_signature_invalid(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::DataError, message)
+            .with_code(CODE_UPDATE_SIGNATURE_INVALID)
+            .with_retryable(false)
+    }
+
+    // 安装包校验和不一致，通常是下载损坏，可重新下载
+    pub fn update_checksum_mismatch(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::DataError, message)
+            .with_code(CODE_UPDATE_CHECKSUM_MISMATCH)
+            .with_retryable(true)
+    }
+
+    // 记录已被其他窗口修改，命令层会在 details.current 中附上最新数据供前端合并
+    pub fn stale_write(message: impl Into<String>) -> Self {
+        AppError::new(ErrorType::DataError, message)
+            .with_code(CODE_STALE_WRITE)
+            .with_retryable(false)
+    }
+
+    pub fn is_stale_write(&self) -> bool {
+        self.code.as_deref() == Some(CODE_STALE_WRITE)
+    }
+
+    // 把最新数据放进 details.current，保留已有的冲突信息
+    pub fn with_current<T: serde::Serialize>(mut self, current: &T) -> Self {
+        let mut details = self.details.take().unwrap_or_else(|| serde_json::json!({}));
+        details["current"] = serde_json::to_value(current).unwrap_or_default();
+        self.with_details(details)
+    }
+
+    // 校验失败，逐字段的错误放在 details.violations 中
+    pub fn validation_failed(result: ValidationResult) -> Self {
+        let message = result
+            .errors
+            .iter()
+            .map(|v| v.message.as_str())
+            .collect::<Vec<_>>()
+            .join(match active_locale() {
+                Locale::ZhCn => "；",
+                Locale::EnUs => "; ",
+            });
+        let violations: Vec<ViolationPayload> = result
+            .errors
+            .into_iter()
+            .map(|v| ViolationPayload {
+                field: v.field,
+                message: v.message,
+                code: v.code,
+            })
+            .collect();
+
+        AppError::new(ErrorType::ValidationError, message)
+            .with_code(CODE_VALIDATION_FAILED)
+            .with_details(serde_json::json!({ "violations": violations }))
+            .with_retryable(false)
+    }
+
+    // 在原始错误信息前加上操作说明，类型和错误码保持不变
+    pub fn context(mut self, action: &str) -> Self {
+        self.message = format!("{}: {}", action, self.message);
+        self
     }
 
     pub fn is_retryable(&self) -> bool {
-        matches!(self, AppError::NetworkError { .. })
-    }
-
-    pub fn error_code(&self) -> &'static str {
-        match self {
-            AppError::NetworkError { .. } => "NETWORK_ERROR",
-            AppError::DatabaseError { .. } => "DATABASE_ERROR",
-            AppError::AuthError { .. } => "AUTH_ERROR",
-            AppError::ValidationError { .. } => "VALIDATION_ERROR",
-            AppError::FileError { .. } => "FILE_ERROR",
-            AppError::PermissionError { .. } => "PERMISSION_ERROR",
-            AppError::UnknownError { .. } => "UNKNOWN_ERROR",
-        }
+        self.retryable.unwrap_or(false)
+    }
+}
+
+impl From<ConflictError> for AppError {
+    fn from(err: ConflictError) -> Self {
+        AppError::stale_write(MessageKey::StaleWrite)
+            .with_details(serde_json::json!({ "entity": err.entity, "id": err.id, "expectedVersion": err.expected_version }))
     }
 }
 
 impl From<rusqlite::Error> for AppError {
     fn from(err: rusqlite::Error) -> Self {
-        AppError::database_error(err.to_string())
+        match err.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => {
+                AppError::new(ErrorType::SystemError, MessageKey::DatabaseBusy)
+                    .with_code(CODE_DB_LOCKED)
+                    .with_retryable(true)
+            }
+            _ => AppError::database_error(MessageKey::DatabaseOperationFailed.text(&[&err])),
+        }
     }
 }
 
 impl From<reqwest::Error> for AppError {
     fn from(err: reqwest::Error) -> Self {
-        AppError::network_error(err.to_string())
+        let retryable = err.is_timeout()
+            || err.is_connect()
+            || err.status().map(|s| s.is_server_error()).unwrap_or(false);
+
+        AppError::new(ErrorType::NetworkError, MessageKey::NetworkRequestFailed.text(&[&err]))
+            .with_code(CODE_NETWORK_ERROR)
+            .with_retryable(retryable)
     }
 }
 
 impl From<std::io::Error> for AppError {
     fn from(err: std::io::Error) -> Self {
-        AppError::file_error(err.to_string())
+        use std::io::ErrorKind;
+
+        match err.kind() {
+            ErrorKind::NotFound => AppError::new(ErrorType::DataError, MessageKey::FileNotFound.text(&[&err]))
+                .with_code("FILE_NOT_FOUND")
+                .with_retryable(false),
+            ErrorKind::PermissionDenied => AppError::new(ErrorType::PermissionError, MessageKey::FilePermissionDenied.text(&[&err]))
+                .with_code("FILE_PERMISSION_DENIED")
+                .with_retryable(false),
+            ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::WouldBlock => {
+                AppError::file_error(MessageKey::FileOperationFailed.text(&[&err])).with_retryable(true)
+            }
+            _ => AppError::file_error(MessageKey::FileOperationFailed.text(&[&err])),
+        }
     }
 }
 
 impl From<serde_json::Error> for AppError {
     fn from(err: serde_json::Error) -> Self {
-        AppError::validation_error(format!("JSON parsing error: {}", err))
+        AppError::new(ErrorType::DataError, MessageKey::DataParseFailed.text(&[&err]))
+            .with_code("INVALID_JSON")
+            .with_retryable(false)
     }
 }
 
 impl From<anyhow::Error> for AppError {
     fn from(err: anyhow::Error) -> Self {
-        AppError::unknown_error(err.to_string())
+        // 服务层用 anyhow 包装的已知错误，按原始类型还原
+        let err = match err.downcast::<AppError>() {
+            Ok(app_error) => return app_error,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ConflictError>() {
+            Ok(conflict) => return conflict.into(),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<rusqlite::Error>() {
+            Ok(db_error) => return db_error.into(),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<reqwest::Error>() {
+            Ok(http_error) => return http_error.into(),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<std::io::Error>() {
+            Ok(io_error) => return io_error.into(),
+            Err(err) => err,
+        };
+
+        AppError::new(ErrorType::UnknownError, err.to_string()).with_retryable(false)
     }
 }
 
-// 结果类型别名
-pub type AppResult<T> = Result<T, AppError>;
+// DAO 层返回的 Box<dyn Error>
+impl From<Box<dyn std::error::Error>> for AppError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        let err = match err.downcast::<AppError>() {
+            Ok(app_error) => return *app_error,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ConflictError>() {
+            Ok(conflict) => return (*conflict).into(),
+            Err(err) => err,
+        };
+        match err.downcast::<rusqlite::Error>() {
+            Ok(db_error) => (*db_error).into(),
+            Err(err) => AppError::database_error(MessageKey::DatabaseOperationFailed.text(&[&err])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-// 错误处理宏
-#[macro_export]
-macro_rules! app_error {
-    ($kind:ident, $msg:expr) => {
-        AppError::$kind { message: $msg.to_string() }
-    };
-    ($kind:ident, $fmt:expr, $($arg:tt)*) => {
-        AppError::$kind { message: format!($fmt, $($arg)*) }
-    };
-}
\ No newline at end of file
+    #[test]
+    fn test_busy_database_is_retryable() {
+        let busy = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        );
+        let error = AppError::from(busy);
+
+        assert_eq!(error.code.as_deref(), Some(CODE_DB_LOCKED));
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn test_anyhow_keeps_wrapped_app_error() {
+        let wrapped: anyhow::Error = AppError::ws_not_connected("WebSocket 未连接").into();
+        let error = AppError::from(wrapped.context("发送消息失败"));
+
+        assert_eq!(error.code.as_deref(), Some(CODE_WS_NOT_CONNECTED));
+        assert!(matches!(error.error_type, ErrorType::NetworkError));
+    }
+
+    #[test]
+    fn test_validation_failed_serializes_violations() {
+        let mut result = ValidationResult::new();
+        result.add_error("phone", "手机号格式不正确", "INVALID_PHONE");
+        result.add_error("name", "姓名不能为空", "REQUIRED");
+
+        let payload = serde_json::to_value(AppError::validation_failed(result)).unwrap();
+
+        assert_eq!(payload["type"], "VALIDATION_ERROR");
+        assert_eq!(payload["code"], CODE_VALIDATION_FAILED);
+        assert_eq!(payload["details"]["violations"][0]["field"], "phone");
+        assert_eq!(payload["details"]["violations"][1]["code"], "REQUIRED");
+    }
+
+    #[test]
+    fn test_conflict_maps_to_stale_write() {
+        let boxed: Box<dyn std::error::Error> = Box::new(ConflictError::new("patient", "p1", 3));
+        let error = AppError::from(boxed).with_current(&serde_json::json!({ "id": "p1", "version": 4 }));
+
+        assert!(error.is_stale_write());
+        assert!(!error.is_retryable());
+        let details = error.details.unwrap();
+        assert_eq!(details["expectedVersion"], 3);
+        assert_eq!(details["current"]["version"], 4);
+
+        let wrapped = anyhow::Error::new(ConflictError::new("consultation", "c1", 1));
+        assert!(AppError::from(wrapped).is_stale_write());
+    }
+}
@@ -0,0 +1,467 @@
+// 后端提示文案的多语言目录：校验和错误信息按 MessageKey 取当前语言的文本，缺少译文时使用简体中文
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en-US")]
+    EnUs,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::ZhCn, Locale::EnUs];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::EnUs => "en-US",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Locale> {
+        Self::ALL.into_iter().find(|locale| locale.as_str().eq_ignore_ascii_case(value))
+    }
+}
+
+// 由 set_locale 命令和启动时加载的配置设置，进程内所有提示共用
+static ACTIVE_LOCALE: AtomicU8 = AtomicU8::new(0);
+
+pub fn active_locale() -> Locale {
+    Locale::ALL
+        .get(ACTIVE_LOCALE.load(Ordering::Relaxed) as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+pub fn set_active_locale(locale: Locale) {
+    let index = Locale::ALL.iter().position(|l| *l == locale).unwrap_or(0);
+    ACTIVE_LOCALE.store(index as u8, Ordering::Relaxed);
+}
+
+/// 提示文案的键，文本中的 `{}` 依次由参数替换
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    // 登录
+    UsernameRequired,
+    PasswordTooShort,
+    PasswordRequired,
+    PhoneInvalid,
+    PhoneRequired,
+    SmsCodeInvalid,
+    SmsCodeRequired,
+    UnlockPinInvalid,
+    IdCardInvalid,
+    IdCardRequired,
+    // 患者
+    PatientNameRequired,
+    PatientNameTooLong,
+    PatientNameInvalid,
+    AgeOutOfRange,
+    IdCardGenderMismatch,
+    IdCardAgeMismatch,
+    // 消息
+    ConsultationIdRequired,
+    MessageContentRequired,
+    MessageContentTooLong,
+    FileIdRequired,
+    MessageTypeUnsupported,
+    // 查询
+    PageMustBePositive,
+    PageSizeOutOfRange,
+    AgeRangeOutOfRange,
+    AgeRangeInverted,
+    SortFieldUnsupported,
+    DateRangeInverted,
+    DateRangeStartInFuture,
+    // 窗口和应用配置
+    WindowTitleRequired,
+    WindowUrlRequired,
+    WindowWidthTooSmall,
+    WindowHeightTooSmall,
+    ApiBaseUrlInvalid,
+    WsUrlInvalid,
+    UpdateManifestUrlInvalid,
+    MaxFileSizeOutOfRange,
+    AllowedFileTypesRequired,
+    RetryAttemptsTooMany,
+    MaxWindowsOutOfRange,
+    ConsultationWindowsOutOfRange,
+    AutoLockTimeoutOutOfRange,
+    ConsultationInactivityOutOfRange,
+    AttachmentQuotaOutOfRange,
+    MessageWarmupCountOutOfRange,
+    UploadCompressionDimensionOutOfRange,
+    UploadCompressionQualityOutOfRange,
+    TimezoneInvalid,
+    ProfilePassphraseTooShort,
+    WindowTypeUnknown,
+    ConsultationNoteTooLong,
+    CommandRateLimited,
+    CommandRateLimitInvalid,
+    // 文件
+    FileTooLarge,
+    ExecutableBlocked,
+    FileTypeUnsupported,
+    FileNameRequired,
+    ConsultationAttachmentQuotaExceeded,
+    PatientAttachmentQuotaExceeded,
+    InlineUploadTooLarge,
+    UploadTransferNotFound,
+    UploadChunkOutOfOrder,
+    UploadIncomplete,
+    // 病历和处方
+    PatientIdRequired,
+    DoctorIdRequired,
+    RecordTypeUnsupported,
+    RecordTitleRequired,
+    RecordTitleTooLong,
+    AttachmentFileIdRequired,
+    AttachmentDuplicate,
+    AttachmentNameRequired,
+    AttachmentNameTooLong,
+    AttachmentMimeTypeInvalid,
+    AttachmentEmpty,
+    AttachmentTooLarge,
+    AttachmentChecksumInvalid,
+    PrescriptionEmpty,
+    PrescriptionTooManyItems,
+    DrugNameRequired,
+    DrugNameTooLong,
+    FrequencyRequired,
+    DosageNotPositive,
+    DaysNotPositive,
+    // 账号和标签
+    UsernameTooShort,
+    UsernameTooLong,
+    UsernameInvalidChars,
+    PasswordBelowMinimum,
+    PasswordTooLong,
+    PasswordTooWeak,
+    TagRequired,
+    TagTooLong,
+    TagInvalidChars,
+    // 通用错误
+    DatabaseBusy,
+    DatabaseOperationFailed,
+    NetworkRequestFailed,
+    FileNotFound,
+    FilePermissionDenied,
+    FileOperationFailed,
+    DataParseFailed,
+    StaleWrite,
+    MessageQueued,
+    LocaleUnsupported,
+}
+
+impl MessageKey {
+    fn zh_cn(&self) -> &'static str {
+        match self {
+            MessageKey::UsernameRequired => "用户名不能为空",
+            MessageKey::PasswordTooShort => "密码长度不能少于6位",
+            MessageKey::PasswordRequired => "密码不能为空",
+            MessageKey::PhoneInvalid => "手机号格式不正确",
+            MessageKey::PhoneRequired => "手机号不能为空",
+            MessageKey::SmsCodeInvalid => "验证码必须是6位数字",
+            MessageKey::SmsCodeRequired => "验证码不能为空",
+            MessageKey::UnlockPinInvalid => "解锁 PIN 必须是6位数字",
+            MessageKey::IdCardInvalid => "身份证号格式不正确",
+            MessageKey::IdCardRequired => "身份证号不能为空",
+            MessageKey::PatientNameRequired => "患者姓名不能为空",
+            MessageKey::PatientNameTooLong => "患者姓名不能超过50个字符",
+            MessageKey::PatientNameInvalid => "患者姓名格式不正确",
+            MessageKey::AgeOutOfRange => "年龄必须在0-150之间",
+            MessageKey::IdCardGenderMismatch => "身份证号与患者性别不一致",
+            MessageKey::IdCardAgeMismatch => "身份证号与患者年龄不一致",
+            MessageKey::ConsultationIdRequired => "问诊ID不能为空",
+            MessageKey::MessageContentRequired => "消息内容不能为空",
+            MessageKey::MessageContentTooLong => "消息内容不能超过{}个字符",
+            MessageKey::FileIdRequired => "文件ID不能为空",
+            MessageKey::MessageTypeUnsupported => "不支持的消息类型",
+            MessageKey::PageMustBePositive => "页码必须大于0",
+            MessageKey::PageSizeOutOfRange => "每页数量必须在1-100之间",
+            MessageKey::AgeRangeOutOfRange => "年龄范围必须在0-150之间",
+            MessageKey::AgeRangeInverted => "最小年龄不能大于最大年龄",
+            MessageKey::SortFieldUnsupported => "不支持的排序字段，可选值: {}",
+            MessageKey::DateRangeInverted => "开始时间不能晚于结束时间",
+            MessageKey::DateRangeStartInFuture => "开始时间不能是未来时间",
+            MessageKey::WindowTitleRequired => "窗口标题不能为空",
+            MessageKey::WindowUrlRequired => "窗口URL不能为空",
+            MessageKey::WindowWidthTooSmall => "窗口宽度不能小于200px",
+            MessageKey::WindowHeightTooSmall => "窗口高度不能小于150px",
+            MessageKey::ApiBaseUrlInvalid => "接口地址必须是有效的 http(s) URL",
+            MessageKey::WsUrlInvalid => "WebSocket 地址必须是有效的 ws(s) URL",
+            MessageKey::UpdateManifestUrlInvalid => "更新地址必须是有效的 http(s) URL",
+            MessageKey::MaxFileSizeOutOfRange => "文件大小上限必须在 {} 到 {} 之间",
+            MessageKey::AllowedFileTypesRequired => "至少允许一种文件类型",
+            MessageKey::RetryAttemptsTooMany => "重试次数不能超过10次",
+            MessageKey::MaxWindowsOutOfRange => "最大窗口数必须在 1 到 20 之间",
+            MessageKey::ConsultationWindowsOutOfRange => "问诊窗口数必须大于0且不超过最大窗口数",
+            MessageKey::AutoLockTimeoutOutOfRange => "自动锁屏时间必须在 60 到 3600 秒之间",
+            MessageKey::ConsultationInactivityOutOfRange => "问诊自动结束时长必须在 2 到 168 小时之间",
+            MessageKey::AttachmentQuotaOutOfRange => "问诊附件上限不能小于单个文件上限，且不能超过患者附件上限",
+            MessageKey::MessageWarmupCountOutOfRange => "启动预加载的问诊数必须在 1 到 50 之间",
+            MessageKey::UploadCompressionDimensionOutOfRange => "图片压缩后的最大边长必须在 320 到 8192 像素之间",
+            MessageKey::UploadCompressionQualityOutOfRange => "图片压缩质量必须在 30 到 95 之间",
+            MessageKey::TimezoneInvalid => "时区必须是有效的 IANA 时区名称，如 Asia/Shanghai",
+            MessageKey::ProfilePassphraseTooShort => "配置包口令不能少于{}位",
+            MessageKey::WindowTypeUnknown => "未知的窗口类型: {}",
+            MessageKey::ConsultationNoteTooLong => "问诊备注不能超过{}个字符",
+            MessageKey::CommandRateLimited => "操作过于频繁，请 {} 秒后重试",
+            MessageKey::CommandRateLimitInvalid => "命令 {} 的限流容量不能小于1，恢复速度必须大于0",
+            MessageKey::FileTooLarge => "文件大小超过限制: {} > {}",
+            MessageKey::ExecutableBlocked => "不允许上传可执行文件或脚本",
+            MessageKey::FileTypeUnsupported => "不支持的文件类型: {}",
+            MessageKey::FileNameRequired => "文件名不能为空",
+            MessageKey::ConsultationAttachmentQuotaExceeded => "本次问诊的附件空间不足：已用 {}，上限 {}，本次需要 {}",
+            MessageKey::PatientAttachmentQuotaExceeded => "该患者的附件空间不足：已用 {}，上限 {}，本次需要 {}",
+            MessageKey::InlineUploadTooLarge => "文件超过 {}，请使用分片上传",
+            MessageKey::UploadTransferNotFound => "上传任务不存在或已过期",
+            MessageKey::UploadChunkOutOfOrder => "分片顺序错误：应为第 {} 片，收到第 {} 片",
+            MessageKey::UploadIncomplete => "文件未传输完整：已收到 {}，应为 {}",
+            MessageKey::PatientIdRequired => "患者ID不能为空",
+            MessageKey::DoctorIdRequired => "医生ID不能为空",
+            MessageKey::RecordTypeUnsupported => "不支持的病历类型",
+            MessageKey::RecordTitleRequired => "病历标题不能为空",
+            MessageKey::RecordTitleTooLong => "病历标题不能超过200个字符",
+            MessageKey::AttachmentFileIdRequired => "附件文件ID不能为空",
+            MessageKey::AttachmentDuplicate => "附件重复引用同一文件",
+            MessageKey::AttachmentNameRequired => "附件名称不能为空",
+            MessageKey::AttachmentNameTooLong => "附件名称过长",
+            MessageKey::AttachmentMimeTypeInvalid => "附件类型格式不正确",
+            MessageKey::AttachmentEmpty => "附件大小必须大于0",
+            MessageKey::AttachmentTooLarge => "附件大小超过限制: {}",
+            MessageKey::AttachmentChecksumInvalid => "附件校验值格式不正确",
+            MessageKey::PrescriptionEmpty => "处方至少需要一味药品",
+            MessageKey::PrescriptionTooManyItems => "处方药品不能超过{}项",
+            MessageKey::DrugNameRequired => "药品名称不能为空",
+            MessageKey::DrugNameTooLong => "药品名称不能超过100个字符",
+            MessageKey::FrequencyRequired => "用药频次不能为空",
+            MessageKey::DosageNotPositive => "单次用量必须大于0",
+            MessageKey::DaysNotPositive => "用药天数必须大于0",
+            MessageKey::UsernameTooShort => "用户名长度至少3位",
+            MessageKey::UsernameTooLong => "用户名长度不能超过20位",
+            MessageKey::UsernameInvalidChars => "用户名只能包含字母、数字和下划线",
+            MessageKey::PasswordBelowMinimum => "密码长度至少8位",
+            MessageKey::PasswordTooLong => "密码长度不能超过128位",
+            MessageKey::PasswordTooWeak => "密码必须包含至少3种字符类型(大写字母,小写字母,数字,特殊字符)",
+            MessageKey::TagRequired => "标签不能为空",
+            MessageKey::TagTooLong => "标签长度不能超过20个字符",
+            MessageKey::TagInvalidChars => "标签只能包含中文、字母和数字",
+            MessageKey::DatabaseBusy => "数据库正忙，请稍后重试",
+            MessageKey::DatabaseOperationFailed => "数据库操作失败: {}",
+            MessageKey::NetworkRequestFailed => "网络请求失败: {}",
+            MessageKey::FileNotFound => "文件不存在: {}",
+            MessageKey::FilePermissionDenied => "没有文件访问权限: {}",
+            MessageKey::FileOperationFailed => "文件操作失败: {}",
+            MessageKey::DataParseFailed => "数据解析失败: {}",
+            MessageKey::StaleWrite => "数据已被其他窗口修改，请刷新后重试",
+            MessageKey::MessageQueued => "网络已断开，消息已加入发送队列（第 {} 条）",
+            MessageKey::LocaleUnsupported => "不支持的语言: {}",
+        }
+    }
+
+    fn en_us(&self) -> Option<&'static str> {
+        let text = match self {
+            MessageKey::UsernameRequired => "Username is required",
+            MessageKey::PasswordTooShort => "Password must be at least 6 characters",
+            MessageKey::PasswordRequired => "Password is required",
+            MessageKey::PhoneInvalid => "Invalid phone number",
+            MessageKey::PhoneRequired => "Phone number is required",
+            MessageKey::SmsCodeInvalid => "Verification code must be 6 digits",
+            MessageKey::SmsCodeRequired => "Verification code is required",
+            MessageKey::UnlockPinInvalid => "Unlock PIN must be 6 digits",
+            MessageKey::IdCardInvalid => "Invalid ID card number",
+            MessageKey::IdCardRequired => "ID card number is required",
+            MessageKey::PatientNameRequired => "Patient name is required",
+            MessageKey::PatientNameTooLong => "Patient name must not exceed 50 characters",
+            MessageKey::PatientNameInvalid => "Invalid patient name",
+            MessageKey::AgeOutOfRange => "Age must be between 0 and 150",
+            // 居民身份证核对仅用于境内患者，暂未翻译
+            MessageKey::IdCardGenderMismatch | MessageKey::IdCardAgeMismatch => return None,
+            MessageKey::ConsultationIdRequired => "Consultation ID is required",
+            MessageKey::MessageContentRequired => "Message content is required",
+            MessageKey::MessageContentTooLong => "Message content must not exceed {} characters",
+            MessageKey::FileIdRequired => "File ID is required",
+            MessageKey::MessageTypeUnsupported => "Unsupported message type",
+            MessageKey::PageMustBePositive => "Page must be greater than 0",
+            MessageKey::PageSizeOutOfRange => "Page size must be between 1 and 100",
+            MessageKey::AgeRangeOutOfRange => "Age range must be between 0 and 150",
+            MessageKey::AgeRangeInverted => "Minimum age must not exceed maximum age",
+            MessageKey::SortFieldUnsupported => "Unsupported sort field, expected one of: {}",
+            MessageKey::DateRangeInverted => "Start time must not be later than end time",
+            MessageKey::DateRangeStartInFuture => "Start time must not be in the future",
+            MessageKey::WindowTitleRequired => "Window title is required",
+            MessageKey::WindowUrlRequired => "Window URL is required",
+            MessageKey::WindowWidthTooSmall => "Window width must be at least 200px",
+            MessageKey::WindowHeightTooSmall => "Window height must be at least 150px",
+            MessageKey::ApiBaseUrlInvalid => "API base URL must be a valid http(s) URL",
+            MessageKey::WsUrlInvalid => "WebSocket URL must be a valid ws(s) URL",
+            MessageKey::UpdateManifestUrlInvalid => "Update URL must be a valid http(s) URL",
+            MessageKey::MaxFileSizeOutOfRange => "Maximum file size must be between {} and {}",
+            MessageKey::AllowedFileTypesRequired => "At least one file type must be allowed",
+            MessageKey::RetryAttemptsTooMany => "Retry attempts must not exceed 10",
+            MessageKey::MaxWindowsOutOfRange => "Maximum windows must be between 1 and 20",
+            MessageKey::ConsultationWindowsOutOfRange => {
+                "Consultation windows must be greater than 0 and not exceed the maximum windows"
+            }
+            MessageKey::AutoLockTimeoutOutOfRange => "Auto-lock timeout must be between 60 and 3600 seconds",
+            MessageKey::ConsultationInactivityOutOfRange => {
+                "Consultation auto-complete time must be between 2 and 168 hours"
+            }
+            MessageKey::AttachmentQuotaOutOfRange => {
+                "Consultation attachment quota must be at least the maximum file size and not exceed the patient quota"
+            }
+            MessageKey::MessageWarmupCountOutOfRange => {
+                "Startup preload consultation count must be between 1 and 50"
+            }
+            MessageKey::UploadCompressionDimensionOutOfRange => {
+                "Compressed image max dimension must be between 320 and 8192 pixels"
+            }
+            MessageKey::UploadCompressionQualityOutOfRange => "Image compression quality must be between 30 and 95",
+            MessageKey::TimezoneInvalid => "Time zone must be a valid IANA name such as Asia/Shanghai",
+            MessageKey::ProfilePassphraseTooShort => "Profile passphrase must be at least {} characters",
+            MessageKey::WindowTypeUnknown => "Unknown window type: {}",
+            MessageKey::ConsultationNoteTooLong => "Consultation note must not exceed {} characters",
+            MessageKey::CommandRateLimited => "Too many requests, please retry in {} seconds",
+            MessageKey::CommandRateLimitInvalid => {
+                "Rate limit for command {} needs a capacity of at least 1 and a positive refill rate"
+            }
+            MessageKey::FileTooLarge => "File size exceeds the limit: {} > {}",
+            MessageKey::ExecutableBlocked => "Executable files and scripts are not allowed",
+            MessageKey::FileTypeUnsupported => "Unsupported file type: {}",
+            MessageKey::FileNameRequired => "File name is required",
+            MessageKey::ConsultationAttachmentQuotaExceeded => {
+                "Attachment quota for this consultation exceeded: {} used of {}, {} requested"
+            }
+            MessageKey::PatientAttachmentQuotaExceeded => {
+                "Attachment quota for this patient exceeded: {} used of {}, {} requested"
+            }
+            MessageKey::InlineUploadTooLarge => "File is larger than {}, use chunked upload instead",
+            MessageKey::UploadTransferNotFound => "Upload transfer not found or expired",
+            MessageKey::UploadChunkOutOfOrder => "Upload chunk out of order: expected chunk {}, received chunk {}",
+            MessageKey::UploadIncomplete => "File transfer incomplete: received {} of {}",
+            MessageKey::PatientIdRequired => "Patient ID is required",
+            MessageKey::DoctorIdRequired => "Doctor ID is required",
+            MessageKey::RecordTypeUnsupported => "Unsupported medical record type",
+            MessageKey::RecordTitleRequired => "Medical record title is required",
+            MessageKey::RecordTitleTooLong => "Medical record title must not exceed 200 characters",
+            MessageKey::AttachmentFileIdRequired => "Attachment file ID is required",
+            MessageKey::AttachmentDuplicate => "The same file is attached more than once",
+            MessageKey::AttachmentNameRequired => "Attachment name is required",
+            MessageKey::AttachmentNameTooLong => "Attachment name is too long",
+            MessageKey::AttachmentMimeTypeInvalid => "Invalid attachment type",
+            MessageKey::AttachmentEmpty => "Attachment size must be greater than 0",
+            MessageKey::AttachmentTooLarge => "Attachment size exceeds the limit: {}",
+            MessageKey::AttachmentChecksumInvalid => "Invalid attachment checksum",
+            MessageKey::PrescriptionEmpty => "A prescription needs at least one drug",
+            MessageKey::PrescriptionTooManyItems => "A prescription must not contain more than {} drugs",
+            MessageKey::DrugNameRequired => "Drug name is required",
+            MessageKey::DrugNameTooLong => "Drug name must not exceed 100 characters",
+            MessageKey::FrequencyRequired => "Dosing frequency is required",
+            MessageKey::DosageNotPositive => "Dosage must be greater than 0",
+            MessageKey::DaysNotPositive => "Number of days must be greater than 0",
+            MessageKey::UsernameTooShort => "Username must be at least 3 characters",
+            MessageKey::UsernameTooLong => "Username must not exceed 20 characters",
+            MessageKey::UsernameInvalidChars => "Username may only contain letters, digits and underscores",
+            MessageKey::PasswordBelowMinimum => "Password must be at least 8 characters",
+            MessageKey::PasswordTooLong => "Password must not exceed 128 characters",
+            MessageKey::PasswordTooWeak => {
+                "Password must contain at least 3 of: uppercase letters, lowercase letters, digits, special characters"
+            }
+            MessageKey::TagRequired => "Tag is required",
+            MessageKey::TagTooLong => "Tag must not exceed 20 characters",
+            MessageKey::TagInvalidChars => "Tag may only contain Chinese characters, letters and digits",
+            MessageKey::DatabaseBusy => "The database is busy, please try again later",
+            MessageKey::DatabaseOperationFailed => "Database operation failed: {}",
+            MessageKey::NetworkRequestFailed => "Network request failed: {}",
+            MessageKey::FileNotFound => "File not found: {}",
+            MessageKey::FilePermissionDenied => "Permission denied: {}",
+            MessageKey::FileOperationFailed => "File operation failed: {}",
+            MessageKey::DataParseFailed => "Failed to parse data: {}",
+            MessageKey::StaleWrite => "The data was changed in another window, please refresh and try again",
+            MessageKey::MessageQueued => "You are offline; the message was queued for sending (position {})",
+            MessageKey::LocaleUnsupported => "Unsupported locale: {}",
+        };
+        Some(text)
+    }
+
+    pub fn template(&self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::ZhCn => self.zh_cn(),
+            Locale::EnUs => self.en_us().unwrap_or_else(|| self.zh_cn()),
+        }
+    }
+
+    pub fn text_in(&self, locale: Locale, args: &[&dyn Display]) -> String {
+        fill(self.template(locale), args)
+    }
+
+    /// 按当前语言取文本
+    pub fn text(&self, args: &[&dyn Display]) -> String {
+        self.text_in(active_locale(), args)
+    }
+}
+
+// 无参数的键可以直接传给接受 impl Into<String> 的 AppError 构造函数
+impl From<MessageKey> for String {
+    fn from(key: MessageKey) -> Self {
+        key.text(&[])
+    }
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut rest = template;
+    while let Some(pos) = rest.find("{}") {
+        text.push_str(&rest[..pos]);
+        match args.next() {
+            Some(arg) => {
+                let _ = write!(text, "{}", arg);
+            }
+            None => text.push_str("{}"),
+        }
+        rest = &rest[pos + 2..];
+    }
+    text.push_str(rest);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_fills_arguments_in_order() {
+        assert_eq!(
+            MessageKey::FileTooLarge.text_in(Locale::ZhCn, &[&"60 MB", &"50 MB"]),
+            "文件大小超过限制: 60 MB > 50 MB"
+        );
+        assert_eq!(
+            MessageKey::FileTooLarge.text_in(Locale::EnUs, &[&"60 MB", &"50 MB"]),
+            "File size exceeds the limit: 60 MB > 50 MB"
+        );
+        // 参数不足时保留占位符
+        assert_eq!(MessageKey::FileTypeUnsupported.text_in(Locale::EnUs, &[]), "Unsupported file type: {}");
+    }
+
+    #[test]
+    fn test_missing_translation_falls_back_to_chinese() {
+        assert_eq!(MessageKey::IdCardGenderMismatch.template(Locale::EnUs), "身份证号与患者性别不一致");
+        assert_eq!(MessageKey::PhoneInvalid.template(Locale::EnUs), "Invalid phone number");
+    }
+
+    #[test]
+    fn test_locale_parse_and_serde() {
+        assert_eq!(Locale::parse("en-US"), Some(Locale::EnUs));
+        assert_eq!(Locale::parse("zh-cn"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse("ja-JP"), None);
+        assert_eq!(serde_json::to_string(&Locale::EnUs).unwrap(), "\"en-US\"");
+        assert_eq!(serde_json::from_str::<Locale>("\"zh-CN\"").unwrap(), Locale::ZhCn);
+    }
+}
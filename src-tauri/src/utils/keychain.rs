@@ -0,0 +1,41 @@
+// 系统钥匙串中保管的本地密钥：首次使用时生成随机密钥并保存，之后每次读取同一把
+// （macOS 钥匙串、Windows 凭据管理器或 Linux Secret Service）
+
+use crate::utils::crypto::CryptoService;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use zeroize::Zeroizing;
+
+pub const KEYCHAIN_SERVICE: &str = "telemedicine-desktop";
+// 患者字段加密的主密钥，整库密钥等子密钥也由它派生
+const DATA_MASTER_KEY_ACCOUNT: &str = "local-data-master-key";
+// 加密字段等值查询使用的 HMAC 索引密钥
+const BLIND_INDEX_KEY_ACCOUNT: &str = "blind-index-key";
+
+pub fn load_or_create_keychain_key(account: &str) -> Result<Zeroizing<[u8; 32]>> {
+    let entry = keyring::Entry::new(KEYCHAIN_SERVICE, account)?;
+    match entry.get_password() {
+        Ok(encoded) => {
+            let bytes = Zeroizing::new(STANDARD.decode(encoded.as_bytes())?);
+            let mut key = Zeroizing::new([0u8; 32]);
+            if bytes.len() != key.len() {
+                return Err(anyhow!("钥匙串中的密钥 {} 长度不正确", account));
+            }
+            key.copy_from_slice(&bytes);
+            Ok(key)
+        }
+        Err(keyring::Error::NoEntry) => {
+            let key = CryptoService::generate_key();
+            entry.set_password(&STANDARD.encode(key.as_ref()))?;
+            Ok(key)
+        }
+        Err(e) => Err(anyhow!("无法读取系统钥匙串: {}", e)),
+    }
+}
+
+// 本地数据加密服务，主密钥和查询索引密钥分别保存在钥匙串中
+pub fn local_data_crypto() -> Result<CryptoService> {
+    let master_key = load_or_create_keychain_key(DATA_MASTER_KEY_ACCOUNT)?;
+    let index_key = load_or_create_keychain_key(BLIND_INDEX_KEY_ACCOUNT)?;
+    Ok(CryptoService::with_keys(&master_key, &index_key))
+}
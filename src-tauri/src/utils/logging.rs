@@ -0,0 +1,380 @@
+// 结构化日志：写入应用数据目录下按大小轮转的文件，输出前脱敏手机号和身份证号
+
+use crate::models::{LogEntry, LogLevel};
+use crate::utils::ValidationService;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
+
+pub const LOG_FILE_NAME: &str = "telemedicine.log";
+// 单个日志文件上限
+pub const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+// 保留的日志文件数（含当前文件）
+pub const MAX_LOG_FILES: usize = 7;
+
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+static LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+// 初始化全局日志，重复调用无效
+pub fn init_logging(log_dir: &Path) -> io::Result<()> {
+    if LOG_DIR.get().is_some() {
+        return Ok(());
+    }
+
+    let writer = RotatingFileWriter::new(log_dir, MAX_LOG_FILE_BYTES, MAX_LOG_FILES)?;
+    let default_level = if cfg!(debug_assertions) { LevelFilter::DEBUG } else { LevelFilter::INFO };
+    let (level_layer, level_handle) = reload::Layer::new(default_level);
+
+    let file_layer = tracing_subscriber::fmt::layer()
+        .json()
+        .event_format(ScrubbingFormat(json_format()))
+        .with_writer(writer);
+    // 开发环境同时输出到终端，同样脱敏
+    let console_layer = cfg!(debug_assertions)
+        .then(|| tracing_subscriber::fmt::layer().event_format(ScrubbingFormat(format::Format::default())));
+
+    tracing_subscriber::registry()
+        .with(level_layer)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    let _ = LOG_DIR.set(log_dir.to_path_buf());
+    let _ = LEVEL_HANDLE.set(level_handle);
+    Ok(())
+}
+
+pub fn set_max_log_level(level: &LogLevel) -> Result<(), String> {
+    let handle = LEVEL_HANDLE.get().ok_or_else(|| "日志尚未初始化".to_string())?;
+    handle
+        .modify(|filter| *filter = level_filter(level))
+        .map_err(|e| format!("设置日志级别失败: {}", e))
+}
+
+// 读取最近的日志（新的在前），level 为最低级别
+pub fn recent_log_entries(level: Option<&LogLevel>, limit: usize) -> Result<Vec<LogEntry>, String> {
+    let dir = LOG_DIR.get().ok_or_else(|| "日志尚未初始化".to_string())?;
+    read_recent_logs(dir, level, limit).map_err(|e| format!("读取日志失败: {}", e))
+}
+
+pub fn read_recent_logs(dir: &Path, level: Option<&LogLevel>, limit: usize) -> io::Result<Vec<LogEntry>> {
+    let min_rank = level.map(level_rank).unwrap_or(0);
+    let mut entries = Vec::new();
+
+    // 从最新的文件开始，文件内按行倒序
+    for path in log_files(dir) {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        let lines: Vec<String> = BufReader::new(file).lines().collect::<io::Result<_>>()?;
+        for line in lines.iter().rev() {
+            if let Some(entry) = parse_log_line(line) {
+                if level_rank(&entry.level) >= min_rank {
+                    entries.push(entry);
+                    if entries.len() >= limit {
+                        return Ok(entries);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+// 当前文件在前，其后为 .1、.2 ... 的历史文件
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![dir.join(LOG_FILE_NAME)];
+    for index in 1..MAX_LOG_FILES {
+        let path = rotated_path(dir, index);
+        if !path.exists() {
+            break;
+        }
+        files.push(path);
+    }
+    files
+}
+
+fn rotated_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{}.{}", LOG_FILE_NAME, index))
+}
+
+fn parse_log_line(line: &str) -> Option<LogEntry> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let level = match value.get("level")?.as_str()? {
+        "ERROR" => LogLevel::Error,
+        "WARN" => LogLevel::Warn,
+        "INFO" => LogLevel::Info,
+        _ => LogLevel::Debug,
+    };
+    let timestamp: DateTime<Utc> = value.get("timestamp")?.as_str()?.parse().ok()?;
+
+    let mut fields = value.get("fields").cloned().unwrap_or_else(|| serde_json::json!({}));
+    let message = fields
+        .as_object_mut()
+        .and_then(|f| f.remove("message"))
+        .and_then(|m| m.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let error = fields
+        .as_object_mut()
+        .and_then(|f| f.remove("error"))
+        .map(|e| e.as_str().map(str::to_string).unwrap_or_else(|| e.to_string()));
+
+    let mut context = serde_json::Map::new();
+    if let Some(target) = value.get("target") {
+        context.insert("target".to_string(), target.clone());
+    }
+    if let Some(extra) = fields.as_object().filter(|f| !f.is_empty()) {
+        context.insert("fields".to_string(), serde_json::Value::Object(extra.clone()));
+    }
+
+    Some(LogEntry {
+        level,
+        message,
+        timestamp,
+        context: Some(serde_json::Value::Object(context)),
+        error,
+    })
+}
+
+fn level_rank(level: &LogLevel) -> u8 {
+    match level {
+        LogLevel::Debug => 0,
+        LogLevel::Info => 1,
+        LogLevel::Warn => 2,
+        LogLevel::Error => 3,
+    }
+}
+
+fn level_filter(level: &LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Error => LevelFilter::ERROR,
+    }
+}
+
+// 手机号、身份证号打码，判断规则与 ValidationService 一致
+pub fn scrub_phi(text: &str) -> String {
+    static DIGIT_RUN: OnceLock<Regex> = OnceLock::new();
+    let digit_run = DIGIT_RUN.get_or_init(|| Regex::new(r"\d{11,17}[0-9Xx]?").unwrap());
+
+    digit_run
+        .replace_all(text, |caps: &regex::Captures| {
+            let run = &caps[0];
+            if ValidationService::validate_phone(run) {
+                format!("{}****{}", &run[..3], &run[7..])
+            } else if ValidationService::validate_id_card(run) {
+                format!("{}********{}", &run[..6], &run[14..])
+            } else {
+                run.to_string()
+            }
+        })
+        .into_owned()
+}
+
+/// 先按内部格式输出整条日志，再统一脱敏
+pub struct ScrubbingFormat<F>(pub F);
+
+impl<S, N, F> FormatEvent<S, N> for ScrubbingFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> std::fmt::Result {
+        let mut buffer = String::new();
+        self.0.format_event(ctx, Writer::new(&mut buffer), event)?;
+        std::fmt::Write::write_str(&mut writer, &scrub_phi(&buffer))
+    }
+}
+
+/// 超过大小上限时轮转：telemedicine.log -> telemedicine.log.1 -> ... 最多保留 max_files 个文件
+pub struct RotatingFileWriter {
+    state: Mutex<RotatingFile>,
+}
+
+struct RotatingFile {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFileWriter {
+    pub fn new(dir: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = open_append(&dir.join(LOG_FILE_NAME))?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            state: Mutex::new(RotatingFile {
+                dir: dir.to_path_buf(),
+                max_bytes,
+                max_files: max_files.max(1),
+                file,
+                size,
+            }),
+        })
+    }
+}
+
+impl RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let oldest = rotated_path(&self.dir, self.max_files - 1);
+        if self.max_files > 1 && oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (1..self.max_files.saturating_sub(1)).rev() {
+            let from = rotated_path(&self.dir, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.dir, index + 1))?;
+            }
+        }
+
+        let current = self.dir.join(LOG_FILE_NAME);
+        if self.max_files > 1 {
+            fs::rename(&current, rotated_path(&self.dir, 1))?;
+        } else {
+            fs::remove_file(&current)?;
+        }
+
+        self.file = open_append(&current)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+pub struct RotatingFileGuard<'a> {
+    state: &'a Mutex<RotatingFile>,
+}
+
+impl Write for RotatingFileGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.write(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).file.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFileWriter {
+    type Writer = RotatingFileGuard<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RotatingFileGuard { state: &self.state }
+    }
+}
+
+fn json_format() -> format::Format<format::Json> {
+    format::Format::default().json().with_current_span(false).with_span_list(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub_phone_and_id_card() {
+        let text = "患者 13800138000 身份证 110101199003071233 订单 123456789012";
+        let scrubbed = scrub_phi(text);
+
+        assert!(!scrubbed.contains("13800138000"));
+        assert!(scrubbed.contains("138****8000"));
+        assert!(!scrubbed.contains("110101199003071233"));
+        assert!(scrubbed.contains("110101********1233"));
+        // 不符合规则的数字保持原样
+        assert!(scrubbed.contains("123456789012"));
+    }
+
+    #[test]
+    fn test_rotation_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = RotatingFileWriter::new(dir.path(), 100, 3).unwrap();
+
+        for i in 0..10 {
+            let line = format!("{:0>59}\n", i);
+            writer.make_writer().write_all(line.as_bytes()).unwrap();
+        }
+
+        let files = log_files(dir.path());
+        assert_eq!(files.len(), 3);
+        assert!(!rotated_path(dir.path(), 3).exists());
+        for path in &files {
+            assert!(fs::metadata(path).unwrap().len() <= 100);
+        }
+
+        // 当前文件是最新的一行，.1 是上一行
+        assert!(fs::read_to_string(&files[0]).unwrap().ends_with("9\n"));
+        assert!(fs::read_to_string(&files[1]).unwrap().ends_with("8\n"));
+        assert!(fs::read_to_string(&files[2]).unwrap().ends_with("7\n"));
+    }
+
+    #[test]
+    fn test_logged_events_are_scrubbed_and_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = RotatingFileWriter::new(dir.path(), MAX_LOG_FILE_BYTES, MAX_LOG_FILES).unwrap();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .event_format(ScrubbingFormat(json_format()))
+                .with_writer(writer),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Sending SMS code to 13800138000");
+            tracing::warn!(error = "timeout", "Patient sync failed");
+            tracing::debug!("Cache warmed");
+        });
+
+        let content = fs::read_to_string(dir.path().join(LOG_FILE_NAME)).unwrap();
+        assert!(!content.contains("13800138000"));
+
+        let entries = read_recent_logs(dir.path(), None, 10).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].message, "Cache warmed");
+        assert_eq!(entries[2].message, "Sending SMS code to 138****8000");
+
+        let warnings = read_recent_logs(dir.path(), Some(&LogLevel::Warn), 10).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].error.as_deref(), Some("timeout"));
+
+        assert_eq!(read_recent_logs(dir.path(), None, 2).unwrap().len(), 2);
+    }
+}
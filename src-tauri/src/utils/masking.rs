@@ -0,0 +1,179 @@
+// 患者标识脱敏：无权查看完整信息的角色看到的手机号、身份证号和姓名只保留首尾部分
+
+use crate::models::{Patient, PatientField, PatientRevision, PatientRevisionField};
+
+// 各字段是否脱敏，按角色配置见 PermissionService::role_masking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaskingPolicy {
+    pub phone: bool,
+    pub id_card: bool,
+    pub name: bool,
+}
+
+impl MaskingPolicy {
+    pub const NONE: MaskingPolicy = MaskingPolicy {
+        phone: false,
+        id_card: false,
+        name: false,
+    };
+    pub const ALL: MaskingPolicy = MaskingPolicy {
+        phone: true,
+        id_card: true,
+        name: true,
+    };
+
+    pub fn masks(&self, field: PatientField) -> bool {
+        match field {
+            PatientField::Phone => self.phone,
+            PatientField::IdCard => self.id_card,
+            PatientField::Name => self.name,
+        }
+    }
+
+    // 手机号或身份证号任一需要脱敏
+    pub fn masks_identifiers(&self) -> bool {
+        self.phone || self.id_card
+    }
+
+    pub fn apply(&self, patient: &mut Patient) {
+        if self.phone {
+            patient.phone = patient.phone.as_deref().map(mask_phone);
+        }
+        if self.id_card {
+            patient.id_card = patient.id_card.as_deref().map(mask_id_card);
+        }
+        if self.name {
+            patient.name = mask_name(&patient.name);
+        }
+    }
+
+    // 修改记录中手机号、身份证号和姓名的新旧值按同一策略脱敏
+    pub fn apply_revision(&self, revision: &mut PatientRevision) {
+        for (field, change) in revision.changes.iter_mut() {
+            let mask: fn(&str) -> String = match PatientRevisionField::parse(field) {
+                Some(PatientRevisionField::Phone) if self.phone => mask_phone,
+                Some(PatientRevisionField::IdCard) if self.id_card => mask_id_card,
+                Some(PatientRevisionField::Name) if self.name => mask_name,
+                _ => continue,
+            };
+            for value in [&mut change.old, &mut change.new] {
+                if let Some(plain) = value.as_str() {
+                    *value = serde_json::Value::String(mask(plain));
+                }
+            }
+        }
+    }
+
+    pub fn patient_name(&self, name: &str) -> String {
+        if self.name {
+            mask_name(name)
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+/// 13812345678 -> 138****5678
+pub fn mask_phone(phone: &str) -> String {
+    let chars: Vec<char> = phone.chars().collect();
+    if chars.len() < 7 {
+        return "*".repeat(chars.len());
+    }
+    format!(
+        "{}****{}",
+        chars[..3].iter().collect::<String>(),
+        chars[chars.len() - 4..].iter().collect::<String>()
+    )
+}
+
+/// 110101199001011234 -> 110101********1234
+pub fn mask_id_card(id_card: &str) -> String {
+    let chars: Vec<char> = id_card.chars().collect();
+    if chars.len() < 10 {
+        return "*".repeat(chars.len());
+    }
+    format!(
+        "{}{}{}",
+        chars[..6].iter().collect::<String>(),
+        "*".repeat(chars.len() - 10),
+        chars[chars.len() - 4..].iter().collect::<String>()
+    )
+}
+
+/// 张三 -> 张*，王小明 -> 王*明
+pub fn mask_name(name: &str) -> String {
+    let chars: Vec<char> = name.trim().chars().collect();
+    match chars.len() {
+        0 => String::new(),
+        1 => "*".to_string(),
+        2 => format!("{}*", chars[0]),
+        len => format!("{}{}{}", chars[0], "*".repeat(len - 2), chars[len - 1]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_mask_phone() {
+        assert_eq!(mask_phone("13812345678"), "138****5678");
+        assert_eq!(mask_phone("+8613812345678"), "+86****5678");
+        assert_eq!(mask_phone("12345"), "*****");
+        assert_eq!(mask_phone(""), "");
+    }
+
+    #[test]
+    fn test_mask_id_card() {
+        assert_eq!(mask_id_card("110101199001011234"), "110101********1234");
+        assert_eq!(mask_id_card("11010119900101123X"), "110101********123X");
+        // 15 位旧身份证
+        assert_eq!(mask_id_card("110101900101123"), "110101*****1123");
+        assert_eq!(mask_id_card("123456789"), "*********");
+    }
+
+    #[test]
+    fn test_mask_name() {
+        assert_eq!(mask_name("张三"), "张*");
+        assert_eq!(mask_name("王小明"), "王*明");
+        assert_eq!(mask_name("欧阳娜娜"), "欧**娜");
+        assert_eq!(mask_name("李"), "*");
+        assert_eq!(mask_name(" "), "");
+    }
+
+    #[test]
+    fn test_policy_applies_selected_fields() {
+        let mut patient = Patient {
+            id: "p1".to_string(),
+            name: "王小明".to_string(),
+            age: Some(40),
+            gender: Some("male".to_string()),
+            phone: Some("13812345678".to_string()),
+            id_card: Some("110101199001011234".to_string()),
+            tags: vec![],
+            avatar_url: None,
+            last_sync: None,
+            last_visit: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            version: 1,
+        };
+
+        let identifiers = MaskingPolicy {
+            name: false,
+            ..MaskingPolicy::ALL
+        };
+        identifiers.apply(&mut patient);
+        assert_eq!(patient.phone.as_deref(), Some("138****5678"));
+        assert_eq!(patient.id_card.as_deref(), Some("110101********1234"));
+        assert_eq!(patient.name, "王小明");
+        assert!(identifiers.masks(PatientField::Phone));
+        assert!(!identifiers.masks(PatientField::Name));
+
+        MaskingPolicy::ALL.apply(&mut patient);
+        assert_eq!(patient.name, "王*明");
+        assert_eq!(MaskingPolicy::NONE.patient_name("王小明"), "王小明");
+        assert!(!MaskingPolicy::NONE.masks_identifiers());
+    }
+}
@@ -1,12 +1,27 @@
 // 工具模块
 
 pub mod crypto;
+pub mod keychain;
 pub mod validation;
 pub mod error;
+pub mod logging;
+pub mod i18n;
+pub mod sort_key;
+pub mod timestamp;
+pub mod timezone;
+pub mod masking;
+pub mod sanitize;
 
 #[cfg(test)]
 mod validation_simple_test;
 
 pub use crypto::*;
 pub use validation::*;
-pub use error::*;
\ No newline at end of file
+pub use error::*;
+pub use logging::*;
+pub use i18n::{active_locale, set_active_locale, Locale, MessageKey};
+pub use sort_key::*;
+pub use timestamp::*;
+pub use timezone::*;
+pub use masking::*;
+pub use sanitize::*;
\ No newline at end of file
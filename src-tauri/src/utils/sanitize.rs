@@ -0,0 +1,189 @@
+// 消息正文清洗：发送和接收两端统一规范为 NFC，剥离 HTML 标签，去除控制字符和 emoji 序列以外的零宽字符，并按字符数限制长度
+
+use crate::models::{Message, MessageType};
+use regex::Regex;
+use std::sync::OnceLock;
+use unicode_normalization::UnicodeNormalization;
+
+// 消息正文上限，按字符计算，中文和 emoji 不会因占多个字节提前超限
+pub const MAX_MESSAGE_CHARS: usize = 5000;
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+// script、style 连同内容一起移除，未闭合时移除到末尾
+static HTML_BLOCKS: OnceLock<Regex> = OnceLock::new();
+// 其余标签和注释只移除标记本身，保留其中的文字；"血压 < 140" 这类文本不受影响
+static HTML_TAGS: OnceLock<Regex> = OnceLock::new();
+
+pub fn sanitize_message_content(content: &str) -> String {
+    let normalized: String = content.nfc().collect();
+    let text = strip_html(&normalized);
+    let text = strip_invisible(&text);
+
+    if text.chars().count() <= MAX_MESSAGE_CHARS {
+        return text;
+    }
+    let truncated: String = text.chars().take(MAX_MESSAGE_CHARS).collect();
+    // 截断处不能留下悬空的 ZWJ
+    truncated.trim_end_matches(ZERO_WIDTH_JOINER).to_string()
+}
+
+// 清洗文本和模板消息的正文，正文被改写时返回清洗前的原文
+pub fn sanitize_message(message: &mut Message) -> Option<String> {
+    if !matches!(message.message_type, MessageType::Text | MessageType::Template) {
+        return None;
+    }
+    let raw = message.content.as_deref()?;
+    let sanitized = sanitize_message_content(raw);
+    if sanitized == raw {
+        return None;
+    }
+    message.content.replace(sanitized)
+}
+
+fn strip_html(text: &str) -> String {
+    let blocks = HTML_BLOCKS.get_or_init(|| {
+        Regex::new(r"(?is)<script\b[^>]*>.*?(?:</script\s*>|$)|<style\b[^>]*>.*?(?:</style\s*>|$)").unwrap()
+    });
+    let tags = HTML_TAGS.get_or_init(|| Regex::new(r"(?s)<!--.*?-->|</?[a-zA-Z][a-zA-Z0-9-]*(?:\s[^<>]*)?/?>").unwrap());
+
+    let text = blocks.replace_all(text, "");
+    tags.replace_all(&text, "").into_owned()
+}
+
+// 保留换行和制表符；ZWJ 只在两个 emoji 之间保留（家庭、职业等组合表情）
+fn strip_invisible(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            '\n' | '\t' => out.push(c),
+            ZERO_WIDTH_JOINER => {
+                let after_emoji = out.chars().last().is_some_and(is_emoji_component);
+                let before_emoji = chars.get(i + 1).copied().is_some_and(is_emoji);
+                if after_emoji && before_emoji {
+                    out.push(c);
+                }
+            }
+            c if c.is_control() || is_zero_width(c) => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{200B}' | '\u{200C}' | '\u{2060}' | '\u{FEFF}')
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00A9}'
+            | '\u{00AE}'
+            | '\u{203C}'
+            | '\u{2049}'
+            | '\u{2122}'
+            | '\u{2139}'
+            | '\u{2194}'..='\u{21AA}'
+            | '\u{2300}'..='\u{23FF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{3030}'
+            | '\u{303D}'
+            | '\u{3297}'
+            | '\u{3299}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+// emoji 本身或可跟在其后的变体选择符、标签字符（肤色修饰符已在 emoji 区段内）
+fn is_emoji_component(c: char) -> bool {
+    is_emoji(c) || c == '\u{FE0F}' || ('\u{E0020}'..='\u{E007F}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ReadStatus, SenderType, SyncStatus};
+    use chrono::Utc;
+
+    #[test]
+    fn test_emoji_heavy_text_unchanged() {
+        let text = "今天好多了😀👍🏽🇨🇳❤️ 谢谢医生🙏🙏🙏\n下次复诊见👋";
+        assert_eq!(sanitize_message_content(text), text);
+    }
+
+    #[test]
+    fn test_zwj_kept_only_inside_emoji_sequences() {
+        // 家庭、女医生、彩虹旗
+        let sequences = "👨\u{200D}👩\u{200D}👧\u{200D}👦 👩🏻\u{200D}⚕\u{FE0F} 🏳\u{FE0F}\u{200D}🌈";
+        assert_eq!(sanitize_message_content(sequences), sequences);
+
+        assert_eq!(sanitize_message_content("头\u{200D}痛\u{200B}三天\u{FEFF}"), "头痛三天");
+        assert_eq!(sanitize_message_content("\u{200D}👍"), "👍");
+        assert_eq!(sanitize_message_content("👍\u{200D}好"), "👍好");
+        assert_eq!(sanitize_message_content("发烧\u{0007}\u{001B}[31m\r\n\t38度"), "发烧[31m\n\t38度");
+    }
+
+    #[test]
+    fn test_nfc_normalization() {
+        assert_eq!(sanitize_message_content("cafe\u{0301}"), "caf\u{00E9}");
+    }
+
+    #[test]
+    fn test_html_injection_stripped() {
+        assert_eq!(sanitize_message_content("<script>alert('x')</script>您好"), "您好");
+        assert_eq!(sanitize_message_content("您好<SCRIPT src=//evil.js>"), "您好");
+        assert_eq!(sanitize_message_content("<style>body{display:none}</style>请复查"), "请复查");
+        assert_eq!(
+            sanitize_message_content("<img src=x onerror=\"alert(1)\">请<b>按时</b>服药<!-- 注释 --><br/>"),
+            "请按时服药"
+        );
+        // 不构成标签的尖括号按原文保留
+        assert_eq!(sanitize_message_content("血压 < 140 且 > 90"), "血压 < 140 且 > 90");
+        assert_eq!(sanitize_message_content("&lt;script&gt;"), "&lt;script&gt;");
+    }
+
+    #[test]
+    fn test_length_limit_counts_chars() {
+        let chinese = "痛".repeat(MAX_MESSAGE_CHARS);
+        assert_eq!(sanitize_message_content(&chinese), chinese);
+
+        let emoji = "😀".repeat(MAX_MESSAGE_CHARS + 10);
+        assert_eq!(sanitize_message_content(&emoji).chars().count(), MAX_MESSAGE_CHARS);
+
+        let dangling = format!("{}👨\u{200D}👩", "a".repeat(MAX_MESSAGE_CHARS - 2));
+        assert!(sanitize_message_content(&dangling).ends_with('👨'));
+    }
+
+    #[test]
+    fn test_sanitize_message_returns_raw_only_when_changed() {
+        let mut message = Message {
+            id: "m1".to_string(),
+            consultation_id: "c1".to_string(),
+            sender_type: SenderType::Patient,
+            message_type: MessageType::Text,
+            content: Some("<b>您好</b>".to_string()),
+            file_path: None,
+            file_size: None,
+            mime_type: None,
+            timestamp: Utc::now(),
+            sync_status: SyncStatus::Synced,
+            read_status: ReadStatus::Unread,
+            template_id: None,
+            duration_ms: None,
+            waveform: None,
+            pinned: false,
+        };
+        assert_eq!(sanitize_message(&mut message).as_deref(), Some("<b>您好</b>"));
+        assert_eq!(message.content.as_deref(), Some("您好"));
+        assert_eq!(sanitize_message(&mut message), None);
+
+        // 系统事件的正文是 JSON，不清洗
+        message.message_type = MessageType::Event;
+        message.content = Some("<b>{}</b>".to_string());
+        assert_eq!(sanitize_message(&mut message), None);
+    }
+}
@@ -0,0 +1,69 @@
+// 患者姓名排序键：汉字转为不带声调的拼音，按拼音字母序排列而不是按 Unicode 码位
+
+use pinyin::ToPinyin;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+
+// 迁移回填历史数据时在 SQL 中调用的函数名
+pub const PINYIN_KEY_FUNCTION: &str = "pinyin_key";
+
+/// 每个汉字一个音节，连续的非汉字字符合并为一段，各段之间以空格分隔
+pub fn pinyin_sort_key(name: &str) -> String {
+    let mut segments: Vec<String> = Vec::new();
+    let mut plain = String::new();
+
+    for ch in name.trim().chars() {
+        match ch.to_pinyin() {
+            Some(syllable) => {
+                if !plain.is_empty() {
+                    segments.push(std::mem::take(&mut plain));
+                }
+                segments.push(syllable.plain().to_string());
+            }
+            None if ch.is_whitespace() => {
+                if !plain.is_empty() {
+                    segments.push(std::mem::take(&mut plain));
+                }
+            }
+            None => plain.extend(ch.to_lowercase()),
+        }
+    }
+    if !plain.is_empty() {
+        segments.push(plain);
+    }
+
+    segments.join(" ")
+}
+
+/// 注册 `pinyin_key(name)` 标量函数，供迁移 SQL 回填排序键
+pub fn register_pinyin_key_function(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        PINYIN_KEY_FUNCTION,
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let name: Option<String> = ctx.get(0)?;
+            Ok(name.map(|name| pinyin_sort_key(&name)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinyin_sort_key() {
+        assert_eq!(pinyin_sort_key("张三"), "zhang san");
+        assert_eq!(pinyin_sort_key(" 李 明 "), "li ming");
+        assert_eq!(pinyin_sort_key("Anna王"), "anna wang");
+        assert_eq!(pinyin_sort_key(""), "");
+    }
+
+    #[test]
+    fn test_pinyin_order_differs_from_code_point_order() {
+        let mut names = vec!["张伟", "阿强", "王芳", "李娜", "赵敏"];
+        names.sort_by_key(|name| pinyin_sort_key(name));
+        assert_eq!(names, vec!["阿强", "李娜", "王芳", "张伟", "赵敏"]);
+    }
+}
@@ -0,0 +1,173 @@
+// 时间列的存储格式：统一为定长的 RFC3339 UTC 字符串（微秒精度，如 2024-03-01T08:00:00.000000Z），
+// 按字符串比较即按时间先后比较；读取时兼容旧版本写入的各种格式
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Value, ValueRef};
+use rusqlite::Connection;
+
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6fZ";
+// 迁移改写历史数据时在 SQL 中调用的函数名
+pub const NORMALIZE_TIMESTAMP_FUNCTION: &str = "normalize_timestamp";
+// 超过该值的整数按毫秒时间戳处理
+const EPOCH_MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// 写入时间列时使用的包装类型，比较条件中的时间参数同样需要包装，保证与列中的格式一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SqlTimestamp(pub DateTime<Utc>);
+
+impl SqlTimestamp {
+    pub fn now() -> Self {
+        SqlTimestamp(Utc::now())
+    }
+
+    // 保留期清理的截止时间，早于该时间的记录视为过期
+    pub fn days_ago(days: i32) -> Self {
+        SqlTimestamp(Utc::now() - Duration::days(days as i64))
+    }
+
+    pub fn format(&self) -> String {
+        self.0.format(TIMESTAMP_FORMAT).to_string()
+    }
+
+    /// 解析文本格式的时间：RFC3339（T 或空格分隔，任意时区偏移）、
+    /// SQLite datetime() 的 "YYYY-MM-DD HH:MM:SS" 以及只有日期的 "YYYY-MM-DD"，不带时区的按 UTC 处理
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let normalized = match value.as_bytes().get(10) {
+            Some(b' ') => format!("{}T{}", &value[..10], &value[11..]),
+            _ => value.to_string(),
+        };
+
+        if let Ok(parsed) = DateTime::parse_from_rfc3339(&normalized) {
+            return Some(SqlTimestamp(parsed.with_timezone(&Utc)));
+        }
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%dT%H:%M:%S%.f") {
+            return Some(SqlTimestamp(Utc.from_utc_datetime(&parsed)));
+        }
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%dT%H:%M") {
+            return Some(SqlTimestamp(Utc.from_utc_datetime(&parsed)));
+        }
+        NaiveDate::parse_from_str(&normalized, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|parsed| SqlTimestamp(Utc.from_utc_datetime(&parsed)))
+    }
+
+    // 整数列按 Unix 时间戳处理，数值过大时视为毫秒
+    fn from_epoch(value: i64) -> Option<Self> {
+        let parsed = if value.abs() >= EPOCH_MILLIS_THRESHOLD {
+            DateTime::from_timestamp_millis(value)
+        } else {
+            DateTime::from_timestamp(value, 0)
+        };
+        parsed.map(SqlTimestamp)
+    }
+}
+
+impl From<DateTime<Utc>> for SqlTimestamp {
+    fn from(value: DateTime<Utc>) -> Self {
+        SqlTimestamp(value)
+    }
+}
+
+impl From<SqlTimestamp> for DateTime<Utc> {
+    fn from(value: SqlTimestamp) -> Self {
+        value.0
+    }
+}
+
+impl ToSql for SqlTimestamp {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.format()))
+    }
+}
+
+impl FromSql for SqlTimestamp {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let parsed = match value {
+            ValueRef::Text(_) => SqlTimestamp::parse(value.as_str()?),
+            ValueRef::Integer(seconds) => SqlTimestamp::from_epoch(seconds),
+            ValueRef::Real(seconds) => DateTime::from_timestamp_millis((seconds * 1000.0) as i64).map(SqlTimestamp),
+            _ => return Err(FromSqlError::InvalidType),
+        };
+        parsed.ok_or_else(|| FromSqlError::Other(format!("无法识别的时间格式: {:?}", value).into()))
+    }
+}
+
+/// 注册 `normalize_timestamp(value)` 标量函数，供迁移 SQL 把历史数据改写为统一格式；
+/// NULL 原样返回，无法识别的取值保持不变
+pub fn register_timestamp_function(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        NORMALIZE_TIMESTAMP_FUNCTION,
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let raw = ctx.get_raw(0);
+            if raw == ValueRef::Null {
+                return Ok(Value::Null);
+            }
+            Ok(match SqlTimestamp::column_result(raw) {
+                Ok(timestamp) => Value::Text(timestamp.format()),
+                Err(_) => Value::from(raw),
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_format_is_fixed_width_and_sortable() {
+        let whole = SqlTimestamp(at("2024-03-01T08:00:00Z")).format();
+        let fraction = SqlTimestamp(at("2024-03-01T08:00:00.5Z")).format();
+        let later = SqlTimestamp(at("2024-03-01T08:00:01Z")).format();
+        assert_eq!(whole, "2024-03-01T08:00:00.000000Z");
+        assert_eq!(fraction, "2024-03-01T08:00:00.500000Z");
+        assert_eq!(whole.len(), fraction.len());
+        assert!(whole < fraction && fraction < later);
+    }
+
+    #[test]
+    fn test_parse_legacy_formats() {
+        let expected = at("2024-03-01T08:00:00Z");
+        for value in [
+            "2024-03-01 08:00:00",
+            "2024-03-01T08:00:00",
+            "2024-03-01 08:00:00+00:00",
+            "2024-03-01 16:00:00.000+08:00",
+            "2024-03-01T08:00:00Z",
+            "2024-03-01T08:00:00.000000Z",
+        ] {
+            assert_eq!(SqlTimestamp::parse(value).map(|t| t.0), Some(expected), "{}", value);
+        }
+        assert_eq!(SqlTimestamp::parse("2024-03-01").unwrap().0, at("2024-03-01T00:00:00Z"));
+        assert!(SqlTimestamp::parse("yesterday").is_none());
+
+        let epoch = expected.timestamp();
+        assert_eq!(SqlTimestamp::column_result(ValueRef::Integer(epoch)).unwrap().0, expected);
+        assert_eq!(SqlTimestamp::column_result(ValueRef::Integer(epoch * 1000)).unwrap().0, expected);
+        assert!(SqlTimestamp::column_result(ValueRef::Text(b"not a time")).is_err());
+    }
+
+    #[test]
+    fn test_normalize_function() {
+        let conn = Connection::open_in_memory().unwrap();
+        register_timestamp_function(&conn).unwrap();
+
+        let normalize = |sql: &str| -> Option<String> {
+            conn.query_row(&format!("SELECT normalize_timestamp({})", sql), [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(normalize("'2024-03-01 16:00:00.123456789+08:00'").as_deref(), Some("2024-03-01T08:00:00.123456Z"));
+        assert_eq!(normalize("1709280000").as_deref(), Some("2024-03-01T08:00:00.000000Z"));
+        assert_eq!(normalize("NULL"), None);
+        // 无法识别的取值保持原样，不丢失数据
+        assert_eq!(normalize("'unknown'").as_deref(), Some("unknown"));
+    }
+}
@@ -0,0 +1,165 @@
+// 显示时区：数据库中的时间统一为 UTC，导出文件、提醒和会话列表按配置的 IANA 时区显示，
+// 前端传来的不带时区的筛选时间同样按该时区理解后再换算为 UTC
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer};
+use std::sync::RwLock;
+
+// 读取不到系统时区时使用
+pub const FALLBACK_TIMEZONE: &str = "Asia/Shanghai";
+pub const LOCAL_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// 未设置时跟随系统时区
+static ACTIVE_TIMEZONE: RwLock<Option<Tz>> = RwLock::new(None);
+
+pub fn parse_timezone(id: &str) -> Option<Tz> {
+    id.trim().parse().ok()
+}
+
+// 系统时区的 IANA 名称，作为时区配置的默认值
+pub fn system_timezone_id() -> String {
+    iana_time_zone::get_timezone()
+        .ok()
+        .filter(|id| parse_timezone(id).is_some())
+        .unwrap_or_else(|| FALLBACK_TIMEZONE.to_string())
+}
+
+pub fn active_timezone() -> Tz {
+    if let Some(tz) = *ACTIVE_TIMEZONE.read().unwrap() {
+        return tz;
+    }
+    parse_timezone(&system_timezone_id()).unwrap_or(chrono_tz::Asia::Shanghai)
+}
+
+pub fn set_active_timezone(tz: Tz) {
+    *ACTIVE_TIMEZONE.write().unwrap() = Some(tz);
+}
+
+// 按配置时区格式化，用于导出文件和界面展示
+pub fn format_local(dt: DateTime<Utc>) -> String {
+    format_in(dt, active_timezone())
+}
+
+pub fn format_in(dt: DateTime<Utc>, tz: Tz) -> String {
+    dt.with_timezone(&tz).format(LOCAL_TIME_FORMAT).to_string()
+}
+
+// 本地时间换算为 UTC：夏令时回拨时重复出现的时刻取较早的一次，
+// 拨快时跳过的时刻按跳变前的偏移换算，即顺延到跳变之后
+pub fn local_to_utc(local: NaiveDateTime, tz: Tz) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) => dt.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        LocalResult::None => {
+            let before = tz.offset_from_utc_datetime(&(local - Duration::days(1))).fix();
+            (local - Duration::seconds(before.local_minus_utc() as i64)).and_utc()
+        }
+    }
+}
+
+// 筛选范围的开始时间，只有日期时取当天零点
+pub fn parse_range_start(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    parse_range_bound(value, tz, false)
+}
+
+// 筛选范围的结束时间（闭区间），只有日期时取当天最后一微秒
+pub fn parse_range_end(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    parse_range_bound(value, tz, true)
+}
+
+// 带时区偏移的（如 JS Date 序列化出的 Z 结尾时间）按原样换算，不带时区的按 tz 的本地时间理解
+fn parse_range_bound(value: &str, tz: Tz, end_of_day: bool) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(value) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(local) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(local_to_utc(local, tz));
+        }
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if end_of_day {
+        let next_day = local_to_utc(date.succ_opt()?.and_time(NaiveTime::MIN), tz);
+        Some(next_day - Duration::microseconds(1))
+    } else {
+        Some(local_to_utc(date.and_time(NaiveTime::MIN), tz))
+    }
+}
+
+// 供 serde 使用：按当前配置时区解析前端传来的范围开始和结束时间
+pub fn deserialize_range_start<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_range_start(&value, active_timezone()).ok_or_else(|| de::Error::custom(format!("时间格式无效: {}", value)))
+}
+
+pub fn deserialize_range_end<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_range_end(&value, active_timezone()).ok_or_else(|| de::Error::custom(format!("时间格式无效: {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{America::New_York, Asia::Shanghai};
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_format_in_configured_zone() {
+        assert_eq!(format_in(at("2024-03-01T16:30:00Z"), Shanghai), "2024-03-02 00:30:00");
+        assert_eq!(format_in(at("2024-03-01T16:30:00Z"), New_York), "2024-03-01 11:30:00");
+        assert!(parse_timezone("Asia/Shanghai").is_some());
+        assert!(parse_timezone("UTC+8").is_none());
+        assert!(parse_timezone(&system_timezone_id()).is_some());
+    }
+
+    #[test]
+    fn test_dst_transitions() {
+        // 2024-03-10 纽约 02:00 拨快到 03:00，前后相差一小时的 UTC 时间本地显示相差两小时
+        assert_eq!(format_in(at("2024-03-10T06:59:00Z"), New_York), "2024-03-10 01:59:00");
+        assert_eq!(format_in(at("2024-03-10T07:00:00Z"), New_York), "2024-03-10 03:00:00");
+        // 跳过的 02:30 顺延为 03:30
+        assert_eq!(local_to_utc(local("2024-03-10 02:30"), New_York), at("2024-03-10T07:30:00Z"));
+        assert_eq!(local_to_utc(local("2024-03-10 03:30"), New_York), at("2024-03-10T07:30:00Z"));
+
+        // 2024-11-03 01:00-02:00 出现两次，取较早的（夏令时）
+        assert_eq!(local_to_utc(local("2024-11-03 01:30"), New_York), at("2024-11-03T05:30:00Z"));
+        assert_eq!(format_in(at("2024-11-03T06:30:00Z"), New_York), "2024-11-03 01:30:00");
+
+        // 当天只有 23 小时，整天范围的长度随之变化
+        let start = parse_range_start("2024-03-10", New_York).unwrap();
+        let end = parse_range_end("2024-03-10", New_York).unwrap();
+        assert_eq!(start, at("2024-03-10T05:00:00Z"));
+        assert_eq!(end + Duration::microseconds(1) - start, Duration::hours(23));
+    }
+
+    #[test]
+    fn test_range_filter_spans_local_midnight() {
+        // 上海 3 月 1 日整天对应 UTC 2 月 29 日 16:00 到 3 月 1 日 16:00
+        let start = parse_range_start("2024-03-01", Shanghai).unwrap();
+        let end = parse_range_end("2024-03-01", Shanghai).unwrap();
+        assert_eq!(start, at("2024-02-29T16:00:00Z"));
+        assert_eq!(end, at("2024-03-01T15:59:59.999999Z"));
+
+        // 本地凌晨的记录在 UTC 中属于前一天，仍落在范围内；次日凌晨的不在
+        let early_morning = at("2024-02-29T17:30:00Z");
+        let next_morning = at("2024-03-01T17:30:00Z");
+        assert!(start <= early_morning && early_morning <= end);
+        assert!(next_morning > end);
+
+        // 不带时区的时间按本地理解，带偏移的原样换算
+        assert_eq!(parse_range_start("2024-03-01 08:00", Shanghai), Some(at("2024-03-01T00:00:00Z")));
+        assert_eq!(parse_range_end("2024-03-01T23:59:59", Shanghai), Some(at("2024-03-01T15:59:59Z")));
+        assert_eq!(parse_range_start("2024-03-01T00:00:00Z", Shanghai), Some(at("2024-03-01T00:00:00Z")));
+        assert!(parse_range_start("yesterday", Shanghai).is_none());
+    }
+}
@@ -2,14 +2,48 @@
 
 use regex::Regex;
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use crate::models::*;
+use crate::utils::i18n::{Locale, MessageKey};
+use crate::utils::sanitize::MAX_MESSAGE_CHARS;
+use crate::utils::timezone::parse_timezone;
+use std::fmt::Display;
+
+// 上传文件的真实内容与扩展名不符
+pub const CODE_EXTENSION_MISMATCH: &str = "EXTENSION_MISMATCH";
+// 可执行文件和脚本一律拒绝
+pub const CODE_EXECUTABLE_BLOCKED: &str = "EXECUTABLE_BLOCKED";
+
+const BLOCKED_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "com", "bat", "cmd", "msi", "scr", "pif", "cpl", "lnk", "reg", "ps1", "psm1", "vbs", "vbe",
+    "js", "jse", "wsf", "wsh", "hta", "jar", "sh", "bash", "zsh", "command", "py", "pl", "rb", "app", "dmg",
+    "pkg", "apk",
+];
+
+const EXECUTABLE_MIME_TYPES: &[&str] = &[
+    "application/vnd.microsoft.portable-executable",
+    "application/x-msdownload",
+    "application/x-executable",
+    "application/x-elf",
+    "application/x-sharedlib",
+    "application/x-mach-binary",
+    "application/x-msi",
+    "application/java-archive",
+    "application/vnd.android.package-archive",
+    "application/x-apple-diskimage",
+    "application/x-sh",
+    "text/x-shellscript",
+];
 
 #[derive(Debug, Clone)]
 pub struct ValidationViolation {
     pub field: String,
+    // 按添加时的语言解析好的文本
     pub message: String,
     pub code: String,
+    // 来自文案目录时记录键和参数，可按其他语言重新生成文本
+    pub key: Option<MessageKey>,
+    pub args: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -26,23 +60,68 @@ impl ValidationResult {
         }
     }
 
+    // 文本已由调用方确定（例如下层返回的错误信息）
     pub fn add_error(&mut self, field: &str, message: &str, code: &str) {
         self.is_valid = false;
         self.errors.push(ValidationViolation {
             field: field.to_string(),
             message: message.to_string(),
             code: code.to_string(),
+            key: None,
+            args: Vec::new(),
         });
     }
 
+    // 按当前语言取文案目录中的文本，code 与语言无关
+    pub fn add(&mut self, field: &str, key: MessageKey, args: &[&dyn Display], code: &str) {
+        self.is_valid = false;
+        self.errors.push(ValidationViolation {
+            field: field.to_string(),
+            message: key.text(args),
+            code: code.to_string(),
+            key: Some(key),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        });
+    }
+
+    // 用指定语言重新生成来自文案目录的文本
+    pub fn localized(mut self, locale: Locale) -> Self {
+        for violation in &mut self.errors {
+            if let Some(key) = violation.key {
+                let args: Vec<&dyn Display> = violation.args.iter().map(|arg| arg as &dyn Display).collect();
+                violation.message = key.text_in(locale, &args);
+            }
+        }
+        self
+    }
+
     pub fn merge(&mut self, other: ValidationResult) {
         if !other.is_valid {
             self.is_valid = false;
             self.errors.extend(other.errors);
         }
     }
+
+    // 校验不通过时转换为带字段级错误的 AppError
+    pub fn into_app_result(self) -> Result<(), AppError> {
+        if self.is_valid {
+            Ok(())
+        } else {
+            Err(AppError::validation_failed(self))
+        }
+    }
 }
 
+// 单个病历附件的大小上限
+const MAX_ATTACHMENT_SIZE: u64 = 100 * 1024 * 1024;
+// 一张处方最多包含的药品数
+pub const MAX_PRESCRIPTION_ITEMS: usize = 20;
+// 问诊备注按字符计数的长度上限
+pub const MAX_CONSULTATION_NOTE_CHARS: usize = 10_000;
+// 应用配置中上传文件大小上限的可选范围
+const MIN_CONFIG_FILE_SIZE: u64 = 1024 * 1024;
+const MAX_CONFIG_FILE_SIZE: u64 = 500 * 1024 * 1024;
+
 pub struct ValidationService;
 
 impl ValidationService {
@@ -54,44 +133,44 @@ impl ValidationService {
             LoginType::Password => {
                 if let Some(username) = &credentials.username {
                     if username.trim().is_empty() {
-                        result.add_error("username", "用户名不能为空", "REQUIRED");
+                        result.add("username", MessageKey::UsernameRequired, &[], "REQUIRED");
                     }
                 } else {
-                    result.add_error("username", "用户名不能为空", "REQUIRED");
+                    result.add("username", MessageKey::UsernameRequired, &[], "REQUIRED");
                 }
 
                 if let Some(password) = &credentials.password {
                     if password.len() < 6 {
-                        result.add_error("password", "密码长度不能少于6位", "MIN_LENGTH");
+                        result.add("password", MessageKey::PasswordTooShort, &[], "MIN_LENGTH");
                     }
                 } else {
-                    result.add_error("password", "密码不能为空", "REQUIRED");
+                    result.add("password", MessageKey::PasswordRequired, &[], "REQUIRED");
                 }
             }
             LoginType::Sms => {
                 if let Some(phone) = &credentials.phone {
                     if !Self::validate_phone(phone) {
-                        result.add_error("phone", "手机号格式不正确", "INVALID_FORMAT");
+                        result.add("phone", MessageKey::PhoneInvalid, &[], "INVALID_FORMAT");
                     }
                 } else {
-                    result.add_error("phone", "手机号不能为空", "REQUIRED");
+                    result.add("phone", MessageKey::PhoneRequired, &[], "REQUIRED");
                 }
 
                 if let Some(sms_code) = &credentials.sms_code {
                     if !Self::validate_sms_code(sms_code) {
-                        result.add_error("smsCode", "验证码必须是6位数字", "INVALID_FORMAT");
+                        result.add("smsCode", MessageKey::SmsCodeInvalid, &[], "INVALID_FORMAT");
                     }
                 } else {
-                    result.add_error("smsCode", "验证码不能为空", "REQUIRED");
+                    result.add("smsCode", MessageKey::SmsCodeRequired, &[], "REQUIRED");
                 }
             }
             LoginType::Realname => {
                 if let Some(id_card) = &credentials.id_card {
                     if !Self::validate_id_card(id_card) {
-                        result.add_error("idCard", "身份证号格式不正确", "INVALID_FORMAT");
+                        result.add("idCard", MessageKey::IdCardInvalid, &[], "INVALID_FORMAT");
                     }
                 } else {
-                    result.add_error("idCard", "身份证号不能为空", "REQUIRED");
+                    result.add("idCard", MessageKey::IdCardRequired, &[], "REQUIRED");
                 }
             }
         }
@@ -105,31 +184,48 @@ impl ValidationService {
 
         // 验证姓名
         if patient.name.trim().is_empty() {
-            result.add_error("name", "患者姓名不能为空", "REQUIRED");
+            result.add("name", MessageKey::PatientNameRequired, &[], "REQUIRED");
         } else if patient.name.len() > 50 {
-            result.add_error("name", "患者姓名不能超过50个字符", "MAX_LENGTH");
+            result.add("name", MessageKey::PatientNameTooLong, &[], "MAX_LENGTH");
         } else if !Self::validate_chinese_name(&patient.name) {
-            result.add_error("name", "患者姓名格式不正确", "INVALID_FORMAT");
+            result.add("name", MessageKey::PatientNameInvalid, &[], "INVALID_FORMAT");
         }
 
         // 验证年龄
         if let Some(age) = patient.age {
             if !Self::validate_age(age) {
-                result.add_error("age", "年龄必须在0-150之间", "OUT_OF_RANGE");
+                result.add("age", MessageKey::AgeOutOfRange, &[], "OUT_OF_RANGE");
             }
         }
 
         // 验证手机号
         if let Some(phone) = &patient.phone {
             if !Self::validate_phone(phone) {
-                result.add_error("phone", "手机号格式不正确", "INVALID_FORMAT");
+                result.add("phone", MessageKey::PhoneInvalid, &[], "INVALID_FORMAT");
             }
         }
 
-        // 验证身份证号（如果提供）
+        // 验证身份证号（如果提供），并与填写的性别、年龄核对
         if let Some(id_card) = &patient.id_card {
-            if !Self::validate_id_card(id_card) {
-                result.add_error("idCard", "身份证号格式不正确", "INVALID_FORMAT");
+            match Self::parse_id_card(id_card) {
+                None => result.add("idCard", MessageKey::IdCardInvalid, &[], "INVALID_FORMAT"),
+                Some(info) => {
+                    let gender_mismatch = matches!(
+                        (patient.gender.as_deref(), &info.gender),
+                        (Some("male"), Gender::Female) | (Some("female"), Gender::Male)
+                    );
+                    if gender_mismatch {
+                        result.add("idCard", MessageKey::IdCardGenderMismatch, &[], "ID_CARD_MISMATCH");
+                    }
+
+                    // 年龄可能在生日前后录入，允许相差一岁
+                    if let Some(age) = patient.age {
+                        let actual = Self::age_on(info.birth_date, Utc::now().date_naive());
+                        if age.abs_diff(actual) > 1 {
+                            result.add("idCard", MessageKey::IdCardAgeMismatch, &[], "ID_CARD_MISMATCH");
+                        }
+                    }
+                }
             }
         }
 
@@ -149,25 +245,25 @@ impl ValidationService {
 
         // 验证问诊ID
         if request.consultation_id.trim().is_empty() {
-            result.add_error("consultationId", "问诊ID不能为空", "REQUIRED");
+            result.add("consultationId", MessageKey::ConsultationIdRequired, &[], "REQUIRED");
         }
 
         // 验证消息内容
         match request.message_type.as_str() {
             "text" | "template" => {
                 if request.content.trim().is_empty() {
-                    result.add_error("content", "消息内容不能为空", "REQUIRED");
-                } else if request.content.len() > 5000 {
-                    result.add_error("content", "消息内容不能超过5000个字符", "MAX_LENGTH");
+                    result.add("content", MessageKey::MessageContentRequired, &[], "REQUIRED");
+                } else if request.content.chars().count() > MAX_MESSAGE_CHARS {
+                    result.add("content", MessageKey::MessageContentTooLong, &[&MAX_MESSAGE_CHARS], "MAX_LENGTH");
                 }
             }
             "image" | "voice" | "file" => {
                 if request.file_id.is_none() {
-                    result.add_error("fileId", "文件ID不能为空", "REQUIRED");
+                    result.add("fileId", MessageKey::FileIdRequired, &[], "REQUIRED");
                 }
             }
             _ => {
-                result.add_error("messageType", "不支持的消息类型", "INVALID_TYPE");
+                result.add("messageType", MessageKey::MessageTypeUnsupported, &[], "INVALID_TYPE");
             }
         }
 
@@ -180,20 +276,28 @@ impl ValidationService {
 
         // 验证分页参数
         if query.page < 1 {
-            result.add_error("page", "页码必须大于0", "MIN_VALUE");
+            result.add("page", MessageKey::PageMustBePositive, &[], "MIN_VALUE");
         }
 
         if query.page_size < 1 || query.page_size > 100 {
-            result.add_error("pageSize", "每页数量必须在1-100之间", "OUT_OF_RANGE");
+            result.add("pageSize", MessageKey::PageSizeOutOfRange, &[], "OUT_OF_RANGE");
         }
 
         // 验证年龄范围
         if let Some(age_range) = &query.age_range {
             if age_range.min > 150 || age_range.max > 150 {
-                result.add_error("ageRange", "年龄范围必须在0-150之间", "OUT_OF_RANGE");
+                result.add("ageRange", MessageKey::AgeRangeOutOfRange, &[], "OUT_OF_RANGE");
             }
             if age_range.min > age_range.max {
-                result.add_error("ageRange", "最小年龄不能大于最大年龄", "INVALID_RANGE");
+                result.add("ageRange", MessageKey::AgeRangeInverted, &[], "INVALID_RANGE");
+            }
+        }
+
+        // 排序字段只允许白名单内的值，避免拼接到 ORDER BY
+        if let Some(sort) = &query.sort {
+            if PatientSortField::parse(&sort.field).is_none() {
+                let allowed: Vec<&str> = PatientSortField::ALL.iter().map(|field| field.as_str()).collect();
+                result.add("sort", MessageKey::SortFieldUnsupported, &[&allowed.join(", ")], "INVALID_VALUE");
             }
         }
 
@@ -206,49 +310,313 @@ impl ValidationService {
 
         // 验证标题
         if config.title.trim().is_empty() {
-            result.add_error("title", "窗口标题不能为空", "REQUIRED");
+            result.add("title", MessageKey::WindowTitleRequired, &[], "REQUIRED");
         }
 
         // 验证URL
         if config.url.trim().is_empty() {
-            result.add_error("url", "窗口URL不能为空", "REQUIRED");
+            result.add("url", MessageKey::WindowUrlRequired, &[], "REQUIRED");
         }
 
         // 验证尺寸
         if let Some(width) = config.width {
             if width < 200 {
-                result.add_error("width", "窗口宽度不能小于200px", "MIN_VALUE");
+                result.add("width", MessageKey::WindowWidthTooSmall, &[], "MIN_VALUE");
             }
         }
 
         if let Some(height) = config.height {
             if height < 150 {
-                result.add_error("height", "窗口高度不能小于150px", "MIN_VALUE");
+                result.add("height", MessageKey::WindowHeightTooSmall, &[], "MIN_VALUE");
             }
         }
 
         result
     }
 
+    // 验证应用配置，数据保留策略由 validate_retention_policy 单独校验
+    pub fn validate_app_config(config: &AppConfig) -> ValidationResult {
+        let mut result = ValidationResult::new();
+
+        if !Self::validate_url(&config.api_base_url, &["http", "https"]) {
+            result.add("apiBaseUrl", MessageKey::ApiBaseUrlInvalid, &[], "INVALID_URL");
+        }
+        if !Self::validate_url(&config.ws_url, &["ws", "wss"]) {
+            result.add("wsUrl", MessageKey::WsUrlInvalid, &[], "INVALID_URL");
+        }
+        if !Self::validate_url(&config.update_manifest_url, &["http", "https"]) {
+            result.add("updateManifestUrl", MessageKey::UpdateManifestUrlInvalid, &[], "INVALID_URL");
+        }
+
+        if !(MIN_CONFIG_FILE_SIZE..=MAX_CONFIG_FILE_SIZE).contains(&config.max_file_size) {
+            result.add(
+                "maxFileSize",
+                MessageKey::MaxFileSizeOutOfRange,
+                &[
+                    &Self::format_file_size(MIN_CONFIG_FILE_SIZE),
+                    &Self::format_file_size(MAX_CONFIG_FILE_SIZE),
+                ],
+                "OUT_OF_RANGE",
+            );
+        }
+        if config.allowed_file_types.is_empty() {
+            result.add("allowedFileTypes", MessageKey::AllowedFileTypesRequired, &[], "REQUIRED");
+        }
+        if config.retry_attempts > 10 {
+            result.add("retryAttempts", MessageKey::RetryAttemptsTooMany, &[], "MAX_VALUE");
+        }
+
+        let limits = &config.window_limits;
+        if !(1..=20).contains(&limits.max_windows) {
+            result.add("windowLimits.maxWindows", MessageKey::MaxWindowsOutOfRange, &[], "OUT_OF_RANGE");
+        }
+        if limits.max_consultation_windows == 0 || limits.max_consultation_windows > limits.max_windows {
+            result.add(
+                "windowLimits.maxConsultationWindows",
+                MessageKey::ConsultationWindowsOutOfRange,
+                &[],
+                "OUT_OF_RANGE",
+            );
+        }
+
+        if !(60..=3600).contains(&config.auto_lock_timeout) {
+            result.add("autoLockTimeout", MessageKey::AutoLockTimeoutOutOfRange, &[], "OUT_OF_RANGE");
+        }
+        // 超时前 1 小时提醒，时长需大于提醒提前量
+        if !(2..=168).contains(&config.consultation_inactivity_hours) {
+            result.add(
+                "consultationInactivityHours",
+                MessageKey::ConsultationInactivityOutOfRange,
+                &[],
+                "OUT_OF_RANGE",
+            );
+        }
+        if config.max_consultation_attachment_bytes < config.max_file_size
+            || config.max_consultation_attachment_bytes > config.max_patient_attachment_bytes
+        {
+            result.add(
+                "maxConsultationAttachmentBytes",
+                MessageKey::AttachmentQuotaOutOfRange,
+                &[],
+                "OUT_OF_RANGE",
+            );
+        }
+        if !(1..=50).contains(&config.message_warmup.consultation_count) {
+            result.add(
+                "messageWarmup.consultationCount",
+                MessageKey::MessageWarmupCountOutOfRange,
+                &[],
+                "OUT_OF_RANGE",
+            );
+        }
+        if !(320..=8192).contains(&config.upload_compression.max_dimension) {
+            result.add(
+                "uploadCompression.maxDimension",
+                MessageKey::UploadCompressionDimensionOutOfRange,
+                &[],
+                "OUT_OF_RANGE",
+            );
+        }
+        if !(30..=95).contains(&config.upload_compression.quality) {
+            result.add(
+                "uploadCompression.quality",
+                MessageKey::UploadCompressionQualityOutOfRange,
+                &[],
+                "OUT_OF_RANGE",
+            );
+        }
+        if parse_timezone(&config.timezone).is_none() {
+            result.add("timezone", MessageKey::TimezoneInvalid, &[], "INVALID_VALUE");
+        }
+        for (command, rule) in &config.command_rate_limits {
+            if !(rule.capacity >= 1.0 && rule.capacity.is_finite() && rule.refill_per_second > 0.0) {
+                result.add(
+                    &format!("commandRateLimits.{}", command),
+                    MessageKey::CommandRateLimitInvalid,
+                    &[command],
+                    "INVALID_VALUE",
+                );
+            }
+        }
+
+        result
+    }
+
+    // URL 必须可解析、带主机名且协议在允许列表中
+    pub fn validate_url(url: &str, allowed_schemes: &[&str]) -> bool {
+        match url::Url::parse(url.trim()) {
+            Ok(parsed) => {
+                allowed_schemes.contains(&parsed.scheme()) && parsed.host_str().is_some_and(|host| !host.is_empty())
+            }
+            Err(_) => false,
+        }
+    }
+
     // 验证文件信息
     pub fn validate_file_info(file_info: &FileInfo, max_size: u64, allowed_types: &[String]) -> ValidationResult {
+        Self::validate_upload(&file_info.name, file_info.size, &file_info.file_type, max_size, allowed_types)
+    }
+
+    // 上传文件的公共校验：大小、可执行文件、类型白名单和文件名
+    pub fn validate_upload(name: &str, size: u64, mime_type: &str, max_size: u64, allowed_types: &[String]) -> ValidationResult {
         let mut result = ValidationResult::new();
 
         // 验证文件大小
-        if file_info.size > max_size {
-            result.add_error("size", &format!("文件大小超过限制: {} > {}",
-                Self::format_file_size(file_info.size),
-                Self::format_file_size(max_size)), "FILE_TOO_LARGE");
+        if size > max_size {
+            result.add(
+                "size",
+                MessageKey::FileTooLarge,
+                &[&Self::format_file_size(size), &Self::format_file_size(max_size)],
+                "FILE_TOO_LARGE",
+            );
         }
 
         // 验证文件类型
-        if !allowed_types.contains(&file_info.file_type) {
-            result.add_error("type", &format!("不支持的文件类型: {}", file_info.file_type), "UNSUPPORTED_TYPE");
+        if Self::is_executable(name, mime_type) {
+            result.add("type", MessageKey::ExecutableBlocked, &[], CODE_EXECUTABLE_BLOCKED);
+        } else if !allowed_types.iter().any(|allowed| allowed == mime_type) {
+            result.add("type", MessageKey::FileTypeUnsupported, &[&mime_type], "UNSUPPORTED_TYPE");
         }
 
         // 验证文件名
-        if file_info.name.trim().is_empty() {
-            result.add_error("name", "文件名不能为空", "REQUIRED");
+        if name.trim().is_empty() {
+            result.add("name", MessageKey::FileNameRequired, &[], "REQUIRED");
+        }
+
+        result
+    }
+
+    // 按扩展名或 MIME 类型判断是否为可执行文件或脚本
+    pub fn is_executable(name: &str, mime_type: &str) -> bool {
+        let blocked_extension = Self::file_extension(name)
+            .map(|ext| BLOCKED_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or(false);
+        blocked_extension || EXECUTABLE_MIME_TYPES.contains(&mime_type)
+    }
+
+    pub fn file_extension(name: &str) -> Option<String> {
+        std::path::Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+    }
+
+    // 常见附件扩展名对应的 MIME 类型，与 infer 识别结果的写法一致
+    pub fn mime_type_for_extension(ext: &str) -> Option<&'static str> {
+        match ext.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Some("image/jpeg"),
+            "png" => Some("image/png"),
+            "gif" => Some("image/gif"),
+            "webp" => Some("image/webp"),
+            "pdf" => Some("application/pdf"),
+            "doc" => Some("application/msword"),
+            "docx" => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+            "txt" => Some("text/plain"),
+            "wav" => Some("audio/x-wav"),
+            "mp3" => Some("audio/mpeg"),
+            "m4a" => Some("audio/m4a"),
+            "amr" => Some("audio/amr"),
+            "ogg" => Some("audio/ogg"),
+            _ => None,
+        }
+    }
+
+    // 验证病历及其附件
+    pub fn validate_medical_record(record: &MedicalRecord) -> ValidationResult {
+        let mut result = ValidationResult::new();
+
+        if record.patient_id.trim().is_empty() {
+            result.add("patientId", MessageKey::PatientIdRequired, &[], "REQUIRED");
+        }
+
+        if record.doctor_id.trim().is_empty() {
+            result.add("doctorId", MessageKey::DoctorIdRequired, &[], "REQUIRED");
+        }
+
+        if !["diagnosis", "prescription", "examination", "treatment"].contains(&record.record_type.as_str()) {
+            result.add("recordType", MessageKey::RecordTypeUnsupported, &[], "INVALID_TYPE");
+        }
+
+        if record.title.trim().is_empty() {
+            result.add("title", MessageKey::RecordTitleRequired, &[], "REQUIRED");
+        } else if record.title.chars().count() > 200 {
+            result.add("title", MessageKey::RecordTitleTooLong, &[], "MAX_LENGTH");
+        }
+
+        let mime_regex = Regex::new(r"^[a-zA-Z0-9][\w.+-]*/[\w.+-]+$").unwrap();
+        let checksum_regex = Regex::new(r"^[0-9a-fA-F]{32,128}$").unwrap();
+        let mut seen_files = std::collections::HashSet::new();
+
+        for (index, attachment) in record.attachments.iter().enumerate() {
+            let field = |name: &str| format!("attachments[{}].{}", index, name);
+
+            if attachment.file_id.trim().is_empty() {
+                result.add(&field("fileId"), MessageKey::AttachmentFileIdRequired, &[], "REQUIRED");
+            } else if !seen_files.insert(attachment.file_id.as_str()) {
+                result.add(&field("fileId"), MessageKey::AttachmentDuplicate, &[], "DUPLICATE");
+            }
+
+            if attachment.name.trim().is_empty() {
+                result.add(&field("name"), MessageKey::AttachmentNameRequired, &[], "REQUIRED");
+            } else if attachment.name.len() > 255 {
+                result.add(&field("name"), MessageKey::AttachmentNameTooLong, &[], "MAX_LENGTH");
+            }
+
+            if !mime_regex.is_match(&attachment.mime_type) {
+                result.add(&field("mimeType"), MessageKey::AttachmentMimeTypeInvalid, &[], "INVALID_FORMAT");
+            }
+
+            if attachment.size == 0 {
+                result.add(&field("size"), MessageKey::AttachmentEmpty, &[], "MIN_VALUE");
+            } else if attachment.size > MAX_ATTACHMENT_SIZE {
+                result.add(
+                    &field("size"),
+                    MessageKey::AttachmentTooLarge,
+                    &[&Self::format_file_size(MAX_ATTACHMENT_SIZE)],
+                    "FILE_TOO_LARGE",
+                );
+            }
+
+            if let Some(checksum) = &attachment.checksum {
+                if !checksum_regex.is_match(checksum) {
+                    result.add(&field("checksum"), MessageKey::AttachmentChecksumInvalid, &[], "INVALID_FORMAT");
+                }
+            }
+        }
+
+        result
+    }
+
+    // 验证处方药品明细
+    pub fn validate_prescription(items: &[PrescriptionItem]) -> ValidationResult {
+        let mut result = ValidationResult::new();
+
+        if items.is_empty() {
+            result.add("items", MessageKey::PrescriptionEmpty, &[], "REQUIRED");
+        } else if items.len() > MAX_PRESCRIPTION_ITEMS {
+            result.add("items", MessageKey::PrescriptionTooManyItems, &[&MAX_PRESCRIPTION_ITEMS], "MAX_ITEMS");
+        }
+
+        for (index, item) in items.iter().enumerate() {
+            let field = |name: &str| format!("items[{}].{}", index, name);
+
+            if item.drug.trim().is_empty() {
+                result.add(&field("drug"), MessageKey::DrugNameRequired, &[], "REQUIRED");
+            } else if item.drug.chars().count() > 100 {
+                result.add(&field("drug"), MessageKey::DrugNameTooLong, &[], "MAX_LENGTH");
+            }
+
+            if item.frequency.trim().is_empty() {
+                result.add(&field("frequency"), MessageKey::FrequencyRequired, &[], "REQUIRED");
+            }
+
+            if !item.dosage.is_finite() || item.dosage <= 0.0 {
+                result.add(&field("dosage"), MessageKey::DosageNotPositive, &[], "MIN_VALUE");
+            }
+
+            if item.days == 0 {
+                result.add(&field("days"), MessageKey::DaysNotPositive, &[], "MIN_VALUE");
+            }
         }
 
         result
@@ -266,8 +634,60 @@ impl ValidationService {
     }
 
     pub fn validate_id_card(id_card: &str) -> bool {
-        let id_regex = Regex::new(r"^[1-9]\d{5}(18|19|20)\d{2}((0[1-9])|(1[0-2]))(([0-2][1-9])|10|20|30|31)\d{3}[0-9Xx]$").unwrap();
-        id_regex.is_match(id_card)
+        Self::parse_id_card(id_card).is_some()
+    }
+
+    // 按 GB 11643-1999 校验 18 位身份证号，并解析出生日期、性别和地区码
+    pub fn parse_id_card(id_card: &str) -> Option<IdCardInfo> {
+        let id_card = id_card.trim().to_uppercase();
+        let id_regex = Regex::new(r"^[1-9]\d{5}(18|19|20)\d{2}((0[1-9])|(1[0-2]))(([0-2][1-9])|10|20|30|31)\d{3}[0-9X]$").unwrap();
+        if !id_regex.is_match(&id_card) {
+            return None;
+        }
+
+        if Self::id_card_check_digit(&id_card[..17])? != id_card.chars().last()? {
+            return None;
+        }
+
+        let birth_date = NaiveDate::parse_from_str(&id_card[6..14], "%Y%m%d").ok()?;
+        if birth_date > Utc::now().date_naive() {
+            return None;
+        }
+
+        // 第 17 位顺序码：奇数为男性，偶数为女性
+        let sequence = id_card[16..17].parse::<u32>().ok()?;
+        let gender = if sequence % 2 == 1 { Gender::Male } else { Gender::Female };
+
+        Some(IdCardInfo {
+            birth_date,
+            gender,
+            region_code: id_card[..6].to_string(),
+        })
+    }
+
+    // 根据前 17 位本体码计算校验码
+    pub fn id_card_check_digit(body: &str) -> Option<char> {
+        const WEIGHTS: [u32; 17] = [7, 9, 10, 5, 8, 4, 2, 1, 6, 3, 7, 9, 10, 5, 8, 4, 2];
+        const CHECK_CODES: [char; 11] = ['1', '0', 'X', '9', '8', '7', '6', '5', '4', '3', '2'];
+
+        if body.len() != 17 {
+            return None;
+        }
+
+        let mut sum = 0;
+        for (c, weight) in body.chars().zip(WEIGHTS) {
+            sum += c.to_digit(10)? * weight;
+        }
+        Some(CHECK_CODES[(sum % 11) as usize])
+    }
+
+    // 按出生日期计算周岁
+    pub fn age_on(birth_date: NaiveDate, today: NaiveDate) -> u32 {
+        let mut age = today.year() - birth_date.year();
+        if (today.month(), today.day()) < (birth_date.month(), birth_date.day()) {
+            age -= 1;
+        }
+        age.max(0) as u32
     }
 
     pub fn validate_sms_code(code: &str) -> bool {
@@ -275,22 +695,26 @@ impl ValidationService {
         code_regex.is_match(code)
     }
 
+    pub fn validate_unlock_pin(pin: &str) -> bool {
+        pin.len() == 6 && pin.bytes().all(|b| b.is_ascii_digit())
+    }
+
     pub fn validate_username(username: &str) -> Result<()> {
         if username.is_empty() {
-            return Err(anyhow::anyhow!("用户名不能为空"));
+            return Err(anyhow::anyhow!(MessageKey::UsernameRequired.text(&[])));
         }
 
         if username.len() < 3 {
-            return Err(anyhow::anyhow!("用户名长度至少3位"));
+            return Err(anyhow::anyhow!(MessageKey::UsernameTooShort.text(&[])));
         }
 
         if username.len() > 20 {
-            return Err(anyhow::anyhow!("用户名长度不能超过20位"));
+            return Err(anyhow::anyhow!(MessageKey::UsernameTooLong.text(&[])));
         }
 
         let username_regex = Regex::new(r"^[a-zA-Z0-9_]+$").unwrap();
         if !username_regex.is_match(username) {
-            return Err(anyhow::anyhow!("用户名只能包含字母、数字和下划线"));
+            return Err(anyhow::anyhow!(MessageKey::UsernameInvalidChars.text(&[])));
         }
 
         Ok(())
@@ -298,11 +722,11 @@ impl ValidationService {
 
     pub fn validate_password(password: &str) -> Result<()> {
         if password.len() < 8 {
-            return Err(anyhow::anyhow!("密码长度至少8位"));
+            return Err(anyhow::anyhow!(MessageKey::PasswordBelowMinimum.text(&[])));
         }
 
         if password.len() > 128 {
-            return Err(anyhow::anyhow!("密码长度不能超过128位"));
+            return Err(anyhow::anyhow!(MessageKey::PasswordTooLong.text(&[])));
         }
 
         let has_lowercase = Regex::new(r"[a-z]").unwrap().is_match(password);
@@ -316,7 +740,7 @@ impl ValidationService {
             .count();
 
         if strength_count < 3 {
-            return Err(anyhow::anyhow!("密码必须包含至少3种字符类型(大写字母,小写字母,数字,特殊字符)"));
+            return Err(anyhow::anyhow!(MessageKey::PasswordTooWeak.text(&[])));
         }
 
         Ok(())
@@ -339,21 +763,21 @@ impl ValidationService {
 
     pub fn validate_message_content(content: &str, max_length: usize) -> Result<()> {
         if content.trim().is_empty() {
-            return Err(anyhow::anyhow!("消息内容不能为空"));
+            return Err(anyhow::anyhow!(MessageKey::MessageContentRequired.text(&[])));
         }
 
-        if content.len() > max_length {
-            return Err(anyhow::anyhow!("消息内容不能超过 {} 个字符", max_length));
+        if content.chars().count() > max_length {
+            return Err(anyhow::anyhow!(MessageKey::MessageContentTooLong.text(&[&max_length])));
         }
 
-        // 检查敏感词（这里只是示例）
-        let sensitive_words = ["测试敏感词"];
-        for word in sensitive_words.iter() {
-            if content.contains(word) {
-                return Err(anyhow::anyhow!("消息内容包含敏感词"));
-            }
-        }
+        // 敏感词由 SensitiveWordService 按数据库中的词表检查
+        Ok(())
+    }
 
+    pub fn validate_consultation_note(content: &str) -> Result<()> {
+        if content.chars().count() > MAX_CONSULTATION_NOTE_CHARS {
+            return Err(anyhow::anyhow!(MessageKey::ConsultationNoteTooLong.text(&[&MAX_CONSULTATION_NOTE_CHARS])));
+        }
         Ok(())
     }
 
@@ -366,9 +790,13 @@ impl ValidationService {
             sanitized = sanitized.replace(ch, "_");
         }
 
-        // 限制文件名长度
+        // 限制文件名长度，按字符边界截断
         if sanitized.len() > 255 {
-            sanitized.truncate(252);
+            let mut end = 252;
+            while !sanitized.is_char_boundary(end) {
+                end -= 1;
+            }
+            sanitized.truncate(end);
             sanitized.push_str("...");
         }
 
@@ -381,16 +809,16 @@ impl ValidationService {
 
     pub fn validate_tag(tag: &str) -> Result<()> {
         if tag.trim().is_empty() {
-            return Err(anyhow::anyhow!("标签不能为空"));
+            return Err(anyhow::anyhow!(MessageKey::TagRequired.text(&[])));
         }
 
         if tag.len() > 20 {
-            return Err(anyhow::anyhow!("标签长度不能超过20个字符"));
+            return Err(anyhow::anyhow!(MessageKey::TagTooLong.text(&[])));
         }
 
         let tag_regex = Regex::new(r"^[\u{4e00}-\u{9fa5}a-zA-Z0-9]+$").unwrap();
         if !tag_regex.is_match(tag) {
-            return Err(anyhow::anyhow!("标签只能包含中文、字母和数字"));
+            return Err(anyhow::anyhow!(MessageKey::TagInvalidChars.text(&[])));
         }
 
         Ok(())
@@ -415,12 +843,12 @@ impl ValidationService {
         let mut result = ValidationResult::new();
 
         if start > end {
-            result.add_error("dateRange", "开始时间不能晚于结束时间", "INVALID_RANGE");
+            result.add("dateRange", MessageKey::DateRangeInverted, &[], "INVALID_RANGE");
         }
 
         let now = Utc::now();
         if start > &now {
-            result.add_error("dateRange", "开始时间不能是未来时间", "FUTURE_DATE");
+            result.add("dateRange", MessageKey::DateRangeStartInFuture, &[], "FUTURE_DATE");
         }
 
         result
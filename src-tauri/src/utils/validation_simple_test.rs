@@ -1,6 +1,27 @@
 #[cfg(test)]
 mod simple_validation_tests {
-    use crate::utils::validation::ValidationService;
+    use crate::models::{Gender, Patient, PatientQuery, SortOrder, SortParams};
+    use crate::utils::i18n::Locale;
+    use crate::utils::validation::{ValidationResult, ValidationService, CODE_EXECUTABLE_BLOCKED, MAX_PRESCRIPTION_ITEMS};
+    use chrono::{NaiveDate, Utc};
+
+    fn patient_with_id_card(id_card: &str, gender: Option<&str>, age: Option<u32>) -> Patient {
+        Patient {
+            id: "patient-1".to_string(),
+            name: "张三".to_string(),
+            age,
+            gender: gender.map(str::to_string),
+            phone: None,
+            id_card: Some(id_card.to_string()),
+            tags: vec![],
+            avatar_url: None,
+            last_sync: None,
+            last_visit: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
 
     #[test]
     fn test_validate_phone() {
@@ -23,11 +44,131 @@ mod simple_validation_tests {
 
     #[test]
     fn test_validate_id_card() {
-        assert!(ValidationService::validate_id_card("110101199001011234"));
+        assert!(ValidationService::validate_id_card("110101199001011237"));
         assert!(ValidationService::validate_id_card("31010519900101123X"));
+        assert!(ValidationService::validate_id_card("31010519900101123x"));
         assert!(!ValidationService::validate_id_card("12345678901234567"));
         assert!(!ValidationService::validate_id_card("110101199013011234")); // Invalid month
         assert!(!ValidationService::validate_id_card("110101199001321234")); // Invalid day
+        assert!(!ValidationService::validate_id_card("110101199002301236")); // Feb 30
+    }
+
+    #[test]
+    fn test_validate_id_card_checksum() {
+        assert_eq!(ValidationService::id_card_check_digit("11010119900101123"), Some('7'));
+        assert_eq!(ValidationService::id_card_check_digit("31010519900101123"), Some('X'));
+        assert!(!ValidationService::validate_id_card("110101199001011234"));
+        assert!(!ValidationService::validate_id_card("310105199001011231"));
+        assert!(!ValidationService::validate_id_card("11010119900101123X"));
+    }
+
+    #[test]
+    fn test_parse_id_card() {
+        let male = ValidationService::parse_id_card("110101199001011237").unwrap();
+        assert_eq!(male.birth_date, NaiveDate::from_ymd_opt(1990, 1, 1).unwrap());
+        assert!(matches!(male.gender, Gender::Male));
+        assert_eq!(male.region_code, "110101");
+
+        let female = ValidationService::parse_id_card("110101198805120020").unwrap();
+        assert_eq!(female.birth_date, NaiveDate::from_ymd_opt(1988, 5, 12).unwrap());
+        assert!(matches!(female.gender, Gender::Female));
+
+        assert!(ValidationService::parse_id_card("110101199001011234").is_none());
+    }
+
+    #[test]
+    fn test_age_on() {
+        let birth = NaiveDate::from_ymd_opt(1990, 6, 15).unwrap();
+        assert_eq!(ValidationService::age_on(birth, NaiveDate::from_ymd_opt(2020, 6, 14).unwrap()), 29);
+        assert_eq!(ValidationService::age_on(birth, NaiveDate::from_ymd_opt(2020, 6, 15).unwrap()), 30);
+    }
+
+    #[test]
+    fn test_validate_patient_id_card_consistent() {
+        let birth = NaiveDate::from_ymd_opt(1990, 1, 1).unwrap();
+        let age = ValidationService::age_on(birth, Utc::now().date_naive());
+        let patient = patient_with_id_card("110101199001011237", Some("male"), Some(age));
+
+        assert!(ValidationService::validate_patient(&patient).is_valid);
+    }
+
+    #[test]
+    fn test_validate_patient_id_card_mismatch() {
+        let wrong_gender = patient_with_id_card("110101198805120020", Some("male"), None);
+        let result = ValidationService::validate_patient(&wrong_gender);
+        assert!(!result.is_valid);
+        assert_eq!(result.errors[0].field, "idCard");
+        assert_eq!(result.errors[0].code, "ID_CARD_MISMATCH");
+
+        let wrong_age = patient_with_id_card("110101199001011237", Some("male"), Some(5));
+        let result = ValidationService::validate_patient(&wrong_age);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code, "ID_CARD_MISMATCH");
+
+        let wrong_checksum = patient_with_id_card("110101199001011234", Some("male"), None);
+        let result = ValidationService::validate_patient(&wrong_checksum);
+        assert_eq!(result.errors[0].code, "INVALID_FORMAT");
+    }
+
+    #[test]
+    fn test_validation_messages_follow_locale() {
+        let mut patient = patient_with_id_card("110101198805120020", Some("male"), None);
+        patient.name = String::new();
+        patient.phone = Some("12345".to_string());
+
+        let zh = ValidationService::validate_patient(&patient).localized(Locale::ZhCn);
+        let messages: Vec<&str> = zh.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["患者姓名不能为空", "手机号格式不正确", "身份证号与患者性别不一致"]);
+
+        let en = zh.clone().localized(Locale::EnUs);
+        let messages: Vec<&str> = en.errors.iter().map(|e| e.message.as_str()).collect();
+        // 没有英文译文的提示回退到中文
+        assert_eq!(messages, vec!["Patient name is required", "Invalid phone number", "身份证号与患者性别不一致"]);
+
+        // 语言只影响提示文字，字段和错误码保持不变
+        let codes = |result: &ValidationResult| {
+            result.errors.iter().map(|e| (e.field.clone(), e.code.clone())).collect::<Vec<_>>()
+        };
+        assert_eq!(codes(&zh), codes(&en));
+    }
+
+    #[test]
+    fn test_validation_message_args_localized() {
+        let allowed = vec!["image/jpeg".to_string()];
+        let result = ValidationService::validate_upload("scan.png", 2048, "image/png", 1024, &allowed);
+
+        let zh = result.clone().localized(Locale::ZhCn);
+        assert_eq!(zh.errors[0].message, "文件大小超过限制: 2.00 KB > 1.00 KB");
+        assert_eq!(zh.errors[1].message, "不支持的文件类型: image/png");
+
+        let en = result.localized(Locale::EnUs);
+        assert_eq!(en.errors[0].message, "File size exceeds the limit: 2.00 KB > 1.00 KB");
+        assert_eq!(en.errors[1].message, "Unsupported file type: image/png");
+        assert_eq!(en.errors[0].code, "FILE_TOO_LARGE");
+    }
+
+    #[test]
+    fn test_validate_patient_query_sort_whitelist() {
+        let query = |field: &str| PatientQuery {
+            keyword: None,
+            tags: None,
+            gender: None,
+            age_range: None,
+            last_visit_range: None,
+            sort: Some(SortParams {
+                field: field.to_string(),
+                order: SortOrder::Asc,
+            }),
+            page: 1,
+            page_size: 20,
+        };
+
+        for field in ["name", "lastVisit", "createdAt"] {
+            assert!(ValidationService::validate_patient_query(&query(field)).is_valid);
+        }
+        let result = ValidationService::validate_patient_query(&query("phone; DROP TABLE patients"));
+        assert!(!result.is_valid);
+        assert_eq!(result.errors[0].field, "sort");
     }
 
     #[test]
@@ -65,6 +206,14 @@ mod simple_validation_tests {
         assert_eq!(ValidationService::format_file_size(1024 * 1024 * 1024), "1.00 GB");
     }
 
+    #[test]
+    fn test_message_content_limit_counts_chars() {
+        // 5000 个汉字超过 5000 字节，但没有超过字符数上限
+        assert!(ValidationService::validate_message_content(&"痛".repeat(5000), 5000).is_ok());
+        assert!(ValidationService::validate_message_content(&"😀".repeat(5001), 5000).is_err());
+        assert!(ValidationService::validate_message_content(" \n", 5000).is_err());
+    }
+
     #[test]
     fn test_sanitize_filename() {
         assert_eq!(ValidationService::sanitize_filename("test.txt"), "test.txt");
@@ -75,5 +224,107 @@ mod simple_validation_tests {
         let sanitized = ValidationService::sanitize_filename(&long_name);
         assert!(sanitized.len() <= 255);
         assert!(sanitized.ends_with("..."));
+
+        // 多字节字符不能从中间截断
+        let sanitized = ValidationService::sanitize_filename(&"病历".repeat(60));
+        assert!(sanitized.len() <= 255);
+        assert!(sanitized.ends_with("..."));
+    }
+
+    #[test]
+    fn test_validate_upload_blocks_executables() {
+        let allowed = vec!["image/jpeg".to_string(), "application/pdf".to_string()];
+        let max_size = 1024 * 1024;
+
+        assert!(ValidationService::validate_upload("scan.jpg", 1024, "image/jpeg", max_size, &allowed).is_valid);
+
+        let result = ValidationService::validate_upload("setup.exe", 1024, "application/x-msdownload", max_size, &allowed);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code, CODE_EXECUTABLE_BLOCKED);
+
+        // 即使类型在白名单中，脚本扩展名也会被拒绝
+        let allowed_text = vec!["text/plain".to_string()];
+        let result = ValidationService::validate_upload("run.ps1", 10, "text/plain", max_size, &allowed_text);
+        assert_eq!(result.errors[0].code, CODE_EXECUTABLE_BLOCKED);
+
+        let result = ValidationService::validate_upload("", 2 * max_size, "text/html", max_size, &allowed);
+        let codes: Vec<&str> = result.errors.iter().map(|e| e.code.as_str()).collect();
+        assert_eq!(codes, vec!["FILE_TOO_LARGE", "UNSUPPORTED_TYPE", "REQUIRED"]);
+    }
+
+    #[test]
+    fn test_validate_medical_record_attachments() {
+        use crate::models::{Attachment, MedicalRecord};
+        use chrono::Utc;
+
+        let attachment = Attachment {
+            file_id: "f1".to_string(),
+            name: "血常规.pdf".to_string(),
+            mime_type: "application/pdf".to_string(),
+            size: 2048,
+            checksum: Some("d41d8cd98f00b204e9800998ecf8427e".to_string()),
+        };
+        let mut record = MedicalRecord {
+            id: String::new(),
+            patient_id: "p1".to_string(),
+            doctor_id: "d1".to_string(),
+            consultation_id: None,
+            record_type: "examination".to_string(),
+            title: "血常规".to_string(),
+            content: None,
+            attachments: vec![attachment.clone()],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        assert!(ValidationService::validate_medical_record(&record).is_valid);
+
+        record.attachments.push(Attachment {
+            mime_type: "pdf".to_string(),
+            size: 0,
+            checksum: Some("xyz".to_string()),
+            ..attachment
+        });
+        let result = ValidationService::validate_medical_record(&record);
+        assert!(!result.is_valid);
+        let fields: Vec<&str> = result.errors.iter().map(|e| e.field.as_str()).collect();
+        assert!(fields.contains(&"attachments[1].fileId"));
+        assert!(fields.contains(&"attachments[1].mimeType"));
+        assert!(fields.contains(&"attachments[1].size"));
+        assert!(fields.contains(&"attachments[1].checksum"));
+    }
+
+    #[test]
+    fn test_validate_prescription() {
+        use crate::models::PrescriptionItem;
+
+        let item = PrescriptionItem {
+            drug: "阿莫西林胶囊".to_string(),
+            spec: "0.25g×24粒".to_string(),
+            dosage: 0.5,
+            frequency: "一日三次".to_string(),
+            days: 7,
+            note: None,
+        };
+        assert!(ValidationService::validate_prescription(std::slice::from_ref(&item)).is_valid);
+
+        let result = ValidationService::validate_prescription(&[]);
+        assert_eq!(result.errors[0].code, "REQUIRED");
+
+        let invalid = PrescriptionItem {
+            drug: " ".to_string(),
+            dosage: -1.0,
+            days: 0,
+            ..item.clone()
+        };
+        let result = ValidationService::validate_prescription(&[item.clone(), invalid]);
+        let fields: Vec<&str> = result.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["items[1].drug", "items[1].dosage", "items[1].days"]);
+
+        let nan = PrescriptionItem { dosage: f64::NAN, ..item.clone() };
+        assert!(!ValidationService::validate_prescription(&[nan]).is_valid);
+
+        let too_many = vec![item; MAX_PRESCRIPTION_ITEMS + 1];
+        let result = ValidationService::validate_prescription(&too_many);
+        assert_eq!(result.errors[0].code, "MAX_ITEMS");
     }
-}
\ No newline at end of file
+}
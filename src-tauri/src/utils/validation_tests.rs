@@ -141,6 +141,7 @@ mod tests {
             medical_history: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            version: 1,
         };
 
         let result = ValidationService::validate_patient(&valid_patient);
@@ -163,6 +164,7 @@ mod tests {
             medical_history: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            version: 1,
         };
 
         let result = ValidationService::validate_patient(&invalid_patient);
@@ -187,6 +189,7 @@ mod tests {
             medical_history: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            version: 1,
         };
 
         let result = ValidationService::validate_patient(&invalid_patient);
@@ -211,6 +214,7 @@ mod tests {
             medical_history: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            version: 1,
         };
 
         let result = ValidationService::validate_patient(&invalid_patient);
@@ -500,7 +504,7 @@ mod tests {
         assert!(!result.is_valid);
         assert_eq!(result.errors.len(), 1);
         assert_eq!(result.errors[0].field, "type");
-        assert_eq!(result.errors[0].code, "UNSUPPORTED_TYPE");
+        assert_eq!(result.errors[0].code, CODE_EXECUTABLE_BLOCKED);
     }
 
     // Basic validation method tests
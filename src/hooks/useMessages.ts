@@ -207,11 +207,11 @@ export function useMessages(consultationId?: string) {
   const uploadFile = useCallback(
     async (file: File) => {
       return handleAsyncError(async () => {
-        const result = await messageService.uploadFile(file)
+        const result = await messageService.uploadFile(file, consultationId)
         return result
       })
     },
-    [messageService, handleAsyncError]
+    [consultationId, messageService, handleAsyncError]
   )
 
   // 发送文件消息
@@ -1,5 +1,25 @@
 import { invoke } from '@tauri-apps/api/core'
-import type { LoginCredentials, AuthResult, User } from '@/types'
+import type {
+  LoginCredentials,
+  AuthResult,
+  User,
+  SessionRestoreResult,
+} from '@/types'
+
+// 后端 auth_login / try_restore_session 返回的认证结果
+interface BackendAuthResult {
+  token: string
+  user: {
+    id: string
+    username: string
+    name: string
+    role: string
+    avatar?: string
+    department?: string
+    title?: string
+  }
+  expires_at: string
+}
 
 export class AuthService {
   private static instance: AuthService
@@ -25,39 +45,11 @@ export class AuthService {
       }
 
       // 调用 Tauri 命令进行认证
-      const result = await invoke<{
-        token: string
-        user: {
-          id: string
-          username: string
-          name: string
-          role: string
-          avatar?: string
-          department?: string
-          title?: string
-        }
-        expires_at: string
-      }>('auth_login', { credentials })
-
-      // 转换数据格式
-      const authResult: AuthResult = {
-        token: result.token,
-        user: {
-          id: result.user.id,
-          username: result.user.username,
-          name: result.user.name,
-          phone: '', // TODO: 从后端获取
-          department: result.user.department || '',
-          title: result.user.title || '',
-          licenseNumber: '', // TODO: 从后端获取
-          auditStatus: 'approved', // TODO: 从后端获取
-          createdAt: new Date(),
-          lastLogin: new Date(),
-        },
-        expiresAt: new Date(result.expires_at),
-      }
+      const result = await invoke<BackendAuthResult>('auth_login', {
+        credentials,
+      })
 
-      return authResult
+      return this.toAuthResult(result)
     } catch (error) {
       console.error('Login failed:', error)
       throw new Error(
@@ -68,6 +60,42 @@ export class AuthService {
     }
   }
 
+  // 启动时恢复上次登录的会话，失败时返回原因，由调用方决定是否显示登录页
+  async tryRestoreSession(): Promise<SessionRestoreResult<AuthResult>> {
+    const result =
+      await invoke<SessionRestoreResult<BackendAuthResult>>(
+        'try_restore_session'
+      )
+    if (result.status === 'failed') {
+      return result
+    }
+    return {
+      status: 'restored',
+      auth: this.toAuthResult(result.auth),
+      verified: result.verified,
+    }
+  }
+
+  // 转换后端返回的数据格式
+  private toAuthResult(result: BackendAuthResult): AuthResult {
+    return {
+      token: result.token,
+      user: {
+        id: result.user.id,
+        username: result.user.username,
+        name: result.user.name,
+        phone: '', // TODO: 从后端获取
+        department: result.user.department || '',
+        title: result.user.title || '',
+        licenseNumber: '', // TODO: 从后端获取
+        auditStatus: 'approved', // TODO: 从后端获取
+        createdAt: new Date(),
+        lastLogin: new Date(),
+      },
+      expiresAt: new Date(result.expires_at),
+    }
+  }
+
   private checkMockCredentials(credentials: LoginCredentials): boolean {
     // Password login
     if (credentials.type === 'password') {
@@ -119,6 +147,13 @@ export class AuthService {
       console.error('Logout failed:', error)
       // 即使登出失败，也要清除本地状态
     }
+
+    try {
+      // 清空后端内存中的患者数据和会话密钥
+      await invoke('purge_all_session_data')
+    } catch (error) {
+      console.error('Purge session data failed:', error)
+    }
   }
 
   async refreshToken(currentToken: string): Promise<string> {
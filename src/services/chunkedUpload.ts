@@ -0,0 +1,37 @@
+import { invoke } from '@tauri-apps/api/core'
+import type { UploadTransfer } from '@/types'
+
+// 超过该大小的文件不再以字节数组整体传给后端，与后端 INLINE_UPLOAD_LIMIT 一致
+export const INLINE_UPLOAD_LIMIT = 5 * 1024 * 1024
+
+// 按顺序分片传输文件，返回供 finish_file_upload / save_file_locally 使用的 transferId
+// 传输失败时取消，后端删除临时文件
+export async function transferInChunks(
+  data: ArrayBuffer,
+  fileName: string
+): Promise<string> {
+  const transfer = await invoke<UploadTransfer>('begin_file_upload', {
+    fileName,
+    totalSize: data.byteLength,
+  })
+
+  try {
+    for (
+      let index = 0, offset = 0;
+      offset < data.byteLength;
+      index++, offset += transfer.chunkSize
+    ) {
+      const chunk = new Uint8Array(data, offset, Math.min(transfer.chunkSize, data.byteLength - offset))
+      await invoke('append_file_upload_chunk', {
+        transferId: transfer.transferId,
+        index,
+        data: Array.from(chunk),
+      })
+    }
+  } catch (error) {
+    await invoke('cancel_file_upload', { transferId: transfer.transferId }).catch(() => undefined)
+    throw error
+  }
+
+  return transfer.transferId
+}
@@ -1,4 +1,8 @@
-import type { Consultation, ConsultationStatus } from '@/types'
+import type {
+  Consultation,
+  ConsultationExpiry,
+  ConsultationStatus,
+} from '@/types'
 
 // Safe invoke wrapper that checks if Tauri is available
 const safeInvoke = async <T>(
@@ -167,6 +171,20 @@ export class ConsultationService {
     }
   }
 
+  // 收到超时提醒后继续问诊，返回新的超时时间
+  async keepAliveConsultation(
+    consultationId: string
+  ): Promise<ConsultationExpiry> {
+    try {
+      return await safeInvoke<ConsultationExpiry>('keep_alive_consultation', {
+        consultationId,
+      })
+    } catch (error) {
+      console.error('Keep alive consultation failed:', error)
+      throw new Error('延长问诊失败')
+    }
+  }
+
   // 更新问诊状态
   async updateConsultationStatus(
     consultationId: string,
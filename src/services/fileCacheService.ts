@@ -3,6 +3,7 @@ import {
   FileCacheInfo,
   FileCacheCleanupStrategy,
   FileStatistics,
+  PinnedFile,
 } from '../types/file'
 
 export class FileCacheService {
@@ -312,13 +313,25 @@ export class FileCacheService {
   }
 
   /**
-   * 清空所有缓存
+   * 获取被未发送消息等引用而固定的缓存文件
    */
-  async clearAllCache(): Promise<void> {
+  async getPinnedFiles(): Promise<PinnedFile[]> {
+    try {
+      return await invoke<PinnedFile[]>('get_pinned_files')
+    } catch (error) {
+      console.error('Get pinned files failed:', error)
+      return []
+    }
+  }
+
+  /**
+   * 清空所有缓存，force 为 true 时连同固定的文件一并删除
+   */
+  async clearAllCache(force = false): Promise<void> {
     try {
       console.log('FileCacheService.clearAllCache called')
 
-      await invoke('clear_all_file_cache')
+      await invoke('clear_all_file_cache', { force })
 
       // 重置统计信息
       this.cacheStats = null
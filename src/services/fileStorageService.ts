@@ -1,4 +1,5 @@
 import { invoke } from '@tauri-apps/api/core'
+import { INLINE_UPLOAD_LIMIT, transferInChunks } from './chunkedUpload'
 import {
   FileCacheInfo,
   FileStorageConfig,
@@ -42,9 +43,13 @@ export class FileStorageService {
     try {
       console.log('FileStorageService.saveFileLocally called with:', fileName)
 
-      // 调用 Tauri 命令保存文件
+      // 调用 Tauri 命令保存文件，大文件先分片传输
+      const payload =
+        fileData.byteLength > INLINE_UPLOAD_LIMIT
+          ? { transferId: await transferInChunks(fileData, fileName) }
+          : { fileData: Array.from(new Uint8Array(fileData)) }
       const localPath = await invoke<string>('save_file_locally', {
-        fileData: Array.from(new Uint8Array(fileData)),
+        ...payload,
         fileName,
         config: this.config,
       })
@@ -1,8 +1,11 @@
 import { invoke } from '@tauri-apps/api/core'
 import { webSocketService } from './webSocketService'
+import { INLINE_UPLOAD_LIMIT, transferInChunks } from './chunkedUpload'
 import type {
   Message,
   MessageList,
+  MessagePinChange,
+  PinnedMessage,
   SendMessageRequest,
   FileInfo,
 } from '@/types'
@@ -154,25 +157,9 @@ export class MessageService {
       })
 
       // 转换消息格式
-      const messages: Message[] = result.messages.map((msg: any) => ({
-        id: msg.id,
-        consultationId: msg.consultation_id,
-        type: msg.message_type as Message['type'],
-        content: msg.content,
-        sender: msg.sender as Message['sender'],
-        timestamp: new Date(msg.timestamp),
-        status: msg.status as Message['status'],
-        fileInfo: msg.file_path
-          ? {
-              id: msg.id,
-              name: msg.content,
-              size: 0,
-              type: '',
-              url: msg.file_path,
-              localPath: msg.file_path,
-            }
-          : undefined,
-      }))
+      const messages: Message[] = result.messages.map((msg: any) =>
+        this.toMessage(msg)
+      )
 
       return {
         messages,
@@ -186,6 +173,51 @@ export class MessageService {
     }
   }
 
+  // 置顶消息，超出每个问诊 5 条的上限或消息已撤回时后端返回错误
+  async pinMessage(messageId: string): Promise<MessagePinChange> {
+    return invoke<MessagePinChange>('pin_message', { messageId })
+  }
+
+  async unpinMessage(messageId: string): Promise<MessagePinChange> {
+    return invoke<MessagePinChange>('unpin_message', { messageId })
+  }
+
+  async getPinnedMessages(consultationId: string): Promise<PinnedMessage[]> {
+    const result = await invoke<any[]>('get_pinned_messages', {
+      consultationId,
+    })
+
+    return result.map(item => ({
+      message: this.toMessage(item),
+      pinnedAt: new Date(item.pinned_at),
+      pinnedBy: item.pinned_by ?? undefined,
+    }))
+  }
+
+  // 后端返回的消息转换为前端格式
+  private toMessage(msg: any): Message {
+    return {
+      id: msg.id,
+      consultationId: msg.consultation_id,
+      type: msg.message_type as Message['type'],
+      content: msg.content,
+      sender: msg.sender as Message['sender'],
+      timestamp: new Date(msg.timestamp),
+      status: msg.status as Message['status'],
+      fileInfo: msg.file_path
+        ? {
+            id: msg.id,
+            name: msg.content,
+            size: 0,
+            type: '',
+            url: msg.file_path,
+            localPath: msg.file_path,
+          }
+        : undefined,
+      pinned: msg.pinned,
+    }
+  }
+
   subscribeToMessages(
     consultationId: string,
     callback: MessageCallback
@@ -257,19 +289,34 @@ export class MessageService {
     }
   }
 
-  async uploadFile(file: File): Promise<FileInfo> {
+  // 传入问诊 ID 时计入该问诊和患者的附件配额；sendOriginal 为 true 时网络较慢也不压缩图片
+  async uploadFile(
+    file: File,
+    consultationId?: string,
+    options?: { sendOriginal?: boolean }
+  ): Promise<FileInfo> {
     try {
       console.log('MessageService.uploadFile called with:', file.name)
 
-      // 将文件转换为字节数组
       const arrayBuffer = await file.arrayBuffer()
-      const fileData = Array.from(new Uint8Array(arrayBuffer))
 
-      // 调用 Tauri 命令上传文件
-      const result = await invoke<any>('upload_file', {
-        fileData,
-        fileName: file.name,
-      })
+      // 大文件分片传输，小文件直接以字节数组传入
+      let result: any
+      if (arrayBuffer.byteLength > INLINE_UPLOAD_LIMIT) {
+        const transferId = await transferInChunks(arrayBuffer, file.name)
+        result = await invoke<any>('finish_file_upload', {
+          transferId,
+          consultationId,
+          sendOriginal: options?.sendOriginal,
+        })
+      } else {
+        result = await invoke<any>('upload_file', {
+          fileData: Array.from(new Uint8Array(arrayBuffer)),
+          fileName: file.name,
+          consultationId,
+          sendOriginal: options?.sendOriginal,
+        })
+      }
 
       return {
         id: `file-${Date.now()}`,
@@ -278,6 +325,7 @@ export class MessageService {
         type: file.type,
         url: result.url,
         localPath: result.path,
+        compressed: result.compressed,
       }
     } catch (error) {
       console.error('Upload file failed:', error)
@@ -5,6 +5,7 @@
 
 import { invoke } from '@tauri-apps/api/core'
 import { listen } from '@tauri-apps/api/event'
+import type { ConnectivityChange, OfflineState } from '@/types'
 
 // 网络状态类型
 export type NetworkStatus = 'online' | 'offline' | 'slow' | 'unstable'
@@ -120,6 +121,11 @@ export class NetworkStatusService {
     return { ...this.networkInfo }
   }
 
+  // 后端离线状态：连通性、待发送消息数和待同步实体
+  async getOfflineState(): Promise<OfflineState> {
+    return await invoke<OfflineState>('get_offline_state')
+  }
+
   // 添加状态变化监听器
   addStatusListener(callback: NetworkStatusCallback): () => void {
     this.callbacks.push(callback)
@@ -291,6 +297,16 @@ export class NetworkStatusService {
         this.handleTauriNetworkChange(event.payload as any)
       })
 
+      // 后端综合 WebSocket 和 HTTP 探测的连通性，已做防抖
+      await listen<ConnectivityChange>('connectivity-changed', event => {
+        console.log('Connectivity changed (backend):', event.payload)
+        this.updateNetworkStatus(event.payload.online ? 'online' : 'offline', {
+          ...this.networkInfo,
+          status: event.payload.online ? 'online' : 'offline',
+          lastChecked: new Date(),
+        })
+      })
+
       // 监听网络连接类型变化
       await listen('network-type-changed', event => {
         console.log('Network type changed (Tauri):', event.payload)
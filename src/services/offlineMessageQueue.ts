@@ -201,6 +201,8 @@ export class OfflineMessageQueue {
       }
 
       await this.saveQueue()
+      // 同时放弃后端发件箱中的消息，解除其附件的缓存固定
+      await invoke('clear_message_outbox', { consultationId })
       console.log(
         `Queue cleared${consultationId ? ` for consultation ${consultationId}` : ''}`
       )
@@ -3,10 +3,14 @@
 import { invoke } from '@tauri-apps/api/core'
 import type {
   AuditLog,
+  AuditLogEntry,
   AnomalyRecord,
+  AutoLockStatus,
   LogAuditRequest,
   GetAuditLogsRequest,
+  QueryAuditLogsRequest,
 } from '../types/security'
+import type { PaginatedResponse } from '../types/common'
 
 class SecurityService {
   /**
@@ -39,6 +43,27 @@ class SecurityService {
     return await invoke<AuditLog[]>('get_audit_logs', { request })
   }
 
+  /**
+   * 分页查询已持久化的操作日志
+   */
+  async queryAuditLogs(request: QueryAuditLogsRequest): Promise<PaginatedResponse<AuditLogEntry>> {
+    return await invoke<PaginatedResponse<AuditLogEntry>>('query_audit_logs', { request })
+  }
+
+  /**
+   * 获取某个资源的全部操作记录（患者详情页）
+   */
+  async getResourceAuditLogs(resourceType: string, resourceId: string): Promise<AuditLogEntry[]> {
+    return await invoke<AuditLogEntry[]>('get_resource_audit_logs', { resourceType, resourceId })
+  }
+
+  /**
+   * 查看消息清洗前的原文（仅管理员，每次查看都记录操作日志），正文未被改写时为 null
+   */
+  async getMessageRawContent(messageId: string): Promise<string | null> {
+    return await invoke<string | null>('get_message_raw_content', { messageId })
+  }
+
   /**
    * 检测异常访问
    */
@@ -61,10 +86,38 @@ class SecurityService {
   }
 
   /**
-   * 检查是否需要自动锁屏
+   * 记录真实的用户输入，刷新后端自动锁屏计时
+   */
+  async recordUserInteraction(userId: string): Promise<void> {
+    await invoke('record_user_interaction', { userId })
+  }
+
+  /**
+   * 检查是否需要自动锁屏，以及锁屏后能否用 PIN 解锁
+   */
+  async shouldAutoLock(userId: string): Promise<AutoLockStatus> {
+    return await invoke<AutoLockStatus>('should_auto_lock', { userId })
+  }
+
+  /**
+   * 设置6位数字的本地解锁 PIN
+   */
+  async setUnlockPin(pin: string): Promise<void> {
+    await invoke('set_unlock_pin', { pin })
+  }
+
+  /**
+   * 用 PIN 解锁，连续输错5次后返回 REAUTH_REQUIRED，需要重新登录
+   */
+  async unlockWithPin(pin: string): Promise<void> {
+    await invoke('unlock_with_pin', { pin })
+  }
+
+  /**
+   * 清除解锁 PIN，修改密码后调用
    */
-  async shouldAutoLock(userId: string): Promise<boolean> {
-    return await invoke<boolean>('should_auto_lock', { userId })
+  async clearUnlockPin(): Promise<boolean> {
+    return await invoke<boolean>('clear_unlock_pin')
   }
 
   /**
@@ -105,6 +105,7 @@ export class WebSocketService {
           request: {
             url: config.url,
             auth_token: config.authToken,
+            shared: true,
           },
         })
       } catch (error) {
@@ -606,6 +607,12 @@ export class WebSocketService {
       console.log('Tauri WebSocket message failed:', event.payload)
       this.emitEvent('tauri_message_failed', event.payload)
     })
+
+    // 发件箱中的消息已被服务器确认，payload 为消息 ID
+    await listen('message-acknowledged', event => {
+      console.log('Message acknowledged by server:', event.payload)
+      this.emitEvent('message_acknowledged', event.payload)
+    })
   }
 
   // 私有方法：更新连接状态
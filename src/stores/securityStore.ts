@@ -24,6 +24,11 @@ interface SecurityStore extends SecurityState {
 }
 
 let autoLockInterval: NodeJS.Timeout | null = null
+// 自动锁屏监控中的用户，输入事件据此上报到后端
+let monitoredUserId: string | null = null
+let lastReportedInteraction = 0
+// 输入事件很频繁，上报后端做节流
+const INTERACTION_REPORT_INTERVAL_MS = 15000
 
 export const useSecurityStore = create<SecurityStore>((set, get) => ({
   // 初始状态
@@ -31,6 +36,7 @@ export const useSecurityStore = create<SecurityStore>((set, get) => ({
   lastActivity: new Date(),
   autoLockEnabled: true,
   autoLockTimeout: 300, // 5分钟
+  pinUnlockAvailable: false,
   anomalies: [],
 
   // 状态更新方法
@@ -44,7 +50,15 @@ export const useSecurityStore = create<SecurityStore>((set, get) => ({
 
   // 更新活动时间
   updateActivity: () => {
-    set({ lastActivity: new Date() })
+    const now = new Date()
+    set({ lastActivity: now })
+
+    if (monitoredUserId && now.getTime() - lastReportedInteraction >= INTERACTION_REPORT_INTERVAL_MS) {
+      lastReportedInteraction = now.getTime()
+      securityService.recordUserInteraction(monitoredUserId).catch(error => {
+        console.error('上报用户活动失败:', error)
+      })
+    }
   },
 
   // 检查是否需要自动锁屏
@@ -53,8 +67,9 @@ export const useSecurityStore = create<SecurityStore>((set, get) => ({
     if (!autoLockEnabled || isLocked) return
 
     try {
-      const shouldLock = await securityService.shouldAutoLock(userId)
-      if (shouldLock) {
+      const status = await securityService.shouldAutoLock(userId)
+      set({ pinUnlockAvailable: status.pinUnlockAvailable })
+      if (status.shouldLock) {
         get().lockScreen()
       }
     } catch (error) {
@@ -68,6 +83,8 @@ export const useSecurityStore = create<SecurityStore>((set, get) => ({
 
     // 先停止现有的监控
     stopAutoLockMonitor()
+    monitoredUserId = userId
+    lastReportedInteraction = 0
 
     // 每10秒检查一次
     autoLockInterval = setInterval(() => {
@@ -81,6 +98,7 @@ export const useSecurityStore = create<SecurityStore>((set, get) => ({
       clearInterval(autoLockInterval)
       autoLockInterval = null
     }
+    monitoredUserId = null
   },
 
   // 锁定屏幕
@@ -6,6 +6,8 @@ import type {
   WindowDisplayState,
   ResourceUsage,
   WindowConfig,
+  WindowLayoutPreset,
+  WindowLayoutResult,
 } from '@/types'
 import {
   windowCommunicationService,
@@ -27,6 +29,8 @@ interface WindowActions {
   focusWindow: (windowId: string) => Promise<void>
   minimizeWindow: (windowId: string) => Promise<void>
   maximizeWindow: (windowId: string) => Promise<void>
+  applyWindowLayout: (preset: WindowLayoutPreset) => Promise<WindowLayoutResult>
+  setWindowPinned: (windowId: string, pinned: boolean) => Promise<void>
   updateWindow: (windowId: string, updates: Partial<WindowInfo>) => void
   setActiveWindow: (windowId: string | null) => void
   getWindow: (windowId: string) => WindowInfo | undefined
@@ -245,6 +249,40 @@ export const useWindowStore = create<WindowState & WindowActions>()(
         }
       },
 
+      applyWindowLayout: async (
+        preset: WindowLayoutPreset
+      ): Promise<WindowLayoutResult> => {
+        const { setError } = get()
+
+        setError(null)
+
+        try {
+          return await invoke<WindowLayoutResult>('apply_window_layout', {
+            preset,
+          })
+        } catch (error) {
+          const errorMessage =
+            error instanceof Error ? error.message : '排列窗口失败'
+          setError(errorMessage)
+          throw error
+        }
+      },
+
+      setWindowPinned: async (
+        windowId: string,
+        pinned: boolean
+      ): Promise<void> => {
+        await invoke('set_window_pinned', { windowId, pinned })
+
+        const { windows } = get()
+        const window = windows.get(windowId)
+        if (window) {
+          const newWindows = new Map(windows)
+          newWindows.set(windowId, { ...window, pinned })
+          set({ windows: newWindows })
+        }
+      },
+
       updateWindow: (windowId: string, updates: Partial<WindowInfo>) => {
         const { windows } = get()
         const window = windows.get(windowId)
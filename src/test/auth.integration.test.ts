@@ -139,6 +139,7 @@ describe('Authentication Integration Tests', () => {
       await authService.logout()
 
       expect(mockInvoke).toHaveBeenCalledWith('auth_logout')
+      expect(mockInvoke).toHaveBeenCalledWith('purge_all_session_data')
     })
   })
 })
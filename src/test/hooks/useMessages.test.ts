@@ -389,7 +389,10 @@ describe('useMessages', () => {
         expect(result_file).toEqual(mockFileInfo)
       })
 
-      expect(mockMessageServiceInstance.uploadFile).toHaveBeenCalledWith(file)
+      expect(mockMessageServiceInstance.uploadFile).toHaveBeenCalledWith(
+        file,
+        'consultation-1'
+      )
     })
 
     it('should send file message successfully', async () => {
@@ -424,7 +427,10 @@ describe('useMessages', () => {
         await result.current.sendFileMessage(file)
       })
 
-      expect(mockMessageServiceInstance.uploadFile).toHaveBeenCalledWith(file)
+      expect(mockMessageServiceInstance.uploadFile).toHaveBeenCalledWith(
+        file,
+        'consultation-1'
+      )
       expect(mockMessageServiceInstance.sendMessage).toHaveBeenCalledWith(
         'consultation-1',
         expect.objectContaining({
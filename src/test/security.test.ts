@@ -77,6 +77,7 @@ describe('Security Features', () => {
         description: 'Test anomaly',
         detected_at: new Date().toISOString(),
         resolved: false,
+        metadata: {},
       }
 
       act(() => {
@@ -182,14 +183,23 @@ describe('Security Features', () => {
 
     it('should check auto lock status', async () => {
       const { invoke } = await import('@tauri-apps/api/core')
-      vi.mocked(invoke).mockResolvedValue(true)
+      vi.mocked(invoke).mockResolvedValue({ shouldLock: true, pinUnlockAvailable: true })
 
       const result = await securityService.shouldAutoLock('doctor_123')
 
       expect(invoke).toHaveBeenCalledWith('should_auto_lock', {
         userId: 'doctor_123',
       })
-      expect(result).toBe(true)
+      expect(result).toEqual({ shouldLock: true, pinUnlockAvailable: true })
+    })
+
+    it('should unlock with pin', async () => {
+      const { invoke } = await import('@tauri-apps/api/core')
+      vi.mocked(invoke).mockResolvedValue(undefined)
+
+      await securityService.unlockWithPin('246810')
+
+      expect(invoke).toHaveBeenCalledWith('unlock_with_pin', { pin: '246810' })
     })
 
     it('should get audit logs', async () => {
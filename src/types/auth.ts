@@ -39,6 +39,19 @@ export interface User {
   lastLogin?: Date
 }
 
+// 启动时未能恢复会话的原因
+export type SessionRestoreFailureReason =
+  | 'no_session'
+  | 'expired'
+  | 'revoked'
+  | 'offline_unverifiable'
+  | 'corrupted'
+
+// 启动时恢复会话的结果，verified 为 false 表示离线时按宽限期恢复
+export type SessionRestoreResult<T> =
+  | { status: 'restored'; auth: T; verified: boolean }
+  | { status: 'failed'; reason: SessionRestoreFailureReason }
+
 // 审核状态
 export type AuditStatus = 'pending' | 'approved' | 'rejected' | 'incomplete'
 
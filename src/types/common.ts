@@ -122,6 +122,102 @@ export interface AppConfig {
     maxWindows: number
     maxConsultationWindows: number
   }
+  authProvider: 'mock' | 'http'
+  patientStalenessMinutes: number
+  updateManifestUrl: string
+  autoLockTimeout: number // seconds
+  retention: RetentionPolicy
+  consultationInactivityHours: number
+  maxConsultationAttachmentBytes: number
+  maxPatientAttachmentBytes: number
+  locale: Locale
+  // 启动后预先加载最近活跃问诊的第一页消息
+  messageWarmup: {
+    enabled: boolean
+    consultationCount: number
+  }
+  // 最近上传速度低于 slowUploadKbps（kbit/s）时，图片上传前压缩到 maxDimension 像素以内
+  uploadCompression: {
+    enabled: boolean
+    slowUploadKbps: number
+    maxDimension: number
+    quality: number
+  }
+  // 处方笺等打印文书的医院抬头，logoPath 为本地图片路径
+  hospital: {
+    name: string
+    logoPath?: string | null
+  }
+  // 导出文件、提醒和会话列表显示时间使用的 IANA 时区，如 Asia/Shanghai，默认取系统时区
+  timezone: string
+  // 按命令名覆盖前端调用限流：最多连续调用 capacity 次，之后每秒恢复 refillPerSecond 次
+  commandRateLimits: Record<string, { capacity: number; refillPerSecond: number }>
+}
+
+// 后端校验和错误提示的语言
+export type Locale = 'zh-CN' | 'en-US'
+
+export interface RetentionPolicy {
+  messageDays: number
+  auditLogDays: number
+  anomalyRecordDays: number
+  fileCacheDays: number
+  backupDays: number
+  // 软删除的消息和病历在回收站中保留的天数
+  trashDays: number
+  vacuumThresholdMb: number
+}
+
+// 枚举列中无法解析的取值（check_enum_columns）
+export interface EnumColumnViolation {
+  table: string
+  column: string
+  value: string
+  count: number
+}
+
+// 局部更新配置，嵌套对象同样只需提交要修改的字段
+export type AppConfigPatch = {
+  [K in keyof AppConfig]?: AppConfig[K] extends unknown[] ? AppConfig[K] : AppConfig[K] extends object ? Partial<AppConfig[K]> : AppConfig[K]
+}
+
+// config-changed 事件
+export interface ConfigChangedEvent {
+  changedKeys: string[]
+  config: AppConfig
+}
+
+// 工作站配置包导入方式：replace 以配置包为准，merge 保留本机改过的配置
+export type ProfileMergeStrategy = 'replace' | 'merge'
+
+export type ProfileSection = 'appSettings' | 'retentionPolicy' | 'messageTemplates' | 'recordTemplates'
+
+// 未导入的配置项或模板
+export interface ProfileConflict {
+  key: string
+  reason: string
+}
+
+export interface ProfileSectionReport {
+  section: ProfileSection
+  imported: number
+  updated: number
+  unchanged: number
+  removed: number
+  conflicts: ProfileConflict[]
+}
+
+export interface ProfileImportReport {
+  strategy: ProfileMergeStrategy
+  sections: ProfileSectionReport[]
+  changedSettings: string[]
+}
+
+export interface ProfileExportResult {
+  path: string
+  settingCount: number
+  messageTemplateCount: number
+  recordTemplateCount: number
 }
 
 // 日志级别
@@ -183,13 +279,159 @@ export interface SearchParams {
   exact?: boolean
 }
 
-// 时间范围
+// 时间范围；只有日期（YYYY-MM-DD）或不带时区的时间按配置的时区理解
 export interface DateRange {
-  start: Date
-  end: Date
+  start: Date | string
+  end: Date | string
 }
 
 // 统计数据
 export interface Statistics {
   [key: string]: number | string | Date
 }
+
+// 健康检查
+export type HealthStatus = 'ok' | 'degraded' | 'failing'
+
+export interface SubsystemHealth {
+  name: string
+  status: HealthStatus
+  message: string
+  details?: Record<string, unknown>
+}
+
+export interface AppHealthReport {
+  status: HealthStatus
+  appVersion: string
+  checkedAt: string
+  subsystems: SubsystemHealth[]
+}
+
+// 关于页面的设备信息
+export interface DeviceInfo {
+  hostname: string
+  osVersion: string
+  appVersion: string
+  localIp: string | null
+}
+
+// 命令限流统计（get_rate_limit_stats），按命令和窗口分别计数
+export interface RateLimitStats {
+  command: string
+  windowLabel: string
+  capacity: number
+  refillPerSecond: number
+  allowed: number
+  throttled: number
+  lastThrottledAt: string | null
+}
+
+// 技术支持诊断包（export_diagnostics_bundle），调用前需取得用户确认并传 confirmed: true
+export type DiagnosticTable =
+  | 'consultations'
+  | 'messages'
+  | 'sync_state'
+  | 'maintenance_runs'
+  | 'app_settings'
+  | 'patients'
+
+export interface DiagnosticTableSummary {
+  table: DiagnosticTable
+  rowCount: number
+}
+
+export interface DiagnosticsBundleResult {
+  path: string
+  tables: DiagnosticTableSummary[]
+  sizeBytes: number
+}
+
+// 患者、问诊变更通知（data-changed 事件），seq 全局单调递增；
+// 窗口发现 seq 不连续或重新显示时调用 get_changes_since 补齐
+export type ChangeEntity = 'patient' | 'consultation'
+
+export type ChangeOp = 'insert' | 'update' | 'delete'
+
+export interface DataChanged {
+  seq: number
+  entity: ChangeEntity
+  id: string
+  op: ChangeOp
+  changedAt: string
+}
+
+// resetRequired 表示所需的变更已被清理，需重新加载完整列表
+export interface DataChangePage {
+  entity: ChangeEntity
+  changes: DataChanged[]
+  watermark: number
+  hasMore: boolean
+  resetRequired: boolean
+}
+
+// 启动初始化进度（init-progress 事件 / get_init_status）
+export type InitPhase =
+  | 'starting'
+  | 'open_database'
+  | 'encrypt_database'
+  | 'run_migrations'
+  | 'cleanup'
+  | 'warm_caches'
+  | 'completed'
+  | 'failed'
+
+export interface InitStatus {
+  phase: InitPhase
+  percent: number
+  databaseReady: boolean
+  error?: string
+}
+
+// 整库加密模式（get_database_config / set_database_encryption），重启后生效
+export type DatabaseEncryption = 'plaintext' | 'sqlcipher'
+
+export interface DatabaseConfig {
+  encryption: DatabaseEncryption
+}
+
+// 后端综合判断的连通性（connectivity-changed 事件），已做防抖
+export interface ConnectivityChange {
+  online: boolean
+  since: string
+}
+
+// 离线提示（get_offline_state）
+export interface OfflineState extends ConnectivityChange {
+  queuedMessages: number
+  pendingSyncEntities: string[]
+}
+
+// 休眠唤醒后的恢复结果（app-resumed 事件），每次唤醒只发送一次，失败的步骤带 error
+export type ResumeStep = 'autoLock' | 'session' | 'webSocket' | 'sync'
+
+export interface ResumeStepResult {
+  step: ResumeStep
+  success: boolean
+  detail?: string | null
+  error?: string | null
+}
+
+export interface AppResumedEvent {
+  sleptSeconds: number
+  resumedAt: string
+  shouldLock: boolean
+  steps: ResumeStepResult[]
+}
+
+// list_trash 返回：软删除的消息和病历，超过 RetentionPolicy.trashDays 后彻底清除
+export type TrashEntityType = 'message' | 'medical_record'
+
+export interface TrashItem {
+  entityType: TrashEntityType
+  id: string
+  consultationId?: string
+  patientId?: string
+  // 消息内容或病历标题
+  summary?: string
+  deletedAt: string
+}
@@ -14,6 +14,8 @@ export interface FileInfo {
   uploadedAt: Date
   checksum?: string
   mimeType?: string
+  // 图片在慢速网络下压缩后上传
+  compressed?: boolean
 }
 
 // 文件上传进度
@@ -37,6 +39,18 @@ export interface FileCacheInfo {
   expiresAt?: Date
   downloadedAt: Date
   lastAccessed: Date
+  consultationId?: string
+}
+
+// 按引用固定的缓存文件，引用解除前不会被缓存清理删除
+export interface PinnedFile {
+  cacheId: string
+  fileUrl: string
+  localPath: string
+  fileSize?: number
+  reason: string // outbox：被未发送的消息引用
+  refId: string
+  pinnedAt: string
 }
 
 // 文件验证规则
@@ -149,3 +163,22 @@ export interface FileStatistics {
   averageUploadTime: number
   averageDownloadTime: number
 }
+
+// 附件配额的统计范围
+export type AttachmentScope = 'consultation' | 'patient'
+
+// 问诊或患者的附件用量，上传被拒绝时 QUOTA_EXCEEDED 错误的 details 也是此结构并附带 requestedBytes
+export interface AttachmentUsage {
+  scope: AttachmentScope
+  scopeId: string
+  usedBytes: number
+  fileCount: number
+  limitBytes: number
+}
+
+// 分片上传（begin_file_upload），超过 5MB 的文件按 chunkSize 依次调用 append_file_upload_chunk
+// 分片顺序错误时返回 UPLOAD_CHUNK_OUT_OF_ORDER，details.expectedIndex 为应重发的分片
+export interface UploadTransfer {
+  transferId: string
+  chunkSize: number
+}
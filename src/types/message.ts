@@ -14,13 +14,68 @@ export interface Message {
   status: MessageStatus
   fileInfo?: FileInfo
   replyTo?: string
+  // 图片在慢速网络下压缩后发送，显示"已压缩发送"
+  compressed?: boolean
+  // 医生置顶的消息显示在会话顶部
+  pinned?: boolean
+}
+
+// 置顶状态变化，同时是 consultation-message-pin-changed 事件的内容
+export interface MessagePinChange {
+  consultationId: string
+  messageId: string
+  pinned: boolean
+  pinnedAt: string | null
+  pinnedBy: string | null
+}
+
+// 置顶栏中的消息，按置顶时间先后排列，每个问诊最多 5 条
+export interface PinnedMessage {
+  message: Message
+  pinnedAt: Date
+  pinnedBy?: string
+}
+
+// 消息草稿
+export interface MessageDraft {
+  consultationId: string
+  doctorId: string
+  content: string
+  updatedAt: string
+}
+
+// 问诊备注：接诊医生的私人记录，不发送给患者，最多 10000 个字符
+export interface ConsultationNote {
+  id: string
+  consultationId: string
+  doctorId: string
+  content: string
+  updatedAt: string
+}
+
+// v1 问卷内容，患者端新增的字段原样保留
+export interface IntakeFormV1 {
+  chiefComplaint: string
+  duration?: string | null
+  allergies: string[]
+  [key: string]: unknown
+}
+
+// 问诊问卷：formatError 非空时 sections 为 null，界面提示"表单格式异常"并展示 data 原文
+export interface IntakeForm {
+  consultationId: string
+  schemaVersion: number
+  sections: IntakeFormV1 | null
+  data: Record<string, unknown>
+  formatError: string | null
+  submittedAt: string
 }
 
 // 消息类型枚举
-export type MessageType = 'text' | 'image' | 'voice' | 'file' | 'template'
+export type MessageType = 'text' | 'image' | 'voice' | 'file' | 'template' | 'event'
 
 // 发送者类型
-export type MessageSender = 'doctor' | 'patient'
+export type MessageSender = 'doctor' | 'patient' | 'system'
 
 // 消息状态
 export type MessageStatus = 'sending' | 'sent' | 'delivered' | 'read' | 'failed'
@@ -34,6 +89,14 @@ export interface FileInfo {
   url: string
   localPath?: string
   thumbnail?: string
+  // PDF 首页预览图和文档开头文字，悬停时展示
+  preview?: DocumentPreview
+}
+
+// 文档附件预览
+export interface DocumentPreview {
+  previewPath?: string
+  previewText?: string
 }
 
 // 消息列表响应
@@ -68,6 +131,32 @@ export interface Consultation {
   priority: 'low' | 'normal' | 'high' | 'urgent'
   estimatedDuration?: number // 预计问诊时长（分钟）
   actualDuration?: number // 实际问诊时长（分钟）
+  version?: number // 乐观锁版本号，更新时原样传回
+  queuedAt?: Date // 进入候诊队列的时间
+  waitMinutes?: number // 候诊队列（get_consultation_queue）返回的等待分钟数
+}
+
+// 会话列表项（get_conversation_list）
+export interface ConversationOverview {
+  consultationId: string
+  patientId: string
+  patientName: string
+  patientAvatarUrl?: string
+  status: ConsultationStatus
+  title?: string
+  lastMessagePreview?: string // 非文本消息显示为 [图片]、[语音] 等
+  lastMessageAt?: string
+  lastActivityAt: string
+  lastActivityAtLocal: string // 按配置时区格式化的 lastActivityAt
+  unreadCount: number
+}
+
+// 问诊记录导出格式（export_consultation_transcript）
+export type TranscriptFormat = 'html' | 'markdown'
+
+export interface TranscriptExportResult {
+  path: string
+  messageCount: number
 }
 
 // 问诊类型
@@ -81,6 +170,27 @@ export type ConsultationStatus =
   | 'cancelled'
   | 'expired'
 
+// 候诊优先级（set_consultation_priority），候诊队列按优先级从高到低、同级按等待时长排列
+export type ConsultationPriority = 'normal' | 'urgent' | 'critical'
+
+// 医生在线状态，由同步和 WebSocket presence 事件更新
+export type DoctorOnlineStatus = 'online' | 'busy' | 'offline'
+
+// 院内医生名录（search_doctors）
+export interface Doctor {
+  id: string
+  name: string
+  department?: string
+  title?: string // 职称
+  onlineStatus: DoctorOnlineStatus
+  updatedAt: string
+}
+
+// 转接候选医生（get_transfer_candidates），同科室优先，其次按在线状态排列
+export interface TransferCandidate extends Doctor {
+  sameDepartment: boolean
+}
+
 // 医嘱模板
 export interface MedicalTemplate {
   id: string
@@ -109,7 +219,7 @@ export interface MessageService {
     consultationId: string,
     callback: MessageCallback
   ): () => void
-  uploadFile(file: File): Promise<FileInfo>
+  uploadFile(file: File, consultationId?: string): Promise<FileInfo>
   markAsRead(consultationId: string, messageIds: string[]): Promise<void>
   getTemplates(category?: string): Promise<MedicalTemplate[]>
 }
@@ -155,11 +265,39 @@ export type ConnectionStatus =
 
 // WebSocket 事件
 export interface WebSocketEvent {
-  type: 'message' | 'consultation_update' | 'typing' | 'read_receipt'
+  type: 'message' | 'consultation_update' | 'typing' | 'read_receipt' | 'read_receipt_batch'
   data: any
   timestamp: Date
 }
 
+// 问诊窗口打开时补齐的缓冲事件，event 为后端 WebSocket 事件原文
+export interface BufferedWebSocketEvent {
+  receivedAt: string
+  event: { type: string; consultation_id?: string; [key: string]: unknown }
+}
+
+// 已读回执推送，只需刷新 messageIds 对应的消息
+export interface MessagesReadEvent {
+  consultationId: string
+  messageIds: string[]
+}
+
+// consultation-readonly 事件：问诊已结束，窗口应禁用消息输入
+export interface ConsultationReadonlyEvent {
+  consultationId: string
+  status: ConsultationStatus
+}
+
+// consultation-expiring 事件 / keep_alive_consultation 返回：问诊将因长时间无消息自动结束
+export interface ConsultationExpiry {
+  consultationId: string
+  doctorId: string
+  patientId: string
+  lastActivityAt: string
+  expiresAt: string
+  expiresAtLocal: string // 按配置时区格式化的 expiresAt
+}
+
 // 消息队列项
 export interface MessageQueueItem {
   id: string
@@ -167,3 +305,25 @@ export interface MessageQueueItem {
   retryCount: number
   createdAt: Date
 }
+
+// 会话内搜索结果，matches 为按字符计的高亮区间 [start, end)
+export interface MessageSearchHit {
+  message_id: string
+  timestamp: string
+  message_index: number
+  matches: { start: number; end: number }[]
+}
+
+// get_message_latency_metrics 返回：点击发送到服务器确认的延迟直方图，upperBoundMs 为空表示溢出桶
+export interface LatencyBucket {
+  upperBoundMs: number | null
+  count: number
+}
+
+export interface MessageLatencyMetrics {
+  hours: number
+  sampleCount: number
+  buckets: LatencyBucket[]
+  p50Ms: number | null
+  p95Ms: number | null
+}
@@ -8,7 +8,7 @@ export interface Patient {
   id: string
   name: string
   age: number
-  gender: 'male' | 'female'
+  gender: 'male' | 'female' | 'unknown'
   phone: string
   idCard?: string
   avatar?: string
@@ -17,6 +17,7 @@ export interface Patient {
   medicalHistory: MedicalRecord[]
   createdAt: Date
   updatedAt: Date
+  version?: number // 乐观锁版本号，更新时原样传回
 }
 
 // 病历记录
@@ -43,6 +44,12 @@ export interface Prescription {
   instructions: string
 }
 
+// 处方笺 PDF 生成结果（generate_prescription_pdf）
+export interface PrescriptionPdfResult {
+  path: string
+  sha256: string
+}
+
 // 检查报告
 export interface Examination {
   id: string
@@ -62,14 +69,23 @@ export interface PatientQuery {
     min: number
     max: number
   }
+  // 只有日期（YYYY-MM-DD）时按配置的时区取整天
   lastVisitRange?: {
-    start: Date
-    end: Date
+    start: Date | string
+    end: Date | string
+  }
+  // 未指定时按建档时间倒序，姓名按拼音排序
+  sort?: {
+    field: PatientSortField
+    order: 'asc' | 'desc'
   }
   page: number
   pageSize: number
 }
 
+// 患者列表支持的排序字段
+export type PatientSortField = 'name' | 'lastVisit' | 'createdAt'
+
 // 患者列表响应
 export interface PatientList {
   patients: Patient[]
@@ -94,6 +110,41 @@ export interface ConsultationSummary {
   completedAt?: Date
 }
 
+// 患者时间线事件类型
+export type TimelineEventType =
+  | 'consultation_started'
+  | 'consultation_completed'
+  | 'medical_record_created'
+  | 'prescription_issued'
+  | 'file_uploaded'
+
+// 患者时间线事件，refId 指向对应的问诊、病历、处方或消息
+export interface TimelineEvent {
+  eventType: TimelineEventType
+  timestamp: string
+  title: string
+  summary?: string
+  refId: string
+}
+
+// 修改记录跟踪的患者资料字段
+export type PatientRevisionField = 'name' | 'age' | 'gender' | 'phone' | 'idCard' | 'tags' | 'avatarUrl'
+
+// 单个字段修改前后的值，未填写为 null
+export interface PatientFieldChange {
+  old: unknown
+  new: unknown
+}
+
+// 患者资料修改记录，changes 只包含值发生变化的字段
+export interface PatientRevision {
+  id: string
+  patientId: string
+  editorId?: string
+  changes: Partial<Record<PatientRevisionField, PatientFieldChange>>
+  createdAt: string
+}
+
 // 随访提醒
 export interface FollowUpReminder {
   id: string
@@ -11,6 +11,9 @@ export type AuditAction =
   | 'access_sensitive_data'
   | 'change_settings'
   | 'delete_data'
+  | 'restore_data'
+  | 'close_patient_context'
+  | 'update_consultation'
 
 export interface AuditLog {
   id: string
@@ -43,6 +46,13 @@ export interface AnomalyRecord {
   description: string
   detected_at: string
   resolved: boolean
+  // 解锁 PIN 输错产生的异常带 source: 'unlock_pin'
+  metadata: Record<string, string>
+}
+
+export interface AutoLockStatus {
+  shouldLock: boolean
+  pinUnlockAvailable: boolean
 }
 
 export interface LogAuditRequest {
@@ -63,10 +73,36 @@ export interface GetAuditLogsRequest {
   limit: number
 }
 
+export interface QueryAuditLogsRequest {
+  user_id?: string
+  action?: string
+  resource_type?: string
+  resource_id?: string
+  start_time?: string
+  end_time?: string
+  page: number
+  page_size: number
+}
+
+// 数据库中持久化的审计日志，附带操作的展示名称
+export interface AuditLogEntry {
+  id: string
+  userId?: string
+  action: string
+  actionLabel: string
+  resourceType?: string
+  resourceId?: string
+  details: Record<string, unknown>
+  ipAddress?: string
+  userAgent?: string
+  createdAt: string
+}
+
 export interface SecurityState {
   isLocked: boolean
   lastActivity: Date | null
   autoLockEnabled: boolean
   autoLockTimeout: number // 秒
+  pinUnlockAvailable: boolean
   anomalies: AnomalyRecord[]
 }
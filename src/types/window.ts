@@ -21,10 +21,22 @@ export interface WindowInfo {
   position: WindowPosition
   size: WindowSize
   state: WindowDisplayState
+  // 固定的窗口不参与自动排列
+  pinned?: boolean
   createdAt: Date
   lastFocused: Date
 }
 
+// 窗口排列预设
+export type WindowLayoutPreset = 'grid' | 'columns' | 'focus-left'
+
+// 窗口排列结果
+export interface WindowLayoutResult {
+  preset: WindowLayoutPreset
+  arranged: string[]
+  pinned: string[]
+}
+
 // 窗口数据
 export interface WindowData {
   consultationId?: string
@@ -1,5 +1,7 @@
 // 通用辅助函数
 
+import { convertFileSrc } from '@tauri-apps/api/core'
+
 /**
  * 格式化日期
  */
@@ -266,3 +268,10 @@ export async function retry<T>(
     return retry(fn, attempts - 1, delay * 2) // 指数退避
   }
 }
+
+/**
+ * 患者头像地址：通过 avatar 协议读取本地缓存，未缓存时显示姓名首字头像
+ */
+export function patientAvatarSrc(patientId: string): string {
+  return convertFileSrc(patientId, 'avatar')
+}